@@ -1,5 +1,9 @@
-use std::path::PathBuf;
+use std::{net::SocketAddr, path::PathBuf};
 
+use ambient_audio::DEFAULT_MAX_VOICES;
+use ambient_build::pipelines::PipelineFilter;
+use ambient_network::relay::RELAY_PORT;
+use ambient_std::signature::PublicKey;
 use clap::{Args, Parser};
 
 pub mod new_project;
@@ -28,6 +32,8 @@ pub enum Cli {
     Build {
         #[command(flatten)]
         project_args: ProjectCli,
+        #[command(flatten)]
+        build_args: BuildCli,
     },
     /// Builds and runs the project in server-only mode
     Serve {
@@ -35,6 +41,22 @@ pub enum Cli {
         project_args: ProjectCli,
         #[command(flatten)]
         host_args: HostCli,
+        /// Shows the status of the server in the terminal (its tick rate, players, rooms, memory and bandwidth), and takes
+        /// admin commands; redirect the logs (on stderr) to a file to keep the view readable
+        #[arg(long)]
+        status_view: bool,
+    },
+    /// Builds the project and runs its server headlessly for a number of ticks, exiting with an error if a script failed
+    /// or the performance budget was exceeded
+    Test {
+        #[command(flatten)]
+        project_args: ProjectCli,
+        /// How many simulation ticks (at 60 per second) to run for
+        #[arg(long, default_value_t = 600)]
+        ticks: u32,
+        /// How many virtual players to connect to the server
+        #[arg(long, default_value_t = 1)]
+        players: u32,
     },
     /// View an asset
     View {
@@ -49,6 +71,53 @@ pub enum Cli {
         run_args: RunCli,
         /// The server to connect to; defaults to localhost
         host: Option<String>,
+        /// Connect through the relay at this address if the server can't be reached directly (see `ambient relay`)
+        #[arg(long)]
+        relay: Option<SocketAddr>,
+    },
+    /// Open a session recorded with `--record` or `--record-server`, to play it back, scrub through it and inspect its entities
+    Replay {
+        /// The recording to open
+        path: PathBuf,
+    },
+    /// Issue an auth token, which lets a player join the servers which trust the key it's signed with (see `--token-key`)
+    ///
+    /// The token is printed as JSON; the player joins with it with `--auth-token`
+    IssueToken {
+        /// Sign the token with the ed25519 key (PKCS#8) in this file; a new key is generated there if it doesn't exist
+        #[arg(long)]
+        signing_key: PathBuf,
+        /// The user ID of the player
+        user_id: String,
+        /// The name to show for the player
+        #[arg(long)]
+        display_name: Option<String>,
+        /// A role of the player, e.g. `admin`, which the scripts see on the player entity; can be repeated
+        #[arg(long = "role")]
+        roles: Vec<String>,
+        /// How many hours the token is valid for; if not specified, it doesn't expire
+        #[arg(long)]
+        valid_for_hours: Option<f32>,
+        /// The servers the token is for, which they expect with `--token-audience`
+        #[arg(long)]
+        audience: Option<String>,
+    },
+    /// List the servers on the local network, or on a server list
+    Servers {
+        /// Query the server list at this URL instead of looking on the local network
+        #[arg(long)]
+        server_list: Option<String>,
+        /// Only list the servers running the project with this id
+        #[arg(long)]
+        project_id: Option<String>,
+    },
+    /// Run a relay, through which the players who can't reach a server directly (e.g. if it's hosted behind a CGNAT) join it
+    ///
+    /// The servers register with it with `--relay`, and the players join them with `--relay` too
+    Relay {
+        /// The port to listen on
+        #[arg(long, default_value_t = RELAY_PORT)]
+        port: u16,
     },
     /// Updates all WASM APIs with the core primitive components (not for users)
     #[cfg(not(feature = "production"))]
@@ -64,11 +133,65 @@ pub struct RunCli {
     /// The user ID to join this server with
     #[clap(short, long)]
     pub user_id: Option<String>,
+
+    /// Load the renderer quality settings from this TOML file, and re-apply them whenever it changes
+    #[arg(long)]
+    pub renderer_settings: Option<PathBuf>,
+
+    /// Simulate the latency, jitter, packet loss and bandwidth cap in this TOML file on the connection to the server, and
+    /// re-apply them whenever it changes
+    #[arg(long)]
+    pub network_conditions: Option<PathBuf>,
+
+    /// Send the voice from the default microphone to the other players; their voices are heard either way
+    #[arg(long)]
+    pub voice_chat: bool,
+
+    /// The most sounds played at once; beyond them, the sounds of the lowest priority, and among those the quietest,
+    /// wait silently until they're among the ones heard again
+    #[arg(long, default_value_t = DEFAULT_MAX_VOICES)]
+    pub max_voices: usize,
+
+    /// Record the entities received from the server to this file, which can be opened with `ambient replay`
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+
+    /// Join with the auth token in this file, as issued with `ambient issue-token`; the user ID is the one of the token
+    #[arg(long)]
+    pub auth_token: Option<PathBuf>,
+
+    /// Sign in with Steam as the game with this app id; the Steam id is used as the user ID, and the players connecting
+    /// to a server hosted this way need to be signed in with Steam too
+    #[cfg(feature = "steam")]
+    #[arg(long)]
+    pub steam_app_id: Option<u32>,
 }
 #[derive(Args, Clone)]
 pub struct ProjectCli {
     /// The path of the project to run; if not specified, this will default to the current directory
     pub path: Option<PathBuf>,
+    /// Keep downloaded remote assets in this directory, so that later runs only revalidate them instead of downloading them again
+    #[arg(long)]
+    pub download_cache: Option<PathBuf>,
+}
+#[derive(Args, Clone, Default)]
+pub struct BuildCli {
+    /// Only build the assets under this path, relative to the project's `assets` directory (e.g. `--only characters/`); can be repeated
+    ///
+    /// Scripts are not built when the build is restricted
+    #[arg(long)]
+    pub only: Vec<String>,
+    /// Only build the assets of pipelines with this tag; can be repeated
+    #[arg(long = "tag")]
+    pub tags: Vec<String>,
+    /// Sign the build outputs with the ed25519 key (PKCS#8) in this file; a new key is generated there if it doesn't exist
+    #[arg(long)]
+    pub signing_key: Option<PathBuf>,
+}
+impl BuildCli {
+    pub fn pipeline_filter(&self) -> PipelineFilter {
+        PipelineFilter::new(&self.only, self.tags.iter().cloned())
+    }
 }
 #[derive(Args, Clone)]
 pub struct HostCli {
@@ -77,6 +200,48 @@ pub struct HostCli {
     /// Defaults to localhost
     #[arg(long)]
     pub public_host: Option<String>,
+    /// Only load scripts signed by this public key (hex encoded); can be repeated
+    ///
+    /// If no key is specified, scripts are loaded without verifying their signature
+    #[arg(long = "trusted-key")]
+    pub trusted_keys: Vec<PublicKey>,
+    /// Load the server tick and replication settings from this TOML file, and re-apply them whenever it changes
+    #[arg(long)]
+    pub server_settings: Option<PathBuf>,
+    /// Record the whole world replicated by the server to this file, which can be opened with `ambient replay`
+    #[arg(long)]
+    pub record_server: Option<PathBuf>,
+    /// Only let in the players with an auth token signed by this public key (hex encoded); can be repeated
+    ///
+    /// If no key is specified, the players join under the user ID they claim
+    #[arg(long = "token-key")]
+    pub token_keys: Vec<PublicKey>,
+    /// Only let in the players with an auth token issued for this audience (see `ambient issue-token --audience`)
+    #[arg(long)]
+    pub token_audience: Option<String>,
+    /// Only let in this player (by user ID); can be repeated
+    #[arg(long = "allow-user")]
+    pub allowed_users: Vec<String>,
+    /// Don't let in this player (by user ID); can be repeated
+    #[arg(long = "ban-user")]
+    pub banned_users: Vec<String>,
+    /// Register with the relay at this address, so that the players who can't reach this server directly join it through the relay
+    #[arg(long)]
+    pub relay: Option<SocketAddr>,
+    /// Let the players on the local network find this server with `ambient servers`
+    #[arg(long)]
+    pub lan: bool,
+    /// Announce this server to the server list at this URL, where the players find it with `ambient servers --server-list`
+    #[arg(long)]
+    pub server_list: Option<String>,
+    /// The name this server is listed under; defaults to the name of the project
+    #[arg(long)]
+    pub server_name: Option<String>,
+    /// Serve the CPU time of the server at `/metrics` to the requests with the bearer token in this file
+    ///
+    /// If no token is specified, `/metrics` isn't served
+    #[arg(long)]
+    pub metrics_token: Option<PathBuf>,
 }
 
 impl Cli {
@@ -87,8 +252,13 @@ impl Cli {
             Cli::Run { run_args, .. } => Some(run_args),
             Cli::Build { .. } => None,
             Cli::Serve { .. } => None,
+            Cli::Test { .. } => None,
             Cli::View { .. } => None,
             Cli::Join { run_args, .. } => Some(run_args),
+            Cli::Replay { .. } => None,
+            Cli::IssueToken { .. } => None,
+            Cli::Servers { .. } => None,
+            Cli::Relay { .. } => None,
             #[cfg(not(feature = "production"))]
             Cli::UpdateInterfaceComponents => None,
         }
@@ -100,8 +270,31 @@ impl Cli {
             Cli::Run { project_args, .. } => Some(project_args),
             Cli::Build { project_args, .. } => Some(project_args),
             Cli::Serve { project_args, .. } => Some(project_args),
+            Cli::Test { project_args, .. } => Some(project_args),
             Cli::View { project_args, .. } => Some(project_args),
             Cli::Join { .. } => None,
+            Cli::Replay { .. } => None,
+            Cli::IssueToken { .. } => None,
+            Cli::Servers { .. } => None,
+            Cli::Relay { .. } => None,
+            #[cfg(not(feature = "production"))]
+            Cli::UpdateInterfaceComponents => None,
+        }
+    }
+    /// Extract build-relevant state only
+    pub fn build(&self) -> Option<&BuildCli> {
+        match self {
+            Cli::New { .. } => None,
+            Cli::Run { .. } => None,
+            Cli::Build { build_args, .. } => Some(build_args),
+            Cli::Serve { .. } => None,
+            Cli::Test { .. } => None,
+            Cli::View { .. } => None,
+            Cli::Join { .. } => None,
+            Cli::Replay { .. } => None,
+            Cli::IssueToken { .. } => None,
+            Cli::Servers { .. } => None,
+            Cli::Relay { .. } => None,
             #[cfg(not(feature = "production"))]
             Cli::UpdateInterfaceComponents => None,
         }
@@ -113,8 +306,13 @@ impl Cli {
             Cli::Run { host_args, .. } => Some(host_args),
             Cli::Build { .. } => None,
             Cli::Serve { host_args, .. } => Some(host_args),
+            Cli::Test { .. } => None,
             Cli::View { .. } => None,
             Cli::Join { .. } => None,
+            Cli::Replay { .. } => None,
+            Cli::IssueToken { .. } => None,
+            Cli::Servers { .. } => None,
+            Cli::Relay { .. } => None,
             #[cfg(not(feature = "production"))]
             Cli::UpdateInterfaceComponents => None,
         }
@@ -1,40 +1,139 @@
 use std::{net::SocketAddr, sync::Arc};
 
 use ambient_app::{window_title, AppBuilder};
+use ambient_audio::{AudioMixer, AudioStream};
 use ambient_cameras::UICamera;
-use ambient_core::camera::active_camera;
+use ambient_core::{
+    asset_cache,
+    budget::{budget_monitor, budget_systems, BudgetMonitor, PerformanceBudget},
+    camera::active_camera,
+};
 use ambient_debugger::Debugger;
-use ambient_ecs::{EntityData, SystemGroup};
-use ambient_element::{element_component, Element, ElementComponentExt, Hooks};
+use ambient_discord::DiscordPresenceKey;
+use ambient_ecs::{EntityData, SystemGroup, WorldDiff};
+use ambient_editor::ui::replay::ReplayView;
+use ambient_element::{element_component, Element, ElementComponentExt, Hooks, Setter};
 use ambient_network::{
+    asset_stream::AssetStreamClient,
     client::{GameClient, GameClientNetworkStats, GameClientRenderTarget, GameClientServerStats, GameClientView, UseOnce},
     events::ServerEventRegistry,
+    messages::{message_types, MessageTypes},
+    recording::{SessionRecorder, SessionRecording},
+};
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKeyExt},
+    cb,
+    download_asset::AssetsCacheDir,
+    Cb,
 };
-use ambient_std::{asset_cache::AssetCache, cb};
-use ambient_ui::{use_window_physical_resolution, Dock, FocusRoot, StylesExt, Text, WindowSized};
+use ambient_ui::{use_window_physical_resolution, Dock, FocusRoot, ScrollArea, StylesExt, Text, UiStatePath, UiStateScope, WindowSized};
+use ambient_world_audio::{systems::setup_audio, voice::VoiceChat};
+use parking_lot::Mutex;
 
 use crate::shared;
 
 /// Construct an app and enter the main client view
-pub async fn run(assets: AssetCache, server_addr: SocketAddr, user_id: String, show_debug: bool) {
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    assets: AssetCache,
+    server_addr: SocketAddr,
+    user_id: String,
+    show_debug: bool,
+    budget: PerformanceBudget,
+    messages: MessageTypes,
+    recorder: Option<SessionRecorder>,
+    voice_chat: bool,
+    max_voices: usize,
+) {
+    UiStatePath.insert(&assets, AssetsCacheDir.get(&assets).join("ui_state.json"));
+    let recorder = recorder.map(|recorder| Arc::new(Mutex::new(recorder)));
+    let mixer = start_audio(max_voices);
+    AppBuilder::simple()
+        .ui_renderer(true)
+        .with_asset_cache(assets)
+        .run(|app, _runtime| {
+            MainApp { server_addr, user_id, show_debug, budget, messages, recorder, mixer, voice_chat }
+                .el()
+                .spawn_interactive(&mut app.world);
+        })
+        .await;
+}
+
+/// Construct an app showing a recorded session in the replay view of the editor
+pub async fn replay(assets: AssetCache, recording: SessionRecording) {
+    UiStatePath.insert(&assets, AssetsCacheDir.get(&assets).join("ui_state.json"));
     AppBuilder::simple()
         .ui_renderer(true)
         .with_asset_cache(assets)
         .run(|app, _runtime| {
-            MainApp { server_addr, user_id, show_debug }.el().spawn_interactive(&mut app.world);
+            *app.world.resource_mut(window_title()) = "Ambient replay".to_string();
+            FocusRoot::el([
+                UICamera.el().set(active_camera(), 0.),
+                WindowSized::el([UiStateScope::el("replay", ScrollArea::el(ReplayView { recording: Arc::new(recording) }.el()))]),
+            ])
+            .spawn_interactive(&mut app.world);
         })
         .await;
 }
 
+/// Plays the audio on the default output device for as long as the client runs. The stream can't be moved across
+/// threads, so it stays on a thread of its own.
+fn start_audio(max_voices: usize) -> Option<AudioMixer> {
+    let (tx, rx) = flume::bounded(1);
+    let spawned = std::thread::Builder::new().name("audio".to_string()).spawn(move || match AudioStream::new() {
+        Ok(stream) => {
+            stream.mixer().set_max_voices(max_voices);
+            tx.send(Ok(stream.mixer().clone())).ok();
+            loop {
+                std::thread::park();
+            }
+        }
+        Err(err) => {
+            tx.send(Err(err.to_string())).ok();
+        }
+    });
+    if let Err(err) = spawned {
+        log::warn!("Failed to start the audio thread: {err}");
+        return None;
+    }
+    match rx.recv() {
+        Ok(Ok(mixer)) => Some(mixer),
+        Ok(Err(err)) => {
+            log::warn!("Failed to start the audio, the game is silent: {err}");
+            None
+        }
+        Err(_) => None,
+    }
+}
+
 #[element_component]
-fn MainApp(hooks: &mut Hooks, server_addr: SocketAddr, user_id: String, show_debug: bool) -> Element {
+fn MainApp(
+    hooks: &mut Hooks,
+    server_addr: SocketAddr,
+    user_id: String,
+    show_debug: bool,
+    budget: PerformanceBudget,
+    messages: MessageTypes,
+    recorder: Option<Arc<Mutex<SessionRecorder>>>,
+    mixer: Option<AudioMixer>,
+    voice_chat: bool,
+) -> Element {
     let resolution = use_window_physical_resolution(hooks);
+    // Changes when joining another server from outside of the game
+    let (server_addr, _set_server_addr) = hooks.use_state(server_addr);
 
     hooks.provide_context(GameClientNetworkStats::default);
     hooks.provide_context(GameClientServerStats::default);
 
     *hooks.world.resource_mut(window_title()) = "Ambient".to_string();
 
+    // The blobs the server streams are kept with the rest of the downloads
+    let (asset_stream, _) = hooks.use_state_with(|world| AssetStreamClient::new(AssetsCacheDir.get(world.resource(asset_cache()))));
+
+    use_discord_join_requests(hooks, _set_server_addr.clone());
+    #[cfg(feature = "steam")]
+    let steam = use_steam_join_requests(hooks, _set_server_addr);
+
     FocusRoot::el([
         UICamera.el().set(active_camera(), 0.),
         shared::player::PlayerRawInputHandler.el(),
@@ -44,33 +143,98 @@ fn MainApp(hooks: &mut Hooks, server_addr: SocketAddr, user_id: String, show_deb
             resolution,
             on_disconnect: cb(move || {}),
             init_world: cb(UseOnce::new(Box::new(move |world, _render_target| {
-                world.add_resource(ambient_network::events::event_registry(), Arc::new(ServerEventRegistry::new()));
+                let event_registry = ServerEventRegistry::new();
+                event_registry.register(ambient_network::messages::receive_from_server);
+                world.add_resource(ambient_network::events::event_registry(), Arc::new(event_registry));
+                ambient_network::asset_stream::register_client_handler(world, asset_stream);
+                if let Some(mixer) = mixer {
+                    if let Err(err) = setup_audio(world, mixer) {
+                        log::warn!("Failed to set up the spatial audio: {err:?}");
+                    }
+                }
+                let voice = VoiceChat::default();
+                voice.set_transmitting(voice_chat);
+                ambient_world_audio::voice::register_client_handler(world, voice);
             }))),
             on_loaded: cb(move |_game_state, _game_client| Ok(Box::new(|| {}))),
             error_view: cb(move |error| Dock(vec![Text::el("Error").header_style(), Text::el(error)]).el()),
-            systems_and_resources: cb(|| (systems(), EntityData::new())),
+            systems_and_resources: cb(move || {
+                let resources = EntityData::new()
+                    .set(budget_monitor(), BudgetMonitor::new(budget.clone()))
+                    .set(message_types(), messages.clone())
+                    .set(shared::prediction::prediction_step(), shared::prediction::walk_step());
+                #[cfg(feature = "steam")]
+                let resources = match &steam {
+                    Some(steam) => resources.set(ambient_steam::steam_client(), steam.clone()),
+                    None => resources,
+                };
+                (systems(), resources)
+            }),
             create_rpc_registry: cb(shared::create_rpc_registry),
-            on_in_entities: None,
+            on_in_entities: recorder.map(|recorder| -> Cb<dyn Fn(&WorldDiff) + Sync + Send> {
+                cb(move |diff: &WorldDiff| {
+                    if let Err(err) = recorder.lock().record(diff) {
+                        log::warn!("Failed to record the diff: {err:?}");
+                    }
+                })
+            }),
             ui: GameView { show_debug }.el(),
         }
-        .el()]),
+        .el()
+        .key(server_addr.to_string())]),
     ])
 }
 
+/// Switches to the servers of the friends the player is accepted to join on Discord
+fn use_discord_join_requests(hooks: &mut Hooks, set_server_addr: Setter<SocketAddr>) {
+    let discord = DiscordPresenceKey.get(hooks.world.resource(asset_cache()));
+    hooks.use_frame(move |_| {
+        if let Some(address) = discord.join_requests().try_iter().last() {
+            match address.parse() {
+                Ok(addr) => set_server_addr(addr),
+                Err(err) => log::warn!("Can't join {address}: {err}"),
+            }
+        }
+    });
+}
+
+/// Switches to the servers the player asks to join from their Steam friends list
+#[cfg(feature = "steam")]
+fn use_steam_join_requests(hooks: &mut Hooks, set_server_addr: Setter<SocketAddr>) -> Option<ambient_steam::SteamClient> {
+    let steam = ambient_steam::SteamClientKey.try_get(hooks.world.resource(asset_cache()));
+    if let Some(steam) = steam.clone() {
+        hooks.use_frame(move |_| {
+            if let Some(host) = steam.join_requests().try_iter().last() {
+                match host.parse() {
+                    Ok(addr) => {
+                        steam.set_joinable(Some(&host));
+                        set_server_addr(addr);
+                    }
+                    Err(err) => log::warn!("Can't join {host}: {err}"),
+                }
+            }
+        });
+    }
+    steam
+}
+
 #[element_component]
 fn GameView(hooks: &mut Hooks, show_debug: bool) -> Element {
     let (state, _) = hooks.consume_context::<GameClient>().unwrap();
     let (render_target, _) = hooks.consume_context::<GameClientRenderTarget>().unwrap();
 
     if show_debug {
-        Debugger {
-            get_state: cb(move |cb| {
-                let mut game_state = state.game_state.lock();
-                let game_state = &mut *game_state;
-                cb(&mut game_state.renderer, &render_target.0, &mut game_state.world);
-            }),
-        }
-        .el()
+        UiStateScope::el(
+            "debugger",
+            Debugger {
+                get_state: cb(move |cb| {
+                    let mut game_state = state.game_state.lock();
+                    let game_state = &mut *game_state;
+                    cb(&mut game_state.renderer, &render_target.0, &mut game_state.world);
+                }),
+            }
+            .el(),
+        )
     } else {
         Element::new()
     }
@@ -81,11 +245,18 @@ fn systems() -> SystemGroup {
         "client",
         vec![
             Box::new(ambient_decals::client_systems()),
+            Box::new(ambient_renderer::custom_material::client_systems()),
             Box::new(ambient_primitives::systems()),
             Box::new(ambient_sky::systems()),
             Box::new(ambient_water::systems()),
             Box::new(ambient_physics::client_systems()),
             Box::new(shared::player::client_systems()),
+            Box::new(ambient_discord::client_systems()),
+            Box::new(ambient_video::client_systems()),
+            Box::new(ambient_world_audio::systems::client_systems()),
+            #[cfg(feature = "steam")]
+            Box::new(ambient_steam::client_systems()),
+            budget_systems(),
         ],
     )
 }
@@ -1,21 +1,72 @@
 use ambient_std::{
     asset_cache::{AssetCache, SyncAssetKeyExt},
     download_asset::AssetsCacheOnDisk,
+    download_cache::DownloadCacheDir,
     friendly_id,
+    signature::SigningKey,
 };
 use clap::Parser;
+use futures::future;
 
 mod cli;
 mod client;
 mod server;
 mod shared;
 
+use std::{sync::Arc, time::Duration};
+
+use ambient_network::{
+    auth::{
+        AuthToken, ClientAuthTicketKey, Identity, ServerAuthenticatorKey, ServerAuthorizerKey, TokenAuthenticator, TokenClaims,
+        UserListAuthorizer,
+    },
+    conditioner::NetworkConditionsPath,
+    discovery::{discover_lan_servers, HttpServerList, ServerList, ServerListKey},
+    recording::{SessionRecorder, SessionRecording},
+    relay::{Relay, RelayAddressKey},
+    server::ServerSettingsPath,
+};
 use ambient_physics::physx::PhysicsKey;
+use ambient_renderer::RendererSettingsPath;
 use anyhow::Context;
 use cli::Cli;
 use log::LevelFilter;
 use server::QUIC_INTERFACE_PORT;
 
+#[cfg(feature = "instrument-alloc")]
+#[global_allocator]
+static ALLOCATOR: ambient_std::instrumented_alloc::InstrumentedAllocator<std::alloc::System> =
+    ambient_std::instrumented_alloc::InstrumentedAllocator::new(std::alloc::System);
+
+/// Logs the growth of the allocations every 10 seconds, and starts the leak checks of the tags in `AMBIENT_LEAK_CHECK`
+/// (a comma-separated list); the outstanding allocations are dumped when the server shuts down
+#[cfg(feature = "instrument-alloc")]
+fn start_alloc_instrumentation(runtime: &tokio::runtime::Runtime) {
+    if let Ok(tags) = std::env::var("AMBIENT_LEAK_CHECK") {
+        for tag in tags.split(',').map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+            log::info!("Checking the allocations of {tag} for leaks");
+            ALLOCATOR.leak_check(Box::leak(tag.to_string().into_boxed_str()));
+        }
+    }
+    runtime.spawn(async {
+        let mut logger = ambient_std::instrumented_alloc::AllocGrowthLogger::new();
+        loop {
+            logger.log(&ALLOCATOR.stats());
+            tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+        }
+    });
+}
+
+/// Signs in with Steam, and only lets the players signed in with Steam connect to the servers hosted by this process
+#[cfg(feature = "steam")]
+fn start_steam(assets: &AssetCache, app_id: u32) -> anyhow::Result<()> {
+    let steam = ambient_steam::SteamClient::init(app_id)?;
+    ClientAuthTicketKey.insert(assets, Arc::new(steam.ticket_provider()));
+    ServerAuthenticatorKey.insert(assets, Arc::new(steam.authenticator()));
+    ambient_steam::SteamClientKey.insert(assets, steam);
+    Ok(())
+}
+
 fn main() -> anyhow::Result<()> {
     // Initialize the logger and lower the log level for modules we don't need to hear from by default.
     {
@@ -57,6 +108,8 @@ fn main() -> anyhow::Result<()> {
     }
     shared::components::init()?;
     let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    #[cfg(feature = "instrument-alloc")]
+    start_alloc_instrumentation(&runtime);
     let assets = AssetCache::new(runtime.handle().clone());
     PhysicsKey.get(&assets); // Load physics
     AssetsCacheOnDisk.insert(&assets, false); // Disable disk caching for now; see https://github.com/AmbientRun/Ambient/issues/81
@@ -68,6 +121,46 @@ fn main() -> anyhow::Result<()> {
     let project_path =
         if project_path.is_absolute() { project_path } else { ambient_std::path::normalize(&current_dir.join(project_path)) };
 
+    if let Some(download_cache) = cli.project().and_then(|p| p.download_cache.clone()) {
+        DownloadCacheDir.insert(&assets, download_cache);
+    }
+    if let Some(server_settings) = cli.host().and_then(|host| host.server_settings.clone()) {
+        ServerSettingsPath.insert(&assets, server_settings);
+    }
+    if let Some(renderer_settings) = cli.run().and_then(|run| run.renderer_settings.clone()) {
+        RendererSettingsPath.insert(&assets, renderer_settings);
+    }
+    if let Some(network_conditions) = cli.run().and_then(|run| run.network_conditions.clone()) {
+        NetworkConditionsPath.insert(&assets, network_conditions);
+    }
+    #[cfg(feature = "steam")]
+    if let Some(app_id) = cli.run().and_then(|run| run.steam_app_id) {
+        start_steam(&assets, app_id)?;
+    }
+    if let Some(url) = cli.host().and_then(|host| host.server_list.as_ref()) {
+        ServerListKey.insert(&assets, Arc::new(HttpServerList::new(assets.clone(), url)));
+    }
+    if let Some(host) = cli.host().filter(|host| !host.token_keys.is_empty()) {
+        let authenticator = TokenAuthenticator { trusted_keys: host.token_keys.clone(), audience: host.token_audience.clone() };
+        ServerAuthenticatorKey.insert(&assets, Arc::new(authenticator));
+    }
+    if let Some(host) = cli.host().filter(|host| !host.allowed_users.is_empty() || !host.banned_users.is_empty()) {
+        let authorizer = UserListAuthorizer {
+            allowed: host.allowed_users.iter().cloned().collect(),
+            banned: host.banned_users.iter().cloned().collect(),
+        };
+        ServerAuthorizerKey.insert(&assets, Arc::new(authorizer));
+    }
+    let auth_token = match cli.run().and_then(|run| run.auth_token.as_ref()) {
+        Some(path) => {
+            let token = AuthToken::parse(&std::fs::read(path).with_context(|| format!("Failed to read the auth token {path:?}"))?)?;
+            let user_id = token.unverified_claims()?.identity.user_id;
+            ClientAuthTicketKey.insert(&assets, Arc::new(token));
+            Some(user_id)
+        }
+        None => None,
+    };
+
     if project_path.exists() && !project_path.is_dir() {
         anyhow::bail!("Project path {project_path:?} exists and is not a directory.");
     }
@@ -80,6 +173,23 @@ fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
+    // If issuing a token: print it, immediately exit
+    if let Cli::IssueToken { signing_key, user_id, display_name, roles, valid_for_hours, audience } = &cli {
+        if let Some(hours) = valid_for_hours {
+            // A century is plenty, and keeps the expiry time representable
+            anyhow::ensure!(
+                *hours > 0. && *hours <= 100. * 365. * 24.,
+                "--valid-for-hours must be a positive number of hours, up to a century, not {hours}"
+            );
+        }
+        let key = SigningKey::load_or_generate(signing_key)?;
+        let identity = Identity { user_id: user_id.clone(), display_name: display_name.clone(), roles: roles.clone() };
+        let mut claims = TokenClaims::new(identity, valid_for_hours.map(|hours| Duration::from_secs_f64(hours as f64 * 3600.)));
+        claims.audience = audience.clone();
+        println!("{}", AuthToken::issue(&claims, &key).to_json());
+        return Ok(());
+    }
+
     // If UIC: write components to disk, immediately exit
     #[cfg(not(feature = "production"))]
     if let Cli::UpdateInterfaceComponents = cli {
@@ -98,6 +208,44 @@ fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
+    // If this is a replay, open it in its own window, and exit once it's closed
+    if let Cli::Replay { path } = &cli {
+        let recording = SessionRecording::load(path)?;
+        runtime.block_on(client::replay(assets, recording));
+        return Ok(());
+    }
+
+    // If listing the servers: print them, immediately exit
+    if let Cli::Servers { server_list, project_id } = &cli {
+        let servers = runtime.block_on(async {
+            match server_list {
+                Some(url) => HttpServerList::new(assets.clone(), url).query(project_id.as_deref()).await,
+                None => discover_lan_servers(Duration::from_secs(1)).await.map(|servers| {
+                    servers.into_iter().filter(|server| project_id.as_ref().map(|id| *id == server.project_id).unwrap_or(true)).collect()
+                }),
+            }
+        })?;
+        if servers.is_empty() {
+            println!("No server found");
+        }
+        for server in servers {
+            let max_players = server.max_players.map(|max| format!("/{max}")).unwrap_or_default();
+            println!("{}\t{}\t{}{max_players} players\t{}", server.address, server.name, server.players, server.project_id);
+        }
+        return Ok(());
+    }
+
+    // If this is a relay, run it until it fails or is interrupted
+    if let Cli::Relay { port } = &cli {
+        return runtime.block_on(async move {
+            let relay = Relay::new_with_port(*port).await?;
+            tokio::select! {
+                result = relay.run() => result,
+                _ = tokio::signal::ctrl_c() => Ok(()),
+            }
+        });
+    }
+
     // If a project was specified, assume that assets need to be built
     let manifest = cli
         .project()
@@ -111,7 +259,12 @@ fn main() -> anyhow::Result<()> {
     if let Some(manifest) = manifest.as_ref() {
         let project_name = manifest.project.name.as_deref().unwrap_or("project");
         log::info!("Building {}", project_name);
-        runtime.block_on(ambient_build::build(PhysicsKey.get(&assets), &assets, project_path.clone(), manifest));
+        let filter = cli.build().map(|build| build.pipeline_filter()).unwrap_or_default();
+        let signing_key = match cli.build().and_then(|build| build.signing_key.as_ref()) {
+            Some(path) => Some(Arc::new(SigningKey::load_or_generate(path)?)),
+            None => None,
+        };
+        runtime.block_on(ambient_build::build(PhysicsKey.get(&assets), &assets, project_path.clone(), manifest, filter, signing_key));
         log::info!("Done building {}", project_name);
     }
 
@@ -120,8 +273,25 @@ fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
+    // If this is a headless test, run it and exit with its result
+    if let Cli::Test { ticks, players, .. } = &cli {
+        let manifest = manifest.as_ref().expect("no manifest");
+        if let Err(err) = runtime.block_on(server::run_headless(assets, project_path, manifest, *ticks, *players)) {
+            log::error!("{err:?}");
+            std::process::exit(1);
+        }
+        log::info!("Headless run passed");
+        #[cfg(feature = "instrument-alloc")]
+        ALLOCATOR.dump_leaks();
+        return Ok(());
+    }
+
     // Otherwise, either connect to a server or host one
-    let server_addr = if let Cli::Join { host, .. } = &cli {
+    let status_view = if let Cli::Serve { status_view: true, .. } = &cli { Some(flume::bounded(1)) } else { None };
+    let server_addr = if let Cli::Join { host, relay, .. } = &cli {
+        if let Some(relay) = relay {
+            RelayAddressKey.insert(&assets, *relay);
+        }
         if let Some(mut host) = host.clone() {
             if !host.contains(':') {
                 host = format!("{host}:{QUIC_INTERFACE_PORT}");
@@ -131,7 +301,17 @@ fn main() -> anyhow::Result<()> {
             format!("127.0.0.1:{QUIC_INTERFACE_PORT}").parse()?
         }
     } else {
-        let port = server::start(&runtime, assets.clone(), cli.clone(), project_path, manifest.as_ref().expect("no manifest"));
+        let recorder = cli.host().and_then(|host| host.record_server.as_ref()).map(SessionRecorder::create).transpose()?;
+        let state_listener = status_view.as_ref().map(|(state_tx, _)| state_tx.clone());
+        let port = server::start(
+            &runtime,
+            assets.clone(),
+            cli.clone(),
+            project_path,
+            manifest.as_ref().expect("no manifest"),
+            recorder,
+            state_listener,
+        );
         format!("127.0.0.1:{port}").parse()?
     };
 
@@ -139,16 +319,49 @@ fn main() -> anyhow::Result<()> {
     let handle = runtime.handle().clone();
     if let Some(run) = cli.run() {
         // If we have run parameters, start a client and join a server
-        let user_id = run.user_id.clone().unwrap_or_else(|| format!("user_{}", friendly_id()));
-        runtime.block_on(client::run(assets, server_addr, user_id, run.debug));
+        let user_id = auth_token.or_else(|| run.user_id.clone()).unwrap_or_else(|| format!("user_{}", friendly_id()));
+        #[cfg(feature = "steam")]
+        let user_id = match ambient_steam::SteamClientKey.try_get(&assets) {
+            Some(steam) => {
+                let host = if matches!(cli, Cli::Join { .. }) {
+                    server_addr.to_string()
+                } else {
+                    format!("{}:{}", server::public_host(&cli), server_addr.port())
+                };
+                steam.set_joinable(Some(&host));
+                steam.user_id()
+            }
+            None => user_id,
+        };
+        let budget = manifest.as_ref().map(|manifest| shared::performance_budget(&manifest.budget)).unwrap_or_default();
+        // A client joining without the project receives the messages of any version
+        let messages = manifest.as_ref().map(|manifest| shared::message_types(&manifest.messages)).unwrap_or_default();
+        let recorder = run.record.as_ref().map(SessionRecorder::create).transpose()?;
+        runtime.block_on(client::run(assets, server_addr, user_id, run.debug, budget, messages, recorder, run.voice_chat, run.max_voices));
     } else {
-        // Otherwise, wait for the Ctrl+C signal
+        // Otherwise, wait for the Ctrl+C signal, or for the operator to quit the status view
         handle.block_on(async move {
-            match tokio::signal::ctrl_c().await {
-                Ok(()) => {}
-                Err(err) => log::error!("Unable to listen for shutdown signal: {}", err),
+            let status_view = async {
+                match status_view {
+                    Some((_, state_rx)) => server::status_view::run(state_rx, server_addr.port()).await,
+                    None => future::pending().await,
+                }
+            };
+            tokio::select! {
+                result = tokio::signal::ctrl_c() => {
+                    if let Err(err) = result {
+                        log::error!("Unable to listen for shutdown signal: {}", err);
+                    }
+                }
+                result = status_view => {
+                    if let Err(err) = result {
+                        log::error!("The status view failed: {:?}", err);
+                    }
+                }
             }
         });
+        #[cfg(feature = "instrument-alloc")]
+        ALLOCATOR.dump_leaks();
     }
     Ok(())
 }
@@ -0,0 +1,203 @@
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use ambient_core::{
+    budget::{budget_monitor, BudgetMonitor},
+    no_sync,
+};
+use ambient_ecs::{
+    query, ArchetypeFilter, ComponentRegistry, EntityData, FrameEvent, System, World, WorldDiff, WorldStream, WorldStreamFilter,
+};
+use ambient_network::{
+    asset_stream::AssetStreamServer,
+    server::{create_player_entity_data, player_entity_stream},
+};
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKeyExt},
+    asset_manifest::AssetManifestKey,
+    asset_url::{AbsAssetUrl, ServerBaseUrlKey},
+};
+use anyhow::Context;
+use flume::Receiver;
+use tokio::time::{interval, MissedTickBehavior};
+
+use super::{create_resources, is_sync_component, load_asset_manifest, systems, wasm};
+use crate::shared;
+
+/// A player which is connected to the headless server without a network connection; it keeps its own copy of the
+/// replicated world up to date, like a real client would.
+struct VirtualPlayer {
+    world: World,
+    entities_rx: Receiver<Vec<u8>>,
+}
+impl VirtualPlayer {
+    /// Spawns the player entity of a new virtual player on the server, which starts from the whole replicated world
+    fn connect(world: &mut World, world_stream_filter: &WorldStreamFilter, user_id: &str) -> anyhow::Result<Self> {
+        let (entities_tx, entities_rx) = flume::unbounded();
+        let (events_tx, _) = flume::unbounded();
+        let (stats_tx, _) = flume::unbounded();
+        let (datagrams_tx, _) = flume::unbounded();
+        entities_tx.send(bincode::serialize(&world_stream_filter.initial_diff(world))?)?;
+        create_player_entity_data(user_id, entities_tx, events_tx, stats_tx, datagrams_tx).spawn(world);
+        Ok(Self { world: World::new("virtual_player"), entities_rx })
+    }
+    fn receive_diffs(&mut self) -> anyhow::Result<()> {
+        for msg in self.entities_rx.try_iter() {
+            let diff: WorldDiff = bincode::deserialize(&msg).context("Failed to deserialize world diff")?;
+            diff.apply(&mut self.world, EntityData::new(), false);
+        }
+        Ok(())
+    }
+}
+
+/// Sends the changes of the replicated world since the last frame to all the players
+fn send_diff(world: &World, world_stream: &mut WorldStream) -> anyhow::Result<()> {
+    let diff = world_stream.next_diff(world);
+    if !diff.is_empty() {
+        let msg = bincode::serialize(&diff)?;
+        for (_, (stream,)) in query((player_entity_stream(),)).iter(world, None) {
+            stream.send(msg.clone()).ok();
+        }
+    }
+    Ok(())
+}
+
+/// Runs the server of the project for `ticks` frames, without rendering or networking, with `players` virtual players
+/// connected to it.
+///
+/// Scripts make their assertions with `assert!` (or by panicking); the run fails if any script reported an error, or if the
+/// performance budget of the project was exceeded.
+pub async fn run_headless(
+    assets: AssetCache,
+    project_path: PathBuf,
+    manifest: &ambient_project::Manifest,
+    ticks: u32,
+    players: u32,
+) -> anyhow::Result<()> {
+    wasm::init_all_components();
+    ServerBaseUrlKey.insert(&assets, AbsAssetUrl::from_directory_path(project_path.join("build")));
+    if let Some(asset_manifest) = load_asset_manifest(&project_path)? {
+        AssetManifestKey.insert(&assets, Arc::new(asset_manifest));
+    }
+    ComponentRegistry::get_mut().add_external(manifest.all_defined_components(false).map_err(|err| anyhow::anyhow!(err))?);
+
+    let mut world = World::new_with_config("headless_server", true);
+    world.init_shape_change_tracking();
+    let monitor = BudgetMonitor::new(shared::performance_budget(&manifest.budget));
+    // Without networking, nothing is streamed
+    let asset_stream = AssetStreamServer::new(None);
+    world.add_components(
+        world.resource_entity(),
+        create_resources(assets.clone(), monitor, shared::message_types(&manifest.messages), asset_stream),
+    )?;
+    wasm::initialize(&mut world, project_path, manifest, &[]).await?;
+
+    let world_stream_filter = WorldStreamFilter::new(ArchetypeFilter::new().excl(no_sync()), Arc::new(is_sync_component));
+    let mut world_stream = WorldStream::new(world_stream_filter.clone());
+    // Bring the world stream up to date; the players start from the initial diff
+    world_stream.next_diff(&world);
+    let mut virtual_players = (0..players)
+        .map(|i| VirtualPlayer::connect(&mut world, &world_stream_filter, &format!("virtual_player_{i}")))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    log::info!("Running {ticks} ticks with {players} virtual players");
+    let mut systems = systems(&mut world);
+    let mut sim_interval = interval(Duration::from_secs_f32(1. / 60.));
+    sim_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    for _ in 0..ticks {
+        sim_interval.tick().await;
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
+        world.set(world.resource_entity(), ambient_core::time(), now)?;
+        systems.run(&mut world, &FrameEvent);
+        world.next_frame();
+        send_diff(&world, &mut world_stream)?;
+        for player in &mut virtual_players {
+            player.receive_diffs()?;
+        }
+    }
+    log::info!("Ran {ticks} ticks; {} entities on the server", world.len());
+    log::info!("{}", world.resource(budget_monitor()).report());
+
+    let mut failures = world.resource(wasm::script_errors()).lock().clone();
+    failures
+        .extend(world.resource(budget_monitor()).violations().iter().map(|violation| format!("Performance budget exceeded: {violation}")));
+    if !failures.is_empty() {
+        anyhow::bail!("Headless run failed:\n{}", failures.join("\n"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ambient_ecs::{components, Networked, Serializable};
+
+    use super::*;
+
+    components!("test", {
+        @[Networked, Serializable]
+        health: f32,
+        secret: f32,
+    });
+
+    fn server_world() -> (World, WorldStreamFilter, WorldStream) {
+        ambient_core::init_all_components();
+        ambient_network::init_all_components();
+        init_components();
+        let mut world = World::new_with_config("headless_server", true);
+        world.init_shape_change_tracking();
+        let world_stream_filter = WorldStreamFilter::new(ArchetypeFilter::new().excl(no_sync()), Arc::new(is_sync_component));
+        let mut world_stream = WorldStream::new(world_stream_filter.clone());
+        world_stream.next_diff(&world);
+        (world, world_stream_filter, world_stream)
+    }
+
+    #[test]
+    fn virtual_players_replicate_the_world() {
+        let (mut world, world_stream_filter, mut world_stream) = server_world();
+        let monster = EntityData::new().set(health(), 10.).set(secret(), 1.).spawn(&mut world);
+        world.next_frame();
+        world_stream.next_diff(&world);
+
+        let mut players = ["first", "second"]
+            .into_iter()
+            .map(|user_id| VirtualPlayer::connect(&mut world, &world_stream_filter, user_id))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(query(player_entity_stream()).iter(&world, None).count(), 2);
+        for player in &mut players {
+            player.receive_diffs().unwrap();
+            assert_eq!(player.world.get(monster, health()).unwrap(), 10.);
+            assert!(!player.world.has_component(monster, secret()));
+        }
+
+        world.set(monster, health(), 5.).unwrap();
+        let grunt = EntityData::new().set(health(), 3.).spawn(&mut world);
+        world.next_frame();
+        send_diff(&world, &mut world_stream).unwrap();
+        for player in &mut players {
+            player.receive_diffs().unwrap();
+            assert_eq!(player.world.get(monster, health()).unwrap(), 5.);
+            assert_eq!(player.world.get(grunt, health()).unwrap(), 3.);
+        }
+
+        world.despawn(grunt);
+        world.next_frame();
+        send_diff(&world, &mut world_stream).unwrap();
+        for player in &mut players {
+            player.receive_diffs().unwrap();
+            assert!(!player.world.exists(grunt));
+        }
+    }
+
+    #[test]
+    fn virtual_players_fail_on_invalid_diffs() {
+        let (mut world, world_stream_filter, _) = server_world();
+        let mut player = VirtualPlayer::connect(&mut world, &world_stream_filter, "player").unwrap();
+        let (_, (stream,)) = query((player_entity_stream(),)).iter(&world, None).next().unwrap();
+        stream.send(vec![0xff; 3]).unwrap();
+        assert!(player.receive_diffs().is_err());
+    }
+}
@@ -6,68 +6,137 @@ use std::{
     time::SystemTime,
 };
 
-use ambient_core::{app_start_time, asset_cache, dtime, no_sync, time};
-use ambient_ecs::{world_events, ComponentDesc, ComponentRegistry, EntityData, Networked, SystemGroup, World, WorldStreamCompEvent};
+use ambient_core::{
+    app_start_time, asset_cache,
+    budget::{budget_monitor, budget_systems, budgeted, BudgetMonitor, BudgetReport},
+    dtime, no_sync, time,
+};
+use ambient_ecs::{
+    world_events, ComponentDesc, ComponentRegistry, EntityData, SerializationProfile, SystemGroup, World, WorldStreamCompEvent,
+};
 use ambient_network::{
+    asset_stream::{asset_stream_server, AssetStreamServer},
     bi_stream_handlers, datagram_handlers,
-    server::{ForkingEvent, GameServer, ShutdownEvent},
+    discovery::ServerAnnouncement,
+    messages::{message_types, MessageTypes},
+    recording::SessionRecorder,
+    relay::RelayListener,
+    server::{ForkingEvent, GameServer, SharedServerState, ShutdownEvent},
 };
 use ambient_prefab::PrefabFromUrl;
 use ambient_std::{
     asset_cache::{AssetCache, AsyncAssetKeyExt, SyncAssetKeyExt},
-    asset_url::{AbsAssetUrl, ServerBaseUrlKey},
+    asset_manifest::{AssetManifest, AssetManifestKey},
+    asset_url::{AbsAssetUrl, ServerBaseUrlKey, ServerContentDirKey},
 };
 use ambient_sys::task::RuntimeHandle;
 use anyhow::Context;
 use axum::{
-    http::{Method, StatusCode},
+    http::{header::AUTHORIZATION, HeaderMap, Method, StatusCode},
     response::IntoResponse,
     routing::{get, get_service},
     Router,
 };
+use parking_lot::Mutex;
 use tower_http::{cors::CorsLayer, services::ServeDir};
 
 use crate::{cli::Cli, shared};
 
+mod headless;
+pub mod status_view;
 mod wasm;
 
+pub use headless::run_headless;
+
 pub fn start(
     runtime: &tokio::runtime::Runtime,
     assets: AssetCache,
     cli: Cli,
     project_path: PathBuf,
     manifest: &ambient_project::Manifest,
+    recorder: Option<SessionRecorder>,
+    state_listener: Option<flume::Sender<SharedServerState>>,
 ) -> u16 {
     log::info!("Creating server");
-    let server = runtime.block_on(async move {
+    let mut server = runtime.block_on(async move {
         GameServer::new_with_port_in_range(QUIC_INTERFACE_PORT..(QUIC_INTERFACE_PORT + 10))
             .await
             .context("failed to create game server with port in range")
             .unwrap()
     });
+    server.recorder = recorder;
+    server.state_listener = state_listener;
     let port = server.port;
+    if let Some(relay_addr) = cli.host().and_then(|host| host.relay) {
+        match runtime.block_on(RelayListener::register(relay_addr, port)) {
+            Ok(relay) => {
+                log::info!(
+                    "Registered with the relay at {relay_addr}; players can join at {} with `--relay {relay_addr}`",
+                    relay.session()
+                );
+                server.relay = Some(relay);
+            }
+            Err(err) => log::error!("Failed to register with the relay at {relay_addr}: {err}"),
+        }
+    }
 
     wasm::init_all_components();
-    let public_host = cli
-        .host()
-        .and_then(|h| h.public_host.clone())
-        .or_else(|| local_ip_address::local_ip().ok().map(|x| x.to_string()))
-        .unwrap_or("localhost".to_string());
+    let public_host = public_host(&cli);
     log::info!("Created server, running at {public_host}:{port}");
+    if let Some(host) = cli.host().filter(|host| host.lan || host.server_list.is_some()) {
+        server.announcement = Some(ServerAnnouncement {
+            name: host.server_name.clone().or_else(|| manifest.project.name.clone()).unwrap_or_else(|| manifest.project.id.to_string()),
+            project_id: manifest.project.id.to_string(),
+            max_players: None,
+            address: format!("{public_host}:{port}"),
+            lan: host.lan,
+        });
+    }
     ServerBaseUrlKey.insert(&assets, AbsAssetUrl::parse(format!("http://{public_host}:{HTTP_INTERFACE_PORT}/content/")).unwrap());
+    ServerContentDirKey.insert(&assets, Some(project_path.join("build")));
 
-    start_http_interface(runtime, &project_path);
+    let budget_monitor = BudgetMonitor::new(shared::performance_budget(&manifest.budget));
+    let messages = shared::message_types(&manifest.messages);
+    let metrics_token = cli.host().and_then(|host| host.metrics_token.as_ref()).and_then(|path| {
+        match std::fs::read_to_string(path).with_context(|| format!("Failed to read the metrics token from {path:?}")) {
+            Ok(token) => Some(token.trim().to_string()),
+            Err(err) => {
+                log::error!("{err:#}; /metrics won't be served");
+                None
+            }
+        }
+    });
+    start_http_interface(runtime, &project_path, budget_monitor.shared_report(), metrics_token);
+
+    // The clients may fetch the content over the game connection too, if they can't reach the HTTP interface
+    let asset_stream = AssetStreamServer::new(None);
+    asset_stream.serve_content(ServerBaseUrlKey.get(&assets), project_path.join("build"));
+    match load_asset_manifest(&project_path) {
+        Ok(Some(asset_manifest)) => {
+            asset_stream.serve_manifest(&project_path.join("build"), &asset_manifest);
+            AssetManifestKey.insert(&assets, Arc::new(asset_manifest));
+        }
+        Ok(None) => {}
+        Err(err) => log::warn!("Failed to load asset manifest: {:?}", err),
+    }
 
     ComponentRegistry::get_mut().add_external(manifest.all_defined_components(false).unwrap());
 
     let manifest = manifest.clone();
+    let join_address = format!("{public_host}:{port}");
     runtime.spawn(async move {
         let mut server_world = World::new_with_config("server", true);
         server_world.init_shape_change_tracking();
 
-        server_world.add_components(server_world.resource_entity(), create_resources(assets.clone())).unwrap();
+        server_world
+            .add_components(server_world.resource_entity(), create_resources(assets.clone(), budget_monitor, messages, asset_stream))
+            .unwrap();
+        if let Some(discord_activity) = ambient_discord::activity_entity_data(&manifest, join_address) {
+            discord_activity.spawn(&mut server_world);
+        }
 
-        wasm::initialize(&mut server_world, project_path.clone(), &manifest).await.unwrap();
+        let trusted_keys = cli.host().map(|h| h.trusted_keys.clone()).unwrap_or_default();
+        wasm::initialize(&mut server_world, project_path.clone(), &manifest, &trusted_keys).await.unwrap();
 
         if let Cli::View { asset_path, .. } = cli.clone() {
             let asset_path = AbsAssetUrl::from_file_path(project_path.join("build").join(asset_path).join("prefabs/main.json"));
@@ -83,23 +152,37 @@ pub fn start(
     port
 }
 
+/// The host players connect to from other machines
+pub fn public_host(cli: &Cli) -> String {
+    cli.host()
+        .and_then(|h| h.public_host.clone())
+        .or_else(|| local_ip_address::local_ip().ok().map(|x| x.to_string()))
+        .unwrap_or("localhost".to_string())
+}
+
 fn systems(_world: &mut World) -> SystemGroup {
     SystemGroup::new(
         "server",
         vec![
-            ambient_physics::run_simulation_system(),
+            // The inputs of the players move their entities before the physics step
+            budgeted("prediction", Box::new(shared::prediction::server_systems())),
+            budgeted("run_simulation", ambient_physics::run_simulation_system()),
             // Can happen *during* the physics step
-            Box::new(ambient_core::async_ecs::async_ecs_systems()),
-            Box::new(ambient_prefab::systems()),
+            budgeted("async_ecs", Box::new(ambient_core::async_ecs::async_ecs_systems())),
+            budgeted("prefab", Box::new(ambient_prefab::systems())),
+            budgeted("prefab_instances", Box::new(ambient_prefab::instance_systems())),
+            budgeted("prefab_levels", Box::new(ambient_prefab::level_systems())),
             // Happens after the physics step
-            ambient_physics::fetch_simulation_system(),
-            Box::new(ambient_physics::physx::sync_ecs_physics()),
-            Box::new(ambient_core::transform::TransformSystem::new()),
-            ambient_core::remove_at_time_system(),
-            Box::new(ambient_physics::server_systems()),
-            Box::new(shared::player::server_systems()),
-            Box::new(wasm::systems()),
-            Box::new(shared::player::server_systems_final()),
+            budgeted("fetch_simulation", ambient_physics::fetch_simulation_system()),
+            budgeted("sync_ecs_physics", Box::new(ambient_physics::physx::sync_ecs_physics())),
+            budgeted("transform", Box::new(ambient_core::transform::TransformSystem::new())),
+            budgeted("remove_at_time", ambient_core::remove_at_time_system()),
+            budgeted("physics", Box::new(ambient_physics::server_systems())),
+            budgeted("player", Box::new(shared::player::server_systems())),
+            budgeted("video", Box::new(ambient_video::server_systems())),
+            budgeted("wasm", Box::new(wasm::systems())),
+            budgeted("player_final", Box::new(shared::player::server_systems_final())),
+            budget_systems(),
         ],
     )
 }
@@ -111,11 +194,18 @@ fn on_shutdown_systems() -> SystemGroup<ShutdownEvent> {
 }
 
 fn is_sync_component(component: ComponentDesc, _: WorldStreamCompEvent) -> bool {
-    component.has_attribute::<Networked>()
+    SerializationProfile::REPLICATION.includes(component)
 }
 
-fn create_resources(assets: AssetCache) -> EntityData {
-    let mut server_resources = EntityData::new().set(asset_cache(), assets.clone()).set(no_sync(), ()).set_default(world_events());
+fn create_resources(assets: AssetCache, monitor: BudgetMonitor, messages: MessageTypes, asset_stream: AssetStreamServer) -> EntityData {
+    let mut server_resources = EntityData::new()
+        .set(asset_cache(), assets.clone())
+        .set(no_sync(), ())
+        .set_default(world_events())
+        .set(budget_monitor(), monitor)
+        .set(message_types(), messages)
+        .set(asset_stream_server(), asset_stream.clone())
+        .set(shared::prediction::prediction_step(), shared::prediction::walk_step());
 
     ambient_physics::create_server_resources(&assets, &mut server_resources);
 
@@ -126,13 +216,18 @@ fn create_resources(assets: AssetCache) -> EntityData {
     server_resources.set_self(app_start_time(), now);
     server_resources.set_self(dtime(), 1. / 60.);
 
-    let mut handlers = HashMap::new();
-    ambient_network::register_rpc_bi_stream_handler(&mut handlers, shared::create_rpc_registry());
-    server_resources.set_self(bi_stream_handlers(), handlers);
+    let mut bi_handlers = HashMap::new();
+    ambient_network::register_rpc_bi_stream_handler(&mut bi_handlers, shared::create_rpc_registry());
+
+    let mut datagram_handlers = HashMap::new();
+    shared::player::register_datagram_handler(&mut datagram_handlers);
+    ambient_network::ownership::register_datagram_handler(&mut datagram_handlers);
 
-    let mut handlers = HashMap::new();
-    shared::player::register_datagram_handler(&mut handlers);
-    server_resources.set_self(datagram_handlers(), handlers);
+    ambient_network::messages::register_handlers(&mut bi_handlers, &mut datagram_handlers);
+    ambient_network::asset_stream::register_handlers(&mut bi_handlers, &mut datagram_handlers, asset_stream);
+    ambient_world_audio::voice::register_handlers(&mut bi_handlers, &mut datagram_handlers);
+    server_resources.set_self(bi_stream_handlers(), bi_handlers);
+    server_resources.set_self(self::datagram_handlers(), datagram_handlers);
 
     server_resources
 }
@@ -140,9 +235,34 @@ fn create_resources(assets: AssetCache) -> EntityData {
 pub const HTTP_INTERFACE_PORT: u16 = 8999;
 pub const QUIC_INTERFACE_PORT: u16 = 9000;
 
-fn start_http_interface(runtime: &tokio::runtime::Runtime, project_path: &Path) {
-    let router = Router::new()
-        .route("/ping", get(|| async move { "ok" }))
+fn load_asset_manifest(project_path: &Path) -> anyhow::Result<Option<AssetManifest>> {
+    let path = project_path.join("build").join(AssetManifest::FILE_NAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(&std::fs::read(&path)?).with_context(|| format!("Failed to parse {path:?}"))?))
+}
+
+fn start_http_interface(
+    runtime: &tokio::runtime::Runtime,
+    project_path: &Path,
+    budget_report: Arc<Mutex<BudgetReport>>,
+    metrics_token: Option<String>,
+) {
+    let mut router = Router::new().route("/ping", get(|| async move { "ok" }));
+    if let Some(token) = metrics_token.filter(|token| !token.is_empty()) {
+        // The CPU time of the server's systems and WASM modules, for the operators to find what slows the server down
+        router = router.route(
+            "/metrics",
+            get(move |headers: HeaderMap| async move {
+                if !is_bearer(&headers, &token) {
+                    return Err(StatusCode::UNAUTHORIZED);
+                }
+                Ok(budget_report.lock().to_prometheus())
+            }),
+        );
+    }
+    let router = router
         .nest_service("/content", get_service(ServeDir::new(project_path.join("build"))).handle_error(handle_error))
         .layer(CorsLayer::new().allow_origin(tower_http::cors::Any).allow_methods(vec![Method::GET]).allow_headers(tower_http::cors::Any));
 
@@ -152,6 +272,12 @@ fn start_http_interface(runtime: &tokio::runtime::Runtime, project_path: &Path)
     });
 }
 
+/// Whether the request with `headers` is authorized with the bearer `token`, which is compared in constant time
+fn is_bearer(headers: &HeaderMap, token: &str) -> bool {
+    let given = headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("Bearer "));
+    given.map_or(false, |given| given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0)
+}
+
 async fn handle_error(_err: std::io::Error) -> impl IntoResponse {
     (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong...")
 }
@@ -0,0 +1,161 @@
+//! A status view of the server in the terminal, for the operators of headless servers: it's redrawn every second, and
+//! takes the admin commands typed on stdin.
+
+use std::{
+    fmt::Write,
+    io::{BufRead, Write as _},
+    time::{Duration, Instant},
+};
+
+use ambient_network::{
+    server::SharedServerState,
+    status::{run_admin_command, server_status, AdminCommand, ServerStatus, ADMIN_COMMANDS_HELP},
+};
+use ambient_std::to_byte_unit;
+use flume::Receiver;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shows the status of the server once it runs, until the operator quits
+pub async fn run(state_rx: Receiver<SharedServerState>, port: u16) -> anyhow::Result<()> {
+    let state = state_rx.recv_async().await?;
+    let start = Instant::now();
+
+    // Stdin is read on its own thread, as reading it blocks
+    let (line_tx, line_rx) = flume::unbounded();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            match line {
+                Ok(line) => {
+                    if line_tx.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    });
+
+    let mut output = format!("Type a command ({ADMIN_COMMANDS_HELP}), help or quit");
+    let mut stdin_open = true;
+    let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            line = line_rx.recv_async(), if stdin_open => {
+                let line = match line {
+                    Ok(line) => line,
+                    // Stdin was closed, e.g. when it isn't a terminal; the view keeps running without the commands
+                    Err(_) => {
+                        stdin_open = false;
+                        continue;
+                    }
+                };
+                match line.trim() {
+                    "" => {}
+                    "quit" => return Ok(()),
+                    "help" => output = format!("The commands are: {ADMIN_COMMANDS_HELP}, help and quit"),
+                    line => {
+                        output = match line.parse::<AdminCommand>().and_then(|command| run_admin_command(&mut state.lock(), command)) {
+                            Ok(output) => output,
+                            Err(err) => format!("Error: {err:#}"),
+                        };
+                    }
+                }
+            }
+        }
+        let status = server_status(&state.lock());
+        let mut stdout = std::io::stdout().lock();
+        // Clears the terminal, and moves the cursor back to the top
+        write!(stdout, "\x1b[2J\x1b[H{}", render(&status, port, start.elapsed(), &output))?;
+        stdout.flush()?;
+    }
+}
+
+fn render(status: &ServerStatus, port: u16, uptime: Duration, output: &str) -> String {
+    let mut text = String::new();
+    let uptime = uptime.as_secs();
+    writeln!(text, "Ambient server on port {port}, up for {}:{:02}:{:02}", uptime / 3600, uptime / 60 % 60, uptime % 60).unwrap();
+    match &status.tick {
+        Some(tick) => writeln!(
+            text,
+            "Tick rate: {:.1}/s, {:.2}ms per tick, slowest {:.2}ms",
+            tick.fps(),
+            tick.active_frame_time_ms(),
+            tick.slowest_frame.as_secs_f32() * 1000.
+        )
+        .unwrap(),
+        None => writeln!(text, "Tick rate: -").unwrap(),
+    }
+    writeln!(
+        text,
+        "Memory: {}, bandwidth: {}/s out, {}/s in",
+        status.memory.map(to_byte_unit).unwrap_or_else(|| "-".to_string()),
+        to_byte_unit(status.bytes_sent_per_second()),
+        to_byte_unit(status.bytes_received_per_second())
+    )
+    .unwrap();
+
+    writeln!(text, "\nRooms").unwrap();
+    for room in &status.rooms {
+        writeln!(text, "  {:<20} {:>8} entities {:>4} players", room.id, room.entities, room.players).unwrap();
+    }
+
+    writeln!(text, "\nPlayers ({})", status.players.len()).unwrap();
+    writeln!(text, "  {:<24} {:<16} {:<10} {:>8} {:>7} {:>10} {:>10}", "User", "Room", "Transport", "Ping", "Loss", "Out", "In").unwrap();
+    for player in &status.players {
+        let name = match &player.display_name {
+            Some(display_name) => format!("{display_name} ({})", player.user_id),
+            None => player.user_id.clone(),
+        };
+        let transport = match player.transport {
+            Some(_) if player.connection_lost => "lost".to_string(),
+            Some(transport) => transport.to_string(),
+            None => "local".to_string(),
+        };
+        let (ping, loss, sent, received) = match &player.stats {
+            Some(stats) => (
+                format!("{}ms", stats.rtt.as_millis()),
+                format!("{:.1}%", stats.packet_loss * 100.),
+                format!("{}/s", to_byte_unit(stats.bytes_sent_per_second)),
+                format!("{}/s", to_byte_unit(stats.bytes_received_per_second)),
+            ),
+            None => Default::default(),
+        };
+        writeln!(text, "  {name:<24} {:<16} {transport:<10} {ping:>8} {loss:>7} {sent:>10} {received:>10}", player.room).unwrap();
+    }
+
+    write!(text, "\n{output}\n> ").unwrap();
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use ambient_network::{status::PlayerStatus, transport::Transport};
+
+    use super::*;
+
+    #[test]
+    fn render_status() {
+        let player = |user_id: &str, transport, connection_lost| PlayerStatus {
+            user_id: user_id.to_string(),
+            display_name: None,
+            room: "main".to_string(),
+            transport,
+            connection_lost,
+            stats: None,
+        };
+        let status = ServerStatus {
+            tick: None,
+            rooms: Vec::new(),
+            players: vec![player("host", None, false), player("guest", Some(Transport::Quic), true)],
+            memory: None,
+        };
+        let text = render(&status, 9000, Duration::from_secs(3725), "Moving guest to arena");
+        assert!(text.starts_with("Ambient server on port 9000, up for 1:02:05\nTick rate: -\n"), "{text}");
+        assert!(text.contains("Players (2)"), "{text}");
+        assert!(text.lines().any(|line| line.trim_start().starts_with("host") && line.contains("local")), "{text}");
+        assert!(text.lines().any(|line| line.trim_start().starts_with("guest") && line.contains("lost")), "{text}");
+        assert!(text.ends_with("\nMoving guest to arena\n> "), "{text}");
+    }
+}
@@ -1,7 +1,18 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
+use ambient_core::asset_cache;
 use ambient_ecs::{components, EntityId, Resource, SystemGroup, World};
 use ambient_network::server::{ForkingEvent, ShutdownEvent};
+use ambient_project::Identifier;
+use ambient_std::{
+    asset_cache::SyncAssetKeyExt,
+    asset_manifest::{AssetManifest, AssetManifestKey},
+    asset_url::{AbsAssetUrl, AssetType},
+    signature::{self, PublicKey},
+};
 use ambient_wasm::{
     server::bindings::{Bindings as ElementsBindings, WasmServerContext},
     shared::{
@@ -9,7 +20,8 @@ use ambient_wasm::{
     },
     Linker, WasiCtx,
 };
-use parking_lot::RwLock;
+use anyhow::Context;
+use parking_lot::{Mutex, RwLock};
 
 pub type ModuleServerState = ModuleState<ElementsBindings, WasmServerContext, BaseHostGuestState>;
 
@@ -20,6 +32,9 @@ components!("wasm::server", {
     make_wasm_context: Arc<dyn Fn(WasiCtx, Arc<RwLock<BaseHostGuestState>>) -> WasmServerContext + Send + Sync>,
     @[Resource]
     add_to_linker: Arc<dyn Fn(&mut Linker<WasmServerContext>) -> anyhow::Result<()> + Send + Sync>,
+    /// Every error reported by a script (including panics and failed assertions) since the server started
+    @[Resource]
+    script_errors: Arc<Mutex<Vec<String>>>,
 });
 
 pub fn init_all_components() {
@@ -38,9 +53,24 @@ pub fn on_shutdown_systems() -> SystemGroup<ShutdownEvent> {
     ambient_wasm::server::on_shutdown_systems(module_state())
 }
 
-pub async fn initialize(world: &mut World, project_path: PathBuf, manifest: &ambient_project::Manifest) -> anyhow::Result<()> {
-    let messenger = Arc::new(|world: &World, id: EntityId, type_: MessageType, message: &str| {
+/// Loads the main script of the project, and the script bundles of its build.
+///
+/// If `trusted_keys` is not empty, each script is only loaded if it has been signed by one of them; the ones which aren't are
+/// refused, and reported as script errors.
+pub async fn initialize(
+    world: &mut World,
+    project_path: PathBuf,
+    manifest: &ambient_project::Manifest,
+    trusted_keys: &[PublicKey],
+) -> anyhow::Result<()> {
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    world.add_resource(script_errors(), errors.clone());
+
+    let messenger = Arc::new(move |world: &World, id: EntityId, type_: MessageType, message: &str| {
         let name = get_module_name(world, id);
+        if let MessageType::Error = type_ {
+            errors.lock().push(format!("[{name}] {message}"));
+        }
         let (prefix, level) = match type_ {
             MessageType::Info => ("info", log::Level::Info),
             MessageType::Error => ("error", log::Level::Error),
@@ -59,13 +89,55 @@ pub async fn initialize(world: &mut World, project_path: PathBuf, manifest: &amb
     )
     .await?;
 
-    let main_wasm_path = project_path.join("build").join(format!("{}.wasm", manifest.project.id));
+    let build_path = project_path.join("build");
+    let mut scripts = Vec::new();
+    let main_wasm_path = build_path.join(format!("{}.wasm", manifest.project.id));
     if main_wasm_path.exists() {
-        let bytecode = std::fs::read(main_wasm_path)?;
+        scripts.push(Ok((manifest.project.id.clone(), manifest.project.description.clone().unwrap_or_default(), main_wasm_path)));
+    }
+    let asset_manifest = AssetManifestKey.get(world.resource(asset_cache()));
+    scripts.extend(script_bundles(&asset_manifest, &build_path));
 
-        let id = spawn_module(world, &manifest.project.id, manifest.project.description.clone().unwrap_or_default(), true)?;
-        world.add_component(id, module_bytecode(), ModuleBytecode(bytecode))?;
+    for script in scripts {
+        let res = script.and_then(|(name, description, path)| load_script(world, &name, description, &path, trusted_keys));
+        if let Err(err) = res {
+            log::error!("{:?}", err);
+            world.resource(script_errors()).lock().push(format!("{err:?}"));
+        }
     }
 
     Ok(())
 }
+
+/// The name, description and path of each script bundle in the `asset_manifest` of the build at `build_path`
+fn script_bundles(asset_manifest: &AssetManifest, build_path: &Path) -> Vec<anyhow::Result<(Identifier, String, PathBuf)>> {
+    let build_url = AbsAssetUrl::from_directory_path(build_path);
+    asset_manifest
+        .assets
+        .iter()
+        .filter(|(_, entry)| entry.type_ == AssetType::ScriptBundle)
+        .map(|(id, entry)| {
+            // Crate names may contain dashes, which module names can't
+            let name = Identifier::new(entry.name.replace('-', "_"))
+                .map_err(|err| anyhow::anyhow!("Invalid name for the script bundle {id}: {err}"))?;
+            let path = entry
+                .url
+                .resolve(&build_url)
+                .ok()
+                .and_then(|url| url.to_file_path().ok().flatten())
+                .with_context(|| format!("The script bundle {id} is not in the build directory"))?;
+            Ok((name, format!("Script bundle {id}"), path))
+        })
+        .collect()
+}
+
+/// Spawns the script at `path` as a module, if it has been signed by one of the `trusted_keys` (when there are any)
+fn load_script(world: &mut World, name: &Identifier, description: String, path: &Path, trusted_keys: &[PublicKey]) -> anyhow::Result<()> {
+    let bytecode = std::fs::read(path).with_context(|| format!("Failed to read the script {name} from {path:?}"))?;
+    if !trusted_keys.is_empty() {
+        signature::verify_file(path, &bytecode, trusted_keys).with_context(|| format!("Refused to load the script {name}"))?;
+    }
+    let id = spawn_module(world, name, description, true)?;
+    world.add_component(id, module_bytecode(), ModuleBytecode(bytecode))?;
+    Ok(())
+}
@@ -28,7 +28,9 @@ fn make_components() -> toml_edit::Table {
     let namespaces = [
         ("core", "Core", "Contains all core components for the Ambient Runtime."),
         ("core::app", "App", "High-level state relevant to the application (including the in-development Editor)."),
+        ("core::audio", "Audio", "Spatial audio, voice chat and music."),
         ("core::camera", "Camera", "Camera matrices, types, parameters, and more."),
+        ("core::discord", "Discord", "The activity shown on the Discord profile of the players."),
         ("core::ecs", "Entity Component System", "Core components for the ECS and entities."),
         ("core::game_objects", "Game Objects", "Pre-defined game objects that implement specific behaviours."),
         ("core::model", "Model", "Information about models attached to entities."),
@@ -48,6 +50,7 @@ fn make_components() -> toml_edit::Table {
             "Entity transform state (including translation, rotation and scale), as well as other transformations for this entity.",
         ),
         ("core::ui", "UI", "Anything related to UI and text."),
+        ("core::video", "Video", "Video playback on entities."),
     ];
 
     for (path, name, description) in namespaces {
@@ -6,14 +6,20 @@ pub(crate) fn init() -> anyhow::Result<()> {
     ambient_physics::init_all_components();
     ambient_wasm::shared::init_components();
     ambient_decals::init_components();
-    ambient_world_audio::init_components();
+    ambient_discord::init_components();
+    ambient_world_audio::init_all_components();
     ambient_primitives::init_components();
     ambient_project::init_components();
     ambient_prefab::init_components();
     ambient_sky::init_components();
     ambient_water::init_components();
+    ambient_video::init_components();
+    ambient_debugger::init_components();
+    #[cfg(feature = "steam")]
+    ambient_steam::init_components();
 
     shared::player::init_all_components();
+    shared::prediction::init_components();
 
     Ok(())
 }
@@ -1,8 +1,15 @@
-use ambient_network::client::GameRpcArgs;
+use std::{collections::HashMap, time::Duration};
+
+use ambient_core::budget::PerformanceBudget;
+use ambient_network::{
+    client::GameRpcArgs,
+    messages::{MessageType, MessageTypes},
+};
 use ambient_rpc::RpcRegistry;
 
 pub mod components;
 pub mod player;
+pub mod prediction;
 
 pub fn create_rpc_registry() -> RpcRegistry<GameRpcArgs> {
     let mut reg = RpcRegistry::new();
@@ -10,3 +17,16 @@ pub fn create_rpc_registry() -> RpcRegistry<GameRpcArgs> {
     ambient_debugger::register_rpcs(&mut reg);
     reg
 }
+
+pub fn performance_budget(budget: &ambient_project::Budget) -> PerformanceBudget {
+    let from_ms = |ms: f32| Duration::from_secs_f32(ms / 1000.);
+    PerformanceBudget {
+        max_entities: budget.max_entities,
+        max_system_time: budget.max_system_time_ms.map(from_ms),
+        max_frame_time: budget.max_frame_time_ms.map(from_ms),
+    }
+}
+
+pub fn message_types(messages: &HashMap<ambient_project::Identifier, ambient_project::Message>) -> MessageTypes {
+    messages.iter().map(|(id, message)| MessageType::new(id.to_string(), message.version, message.reliable)).collect()
+}
@@ -1,29 +1,41 @@
-use std::{io::Write, sync::Arc};
+use std::{io::Write, sync::Arc, time::Duration};
 
 use ambient_audio::AudioListener;
 use ambient_core::{
+    asset_cache,
     camera::{active_camera, aspect_ratio_from_window},
-    main_scene, on_frame, runtime,
+    dtime, main_scene, on_frame, runtime,
+    window::{WindowCtl, WindowMode},
+    window_ctl,
 };
-use ambient_ecs::{query, query_mut, EntityData, SystemGroup};
+use ambient_ecs::{query, query_mut, Component, ComponentValue, DynSystem, EntityData, SystemGroup};
 use ambient_element::{element_component, Element, Hooks};
 use ambient_input::{
+    gamepad::{GamepadRumble, GamepadsKey},
     on_app_focus_change, on_app_keyboard_input, on_app_mouse_input, on_app_mouse_motion, on_app_mouse_wheel, player_prev_raw_input,
     player_raw_input, ElementState, MouseScrollDelta, PlayerRawInput,
 };
 use ambient_network::{
     client::game_client,
-    get_player_by_user_id,
+    get_player_by_user_id, ownership,
     player::{local_user_id, player, user_id},
     DatagramHandlers,
 };
-use ambient_std::unwrap_log_err;
+use ambient_std::{asset_cache::SyncAssetKeyExt, unwrap_log_err};
 use ambient_world_audio::audio_listener;
 use byteorder::{BigEndian, WriteBytesExt};
-pub use components::game_objects::player_camera;
-use glam::{Mat4, Vec3};
+pub use components::{
+    game_objects::player_camera,
+    player::{
+        gamepad_index, gamepad_rumble_attack, gamepad_rumble_duration, gamepad_rumble_fade, gamepad_rumble_high_frequency,
+        gamepad_rumble_low_frequency, window_min_size, window_mode, window_monitor, window_resolution, window_title,
+    },
+};
+use glam::{Mat4, Vec2, Vec3};
 use parking_lot::Mutex;
 
+use super::prediction::{self, InputFrame, InputHistory};
+
 const PLAYER_INPUT_DATAGRAM_ID: u32 = 5;
 
 mod components {
@@ -41,21 +53,97 @@ mod components {
             player_camera: (),
         });
     }
+
+    pub mod player {
+        use ambient_ecs::{components, Debuggable, Description, Name, Networked};
+        use glam::Vec2;
+
+        components!("player", {
+            @[
+                Networked, Debuggable,
+                Name["Gamepad index"],
+                Description["The gamepad this player uses, among the ones connected to their machine, from 0. Defaults to 0.\nThe players of a local multiplayer game are the ones whose `owner` is the player of the machine; giving them different indices routes the rumble of each of them to their own gamepad."]
+            ]
+            gamepad_index: u32,
+            @[
+                Networked, Debuggable,
+                Name["Gamepad rumble low frequency"],
+                Description["The strength of the low frequency (strong) motor of the rumble of the gamepad of this player, from 0 to 1."]
+            ]
+            gamepad_rumble_low_frequency: f32,
+            @[
+                Networked, Debuggable,
+                Name["Gamepad rumble high frequency"],
+                Description["The strength of the high frequency (weak) motor of the rumble of the gamepad of this player, from 0 to 1."]
+            ]
+            gamepad_rumble_high_frequency: f32,
+            @[
+                Networked, Debuggable,
+                Name["Gamepad rumble duration"],
+                Description["How long the gamepad of this player rumbles for, in seconds.\nSetting this (re)starts the rumble with the other `gamepad_rumble_` components of the player; setting it to 0 stops the rumble."]
+            ]
+            gamepad_rumble_duration: f32,
+            @[
+                Networked, Debuggable,
+                Name["Gamepad rumble attack"],
+                Description["How long the rumble of the gamepad of this player takes to ramp up to its strength, in seconds. Defaults to 0."]
+            ]
+            gamepad_rumble_attack: f32,
+            @[
+                Networked, Debuggable,
+                Name["Gamepad rumble fade"],
+                Description["How long the rumble of the gamepad of this player takes to fade out at its end, in seconds. Defaults to 0."]
+            ]
+            gamepad_rumble_fade: f32,
+            @[
+                Networked, Debuggable,
+                Name["Window mode"],
+                Description["How the window of this player is shown: 0 for windowed, 1 for borderless fullscreen and 2 for exclusive fullscreen."]
+            ]
+            window_mode: u32,
+            @[
+                Networked, Debuggable,
+                Name["Window monitor"],
+                Description["The monitor the window of this player is shown on, among the ones connected to their machine, from 0."]
+            ]
+            window_monitor: u32,
+            @[
+                Networked, Debuggable,
+                Name["Window resolution"],
+                Description["The size of the window of this player, in physical pixels.\nIn exclusive fullscreen, this is the resolution of the monitor."]
+            ]
+            window_resolution: Vec2,
+            @[
+                Networked, Debuggable,
+                Name["Window minimum size"],
+                Description["The size the window of this player can't be resized below, in physical pixels."]
+            ]
+            window_min_size: Vec2,
+            @[
+                Networked, Debuggable,
+                Name["Window title"],
+                Description["The title of the window of this player."]
+            ]
+            window_title: String,
+        });
+    }
 }
 
 pub fn init_all_components() {
     components::game_objects::init_components();
+    components::player::init_components();
 }
 
 pub fn register_datagram_handler(handlers: &mut DatagramHandlers) {
     handlers.insert(
         PLAYER_INPUT_DATAGRAM_ID,
         Arc::new(|state, _assets, user_id, data| {
-            let input: PlayerRawInput = unwrap_log_err!(bincode::deserialize(&data));
+            let frame: InputFrame = unwrap_log_err!(bincode::deserialize(&data));
             let mut state = state.lock();
             if let Some(world) = state.get_player_world_mut(user_id) {
                 if let Some(player_id) = get_player_by_user_id(world, user_id) {
-                    world.set(player_id, player_raw_input(), input).ok();
+                    world.set(player_id, player_raw_input(), frame.input.clone()).ok();
+                    prediction::queue_input(world, player_id, frame);
                 }
             }
         }),
@@ -90,32 +178,82 @@ pub fn server_systems_final() -> SystemGroup {
 pub fn client_systems() -> SystemGroup {
     SystemGroup::new(
         "player/client_systems",
-        vec![query(player_camera()).spawned().to_system(|q, world, qs, _| {
-            // TEMP: This synchronises server cameras to the client. This is a temporary solution until this
-            // is moved to/controlled by clientside WASM.
-
-            let local = world.resource(local_user_id()).clone();
-            for (id, _) in q.collect_cloned(world, qs) {
-                let camera_user_id = world.get_ref(id, user_id());
-                // Activate this camera if no user ID was specified or if this user ID matches
-                // our local user ID
-                if !camera_user_id.map_or(true, |uid| *uid == local) {
-                    continue;
+        vec![
+            query(player_camera()).spawned().to_system(|q, world, qs, _| {
+                // TEMP: This synchronises server cameras to the client. This is a temporary solution until this
+                // is moved to/controlled by clientside WASM.
+
+                let local = world.resource(local_user_id()).clone();
+                for (id, _) in q.collect_cloned(world, qs) {
+                    let camera_user_id = world.get_ref(id, user_id());
+                    // Activate this camera if no user ID was specified or if this user ID matches
+                    // our local user ID
+                    if !camera_user_id.map_or(true, |uid| *uid == local) {
+                        continue;
+                    }
+
+                    world
+                        .add_components(
+                            id,
+                            EntityData::new()
+                                .set(active_camera(), 0.)
+                                .set(main_scene(), ())
+                                .set(audio_listener(), Arc::new(Mutex::new(AudioListener::new(Mat4::IDENTITY, Vec3::X * 0.2))))
+                                .set(aspect_ratio_from_window(), ()),
+                        )
+                        .unwrap();
                 }
+            }),
+            // Plays the rumbles of the players of this machine on their gamepads: the local player, and the players of a local
+            // multiplayer game which it owns
+            query((player(), gamepad_rumble_duration().changed())).to_system(|q, world, qs, _| {
+                let local = world.resource(local_user_id()).clone();
+                let gamepads = GamepadsKey.get(world.resource(asset_cache()));
+                for (id, (_, &duration)) in q.iter(world, qs) {
+                    if world.get_ref(id, user_id()).map_or(true, |uid| *uid != local) && !ownership::is_locally_owned(world, id) {
+                        continue;
+                    }
+                    let gamepad = world.get(id, gamepad_index()).unwrap_or(0) as usize;
+                    if duration > 0. {
+                        let seconds = |component| Duration::from_secs_f32(world.get(id, component).unwrap_or(0.).max(0.));
+                        let rumble = GamepadRumble::new(
+                            world.get(id, gamepad_rumble_low_frequency()).unwrap_or(0.),
+                            world.get(id, gamepad_rumble_high_frequency()).unwrap_or(0.),
+                            Duration::from_secs_f32(duration),
+                        )
+                        .with_envelope(seconds(gamepad_rumble_attack()), seconds(gamepad_rumble_fade()));
+                        gamepads.rumble(gamepad, rumble);
+                    } else {
+                        gamepads.stop_rumble(gamepad);
+                    }
+                }
+            }),
+            // Applies the window settings of the local player
+            window_system(window_mode(), |mode| {
+                WindowCtl::SetMode(match mode {
+                    1 => WindowMode::BorderlessFullscreen,
+                    2 => WindowMode::ExclusiveFullscreen,
+                    _ => WindowMode::Windowed,
+                })
+            }),
+            window_system(window_monitor(), |monitor| WindowCtl::SetMonitor(monitor as usize)),
+            window_system(window_resolution(), |size| WindowCtl::SetResolution(size.max(Vec2::ONE).as_uvec2())),
+            window_system(window_min_size(), |size| WindowCtl::SetMinSize(Some(size.max(Vec2::ZERO).as_uvec2()))),
+            window_system(window_title(), WindowCtl::SetTitle),
+        ],
+    )
+}
 
-                world
-                    .add_components(
-                        id,
-                        EntityData::new()
-                            .set(active_camera(), 0.)
-                            .set(main_scene(), ())
-                            .set(audio_listener(), Arc::new(Mutex::new(AudioListener::new(Mat4::IDENTITY, Vec3::X * 0.2))))
-                            .set(aspect_ratio_from_window(), ()),
-                    )
-                    .unwrap();
+/// Sends `ctl(value)` to the window when `component` changes on the local player
+fn window_system<T: ComponentValue + Clone>(component: Component<T>, ctl: fn(T) -> WindowCtl) -> DynSystem {
+    query((player(), component.changed())).to_system(move |q, world, qs, _| {
+        let local = world.resource(local_user_id()).clone();
+        for (id, (_, value)) in q.iter(world, qs) {
+            if world.get_ref(id, user_id()).map_or(false, |uid| *uid == local) {
+                world.resource(window_ctl()).send(ctl(value.clone())).ok();
             }
-        })],
-    )
+        }
+    })
 }
 
 #[element_component]
@@ -123,6 +261,7 @@ pub fn PlayerRawInputHandler(hooks: &mut Hooks) -> Element {
     const PIXELS_PER_LINE: f32 = 5.0;
 
     let input = hooks.use_ref_with(|_| PlayerRawInput::default());
+    let history = hooks.use_ref_with(|_| InputHistory::default());
     let (has_focus, set_has_focus) = hooks.use_state(false);
 
     Element::new()
@@ -194,23 +333,29 @@ pub fn PlayerRawInputHandler(hooks: &mut Hooks) -> Element {
         .listener(
             on_frame(),
             Arc::new(move |world, _, _| {
-                if !has_focus {
-                    return;
-                }
+                let gc = match world.resource_opt(game_client()).cloned() {
+                    Some(Some(gc)) => gc,
+                    _ => return,
+                };
+                let mut history = history.lock();
 
-                if let Some(Some(gc)) = world.resource_opt(game_client()).cloned() {
+                if has_focus {
+                    let frame = history.push(input.lock().clone(), *world.resource(dtime()));
                     let runtime = world.resource(runtime()).clone();
-                    let input = input.clone();
+                    let connection = gc.connection.clone();
 
                     runtime.spawn(async move {
                         let mut data = Vec::new();
                         data.write_u32::<BigEndian>(PLAYER_INPUT_DATAGRAM_ID).unwrap();
 
-                        let msg = bincode::serialize(&*input.lock()).unwrap();
+                        let msg = bincode::serialize(&frame).unwrap();
                         data.write_all(&msg).unwrap();
-                        gc.connection.send_datagram(data.into()).ok();
+                        connection.send_datagram(data.into()).ok();
                     });
                 }
+
+                // Predicts the inputs right away, on top of the latest state from the server
+                prediction::reconcile(&mut gc.game_state.lock().world, &mut history);
             }),
         )
 }
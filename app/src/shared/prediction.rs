@@ -0,0 +1,373 @@
+//! Client-side prediction of the entities controlled by the local player.
+//!
+//! Every frame, the client numbers its input and sends it to the server, which queues it on the player, simulates it
+//! with the [PredictionStep] in its [server_systems], and acknowledges it with [player_input_ack]. The [prediction_step]
+//! is the [walk_step] unless the game sets its own. Meanwhile, the client simulates the same inputs on the entities it
+//! predicts (the ones with [predicted] and its `user_id`) right away, so that they react without waiting for the
+//! round trip to the server.
+//!
+//! When the server's state of a predicted entity arrives, the client rolls the entity back to it, and replays the
+//! inputs the server hasn't acknowledged yet on top of it.
+//!
+//! A predicted entity which the client owns (see [ambient_network::ownership]) is only simulated by the client: the
+//! server doesn't simulate the inputs on it, and the client steps each input on it once, without rolling it back.
+
+use std::{collections::VecDeque, fmt::Debug, sync::Arc};
+
+use ambient_core::transform::translation;
+use ambient_ecs::{
+    components, query, ComponentDesc, Debuggable, Description, EntityData, EntityId, Name, Networked, Resource, SystemGroup, World,
+};
+use ambient_input::{PlayerRawInput, VirtualKeyCode};
+use ambient_network::{
+    get_player_by_user_id,
+    interpolation::no_interpolation,
+    movement::max_speed,
+    ownership::{is_owned_by, owner_of},
+    player::{local_user_id, user_id},
+};
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+components!("prediction", {
+    @[
+        Debuggable, Networked,
+        Name["Predicted"],
+        Description["If attached, the client of the player with the same `user_id` as this entity simulates the player's inputs on it before the server does."]
+    ]
+    predicted: (),
+    @[
+        Debuggable, Networked,
+        Name["Player input acknowledgement"],
+        Description["The sequence number of the last input of this player that the server has simulated."]
+    ]
+    player_input_ack: u32,
+    /// The inputs received from this player which the server hasn't simulated yet
+    pending_inputs: Vec<InputFrame>,
+    /// The state of a predicted entity, as last sent by the server
+    prediction_state: PredictionState,
+    @[Resource]
+    prediction_step: PredictionStep,
+});
+
+/// How many inputs are kept for replaying at most; if the server doesn't acknowledge them, the oldest ones are dropped
+const MAX_UNACKNOWLEDGED_INPUTS: usize = 256;
+/// The longest frame the server simulates an input for, in seconds, so that a client can't move further in one input by
+/// claiming a long frame
+pub const MAX_INPUT_DT: f32 = 0.1;
+/// How fast the [walk_step] moves the entities without a [max_speed], in meters per second
+pub const DEFAULT_WALK_SPEED: f32 = 5.;
+
+/// A frame of input of the local player, as sent to the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputFrame {
+    /// Starts from 1, and increases by 1 every frame
+    pub seq: u32,
+    /// The duration of the frame, in seconds
+    pub dt: f32,
+    pub input: PlayerRawInput,
+}
+
+/// The inputs of the local player which the server hasn't acknowledged yet
+#[derive(Debug, Clone, Default)]
+pub struct InputHistory {
+    frames: VecDeque<InputFrame>,
+    last_seq: u32,
+}
+impl InputHistory {
+    pub fn push(&mut self, input: PlayerRawInput, dt: f32) -> InputFrame {
+        self.last_seq += 1;
+        let frame = InputFrame { seq: self.last_seq, dt: clamp_input_dt(dt), input };
+        if self.frames.len() == MAX_UNACKNOWLEDGED_INPUTS {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame.clone());
+        frame
+    }
+    /// Drops the inputs up to and including `ack`
+    pub fn acknowledge(&mut self, ack: u32) {
+        while self.frames.front().map_or(false, |frame| frame.seq <= ack) {
+            self.frames.pop_front();
+        }
+    }
+    pub fn unacknowledged(&self) -> impl Iterator<Item = &InputFrame> {
+        self.frames.iter()
+    }
+    /// The sequence number of the last input pushed
+    pub fn last_seq(&self) -> u32 {
+        self.last_seq
+    }
+}
+
+/// Simulates a frame of input of a player on one of the entities they control, both on the server and on the client
+/// predicting it.
+///
+/// The step must only change the `components` of the entity, so that they can be rolled back, and it must be
+/// deterministic, so that the client and the server agree.
+#[derive(Clone)]
+pub struct PredictionStep {
+    pub components: Vec<ComponentDesc>,
+    pub step: Arc<dyn Fn(&mut World, EntityId, &PlayerRawInput, f32) + Sync + Send>,
+}
+impl Debug for PredictionStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PredictionStep").field("components", &self.components).finish_non_exhaustive()
+    }
+}
+
+/// The default [PredictionStep]: walks the entity on the ground with the WASD keys, at its [max_speed] (or
+/// [DEFAULT_WALK_SPEED])
+pub fn walk_step() -> PredictionStep {
+    PredictionStep {
+        components: vec![translation().desc()],
+        step: Arc::new(|world, id, input, dt| {
+            let key = |key| if input.keys.contains(&key) { 1. } else { 0. };
+            let direction = Vec3::new(key(VirtualKeyCode::D) - key(VirtualKeyCode::A), key(VirtualKeyCode::W) - key(VirtualKeyCode::S), 0.);
+            if direction == Vec3::ZERO {
+                return;
+            }
+            let speed = world.get(id, max_speed()).unwrap_or(DEFAULT_WALK_SPEED);
+            if let Ok(position) = world.get_mut(id, translation()) {
+                *position += direction.normalize() * speed * dt;
+            }
+        }),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PredictionState {
+    /// The values of the predicted components sent by the server
+    authoritative: EntityData,
+    /// The content versions of the predicted components after the last prediction, to tell which ones the server
+    /// has changed since
+    versions: Vec<(u32, u64)>,
+    /// The sequence number of the last input simulated on the entity
+    stepped_seq: u32,
+}
+
+/// Queues an input frame received from the player `player_id`, to be simulated by the [server_systems]
+pub fn queue_input(world: &mut World, player_id: EntityId, frame: InputFrame) {
+    match world.get_mut(player_id, pending_inputs()) {
+        Ok(pending) => {
+            if pending.len() == MAX_UNACKNOWLEDGED_INPUTS {
+                pending.remove(0);
+            }
+            pending.push(frame);
+        }
+        Err(_) => {
+            world.add_component(player_id, pending_inputs(), vec![frame]).ok();
+        }
+    }
+}
+
+/// Simulates the inputs queued by the players, in the order the players sent them
+pub fn server_systems() -> SystemGroup {
+    SystemGroup::new(
+        "prediction/server_systems",
+        vec![query(pending_inputs()).to_system(|q, world, qs, _| {
+            let players = q.iter(world, qs).filter(|(_, pending)| !pending.is_empty()).map(|(id, _)| id).collect::<Vec<_>>();
+            for player_id in players {
+                let mut frames = std::mem::take(world.get_mut(player_id, pending_inputs()).unwrap());
+                frames.sort_by_key(|frame| frame.seq);
+                for frame in &frames {
+                    simulate_input(world, player_id, frame);
+                }
+            }
+        })],
+    )
+}
+
+/// Simulates an input frame received from the player `player_id` on the server, unless it's older than the last one
+pub fn simulate_input(world: &mut World, player_id: EntityId, frame: &InputFrame) {
+    if world.get(player_id, player_input_ack()).map_or(false, |ack| frame.seq <= ack) {
+        return;
+    }
+    if let Some(step) = world.resource_opt(prediction_step()).cloned() {
+        let uid = world.get_cloned(player_id, user_id()).ok();
+        // The owners of the entities simulate them, and send their state
+        let mut entities = controlled_entities(world, uid.as_deref());
+        entities.retain(|&id| owner_of(world, id).is_none());
+        let dt = clamp_input_dt(frame.dt);
+        for id in entities {
+            (step.step)(world, id, &frame.input, dt);
+        }
+    }
+    world.add_component(player_id, player_input_ack(), frame.seq).ok();
+}
+
+/// The duration the server simulates an input with the duration `dt` for, as claimed by the client
+fn clamp_input_dt(dt: f32) -> f32 {
+    if dt.is_finite() {
+        dt.clamp(0., MAX_INPUT_DT)
+    } else {
+        0.
+    }
+}
+
+/// Rolls the entities predicted by the local player back to their state on the server, and replays the inputs of
+/// `history` which the server hasn't acknowledged yet on them
+pub fn reconcile(world: &mut World, history: &mut InputHistory) {
+    let local = world.resource(local_user_id()).clone();
+    if let Some(ack) = get_player_by_user_id(world, &local).and_then(|id| world.get(id, player_input_ack()).ok()) {
+        history.acknowledge(ack);
+    }
+    let step = match world.resource_opt(prediction_step()) {
+        Some(step) => step.clone(),
+        None => return,
+    };
+
+    for id in controlled_entities(world, Some(&local)) {
+        if !world.has_component(id, prediction_state()) {
+            world.add_component(id, prediction_state(), PredictionState::default()).unwrap();
+            // The predicted state is shown right away, rather than interpolated
+            world.add_component(id, no_interpolation(), ()).unwrap();
+        }
+        let mut state = world.get_cloned(id, prediction_state()).unwrap();
+
+        if is_owned_by(world, id, &local) {
+            // The client has the authority, so its state is the authoritative one
+            for frame in history.unacknowledged().filter(|frame| frame.seq > state.stepped_seq) {
+                (step.step)(world, id, &frame.input, frame.dt);
+            }
+            for &desc in &step.components {
+                if let Ok(entry) = world.get_entry(id, desc) {
+                    state.authoritative.set_entry(entry);
+                }
+            }
+        } else {
+            // The components which have been changed since the last prediction were changed by the server
+            for &desc in &step.components {
+                let version = match world.get_component_content_version(id, desc.index()) {
+                    Ok(version) => version,
+                    Err(_) => continue,
+                };
+                if !state.versions.contains(&(desc.index(), version)) {
+                    state.authoritative.set_entry(world.get_entry(id, desc).unwrap());
+                }
+            }
+
+            for entry in state.authoritative.iter() {
+                world.set_entry(id, entry.clone()).ok();
+            }
+            for frame in history.unacknowledged() {
+                (step.step)(world, id, &frame.input, frame.dt);
+            }
+        }
+
+        state.versions = step
+            .components
+            .iter()
+            .filter_map(|desc| Some((desc.index(), world.get_component_content_version(id, desc.index()).ok()?)))
+            .collect();
+        state.stepped_seq = history.last_seq();
+        world.set(id, prediction_state(), state).unwrap();
+    }
+}
+
+/// The entities with [predicted] which belong to the user `uid`
+fn controlled_entities(world: &World, uid: Option<&str>) -> Vec<EntityId> {
+    let uid = match uid {
+        Some(uid) => uid,
+        None => return Vec::new(),
+    };
+    query((predicted(), user_id())).iter(world, None).filter(|(_, (_, id))| id.as_str() == uid).map(|(id, _)| id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ambient_ecs::{FrameEvent, System};
+    use ambient_network::player::player;
+
+    use super::*;
+
+    fn world_with_player(name: &'static str) -> (World, EntityId, EntityId) {
+        ambient_core::init_all_components();
+        ambient_network::init_all_components();
+        init_components();
+        let mut world = World::new(name);
+        world.add_resource(prediction_step(), walk_step());
+        world.add_resource(local_user_id(), "user".to_string());
+        let player_id = EntityData::new().set(player(), ()).set(user_id(), "user".to_string()).spawn(&mut world);
+        let entity =
+            EntityData::new().set(predicted(), ()).set(user_id(), "user".to_string()).set(translation(), Vec3::ZERO).spawn(&mut world);
+        (world, player_id, entity)
+    }
+
+    fn walk(dt: f32) -> (PlayerRawInput, f32) {
+        let mut input = PlayerRawInput::default();
+        input.keys.insert(VirtualKeyCode::W);
+        (input, dt)
+    }
+
+    #[test]
+    fn input_history() {
+        let mut history = InputHistory::default();
+        for _ in 0..MAX_UNACKNOWLEDGED_INPUTS + 2 {
+            let (input, dt) = walk(0.01);
+            history.push(input, dt);
+        }
+        assert_eq!(history.last_seq(), MAX_UNACKNOWLEDGED_INPUTS as u32 + 2);
+        assert_eq!(history.unacknowledged().next().unwrap().seq, 3);
+
+        history.acknowledge(10);
+        assert_eq!(history.unacknowledged().next().unwrap().seq, 11);
+        history.acknowledge(history.last_seq());
+        assert_eq!(history.unacknowledged().count(), 0);
+    }
+
+    #[test]
+    fn input_dt_is_clamped() {
+        assert_eq!(clamp_input_dt(0.016), 0.016);
+        assert_eq!(clamp_input_dt(10.), MAX_INPUT_DT);
+        assert_eq!(clamp_input_dt(-1.), 0.);
+        assert_eq!(clamp_input_dt(f32::NAN), 0.);
+        assert_eq!(clamp_input_dt(f32::INFINITY), 0.);
+    }
+
+    #[test]
+    fn server_simulates_queued_inputs_in_order() {
+        let (mut world, player_id, entity) = world_with_player("server_simulates_queued_inputs_in_order");
+        let mut systems = server_systems();
+        let (input, _) = walk(0.);
+        // A modified client claims a long frame, and the datagrams arrive out of order
+        queue_input(&mut world, player_id, InputFrame { seq: 2, dt: 10., input: input.clone() });
+        queue_input(&mut world, player_id, InputFrame { seq: 1, dt: 0.1, input: input.clone() });
+        systems.run(&mut world, &FrameEvent);
+        assert_eq!(world.get(player_id, player_input_ack()), Ok(2));
+        assert!((world.get(entity, translation()).unwrap().y - 2. * MAX_INPUT_DT * DEFAULT_WALK_SPEED).abs() < 1e-5);
+
+        // A duplicated input isn't simulated again
+        queue_input(&mut world, player_id, InputFrame { seq: 2, dt: 0.1, input });
+        systems.run(&mut world, &FrameEvent);
+        assert!((world.get(entity, translation()).unwrap().y - 2. * MAX_INPUT_DT * DEFAULT_WALK_SPEED).abs() < 1e-5);
+        assert!(world.get_ref(player_id, pending_inputs()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn client_rolls_back_and_replays() {
+        let (mut world, player_id, entity) = world_with_player("client_rolls_back_and_replays");
+        world.add_component(entity, max_speed(), 10.).unwrap();
+        let mut history = InputHistory::default();
+        for _ in 0..3 {
+            let (input, dt) = walk(0.1);
+            history.push(input, dt);
+        }
+
+        // The server has simulated the first input
+        world.add_component(player_id, player_input_ack(), 1).unwrap();
+        world.set(entity, translation(), Vec3::Y).unwrap();
+        reconcile(&mut world, &mut history);
+        assert_eq!(history.unacknowledged().count(), 2);
+        assert!((world.get(entity, translation()).unwrap().y - 3.).abs() < 1e-5);
+
+        // Nothing new from the server: the prediction stays where it was
+        reconcile(&mut world, &mut history);
+        assert!((world.get(entity, translation()).unwrap().y - 3.).abs() < 1e-5);
+
+        // The server disagrees with the second input, e.g. the entity was blocked
+        world.set(player_id, player_input_ack(), 2).unwrap();
+        world.set(entity, translation(), Vec3::Y).unwrap();
+        reconcile(&mut world, &mut history);
+        assert!((world.get(entity, translation()).unwrap().y - 2.).abs() < 1e-5);
+    }
+}
@@ -4,8 +4,10 @@ use std::{
     time::{Duration, SystemTime},
 };
 
-use ambient_core::{asset_cache, hierarchy::children, time};
-use ambient_ecs::{components, query, Debuggable, EntityId, MakeDefault, Networked, Store, SystemGroup};
+use ambient_core::{asset_cache, hierarchy::children, jobs, time};
+use ambient_ecs::{
+    components, query, Debuggable, EntityId, MakeDefault, Networked, SerializationProfile, SerializationProfiles, Store, SystemGroup,
+};
 use ambient_model::{animation_binder, model, model_from_url, ModelFromUrl};
 use ambient_std::{
     asset_cache::{AssetCache, AsyncAssetKeyExt},
@@ -34,7 +36,7 @@ components!("animation", {
     animation_apply_base_pose: ModelFromUrl,
     @[Debuggable, Networked, Store]
     copy_animation_controller_to_children: (),
-    @[Debuggable, Networked, Store]
+    @[Debuggable, Networked, Store, SerializationProfiles[[SerializationProfile::REPLICATION]]]
     animation_errors: String,
 
     /// This is a shorthand for working directly with the animation_controller
@@ -204,63 +206,64 @@ pub fn animation_systems() -> SystemGroup {
             query((animation_controller(), animation_binder())).excl(animation_errors()).to_system(|q, world, qs, _| {
                 let assets = world.resource(asset_cache()).clone();
                 let time = *world.resource(time());
-                let mut outputs: HashMap<String, AnimationBlendOutput> = HashMap::new();
                 let mut in_error = Vec::new();
+                // The clips are resolved here, as it may start loading them, and then sampled in parallel
+                let mut animated = Vec::new();
                 for (id, (controller, binder)) in q.iter(world, qs) {
                     let retaget = world.get(id, animation_retargeting()).unwrap_or(AnimationRetargeting::None);
                     let model = world.get_ref(id, model_from_url()).ok().and_then(|def| TypedAssetUrl::parse(def).ok());
-                    // Calc
+                    let mut clips = Vec::new();
                     for action in controller.actions.iter() {
                         match action.clip.get_clip(assets.clone(), retaget, model.clone()) {
                             Some(Err(err)) => {
                                 in_error.push((id, err));
                                 break;
                             }
-                            Some(Ok(clip)) => {
-                                let anim_time = action.time(time, &clip);
-                                for track in clip.tracks.iter() {
-                                    let value = AnimationTrackInterpolator::new().value(track, anim_time);
-                                    let key = format!(
-                                        "{}_{:?}_{}_{:?}",
-                                        id,
-                                        track.target,
-                                        track.outputs.component().index(),
-                                        track.outputs.field()
-                                    );
-                                    if action.weight == 0.0 {
-                                        continue;
-                                    }
-                                    if let Some(o) = outputs.get_mut(&key) {
-                                        o.weight += action.weight;
-                                        let p = action.weight / o.weight;
-                                        o.value = o.value.mix(value, p);
-                                    } else {
-                                        outputs.insert(
-                                            key.to_string(),
-                                            AnimationBlendOutput {
-                                                target: match &track.target {
-                                                    AnimationTarget::BinderId(index) => match binder.get(index) {
-                                                        Some(entity) => *entity,
-                                                        None => {
-                                                            continue;
-                                                        }
-                                                    },
-                                                    AnimationTarget::Entity(entity) => *entity,
-                                                },
-                                                value,
-                                                weight: action.weight,
-                                            },
-                                        );
-                                    }
-                                }
-                            }
+                            Some(Ok(clip)) => clips.push((action, clip)),
                             None => {}
                         }
                     }
+                    animated.push((binder, clips));
                 }
+                let outputs = jobs::par_map(&animated, |(binder, clips)| {
+                    let mut outputs: HashMap<String, AnimationBlendOutput> = HashMap::new();
+                    for (action, clip) in clips {
+                        let anim_time = action.time(time, clip);
+                        for track in clip.tracks.iter() {
+                            let value = AnimationTrackInterpolator::new().value(track, anim_time);
+                            let key = format!("{:?}_{}_{:?}", track.target, track.outputs.component().index(), track.outputs.field());
+                            if action.weight == 0.0 {
+                                continue;
+                            }
+                            if let Some(o) = outputs.get_mut(&key) {
+                                o.weight += action.weight;
+                                let p = action.weight / o.weight;
+                                o.value = o.value.mix(value, p);
+                            } else {
+                                outputs.insert(
+                                    key.to_string(),
+                                    AnimationBlendOutput {
+                                        target: match &track.target {
+                                            AnimationTarget::BinderId(index) => match binder.get(index) {
+                                                Some(entity) => *entity,
+                                                None => {
+                                                    continue;
+                                                }
+                                            },
+                                            AnimationTarget::Entity(entity) => *entity,
+                                        },
+                                        value,
+                                        weight: action.weight,
+                                    },
+                                );
+                            }
+                        }
+                    }
+                    outputs
+                });
 
                 // Apply
-                for (_, output) in outputs.into_iter() {
+                for (_, output) in outputs.into_iter().flatten() {
                     match output.value {
                         AnimationOutput::Vec3 { component, value } => {
                             world.set(output.target, component, value).ok();
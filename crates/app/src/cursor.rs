@@ -0,0 +1,173 @@
+//! Shows the cursor asked for with the [WindowCtl](ambient_core::window::WindowCtl)s.
+//!
+//! Winit can only show the system cursor icons, so the [CursorImage]s are shown as the hardware cursor through the
+//! windowing system itself, which is only done on X11 for now. Elsewhere `window_custom_cursors` is false, and the UI
+//! draws the custom cursors instead.
+
+use std::sync::Arc;
+
+use ambient_core::window::CursorImage;
+use winit::window::{CursorIcon, Window};
+
+pub(crate) struct WindowCursor {
+    custom_cursors: Option<platform::CustomCursors>,
+    custom: Option<Arc<CursorImage>>,
+    visible: bool,
+}
+impl WindowCursor {
+    pub fn new(window: &Window) -> Self {
+        Self { custom_cursors: platform::CustomCursors::new(window), custom: None, visible: true }
+    }
+    /// If [CursorImage]s can be shown as the hardware cursor
+    pub fn custom_cursors(&self) -> bool {
+        self.custom_cursors.is_some()
+    }
+
+    pub fn show(&mut self, window: &Window, visible: bool) {
+        window.set_cursor_visible(visible);
+        self.visible = visible;
+        // Winit replaced the custom cursor with its own
+        self.show_custom();
+    }
+    pub fn set_icon(&mut self, window: &Window, icon: CursorIcon) {
+        window.set_cursor_icon(icon);
+        self.show_custom();
+    }
+    pub fn set_custom(&mut self, window: &Window, custom: Option<Arc<CursorImage>>) {
+        let was_custom = self.custom.is_some();
+        self.custom = custom;
+        if self.custom.is_some() {
+            self.show_custom();
+        } else if let (Some(custom_cursors), true) = (&mut self.custom_cursors, was_custom) {
+            custom_cursors.forget();
+            // Winit only shows its cursor again when the visibility or the icon changes
+            if self.visible {
+                window.set_cursor_visible(false);
+                window.set_cursor_visible(true);
+            }
+        }
+    }
+
+    fn show_custom(&mut self) {
+        if let (Some(custom_cursors), Some(custom), true) = (&mut self.custom_cursors, &self.custom, self.visible) {
+            custom_cursors.show(custom);
+        }
+    }
+}
+
+/// A pixel of `rgba` as the premultiplied ARGB of the cursors of X11
+#[cfg_attr(not(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android")))), allow(dead_code))]
+fn premultiplied_argb(rgba: &[u8]) -> u32 {
+    let alpha = rgba[3] as u32;
+    let premultiply = |channel: u8| (channel as u32 * alpha + 127) / 255;
+    alpha << 24 | premultiply(rgba[0]) << 16 | premultiply(rgba[1]) << 8 | premultiply(rgba[2])
+}
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))]
+mod platform {
+    use std::os::raw::{c_int, c_ulong};
+
+    use ambient_core::window::CursorImage;
+    use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle};
+    use winit::window::Window;
+    use x11_dl::{
+        xcursor::Xcursor,
+        xlib::{Display, Xlib},
+    };
+
+    use super::premultiplied_argb;
+
+    /// Shows the custom cursors with Xcursor, if the window is on X11
+    pub(super) struct CustomCursors {
+        xlib: Xlib,
+        xcursor: Xcursor,
+        display: *mut Display,
+        window: c_ulong,
+        /// The cursor shown, which is freed once it's replaced
+        shown: Option<c_ulong>,
+    }
+    // Only used on the thread of the event loop, like the display of winit it comes from
+    unsafe impl Send for CustomCursors {}
+
+    impl CustomCursors {
+        pub fn new(window: &Window) -> Option<Self> {
+            let display = match window.raw_display_handle() {
+                RawDisplayHandle::Xlib(handle) => handle.display as *mut Display,
+                _ => return None,
+            };
+            let window = match window.raw_window_handle() {
+                RawWindowHandle::Xlib(handle) => handle.window,
+                _ => return None,
+            };
+            let xlib = Xlib::open().ok()?;
+            let xcursor = match Xcursor::open() {
+                Ok(xcursor) => xcursor,
+                Err(err) => {
+                    tracing::warn!("Failed to load Xcursor, the custom cursors are drawn by the UI instead: {err}");
+                    return None;
+                }
+            };
+            Some(Self { xlib, xcursor, display, window, shown: None })
+        }
+
+        pub fn show(&mut self, image: &CursorImage) {
+            unsafe {
+                let cursor_image = (self.xcursor.XcursorImageCreate)(image.size.x as c_int, image.size.y as c_int);
+                if cursor_image.is_null() {
+                    return;
+                }
+                (*cursor_image).xhot = image.hotspot.x.min(image.size.x.saturating_sub(1));
+                (*cursor_image).yhot = image.hotspot.y.min(image.size.y.saturating_sub(1));
+                let pixels = std::slice::from_raw_parts_mut((*cursor_image).pixels, (image.size.x * image.size.y) as usize);
+                for (pixel, rgba) in pixels.iter_mut().zip(image.rgba.chunks_exact(4)) {
+                    *pixel = premultiplied_argb(rgba);
+                }
+                let cursor = (self.xcursor.XcursorImageLoadCursor)(self.display, cursor_image);
+                (self.xcursor.XcursorImageDestroy)(cursor_image);
+                (self.xlib.XDefineCursor)(self.display, self.window, cursor);
+                (self.xlib.XFlush)(self.display);
+                self.forget();
+                self.shown = Some(cursor);
+            }
+        }
+
+        /// Frees the cursor shown, once it's replaced
+        pub fn forget(&mut self) {
+            if let Some(cursor) = self.shown.take() {
+                unsafe { (self.xlib.XFreeCursor)(self.display, cursor) };
+            }
+        }
+    }
+    impl Drop for CustomCursors {
+        fn drop(&mut self) {
+            self.forget();
+        }
+    }
+}
+
+#[cfg(not(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android")))))]
+mod platform {
+    use ambient_core::window::CursorImage;
+    use winit::window::Window;
+
+    pub(super) struct CustomCursors;
+    impl CustomCursors {
+        pub fn new(_window: &Window) -> Option<Self> {
+            None
+        }
+        pub fn show(&mut self, _image: &CursorImage) {}
+        pub fn forget(&mut self) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_are_premultiplied() {
+        assert_eq!(premultiplied_argb(&[255, 128, 0, 255]), 0xff_ff_80_00);
+        assert_eq!(premultiplied_argb(&[255, 128, 0, 128]), 0x80_80_40_00);
+        assert_eq!(premultiplied_argb(&[255, 255, 255, 0]), 0);
+    }
+}
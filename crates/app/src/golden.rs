@@ -0,0 +1,305 @@
+//! Golden image tests: a scene is rendered without a window from fixed camera positions, and the frames are compared
+//! against reference images stored with the tests.
+//!
+//! Set `AMBIENT_UPDATE_GOLDEN=1` to (re)write the reference images instead of comparing against them.
+
+use std::{path::Path, sync::Arc, time::Duration};
+
+use ambient_core::{
+    camera::{active_camera, aspect_ratio, fovy, near, perspective_infinite_reverse, projection, projection_view},
+    frame_index,
+    gpu_ecs::GpuWorldSyncEvent,
+    main_scene, time,
+    transform::{inv_local_to_world, local_to_world, lookat_center, lookat_up, translation},
+    RuntimeKey,
+};
+use ambient_ecs::{EntityData, EntityId, FrameEvent, System, SystemGroup, World};
+use ambient_gpu::gpu::{Gpu, GpuKey};
+use ambient_renderer::{RenderTarget, Renderer, RendererConfig, RendererTarget};
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKeyExt},
+    color::Color,
+    colorspace::{OklabRepresentation, SrgbColorSpace},
+};
+use ambient_sys::task::RuntimeHandle;
+use anyhow::Context;
+use glam::{UVec2, Vec3};
+use image::{Rgba, RgbaImage};
+
+use crate::{gpu_world_sync_systems, world_instance_resources, world_instance_systems_with_time, AppResources};
+
+/// The time step of [HeadlessRenderer::run_frames]
+pub const FRAME_TIME: Duration = Duration::from_nanos(16_666_667);
+
+/// Renders the main scene of a world without a window.
+///
+/// The time advances by exactly [FRAME_TIME] each frame, and shadows are disabled as their cascades are the least stable
+/// part of a frame across drivers. Set `AMBIENT_FORCE_FALLBACK_ADAPTER=1` to render with a software adapter (such as
+/// lavapipe or WARP), so that the frames are the same on every machine.
+pub struct HeadlessRenderer {
+    pub world: World,
+    systems: SystemGroup,
+    gpu_world_sync_systems: SystemGroup<GpuWorldSyncEvent>,
+    renderer: Renderer,
+    render_target: RenderTarget,
+    gpu: Arc<Gpu>,
+    size: UVec2,
+    camera: Option<EntityId>,
+}
+impl HeadlessRenderer {
+    pub async fn new(size: UVec2) -> Self {
+        crate::init_all_components();
+        let runtime = RuntimeHandle::current();
+        let assets = AssetCache::new(runtime.clone());
+        let gpu = Arc::new(Gpu::new(None).await);
+        RuntimeKey.insert(&assets, runtime.clone());
+        GpuKey.insert(&assets, gpu.clone());
+
+        let (ctl_tx, _) = flume::unbounded();
+        let mut world = World::new("headless_renderer");
+        let resources = world_instance_resources(AppResources {
+            assets: assets.clone(),
+            gpu: gpu.clone(),
+            runtime,
+            ctl_tx,
+            window_physical_size: size,
+            window_logical_size: size,
+            window_scale_factor: 1.,
+        });
+        world.add_components(world.resource_entity(), resources).unwrap();
+
+        let renderer = Renderer::new(&mut world, assets, RendererConfig { scene: main_scene(), shadows: false, ..Default::default() });
+        let start_time = *world.resource(time());
+        Self {
+            systems: world_instance_systems_with_time(false, Box::new(FixedTimeSystem { start_time, frame: 0 })),
+            gpu_world_sync_systems: gpu_world_sync_systems(),
+            renderer,
+            render_target: RenderTarget::new(gpu.clone(), size, None),
+            gpu,
+            size,
+            camera: None,
+            world,
+        }
+    }
+    /// Runs the systems for `frames` frames. Async tasks, such as asset loads, can progress between the frames.
+    pub async fn run_frames(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.world.next_frame();
+            self.systems.run(&mut self.world, &FrameEvent);
+            self.gpu_world_sync_systems.run(&mut self.world, &GpuWorldSyncEvent);
+            tokio::task::yield_now().await;
+        }
+    }
+    /// Replaces the camera with one at `eye`, looking at `lookat` with +Z up
+    pub fn set_camera(&mut self, eye: Vec3, lookat: Vec3) {
+        if let Some(camera) = self.camera.take() {
+            self.world.despawn(camera);
+        }
+        let camera = EntityData::new()
+            .set_default(local_to_world())
+            .set_default(inv_local_to_world())
+            .set(near(), 0.1)
+            .set(fovy(), 1.0)
+            .set(perspective_infinite_reverse(), ())
+            .set(aspect_ratio(), self.size.x as f32 / self.size.y as f32)
+            .set_default(projection())
+            .set_default(projection_view())
+            .set(translation(), eye)
+            .set(lookat_center(), lookat)
+            .set(lookat_up(), Vec3::Z)
+            .set(active_camera(), 0.)
+            .set(main_scene(), ())
+            .spawn(&mut self.world);
+        self.camera = Some(camera);
+    }
+    /// Runs a frame, so that the camera and the scene are up to date, and renders it
+    pub async fn render(&mut self) -> RgbaImage {
+        self.run_frames(1).await;
+        let mut encoder = self.gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("HeadlessRenderer") });
+        let mut post_submit = Vec::new();
+        self.renderer.render(
+            &mut self.world,
+            &mut encoder,
+            &mut post_submit,
+            RendererTarget::Target(&self.render_target),
+            Some(Color::rgba(0., 0., 0., 1.)),
+        );
+        self.gpu.queue.submit(Some(encoder.finish()));
+        for action in post_submit {
+            action();
+        }
+        self.render_target.color_buffer.reader().read_image().await.expect("Failed to read the rendered frame").into_rgba8()
+    }
+    /// Moves the camera to `eye` and checks the rendered frame against the reference image `name`, see [assert_golden]
+    pub async fn assert_golden_view(
+        &mut self,
+        dir: impl AsRef<Path>,
+        name: &str,
+        eye: Vec3,
+        lookat: Vec3,
+        threshold: &GoldenThreshold,
+    ) -> anyhow::Result<()> {
+        self.set_camera(eye, lookat);
+        let image = self.render().await;
+        assert_golden(dir, name, &image, threshold)
+    }
+}
+
+#[derive(Debug)]
+struct FixedTimeSystem {
+    start_time: Duration,
+    frame: u32,
+}
+impl System for FixedTimeSystem {
+    fn run(&mut self, world: &mut World, _event: &FrameEvent) {
+        self.frame += 1;
+        world.set(world.resource_entity(), time(), self.start_time + FRAME_TIME * self.frame).unwrap();
+        world.set(world.resource_entity(), ambient_core::dtime(), FRAME_TIME.as_secs_f32()).unwrap();
+        world.set(world.resource_entity(), frame_index(), self.frame as usize).unwrap();
+    }
+}
+
+/// How different a frame may be from its reference image
+#[derive(Debug, Clone)]
+pub struct GoldenThreshold {
+    /// The distance in Oklab above which a pixel is considered different; about 0.02 is a just noticeable difference
+    pub max_pixel_difference: f32,
+    /// The fraction of the pixels which may be different
+    pub max_different_pixels: f32,
+}
+impl Default for GoldenThreshold {
+    fn default() -> Self {
+        Self { max_pixel_difference: 0.02, max_different_pixels: 0.001 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageDiff {
+    pub different_pixels: usize,
+    pub total_pixels: usize,
+    pub max_difference: f32,
+    /// The reference image in dimmed grayscale, with the different pixels in red
+    pub image: RgbaImage,
+}
+impl ImageDiff {
+    pub fn different_fraction(&self) -> f32 {
+        self.different_pixels as f32 / self.total_pixels as f32
+    }
+}
+
+/// Compares two images of the same size, pixel by pixel, in Oklab
+pub fn diff_images(actual: &RgbaImage, reference: &RgbaImage, max_pixel_difference: f32) -> anyhow::Result<ImageDiff> {
+    anyhow::ensure!(
+        actual.dimensions() == reference.dimensions(),
+        "The image is {:?}, but the reference is {:?}",
+        actual.dimensions(),
+        reference.dimensions()
+    );
+    let mut image = RgbaImage::new(reference.width(), reference.height());
+    let mut different_pixels = 0;
+    let mut max_difference = 0f32;
+    for ((actual, reference), diff) in actual.pixels().zip(reference.pixels()).zip(image.pixels_mut()) {
+        let (actual, reference) = (to_oklab(actual), to_oklab(reference));
+        let difference = actual.iter().zip(reference.iter()).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt();
+        max_difference = max_difference.max(difference);
+        *diff = if difference > max_pixel_difference {
+            different_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let gray = (reference[0] * 100.) as u8;
+            Rgba([gray, gray, gray, 255])
+        };
+    }
+    Ok(ImageDiff { different_pixels, total_pixels: (image.width() * image.height()) as usize, max_difference, image })
+}
+
+/// Alpha is premultiplied first, so that transparent pixels compare equal regardless of their color
+fn to_oklab(pixel: &Rgba<u8>) -> [f32; 3] {
+    let alpha = pixel[3] as f32 / 255.;
+    let linear = [0, 1, 2].map(|i| (pixel[i] as f32 / 255.).nonlinear_to_linear_srgb() * alpha);
+    OklabRepresentation::linear_srgb_to_oklab(linear)
+}
+
+/// Checks `image` against the reference image `{dir}/{name}.png`.
+///
+/// If they differ by more than `threshold`, the frame and a diff image are written next to the reference, as
+/// `{name}.actual.png` and `{name}.diff.png`. With `AMBIENT_UPDATE_GOLDEN=1`, the reference is replaced by `image` instead.
+pub fn assert_golden(dir: impl AsRef<Path>, name: &str, image: &RgbaImage, threshold: &GoldenThreshold) -> anyhow::Result<()> {
+    let dir = dir.as_ref();
+    let reference_path = dir.join(format!("{name}.png"));
+    let actual_path = dir.join(format!("{name}.actual.png"));
+    let diff_path = dir.join(format!("{name}.diff.png"));
+    std::fs::remove_file(&actual_path).ok();
+    std::fs::remove_file(&diff_path).ok();
+
+    if std::env::var("AMBIENT_UPDATE_GOLDEN").map(|value| value == "1").unwrap_or(false) {
+        std::fs::create_dir_all(dir)?;
+        image.save(&reference_path).with_context(|| format!("Failed to write {reference_path:?}"))?;
+        tracing::info!("Updated golden image {reference_path:?}");
+        return Ok(());
+    }
+    if !reference_path.exists() {
+        save(image, &actual_path)?;
+        anyhow::bail!("There is no golden image {reference_path:?}; run with AMBIENT_UPDATE_GOLDEN=1 to create it from {actual_path:?}");
+    }
+
+    let reference = image::open(&reference_path).with_context(|| format!("Failed to read {reference_path:?}"))?.into_rgba8();
+    let diff = match diff_images(image, &reference, threshold.max_pixel_difference) {
+        Ok(diff) => diff,
+        Err(err) => {
+            save(image, &actual_path)?;
+            return Err(err.context(format!("{name} doesn't match {reference_path:?}")));
+        }
+    };
+    if diff.different_fraction() > threshold.max_different_pixels {
+        save(image, &actual_path)?;
+        save(&diff.image, &diff_path)?;
+        anyhow::bail!(
+            "{name} doesn't match {reference_path:?}: {} of {} pixels are different (max difference {:.3}); see {diff_path:?}",
+            diff.different_pixels,
+            diff.total_pixels,
+            diff.max_difference
+        );
+    }
+    Ok(())
+}
+
+fn save(image: &RgbaImage, path: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    image.save(path).with_context(|| format!("Failed to write {path:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::{assert_golden, diff_images, GoldenThreshold};
+
+    #[test]
+    fn compares_images() {
+        let reference = RgbaImage::from_pixel(10, 10, Rgba([100, 150, 200, 255]));
+        let mut actual = reference.clone();
+        // Imperceptible everywhere, and a single clearly different pixel
+        for pixel in actual.pixels_mut() {
+            pixel[0] += 1;
+        }
+        actual.put_pixel(3, 4, Rgba([255, 0, 0, 255]));
+
+        let diff = diff_images(&actual, &reference, 0.02).unwrap();
+        assert_eq!(diff.different_pixels, 1);
+        assert_eq!(diff.image.get_pixel(3, 4), &Rgba([255, 0, 0, 255]));
+        assert!(diff_images(&RgbaImage::new(5, 5), &reference, 0.02).is_err());
+
+        let dir = std::env::temp_dir().join(format!("ambient_golden_{}", std::process::id()));
+        assert!(assert_golden(&dir, "scene", &actual, &GoldenThreshold::default()).is_err());
+        assert!(dir.join("scene.actual.png").exists());
+        reference.save(dir.join("scene.png")).unwrap();
+        assert!(assert_golden(&dir, "scene", &actual, &GoldenThreshold::default()).is_err());
+        assert!(dir.join("scene.diff.png").exists());
+        assert_golden(&dir, "scene", &actual, &GoldenThreshold { max_different_pixels: 0.01, ..Default::default() }).unwrap();
+        assert!(!dir.join("scene.diff.png").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
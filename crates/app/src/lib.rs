@@ -16,8 +16,8 @@ use ambient_core::{
     hierarchy::dump_world_hierarchy_to_tmp_file,
     mouse_position, on_frame_system, remove_at_time_system, runtime, time,
     transform::TransformSystem,
-    window::WindowCtl,
-    window_logical_size, window_physical_size, window_scale_factor, RuntimeKey, TimeResourcesSystem, WinitEventsSystem,
+    window::{WindowCtl, WindowMode},
+    window_focused, window_logical_size, window_physical_size, window_scale_factor, RuntimeKey, TimeResourcesSystem, WinitEventsSystem,
 };
 use ambient_ecs::{components, Debuggable, DynSystem, EntityData, FrameEvent, MakeDefault, MaybeResource, System, SystemGroup, World};
 use ambient_element::ambient_system;
@@ -29,7 +29,7 @@ use ambient_gpu::{
 use ambient_renderer::lod::lod_system;
 use ambient_std::{
     asset_cache::{AssetCache, SyncAssetKeyExt},
-    fps_counter::{FpsCounter, FpsSample},
+    fps_counter::{FpsCounter, FpsSample, FrameTimeStats},
 };
 use ambient_sys::task::RuntimeHandle;
 use glam::{uvec2, vec2, UVec2, Vec2};
@@ -41,9 +41,16 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
-use crate::renderers::ExamplesRender;
+use crate::{
+    cursor::WindowCursor,
+    renderers::ExamplesRender,
+    window::{update_window_resources, WindowSettings},
+};
 
+mod cursor;
+pub mod golden;
 mod renderers;
+mod window;
 
 fn default_title() -> String {
     "ambient".into()
@@ -53,6 +60,8 @@ components!("app", {
     @[MakeDefault[default_title], Debuggable, MaybeResource]
     window_title: String,
     fps_stats: FpsSample,
+    /// The frame time distribution since the app started
+    frame_time_stats: FrameTimeStats,
 });
 
 pub fn init_all_components() {
@@ -87,10 +96,15 @@ pub fn gpu_world_sync_systems() -> SystemGroup<GpuWorldSyncEvent> {
 }
 
 pub fn world_instance_systems(full: bool) -> SystemGroup {
+    world_instance_systems_with_time(full, Box::new(TimeResourcesSystem::new()))
+}
+
+/// Like [world_instance_systems], with `time_system` updating the time resources instead of the wall clock
+fn world_instance_systems_with_time(full: bool, time_system: DynSystem) -> SystemGroup {
     SystemGroup::new(
         "world_instance",
         vec![
-            Box::new(TimeResourcesSystem::new()),
+            time_system,
             Box::new(async_ecs_systems()),
             on_frame_system(),
             remove_at_time_system(),
@@ -103,6 +117,7 @@ pub fn world_instance_systems(full: bool) -> SystemGroup {
             Box::new(ambient_animation::animation_systems()),
             Box::new(TransformSystem::new()),
             Box::new(ambient_renderer::skinning::skinning_systems()),
+            Box::new(ambient_renderer::trails::trail_systems()),
             Box::new(bounding_systems()),
             Box::new(camera_systems()),
         ],
@@ -148,10 +163,16 @@ pub fn world_instance_resources(resources: AppResources) -> EntityData {
         .set(ambient_core::dtime(), 0.)
         .set(gpu_world(), GpuWorld::new_arced(resources.assets))
         .append(ambient_input::picking::resources())
+        .append(ambient_input::resources())
         .append(ambient_core::async_ecs::async_ecs_resources())
         .set(ambient_core::window_physical_size(), resources.window_physical_size)
         .set(ambient_core::window_logical_size(), resources.window_logical_size)
         .set(ambient_core::window_scale_factor(), resources.window_scale_factor)
+        .set(ambient_core::window_focused(), true)
+        .set(ambient_core::window_custom_cursors(), false)
+        .set(ambient_core::window_mode(), WindowMode::Windowed)
+        .set(ambient_core::window_monitors(), Vec::new())
+        .set(ambient_core::window_monitor(), None)
         .set(ambient_core::window_ctl(), resources.ctl_tx)
 }
 
@@ -270,6 +291,9 @@ impl AppBuilder {
         let resources = world_instance_resources(app_resources);
 
         world.add_components(world.resource_entity(), resources).unwrap();
+        update_window_resources(&window, &mut world);
+        let cursor = WindowCursor::new(&window);
+        world.set(world.resource_entity(), ambient_core::window_custom_cursors(), cursor.custom_cursors()).unwrap();
         if self.ui_renderer || self.main_renderer {
             if !self.main_renderer {
                 let renderer = Arc::new(Mutex::new(UIRender::new(&mut world)));
@@ -308,6 +332,8 @@ impl AppBuilder {
             _puffin: puffin_server,
             modifiers: Default::default(),
             ctl_rx,
+            window_settings: WindowSettings::default(),
+            cursor,
         })
     }
 
@@ -354,6 +380,8 @@ pub struct App {
     modifiers: ModifiersState,
 
     window_focused: bool,
+    window_settings: WindowSettings,
+    cursor: WindowCursor,
 }
 
 impl std::fmt::Debug for App {
@@ -423,8 +451,14 @@ impl App {
                         WindowCtl::GrabCursor(mode) => {
                             self.window.set_cursor_grab(mode).ok();
                         }
-                        WindowCtl::ShowCursor(show) => self.window.set_cursor_visible(show),
-                        WindowCtl::SetCursorIcon(icon) => self.window.set_cursor_icon(icon),
+                        WindowCtl::ShowCursor(show) => self.cursor.show(&self.window, show),
+                        WindowCtl::SetCursorIcon(icon) => self.cursor.set_icon(&self.window, icon),
+                        WindowCtl::SetCustomCursor(image) => self.cursor.set_custom(&self.window, image),
+                        WindowCtl::SetTitle(title) => {
+                            self.window.set_title(&title);
+                            world.set(world.resource_entity(), self::window_title(), title).unwrap();
+                        }
+                        ctl => self.window_settings.handle(&self.window, world, ctl),
                     }
                 }
 
@@ -439,16 +473,28 @@ impl App {
 
                 if let Some(fps) = self.fps.frame_next() {
                     world.set(world.resource_entity(), self::fps_stats(), fps.clone()).unwrap();
+                    world.set(world.resource_entity(), self::frame_time_stats(), self.fps.frame_times().stats()).unwrap();
                     self.window.set_title(&format!("{} [{}, {} entities]", world.resource(window_title()), fps.dump_both(), world.len()));
                 }
 
                 self.window.request_redraw();
                 profiling::finish_frame!();
             }
+            Event::RedrawEventsCleared => {
+                // The frame has been submitted by now, so the resources dropped during it are only destroyed once the GPU is done with it
+                profiling::scope!("gpu_garbage");
+                let gpu = world.resource(gpu()).clone();
+                gpu.garbage.collect(&gpu.queue, GPU_GARBAGE_BUDGET);
+            }
 
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::Focused(focused) => {
                     self.window_focused = *focused;
+                    world.set_if_changed(world.resource_entity(), window_focused(), *focused).unwrap();
+                }
+                WindowEvent::Moved(_) => {
+                    // The window may have moved to another monitor
+                    update_window_resources(&self.window, world);
                 }
                 WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
                     *self.world.resource_mut(window_scale_factor()) = *scale_factor;
@@ -458,13 +504,16 @@ impl App {
                     gpu.resize(*size);
 
                     let size = uvec2(size.width, size.height);
-                    let logical_size = (size.as_dvec2() * self.window.scale_factor()).as_uvec2();
+                    let logical_size = (size.as_dvec2() / self.window.scale_factor()).as_uvec2();
 
                     world.set_if_changed(world.resource_entity(), window_physical_size(), size).unwrap();
                     world.set_if_changed(world.resource_entity(), window_logical_size(), logical_size).unwrap();
+                    // Fullscreen can also be toggled by the OS, which resizes the window
+                    update_window_resources(&self.window, world);
                 }
                 WindowEvent::CloseRequested => {
                     tracing::info!("Closing...");
+                    self.export_frame_times();
                     *control_flow = ControlFlow::Exit;
                 }
                 WindowEvent::KeyboardInput { input, .. } => {
@@ -472,6 +521,7 @@ impl App {
                         if input.state == ElementState::Pressed {
                             if let VirtualKeyCode::Q = keycode {
                                 if self.modifiers.logo() {
+                                    self.export_frame_times();
                                     *control_flow = ControlFlow::Exit;
                                 }
                             }
@@ -491,6 +541,23 @@ impl App {
             _ => {}
         }
     }
+    /// Logs the frame time distribution of the session, and writes it to the directory in `AMBIENT_FRAME_TIMES_DIR`
+    /// (as `frame_times.json` and `frame_times.csv`) if it's set
+    fn export_frame_times(&self) {
+        let frame_times = self.fps.frame_times();
+        let stats = frame_times.stats();
+        tracing::info!("Frame times: {}", stats.dump());
+        if let Ok(dir) = std::env::var("AMBIENT_FRAME_TIMES_DIR") {
+            let dir = std::path::PathBuf::from(dir);
+            let res = std::fs::create_dir_all(&dir)
+                .and_then(|_| std::fs::write(dir.join("frame_times.json"), serde_json::to_string_pretty(&stats).unwrap()))
+                .and_then(|_| std::fs::write(dir.join("frame_times.csv"), frame_times.to_csv()));
+            match res {
+                Ok(()) => tracing::info!("Frame times written to {dir:?}"),
+                Err(err) => tracing::error!("Failed to write frame times to {dir:?}: {err}"),
+            }
+        }
+    }
     pub fn add_system(&mut self, system: DynSystem) -> &mut Self {
         self.systems.add(system);
         self
@@ -533,6 +600,9 @@ impl System for MeshBufferUpdate {
     }
 }
 
+/// How long the destruction of the dropped GPU resources may take per frame
+const GPU_GARBAGE_BUDGET: Duration = Duration::from_millis(1);
+
 #[derive(Debug)]
 pub struct DummySystem;
 impl System for DummySystem {
@@ -8,8 +8,16 @@ use ambient_gpu::{
     gpu::Gpu,
     texture::{Texture, TextureView},
 };
-use ambient_renderer::{renderer_stats, RenderTarget, Renderer, RendererConfig, RendererTarget};
-use ambient_std::{asset_cache::SyncAssetKeyExt, color::Color};
+use ambient_renderer::{
+    render_scale, renderer_stats, scaled_target_size, RenderTarget, Renderer, RendererConfig, RendererSettings, RendererSettingsPath,
+    RendererTarget,
+};
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKeyExt},
+    color::Color,
+    colorspace::RgbColorSpace,
+    watched_config::WatchedConfig,
+};
 use ambient_ui::app_background_color;
 use glam::uvec2;
 use parking_lot::Mutex;
@@ -64,7 +72,15 @@ pub struct ExamplesRender {
     main: Option<Renderer>,
     ui: Option<Renderer>,
     blit: Arc<Blitter>,
+    scene_blit: Arc<Blitter>,
     render_target: RenderTarget,
+    /// The target the main scene is rendered to when its [render_scale] isn't 1; it's then upscaled to the `render_target`
+    /// before the UI is rendered on top of it at the native resolution
+    scene_target: Option<RenderTarget>,
+    scale: f32,
+    /// The settings the [RendererSettings::output_color_space] of the final blit is taken from
+    settings: Option<WatchedConfig<RendererSettings>>,
+    assets: AssetCache,
 }
 
 impl ExamplesRender {
@@ -75,13 +91,15 @@ impl ExamplesRender {
         let wind_size = *world.resource(ambient_core::window_physical_size());
 
         let render_target = RenderTarget::new(gpu.clone(), wind_size, None);
+        let settings = RendererSettingsPath.try_get(&assets).map(WatchedConfig::<RendererSettings>::new);
+        let output_color_space = settings.as_ref().map(|settings| settings.current().output_color_space).unwrap_or_default();
 
         Self {
             main: if main {
                 let mut renderer = Renderer::new(
                     world,
                     world.resource(asset_cache()).clone(),
-                    RendererConfig { scene: main_scene(), shadows: true, ..Default::default() },
+                    RendererConfig { scene: main_scene(), shadows: true, hdr: true, occlusion_culling: true, ..Default::default() },
                 );
                 renderer.post_transparent = Some(Box::new(GizmoRenderer::new(&assets)));
                 Some(renderer)
@@ -97,13 +115,30 @@ impl ExamplesRender {
             } else {
                 None
             },
-            blit: BlitterKey { format: gpu.swapchain_format().into(), linear: false }.get(&world.resource(asset_cache()).clone()),
+            blit: Self::create_blit(&gpu, &assets, output_color_space),
+            scene_blit: BlitterKey { format: gpu.swapchain_format().into(), linear: true, color_space: None }.get(&assets),
             render_target,
+            scene_target: None,
+            scale: 1.,
+            settings,
+            assets,
             gpu,
         }
     }
+    /// The blit to the window, converting the frame to the color space of the display
+    fn create_blit(gpu: &Gpu, assets: &AssetCache, output_color_space: RgbColorSpace) -> Arc<Blitter> {
+        let color_space = Some(output_color_space).filter(|color_space| *color_space != RgbColorSpace::Srgb);
+        BlitterKey { format: gpu.swapchain_format().into(), linear: false, color_space }.get(assets)
+    }
     fn resize(&mut self, size: &PhysicalSize<u32>) {
         self.render_target = RenderTarget::new(self.gpu.clone(), uvec2(size.width, size.height), None);
+        self.update_scene_target();
+    }
+    fn update_scene_target(&mut self) {
+        let size = uvec2(self.render_target.color_buffer.size.width, self.render_target.color_buffer.size.height);
+        let scene_size = scaled_target_size(size, self.scale);
+        self.scene_target =
+            if self.main.is_some() && scene_size != size { Some(RenderTarget::new(self.gpu.clone(), scene_size, None)) } else { None };
     }
 
     pub fn dump_to_tmp_file(&self) {
@@ -143,6 +178,14 @@ impl std::fmt::Debug for ExamplesRender {
 impl System for ExamplesRender {
     fn run(&mut self, world: &mut World, _: &FrameEvent) {
         profiling::scope!("Renderers.run");
+        if let Some(settings) = self.settings.as_mut().and_then(|settings| settings.poll()) {
+            self.blit = Self::create_blit(&self.gpu, &self.assets, settings.output_color_space);
+        }
+        let scale = world.resource_opt(render_scale()).copied().unwrap_or(1.);
+        if scale != self.scale {
+            self.scale = scale;
+            self.update_scene_target();
+        }
         let mut encoder = self.gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
         let mut post_submit = Vec::new();
 
@@ -152,10 +195,24 @@ impl System for ExamplesRender {
                 world,
                 &mut encoder,
                 &mut post_submit,
-                RendererTarget::Target(&self.render_target),
+                RendererTarget::Target(self.scene_target.as_ref().unwrap_or(&self.render_target)),
                 Some(Color::rgba(0., 0., 0., 1.)),
             );
         }
+        if let Some(scene_target) = &self.scene_target {
+            profiling::scope!("Upscale");
+            self.scene_blit.run(&mut encoder, &scene_target.color_buffer_view, &self.render_target.color_buffer_view);
+            // The depth of the scene doesn't match the native resolution, and the UI doesn't need it
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("ExamplesRender.clear_depth"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.render_target.depth_buffer_view,
+                    depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(0.0), store: true }),
+                    stencil_ops: None,
+                }),
+            });
+        }
         if let Some(ui) = &mut self.ui {
             profiling::scope!("UI");
             ui.render(
@@ -270,6 +327,7 @@ impl UIRender {
                 depth: &self.depth_buffer_view,
                 size: wgpu::Extent3d { width: window_size.x, height: window_size.y, depth_or_array_layers: 1 },
                 normals: &self.normals_view,
+                format: gpu.swapchain_format(),
             },
             Some(app_background_color()),
         );
@@ -0,0 +1,117 @@
+use ambient_core::{
+    window::{MonitorInfo, VideoModeInfo, WindowCtl, WindowMode},
+    window_mode, window_monitor, window_monitors,
+};
+use ambient_ecs::World;
+use glam::{ivec2, uvec2, UVec2};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    monitor::{MonitorHandle, VideoMode},
+    window::{Fullscreen, Window},
+};
+
+/// The mode, monitor and resolution the window was asked for, which are re-applied together whenever one of them changes
+#[derive(Debug, Default)]
+pub(crate) struct WindowSettings {
+    mode: WindowMode,
+    monitor: Option<usize>,
+    resolution: Option<UVec2>,
+}
+impl WindowSettings {
+    /// Handles the window controls which change the mode, monitor or size of the window
+    pub fn handle(&mut self, window: &Window, world: &mut World, ctl: WindowCtl) {
+        match ctl {
+            WindowCtl::SetMode(mode) => {
+                self.mode = mode;
+                self.apply_mode(window);
+            }
+            WindowCtl::SetMonitor(monitor) => {
+                self.monitor = Some(monitor);
+                if self.mode == WindowMode::Windowed {
+                    if let Some(monitor) = self.monitor(window) {
+                        window.set_outer_position(monitor.position());
+                    }
+                } else {
+                    self.apply_mode(window);
+                }
+            }
+            WindowCtl::SetResolution(resolution) => {
+                self.resolution = Some(resolution);
+                match self.mode {
+                    WindowMode::Windowed => window.set_inner_size(PhysicalSize::new(resolution.x, resolution.y)),
+                    WindowMode::BorderlessFullscreen => {}
+                    WindowMode::ExclusiveFullscreen => self.apply_mode(window),
+                }
+            }
+            WindowCtl::SetMinSize(size) => window.set_min_inner_size(size.map(|size| PhysicalSize::new(size.x, size.y))),
+            WindowCtl::SetIcon(icon) => window.set_window_icon(icon),
+            _ => return,
+        }
+        update_window_resources(window, world);
+    }
+    fn monitor(&self, window: &Window) -> Option<MonitorHandle> {
+        self.monitor.and_then(|index| window.available_monitors().nth(index)).or_else(|| window.current_monitor())
+    }
+    fn apply_mode(&self, window: &Window) {
+        let monitor = self.monitor(window);
+        let fullscreen = match self.mode {
+            WindowMode::Windowed => None,
+            WindowMode::BorderlessFullscreen => Some(Fullscreen::Borderless(monitor)),
+            WindowMode::ExclusiveFullscreen => match monitor.as_ref().and_then(|monitor| self.video_mode(monitor)) {
+                Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+                None => {
+                    tracing::warn!("No video mode matches the resolution {:?}, using borderless fullscreen instead", self.resolution);
+                    Some(Fullscreen::Borderless(monitor))
+                }
+            },
+        };
+        window.set_fullscreen(fullscreen);
+        if let (WindowMode::Windowed, Some(resolution)) = (self.mode, self.resolution) {
+            window.set_inner_size(PhysicalSize::new(resolution.x, resolution.y));
+        }
+    }
+    /// The video mode of `monitor` with the requested resolution (or the current one), at the highest refresh rate
+    fn video_mode(&self, monitor: &MonitorHandle) -> Option<VideoMode> {
+        let size = self.resolution.unwrap_or_else(|| uvec2(monitor.size().width, monitor.size().height));
+        monitor
+            .video_modes()
+            .filter(|mode| mode.size() == PhysicalSize::new(size.x, size.y))
+            .max_by_key(|mode| (mode.refresh_rate_millihertz(), mode.bit_depth()))
+    }
+}
+
+/// Updates `window_mode`, `window_monitors` and `window_monitor` from the `window`
+pub(crate) fn update_window_resources(window: &Window, world: &mut World) {
+    let mode = match window.fullscreen() {
+        None => WindowMode::Windowed,
+        Some(Fullscreen::Borderless(_)) => WindowMode::BorderlessFullscreen,
+        Some(Fullscreen::Exclusive(_)) => WindowMode::ExclusiveFullscreen,
+    };
+    let monitors = window.available_monitors().collect::<Vec<_>>();
+    let current = window.current_monitor().and_then(|current| monitors.iter().position(|monitor| *monitor == current));
+    let monitors = monitors.iter().map(monitor_info).collect::<Vec<_>>();
+
+    let resources = world.resource_entity();
+    world.set_if_changed(resources, window_mode(), mode).unwrap();
+    world.set_if_changed(resources, window_monitors(), monitors).unwrap();
+    world.set_if_changed(resources, window_monitor(), current).unwrap();
+}
+
+fn monitor_info(monitor: &MonitorHandle) -> MonitorInfo {
+    let PhysicalPosition { x, y } = monitor.position();
+    let PhysicalSize { width, height } = monitor.size();
+    MonitorInfo {
+        name: monitor.name().unwrap_or_default(),
+        position: ivec2(x, y),
+        size: uvec2(width, height),
+        scale_factor: monitor.scale_factor(),
+        video_modes: monitor
+            .video_modes()
+            .map(|mode| VideoModeInfo {
+                size: uvec2(mode.size().width, mode.size().height),
+                refresh_rate_millihertz: mode.refresh_rate_millihertz(),
+                bit_depth: mode.bit_depth(),
+            })
+            .collect(),
+    }
+}
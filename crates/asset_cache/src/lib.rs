@@ -2,7 +2,7 @@ mod background;
 
 use std::{
     any::Any,
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
     ops::Deref,
     pin::Pin,
     sync::{Arc, Weak},
@@ -75,8 +75,14 @@ impl Deref for AssetKey {
 
 #[derive(Clone)]
 pub(crate) enum ContentState {
-    Loading { fut: WeakShared<BoxFuture<'static, LoadPayload>> },
-    Loaded { value: Arc<dyn AssetHolder>, check_alive: Arc<dyn Fn() -> bool + Send + Sync> },
+    Loading {
+        fut: WeakShared<BoxFuture<'static, LoadPayload>>,
+    },
+    Loaded {
+        value: Arc<dyn AssetHolder>,
+        check_alive: Arc<dyn Fn() -> bool + Send + Sync>,
+        ref_count: Arc<dyn Fn() -> Option<usize> + Send + Sync>,
+    },
     Aborted,
     Expired,
 }
@@ -95,11 +101,17 @@ impl std::fmt::Debug for ContentState {
 struct AsyncAssetLoc {
     #[allow(dead_code)]
     key: AssetKey,
+    type_name: &'static str,
     /// Since there may be multiple tasks for keepalive, keepalive_end should only be called when
     /// **All** keepalive tasks are done.
     keepalive_guard: Weak<KeepaliveGuard>,
     content: ContentState,
     keepalive_task: Option<ChildTask<()>>,
+    cpu_size: u64,
+    gpu_size: u64,
+    last_used: time::Instant,
+    /// Assets with [AssetKeepalive::Forever] are never released to stay within the [MemoryBudget]
+    keepalive_forever: bool,
 }
 
 impl AsyncAssetLoc {
@@ -157,6 +169,51 @@ struct SyncAssetLoc {
     content: Arc<Mutex<Option<Arc<dyn AssetHolder>>>>,
 }
 
+/// Limits how much memory the assets kept alive by an [AssetCache] can use.
+///
+/// The cache can only release the assets it keeps alive (see [AssetKeepalive]); assets which are still referenced
+/// elsewhere stay loaded, but count towards the budget. The size of an asset is reported by
+/// [AsyncAssetKey::cpu_size] and [AsyncAssetKey::gpu_size].
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBudget {
+    /// Once the total size of the loaded assets exceeds this, the least recently used ones are released until it doesn't
+    pub max_bytes: Option<u64>,
+    /// Assets which haven't been used for this long are released, even if their keepalive hasn't ended yet
+    pub ttl: Option<Duration>,
+}
+impl MemoryBudget {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_bytes.is_none() && self.ttl.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub cpu: u64,
+    pub gpu: u64,
+}
+impl MemoryUsage {
+    pub fn total(&self) -> u64 {
+        self.cpu + self.gpu
+    }
+}
+
+/// A snapshot of an asset which is loaded and alive, see [AssetCache::loaded_assets]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadedAsset {
+    pub key: AssetKey,
+    /// The type of the asset, as given by [std::any::type_name]
+    pub type_name: String,
+    pub cpu_size: u64,
+    pub gpu_size: u64,
+    /// The number of strong references to the asset, including the one the cache holds while it's kept alive.
+    /// `None` if the asset type doesn't support it (see [Asset::ref_count]).
+    pub ref_count: Option<usize>,
+    /// How long the last load of the asset took
+    pub load_time: Option<Duration>,
+    pub pinned: bool,
+}
+
 #[derive(Clone)]
 pub struct AssetCache {
     async_cache: Arc<Mutex<HashMap<AssetKey, AsyncAssetLoc>>>,
@@ -164,6 +221,9 @@ pub struct AssetCache {
     pub timeline: Arc<Mutex<AssetsTimeline>>,
     runtime: RuntimeHandle,
     max_keepalive: Option<Duration>,
+    memory_budget: Arc<Mutex<MemoryBudget>>,
+    /// Pinned assets are never released to stay within the [MemoryBudget]
+    pinned: Arc<Mutex<HashSet<AssetKey>>>,
     /// stack is used for nested asset loading, to visualize for the timeline who loaded what
     stack: Vec<AssetKey>,
 }
@@ -180,6 +240,8 @@ impl AssetCache {
             timeline: Arc::new(Mutex::new(AssetsTimeline::new())),
             runtime: runtime.clone(),
             max_keepalive,
+            memory_budget: Default::default(),
+            pinned: Default::default(),
             stack: Vec::new(),
         };
         {
@@ -187,12 +249,70 @@ impl AssetCache {
             runtime.spawn(async move {
                 loop {
                     time::sleep(Duration::from_millis(1000)).await;
+                    assets.enforce_memory_budget();
                     assets.clean_up_dropped();
                 }
             });
         }
         assets
     }
+    pub fn memory_budget(&self) -> MemoryBudget {
+        self.memory_budget.lock().clone()
+    }
+    /// The budget is enforced periodically, rather than when an asset is loaded
+    pub fn set_memory_budget(&self, budget: MemoryBudget) {
+        *self.memory_budget.lock() = budget;
+    }
+    /// Returns the memory used by the loaded assets which are still alive
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut cache = self.async_cache.lock();
+        let mut usage = MemoryUsage::default();
+        for loc in cache.values_mut() {
+            if let AsyncAssetState::Alive = loc.state() {
+                usage.cpu += loc.cpu_size;
+                usage.gpu += loc.gpu_size;
+            }
+        }
+        usage
+    }
+    /// Returns a snapshot of the assets which are loaded and still alive
+    pub fn loaded_assets(&self) -> Vec<LoadedAsset> {
+        let pinned = self.pinned.lock().clone();
+        let timeline = self.timeline.lock();
+        let mut cache = self.async_cache.lock();
+        let mut assets = Vec::new();
+        for (key, loc) in cache.iter_mut() {
+            if !matches!(loc.state(), AsyncAssetState::Alive) {
+                continue;
+            }
+            let ref_count = match &loc.content {
+                ContentState::Loaded { ref_count, .. } => ref_count(),
+                _ => None,
+            };
+            let load_time = timeline
+                .assets
+                .get(key)
+                .and_then(|asset| asset.lifetimes.last())
+                .and_then(|lifetime| Some((lifetime.end_load? - lifetime.start_load).to_std().unwrap_or_default()));
+            assets.push(LoadedAsset {
+                key: key.clone(),
+                type_name: loc.type_name.to_string(),
+                cpu_size: loc.cpu_size,
+                gpu_size: loc.gpu_size,
+                ref_count,
+                load_time,
+                pinned: pinned.contains(key),
+            });
+        }
+        assets
+    }
+    /// Prevents the asset from being released to stay within the [MemoryBudget]. This can be done before it's loaded.
+    pub fn pin<T: 'static + Clone + Asset + Send + Sync, K: AsyncAssetKeyExt<T>>(&self, key: &K) {
+        self.pinned.lock().insert(AssetKey::new(key.key()));
+    }
+    pub fn unpin<T: 'static + Clone + Asset + Send + Sync, K: AsyncAssetKeyExt<T>>(&self, key: &K) {
+        self.pinned.lock().remove(&AssetKey::new(key.key()));
+    }
     #[deprecated(note = "Use a SyncAssetKey instead")]
     pub fn get_sync<K: Into<String>, T: Clone + Sync + Send + 'static>(
         &self,
@@ -250,6 +370,48 @@ impl AssetCache {
         }
     }
 
+    /// Releases the assets kept alive by the cache which have outlived the TTL of the [MemoryBudget], and then the least
+    /// recently used ones until the memory usage is within the budget. Returns the number of released assets.
+    fn enforce_memory_budget(&self) -> usize {
+        let budget = self.memory_budget();
+        if budget.is_unlimited() {
+            return 0;
+        }
+        let pinned = self.pinned.lock().clone();
+        let mut cache = self.async_cache.lock();
+        let now = time::Instant::now();
+
+        let mut usage = 0;
+        let mut releasable = Vec::new();
+        for (key, loc) in cache.iter_mut() {
+            if let AsyncAssetState::Alive = loc.state() {
+                usage += loc.cpu_size + loc.gpu_size;
+                if loc.keepalive_task.is_some() && !loc.keepalive_forever && !pinned.contains(key) {
+                    releasable.push((loc.last_used, key.clone()));
+                }
+            }
+        }
+        releasable.sort_by_key(|(last_used, _)| *last_used);
+
+        let mut released = 0;
+        for (last_used, key) in releasable {
+            let expired = budget.ttl.map(|ttl| now.duration_since(last_used) > ttl).unwrap_or(false);
+            let over_budget = budget.max_bytes.map(|max_bytes| usage > max_bytes).unwrap_or(false);
+            if !expired && !over_budget {
+                // The remaining assets were used more recently, so they haven't expired either
+                break;
+            }
+            let loc = cache.get_mut(&key).unwrap();
+            tracing::debug!("Releasing {key:?} to stay within the memory budget");
+            // Dropping the task drops the reference it keeps to the asset. If the asset is still referenced elsewhere it
+            // stays alive, which will be accounted for the next time the budget is enforced
+            loc.keepalive_task = None;
+            usage = usage.saturating_sub(loc.cpu_size + loc.gpu_size);
+            released += 1;
+        }
+        released
+    }
+
     /// Returns a snapshot of the current state of the asset
     pub(crate) fn content_state<T: 'static + Clone + Asset + Send + Sync, K: AsyncAssetKeyExt<T>>(&self, key: &K) -> Option<ContentState> {
         let key = AssetKey::new(key.key());
@@ -357,7 +519,17 @@ impl AssetCache {
                 let (fut, content, keepalive_task) = load();
                 let key = slot.key().clone();
 
-                slot.insert(AsyncAssetLoc { key, content, keepalive_task, keepalive_guard: Weak::new() });
+                slot.insert(AsyncAssetLoc {
+                    key,
+                    type_name: std::any::type_name::<T>(),
+                    content,
+                    keepalive_task,
+                    keepalive_guard: Weak::new(),
+                    cpu_size: 0,
+                    gpu_size: 0,
+                    last_used: time::Instant::now(),
+                    keepalive_forever: false,
+                });
 
                 fut
             }
@@ -385,6 +557,8 @@ impl AssetCache {
 
         let mut cache = self.async_cache.lock();
         let loc = cache.get_mut(&asset_key).expect("Asset loc was removed during loading");
+        loc.last_used = time::Instant::now();
+        loc.keepalive_forever = matches!(keepalive, AssetKeepalive::Forever);
 
         // Start or replace the keepalive task
 
@@ -552,6 +726,10 @@ pub trait Asset {
     fn from_weak(weak: &Self::WeakType) -> Option<Self>
     where
         Self: Sized;
+    /// The number of strong references to the asset, if it can be known
+    fn ref_count(_weak: &Self::WeakType) -> Option<usize> {
+        None
+    }
 }
 impl<T: Sync + Send + ?Sized> Asset for Arc<T> {
     type WeakType = Weak<T>;
@@ -561,6 +739,9 @@ impl<T: Sync + Send + ?Sized> Asset for Arc<T> {
     fn from_weak(weak: &Self::WeakType) -> Option<Self> {
         Weak::upgrade(weak)
     }
+    fn ref_count(weak: &Self::WeakType) -> Option<usize> {
+        Some(Weak::strong_count(weak))
+    }
 }
 
 impl<T: Asset + Sync + Send, E: Clone + Sync + Send> Asset for Result<T, E> {
@@ -577,6 +758,9 @@ impl<T: Asset + Sync + Send, E: Clone + Sync + Send> Asset for Result<T, E> {
             Err(err) => Some(Err(err.clone())),
         }
     }
+    fn ref_count(weak: &Self::WeakType) -> Option<usize> {
+        weak.as_ref().ok().and_then(T::ref_count)
+    }
 }
 
 impl<T: Asset + Sync + Send> Asset for Option<T> {
@@ -590,6 +774,9 @@ impl<T: Asset + Sync + Send> Asset for Option<T> {
             None => Some(None),
         }
     }
+    fn ref_count(weak: &Self::WeakType) -> Option<usize> {
+        weak.as_ref().and_then(T::ref_count)
+    }
 }
 
 impl<T0: Asset + Sync + Send, T1: Asset + Sync + Send> Asset for (T0, T1) {
@@ -757,6 +944,10 @@ where
                 let weak_res = T::to_weak(&res);
                 move || T::from_weak(&weak_res).is_some()
             });
+            let ref_count = Arc::new({
+                let weak_res = T::to_weak(&res);
+                move || T::ref_count(&weak_res)
+            });
 
             // Type erase
             let value = Arc::new(res) as Arc<dyn AssetHolder>;
@@ -767,7 +958,9 @@ where
 
             // Replace the loading state with the loaded state
             assert!(loc.content.is_loading());
-            loc.content = ContentState::Loaded { value: weak_res, check_alive };
+            loc.content = ContentState::Loaded { value: weak_res, check_alive, ref_count };
+            loc.cpu_size = cpu_size.unwrap_or_default();
+            loc.gpu_size = gpu_size.unwrap_or_default();
 
             Poll::Ready(LoadPayload { asset_key: p.asset_key.clone(), strong: value })
         } else {
@@ -877,6 +1070,65 @@ mod test {
             assert_eq!(val, 3);
         }
     }
+
+    #[tokio::test]
+    async fn memory_budget() {
+        #[derive(Debug, Clone)]
+        struct SizedKey(u32);
+        #[async_trait]
+        impl AsyncAssetKey<Arc<u32>> for SizedKey {
+            async fn load(self, _assets: AssetCache) -> Arc<u32> {
+                Arc::new(self.0)
+            }
+            fn cpu_size(&self, _asset: &Arc<u32>) -> Option<u64> {
+                Some(10)
+            }
+        }
+
+        let assets = AssetCache::new(tokio::runtime::Handle::current());
+        assets.pin(&SizedKey(0));
+        for i in 0..3 {
+            SizedKey(i).get(&assets).await;
+        }
+        let in_use = SizedKey(3).get(&assets).await;
+        assert_eq!(assets.memory_usage(), MemoryUsage { cpu: 40, gpu: 0 });
+
+        // The pinned asset can't be released, and the most recently used one isn't needed to get within the budget
+        assets.set_memory_budget(MemoryBudget { max_bytes: Some(25), ttl: None });
+        assert_eq!(assets.enforce_memory_budget(), 2);
+        // Let the keepalive tasks be cancelled
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(SizedKey(0).is_loaded(&assets).is_some());
+        assert!(SizedKey(1).is_loaded(&assets).is_none());
+        assert!(SizedKey(2).is_loaded(&assets).is_none());
+        assert!(SizedKey(3).is_loaded(&assets).is_some());
+        assert_eq!(assets.memory_usage(), MemoryUsage { cpu: 20, gpu: 0 });
+        assert_eq!(*in_use, 3);
+
+        assets.unpin(&SizedKey(0));
+        assets.set_memory_budget(MemoryBudget { max_bytes: None, ttl: Some(Duration::ZERO) });
+        assert_eq!(assets.enforce_memory_budget(), 2);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(SizedKey(0).is_loaded(&assets).is_none());
+        // Still referenced
+        assert!(SizedKey(3).is_loaded(&assets).is_some());
+    }
+
+    #[tokio::test]
+    async fn loaded_assets() {
+        let assets = AssetCache::new(tokio::runtime::Handle::current());
+        let asset = TestAssetKey { name: "foo".into() }.get(&assets).await;
+        let other = asset.clone();
+
+        let loaded = assets.loaded_assets();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].type_name, std::any::type_name::<Arc<TestAsset>>());
+        // The two references above, and the one kept alive by the cache
+        assert_eq!(loaded[0].ref_count, Some(3));
+        assert!(loaded[0].load_time.unwrap() >= Duration::from_secs(1));
+        drop((asset, other));
+        assert_eq!(assets.loaded_assets()[0].ref_count, Some(1));
+    }
 }
 
 struct KeepaliveGuard {
@@ -0,0 +1,82 @@
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait}, InputCallbackInfo, Sample
+};
+
+use crate::{
+    error::{Error, Result}, SampleRate
+};
+
+/// Wraps a cpal input Stream, which records the default input device. Can not be moved across
+/// threads.
+pub struct AudioCapture {
+    _stream: cpal::Stream,
+    sample_rate: SampleRate,
+    _device: cpal::Device,
+}
+
+impl AudioCapture {
+    /// Starts recording, handing the samples to `on_samples` as they come in, mixed down to mono
+    pub fn new(on_samples: impl FnMut(&[f32]) + Send + 'static) -> Result<Self> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or(Error::NoInputDevice)?;
+
+        let config = device.default_input_config()?;
+
+        let format = config.sample_format();
+        let config: cpal::StreamConfig = config.into();
+
+        tracing::info!("Audio capture config: {config:?}");
+        if config.channels < 1 {
+            return Err(Error::InvalidChannelCount(config.channels));
+        }
+
+        let err_func = |err| log::error!("Audio capture error: {err}");
+
+        let channels = config.channels;
+
+        fn reader<T>(
+            channel_count: u16,
+            mut on_samples: impl FnMut(&[f32]),
+        ) -> impl FnMut(&[T], &InputCallbackInfo)
+        where
+            T: Sample,
+        {
+            let mut buf = Vec::new();
+            move |data, _| {
+                buf.clear();
+                buf.extend(data.chunks(channel_count as usize).map(|frame| {
+                    frame.iter().map(|v| v.to_f32()).sum::<f32>() / frame.len() as f32
+                }));
+
+                on_samples(&buf);
+            }
+        }
+
+        let stream = match format {
+            cpal::SampleFormat::I16 => {
+                device.build_input_stream(&config, reader::<i16>(channels, on_samples), err_func)
+            }
+            cpal::SampleFormat::U16 => {
+                device.build_input_stream(&config, reader::<u16>(channels, on_samples), err_func)
+            }
+            cpal::SampleFormat::F32 => {
+                device.build_input_stream(&config, reader::<f32>(channels, on_samples), err_func)
+            }
+        }?;
+
+        stream.play()?;
+
+        Ok(Self {
+            _stream: stream,
+            sample_rate: config.sample_rate.0 as _,
+            _device: device,
+        })
+    }
+
+    /// The rate of the samples handed to the callback
+    #[must_use]
+    pub fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+}
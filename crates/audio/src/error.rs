@@ -11,6 +11,8 @@ use crate::hrtf;
 pub enum Error {
     #[error("Failed to find audio output device")]
     NoOutputDevice,
+    #[error("Failed to find audio input device")]
+    NoInputDevice,
     #[error("Failed to find appropriate audio config")]
     NoOutputConfig,
     #[error("Default stream config error")]
@@ -1,4 +1,5 @@
 mod assets;
+mod capture;
 mod error;
 mod mixer;
 // mod sink;
@@ -18,6 +19,7 @@ pub mod vorbis;
 pub mod wav;
 
 pub use assets::*;
+pub use capture::*;
 pub use error::*;
 pub use mixer::*;
 // pub use sink::*;
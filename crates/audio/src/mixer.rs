@@ -1,5 +1,7 @@
 use std::{
-    future::Future, sync::{Arc, Weak}, task::Poll, thread, time::Duration
+    future::Future, sync::{
+        atomic::{AtomicUsize, Ordering}, Arc, Weak
+    }, task::Poll, thread, time::Duration
 };
 
 use parking_lot::Mutex;
@@ -15,10 +17,154 @@ new_key_type! {
 
 type SignalVec = Vec<(SoundId, Arc<dyn Signal>)>;
 
+/// The voices the mixer plays at once by default; the sounds beyond them are virtual
+pub const DEFAULT_MAX_VOICES: usize = 32;
+/// The audibility below which a sound is virtual, however many voices are free
+const MIN_AUDIBILITY: f32 = 1e-3;
+/// How much louder a sound which has a voice counts as, so that two sounds about as loud as each
+/// other don't keep taking the voice from one another
+const VOICE_HYSTERESIS: f32 = 1.25;
+/// How often the sounds are ranked for the voices, in samples, when the mixer is pulled a sample at
+/// a time
+const VOICE_UPDATE_INTERVAL: usize = 512;
+/// How long a sound fades in and out for as it gets and loses its voice, so that it doesn't click
+const VOICE_FADE: Duration = Duration::from_millis(20);
+
+/// What a sound competes with the other sounds for a voice of the mixer with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoicePriority {
+    /// The sounds of a higher priority take the voices of the ones of a lower priority
+    pub priority: i32,
+    /// How loud the sound is heard, where 1 is at full volume; among the sounds of the same
+    /// priority, the quietest lose their voices first
+    pub audibility: f32,
+}
+
+impl Default for VoicePriority {
+    fn default() -> Self {
+        Self {
+            priority: 0,
+            audibility: 1.0,
+        }
+    }
+}
+
+type PriorityFn = Box<dyn Fn() -> VoicePriority + Send + Sync>;
+
+/// A sound is *virtual* while it doesn't have a voice: it isn't heard, but its source keeps going
+/// without being mixed, so that the sound keeps time, and is heard from where it's at once it gets a
+/// voice again. A sound which ends while virtual ends unheard
 struct PlayingSound {
-    #[allow(dead_code)]
-    cursor: usize,
+    /// The samples played so far, heard or not
+    cursor: u64,
     source: Box<dyn Source>,
+    priority: PriorityFn,
+    /// Whether the sound has a voice, as of the last time the sounds were ranked
+    audible: bool,
+    /// Fades the sound in and out as it gets and loses its voice; it's virtual once faded out
+    gain: f32,
+}
+
+impl PlayingSound {
+    fn is_virtual(&self) -> bool {
+        !self.audible && self.gain <= 0.0
+    }
+
+    /// A sound which didn't start yet starts at full volume, rather than fading in
+    fn step_gain(&mut self, fade_step: f32) {
+        self.gain = if !self.audible {
+            (self.gain - fade_step).max(0.0)
+        } else if self.cursor == 0 {
+            1.0
+        } else {
+            (self.gain + fade_step).min(1.0)
+        };
+    }
+
+    /// Returns the next sample, which is silent while the sound is virtual, or None once it ended
+    fn next_sample(&mut self, fade_step: f32) -> Option<Frame> {
+        if self.is_virtual() {
+            self.source.next_sample()?;
+            self.cursor += 1;
+            return Some(Frame::ZERO);
+        }
+        self.step_gain(fade_step);
+        let sample = self.source.next_sample()?;
+        self.cursor += 1;
+        Some(sample * self.gain)
+    }
+
+    /// Adds the next samples to the output, returning false once the sound ended
+    fn sample_buffered(
+        &mut self,
+        output: &mut [Frame],
+        scratch: &mut Vec<Frame>,
+        fade_step: f32,
+    ) -> bool {
+        if self.is_virtual() {
+            // Skips the samples
+            scratch.clear();
+            scratch.resize(output.len(), Frame::ZERO);
+            let written = self.source.sample_buffered(scratch);
+            self.cursor += written as u64;
+            return written == output.len();
+        }
+
+        if self.audible && (self.gain >= 1.0 || self.cursor == 0) {
+            self.gain = 1.0;
+            let written = self.source.sample_buffered(output);
+            self.cursor += written as u64;
+            return written == output.len();
+        }
+
+        // Fading in or out
+        scratch.clear();
+        scratch.resize(output.len(), Frame::ZERO);
+        let written = self.source.sample_buffered(scratch);
+        self.cursor += written as u64;
+        for (v, &sample) in output.iter_mut().zip(&scratch[..written]) {
+            self.step_gain(fade_step);
+            *v += sample * self.gain;
+        }
+        written == output.len()
+    }
+}
+
+#[derive(Default)]
+struct Voices {
+    sounds: SlotMap<SoundId, PlayingSound>,
+    /// Reused to rank the sounds
+    ranking: Vec<(VoicePriority, SoundId)>,
+    /// Reused to fade the sounds in and out
+    scratch: Vec<Frame>,
+    /// The samples until the sounds are ranked again, when the mixer is pulled a sample at a time
+    until_update: usize,
+}
+
+impl Voices {
+    /// Gives the voices to the sounds of the highest priority, and among those to the loudest
+    fn update(&mut self, max_voices: usize) {
+        self.ranking.clear();
+        for (id, sound) in &mut self.sounds {
+            let mut priority = (sound.priority)();
+            if sound.audible {
+                priority.audibility *= VOICE_HYSTERESIS;
+            }
+            sound.audible = false;
+            if priority.audibility > MIN_AUDIBILITY {
+                self.ranking.push((priority, id));
+            }
+        }
+
+        self.ranking.sort_unstable_by(|(a, _), (b, _)| {
+            b.priority
+                .cmp(&a.priority)
+                .then(b.audibility.total_cmp(&a.audibility))
+        });
+        for &(_, id) in self.ranking.iter().take(max_voices) {
+            self.sounds[id].audible = true;
+        }
+    }
 }
 
 /// Handle to a playing sound
@@ -98,14 +244,18 @@ pub struct AudioMixer {
 struct AudioMixerInner {
     sample_rate: SampleRate,
     waiters: Mutex<SignalVec>,
-    sources: Mutex<SlotMap<SoundId, PlayingSound>>,
+    voices: Mutex<Voices>,
+    max_voices: AtomicUsize,
+    /// How much the gain of a sound changes by per sample, as it fades in or out
+    fade_step: f32,
 }
 
 impl std::fmt::Debug for AudioMixerInner {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AudioMixerInner")
             .field("sample_rate", &self.sample_rate)
-            .field("sources", &self.sources.lock().len())
+            .field("sources", &self.voices.lock().sounds.len())
+            .field("max_voices", &self.max_voices.load(Ordering::Relaxed))
             .finish()
     }
 }
@@ -115,12 +265,24 @@ impl AudioMixer {
         Self {
             inner: Arc::new(AudioMixerInner {
                 sample_rate,
-                sources: Mutex::default(),
+                voices: Mutex::default(),
                 waiters: Default::default(),
+                max_voices: AtomicUsize::new(DEFAULT_MAX_VOICES),
+                fade_step: 1.0 / (sample_rate as f32 * VOICE_FADE.as_secs_f32()),
             }),
         }
     }
 
+    /// Sets the most sounds played at once; the sounds of the lowest priority, and among those the
+    /// quietest, are virtual beyond them
+    pub fn set_max_voices(&self, max_voices: usize) {
+        self.inner.max_voices.store(max_voices, Ordering::Relaxed);
+    }
+
+    pub fn max_voices(&self) -> usize {
+        self.inner.max_voices.load(Ordering::Relaxed)
+    }
+
     pub fn downgrade(&self) -> WeakAudioMixer {
         WeakAudioMixer {
             inner: Arc::downgrade(&self.inner),
@@ -129,6 +291,18 @@ impl AudioMixer {
 
     /// Play a source on the mixer, returning a handle which can be used to control it
     pub fn play<S: Source + 'static>(&self, source: S) -> Sound {
+        self.play_with_priority(source, VoicePriority::default)
+    }
+
+    /// Play a source on the mixer, competing with the other sounds for a voice with the priority
+    /// `priority` returns.
+    ///
+    /// `priority` is called on the audio thread, every time the sounds are ranked for the voices
+    pub fn play_with_priority<S: Source + 'static>(
+        &self,
+        source: S,
+        priority: impl Fn() -> VoicePriority + Send + Sync + 'static,
+    ) -> Sound {
         let sample_rate = source.sample_rate();
 
         let source = if sample_rate == self.inner.sample_rate {
@@ -137,11 +311,17 @@ impl AudioMixer {
             Box::new(SampleConversion::new(source, self.inner.sample_rate as _)) as Box<dyn Source>
         };
 
-        let id = self
-            .inner
-            .sources
-            .lock()
-            .insert(PlayingSound { cursor: 0, source });
+        let mut voices = self.inner.voices.lock();
+        let id = voices.sounds.insert(PlayingSound {
+            cursor: 0,
+            source,
+            priority: Box::new(priority),
+            audible: false,
+            gain: 0.0,
+        });
+        // Ranks the sound before it's first pulled, so that it starts without a delay if it has a
+        // voice
+        voices.until_update = 0;
         Sound {
             id,
             mixer: self.clone(),
@@ -168,20 +348,27 @@ impl AudioMixer {
 
 impl Source for AudioMixer {
     fn next_sample(&mut self) -> Option<crate::Frame> {
-        let mut sources = self.inner.sources.lock();
+        let mut voices = self.inner.voices.lock();
+        if voices.until_update == 0 {
+            voices.update(self.max_voices());
+            voices.until_update = VOICE_UPDATE_INTERVAL;
+        }
+        voices.until_update -= 1;
+
+        let fade_step = self.inner.fade_step;
         let mut res = Frame::ZERO;
-        sources.retain(|id, source| {
-            let sample = match source.source.next_sample() {
-                Some(v) => v,
+        voices
+            .sounds
+            .retain(|id, sound| match sound.next_sample(fade_step) {
+                Some(sample) => {
+                    res += sample;
+                    true
+                }
                 None => {
-                    self.terminate_source(id, source);
-                    return false;
+                    self.terminate_source(id, sound);
+                    false
                 }
-            };
-            res += sample;
-
-            true
-        });
+            });
 
         Some(res)
     }
@@ -191,13 +378,18 @@ impl Source for AudioMixer {
     }
 
     fn sample_buffered(&mut self, output: &mut [Frame]) -> usize {
-        let mut sources = self.inner.sources.lock();
-        sources.retain(|id, source| {
-            let written = source.source.sample_buffered(output);
-
+        let mut voices = self.inner.voices.lock();
+        voices.update(self.max_voices());
+        voices.until_update = VOICE_UPDATE_INTERVAL;
+
+        let fade_step = self.inner.fade_step;
+        let Voices {
+            sounds, scratch, ..
+        } = &mut *voices;
+        sounds.retain(|id, sound| {
             // No more samples in source
-            if written != output.len() {
-                self.terminate_source(id, source);
+            if !sound.sample_buffered(output, scratch, fade_step) {
+                self.terminate_source(id, sound);
                 return false;
             }
 
@@ -238,14 +430,212 @@ impl AudioMixer {
     /// Get the total number of pending tracks for all sinks
     #[must_use]
     pub fn playing_sinks(&self) -> usize {
-        self.inner.sources.lock().len()
+        self.inner.voices.lock().sounds.len()
         // self.playing_sinks.load(std::sync::atomic::Ordering::Relaxed)
     }
 }
 
+impl AudioMixer {
+    /// Get the number of sounds which are virtual, i.e. waiting for a voice
+    #[must_use]
+    pub fn virtual_sounds(&self) -> usize {
+        self.inner
+            .voices
+            .lock()
+            .sounds
+            .values()
+            .filter(|sound| sound.is_virtual())
+            .count()
+    }
+}
+
 /// Notify all waiters when dropped
 impl Drop for AudioMixerInner {
     fn drop(&mut self) {
         self.waiters.lock().iter_mut().for_each(|(_, v)| v.fire())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicU32;
+
+    use super::*;
+
+    /// Yields the index of each sample
+    struct Counter {
+        next: u64,
+        len: Option<u64>,
+    }
+
+    impl Source for Counter {
+        fn next_sample(&mut self) -> Option<Frame> {
+            if self.len.map(|len| self.next >= len).unwrap_or(false) {
+                return None;
+            }
+            self.next += 1;
+            Some(Frame::splat((self.next - 1) as f32))
+        }
+
+        fn sample_rate(&self) -> SampleRate {
+            1000
+        }
+
+        fn sample_count(&self) -> Option<u64> {
+            self.len.map(|len| len - self.next)
+        }
+    }
+
+    struct Silence;
+
+    impl Source for Silence {
+        fn next_sample(&mut self) -> Option<Frame> {
+            Some(Frame::ZERO)
+        }
+
+        fn sample_rate(&self) -> SampleRate {
+            1000
+        }
+
+        fn sample_count(&self) -> Option<u64> {
+            None
+        }
+    }
+
+    fn counter(len: Option<u64>) -> Counter {
+        Counter { next: 0, len }
+    }
+
+    /// An audibility which can be changed while the sound plays
+    fn audibility(value: f32) -> Arc<AtomicU32> {
+        Arc::new(AtomicU32::new(value.to_bits()))
+    }
+
+    fn set(audibility: &AtomicU32, value: f32) {
+        audibility.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    fn priority(
+        priority: i32,
+        audibility: &Arc<AtomicU32>,
+    ) -> impl Fn() -> VoicePriority + Send + Sync + 'static {
+        let audibility = audibility.clone();
+        move || VoicePriority {
+            priority,
+            audibility: f32::from_bits(audibility.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn sound(priority: impl Fn() -> VoicePriority + Send + Sync + 'static) -> PlayingSound {
+        PlayingSound {
+            cursor: 0,
+            source: Box::new(counter(None)),
+            priority: Box::new(priority),
+            audible: false,
+            gain: 0.0,
+        }
+    }
+
+    fn audible(voices: &Voices, ids: &[SoundId]) -> Vec<bool> {
+        ids.iter().map(|&id| voices.sounds[id].audible).collect()
+    }
+
+    #[test]
+    fn voices_go_to_the_highest_priorities_then_the_loudest() {
+        let mut voices = Voices::default();
+        let ids = [
+            voices.sounds.insert(sound(priority(0, &audibility(1.0)))),
+            voices.sounds.insert(sound(priority(1, &audibility(0.1)))),
+            voices.sounds.insert(sound(priority(0, &audibility(0.5)))),
+            voices.sounds.insert(sound(priority(2, &audibility(0.0)))),
+        ];
+
+        voices.update(2);
+        assert_eq!(audible(&voices, &ids), [true, true, false, false]);
+        // The inaudible sounds don't get a voice, even if there are some left
+        voices.update(8);
+        assert_eq!(audible(&voices, &ids), [true, true, true, false]);
+        voices.update(0);
+        assert_eq!(audible(&voices, &ids), [false, false, false, false]);
+    }
+
+    #[test]
+    fn a_voice_goes_to_a_sound_clearly_louder() {
+        let (first, second) = (audibility(1.0), audibility(0.9));
+        let mut voices = Voices::default();
+        let ids = [
+            voices.sounds.insert(sound(priority(0, &first))),
+            voices.sounds.insert(sound(priority(0, &second))),
+        ];
+        voices.update(1);
+        assert_eq!(audible(&voices, &ids), [true, false]);
+
+        set(&second, 1.2);
+        voices.update(1);
+        assert_eq!(audible(&voices, &ids), [true, false]);
+        set(&second, 1.3);
+        voices.update(1);
+        assert_eq!(audible(&voices, &ids), [false, true]);
+        // The sound which has the voice is favored in turn
+        set(&first, 1.6);
+        voices.update(1);
+        assert_eq!(audible(&voices, &ids), [false, true]);
+    }
+
+    #[test]
+    fn virtual_sounds_keep_time() {
+        let mut mixer = AudioMixer::new(1000);
+        mixer.set_max_voices(1);
+        let (quiet, loud) = (audibility(0.5), audibility(1.0));
+        mixer.play_with_priority(counter(None), priority(0, &quiet));
+        mixer.play_with_priority(Silence, priority(0, &loud));
+
+        let mut output = vec![Frame::ZERO; 100];
+        mixer.sample_buffered(&mut output);
+        assert!(output.iter().all(|&sample| sample == Frame::ZERO));
+        assert_eq!(mixer.virtual_sounds(), 1);
+
+        // The counter gets the voice, and fades in over 20 samples from where it's at
+        set(&quiet, 2.0);
+        let mut output = vec![Frame::ZERO; 100];
+        mixer.sample_buffered(&mut output);
+        assert!(output[0].x > 0.0 && output[0].x < 100.0, "{:?}", output[0]);
+        assert_eq!(output[50], Frame::splat(150.0));
+        assert_eq!(mixer.virtual_sounds(), 1);
+
+        // The same, a sample at a time
+        set(&quiet, 0.5);
+        let mut output = vec![Frame::ZERO; 100];
+        mixer.sample_buffered(&mut output);
+        assert_eq!(output[50], Frame::ZERO);
+        set(&quiet, 2.0);
+        let output = (0..VOICE_UPDATE_INTERVAL + 50)
+            .map(|_| mixer.next_sample().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            output[VOICE_UPDATE_INTERVAL + 49],
+            Frame::splat((300 + VOICE_UPDATE_INTERVAL + 49) as f32)
+        );
+    }
+
+    #[test]
+    fn sounds_which_end_while_virtual_end_unheard() {
+        let mut mixer = AudioMixer::new(1000);
+        let inaudible = audibility(0.0);
+        mixer.play_with_priority(counter(Some(50)), priority(0, &inaudible));
+        let mut output = vec![Frame::ZERO; 30];
+        mixer.sample_buffered(&mut output);
+        assert_eq!((mixer.playing_sinks(), mixer.virtual_sounds()), (1, 1));
+        mixer.sample_buffered(&mut output);
+        assert_eq!(mixer.playing_sinks(), 0);
+        assert!(output.iter().all(|&sample| sample == Frame::ZERO));
+
+        mixer.play_with_priority(counter(Some(50)), priority(0, &inaudible));
+        for _ in 0..50 {
+            assert_eq!(mixer.next_sample(), Some(Frame::ZERO));
+        }
+        assert_eq!(mixer.playing_sinks(), 1);
+        mixer.next_sample();
+        assert_eq!(mixer.playing_sinks(), 0);
+    }
+}
@@ -3,6 +3,8 @@ use std::f32::consts::E;
 use glam::{Mat4, Vec3};
 use serde::{Deserialize, Serialize};
 
+use crate::VoicePriority;
+
 /// The speed of sound in units/s
 pub const SPEED_OF_SOUND: f32 = 343.0;
 /// The "maximum" speed of the source relative to the listener, in
@@ -23,6 +25,9 @@ pub struct AudioEmitter {
     pub amplitude: f32,
     pub pos: Vec3,
     pub attenuation: Attenuation,
+    /// The sounds of emitters of a higher priority take the voices of the mixer from the ones of a
+    /// lower priority
+    pub priority: i32,
 }
 
 impl Default for AudioEmitter {
@@ -31,6 +36,23 @@ impl Default for AudioEmitter {
             amplitude: 1.0,
             pos: Default::default(),
             attenuation: Default::default(),
+            priority: 0,
+        }
+    }
+}
+
+impl AudioEmitter {
+    /// What the sounds of the emitter compete for the voices of the mixer with, as heard by the
+    /// listener
+    pub fn voice_priority(&self, listener: &AudioListener) -> VoicePriority {
+        let dist = listener
+            .transform
+            .inverse()
+            .transform_point3(self.pos)
+            .length();
+        VoicePriority {
+            priority: self.priority,
+            audibility: self.amplitude * self.attenuation.attenuate(dist),
         }
     }
 }
@@ -0,0 +1,79 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ambient_std::{
+    asset_cache::AssetCache,
+    asset_manifest::{AssetManifest, AssetManifestEntry},
+    asset_url::{AbsAssetUrl, AssetUrl},
+    sha256_digest_bytes,
+};
+use itertools::Itertools;
+use relative_path::{RelativePath, RelativePathBuf};
+
+use crate::pipelines::out_asset::{OutAsset, OutAssetContent};
+
+/// Creates a manifest of the built assets, keyed by stable ids derived from their source paths.
+///
+/// An asset is identified by its source path without extension (`characters/knight.glb` -> `characters/knight`).
+/// When a source produces several assets (e.g. a model and its animations), the first one gets the plain id and
+/// the others are suffixed with the name of their output file (`characters/knight/walk`). Which asset gets the plain id
+/// is decided by the order of the asset types in [AssetType](ambient_std::asset_url::AssetType).
+/// Collections are not part of the manifest.
+pub async fn create_asset_manifest(
+    assets: &AssetCache,
+    in_root: &AbsAssetUrl,
+    out_root: &AbsAssetUrl,
+    build_root: &AbsAssetUrl,
+    out_assets: &[OutAsset],
+) -> anyhow::Result<AssetManifest> {
+    let mut groups: BTreeMap<RelativePathBuf, Vec<(&OutAsset, &AbsAssetUrl)>> = BTreeMap::new();
+    for asset in out_assets {
+        let url = match &asset.content {
+            OutAssetContent::Content(url) => url,
+            OutAssetContent::Collection(_) => continue,
+        };
+        let base = match &asset.source {
+            Some(source) => in_root.relative_path(source.path()),
+            None => out_root.relative_path(url.path()),
+        };
+        groups.entry(without_extension(&base)).or_default().push((asset, url));
+    }
+
+    let mut manifest = AssetManifest::default();
+    for (base, mut group) in groups {
+        group.sort_by_key(|&(asset, url)| (asset.hidden, asset.type_ as u32, url.0.as_str()));
+        let mut counts = HashMap::new();
+        for (i, (asset, url)) in group.into_iter().enumerate() {
+            let id = if i == 0 {
+                base.to_string()
+            } else {
+                let stem = url.path().file_stem().unwrap_or_default().to_string();
+                let id = base.join(stem).to_string();
+                let count = counts.entry(id.clone()).and_modify(|n| *n += 1).or_insert(0);
+                if *count == 0 {
+                    id
+                } else {
+                    format!("{id}-{count}")
+                }
+            };
+            let content = url.download_bytes(assets).await?;
+            manifest.assets.insert(
+                id,
+                AssetManifestEntry {
+                    url: AssetUrl::Relative(build_root.relative_path(url.path())),
+                    type_: asset.type_,
+                    hash: sha256_digest_bytes(&content),
+                    name: asset.name.clone(),
+                    tags: asset.tags.iter().unique().cloned().collect(),
+                },
+            );
+        }
+    }
+    Ok(manifest)
+}
+
+fn without_extension(path: &RelativePath) -> RelativePathBuf {
+    match path.extension() {
+        Some(ext) => RelativePathBuf::from(&path.as_str()[..path.as_str().len() - ext.len() - 1]),
+        None => path.to_relative_path_buf(),
+    }
+}
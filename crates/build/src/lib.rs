@@ -6,12 +6,21 @@ use std::{
 use ambient_asset_cache::{AssetCache, SyncAssetKeyExt};
 use ambient_physics::physx::{Physics, PhysicsKey};
 use ambient_project::Manifest as ProjectManifest;
-use ambient_std::asset_url::AbsAssetUrl;
+use ambient_std::{
+    asset_manifest::AssetManifest,
+    asset_url::AbsAssetUrl,
+    download_cache::DownloadCacheDir,
+    signature::{self, SigningKey, SIGNATURE_EXTENSION},
+};
 use futures::FutureExt;
 use itertools::Itertools;
-use pipelines::{FileCollection, ProcessCtx, ProcessCtxKey};
+use output_index::OutputIndex;
+use parking_lot::Mutex;
+use pipelines::{out_asset::OutAsset, FatalPipelineError, FileCollection, PipelineFilter, ProcessCtx, ProcessCtxKey};
 use walkdir::WalkDir;
 
+pub mod asset_manifest;
+pub mod output_index;
 pub mod pipelines;
 
 /// This takes the path to an Ambient project and builds it. An Ambient project is expected to
@@ -19,9 +28,19 @@ pub mod pipelines;
 ///
 /// assets/**  Here assets such as .glb files are stored. Any files found in this directory will be processed
 /// src/**  This is where you store Rust source files
-/// build  This is the output directory, and is created when building
+/// build  This is the output directory, and is created when building. It also contains the asset manifest
 /// ambient.toml  This is a metadata file to describe the project
-pub async fn build(physics: Physics, _assets: &AssetCache, path: PathBuf, manifest: &ProjectManifest) {
+///
+/// If `filter` is not empty, only the matching part of the assets is rebuilt, and the scripts are skipped.
+/// If there is a `signing_key`, each output is signed, and its signature is written next to it (see [ambient_std::signature]).
+pub async fn build(
+    physics: Physics,
+    assets: &AssetCache,
+    path: PathBuf,
+    manifest: &ProjectManifest,
+    filter: PipelineFilter,
+    signing_key: Option<Arc<SigningKey>>,
+) {
     log::info!(
         "Building project `{}` ({})",
         manifest.project.id,
@@ -34,33 +53,65 @@ pub async fn build(physics: Physics, _assets: &AssetCache, path: PathBuf, manife
     let assets_path = path.join("assets");
 
     std::fs::create_dir_all(&build_path).unwrap();
-    build_assets(physics, &assets_path, &build_path).await;
-    build_scripts(&path, manifest, &build_path).await.unwrap();
+    let partial = !filter.is_empty();
+    build_assets(physics, assets, &assets_path, &build_path, filter, signing_key.clone()).await.unwrap();
+    if partial {
+        log::info!("Skipping scripts for partial build");
+    } else {
+        build_scripts(&path, manifest, &build_path, signing_key.as_deref()).await.unwrap();
+    }
 }
 
-async fn build_assets(physics: Physics, assets_path: &Path, build_path: &Path) {
+async fn build_assets(
+    physics: Physics,
+    parent_assets: &AssetCache,
+    assets_path: &Path,
+    build_path: &Path,
+    filter: PipelineFilter,
+    signing_key: Option<Arc<SigningKey>>,
+) -> anyhow::Result<()> {
     let files = WalkDir::new(assets_path)
         .into_iter()
+        // Skip the build artifacts of script crates
+        .filter_entry(|e| !(e.file_type().is_dir() && e.file_name() == "target" && e.path().with_file_name("Cargo.toml").exists()))
         .filter_map(|e| e.ok())
         .filter(|e| e.metadata().map(|x| x.is_file()).unwrap_or(false))
         .map(|x| AbsAssetUrl::from_file_path(x.into_path()))
         .collect_vec();
     let assets = AssetCache::new_with_config(tokio::runtime::Handle::current(), None);
     PhysicsKey.insert(&assets, physics);
+    if let Some(download_cache) = DownloadCacheDir.try_get(parent_assets) {
+        DownloadCacheDir.insert(&assets, download_cache);
+    }
+    let written_files = Arc::new(Mutex::new(Vec::new()));
+    let fatal_errors = Arc::new(Mutex::new(0));
     let ctx = ProcessCtx {
         assets: assets.clone(),
         files: FileCollection(Arc::new(files)),
         in_root: AbsAssetUrl::from_directory_path(assets_path),
         out_root: AbsAssetUrl::from_directory_path(build_path.join("assets")),
         input_file_filter: None,
+        filter,
         package_name: "".to_string(),
         write_file: Arc::new({
             let build_path = build_path.to_owned();
+            let written_files = written_files.clone();
             move |path, contents| {
+                let signature = signing_key.as_ref().map(|key| key.sign(&contents));
+                {
+                    let mut written_files = written_files.lock();
+                    if signature.is_some() {
+                        written_files.push(format!("{path}.{SIGNATURE_EXTENSION}"));
+                    }
+                    written_files.push(path.clone());
+                }
                 let path = build_path.join("assets").join(path);
                 async move {
                     std::fs::create_dir_all(path.parent().unwrap()).unwrap();
                     tokio::fs::write(&path, contents).await.unwrap();
+                    if let Some(signature) = signature {
+                        tokio::fs::write(signature::signature_path(&path), serde_json::to_vec_pretty(&signature).unwrap()).await.unwrap();
+                    }
                     AbsAssetUrl::from_file_path(path)
                 }
                 .boxed()
@@ -70,16 +121,68 @@ async fn build_assets(physics: Physics, assets_path: &Path, build_path: &Path) {
             log::info!("{}", msg);
             async {}.boxed()
         }),
-        on_error: Arc::new(|err| {
-            log::error!("{:?}", err);
-            async {}.boxed()
+        on_error: Arc::new({
+            let fatal_errors = fatal_errors.clone();
+            move |err| {
+                if err.downcast_ref::<FatalPipelineError>().is_some() {
+                    *fatal_errors.lock() += 1;
+                }
+                log::error!("{:?}", err);
+                async {}.boxed()
+            }
         }),
     };
     ProcessCtxKey.insert(&ctx.assets, ctx.clone());
-    pipelines::process_pipelines(&ctx).await;
+    let out_assets = pipelines::process_pipelines(&ctx).await;
+    let fatal_errors = *fatal_errors.lock();
+    anyhow::ensure!(fatal_errors == 0, "The build failed with {fatal_errors} errors");
+    if let Err(err) = write_asset_manifest(&ctx, build_path, &out_assets).await {
+        log::error!("Failed to write asset manifest: {:?}", err);
+    }
+    let written_files = std::mem::take(&mut *written_files.lock());
+    if let Err(err) = update_output_index(build_path, written_files, !ctx.filter.is_empty()) {
+        log::error!("Failed to clean up stale outputs: {:?}", err);
+    }
+    Ok(())
+}
+
+/// Records the files written by this build, and deletes the ones from previous builds which weren't written again.
+/// A partial build only adds to the index, as it doesn't know which of the other outputs are still current.
+fn update_output_index(build_path: &Path, written_files: Vec<String>, partial: bool) -> anyhow::Result<()> {
+    let previous = OutputIndex::load(build_path)?;
+    let mut current = OutputIndex::new(written_files);
+    if partial {
+        current.files.extend(previous.files);
+    } else {
+        let deleted = output_index::collect_garbage(&build_path.join("assets"), &previous, &current)?;
+        if deleted > 0 {
+            log::info!("Deleted {} stale build outputs", deleted);
+        }
+    }
+    current.save(build_path)
 }
 
-async fn build_scripts(path: &Path, manifest: &ProjectManifest, build_path: &Path) -> anyhow::Result<()> {
+async fn write_asset_manifest(ctx: &ProcessCtx, build_path: &Path, out_assets: &[OutAsset]) -> anyhow::Result<()> {
+    let manifest_path = build_path.join(AssetManifest::FILE_NAME);
+    let mut manifest = asset_manifest::create_asset_manifest(
+        &ctx.assets,
+        &ctx.in_root,
+        &ctx.out_root,
+        &AbsAssetUrl::from_directory_path(build_path),
+        out_assets,
+    )
+    .await?;
+    // A partial build only knows about some of the assets, so keep the others from the previous build
+    if !ctx.filter.is_empty() && manifest_path.exists() {
+        let mut previous: AssetManifest = serde_json::from_slice(&tokio::fs::read(&manifest_path).await?)?;
+        previous.assets.append(&mut manifest.assets);
+        manifest = previous;
+    }
+    tokio::fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?).await?;
+    Ok(())
+}
+
+async fn build_scripts(path: &Path, manifest: &ProjectManifest, build_path: &Path, signing_key: Option<&SigningKey>) -> anyhow::Result<()> {
     let cargo_toml_path = path.join("Cargo.toml");
     if !cargo_toml_path.exists() {
         return Ok(());
@@ -101,7 +204,11 @@ async fn build_scripts(path: &Path, manifest: &ProjectManifest, build_path: &Pat
     let rustc = ambient_rustc::Rust::get_system_installation().await?;
     let bytecode = rustc.build(path, manifest.project.id.as_ref())?;
 
-    tokio::fs::write(build_path.join(format!("{}.wasm", manifest.project.id)), bytecode).await?;
+    let wasm_path = build_path.join(format!("{}.wasm", manifest.project.id));
+    if let Some(signing_key) = signing_key {
+        tokio::fs::write(signature::signature_path(&wasm_path), serde_json::to_vec_pretty(&signing_key.sign(&bytecode))?).await?;
+    }
+    tokio::fs::write(wasm_path, bytecode).await?;
 
     Ok(())
 }
@@ -0,0 +1,107 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use relative_path::RelativePath;
+use serde::{Deserialize, Serialize};
+
+/// Keeps track of the files written by the pipelines, so that the outputs of assets
+/// which are no longer built can be removed instead of accumulating forever.
+///
+/// Paths are relative to the output directory of the pipelines (`build/assets`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutputIndex {
+    pub files: BTreeSet<String>,
+}
+impl OutputIndex {
+    /// The file name of the index, relative to the build directory
+    pub const FILE_NAME: &'static str = "output_index.json";
+
+    pub fn new(files: impl IntoIterator<Item = String>) -> Self {
+        Self { files: files.into_iter().collect() }
+    }
+    /// Loads the index of the previous build; this is empty if there was none
+    pub fn load(build_path: &Path) -> anyhow::Result<Self> {
+        let path = build_path.join(Self::FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        serde_json::from_slice(&std::fs::read(&path)?).with_context(|| format!("Failed to parse output index {path:?}"))
+    }
+    pub fn save(&self, build_path: &Path) -> anyhow::Result<()> {
+        std::fs::write(build_path.join(Self::FILE_NAME), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+    /// The files which are in this index, but not in `current`
+    pub fn orphans<'a>(&'a self, current: &'a OutputIndex) -> impl Iterator<Item = &'a String> + 'a {
+        self.files.difference(&current.files)
+    }
+}
+
+/// Deletes the files of `previous` which were not written again in `current`, as well as the directories
+/// left empty by them. Returns the number of deleted files.
+pub fn collect_garbage(out_path: &Path, previous: &OutputIndex, current: &OutputIndex) -> anyhow::Result<usize> {
+    let mut deleted = 0;
+    for file in previous.orphans(current) {
+        let path = match resolve_output_path(out_path, file) {
+            Some(path) => path,
+            None => {
+                log::warn!("Ignoring invalid path in output index: {}", file);
+                continue;
+            }
+        };
+        if !path.exists() {
+            continue;
+        }
+        std::fs::remove_file(&path).with_context(|| format!("Failed to delete stale output {path:?}"))?;
+        deleted += 1;
+
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if d == out_path || std::fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+    Ok(deleted)
+}
+
+/// Makes sure an index entry can't point outside of the output directory
+fn resolve_output_path(out_path: &Path, file: &str) -> Option<PathBuf> {
+    let path = RelativePath::new(file).normalize();
+    if path.as_str().is_empty() || path.as_str().starts_with("..") {
+        return None;
+    }
+    Some(path.to_path(out_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_index_gc() {
+        let out_path = std::env::temp_dir().join(format!("ambient_output_index_{}", std::process::id()));
+        for file in ["a.glb/prefabs/main.json", "a.glb/models/main.json", "b.ogg/graph.json"] {
+            let path = out_path.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let previous = OutputIndex::new(
+            ["a.glb/prefabs/main.json", "a.glb/models/main.json", "b.ogg/graph.json", "../outside.json"].map(String::from),
+        );
+        let current = OutputIndex::new(["a.glb/prefabs/main.json".to_string()]);
+        assert_eq!(previous.orphans(&current).count(), 3);
+
+        assert_eq!(collect_garbage(&out_path, &previous, &current).unwrap(), 2);
+        assert!(out_path.join("a.glb/prefabs/main.json").exists());
+        assert!(!out_path.join("a.glb/models").exists());
+        assert!(!out_path.join("b.ogg").exists());
+        assert!(out_path.exists());
+
+        std::fs::remove_dir_all(&out_path).unwrap();
+    }
+}
@@ -74,7 +74,7 @@ fn save_audio_graph(root: AudioNode) -> anyhow::Result<Vec<u8>> {
 }
 
 #[tracing::instrument(level = "info", skip(input))]
-async fn ffmpeg_convert<A>(input: A) -> anyhow::Result<Vec<u8>>
+pub(crate) async fn ffmpeg_convert<A>(input: A) -> anyhow::Result<Vec<u8>>
 where
     A: 'static + Send + AsyncRead,
 {
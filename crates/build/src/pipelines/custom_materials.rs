@@ -0,0 +1,74 @@
+use ambient_renderer::materials::custom_material::{validate_custom_material, CustomMaterialFromUrl};
+use ambient_std::asset_url::{AbsAssetUrl, AssetType, AssetUrl};
+use anyhow::Context;
+use itertools::Itertools;
+use tracing::{info_span, Instrument};
+
+use super::{
+    context::PipelineCtx,
+    out_asset::{asset_id_from_url, OutAsset, OutAssetContent, OutAssetPreview},
+    FatalPipelineError,
+};
+
+pub const CUSTOM_MATERIAL_EXTENSION: &str = "custom_material.json";
+
+/// Copies a file the material refers to into the build
+async fn pipe_file(ctx: &PipelineCtx, file: &AbsAssetUrl, url: &AssetUrl) -> anyhow::Result<AssetUrl> {
+    let source = url.resolve(file).with_context(|| format!("Invalid path {url}"))?;
+    let source = ctx.get_downloadable_url(&source)?.clone();
+    let contents = source.download_bytes(ctx.assets()).await?;
+    let rel_path = ctx.in_root().relative_path(source.path());
+    Ok(ctx.write_file(&rel_path, contents).await.into())
+}
+
+async fn read_wgsl(ctx: &PipelineCtx, file: &AbsAssetUrl, url: &AssetUrl) -> anyhow::Result<String> {
+    let source = url.resolve(file).with_context(|| format!("Invalid path {url}"))?;
+    let contents = ctx.get_downloadable_url(&source)?.download_bytes(ctx.assets()).await?;
+    String::from_utf8(contents).with_context(|| format!("{url} is not UTF-8"))
+}
+
+pub async fn pipeline(ctx: &PipelineCtx) -> Vec<OutAsset> {
+    ctx.process_files(
+        |file| file.path().as_str().ends_with(&format!(".{CUSTOM_MATERIAL_EXTENSION}")),
+        |ctx, file| async move {
+            let contents = file.download_bytes(ctx.assets()).await?;
+            let mut desc: CustomMaterialFromUrl = serde_json::from_slice(&contents).context("Invalid custom material")?;
+
+            let fragment = read_wgsl(&ctx, &file, &desc.fragment).await?;
+            let vertex = match &desc.vertex {
+                Some(vertex) => Some(read_wgsl(&ctx, &file, vertex).await?),
+                None => None,
+            };
+            let params = desc.params.iter().map(|(name, param)| (name.clone(), *param)).collect_vec();
+            let textures = desc.textures.keys().map(|name| name.as_str()).collect_vec();
+            validate_custom_material(ctx.assets(), &fragment, vertex.as_deref(), &params, &textures)
+                .context("Invalid custom material")
+                .context(FatalPipelineError)?;
+
+            desc.fragment = pipe_file(&ctx, &file, &desc.fragment).await?;
+            if let Some(vertex) = &desc.vertex {
+                desc.vertex = Some(pipe_file(&ctx, &file, vertex).await?);
+            }
+            for texture in desc.textures.values_mut() {
+                texture.url = pipe_file(&ctx, &file, &texture.url).await?;
+            }
+
+            let rel_path = ctx.in_root().relative_path(file.path());
+            let desc_url = ctx.write_file(&rel_path, serde_json::to_vec_pretty(&desc)?).await;
+
+            Ok(vec![OutAsset {
+                id: asset_id_from_url(&file),
+                type_: AssetType::Material,
+                hidden: false,
+                name: desc.name.clone().unwrap_or_else(|| file.path().file_name().unwrap().to_string()),
+                tags: Vec::new(),
+                categories: Default::default(),
+                preview: OutAssetPreview::None,
+                content: OutAssetContent::Content(desc_url),
+                source: Some(file.clone()),
+            }])
+        },
+    )
+    .instrument(info_span!("custom_materials_pipeline"))
+    .await
+}
@@ -0,0 +1,70 @@
+use std::io::Cursor;
+
+use ambient_std::asset_url::AbsAssetUrl;
+use anyhow::Context;
+
+use super::super::context::PipelineCtx;
+
+/// The gamma of a linear PNG, as stored in its gAMA chunk
+const PNG_LINEAR_GAMMA: u32 = 100_000;
+
+/// Fails if the emissive map at `source` isn't linear. Emissive maps hold intensities rather than reflectances, so the
+/// renderer samples them as they are; an sRGB encoded map would glow much brighter in its mid tones than intended.
+pub async fn ensure_linear_emissive_map(ctx: &PipelineCtx, source: &AbsAssetUrl) -> anyhow::Result<()> {
+    let source = ctx.get_downloadable_url(source)?.clone();
+    let data = source.download_bytes(ctx.assets()).await?;
+    let extension = source.extension().unwrap_or_default();
+    tokio::task::block_in_place(|| ensure_linear_image(&data, &extension)).with_context(|| {
+        format!("The emissive map {source} must be linear; export it with a gamma of 1, e.g. as an OpenEXR image or a PNG without an sRGB profile")
+    })
+}
+
+/// JPEG images are always sRGB encoded, and PNG images are when they say so with an sRGB chunk, an ICC profile or a
+/// gamma other than 1. The other images are taken to be linear, as nothing says otherwise.
+fn ensure_linear_image(data: &[u8], extension: &str) -> anyhow::Result<()> {
+    match extension.to_lowercase().as_str() {
+        "jpg" | "jpeg" => anyhow::bail!("JPEG images are sRGB encoded"),
+        "png" => {
+            let reader = png::Decoder::new(Cursor::new(data)).read_info()?;
+            let info = reader.info();
+            anyhow::ensure!(info.srgb.is_none(), "The image has an sRGB chunk");
+            anyhow::ensure!(info.icc_profile.is_none(), "The image has an ICC profile");
+            if let Some(gamma) = info.source_gamma {
+                let gamma = gamma.into_scaled();
+                anyhow::ensure!(
+                    gamma.abs_diff(PNG_LINEAR_GAMMA) < 1000,
+                    "The image has a gamma of {}",
+                    gamma as f32 / PNG_LINEAR_GAMMA as f32
+                );
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ensure_linear_image;
+
+    fn png(set_chunks: impl FnOnce(&mut png::Encoder<&mut Vec<u8>>)) -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut data, 1, 1);
+            encoder.set_color(png::ColorType::Rgba);
+            set_chunks(&mut encoder);
+            encoder.write_header().unwrap().write_image_data(&[255, 128, 0, 255]).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn linear_images() {
+        assert!(ensure_linear_image(&png(|_| {}), "png").is_ok());
+        assert!(ensure_linear_image(&png(|encoder| encoder.set_source_gamma(png::ScaledFloat::new(1.))), "png").is_ok());
+        assert!(ensure_linear_image(&png(|encoder| encoder.set_source_gamma(png::ScaledFloat::new(1. / 2.2))), "PNG").is_err());
+        assert!(ensure_linear_image(&png(|encoder| encoder.set_srgb(png::SrgbRenderingIntent::Perceptual)), "png").is_err());
+        assert!(ensure_linear_image(&[], "jpg").is_err());
+        assert!(ensure_linear_image(&[], "exr").is_ok());
+    }
+}
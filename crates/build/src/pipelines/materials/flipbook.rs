@@ -0,0 +1,204 @@
+use std::io::Cursor;
+
+use ambient_asset_cache::AsyncAssetKeyExt;
+use ambient_model_import::model_crate::cap_texture_size;
+use ambient_renderer::materials::pbr_material::Flipbook;
+use ambient_std::asset_url::{AbsAssetUrl, AssetUrl};
+use anyhow::Context;
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder},
+    AnimationDecoder, Frames, ImageFormat, ImageOutputFormat, RgbaImage,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{super::context::PipelineCtx, PipeImage};
+
+/// The largest flipbook sheet that is built from an animated image; the frames are scaled down to fit in it
+const MAX_SHEET_SIZE: u32 = 8192;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PipelineFlipbook {
+    /// The number of columns of frames in the base color map.
+    /// Required if the base color map is a sheet of frames; computed if it's an animated GIF or PNG.
+    pub columns: Option<u32>,
+    /// The number of rows of frames in the base color map.
+    /// Required if the base color map is a sheet of frames; computed if it's an animated GIF or PNG.
+    pub rows: Option<u32>,
+    /// The number of frames to play. Defaults to all of them.
+    pub frame_count: Option<u32>,
+    /// The number of frames played per second.
+    /// Defaults to the frame rate of the animated GIF or PNG, or to 10 for sheets of frames.
+    pub fps: Option<f32>,
+    /// Whether or not the animation starts over when it ends. Defaults to true.
+    pub looping: Option<bool>,
+}
+
+/// Turns the base color map into a sheet of frames: the frames of animated GIFs and PNGs are packed into a new image,
+/// and other images are expected to already be a sheet
+pub(super) async fn pipe_flipbook(
+    ctx: &PipelineCtx,
+    source: AbsAssetUrl,
+    config: &PipelineFlipbook,
+) -> anyhow::Result<(AssetUrl, Flipbook)> {
+    let source = ctx.get_downloadable_url(&source)?.clone();
+    let data = source.download_bytes(ctx.assets()).await?;
+    let frames = tokio::task::block_in_place(|| decode_animation(&data)).with_context(|| format!("Failed to decode {source}"))?;
+
+    let (frames, duration) = match frames {
+        Some(frames) => frames,
+        None => {
+            let (columns, rows) = match (config.columns, config.rows) {
+                (Some(columns), Some(rows)) => (columns, rows),
+                _ => anyhow::bail!("{source} isn't animated, so the columns and rows of its flipbook must be specified"),
+            };
+            let url = PipeImage::resolve(ctx, source).get(ctx.assets()).await?;
+            let flipbook = Flipbook {
+                columns,
+                rows,
+                frame_count: config.frame_count.unwrap_or(columns * rows),
+                fps: config.fps.unwrap_or(10.),
+                looping: config.looping.unwrap_or(true),
+            };
+            return Ok(((*url).clone().into(), flipbook));
+        }
+    };
+
+    let flipbook = animated_flipbook(config, frames.len() as u32, duration);
+    let mut data = Cursor::new(Vec::new());
+    tokio::task::block_in_place(|| {
+        let sheet = pack_sheet(&frames, flipbook.columns, flipbook.rows);
+        sheet.write_to(&mut data, ImageOutputFormat::Png)
+    })?;
+    let path = ctx.in_root().relative_path(source.path()).with_extension("flipbook.png");
+    let url = ctx.write_file(path, data.into_inner()).await;
+    Ok((url.into(), flipbook))
+}
+
+/// The flipbook of the sheet packed from the `frame_count` frames of an animated image, which plays for `duration` seconds
+fn animated_flipbook(config: &PipelineFlipbook, frame_count: u32, duration: f32) -> Flipbook {
+    let columns = config.columns.unwrap_or_else(|| (frame_count as f32).sqrt().ceil() as u32).max(1);
+    Flipbook {
+        columns,
+        rows: frame_count.div_ceil(columns),
+        frame_count: config.frame_count.unwrap_or(frame_count).min(frame_count),
+        fps: config.fps.unwrap_or(if duration > 0. { frame_count as f32 / duration } else { 10. }),
+        looping: config.looping.unwrap_or(true),
+    }
+}
+
+/// Returns the frames of an animated GIF or PNG and its duration in seconds, or None if the image isn't animated
+fn decode_animation(data: &[u8]) -> anyhow::Result<Option<(Vec<RgbaImage>, f32)>> {
+    let frames = match image::guess_format(data)? {
+        ImageFormat::Gif => GifDecoder::new(Cursor::new(data))?.into_frames(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(Cursor::new(data))?;
+            if !decoder.is_apng() {
+                return Ok(None);
+            }
+            decoder.apng().into_frames()
+        }
+        _ => return Ok(None),
+    };
+    collect_frames(frames)
+}
+
+fn collect_frames(frames: Frames<'_>) -> anyhow::Result<Option<(Vec<RgbaImage>, f32)>> {
+    let frames = frames.collect_frames()?;
+    if frames.len() < 2 {
+        return Ok(None);
+    }
+    let duration = frames
+        .iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            numer as f32 / denom.max(1) as f32 / 1000.
+        })
+        .sum();
+    Ok(Some((frames.into_iter().map(|frame| frame.into_buffer()).collect(), duration)))
+}
+
+fn pack_sheet(frames: &[RgbaImage], columns: u32, rows: u32) -> RgbaImage {
+    let (width, height) = frames[0].dimensions();
+    let mut sheet = RgbaImage::new(width * columns, height * rows);
+    for (index, frame) in frames.iter().enumerate() {
+        let index = index as u32;
+        image::imageops::replace(&mut sheet, frame, ((index % columns) * width) as i64, ((index / columns) * height) as i64);
+    }
+    cap_texture_size(&mut sheet, MAX_SHEET_SIZE);
+    sheet
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{codecs::gif::GifEncoder, Delay, Frame, ImageOutputFormat, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn frame(index: u8) -> RgbaImage {
+        RgbaImage::from_pixel(2, 1, Rgba([index * 50, 0, 0, 255]))
+    }
+
+    fn gif(frame_count: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        GifEncoder::new(&mut data)
+            .encode_frames((0..frame_count).map(|index| Frame::from_parts(frame(index), 0, 0, Delay::from_numer_denom_ms(100, 1))))
+            .unwrap();
+        data
+    }
+
+    fn apng(frame_count: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut data, 2, 1);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_animated(frame_count as u32, 0).unwrap();
+            encoder.set_frame_delay(1, 20).unwrap();
+            let mut writer = encoder.write_header().unwrap();
+            for index in 0..frame_count {
+                writer.write_image_data(frame(index).as_raw()).unwrap();
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn animations_are_decoded() {
+        let (frames, duration) = decode_animation(&gif(3)).unwrap().unwrap();
+        assert_eq!(frames, (0..3).map(frame).collect::<Vec<_>>());
+        assert!((duration - 0.3).abs() < 1e-5);
+
+        let (frames, duration) = decode_animation(&apng(4)).unwrap().unwrap();
+        assert_eq!(frames, (0..4).map(frame).collect::<Vec<_>>());
+        assert!((duration - 0.2).abs() < 1e-5);
+
+        // A single frame isn't an animation, and neither are still images
+        assert!(decode_animation(&gif(1)).unwrap().is_none());
+        let mut still = Cursor::new(Vec::new());
+        frame(0).write_to(&mut still, ImageOutputFormat::Png).unwrap();
+        assert!(decode_animation(still.get_ref()).unwrap().is_none());
+        assert!(decode_animation(b"not an image").is_err());
+    }
+
+    #[test]
+    fn frames_are_packed_left_to_right_then_top_to_bottom() {
+        let frames = (0..5).map(frame).collect::<Vec<_>>();
+        let flipbook = animated_flipbook(&PipelineFlipbook::default(), 5, 0.5);
+        assert_eq!(flipbook, Flipbook { columns: 3, rows: 2, frame_count: 5, fps: 10., looping: true });
+
+        let sheet = pack_sheet(&frames, flipbook.columns, flipbook.rows);
+        assert_eq!(sheet.dimensions(), (6, 2));
+        for (index, frame) in frames.iter().enumerate() {
+            let (x, y) = (index as u32 % 3 * 2, index as u32 / 3);
+            assert_eq!(image::imageops::crop_imm(&sheet, x, y, 2, 1).to_image(), *frame);
+        }
+        assert_eq!(*sheet.get_pixel(4, 1), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn flipbook_settings_override_the_animation() {
+        let config = PipelineFlipbook { columns: Some(2), rows: None, frame_count: Some(10), fps: Some(24.), looping: Some(false) };
+        assert_eq!(animated_flipbook(&config, 5, 0.5), Flipbook { columns: 2, rows: 3, frame_count: 5, fps: 24., looping: false });
+        // Images without delays play at the default rate
+        assert_eq!(animated_flipbook(&PipelineFlipbook::default(), 4, 0.).fps, 10.);
+    }
+}
@@ -8,7 +8,10 @@ use ambient_model_import::{
     ModelTextureSize,
 };
 use ambient_physics::collider::{collider, collider_type};
-use ambient_renderer::materials::pbr_material::PbrMaterialFromUrl;
+use ambient_renderer::materials::{
+    pbr_material::{DetailMaps, PbrMaterialFromUrl},
+    sampler::TextureSampling,
+};
 use ambient_std::{
     asset_url::{AbsAssetUrl, AssetType, AssetUrl},
     download_asset::AssetResult,
@@ -28,6 +31,8 @@ use super::{
 };
 use crate::pipelines::download_image;
 
+pub mod emissive;
+pub mod flipbook;
 pub mod quixel_surfaces;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -126,11 +131,19 @@ pub struct PipelinePbrMaterial {
     pub normalmap: Option<AssetUrl>,
     /// The metallic roughness map of this material.
     pub metallic_roughness: Option<AssetUrl>,
+    /// The emissive map of this material, which the emissive factor is multiplied by.
+    /// It must be linear (i.e. not sRGB encoded), as it holds intensities; the build fails otherwise.
+    pub emissive: Option<AssetUrl>,
 
     /// The color that this material should be multiplied by. Defaults to white for PBR.
     pub base_color_factor: Option<Vec4>,
-    /// The emissive factor of this material (i.e. the color that it emits). Defaults to black for PBR.
+    /// The emissive factor of this material (i.e. the color that it emits). Defaults to black for PBR, or to white if
+    /// there's an emissive map or strength.
     pub emissive_factor: Option<Vec4>,
+    /// The luminance of the emissive color in nits (cd/m²), 203 nits being the white of the display.
+    /// Brighter emissives glow with the bloom of the camera.
+    /// Defaults to the emissive factor being the color as displayed.
+    pub emissive_strength: Option<f32>,
     /// Whether or not this material is transparent. Defaults to false for PBR.
     pub transparent: Option<bool>,
     /// The opacity level (between 0 and 1) at which this material will not be rendered.
@@ -143,6 +156,12 @@ pub struct PipelinePbrMaterial {
     pub metallic: Option<f32>,
     /// The roughness coefficient of this material. Defaults to 1 for PBR.
     pub roughness: Option<f32>,
+    /// Plays the base color map as an animation. The base color map can be an animated GIF or PNG, or a sheet of frames.
+    pub flipbook: Option<flipbook::PipelineFlipbook>,
+    /// How the maps of this material are sampled. Defaults to the texture quality settings of the renderer.
+    pub sampling: Option<TextureSampling>,
+    /// A second base color and normal map, tiled over this material and blended in by the vertex colors or a mask.
+    pub detail: Option<DetailMaps>,
 
     // Non-PBR properties that get translated to PBR.
     /// The non-PBR specular map of this material. If specified, it will be translated to a PBR equivalent.
@@ -165,10 +184,24 @@ impl PipelinePbrMaterial {
             }
             .boxed()
         };
+        if let Some(strength) = self.emissive_strength {
+            anyhow::ensure!(strength.is_finite() && strength >= 0., "The emissive strength must be a non-negative number of nits");
+        }
+        if let Some(emissive) = &self.emissive {
+            emissive::ensure_linear_emissive_map(ctx, &emissive.resolve(source_root)?).await?;
+        }
+        let (base_color, flipbook) = match &self.flipbook {
+            Some(flipbook) => {
+                let base_color = self.base_color.as_ref().context("A flipbook material needs a base color map")?;
+                let (base_color, flipbook) = flipbook::pipe_flipbook(ctx, base_color.resolve(source_root)?, flipbook).await?;
+                (Some(base_color), Some(flipbook))
+            }
+            None => (pipe_image(&self.base_color).await?, None),
+        };
         Ok(PbrMaterialFromUrl {
             name: self.name.clone(),
             source: self.source.clone(),
-            base_color: pipe_image(&self.base_color).await?,
+            base_color,
             opacity: pipe_image(&self.opacity).await?,
             normalmap: pipe_image(&self.normalmap).await?,
             metallic_roughness: if let Some(url) = &self.metallic_roughness {
@@ -194,13 +227,31 @@ impl PipelinePbrMaterial {
                 None
             },
 
+            emissive: pipe_image(&self.emissive).await?,
+            emissive_srgb: false,
+
             base_color_factor: self.base_color_factor,
             emissive_factor: self.emissive_factor,
+            emissive_strength: self.emissive_strength,
             transparent: self.transparent,
             alpha_cutoff: self.alpha_cutoff,
             double_sided: self.double_sided,
             metallic: self.metallic.unwrap_or(1.),
             roughness: self.roughness.unwrap_or(1.),
+            flipbook,
+            transmission: None,
+            clearcoat: None,
+            sheen: None,
+            sampling: self.sampling,
+            detail: match &self.detail {
+                Some(detail) => Some(DetailMaps {
+                    base_color: pipe_image(&detail.base_color).await?,
+                    normalmap: pipe_image(&detail.normalmap).await?,
+                    mask: pipe_image(&detail.mask).await?,
+                    ..detail.clone()
+                }),
+                None => None,
+            },
         }
         .relative_path_from(out_root))
     }
@@ -6,16 +6,22 @@ use anyhow::Context;
 use context::PipelineCtx;
 use futures::{future::BoxFuture, StreamExt};
 use image::ImageFormat;
+use itertools::Itertools;
 use out_asset::{OutAsset, OutAssetContent, OutAssetPreview};
+use relative_path::{RelativePath, RelativePathBuf};
 use serde::{Deserialize, Serialize};
 
-use self::{materials::MaterialsPipeline, models::ModelsPipeline};
+use self::{materials::MaterialsPipeline, models::ModelsPipeline, script_bundles::ScriptBundlesPipeline, video::VideoPipeline};
 
 pub mod audio;
 pub mod context;
+pub mod custom_materials;
 pub mod materials;
 pub mod models;
+pub mod music;
 pub mod out_asset;
+pub mod script_bundles;
+pub mod video;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -29,6 +35,18 @@ pub enum PipelineConfig {
     /// The audio asset pipeline.
     /// Will import supported audio file formats and produce Ogg Vorbis files to be used by the runtime.
     Audio,
+    /// The script bundles asset pipeline.
+    /// Will copy `.script_bundle` files, and compile Rust crates (directories with a `Cargo.toml`) to WASM.
+    ScriptBundles(ScriptBundlesPipeline),
+    /// The video asset pipeline.
+    /// Will import supported video file formats and split them into frames and an Ogg Vorbis audio track, to be played by the runtime.
+    Video(VideoPipeline),
+    /// The music asset pipeline.
+    /// Will import `.music.json` files, along with the stems they refer to as Ogg Vorbis files, to be played by the runtime.
+    Music,
+    /// The custom materials asset pipeline.
+    /// Will import `.custom_material.json` files, along with the WGSL and textures they refer to, to be rendered with by the runtime.
+    CustomMaterials,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,6 +72,10 @@ impl Pipeline {
             PipelineConfig::Models(config) => models::pipeline(&ctx, config.clone()).await,
             PipelineConfig::Materials(config) => materials::pipeline(&ctx, config.clone()).await,
             PipelineConfig::Audio => audio::pipeline(&ctx).await,
+            PipelineConfig::ScriptBundles(config) => script_bundles::pipeline(&ctx, config.clone()).await,
+            PipelineConfig::Video(config) => video::pipeline(&ctx, config.clone()).await,
+            PipelineConfig::Music => music::pipeline(&ctx).await,
+            PipelineConfig::CustomMaterials => custom_materials::pipeline(&ctx).await,
         };
         for asset in &mut assets {
             asset.tags.extend(self.tags.clone());
@@ -67,8 +89,44 @@ impl Pipeline {
     }
 }
 
+/// Restricts a build to a part of the content root, so that a subtree or a set of tagged
+/// pipelines can be rebuilt without walking everything.
+///
+/// An empty filter accepts everything.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineFilter {
+    /// Only process files under these paths (relative to the content root).
+    pub only: Vec<RelativePathBuf>,
+    /// Only process pipelines which have at least one of these tags.
+    pub tags: Vec<String>,
+}
+impl PipelineFilter {
+    pub fn new(only: impl IntoIterator<Item = impl AsRef<str>>, tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            only: only.into_iter().map(|path| RelativePath::new(path.as_ref().trim_start_matches("./")).normalize()).collect(),
+            tags: tags.into_iter().map(|tag| tag.into()).collect(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.tags.is_empty()
+    }
+    /// A pipeline rooted at `root` is relevant if it covers one of the paths, or is inside one of them
+    pub fn matches_pipeline_root(&self, root: &RelativePath) -> bool {
+        self.only.is_empty() || self.only.iter().any(|only| only.starts_with(root) || root.starts_with(only))
+    }
+    pub fn matches_tags(&self, tags: &[String]) -> bool {
+        self.tags.is_empty() || tags.iter().any(|tag| self.tags.contains(tag))
+    }
+    pub fn matches_file(&self, path: &RelativePath) -> bool {
+        self.only.is_empty() || self.only.iter().any(|only| path.starts_with(only))
+    }
+}
+
 pub async fn process_pipelines(ctx: &ProcessCtx) -> Vec<OutAsset> {
     log::info!("Processing pipeline with out_root={}", ctx.out_root);
+    if !ctx.filter.is_empty() {
+        log::info!("Restricting build to {:?}", ctx.filter);
+    }
 
     #[derive(Debug, Clone, Deserialize)]
     #[serde(untagged)]
@@ -87,15 +145,20 @@ pub async fn process_pipelines(ctx: &ProcessCtx) -> Vec<OutAsset> {
 
     futures::stream::iter(ctx.files.0.iter())
         .filter_map(|file| async move {
-            let pipelines: PipelineOneOrMany = if file.0.path().ends_with("pipeline.json") {
-                file.download_json(&ctx.assets).await.unwrap()
-            } else {
+            if !file.0.path().ends_with("pipeline.json") {
                 return None;
-            };
-            Some((file, pipelines.into_vec()))
+            }
+            let root = ctx.in_root.relative_path(file.join(".").unwrap().path());
+            if !ctx.filter.matches_pipeline_root(&root) {
+                return None;
+            }
+            let pipelines: PipelineOneOrMany = file.download_json(&ctx.assets).await.unwrap();
+            // The pipelines keep their index in the file, which their outputs are identified by, when some are left out
+            let pipelines = pipelines.into_vec().into_iter().enumerate().filter(|(_, p)| ctx.filter.matches_tags(&p.tags)).collect_vec();
+            Some((file, pipelines))
         })
         .flat_map(|(file, pipelines)| {
-            futures::stream::iter(pipelines.into_iter().enumerate().map(|(i, pipeline)| {
+            futures::stream::iter(pipelines.into_iter().map(|(i, pipeline)| {
                 let mut file = file.clone();
                 file.0.set_fragment(Some(&i.to_string()));
                 (file, pipeline)
@@ -103,8 +166,14 @@ pub async fn process_pipelines(ctx: &ProcessCtx) -> Vec<OutAsset> {
         })
         .map(|(pipeline_file, pipeline)| {
             let root = pipeline_file.join(".").unwrap();
+            let files = ctx.files.sub_directory(root.path().as_str());
+            let files = if ctx.filter.only.is_empty() {
+                files
+            } else {
+                files.filter(|url| ctx.filter.matches_file(&ctx.in_root.relative_path(url.path())))
+            };
             let ctx = PipelineCtx {
-                files: ctx.files.sub_directory(root.path().as_str()),
+                files,
                 process_ctx: ctx.clone(),
                 pipeline: Arc::new(pipeline.clone()),
                 pipeline_file,
@@ -128,6 +197,9 @@ pub struct ProcessCtx {
     pub assets: AssetCache,
     pub files: FileCollection,
     pub input_file_filter: Option<String>,
+    /// Restricts which pipelines and files are processed; files outside of it are still
+    /// available to pipelines as dependencies
+    pub filter: PipelineFilter,
     pub package_name: String,
     pub in_root: AbsAssetUrl,
     pub out_root: AbsAssetUrl,
@@ -135,6 +207,16 @@ pub struct ProcessCtx {
     pub on_status: Arc<dyn Fn(String) -> BoxFuture<'static, ()> + Sync + Send>,
     pub on_error: Arc<dyn Fn(anyhow::Error) -> BoxFuture<'static, ()> + Sync + Send>,
 }
+/// Fails the whole build when it's the context of the error of a file, instead of only leaving the file out of it; e.g.
+/// for invalid WGSL, which would otherwise only fail once the clients render it
+#[derive(Debug, Clone, Copy)]
+pub struct FatalPipelineError;
+impl std::fmt::Display for FatalPipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The build can't succeed with this error")
+    }
+}
+
 #[derive(Clone)]
 pub struct FileCollection(pub Arc<Vec<AbsAssetUrl>>);
 impl FileCollection {
@@ -151,6 +233,9 @@ impl FileCollection {
     pub fn sub_directory(&self, path: &str) -> Self {
         Self(Arc::new(self.0.iter().filter(|url| url.path().starts_with(path)).cloned().collect()))
     }
+    pub fn filter(&self, filter: impl Fn(&AbsAssetUrl) -> bool) -> Self {
+        Self(Arc::new(self.0.iter().filter(|url| filter(url)).cloned().collect()))
+    }
 }
 
 pub async fn download_image(assets: &AssetCache, url: &AbsAssetUrl) -> anyhow::Result<image::DynamicImage> {
@@ -161,3 +246,30 @@ pub async fn download_image(assets: &AssetCache, url: &AbsAssetUrl) -> anyhow::R
         Ok(image::load_from_memory(&data).with_context(|| format!("Failed to load image {url}"))?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use relative_path::RelativePath;
+
+    use super::PipelineFilter;
+
+    #[test]
+    fn pipeline_filter() {
+        let filter = PipelineFilter::new(["./characters/knight/"], Vec::<String>::new());
+        assert!(filter.matches_pipeline_root(RelativePath::new("")));
+        assert!(filter.matches_pipeline_root(RelativePath::new("characters")));
+        assert!(filter.matches_pipeline_root(RelativePath::new("characters/knight/armor")));
+        assert!(!filter.matches_pipeline_root(RelativePath::new("characters/knight_old")));
+        assert!(!filter.matches_pipeline_root(RelativePath::new("ui")));
+
+        assert!(filter.matches_file(RelativePath::new("characters/knight/knight.glb")));
+        assert!(!filter.matches_file(RelativePath::new("characters/archer.glb")));
+        assert!(filter.matches_tags(&[]));
+
+        let filter = PipelineFilter::new(Vec::<String>::new(), ["ui"]);
+        assert!(filter.matches_tags(&["ui".to_string(), "hud".to_string()]));
+        assert!(!filter.matches_tags(&["characters".to_string()]));
+        assert!(filter.matches_file(RelativePath::new("anything.png")));
+        assert!(PipelineFilter::default().is_empty());
+    }
+}
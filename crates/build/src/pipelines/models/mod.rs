@@ -4,7 +4,7 @@ use ambient_core::hierarchy::children;
 use ambient_ecs::EntityData;
 use ambient_model_import::{model_crate::ModelCrate, MaterialFilter, ModelTextureSize, ModelTransform, TextureResolver};
 use ambient_physics::collider::{collider_type, ColliderType};
-use ambient_std::asset_url::AssetType;
+use ambient_std::{asset_url::AssetType, uv_unwrap::LightmapUvSettings};
 use futures::FutureExt;
 use relative_path::RelativePath;
 use serde::{Deserialize, Serialize};
@@ -93,6 +93,12 @@ pub struct ModelsPipeline {
     /// These will be applied in sequence.
     #[serde(default)]
     transforms: Vec<ModelTransform>,
+    /// Compress the meshes with meshopt, which makes them a few times smaller to download. Off by default.
+    #[serde(default)]
+    compress_meshes: bool,
+    /// Generate the second UVs of the static meshes which don't have them, which the lightmaps are baked with. Off by default.
+    #[serde(default)]
+    lightmap_uvs: Option<LightmapUvs>,
 }
 impl ModelsPipeline {
     pub async fn apply(
@@ -112,6 +118,12 @@ impl ModelsPipeline {
         if let Some(max_size) = self.cap_texture_sizes {
             model_crate.cap_texture_sizes(max_size.size());
         }
+        if let Some(lightmap_uvs) = &self.lightmap_uvs {
+            model_crate.generate_lightmap_uvs(&lightmap_uvs.settings, matches!(lightmap_uvs.packing, LightmapPacking::PerModel));
+        }
+        if self.compress_meshes {
+            model_crate.compress_meshes();
+        }
         model_crate.finalize_model();
         match self.collider {
             Collider::None => {}
@@ -151,6 +163,26 @@ pub enum ModelImporter {
     Quixel,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct LightmapUvs {
+    /// The resolution of the lightmap, the padding between the charts, and how much the charts may bend.
+    #[serde(flatten)]
+    settings: LightmapUvSettings,
+    /// Which meshes share a lightmap.
+    packing: LightmapPacking,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(tag = "type")]
+pub enum LightmapPacking {
+    #[default]
+    /// Each mesh has a lightmap of its own. The default.
+    PerMesh,
+    /// The static meshes of a model share a lightmap, as when they're merged into one.
+    PerModel,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(tag = "type")]
 pub enum Collider {
@@ -324,11 +324,20 @@ impl UnityMaterials {
                 opacity: None,
                 base_color_factor: None,
                 emissive_factor: None,
+                emissive_strength: None,
+                emissive: None,
+                emissive_srgb: false,
                 transparent: None,
                 alpha_cutoff: mat.alpha_cutoff,
                 double_sided: Some(true), // TODO: Double sided is configured in the shader in unity, so hard to know. Maybe make user configureable
                 metallic: 1.,
                 roughness: 1.,
+                flipbook: None,
+                transmission: None,
+                clearcoat: None,
+                sheen: None,
+                sampling: None,
+                detail: None,
             };
             self.materials.insert(name.to_string(), mat.clone());
             Ok(mat)
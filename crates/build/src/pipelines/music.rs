@@ -0,0 +1,56 @@
+use ambient_std::asset_url::AssetType;
+use ambient_world_audio::music::MusicDesc;
+use anyhow::Context;
+use tracing::{info_span, Instrument};
+
+use super::{
+    audio::ffmpeg_convert,
+    context::PipelineCtx,
+    out_asset::{asset_id_from_url, OutAsset, OutAssetContent, OutAssetPreview},
+};
+
+pub const MUSIC_EXTENSION: &str = "music.json";
+
+pub async fn pipeline(ctx: &PipelineCtx) -> Vec<OutAsset> {
+    ctx.process_files(
+        |file| file.path().as_str().ends_with(&format!(".{MUSIC_EXTENSION}")),
+        |ctx, file| async move {
+            let contents = file.download_bytes(ctx.assets()).await?;
+            let mut desc: MusicDesc = serde_json::from_slice(&contents).context("Invalid music")?;
+            anyhow::ensure!(desc.bpm > 0., "The bpm of the music has to be positive");
+
+            for stem in &mut desc.stems {
+                let source = file.join(&stem.audio).with_context(|| format!("Invalid stem path {:?}", stem.audio))?;
+                let source = ctx.get_downloadable_url(&source)?.clone();
+                let contents = source.download_bytes(ctx.assets()).await?;
+                let rel_path = ctx.in_root().relative_path(source.path());
+                let content_url = match source.extension().as_deref() {
+                    Some("ogg") => ctx.write_file(&rel_path, contents).await,
+                    Some("wav" | "mp3") => {
+                        let contents = ffmpeg_convert(std::io::Cursor::new(contents)).await?;
+                        ctx.write_file(rel_path.with_extension("ogg"), contents).await
+                    }
+                    other => anyhow::bail!("Stem filetype {:?} is not yet supported", other.unwrap_or_default()),
+                };
+                stem.audio = content_url.to_string();
+            }
+
+            let rel_path = ctx.in_root().relative_path(file.path());
+            let desc_url = ctx.write_file(&rel_path, serde_json::to_vec_pretty(&desc)?).await;
+
+            Ok(vec![OutAsset {
+                id: asset_id_from_url(&file),
+                type_: AssetType::Music,
+                hidden: false,
+                name: file.path().file_name().unwrap().to_string(),
+                tags: Vec::new(),
+                categories: Default::default(),
+                preview: OutAssetPreview::None,
+                content: OutAssetContent::Content(desc_url),
+                source: Some(file.clone()),
+            }])
+        },
+    )
+    .instrument(info_span!("music_pipeline"))
+    .await
+}
@@ -0,0 +1,202 @@
+use std::path::{Path, PathBuf};
+
+use ambient_rustc::Rust;
+use ambient_std::asset_url::{AbsAssetUrl, AssetType};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::{info_span, Instrument};
+
+use super::{
+    context::PipelineCtx,
+    out_asset::{asset_id_from_url, OutAsset, OutAssetContent, OutAssetPreview},
+};
+
+pub const SCRIPT_BUNDLE_EXTENSION: &str = "script_bundle";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptBundlesPipeline {
+    /// Whether or not compiled scripts should be optimized with `wasm-opt`. This is skipped with a warning if `wasm-opt` is not installed.
+    /// Defaults to true.
+    #[serde(default = "true_value")]
+    pub optimize: bool,
+    /// Whether or not the custom sections (debug info, names, producers, etc.) of compiled scripts should be removed from the bundle.
+    /// Defaults to true.
+    #[serde(default = "true_value")]
+    pub strip_custom_sections: bool,
+    /// Compile scripts with debug info, and write it to a separate `.debug.wasm` file next to the build artifacts in the `target` directory.
+    /// This file is not part of the build output, but can be used to symbolicate the bundle locally.
+    #[serde(default)]
+    pub split_debug_info: bool,
+}
+
+fn true_value() -> bool {
+    true
+}
+
+/// Emits a script bundle for each `.script_bundle` file, which are copied as-is, and for each Rust crate (i.e. a directory
+/// with a `Cargo.toml`), which is compiled to WASM.
+pub async fn pipeline(ctx: &PipelineCtx, config: ScriptBundlesPipeline) -> Vec<OutAsset> {
+    ctx.process_files(
+        |file| file.extension_is(SCRIPT_BUNDLE_EXTENSION) || file.path().file_name() == Some("Cargo.toml"),
+        move |ctx, file| {
+            let config = config.clone();
+            async move {
+                let rel_path = ctx.in_root().relative_path(file.path());
+                if file.extension_is(SCRIPT_BUNDLE_EXTENSION) {
+                    let contents = file.download_bytes(ctx.assets()).await?;
+                    let content_url = ctx.write_file(&rel_path, contents).await;
+                    let name = file.path().file_stem().unwrap_or_default().to_string();
+                    return Ok(vec![script_bundle_asset(&file, name, content_url, file.clone())]);
+                }
+
+                let manifest_path = file.to_file_path()?.context("Script crates can only be built from local files")?;
+                let crate_path = manifest_path.parent().unwrap().to_path_buf();
+                let package_name = cargo_toml::Manifest::from_path(&manifest_path)?
+                    .package
+                    .with_context(|| format!("No [package] present in {manifest_path:?}"))?
+                    .name;
+                let target_path = target_directory(&ctx)?;
+                let rustc = Rust::get_system_installation().await?;
+
+                (ctx.process_ctx.on_status)(format!("[{}] Compiling script {package_name}", ctx.pipeline_path())).await;
+                let bytecode = tokio::task::spawn_blocking({
+                    let package_name = package_name.clone();
+                    move || build_script(&rustc, &crate_path, &package_name, &target_path, &config)
+                })
+                .await??;
+
+                let content_url = ctx.write_file(rel_path.parent().unwrap().join(format!("{package_name}.wasm")), bytecode).await;
+                let crate_url = file.join(".")?.as_file();
+                Ok(vec![script_bundle_asset(&file, package_name, content_url, crate_url)])
+            }
+        },
+    )
+    .instrument(info_span!("script_bundles_pipeline"))
+    .await
+}
+
+fn script_bundle_asset(file: &AbsAssetUrl, name: String, content_url: AbsAssetUrl, source: AbsAssetUrl) -> OutAsset {
+    OutAsset {
+        id: asset_id_from_url(file),
+        type_: AssetType::ScriptBundle,
+        hidden: false,
+        name,
+        tags: Vec::new(),
+        categories: Default::default(),
+        preview: OutAssetPreview::None,
+        content: OutAssetContent::Content(content_url),
+        source: Some(source),
+    }
+}
+
+/// Scripts are compiled into the project's `target` directory, so that they share dependencies with the project
+/// and their build artifacts don't end up in the assets
+fn target_directory(ctx: &PipelineCtx) -> anyhow::Result<PathBuf> {
+    let assets_path = ctx.process_ctx.in_root.to_file_path()?.context("Script crates can only be built from local files")?;
+    Ok(assets_path.parent().unwrap_or(&assets_path).join("target"))
+}
+
+fn build_script(
+    rustc: &Rust,
+    crate_path: &Path,
+    package_name: &str,
+    target_path: &Path,
+    config: &ScriptBundlesPipeline,
+) -> anyhow::Result<Vec<u8>> {
+    let artifact = rustc.build_artifact(crate_path, package_name, Some(target_path), config.split_debug_info)?;
+    let module = if !config.optimize {
+        std::fs::read(&artifact)?
+    } else if !ambient_rustc::wasm_opt_installed() {
+        log::warn!("`wasm-opt` is not installed, so {package_name} will not be optimized. It can be installed from https://github.com/WebAssembly/binaryen");
+        std::fs::read(&artifact)?
+    } else {
+        let optimized = artifact.with_extension("opt.wasm");
+        // `-g` makes wasm-opt keep the debug info (and update it to match the optimized code), instead of dropping it
+        let args = if config.split_debug_info { vec!["-O", "-g"] } else { vec!["-O"] };
+        ambient_rustc::optimize_wasm(&artifact, &optimized, args).with_context(|| format!("Failed to optimize {package_name}"))?;
+        std::fs::read(&optimized)?
+    };
+    if !config.strip_custom_sections && !config.split_debug_info {
+        return Ok(module);
+    }
+
+    let (stripped, custom_sections) = split_custom_sections(&module).with_context(|| format!("Failed to strip {package_name}"))?;
+    if config.split_debug_info {
+        let debug_path = artifact.with_extension("debug.wasm");
+        std::fs::write(&debug_path, custom_sections)?;
+        log::info!("Wrote the debug info of {package_name} to {debug_path:?}");
+    }
+    if config.strip_custom_sections {
+        log::info!("Stripped {package_name} from {} to {} bytes", module.len(), stripped.len());
+        Ok(stripped)
+    } else {
+        Ok(module)
+    }
+}
+
+/// Splits a WASM module in two: the module without its custom sections, and a module which only contains them.
+///
+/// As the code section is left as-is, the offsets in the DWARF sections of the latter still apply to the former, so it
+/// can be used to symbolicate it.
+fn split_custom_sections(module: &[u8]) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    const HEADER_LEN: usize = 8;
+    const CUSTOM_SECTION_ID: u8 = 0;
+    anyhow::ensure!(module.len() >= HEADER_LEN && module.starts_with(b"\0asm"), "Not a WASM module");
+
+    let mut stripped = module[..HEADER_LEN].to_vec();
+    let mut custom_sections = module[..HEADER_LEN].to_vec();
+    let mut offset = HEADER_LEN;
+    while offset < module.len() {
+        let id = module[offset];
+        let (size, size_len) = read_leb128_u32(&module[offset + 1..]).with_context(|| format!("Invalid section size at {offset}"))?;
+        let end = offset + 1 + size_len + size as usize;
+        anyhow::ensure!(end <= module.len(), "Truncated section at {offset}");
+        if id == CUSTOM_SECTION_ID {
+            custom_sections.extend_from_slice(&module[offset..end]);
+        } else {
+            stripped.extend_from_slice(&module[offset..end]);
+        }
+        offset = end;
+    }
+    Ok((stripped, custom_sections))
+}
+
+/// Returns the value and the number of bytes it was encoded with
+fn read_leb128_u32(bytes: &[u8]) -> anyhow::Result<(u32, usize)> {
+    let mut value = 0;
+    for (i, byte) in bytes.iter().take(5).enumerate() {
+        value |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    anyhow::bail!("Unterminated LEB128 integer")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_leb128_u32, split_custom_sections};
+
+    #[test]
+    fn leb128() {
+        assert_eq!(read_leb128_u32(&[0x05]).unwrap(), (5, 1));
+        assert_eq!(read_leb128_u32(&[0xe5, 0x8e, 0x26]).unwrap(), (624485, 3));
+        assert!(read_leb128_u32(&[0x80, 0x80]).is_err());
+    }
+
+    #[test]
+    fn custom_sections() {
+        let header = b"\0asm\x01\0\0\0".to_vec();
+        // An empty type section, and a custom section named `name`
+        let type_section = [0x01, 0x01, 0x00];
+        let name_section = [0x00, 0x06, 0x04, b'n', b'a', b'm', b'e', 0x00];
+        let module = [header.clone(), name_section.to_vec(), type_section.to_vec(), name_section.to_vec()].concat();
+
+        let (stripped, custom_sections) = split_custom_sections(&module).unwrap();
+        assert_eq!(stripped, [header.clone(), type_section.to_vec()].concat());
+        assert_eq!(custom_sections, [header, name_section.to_vec(), name_section.to_vec()].concat());
+
+        assert!(split_custom_sections(b"not wasm").is_err());
+        assert!(split_custom_sections(&module[..module.len() - 1]).is_err());
+    }
+}
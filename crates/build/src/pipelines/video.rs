@@ -0,0 +1,127 @@
+use std::{io::Cursor, path::Path};
+
+use ambient_std::{asset_url::AssetType, friendly_id};
+use ambient_video::{VideoDesc, VideoFrames};
+use anyhow::Context;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tracing::{info_span, Instrument};
+
+use super::{
+    context::PipelineCtx,
+    out_asset::{asset_id_from_url, OutAsset, OutAssetContent, OutAssetPreview},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoPipeline {
+    /// The number of frames per second of the output videos.
+    /// Defaults to 30.
+    #[serde(default)]
+    pub fps: Option<f32>,
+    /// Videos taller than this are scaled down to this height, keeping their aspect ratio.
+    /// Defaults to 720.
+    #[serde(default)]
+    pub max_height: Option<u32>,
+}
+
+pub async fn pipeline(ctx: &PipelineCtx, config: VideoPipeline) -> Vec<OutAsset> {
+    ctx.process_files(
+        |file| matches!(file.extension().as_deref(), Some("mp4") | Some("webm") | Some("mov") | Some("mkv") | Some("avi")),
+        move |ctx, file| {
+            let config = config.clone();
+            async move {
+                let contents = file.download_bytes(ctx.assets()).await?;
+                let filename = file.path().file_name().unwrap().to_string();
+                let rel_path = ctx.in_root().relative_path(file.path());
+
+                let tmp_dir = std::env::temp_dir().join(format!("ambient_video_{}", friendly_id()));
+                tokio::fs::create_dir_all(&tmp_dir).await?;
+                let converted = convert(&tmp_dir, contents, &config).await;
+                tokio::fs::remove_dir_all(&tmp_dir).await.ok();
+                let (fps, frames, audio) = converted?;
+
+                let first_frame = frames.frames.first().context("The video has no frames")?;
+                let (width, height) = image::io::Reader::new(Cursor::new(first_frame)).with_guessed_format()?.into_dimensions()?;
+                let frames_url = ctx.write_file(rel_path.with_extension("frames"), frames.to_bytes()).await;
+                let audio_url = match audio {
+                    Some(audio) => Some(ctx.write_file(rel_path.with_extension("ogg"), audio).await.to_string()),
+                    None => None,
+                };
+                let desc = VideoDesc {
+                    width,
+                    height,
+                    fps,
+                    frame_count: frames.frames.len() as u32,
+                    frames: frames_url.to_string(),
+                    audio: audio_url,
+                };
+                let desc_url = ctx.write_file(rel_path.with_extension("video.json"), serde_json::to_vec_pretty(&desc)?).await;
+
+                Ok(vec![OutAsset {
+                    id: asset_id_from_url(&file),
+                    type_: AssetType::Video,
+                    hidden: false,
+                    name: filename,
+                    tags: Vec::new(),
+                    categories: Default::default(),
+                    preview: OutAssetPreview::None,
+                    content: OutAssetContent::Content(desc_url),
+                    source: Some(file.clone()),
+                }])
+            }
+        },
+    )
+    .instrument(info_span!("video_pipeline"))
+    .await
+}
+
+/// Splits the video into JPEG frames and an Ogg Vorbis audio track with ffmpeg, which needs the whole file as input (the
+/// index of mp4 files is often at their end)
+async fn convert(tmp_dir: &Path, contents: Vec<u8>, config: &VideoPipeline) -> anyhow::Result<(f32, VideoFrames, Option<Vec<u8>>)> {
+    let fps = config.fps.unwrap_or(30.);
+    let max_height = config.max_height.unwrap_or(720);
+    let input = tmp_dir.join("input");
+    tokio::fs::write(&input, contents).await?;
+
+    let frames_dir = tmp_dir.join("frames");
+    tokio::fs::create_dir_all(&frames_dir).await?;
+    let filter = format!("fps={fps},scale=-2:'min({max_height},ih)'");
+    ffmpeg(&input, &["-vf", &filter, "-q:v", "3"], &frames_dir.join("%06d.jpg")).await.context("Failed to extract the frames")?;
+    let mut frame_paths = Vec::new();
+    let mut entries = tokio::fs::read_dir(&frames_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        frame_paths.push(entry.path());
+    }
+    let mut frames = VideoFrames::default();
+    for path in frame_paths.into_iter().sorted() {
+        frames.frames.push(tokio::fs::read(path).await?);
+    }
+
+    let audio_path = tmp_dir.join("audio.ogg");
+    let audio = match ffmpeg(&input, &["-vn", "-map", "0:a:0", "-c:a", "libvorbis"], &audio_path).await {
+        Ok(()) => Some(tokio::fs::read(&audio_path).await?),
+        Err(err) => {
+            // Most likely because the video doesn't have any audio
+            tracing::info!("Not extracting any audio: {err:?}");
+            None
+        }
+    };
+
+    Ok((fps, frames, audio))
+}
+
+#[tracing::instrument(level = "info")]
+async fn ffmpeg(input: &Path, args: &[&str], output: &Path) -> anyhow::Result<()> {
+    let result = tokio::process::Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y", "-i"])
+        .arg(input)
+        .args(args)
+        .arg(output)
+        .output()
+        .await
+        .context("Failed to execute ffmpeg")?;
+    if !result.status.success() {
+        anyhow::bail!("ffmpeg failed: {}", String::from_utf8_lossy(&result.stderr));
+    }
+    Ok(())
+}
@@ -14,6 +14,7 @@ use crate::{
     gpu, gpu_components,
     gpu_ecs::{gpu_world, ArchChangeDetection, ComponentToGpuSystem, GpuComponentFormat, GpuWorldSyncEvent},
     hierarchy::children,
+    jobs,
     transform::local_to_world,
 };
 
@@ -37,11 +38,12 @@ pub fn bounding_systems() -> SystemGroup {
         "bounding",
         vec![query_mut((world_bounding_aabb(), world_bounding_sphere()), (local_bounding_aabb().changed(), local_to_world().changed()))
             .to_system(|q, world, qs, _| {
-                for (_, (world_aabb, world_sphere), (aabb, local_to_world)) in q.iter(world, qs) {
+                let mut bounds = q.iter(world, qs).collect_vec();
+                jobs::par_for_each_mut(&mut bounds, |(_, (world_aabb, world_sphere), (aabb, local_to_world))| {
                     let world_box = aabb.transform(local_to_world);
-                    *world_aabb = world_box.to_aabb();
-                    *world_sphere = world_box.to_sphere();
-                }
+                    **world_aabb = world_box.to_aabb();
+                    **world_sphere = world_box.to_sphere();
+                });
             })],
     )
 }
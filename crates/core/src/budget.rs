@@ -0,0 +1,363 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+    sync::Arc,
+    time::Duration,
+};
+
+use ambient_ecs::{components, DynSystem, FrameEvent, Resource, System, World};
+use ambient_std::instrumented_alloc::AllocScope;
+use ambient_sys::time::Instant;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+components!("app", {
+    /// Checks the world against a [PerformanceBudget]; add [budget_systems] to enforce it
+    @[Resource]
+    budget_monitor: BudgetMonitor,
+});
+
+/// Limits which a project should stay within. Exceeding one doesn't stop anything, but is reported, see [BudgetMonitor].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PerformanceBudget {
+    pub max_entities: Option<usize>,
+    /// How long a single system (wrapped with [budgeted]) may take to run
+    pub max_system_time: Option<Duration>,
+    /// How long a whole frame may take. There are no GPU timings yet, so on the client this is the full frame time,
+    /// which includes waiting for the GPU.
+    pub max_frame_time: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BudgetViolation {
+    Entities { count: usize, max: usize },
+    SystemTime { system: String, time: Duration, max: Duration },
+    FrameTime { time: Duration, max: Duration },
+}
+impl Display for BudgetViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |duration: &Duration| duration.as_secs_f64() * 1000.;
+        match self {
+            BudgetViolation::Entities { count, max } => write!(f, "{count} entities (max {max})"),
+            BudgetViolation::SystemTime { system, time, max } => {
+                write!(f, "system {system} took {:.2} ms (max {:.2} ms)", ms(time), ms(max))
+            }
+            BudgetViolation::FrameTime { time, max } => write!(f, "frame took {:.2} ms (max {:.2} ms)", ms(time), ms(max)),
+        }
+    }
+}
+
+/// How many frames a [BudgetReport] covers
+const REPORT_FRAMES: usize = 300;
+/// How often the [BudgetMonitor::shared_report] is updated
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The CPU time taken by a source (a system, or a WASM module) over the frames of a [BudgetReport]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceTime {
+    pub source: String,
+    pub mean: Duration,
+    pub max: Duration,
+    /// The part of the [BudgetReport::frame_time] taken by this source, from 0 to 1
+    pub share: f32,
+}
+
+/// Where the CPU time of the last frames went, by source.
+///
+/// The sources named `parent/child` (such as `wasm/my_module`) are a breakdown of the time of their parent, so they
+/// aren't counted in the frame time.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BudgetReport {
+    pub frames: usize,
+    /// The mean CPU time of a frame, over all the top level sources
+    pub frame_time: Duration,
+    /// The sources, from the one taking the most time
+    pub sources: Vec<SourceTime>,
+}
+impl BudgetReport {
+    /// The report in the Prometheus text format, to be scraped by a metrics endpoint
+    pub fn to_prometheus(&self) -> String {
+        let label = |source: &str| source.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+        let mut out = String::new();
+        out += "# HELP ambient_frame_cpu_seconds The mean CPU time of a frame.\n";
+        out += "# TYPE ambient_frame_cpu_seconds gauge\n";
+        out += &format!("ambient_frame_cpu_seconds {}\n", self.frame_time.as_secs_f64());
+        out += "# HELP ambient_source_cpu_seconds The mean CPU time of a system or WASM module per frame.\n";
+        out += "# TYPE ambient_source_cpu_seconds gauge\n";
+        for source in &self.sources {
+            out += &format!("ambient_source_cpu_seconds{{source=\"{}\"}} {}\n", label(&source.source), source.mean.as_secs_f64());
+        }
+        out += "# HELP ambient_source_cpu_max_seconds The longest CPU time of a system or WASM module in a frame.\n";
+        out += "# TYPE ambient_source_cpu_max_seconds gauge\n";
+        for source in &self.sources {
+            out += &format!("ambient_source_cpu_max_seconds{{source=\"{}\"}} {}\n", label(&source.source), source.max.as_secs_f64());
+        }
+        out
+    }
+}
+impl Display for BudgetReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |duration: &Duration| duration.as_secs_f64() * 1000.;
+        write!(f, "CPU time over the last {} frames: {:.2} ms per frame", self.frames, ms(&self.frame_time))?;
+        for source in &self.sources {
+            write!(
+                f,
+                "\n  {:<32} {:>8.3} ms (max {:.3} ms, {:.1}%)",
+                source.source,
+                ms(&source.mean),
+                ms(&source.max),
+                source.share * 100.
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks measurements against a [PerformanceBudget], and logs a warning when one starts exceeding it.
+///
+/// A violation which persists over several frames is only logged and recorded once, until it's back within the budget.
+///
+/// It also keeps the CPU time of the sources recorded with [BudgetMonitor::record_time] over the last frames, to tell
+/// where the time goes; see [BudgetMonitor::report].
+#[derive(Debug, Clone, Default)]
+pub struct BudgetMonitor {
+    budget: PerformanceBudget,
+    ongoing: HashSet<String>,
+    violations: Vec<BudgetViolation>,
+    frame: HashMap<String, Duration>,
+    frames: VecDeque<HashMap<String, Duration>>,
+    shared_report: Arc<Mutex<BudgetReport>>,
+    last_shared: Option<Instant>,
+}
+impl BudgetMonitor {
+    pub fn new(budget: PerformanceBudget) -> Self {
+        Self { budget, ..Default::default() }
+    }
+    /// Adds `time` to the CPU time of `source` in the current frame
+    pub fn record_time(&mut self, source: &str, time: Duration) {
+        *self.frame.entry(source.to_string()).or_default() += time;
+    }
+    /// Ends the current frame of the [BudgetMonitor::record_time]s
+    pub fn end_frame(&mut self) {
+        self.frames.push_back(std::mem::take(&mut self.frame));
+        if self.frames.len() > REPORT_FRAMES {
+            self.frames.pop_front();
+        }
+        if self.last_shared.map_or(true, |last| last.elapsed() >= REPORT_INTERVAL) {
+            self.last_shared = Some(Instant::now());
+            *self.shared_report.lock() = self.report();
+        }
+    }
+    /// Where the CPU time went over the last frames
+    pub fn report(&self) -> BudgetReport {
+        if self.frames.is_empty() {
+            return BudgetReport::default();
+        }
+        let mut totals = HashMap::<&str, (Duration, Duration)>::new();
+        for frame in &self.frames {
+            for (source, &time) in frame {
+                let (total, max) = totals.entry(source.as_str()).or_default();
+                *total += time;
+                *max = (*max).max(time);
+            }
+        }
+        let frames = self.frames.len() as u32;
+        let frame_time = totals.iter().filter(|(source, _)| !source.contains('/')).map(|(_, (total, _))| *total).sum::<Duration>() / frames;
+        let mut sources = totals
+            .into_iter()
+            .map(|(source, (total, max))| SourceTime {
+                source: source.to_string(),
+                mean: total / frames,
+                max,
+                share: if frame_time.is_zero() { 0. } else { (total / frames).as_secs_f32() / frame_time.as_secs_f32() },
+            })
+            .collect::<Vec<_>>();
+        sources.sort_by(|a, b| b.mean.cmp(&a.mean).then_with(|| a.source.cmp(&b.source)));
+        BudgetReport { frames: self.frames.len(), frame_time, sources }
+    }
+    /// The [BudgetMonitor::report], updated every second, which can be read from other threads (such as the ones
+    /// serving the metrics)
+    pub fn shared_report(&self) -> Arc<Mutex<BudgetReport>> {
+        self.shared_report.clone()
+    }
+    pub fn budget(&self) -> &PerformanceBudget {
+        &self.budget
+    }
+    /// All the violations since the monitor was created, in the order they started
+    pub fn violations(&self) -> &[BudgetViolation] {
+        &self.violations
+    }
+    pub fn check_entities(&mut self, count: usize) {
+        let violation = self.budget.max_entities.filter(|max| count > *max).map(|max| BudgetViolation::Entities { count, max });
+        self.track_violation("entities".to_string(), violation);
+    }
+    pub fn check_system_time(&mut self, system: &str, time: Duration) {
+        let violation = self.budget.max_system_time.filter(|max| time > *max).map(|max| BudgetViolation::SystemTime {
+            system: system.to_string(),
+            time,
+            max,
+        });
+        self.track_violation(format!("system {system}"), violation);
+    }
+    pub fn check_frame_time(&mut self, time: Duration) {
+        let violation = self.budget.max_frame_time.filter(|max| time > *max).map(|max| BudgetViolation::FrameTime { time, max });
+        if self.track_violation("frame".to_string(), violation) {
+            log::warn!("{}", self.report());
+        }
+    }
+    /// Returns whether the violation started
+    fn track_violation(&mut self, key: String, violation: Option<BudgetViolation>) -> bool {
+        match violation {
+            Some(violation) => {
+                let started = self.ongoing.insert(key);
+                if started {
+                    log::warn!("Performance budget exceeded: {violation}");
+                    self.violations.push(violation);
+                }
+                started
+            }
+            None => {
+                self.ongoing.remove(&key);
+                false
+            }
+        }
+    }
+}
+
+/// Checks the entity count and the frame time against the [budget_monitor], if there is one
+pub fn budget_systems() -> DynSystem {
+    Box::new(FrameBudgetSystem { last_frame: None })
+}
+
+#[derive(Debug)]
+struct FrameBudgetSystem {
+    last_frame: Option<Instant>,
+}
+impl System for FrameBudgetSystem {
+    fn run(&mut self, world: &mut World, _event: &FrameEvent) {
+        let now = Instant::now();
+        let frame_time = self.last_frame.map(|last_frame| now.duration_since(last_frame));
+        self.last_frame = Some(now);
+        let count = world.len();
+        if let Some(monitor) = world.resource_mut_opt(budget_monitor()) {
+            monitor.end_frame();
+            monitor.check_entities(count);
+            if let Some(frame_time) = frame_time {
+                monitor.check_frame_time(frame_time);
+            }
+        }
+    }
+}
+
+/// Measures how long `system` takes to run, records it as `name` and checks it against the [budget_monitor], if there is
+/// one. Its allocations are tagged with `name`, see [ambient_std::instrumented_alloc].
+pub fn budgeted(name: &'static str, system: DynSystem) -> DynSystem {
+    Box::new(BudgetedSystem { name, system })
+}
+
+#[derive(Debug)]
+struct BudgetedSystem {
+    name: &'static str,
+    system: DynSystem,
+}
+impl System for BudgetedSystem {
+    fn run(&mut self, world: &mut World, event: &FrameEvent) {
+        let start = Instant::now();
+        {
+            let _scope = AllocScope::enter(self.name);
+            self.system.run(world, event);
+        }
+        let time = start.elapsed();
+        if let Some(monitor) = world.resource_mut_opt(budget_monitor()) {
+            monitor.record_time(self.name, time);
+            monitor.check_system_time(self.name, time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{BudgetMonitor, BudgetReport, BudgetViolation, PerformanceBudget, SourceTime};
+
+    #[test]
+    fn ongoing_violations_are_reported_once() {
+        let mut monitor = BudgetMonitor::new(PerformanceBudget {
+            max_entities: Some(10),
+            max_system_time: Some(Duration::from_millis(2)),
+            ..Default::default()
+        });
+        monitor.check_entities(5);
+        monitor.check_entities(11);
+        monitor.check_entities(12);
+        monitor.check_system_time("physics", Duration::from_millis(1));
+        monitor.check_system_time("physics", Duration::from_millis(3));
+        monitor.check_system_time("scripts", Duration::from_millis(3));
+        monitor.check_frame_time(Duration::from_secs(1));
+        monitor.check_entities(5);
+        monitor.check_entities(13);
+        assert_eq!(
+            monitor.violations(),
+            &[
+                BudgetViolation::Entities { count: 11, max: 10 },
+                BudgetViolation::SystemTime {
+                    system: "physics".to_string(),
+                    time: Duration::from_millis(3),
+                    max: Duration::from_millis(2)
+                },
+                BudgetViolation::SystemTime {
+                    system: "scripts".to_string(),
+                    time: Duration::from_millis(3),
+                    max: Duration::from_millis(2)
+                },
+                BudgetViolation::Entities { count: 13, max: 10 },
+            ]
+        );
+    }
+
+    #[test]
+    fn report_breaks_down_frame_time() {
+        let ms = Duration::from_millis;
+        let mut monitor = BudgetMonitor::new(PerformanceBudget::default());
+        monitor.record_time("physics", ms(1));
+        monitor.record_time("wasm", ms(3));
+        monitor.record_time("wasm/slow", ms(2));
+        monitor.record_time("wasm/slow", ms(1));
+        monitor.end_frame();
+        monitor.record_time("physics", ms(3));
+        monitor.record_time("wasm", ms(1));
+        monitor.end_frame();
+
+        let report = monitor.report();
+        assert_eq!(report.frames, 2);
+        assert_eq!(report.frame_time, ms(4));
+        assert_eq!(
+            report.sources,
+            vec![
+                SourceTime { source: "physics".to_string(), mean: ms(2), max: ms(3), share: 0.5 },
+                SourceTime { source: "wasm".to_string(), mean: ms(2), max: ms(3), share: 0.5 },
+                SourceTime { source: "wasm/slow".to_string(), mean: Duration::from_micros(1500), max: ms(3), share: 0.375 },
+            ]
+        );
+        // The shared report is updated at most every second
+        assert_eq!(monitor.shared_report().lock().frames, 1);
+    }
+
+    #[test]
+    fn prometheus_labels_are_escaped() {
+        let source = |source: &str| SourceTime {
+            source: source.to_string(),
+            mean: Duration::from_millis(2),
+            max: Duration::from_millis(5),
+            share: 1.,
+        };
+        let report = BudgetReport { frames: 1, frame_time: Duration::from_millis(2), sources: vec![source("wasm/a \"b\"\\c\nd")] };
+        let metrics = report.to_prometheus();
+        assert!(metrics.contains("ambient_frame_cpu_seconds 0.002\n"), "{metrics}");
+        assert!(metrics.contains("ambient_source_cpu_seconds{source=\"wasm/a \\\"b\\\"\\\\c\\nd\"} 0.002\n"), "{metrics}");
+        assert!(metrics.contains("ambient_source_cpu_max_seconds{source=\"wasm/a \\\"b\\\"\\\\c\\nd\"} 0.005\n"), "{metrics}");
+        // Every sample is on a line of its own
+        assert_eq!(metrics.lines().filter(|line| !line.starts_with('#')).count(), 3);
+    }
+}
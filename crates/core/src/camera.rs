@@ -8,6 +8,7 @@ use ambient_std::{
 use glam::{vec3, Mat4, Vec2, Vec3, Vec3Swizzles};
 use itertools::Itertools;
 use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     transform::{inv_local_to_world, local_to_world},
@@ -126,6 +127,38 @@ components!("camera", {
     ]
     fog: (),
 
+    // Post-processing
+    @[
+        Networked, Store,
+        Name["Exposure"],
+        Description["The exposure compensation of this camera in stops; each stop doubles the brightness of what it sees. With `auto_exposure`, it's added to the exposure the camera adapts to.\nDefaults to 0."]
+    ]
+    exposure: f32,
+    @[
+        Networked, Store,
+        Name["Auto exposure"],
+        Description["If attached, the exposure of this camera adapts over time to the average brightness of what it sees, like an eye getting used to the dark."]
+    ]
+    auto_exposure: (),
+    @[
+        Networked, Store,
+        Name["Bloom intensity"],
+        Description["How strongly the parts of the image brighter than `bloom_threshold` glow onto their surroundings, such as the sun or bright emissive materials.\nDefaults to 0, which disables bloom."]
+    ]
+    bloom_intensity: f32,
+    @[
+        Networked, Store,
+        Name["Bloom threshold"],
+        Description["How bright a pixel must be to glow with `bloom_intensity`, after exposure, relative to the white of the display.\nDefaults to 1."]
+    ]
+    bloom_threshold: f32,
+    @[
+        Networked, Store,
+        Name["Tonemapper"],
+        Description["How the brightness this camera sees is mapped to the range of the display: 0 to clip it at white (the default), 1 for ACES, which rolls the highlights off like film, and 2 for Reinhard."]
+    ]
+    tonemapper: u32,
+
     // Shadows
     @[
         Networked, Store,
@@ -372,20 +405,18 @@ impl Camera {
             proj_view_inv.project_point3(vec3(s, s, s)),
         ]
     }
-    pub fn world_space_frustum_points_for_shadow_cascade(&self, cascade_index: u32, n_cascades: u32) -> Vec<Vec3> {
-        // From: http://developer.download.nvidia.com/SDK/10.5/opengl/src/cascaded_shadow_maps/doc/cascaded_shadow_maps.pdf
-        fn split_z(linear_factor: f32, near: f32, far: f32, i: u32, n: u32) -> f32 {
-            let p = (i as f32) / (n as f32);
-            (1. - linear_factor) * near * (far / near).powf(p) + linear_factor * (near + p * (far - near))
-        }
-
+    pub fn world_space_frustum_points_for_shadow_cascade(
+        &self,
+        cascade_index: u32,
+        n_cascades: u32,
+        splits: &ShadowCascadeSplits,
+    ) -> Vec<Vec3> {
         let near = 1.;
-        let linear_factor = 0.0;
         let main_projection = self.projection.matrix();
         let main_projection_view_inv = self.projection_view().inverse();
         let far = self.projection.far().expect("Shadow camera can't be infinite. Use set_far(shadow_far) to get a shadow camera");
-        let p0 = split_z(linear_factor, near, far, cascade_index, n_cascades);
-        let p1 = split_z(linear_factor, near, far, cascade_index + 1, n_cascades);
+        let p0 = splits.split_z(near, far, cascade_index, n_cascades);
+        let p1 = splits.split_z(near, far, cascade_index + 1, n_cascades);
         let z0 = main_projection.project_point3(vec3(0., 0., p0)).z;
         let z1 = main_projection.project_point3(vec3(0., 0., p1)).z;
         let frustum = vec![
@@ -439,10 +470,11 @@ impl Camera {
         light_direction: Vec3,
         cascade_index: u32,
         n_cascades: u32,
+        splits: &ShadowCascadeSplits,
         shadow_map_resolution: u32,
     ) -> Self {
         let main_camera = self.to_shadows_far_bound();
-        let frustum_world = main_camera.world_space_frustum_points_for_shadow_cascade(cascade_index, n_cascades);
+        let frustum_world = main_camera.world_space_frustum_points_for_shadow_cascade(cascade_index, n_cascades, splits);
         let frustum_perspective = frustum_world.iter().map(|x| main_camera.view.project_point3(*x)).collect_vec();
         let frustum_size = AABB::from_points(&frustum_perspective);
         let mut shadow_view = if light_direction != Vec3::Z {
@@ -531,16 +563,50 @@ pub struct CameraViewSpaceFrustum {
 pub fn shadow_cameras_from_world(
     world: &World,
     shadow_cascades: u32,
+    splits: &ShadowCascadeSplits,
     shadow_map_resolution: u32,
     light_direction: Vec3,
     scene: Component<()>,
 ) -> Vec<Camera> {
     let camera = Camera::get_active(world, scene).unwrap();
     (0..shadow_cascades)
-        .map(|cascade| camera.create_snapping_shadow_camera(light_direction, cascade, shadow_cascades, shadow_map_resolution))
+        .map(|cascade| camera.create_snapping_shadow_camera(light_direction, cascade, shadow_cascades, splits, shadow_map_resolution))
         .collect()
 }
 
+/// Where the view frustum of a camera is split into the cascades of its shadow map, between the near plane and `shadows_far`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShadowCascadeSplits {
+    /// The practical split scheme, which blends logarithmic splits (0) with uniform ones (1).
+    /// From: http://developer.download.nvidia.com/SDK/10.5/opengl/src/cascaded_shadow_maps/doc/cascaded_shadow_maps.pdf
+    Blend(f32),
+    /// The distances from the camera at which each cascade but the last ends, in increasing order; the last one ends at `shadows_far`
+    Distances(Vec<f32>),
+}
+impl Default for ShadowCascadeSplits {
+    fn default() -> Self {
+        Self::Blend(0.)
+    }
+}
+impl ShadowCascadeSplits {
+    /// The view space depth at which cascade `i` starts, or cascade `i - 1` ends
+    pub fn split_z(&self, near: f32, far: f32, i: u32, n: u32) -> f32 {
+        if i == 0 {
+            return near;
+        } else if i >= n {
+            return far;
+        }
+        match self {
+            Self::Blend(linear_factor) => {
+                let p = (i as f32) / (n as f32);
+                (1. - linear_factor) * near * (far / near).powf(p) + linear_factor * (near + p * (far - near))
+            }
+            Self::Distances(distances) => distances.get(i as usize - 1).map(|d| d.clamp(near, far)).unwrap_or(far),
+        }
+    }
+}
+
 #[test]
 fn test_frustum() {
     let projection = Projection::Orthographic { rect: OrthographicRect { left: -5., right: 5., bottom: -5., top: 5. }, near: -5., far: 5. };
@@ -573,3 +639,21 @@ fn test_frustum_reverse_z() {
     assert!(frustum.right.distance(Vec3::Z * 100.) < 0.);
     assert!(frustum.top.distance(Vec3::Z * 100.) < 0.);
 }
+
+#[test]
+fn test_shadow_cascade_splits() {
+    let logarithmic = ShadowCascadeSplits::Blend(0.);
+    let splits = (0..=4).map(|i| logarithmic.split_z(1., 10_000., i, 4)).collect_vec();
+    for (split, expected) in splits.iter().zip([1., 10., 100., 1_000., 10_000.]) {
+        assert!((split - expected).abs() < expected * 1e-4, "{splits:?}");
+    }
+
+    let uniform = ShadowCascadeSplits::Blend(1.);
+    assert_eq!(uniform.split_z(1., 101., 1, 4), 26.);
+
+    let distances = ShadowCascadeSplits::Distances(vec![20., 80., 5_000.]);
+    let splits = (0..=4).map(|i| distances.split_z(1., 2_000., i, 4)).collect_vec();
+    assert_eq!(splits, vec![1., 20., 80., 2_000., 2_000.]);
+    assert_eq!(distances.split_z(1., 2_000., 2, 3), 80.);
+    assert_eq!(ShadowCascadeSplits::Distances(vec![]).split_z(1., 2_000., 1, 2), 2_000.);
+}
@@ -39,6 +39,60 @@ pub fn add_child(world: &mut World, id: EntityId, child_id: EntityId) -> Result<
     }
     Ok(())
 }
+/// Does nothing if `child_id` isn't a child of `id`
+pub fn remove_child(world: &mut World, id: EntityId, child_id: EntityId) -> Result<(), ECSError> {
+    if world.has_component(id, children()) {
+        world.get_mut(id, children())?.retain(|&c| c != child_id);
+    }
+    Ok(())
+}
+/// Moves `id` to the children of `new_parent`, or makes it a root, keeping the [parent] and [children] of both sides in
+/// sync. The transform of `id` is left as is; see [crate::transform::reparent] to keep its world transform instead.
+///
+/// Fails with [ECSError::HierarchyCycle] if `new_parent` is `id` or one of its descendants.
+pub fn set_parent(world: &mut World, id: EntityId, new_parent: Option<EntityId>) -> Result<(), ECSError> {
+    if !world.exists(id) {
+        return Err(ECSError::NoSuchEntity { entity_id: id });
+    }
+    if let Some(new_parent) = new_parent {
+        if is_self_or_ancestor(world, id, new_parent) {
+            return Err(ECSError::HierarchyCycle { entity_id: id, parent_id: new_parent });
+        }
+    }
+    if let Ok(old_parent) = world.get(id, parent()) {
+        if Some(old_parent) == new_parent {
+            return Ok(());
+        }
+        if world.exists(old_parent) {
+            remove_child(world, old_parent, id)?;
+        }
+    }
+    match new_parent {
+        Some(new_parent) => {
+            add_child(world, new_parent, id)?;
+            world.add_component(id, parent(), new_parent)?;
+        }
+        None => world.remove_component(id, parent())?,
+    }
+    Ok(())
+}
+
+/// Whether `ancestor` is `id` or one of its ancestors
+fn is_self_or_ancestor(world: &World, ancestor: EntityId, id: EntityId) -> bool {
+    let mut visited = HashSet::new();
+    let mut current = Some(id);
+    while let Some(id) = current {
+        if id == ancestor {
+            return true;
+        }
+        // Stops at a cycle which was made without going through [set_parent]
+        if !visited.insert(id) {
+            return false;
+        }
+        current = world.get(id, parent()).ok();
+    }
+    false
+}
 
 pub fn find_child<F: Fn(&World, EntityId) -> bool>(world: &World, entity: EntityId, query: &F) -> Option<EntityId> {
     if let Ok(children) = world.get_ref(entity, children()) {
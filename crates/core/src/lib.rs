@@ -6,7 +6,7 @@ use std::{
     sync::Arc,
     time::{Duration, SystemTime},
 };
-use window::WindowCtl;
+use window::{MonitorInfo, WindowCtl, WindowMode};
 
 use ambient_ecs::{
     components, query, Debuggable, Description, DynSystem, EntityId, FrameEvent, Name, Networked, QueryState, Resource, Store, System,
@@ -23,12 +23,16 @@ use ambient_std::{
     events::EventDispatcher,
     math::interpolate,
 };
+/// The job system, which the systems spread their heavy work over the threads with
+pub use ambient_sys::jobs;
 use glam::{uvec2, vec2, UVec2, Vec2};
 pub use paste;
 use serde::{Deserialize, Serialize};
 use winit::{event::Event, window::Window};
 pub mod bounding;
+pub mod budget;
 pub mod camera;
+pub mod scene;
 pub mod transform;
 
 components!("app", {
@@ -51,6 +55,19 @@ components!("app", {
     /// The physical size is the actual number of pixels on the screen
     @[Resource]
     window_physical_size: UVec2,
+    @[Resource, Debuggable]
+    window_focused: bool,
+    /// If the window can show a [window::CursorImage] as the hardware cursor, with [WindowCtl::SetCustomCursor]
+    @[Resource, Debuggable]
+    window_custom_cursors: bool,
+    @[Resource, Debuggable]
+    window_mode: WindowMode,
+    /// The monitors connected to the machine, which can be selected with [WindowCtl::SetMonitor]
+    @[Resource, Debuggable]
+    window_monitors: Vec<MonitorInfo>,
+    /// The index of the monitor the window is on in `window_monitors`, if it's known
+    @[Resource, Debuggable]
+    window_monitor: Option<usize>,
     /// Mouse position in screen space
     @[Resource]
     mouse_position: Vec2,
@@ -126,6 +143,7 @@ components!("app", {
 pub fn init_all_components() {
     init_components();
     hierarchy::init_components();
+    scene::init_components();
     async_ecs::init_components();
     gpu_ecs::init_components();
     camera::init_components();
@@ -133,6 +151,7 @@ pub fn init_all_components() {
     transform::init_gpu_components();
     bounding::init_components();
     bounding::init_gpu_components();
+    budget::init_components();
 }
 
 pub fn screen_to_clip_space(world: &World, screen_pos: Vec2) -> Vec2 {
@@ -268,6 +287,11 @@ pub fn mirror_window_components(src: &mut World, dst: &mut World) {
     dst.set_if_changed(dr, window_physical_size(), *src.resource(window_physical_size())).unwrap();
     dst.set_if_changed(dr, window_logical_size(), *src.resource(window_logical_size())).unwrap();
     dst.set_if_changed(dr, window_scale_factor(), *src.resource(window_scale_factor())).unwrap();
+    dst.set_if_changed(dr, window_focused(), *src.resource(window_focused())).unwrap();
+    dst.set_if_changed(dr, window_custom_cursors(), *src.resource(window_custom_cursors())).unwrap();
+    dst.set_if_changed(dr, window_mode(), *src.resource(window_mode())).unwrap();
+    dst.set_if_changed(dr, window_monitors(), src.resource(window_monitors()).clone()).unwrap();
+    dst.set_if_changed(dr, window_monitor(), *src.resource(window_monitor())).unwrap();
 
     dst.set_if_changed(dr, mouse_position(), *src.resource(mouse_position())).unwrap();
 }
@@ -0,0 +1,308 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use ambient_ecs::{
+    components, ComponentEntry, ComponentSet, Debuggable, Description, ECSError, EntityData, EntityId, Name, SerializationProfile, Store,
+    World,
+};
+use ambient_sys::time::Instant;
+use serde::{Deserialize, Serialize};
+
+use crate::hierarchy::{add_child, children, despawn_recursive, parent, remove_child};
+
+components!("scene", {
+    @[
+        Debuggable, Store,
+        Name["Prefab entity"],
+        Description["The id of the entity of the prefab scene this entity was instantiated from.\nIts components which still have the values of the prefab are updated when the prefab changes; the others are kept as overrides."]
+    ]
+    prefab_entity: EntityId,
+});
+
+/// A set of entities with some of their components, which can be written to (and read from) a human-readable JSON
+/// file, and spawned into a [World]. This makes it possible to author levels outside of code.
+///
+/// Only the components written for the [SerializationProfile::SAVE_GAME] profile are saved. Asset references
+/// are saved as the urls they are set to, so relative urls stay relative to whatever they were resolved against at
+/// runtime.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Scene {
+    pub entities: Vec<SceneEntity>,
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SceneEntity {
+    /// The id of the entity when it was saved. Entities are spawned with new ids, and the references between the entities
+    /// of the scene are updated to match
+    pub id: EntityId,
+    pub components: EntityData,
+}
+impl Scene {
+    /// Saves `roots` and all their descendants. If `components` is given, only those components are saved ([parent] and
+    /// [children] are always saved).
+    ///
+    /// The hierarchy is cut at the edges of the scene: the [parent] of the roots is not saved.
+    pub fn from_world(
+        world: &World,
+        roots: impl IntoIterator<Item = EntityId>,
+        components: Option<&ComponentSet>,
+    ) -> Result<Self, ECSError> {
+        Self::from_world_for_profile(world, roots, components, SerializationProfile::SAVE_GAME)
+    }
+    /// Like [Scene::from_world], but saves the components written for `profile`
+    pub fn from_world_for_profile(
+        world: &World,
+        roots: impl IntoIterator<Item = EntityId>,
+        components: Option<&ComponentSet>,
+        profile: SerializationProfile,
+    ) -> Result<Self, ECSError> {
+        let mut ids = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = roots.into_iter().collect::<Vec<_>>();
+        stack.reverse();
+        while let Some(id) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            if !world.exists(id) {
+                return Err(ECSError::NoSuchEntity { entity_id: id });
+            }
+            ids.push(id);
+            if let Ok(children) = world.get_ref(id, children()) {
+                stack.extend(children.iter().rev().copied());
+            }
+        }
+
+        let entities = ids
+            .into_iter()
+            .map(|id| {
+                let mut data = world.clone_entity(id)?.for_profile(profile);
+                if let Some(components) = components {
+                    data.filter(&|desc| components.contains(desc) || desc == parent().desc() || desc == children().desc());
+                }
+                if data.get(parent()).map(|parent| !visited.contains(&parent)).unwrap_or(false) {
+                    data.remove_self(parent());
+                }
+                if let Some(children) = data.get_mut(children()) {
+                    children.retain(|child| visited.contains(child));
+                }
+                Ok(SceneEntity { id, components: data })
+            })
+            .collect::<Result<Vec<_>, ECSError>>()?;
+        Ok(Self { entities })
+    }
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Spawns the entities of this scene into `world`, next to the entities already in it, and returns the new ids of
+    /// the roots of the scene (the entities without a [parent] in it).
+    ///
+    /// Every [EntityId] component which refers to an entity of the scene is updated to its new id; other ids are left
+    /// as they are.
+    pub fn spawn(&self, world: &mut World) -> Vec<EntityId> {
+        let ids = self.new_ids();
+        for entity in &self.entities {
+            spawn_entity(world, entity, &ids);
+        }
+        self.roots(&ids)
+    }
+    fn new_ids(&self) -> HashMap<EntityId, EntityId> {
+        self.entities.iter().map(|entity| (entity.id, EntityId::new())).collect()
+    }
+    fn roots(&self, ids: &HashMap<EntityId, EntityId>) -> Vec<EntityId> {
+        self.entities
+            .iter()
+            .filter(|entity| entity.components.get(parent()).map(|parent| !ids.contains_key(&parent)).unwrap_or(true))
+            .map(|entity| ids[&entity.id])
+            .collect()
+    }
+
+    /// Spawns the entities of this scene as an instance of a prefab under `instance`: the roots of the scene become
+    /// children of `instance`, and each entity is tagged with the [prefab_entity] it was instantiated from, so that the
+    /// instance can follow the changes of the prefab with [Scene::update_instance].
+    ///
+    /// Returns the new ids of the entities by their ids in the scene, to override some of their components.
+    pub fn instantiate(&self, world: &mut World, instance: EntityId) -> HashMap<EntityId, EntityId> {
+        let ids = self.entities.iter().map(|entity| (entity.id, EntityId::new())).collect::<HashMap<_, _>>();
+        spawn_instance_entities(world, instance, self.entities.iter(), &ids);
+        ids
+    }
+
+    /// Updates an `instance` of the `old` version of a prefab to this version.
+    ///
+    /// The components of the instance which still have the values they have in `old` are updated (or removed, if they
+    /// were removed from the prefab); the components which were changed, added or removed on the instance are
+    /// overrides, and are kept as they are. Entities added to the prefab are spawned, and entities removed from it are
+    /// despawned, but entities which were despawned from the instance stay despawned.
+    pub fn update_instance(&self, world: &mut World, instance: EntityId, old: &Scene) {
+        let mut ids = HashMap::new();
+        let mut stack = vec![instance];
+        while let Some(id) = stack.pop() {
+            if let Ok(prefab_id) = world.get(id, prefab_entity()) {
+                ids.insert(prefab_id, id);
+            }
+            if let Ok(children) = world.get_ref(id, children()) {
+                stack.extend(children.iter().copied());
+            }
+        }
+        let old_entities = old.entities.iter().map(|entity| (entity.id, &entity.components)).collect::<HashMap<_, _>>();
+        let new_ids = self.entities.iter().map(|entity| entity.id).collect::<HashSet<_>>();
+
+        for (prefab_id, id) in ids.clone() {
+            if old_entities.contains_key(&prefab_id) && !new_ids.contains(&prefab_id) {
+                if let Ok(parent_id) = world.get(id, parent()) {
+                    remove_child(world, parent_id, id).ok();
+                }
+                despawn_recursive(world, id);
+                ids.remove(&prefab_id);
+            }
+        }
+
+        // The entities added to the prefab, unless they're under an entity which was despawned from the instance
+        let mut spawned = Vec::new();
+        loop {
+            let count = spawned.len();
+            for entity in &self.entities {
+                if ids.contains_key(&entity.id) || old_entities.contains_key(&entity.id) {
+                    continue;
+                }
+                let scene_parent = entity.components.get(parent()).filter(|parent| new_ids.contains(parent));
+                if scene_parent.map(|parent| ids.contains_key(&parent)).unwrap_or(true) {
+                    ids.insert(entity.id, EntityId::new());
+                    spawned.push(entity);
+                }
+            }
+            if spawned.len() == count {
+                break;
+            }
+        }
+
+        for entity in &self.entities {
+            let id = match ids.get(&entity.id) {
+                Some(&id) if world.exists(id) => id,
+                _ => continue,
+            };
+            let mut new_data = entity.components.clone();
+            new_data.remap_entity_ids(&ids);
+            let mut old_data = old_entities.get(&entity.id).map(|&data| data.clone()).unwrap_or_default();
+            old_data.remap_entity_ids(&ids);
+            let is_updated = |desc| desc != parent().desc() && desc != children().desc();
+
+            let mut added = EntityData::new();
+            for desc in new_data.components().into_iter().filter(|&desc| is_updated(desc)) {
+                let value = new_data.get_entry(desc).unwrap().clone();
+                match (world.get_entry(id, desc).ok(), old_data.get_entry(desc)) {
+                    (Some(current), Some(old)) if same_value(&current, old) => {
+                        world.set_entry(id, value).unwrap();
+                    }
+                    (None, None) => added.set_entry(value),
+                    _ => {}
+                }
+            }
+            world.add_components(id, added).unwrap();
+
+            let removed = old_data
+                .components()
+                .into_iter()
+                .filter(|&desc| is_updated(desc) && new_data.get_entry(desc).is_none())
+                .filter(|&desc| {
+                    world.get_entry(id, desc).map(|current| same_value(&current, old_data.get_entry(desc).unwrap())).unwrap_or(false)
+                })
+                .collect();
+            world.remove_components(id, removed).unwrap();
+        }
+
+        spawn_instance_entities(world, instance, spawned.into_iter(), &ids);
+    }
+}
+
+/// Spawns a [Scene] a few entities at a time, to spread the cost of spawning a large scene over several frames instead of
+/// stalling one.
+///
+/// The entities are spawned in the order of the scene, with their final ids, so the references to the entities which
+/// are not spawned yet are already set.
+#[derive(Debug, Clone)]
+pub struct SceneSpawner {
+    scene: Arc<Scene>,
+    ids: HashMap<EntityId, EntityId>,
+    spawned: usize,
+}
+impl SceneSpawner {
+    pub fn new(scene: Arc<Scene>) -> Self {
+        let ids = scene.new_ids();
+        Self { scene, ids, spawned: 0 }
+    }
+    /// Spawns entities into `world` for up to `budget` (but at least one, so that it always progresses). Returns whether
+    /// the whole scene has been spawned.
+    pub fn spawn(&mut self, world: &mut World, budget: Duration) -> bool {
+        let start = Instant::now();
+        while let Some(entity) = self.scene.entities.get(self.spawned) {
+            spawn_entity(world, entity, &self.ids);
+            self.spawned += 1;
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+        self.is_done()
+    }
+    pub fn is_done(&self) -> bool {
+        self.spawned == self.scene.entities.len()
+    }
+    /// How much of the scene has been spawned, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        if self.scene.entities.is_empty() {
+            1.
+        } else {
+            self.spawned as f32 / self.scene.entities.len() as f32
+        }
+    }
+    /// The new ids of the entities which have been spawned so far
+    pub fn spawned(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.scene.entities[..self.spawned].iter().map(|entity| self.ids[&entity.id])
+    }
+    /// The new ids of the roots of the scene, as returned by [Scene::spawn]
+    pub fn roots(&self) -> Vec<EntityId> {
+        self.scene.roots(&self.ids)
+    }
+}
+
+fn spawn_entity(world: &mut World, entity: &SceneEntity, ids: &HashMap<EntityId, EntityId>) {
+    let mut data = entity.components.clone();
+    data.remap_entity_ids(ids);
+    world.spawn_with_id(ids[&entity.id], data);
+}
+
+/// Spawns `entities` with the new `ids` as part of the `instance`. The entities which are not under another entity of the
+/// instance are added to the children of `instance`.
+fn spawn_instance_entities<'a>(
+    world: &mut World,
+    instance: EntityId,
+    entities: impl Iterator<Item = &'a SceneEntity>,
+    ids: &HashMap<EntityId, EntityId>,
+) {
+    let entities = entities.collect::<Vec<_>>();
+    let spawned = entities.iter().map(|entity| ids[&entity.id]).collect::<HashSet<_>>();
+    for entity in entities {
+        let id = ids[&entity.id];
+        let parent_id = entity.components.get(parent()).and_then(|parent| ids.get(&parent)).copied().unwrap_or(instance);
+        let mut data = entity.components.clone();
+        data.remap_entity_ids(ids);
+        if let Some(children) = data.get_mut(children()) {
+            children.retain(|child| spawned.contains(child));
+        }
+        world.spawn_with_id(id, data.set(parent(), parent_id).set(prefab_entity(), entity.id));
+        if !spawned.contains(&parent_id) {
+            add_child(world, parent_id, id).unwrap();
+        }
+    }
+}
+
+fn same_value(a: &ComponentEntry, b: &ComponentEntry) -> bool {
+    a.desc().to_json(a).ok() == b.desc().to_json(b).ok()
+}
@@ -1,17 +1,19 @@
 use std::collections::HashSet;
 
 use ambient_ecs::{
-    components, ensure_has_component, query, query_mut, Debuggable, Description, ECSError, EntityId, FrameEvent, MakeDefault,
-    MaybeResource, Name, Networked, QueryState, Store, System, SystemGroup, World,
+    components, ensure_has_component, query, query_mut, Debuggable, Description, ECSError, EntityData, EntityId, FnSystem, FrameEvent,
+    MakeDefault, MaybeResource, Name, Networked, Quantized, QueryState, ReplicationPriority, ReplicationRate, Resource, Store, System,
+    SystemGroup, World,
 };
 use glam::*;
+use itertools::Itertools;
 
 use crate::{
     camera::get_active_camera,
     gpu_components,
     gpu_ecs::{ComponentToGpuSystem, GpuComponentFormat, GpuWorldSyncEvent},
-    hierarchy::{children, parent},
-    main_scene,
+    hierarchy::{children, parent, set_parent},
+    jobs, main_scene,
 };
 
 fn vec3_one() -> Vec3 {
@@ -20,19 +22,19 @@ fn vec3_one() -> Vec3 {
 
 components!("transform", {
     @[
-        MakeDefault, Debuggable, Networked, Store,
+        MakeDefault, Debuggable, Networked, Store, Quantized[0.001], ReplicationRate[30.], ReplicationPriority[10],
         Name["Translation"],
         Description["The translation/position of this entity."]
     ]
     translation: Vec3,
     @[
-        MakeDefault[vec3_one], Debuggable, Networked, Store,
+        MakeDefault[vec3_one], Debuggable, Networked, Store, Quantized[0.001], ReplicationRate[30.], ReplicationPriority[10],
         Name["Scale"],
         Description["The scale of this entity."]
     ]
     scale: Vec3,
     @[
-        Debuggable, Networked, Store,
+        Debuggable, Networked, Store, Quantized[0.0001], ReplicationRate[30.], ReplicationPriority[10],
         Name["Rotation"],
         Description["The rotation of this entity."]
     ]
@@ -100,6 +102,25 @@ components!("transform", {
     ]
     cylindrical_billboard_z: (),
 
+    @[
+        Debuggable, Networked, Store,
+        Name["World position"],
+        Description["The position of this entity in double precision, for the worlds which span more than a few kilometers.\nIf attached, `translation` is kept at this position relative to the `world_origin` of the world it's in, so set this instead of `translation`; a `translation` set otherwise (e.g. by the server, relative to its own origin) is overwritten. Only for the entities without a parent."]
+    ]
+    world_position: DVec3,
+    @[
+        Debuggable, Resource,
+        Name["World origin"],
+        Description["The position in the world which the `translation` of the entities with a `world_position` is relative to.\nIt follows the `floating_origin` entity, so that the transforms sent to the GPU stay precise around it."]
+    ]
+    world_origin: DVec3,
+    @[
+        Debuggable, Store,
+        Name["Floating origin"],
+        Description["If attached to an entity with a `world_position` (usually the camera), the `world_origin` is moved to it when it gets further than `REBASE_DISTANCE` from it.\nIt's not networked, as each world follows its own entity (e.g. the camera of the client). If several entities have it, the one with the lowest id is followed."]
+    ]
+    floating_origin: (),
+
     @[
         Debuggable, Networked, Store,
         Name["Reset scale"],
@@ -128,6 +149,19 @@ gpu_components! {
     mesh_to_world() => mesh_to_world: GpuComponentFormat::Mat4,
 }
 
+/// How far the `floating_origin` entity may get from the `world_origin`, in meters, before the origin is moved to it
+pub const REBASE_DISTANCE: f64 = 1024.;
+
+/// The position in the world of a `translation` of an entity with a `world_position`
+pub fn to_world_position(world: &World, translation: Vec3) -> DVec3 {
+    world.resource_opt(world_origin()).copied().unwrap_or_default() + translation.as_dvec3()
+}
+
+/// The `translation` of a position in the world, relative to the `world_origin`
+pub fn from_world_position(world: &World, position: DVec3) -> Vec3 {
+    (position - world.resource_opt(world_origin()).copied().unwrap_or_default()).as_vec3()
+}
+
 #[derive(Debug)]
 pub struct TransformSystem {
     systems: SystemGroup,
@@ -141,6 +175,37 @@ impl TransformSystem {
             systems: SystemGroup::new(
                 "transform_systems",
                 vec![
+                    // Large worlds
+                    Box::new(FnSystem::new(|world, _| {
+                        let origin = world.resource_opt(world_origin()).copied().unwrap_or_default();
+                        let target = query(world_position())
+                            .incl(floating_origin())
+                            .iter(world, None)
+                            .min_by_key(|&(id, _)| id)
+                            .map(|(_, &position)| position);
+                        let target = match target {
+                            Some(target) if target.distance(origin) > REBASE_DISTANCE => target.round(),
+                            _ => return,
+                        };
+                        world.add_resource(world_origin(), target);
+                        for (id, position) in query(world_position()).collect_cloned(world, None) {
+                            world.set(id, translation(), (position - target).as_vec3()).ok();
+                        }
+                    })),
+                    // The translation is also checked when it changes, as it's networked, and the one of the server is relative
+                    // to the origin of the server. Only the wrong ones are set, so that it settles.
+                    query((world_position().changed(), translation().changed())).to_system(|q, world, qs, _| {
+                        let origin = world.resource_opt(world_origin()).copied().unwrap_or_default();
+                        let wrong = q
+                            .iter(world, qs)
+                            .map(|(id, (&position, &translation))| (id, (position - origin).as_vec3(), translation))
+                            .filter(|(_, expected, translation)| expected != translation)
+                            .map(|(id, expected, _)| (id, expected))
+                            .collect_vec();
+                        for (id, expected) in wrong {
+                            world.set(id, translation(), expected).ok();
+                        }
+                    }),
                     query_mut((rotation(),), (euler_rotation().changed(),)).to_system(|query, world, state, _| {
                         for (_, (rot,), (&r,)) in query.iter(world, state) {
                             *rot = Quat::from_euler(EulerRot::ZYX, r.z, r.y, r.x);
@@ -149,9 +214,10 @@ impl TransformSystem {
                     query_mut((local_to_parent(),), (translation().changed(), rotation().changed(), scale().changed()))
                         .excl(fbx_complex_transform())
                         .to_system(|query, world, state, _| {
-                            for (_, (local_to_parent,), (&translation, &rotation, &scale)) in query.iter(world, state) {
-                                *local_to_parent = Mat4::from_scale_rotation_translation(scale, rotation, translation);
-                            }
+                            let mut transforms = query.iter(world, state).collect_vec();
+                            jobs::par_for_each_mut(&mut transforms, |(_, (local_to_parent,), (translation, rotation, scale))| {
+                                **local_to_parent = Mat4::from_scale_rotation_translation(**scale, **rotation, **translation);
+                            });
                         }),
                     query_mut((local_to_parent(),), (translation().changed(), scale().changed()))
                         .excl(rotation())
@@ -209,9 +275,10 @@ impl TransformSystem {
                         .excl(lookat_center())
                         .excl(fbx_complex_transform())
                         .to_system(|query, world, state, _| {
-                            for (_, (local_to_world,), (&translation, &rotation, &scale)) in query.iter(world, state) {
-                                *local_to_world = Mat4::from_scale_rotation_translation(scale, rotation, translation);
-                            }
+                            let mut transforms = query.iter(world, state).collect_vec();
+                            jobs::par_for_each_mut(&mut transforms, |(_, (local_to_world,), (translation, rotation, scale))| {
+                                **local_to_world = Mat4::from_scale_rotation_translation(**scale, **rotation, **translation);
+                            });
                         }),
                     query_mut((local_to_world(),), (translation().changed(), rotation().changed()))
                         .excl(local_to_parent())
@@ -338,9 +405,10 @@ impl TransformSystem {
                 "transform_systems",
                 vec![
                     query_mut((mesh_to_world(),), (local_to_world().changed(), mesh_to_local().changed())).to_system(|q, world, qs, _| {
-                        for (_, (mesh_to_world,), (&local_to_world, &mesh_to_local)) in q.iter(world, qs) {
-                            *mesh_to_world = local_to_world * mesh_to_local;
-                        }
+                        let mut transforms = q.iter(world, qs).collect_vec();
+                        jobs::par_for_each_mut(&mut transforms, |(_, (mesh_to_world,), (local_to_world, mesh_to_local))| {
+                            **mesh_to_world = **local_to_world * **mesh_to_local;
+                        });
                     }),
                     query_mut((mesh_to_world(),), (local_to_world().changed(),)).excl(mesh_to_local()).to_system(|q, world, qs, _| {
                         for (_, (mesh_to_world,), (&local_to_world,)) in q.iter(world, qs) {
@@ -348,9 +416,10 @@ impl TransformSystem {
                         }
                     }),
                     query_mut((inv_local_to_world(),), (local_to_world().changed(),)).excl(lookat_center()).to_system(|q, world, qs, _| {
-                        for (_, (inv_local_to_world,), (local_to_world,)) in q.iter(world, qs) {
-                            *inv_local_to_world = local_to_world.inverse();
-                        }
+                        let mut transforms = q.iter(world, qs).collect_vec();
+                        jobs::par_for_each_mut(&mut transforms, |(_, (inv_local_to_world,), (local_to_world,))| {
+                            **inv_local_to_world = local_to_world.inverse();
+                        });
                     }),
                 ],
             ),
@@ -372,25 +441,18 @@ impl TransformSystem {
             changed_roots.insert(id);
         }
 
-        for id in changed_roots.into_iter() {
-            if let Ok(transform) = world.get(id, local_to_parent()) {
-                if world.set(id, local_to_world(), transform).is_err() {
-                    return;
-                }
-                if let Ok(children) = world.get_ref(id, children()).cloned() {
-                    for child in children {
-                        update_transform_recursive(world, child, transform);
-                    }
-                }
-            } else if let Ok(transform) = world.get(id, local_to_world()) {
-                if let Ok(children) = world.get_ref(id, children()).cloned() {
-                    for child in children {
-                        update_transform_recursive(world, child, transform);
-                    }
-                }
-            } else {
-                log::warn!("Bad transform hierarchy; bad root: {}", id);
+        // The hierarchies are walked in parallel, and their transforms are set once they're all computed
+        let roots = changed_roots.into_iter().collect_vec();
+        let updates = jobs::par_chunks(&roots, |roots| {
+            let mut updates = Vec::new();
+            let mut stack = Vec::new();
+            for &root in roots {
+                propagate_transforms(world, root, &mut stack, &mut updates);
             }
+            updates
+        });
+        for (id, transform) in updates.into_iter().flatten() {
+            world.set(id, local_to_world(), transform).ok();
         }
     }
 }
@@ -425,23 +487,43 @@ pub fn transform_gpu_systems() -> SystemGroup<GpuWorldSyncEvent> {
         vec![Box::new(ComponentToGpuSystem::new(GpuComponentFormat::Mat4, mesh_to_world(), gpu_components::mesh_to_world()))],
     )
 }
-fn update_transform_recursive(world: &mut World, id: EntityId, mut parent_transform: Mat4) {
-    if world.has_component(id, reset_scale()) {
-        let (_s, r, t) = parent_transform.to_scale_rotation_translation();
-        parent_transform = Mat4::from_rotation_translation(r, t);
-    }
-    let transform = if let Ok(local_to_parent) = world.get(id, local_to_parent()) {
-        parent_transform * local_to_parent
+/// Computes the transforms of the hierarchy under `root`, walking it depth first with an explicit stack so that the children
+/// lists don't need to be cloned
+fn propagate_transforms(world: &World, root: EntityId, stack: &mut Vec<(EntityId, Mat4)>, updates: &mut Vec<(EntityId, Mat4)>) {
+    let transform = if let Ok(transform) = world.get(root, local_to_parent()) {
+        if !world.has_component(root, local_to_world()) {
+            return;
+        }
+        updates.push((root, transform));
+        transform
+    } else if let Ok(transform) = world.get(root, local_to_world()) {
+        transform
     } else {
+        log::warn!("Bad transform hierarchy; bad root: {}", root);
         return;
     };
-    if world.set(id, local_to_world(), transform).is_err() {
-        return;
-    }
-    if let Ok(children) = world.get_ref(id, children()).cloned() {
-        for child in children {
-            update_transform_recursive(world, child, transform);
+    push_children(world, root, transform, stack);
+    while let Some((id, mut parent_transform)) = stack.pop() {
+        if world.has_component(id, reset_scale()) {
+            let (_s, r, t) = parent_transform.to_scale_rotation_translation();
+            parent_transform = Mat4::from_rotation_translation(r, t);
         }
+        let transform = if let Ok(local_to_parent) = world.get(id, local_to_parent()) {
+            parent_transform * local_to_parent
+        } else {
+            continue;
+        };
+        if !world.has_component(id, local_to_world()) {
+            continue;
+        }
+        updates.push((id, transform));
+        push_children(world, id, transform, stack);
+    }
+}
+fn push_children(world: &World, id: EntityId, transform: Mat4, stack: &mut Vec<(EntityId, Mat4)>) {
+    if let Ok(children) = world.get_ref(id, children()) {
+        // Reversed so that the children are updated in order
+        stack.extend(children.iter().rev().map(|&child| (child, transform)));
     }
 }
 fn get_fbx_transform(world: &World, id: EntityId) -> Mat4 {
@@ -505,10 +587,47 @@ pub fn get_world_transform(world: &World, entity: EntityId) -> Result<Mat4, ECSE
             )),
             ECSError::NoSuchEntity { .. } => Err(err),
             ECSError::AddedResourceToEntity { .. } => Err(err),
+            ECSError::HierarchyCycle { .. } => Err(err),
         },
     }
 }
 
+/// Moves `id` to the children of `new_parent`, or makes it a root, without moving it in the world.
+///
+/// Its local transform (the [translation], [rotation] and [scale], and [local_to_parent] when it has a parent) is
+/// recomputed from its current world transform, so this should be called after the transforms of both entities are up
+/// to date. Any shear of the new local transform is lost, as it's stored as a translation, rotation and scale.
+pub fn reparent(world: &mut World, id: EntityId, new_parent: Option<EntityId>) -> Result<(), ECSError> {
+    let world_transform = get_world_transform(world, id)?;
+    let local_transform = match new_parent {
+        Some(new_parent) => {
+            let parent_transform = get_world_transform(world, new_parent)?;
+            // The propagation only goes through parents with a world transform
+            if !world.has_component(new_parent, local_to_world()) {
+                world.add_component(new_parent, local_to_world(), parent_transform)?;
+            }
+            parent_transform.inverse() * world_transform
+        }
+        None => world_transform,
+    };
+    set_parent(world, id, new_parent)?;
+
+    let (s, r, t) = local_transform.to_scale_rotation_translation();
+    let mut data = EntityData::new().set(translation(), t).set(rotation(), r).set(scale(), s).set(local_to_world(), world_transform);
+    if world.has_component(id, euler_rotation()) {
+        let (z, y, x) = r.to_euler(EulerRot::ZYX);
+        data.set_self(euler_rotation(), vec3(x, y, z));
+    }
+    if new_parent.is_some() {
+        data.set_self(local_to_parent(), local_transform);
+    }
+    world.add_components(id, data)?;
+    if new_parent.is_none() {
+        world.remove_component(id, local_to_parent())?;
+    }
+    Ok(())
+}
+
 pub fn get_world_position(world: &World, entity: EntityId) -> Result<Vec3, ECSError> {
     match world.get(entity, local_to_world()) {
         Ok(ltw) => Ok(ltw.transform_point3(Vec3::ZERO)),
@@ -516,6 +635,7 @@ pub fn get_world_position(world: &World, entity: EntityId) -> Result<Vec3, ECSEr
             ECSError::EntityDoesntHaveComponent { .. } => world.get(entity, translation()),
             ECSError::NoSuchEntity { .. } => Err(err),
             ECSError::AddedResourceToEntity { .. } => Err(err),
+            ECSError::HierarchyCycle { .. } => Err(err),
         },
     }
 }
@@ -530,6 +650,7 @@ pub fn get_world_rotation(world: &World, entity: EntityId) -> Result<Quat, ECSEr
             ECSError::EntityDoesntHaveComponent { .. } => world.get(entity, rotation()),
             ECSError::NoSuchEntity { .. } => Err(err),
             ECSError::AddedResourceToEntity { .. } => Err(err),
+            ECSError::HierarchyCycle { .. } => Err(err),
         },
     }
 }
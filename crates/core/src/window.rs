@@ -1,4 +1,8 @@
-use winit::window::{CursorGrabMode, CursorIcon};
+use std::sync::Arc;
+
+use glam::{IVec2, UVec2};
+use serde::{Deserialize, Serialize};
+use winit::window::{CursorGrabMode, CursorIcon, Icon};
 
 /// Allows controlling the window
 #[derive(Debug, Clone)]
@@ -6,4 +10,62 @@ pub enum WindowCtl {
     GrabCursor(CursorGrabMode),
     SetCursorIcon(CursorIcon),
     ShowCursor(bool),
+    /// Shows this image as the hardware cursor instead of the cursor icon, or the cursor icon again. Does nothing if
+    /// `window_custom_cursors` is false.
+    SetCustomCursor(Option<Arc<CursorImage>>),
+    /// Switches between windowed and fullscreen, on the monitor selected with [WindowCtl::SetMonitor]
+    SetMode(WindowMode),
+    /// Moves the window to the monitor with this index in `window_monitors`
+    SetMonitor(usize),
+    /// Sets the size of the window in physical pixels. In exclusive fullscreen, this is the resolution of the monitor,
+    /// which has to be one of the `video_modes` of the monitor.
+    SetResolution(UVec2),
+    /// Sets the minimum size of the window in physical pixels, if any
+    SetMinSize(Option<UVec2>),
+    SetTitle(String),
+    SetIcon(Option<Icon>),
+}
+
+/// An image to show as the cursor, in physical pixels
+#[derive(Clone, PartialEq, Eq)]
+pub struct CursorImage {
+    /// The RGBA pixels, row by row from the top
+    pub rgba: Vec<u8>,
+    pub size: UVec2,
+    /// The pixel which is at the position of the mouse
+    pub hotspot: UVec2,
+}
+impl std::fmt::Debug for CursorImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CursorImage").field("size", &self.size).field("hotspot", &self.hotspot).finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    /// A window covering the whole monitor, at its current resolution
+    BorderlessFullscreen,
+    /// Takes over the monitor, which can change its resolution
+    ExclusiveFullscreen,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonitorInfo {
+    pub name: String,
+    /// The position of the monitor on the desktop, in physical pixels
+    pub position: IVec2,
+    /// The current resolution of the monitor
+    pub size: UVec2,
+    pub scale_factor: f64,
+    /// The resolutions and refresh rates this monitor supports in exclusive fullscreen
+    pub video_modes: Vec<VideoModeInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VideoModeInfo {
+    pub size: UVec2,
+    pub refresh_rate_millihertz: u32,
+    pub bit_depth: u16,
 }
@@ -0,0 +1,116 @@
+use ambient_core::{
+    hierarchy::{children, parent, set_parent},
+    transform::{local_to_parent, local_to_world, reparent, rotation, scale, translation, TransformSystem},
+};
+use ambient_ecs::{ECSError, EntityData, FrameEvent, System, World};
+use glam::{vec3, Mat4, Quat};
+
+fn transform(translation_: glam::Vec3, rotation_: Quat, scale_: glam::Vec3) -> EntityData {
+    EntityData::new().set(translation(), translation_).set(rotation(), rotation_).set(scale(), scale_).set(local_to_world(), Mat4::IDENTITY)
+}
+
+#[test]
+fn reparent_keeps_world_transform() {
+    ambient_core::init_all_components();
+    let mut world = World::new("reparent_keeps_world_transform");
+    let mut transforms = TransformSystem::new();
+
+    let a = world.spawn(transform(vec3(1., 2., 3.), Quat::from_rotation_z(0.5), vec3(2., 2., 2.)));
+    let b = world.spawn(transform(vec3(-4., 0., 1.), Quat::from_rotation_x(1.), vec3(0.5, 0.5, 0.5)));
+    let child = world.spawn(transform(vec3(0., 1., 0.), Quat::from_rotation_y(0.3), vec3(1., 1., 1.)));
+    transforms.run(&mut world, &FrameEvent);
+    let child_world = world.get(child, local_to_world()).unwrap();
+
+    reparent(&mut world, child, Some(a)).unwrap();
+    transforms.run(&mut world, &FrameEvent);
+    assert_eq!(world.get(child, parent()).unwrap(), a);
+    assert_eq!(world.get_ref(a, children()).unwrap(), &vec![child]);
+    assert!(world.get(child, local_to_world()).unwrap().abs_diff_eq(child_world, 1e-4));
+
+    // Moving the parent moves the child
+    world.set(a, translation(), vec3(1., 2., 4.)).unwrap();
+    transforms.run(&mut world, &FrameEvent);
+    assert!(world.get(child, local_to_world()).unwrap().abs_diff_eq(Mat4::from_translation(vec3(0., 0., 1.)) * child_world, 1e-4));
+    let child_world = world.get(child, local_to_world()).unwrap();
+
+    reparent(&mut world, child, Some(b)).unwrap();
+    transforms.run(&mut world, &FrameEvent);
+    assert_eq!(world.get(child, parent()).unwrap(), b);
+    assert!(world.get_ref(a, children()).unwrap().is_empty());
+    assert!(world.get(child, local_to_world()).unwrap().abs_diff_eq(child_world, 1e-4));
+
+    reparent(&mut world, child, None).unwrap();
+    transforms.run(&mut world, &FrameEvent);
+    assert!(!world.has_component(child, parent()));
+    assert!(!world.has_component(child, local_to_parent()));
+    assert!(world.get_ref(b, children()).unwrap().is_empty());
+    assert!(world.get(child, local_to_world()).unwrap().abs_diff_eq(child_world, 1e-4));
+}
+
+#[test]
+fn propagates_through_deep_hierarchies() {
+    ambient_core::init_all_components();
+    let mut world = World::new("propagates_through_deep_hierarchies");
+    let mut transforms = TransformSystem::new();
+
+    let root = world.spawn(transform(vec3(1., 0., 0.), Quat::IDENTITY, vec3(1., 1., 1.)));
+    let mut leaf = root;
+    for _ in 0..1000 {
+        let child = world.spawn(transform(vec3(1., 0., 0.), Quat::IDENTITY, vec3(1., 1., 1.)).set(local_to_parent(), Mat4::IDENTITY));
+        set_parent(&mut world, child, Some(leaf)).unwrap();
+        leaf = child;
+    }
+    transforms.run(&mut world, &FrameEvent);
+    assert!(world.get(leaf, local_to_world()).unwrap().abs_diff_eq(Mat4::from_translation(vec3(1001., 0., 0.)), 1e-3));
+}
+
+#[test]
+fn propagates_many_hierarchies_in_parallel() {
+    ambient_core::init_all_components();
+    let mut world = World::new("propagates_many_hierarchies_in_parallel");
+    let mut transforms = TransformSystem::new();
+
+    let children = (0..1000)
+        .map(|i| {
+            let root = world.spawn(transform(vec3(i as f32, 0., 0.), Quat::IDENTITY, vec3(2., 2., 2.)));
+            let child = world.spawn(transform(vec3(0., 1., 0.), Quat::IDENTITY, vec3(1., 1., 1.)).set(local_to_parent(), Mat4::IDENTITY));
+            set_parent(&mut world, child, Some(root)).unwrap();
+            (i, child)
+        })
+        .collect::<Vec<_>>();
+    transforms.run(&mut world, &FrameEvent);
+    for (i, child) in children {
+        let expected = Mat4::from_scale_rotation_translation(vec3(2., 2., 2.), Quat::IDENTITY, vec3(i as f32, 2., 0.));
+        assert!(world.get(child, local_to_world()).unwrap().abs_diff_eq(expected, 1e-3));
+    }
+}
+
+#[test]
+fn set_parent_rejects_cycles() {
+    ambient_core::init_all_components();
+    let mut world = World::new("set_parent_rejects_cycles");
+
+    let root = world.spawn(EntityData::new());
+    let child = world.spawn(EntityData::new());
+    let grandchild = world.spawn(EntityData::new());
+    set_parent(&mut world, child, Some(root)).unwrap();
+    set_parent(&mut world, grandchild, Some(child)).unwrap();
+
+    for (id, new_parent) in [(root, grandchild), (root, child), (child, child)] {
+        assert!(matches!(
+            set_parent(&mut world, id, Some(new_parent)),
+            Err(ECSError::HierarchyCycle { entity_id, parent_id }) if entity_id == id && parent_id == new_parent
+        ));
+    }
+    // The hierarchy is left as is
+    assert!(!world.has_component(root, parent()));
+    assert_eq!(world.get(child, parent()).unwrap(), root);
+    assert_eq!(world.get_ref(child, children()).unwrap(), &vec![grandchild]);
+    assert!(matches!(reparent(&mut world, root, Some(grandchild)), Err(ECSError::HierarchyCycle { .. })));
+
+    // Moving a subtree under another branch is fine
+    let other = world.spawn(EntityData::new());
+    set_parent(&mut world, child, Some(other)).unwrap();
+    set_parent(&mut world, root, Some(grandchild)).unwrap();
+    assert_eq!(world.get(root, parent()).unwrap(), grandchild);
+}
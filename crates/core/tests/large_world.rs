@@ -0,0 +1,86 @@
+use ambient_core::transform::{
+    floating_origin, from_world_position, local_to_world, to_world_position, translation, world_origin, world_position, TransformSystem,
+    REBASE_DISTANCE,
+};
+use ambient_ecs::{EntityData, FrameEvent, System, World};
+use glam::{dvec3, vec3, Mat4};
+
+#[test]
+fn floating_origin_follows_the_camera() {
+    ambient_core::init_all_components();
+    let mut world = World::new("floating_origin_follows_the_camera");
+    let mut transforms = TransformSystem::new();
+
+    let far = dvec3(5_000_000., 12.25, -3.5);
+    let camera = world.spawn(
+        EntityData::new()
+            .set(world_position(), far)
+            .set_default(translation())
+            .set(local_to_world(), Mat4::IDENTITY)
+            .set(floating_origin(), ()),
+    );
+    let rock = world.spawn(
+        EntityData::new()
+            .set(world_position(), far + dvec3(0.125, 0., 1.))
+            .set_default(translation())
+            .set(local_to_world(), Mat4::IDENTITY),
+    );
+    transforms.run(&mut world, &FrameEvent);
+    assert_eq!(*world.resource(world_origin()), dvec3(5_000_000., 12., -4.));
+    assert_eq!(world.get(camera, translation()).unwrap(), vec3(0., 0.25, 0.5));
+    // Precise enough to tell the camera and the rock apart, unlike an f32 position 5000km away
+    assert_eq!(world.get(rock, translation()).unwrap(), vec3(0.125, 0.25, 1.5));
+    assert_eq!(world.get(rock, local_to_world()).unwrap(), Mat4::from_translation(vec3(0.125, 0.25, 1.5)));
+    assert_eq!(to_world_position(&world, vec3(0.125, 0.25, 1.5)), far + dvec3(0.125, 0., 1.));
+
+    // The origin stays until the camera gets far enough from it
+    world.set(camera, world_position(), far + dvec3(REBASE_DISTANCE - 1., 0., 0.)).unwrap();
+    transforms.run(&mut world, &FrameEvent);
+    assert_eq!(*world.resource(world_origin()), dvec3(5_000_000., 12., -4.));
+    assert_eq!(world.get(camera, translation()).unwrap(), vec3(REBASE_DISTANCE as f32 - 1., 0.25, 0.5));
+
+    world.set(camera, world_position(), far + dvec3(2. * REBASE_DISTANCE, 0., 0.)).unwrap();
+    transforms.run(&mut world, &FrameEvent);
+    assert_eq!(*world.resource(world_origin()), dvec3(5_000_000. + 2. * REBASE_DISTANCE, 12., -4.));
+    assert_eq!(world.get(rock, translation()).unwrap(), vec3(0.125 - 2. * REBASE_DISTANCE as f32, 0.25, 1.5));
+    assert_eq!(from_world_position(&world, far), vec3(-2. * REBASE_DISTANCE as f32, 0.25, 0.5));
+}
+
+#[test]
+fn translations_are_relative_to_the_local_origin() {
+    ambient_core::init_all_components();
+    let mut world = World::new("translations_are_relative_to_the_local_origin");
+    let mut transforms = TransformSystem::new();
+    world.add_resource(world_origin(), dvec3(1000., 0., 0.));
+
+    let rock = world.spawn(EntityData::new().set(world_position(), dvec3(1010., 0., 0.)).set_default(translation()));
+    transforms.run(&mut world, &FrameEvent);
+    assert_eq!(world.get(rock, translation()).unwrap(), vec3(10., 0., 0.));
+
+    // E.g. the translation of the server, relative to its origin at 0
+    world.set(rock, translation(), vec3(1010., 0., 0.)).unwrap();
+    transforms.run(&mut world, &FrameEvent);
+    assert_eq!(world.get(rock, translation()).unwrap(), vec3(10., 0., 0.));
+    transforms.run(&mut world, &FrameEvent);
+    assert_eq!(world.get(rock, translation()).unwrap(), vec3(10., 0., 0.));
+}
+
+#[test]
+fn the_first_floating_origin_is_followed() {
+    ambient_core::init_all_components();
+    let mut world = World::new("the_first_floating_origin_is_followed");
+    let mut transforms = TransformSystem::new();
+
+    let first = world.spawn(EntityData::new().set(world_position(), dvec3(5000., 0., 0.)).set_default(translation()));
+    let second = world.spawn(EntityData::new().set(world_position(), dvec3(-5000., 0., 0.)).set_default(translation()));
+    let (first, second) = if first < second { (first, second) } else { (second, first) };
+    // Added in the reverse order, so that the order of the query doesn't match the order of the ids
+    world.add_component(second, floating_origin(), ()).unwrap();
+    world.add_component(first, floating_origin(), ()).unwrap();
+
+    transforms.run(&mut world, &FrameEvent);
+    let expected = *world.get_ref(first, world_position()).unwrap();
+    assert_eq!(*world.resource(world_origin()), expected);
+    transforms.run(&mut world, &FrameEvent);
+    assert_eq!(*world.resource(world_origin()), expected);
+}
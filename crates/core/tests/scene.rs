@@ -0,0 +1,147 @@
+use std::{sync::Arc, time::Duration};
+
+use ambient_core::{
+    hierarchy::{children, parent, set_parent},
+    name,
+    scene::{prefab_entity, Scene, SceneSpawner},
+    transform::{rotation, translation},
+};
+use ambient_ecs::{ComponentSet, EntityData, World};
+use glam::{vec3, Quat};
+
+#[test]
+fn scene_round_trip() {
+    ambient_core::init_all_components();
+    let mut world = World::new("scene_round_trip");
+    let outside = world.spawn(EntityData::new().set(name(), "outside".to_string()));
+    let root = world.spawn(EntityData::new().set(name(), "root".to_string()).set(translation(), vec3(1., 2., 3.)));
+    let child = world.spawn(EntityData::new().set(name(), "child".to_string()));
+    set_parent(&mut world, root, Some(outside)).unwrap();
+    set_parent(&mut world, child, Some(root)).unwrap();
+
+    let json = Scene::from_world(&world, [root], None).unwrap().to_json().unwrap();
+    let scene = Scene::from_json(&json).unwrap();
+    assert_eq!(scene.entities.len(), 2);
+
+    let mut loaded = World::new("scene_round_trip_loaded");
+    let roots = scene.spawn(&mut loaded);
+    assert_eq!(roots.len(), 1);
+    let new_root = roots[0];
+    assert_ne!(new_root, root);
+    assert_eq!(loaded.get_ref(new_root, name()).unwrap(), "root");
+    assert_eq!(loaded.get(new_root, translation()).unwrap(), vec3(1., 2., 3.));
+    // The parent outside of the scene isn't saved
+    assert!(!loaded.has_component(new_root, parent()));
+    let new_children = loaded.get_cloned(new_root, children()).unwrap();
+    assert_eq!(new_children.len(), 1);
+    assert_eq!(loaded.get_ref(new_children[0], name()).unwrap(), "child");
+    assert_eq!(loaded.get(new_children[0], parent()).unwrap(), new_root);
+}
+
+#[test]
+fn scene_spawns_additively() {
+    ambient_core::init_all_components();
+    let mut world = World::new("scene_spawns_additively");
+    let root = world.spawn(EntityData::new().set(name(), "root".to_string()).set(translation(), vec3(1., 0., 0.)));
+    let child = world.spawn(EntityData::new().set(name(), "child".to_string()));
+    set_parent(&mut world, child, Some(root)).unwrap();
+
+    let mut components = ComponentSet::new();
+    components.insert(name().desc());
+    let scene = Scene::from_world(&world, [root], Some(&components)).unwrap();
+    assert!(!scene.entities[0].components.contains(translation()));
+
+    let before = world.len();
+    let first = scene.spawn(&mut world)[0];
+    let second = scene.spawn(&mut world)[0];
+    assert_ne!(first, second);
+    assert_eq!(world.len(), before + 4);
+    let first_child = world.get_ref(first, children()).unwrap()[0];
+    let second_child = world.get_ref(second, children()).unwrap()[0];
+    assert_ne!(first_child, second_child);
+    assert_eq!(world.get(second_child, parent()).unwrap(), second);
+    assert_eq!(world.get_ref(root, children()).unwrap(), &vec![child]);
+}
+
+#[test]
+fn prefab_instances_keep_overrides() {
+    ambient_core::init_all_components();
+    let mut prefab = World::new("prefab_instances_keep_overrides_prefab");
+    let root = prefab.spawn(EntityData::new().set(name(), "root".to_string()).set(translation(), vec3(1., 0., 0.)));
+    let child = prefab.spawn(EntityData::new().set(name(), "child".to_string()));
+    set_parent(&mut prefab, child, Some(root)).unwrap();
+    let old = Scene::from_world(&prefab, [root], None).unwrap();
+
+    let mut world = World::new("prefab_instances_keep_overrides");
+    let instance = world.spawn(EntityData::new());
+    let ids = old.instantiate(&mut world, instance);
+    let other_instance = world.spawn(EntityData::new());
+    let other_ids = old.instantiate(&mut world, other_instance);
+    assert_eq!(world.get_ref(instance, children()).unwrap(), &vec![ids[&root]]);
+    assert_eq!(world.get(ids[&root], parent()).unwrap(), instance);
+    assert_eq!(world.get(ids[&child], parent()).unwrap(), ids[&root]);
+    assert_eq!(world.get(ids[&child], prefab_entity()).unwrap(), child);
+
+    // Override the translation of the first instance, and despawn the child of the other one
+    world.set(ids[&root], translation(), vec3(5., 0., 0.)).unwrap();
+    world.despawn(other_ids[&child]);
+    world.set(other_ids[&root], children(), vec![]).unwrap();
+
+    prefab.set(root, translation(), vec3(2., 0., 0.)).unwrap();
+    prefab.add_component(root, rotation(), Quat::from_rotation_z(1.)).unwrap();
+    prefab.set(child, name(), "renamed".to_string()).unwrap();
+    let grandchild = prefab.spawn(EntityData::new().set(name(), "grandchild".to_string()));
+    set_parent(&mut prefab, grandchild, Some(child)).unwrap();
+    let new = Scene::from_world(&prefab, [root], None).unwrap();
+    new.update_instance(&mut world, instance, &old);
+    new.update_instance(&mut world, other_instance, &old);
+
+    assert_eq!(world.get(ids[&root], translation()).unwrap(), vec3(5., 0., 0.));
+    assert_eq!(world.get(ids[&root], rotation()).unwrap(), Quat::from_rotation_z(1.));
+    assert_eq!(world.get_ref(ids[&child], name()).unwrap(), "renamed");
+    let new_grandchild = world.get_ref(ids[&child], children()).unwrap()[0];
+    assert_eq!(world.get_ref(new_grandchild, name()).unwrap(), "grandchild");
+    assert_eq!(world.get(new_grandchild, parent()).unwrap(), ids[&child]);
+
+    assert_eq!(world.get(other_ids[&root], translation()).unwrap(), vec3(2., 0., 0.));
+    assert!(!world.exists(other_ids[&child]));
+    assert!(world.get_ref(other_ids[&root], children()).unwrap().is_empty());
+
+    // Entities removed from the prefab are despawned
+    prefab.despawn(grandchild);
+    prefab.set(child, children(), vec![]).unwrap();
+    let newest = Scene::from_world(&prefab, [root], None).unwrap();
+    newest.update_instance(&mut world, instance, &new);
+    assert!(!world.exists(new_grandchild));
+    assert!(world.get_ref(ids[&child], children()).unwrap().is_empty());
+}
+
+#[test]
+fn scene_spawner_spreads_spawning() {
+    ambient_core::init_all_components();
+    let mut world = World::new("scene_spawner_spreads_spawning");
+    let root = world.spawn(EntityData::new().set(name(), "root".to_string()));
+    for i in 0..3 {
+        let child = world.spawn(EntityData::new().set(name(), format!("child {i}")));
+        set_parent(&mut world, child, Some(root)).unwrap();
+    }
+    let scene = Arc::new(Scene::from_world(&world, [root], None).unwrap());
+
+    let mut loaded = World::new("scene_spawner_spreads_spawning_loaded");
+    let mut spawner = SceneSpawner::new(scene);
+    let mut frames = 0;
+    while !spawner.spawn(&mut loaded, Duration::ZERO) {
+        frames += 1;
+        assert_eq!(spawner.spawned().count(), frames);
+    }
+    assert_eq!(frames, 3);
+    assert_eq!(spawner.progress(), 1.);
+
+    let roots = spawner.roots();
+    assert_eq!(roots.len(), 1);
+    let new_children = loaded.get_cloned(roots[0], children()).unwrap();
+    assert_eq!(new_children.len(), 3);
+    for child in new_children {
+        assert_eq!(loaded.get(child, parent()).unwrap(), roots[0]);
+    }
+}
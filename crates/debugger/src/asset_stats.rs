@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use ambient_core::asset_cache;
+use ambient_element::{element_component, Element, ElementComponentExt, Hooks};
+use ambient_std::{
+    asset_cache::{LoadedAsset, SyncAssetKeyExt},
+    download_asset::AssetsCacheDir,
+    pretty_duration, to_byte_unit,
+};
+use ambient_ui::{
+    fit_horizontal, height, space_between_items, use_interval, width, Button, ButtonStyle, Fit, FlowColumn, FlowRow, ScrollArea, Text,
+    STREET,
+};
+
+/// Lists the assets which are currently loaded, grouped by type, with their memory footprint, reference count and load time
+#[element_component]
+pub fn AssetStats(hooks: &mut Hooks) -> Element {
+    let (loaded, set_loaded) = hooks.use_state_with(|world| world.resource(asset_cache()).loaded_assets());
+    let assets = hooks.world.resource(asset_cache()).clone();
+    use_interval(hooks, 1., move || set_loaded(assets.loaded_assets()));
+
+    let mut groups = HashMap::<String, Vec<LoadedAsset>>::new();
+    for asset in &loaded {
+        groups.entry(asset.type_name.clone()).or_default().push(asset.clone());
+    }
+    let mut groups = groups.into_iter().collect::<Vec<_>>();
+    groups.sort_by_key(|(type_name, assets)| (std::cmp::Reverse(total_size(assets)), type_name.clone()));
+
+    let (cpu, gpu) = loaded.iter().fold((0, 0), |(cpu, gpu), asset| (cpu + asset.cpu_size, gpu + asset.gpu_size));
+    FlowColumn::el([
+        FlowRow::el([
+            Text::el(format!("{} assets, memory: {}, VRAM: {}", loaded.len(), to_byte_unit(cpu), to_byte_unit(gpu))),
+            Button::new("Dump to JSON", move |world| {
+                let cache_dir = AssetsCacheDir.get(world.resource(asset_cache()));
+                std::fs::create_dir_all(&cache_dir).ok();
+                let path = cache_dir.join("loaded_assets.json");
+                match std::fs::write(&path, serde_json::to_string_pretty(&loaded).unwrap()) {
+                    Ok(()) => log::info!("Wrote {:?}", path),
+                    Err(err) => log::error!("Failed to write {path:?}: {err:?}"),
+                }
+            })
+            .style(ButtonStyle::Flat)
+            .el(),
+        ])
+        .set(space_between_items(), STREET),
+        ScrollArea(FlowColumn::el(
+            groups.into_iter().map(|(type_name, assets)| AssetStatsGroup { type_name, assets }.el()).collect::<Vec<_>>(),
+        ))
+        .el()
+        .set(height(), 300.),
+    ])
+    .set(fit_horizontal(), Fit::Parent)
+}
+
+#[element_component]
+fn AssetStatsGroup(hooks: &mut Hooks, type_name: String, assets: Vec<LoadedAsset>) -> Element {
+    let (expanded, set_expanded) = hooks.use_state(false);
+    let (cpu, gpu) = assets.iter().fold((0, 0), |(cpu, gpu), asset| (cpu + asset.cpu_size, gpu + asset.gpu_size));
+    let header = FlowRow::el([
+        Button::new(format!("{} ({})", short_type_name(&type_name), assets.len()), move |_| set_expanded(!expanded))
+            .toggled(expanded)
+            .style(ButtonStyle::Flat)
+            .tooltip(type_name)
+            .el()
+            .set(width(), 400.)
+            .set(fit_horizontal(), Fit::None),
+        size_column(cpu),
+        size_column(gpu),
+    ]);
+    if !expanded {
+        return header;
+    }
+
+    let mut assets = assets;
+    assets.sort_by_key(|asset| (std::cmp::Reverse(asset.cpu_size + asset.gpu_size), asset.key.clone()));
+    let mut rows = vec![header];
+    rows.extend(assets.into_iter().map(|asset| {
+        let key = if asset.key.chars().count() > 60 {
+            format!("{}...", asset.key.chars().take(60).collect::<String>())
+        } else {
+            asset.key.to_string()
+        };
+        FlowRow::el([
+            Text::el(key).set(width(), 400.).set(fit_horizontal(), Fit::None),
+            size_column(asset.cpu_size),
+            size_column(asset.gpu_size),
+            Text::el(format!("refs: {}", asset.ref_count.map(|count| count.to_string()).unwrap_or_else(|| "-".to_string())))
+                .set(width(), 80.)
+                .set(fit_horizontal(), Fit::None),
+            Text::el(asset.load_time.map(pretty_duration).unwrap_or_default()),
+            if asset.pinned { Text::el("pinned") } else { Element::new() },
+        ])
+        .set(space_between_items(), STREET)
+    }));
+    FlowColumn::el(rows)
+}
+
+fn size_column(bytes: u64) -> Element {
+    Text::el(to_byte_unit(bytes)).set(width(), 100.).set(fit_horizontal(), Fit::None)
+}
+
+fn total_size(assets: &[LoadedAsset]) -> u64 {
+    assets.iter().map(|asset| asset.cpu_size + asset.gpu_size).sum()
+}
+
+/// Removes the module paths from a type name, i.e. `alloc::sync::Arc<ambient_gpu::mesh::Mesh>` becomes `Arc<Mesh>`
+fn short_type_name(type_name: &str) -> String {
+    let mut res = String::new();
+    let mut path = String::new();
+    for c in type_name.chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            path.push(c);
+        } else {
+            res.push_str(path.rsplit("::").next().unwrap());
+            path.clear();
+            res.push(c);
+        }
+    }
+    res.push_str(path.rsplit("::").next().unwrap());
+    res
+}
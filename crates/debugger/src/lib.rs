@@ -1,8 +1,11 @@
 use std::{num::NonZeroU32, sync::Arc};
 
+use asset_stats::AssetStats;
+
 use ambient_core::{
     asset_cache,
     bounding::world_bounding_sphere,
+    budget::budget_monitor,
     camera::shadow_cameras_from_world,
     hierarchy::{dump_world_hierarchy, dump_world_hierarchy_to_tmp_file},
     main_scene, runtime,
@@ -12,16 +15,23 @@ use ambient_ecs_editor::ECSEditor;
 use ambient_element::{element_component, Element, ElementComponentExt, Hooks};
 use ambient_gizmos::{gizmos, GizmoPrimitive};
 use ambient_network::client::{GameClient, GameRpcArgs};
-use ambient_renderer::{RenderTarget, Renderer};
+use ambient_renderer::{get_sun_light_direction, RenderTarget, Renderer};
 use ambient_rpc::RpcRegistry;
 use ambient_std::{asset_cache::SyncAssetKeyExt, cb, color::Color, download_asset::AssetsCacheDir, line_hash, Cb};
 use ambient_ui::{
-    fit_horizontal, height, space_between_items, width, Button, ButtonStyle, Dropdown, Fit, FlowColumn, FlowRow, Image, UIExt,
-    VirtualKeyCode,
+    fit_horizontal, height, space_between_items, use_ui_state, width, Button, ButtonStyle, Dropdown, Fit, FlowColumn, FlowRow, Image,
+    UIExt, UiStateScope, VirtualKeyCode,
 };
 use glam::Vec3;
 use winit::event::ModifiersState;
 
+mod asset_stats;
+mod network_stats;
+mod tuning;
+
+pub use network_stats::*;
+pub use tuning::*;
+
 type GetDebuggerState = Cb<dyn Fn(&mut dyn FnMut(&mut Renderer, &RenderTarget, &mut World)) + Sync + Send>;
 
 pub async fn rpc_dump_world_hierarchy(args: GameRpcArgs, _: ()) -> Option<String> {
@@ -32,14 +42,27 @@ pub async fn rpc_dump_world_hierarchy(args: GameRpcArgs, _: ()) -> Option<String
     Some(String::from_utf8(res).unwrap())
 }
 
+/// Where the CPU time of the server went over the last frames, by system and WASM module
+pub async fn rpc_budget_report(args: GameRpcArgs, _: ()) -> Option<String> {
+    let mut state = args.state.lock();
+    let world = state.get_player_world_mut(&args.user_id)?;
+    Some(world.resource_opt(budget_monitor())?.report().to_string())
+}
+
 pub fn register_rpcs(reg: &mut RpcRegistry<GameRpcArgs>) {
     reg.register(rpc_dump_world_hierarchy);
+    reg.register(rpc_budget_report);
+    reg.register(rpc_network_stats);
+    reg.register(rpc_press_tuning_button);
 }
 
 #[element_component]
 pub fn Debugger(hooks: &mut Hooks, get_state: GetDebuggerState) -> Element {
-    let (show_shadows, set_show_shadows) = hooks.use_state(false);
-    let (show_ecs, set_show_ecs) = hooks.use_state(false);
+    let (show_shadows, set_show_shadows) = use_ui_state(hooks, "show_shadows", false);
+    let (show_ecs, set_show_ecs) = use_ui_state(hooks, "show_ecs", false);
+    let (show_assets, set_show_assets) = use_ui_state(hooks, "show_assets", false);
+    let (show_tuning, set_show_tuning) = use_ui_state(hooks, "show_tuning", false);
+    let (show_network, set_show_network) = use_ui_state(hooks, "show_network", false);
     let (game_client, _) = hooks.consume_context::<GameClient>().unwrap();
     FlowColumn::el([
         FlowRow(vec![
@@ -64,7 +87,7 @@ pub fn Debugger(hooks: &mut Hooks, get_state: GetDebuggerState) -> Element {
             .style(ButtonStyle::Flat)
             .el(),
             Button::new("Dump Server World", {
-                let game_client = game_client;
+                let game_client = game_client.clone();
                 move |world| {
                     let game_client = game_client.clone();
                     let cache_dir = AssetsCacheDir.get(world.resource(asset_cache()));
@@ -82,6 +105,19 @@ pub fn Debugger(hooks: &mut Hooks, get_state: GetDebuggerState) -> Element {
             .hotkey(VirtualKeyCode::F6)
             .style(ButtonStyle::Flat)
             .el(),
+            Button::new("Log Server Budget", {
+                let game_client = game_client;
+                move |world| {
+                    let game_client = game_client.clone();
+                    world.resource(runtime()).clone().spawn(async move {
+                        if let Ok(Some(report)) = game_client.rpc(rpc_budget_report, ()).await {
+                            log::info!("Server {report}");
+                        }
+                    });
+                }
+            })
+            .style(ButtonStyle::Flat)
+            .el(),
             Button::new("Dump Client Renderer", {
                 let get_state = get_state.clone();
                 move |world| {
@@ -100,12 +136,21 @@ pub fn Debugger(hooks: &mut Hooks, get_state: GetDebuggerState) -> Element {
             Button::new("Show Shadow Frustums", {
                 let get_state = get_state.clone();
                 move |_| {
-                    get_state(&mut |_, _, world| {
+                    get_state(&mut |renderer, _, world| {
                         let gizmos = world.resource(gizmos());
                         let mut g = gizmos.scope(line_hash!());
-                        let cascades = 5;
-                        for (i, cam) in
-                            shadow_cameras_from_world(world, cascades, 1024, Vec3::ONE.normalize(), main_scene()).into_iter().enumerate()
+                        let config = &renderer.config;
+                        let cascades = config.shadow_cascades;
+                        for (i, cam) in shadow_cameras_from_world(
+                            world,
+                            cascades,
+                            &config.shadow_cascade_splits,
+                            config.shadow_map_resolution,
+                            get_sun_light_direction(world, main_scene()),
+                            main_scene(),
+                        )
+                        .into_iter()
+                        .enumerate()
                         {
                             for line in cam.world_space_frustum_lines() {
                                 g.draw(
@@ -145,10 +190,43 @@ pub fn Debugger(hooks: &mut Hooks, get_state: GetDebuggerState) -> Element {
             })
             .style(ButtonStyle::Flat)
             .el(),
+            Button::new("Show Assets", {
+                move |_| {
+                    set_show_assets(!show_assets);
+                }
+            })
+            .toggled(show_assets)
+            .hotkey_modifier(ModifiersState::SHIFT)
+            .hotkey(VirtualKeyCode::F8)
+            .style(ButtonStyle::Flat)
+            .el(),
+            Button::new("Show Tuning", {
+                move |_| {
+                    set_show_tuning(!show_tuning);
+                }
+            })
+            .toggled(show_tuning)
+            .hotkey_modifier(ModifiersState::SHIFT)
+            .hotkey(VirtualKeyCode::F9)
+            .style(ButtonStyle::Flat)
+            .el(),
+            Button::new("Show Network", {
+                move |_| {
+                    set_show_network(!show_network);
+                }
+            })
+            .toggled(show_network)
+            .hotkey_modifier(ModifiersState::SHIFT)
+            .hotkey(VirtualKeyCode::F10)
+            .style(ButtonStyle::Flat)
+            .el(),
         ])
         .el()
         .set(space_between_items(), 5.),
         if show_shadows { ShadowMapsViz { get_state: get_state.clone() }.el() } else { Element::new() },
+        if show_assets { UiStateScope::el("assets", AssetStats.el()) } else { Element::new() },
+        if show_tuning { UiStateScope::el("tuning", TuningPanel.el()) } else { Element::new() },
+        if show_network { NetworkStatsPanel.el() } else { Element::new() },
         if show_ecs {
             ECSEditor { get_world: cb(move |res| get_state(&mut move |_, _, world| res(world))), on_change: cb(|_, _| {}) }
                 .el()
@@ -0,0 +1,59 @@
+use ambient_core::runtime;
+use ambient_element::{element_component, Element, ElementComponentExt, Hooks};
+use ambient_network::{
+    client::{GameClient, GameClientNetworkStats, GameRpcArgs},
+    get_player_by_user_id,
+    stats::{network_stats, NetworkStats, STATS_INTERVAL},
+};
+use ambient_std::to_byte_unit;
+use ambient_ui::{fit_horizontal, space_between_items, use_interval, width, Fit, FlowColumn, FlowRow, StylesExt, Text, STREET};
+
+/// How many components are listed at most, from the most expensive one
+const MAX_LISTED_COMPONENTS: usize = 20;
+
+/// The statistics of the connection of the player, as seen by the server
+pub async fn rpc_network_stats(args: GameRpcArgs, _: ()) -> Option<NetworkStats> {
+    let state = args.state.lock();
+    let world = state.get_player_world(&args.user_id)?;
+    let player_id = get_player_by_user_id(world, &args.user_id)?;
+    world.get_cloned(player_id, network_stats()).ok()
+}
+
+/// The statistics of the connection to the server as seen by the client and by the server, with the components which cost
+/// the most to replicate
+#[element_component]
+pub fn NetworkStatsPanel(hooks: &mut Hooks) -> Element {
+    let (client_stats, _) = hooks.consume_context::<GameClientNetworkStats>().unwrap();
+    let (game_client, _) = hooks.consume_context::<GameClient>().unwrap();
+    let (server_stats, set_server_stats) = hooks.use_state(None::<NetworkStats>);
+    let runtime = hooks.world.resource(runtime()).clone();
+    use_interval(hooks, STATS_INTERVAL.as_secs_f32(), move || {
+        let (game_client, set_server_stats) = (game_client.clone(), set_server_stats.clone());
+        runtime.spawn(async move {
+            if let Ok(stats) = game_client.rpc(rpc_network_stats, ()).await {
+                set_server_stats(stats);
+            }
+        });
+    });
+
+    FlowRow::el([stats_column("Client", Some(&client_stats.0)), stats_column("Server", server_stats.as_ref())])
+        .set(space_between_items(), STREET)
+}
+
+fn stats_column(title: &str, stats: Option<&NetworkStats>) -> Element {
+    let mut rows = vec![Text::el(title).section_style()];
+    match stats {
+        Some(stats) => {
+            rows.push(Text::el(stats.to_string()));
+            rows.extend(
+                stats
+                    .replication_costs
+                    .iter()
+                    .take(MAX_LISTED_COMPONENTS)
+                    .map(|cost| Text::el(format!("{}: {}/s", cost.component, to_byte_unit(cost.bytes_per_second)))),
+            );
+        }
+        None => rows.push(Text::el("No statistics yet")),
+    }
+    FlowColumn::el(rows).set(width(), 400.).set(fit_horizontal(), Fit::None)
+}
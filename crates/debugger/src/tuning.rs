@@ -0,0 +1,214 @@
+//! Tuning controls which scripts spawn on the server (as entities with a [tuning_label]), and which are shown in the
+//! debugger of the clients, so that gameplay constants can be tweaked live.
+
+use std::collections::{BTreeMap, HashSet};
+
+use ambient_core::runtime;
+use ambient_ecs::{
+    components, query, world_events, Debuggable, Description, EntityData, EntityId, Name, Networked, World, WorldDiff, WorldEvent,
+};
+use ambient_element::{element_component, Element, ElementComponentExt, Hooks};
+use ambient_network::{
+    client::{GameClient, GameRpcArgs},
+    log_network_result,
+    rpc::rpc_world_diff,
+};
+use ambient_std::{cb, color::Color, Cb};
+use ambient_ui::{min_width, space_between_items, use_ui_state, Button, ButtonStyle, Checkbox, FlowColumn, FlowRow, Slider, Text, UIExt};
+
+components!("tuning", {
+    @[
+        Debuggable, Networked,
+        Name["Tuning label"],
+        Description["The label of the tuning control of this entity in the debugger.\nThe part before the last `/`, if any, is the group the control is shown in."]
+    ]
+    tuning_label: String,
+    @[
+        Debuggable, Networked,
+        Name["Tuning value"],
+        Description["The value of the tuning slider of this entity, between `tuning_min` and `tuning_max`."]
+    ]
+    tuning_value: f32,
+    @[
+        Debuggable, Networked,
+        Name["Tuning min"],
+        Description["The minimum value of the tuning slider of this entity."]
+    ]
+    tuning_min: f32,
+    @[
+        Debuggable, Networked,
+        Name["Tuning max"],
+        Description["The maximum value of the tuning slider of this entity."]
+    ]
+    tuning_max: f32,
+    @[
+        Debuggable, Networked,
+        Name["Tuning toggle"],
+        Description["The value of the tuning toggle of this entity."]
+    ]
+    tuning_toggle: bool,
+    @[
+        Debuggable, Networked,
+        Name["Tuning button"],
+        Description["If attached, the tuning control of this entity is a button, which fires `core/tuning_button` when pressed."]
+    ]
+    tuning_button: (),
+});
+
+/// Fired on the server when a tuning button is pressed. The event data contains the `id` of the button's entity.
+pub const TUNING_BUTTON_EVENT: &str = "core/tuning_button";
+
+/// The group of the controls whose label doesn't have one
+const DEFAULT_GROUP: &str = "General";
+
+pub async fn rpc_press_tuning_button(args: GameRpcArgs, id: EntityId) {
+    let mut state = args.state.lock();
+    let world = match state.get_player_world_mut(&args.user_id) {
+        Some(world) => world,
+        None => return,
+    };
+    if world.has_component(id, tuning_button()) {
+        world
+            .resource_mut(world_events())
+            .add_event(WorldEvent { name: TUNING_BUTTON_EVENT.to_string(), data: EntityData::new().set(ambient_ecs::id(), id) });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TuningKind {
+    Slider { value: f32, min: f32, max: f32 },
+    Toggle(bool),
+    Button,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TuningControl {
+    id: EntityId,
+    group: String,
+    label: String,
+    kind: TuningKind,
+}
+impl TuningControl {
+    fn from_world(world: &World) -> Vec<Self> {
+        let mut controls = query(tuning_label())
+            .iter(world, None)
+            .filter_map(|(id, label)| {
+                let kind = if world.has_component(id, tuning_button()) {
+                    TuningKind::Button
+                } else if let Ok(value) = world.get(id, tuning_toggle()) {
+                    TuningKind::Toggle(value)
+                } else {
+                    TuningKind::Slider {
+                        value: world.get(id, tuning_value()).ok()?,
+                        min: world.get(id, tuning_min()).unwrap_or(0.),
+                        max: world.get(id, tuning_max()).unwrap_or(1.),
+                    }
+                };
+                let (group, label) = match label.rsplit_once('/') {
+                    Some((group, label)) => (group.to_string(), label.to_string()),
+                    None => (DEFAULT_GROUP.to_string(), label.clone()),
+                };
+                Some(Self { id, group, label, kind })
+            })
+            .collect::<Vec<_>>();
+        controls.sort_by(|a, b| (&a.group, &a.label).cmp(&(&b.group, &b.label)));
+        controls
+    }
+}
+
+/// The tuning controls of the server world, by group. The groups can be collapsed by clicking on their name, which is
+/// remembered across sessions when the panel is in a [ambient_ui::UiStateScope].
+#[element_component]
+pub fn TuningPanel(hooks: &mut Hooks) -> Element {
+    let (game_client, _) = hooks.consume_context::<GameClient>().unwrap();
+    let (controls, set_controls) = hooks.use_state(Vec::<TuningControl>::new());
+    let (collapsed, set_collapsed) = use_ui_state(hooks, "collapsed_groups", HashSet::<String>::new());
+    let last_controls = hooks.use_ref_with(|_| Vec::<TuningControl>::new());
+    hooks.use_frame({
+        let game_client = game_client.clone();
+        move |_| {
+            let controls = TuningControl::from_world(&game_client.game_state.lock().world);
+            let mut last_controls = last_controls.lock();
+            if *last_controls != controls {
+                *last_controls = controls.clone();
+                set_controls(controls);
+            }
+        }
+    });
+
+    let runtime = hooks.world.resource(runtime()).clone();
+    let send_diff: Cb<dyn Fn(WorldDiff) + Sync + Send> = cb({
+        let game_client = game_client.clone();
+        let runtime = runtime.clone();
+        move |diff| {
+            let game_client = game_client.clone();
+            runtime.spawn(async move { log_network_result!(game_client.rpc(rpc_world_diff, diff).await) });
+        }
+    });
+    let press: Cb<dyn Fn(EntityId) + Sync + Send> = cb(move |id| {
+        let game_client = game_client.clone();
+        runtime.spawn(async move { log_network_result!(game_client.rpc(rpc_press_tuning_button, id).await) });
+    });
+
+    let mut groups = BTreeMap::<String, Vec<TuningControl>>::new();
+    for control in controls {
+        groups.entry(control.group.clone()).or_default().push(control);
+    }
+    if groups.is_empty() {
+        return Text::el("No tuning controls");
+    }
+    FlowColumn::el(
+        groups
+            .into_iter()
+            .flat_map(|(group, controls)| {
+                let is_collapsed = collapsed.contains(&group);
+                let header = Button::new(group.clone(), {
+                    let collapsed = collapsed.clone();
+                    let set_collapsed = set_collapsed.clone();
+                    move |_| {
+                        let mut collapsed = collapsed.clone();
+                        if !collapsed.remove(&group) {
+                            collapsed.insert(group.clone());
+                        }
+                        set_collapsed(collapsed);
+                    }
+                })
+                .toggled(!is_collapsed)
+                .style(ButtonStyle::Flat)
+                .el();
+                let rows = if is_collapsed {
+                    Vec::new()
+                } else {
+                    controls.into_iter().map(|control| control_row(control, send_diff.clone(), press.clone())).collect()
+                };
+                std::iter::once(header).chain(rows)
+            })
+            .collect::<Vec<_>>(),
+    )
+    .set(space_between_items(), 5.)
+    .with_background(Color::rgba(0., 0., 0.2, 1.))
+}
+
+fn control_row(
+    control: TuningControl,
+    send_diff: Cb<dyn Fn(WorldDiff) + Sync + Send>,
+    press: Cb<dyn Fn(EntityId) + Sync + Send>,
+) -> Element {
+    let TuningControl { id, label, kind, .. } = control;
+    let input = match kind {
+        TuningKind::Slider { value, min, max } => Slider {
+            value,
+            on_change: Some(cb(move |value| send_diff(WorldDiff::new().set(id, tuning_value(), value)))),
+            min,
+            max,
+            width: 200.,
+            logarithmic: false,
+            round: Some(2),
+            suffix: None,
+        }
+        .el(),
+        TuningKind::Toggle(value) => Checkbox::new(value, move |value| send_diff(WorldDiff::new().set(id, tuning_toggle(), value))).el(),
+        TuningKind::Button => return Button::new(label, move |_| press(id)).el(),
+    };
+    FlowRow::el([Text::el(label).set(min_width(), 150.), input]).set(space_between_items(), 5.)
+}
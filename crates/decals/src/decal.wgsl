@@ -73,6 +73,7 @@ fn get_decal(in: VertexOutput) -> Decal {
     material_in.normal = screen_normal_mat * vec3<f32>(0., 0., 1.);
     material_in.normal_matrix = screen_normal_mat;
     material_in.instance_index = in.instance_index;
+    material_in.color = vec4<f32>(1., 1., 1., 1.);
     res.material_in = material_in;
 
     return res;
@@ -108,7 +109,7 @@ fn fs_forward_lit_main(in: VertexOutput, @builtin(front_facing) is_front: bool)
         discard;
     }
     var res: FsOutputs;
-    res.color = shading(material, vec4<f32>(decal.material_in.world_position, 1.));
+    res.color = shading(material, vec4<f32>(decal.material_in.world_position, 1.), true);
     res.depth = decal.depth + 0.0001;
     return res;
 }
@@ -0,0 +1,111 @@
+//! Shows what the players are doing on their Discord profile, and lets their friends ask to join them.
+//!
+//! The server spawns an entity with the Discord activity of the project (see [activity_entity_data]), which scripts can
+//! change. The clients publish it to the Discord client running on their machine, along with the size of their party.
+
+use ambient_core::asset_cache;
+use ambient_ecs::{components, query, Debuggable, Description, EntityData, EntityId, Name, Networked, Store, SystemGroup, World};
+use ambient_network::player::player;
+use ambient_project::Manifest;
+use ambient_std::{asset_cache::SyncAssetKeyExt, friendly_id};
+
+mod presence;
+
+pub use presence::{Activity, DiscordPresence, DiscordPresenceKey, Party};
+
+components!("discord", {
+    @[
+        Debuggable, Networked, Store,
+        Name["Discord app ID"],
+        Description["The id of the Discord application the activity of the players is shown as.\nThe other Discord components are read from the entity with this component."]
+    ]
+    discord_app_id: u64,
+    @[
+        Debuggable, Networked, Store,
+        Name["Discord details"],
+        Description["The first line of the Discord activity of the players."]
+    ]
+    discord_details: String,
+    @[
+        Debuggable, Networked, Store,
+        Name["Discord state"],
+        Description["The second line of the Discord activity of the players, e.g. the current map."]
+    ]
+    discord_state: String,
+    @[
+        Debuggable, Networked, Store,
+        Name["Discord large image"],
+        Description["The key of the art asset of the Discord application to show in the activity of the players."]
+    ]
+    discord_large_image: String,
+    @[
+        Debuggable, Networked, Store,
+        Name["Discord party ID"],
+        Description["Identifies the players of this server as a party on Discord."]
+    ]
+    discord_party_id: String,
+    @[
+        Debuggable, Networked, Store,
+        Name["Discord max party size"],
+        Description["If attached, the number of players is shown in their Discord activity, out of this maximum."]
+    ]
+    discord_max_party_size: u32,
+    @[
+        Debuggable, Networked, Store,
+        Name["Discord join address"],
+        Description["If attached, the friends of the players can ask to join them on Discord, and connect to this address when accepted."]
+    ]
+    discord_join_address: String,
+});
+
+/// The entity holding the Discord activity of the project, if its manifest has a `[discord]` section. `join_address` is
+/// where the players of other machines connect to this server.
+pub fn activity_entity_data(manifest: &Manifest, join_address: String) -> Option<EntityData> {
+    let discord = manifest.discord.as_ref()?;
+    let details = discord.details.clone().or_else(|| manifest.project.name.clone()).unwrap_or_else(|| manifest.project.id.to_string());
+    let mut data =
+        EntityData::new().set(discord_app_id(), discord.app_id).set(discord_details(), details).set(discord_party_id(), friendly_id());
+    if let Some(state) = &discord.state {
+        data.set_self(discord_state(), state.clone());
+    }
+    if let Some(large_image) = &discord.large_image {
+        data.set_self(discord_large_image(), large_image.clone());
+    }
+    if let Some(max_party_size) = discord.max_party_size {
+        data.set_self(discord_max_party_size(), max_party_size);
+    }
+    if discord.joinable {
+        data.set_self(discord_join_address(), join_address);
+    }
+    Some(data)
+}
+
+fn get_activity(world: &World, id: EntityId, app_id: u64) -> Activity {
+    let party = match (world.get_ref(id, discord_party_id()), world.get(id, discord_max_party_size())) {
+        (Ok(party_id), Ok(max)) => Some(Party { id: party_id.clone(), size: query(player()).iter(world, None).count() as u32, max }),
+        _ => None,
+    };
+    Activity {
+        app_id,
+        details: world.get_ref(id, discord_details()).ok().cloned(),
+        state: world.get_ref(id, discord_state()).ok().cloned(),
+        large_image: world.get_ref(id, discord_large_image()).ok().cloned(),
+        party,
+        join_secret: world.get_ref(id, discord_join_address()).ok().cloned(),
+    }
+}
+
+/// Publishes the Discord activity of the project whenever it changes
+pub fn client_systems() -> SystemGroup {
+    let mut published = None;
+    SystemGroup::new(
+        "discord",
+        vec![query(discord_app_id()).to_system(move |q, world, qs, _| {
+            let activity = q.iter(world, qs).next().map(|(id, &app_id)| get_activity(world, id, app_id));
+            if activity != published {
+                DiscordPresenceKey.get(world.resource(asset_cache())).set_activity(activity.clone());
+                published = activity;
+            }
+        })],
+    )
+}
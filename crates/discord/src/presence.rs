@@ -0,0 +1,161 @@
+use std::{num::NonZeroU32, time::Duration};
+
+use ambient_std::asset_cache::{AssetCache, SyncAssetKey};
+use discord_sdk::{
+    activity::{ActivityBuilder, Assets, PartyPrivacy, Secrets},
+    wheel::Wheel,
+    Discord, DiscordApp, Subscriptions,
+};
+use tokio::{
+    sync::broadcast::error::RecvError,
+    task::JoinHandle,
+    time::{timeout, Instant},
+};
+
+/// How long the Discord client has to accept the connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Activity {
+    pub app_id: u64,
+    pub details: Option<String>,
+    pub state: Option<String>,
+    pub large_image: Option<String>,
+    pub party: Option<Party>,
+    /// Handed to the friends who are accepted to join the player
+    pub join_secret: Option<String>,
+}
+impl Activity {
+    fn builder(&self) -> ActivityBuilder {
+        let mut builder = ActivityBuilder::default();
+        if let Some(details) = &self.details {
+            builder = builder.details(details);
+        }
+        if let Some(state) = &self.state {
+            builder = builder.state(state);
+        }
+        if let Some(large_image) = &self.large_image {
+            builder = builder.assets(Assets::default().large(large_image, None::<String>));
+        }
+        if let Some(party) = &self.party {
+            builder = builder.party(&party.id, NonZeroU32::new(party.size), NonZeroU32::new(party.max), PartyPrivacy::Public);
+        }
+        if let Some(join_secret) = &self.join_secret {
+            builder = builder.secrets(Secrets { join: Some(join_secret.clone()), ..Default::default() });
+        }
+        builder
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Party {
+    pub id: String,
+    pub size: u32,
+    pub max: u32,
+}
+
+/// Publishes the activity of the player to the Discord client running on this machine, if there's one
+#[derive(Debug, Clone)]
+pub struct DiscordPresence {
+    activity_tx: flume::Sender<Option<Activity>>,
+    join_requests: flume::Receiver<String>,
+}
+impl DiscordPresence {
+    pub fn new(assets: &AssetCache) -> Self {
+        let (activity_tx, activity_rx) = flume::unbounded();
+        let (join_tx, join_requests) = flume::unbounded();
+        assets.runtime().spawn(run(activity_rx, join_tx));
+        Self { activity_tx, join_requests }
+    }
+    /// Shows `activity`, or clears it
+    pub fn set_activity(&self, activity: Option<Activity>) {
+        self.activity_tx.send(activity).ok();
+    }
+    /// The join secrets of the friends the player accepted to join, from Discord
+    pub fn join_requests(&self) -> &flume::Receiver<String> {
+        &self.join_requests
+    }
+}
+
+#[derive(Debug)]
+pub struct DiscordPresenceKey;
+impl SyncAssetKey<DiscordPresence> for DiscordPresenceKey {
+    fn load(&self, assets: AssetCache) -> DiscordPresence {
+        DiscordPresence::new(&assets)
+    }
+}
+
+struct Connection {
+    app_id: u64,
+    discord: Discord,
+    _wheel: Wheel,
+    join_task: JoinHandle<()>,
+}
+impl Connection {
+    async fn new(app_id: u64, join_tx: flume::Sender<String>) -> anyhow::Result<Self> {
+        let (wheel, handler) = Wheel::new(Box::new(|err| log::warn!("Discord error: {err:?}")));
+        let mut user = wheel.user();
+        let discord = Discord::new(DiscordApp::PlainId(app_id as i64), Subscriptions::ACTIVITY, Box::new(handler))?;
+        timeout(CONNECT_TIMEOUT, user.0.changed()).await??;
+
+        let mut activity_events = wheel.activity();
+        let join_task = tokio::spawn(async move {
+            loop {
+                match activity_events.0.recv().await {
+                    Ok(discord_sdk::activity::events::ActivityEvent::Join(join)) => {
+                        log::info!("Joining a friend from Discord");
+                        join_tx.send(join.secret).ok();
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        Ok(Self { app_id, discord, _wheel: wheel, join_task })
+    }
+}
+
+async fn run(activity_rx: flume::Receiver<Option<Activity>>, join_tx: flume::Sender<String>) {
+    let start = std::time::SystemTime::now();
+    let mut connection: Option<Connection> = None;
+    let mut last_failure: Option<Instant> = None;
+    while let Ok(mut activity) = activity_rx.recv_async().await {
+        // Only the latest activity matters
+        while let Ok(latest) = activity_rx.try_recv() {
+            activity = latest;
+        }
+        let activity = match activity {
+            Some(activity) => activity,
+            None => {
+                if let Some(connection) = connection.take() {
+                    connection.join_task.abort();
+                    connection.discord.disconnect().await;
+                }
+                continue;
+            }
+        };
+        if connection.as_ref().map(|connection| connection.app_id) != Some(activity.app_id) {
+            if let Some(connection) = connection.take() {
+                connection.join_task.abort();
+                connection.discord.disconnect().await;
+            }
+            // Don't keep trying if Discord isn't running
+            if last_failure.map(|last_failure| last_failure.elapsed() < CONNECT_TIMEOUT * 12).unwrap_or(false) {
+                continue;
+            }
+            match Connection::new(activity.app_id, join_tx.clone()).await {
+                Ok(new_connection) => connection = Some(new_connection),
+                Err(err) => {
+                    log::info!("Not showing the activity on Discord: {err:?}");
+                    last_failure = Some(Instant::now());
+                    continue;
+                }
+            }
+        }
+        if let Some(connection) = &connection {
+            if let Err(err) = connection.discord.update_activity(activity.builder().start_timestamp(start)).await {
+                log::warn!("Failed to update the Discord activity: {err:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Measures how long it takes to iterate over queries of a large world. Run with `cargo bench -p ambient_ecs`.
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use ambient_ecs::{components, query, query_mut, EntityData, QueryState, World};
+use glam::{Quat, Vec3};
+
+components!("bench", {
+    position: Vec3,
+    velocity: Vec3,
+    rotation: Quat,
+    scale: Vec3,
+    health: f32,
+});
+
+const ENTITIES: usize = 1_000_000;
+const RUNS: usize = 20;
+
+fn bench(name: &str, mut run: impl FnMut()) {
+    let mut times = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .collect::<Vec<_>>();
+    times.sort();
+    let ms = |time: Duration| time.as_secs_f64() * 1000.;
+    println!("{name}: median {:.2}ms, min {:.2}ms", ms(times[RUNS / 2]), ms(times[0]));
+}
+
+fn main() {
+    init_components();
+    let mut world = World::new("query_bench");
+    let entity =
+        EntityData::new().set(position(), Vec3::ZERO).set(velocity(), Vec3::X).set(rotation(), Quat::IDENTITY).set(scale(), Vec3::ONE);
+    // Most of the entities are in one archetype, the rest are spread over a few others
+    world.batch_spawn(entity.clone(), ENTITIES * 3 / 4);
+    world.batch_spawn(entity.clone().set(health(), 100.), ENTITIES / 8);
+    world.batch_spawn(entity.remove(scale()), ENTITIES / 8);
+    println!("{} entities", world.len());
+
+    bench("read 3 components", || {
+        let mut sum = Vec3::ZERO;
+        for (_, (position, velocity, scale)) in query((position(), velocity(), scale())).iter(&world, None) {
+            sum += *position + *velocity * *scale;
+        }
+        black_box(sum);
+    });
+
+    let mut state = QueryState::new();
+    bench("read 4 components with a cached query state", || {
+        let mut sum = Vec3::ZERO;
+        for (_, (position, velocity, rotation, scale)) in
+            query((position(), velocity(), rotation(), scale())).iter(&world, Some(&mut state))
+        {
+            sum += *position + *rotation * (*velocity * *scale);
+        }
+        black_box(sum);
+    });
+
+    bench("write 1 component, read 2", || {
+        for (_, position, (velocity, rotation)) in query_mut(position(), (velocity(), rotation())).iter(&mut world, None) {
+            *position += *rotation * *velocity * 0.01;
+        }
+        // Writes are recorded as changes, which are dropped after a few frames
+        world.next_frame();
+    });
+
+    bench("read 3 components by looking up each entity", || {
+        let mut sum = Vec3::ZERO;
+        for id in query((position(), velocity(), scale())).collect_ids(&world, None) {
+            sum += world.get(id, position()).unwrap() + world.get(id, velocity()).unwrap() * world.get(id, scale()).unwrap();
+        }
+        black_box(sum);
+    });
+}
@@ -1,7 +1,7 @@
 use std::cell::UnsafeCell;
 
 use ambient_std::sparse_vec::SparseVec;
-use atomic_refcell::AtomicRefCell;
+use atomic_refcell::{AtomicRefCell, AtomicRefMut};
 
 use super::*;
 use crate::{
@@ -68,6 +68,51 @@ impl ArchComponent {
     }
 }
 
+/// Mutable access to the values of a component in an archetype, which marks them as changed when they're accessed.
+/// Created by [ComponentQuery::fetch_mut] when iterating over a query; the world is borrowed mutably for the whole
+/// iteration, so the change tracking of the component is borrowed once rather than for each entity.
+pub struct ArchetypeFetchMut<'a, T> {
+    component: &'a ArchComponent,
+    ids: &'a [EntityId],
+    data: *mut T,
+    version: u64,
+    changes: AtomicRefMut<'a, FramedEvents<EntityId>>,
+    content_versions: AtomicRefMut<'a, Vec<u64>>,
+    written: bool,
+}
+impl<'a, T: ComponentValue> ArchetypeFetchMut<'a, T> {
+    pub(crate) fn new(world: &'a World, arch: &'a Archetype, component: Component<T>) -> Self {
+        let arch_comp = arch.components.get(component.index() as _).expect("Archetype doesn't have the component");
+        let buffer = unsafe { &mut **arch_comp.data.0.get() };
+        let data = buffer.as_mut_any().downcast_mut::<ComponentBuffer<T>>().unwrap().data.as_mut_ptr();
+        world.inc_version();
+        Self {
+            component: arch_comp,
+            ids: &arch.entity_indices_to_ids,
+            data,
+            version: world.version(),
+            changes: arch_comp.changes.borrow_mut(),
+            content_versions: arch_comp.content_versions.borrow_mut(),
+            written: false,
+        }
+    }
+    pub(crate) fn get(&mut self, index: usize) -> &'a mut T {
+        let id = self.ids[index];
+        self.changes.add_event(id);
+        self.content_versions[index] = self.version;
+        self.written = true;
+        unsafe { &mut *self.data.add(index) }
+    }
+}
+impl<'a, T> Drop for ArchetypeFetchMut<'a, T> {
+    fn drop(&mut self) {
+        if self.written {
+            self.component.max_content_version.0.store(self.version, Ordering::Relaxed);
+            self.component.data_version.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
 #[derive(Clone)]
 pub(super) struct MoveComponent {
     pub data: ComponentEntry,
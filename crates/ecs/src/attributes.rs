@@ -107,20 +107,48 @@ where
     }
 }
 impl Serializable {
-    /// Serialize a value
-    pub fn serialize<'a>(&self, entry: &'a ComponentEntry) -> &'a dyn erased_serde::Serialize {
-        (self.ser)(entry)
+    /// Serialize a value, along with the [ComponentVersion] of the component if it has one
+    pub fn serialize<'a>(&self, entry: &'a ComponentEntry) -> SerializedComponent<'a> {
+        SerializedComponent { entry, ser: self.ser }
     }
 
-    /// Deserialize a value
+    /// Deserialize a value, migrating it with [Migrate] if it was saved with an older [ComponentVersion]
     pub fn deserializer(&self, desc: ComponentDesc) -> ComponentDeserializer {
-        ComponentDeserializer { desc, deser: self.deser }
+        let attributes = desc.attributes();
+        let version = attributes.get::<ComponentVersion>().map(|version| version.0);
+        let migrate = attributes.get::<Migrate>().map(|migrate| migrate.migrate.clone());
+        ComponentDeserializer { desc, deser: self.deser, version, migrate }
     }
 }
 
+pub struct SerializedComponent<'a> {
+    entry: &'a ComponentEntry,
+    ser: fn(&ComponentEntry) -> &dyn erased_serde::Serialize,
+}
+
+impl<'a> Serialize for SerializedComponent<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let version = self.entry.attribute::<ComponentVersion>().map(|version| version.0);
+        let value = (self.ser)(self.entry);
+        match version {
+            Some(version) => (version, value).serialize(serializer),
+            None => value.serialize(serializer),
+        }
+    }
+}
+
+type MigrateFn =
+    Arc<dyn Fn(ComponentDesc, u32, &mut dyn erased_serde::Deserializer) -> Result<ComponentEntry, erased_serde::Error> + Send + Sync>;
+
+#[derive(Clone)]
 pub struct ComponentDeserializer {
     desc: ComponentDesc,
     deser: fn(ComponentDesc, &mut dyn erased_serde::Deserializer) -> Result<ComponentEntry, erased_serde::Error>,
+    version: Option<u32>,
+    migrate: Option<MigrateFn>,
 }
 
 impl<'de> serde::de::DeserializeSeed<'de> for ComponentDeserializer {
@@ -130,9 +158,110 @@ impl<'de> serde::de::DeserializeSeed<'de> for ComponentDeserializer {
     where
         D: serde::Deserializer<'de>,
     {
+        let desc = self.desc;
+        let entry = match desc.attribute::<MakeDefault>() {
+            // A missing value, i.e. a `null` in a scene or a prefab, is the default value of the component
+            Some(make_default) if deserializer.is_human_readable() => {
+                let value = serde_json::Value::deserialize(deserializer)?;
+                let missing = value.is_null();
+                match self.deserialize_value(value) {
+                    Ok(entry) => entry,
+                    Err(_) if missing => make_default.make_default(desc),
+                    Err(err) => return Err(serde::de::Error::custom(err)),
+                }
+            }
+            _ => self.deserialize_value(deserializer)?,
+        };
+        validate_component(entry).map_err(serde::de::Error::custom)
+    }
+}
+
+impl ComponentDeserializer {
+    fn deserialize_value<'de, D>(self, deserializer: D) -> Result<ComponentEntry, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        if self.version.is_none() {
+            let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
+            let deserializer = &mut deserializer;
+            return (self.deser)(self.desc, deserializer).map_err(serde::de::Error::custom);
+        }
+
+        if deserializer.is_human_readable() {
+            // Values saved before the component was versioned are not wrapped in a `[version, value]` pair, and may be pairs
+            // which start with a number themselves (e.g. a `Vec<u32>`), so they're read as such if they're not a valid
+            // versioned value
+            let value = serde_json::Value::deserialize(deserializer)?;
+            let mut versioned_err = None;
+            if let serde_json::Value::Array(pair) = &value {
+                if pair.len() == 2 && pair[0].is_u64() {
+                    let versioned = VersionedDeserializer { inner: self.clone(), version: pair[0].as_u64().unwrap() as u32 };
+                    match serde::de::DeserializeSeed::deserialize(versioned, pair[1].clone()) {
+                        Ok(entry) => return Ok(entry),
+                        Err(err) => versioned_err = Some(err),
+                    }
+                }
+            }
+            let unversioned = VersionedDeserializer { inner: self, version: 0 };
+            serde::de::DeserializeSeed::deserialize(unversioned, value)
+                .map_err(|err| serde::de::Error::custom(versioned_err.unwrap_or(err)))
+        } else {
+            struct VersionedVisitor(ComponentDeserializer);
+            impl<'de> serde::de::Visitor<'de> for VersionedVisitor {
+                type Value = ComponentEntry;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    formatter.write_str("a version and a component value")
+                }
+
+                fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                where
+                    A: serde::de::SeqAccess<'de>,
+                {
+                    let version: u32 = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+                    seq.next_element_seed(VersionedDeserializer { inner: self.0, version })?
+                        .ok_or_else(|| serde::de::Error::invalid_length(1, &"a version and a component value"))
+                }
+            }
+            deserializer.deserialize_tuple(2, VersionedVisitor(self))
+        }
+    }
+}
+
+/// Deserializes a value which was saved with `version`
+struct VersionedDeserializer {
+    inner: ComponentDeserializer,
+    version: u32,
+}
+
+impl<'de> serde::de::DeserializeSeed<'de> for VersionedDeserializer {
+    type Value = ComponentEntry;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let ComponentDeserializer { desc, deser, version: current, migrate } = self.inner;
+        let current = current.unwrap_or_default();
         let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
         let deserializer = &mut deserializer;
-        (self.deser)(self.desc, deserializer).map_err(serde::de::Error::custom)
+        let res = match (self.version, migrate) {
+            (version, _) if version == current => deser(desc, deserializer),
+            (version, Some(migrate)) if version < current => migrate(desc, version, deserializer),
+            (version, _) if version < current => {
+                return Err(serde::de::Error::custom(format!(
+                    "Component {} was saved with version {version}, and can't be migrated to version {current}",
+                    desc.path()
+                )))
+            }
+            (version, _) => {
+                return Err(serde::de::Error::custom(format!(
+                    "Component {} was saved with version {version}, which is newer than version {current}",
+                    desc.path()
+                )))
+            }
+        };
+        res.map_err(serde::de::Error::custom)
     }
 }
 
@@ -154,7 +283,8 @@ where
     }
 }
 
-/// Allows constructing a default value of the type
+/// Allows constructing a default value of the type, which also replaces the missing (`null`) values in scenes and prefabs,
+/// and the values rejected by [Validate]
 #[derive(Clone)]
 pub struct MakeDefault {
     make_default: Arc<dyn Fn(ComponentDesc) -> ComponentEntry + Send + Sync>,
@@ -177,6 +307,54 @@ impl<T: ComponentValue, F: 'static + Send + Sync + Fn() -> T> AttributeConstruct
     }
 }
 
+type ValidateFn = Arc<dyn Fn(&mut ComponentEntry) -> Result<(), String> + Send + Sync>;
+
+/// Checks the values of the component which come from outside of the code, such as from scenes, prefabs, the network
+/// and scripts. The function can fix the value in place (e.g. clamp it into range), or reject it with an error.
+/// (e.g. `Validate[|v: &mut f32| { *v = v.clamp(0., 1.); Ok(()) }]`)
+///
+/// See [validate_component] for what happens to the rejected values.
+#[derive(Clone)]
+pub struct Validate {
+    validate: ValidateFn,
+}
+impl ComponentAttribute for Validate {}
+impl Validate {
+    pub fn validate(&self, entry: &mut ComponentEntry) -> Result<(), String> {
+        (self.validate)(entry)
+    }
+}
+impl<T, F> AttributeConstructor<T, F> for Validate
+where
+    T: ComponentValue,
+    F: 'static + Send + Sync + Fn(&mut T) -> Result<(), String>,
+{
+    fn construct(store: &mut AttributeStore, func: F) {
+        store.set(Self { validate: Arc::new(move |entry| func(entry.downcast_mut::<T>())) })
+    }
+}
+
+/// Runs the [Validate] function of the component of `entry`, if it has one. A value which is rejected is replaced with
+/// the default value of the component if it has one ([MakeDefault]), and is an error otherwise.
+pub fn validate_component(mut entry: ComponentEntry) -> Result<ComponentEntry, String> {
+    let desc = entry.desc();
+    let validate = match entry.attribute::<Validate>() {
+        Some(validate) => validate.clone(),
+        None => return Ok(entry),
+    };
+    let err = match validate.validate(&mut entry) {
+        Ok(()) => return Ok(entry),
+        Err(err) => err,
+    };
+    match desc.attribute::<MakeDefault>() {
+        Some(make_default) => {
+            log::warn!("Invalid value for component {}, using the default value instead: {err}", desc.path());
+            Ok(make_default.make_default(desc))
+        }
+        None => Err(format!("Invalid value for component {}: {err}", desc.path())),
+    }
+}
+
 /// Store the component on disc
 ///
 /// Provides `Serializable`
@@ -274,3 +452,239 @@ impl<T: ComponentValue> AttributeConstructor<T, T> for DefaultValue<T> {
         MakeDefault::construct(store, move || value.clone());
     }
 }
+
+/// Don't save the component on disc, even though it's serializable (e.g. because it's [Networked])
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DontStore;
+impl ComponentAttribute for DontStore {}
+impl<T: ComponentValue> AttributeConstructor<T, ()> for DontStore {
+    fn construct(store: &mut AttributeStore, _: ()) {
+        store.set(Self)
+    }
+}
+
+/// Don't synchronize the component to the clients, even though it's serializable (e.g. because it's [Store]d)
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DontNetwork;
+impl ComponentAttribute for DontNetwork {}
+impl<T: ComponentValue> AttributeConstructor<T, ()> for DontNetwork {
+    fn construct(store: &mut AttributeStore, _: ()) {
+        store.set(Self)
+    }
+}
+
+/// What the components of the entities are serialized for, which decides which of them are written. The components
+/// list the profiles they're written for with [SerializationProfiles]; the ones which don't are written for
+/// [SerializationProfile::REPLICATION] if they're [Networked] and not [DontNetwork], and for the other profiles if
+/// they're serializable and not [DontStore].
+///
+/// Games may define profiles of their own (e.g. `SerializationProfile("network_snapshot")`).
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct SerializationProfile(pub &'static str);
+impl SerializationProfile {
+    /// Saving the world, or a scene of it, to disc
+    pub const SAVE_GAME: Self = Self("save_game");
+    /// Synchronizing the world to the clients
+    pub const REPLICATION: Self = Self("replication");
+    /// Copying, pasting and duplicating entities in the editor
+    pub const EDITOR_COPY_PASTE: Self = Self("editor_copy_paste");
+
+    /// Whether the component is written for this profile
+    pub fn includes(&self, desc: ComponentDesc) -> bool {
+        if !desc.has_attribute::<Serializable>() {
+            return false;
+        }
+        if let Some(profiles) = desc.attribute::<SerializationProfiles>() {
+            return profiles.0.contains(self);
+        }
+        if *self == Self::REPLICATION {
+            desc.has_attribute::<Networked>() && !desc.has_attribute::<DontNetwork>()
+        } else {
+            !desc.has_attribute::<DontStore>()
+        }
+    }
+}
+
+/// The only [SerializationProfile]s the component is written for, such as a transient or a render-only component which
+/// is replicated, but doesn't belong in the saves nor in the clipboard.
+/// (e.g. `SerializationProfiles[[SerializationProfile::REPLICATION]]`)
+#[derive(Debug, Clone)]
+pub struct SerializationProfiles(pub Vec<SerializationProfile>);
+impl ComponentAttribute for SerializationProfiles {}
+impl<T: ComponentValue, const N: usize> AttributeConstructor<T, [SerializationProfile; N]> for SerializationProfiles {
+    fn construct(store: &mut AttributeStore, profiles: [SerializationProfile; N]) {
+        store.set(Self(profiles.to_vec()))
+    }
+}
+
+/// The paths the component used to have, so that scenes and worlds saved before it was renamed can still be loaded.
+/// (e.g. `FormerPaths["core::game::hp"]` or `FormerPaths[["core::game::hp", "core::game::hit_points"]]`)
+#[derive(Debug, Clone)]
+pub struct FormerPaths(pub Vec<String>);
+impl ComponentAttribute for FormerPaths {}
+impl<T: ComponentValue> AttributeConstructor<T, &str> for FormerPaths {
+    fn construct(store: &mut AttributeStore, path: &str) {
+        store.set(Self(vec![path.to_string()]))
+    }
+}
+impl<T: ComponentValue, const N: usize> AttributeConstructor<T, [&str; N]> for FormerPaths {
+    fn construct(store: &mut AttributeStore, paths: [&str; N]) {
+        store.set(Self(paths.iter().map(|path| path.to_string()).collect()))
+    }
+}
+
+/// The version of the serialized format of the component, which is saved along with its values. Bump it when the type
+/// of the component changes, and add a [Migrate] function to convert the values saved with the previous versions.
+///
+/// The values which were saved before the component had a version are version 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentVersion(pub u32);
+impl ComponentAttribute for ComponentVersion {}
+impl<T: ComponentValue> AttributeConstructor<T, u32> for ComponentVersion {
+    fn construct(store: &mut AttributeStore, version: u32) {
+        store.set(Self(version))
+    }
+}
+
+/// Converts the values which were saved with an older [ComponentVersion] of the component. The function gets the version
+/// the value was saved with, and a deserializer for the saved value.
+#[derive(Clone)]
+pub struct Migrate {
+    migrate: MigrateFn,
+}
+impl ComponentAttribute for Migrate {}
+impl<T, F> AttributeConstructor<T, F> for Migrate
+where
+    T: ComponentValue,
+    F: 'static + Send + Sync + Fn(u32, &mut dyn erased_serde::Deserializer) -> Result<T, erased_serde::Error>,
+{
+    fn construct(store: &mut AttributeStore, func: F) {
+        store.set(Self {
+            migrate: Arc::new(move |desc, version, deserializer| Ok(ComponentEntry::from_raw_parts(desc, func(version, deserializer)?))),
+        })
+    }
+}
+
+/// The types which can be [Quantized], as a fixed number of floats
+pub trait QuantizedValue: ComponentValue {
+    /// How many floats the values are made of
+    const LEN: usize;
+    fn to_floats(&self) -> Vec<f32>;
+    fn from_floats(floats: &[f32]) -> Self;
+}
+impl QuantizedValue for f32 {
+    const LEN: usize = 1;
+    fn to_floats(&self) -> Vec<f32> {
+        vec![*self]
+    }
+    fn from_floats(floats: &[f32]) -> Self {
+        floats[0]
+    }
+}
+impl QuantizedValue for glam::Vec2 {
+    const LEN: usize = 2;
+    fn to_floats(&self) -> Vec<f32> {
+        self.to_array().to_vec()
+    }
+    fn from_floats(floats: &[f32]) -> Self {
+        Self::from_slice(floats)
+    }
+}
+impl QuantizedValue for glam::Vec3 {
+    const LEN: usize = 3;
+    fn to_floats(&self) -> Vec<f32> {
+        self.to_array().to_vec()
+    }
+    fn from_floats(floats: &[f32]) -> Self {
+        Self::from_slice(floats)
+    }
+}
+impl QuantizedValue for glam::Vec4 {
+    const LEN: usize = 4;
+    fn to_floats(&self) -> Vec<f32> {
+        self.to_array().to_vec()
+    }
+    fn from_floats(floats: &[f32]) -> Self {
+        Self::from_slice(floats)
+    }
+}
+impl QuantizedValue for glam::Quat {
+    const LEN: usize = 4;
+    fn to_floats(&self) -> Vec<f32> {
+        self.to_array().to_vec()
+    }
+    /// The quantized rotation is normalized again, as it's only approximately of unit length
+    fn from_floats(floats: &[f32]) -> Self {
+        Self::from_slice(floats).normalize()
+    }
+}
+
+/// Replicates the changes to the component as deltas of its values rounded to multiples of `step`, which are much
+/// smaller than the full values. (e.g. `Quantized[0.001]` for positions in meters)
+///
+/// Only the changes to the component are quantized; the value is sent as is when the entity is spawned on the client,
+/// or when the component is added.
+#[derive(Clone, Copy)]
+pub struct Quantized {
+    pub step: f32,
+    len: usize,
+    to_floats: fn(&ComponentEntry) -> Vec<f32>,
+    from_floats: fn(ComponentDesc, &[f32]) -> ComponentEntry,
+}
+impl ComponentAttribute for Quantized {}
+impl Quantized {
+    /// How many floats the values are made of
+    pub fn float_count(&self) -> usize {
+        self.len
+    }
+    /// The value of `entry` in multiples of `step`
+    pub fn quantize(&self, entry: &ComponentEntry) -> Vec<i64> {
+        (self.to_floats)(entry).into_iter().map(|value| (value / self.step).round() as i64).collect()
+    }
+    /// The value which was quantized to `values` by [Quantized::quantize]
+    pub fn dequantize(&self, desc: ComponentDesc, values: &[i64]) -> ComponentEntry {
+        let floats = values.iter().map(|&value| value as f32 * self.step).collect::<Vec<_>>();
+        (self.from_floats)(desc, &floats)
+    }
+    /// Whether `entry` can be quantized; infinite and NaN values can't
+    pub fn can_quantize(&self, entry: &ComponentEntry) -> bool {
+        (self.to_floats)(entry).into_iter().all(|value| (value / self.step).is_finite())
+    }
+}
+impl<T: QuantizedValue> AttributeConstructor<T, f32> for Quantized {
+    fn construct(store: &mut AttributeStore, step: f32) {
+        store.set(Self {
+            step,
+            len: T::LEN,
+            to_floats: |entry| entry.downcast_ref::<T>().to_floats(),
+            from_floats: |desc, floats| ComponentEntry::from_raw_parts(desc, T::from_floats(floats)),
+        })
+    }
+}
+
+/// The most times per second the server replicates the changes to the component of an entity; the changes in between
+/// are merged, and only the latest value is sent. (e.g. `ReplicationRate[2.]` for an inventory)
+///
+/// The components without a rate are replicated with every diff.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplicationRate(pub f32);
+impl ComponentAttribute for ReplicationRate {}
+impl<T: ComponentValue> AttributeConstructor<T, f32> for ReplicationRate {
+    fn construct(store: &mut AttributeStore, rate: f32) {
+        assert!(rate > 0., "The replication rate must be positive");
+        store.set(Self(rate))
+    }
+}
+
+/// Which changes the server replicates first when the bandwidth budget of a player doesn't cover all of them: the higher
+/// the priority, the sooner. (e.g. `ReplicationPriority[10]` for the transforms)
+///
+/// The components without a priority have priority 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplicationPriority(pub i32);
+impl ComponentAttribute for ReplicationPriority {}
+impl<T: ComponentValue> AttributeConstructor<T, i32> for ReplicationPriority {
+    fn construct(store: &mut AttributeStore, priority: i32) {
+        store.set(Self(priority))
+    }
+}
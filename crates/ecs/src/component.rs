@@ -270,7 +270,7 @@ impl ComponentDesc {
 
     /// Converts the **value** to json
     pub fn to_json(&self, value: &ComponentEntry) -> Result<String, serde_json::Error> {
-        serde_json::to_string(self.attribute::<Serializable>().expect("Component is not serializable").serialize(value))
+        serde_json::to_string(&self.attribute::<Serializable>().expect("Component is not serializable").serialize(value))
     }
 
     pub fn create_buffer(&self) -> Box<dyn IComponentBuffer> {
@@ -449,7 +449,7 @@ mod test {
         let p = Person { name: "Adam".into(), age: 28 };
         let entry = ComponentEntry::new(person(), p);
 
-        let str = serde_json::to_string_pretty(entry.attribute::<Serializable>().unwrap().serialize(&entry)).unwrap();
+        let str = serde_json::to_string_pretty(&entry.attribute::<Serializable>().unwrap().serialize(&entry)).unwrap();
 
         eprintln!("Serialized: {str}");
 
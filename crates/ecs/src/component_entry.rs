@@ -1,11 +1,14 @@
 use std::{
-    any::{Any, TypeId}, fmt::Debug, mem::{self, ManuallyDrop, MaybeUninit}
+    any::{Any, TypeId},
+    fmt::Debug,
+    mem::{self, ManuallyDrop, MaybeUninit},
 };
 
 use parking_lot::{MappedRwLockReadGuard, MappedRwLockWriteGuard};
 
 use crate::{
-    component_traits::{ComponentBuffer, IComponentBuffer}, get_external_attributes, get_external_attributes_init, AttributeStore, Component, ComponentDesc, ComponentValue
+    component_traits::{ComponentBuffer, IComponentBuffer},
+    get_external_attributes, get_external_attributes_init, AttributeStore, Component, ComponentDesc, ComponentValue,
 };
 
 pub(crate) type ErasedHolder = ManuallyDrop<Box<ComponentHolder<()>>>;
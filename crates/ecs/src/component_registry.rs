@@ -3,6 +3,7 @@ use std::collections::{hash_map::Entry, BTreeMap, HashMap};
 use ambient_std::events::EventDispatcher;
 use once_cell::sync::Lazy;
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use thiserror::Error;
 
 use super::*;
 use crate::ComponentVTable;
@@ -32,7 +33,7 @@ pub(crate) struct RegistryComponent {
     pub(crate) primitive_component: Option<PrimitiveComponent>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ExternalComponentDesc {
     pub path: String,
     pub ty: PrimitiveComponentType,
@@ -101,6 +102,28 @@ macro_rules! define_external_component_attribute_flags {
 
 define_external_component_attribute_flags![(debuggable, Debuggable), (networked, Networked), (resource, Resource), (store, Store)];
 
+/// Everything the registry knows about a component, for tools (inspectors, schemas) and scripting interop.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ComponentReflection {
+    pub index: u32,
+    pub path: String,
+    /// The Rust type of the component's value.
+    pub type_name: String,
+    /// The type of the component's value, if it's one that can be used from scripts.
+    pub primitive_type: Option<PrimitiveComponentType>,
+    /// Whether the component was registered at runtime, instead of being defined in Rust.
+    pub external: bool,
+    pub attributes: ExternalComponentAttributes,
+}
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum ComponentRegistryError {
+    #[error("Invalid component path {path:?}; expected identifiers separated by `::`")]
+    InvalidPath { path: String },
+    #[error("Component {path} is already registered as a {existing}, not a {requested:?}")]
+    TypeMismatch { path: String, existing: String, requested: PrimitiveComponentType },
+}
+
 #[derive(Default)]
 pub struct ComponentRegistry {
     pub(crate) components: Vec<RegistryComponent>,
@@ -121,7 +144,10 @@ impl ComponentRegistry {
 
     pub fn add_external(&mut self, components: Vec<ExternalComponentDesc>) {
         for desc in components {
-            desc.ty.register(self, &desc.path, desc.attributes);
+            let path = desc.path.clone();
+            if let Err(err) = self.try_register(desc) {
+                log::error!("Failed to register external component {path}: {err}");
+            }
         }
 
         for handler in self.on_external_components_change.iter() {
@@ -129,6 +155,35 @@ impl ComponentRegistry {
         }
     }
 
+    /// Registers a single component at runtime from its description, notifying the `on_external_components_change`
+    /// handlers. Registering a component that already exists with the same type updates its attributes.
+    pub fn register_from_desc(&mut self, desc: ExternalComponentDesc) -> Result<ComponentDesc, ComponentRegistryError> {
+        let component = self.try_register(desc)?;
+        for handler in self.on_external_components_change.iter() {
+            handler();
+        }
+        Ok(component)
+    }
+
+    fn try_register(&mut self, desc: ExternalComponentDesc) -> Result<ComponentDesc, ComponentRegistryError> {
+        let valid_path =
+            desc.path.split("::").all(|segment| !segment.is_empty() && segment.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+        if !valid_path {
+            return Err(ComponentRegistryError::InvalidPath { path: desc.path });
+        }
+        if let Some(index) = self.path_to_index(&desc.path) {
+            let existing = &self.components[index as usize];
+            if existing.primitive_component.as_ref().map(|pc| pc.ty) != Some(desc.ty) {
+                return Err(ComponentRegistryError::TypeMismatch {
+                    existing: existing.desc.type_name().to_string(),
+                    path: desc.path,
+                    requested: desc.ty,
+                });
+            }
+        }
+        Ok(desc.ty.register(self, &desc.path, desc.attributes))
+    }
+
     fn register(&mut self, path: String, vtable: &'static ComponentVTable<()>, attributes: Option<AttributeStore>) -> ComponentDesc {
         if let Some(vpath) = vtable.path {
             assert_eq!(path, vpath, "Static name does not match provided name");
@@ -187,9 +242,16 @@ impl ComponentRegistry {
         self.component_paths.get(path).copied()
     }
 
+    /// Finds a component by its path, or by one of its [FormerPaths] if it was renamed
     pub fn get_by_path(&self, path: &str) -> Option<ComponentDesc> {
-        let index = *self.component_paths.get(path)?;
-        Some(self.components[index as usize].desc)
+        match self.component_paths.get(path) {
+            Some(&index) => Some(self.components[index as usize].desc),
+            None => self
+                .components
+                .iter()
+                .map(|component| component.desc)
+                .find(|desc| matches!(desc.attribute::<FormerPaths>(), Some(former) if former.0.iter().any(|former| former == path))),
+        }
     }
 
     pub fn get_by_index(&self, index: u32) -> Option<ComponentDesc> {
@@ -223,6 +285,22 @@ impl ComponentRegistry {
         self.components.iter().map(|v| v.desc)
     }
 
+    pub fn reflect(&self, desc: ComponentDesc) -> ComponentReflection {
+        ComponentReflection {
+            index: desc.index(),
+            path: desc.path(),
+            type_name: desc.type_name().to_string(),
+            primitive_type: self.components[desc.index() as usize].primitive_component.as_ref().map(|pc| pc.ty),
+            external: desc.has_attribute::<External>(),
+            attributes: ExternalComponentAttributes::from_existing_component(desc),
+        }
+    }
+
+    /// Returns the reflection of all registered components, in the order they were registered.
+    pub fn reflect_all(&self) -> impl Iterator<Item = ComponentReflection> + '_ {
+        self.all().map(|desc| self.reflect(desc))
+    }
+
     pub fn component_count(&self) -> usize {
         self.components.len()
     }
@@ -1,7 +1,9 @@
 use std::fmt;
 
 use serde::{
-    de::{self, SeqAccess, Visitor}, ser::SerializeTuple, Deserializer, Serializer
+    de::{self, SeqAccess, Visitor},
+    ser::SerializeTuple,
+    Deserializer, Serializer,
 };
 
 use super::*;
@@ -1,5 +1,6 @@
 use std::{
     self,
+    collections::HashMap,
     fmt::{self, Debug},
     iter::Flatten,
 };
@@ -13,7 +14,10 @@ use serde::{
 };
 
 use super::{with_component_registry, Component, ComponentValue, ECSError, EntityId, World};
-use crate::{ComponentDesc, ComponentEntry, ComponentSet, ECSDeserializationWarnings, Serializable};
+use crate::{
+    validate_component, ComponentDesc, ComponentEntry, ComponentSet, ECSDeserializationWarnings, MakeDefault, Serializable,
+    SerializationProfile,
+};
 
 #[derive(Clone)]
 pub struct EntityData {
@@ -125,6 +129,44 @@ impl EntityData {
         }
     }
 
+    /// Replaces the ids which are in `ids` with their new ids in all the [EntityId], `Vec<EntityId>` and
+    /// `Option<EntityId>` components
+    pub fn remap_entity_ids(&mut self, ids: &HashMap<EntityId, EntityId>) {
+        let remap = |id: &mut EntityId| {
+            if let Some(&new_id) = ids.get(id) {
+                *id = new_id;
+            }
+        };
+        for desc in self.components() {
+            if desc.is::<EntityId>() {
+                if let Some(id) = self.get_mut(Component::<EntityId>::new(desc)) {
+                    remap(id);
+                }
+            } else if desc.is::<Vec<EntityId>>() {
+                if let Some(ids) = self.get_mut(Component::<Vec<EntityId>>::new(desc)) {
+                    ids.iter_mut().for_each(remap);
+                }
+            } else if desc.is::<Option<EntityId>>() {
+                if let Some(Some(id)) = self.get_mut(Component::<Option<EntityId>>::new(desc)) {
+                    remap(id);
+                }
+            }
+        }
+    }
+
+    /// Runs [validate_component] on all the components, and removes the values which are rejected. Returns the errors of
+    /// the removed values.
+    pub fn validate(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for entry in std::mem::take(self) {
+            match validate_component(entry) {
+                Ok(entry) => self.set_entry(entry),
+                Err(err) => errors.push(err),
+            }
+        }
+        errors
+    }
+
     pub fn components(&self) -> Vec<ComponentDesc> {
         self.content.iter().map(|x| x.desc()).collect_vec()
     }
@@ -166,6 +208,11 @@ impl EntityData {
         }
         self
     }
+    /// Removes the components which aren't written for `profile`
+    pub fn for_profile(mut self, profile: SerializationProfile) -> Self {
+        self.filter(&|desc| profile.includes(desc));
+        self
+    }
     pub fn len(&self) -> usize {
         self.content.len()
     }
@@ -290,10 +337,17 @@ impl<'de> Deserialize<'de> for DeserEntityDataWithWarnings {
                     let value = ser.deserializer(desc).deserialize(value);
                     let value = match value {
                         Ok(v) => v,
-                        Err(err) => {
-                            self.warnings.push((EntityId::null(), key, format!("{err:?}")));
-                            continue;
-                        }
+                        // Malformed values fall back to the default value of the component
+                        Err(err) => match desc.attribute::<MakeDefault>() {
+                            Some(make_default) => {
+                                self.warnings.push((EntityId::null(), key, format!("{err:?}, using the default value instead")));
+                                make_default.make_default(desc)
+                            }
+                            None => {
+                                self.warnings.push((EntityId::null(), key, format!("{err:?}")));
+                                continue;
+                            }
+                        },
                     };
 
                     res.set_entry(value);
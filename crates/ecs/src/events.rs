@@ -114,6 +114,118 @@ pub struct DBEventId {
     index: usize,
 }
 
+/// A typed event channel, which systems can send events to and read them from with an [EventReader], instead of sharing
+/// ad-hoc queues. It's meant to be a resource of a world, added with [World::add_events], which updates it on every
+/// [World::next_frame] or [World::update_event_channels].
+///
+/// The events are double buffered: they're kept for the frame they're sent in and the next one, so a reader sees every
+/// event once whether it runs before or after the system sending it, as long as it reads at least once per frame.
+#[derive(Debug, Clone)]
+pub struct Events<T> {
+    previous: Vec<T>,
+    current: Vec<T>,
+    /// The number of events sent before the first one of `previous`
+    start: usize,
+}
+impl<T> Events<T> {
+    pub fn new() -> Self {
+        Self { previous: Vec::new(), current: Vec::new(), start: 0 }
+    }
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        self.current.extend(events);
+    }
+    /// Drops the events of the previous frame, and keeps the ones of this frame for one more frame
+    pub fn update(&mut self) {
+        self.start += self.previous.len();
+        std::mem::swap(&mut self.previous, &mut self.current);
+        self.current.clear();
+    }
+    /// Drops all the events, without the readers missing any of the ones sent afterwards
+    pub fn clear(&mut self) {
+        self.start += self.len();
+        self.previous.clear();
+        self.current.clear();
+    }
+    /// The number of events of the previous frame and this one
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// All the events of the previous frame and this one, regardless of what has been read
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.previous.iter().chain(self.current.iter())
+    }
+    fn end(&self) -> usize {
+        self.start + self.len()
+    }
+}
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads the events sent to an [Events] channel since it last read from it. Each system reading a channel should have
+/// its own reader.
+#[derive(Debug, Clone)]
+pub struct EventReader<T> {
+    read: usize,
+    _type: PhantomData<T>,
+}
+impl<T> EventReader<T> {
+    pub fn new() -> Self {
+        Self { read: 0, _type: PhantomData }
+    }
+    /// Skips all the events which have been sent so far
+    pub fn move_to_end(&mut self, events: &Events<T>) {
+        self.read = events.end();
+    }
+    /// Returns the events sent since the last read. Events which were dropped before this reader got to them are skipped.
+    pub fn iter<'a>(&mut self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> {
+        let skip = self.read.saturating_sub(events.start);
+        self.move_to_end(events);
+        events.iter().skip(skip)
+    }
+}
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) type EventsUpdate = fn(&mut World, ComponentDesc);
+fn update_events<T: ComponentValue>(world: &mut World, desc: ComponentDesc) {
+    if let Some(events) = world.resource_mut_opt(Component::<Events<T>>::new(desc)) {
+        events.update();
+    }
+}
+
+impl World {
+    /// Adds an empty [Events] channel as a resource of this world, which is then updated on every [World::next_frame]
+    pub fn add_events<T: ComponentValue>(&mut self, component: Component<Events<T>>) {
+        self.add_resource(component, Events::new());
+        if !self.event_channels.iter().any(|&(desc, _)| desc == component.desc()) {
+            self.event_channels.push((component.desc(), update_events::<T>));
+        }
+    }
+    /// Sends an event to a channel added with [World::add_events]
+    pub fn send_event<T: ComponentValue>(&mut self, component: Component<Events<T>>, event: T) {
+        self.resource_mut(component).send(event);
+    }
+    /// Updates the [Events] channels of this world, which [World::next_frame] does; worlds which don't go through
+    /// [World::next_frame] call this once per frame instead
+    pub fn update_event_channels(&mut self) {
+        for (desc, update) in self.event_channels.clone() {
+            update(self, desc);
+        }
+    }
+}
+
 #[test]
 fn test_events() {
     let mut events = FramedEvents::new_with_history_size(5);
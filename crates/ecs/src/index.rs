@@ -1,5 +1,8 @@
 use std::{
-    cmp::Ordering, collections::{btree_set::Range, BTreeSet, HashMap}, fmt::Debug, ops::RangeBounds
+    cmp::Ordering,
+    collections::{btree_set::Range, BTreeSet, HashMap},
+    fmt::Debug,
+    ops::RangeBounds,
 };
 
 use itertools::Itertools;
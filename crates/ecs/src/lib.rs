@@ -12,12 +12,13 @@ use ambient_std::sparse_vec::SparseVec;
 use anyhow::Context;
 use bit_set::BitSet;
 use bit_vec::BitVec;
-use itertools::Itertools;
 /// Expose to macros
 #[doc(hidden)]
-pub use once_cell::sync::OnceCell;
+pub use erased_serde;
+use itertools::Itertools;
 /// Expose to macros
 #[doc(hidden)]
+pub use once_cell::sync::OnceCell;
 pub use parking_lot;
 use parking_lot::Mutex;
 /// Expose to macros
@@ -39,7 +40,9 @@ mod index;
 mod location;
 mod primitive_component;
 mod query;
+mod relationship;
 mod serialization;
+mod snapshot;
 mod stream;
 pub use archetype::*;
 pub use attributes::*;
@@ -53,7 +56,9 @@ pub use index::*;
 pub use location::*;
 pub use primitive_component::*;
 pub use query::*;
+pub use relationship::*;
 pub use serialization::*;
+pub use snapshot::*;
 pub use stream::*;
 
 pub struct DebugWorldArchetypes<'a> {
@@ -112,6 +117,8 @@ pub struct World {
     loc_changed: FramedEvents<EntityId>,
     version: CloneableAtomicU64,
     shape_change_events: Option<FramedEvents<WorldChange>>,
+    /// The [Events] resources to update on every frame
+    event_channels: Vec<(ComponentDesc, EventsUpdate)>,
     /// Used for reset_events. Prevents change events in queries when you use reset_events
     ignore_query_inits: bool,
     query_ticker: CloneableAtomicU64,
@@ -131,6 +138,7 @@ impl World {
             loc_changed: FramedEvents::new(),
             version: CloneableAtomicU64::new(0),
             shape_change_events: None,
+            event_channels: Vec::new(),
             ignore_query_inits: false,
             query_ticker: CloneableAtomicU64::new(0),
         };
@@ -235,6 +243,7 @@ impl World {
         if let Some(events) = &mut self.shape_change_events {
             events.next_frame();
         }
+        self.update_event_channels();
         self.ignore_query_inits = false;
     }
 
@@ -515,6 +524,50 @@ impl World {
         }
         old_to_new_ids.into_values().collect()
     }
+    /// Loads all the entities of `other` (except its resources) into this world, next to the entities already in it, and
+    /// returns the ids they got by their ids in `other`.
+    ///
+    /// The ids of the entities which are not kept (see [MergePolicy]) are updated in all the [EntityId] components of the
+    /// merged entities.
+    pub fn merge(&mut self, other: &World, policy: MergePolicy) -> HashMap<EntityId, EntityId> {
+        let entities = other.entities().into_iter().filter(|(id, _)| *id != other.resource_entity()).collect_vec();
+        let ids = entities
+            .iter()
+            .map(|(id, _)| {
+                let new_id = match policy {
+                    MergePolicy::Remap => EntityId::new(),
+                    MergePolicy::KeepIds if self.exists(*id) => EntityId::new(),
+                    _ => *id,
+                };
+                (*id, new_id)
+            })
+            .collect::<HashMap<_, _>>();
+
+        for (id, mut entity) in entities {
+            let new_id = ids[&id];
+            entity.remap_entity_ids(&ids);
+            if !self.exists(new_id) {
+                self.spawn_with_id(new_id, entity);
+                continue;
+            }
+            match policy {
+                MergePolicy::Replace => {
+                    self.despawn(new_id);
+                    self.spawn_with_id(new_id, entity);
+                }
+                MergePolicy::Merge => self.add_components(new_id, entity).unwrap(),
+                MergePolicy::Remap | MergePolicy::KeepIds | MergePolicy::Skip => {}
+            }
+        }
+
+        let migraters = COMPONENT_ENTITY_ID_MIGRATERS.lock();
+        for migrater in migraters.iter() {
+            for id in ids.values() {
+                migrater(self, *id, &ids);
+            }
+        }
+        ids
+    }
     fn version(&self) -> u64 {
         self.version.0.load(Ordering::Relaxed)
     }
@@ -595,6 +648,25 @@ impl std::fmt::Debug for World {
 unsafe impl Send for World {}
 unsafe impl Sync for World {}
 
+/// What [World::merge] does with the entities of the other world whose ids are already used in this world
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    /// All the merged entities get new ids, so nothing collides. Use it to load the same entities several times, such
+    /// as a chunk of a level which is repeated.
+    #[default]
+    Remap,
+    /// The merged entities keep their ids, except for those whose id is already used, which get new ids
+    KeepIds,
+    /// The merged entities keep their ids, and those whose id is already used are not merged: references to them refer to
+    /// the entities already in the world
+    Skip,
+    /// The merged entities keep their ids, and those whose id is already used replace the entities already in the world
+    Replace,
+    /// The merged entities keep their ids, and the components of those whose id is already used are added to the entities
+    /// already in the world, replacing the components they both have
+    Merge,
+}
+
 // TODO(fred): Move this into the actual components instead
 pub static COMPONENT_ENTITY_ID_MIGRATERS: Mutex<Vec<fn(&mut World, EntityId, &HashMap<EntityId, EntityId>)>> = Mutex::new(Vec::new());
 
@@ -606,6 +678,8 @@ pub enum ECSError {
     NoSuchEntity { entity_id: EntityId },
     #[error("Attempted to add resource component `{component_path}` to non-resource entity {entity_id}")]
     AddedResourceToEntity { component_path: String, entity_id: EntityId },
+    #[error("Can't make {parent_id} the parent of {entity_id}, as it's {entity_id} or one of its descendants")]
+    HierarchyCycle { entity_id: EntityId, parent_id: EntityId },
 }
 
 struct MapEntity {
@@ -1,10 +1,13 @@
 use std::{
-    fmt::{self, Debug}, hash::{BuildHasher, Hasher}, str::FromStr
+    fmt::{self, Debug},
+    hash::{BuildHasher, Hasher},
+    str::FromStr,
 };
 
 use data_encoding::BASE64URL_NOPAD;
 use serde::{
-    de::{self, Visitor}, Deserialize, Deserializer, Serialize, Serializer
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
 };
 
 #[derive(Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
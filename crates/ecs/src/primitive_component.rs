@@ -129,7 +129,7 @@ macro_rules! make_primitive_component {
                     }
                 }
 
-                pub(crate) fn register(&self, reg: &mut ComponentRegistry, path: &str, attributes: ExternalComponentAttributes) {
+                pub(crate) fn register(&self, reg: &mut ComponentRegistry, path: &str, attributes: ExternalComponentAttributes) -> ComponentDesc {
                     let mut store = AttributeStore::new();
                     let vtable = match self {
                         $(
@@ -145,7 +145,7 @@ macro_rules! make_primitive_component {
                         )*
                     };
 
-                    reg.register_external(path.into(), vtable, store);
+                    reg.register_external(path.into(), vtable, store)
                 }
             }
             impl PartialEq<PrimitiveComponentType> for PrimitiveComponent {
@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-use itertools::Itertools;
+use itertools::{Either, Itertools};
 
 use super::*;
 
@@ -69,12 +69,24 @@ pub trait ComponentQuery<'a>: Send + Sync {
     type Data;
     type DataMut;
     type DataCloned;
+    /// The values of the components of an archetype, see [ComponentQuery::fetch]
+    type Fetch;
+    type FetchMut;
 
     fn write_component_ids(&self, set: &mut ComponentSet);
     fn get_change_filtered(&self, out: &mut Vec<ComponentDesc>);
+    fn get_added_filtered(&self, out: &mut Vec<ComponentDesc>);
+    fn get_removed_filtered(&self, out: &mut Vec<ComponentDesc>);
     fn get_data(&self, world: &'a World, acc: &EntityAccessor) -> Self::Data;
     fn get_data_mut(&self, world: &'a World, acc: &EntityAccessor) -> Self::DataMut;
     fn get_data_cloned(&self, world: &'a World, acc: &EntityAccessor) -> Self::DataCloned;
+    /// Looks up the components in `arch` once, so that the data of its entities can be read by index with
+    /// [ComponentQuery::get_fetched] when iterating over it
+    fn fetch(&self, arch: &'a Archetype) -> Self::Fetch;
+    fn fetch_mut(&self, world: &'a World, arch: &'a Archetype) -> Self::FetchMut;
+    fn get_fetched(fetch: &Self::Fetch, index: usize) -> Self::Data;
+    fn get_fetched_mut(fetch: &mut Self::FetchMut, index: usize) -> Self::DataMut;
+    fn get_fetched_cloned(fetch: &Self::Fetch, index: usize) -> Self::DataCloned;
 }
 
 pub trait ComponentsTupleAppend<T: ComponentValue> {
@@ -89,6 +101,8 @@ macro_rules! tuple_impls {
             type Data       = ($($name::Data,)*);
             type DataMut    = ($($name::DataMut,)*);
             type DataCloned = ($($name::DataCloned,)*);
+            type Fetch      = ($($name::Fetch,)*);
+            type FetchMut   = ($($name::FetchMut,)*);
 
             fn write_component_ids(&self, set: &mut ComponentSet) {
                 #[allow(non_snake_case)]
@@ -102,6 +116,18 @@ macro_rules! tuple_impls {
                 $($name.get_change_filtered(out);)*
             }
 
+            fn get_added_filtered(&self, out: &mut Vec<ComponentDesc>) {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = self;
+                $($name.get_added_filtered(out);)*
+            }
+
+            fn get_removed_filtered(&self, out: &mut Vec<ComponentDesc>) {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = self;
+                $($name.get_removed_filtered(out);)*
+            }
+
             fn get_data(&self, world: &'a World, acc: &EntityAccessor) -> Self::Data {
                 #[allow(non_snake_case)]
                 let ($($name,)*) = self;
@@ -119,6 +145,36 @@ macro_rules! tuple_impls {
                 let ($($name,)*) = self;
                 ($($name.get_data_cloned(world, acc),)*)
             }
+
+            fn fetch(&self, arch: &'a Archetype) -> Self::Fetch {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = self;
+                ($($name.fetch(arch),)*)
+            }
+
+            fn fetch_mut(&self, world: &'a World, arch: &'a Archetype) -> Self::FetchMut {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = self;
+                ($($name.fetch_mut(world, arch),)*)
+            }
+
+            fn get_fetched(fetch: &Self::Fetch, index: usize) -> Self::Data {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = fetch;
+                ($($name::get_fetched($name, index),)*)
+            }
+
+            fn get_fetched_mut(fetch: &mut Self::FetchMut, index: usize) -> Self::DataMut {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = fetch;
+                ($($name::get_fetched_mut($name, index),)*)
+            }
+
+            fn get_fetched_cloned(fetch: &Self::Fetch, index: usize) -> Self::DataCloned {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = fetch;
+                ($($name::get_fetched_cloned($name, index),)*)
+            }
         }
 
         impl<T: ComponentValue, $($name: Clone),*> ComponentsTupleAppend<T> for ($($name,)*) {
@@ -143,42 +199,81 @@ tuple_impls! { A B C D E F G H }
 tuple_impls! { A B C D E F G H I }
 
 impl<T: ComponentValue> Component<T> {
-    pub fn changed(self) -> ChangedQuery<T> {
-        ChangedQuery { component: self }
+    /// Only visit the entities whose value of this component changed since the query last ran (or which started matching it)
+    pub fn changed(self) -> FilteredComponent<T> {
+        FilteredComponent { component: self, filter: ComponentFilter::Changed }
+    }
+    /// Only visit the entities which started matching the query since it last ran, because they were spawned with this
+    /// component, or it (or another component the query requires) was added to them
+    pub fn added(self) -> FilteredComponent<T> {
+        FilteredComponent { component: self, filter: ComponentFilter::Added }
+    }
+    /// Only visit the entities which stopped matching the query since it last ran, because they were despawned, or this
+    /// component (or another component the query requires) was removed from them. Their last values can still be read.
+    pub fn removed(self) -> FilteredComponent<T> {
+        FilteredComponent { component: self, filter: ComponentFilter::Removed }
     }
 }
 
-pub struct ChangedQuery<T: 'static> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentFilter {
+    Changed,
+    Added,
+    Removed,
+}
+
+/// A component of a typed query which also restricts the entities it visits; see [Component::changed], [Component::added]
+/// and [Component::removed]
+pub struct FilteredComponent<T: 'static> {
     component: Component<T>,
+    filter: ComponentFilter,
 }
 
-impl<T> Clone for ChangedQuery<T> {
+impl<T> Clone for FilteredComponent<T> {
     fn clone(&self) -> Self {
-        Self { component: self.component }
+        *self
     }
 }
 
-impl<T> Debug for ChangedQuery<T> {
+impl<T> Debug for FilteredComponent<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_struct("ChangedQuery").field("component", &self.component).finish()
+        f.debug_struct("FilteredComponent").field("component", &self.component).field("filter", &self.filter).finish()
     }
 }
 
-impl<T> Copy for ChangedQuery<T> {}
+impl<T> Copy for FilteredComponent<T> {}
 
-impl<'a, T: ComponentValue> ComponentQuery<'a> for ChangedQuery<T> {
+impl<'a, T: ComponentValue> ComponentQuery<'a> for FilteredComponent<T> {
     type Data = &'a T;
 
     type DataMut = &'a mut T;
 
     type DataCloned = T;
 
+    type Fetch = &'a [T];
+
+    type FetchMut = ArchetypeFetchMut<'a, T>;
+
     fn write_component_ids(&self, set: &mut ComponentSet) {
         self.component.write_component_ids(set)
     }
 
     fn get_change_filtered(&self, out: &mut Vec<ComponentDesc>) {
-        out.push(self.component.desc())
+        if self.filter == ComponentFilter::Changed {
+            out.push(self.component.desc())
+        }
+    }
+
+    fn get_added_filtered(&self, out: &mut Vec<ComponentDesc>) {
+        if self.filter == ComponentFilter::Added {
+            out.push(self.component.desc())
+        }
+    }
+
+    fn get_removed_filtered(&self, out: &mut Vec<ComponentDesc>) {
+        if self.filter == ComponentFilter::Removed {
+            out.push(self.component.desc())
+        }
     }
 
     fn get_data(&self, world: &'a World, acc: &EntityAccessor) -> Self::Data {
@@ -192,6 +287,26 @@ impl<'a, T: ComponentValue> ComponentQuery<'a> for ChangedQuery<T> {
     fn get_data_cloned(&self, world: &'a World, acc: &EntityAccessor) -> Self::DataCloned {
         self.component.get_data_cloned(world, acc)
     }
+
+    fn fetch(&self, arch: &'a Archetype) -> Self::Fetch {
+        self.component.fetch(arch)
+    }
+
+    fn fetch_mut(&self, world: &'a World, arch: &'a Archetype) -> Self::FetchMut {
+        self.component.fetch_mut(world, arch)
+    }
+
+    fn get_fetched(fetch: &Self::Fetch, index: usize) -> Self::Data {
+        Component::<T>::get_fetched(fetch, index)
+    }
+
+    fn get_fetched_mut(fetch: &mut Self::FetchMut, index: usize) -> Self::DataMut {
+        Component::<T>::get_fetched_mut(fetch, index)
+    }
+
+    fn get_fetched_cloned(fetch: &Self::Fetch, index: usize) -> Self::DataCloned {
+        Component::<T>::get_fetched_cloned(fetch, index)
+    }
 }
 
 impl<'a, T: ComponentValue> ComponentQuery<'a> for Component<T> {
@@ -201,12 +316,20 @@ impl<'a, T: ComponentValue> ComponentQuery<'a> for Component<T> {
 
     type DataCloned = T;
 
+    type Fetch = &'a [T];
+
+    type FetchMut = ArchetypeFetchMut<'a, T>;
+
     fn write_component_ids(&self, set: &mut ComponentSet) {
         set.insert(self.desc());
     }
 
     fn get_change_filtered(&self, _: &mut Vec<ComponentDesc>) {}
 
+    fn get_added_filtered(&self, _: &mut Vec<ComponentDesc>) {}
+
+    fn get_removed_filtered(&self, _: &mut Vec<ComponentDesc>) {}
+
     fn get_data(&self, world: &'a World, acc: &EntityAccessor) -> Self::Data {
         acc.get(world, *self)
     }
@@ -218,18 +341,48 @@ impl<'a, T: ComponentValue> ComponentQuery<'a> for Component<T> {
     fn get_data_cloned(&self, world: &'a World, acc: &EntityAccessor) -> Self::DataCloned {
         acc.get(world, *self).clone()
     }
+
+    fn fetch(&self, arch: &'a Archetype) -> Self::Fetch {
+        &arch.get_component_buffer(*self).expect("Archetype doesn't have the component").data
+    }
+
+    fn fetch_mut(&self, world: &'a World, arch: &'a Archetype) -> Self::FetchMut {
+        ArchetypeFetchMut::new(world, arch, *self)
+    }
+
+    fn get_fetched(fetch: &Self::Fetch, index: usize) -> Self::Data {
+        let data: &'a [T] = fetch;
+        &data[index]
+    }
+
+    fn get_fetched_mut(fetch: &mut Self::FetchMut, index: usize) -> Self::DataMut {
+        fetch.get(index)
+    }
+
+    fn get_fetched_cloned(fetch: &Self::Fetch, index: usize) -> Self::DataCloned {
+        fetch[index].clone()
+    }
 }
 
 impl<'a> ComponentQuery<'a> for () {
     type Data = ();
     type DataMut = ();
     type DataCloned = ();
+    type Fetch = ();
+    type FetchMut = ();
 
     fn write_component_ids(&self, _: &mut ComponentSet) {}
     fn get_change_filtered(&self, _: &mut Vec<ComponentDesc>) {}
+    fn get_added_filtered(&self, _: &mut Vec<ComponentDesc>) {}
+    fn get_removed_filtered(&self, _: &mut Vec<ComponentDesc>) {}
     fn get_data(&self, _: &World, _: &EntityAccessor) -> Self::Data {}
     fn get_data_mut(&self, _: &World, _: &EntityAccessor) -> Self::DataMut {}
     fn get_data_cloned(&self, _: &World, _: &EntityAccessor) -> Self::DataCloned {}
+    fn fetch(&self, _: &Archetype) -> Self::Fetch {}
+    fn fetch_mut(&self, _: &World, _: &Archetype) -> Self::FetchMut {}
+    fn get_fetched(_: &Self::Fetch, _: usize) -> Self::Data {}
+    fn get_fetched_mut(_: &mut Self::FetchMut, _: usize) -> Self::DataMut {}
+    fn get_fetched_cloned(_: &Self::Fetch, _: usize) -> Self::DataCloned {}
 }
 impl<T: ComponentValue> ComponentsTupleAppend<T> for () {
     type Output = (Component<T>,);
@@ -248,6 +401,9 @@ pub struct QueryState {
     entered: HashSet<EntityId>,
     world_version: u64,
     entities: Vec<EntityAccessor>,
+    /// The archetypes matching the query, among the first `archetypes_checked` archetypes of the world
+    archetypes: Vec<ArchetypeId>,
+    archetypes_checked: usize,
 }
 impl QueryState {
     pub fn new() -> Self {
@@ -260,6 +416,8 @@ impl QueryState {
             entered: Default::default(),
             world_version: 0,
             entities: Vec::new(),
+            archetypes: Vec::new(),
+            archetypes_checked: 0,
         }
     }
     pub(super) fn get_change_reader(&mut self, arch: usize, comp: usize) -> &mut FramedEventsReader<EntityId> {
@@ -319,10 +477,27 @@ impl Query {
         q
     }
 
-    fn new_for_typed_query(component_ids: ComponentSet, changed_components: Vec<ComponentDesc>) -> Self {
+    fn new_for_typed_query(
+        component_ids: ComponentSet,
+        changed_components: Vec<ComponentDesc>,
+        added_components: Vec<ComponentDesc>,
+        removed_components: Vec<ComponentDesc>,
+    ) -> Self {
+        let n_filters = [&changed_components, &added_components, &removed_components].iter().filter(|c| !c.is_empty()).count();
+        if n_filters > 1 {
+            panic!("A query can only filter on either changed, added or removed components");
+        }
         Query {
             filter: ArchetypeFilter { components: component_ids, not_components: ComponentSet::new() },
-            event: if !changed_components.is_empty() { QueryEvent::Changed { components: changed_components } } else { QueryEvent::Frame },
+            event: if !changed_components.is_empty() {
+                QueryEvent::Changed { components: changed_components }
+            } else if !added_components.is_empty() {
+                QueryEvent::Spawned
+            } else if !removed_components.is_empty() {
+                QueryEvent::Despawned
+            } else {
+                QueryEvent::Frame
+            },
         }
     }
 
@@ -384,8 +559,9 @@ impl Query {
         self
     }
     fn get_changed(&self, world: &World, state: &mut QueryState, components: &Vec<ComponentDesc>) {
+        let archetypes = self.take_archetypes(world, state);
         if !state.inited && !world.ignore_query_inits {
-            for arch in self.filter.iter_by_archetypes(&world.archetypes) {
+            for arch in archetypes.iter().map(|&id| &world.archetypes[id]) {
                 for comp in components {
                     if let Some(arch_comp) = arch.components.get(comp.index() as _) {
                         let events = &*arch_comp.changes.borrow();
@@ -394,9 +570,10 @@ impl Query {
                     }
                 }
             }
+            state.archetypes = archetypes;
             return;
         }
-        for arch in self.filter.iter_by_archetypes(&world.archetypes) {
+        for arch in archetypes.iter().map(|&id| &world.archetypes[id]) {
             for comp in components {
                 if let Some(arch_comp) = arch.components.get(comp.index() as _) {
                     let read = state.get_change_reader(arch.id, comp.index() as _);
@@ -414,6 +591,7 @@ impl Query {
                 }
             }
         }
+        state.archetypes = archetypes;
     }
     fn get_spawned(&self, world: &World, state: &mut QueryState) {
         if self.init_state_event_readers(world, state) {
@@ -425,7 +603,8 @@ impl Query {
             return;
         }
         state.entities.clear();
-        for arch in self.filter.iter_by_archetypes(&world.archetypes) {
+        let archetypes = self.take_archetypes(world, state);
+        for arch in archetypes.iter().map(|&id| &world.archetypes[id]) {
             let read = state.get_movein_reader(arch.id);
             for (_, id) in read.iter(&arch.movein_events) {
                 if let Some(loc) = world.locs.get(id) {
@@ -444,6 +623,7 @@ impl Query {
                 }
             }
         }
+        state.archetypes = archetypes;
     }
     fn get_despawned(&self, world: &World, state: &mut QueryState) {
         if self.init_state_event_readers(world, state) {
@@ -451,7 +631,8 @@ impl Query {
         }
 
         state.entities.clear();
-        for arch in self.filter.iter_by_archetypes(&world.archetypes) {
+        let archetypes = self.take_archetypes(world, state);
+        for arch in archetypes.iter().map(|&id| &world.archetypes[id]) {
             let read = state.get_moveout_reader(arch.id);
             for (event_id, (id, _)) in read.iter(&arch.moveout_events) {
                 let next_matched = if let Some(loc) = world.locs.get(id) {
@@ -465,22 +646,59 @@ impl Query {
                 }
             }
         }
+        state.archetypes = archetypes;
     }
     fn init_state_event_readers(&self, world: &World, state: &mut QueryState) -> bool {
         if state.inited || world.ignore_query_inits {
             return false;
         }
-        for arch in self.filter.iter_by_archetypes(&world.archetypes) {
+        let archetypes = self.take_archetypes(world, state);
+        for arch in archetypes.iter().map(|&id| &world.archetypes[id]) {
             let read_in = state.get_movein_reader(arch.id);
             read_in.move_to_end(&arch.movein_events);
             let read_out = state.get_moveout_reader(arch.id);
             read_out.move_to_end(&arch.moveout_events);
         }
+        state.archetypes = archetypes;
         true
     }
+    /// Updates the archetypes matching this query which are cached in the `state`: only the archetypes which were created
+    /// since the last update are matched against the filter.
+    fn update_archetypes(&self, world: &World, state: &mut QueryState) {
+        if state.archetypes_checked > world.archetypes.len() {
+            state.archetypes.clear();
+            state.archetypes_checked = 0;
+        }
+        for arch in &world.archetypes[state.archetypes_checked..] {
+            if self.filter.matches(&arch.active_components) {
+                state.archetypes.push(arch.id);
+            }
+        }
+        state.archetypes_checked = world.archetypes.len();
+    }
+    /// Takes the archetypes matching this query out of the `state`, so that the readers of the state can be updated while
+    /// going through them; they have to be put back afterwards
+    fn take_archetypes(&self, world: &World, state: &mut QueryState) -> Vec<ArchetypeId> {
+        self.update_archetypes(world, state);
+        std::mem::take(&mut state.archetypes)
+    }
+    /// The archetypes matching this query. They're cached in the `state` if there's one, so that only new archetypes are
+    /// matched against the filter.
+    pub fn iter_archetypes<'a>(&self, world: &'a World, state: Option<&'a mut QueryState>) -> impl Iterator<Item = &'a Archetype> + 'a {
+        match state {
+            Some(state) => {
+                self.update_archetypes(world, state);
+                Either::Left(state.archetypes.iter().map(move |&id| &world.archetypes[id]))
+            }
+            None => Either::Right(self.filter.iter_by_archetypes(&world.archetypes)),
+        }
+    }
     pub fn iter<'a>(&self, world: &'a World, state: Option<&'a mut QueryState>) -> Box<dyn Iterator<Item = EntityAccessor> + 'a> {
         if let QueryEvent::Frame = &self.event {
-            return Box::new(self.filter.iter_entities(world));
+            return Box::new(
+                self.iter_archetypes(world, state)
+                    .flat_map(|arch| arch.entity_indices_to_ids.iter().map(|&id| EntityAccessor::World { id })),
+            );
         }
 
         let state = state.expect("Spawn/despawn/change queries must have a query state");
@@ -591,7 +809,11 @@ impl<'a, R: ComponentQuery<'a> + Clone + 'static> TypedReadQuery<R> {
         read_components.write_component_ids(&mut component_ids);
         let mut changed_components = Vec::new();
         read_components.get_change_filtered(&mut changed_components);
-        Self { query: Query::new_for_typed_query(component_ids, changed_components), read_components }
+        let mut added_components = Vec::new();
+        read_components.get_added_filtered(&mut added_components);
+        let mut removed_components = Vec::new();
+        read_components.get_removed_filtered(&mut removed_components);
+        Self { query: Query::new_for_typed_query(component_ids, changed_components, added_components, removed_components), read_components }
     }
     pub fn read<T: ComponentValue>(&self, component: Component<T>) -> TypedReadQuery<<R as ComponentsTupleAppend<T>>::Output>
     where
@@ -634,7 +856,15 @@ impl<'a, R: ComponentQuery<'a> + Clone + 'static> TypedReadQuery<R> {
         state: Option<&'a mut QueryState>,
     ) -> impl Iterator<Item = (EntityId, <R as ComponentQuery<'a>>::Data)> + 'a {
         let r = self.read_components.clone();
-        self.query.iter(world, state).into_iter().map(move |acc| (acc.id(), r.get_data(world, &acc)))
+        if self.query.event.is_frame() {
+            // Frame queries go through the matching archetypes directly, which is a lot faster than looking up each entity
+            Either::Left(self.query.iter_archetypes(world, state).flat_map(move |arch| {
+                let fetch = r.fetch(arch);
+                arch.entity_indices_to_ids.iter().enumerate().map(move |(index, &id)| (id, R::get_fetched(&fetch, index)))
+            }))
+        } else {
+            Either::Right(self.query.iter(world, state).map(move |acc| (acc.id(), r.get_data(world, &acc))))
+        }
     }
     pub fn iter_cloned(
         &self,
@@ -642,7 +872,14 @@ impl<'a, R: ComponentQuery<'a> + Clone + 'static> TypedReadQuery<R> {
         state: Option<&'a mut QueryState>,
     ) -> impl Iterator<Item = (EntityId, <R as ComponentQuery<'a>>::DataCloned)> + 'a {
         let r = self.read_components.clone();
-        self.query.iter(world, state).into_iter().map(move |acc| (acc.id(), r.get_data_cloned(world, &acc)))
+        if self.query.event.is_frame() {
+            Either::Left(self.query.iter_archetypes(world, state).flat_map(move |arch| {
+                let fetch = r.fetch(arch);
+                arch.entity_indices_to_ids.iter().enumerate().map(move |(index, &id)| (id, R::get_fetched_cloned(&fetch, index)))
+            }))
+        } else {
+            Either::Right(self.query.iter(world, state).map(move |acc| (acc.id(), r.get_data_cloned(world, &acc))))
+        }
     }
     pub fn collect_ids(&self, world: &'a World, state: Option<&'a mut QueryState>) -> Vec<EntityId> {
         self.query.iter(world, state).into_iter().map(move |acc| acc.id()).collect_vec()
@@ -717,7 +954,17 @@ impl<'a, RW: ComponentQuery<'a> + Clone + 'static, R: ComponentQuery<'a> + Clone
         let mut changed_components = Vec::new();
         read_write_components.get_change_filtered(&mut changed_components);
         read_components.get_change_filtered(&mut changed_components);
-        Self { query: Query::new_for_typed_query(component_ids, changed_components), read_write_components, read_components }
+        let mut added_components = Vec::new();
+        read_write_components.get_added_filtered(&mut added_components);
+        read_components.get_added_filtered(&mut added_components);
+        let mut removed_components = Vec::new();
+        read_write_components.get_removed_filtered(&mut removed_components);
+        read_components.get_removed_filtered(&mut removed_components);
+        Self {
+            query: Query::new_for_typed_query(component_ids, changed_components, added_components, removed_components),
+            read_write_components,
+            read_components,
+        }
     }
     pub fn read_write<T: ComponentValue>(&self, component: Component<T>) -> TypedReadWriteQuery<<RW as ComponentsTupleAppend<T>>::Output, R>
     where
@@ -772,7 +1019,18 @@ impl<'a, RW: ComponentQuery<'a> + Clone + 'static, R: ComponentQuery<'a> + Clone
         let rw = self.read_write_components.clone();
         let r = self.read_components.clone();
         let world = &*world;
-        self.query.iter(world, state).into_iter().map(move |acc| (acc.id(), rw.get_data_mut(world, &acc), r.get_data(world, &acc)))
+        if self.query.event.is_frame() {
+            Either::Left(self.query.iter_archetypes(world, state).flat_map(move |arch| {
+                let mut fetch_mut = rw.fetch_mut(world, arch);
+                let fetch = r.fetch(arch);
+                arch.entity_indices_to_ids
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, &id)| (id, RW::get_fetched_mut(&mut fetch_mut, index), R::get_fetched(&fetch, index)))
+            }))
+        } else {
+            Either::Right(self.query.iter(world, state).map(move |acc| (acc.id(), rw.get_data_mut(world, &acc), r.get_data(world, &acc))))
+        }
     }
     pub fn to_system<F: Fn(&Self, &mut World, Option<&mut QueryState>, &E) + Send + Sync + 'static, E: 'static>(
         self,
@@ -0,0 +1,114 @@
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+
+use crate::{query, Component, ECSError, EntityId, FnSystem, Query, SystemGroup, World};
+
+/// The reverse lookup of a relationship component, i.e. a `Vec<EntityId>` component listing the entities an entity is
+/// related to (such as `targets` or `member_of`), which is many-to-many.
+///
+/// It's maintained as a resource of the world by [relationship_system].
+#[derive(Debug, Clone, Default)]
+pub struct RelationshipIndex {
+    targets: HashMap<EntityId, Vec<EntityId>>,
+    sources: HashMap<EntityId, HashSet<EntityId>>,
+}
+impl RelationshipIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The entities which are related to `target`
+    pub fn sources(&self, target: EntityId) -> impl Iterator<Item = EntityId> + '_ {
+        self.sources.get(&target).into_iter().flatten().copied()
+    }
+    /// The entities `source` is related to
+    pub fn targets(&self, source: EntityId) -> &[EntityId] {
+        self.targets.get(&source).map(|targets| targets.as_slice()).unwrap_or(&[])
+    }
+    pub fn is_related(&self, source: EntityId, target: EntityId) -> bool {
+        self.sources.get(&target).map(|sources| sources.contains(&source)).unwrap_or(false)
+    }
+    fn set(&mut self, source: EntityId, targets: Vec<EntityId>) {
+        self.remove_source(source);
+        for &target in &targets {
+            self.sources.entry(target).or_default().insert(source);
+        }
+        self.targets.insert(source, targets);
+    }
+    fn remove_source(&mut self, source: EntityId) {
+        for target in self.targets.remove(&source).unwrap_or_default() {
+            if let Some(sources) = self.sources.get_mut(&target) {
+                sources.remove(&source);
+                if sources.is_empty() {
+                    self.sources.remove(&target);
+                }
+            }
+        }
+    }
+}
+
+/// Creates and maintains the [RelationshipIndex] of `relationship` as a resource on the world, and removes despawned
+/// entities from the relationship: when a target is despawned, it's removed from the `relationship` of all the entities
+/// related to it.
+pub fn relationship_system(relationship: Component<Vec<EntityId>>, index_resource: Component<RelationshipIndex>) -> SystemGroup {
+    SystemGroup::new(
+        "relationship_system",
+        vec![
+            Box::new(FnSystem::new(move |world, _| {
+                if !world.has_component(world.resource_entity(), index_resource) {
+                    world.add_resource(index_resource, RelationshipIndex::new());
+                }
+            })),
+            query(()).incl(relationship).despawned().to_system(move |q, world, qs, _| {
+                let ids = q.iter(world, qs).map(|(id, _)| id).collect_vec();
+                let index = world.resource_mut(index_resource);
+                for id in ids {
+                    index.remove_source(id);
+                }
+            }),
+            Query::all().despawned().to_system(move |q, world, qs, _| {
+                let ids = q.iter(world, Some(qs)).map(|ea| ea.id()).collect_vec();
+                for target in ids {
+                    let sources = world.resource(index_resource).sources(target).collect_vec();
+                    for source in sources {
+                        if let Ok(targets) = world.get_mut(source, relationship) {
+                            targets.retain(|&id| id != target);
+                        }
+                    }
+                }
+            }),
+            query(relationship).spawned().to_system(move |q, world, qs, _| {
+                let changed = q.iter(world, qs).map(|(id, targets)| (id, targets.clone())).collect_vec();
+                let index = world.resource_mut(index_resource);
+                for (id, targets) in changed {
+                    index.set(id, targets);
+                }
+            }),
+            query(relationship.changed()).to_system(move |q, world, qs, _| {
+                let changed = q.iter(world, qs).map(|(id, targets)| (id, targets.clone())).collect_vec();
+                let index = world.resource_mut(index_resource);
+                for (id, targets) in changed {
+                    index.set(id, targets);
+                }
+            }),
+        ],
+    )
+}
+
+/// Relates `source` to `target` through `relationship`, unless it already is
+pub fn add_relation(world: &mut World, source: EntityId, relationship: Component<Vec<EntityId>>, target: EntityId) -> Result<(), ECSError> {
+    if let Ok(targets) = world.get_mut(source, relationship) {
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+        Ok(())
+    } else {
+        world.add_component(source, relationship, vec![target])
+    }
+}
+/// Removes the relation from `source` to `target` through `relationship`, if there's one
+pub fn remove_relation(world: &mut World, source: EntityId, relationship: Component<Vec<EntityId>>, target: EntityId) {
+    if let Ok(targets) = world.get_mut(source, relationship) {
+        targets.retain(|&id| id != target);
+    }
+}
@@ -7,7 +7,7 @@ use serde::{
     Deserialize, Deserializer, Serialize, Serializer,
 };
 
-use crate::{dont_store, query, DeserEntityDataWithWarnings, EntityData, EntityId, Serializable, World};
+use crate::{dont_store, query, DeserEntityDataWithWarnings, EntityData, EntityId, Serializable, SerializationProfile, World};
 
 impl Serialize for World {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
@@ -26,13 +26,14 @@ struct SerWorldEntity<'a> {
 }
 impl<'a> Serialize for SerWorldEntity<'a> {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let comps = self.world.get_components(self.id).unwrap().into_iter().filter(|x| x.has_attribute::<Serializable>()).collect_vec();
+        let comps =
+            self.world.get_components(self.id).unwrap().into_iter().filter(|&x| SerializationProfile::SAVE_GAME.includes(x)).collect_vec();
 
         let mut entity = serializer.serialize_map(Some(comps.len()))?;
         for comp in comps {
             if let Some(ser) = comp.attribute::<Serializable>() {
                 let value = self.world.get_entry(self.id, comp).unwrap();
-                entity.serialize_entry(&comp.path(), ser.serialize(&value))?;
+                entity.serialize_entry(&comp.path(), &ser.serialize(&value))?;
             }
         }
         entity.end()
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::{ComponentDesc, ComponentEntry, ComponentSet, EntityData, EntityId, EntityIdHashBuilder, World};
+
+/// A copy of the components of a [World] at some point in time, taken with [World::snapshot] or
+/// [World::snapshot_components], which the world can be rolled back to with [World::restore].
+///
+/// The content versions of the components are stored alongside them, so that restoring only writes the components that
+/// have changed since the snapshot was taken. This means that a snapshot should only be restored into the world it was
+/// taken from (or a clone of it).
+#[derive(Clone)]
+pub struct WorldSnapshot {
+    /// The components this snapshot is restricted to, or None if it contains all of them
+    components: Option<ComponentSet>,
+    entities: HashMap<EntityId, Vec<(ComponentEntry, u64)>, EntityIdHashBuilder>,
+}
+impl WorldSnapshot {
+    /// The number of entities in this snapshot
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.entities.contains_key(&id)
+    }
+    /// The components of an entity in this snapshot
+    pub fn entity(&self, id: EntityId) -> Option<EntityData> {
+        Some(self.entities.get(&id)?.iter().map(|(entry, _)| entry.clone()).collect())
+    }
+    fn includes(&self, component: ComponentDesc) -> bool {
+        self.components.as_ref().map(|components| components.contains(component)).unwrap_or(true)
+    }
+}
+impl std::fmt::Debug for WorldSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorldSnapshot").field("entities", &self.entities.len()).finish()
+    }
+}
+
+impl World {
+    /// Copies all the entities and components of this world, resources included
+    pub fn snapshot(&self) -> WorldSnapshot {
+        self.snapshot_internal(None)
+    }
+    /// Copies the given components of all the entities that have at least one of them
+    pub fn snapshot_components(&self, components: ComponentSet) -> WorldSnapshot {
+        self.snapshot_internal(Some(components))
+    }
+    fn snapshot_internal(&self, components: Option<ComponentSet>) -> WorldSnapshot {
+        let mut snapshot = WorldSnapshot { components, entities: HashMap::with_hasher(EntityIdHashBuilder) };
+        for &id in self.locs.keys() {
+            let entries = self
+                .get_components(id)
+                .unwrap()
+                .into_iter()
+                .filter(|&component| snapshot.includes(component))
+                .map(|component| {
+                    (self.get_entry(id, component).unwrap(), self.get_component_content_version(id, component.index()).unwrap())
+                })
+                .collect_vec();
+            if !entries.is_empty() || snapshot.components.is_none() {
+                snapshot.entities.insert(id, entries);
+            }
+        }
+        snapshot
+    }
+
+    /// Rolls the world back to the state it was in when `snapshot` was taken.
+    ///
+    /// Entities which were spawned since are despawned, and entities which were despawned are spawned again with the
+    /// same id. For a snapshot of some components only, the components outside of it are left as they are, and entities
+    /// are only spawned (with just the snapshotted components) or stripped of the snapshotted components, not despawned.
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        for id in self.locs.keys().copied().collect_vec() {
+            if snapshot.contains(id) {
+                continue;
+            }
+            if snapshot.components.is_none() {
+                self.despawn(id);
+            } else {
+                let removed = self.get_components(id).unwrap().into_iter().filter(|&component| snapshot.includes(component)).collect_vec();
+                if !removed.is_empty() {
+                    self.remove_components(id, removed).unwrap();
+                }
+            }
+        }
+
+        for (&id, entries) in &snapshot.entities {
+            if !self.exists(id) {
+                self.spawn_with_id(id, entries.iter().map(|(entry, _)| entry.clone()).collect());
+                continue;
+            }
+            let removed = self
+                .get_components(id)
+                .unwrap()
+                .into_iter()
+                .filter(|&component| snapshot.includes(component) && !entries.iter().any(|(entry, _)| entry.desc() == component))
+                .collect_vec();
+            if !removed.is_empty() {
+                self.remove_components(id, removed).unwrap();
+            }
+            let mut added = EntityData::new();
+            for (entry, version) in entries {
+                let component = entry.desc();
+                if !self.has_component(id, component) {
+                    added.set_entry(entry.clone());
+                } else if self.get_component_content_version(id, component.index()) != Ok(*version) {
+                    self.set_entry(id, entry.clone()).unwrap();
+                }
+            }
+            if !added.is_empty() {
+                self.add_components(id, added).unwrap();
+            }
+        }
+    }
+}
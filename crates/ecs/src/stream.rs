@@ -8,7 +8,7 @@ use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
 use super::{ArchetypeFilter, Component, ComponentValue, EntityData, EntityId, FramedEventsReader, Query, QueryState, World};
-use crate::{ComponentDesc, ComponentEntry, Serializable};
+use crate::{ComponentDesc, ComponentEntry, Debuggable, Serializable};
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct WorldDiff {
@@ -61,7 +61,35 @@ impl WorldDiff {
         self.changes.len() == 0
     }
     /// This creates a list of changes that would take you from the `from` world to the `to` world, if applied to the `from` world.
+    ///
+    /// The components are compared by their content versions, so `to` has to be a later version of `from` (i.e. a clone of it
+    /// which has been modified). Use [WorldDiff::between] to compare unrelated worlds.
     pub fn from_a_to_b(filter: WorldStreamFilter, from: &World, to: &World) -> Self {
+        Self::diff(filter, from, to, |id, comp| {
+            from.get_component_content_version(id, comp.index()).unwrap() != to.get_component_content_version(id, comp.index()).unwrap()
+        })
+    }
+    /// Creates the changes which would turn `old` into `new`, i.e. the spawned and despawned entities, and the added, changed
+    /// and removed components of the entities which are in both worlds.
+    ///
+    /// Unlike [WorldDiff::from_a_to_b], the worlds don't need to share a history: the components are compared by value, so the
+    /// diff can be applied to any world with the same content as `old`. The values are compared serialized, or debug formatted
+    /// if they can't be serialized. Components which are neither [Serializable] nor [Debuggable] can't be compared, so only
+    /// their addition and removal are in the diff.
+    pub fn between(old: &World, new: &World) -> Self {
+        Self::diff(WorldStreamFilter::default(), old, new, |id, comp| {
+            let (old, new) = (old.get_entry(id, comp).unwrap(), new.get_entry(id, comp).unwrap());
+            if let Some(ser) = comp.attribute::<Serializable>() {
+                let value = |entry: &ComponentEntry| serde_json::to_value(ser.serialize(entry)).ok();
+                value(&old) != value(&new)
+            } else if comp.has_attribute::<Debuggable>() {
+                format!("{:?}", old.as_debug()) != format!("{:?}", new.as_debug())
+            } else {
+                false
+            }
+        })
+    }
+    fn diff(filter: WorldStreamFilter, from: &World, to: &World, changed: impl Fn(EntityId, ComponentDesc) -> bool) -> Self {
         let from_entities: HashSet<EntityId> = filter.all_entities(from).collect();
         let to_entities: HashSet<EntityId> = filter.all_entities(to).collect();
         let spawned = to_entities.iter().filter(|id| !from_entities.contains(id)).cloned().collect_vec();
@@ -78,10 +106,7 @@ impl WorldDiff {
             let removed = from_comps.iter().filter(|c| !to_comps.contains_key(c.0)).map(|v| *v.1).collect_vec();
             let in_both = to_comps.iter().filter(|c| from_comps.contains_key(c.0)).collect_vec();
 
-            let changed = in_both
-                .iter()
-                .filter(|&c| from.get_component_content_version(id, *c.0).unwrap() != to.get_component_content_version(id, *c.0).unwrap())
-                .collect_vec();
+            let changed = in_both.iter().filter(|&c| changed(id, *c.1)).collect_vec();
 
             let added: EntityData = added.iter().map(|&comp| to.get_entry(id, comp).unwrap()).collect();
 
@@ -94,7 +119,7 @@ impl WorldDiff {
                     WorldChange::Set(id, entry)
                 })
                 .collect_vec();
-            added.into_iter().chain(removed.into_iter()).chain(changed.into_iter())
+            added.into_iter().chain(removed).chain(changed)
         });
 
         Self { changes: despanwed.chain(spawned).chain(updated).collect_vec() }
@@ -153,7 +178,7 @@ impl WorldStreamFilter {
             .filter(|&comp| (self.component_filter)(comp, WorldStreamCompEvent::Init))
             .collect_vec()
     }
-    fn read_entity_components(&self, world: &World, id: EntityId) -> Vec<ComponentEntry> {
+    pub fn read_entity_components(&self, world: &World, id: EntityId) -> Vec<ComponentEntry> {
         self.get_entity_components(world, id).into_iter().map(|comp| world.get_entry(id, comp).unwrap()).collect_vec()
     }
 }
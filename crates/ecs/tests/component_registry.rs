@@ -0,0 +1,81 @@
+use ambient_ecs::{
+    components, ComponentRegistry, ComponentRegistryError, EntityData, ExternalComponentAttributes, ExternalComponentDesc,
+    ExternalComponentFlagAttributes, Name, Networked, PrimitiveComponentType, World,
+};
+
+components!("reflection", {
+    @[Networked, Name["Health"]]
+    health: f32,
+    secret: String,
+});
+
+fn external(path: &str, ty: PrimitiveComponentType) -> ExternalComponentDesc {
+    ExternalComponentDesc {
+        path: path.to_string(),
+        ty,
+        attributes: ExternalComponentAttributes {
+            name: Some("Speed".to_string()),
+            description: None,
+            flags: ExternalComponentFlagAttributes { networked: true, ..Default::default() },
+        },
+    }
+}
+
+#[test]
+fn reflects_static_components() {
+    init_components();
+    let registry = ComponentRegistry::get();
+    let health = registry.reflect_all().find(|c| c.path == "core::reflection::health").unwrap();
+    assert_eq!(health.index, self::health().index());
+    assert_eq!(health.type_name, "f32");
+    assert_eq!(health.primitive_type, Some(PrimitiveComponentType::F32));
+    assert!(!health.external);
+    assert_eq!(health.attributes.name.as_deref(), Some("Health"));
+    assert!(health.attributes.flags.networked);
+    assert!(!health.attributes.flags.store);
+
+    let secret = registry.reflect(self::secret().desc());
+    assert_eq!(secret.primitive_type, Some(PrimitiveComponentType::String));
+    assert!(!secret.attributes.flags.networked);
+}
+
+#[test]
+fn registers_components_at_runtime() {
+    init_components();
+    let speed =
+        ComponentRegistry::get_mut().register_from_desc(external("reflection::runtime::speed", PrimitiveComponentType::F32)).unwrap();
+    let reflection = ComponentRegistry::get().reflect(speed);
+    assert_eq!(reflection.path, "reflection::runtime::speed");
+    assert!(reflection.external);
+    assert_eq!(reflection.attributes.name.as_deref(), Some("Speed"));
+
+    // Registering it again with the same type keeps the same component
+    let again =
+        ComponentRegistry::get_mut().register_from_desc(external("reflection::runtime::speed", PrimitiveComponentType::F32)).unwrap();
+    assert_eq!(again, speed);
+
+    let mut world = World::new("registers_components_at_runtime");
+    let mut data = EntityData::new();
+    data.set_entry(speed.from_json("4.0").unwrap());
+    let id = world.spawn(data);
+    assert_eq!(world.get_entry(id, speed).unwrap().try_downcast_ref::<f32>(), Some(&4.));
+}
+
+#[test]
+fn rejects_invalid_components() {
+    init_components();
+    let mut registry = ComponentRegistry::get_mut();
+    assert!(matches!(
+        registry.register_from_desc(external("core::reflection::health", PrimitiveComponentType::String)),
+        Err(ComponentRegistryError::TypeMismatch { .. })
+    ));
+    assert!(matches!(
+        registry.register_from_desc(external("reflection::has space", PrimitiveComponentType::F32)),
+        Err(ComponentRegistryError::InvalidPath { .. })
+    ));
+    assert!(matches!(
+        registry.register_from_desc(external("reflection::::empty", PrimitiveComponentType::F32)),
+        Err(ComponentRegistryError::InvalidPath { .. })
+    ));
+    assert!(registry.get_by_path("reflection::has space").is_none());
+}
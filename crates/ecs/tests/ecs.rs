@@ -1,4 +1,4 @@
-use ambient_ecs::{components, query, query_mut, ECSError, EntityData, EntityId, Query, QueryState, Resource, World};
+use ambient_ecs::{components, query, query_mut, ECSError, EntityData, EntityId, MergePolicy, Query, QueryState, Resource, World};
 use itertools::Itertools;
 
 components!("test", {
@@ -8,6 +8,7 @@ components!("test", {
     b: f32,
     c: f32,
     counter: usize,
+    target: EntityId,
     @[Resource]
     a_resource: (),
 });
@@ -222,3 +223,81 @@ fn can_add_a_resource() {
     init();
     World::new("can_add_a_resource").add_resource(a_resource(), ());
 }
+
+#[test]
+fn iter_archetypes() {
+    init();
+    let mut world = World::new("iter_archetypes");
+    let x = world.spawn(EntityData::new().set(a(), 1.).set(b(), 1.));
+    let y = world.spawn(EntityData::new().set(a(), 2.).set(b(), 2.).set(c(), 2.));
+    world.spawn(EntityData::new().set(a(), 3.));
+    let changed_query = query(a().changed());
+    let mut changed_state = QueryState::new();
+    // The state starts from the spawned entities, so that only the changes made below are reported
+    assert_eq!(changed_query.iter(&world, Some(&mut changed_state)).count(), 3);
+
+    let q = query_mut(a(), b());
+    let mut state = QueryState::new();
+    for (_, a, b) in q.iter(&mut world, Some(&mut state)) {
+        *a += *b;
+    }
+    assert_eq!(world.get(x, a()).unwrap(), 2.);
+    assert_eq!(world.get(y, a()).unwrap(), 4.);
+    let changed = changed_query.iter(&world, Some(&mut changed_state)).map(|(id, _)| id).sorted().collect_vec();
+    assert_eq!(changed, [x, y].into_iter().sorted().collect_vec());
+
+    // Archetypes created after the matches were cached are picked up
+    let z = world.spawn(EntityData::new().set(a(), 5.).set(b(), 5.).set(counter(), 0));
+    let sums = query((a(), b())).iter(&world, Some(&mut state)).map(|(id, (a, b))| (id, a + b)).sorted_by_key(|(id, _)| *id).collect_vec();
+    assert_eq!(sums, [(x, 3.), (y, 6.), (z, 10.)].into_iter().sorted_by_key(|(id, _)| *id).collect_vec());
+}
+
+#[test]
+fn merge_remaps_ids() {
+    init();
+    let mut world = World::new("merge_remaps_ids");
+    let mut other = World::new("merge_remaps_ids_other");
+    let first = other.spawn(EntityData::new().set(a(), 1.));
+    let second = other.spawn(EntityData::new().set(a(), 2.).set(target(), first));
+
+    let ids = world.merge(&other, MergePolicy::Remap);
+    let ids2 = world.merge(&other, MergePolicy::Remap);
+    assert_eq!(query(a()).iter(&world, None).count(), 4);
+    assert_ne!(ids[&first], first);
+    assert_ne!(ids[&first], ids2[&first]);
+    assert_eq!(world.get(ids[&second], target()), Ok(ids[&first]));
+    assert_eq!(world.get(ids2[&second], target()), Ok(ids2[&first]));
+}
+
+#[test]
+fn merge_policies_for_used_ids() {
+    init();
+    let mut other = World::new("merge_policies_other");
+    let existing = other.spawn(EntityData::new().set(a(), 1.));
+    let new = other.spawn(EntityData::new().set(a(), 2.).set(target(), existing));
+    let mut world = World::new("merge_policies");
+    world.spawn_with_id(existing, EntityData::new().set(a(), 0.).set(b(), 0.));
+
+    let mut keep_ids = world.clone();
+    let ids = keep_ids.merge(&other, MergePolicy::KeepIds);
+    assert_eq!(ids[&new], new);
+    assert_ne!(ids[&existing], existing);
+    assert_eq!(keep_ids.get(new, target()), Ok(ids[&existing]));
+    assert_eq!(keep_ids.get(existing, a()), Ok(0.));
+
+    let mut skip = world.clone();
+    skip.merge(&other, MergePolicy::Skip);
+    assert_eq!(skip.get(existing, a()), Ok(0.));
+    assert_eq!(skip.get(new, target()), Ok(existing));
+    assert_eq!(query(a()).iter(&skip, None).count(), 2);
+
+    let mut replace = world.clone();
+    replace.merge(&other, MergePolicy::Replace);
+    assert_eq!(replace.get(existing, a()), Ok(1.));
+    assert!(!replace.has_component(existing, b()));
+
+    let mut merge = world.clone();
+    merge.merge(&other, MergePolicy::Merge);
+    assert_eq!(merge.get(existing, a()), Ok(1.));
+    assert_eq!(merge.get(existing, b()), Ok(0.));
+}
@@ -138,6 +138,37 @@ fn change_query_multi_frame() {
     assert_eq!(query((a().changed(),)).iter(&world, Some(&mut state)).count(), 0);
 }
 
+#[test]
+fn added_removed_query() {
+    init();
+    let mut world = World::new("added_removed_query");
+    let x = world.spawn(EntityData::new().set(a(), 1.));
+    let mut added = QueryState::new();
+    let mut removed = QueryState::new();
+    assert_eq!(query((a(), b().added())).collect_ids(&world, Some(&mut added)), Vec::<EntityId>::new());
+    assert_eq!(query((a(), b().removed())).collect_ids(&world, Some(&mut removed)), Vec::<EntityId>::new());
+
+    world.add_component(x, b(), 2.).unwrap();
+    let y = world.spawn(EntityData::new().set(a(), 1.).set(b(), 3.));
+    assert_eq!(query((a(), b().added())).collect_ids(&world, Some(&mut added)), vec![x, y]);
+    assert_eq!(query((a(), b().added())).collect_ids(&world, Some(&mut added)), Vec::<EntityId>::new());
+
+    world.set(x, b(), 4.).unwrap();
+    world.remove_component(x, b()).unwrap();
+    world.despawn(y);
+    assert_eq!(query((a(), b().added())).collect_ids(&world, Some(&mut added)), Vec::<EntityId>::new());
+    let removed_values = query((a(), b().removed())).iter(&world, Some(&mut removed)).map(|(id, (_, &b))| (id, b)).collect_vec();
+    assert_eq!(removed_values, vec![(x, 4.), (y, 3.)]);
+    assert_eq!(query((a(), b().removed())).collect_ids(&world, Some(&mut removed)), Vec::<EntityId>::new());
+}
+
+#[test]
+#[should_panic]
+fn changed_and_added_query() {
+    init();
+    query((a().changed(), b().added()));
+}
+
 #[test]
 fn add_component_events() {
     init();
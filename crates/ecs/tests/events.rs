@@ -0,0 +1,62 @@
+use ambient_ecs::{components, EventReader, Events, Resource, World};
+
+components!("events", {
+    @[Resource]
+    damage_events: Events<f32>,
+});
+
+fn init() {
+    init_components();
+}
+
+#[test]
+fn readers_see_each_event_once() {
+    init();
+    let mut world = World::new("readers_see_each_event_once");
+    world.add_events(damage_events());
+    let mut early = EventReader::new();
+    let mut late = EventReader::new();
+
+    // The early reader runs before the event is sent, so it only sees it on the next frame
+    assert_eq!(early.iter(world.resource(damage_events())).count(), 0);
+    world.send_event(damage_events(), 1.);
+    assert_eq!(late.iter(world.resource(damage_events())).copied().collect::<Vec<_>>(), vec![1.]);
+    world.next_frame();
+
+    assert_eq!(early.iter(world.resource(damage_events())).copied().collect::<Vec<_>>(), vec![1.]);
+    world.send_event(damage_events(), 2.);
+    assert_eq!(late.iter(world.resource(damage_events())).copied().collect::<Vec<_>>(), vec![2.]);
+    assert_eq!(early.iter(world.resource(damage_events())).copied().collect::<Vec<_>>(), vec![2.]);
+    assert_eq!(late.iter(world.resource(damage_events())).count(), 0);
+}
+
+#[test]
+fn events_are_dropped_after_two_frames() {
+    init();
+    let mut world = World::new("events_are_dropped_after_two_frames");
+    world.add_events(damage_events());
+    world.send_event(damage_events(), 1.);
+    world.next_frame();
+    assert_eq!(world.resource(damage_events()).len(), 1);
+    world.send_event(damage_events(), 2.);
+    world.next_frame();
+    world.next_frame();
+    assert!(world.resource(damage_events()).is_empty());
+
+    // A reader which fell behind skips the events it missed
+    let mut reader = EventReader::new();
+    world.send_event(damage_events(), 3.);
+    assert_eq!(reader.iter(world.resource(damage_events())).copied().collect::<Vec<_>>(), vec![3.]);
+}
+
+#[test]
+fn channels_update_without_next_frame() {
+    init();
+    let mut world = World::new("channels_update_without_next_frame");
+    world.add_events(damage_events());
+    world.send_event(damage_events(), 1.);
+    world.update_event_channels();
+    world.send_event(damage_events(), 2.);
+    world.update_event_channels();
+    assert_eq!(world.resource(damage_events()).iter().copied().collect::<Vec<_>>(), vec![2.]);
+}
@@ -0,0 +1,66 @@
+use ambient_ecs::{
+    add_relation, components, relationship_system, remove_relation, EntityData, EntityId, FrameEvent, RelationshipIndex, Resource, System,
+    World,
+};
+use itertools::Itertools;
+
+components!("relationship", {
+    member_of: Vec<EntityId>,
+    @[Resource]
+    members: RelationshipIndex,
+});
+
+fn init() {
+    init_components();
+}
+
+#[test]
+fn reverse_lookup() {
+    init();
+    let mut world = World::new("reverse_lookup");
+    let mut systems = relationship_system(member_of(), members());
+    let red = world.spawn(EntityData::new());
+    let blue = world.spawn(EntityData::new());
+    let a = world.spawn(EntityData::new().set(member_of(), vec![red]));
+    systems.run(&mut world, &FrameEvent);
+    assert_eq!(world.resource(members()).sources(red).collect_vec(), vec![a]);
+
+    let b = world.spawn(EntityData::new());
+    add_relation(&mut world, b, member_of(), red).unwrap();
+    add_relation(&mut world, b, member_of(), blue).unwrap();
+    add_relation(&mut world, a, member_of(), blue).unwrap();
+    systems.run(&mut world, &FrameEvent);
+    assert_eq!(world.resource(members()).sources(red).sorted().collect_vec(), vec![a, b].into_iter().sorted().collect_vec());
+    assert_eq!(world.resource(members()).targets(b), &[red, blue]);
+
+    remove_relation(&mut world, a, member_of(), red);
+    world.remove_component(b, member_of()).unwrap();
+    systems.run(&mut world, &FrameEvent);
+    assert_eq!(world.resource(members()).sources(red).count(), 0);
+    assert_eq!(world.resource(members()).sources(blue).collect_vec(), vec![a]);
+    assert!(!world.resource(members()).is_related(b, blue));
+}
+
+#[test]
+fn cleanup_on_despawn() {
+    init();
+    let mut world = World::new("cleanup_on_despawn");
+    let mut systems = relationship_system(member_of(), members());
+    let red = world.spawn(EntityData::new());
+    let blue = world.spawn(EntityData::new());
+    let a = world.spawn(EntityData::new().set(member_of(), vec![red, blue]));
+    let b = world.spawn(EntityData::new().set(member_of(), vec![red]));
+    systems.run(&mut world, &FrameEvent);
+
+    world.despawn(red);
+    systems.run(&mut world, &FrameEvent);
+    assert_eq!(world.get_ref(a, member_of()).unwrap(), &vec![blue]);
+    assert!(world.get_ref(b, member_of()).unwrap().is_empty());
+    systems.run(&mut world, &FrameEvent);
+    assert_eq!(world.resource(members()).sources(red).count(), 0);
+    assert_eq!(world.resource(members()).targets(a), &[blue]);
+
+    world.despawn(a);
+    systems.run(&mut world, &FrameEvent);
+    assert_eq!(world.resource(members()).sources(blue).count(), 0);
+}
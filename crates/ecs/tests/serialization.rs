@@ -0,0 +1,186 @@
+use ambient_ecs::{
+    components, erased_serde, ComponentVersion, DeserEntityDataWithWarnings, DontStore, EntityData, FormerPaths, MakeDefault, Migrate,
+    Networked, Serializable, SerializationProfile, SerializationProfiles, Store, Validate, World,
+};
+use serde::Deserialize;
+
+components!("serialization", {
+    @[Store, FormerPaths[["core::serialization::hp", "core::serialization::hit_points"]]]
+    health: f32,
+    @[Store, ComponentVersion[2], Migrate[migrate_speed]]
+    speed: f32,
+    @[Store, ComponentVersion[1]]
+    strength: f32,
+    @[Store, ComponentVersion[1], Migrate[migrate_sizes]]
+    sizes: Vec<u32>,
+    @[Networked, DontStore]
+    selected: bool,
+    @[Store, MakeDefault[full_volume], Validate[validate_volume]]
+    volume: f32,
+    @[Store, Validate[validate_name]]
+    nickname: String,
+    @[Networked, Store, SerializationProfiles[[SerializationProfile::REPLICATION]]]
+    loaded: (),
+});
+
+fn full_volume() -> f32 {
+    1.
+}
+fn validate_volume(volume: &mut f32) -> Result<(), String> {
+    if volume.is_nan() {
+        return Err("NaN".to_string());
+    }
+    *volume = volume.clamp(0., 1.);
+    Ok(())
+}
+fn validate_name(name: &mut String) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("Empty name".to_string());
+    }
+    Ok(())
+}
+
+/// Version 0 was in km/h, and version 1 was a `{ "value": .. }` object in km/h
+fn migrate_speed(version: u32, deserializer: &mut dyn erased_serde::Deserializer) -> Result<f32, erased_serde::Error> {
+    #[derive(Deserialize)]
+    struct V1 {
+        value: f32,
+    }
+    let kmh = match version {
+        0 => f32::deserialize(deserializer)?,
+        _ => V1::deserialize(deserializer)?.value,
+    };
+    Ok(kmh / 3.6)
+}
+
+/// Version 0 was sorted in decreasing order
+fn migrate_sizes(_version: u32, deserializer: &mut dyn erased_serde::Deserializer) -> Result<Vec<u32>, erased_serde::Error> {
+    let mut sizes = Vec::<u32>::deserialize(deserializer)?;
+    sizes.reverse();
+    Ok(sizes)
+}
+
+fn init() {
+    ambient_ecs::init_components();
+    init_components();
+}
+
+#[test]
+fn loads_renamed_components() {
+    init();
+    let data: EntityData = serde_json::from_str(r#"{ "core::serialization::hp": 1.0 }"#).unwrap();
+    assert_eq!(data.get(health()), Some(1.));
+    let data: EntityData = serde_json::from_str(r#"{ "core::serialization::hit_points": 2.0 }"#).unwrap();
+    assert_eq!(data.get(health()), Some(2.));
+}
+
+#[test]
+fn saves_versions() {
+    init();
+    let json = speed().desc().to_json(&speed().desc().from_json("[2, 5.0]").unwrap()).unwrap();
+    assert_eq!(json, "[2,5.0]");
+    let data: EntityData = serde_json::from_str(&serde_json::to_string(&EntityData::new().set(speed(), 5.)).unwrap()).unwrap();
+    assert_eq!(data.get(speed()), Some(5.));
+}
+
+#[test]
+fn round_trips_versions_in_binary() {
+    init();
+    let bytes = bincode::serialize(&EntityData::new().set(speed(), 5.).set(health(), 1.)).unwrap();
+    let data: EntityData = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(data.get(speed()), Some(5.));
+    assert_eq!(data.get(health()), Some(1.));
+}
+
+#[test]
+fn migrates_older_versions() {
+    init();
+    let data: EntityData = serde_json::from_str(r#"{ "core::serialization::speed": 36.0 }"#).unwrap();
+    assert_eq!(data.get(speed()), Some(10.));
+    let data: EntityData = serde_json::from_str(r#"{ "core::serialization::speed": [1, { "value": 72.0 }] }"#).unwrap();
+    assert_eq!(data.get(speed()), Some(20.));
+}
+
+#[test]
+fn migrates_unversioned_pairs() {
+    init();
+    let data: EntityData = serde_json::from_str(r#"{ "core::serialization::sizes": [1, [3, 2]] }"#).unwrap();
+    assert_eq!(data.get_cloned(sizes()), Some(vec![3, 2]));
+    // Values saved before the component was versioned which look like a version and a value
+    let data: EntityData = serde_json::from_str(r#"{ "core::serialization::sizes": [2, 1] }"#).unwrap();
+    assert_eq!(data.get_cloned(sizes()), Some(vec![1, 2]));
+    let data: EntityData = serde_json::from_str(r#"{ "core::serialization::sizes": [1, 0] }"#).unwrap();
+    assert_eq!(data.get_cloned(sizes()), Some(vec![0, 1]));
+}
+
+#[test]
+fn rejects_versions_without_migration() {
+    init();
+    assert!(strength().desc().from_json("[1, 3.0]").is_ok());
+    assert!(strength().desc().from_json("3.0").is_err());
+    assert!(strength().desc().from_json("[2, 3.0]").is_err());
+}
+
+#[test]
+fn skips_dont_store_components() {
+    init();
+    let mut world = World::new("skips_dont_store_components");
+    let id = world.spawn(EntityData::new().set(health(), 1.).set(selected(), true));
+    assert!(selected().has_attribute::<Serializable>());
+
+    let world: World = serde_json::from_str(&serde_json::to_string(&world).unwrap()).unwrap();
+    assert_eq!(world.get(id, health()), Ok(1.));
+    assert!(!world.has_component(id, selected()));
+}
+
+#[test]
+fn filters_components_by_profile() {
+    init();
+    let mut world = World::new("filters_components_by_profile");
+    let id = world.spawn(EntityData::new().set(health(), 1.).set(selected(), true).set(loaded(), ()));
+
+    let saved: World = serde_json::from_str(&serde_json::to_string(&world).unwrap()).unwrap();
+    assert_eq!(saved.get(id, health()), Ok(1.));
+    assert!(!saved.has_component(id, loaded()));
+
+    let copied = world.clone_entity(id).unwrap().for_profile(SerializationProfile::EDITOR_COPY_PASTE);
+    assert!(copied.contains(health()) && !copied.contains(selected()) && !copied.contains(loaded()));
+    let replicated = world.clone_entity(id).unwrap().for_profile(SerializationProfile::REPLICATION);
+    assert!(!replicated.contains(health()) && replicated.contains(selected()) && replicated.contains(loaded()));
+}
+
+#[test]
+fn validates_values() {
+    init();
+    let data: EntityData = serde_json::from_str(r#"{ "core::serialization::volume": 2.0 }"#).unwrap();
+    assert_eq!(data.get(volume()), Some(1.));
+    assert!(serde_json::from_str::<EntityData>(r#"{ "core::serialization::nickname": "" }"#).is_err());
+
+    let mut data = EntityData::new().set(volume(), f32::NAN).set(nickname(), String::new()).set(health(), 1.);
+    assert_eq!(data.validate().len(), 1);
+    assert_eq!(data.get(volume()), Some(1.));
+    assert!(!data.contains(nickname()));
+    assert_eq!(data.get(health()), Some(1.));
+}
+
+#[test]
+fn uses_defaults_for_missing_values() {
+    init();
+    let data: EntityData = serde_json::from_str(r#"{ "core::serialization::volume": null }"#).unwrap();
+    assert_eq!(data.get(volume()), Some(1.));
+    assert!(serde_json::from_str::<EntityData>(r#"{ "core::serialization::health": null }"#).is_err());
+
+    let data: DeserEntityDataWithWarnings = serde_json::from_str(r#"{ "core::serialization::volume": null }"#).unwrap();
+    assert_eq!(data.entity.get(volume()), Some(1.));
+    assert!(data.warnings.warnings.is_empty());
+}
+
+#[test]
+fn uses_defaults_for_malformed_values() {
+    init();
+    let data: DeserEntityDataWithWarnings =
+        serde_json::from_str(r#"{ "core::serialization::volume": "loud", "core::serialization::health": "full" }"#).unwrap();
+    assert_eq!(data.entity.get(volume()), Some(1.));
+    assert!(!data.entity.contains(health()));
+    assert_eq!(data.warnings.warnings.len(), 2);
+}
@@ -0,0 +1,79 @@
+use ambient_ecs::{components, query, ComponentSet, EntityData, QueryState, World};
+
+components!("snapshot", {
+    a: f32,
+    b: f32,
+    name: String,
+});
+
+fn init() {
+    init_components();
+}
+
+#[test]
+fn restore_full_snapshot() {
+    init();
+    let mut world = World::new("restore_full_snapshot");
+    let kept = world.spawn(EntityData::new().set(a(), 1.).set(name(), "kept".to_string()));
+    let despawned = world.spawn(EntityData::new().set(a(), 2.).set(b(), 3.));
+    let snapshot = world.snapshot();
+
+    world.set(kept, a(), 10.).unwrap();
+    world.remove_component(kept, name()).unwrap();
+    world.add_component(kept, b(), 5.).unwrap();
+    world.despawn(despawned);
+    let spawned = world.spawn(EntityData::new().set(a(), 4.));
+
+    world.restore(&snapshot);
+    assert_eq!(world.get(kept, a()).unwrap(), 1.);
+    assert_eq!(world.get_ref(kept, name()).unwrap(), "kept");
+    assert!(!world.has_component(kept, b()));
+    assert_eq!(world.get(despawned, a()).unwrap(), 2.);
+    assert_eq!(world.get(despawned, b()).unwrap(), 3.);
+    assert!(!world.exists(spawned));
+    assert_eq!(world.len(), snapshot.len());
+}
+
+#[test]
+fn restore_filtered_snapshot() {
+    init();
+    let mut world = World::new("restore_filtered_snapshot");
+    let entity = world.spawn(EntityData::new().set(a(), 1.).set(b(), 1.));
+    let other = world.spawn(EntityData::new().set(b(), 2.));
+    let mut components = ComponentSet::new();
+    components.insert(a().desc());
+    let snapshot = world.snapshot_components(components);
+    assert!(snapshot.contains(entity));
+    assert!(!snapshot.contains(other));
+
+    world.set(entity, a(), 10.).unwrap();
+    world.set(entity, b(), 10.).unwrap();
+    world.add_component(other, a(), 3.).unwrap();
+    let spawned = world.spawn(EntityData::new().set(b(), 4.));
+
+    world.restore(&snapshot);
+    assert_eq!(world.get(entity, a()).unwrap(), 1.);
+    // Components outside of the snapshot are left as they are
+    assert_eq!(world.get(entity, b()).unwrap(), 10.);
+    assert!(!world.has_component(other, a()));
+    assert!(world.exists(spawned));
+}
+
+#[test]
+fn restore_only_writes_changed_components() {
+    init();
+    let mut world = World::new("restore_only_writes_changed_components");
+    let unchanged = world.spawn(EntityData::new().set(a(), 1.));
+    let changed = world.spawn(EntityData::new().set(a(), 2.));
+    let snapshot = world.snapshot();
+
+    let q = query(a().changed());
+    let mut state = QueryState::new();
+    q.iter(&world, Some(&mut state)).count();
+    world.set(changed, a(), 5.).unwrap();
+    world.restore(&snapshot);
+    let changes = q.iter(&world, Some(&mut state)).map(|(id, _)| id).collect::<Vec<_>>();
+    assert_eq!(changes, vec![changed]);
+    assert_eq!(world.get(changed, a()).unwrap(), 2.);
+    assert_eq!(world.get(unchanged, a()).unwrap(), 1.);
+}
@@ -1,6 +1,9 @@
 use std::sync::Arc;
 
-use ambient_ecs::{components, ArchetypeFilter, EntityData, Query, World, WorldDiff, WorldStream, WorldStreamFilter};
+use ambient_ecs::{
+    components, ArchetypeFilter, Debuggable, EntityData, EntityId, Networked, Query, World, WorldChange, WorldDiff, WorldStream,
+    WorldStreamFilter,
+};
 use itertools::Itertools;
 
 components!("test", {
@@ -8,6 +11,12 @@ components!("test", {
     b: f32,
     c: f32,
     no_sync: (),
+    @[Networked, Debuggable]
+    health: f32,
+    @[Networked, Debuggable]
+    name: String,
+    @[Debuggable]
+    label: String,
 });
 
 fn init() {
@@ -40,6 +49,62 @@ fn from_a_to_b_remove_component() {
     }
 }
 
+#[test]
+fn between_unrelated_worlds() {
+    init();
+    let (x, y, z, w) = (EntityId::new(), EntityId::new(), EntityId::new(), EntityId::new());
+    let mut old = World::new("between_unrelated_worlds_old");
+    old.spawn_with_id(x, EntityData::new().set(health(), 1.).set(name(), "x".to_string()));
+    old.spawn_with_id(y, EntityData::new().set(health(), 2.).set(name(), "y".to_string()));
+    old.spawn_with_id(z, EntityData::new().set(health(), 3.));
+
+    // Built separately, so the content versions don't match the old world's
+    let mut new = World::new("between_unrelated_worlds_new");
+    new.spawn_with_id(w, EntityData::new().set(health(), 4.));
+    new.spawn_with_id(y, EntityData::new().set(health(), 2.));
+    new.set(y, health(), 2.).unwrap();
+    new.add_component(y, a(), 5.).unwrap();
+    new.spawn_with_id(x, EntityData::new().set(health(), 1.).set(name(), "renamed".to_string()));
+
+    let diff = WorldDiff::between(&old, &new);
+    assert_eq!(diff.changes.iter().filter(|c| c.is_set()).count(), 1);
+    assert_eq!(diff.changes.iter().filter(|c| c.is_remove_components()).count(), 1);
+    assert_eq!(diff.changes.len(), 5);
+
+    let mut target = old.clone();
+    diff.apply(&mut target, EntityData::new(), false);
+    assert_eq!(dump_content_string(&target), dump_content_string(&new));
+    assert_eq!(WorldDiff::between(&new, &new).changes.len(), 0);
+}
+
+#[test]
+fn between_compares_values_only() {
+    init();
+    let x = EntityId::new();
+    let mut old = World::new("between_compares_values_only_old");
+    old.spawn_with_id(x, EntityData::new().set(label(), "x".to_string()).set(a(), 1.));
+    let mut new = World::new("between_compares_values_only_new");
+    new.spawn_with_id(x, EntityData::new().set(label(), "x".to_string()).set(a(), 1.));
+    // Both labels were set once since they were spawned, but to different values
+    new.set(x, label(), "y".to_string()).unwrap();
+    old.set(x, label(), "x".to_string()).unwrap();
+
+    let diff = WorldDiff::between(&old, &new);
+    // The label is compared debug formatted, while `a` can't be compared
+    assert_eq!(diff.changes.len(), 1);
+    let mut target = old.clone();
+    diff.apply(&mut target, EntityData::new(), false);
+    assert_eq!(target.get_ref(x, label()).unwrap(), "y");
+
+    // Set twice, to the same value as the old label
+    new.set(x, label(), "z".to_string()).unwrap();
+    new.set(x, label(), "x".to_string()).unwrap();
+    new.add_component(x, b(), 2.).unwrap();
+    let diff = WorldDiff::between(&old, &new);
+    assert_eq!(diff.changes.len(), 1);
+    assert!(matches!(&diff.changes[0], WorldChange::AddComponents(id, _) if *id == x));
+}
+
 #[test]
 fn streaming() {
     init();
@@ -79,6 +144,7 @@ fn streaming() {
 fn dump_content_string(world: &World) -> String {
     Query::all()
         .iter(world, None)
+        .sorted_by_key(|ea| ea.id())
         .filter_map(|ea| {
             let id = ea.id();
             if id != world.resource_entity() {
@@ -88,7 +154,7 @@ fn dump_content_string(world: &World) -> String {
                     .into_iter()
                     .map(|desc| {
                         let value = world.get_entry(id, desc).unwrap();
-                        format!("{:?}: {:?}", desc, desc.as_debug(&value))
+                        format!("{:?}: {:?}", desc, value.as_debug())
                     })
                     .join(", ");
                 Some(format!("[{id} {data}]"))
@@ -1,8 +1,14 @@
+use std::collections::HashSet;
+
 use ambient_core::{
-    self, selectable, snap_to_ground,
+    self,
+    hierarchy::{add_child, despawn_recursive, parent, remove_child},
+    name,
+    scene::Scene,
+    selectable, snap_to_ground,
     transform::{get_world_transform, rotation, scale, translation},
 };
-use ambient_ecs::{components, EntityData, EntityId, World};
+use ambient_ecs::{components, EntityData, EntityId, SerializationProfile, World};
 use ambient_intent::{use_old_state, IntentContext, IntentRegistry};
 use ambient_network::get_player_by_user_id;
 use ambient_physics::{collider::collider_shapes_convex, main_physics_scene, physx::rigid_actor, PxShapeUserData};
@@ -16,7 +22,7 @@ use ordered_float::OrderedFloat;
 use physxx::{PxActor, PxQueryFilterData, PxRaycastCallback, PxTransform, PxUserData};
 use serde::{Deserialize, Serialize};
 
-use crate::{selection, ui::entity_editor::EntityComponentChange, Selection};
+use crate::{annotation, annotation_arrow, selection, ui::entity_editor::EntityComponentChange, Selection};
 use ambient_prefab::prefab_from_url;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -62,7 +68,11 @@ components!("editor", {
     intent_spawn_object_undo: (EntityId, bool, Selection),
     intent_spawn_object: IntentSpawnObject,
     intent_duplicate: IntentDuplicate,
-    intent_duplicate_undo: Vec<EntityId>,
+    intent_duplicate_undo: (Vec<EntityId>, Selection),
+    intent_paste: IntentPaste,
+    intent_paste_undo: (Vec<EntityId>, Selection),
+    intent_spawn_annotation: IntentSpawnAnnotation,
+    intent_spawn_annotation_undo: (Vec<EntityId>, Selection),
     intent_delete: Vec<EntityId>,
     intent_delete_undo: (World, Selection),
     intent_component_change: (EntityId, EntityComponentChange),
@@ -77,10 +87,21 @@ pub struct IntentTransform {
     pub terrain_offset: TerrainOffset,
 }
 
+/// Duplicates `entities` with all their descendants, next to them
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct IntentDuplicate {
     pub entities: Vec<EntityId>,
-    pub new_uids: Vec<EntityId>,
+    /// Added to the translation of the copies of `entities`
+    pub offset: Vec3,
+    pub select: bool,
+}
+
+/// Spawns the entities copied to the clipboard with [copy_to_clipboard]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IntentPaste {
+    pub scene: Scene,
+    /// Added to the translation of the roots of the scene
+    pub offset: Vec3,
     pub select: bool,
 }
 
@@ -92,6 +113,17 @@ pub struct IntentSpawnObject {
     pub select: bool,
 }
 
+/// Leaves an [annotation] in the level
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IntentSpawnAnnotation {
+    pub entity_id: EntityId,
+    pub position: Vec3,
+    pub text: String,
+    /// The point the annotation points at, if any
+    pub arrow: Option<Vec3>,
+    pub select: bool,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum MovePosition {
     Raycast { ray: Ray },
@@ -548,29 +580,50 @@ pub fn register_intents(reg: &mut IntentRegistry) {
     reg.register(
         intent_duplicate(),
         intent_duplicate_undo(),
-        |ctx, IntentDuplicate { entities, new_uids, select }| {
+        |ctx, IntentDuplicate { entities, offset, select }| {
             let world = ctx.world;
-            let player_entity = get_player_by_user_id(world, ctx.user_id).context("Player not found")?;
-
-            for (id, new_id) in entities.iter().zip(new_uids.iter()) {
-                let data = world.clone_entity(*id)?.serializable();
-                world.spawn_with_id(*new_id, data);
-            }
-
-            // Set the selection to the new objects
-            if select {
-                world.set(player_entity, selection(), Selection::new(new_uids.clone())).ok();
+            let scene = Scene::from_world_for_profile(world, entities, None, SerializationProfile::EDITOR_COPY_PASTE)?;
+            // The copies go under the parents of the originals
+            let parents = scene_roots(&scene).map(|id| world.get(id, parent()).ok()).collect_vec();
+            let roots = spawn_copy(world, &scene, offset);
+            for (&id, parent_id) in roots.iter().zip(parents) {
+                if let Some(parent_id) = parent_id {
+                    world.add_component(id, parent(), parent_id)?;
+                    add_child(world, parent_id, id)?;
+                }
             }
-
-            Ok(new_uids)
+            let old_selection = select_new_entities(world, ctx.user_id, &roots, select)?;
+            Ok((roots, old_selection))
+        },
+        undo_spawn,
+        use_old_state,
+    );
+    reg.register(
+        intent_paste(),
+        intent_paste_undo(),
+        |ctx, IntentPaste { scene, offset, select }| {
+            let world = ctx.world;
+            let roots = spawn_copy(world, &scene, offset);
+            let old_selection = select_new_entities(world, ctx.user_id, &roots, select)?;
+            Ok((roots, old_selection))
         },
-        |ctx, ids| {
+        undo_spawn,
+        use_old_state,
+    );
+    reg.register(
+        intent_spawn_annotation(),
+        intent_spawn_annotation_undo(),
+        |ctx, IntentSpawnAnnotation { entity_id, position, text, arrow, select }| {
             let world = ctx.world;
-            for id in ids {
-                world.despawn(id);
+            let mut data = EntityData::new().set(name(), "Annotation".to_string()).set(translation(), position).set(annotation(), text);
+            if let Some(arrow) = arrow {
+                data.set_self(annotation_arrow(), arrow);
             }
-            Ok(())
+            world.spawn_with_id(entity_id, data);
+            let old_selection = select_new_entities(world, ctx.user_id, &[entity_id], select)?;
+            Ok((vec![entity_id], old_selection))
         },
+        undo_spawn,
         use_old_state,
     );
     reg.register(
@@ -623,6 +676,77 @@ pub fn register_intents(reg: &mut IntentRegistry) {
     // ],
 }
 
+/// The [Scene] of `entities` with all their descendants, for [IntentPaste].
+///
+/// `world` is the world of the client, so only the components which are replicated are copied.
+pub fn copy_scene(world: &World, entities: Vec<EntityId>) -> anyhow::Result<Scene> {
+    let mut scene = Scene::from_world_for_profile(world, entities, None, SerializationProfile::EDITOR_COPY_PASTE)?;
+    for entity in &mut scene.entities {
+        entity.components.filter(&|desc| SerializationProfile::REPLICATION.includes(desc));
+    }
+    Ok(scene)
+}
+
+/// Copies `entities` with all their descendants to the clipboard, as a [Scene] for [IntentPaste]
+pub fn copy_to_clipboard(world: &World, entities: Vec<EntityId>) -> anyhow::Result<()> {
+    arboard::Clipboard::new()?.set_text(copy_scene(world, entities)?.to_json()?)?;
+    Ok(())
+}
+
+/// The [Scene] on the clipboard, if any
+pub fn paste_from_clipboard() -> Option<Scene> {
+    let text = arboard::Clipboard::new().ok()?.get_text().ok()?;
+    Scene::from_json(&text).ok()
+}
+
+/// The ids the roots of `scene` had when it was saved
+fn scene_roots(scene: &Scene) -> impl Iterator<Item = EntityId> + '_ {
+    let ids = scene.entities.iter().map(|entity| entity.id).collect::<HashSet<_>>();
+    scene
+        .entities
+        .iter()
+        .filter(move |entity| entity.components.get(parent()).map(|parent| !ids.contains(&parent)).unwrap_or(true))
+        .map(|entity| entity.id)
+}
+
+/// Spawns a copy of `scene`, with the references between its entities remapped to the copies, and its roots moved by
+/// `offset`. Returns the new ids of the roots.
+fn spawn_copy(world: &mut World, scene: &Scene, offset: Vec3) -> Vec<EntityId> {
+    let roots = scene_roots(scene).collect::<HashSet<_>>();
+    let mut scene = scene.clone();
+    for entity in scene.entities.iter_mut().filter(|entity| roots.contains(&entity.id)) {
+        if let Some(translation) = entity.components.get_mut(translation()) {
+            *translation += offset;
+        }
+    }
+    scene.spawn(world)
+}
+
+/// Selects the new entities `roots`, if `select`. Returns the previous selection.
+fn select_new_entities(world: &mut World, user_id: &str, roots: &[EntityId], select: bool) -> anyhow::Result<Selection> {
+    let player_entity = get_player_by_user_id(world, user_id).context("Player not found")?;
+    let old_selection = world.get_ref(player_entity, selection()).cloned().context("Failed to get selection")?;
+    if select {
+        world.set(player_entity, selection(), Selection::new(roots.to_vec()))?;
+    }
+    Ok(old_selection)
+}
+
+/// Despawns the new entities `roots` with their descendants, and restores the previous selection
+fn undo_spawn(ctx: IntentContext, (roots, old_selection): (Vec<EntityId>, Selection)) -> anyhow::Result<()> {
+    let world = ctx.world;
+    for id in roots {
+        if let Ok(parent_id) = world.get(id, parent()) {
+            remove_child(world, parent_id, id).ok();
+        }
+        despawn_recursive(world, id);
+    }
+    if let Some(player_entity) = get_player_by_user_id(world, ctx.user_id) {
+        world.set(player_entity, selection(), old_selection).ok();
+    }
+    Ok(())
+}
+
 /// Describes a ray intersection
 #[derive(Debug, Clone)]
 pub struct Intersection {
@@ -683,3 +807,40 @@ fn set_snap_to_ground(world: &mut World, id: EntityId, height: f32) {
     // Modify the transformed z value
     world.add_component(id, snap_to_ground(), height).expect("Invalid entity");
 }
+
+#[cfg(test)]
+mod tests {
+    use ambient_core::hierarchy::{children, set_parent};
+    use glam::vec3;
+
+    use super::*;
+
+    #[test]
+    fn copy_paste_round_trip() {
+        ambient_core::init_all_components();
+        let mut world = World::new("copy_paste_round_trip");
+        let root = world.spawn(EntityData::new().set(name(), "root".to_string()).set(translation(), vec3(1., 2., 3.)));
+        let child = world.spawn(EntityData::new().set(name(), "child".to_string()).set(translation(), vec3(0., 0., 1.)));
+        set_parent(&mut world, child, Some(root)).unwrap();
+
+        let json = copy_scene(&world, vec![root]).unwrap().to_json().unwrap();
+        let scene = Scene::from_json(&json).unwrap();
+        assert_eq!(scene_roots(&scene).collect_vec(), vec![root]);
+
+        let roots = spawn_copy(&mut world, &scene, vec3(10., 0., 0.));
+        assert_eq!(roots.len(), 1);
+        let new_root = roots[0];
+        assert_ne!(new_root, root);
+        assert_eq!(world.get_ref(new_root, name()).unwrap(), "root");
+        // Only the roots are moved, the descendants follow them
+        assert_eq!(world.get(new_root, translation()).unwrap(), vec3(11., 2., 3.));
+        let new_children = world.get_cloned(new_root, children()).unwrap();
+        assert_eq!(new_children.len(), 1);
+        assert_ne!(new_children[0], child);
+        assert_eq!(world.get(new_children[0], parent()).unwrap(), new_root);
+        assert_eq!(world.get(new_children[0], translation()).unwrap(), vec3(0., 0., 1.));
+        // The originals are untouched
+        assert_eq!(world.get(root, translation()).unwrap(), vec3(1., 2., 3.));
+        assert_eq!(world.get_ref(root, children()).unwrap(), &vec![child]);
+    }
+}
@@ -1,4 +1,5 @@
-use ambient_ecs::{components, EntityId};
+use ambient_ecs::{components, Debuggable, Description, EntityId, Name, Networked, Store};
+use glam::Vec3;
 use std::iter::Cloned;
 
 #[macro_use]
@@ -10,6 +11,19 @@ pub mod ui;
 components!("editor", {
     selection: Selection,
     prev_selection: Selection,
+
+    @[
+        Debuggable, Networked, Store,
+        Name["Annotation"],
+        Description["A note left in the level, e.g. during a review, which the editor shows at the position of this entity.\nThe game doesn't show it."]
+    ]
+    annotation: String,
+    @[
+        Debuggable, Networked, Store,
+        Name["Annotation arrow"],
+        Description["If attached to an annotation, the editor draws an arrow from the note to this point."]
+    ]
+    annotation_arrow: Vec3,
 });
 
 pub fn init_all_components() {
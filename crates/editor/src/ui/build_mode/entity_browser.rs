@@ -8,7 +8,9 @@ use ambient_network::{
     rpc::rpc_world_diff,
 };
 use ambient_std::{cb, Cb};
-use ambient_ui::{fit_horizontal, space_between_items, Button, ButtonStyle, DialogScreen, Fit, FlowColumn, FlowRow, ScrollArea, STREET};
+use ambient_ui::{
+    fit_horizontal, space_between_items, Button, ButtonStyle, DialogScreen, Fit, FlowColumn, FlowRow, ScrollArea, UiStateScope, STREET,
+};
 use itertools::Itertools;
 
 #[derive(Debug, Clone)]
@@ -92,7 +94,8 @@ impl ElementComponent for EntityBrowserScreen {
     fn render(self: Box<Self>, hooks: &mut Hooks) -> Element {
         let Self { on_select, on_back } = *self;
         let (advanced, set_advanced) = hooks.use_state(false);
-        DialogScreen(
+        DialogScreen(UiStateScope::el(
+            "entity_browser",
             ScrollArea(
                 FlowColumn::el([
                     FlowRow::el([
@@ -138,7 +141,7 @@ impl ElementComponent for EntityBrowserScreen {
             )
             .el()
             .set(fit_horizontal(), Fit::Parent),
-        )
+        ))
         .el()
     }
 }
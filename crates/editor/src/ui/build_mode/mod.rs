@@ -9,6 +9,7 @@ use ambient_network::client::GameClient;
 use ambient_sys::task::RuntimeHandle;
 use derive_more::Display;
 use futures_signals::signal::SignalExt;
+use glam::Vec3;
 use itertools::Itertools;
 
 use ambient_std::{
@@ -26,7 +27,10 @@ use winit::event::{ElementState, VirtualKeyCode};
 
 use super::{terrain_mode::GenerateTerrainButton, EditorPlayerInputHandler, EditorPrefs};
 use crate::{
-    intents::{intent_delete, intent_duplicate, intent_spawn_object, IntentDuplicate, IntentSpawnObject, SelectMode},
+    intents::{
+        copy_to_clipboard, intent_delete, intent_duplicate, intent_paste, intent_spawn_object, paste_from_clipboard, IntentDuplicate,
+        IntentPaste, IntentSpawnObject, SelectMode,
+    },
     ui::use_player_selection,
     Selection, GRID_SIZE,
 };
@@ -37,14 +41,19 @@ mod guide;
 mod select_area;
 mod selection_panel;
 mod transform;
+mod viewport_tools;
 
 use guide::*;
 use select_area::*;
 use selection_panel::*;
 use transform::*;
+use viewport_tools::*;
 
 use self::entity_browser::EntityBrowserScreen;
 
+/// How far the pasted entities are moved from where they were copied, so that they don't overlap the originals
+const PASTE_OFFSET: Vec3 = Vec3::new(1., 1., 0.);
+
 /// An editor can only be in one action at a time.
 /// They can be confirmed or aborted.
 ///
@@ -129,6 +138,7 @@ impl ElementComponent for EditorBuildMode {
         let set_select_mode = hooks.provide_context(|| SelectMode::Set);
         let set_srt_mode = hooks.provide_context(|| None as Option<TransformMode>);
         let (screen, set_screen) = hooks.use_state(None);
+        let (tool, set_tool) = hooks.use_state(None as Option<ViewportTool>);
 
         let targets = hooks.use_ref_with::<Arc<[EntityId]>>(|_| Arc::from([]));
         let rerender = hooks.use_rerender_signal();
@@ -259,10 +269,51 @@ impl ElementComponent for EditorBuildMode {
                     })
                     .tooltip("Browse entities")
                     .el(),
+                    Button::new("\u{f0ea}", closure!(clone game_client, clone set_srt_mode, |world| {
+                        let scene = match paste_from_clipboard() {
+                            Some(scene) => scene,
+                            None => return,
+                        };
+                        let set_srt_mode = set_srt_mode.clone();
+                        world.resource(runtime()).spawn(client_push_intent(
+                            game_client.clone(),
+                            intent_paste(),
+                            IntentPaste { scene, offset: PASTE_OFFSET, select: true },
+                            None,
+                            Some(Box::new(move || set_srt_mode(Some(TransformMode::Translate)))),
+                        ));
+                    }))
+                    .tooltip("Paste")
+                    .hotkey(VirtualKeyCode::V)
+                    .hotkey_modifier(command_modifier())
+                    .el(),
+                    Separator { vertical: true }.el(),
+                    Button::new("\u{f545}", closure!(clone set_tool, |_| {
+                        set_tool(if tool == Some(ViewportTool::Measure) { None } else { Some(ViewportTool::Measure) })
+                    }))
+                    .tooltip("Measure")
+                    .toggled(tool == Some(ViewportTool::Measure))
+                    .el(),
+                    Button::new("\u{f249}", closure!(clone set_tool, |_| {
+                        set_tool(if tool == Some(ViewportTool::Annotate) { None } else { Some(ViewportTool::Annotate) })
+                    }))
+                    .tooltip("Annotate")
+                    .toggled(tool == Some(ViewportTool::Annotate))
+                    .el(),
                 ];
                 if !selection.is_empty() {
                     items.extend([
                         Separator { vertical: true }.el(),
+                        Button::new("\u{f0c5}", closure!(clone game_client, clone targets, |_| {
+                            let res = copy_to_clipboard(&game_client.game_state.lock().world, targets.to_vec());
+                            if let Err(err) = res {
+                                tracing::error!("Failed to copy {targets:?}: {err:?}");
+                            }
+                        }))
+                        .tooltip("Copy")
+                        .hotkey(VirtualKeyCode::C)
+                        .hotkey_modifier(command_modifier())
+                        .el(),
                         Button::new(
                             "\u{f68e}",
                             closure!(clone game_client, clone targets, clone set_srt_mode, |world| {
@@ -271,7 +322,7 @@ impl ElementComponent for EditorBuildMode {
 
                                 tracing::info!("Duplicating {targets:?}");
                                 world.resource(runtime()).spawn(
-                                    client_push_intent(game_client, intent_duplicate(), IntentDuplicate { new_uids: targets.iter().map(|_| EntityId::new()).collect(), entities: targets.to_vec(), select: true }, None, Some(Box::new(move || {
+                                    client_push_intent(game_client, intent_duplicate(), IntentDuplicate { entities: targets.to_vec(), offset: Vec3::ZERO, select: true }, None, Some(Box::new(move || {
                                         tracing::info!("Entering translate move");
 
 
@@ -313,7 +364,12 @@ impl ElementComponent for EditorBuildMode {
                 .set(padding(), Borders::even(STREET)),
             GenerateTerrainButton.el()
                 .set(margin(), Borders::even(STREET)),
-            SelectArea.el(),
+            Annotations.el(),
+            match tool {
+                None => SelectArea.el(),
+                Some(ViewportTool::Measure) => MeasureTool.el(),
+                Some(ViewportTool::Annotate) => AnnotateTool.el(),
+            },
         ])
             .el()
     }
@@ -0,0 +1,397 @@
+//! Tools to review a level in the viewport: measuring the distances and the angles between points and entities, and
+//! leaving [annotation]s in the level for the others to read.
+//!
+//! The tools pick the points which are clicked in the viewport instead of selecting the entities there. The annotations
+//! are entities of their own, which are saved with the level, but which only the editor shows.
+
+use ambient_core::{
+    get_mouse_clip_space_position, runtime,
+    transform::{get_world_position, local_to_parent, translation},
+    window_scale_factor,
+};
+use ambient_ecs::{query, EntityId, World};
+use ambient_element::{Element, ElementComponent, ElementComponentExt, Group, Hooks};
+use ambient_gizmos::{gizmos, GizmoPrimitive};
+use ambient_input::MouseButton;
+use ambient_intent::client_push_intent;
+use ambient_network::{client::GameClient, client_game_state::ClientGameState, log_network_result};
+use ambient_physics::intersection::{rpc_pick, RaycastFilter};
+use ambient_physics::ColliderScene;
+use ambient_renderer::color;
+use ambient_std::{cb, color::Color, line_hash, Cb};
+use ambient_ui::{
+    layout::{docking, width, Docking},
+    margin, padding, space_between_items, Borders, Button, Checkbox, Dock, FlowColumn, FlowRow, StylesExt, Text, TextInput, UIBase, UIExt,
+    STREET,
+};
+use glam::{Vec2, Vec3, Vec3Swizzles};
+use itertools::Itertools;
+
+use crate::{
+    annotation, annotation_arrow,
+    intents::{intent_spawn_annotation, IntentSpawnAnnotation, SelectMode},
+    rpc::{rpc_select, SelectMethod},
+    Selection,
+};
+
+/// The tool which handles the clicks in the viewport, instead of the selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewportTool {
+    Measure,
+    Annotate,
+}
+
+/// The color of the measurements and the annotations
+const TOOL_COLOR: Color = Color::rgba(1.0, 0.85, 0.2, 1.0);
+/// The radius of the lines the tools draw
+const LINE_RADIUS: f32 = 0.02;
+/// The radius of the points the tools draw
+const POINT_RADIUS: f32 = 0.08;
+
+/// Picks the points of the level which are clicked in the viewport, along with the entities they're on
+#[derive(Clone)]
+struct PickArea {
+    on_pick: Cb<dyn Fn(Vec3, EntityId) + Sync + Send>,
+}
+impl std::fmt::Debug for PickArea {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PickArea").finish_non_exhaustive()
+    }
+}
+impl ElementComponent for PickArea {
+    fn render(self: Box<Self>, hooks: &mut Hooks) -> Element {
+        let Self { on_pick } = *self;
+        let (game_client, _) = hooks.consume_context::<GameClient>().unwrap();
+        UIBase.el().on_mouse_down(move |world, _, button| {
+            if button != MouseButton::Left {
+                return;
+            }
+            let ray = game_client.game_state.lock().screen_ray(get_mouse_clip_space_position(world));
+            let game_client = game_client.clone();
+            let on_pick = on_pick.clone();
+            world.resource(runtime()).spawn(async move {
+                let filter = RaycastFilter { entities: None, collider_type: Some(ColliderScene::Physics) };
+                match game_client.rpc(rpc_pick, (ray, filter)).await {
+                    Ok(Some((id, distance))) => on_pick(ray.origin + ray.dir * distance, id),
+                    Ok(None) => {}
+                    Err(err) => tracing::warn!("Failed to pick a point: {err:?}"),
+                }
+            });
+        })
+    }
+}
+
+/// A point of a measurement
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MeasurePoint {
+    Position(Vec3),
+    /// The origin of an entity, which the measurement follows as it moves
+    Entity(EntityId),
+}
+impl MeasurePoint {
+    fn position(&self, world: &World) -> Option<Vec3> {
+        match *self {
+            Self::Position(position) => Some(position),
+            Self::Entity(id) => get_world_position(world, id).ok(),
+        }
+    }
+}
+
+/// A text shown at a point of the level
+#[derive(Debug, Clone, PartialEq)]
+struct Label {
+    /// In logical pixels
+    screen_position: Vec2,
+    text: String,
+    /// The annotation the label is of, which is selected when it's clicked
+    entity: Option<EntityId>,
+}
+
+/// Where `position` is on the screen, in logical pixels, if it's in front of the camera
+fn label_position(state: &ClientGameState, scale_factor: f32, position: Vec3) -> Option<Vec2> {
+    let clip = state.world_to_clip_space(position);
+    if !(0. ..=1.).contains(&clip.z) {
+        return None;
+    }
+    Some(state.clip_to_screen_space(clip) / scale_factor)
+}
+
+fn labels_el(labels: Vec<Label>, on_click: Option<Cb<dyn Fn(EntityId) + Sync + Send>>) -> Element {
+    Group(
+        labels
+            .into_iter()
+            .map(|Label { screen_position, text, entity }| {
+                let mut el = Text::el(text)
+                    .set(color(), TOOL_COLOR.into())
+                    .set(translation(), screen_position.extend(-0.05))
+                    .remove(local_to_parent());
+                if let (Some(entity), Some(on_click)) = (entity, on_click.clone()) {
+                    el = el.on_mouse_down(move |_, _, _| on_click(entity));
+                }
+                el
+            })
+            .collect(),
+    )
+    .el()
+}
+
+/// The distance between `a` and `b`, and how much of it is horizontal and vertical
+fn describe_distance(a: Vec3, b: Vec3) -> String {
+    let delta = b - a;
+    format!("{:.2} m ({:.2} m horizontally, {:.2} m vertically)", delta.length(), delta.xy().length(), delta.z)
+}
+
+/// The angle at `vertex` between the directions to `a` and to `b`, in degrees
+fn describe_angle(a: Vec3, vertex: Vec3, b: Vec3) -> String {
+    format!("{:.1}°", (a - vertex).angle_between(b - vertex).to_degrees())
+}
+
+/// Measures the distances between the points clicked in the viewport, and the angle between them once there are three
+#[derive(Debug, Clone)]
+pub struct MeasureTool;
+impl ElementComponent for MeasureTool {
+    fn render(self: Box<Self>, hooks: &mut Hooks) -> Element {
+        let (game_client, _) = hooks.consume_context::<GameClient>().unwrap();
+        let (points, set_points) = hooks.use_state(Vec::<MeasurePoint>::new());
+        let (snap_to_entities, set_snap_to_entities) = hooks.use_state(false);
+        let (labels, set_labels) = hooks.use_state(Vec::<Label>::new());
+        let (summary, set_summary) = hooks.use_state(Vec::<String>::new());
+        let gizmo_scope = line_hash!();
+
+        {
+            let game_state = game_client.game_state.clone();
+            hooks.use_spawn(move |_| {
+                Box::new(move |_| {
+                    // Clears the measurement
+                    game_state.lock().world.resource(gizmos()).scope(gizmo_scope);
+                })
+            });
+        }
+        {
+            let game_state = game_client.game_state.clone();
+            let points = points.clone();
+            let labels = labels.clone();
+            let summary = summary.clone();
+            hooks.use_frame(move |world| {
+                let scale_factor = *world.resource(window_scale_factor()) as f32;
+                let state = game_state.lock();
+                let positions = points.iter().filter_map(|point| point.position(&state.world)).collect_vec();
+
+                let mut new_labels = Vec::new();
+                let mut new_summary = Vec::new();
+                let mut scope = state.world.resource(gizmos()).scope(gizmo_scope);
+                for &position in &positions {
+                    scope.draw(GizmoPrimitive::sphere(position, POINT_RADIUS).with_color(TOOL_COLOR.into()));
+                }
+                for (&a, &b) in positions.iter().tuple_windows() {
+                    scope.draw(GizmoPrimitive::line(a, b, LINE_RADIUS).with_color(TOOL_COLOR.into()));
+                    let distance = describe_distance(a, b);
+                    if let Some(screen_position) = label_position(&state, scale_factor, (a + b) / 2.) {
+                        new_labels.push(Label { screen_position, text: format!("{:.2} m", a.distance(b)), entity: None });
+                    }
+                    new_summary.push(format!("Distance: {distance}"));
+                }
+                if let [a, vertex, b] = positions[..] {
+                    let angle = describe_angle(a, vertex, b);
+                    if let Some(screen_position) = label_position(&state, scale_factor, vertex) {
+                        new_labels.push(Label { screen_position, text: angle.clone(), entity: None });
+                    }
+                    new_summary.push(format!("Angle: {angle}"));
+                }
+                drop(scope);
+                drop(state);
+
+                if new_labels != labels {
+                    set_labels(new_labels);
+                }
+                if new_summary != summary {
+                    set_summary(new_summary);
+                }
+            });
+        }
+
+        let on_pick = cb(closure!(clone points, clone set_points, |position: Vec3, id: EntityId| {
+            let point = if snap_to_entities { MeasurePoint::Entity(id) } else { MeasurePoint::Position(position) };
+            // Up to three points: the distances between them, and the angle at the second one
+            let mut points = if points.len() < 3 { points.clone() } else { Vec::new() };
+            points.push(point);
+            set_points(points);
+        }));
+
+        let mut panel = vec![
+            Text::el(if summary.is_empty() { "Click in the viewport to measure".to_string() } else { summary.join("\n") }),
+            FlowRow(vec![
+                Checkbox::new(snap_to_entities, move |value| set_snap_to_entities(value)).el(),
+                Text::el("Snap to the origins of the entities"),
+            ])
+            .el(),
+        ];
+        if !points.is_empty() {
+            panel.push(Button::new("Clear", move |_| set_points(Vec::new())).el());
+        }
+
+        Dock(vec![
+            labels_el(labels, None),
+            FlowColumn(panel)
+                .el()
+                .floating_panel()
+                .set(docking(), Docking::Bottom)
+                .set(space_between_items(), STREET)
+                .set(margin(), Borders::even(STREET))
+                .set(padding(), Borders::even(STREET)),
+            PickArea { on_pick }.el(),
+        ])
+        .el()
+    }
+}
+
+/// Leaves an annotation at the point clicked in the viewport. With an arrow, the first click picks the point the arrow
+/// points at, and the second one where the note is.
+#[derive(Debug, Clone)]
+pub struct AnnotateTool;
+impl ElementComponent for AnnotateTool {
+    fn render(self: Box<Self>, hooks: &mut Hooks) -> Element {
+        let (game_client, _) = hooks.consume_context::<GameClient>().unwrap();
+        let (text, set_text) = hooks.use_state(String::new());
+        let (with_arrow, set_with_arrow) = hooks.use_state(false);
+        let (arrow, set_arrow) = hooks.use_state(None as Option<Vec3>);
+        let runtime = hooks.world.resource(runtime()).clone();
+
+        let on_pick = cb({
+            let text = text.clone();
+            let set_arrow = set_arrow.clone();
+            move |position: Vec3, _: EntityId| {
+                if text.is_empty() {
+                    return;
+                }
+                if with_arrow && arrow.is_none() {
+                    set_arrow(Some(position));
+                    return;
+                }
+                let intent = IntentSpawnAnnotation {
+                    entity_id: EntityId::new(),
+                    position,
+                    text: text.clone(),
+                    arrow: if with_arrow { arrow } else { None },
+                    select: false,
+                };
+                runtime.spawn(client_push_intent(game_client.clone(), intent_spawn_annotation(), intent, None, None));
+                set_arrow(None);
+            }
+        });
+
+        let hint = if text.is_empty() {
+            "Write the note, then click in the viewport to place it"
+        } else if with_arrow && arrow.is_none() {
+            "Click the point the arrow points at"
+        } else {
+            "Click where the note goes"
+        };
+        Dock(vec![
+            FlowColumn(vec![
+                TextInput::new(text, set_text).placeholder(Some("Note")).el().set(width(), 300.),
+                FlowRow(vec![
+                    Checkbox::new(with_arrow, move |value| {
+                        set_with_arrow(value);
+                        set_arrow(None);
+                    })
+                    .el(),
+                    Text::el("With an arrow"),
+                ])
+                .el(),
+                Text::el(hint),
+            ])
+            .el()
+            .floating_panel()
+            .set(docking(), Docking::Bottom)
+            .set(space_between_items(), STREET)
+            .set(margin(), Borders::even(STREET))
+            .set(padding(), Borders::even(STREET)),
+            PickArea { on_pick }.el(),
+        ])
+        .el()
+    }
+}
+
+/// Shows the annotations of the level. Clicking one selects it, to edit or delete it.
+#[derive(Debug, Clone)]
+pub struct Annotations;
+impl ElementComponent for Annotations {
+    fn render(self: Box<Self>, hooks: &mut Hooks) -> Element {
+        let (game_client, _) = hooks.consume_context::<GameClient>().unwrap();
+        let (labels, set_labels) = hooks.use_state(Vec::<Label>::new());
+        let runtime = hooks.world.resource(runtime()).clone();
+        let gizmo_scope = line_hash!();
+
+        {
+            let game_state = game_client.game_state.clone();
+            hooks.use_spawn(move |_| {
+                Box::new(move |_| {
+                    game_state.lock().world.resource(gizmos()).scope(gizmo_scope);
+                })
+            });
+        }
+        {
+            let game_state = game_client.game_state.clone();
+            let labels = labels.clone();
+            hooks.use_frame(move |world| {
+                let scale_factor = *world.resource(window_scale_factor()) as f32;
+                let state = game_state.lock();
+                let mut new_labels = Vec::new();
+                let mut scope = state.world.resource(gizmos()).scope(gizmo_scope);
+                for (id, (text,)) in query((annotation(),)).iter(&state.world, None) {
+                    let position = match get_world_position(&state.world, id) {
+                        Ok(position) => position,
+                        Err(_) => continue,
+                    };
+                    scope.draw(GizmoPrimitive::sphere(position, POINT_RADIUS).with_color(TOOL_COLOR.into()));
+                    if let Ok(arrow) = state.world.get(id, annotation_arrow()) {
+                        scope.draw(GizmoPrimitive::line(position, arrow, LINE_RADIUS).with_color(TOOL_COLOR.into()));
+                        scope.draw(GizmoPrimitive::sphere(arrow, POINT_RADIUS / 2.).with_color(TOOL_COLOR.into()));
+                    }
+                    if let Some(screen_position) = label_position(&state, scale_factor, position) {
+                        new_labels.push(Label { screen_position, text: text.clone(), entity: Some(id) });
+                    }
+                }
+                drop(scope);
+                drop(state);
+
+                if new_labels != labels {
+                    set_labels(new_labels);
+                }
+            });
+        }
+
+        let on_click = cb(move |id: EntityId| {
+            let game_client = game_client.clone();
+            runtime.spawn(async move {
+                log_network_result!(game_client.rpc(rpc_select, (SelectMethod::Manual(Selection::new([id])), SelectMode::Set)).await);
+            });
+        });
+        labels_el(labels, Some(on_click))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+
+    #[test]
+    fn distances() {
+        assert_eq!(describe_distance(vec3(1., 1., 0.), vec3(4., 5., 12.)), "13.00 m (5.00 m horizontally, 12.00 m vertically)");
+        // The vertical distance is signed, the others aren't
+        assert_eq!(describe_distance(vec3(0., 0., 2.), vec3(0., -3., 2.5)), "3.04 m (3.00 m horizontally, 0.50 m vertically)");
+        assert_eq!(describe_distance(vec3(0., 0., 2.), vec3(0., 0., 0.)), "2.00 m (0.00 m horizontally, -2.00 m vertically)");
+    }
+
+    #[test]
+    fn angles() {
+        let vertex = vec3(1., 1., 1.);
+        assert_eq!(describe_angle(vertex + Vec3::X, vertex, vertex + 2. * Vec3::Y), "90.0°");
+        assert_eq!(describe_angle(vertex + Vec3::X, vertex, vertex + vec3(1., 1., 0.)), "45.0°");
+        assert_eq!(describe_angle(vertex + Vec3::X, vertex, vertex - Vec3::X), "180.0°");
+        assert_eq!(describe_angle(vertex + Vec3::Z, vertex, vertex + Vec3::Z), "0.0°");
+    }
+}
@@ -2,6 +2,7 @@ use std::{collections::HashMap, fmt::Debug, io::Cursor};
 
 mod build_mode;
 pub mod entity_editor;
+pub mod replay;
 mod terrain_mode;
 
 use ambient_core::{game_mode, runtime, transform::translation, GameMode};
@@ -27,7 +28,7 @@ use ambient_ui::{
     command_modifier, height,
     layout::{docking, space_between_items, width, Borders, Docking},
     margin, use_interval, use_window_logical_resolution, Button, Editor, FlowColumn, FlowRow, FontAwesomeIcon, Hotkey, Rectangle,
-    ScreenContainer, ScrollArea, Separator, StylesExt, Text, UIExt, WindowSized, STREET,
+    ScreenContainer, ScrollArea, Separator, StylesExt, Text, UIExt, UiStateScope, WindowSized, STREET,
 };
 use build_mode::*;
 use glam::{vec3, Vec3};
@@ -131,111 +132,117 @@ pub fn EditorUI(hooks: &mut Hooks) -> Element {
             .el();
     }
 
-    Group(vec![
-        Crosshair.el(),
-        WindowSized(vec![
-            ScreenContainer(screen).el(),
-            FlowColumn::el([FlowRow::el([
-                Button::new(
-                    FontAwesomeIcon::el(0xf21c, true),
-                    closure!(clone set_editor_mode, |_| set_editor_mode(EditorMode::Experience)),
-                )
-                .hotkey(VirtualKeyCode::F1)
-                .toggled(editor_mode == EditorMode::Experience)
-                .tooltip("Experience")
-                .el(),
-                Button::new(
-                    FontAwesomeIcon::el(0xf6e3, true),
-                    closure!(clone set_editor_mode, |_| {
-                        set_editor_mode(EditorMode::Build);
-
-                    }),
-                )
-                .hotkey(VirtualKeyCode::F2)
-                .toggled(editor_mode == EditorMode::Build)
-                .tooltip("Build")
-                .el(),
-                Button::new(FontAwesomeIcon::el(0xe52f, true), closure!(clone set_editor_mode, |_| set_editor_mode(EditorMode::Terrain)))
+    UiStateScope::el(
+        "editor",
+        Group(vec![
+            Crosshair.el(),
+            WindowSized(vec![
+                ScreenContainer(screen).el(),
+                FlowColumn::el([FlowRow::el([
+                    Button::new(
+                        FontAwesomeIcon::el(0xf21c, true),
+                        closure!(clone set_editor_mode, |_| set_editor_mode(EditorMode::Experience)),
+                    )
+                    .hotkey(VirtualKeyCode::F1)
+                    .toggled(editor_mode == EditorMode::Experience)
+                    .tooltip("Experience")
+                    .el(),
+                    Button::new(
+                        FontAwesomeIcon::el(0xf6e3, true),
+                        closure!(clone set_editor_mode, |_| {
+                            set_editor_mode(EditorMode::Build);
+
+                        }),
+                    )
+                    .hotkey(VirtualKeyCode::F2)
+                    .toggled(editor_mode == EditorMode::Build)
+                    .tooltip("Build")
+                    .el(),
+                    Button::new(
+                        FontAwesomeIcon::el(0xe52f, true),
+                        closure!(clone set_editor_mode, |_| set_editor_mode(EditorMode::Terrain)),
+                    )
                     .hotkey(VirtualKeyCode::F3)
                     .toggled(editor_mode == EditorMode::Terrain)
                     .tooltip("Terrain")
                     .el(),
-                Button::new(
-                    FontAwesomeIcon::el(0xf73c, true),
-                    closure!(clone set_editor_mode, |_| set_editor_mode(EditorMode::Atmosphere)),
-                )
-                .hotkey(VirtualKeyCode::F5)
-                .toggled(editor_mode == EditorMode::Atmosphere)
-                .tooltip("Atmosphere")
-                .el(),
-                Button::new(
-                    FontAwesomeIcon::el(0xf1bb, true),
-                    closure!(clone set_editor_mode, |_| set_editor_mode(EditorMode::NaturalLayers)),
-                )
-                .hotkey(VirtualKeyCode::F7)
-                .toggled(editor_mode == EditorMode::NaturalLayers)
-                .tooltip("Biomes")
-                .el(),
-                Button::new(
-                    FontAwesomeIcon::el(0xf06c, true),
-                    closure!(clone set_editor_mode, |_| set_editor_mode(EditorMode::TerrainMaterial)),
-                )
-                .hotkey(VirtualKeyCode::F8)
-                .toggled(editor_mode == EditorMode::TerrainMaterial)
-                .tooltip("Ground materials")
-                .el(),
-                Separator { vertical: true }.el(),
-                Button::new(FontAwesomeIcon::el(0xf815, true), closure!(clone set_hide_ui, |_| set_hide_ui(true)))
-                    .hotkey(VirtualKeyCode::P)
+                    Button::new(
+                        FontAwesomeIcon::el(0xf73c, true),
+                        closure!(clone set_editor_mode, |_| set_editor_mode(EditorMode::Atmosphere)),
+                    )
+                    .hotkey(VirtualKeyCode::F5)
+                    .toggled(editor_mode == EditorMode::Atmosphere)
+                    .tooltip("Atmosphere")
+                    .el(),
+                    Button::new(
+                        FontAwesomeIcon::el(0xf1bb, true),
+                        closure!(clone set_editor_mode, |_| set_editor_mode(EditorMode::NaturalLayers)),
+                    )
+                    .hotkey(VirtualKeyCode::F7)
+                    .toggled(editor_mode == EditorMode::NaturalLayers)
+                    .tooltip("Biomes")
+                    .el(),
+                    Button::new(
+                        FontAwesomeIcon::el(0xf06c, true),
+                        closure!(clone set_editor_mode, |_| set_editor_mode(EditorMode::TerrainMaterial)),
+                    )
+                    .hotkey(VirtualKeyCode::F8)
+                    .toggled(editor_mode == EditorMode::TerrainMaterial)
+                    .tooltip("Ground materials")
+                    .el(),
+                    Separator { vertical: true }.el(),
+                    Button::new(FontAwesomeIcon::el(0xf815, true), closure!(clone set_hide_ui, |_| set_hide_ui(true)))
+                        .hotkey(VirtualKeyCode::P)
+                        .hotkey_modifier(command_modifier())
+                        .tooltip("Hide UI")
+                        .el(),
+                    // UploadThumbnailButton.el(),
+                    Button::new_async(FontAwesomeIcon::el(0xf2ea, true), {
+                        let game_client = game_client.clone();
+                        move || {
+                            let game_client = game_client.clone();
+                            async move {
+                                game_client.rpc(rpc_undo_head, ()).await.ok();
+                            }
+                        }
+                    })
+                    .hotkey(VirtualKeyCode::Z)
                     .hotkey_modifier(command_modifier())
-                    .tooltip("Hide UI")
+                    .tooltip("Undo")
                     .el(),
-                // UploadThumbnailButton.el(),
-                Button::new_async(FontAwesomeIcon::el(0xf2ea, true), {
-                    let game_client = game_client.clone();
-                    move || {
+                    Button::new_async(FontAwesomeIcon::el(0xf2f9, true), move || {
                         let game_client = game_client.clone();
                         async move {
-                            game_client.rpc(rpc_undo_head, ()).await.ok();
+                            game_client.rpc(rpc_redo, ()).await.ok();
                         }
-                    }
-                })
-                .hotkey(VirtualKeyCode::Z)
-                .hotkey_modifier(command_modifier())
-                .tooltip("Undo")
-                .el(),
-                Button::new_async(FontAwesomeIcon::el(0xf2f9, true), move || {
-                    let game_client = game_client.clone();
-                    async move {
-                        game_client.rpc(rpc_redo, ()).await.ok();
-                    }
-                })
-                .hotkey(VirtualKeyCode::Z)
-                .hotkey_modifier(command_modifier() | ModifiersState::SHIFT)
-                .tooltip("Redo")
-                .el(),
-                ServerInstancesInfo.el(),
+                    })
+                    .hotkey(VirtualKeyCode::Z)
+                    .hotkey_modifier(command_modifier() | ModifiersState::SHIFT)
+                    .tooltip("Redo")
+                    .el(),
+                    ServerInstancesInfo.el(),
+                ])
+                .floating_panel()
+                .keyboard()
+                .set(margin(), Borders::even(STREET).set_bottom(0.))]),
+                if user_settings.debug_intents {
+                    IntentHistoryVisualizer.el().set(margin(), Borders::even(STREET)).set(docking(), Docking::Top)
+                } else {
+                    Element::new()
+                },
+                match editor_mode {
+                    EditorMode::Experience => EditorExperienceMode.el(),
+                    EditorMode::Terrain => EditorTerrainMode.el(),
+                    EditorMode::Build => EditorBuildMode.el(),
+                    EditorMode::Atmosphere => EditorAtmosphereMode.el(),
+                    EditorMode::NaturalLayers => NaturalLayersEditor.el().set(docking(), Docking::Left).set(width(), 500.),
+                    EditorMode::TerrainMaterial => TerrainMaterialEditor.el().set(docking(), Docking::Left).set(width(), 500.),
+                },
             ])
-            .floating_panel()
-            .keyboard()
-            .set(margin(), Borders::even(STREET).set_bottom(0.))]),
-            if user_settings.debug_intents {
-                IntentHistoryVisualizer.el().set(margin(), Borders::even(STREET)).set(docking(), Docking::Top)
-            } else {
-                Element::new()
-            },
-            match editor_mode {
-                EditorMode::Experience => EditorExperienceMode.el(),
-                EditorMode::Terrain => EditorTerrainMode.el(),
-                EditorMode::Build => EditorBuildMode.el(),
-                EditorMode::Atmosphere => EditorAtmosphereMode.el(),
-                EditorMode::NaturalLayers => NaturalLayersEditor.el().set(docking(), Docking::Left).set(width(), 500.),
-                EditorMode::TerrainMaterial => TerrainMaterialEditor.el().set(docking(), Docking::Left).set(width(), 500.),
-            },
+            .el(),
         ])
         .el(),
-    ])
-    .el()
+    )
 }
 
 #[element_component]
@@ -270,25 +277,28 @@ fn TerrainMaterialEditor(hooks: &mut Hooks) -> Element {
     let set_value = cb(move |value| set_value(Some(value)));
     FlowColumn::el([
         EditorPlayerInputHandler.el(),
-        ScrollArea::el(
-            FlowColumn::el([
-                FlowRow::el([
-                    CopyPasteButtons { value: value.clone(), on_change: set_value.clone() }.el().set(margin(), Borders::bottom(STREET)),
-                    // SelectAndDownloadJsonAssetButton2::<TerrainMaterialDef> {
-                    //     asset_type: AssetType::TerrainMaterial,
-                    //     on_select_file: Cb::new({
-                    //         let set_value = set_value.clone();
-                    //         move |value| {
-                    //             set_value((**value.random().unwrap()).clone());
-                    //         }
-                    //     }),
-                    // }
-                    // .el(),
+        UiStateScope::el(
+            "terrain_material",
+            ScrollArea::el(
+                FlowColumn::el([
+                    FlowRow::el([
+                        CopyPasteButtons { value: value.clone(), on_change: set_value.clone() }.el().set(margin(), Borders::bottom(STREET)),
+                        // SelectAndDownloadJsonAssetButton2::<TerrainMaterialDef> {
+                        //     asset_type: AssetType::TerrainMaterial,
+                        //     on_select_file: Cb::new({
+                        //         let set_value = set_value.clone();
+                        //         move |value| {
+                        //             set_value((**value.random().unwrap()).clone());
+                        //         }
+                        //     }),
+                        // }
+                        // .el(),
+                    ])
+                    .keyboard(),
+                    TerrainMaterialDef::editor(value, set_value, Default::default()),
                 ])
-                .keyboard(),
-                TerrainMaterialDef::editor(value, set_value, Default::default()),
-            ])
-            .floating_panel(),
+                .floating_panel(),
+            ),
         ),
     ])
     .set(margin(), Borders::even(STREET))
@@ -0,0 +1,176 @@
+use std::{sync::Arc, time::Duration};
+
+use ambient_core::{dtime, name};
+use ambient_ecs::{EntityId, Query};
+use ambient_ecs_editor::ECSEditor;
+use ambient_element::{element_component, Element, ElementComponentExt, Hooks};
+use ambient_network::recording::{Playhead, SessionRecording, SessionReplay};
+use ambient_std::cb;
+use ambient_ui::{
+    fit_horizontal, space_between_items, Button, ButtonStyle, Fit, FlowColumn, FlowRow, IntegerSlider, StylesExt, Text, TextInput, STREET,
+};
+use itertools::Itertools;
+use parking_lot::Mutex;
+
+/// How many entities are listed at most
+const MAX_LISTED_ENTITIES: usize = 100;
+/// The time scales the recording can be played at
+const TIME_SCALES: [f32; 5] = [0.25, 0.5, 1., 2., 4.];
+
+/// Plays and scrubs through a recorded session, with the entities of the world at the selected frame, and the components
+/// of the entities picked in the inspector
+#[element_component]
+pub fn ReplayView(hooks: &mut Hooks, recording: Arc<SessionRecording>) -> Element {
+    let (replay, _) = hooks.use_state_with(|_| Arc::new(Mutex::new(SessionReplay::new(recording.clone()))));
+    let (playhead, set_playhead) = hooks.use_state(Playhead { paused: true, ..Default::default() });
+    let (entity, set_entity) = hooks.use_state(String::new());
+    let (message, set_message) = hooks.use_state(None::<String>);
+
+    let frames = recording.frames.len();
+    let frame = recording.frame_at(playhead.time).unwrap_or_default();
+    let set_frame = {
+        let (recording, set_playhead) = (recording.clone(), set_playhead.clone());
+        cb(move |frame: usize| {
+            let time = recording.frames.get(frame).map(|frame| frame.time).unwrap_or_default();
+            set_playhead(Playhead { time, ..playhead })
+        })
+    };
+    if !playhead.paused {
+        let (recording, set_playhead) = (recording.clone(), set_playhead.clone());
+        hooks.use_frame(move |world| {
+            let mut next = playhead;
+            next.advance(Duration::from_secs_f32(*world.resource(dtime())), recording.duration());
+            next.paused = next.time >= recording.duration();
+            set_playhead(next);
+        });
+    }
+    let mut replay_state = replay.lock();
+    replay_state.seek(frame);
+    let entities = Query::all()
+        .iter(replay_state.world(), None)
+        .map(|ea| (ea.id(), replay_state.world().get_ref(ea.id(), name()).cloned().unwrap_or_default()))
+        .collect_vec();
+    drop(replay_state);
+
+    let find_entity = {
+        let entity = entity.clone();
+        let set_message = set_message.clone();
+        move || match entity.trim().parse::<EntityId>() {
+            Ok(id) => Some(id),
+            Err(_) => {
+                set_message(Some(format!("Invalid entity id: {entity:?}")));
+                None
+            }
+        }
+    };
+
+    FlowColumn::el([
+        FlowRow::el(
+            [Button::new(if playhead.paused { "Play" } else { "Pause" }, {
+                let (recording, set_playhead) = (recording.clone(), set_playhead.clone());
+                move |_| {
+                    // Playing from the end starts over
+                    let time = if playhead.paused && playhead.time >= recording.duration() { Duration::ZERO } else { playhead.time };
+                    set_playhead(Playhead { time, paused: !playhead.paused, ..playhead })
+                }
+            })
+            .el()]
+            .into_iter()
+            .chain(TIME_SCALES.into_iter().map(|time_scale| {
+                let set_playhead = set_playhead.clone();
+                Button::new(format!("{time_scale}x"), move |_| set_playhead(Playhead { time_scale, ..playhead }))
+                    .toggled(playhead.time_scale == time_scale)
+                    .style(ButtonStyle::Flat)
+                    .el()
+            }))
+            .collect_vec(),
+        )
+        .set(space_between_items(), STREET),
+        FlowRow::el([
+            IntegerSlider {
+                value: frame as i32,
+                on_change: Some(cb({
+                    let set_frame = set_frame.clone();
+                    move |frame| set_frame(frame as usize)
+                })),
+                min: 0,
+                max: frames.saturating_sub(1) as i32,
+                width: 500.,
+                logarithmic: false,
+                suffix: None,
+            }
+            .el(),
+            Text::el(match recording.frames.get(frame) {
+                Some(recorded) => format!(
+                    "Frame {frame} of {frames}, at {:.2}s of {:.2}s",
+                    recorded.time.as_secs_f32(),
+                    recording.duration().as_secs_f32()
+                ),
+                None => "The recording is empty".to_string(),
+            }),
+        ])
+        .set(space_between_items(), STREET),
+        FlowRow::el([
+            TextInput::new(entity, set_entity.clone()).placeholder(Some("Entity id")).el(),
+            Button::new("Jump to spawn", {
+                let (recording, find_entity, set_frame, set_message) =
+                    (recording.clone(), find_entity.clone(), set_frame.clone(), set_message.clone());
+                move |_| {
+                    if let Some(id) = find_entity() {
+                        match recording.spawn_frame(id) {
+                            Some(frame) => {
+                                set_frame(frame);
+                                set_message(None);
+                            }
+                            None => set_message(Some(format!("{id} is never spawned in this recording"))),
+                        }
+                    }
+                }
+            })
+            .el(),
+            Button::new("Jump to first invalid component", {
+                let (recording, set_frame, set_message) = (recording.clone(), set_frame.clone(), set_message.clone());
+                move |_| {
+                    if let Some(id) = find_entity() {
+                        match recording.first_invalid_frame(id) {
+                            Some((frame, component)) => {
+                                set_frame(frame);
+                                set_message(Some(format!("{} becomes invalid at frame {frame}", component.path())));
+                            }
+                            None => set_message(Some(format!("The components of {id} are always valid in this recording"))),
+                        }
+                    }
+                }
+            })
+            .el(),
+        ])
+        .set(space_between_items(), STREET),
+        match message {
+            Some(message) => Text::el(message),
+            None => Element::new(),
+        },
+        FlowRow::el([
+            FlowColumn::el(
+                [Text::el(format!("{} entities", entities.len())).section_style()]
+                    .into_iter()
+                    .chain(entities.into_iter().take(MAX_LISTED_ENTITIES).map(|(id, name)| {
+                        let set_entity = set_entity.clone();
+                        Button::new(format!("{id} {name}"), move |_| set_entity(id.to_string())).style(ButtonStyle::Flat).el()
+                    }))
+                    .collect_vec(),
+            ),
+            ECSEditor {
+                get_world: cb(move |run| {
+                    let replay = replay.lock();
+                    run(replay.world());
+                }),
+                // The recording is read only
+                on_change: cb(|_, _| {}),
+            }
+            .el(),
+        ])
+        .set(space_between_items(), STREET),
+    ])
+    .set(fit_horizontal(), Fit::Parent)
+    .set(space_between_items(), STREET)
+}
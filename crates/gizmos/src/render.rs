@@ -99,7 +99,7 @@ impl SubRenderer for GizmoRenderer {
                 gpu,
                 GraphicsPipelineInfo {
                     targets: &[Some(ColorTargetState {
-                        format: gpu.swapchain_format(),
+                        format: target.format(),
                         blend: Some(BlendState::ALPHA_BLENDING),
                         write_mask: ColorWrites::ALL,
                     })],
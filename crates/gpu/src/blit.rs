@@ -1,6 +1,10 @@
 use std::{borrow::Cow, sync::Arc};
 
-use ambient_std::asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt};
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt},
+    colorspace::{RgbColorSpace, TransferFunction},
+};
+use wgpu::util::DeviceExt;
 
 use super::gpu::{Gpu, GpuKey};
 
@@ -8,6 +12,9 @@ use super::gpu::{Gpu, GpuKey};
 pub struct BlitterKey {
     pub format: wgpu::ColorTargetState,
     pub linear: bool,
+    /// The color space the sRGB colors of the source are converted to, e.g. the one of a wide gamut display; `None` to
+    /// copy them as they are
+    pub color_space: Option<RgbColorSpace>,
 }
 impl SyncAssetKey<Arc<Blitter>> for BlitterKey {
     fn load(&self, assets: AssetCache) -> Arc<Blitter> {
@@ -19,6 +26,7 @@ impl SyncAssetKey<Arc<Blitter>> for BlitterKey {
 pub struct Blitter {
     pipeline: wgpu::RenderPipeline,
     sampler: wgpu::Sampler,
+    conversion: Option<wgpu::Buffer>,
     gpu: Arc<Gpu>,
 }
 impl Blitter {
@@ -32,7 +40,11 @@ impl Blitter {
             label: Some("Blitter.pipeline"),
             layout: None,
             vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
-            fragment: Some(wgpu::FragmentState { module: &shader, entry_point: "fs_main", targets: &[Some(conf.format.clone())] }),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: if conf.color_space.is_some() { "fs_convert" } else { "fs_main" },
+                targets: &[Some(conf.format.clone())],
+            }),
             primitive: wgpu::PrimitiveState { topology: wgpu::PrimitiveTopology::TriangleStrip, ..Default::default() },
             depth_stencil: None,
             multisample: wgpu::MultisampleState::default(),
@@ -50,19 +62,28 @@ impl Blitter {
             ..Default::default()
         });
 
-        Self { pipeline, sampler, gpu }
+        let conversion = conf.color_space.map(|color_space| {
+            gpu.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Blitter.conversion"),
+                contents: bytemuck::cast_slice(&[ColorConversion::new(color_space, conf.format.format)]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            })
+        });
+
+        Self { pipeline, sampler, conversion, gpu }
     }
     pub fn run(&self, encoder: &mut wgpu::CommandEncoder, source: &wgpu::TextureView, target: &wgpu::TextureView) {
         let bind_group_layout = self.pipeline.get_bind_group_layout(0);
 
-        let bind_group = self.gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(source) },
-                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) },
-            ],
-            label: None,
-        });
+        let mut entries = vec![
+            wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(source) },
+            wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+        ];
+        if let Some(conversion) = &self.conversion {
+            entries.push(wgpu::BindGroupEntry { binding: 2, resource: conversion.as_entire_binding() });
+        }
+        let bind_group =
+            self.gpu.device.create_bind_group(&wgpu::BindGroupDescriptor { layout: &bind_group_layout, entries: &entries, label: None });
 
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
@@ -78,3 +99,29 @@ impl Blitter {
         rpass.draw(0..4, 0..1);
     }
 }
+
+/// The parameters of `fs_convert`, laid out as the `ColorConversion` of blit.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorConversion {
+    /// The columns of the matrix from linear sRGB to the linear color space, padded to 4 components
+    from_srgb: [[f32; 4]; 3],
+    transfer: u32,
+    srgb_target: u32,
+    _padding: [u32; 2],
+}
+impl ColorConversion {
+    fn new(color_space: RgbColorSpace, format: wgpu::TextureFormat) -> Self {
+        let rows = color_space.from_linear_srgb_matrix();
+        Self {
+            from_srgb: [0, 1, 2].map(|column| [rows[0][column], rows[1][column], rows[2][column], 0.]),
+            transfer: match color_space.transfer_function() {
+                TransferFunction::Linear => 0,
+                TransferFunction::Srgb => 1,
+                TransferFunction::Rec2020 => 2,
+            },
+            srgb_target: format.describe().srgb as u32,
+            _padding: [0; 2],
+        }
+    }
+}
@@ -32,3 +32,53 @@ var r_sampler: sampler;
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return textureSample(r_color, r_sampler, in.tex_coords);
 }
+
+struct ColorConversion {
+    // From linear sRGB to the linear output color space
+    from_srgb: mat3x3<f32>,
+    // The transfer function of the output color space: 0 for linear, 1 for sRGB, 2 for Rec.2020
+    transfer: u32,
+    // Whether the target encodes the values with the sRGB transfer function when they're written, and decodes them
+    // when they're sampled (the source has the same format)
+    srgb_target: u32,
+};
+
+@group(0)
+@binding(2)
+var<uniform> conversion: ColorConversion;
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    return select(pow((color + 0.055) / 1.055, vec3<f32>(2.4)), color / 12.92, color <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055, color * 12.92, color <= vec3<f32>(0.0031308));
+}
+
+fn linear_to_rec2020(color: vec3<f32>) -> vec3<f32> {
+    let alpha = 1.0992968;
+    let beta = 0.018053968;
+    return select(alpha * pow(color, vec3<f32>(0.45)) - (alpha - 1.0), color * 4.5, color < vec3<f32>(beta));
+}
+
+// Converts the sRGB colors of the source to the output color space, e.g. for a wide gamut display which would otherwise
+// show them oversaturated
+@fragment
+fn fs_convert(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(r_color, r_sampler, in.tex_coords);
+    var rgb = color.rgb;
+    if (conversion.srgb_target == 0u) {
+        rgb = srgb_to_linear(rgb);
+    }
+    rgb = max(conversion.from_srgb * rgb, vec3<f32>(0.0));
+    if (conversion.transfer == 1u) {
+        rgb = linear_to_srgb(rgb);
+    } else if (conversion.transfer == 2u) {
+        rgb = linear_to_rec2020(rgb);
+    }
+    // The target encodes the values again
+    if (conversion.srgb_target == 1u) {
+        rgb = srgb_to_linear(rgb);
+    }
+    return vec4<f32>(rgb, color.a);
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use ambient_sys::time::Instant;
+use parking_lot::Mutex;
+
+/// A GPU resource which is waiting to be destroyed
+#[derive(Debug)]
+pub enum GpuGarbageItem {
+    Buffer(wgpu::Buffer),
+    Texture(wgpu::Texture),
+}
+impl GpuGarbageItem {
+    fn destroy(self) {
+        match self {
+            GpuGarbageItem::Buffer(buffer) => buffer.destroy(),
+            GpuGarbageItem::Texture(texture) => texture.destroy(),
+        }
+    }
+}
+
+/// The items dropped before a submission, which can be destroyed once the GPU is done with it
+#[derive(Debug)]
+struct GarbageBatch<T> {
+    done: Arc<AtomicBool>,
+    items: Vec<T>,
+}
+
+#[derive(Debug)]
+struct GarbageQueue<T> {
+    /// Dropped since the last fence
+    pending: Vec<T>,
+    /// Waiting for the GPU to be done with them, oldest first
+    in_flight: VecDeque<GarbageBatch<T>>,
+    /// The GPU is done with them, and they're destroyed as the budget allows
+    ready: VecDeque<T>,
+}
+impl<T> Default for GarbageQueue<T> {
+    fn default() -> Self {
+        Self { pending: Vec::new(), in_flight: VecDeque::new(), ready: VecDeque::new() }
+    }
+}
+impl<T> GarbageQueue<T> {
+    fn len(&self) -> usize {
+        self.pending.len() + self.in_flight.iter().map(|batch| batch.items.len()).sum::<usize>() + self.ready.len()
+    }
+    /// Puts the pending items in a batch, which becomes ready once the returned flag is set
+    fn fence(&mut self) -> Option<Arc<AtomicBool>> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let done = Arc::new(AtomicBool::new(false));
+        self.in_flight.push_back(GarbageBatch { done: done.clone(), items: std::mem::take(&mut self.pending) });
+        Some(done)
+    }
+    /// The next item the GPU is done with. The batches are finished in the order they were submitted, so only the
+    /// oldest one is checked.
+    fn next_ready(&mut self) -> Option<T> {
+        while self.in_flight.front().map(|batch| batch.done.load(Ordering::Acquire)).unwrap_or(false) {
+            let batch = self.in_flight.pop_front().unwrap();
+            self.ready.extend(batch.items);
+        }
+        self.ready.pop_front()
+    }
+}
+
+/// Defers the destruction of the buffers and textures which are dropped, so that dropping many of them at once (e.g. when
+/// despawning lots of entities) doesn't stall the frame.
+///
+/// The resources are only destroyed once the GPU has finished the submission following their drop, which is the last one
+/// that can use them, and only for a limited time per frame. Until [GpuGarbage::collect] is called for the first time,
+/// resources are destroyed as soon as they're dropped, so that nothing piles up without a frame loop.
+#[derive(Debug, Default)]
+pub struct GpuGarbage {
+    collecting: AtomicBool,
+    queue: Mutex<GarbageQueue<GpuGarbageItem>>,
+}
+impl GpuGarbage {
+    pub fn drop_buffer(&self, buffer: wgpu::Buffer) {
+        self.drop_item(GpuGarbageItem::Buffer(buffer));
+    }
+    pub fn drop_texture(&self, texture: wgpu::Texture) {
+        self.drop_item(GpuGarbageItem::Texture(texture));
+    }
+    fn drop_item(&self, item: GpuGarbageItem) {
+        if self.collecting.load(Ordering::Relaxed) {
+            self.queue.lock().pending.push(item);
+        } else {
+            item.destroy();
+        }
+    }
+    /// The number of resources which are waiting to be destroyed
+    pub fn len(&self) -> usize {
+        self.queue.lock().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Fences the resources dropped since the last call with the work submitted to `queue` so far, and destroys the
+    /// resources the GPU is done with for up to `budget` (but at least one, so that the garbage always goes down).
+    ///
+    /// Call it once per frame, after the frame has been submitted; the fence only covers the submissions made before it.
+    pub fn collect(&self, queue: &wgpu::Queue, budget: Duration) {
+        self.collecting.store(true, Ordering::Relaxed);
+        let start = Instant::now();
+        let mut garbage = self.queue.lock();
+        if let Some(done) = garbage.fence() {
+            queue.on_submitted_work_done(move || done.store(true, Ordering::Release));
+        }
+        while let Some(item) = garbage.next_ready() {
+            item.destroy();
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(queue: &mut GarbageQueue<u32>) -> Vec<u32> {
+        std::iter::from_fn(|| queue.next_ready()).collect()
+    }
+
+    #[test]
+    fn items_wait_for_their_fence() {
+        let mut queue = GarbageQueue::default();
+        queue.pending.extend([1, 2]);
+        assert_eq!(drain(&mut queue), Vec::<u32>::new());
+
+        let done = queue.fence().unwrap();
+        assert_eq!(drain(&mut queue), Vec::<u32>::new());
+        assert_eq!(queue.len(), 2);
+
+        done.store(true, Ordering::Release);
+        assert_eq!(drain(&mut queue), vec![1, 2]);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn items_dropped_after_a_fence_wait_for_the_next_one() {
+        let mut queue = GarbageQueue::default();
+        queue.pending.push(1);
+        let first = queue.fence().unwrap();
+        queue.pending.push(2);
+        first.store(true, Ordering::Release);
+        assert_eq!(drain(&mut queue), vec![1]);
+        assert_eq!(queue.len(), 1);
+
+        let second = queue.fence().unwrap();
+        assert_eq!(drain(&mut queue), Vec::<u32>::new());
+        second.store(true, Ordering::Release);
+        assert_eq!(drain(&mut queue), vec![2]);
+    }
+
+    #[test]
+    fn batches_are_ready_in_order() {
+        let mut queue = GarbageQueue::default();
+        queue.pending.push(1);
+        let first = queue.fence().unwrap();
+        queue.pending.push(2);
+        let second = queue.fence().unwrap();
+
+        second.store(true, Ordering::Release);
+        assert_eq!(drain(&mut queue), Vec::<u32>::new());
+        first.store(true, Ordering::Release);
+        assert_eq!(drain(&mut queue), vec![1, 2]);
+    }
+
+    #[test]
+    fn no_fence_without_garbage() {
+        let mut queue = GarbageQueue::<u32>::default();
+        assert!(queue.fence().is_none());
+    }
+}
@@ -6,6 +6,8 @@ use glam::{uvec2, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4};
 use wgpu::{PresentMode, TextureFormat};
 use winit::window::Window;
 
+use crate::garbage::GpuGarbage;
+
 // #[cfg(debug_assertions)]
 pub const DEFAULT_SAMPLE_COUNT: u32 = 1;
 // #[cfg(not(debug_assertions))]
@@ -25,6 +27,8 @@ pub struct Gpu {
     pub adapter: wgpu::Adapter,
     /// If this is true, we don't need to use blocking device.polls, since they are assumed to be polled elsewhere
     pub will_be_polled: bool,
+    /// The buffers and textures which were dropped, and are waiting to be destroyed
+    pub garbage: GpuGarbage,
 }
 impl Gpu {
     pub async fn new(window: Option<&Window>) -> Self {
@@ -52,7 +56,8 @@ impl Gpu {
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::HighPerformance,
                 compatible_surface: surface.as_ref(),
-                force_fallback_adapter: false,
+                // A software adapter renders the same on every machine, which golden image tests rely on
+                force_fallback_adapter: std::env::var("AMBIENT_FORCE_FALLBACK_ADAPTER").map(|value| value == "1").unwrap_or(false),
             })
             .await
             .expect("Failed to find an appropiate adapter");
@@ -103,7 +108,7 @@ impl Gpu {
             surface.configure(&device, &Self::create_sc_desc(format, mode, uvec2(size.width, size.height)));
         }
 
-        Self { device, surface, queue, swapchain_format, swapchain_mode, adapter, will_be_polled }
+        Self { device, surface, queue, swapchain_format, swapchain_mode, adapter, will_be_polled, garbage: GpuGarbage::default() }
     }
     pub fn resize(&self, size: winit::dpi::PhysicalSize<u32>) {
         if let Some(surface) = &self.surface {
@@ -1,5 +1,6 @@
 pub mod blit;
 pub mod fill;
+pub mod garbage;
 pub mod gpu;
 pub mod gpu_run;
 pub mod mesh_buffer;
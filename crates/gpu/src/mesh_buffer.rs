@@ -13,7 +13,7 @@ use ambient_std::{
     mesh::Mesh,
 };
 use async_trait::async_trait;
-use glam::{UVec4, Vec2, Vec4};
+use glam::{UVec4, Vec2, Vec3, Vec4};
 use itertools::Itertools;
 use parking_lot::Mutex;
 use wgpu::RenderPass;
@@ -100,7 +100,8 @@ impl AsyncAssetKey<AssetResult<Arc<GpuMesh>>> for GpuMeshFromUrl {
 pub struct MeshBuffer {
     gpu: Arc<Gpu>,
     pub metadata_buffer: TypedBuffer<MeshMetadata>,
-    pub position_buffer: AttributeBuffer<Vec4>, // Vec4 instead of Vec3 because of alignment (16)
+    /// Vec4 instead of Vec3 because of alignment (16); the w lane holds the RGBA8 vertex color bits, as unpacked by unpack4x8unorm
+    pub position_buffer: AttributeBuffer<Vec4>,
     pub normal_buffer: AttributeBuffer<Vec4>,
     pub tangent_buffer: AttributeBuffer<Vec4>,
     pub texcoord0_buffer: AttributeBuffer<Vec2>,
@@ -191,7 +192,7 @@ impl MeshBuffer {
         let mut internal_mesh = InternalMesh { metadata, ..Default::default() };
         if let Some(positions) = &mesh.positions {
             self.position_buffer.front.resize(self.position_buffer.front.len() + positions.len() as u64, true);
-            self.position_buffer.front.write(metadata.position_offset as u64, &positions.iter().map(|p| p.extend(0.)).collect_vec());
+            self.position_buffer.front.write(metadata.position_offset as u64, &pack_positions(positions, mesh.colors.as_deref()));
             internal_mesh.position_count = positions.len() as u64;
         }
         if let Some(normals) = &mesh.normals {
@@ -411,6 +412,25 @@ struct InternalMesh {
     index_count: u64,
 }
 
+/// Puts the vertex colors in the w lane of the positions, so that they don't need a storage buffer of their own.
+/// Meshes without vertex colors get white ones, so that the shaders can always read them
+fn pack_positions(positions: &[Vec3], colors: Option<&[Vec4]>) -> Vec<Vec4> {
+    positions
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let color = colors.and_then(|colors| colors.get(i)).map(|&color| pack_color(color)).unwrap_or(u32::MAX);
+            p.extend(f32::from_bits(color))
+        })
+        .collect_vec()
+}
+
+/// Packs a color into RGBA8, the inverse of WGSL's unpack4x8unorm
+fn pack_color(color: Vec4) -> u32 {
+    let [r, g, b, a] = (color.clamp(Vec4::ZERO, Vec4::ONE) * 255.).round().to_array().map(|x| x as u32);
+    r | (g << 8) | (b << 16) | (a << 24)
+}
+
 pub struct AttributeBuffer<T: bytemuck::Pod> {
     pub front: TypedBuffer<T>,
     pub tmp: TypedBuffer<T>,
@@ -426,3 +446,30 @@ impl<T: bytemuck::Pod> AttributeBuffer<T> {
         self.front.buffer()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use glam::{vec3, vec4};
+
+    use super::*;
+
+    #[test]
+    fn test_pack_color() {
+        assert_eq!(pack_color(Vec4::ONE), u32::MAX);
+        assert_eq!(pack_color(vec4(1., 0., 0.5, 0.)), 0x0080_00ff);
+        assert_eq!(pack_color(vec4(2., -1., 0., 1.)), 0xff00_00ff);
+    }
+
+    #[test]
+    fn test_pack_positions() {
+        let positions = [vec3(1., 2., 3.), vec3(-1., 0., 0.5)];
+        let packed = pack_positions(&positions, Some(&[vec4(1., 0., 0.5, 0.), Vec4::ONE]));
+        assert_eq!(packed[0].truncate(), positions[0]);
+        assert_eq!(packed[0].w.to_bits(), 0x0080_00ff);
+        assert_eq!(packed[1].w.to_bits(), u32::MAX);
+
+        let packed = pack_positions(&positions, None);
+        assert_eq!(packed[1].truncate(), positions[1]);
+        assert!(packed.iter().all(|p| p.w.to_bits() == u32::MAX));
+    }
+}
@@ -14,7 +14,7 @@ pub fn generate_mipmaps(
     mip_count: u32,
     layer: u32,
 ) {
-    let blitter = BlitterKey { format: format.into(), linear: true }.get(&assets);
+    let blitter = BlitterKey { format: format.into(), linear: true, color_space: None }.get(&assets);
 
     let views = (0..mip_count)
         .map(|mip| {
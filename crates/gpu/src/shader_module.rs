@@ -179,6 +179,48 @@ impl SyncAssetKey<Arc<wgpu::BindGroupLayout>> for BindGroupDesc {
     }
 }
 
+/// Concatenates the modules, and fills in their constants and bind group indices; `bind_group` is called with each of
+/// the bind groups, in the order of their indices
+fn preprocess<'a>(
+    modules: impl IntoIterator<Item = &'a ShaderModule>,
+    mut bind_group: impl FnMut(&BindGroupDesc),
+) -> (String, HashMap<CowStr, WgslValue>) {
+    let mut idents: HashMap<CowStr, WgslValue> = HashMap::new();
+    let mut bind_groups = 0;
+
+    #[allow(unstable_name_collisions)]
+    let mut source: String = modules
+        .into_iter()
+        .flat_map(|module| {
+            for ident in module.idents.iter() {
+                match ident {
+                    ShaderModuleIdentifier::BindGroup(desc) => {
+                        if idents.insert(desc.label.clone(), WgslValue::Int32(bind_groups)).is_some() {
+                            panic!("Duplicate bind group {}", desc.label);
+                        }
+                        bind_groups += 1;
+                        bind_group(desc);
+                    }
+                    ShaderModuleIdentifier::Constant { name, value } => {
+                        if idents.insert(name.clone(), value.clone()).is_some() {
+                            panic!("Redefined constant {name}={value:?} in {}", module.label);
+                        }
+                    }
+                }
+            }
+
+            module.source.lines()
+        })
+        .filter(|line| !line.starts_with("//"))
+        .intersperse("\n")
+        .collect();
+
+    for (key, value) in idents.iter() {
+        source = source.replace(&format!("#{key}"), &value.to_wgsl());
+    }
+    (source, idents)
+}
+
 /// Represents a shader and its layout
 pub struct Shader {
     module: wgpu::ShaderModule,
@@ -199,44 +241,13 @@ impl Shader {
         let label = label.into();
         let gpu = GpuKey.get(assets);
 
-        let mut idents: HashMap<CowStr, WgslValue> = HashMap::new();
         let mut bind_group_layouts = Vec::new();
         let mut bind_group_labels = Vec::new();
-
-        #[allow(unstable_name_collisions)]
-        let mut source: String = modules
-            .into_iter()
-            .flat_map(|module| {
-                for ident in module.idents.iter() {
-                    match ident {
-                        ShaderModuleIdentifier::BindGroup(desc) => {
-                            // Allocate group
-                            let layout = desc.load(assets.clone());
-
-                            if idents.insert(desc.label.clone(), WgslValue::Int32(bind_group_layouts.len() as u32)).is_some() {
-                                panic!("Duplicate bind group {}", desc.label);
-                            }
-
-                            bind_group_layouts.push(layout);
-                            bind_group_labels.push(desc.label.to_string());
-                        }
-                        ShaderModuleIdentifier::Constant { name, value } => {
-                            if idents.insert(name.clone(), value.clone()).is_some() {
-                                panic!("Redefined constant {name}={value:?} in {}", module.label);
-                            }
-                        }
-                    }
-                }
-
-                module.source.lines()
-            })
-            .filter(|line| !line.starts_with("//"))
-            .intersperse("\n")
-            .collect();
-
-        for (key, value) in idents.iter() {
-            source = source.replace(&format!("#{key}"), &value.to_wgsl());
-        }
+        let (source, idents) = preprocess(modules, |desc| {
+            // Allocate group
+            bind_group_layouts.push(desc.load(assets.clone()));
+            bind_group_labels.push(desc.label.to_string());
+        });
 
         #[cfg(debug_assertions)]
         {
@@ -255,6 +266,12 @@ impl Shader {
         Arc::new(Self { module, bind_group_layouts, bind_group_labels, idents, source: src, label })
     }
 
+    /// The WGSL [Shader::from_modules] compiles, with the constants and the bind group indices filled in; it doesn't need a
+    /// GPU, e.g. to validate shaders ahead of time
+    pub fn preprocess<'a>(modules: impl IntoIterator<Item = &'a ShaderModule>) -> String {
+        preprocess(modules, |_| {}).0
+    }
+
     pub fn ref_layouts(&self) -> Vec<&wgpu::BindGroupLayout> {
         self.bind_group_layouts.iter().map(|v| &**v).collect_vec()
     }
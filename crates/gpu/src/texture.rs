@@ -1,5 +1,6 @@
 use std::{
     io::Cursor,
+    mem::ManuallyDrop,
     num::NonZeroU32,
     ops::Deref,
     path::Path,
@@ -34,7 +35,8 @@ pub struct Texture {
     pub gpu: Arc<Gpu>,
     pub id: u32,
     pub label: Option<String>,
-    pub handle: wgpu::Texture,
+    /// Destroyed through [crate::garbage::GpuGarbage] when the texture is dropped
+    pub handle: ManuallyDrop<wgpu::Texture>,
     pub size: wgpu::Extent3d,
     pub size_in_bytes: u64,
     pub format: wgpu::TextureFormat,
@@ -74,7 +76,7 @@ impl Texture {
             format: descriptor.format,
             sample_count: descriptor.sample_count,
             mip_level_count: descriptor.mip_level_count,
-            handle: gpu.device.create_texture(descriptor),
+            handle: ManuallyDrop::new(gpu.device.create_texture(descriptor)),
             gpu,
         }
     }
@@ -91,7 +93,7 @@ impl Texture {
             format: descriptor.format,
             sample_count: descriptor.sample_count,
             mip_level_count: descriptor.mip_level_count,
-            handle: gpu.device.create_texture_with_data(&gpu.queue, descriptor, data),
+            handle: ManuallyDrop::new(gpu.device.create_texture_with_data(&gpu.queue, descriptor, data)),
             gpu,
         }
     }
@@ -325,6 +327,8 @@ impl Drop for Texture {
     fn drop(&mut self) {
         TEXTURE_ALIVE_COUNT.fetch_sub(1, Ordering::SeqCst);
         TEXTURES_TOTAL_SIZE.fetch_sub(self.size_in_bytes, Ordering::SeqCst);
+        // Safety: the handle isn't used after this
+        self.gpu.garbage.drop_texture(unsafe { ManuallyDrop::take(&mut self.handle) });
     }
 }
 
@@ -3,6 +3,7 @@ use std::{borrow::Cow, fmt, io::Cursor, sync::Arc};
 use ambient_std::{
     asset_cache::{AssetCache, AsyncAssetKey, AsyncAssetKeyExt},
     asset_url::AbsAssetUrl,
+    colorspace::RgbColorSpace,
     download_asset::{AssetError, AssetResult, BytesFromUrl},
     CowStr,
 };
@@ -36,21 +37,65 @@ impl AsyncAssetKey<Result<Arc<image::RgbaImage>, AssetError>> for Rgba8ImageFrom
 }
 
 async fn image_from_url(assets: AssetCache, url: AbsAssetUrl) -> Result<DynamicImage, AssetError> {
+    tagged_image_from_url(assets, url).await.map(|(image, _)| image)
+}
+
+/// Loads an image, and the color space its file is tagged with, if any
+async fn tagged_image_from_url(assets: AssetCache, url: AbsAssetUrl) -> Result<(DynamicImage, Option<RgbColorSpace>), AssetError> {
     let data = BytesFromUrl::new(url.clone(), true).get(&assets).await?;
 
     let extension = url.extension().context("No extension")?;
-    Ok(task::block_in_place(move || -> anyhow::Result<DynamicImage> {
+    Ok(task::block_in_place(move || -> anyhow::Result<(DynamicImage, Option<RgbColorSpace>)> {
         let format = ImageFormat::from_extension(extension).context("Invalid extension")?;
-        Ok(image::io::Reader::with_format(Cursor::new(&*data), format).decode()?)
+        let image = image::io::Reader::with_format(Cursor::new(&*data), format).decode()?;
+        let color_space = if format == ImageFormat::Png { png_color_space(&data) } else { None };
+        Ok((image, color_space))
     })
     .with_context(|| format!("Failed to load image {url}"))
     .unwrap())
 }
 
+/// The color space a PNG file is tagged with by its `cICP` chunk (or `sRGB` chunk), if it's one of the
+/// [RgbColorSpace]s. Embedded ICC profiles aren't read.
+pub fn png_color_space(data: &[u8]) -> Option<RgbColorSpace> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    let mut chunks = data.strip_prefix(SIGNATURE)?;
+    // The chunks are a big endian length, a type, the data, and a CRC
+    while chunks.len() >= 12 {
+        let len = u32::from_be_bytes(chunks[0..4].try_into().unwrap()) as usize;
+        let (kind, rest) = chunks[4..].split_at(4);
+        let chunk = rest.get(..len)?;
+        match kind {
+            // https://www.w3.org/TR/png-3/#cICP-chunk, with the code points of ITU-T H.273
+            b"cICP" if len == 4 => {
+                return match (chunk[0], chunk[1]) {
+                    (1, 13) => Some(RgbColorSpace::Srgb),
+                    (1, 8) => Some(RgbColorSpace::LinearSrgb),
+                    (12, 13) => Some(RgbColorSpace::DisplayP3),
+                    (12, 8) => Some(RgbColorSpace::LinearDisplayP3),
+                    (9, 1 | 6 | 14 | 15) => Some(RgbColorSpace::Rec2020),
+                    (9, 8) => Some(RgbColorSpace::LinearRec2020),
+                    _ => None,
+                };
+            }
+            b"sRGB" => return Some(RgbColorSpace::Srgb),
+            // The color chunks come before the image data
+            b"IDAT" | b"IEND" => return None,
+            _ => {}
+        }
+        chunks = rest.get(len + 4..)?;
+    }
+    None
+}
+
 #[derive(Debug, Clone)]
 pub struct TextureFromUrl {
     pub url: AbsAssetUrl,
     pub format: wgpu::TextureFormat,
+    /// The color space of the image, whose colors are converted to the sRGB primaries the renderer works in. If `None`,
+    /// the color space the file is tagged with (see [png_color_space]) is used for the color textures, i.e. the ones with
+    /// an sRGB `format`; the other ones hold data like normal maps, and are uploaded as they are.
+    pub color_space: Option<RgbColorSpace>,
 }
 #[async_trait]
 impl AsyncAssetKey<Result<Arc<Texture>, AssetError>> for TextureFromUrl {
@@ -59,11 +104,35 @@ impl AsyncAssetKey<Result<Arc<Texture>, AssetError>> for TextureFromUrl {
     }
     #[tracing::instrument(level = "info", name = "texture_from_url")]
     async fn load(self, assets: AssetCache) -> Result<Arc<Texture>, AssetError> {
-        let image = image_from_url(assets.clone(), self.url.clone()).await?;
-        task::block_in_place(|| Ok(Arc::new(Texture::from_image_mipmapped(assets, image, self.format, Some(&self.url.to_string())))))
+        let (image, tagged) = tagged_image_from_url(assets.clone(), self.url.clone()).await?;
+        let color_space = self.color_space.or(tagged.filter(|_| self.format.describe().srgb));
+        task::block_in_place(|| {
+            let image = match color_space {
+                Some(color_space) => DynamicImage::ImageRgba8(convert_to_working_space(image.into_rgba8(), color_space, self.format)),
+                None => image,
+            };
+            Ok(Arc::new(Texture::from_image_mipmapped(assets, image, self.format, Some(&self.url.to_string()))))
+        })
     }
 }
 
+/// Converts the colors of `image` from `color_space` to the sRGB primaries the renderer works in, encoded as `format`
+/// expects them: with the sRGB transfer function for the sRGB formats, and linearly otherwise. The colors outside of the
+/// sRGB gamut are clamped; the alpha is unchanged.
+pub fn convert_to_working_space(mut image: RgbaImage, color_space: RgbColorSpace, format: wgpu::TextureFormat) -> RgbaImage {
+    let target = if format.describe().srgb { RgbColorSpace::Srgb } else { RgbColorSpace::LinearSrgb };
+    if color_space == target {
+        return image;
+    }
+    for pixel in image.pixels_mut() {
+        let rgb = color_space.convert(target, [0, 1, 2].map(|i| pixel.0[i] as f32 / 255.));
+        for (channel, value) in pixel.0.iter_mut().zip(rgb) {
+            *channel = (value.clamp(0., 1.) * 255.).round() as u8;
+        }
+    }
+    image
+}
+
 #[derive(Clone, Debug)]
 pub struct TextureFromRgba8Image {
     pub image: Arc<dyn AsyncAssetKeyExt<Result<Arc<image::RgbaImage>, AssetError>>>,
@@ -287,3 +356,64 @@ impl AsyncAssetKey<Result<Arc<Texture>, AssetError>> for TextureArrayFromUrls {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        // The CRC isn't checked
+        [&(data.len() as u32).to_be_bytes()[..], kind, data, &[0; 4]].concat()
+    }
+
+    fn png(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let ihdr = chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        [&b"\x89PNG\r\n\x1a\n"[..], &ihdr, &chunks.concat(), &chunk(b"IDAT", &[0; 8]), &chunk(b"IEND", &[])].concat()
+    }
+
+    #[test]
+    fn pngs_are_tagged_with_their_color_space() {
+        assert_eq!(png_color_space(&png(&[chunk(b"cICP", &[12, 13, 0, 1])])), Some(RgbColorSpace::DisplayP3));
+        assert_eq!(png_color_space(&png(&[chunk(b"cICP", &[9, 14, 0, 1])])), Some(RgbColorSpace::Rec2020));
+        assert_eq!(png_color_space(&png(&[chunk(b"cICP", &[1, 8, 0, 1])])), Some(RgbColorSpace::LinearSrgb));
+        assert_eq!(png_color_space(&png(&[chunk(b"gAMA", &[0, 0, 177, 143]), chunk(b"sRGB", &[0])])), Some(RgbColorSpace::Srgb));
+        // PQ isn't supported
+        assert_eq!(png_color_space(&png(&[chunk(b"cICP", &[9, 16, 0, 1])])), None);
+        assert_eq!(png_color_space(&png(&[])), None);
+        // A tag after the image data doesn't count
+        let late = [png(&[]), chunk(b"cICP", &[12, 13, 0, 1])].concat();
+        assert_eq!(png_color_space(&late), None);
+        assert_eq!(png_color_space(b"GIF89a"), None);
+        assert_eq!(png_color_space(&png(&[chunk(b"cICP", &[12, 13, 0, 1])])[..40]), None);
+    }
+
+    #[test]
+    fn images_are_converted_to_the_working_space() {
+        let image = RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 128]));
+        let srgb = convert_to_working_space(image.clone(), RgbColorSpace::Srgb, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(srgb, image);
+
+        // The P3 red is outside of the sRGB gamut, so it's clamped
+        let p3 = convert_to_working_space(image.clone(), RgbColorSpace::DisplayP3, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(p3.get_pixel(0, 0).0, [255, 0, 0, 128]);
+        let p3 = convert_to_working_space(
+            RgbaImage::from_pixel(1, 1, Rgba([128, 128, 128, 255])),
+            RgbColorSpace::DisplayP3,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        );
+        assert_eq!(p3.get_pixel(0, 0).0, [128, 128, 128, 255], "The white points are the same");
+        let p3 = convert_to_working_space(
+            RgbaImage::from_pixel(1, 1, Rgba([200, 100, 50, 255])),
+            RgbColorSpace::DisplayP3,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        );
+        assert!(p3.get_pixel(0, 0).0[0] > 200 && p3.get_pixel(0, 0).0[2] < 50, "{:?}", p3.get_pixel(0, 0));
+
+        let linear = convert_to_working_space(
+            RgbaImage::from_pixel(1, 1, Rgba([128, 128, 128, 255])),
+            RgbColorSpace::Srgb,
+            wgpu::TextureFormat::Rgba8Unorm,
+        );
+        assert_eq!(linear.get_pixel(0, 0).0, [55, 55, 55, 255]);
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    marker::PhantomData, ops::{DerefMut, RangeBounds}, sync::{
+    marker::PhantomData, mem::ManuallyDrop, ops::{DerefMut, RangeBounds}, sync::{
         atomic::{AtomicUsize, Ordering}, Arc
     }
 };
@@ -14,7 +14,8 @@ static UNTYPED_BUFFERS_TOTAL_SIZE: AtomicUsize = AtomicUsize::new(0);
 pub struct UntypedBuffer {
     gpu: Arc<Gpu>,
     label: String,
-    pub buffer: wgpu::Buffer,
+    /// Destroyed through [crate::garbage::GpuGarbage] when it's replaced or dropped
+    pub buffer: ManuallyDrop<wgpu::Buffer>,
     usage: wgpu::BufferUsages,
     capacity: u64,
     length: u64,
@@ -25,12 +26,12 @@ impl UntypedBuffer {
     pub fn new(gpu: Arc<Gpu>, label: &str, capacity: u64, length: u64, usage: wgpu::BufferUsages, item_size: u64) -> Self {
         UNTYPED_BUFFERS_TOTAL_SIZE.fetch_add((capacity * item_size) as usize, Ordering::SeqCst);
         Self {
-            buffer: gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            buffer: ManuallyDrop::new(gpu.device.create_buffer(&wgpu::BufferDescriptor {
                 label: Some(label),
                 usage,
                 size: capacity * item_size,
                 mapped_at_creation: false,
-            }),
+            })),
             label: label.to_string(),
             usage,
             capacity,
@@ -43,7 +44,11 @@ impl UntypedBuffer {
     pub fn new_init(gpu: Arc<Gpu>, label: &str, usage: wgpu::BufferUsages, data: &[u8], item_size: u64) -> Self {
         UNTYPED_BUFFERS_TOTAL_SIZE.fetch_add(data.len(), Ordering::SeqCst);
         Self {
-            buffer: gpu.device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some(label), usage, contents: data }),
+            buffer: ManuallyDrop::new(gpu.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                usage,
+                contents: data,
+            })),
             label: label.to_string(),
             usage,
             capacity: data.len() as u64 / item_size,
@@ -108,7 +113,8 @@ impl UntypedBuffer {
             encoder.copy_buffer_to_buffer(&self.buffer, 0, &new_buffer, 0, self.capacity * self.item_size);
             self.gpu.queue.submit(Some(encoder.finish()));
         }
-        self.buffer = new_buffer;
+        let old_buffer = std::mem::replace(&mut self.buffer, ManuallyDrop::new(new_buffer));
+        self.gpu.garbage.drop_buffer(ManuallyDrop::into_inner(old_buffer));
         self.capacity = new_capacity;
     }
 
@@ -165,6 +171,8 @@ impl UntypedBuffer {
 impl Drop for UntypedBuffer {
     fn drop(&mut self) {
         UNTYPED_BUFFERS_TOTAL_SIZE.fetch_sub(self.size() as usize, Ordering::SeqCst);
+        // Safety: the buffer isn't used after this
+        self.gpu.garbage.drop_buffer(unsafe { ManuallyDrop::take(&mut self.buffer) });
     }
 }
 
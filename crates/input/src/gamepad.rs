@@ -0,0 +1,248 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ambient_std::asset_cache::{AssetCache, SyncAssetKey};
+pub use gilrs::Button;
+use gilrs::{
+    ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Envelope, Repeat, Replay, Ticks},
+    GamepadId, Gilrs,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// How often the gamepads are polled for connections and rumble requests
+const POLL_INTERVAL: Duration = Duration::from_millis(16);
+/// The time resolution of the force feedback of gilrs, in milliseconds
+const TICK_MS: u32 = 50;
+
+/// A rumble of the two motors of a gamepad
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GamepadRumble {
+    /// The strength of the low frequency (strong) motor, from 0 to 1
+    pub low_frequency: f32,
+    /// The strength of the high frequency (weak) motor, from 0 to 1
+    pub high_frequency: f32,
+    pub duration: Duration,
+    /// How long it takes for the motors to ramp up to their strength at the start of the rumble
+    pub attack: Duration,
+    /// How long it takes for the motors to fade out at the end of the rumble
+    pub fade: Duration,
+}
+impl GamepadRumble {
+    pub fn new(low_frequency: f32, high_frequency: f32, duration: Duration) -> Self {
+        Self { low_frequency, high_frequency, duration, attack: Duration::ZERO, fade: Duration::ZERO }
+    }
+    pub fn with_envelope(self, attack: Duration, fade: Duration) -> Self {
+        Self { attack, fade, ..self }
+    }
+    fn effect(&self, gilrs: &mut Gilrs, gamepad: GamepadId) -> Result<Effect, gilrs::ff::Error> {
+        let ticks = |duration: Duration| (duration.as_millis() as u32 + TICK_MS - 1) / TICK_MS;
+        let duration = ticks(self.duration).max(1);
+        // The envelope has to fit in the rumble
+        let attack = ticks(self.attack).min((duration - 1) / 2);
+        let fade = ticks(self.fade).min(duration - 1 - attack);
+        let envelope = Envelope {
+            attack_length: Ticks::from_ms(attack * TICK_MS),
+            attack_level: 0.,
+            fade_length: Ticks::from_ms(fade * TICK_MS),
+            fade_level: 0.,
+        };
+        let duration = Ticks::from_ms(duration * TICK_MS);
+        let scheduling = Replay { play_for: duration, with_delay: Ticks::from_ms(0), after: Ticks::from_ms(0) };
+        let magnitude = |strength: f32| (strength.clamp(0., 1.) * u16::MAX as f32) as u16;
+        EffectBuilder::new()
+            .add_effect(BaseEffect { kind: BaseEffectType::Strong { magnitude: magnitude(self.low_frequency) }, scheduling, envelope })
+            .add_effect(BaseEffect { kind: BaseEffectType::Weak { magnitude: magnitude(self.high_frequency) }, scheduling, envelope })
+            .repeat(Repeat::For(duration))
+            .gamepads(&[gamepad])
+            .finish(gilrs)
+    }
+}
+
+/// The family of a gamepad, which determines the labels printed on its buttons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum GamepadStyle {
+    #[default]
+    Xbox,
+    PlayStation,
+    Nintendo,
+}
+impl GamepadStyle {
+    fn from_gamepad(gamepad: gilrs::Gamepad) -> Self {
+        const SONY: u16 = 0x054c;
+        const NINTENDO: u16 = 0x057e;
+        let name = gamepad.name().to_lowercase();
+        match gamepad.vendor_id() {
+            Some(SONY) => Self::PlayStation,
+            Some(NINTENDO) => Self::Nintendo,
+            _ if name.contains("playstation") || name.contains("dualshock") || name.contains("dualsense") => Self::PlayStation,
+            _ if name.contains("nintendo") || name.contains("switch") => Self::Nintendo,
+            _ => Self::Xbox,
+        }
+    }
+    /// The label or glyph printed on `button`, for binding prompts such as "Press A"
+    pub fn button_glyph(&self, button: Button) -> &'static str {
+        match (self, button) {
+            (Self::Xbox, Button::South) => "A",
+            (Self::Xbox, Button::East) => "B",
+            (Self::Xbox, Button::West) => "X",
+            (Self::Xbox, Button::North) => "Y",
+            (Self::Xbox, Button::LeftTrigger) => "LB",
+            (Self::Xbox, Button::RightTrigger) => "RB",
+            (Self::Xbox, Button::LeftTrigger2) => "LT",
+            (Self::Xbox, Button::RightTrigger2) => "RT",
+            (Self::Xbox, Button::Select) => "View",
+            (Self::Xbox, Button::Start) => "Menu",
+            (Self::Xbox, Button::LeftThumb) => "LS",
+            (Self::Xbox, Button::RightThumb) => "RS",
+
+            (Self::PlayStation, Button::South) => "✕",
+            (Self::PlayStation, Button::East) => "○",
+            (Self::PlayStation, Button::West) => "□",
+            (Self::PlayStation, Button::North) => "△",
+            (Self::PlayStation, Button::LeftTrigger) => "L1",
+            (Self::PlayStation, Button::RightTrigger) => "R1",
+            (Self::PlayStation, Button::LeftTrigger2) => "L2",
+            (Self::PlayStation, Button::RightTrigger2) => "R2",
+            (Self::PlayStation, Button::Select) => "Share",
+            (Self::PlayStation, Button::Start) => "Options",
+            (Self::PlayStation, Button::LeftThumb) => "L3",
+            (Self::PlayStation, Button::RightThumb) => "R3",
+
+            // The face buttons of Nintendo gamepads are mirrored
+            (Self::Nintendo, Button::South) => "B",
+            (Self::Nintendo, Button::East) => "A",
+            (Self::Nintendo, Button::West) => "Y",
+            (Self::Nintendo, Button::North) => "X",
+            (Self::Nintendo, Button::LeftTrigger) => "L",
+            (Self::Nintendo, Button::RightTrigger) => "R",
+            (Self::Nintendo, Button::LeftTrigger2) => "ZL",
+            (Self::Nintendo, Button::RightTrigger2) => "ZR",
+            (Self::Nintendo, Button::Select) => "−",
+            (Self::Nintendo, Button::Start) => "+",
+            (Self::Nintendo, Button::LeftThumb) => "LS",
+            (Self::Nintendo, Button::RightThumb) => "RS",
+
+            (_, Button::DPadUp) => "↑",
+            (_, Button::DPadDown) => "↓",
+            (_, Button::DPadLeft) => "←",
+            (_, Button::DPadRight) => "→",
+            (_, Button::Mode) => "Home",
+            (_, Button::C) => "C",
+            (_, Button::Z) => "Z",
+            (_, Button::Unknown) => "?",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum GamepadRequest {
+    Rumble { gamepad: usize, rumble: GamepadRumble },
+    Stop { gamepad: usize },
+}
+
+/// The gamepads connected to this machine, which are polled on their own thread. They're numbered from 0, in the order
+/// they were connected in, so that each local player can be routed to their own gamepad.
+#[derive(Debug, Clone)]
+pub struct Gamepads {
+    requests: flume::Sender<GamepadRequest>,
+    styles: Arc<Mutex<Vec<GamepadStyle>>>,
+}
+impl Gamepads {
+    pub fn new() -> Self {
+        let (requests, rx) = flume::unbounded();
+        let styles = Arc::new(Mutex::new(Vec::new()));
+        let thread_styles = styles.clone();
+        std::thread::Builder::new()
+            .name("gamepads".to_string())
+            .spawn(move || run(rx, thread_styles))
+            .expect("Failed to spawn the gamepad thread");
+        Self { requests, styles }
+    }
+    /// The style of the `gamepad`th gamepad, or [GamepadStyle::Xbox] if there's no such gamepad
+    pub fn style(&self, gamepad: usize) -> GamepadStyle {
+        self.styles.lock().get(gamepad).copied().unwrap_or_default()
+    }
+    /// Rumbles the motors of the `gamepad`th gamepad, replacing the rumble it's playing if any. This does nothing if there's no
+    /// such gamepad, or if it doesn't support force feedback.
+    pub fn rumble(&self, gamepad: usize, rumble: GamepadRumble) {
+        self.requests.send(GamepadRequest::Rumble { gamepad, rumble }).ok();
+    }
+    /// Stops the rumble of the `gamepad`th gamepad
+    pub fn stop_rumble(&self, gamepad: usize) {
+        self.requests.send(GamepadRequest::Stop { gamepad }).ok();
+    }
+}
+impl Default for Gamepads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct GamepadsKey;
+impl SyncAssetKey<Gamepads> for GamepadsKey {
+    fn load(&self, _assets: AssetCache) -> Gamepads {
+        Gamepads::new()
+    }
+}
+
+fn run(requests: flume::Receiver<GamepadRequest>, styles: Arc<Mutex<Vec<GamepadStyle>>>) {
+    let mut gilrs = match Gilrs::new() {
+        Ok(gilrs) => gilrs,
+        Err(err) => {
+            tracing::warn!("Gamepads are not available: {err}");
+            return;
+        }
+    };
+    let mut gamepads = gilrs.gamepads().map(|(id, _)| id).collect::<Vec<_>>();
+    *styles.lock() = gamepads.iter().map(|&id| GamepadStyle::from_gamepad(gilrs.gamepad(id))).collect();
+    // The rumbles being played, which stop when they're dropped
+    let mut rumbles: Vec<(GamepadId, Effect, Instant)> = Vec::new();
+    loop {
+        while let Some(event) = gilrs.next_event() {
+            match event.event {
+                gilrs::EventType::Connected => {
+                    if !gamepads.contains(&event.id) {
+                        gamepads.push(event.id);
+                        styles.lock().push(GamepadStyle::from_gamepad(gilrs.gamepad(event.id)));
+                    }
+                }
+                gilrs::EventType::Disconnected => {
+                    if let Some(index) = gamepads.iter().position(|&id| id == event.id) {
+                        gamepads.remove(index);
+                        styles.lock().remove(index);
+                    }
+                    rumbles.retain(|(id, _, _)| *id != event.id);
+                }
+                _ => {}
+            }
+        }
+        let now = Instant::now();
+        rumbles.retain(|(_, _, end)| *end > now);
+
+        match requests.recv_timeout(POLL_INTERVAL) {
+            Ok(GamepadRequest::Rumble { gamepad, rumble }) => {
+                if let Some(&id) = gamepads.get(gamepad) {
+                    rumbles.retain(|(rumbling, _, _)| *rumbling != id);
+                    if rumble.duration.is_zero() {
+                        continue;
+                    }
+                    match rumble.effect(&mut gilrs, id).and_then(|effect| effect.play().map(|_| effect)) {
+                        Ok(effect) => rumbles.push((id, effect, now + rumble.duration)),
+                        Err(err) => tracing::debug!("Can't rumble gamepad {gamepad}: {err}"),
+                    }
+                }
+            }
+            Ok(GamepadRequest::Stop { gamepad }) => {
+                if let Some(&id) = gamepads.get(gamepad) {
+                    rumbles.retain(|(rumbling, _, _)| *rumbling != id);
+                }
+            }
+            Err(flume::RecvTimeoutError::Timeout) => {}
+            Err(flume::RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use winit::event::{ScanCode, VirtualKeyCode};
+
+use crate::KeyboardEvent;
+
+/// A key a game action is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KeyBinding {
+    /// A key at a position on the keyboard, whatever the layout, such as the key under `W` on QWERTY keyboards (`Z` on
+    /// AZERTY) for moving forwards. Use it for the actions which are bound for their position.
+    Physical(ScanCode),
+    /// A key with the given meaning in the current layout, wherever it is, such as `Z` for undo. Use it for text
+    /// shortcuts.
+    Logical(VirtualKeyCode),
+}
+impl KeyBinding {
+    /// Binds to the key which is at the position of `keycode` on a US QWERTY keyboard
+    pub fn physical_from_qwerty(keycode: VirtualKeyCode) -> Option<Self> {
+        qwerty_scancode(keycode).map(Self::Physical)
+    }
+    pub fn matches(&self, event: &KeyboardEvent) -> bool {
+        match self {
+            Self::Physical(scancode) => event.scancode == *scancode,
+            Self::Logical(keycode) => event.keycode == Some(*keycode),
+        }
+    }
+    /// The name of the key in the current `layout`, for binding prompts such as "Press W"
+    pub fn display_name(&self, layout: &KeyboardLayout) -> String {
+        match self {
+            Self::Physical(scancode) => layout.display_name(*scancode),
+            Self::Logical(keycode) => key_display_name(*keycode).to_string(),
+        }
+    }
+}
+
+/// The keys of the current keyboard layout, which turns the positions of the keys (their scancodes) into the names
+/// printed on them.
+///
+/// The OS doesn't tell which layout is used, so it's learned from the key presses. Until a key has been pressed, it's
+/// assumed to be where it is on one of the common layouts: US QWERTY at first, then the one which agrees with the keys
+/// pressed so far (e.g. pressing the key under `Q` on QWERTY gives `A` on AZERTY, which tells where `W` and `Z` are too).
+/// When a key turns out to have changed meaning, the layout was switched, and what has been learned about the other keys
+/// is forgotten.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeyboardLayout {
+    /// The index in [LAYOUTS] of the common layout the keys which haven't been pressed are assumed to follow
+    layout: usize,
+    /// The keys which have been pressed, some of which don't have a [VirtualKeyCode]
+    keycodes: HashMap<ScanCode, Option<VirtualKeyCode>>,
+    /// The characters the keys type, for the keys which don't have a [VirtualKeyCode] such as `ü` or `ñ`
+    chars: HashMap<ScanCode, char>,
+}
+impl KeyboardLayout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The key at the position of `scancode` in this layout
+    pub fn keycode(&self, scancode: ScanCode) -> Option<VirtualKeyCode> {
+        match self.keycodes.get(&scancode) {
+            Some(keycode) => *keycode,
+            None => layout_keycode(self.layout, scancode).filter(|&keycode| !self.keycodes.values().any(|&k| k == Some(keycode))),
+        }
+    }
+    /// The position of `keycode` in this layout
+    pub fn scancode(&self, keycode: VirtualKeyCode) -> Option<ScanCode> {
+        self.keycodes
+            .iter()
+            .find(|(_, &k)| k == Some(keycode))
+            .map(|(&scancode, _)| scancode)
+            .or_else(|| layout_scancode(self.layout, keycode).filter(|scancode| !self.keycodes.contains_key(scancode)))
+    }
+    /// The name printed on the key at the position of `scancode`
+    pub fn display_name(&self, scancode: ScanCode) -> String {
+        match (self.keycode(scancode), self.chars.get(&scancode)) {
+            (Some(keycode), _) => key_display_name(keycode).to_string(),
+            (None, Some(c)) => c.to_uppercase().to_string(),
+            (None, None) => format!("Key {scancode}"),
+        }
+    }
+    /// Learns the layout from a key press
+    pub fn key_pressed(&mut self, scancode: ScanCode, keycode: Option<VirtualKeyCode>) {
+        match self.keycodes.insert(scancode, keycode) {
+            // The numpad keys change meaning with Num Lock, which isn't a change of layout
+            Some(previous) if previous != keycode && previous.map_or(true, is_layout_key) && keycode.map_or(true, is_layout_key) => {
+                self.keycodes.clear();
+                self.chars.clear();
+                self.keycodes.insert(scancode, keycode);
+            }
+            // Another key may have had this meaning in the previous layout
+            None if keycode.is_some() => self.keycodes.retain(|&other, &mut k| other == scancode || k != keycode),
+            _ => {}
+        }
+        // The current layout is kept for as long as it agrees with the keys, as the keys which are at the same place in
+        // several layouts don't tell them apart
+        let agrees = |layout: usize| {
+            self.keycodes.iter().all(|(&scancode, &keycode)| match (keycode, layout_keycode(layout, scancode)) {
+                (Some(keycode), Some(expected)) if is_layout_key(keycode) => keycode == expected,
+                _ => true,
+            })
+        };
+        if !agrees(self.layout) {
+            if let Some(layout) = (0..LAYOUTS.len()).find(|&layout| agrees(layout)) {
+                self.layout = layout;
+            }
+        }
+    }
+    /// Learns the character typed by the key at `scancode`, which has to be pressed without modifiers to give its base
+    /// character
+    pub fn received_character(&mut self, scancode: ScanCode, c: char) {
+        if !c.is_control() {
+            self.chars.insert(scancode, c);
+        }
+    }
+}
+
+/// A short, human readable name for `keycode`, such as `W`, `Space` or `Left Shift`
+pub fn key_display_name(keycode: VirtualKeyCode) -> &'static str {
+    use VirtualKeyCode::*;
+    match keycode {
+        Key1 | Numpad1 => "1",
+        Key2 | Numpad2 => "2",
+        Key3 | Numpad3 => "3",
+        Key4 | Numpad4 => "4",
+        Key5 | Numpad5 => "5",
+        Key6 | Numpad6 => "6",
+        Key7 | Numpad7 => "7",
+        Key8 | Numpad8 => "8",
+        Key9 | Numpad9 => "9",
+        Key0 | Numpad0 => "0",
+        A => "A",
+        B => "B",
+        C => "C",
+        D => "D",
+        E => "E",
+        F => "F",
+        G => "G",
+        H => "H",
+        I => "I",
+        J => "J",
+        K => "K",
+        L => "L",
+        M => "M",
+        N => "N",
+        O => "O",
+        P => "P",
+        Q => "Q",
+        R => "R",
+        S => "S",
+        T => "T",
+        U => "U",
+        V => "V",
+        W => "W",
+        X => "X",
+        Y => "Y",
+        Z => "Z",
+        Escape => "Esc",
+        F1 => "F1",
+        F2 => "F2",
+        F3 => "F3",
+        F4 => "F4",
+        F5 => "F5",
+        F6 => "F6",
+        F7 => "F7",
+        F8 => "F8",
+        F9 => "F9",
+        F10 => "F10",
+        F11 => "F11",
+        F12 => "F12",
+        F13 => "F13",
+        F14 => "F14",
+        F15 => "F15",
+        F16 => "F16",
+        F17 => "F17",
+        F18 => "F18",
+        F19 => "F19",
+        F20 => "F20",
+        F21 => "F21",
+        F22 => "F22",
+        F23 => "F23",
+        F24 => "F24",
+        Snapshot => "Print Screen",
+        Scroll => "Scroll Lock",
+        Pause => "Pause",
+        Insert => "Insert",
+        Home => "Home",
+        Delete => "Delete",
+        End => "End",
+        PageDown => "Page Down",
+        PageUp => "Page Up",
+        Left => "←",
+        Up => "↑",
+        Right => "→",
+        Down => "↓",
+        Back => "Backspace",
+        Return | NumpadEnter => "Enter",
+        Space => "Space",
+        Compose => "Compose",
+        Caret => "^",
+        Numlock => "Num Lock",
+        NumpadAdd | Plus => "+",
+        NumpadDivide | Slash => "/",
+        NumpadDecimal | Period => ".",
+        NumpadComma | Comma => ",",
+        NumpadEquals | Equals => "=",
+        NumpadMultiply | Asterisk => "*",
+        NumpadSubtract | Minus => "-",
+        Apostrophe => "'",
+        At => "@",
+        Backslash => "\\",
+        Capital => "Caps Lock",
+        Colon => ":",
+        Grave => "`",
+        LAlt => "Left Alt",
+        LBracket => "[",
+        LControl => "Left Ctrl",
+        LShift => "Left Shift",
+        LWin => "Left Super",
+        RAlt => "Right Alt",
+        RBracket => "]",
+        RControl => "Right Ctrl",
+        RShift => "Right Shift",
+        RWin => "Right Super",
+        Semicolon => ";",
+        Tab => "Tab",
+        Underline => "_",
+        Mute => "Mute",
+        VolumeDown => "Volume Down",
+        VolumeUp => "Volume Up",
+        PlayPause => "Play/Pause",
+        NextTrack => "Next Track",
+        PrevTrack => "Previous Track",
+        _ => "Unknown",
+    }
+}
+
+/// The US QWERTY keys, by the scancodes winit reports for them on this platform
+#[cfg(not(target_os = "macos"))]
+const QWERTY: &[(ScanCode, VirtualKeyCode)] = {
+    use VirtualKeyCode::*;
+    &[
+        (1, Escape),
+        (2, Key1),
+        (3, Key2),
+        (4, Key3),
+        (5, Key4),
+        (6, Key5),
+        (7, Key6),
+        (8, Key7),
+        (9, Key8),
+        (10, Key9),
+        (11, Key0),
+        (12, Minus),
+        (13, Equals),
+        (14, Back),
+        (15, Tab),
+        (16, Q),
+        (17, W),
+        (18, E),
+        (19, R),
+        (20, T),
+        (21, Y),
+        (22, U),
+        (23, I),
+        (24, O),
+        (25, P),
+        (26, LBracket),
+        (27, RBracket),
+        (28, Return),
+        (29, LControl),
+        (30, A),
+        (31, S),
+        (32, D),
+        (33, F),
+        (34, G),
+        (35, H),
+        (36, J),
+        (37, K),
+        (38, L),
+        (39, Semicolon),
+        (40, Apostrophe),
+        (41, Grave),
+        (42, LShift),
+        (43, Backslash),
+        (44, Z),
+        (45, X),
+        (46, C),
+        (47, V),
+        (48, B),
+        (49, N),
+        (50, M),
+        (51, Comma),
+        (52, Period),
+        (53, Slash),
+        (54, RShift),
+        (56, LAlt),
+        (57, Space),
+        (58, Capital),
+        (59, F1),
+        (60, F2),
+        (61, F3),
+        (62, F4),
+        (63, F5),
+        (64, F6),
+        (65, F7),
+        (66, F8),
+        (67, F9),
+        (68, F10),
+    ]
+};
+#[cfg(target_os = "macos")]
+const QWERTY: &[(ScanCode, VirtualKeyCode)] = {
+    use VirtualKeyCode::*;
+    &[
+        (0, A),
+        (1, S),
+        (2, D),
+        (3, F),
+        (4, H),
+        (5, G),
+        (6, Z),
+        (7, X),
+        (8, C),
+        (9, V),
+        (11, B),
+        (12, Q),
+        (13, W),
+        (14, E),
+        (15, R),
+        (16, Y),
+        (17, T),
+        (18, Key1),
+        (19, Key2),
+        (20, Key3),
+        (21, Key4),
+        (22, Key6),
+        (23, Key5),
+        (24, Equals),
+        (25, Key9),
+        (26, Key7),
+        (27, Minus),
+        (28, Key8),
+        (29, Key0),
+        (30, RBracket),
+        (31, O),
+        (32, U),
+        (33, LBracket),
+        (34, I),
+        (35, P),
+        (36, Return),
+        (37, L),
+        (38, J),
+        (39, Apostrophe),
+        (40, K),
+        (41, Semicolon),
+        (42, Backslash),
+        (43, Comma),
+        (44, Slash),
+        (45, N),
+        (46, M),
+        (47, Period),
+        (48, Tab),
+        (49, Space),
+        (50, Grave),
+        (51, Back),
+        (53, Escape),
+        (56, LShift),
+        (57, Capital),
+        (58, LAlt),
+        (59, LControl),
+        (60, RShift),
+    ]
+};
+
+/// The common layouts, as the keys which aren't where they are on a US QWERTY keyboard: the key at the position of the
+/// first key on QWERTY is the second one. The first layout is QWERTY itself.
+const LAYOUTS: &[&[(VirtualKeyCode, VirtualKeyCode)]] = {
+    use VirtualKeyCode::*;
+    &[
+        &[],
+        // AZERTY
+        &[(Q, A), (A, Q), (W, Z), (Z, W), (Semicolon, M), (M, Comma)],
+        // QWERTZ
+        &[(Y, Z), (Z, Y)],
+        // Dvorak
+        &[
+            (Q, Apostrophe),
+            (W, Comma),
+            (E, Period),
+            (R, P),
+            (T, Y),
+            (Y, F),
+            (U, G),
+            (I, C),
+            (O, R),
+            (P, L),
+            (S, O),
+            (D, E),
+            (F, U),
+            (G, I),
+            (H, D),
+            (J, H),
+            (K, T),
+            (L, N),
+            (Semicolon, S),
+            (Z, Semicolon),
+            (X, Q),
+            (C, J),
+            (V, K),
+            (B, X),
+            (N, B),
+            (Comma, W),
+            (Period, V),
+            (Slash, Z),
+        ],
+    ]
+};
+
+/// The keys which move around between the layouts, i.e. the letters, digits and punctuation
+const LAYOUT_KEYS: &[VirtualKeyCode] = {
+    use VirtualKeyCode::*;
+    &[
+        Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X,
+        Y, Z, Apostrophe, Backslash, Caret, Comma, Equals, Grave, LBracket, Minus, Period, RBracket, Semicolon, Slash,
+    ]
+};
+fn is_layout_key(keycode: VirtualKeyCode) -> bool {
+    LAYOUT_KEYS.contains(&keycode)
+}
+
+fn qwerty_keycode(scancode: ScanCode) -> Option<VirtualKeyCode> {
+    QWERTY.iter().find(|(s, _)| *s == scancode).map(|(_, keycode)| *keycode)
+}
+fn qwerty_scancode(keycode: VirtualKeyCode) -> Option<ScanCode> {
+    QWERTY.iter().find(|(_, k)| *k == keycode).map(|(scancode, _)| *scancode)
+}
+/// The key at the position of `scancode` in the common layout `layout`
+fn layout_keycode(layout: usize, scancode: ScanCode) -> Option<VirtualKeyCode> {
+    let qwerty = qwerty_keycode(scancode)?;
+    Some(LAYOUTS[layout].iter().find(|(q, _)| *q == qwerty).map_or(qwerty, |(_, keycode)| *keycode))
+}
+/// The position of `keycode` in the common layout `layout`
+fn layout_scancode(layout: usize, keycode: VirtualKeyCode) -> Option<ScanCode> {
+    let qwerty = match LAYOUTS[layout].iter().find(|(_, k)| *k == keycode) {
+        Some((qwerty, _)) => *qwerty,
+        // The key has been replaced by another one at its place on QWERTY
+        None if LAYOUTS[layout].iter().any(|(q, _)| *q == keycode) => return None,
+        None => keycode,
+    };
+    qwerty_scancode(qwerty)
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::{ElementState, ModifiersState};
+
+    use super::*;
+
+    /// The scancode of the key at the position of `keycode` on QWERTY
+    fn at(keycode: VirtualKeyCode) -> ScanCode {
+        qwerty_scancode(keycode).unwrap()
+    }
+
+    fn physical_name(layout: &KeyboardLayout, keycode: VirtualKeyCode) -> String {
+        KeyBinding::physical_from_qwerty(keycode).unwrap().display_name(layout)
+    }
+
+    #[test]
+    fn keys_are_named_after_qwerty_at_first() {
+        let layout = KeyboardLayout::new();
+        assert_eq!(physical_name(&layout, VirtualKeyCode::W), "W");
+        assert_eq!(physical_name(&layout, VirtualKeyCode::LShift), "Left Shift");
+        assert_eq!(KeyBinding::Logical(VirtualKeyCode::Z).display_name(&layout), "Z");
+        assert_eq!(layout.scancode(VirtualKeyCode::Q), Some(at(VirtualKeyCode::Q)));
+    }
+
+    #[test]
+    fn the_layout_is_recognized_from_any_key() {
+        let mut layout = KeyboardLayout::new();
+        // Pressing the key under Q on AZERTY tells where W is, before it's pressed
+        layout.key_pressed(at(VirtualKeyCode::Q), Some(VirtualKeyCode::A));
+        assert_eq!(physical_name(&layout, VirtualKeyCode::W), "Z");
+        assert_eq!(physical_name(&layout, VirtualKeyCode::Semicolon), "M");
+        assert_eq!(layout.scancode(VirtualKeyCode::W), Some(at(VirtualKeyCode::Z)));
+        assert_eq!(layout.scancode(VirtualKeyCode::Semicolon), None);
+        // The keys which are the same on several layouts keep the current one
+        layout.key_pressed(at(VirtualKeyCode::E), Some(VirtualKeyCode::E));
+        assert_eq!(physical_name(&layout, VirtualKeyCode::W), "Z");
+
+        let mut layout = KeyboardLayout::new();
+        layout.key_pressed(at(VirtualKeyCode::E), Some(VirtualKeyCode::Period));
+        assert_eq!(physical_name(&layout, VirtualKeyCode::S), "O");
+        assert_eq!(layout.scancode(VirtualKeyCode::Z), Some(at(VirtualKeyCode::Slash)));
+    }
+
+    #[test]
+    fn switching_layouts_forgets_the_keys() {
+        let mut layout = KeyboardLayout::new();
+        layout.key_pressed(at(VirtualKeyCode::Z), Some(VirtualKeyCode::Y));
+        assert_eq!(physical_name(&layout, VirtualKeyCode::Y), "Z");
+        layout.key_pressed(at(VirtualKeyCode::Z), Some(VirtualKeyCode::Z));
+        assert_eq!(physical_name(&layout, VirtualKeyCode::Y), "Y");
+        assert_eq!(layout.keycodes.len(), 1);
+
+        // Num Lock isn't a change of layout
+        let mut layout = KeyboardLayout::new();
+        layout.key_pressed(at(VirtualKeyCode::Q), Some(VirtualKeyCode::A));
+        layout.key_pressed(1000, Some(VirtualKeyCode::Numpad7));
+        layout.key_pressed(1000, Some(VirtualKeyCode::Home));
+        assert_eq!(layout.keycodes.len(), 2);
+        assert_eq!(physical_name(&layout, VirtualKeyCode::W), "Z");
+    }
+
+    #[test]
+    fn keys_without_keycodes_are_named_after_their_characters() {
+        let mut layout = KeyboardLayout::new();
+        layout.key_pressed(1000, None);
+        assert_eq!(layout.display_name(1000), "Key 1000");
+        layout.received_character(1000, 'ü');
+        layout.received_character(1000, '\u{8}');
+        assert_eq!(layout.display_name(1000), "Ü");
+    }
+
+    #[test]
+    fn bindings_match_the_keys() {
+        let event = KeyboardEvent {
+            scancode: at(VirtualKeyCode::W),
+            state: ElementState::Pressed,
+            keycode: Some(VirtualKeyCode::Z),
+            modifiers: ModifiersState::empty(),
+            is_focused: true,
+        };
+        assert!(KeyBinding::physical_from_qwerty(VirtualKeyCode::W).unwrap().matches(&event));
+        assert!(KeyBinding::Logical(VirtualKeyCode::Z).matches(&event));
+        assert!(!KeyBinding::Logical(VirtualKeyCode::W).matches(&event));
+    }
+
+    #[test]
+    fn layout_keys() {
+        assert!(is_layout_key(VirtualKeyCode::Key1));
+        assert!(is_layout_key(VirtualKeyCode::Z));
+        assert!(is_layout_key(VirtualKeyCode::Semicolon));
+        assert!(!is_layout_key(VirtualKeyCode::F1));
+        assert!(!is_layout_key(VirtualKeyCode::Numpad1));
+        assert!(!is_layout_key(VirtualKeyCode::Escape));
+    }
+}
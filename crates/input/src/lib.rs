@@ -1,14 +1,16 @@
 use std::{cmp::Reverse, collections::HashSet};
 
-use ambient_ecs::{components, query, EntityId, QueryState, System, SystemGroup, World};
+use ambient_ecs::{components, query, EntityData, EntityId, QueryState, Resource, System, SystemGroup, World};
 use ambient_std::events::EventDispatcher;
 use glam::{vec2, Vec2};
 use serde::{Deserialize, Serialize};
 pub use winit::event::{DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
 use winit::event::{ModifiersState, ScanCode};
 
-use crate::picking::picking_winit_event_system;
+use crate::{keyboard::KeyboardLayout, picking::picking_winit_event_system};
 
+pub mod gamepad;
+pub mod keyboard;
 pub mod picking;
 
 pub type EventCallback<Event, Ret = bool> = EventDispatcher<dyn Fn(&mut World, EntityId, Event) -> Ret + Sync + Send>;
@@ -42,6 +44,9 @@ components!("input", {
 
     player_raw_input: PlayerRawInput,
     player_prev_raw_input: PlayerRawInput,
+    /// The keyboard layout learned from the key presses, to display the names of the keys in binding prompts
+    @[Resource]
+    keyboard_layout: KeyboardLayout,
 });
 
 pub fn init_all_components() {
@@ -49,6 +54,10 @@ pub fn init_all_components() {
     init_components();
 }
 
+pub fn resources() -> EntityData {
+    EntityData::new().set_default(keyboard_layout())
+}
+
 pub fn event_systems() -> SystemGroup<Event<'static, ()>> {
     SystemGroup::new("inputs", vec![Box::new(InputSystem::new()), Box::new(picking_winit_event_system())])
 }
@@ -57,6 +66,8 @@ pub fn event_systems() -> SystemGroup<Event<'static, ()>> {
 pub struct InputSystem {
     modifiers: ModifiersState,
     is_focused: bool,
+    /// The last key pressed, which the next character received comes from
+    last_pressed: Option<ScanCode>,
     received_character_qs: QueryState,
     keyboard_event_qs: QueryState,
     mouse_input_qs: QueryState,
@@ -74,6 +85,7 @@ impl InputSystem {
             mouse_motion_qs: QueryState::new(),
             modifiers: ModifiersState::empty(),
             is_focused: true,
+            last_pressed: None,
         }
     }
 }
@@ -96,6 +108,9 @@ impl System<Event<'static, ()>> for InputSystem {
                     fire_event(world);
                 }
                 WindowEvent::ReceivedCharacter(c) => {
+                    if let Some(scancode) = self.last_pressed.take() {
+                        update_keyboard_layout(world, |layout| layout.received_character(scancode, *c));
+                    }
                     let mut fire_received_character = |world: &mut World| {
                         let mut handlers =
                             query((on_app_received_character(),)).collect_cloned(world, Some(&mut self.received_character_qs));
@@ -112,6 +127,11 @@ impl System<Event<'static, ()>> for InputSystem {
                 }
 
                 WindowEvent::KeyboardInput { input, .. } => {
+                    if input.state == ElementState::Pressed {
+                        // Only the keys typed without modifiers give their base character
+                        self.last_pressed = if self.modifiers.is_empty() { Some(input.scancode) } else { None };
+                        update_keyboard_layout(world, |layout| layout.key_pressed(input.scancode, input.virtual_keycode));
+                    }
                     let mut fire_keyboard_event = |world: &mut World| {
                         let mut handlers = query((on_app_keyboard_input(),)).collect_cloned(world, Some(&mut self.keyboard_event_qs));
 
@@ -186,6 +206,14 @@ impl System<Event<'static, ()>> for InputSystem {
     }
 }
 
+fn update_keyboard_layout(world: &mut World, update: impl FnOnce(&mut KeyboardLayout)) {
+    if let Some(layout) = world.resource_opt(keyboard_layout()) {
+        let mut layout = layout.clone();
+        update(&mut layout);
+        world.set_if_changed(world.resource_entity(), keyboard_layout(), layout).unwrap();
+    }
+}
+
 pub struct MouseInput {
     pub state: ElementState,
     pub button: MouseButton,
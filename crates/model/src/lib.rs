@@ -9,8 +9,8 @@ use ambient_core::{
     transform::{get_world_position, inv_local_to_world, local_to_world, mesh_to_world},
 };
 use ambient_ecs::{
-    components, query, ComponentDesc, Debuggable, Description, EntityData, EntityId, MaybeResource, Name, Networked, Store, SystemGroup,
-    World,
+    components, query, ComponentDesc, Debuggable, Description, EntityData, EntityId, MaybeResource, Name, Networked, SerializationProfile,
+    SerializationProfiles, Store, SystemGroup, World,
 };
 use ambient_gpu::mesh_buffer::GpuMeshFromUrl;
 use ambient_renderer::{
@@ -64,7 +64,10 @@ components!("model", {
     @[Networked, Store]
     model_skin_ix: usize,
 
-    @[Debuggable, Networked, Store, Name["Model loaded"], Description["If attached, this entity has a model attached to it."]]
+    @[
+        Debuggable, Networked, Store, SerializationProfiles[[SerializationProfile::REPLICATION]],
+        Name["Model loaded"], Description["If attached, this entity has a model attached to it."]
+    ]
     model_loaded: (),
     @[Debuggable, Networked, Store]
     is_model_node: (),
@@ -117,17 +120,11 @@ async fn internal_spawn_models_from_defs(
 
     let iter = entities_with_models.into_iter().map(|(url, ids)| async move {
         tracing::debug!("Loading model: {url:#?}");
-        let mut url = match TypedAssetUrl::parse(url).context("Failed to parse url") {
-            Ok(url) => url,
+        let key = match ModelFromUrl::for_model_from_url(&url) {
+            Ok(key) => key,
             Err(e) => return (ids, Err(e)),
         };
-        if !url.0.path().contains("/models/") {
-            url = match url.0.as_directory().join("models/main.json").context("Failed to join url") {
-                Ok(url) => url.into(),
-                Err(e) => return (ids, Err(e)),
-            };
-        };
-        match ModelFromUrl(url).get(assets).await.context("Failed to load model") {
+        match key.get(assets).await.context("Failed to load model") {
             Ok(v) => (ids, Ok(v)),
             Err(e) => (ids, Err(e)),
         }
@@ -240,6 +237,14 @@ impl ModelFromUrl {
     pub fn new(url: impl AsRef<str>) -> anyhow::Result<Self> {
         Ok(Self(TypedAssetUrl::parse(url)?))
     }
+    /// The model loaded for a [model_from_url] set to `url`, which may be the url of a model crate rather than of the model
+    pub fn for_model_from_url(url: &str) -> anyhow::Result<Self> {
+        let url = TypedAssetUrl::parse(url).context("Failed to parse url")?;
+        if url.0.path().contains("/models/") {
+            return Ok(Self(url));
+        }
+        Ok(Self(url.0.as_directory().join("models/main.json").context("Failed to join url")?.into()))
+    }
 }
 #[async_trait]
 impl AsyncAssetKey<Result<Arc<Model>, AssetError>> for ModelFromUrl {
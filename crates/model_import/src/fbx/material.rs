@@ -133,6 +133,9 @@ impl FbxMaterial {
             source: Some(source),
             base_color_factor: self.diffuse_color.map(|x| x.extend(self.opacity.unwrap_or(1.))),
             emissive_factor: self.emissive.map(|x| x.extend(0.)),
+            emissive_strength: None,
+            emissive: None,
+            emissive_srgb: false,
             base_color: get_map(self.diffuse_color_texture).map(img_to_asset),
 
             normalmap: get_map(self.normalmap).map(img_to_asset),
@@ -156,6 +159,12 @@ impl FbxMaterial {
             metallic: 0.0,
             opacity: None,
             roughness: self.specular_color_texture.map(|_| 1.).unwrap_or(0.8),
+            flipbook: None,
+            transmission: None,
+            clearcoat: None,
+            sheen: None,
+            sampling: None,
+            detail: None,
         }
     }
 }
@@ -1,31 +1,427 @@
-use std::path::Path;
+use std::{borrow::Cow, collections::HashMap, path::Path};
 
-use gltf::{buffer, image::Format, Document, Gltf};
+use ambient_std::{draco, meshopt};
+use anyhow::Context;
+use glam::Vec3;
+use gltf::{buffer, image::Format, Document, Glb, Gltf};
 use image::{
-    DynamicImage, ImageFormat::{Jpeg, Png}
+    DynamicImage,
+    ImageFormat::{Jpeg, Png},
 };
+use serde::Deserialize;
+use serde_json::Value;
 
 pub struct GltfImport {
     pub name: String,
     pub document: gltf::Document,
     pub buffers: Vec<gltf::buffer::Data>,
     pub images: Vec<gltf::image::Data>,
+    /// The material extensions which the gltf crate doesn't read, by material index
+    pub material_extensions: Vec<MaterialExtensions>,
 }
 impl GltfImport {
-    pub fn from_slice<S: AsRef<[u8]>>(name: String, import_images: bool, slice: S) -> gltf::Result<Self> {
-        let Gltf { document, blob } = Gltf::from_slice(slice.as_ref())?;
-        let buffers = import_buffer_data(&document, None, blob)?;
+    pub fn from_slice<S: AsRef<[u8]>>(name: String, import_images: bool, slice: S) -> anyhow::Result<Self> {
+        let slice = slice.as_ref();
+        // The accessors of the Draco primitives have no data until they're decompressed, so the document is validated after
+        let Gltf { document, blob } = Gltf::from_slice_without_validation(slice)?;
+        let json = if slice.starts_with(b"glTF") { Glb::from_slice(slice)?.json } else { Cow::Borrowed(slice) };
+        let mut json = serde_json::from_slice::<Value>(&json)?;
+        let material_extensions = MaterialExtensions::from_json(&name, &json, document.materials().len());
+        let meshopt_views = MeshoptBufferView::from_json(&json)?;
+        let draco_primitives = DracoPrimitive::from_json(&json)?;
+        let fallback_buffers = fallback_buffers(&json)?;
+        let mut buffers = import_buffer_data(&document, None, blob, &fallback_buffers)?;
+        for (view_index, view) in &meshopt_views {
+            view.decode(&mut buffers).with_context(|| format!("Failed to decompress the buffer view {view_index} of {name}"))?;
+        }
+        // The Draco primitives are decompressed into a new buffer, which their accessors are pointed at
+        let document = if draco_primitives.is_empty() {
+            Document::from_json(document.into_json())?
+        } else {
+            let mut decoded = Vec::new();
+            let decoded_buffer = buffers.len();
+            for primitive in &draco_primitives {
+                primitive.decode(&mut json, &buffers, decoded_buffer, &mut decoded).with_context(|| {
+                    format!("Failed to decompress the primitive {} of the mesh {} of {name}", primitive.primitive, primitive.mesh)
+                })?;
+            }
+            push_json(&mut json, "buffers", serde_json::json!({ "byteLength": decoded.len() }))?;
+            buffers.push(buffer::Data(decoded));
+            Document::from_json(serde_json::from_value(json)?)?
+        };
         let images = if import_images { import_image_data(&document, None, &buffers)? } else { Vec::new() };
-        Ok(Self { name, document, buffers, images })
+        Ok(Self { name, document, buffers, images, material_extensions })
+    }
+}
+
+/// The `KHR_materials_*` extensions of a material. Only their factors are read; their textures are ignored.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MaterialExtensions {
+    #[serde(rename = "KHR_materials_emissive_strength")]
+    pub emissive_strength: Option<EmissiveStrength>,
+    #[serde(rename = "KHR_materials_transmission")]
+    pub transmission: Option<Transmission>,
+    #[serde(rename = "KHR_materials_clearcoat")]
+    pub clearcoat: Option<Clearcoat>,
+    #[serde(rename = "KHR_materials_sheen")]
+    pub sheen: Option<Sheen>,
+}
+impl MaterialExtensions {
+    fn from_json(name: &str, json: &Value, material_count: usize) -> Vec<Self> {
+        #[derive(Default, Deserialize)]
+        struct Material {
+            #[serde(default)]
+            extensions: MaterialExtensions,
+        }
+        let materials = match json.get("materials") {
+            Some(materials) => Vec::<Material>::deserialize(materials).unwrap_or_else(|err| {
+                log::warn!("Failed to read the material extensions of {name}: {err}");
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        let mut extensions = materials.into_iter().map(|material| material.extensions).collect::<Vec<_>>();
+        extensions.resize_with(material_count, Default::default);
+        extensions
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmissiveStrength {
+    #[serde(default = "one")]
+    pub emissive_strength: f32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transmission {
+    #[serde(default)]
+    pub transmission_factor: f32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Clearcoat {
+    #[serde(default)]
+    pub clearcoat_factor: f32,
+    #[serde(default)]
+    pub clearcoat_roughness_factor: f32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sheen {
+    #[serde(default)]
+    pub sheen_color_factor: Vec3,
+    #[serde(default)]
+    pub sheen_roughness_factor: f32,
+}
+
+fn one() -> f32 {
+    1.
+}
+
+/// A buffer view compressed with `EXT_meshopt_compression`. The compressed data is in another buffer view, and the view
+/// itself is usually in a fallback buffer, which has no data and is only there to hold the decompressed views.
+#[derive(Debug, Clone)]
+struct MeshoptBufferView {
+    buffer: usize,
+    byte_offset: usize,
+    compressed: MeshoptCompression,
+}
+impl MeshoptBufferView {
+    /// The compressed buffer views, with their index
+    fn from_json(json: &Value) -> anyhow::Result<Vec<(usize, Self)>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct BufferView {
+            buffer: usize,
+            #[serde(default)]
+            byte_offset: usize,
+            #[serde(default)]
+            extensions: BufferViewExtensions,
+        }
+        #[derive(Default, Deserialize)]
+        struct BufferViewExtensions {
+            #[serde(rename = "EXT_meshopt_compression")]
+            meshopt: Option<MeshoptCompression>,
+        }
+        let views = match json.get("bufferViews") {
+            Some(views) => Vec::<BufferView>::deserialize(views).context("Invalid buffer views")?,
+            None => Vec::new(),
+        };
+        Ok(views
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, view)| {
+                let compressed = view.extensions.meshopt?;
+                Some((index, Self { buffer: view.buffer, byte_offset: view.byte_offset, compressed }))
+            })
+            .collect())
+    }
+    fn decode(&self, buffers: &mut [buffer::Data]) -> anyhow::Result<()> {
+        let MeshoptCompression { buffer, byte_offset, byte_length, byte_stride, count, mode, filter } = self.compressed;
+        let encoded = buffers
+            .get(buffer)
+            .and_then(|data| data.get(byte_offset..byte_offset + byte_length))
+            .context("The compressed data is out of bounds")?;
+        let mut decoded = match mode {
+            MeshoptMode::Attributes => meshopt::decode_vertex_buffer(encoded, count, byte_stride)?,
+            MeshoptMode::Triangles => index_bytes(meshopt::decode_index_buffer(encoded, count)?, byte_stride)?,
+            MeshoptMode::Indices => index_bytes(meshopt::decode_index_sequence(encoded, count)?, byte_stride)?,
+        };
+        let filter = match filter {
+            MeshoptFilter::None => None,
+            MeshoptFilter::Octahedral => Some(meshopt::Filter::Octahedral),
+            MeshoptFilter::Quaternion => Some(meshopt::Filter::Quaternion),
+            MeshoptFilter::Exponential => Some(meshopt::Filter::Exponential),
+        };
+        if let Some(filter) = filter {
+            meshopt::decode_filter(&mut decoded, byte_stride, filter)?;
+        }
+        let target = buffers
+            .get_mut(self.buffer)
+            .and_then(|data| data.0.get_mut(self.byte_offset..self.byte_offset + decoded.len()))
+            .context("The decompressed data is out of bounds")?;
+        target.copy_from_slice(&decoded);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MeshoptCompression {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    byte_length: usize,
+    byte_stride: usize,
+    count: usize,
+    mode: MeshoptMode,
+    #[serde(default)]
+    filter: MeshoptFilter,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum MeshoptMode {
+    Attributes,
+    Triangles,
+    Indices,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum MeshoptFilter {
+    #[default]
+    None,
+    Octahedral,
+    Quaternion,
+    Exponential,
+}
+
+fn index_bytes(indices: Vec<u32>, byte_stride: usize) -> anyhow::Result<Vec<u8>> {
+    Ok(match byte_stride {
+        2 => indices.into_iter().flat_map(|index| (index as u16).to_le_bytes()).collect(),
+        4 => indices.into_iter().flat_map(u32::to_le_bytes).collect(),
+        _ => anyhow::bail!("Invalid index size {byte_stride}"),
+    })
+}
+
+/// A primitive compressed with `KHR_draco_mesh_compression`. Its accessors have no buffer views, or ones with the
+/// uncompressed fallback, and are pointed at the decompressed data instead.
+#[derive(Debug, Clone)]
+struct DracoPrimitive {
+    mesh: usize,
+    primitive: usize,
+    indices: Option<usize>,
+    /// The accessor of each attribute, and the id of its values in the compressed data
+    attributes: Vec<(usize, u32)>,
+    buffer_view: usize,
+}
+impl DracoPrimitive {
+    fn from_json(json: &Value) -> anyhow::Result<Vec<Self>> {
+        #[derive(Deserialize)]
+        struct Mesh {
+            #[serde(default)]
+            primitives: Vec<Primitive>,
+        }
+        #[derive(Deserialize)]
+        struct Primitive {
+            #[serde(default)]
+            attributes: HashMap<String, usize>,
+            indices: Option<usize>,
+            #[serde(default)]
+            extensions: PrimitiveExtensions,
+        }
+        #[derive(Default, Deserialize)]
+        struct PrimitiveExtensions {
+            #[serde(rename = "KHR_draco_mesh_compression")]
+            draco: Option<DracoCompression>,
+        }
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct DracoCompression {
+            buffer_view: usize,
+            attributes: HashMap<String, u32>,
+        }
+        let meshes = match json.get("meshes") {
+            Some(meshes) => Vec::<Mesh>::deserialize(meshes).context("Invalid meshes")?,
+            None => Vec::new(),
+        };
+        let mut primitives = Vec::new();
+        for (mesh_index, mesh) in meshes.into_iter().enumerate() {
+            for (primitive_index, primitive) in mesh.primitives.into_iter().enumerate() {
+                let compressed = match primitive.extensions.draco {
+                    Some(compressed) => compressed,
+                    None => continue,
+                };
+                let attributes = compressed
+                    .attributes
+                    .iter()
+                    .filter_map(|(name, &unique_id)| Some((*primitive.attributes.get(name)?, unique_id)))
+                    .collect();
+                primitives.push(Self {
+                    mesh: mesh_index,
+                    primitive: primitive_index,
+                    indices: primitive.indices,
+                    attributes,
+                    buffer_view: compressed.buffer_view,
+                });
+            }
+        }
+        Ok(primitives)
     }
+
+    /// Appends the decompressed indices and attributes to `decoded`, the data of the buffer `decoded_buffer`, and points
+    /// the accessors at them
+    fn decode(&self, json: &mut Value, buffers: &[buffer::Data], decoded_buffer: usize, decoded: &mut Vec<u8>) -> anyhow::Result<()> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct BufferView {
+            buffer: usize,
+            #[serde(default)]
+            byte_offset: usize,
+            byte_length: usize,
+        }
+        let view = json.pointer(&format!("/bufferViews/{}", self.buffer_view)).context("Missing compressed buffer view")?;
+        let BufferView { buffer, byte_offset, byte_length } = BufferView::deserialize(view).context("Invalid compressed buffer view")?;
+        let compressed = buffers
+            .get(buffer)
+            .and_then(|data| data.get(byte_offset..byte_offset + byte_length))
+            .context("The compressed data is out of bounds")?;
+        let mesh = draco::decode_mesh(compressed)?;
+
+        if let Some(accessor) = self.indices {
+            let indices = mesh.indices.iter().map(|&index| index as f64).collect::<Vec<_>>();
+            write_accessor(json, accessor, 1, &indices, decoded_buffer, decoded)?;
+        }
+        for &(accessor, unique_id) in &self.attributes {
+            let attribute = mesh
+                .attributes
+                .iter()
+                .find(|attribute| attribute.unique_id == unique_id)
+                .with_context(|| format!("Missing compressed attribute {unique_id}"))?;
+            write_accessor(json, accessor, attribute.components, &attribute.values, decoded_buffer, decoded)?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends `values` to `decoded` in the component type of the accessor, and points the accessor at them
+fn write_accessor(
+    json: &mut Value,
+    accessor_index: usize,
+    components: usize,
+    values: &[f64],
+    decoded_buffer: usize,
+    decoded: &mut Vec<u8>,
+) -> anyhow::Result<()> {
+    let accessor = json.pointer_mut(&format!("/accessors/{accessor_index}")).context("Missing accessor")?;
+    let component_type = accessor.get("componentType").and_then(Value::as_u64).context("Invalid accessor")?;
+    let accessor_components = match accessor.get("type").and_then(Value::as_str) {
+        Some("SCALAR") => 1,
+        Some("VEC2") => 2,
+        Some("VEC3") => 3,
+        Some("VEC4") => 4,
+        _ => anyhow::bail!("Unsupported type of the accessor {accessor_index}"),
+    };
+    anyhow::ensure!(accessor_components == components, "The accessor {accessor_index} doesn't match the compressed data");
+    let bytes = match component_type {
+        5120 => values.iter().flat_map(|&value| (value as i8).to_le_bytes()).collect::<Vec<_>>(),
+        5121 => values.iter().flat_map(|&value| (value as u8).to_le_bytes()).collect(),
+        5122 => values.iter().flat_map(|&value| (value as i16).to_le_bytes()).collect(),
+        5123 => values.iter().flat_map(|&value| (value as u16).to_le_bytes()).collect(),
+        5125 => values.iter().flat_map(|&value| (value as u32).to_le_bytes()).collect(),
+        5126 => values.iter().flat_map(|&value| (value as f32).to_le_bytes()).collect(),
+        _ => anyhow::bail!("Invalid component type {component_type}"),
+    };
+    accessor["count"] = (values.len() / components).into();
+    if let Some(accessor) = accessor.as_object_mut() {
+        accessor.remove("byteOffset");
+    }
+
+    let view = serde_json::json!({ "buffer": decoded_buffer, "byteOffset": decoded.len(), "byteLength": bytes.len() });
+    let view_index = push_json(json, "bufferViews", view)?;
+    json["accessors"][accessor_index]["bufferView"] = view_index.into();
+    decoded.extend_from_slice(&bytes);
+    while decoded.len() % 4 != 0 {
+        decoded.push(0);
+    }
+    Ok(())
+}
+
+/// Appends `value` to the array `key` of the root, and returns its index
+fn push_json(json: &mut Value, key: &str, value: Value) -> anyhow::Result<usize> {
+    let root = json.as_object_mut().context("Invalid glTF")?;
+    let array = root.entry(key).or_insert_with(|| Value::Array(Vec::new())).as_array_mut().with_context(|| format!("Invalid {key}"))?;
+    array.push(value);
+    Ok(array.len() - 1)
+}
+
+/// The indices of the buffers which have no data of their own, and only hold the buffer views decompressed by
+/// `EXT_meshopt_compression`
+fn fallback_buffers(json: &Value) -> anyhow::Result<Vec<usize>> {
+    #[derive(Deserialize)]
+    struct Buffer {
+        #[serde(default)]
+        extensions: BufferExtensions,
+    }
+    #[derive(Default, Deserialize)]
+    struct BufferExtensions {
+        #[serde(rename = "EXT_meshopt_compression")]
+        meshopt: Option<MeshoptBuffer>,
+    }
+    #[derive(Deserialize)]
+    struct MeshoptBuffer {
+        #[serde(default)]
+        fallback: bool,
+    }
+    let buffers = match json.get("buffers") {
+        Some(buffers) => Vec::<Buffer>::deserialize(buffers).context("Invalid buffers")?,
+        None => Vec::new(),
+    };
+    Ok(buffers
+        .into_iter()
+        .enumerate()
+        .filter(|(_, buffer)| buffer.extensions.meshopt.as_ref().map_or(false, |meshopt| meshopt.fallback))
+        .map(|(index, _)| index)
+        .collect())
 }
 
 // All of the below is basically just copied from the gltf crate, except it doesn't panic on bad resource references
 
-fn import_buffer_data(document: &Document, base: Option<&Path>, mut blob: Option<Vec<u8>>) -> gltf::Result<Vec<buffer::Data>> {
+fn import_buffer_data(
+    document: &Document,
+    base: Option<&Path>,
+    mut blob: Option<Vec<u8>>,
+    fallback_buffers: &[usize],
+) -> gltf::Result<Vec<buffer::Data>> {
     let mut buffers = Vec::new();
     for buffer in document.buffers() {
         let mut data = match buffer.source() {
+            _ if fallback_buffers.contains(&buffer.index()) => Ok(vec![0; buffer.length()]),
             buffer::Source::Uri(uri) if base.is_some() => Scheme::read(base.unwrap(), uri),
             buffer::Source::Bin => blob.take().ok_or(gltf::Error::MissingBlob),
             _ => Ok(Vec::new()),
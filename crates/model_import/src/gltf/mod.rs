@@ -9,7 +9,10 @@ use ambient_core::{
 };
 use ambient_ecs::{EntityData, World};
 use ambient_model::{model_skin_ix, model_skins, pbr_renderer_primitives_from_url, Model, ModelSkin, PbrRenderPrimitiveFromUrl};
-use ambient_renderer::materials::pbr_material::PbrMaterialFromUrl;
+use ambient_renderer::materials::{
+    pbr_material::{Clearcoat, PbrMaterialFromUrl, Sheen},
+    sampler::{TextureFiltering, TextureSampling},
+};
 use ambient_std::{asset_cache::AssetCache, asset_url::AbsAssetUrl, mesh::Mesh, shapes::AABB};
 use glam::{uvec4, Mat4, Quat, UVec4, Vec2, Vec3, Vec4, Vec4Swizzles};
 use gltf::animation::util::ReadOutputs;
@@ -47,7 +50,7 @@ pub async fn import(import: &GltfImport, asset_crate: &mut ModelCrate) -> anyhow
                 normals: reader.read_normals().map(|v| v.map(|x| x.into()).collect::<Vec<Vec3>>()),
                 tangents: reader.read_tangents().map(|v| v.map(|x| Vec4::from(x).xyz()).collect::<Vec<Vec3>>()),
                 texcoords,
-                colors: None,
+                colors: reader.read_colors(0).map(|v| v.into_rgba_f32().map(|x| x.into()).collect::<Vec<Vec4>>()),
                 joint_indices: reader
                     .read_joints(0)
                     .map(|v| v.into_u16().map(|v| uvec4(v[0] as u32, v[1] as u32, v[2] as u32, v[3] as u32)).collect::<Vec<UVec4>>()),
@@ -178,12 +181,14 @@ pub async fn import(import: &GltfImport, asset_crate: &mut ModelCrate) -> anyhow
     let mut materials = Vec::new();
     for (index, mat) in import.document.materials().enumerate() {
         let pbr = mat.pbr_metallic_roughness();
+        let extensions = &import.material_extensions[index];
+        let emissive_strength = extensions.emissive_strength.map_or(1., |x| x.emissive_strength);
 
         let mat_def = PbrMaterialFromUrl {
             name: mat.name().map(|x| x.to_string()),
             source: Some(import.name.clone()),
             base_color_factor: Some(glam::Vec4::from_slice(&mat.pbr_metallic_roughness().base_color_factor())),
-            emissive_factor: Some(glam::Vec3::from_slice(&mat.emissive_factor()).extend(0.)),
+            emissive_factor: Some((glam::Vec3::from_slice(&mat.emissive_factor()) * emissive_strength).extend(0.)),
             transparent: Some(mat.alpha_mode() == gltf::material::AlphaMode::Blend),
             alpha_cutoff: mat.alpha_cutoff(),
             metallic: pbr.metallic_factor(),
@@ -194,8 +199,22 @@ pub async fn import(import: &GltfImport, asset_crate: &mut ModelCrate) -> anyhow
                 .metallic_roughness_texture()
                 .and_then(|x| images.get(x.texture().index()))
                 .map(|x| dotdot_path(x).into()),
+            emissive: mat.emissive_texture().and_then(|x| images.get(x.texture().index())).map(|x| dotdot_path(x).into()),
+            // glTF's emissive maps are sRGB encoded, like its base color maps, which they may be shared with
+            emissive_srgb: true,
+            emissive_strength: None,
             double_sided: Some(mat.double_sided()),
             opacity: None,
+            flipbook: None,
+            transmission: extensions.transmission.map(|x| x.transmission_factor),
+            clearcoat: extensions.clearcoat.map(|x| Clearcoat { factor: x.clearcoat_factor, roughness: x.clearcoat_roughness_factor }),
+            sheen: extensions.sheen.map(|x| Sheen { color: x.sheen_color_factor, roughness: x.sheen_roughness_factor }),
+            // Pixel art is usually exported with nearest filtering, which would be blurred by the default sampling
+            sampling: pbr
+                .base_color_texture()
+                .filter(|x| x.texture().sampler().mag_filter() == Some(gltf::texture::MagFilter::Nearest))
+                .map(|_| TextureSampling { filtering: TextureFiltering::Nearest, anisotropy: 1, mip_bias: 0. }),
+            detail: None,
         };
         materials.push(asset_crate.materials.insert(&format!("{}{}", name_(mat.name()), index), mat_def).path);
     }
@@ -20,6 +20,7 @@ use ambient_renderer::{
     double_sided,
     lod::{gpu_lod, lod_cutoffs},
     materials::pbr_material::PbrMaterialFromUrl,
+    skinning::joint_bounding_aabbs,
 };
 use ambient_std::{
     asset_cache::{AssetCache, SyncAssetKeyExt},
@@ -27,6 +28,7 @@ use ambient_std::{
     download_asset::AssetsCacheDir,
     mesh::Mesh,
     shapes::AABB,
+    uv_unwrap::{generate_lightmap_uvs, LightmapUvSettings},
 };
 use anyhow::Context;
 use futures::FutureExt;
@@ -358,6 +360,26 @@ impl ModelCrate {
             if let Some(aabb) = AABB::unions(&aabbs) {
                 world.add_component(node, local_bounding_aabb(), aabb).unwrap();
             }
+            if world.has_component(node, model_skin_ix()) {
+                let mut joint_aabbs: Vec<Option<AABB>> = Vec::new();
+                for mesh in
+                    primitives.iter().filter_map(|p| self.meshes.get_by_path(&RelativePathBuf::from("materials").join(p.mesh.path())))
+                {
+                    let mesh_joint_aabbs = mesh.joint_aabbs();
+                    if joint_aabbs.len() < mesh_joint_aabbs.len() {
+                        joint_aabbs.resize(mesh_joint_aabbs.len(), None);
+                    }
+                    for (joint_aabb, mesh_joint_aabb) in joint_aabbs.iter_mut().zip(mesh_joint_aabbs) {
+                        *joint_aabb = match (*joint_aabb, mesh_joint_aabb) {
+                            (Some(a), Some(b)) => Some(a.union(&b)),
+                            (a, b) => a.or(b),
+                        };
+                    }
+                }
+                if !joint_aabbs.is_empty() {
+                    world.add_component(node, joint_bounding_aabbs(), joint_aabbs).unwrap();
+                }
+            }
         }
     }
     pub fn make_new_root(&mut self, node_id: EntityId) {
@@ -425,6 +447,31 @@ impl ModelCrate {
             cap_texture_size(image, max_size);
         }
     }
+    /// Generates the second UVs of the static meshes which don't have them, for the lightmaps (see
+    /// [generate_lightmap_uvs]). With `merge`, the charts of all these meshes are packed into the same square, for a
+    /// model which is lightmapped as a whole; otherwise each mesh has the square to itself.
+    pub fn generate_lightmap_uvs(&mut self, settings: &LightmapUvSettings, merge: bool) {
+        let mut meshes = self
+            .meshes
+            .content
+            .iter_mut()
+            .filter(|(_, mesh)| mesh.texcoords.len() < 2 && mesh.joint_weights.is_none())
+            // Sorted, for the packing to be the same on each build
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, mesh)| mesh)
+            .collect_vec();
+        if merge {
+            generate_lightmap_uvs(&mut meshes, settings);
+        } else {
+            for mesh in meshes {
+                mesh.generate_lightmap_uvs(settings);
+            }
+        }
+    }
+    /// Writes the meshes compressed with meshopt (see [Mesh::to_compressed_bytes]) instead of with bincode
+    pub fn compress_meshes(&mut self) {
+        self.meshes.serialize = |mesh| mesh.to_compressed_bytes();
+    }
     pub fn update_transforms(&mut self) {
         TransformSystem::new().run(self.model_world_mut(), &FrameEvent);
     }
@@ -167,6 +167,7 @@ impl NaturalsPipeline {
                 url: AbsAssetUrl::parse(format!("{OLD_CONTENT_SERVER_URL}assets/models/Misc/FreeBlueNoiseTextures/64_64/HDR_L_0.png"))
                     .unwrap(),
                 format: wgpu::TextureFormat::Rgba8Unorm,
+                color_space: None,
             }
             .get(&assets)
             .await
@@ -178,6 +179,7 @@ impl NaturalsPipeline {
                 ))
                 .unwrap(),
                 format: wgpu::TextureFormat::Rgba8Unorm,
+                color_space: None,
             }
             .get(&assets)
             .await
@@ -0,0 +1,806 @@
+//! Streams the content of the server to the clients over the game connection, so that a client joining a server it has
+//! never seen can fetch its assets without a separate HTTP host.
+//!
+//! The assets are content-addressed blobs: a blob is requested by the sha256 of its content, as the [AssetManifest] of the
+//! build lists it. The client asks for a blob from an offset, and the server sends it in chunks on the
+//! [ASSET_STREAM_CHANNEL_ID] channel, which is reliable, ordered, and of a lower priority than the rest of the game's
+//! traffic. The server only sends so far ahead of what the client acknowledged having written, so that a slow client
+//! holds back its own transfers rather than filling the server's memory, and the channel may be given a budget on top.
+//!
+//! The client writes the chunks to a partial file in its [AssetsCacheDir], and moves it in place once the hash of its
+//! content checks out. When the connection is lost, the client asks for the blobs it was fetching again once it's back,
+//! from where they stopped. The server may also push the blobs to the clients with [AssetStreamServer::push], and the
+//! clients fetch the ones they don't have yet.
+//!
+//! The client is the [ContentSource] of the asset loading, so the urls of the content of the server (as served with
+//! [AssetStreamServer::serve_content]) are loaded from the blobs: the client asks the server for the hash of the file
+//! of a url, and fetches the blob with it unless it's cached already. The other urls are downloaded as usual.
+//!
+//! [AssetsCacheDir]: ambient_std::download_asset::AssetsCacheDir
+
+use std::{
+    collections::HashMap,
+    io::SeekFrom,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Weak},
+    time::{Duration, SystemTime},
+};
+
+use ambient_core::asset_cache;
+use ambient_ecs::{components, Resource, World};
+use ambient_std::{
+    asset_cache::SyncAssetKeyExt,
+    asset_manifest::AssetManifest,
+    asset_url::{AbsAssetUrl, AssetUrl},
+    download_cache::{ContentSource, ContentSourceKey},
+    sha256_digest_bytes, Sha256Digest,
+};
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::Bytes;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::{oneshot, watch},
+    task::JoinHandle,
+};
+
+use crate::{
+    channels::{register_channel, register_client_channel, Channel, ChannelConfig, Reliability},
+    log_network_result,
+    server::{ServerState, SharedServerState},
+    transport::Connection,
+    BiStreamHandlers, DatagramHandlers,
+};
+
+components!("network", {
+    /// Serves the blobs of the server's content to the clients
+    @[Resource]
+    asset_stream_server: AssetStreamServer,
+    /// Fetches the blobs of the server's content, on the client
+    @[Resource]
+    asset_stream_client: AssetStreamClient,
+});
+
+/// The id of the channel the blobs are streamed on, in both directions
+pub const ASSET_STREAM_CHANNEL_ID: u32 = 7;
+
+/// The most bytes of a blob sent in a message
+const CHUNK_SIZE: usize = 64 * 1024;
+/// The most bytes of a blob which the server sends ahead of what the client acknowledged
+const WINDOW: u64 = 16 * CHUNK_SIZE as u64;
+/// How long the loading of a url waits for the server to tell where its content is, before it's downloaded as usual
+const CONTENT_URL_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn channel_config(budget: Option<u64>) -> ChannelConfig {
+    // The gameplay goes first
+    ChannelConfig { id: ASSET_STREAM_CHANNEL_ID, reliability: Reliability::ReliableOrdered, priority: -1, budget }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum ClientMessage {
+    /// Asks where the content of the server is served at
+    Describe,
+    /// Asks for the hash of the file at `path` in the content of the server
+    Resolve { path: String },
+    /// Asks for the blob with `hash`, from `offset` on
+    Request { hash: String, offset: u64 },
+    /// The client has written the first `received` bytes of the blob
+    Ack { hash: String, received: u64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum ServerMessage {
+    /// The url the content of the server is served at, if it serves it
+    Content {
+        base_url: Option<String>,
+    },
+    /// The hash of the file at `path` in the content, if there's one
+    Resolved {
+        path: String,
+        hash: Option<String>,
+    },
+    /// The client should fetch the blob with `hash`, unless it has it already
+    Offer {
+        hash: String,
+    },
+    Chunk(Chunk),
+    /// The server has no blob with `hash`
+    NotFound {
+        hash: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Chunk {
+    hash: String,
+    /// Where the data goes in the blob
+    offset: u64,
+    /// The length of the whole blob
+    len: u64,
+    data: Vec<u8>,
+}
+
+fn encode<T: Serialize>(message: &T) -> Bytes {
+    bincode::serialize(message).expect("The messages of the asset stream always serialize").into()
+}
+
+/// Whether `hash` looks like the sha256 of a blob, which makes it safe to use as a file name
+fn is_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Whether `path` stays inside of the directory it's relative to
+fn is_content_path(path: &str) -> bool {
+    !path.is_empty() && Path::new(path).components().all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// The path of `url` in the content served at `base_url`, if it's one of its files
+fn content_path(base_url: &AbsAssetUrl, url: &AbsAssetUrl) -> Option<String> {
+    let same_origin = url.0.scheme() == base_url.0.scheme()
+        && url.0.host_str() == base_url.0.host_str()
+        && url.0.port_or_known_default() == base_url.0.port_or_known_default();
+    if !same_origin || url.0.query().is_some() {
+        return None;
+    }
+    let path = url.path().as_str().strip_prefix(base_url.as_directory().path().as_str())?.to_string();
+    is_content_path(&path).then_some(path)
+}
+
+/// Serves the blobs it's given to the clients which request them, over the connections of the players of a server
+#[derive(Debug, Clone)]
+pub struct AssetStreamServer(Arc<ServerInner>);
+#[derive(Debug)]
+struct ServerInner {
+    /// The file of each blob, by its hash
+    blobs: Mutex<HashMap<String, PathBuf>>,
+    /// The content the clients load files from, if it's served
+    content: Mutex<Option<ServedContent>>,
+    /// How many bytes per second each client may be sent, if it's limited
+    budget: Option<u64>,
+    /// The transfers to each player, by their user id
+    peers: Mutex<HashMap<String, Peer>>,
+}
+
+#[derive(Debug)]
+struct ServedContent {
+    base_url: AbsAssetUrl,
+    dir: PathBuf,
+    /// The hash of each file resolved so far, and its modification time when it was hashed
+    hashes: HashMap<PathBuf, (SystemTime, String)>,
+}
+
+#[derive(Debug)]
+struct Peer {
+    /// The connection of the player the channel is on, which changes when they reconnect
+    connection_id: String,
+    channel: Arc<Channel>,
+    transfers: HashMap<String, Transfer>,
+}
+
+#[derive(Debug)]
+struct Transfer {
+    /// How many bytes of the blob the client has written
+    acked: watch::Sender<u64>,
+    task: JoinHandle<()>,
+}
+impl Drop for Transfer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl AssetStreamServer {
+    /// Serves no blobs yet. Each client may be sent at most `budget` bytes per second of blobs, if it's limited.
+    pub fn new(budget: Option<u64>) -> Self {
+        Self(Arc::new(ServerInner {
+            blobs: Mutex::new(HashMap::new()),
+            content: Mutex::new(None),
+            budget,
+            peers: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Serves the files in `dir`, which are at `base_url`, so that the clients load them over the game connection
+    /// instead of from `base_url`
+    pub fn serve_content(&self, base_url: AbsAssetUrl, dir: impl Into<PathBuf>) {
+        *self.0.content.lock() = Some(ServedContent { base_url, dir: dir.into(), hashes: HashMap::new() });
+    }
+
+    /// Serves the assets of `manifest`, which were built to `build_dir`
+    pub fn serve_manifest(&self, build_dir: &Path, manifest: &AssetManifest) {
+        let mut blobs = self.0.blobs.lock();
+        for entry in manifest.assets.values() {
+            if let AssetUrl::Relative(_) = &entry.url {
+                blobs.insert(entry.hash.clone(), build_dir.join(entry.url.path()));
+            }
+        }
+    }
+
+    /// Serves the file at `path`, and returns the hash the clients request it by
+    pub fn serve_file(&self, path: impl Into<PathBuf>) -> std::io::Result<String> {
+        let path = path.into();
+        let hash = sha256_digest_bytes(&std::fs::read(&path)?);
+        self.0.blobs.lock().insert(hash.clone(), path);
+        Ok(hash)
+    }
+
+    /// Offers the blob with `hash` to the player with `user_id`, whose client fetches it unless it has it already
+    pub fn push(&self, state: &ServerState, user_id: &str, hash: &str) -> anyhow::Result<()> {
+        anyhow::ensure!(self.0.blobs.lock().contains_key(hash), "No blob with the hash {hash} is served");
+        let mut peers = self.0.peers.lock();
+        let peer = self.peer(state, &mut peers, user_id).with_context(|| format!("The player {user_id} isn't connected"))?;
+        spawn_send(peer.channel.clone(), encode(&ServerMessage::Offer { hash: hash.to_string() }));
+        Ok(())
+    }
+
+    /// The transfers to the player with `user_id`, on their current connection
+    fn peer<'a>(&self, state: &ServerState, peers: &'a mut HashMap<String, Peer>, user_id: &str) -> Option<&'a mut Peer> {
+        // Forget the players who left
+        peers.retain(|user_id, _| state.players.contains_key(user_id));
+        let player = state.players.get(user_id)?;
+        let connection = player.connection.clone()?;
+        if peers.get(user_id).map(|peer| peer.connection_id != player.connection_id).unwrap_or(true) {
+            let channel = Arc::new(Channel::new(connection, channel_config(self.0.budget)));
+            peers.insert(user_id.to_string(), Peer { connection_id: player.connection_id.clone(), channel, transfers: HashMap::new() });
+        }
+        peers.get_mut(user_id)
+    }
+
+    /// The channel to the player with `user_id`, on their current connection
+    fn channel(&self, state: &SharedServerState, user_id: &str) -> Option<Arc<Channel>> {
+        let state = state.lock();
+        let mut peers = self.0.peers.lock();
+        self.peer(&state, &mut peers, user_id).map(|peer| peer.channel.clone())
+    }
+
+    /// The hash of the file at `path` in the content, which is served as a blob from now on
+    async fn resolve(&self, path: &str) -> anyhow::Result<String> {
+        anyhow::ensure!(is_content_path(path), "{path:?} isn't a path in the content");
+        let dir = self.0.content.lock().as_ref().map(|content| content.dir.clone()).context("The content isn't served")?;
+        let file = dir.join(path);
+        let modified = tokio::fs::metadata(&file).await.with_context(|| format!("Failed to read {file:?}"))?.modified()?;
+        let known =
+            self.0.content.lock().as_ref().and_then(|content| {
+                content.hashes.get(&file).filter(|(hashed_at, _)| *hashed_at == modified).map(|(_, hash)| hash.clone())
+            });
+        let hash = match known {
+            Some(hash) => hash,
+            None => {
+                let hash = sha256_digest_bytes(&tokio::fs::read(&file).await.with_context(|| format!("Failed to read {file:?}"))?);
+                if let Some(content) = &mut *self.0.content.lock() {
+                    content.hashes.insert(file.clone(), (modified, hash.clone()));
+                }
+                hash
+            }
+        };
+        self.0.blobs.lock().insert(hash.clone(), file);
+        Ok(hash)
+    }
+
+    fn receive(&self, state: SharedServerState, user_id: &str, data: Bytes) {
+        let message = match bincode::deserialize::<ClientMessage>(&data) {
+            Ok(message) => message,
+            Err(err) => {
+                log::warn!("Invalid message on the asset stream from {user_id}: {err}");
+                return;
+            }
+        };
+        match message {
+            ClientMessage::Describe => {
+                let base_url = self.0.content.lock().as_ref().map(|content| content.base_url.to_string());
+                if let Some(channel) = self.channel(&state, user_id) {
+                    spawn_send(channel, encode(&ServerMessage::Content { base_url }));
+                }
+            }
+            ClientMessage::Resolve { path } => {
+                let channel = match self.channel(&state, user_id) {
+                    Some(channel) => channel,
+                    None => return,
+                };
+                let server = self.clone();
+                tokio::spawn(async move {
+                    let hash = match server.resolve(&path).await {
+                        Ok(hash) => Some(hash),
+                        Err(err) => {
+                            log::warn!("Failed to resolve {path:?} in the content: {err:#}");
+                            None
+                        }
+                    };
+                    log_network_result!(channel.send(encode(&ServerMessage::Resolved { path, hash })).await);
+                });
+            }
+            ClientMessage::Request { hash, offset } => {
+                let path = self.0.blobs.lock().get(&hash).cloned();
+                let state = state.lock();
+                let mut peers = self.0.peers.lock();
+                let peer = match self.peer(&state, &mut peers, user_id) {
+                    Some(peer) => peer,
+                    None => return,
+                };
+                let path = match path {
+                    Some(path) => path,
+                    None => {
+                        spawn_send(peer.channel.clone(), encode(&ServerMessage::NotFound { hash }));
+                        return;
+                    }
+                };
+                let (acked, acked_rx) = watch::channel(offset);
+                let task = tokio::spawn({
+                    let (channel, hash) = (peer.channel.clone(), hash.clone());
+                    async move {
+                        if let Err(err) = send_blob(channel, path, hash.clone(), offset, acked_rx).await {
+                            log::warn!("Failed to stream the blob {hash}: {err:#}");
+                        }
+                    }
+                });
+                // Replaces the transfer of the same blob, if the client asks for it again
+                peer.transfers.insert(hash, Transfer { acked, task });
+            }
+            ClientMessage::Ack { hash, received } => {
+                let peers = self.0.peers.lock();
+                if let Some(transfer) = peers.get(user_id).and_then(|peer| peer.transfers.get(&hash)) {
+                    let newer = received > *transfer.acked.borrow();
+                    if newer {
+                        transfer.acked.send(received).ok();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Sends the blob `hash` at `path` from `offset` on, staying at most a [WINDOW] ahead of what the client acknowledged
+async fn send_blob(channel: Arc<Channel>, path: PathBuf, hash: String, offset: u64, mut acked: watch::Receiver<u64>) -> anyhow::Result<()> {
+    let mut file = tokio::fs::File::open(&path).await.with_context(|| format!("Failed to open {path:?}"))?;
+    let len = file.metadata().await?.len();
+    anyhow::ensure!(offset <= len, "The offset {offset} is past the end of the blob, of {len} bytes");
+    file.seek(SeekFrom::Start(offset)).await?;
+    let mut sent = offset;
+    let mut buf = vec![0; CHUNK_SIZE];
+    // An empty blob is sent as an empty chunk
+    loop {
+        while sent.saturating_sub(*acked.borrow()) >= WINDOW {
+            acked.changed().await.context("The transfer was replaced")?;
+        }
+        let read = file.read(&mut buf[..(len - sent).min(CHUNK_SIZE as u64) as usize]).await?;
+        anyhow::ensure!(read > 0 || sent == len, "{path:?} got shorter while it was streamed");
+        channel.send(encode(&ServerMessage::Chunk(Chunk { hash: hash.clone(), offset: sent, len, data: buf[..read].to_vec() }))).await?;
+        sent += read as u64;
+        if sent == len {
+            return Ok(());
+        }
+    }
+}
+
+fn spawn_send(channel: Arc<Channel>, data: Bytes) {
+    tokio::spawn(async move {
+        log_network_result!(channel.send(data).await);
+    });
+}
+
+/// Registers the handler of the requests of the clients for the blobs `server` serves
+pub fn register_handlers(bi_stream_handlers: &mut BiStreamHandlers, datagram_handlers: &mut DatagramHandlers, server: AssetStreamServer) {
+    register_channel(
+        bi_stream_handlers,
+        datagram_handlers,
+        ASSET_STREAM_CHANNEL_ID,
+        Arc::new(move |state, user_id, data| server.receive(state, user_id, data)),
+    );
+}
+
+/// Fetches the blobs of the server into a cache directory on the client
+#[derive(Debug, Clone)]
+pub struct AssetStreamClient(Arc<Mutex<ClientState>>);
+#[derive(Debug)]
+struct ClientState {
+    cache_dir: PathBuf,
+    /// The messages to the server, sent in order on the current connection
+    outgoing: Option<flume::Sender<ClientMessage>>,
+    /// The chunks received, which are written to the cache in order
+    chunks: Option<flume::Sender<Chunk>>,
+    /// The url the content of the server is at once the server told, which is None if it doesn't serve it
+    content_url: (watch::Sender<Option<Option<AbsAssetUrl>>>, watch::Receiver<Option<Option<AbsAssetUrl>>>),
+    /// The loads waiting for the hash of a file of the content, by its path
+    resolving: HashMap<String, Vec<oneshot::Sender<Option<String>>>>,
+    downloads: HashMap<String, Download>,
+}
+
+#[derive(Debug)]
+struct Download {
+    /// How many bytes of the blob are in its partial file
+    received: u64,
+    /// The digest of the bytes in the partial file, which is computed from the file again when it's None, e.g. when
+    /// resuming the blob from a previous session
+    digest: Option<Sha256Digest>,
+    waiters: Vec<oneshot::Sender<Result<PathBuf, String>>>,
+}
+
+impl AssetStreamClient {
+    /// Keeps the blobs in `cache_dir`
+    pub fn new(cache_dir: PathBuf) -> Self {
+        Self(Arc::new(Mutex::new(ClientState {
+            cache_dir,
+            outgoing: None,
+            chunks: None,
+            content_url: watch::channel(None),
+            resolving: HashMap::new(),
+            downloads: HashMap::new(),
+        })))
+    }
+
+    /// Sends the requests on `connection` from now on, and asks for the blobs being fetched again, from where they stopped.
+    /// The client calls this each time it connects to the server.
+    pub fn connect(&self, connection: Connection) {
+        let mut state = self.0.lock();
+        let (tx, rx) = flume::unbounded::<ClientMessage>();
+        let channel = Channel::new(connection, channel_config(None));
+        tokio::spawn(async move {
+            while let Ok(message) = rx.recv_async().await {
+                if let Err(err) = channel.send(encode(&message)).await {
+                    log::warn!("Failed to send on the asset stream: {err:?}");
+                    return;
+                }
+            }
+        });
+        // This may be another server, which serves its content elsewhere
+        state.content_url.0.send(None).ok();
+        tx.send(ClientMessage::Describe).ok();
+        for path in state.resolving.keys() {
+            tx.send(ClientMessage::Resolve { path: path.clone() }).ok();
+        }
+        for (hash, download) in &state.downloads {
+            tx.send(ClientMessage::Request { hash: hash.clone(), offset: download.received }).ok();
+        }
+        state.outgoing = Some(tx);
+        if state.chunks.is_none() {
+            let (tx, rx) = flume::unbounded();
+            tokio::spawn(write_chunks(Arc::downgrade(&self.0), rx));
+            state.chunks = Some(tx);
+        }
+    }
+
+    /// The path of the blob with `hash` in the cache, if it's been fetched already
+    pub fn cached(&self, hash: &str) -> Option<PathBuf> {
+        let path = self.blob_path(hash);
+        if is_hash(hash) && path.exists() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Fetches the blob with `hash` from the server, unless it's in the cache already, and returns its path in the cache
+    pub async fn fetch(&self, hash: &str) -> anyhow::Result<PathBuf> {
+        anyhow::ensure!(is_hash(hash), "{hash:?} isn't the sha256 of a blob");
+        if let Some(path) = self.cached(hash) {
+            return Ok(path);
+        }
+        let (tx, rx) = oneshot::channel();
+        self.start(hash, Some(tx));
+        rx.await.context("The asset stream was dropped")?.map_err(|err| anyhow::anyhow!(err))
+    }
+
+    /// The url the content of the server is at, if it serves it; which waits for the server to tell, for a while
+    async fn content_url(&self) -> Option<AbsAssetUrl> {
+        let mut content_url = self.0.lock().content_url.1.clone();
+        let told = async {
+            loop {
+                let url = content_url.borrow().clone();
+                if let Some(url) = url {
+                    return url;
+                }
+                if content_url.changed().await.is_err() {
+                    return None;
+                }
+            }
+        };
+        tokio::time::timeout(CONTENT_URL_TIMEOUT, told).await.ok().flatten()
+    }
+
+    /// Fetches the file at `path` in the content of the server, unless its blob is in the cache already, and returns the
+    /// path of the blob
+    async fn fetch_content(&self, path: &str) -> anyhow::Result<PathBuf> {
+        let (tx, rx) = oneshot::channel();
+        {
+            let state = &mut *self.0.lock();
+            let waiters = state.resolving.entry(path.to_string()).or_default();
+            if waiters.is_empty() {
+                if let Some(outgoing) = &state.outgoing {
+                    outgoing.send(ClientMessage::Resolve { path: path.to_string() }).ok();
+                }
+            }
+            waiters.push(tx);
+        }
+        let hash = rx.await.context("The asset stream was dropped")?.with_context(|| format!("The server has no file at {path}"))?;
+        self.fetch(&hash).await
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.0.lock().cache_dir.join("blobs").join(hash)
+    }
+    fn partial_path(&self, hash: &str) -> PathBuf {
+        self.0.lock().cache_dir.join("blobs").join(format!("{hash}.part"))
+    }
+
+    /// Asks for the blob with `hash`, unless it's being fetched already, resuming from its partial file if there's one
+    fn start(&self, hash: &str, waiter: Option<oneshot::Sender<Result<PathBuf, String>>>) {
+        let received = std::fs::metadata(self.partial_path(hash)).map(|metadata| metadata.len()).unwrap_or_default();
+        let mut state = self.0.lock();
+        if !state.downloads.contains_key(hash) {
+            if let Some(outgoing) = &state.outgoing {
+                outgoing.send(ClientMessage::Request { hash: hash.to_string(), offset: received }).ok();
+            }
+            state.downloads.insert(hash.to_string(), Download { received, digest: None, waiters: Vec::new() });
+        }
+        state.downloads.get_mut(hash).unwrap().waiters.extend(waiter);
+    }
+
+    fn finish(&self, hash: &str, res: Result<PathBuf, String>) {
+        let download = self.0.lock().downloads.remove(hash);
+        for waiter in download.into_iter().flat_map(|download| download.waiters) {
+            waiter.send(res.clone()).ok();
+        }
+    }
+
+    fn receive(&self, data: Bytes) {
+        let message = match bincode::deserialize::<ServerMessage>(&data) {
+            Ok(message) => message,
+            Err(err) => {
+                log::warn!("Invalid message on the asset stream: {err}");
+                return;
+            }
+        };
+        match message {
+            ServerMessage::Content { base_url } => {
+                let base_url = base_url.and_then(|base_url| AbsAssetUrl::parse(base_url).ok());
+                self.0.lock().content_url.0.send(Some(base_url)).ok();
+            }
+            ServerMessage::Resolved { path, hash } => {
+                let waiters = self.0.lock().resolving.remove(&path).unwrap_or_default();
+                for waiter in waiters {
+                    waiter.send(hash.clone()).ok();
+                }
+            }
+            ServerMessage::Offer { hash } => {
+                if is_hash(&hash) && self.cached(&hash).is_none() {
+                    self.start(&hash, None);
+                }
+            }
+            ServerMessage::Chunk(chunk) => {
+                if let Some(chunks) = &self.0.lock().chunks {
+                    chunks.send(chunk).ok();
+                }
+            }
+            ServerMessage::NotFound { hash } => self.finish(&hash, Err(format!("The server has no blob with the hash {hash}"))),
+        }
+    }
+
+    /// Appends `chunk` to the partial file of its blob, and moves the blob in place once it's complete
+    async fn write_chunk(&self, chunk: Chunk) -> anyhow::Result<()> {
+        let received = match self.0.lock().downloads.get(&chunk.hash) {
+            Some(download) => download.received,
+            None => return Ok(()),
+        };
+        let end = chunk.offset + chunk.data.len() as u64;
+        anyhow::ensure!(chunk.offset <= received, "Missed the bytes of the blob {} from {received} on", chunk.hash);
+        anyhow::ensure!(end <= chunk.len, "The blob {} is longer than its length", chunk.hash);
+        if end <= received && end < chunk.len {
+            // Sent again after a reconnection
+            return Ok(());
+        }
+
+        let partial_path = self.partial_path(&chunk.hash);
+        let digest = self.0.lock().downloads.get_mut(&chunk.hash).and_then(|download| download.digest.take());
+        let mut digest = match digest {
+            Some(digest) => digest,
+            None => digest_of_file(&partial_path, received).await?,
+        };
+        if let Some(dir) = partial_path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let data = &chunk.data[(received.min(end) - chunk.offset) as usize..];
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&partial_path).await?;
+        file.write_all(data).await?;
+        file.flush().await?;
+        drop(file);
+        digest.update(data);
+
+        if end < chunk.len {
+            let mut state = self.0.lock();
+            if let Some(download) = state.downloads.get_mut(&chunk.hash) {
+                download.received = end;
+                download.digest = Some(digest);
+            }
+            if let Some(outgoing) = &state.outgoing {
+                outgoing.send(ClientMessage::Ack { hash: chunk.hash, received: end }).ok();
+            }
+            return Ok(());
+        }
+        if digest.finish() != chunk.hash {
+            tokio::fs::remove_file(&partial_path).await.ok();
+            anyhow::bail!("The content of the blob {} doesn't match its hash", chunk.hash);
+        }
+        let path = self.blob_path(&chunk.hash);
+        tokio::fs::rename(&partial_path, &path).await?;
+        self.finish(&chunk.hash, Ok(path));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ContentSource for AssetStreamClient {
+    async fn fetch(&self, url: &AbsAssetUrl) -> Option<anyhow::Result<PathBuf>> {
+        let path = content_path(&self.content_url().await?, url)?;
+        Some(self.fetch_content(&path).await)
+    }
+}
+
+/// The digest of the first `len` bytes of the file at `path`
+async fn digest_of_file(path: &Path, len: u64) -> anyhow::Result<Sha256Digest> {
+    let mut digest = Sha256Digest::new();
+    if len == 0 {
+        return Ok(digest);
+    }
+    let mut file = tokio::fs::File::open(path).await.with_context(|| format!("Failed to open {path:?}"))?.take(len);
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(digest);
+        }
+        digest.update(&buf[..read]);
+    }
+}
+
+/// Writes the chunks received by the client to its cache, until the client is dropped
+async fn write_chunks(state: Weak<Mutex<ClientState>>, chunks: flume::Receiver<Chunk>) {
+    while let Ok(chunk) = chunks.recv_async().await {
+        let client = match state.upgrade() {
+            Some(state) => AssetStreamClient(state),
+            None => return,
+        };
+        let hash = chunk.hash.clone();
+        if let Err(err) = client.write_chunk(chunk).await {
+            log::warn!("Failed to fetch the blob {hash}: {err:#}");
+            client.finish(&hash, Err(format!("{err:#}")));
+        }
+    }
+}
+
+/// Registers the handler of the blobs the server streams to the client `world`, which `client` fetches, and makes the
+/// assets of the world load the content of the server through it. The client connects it to the server itself.
+pub fn register_client_handler(world: &mut World, client: AssetStreamClient) {
+    ContentSourceKey.insert(world.resource(asset_cache()), Arc::new(client.clone()));
+    world.add_resource(asset_stream_client(), client.clone());
+    register_client_channel(world, ASSET_STREAM_CHANNEL_ID, Arc::new(move |_world, data| client.receive(data)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(data: &[u8], hash: &str) -> Vec<Chunk> {
+        let len = data.len() as u64;
+        data.chunks(CHUNK_SIZE)
+            .enumerate()
+            .map(|(i, data)| Chunk { hash: hash.to_string(), offset: (i * CHUNK_SIZE) as u64, len, data: data.to_vec() })
+            .collect()
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ambient_asset_stream_{name}_{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    fn start(client: &AssetStreamClient, hash: &str) -> oneshot::Receiver<Result<PathBuf, String>> {
+        let (tx, rx) = oneshot::channel();
+        client.start(hash, Some(tx));
+        rx
+    }
+
+    #[test]
+    fn content_paths() {
+        let base_url = AbsAssetUrl::parse("http://example.com:8999/content/").unwrap();
+        let path = |url: &str| content_path(&base_url, &AbsAssetUrl::parse(url).unwrap());
+        assert_eq!(
+            path("http://example.com:8999/content/assets/knight.glb/meshes/0.bin").as_deref(),
+            Some("assets/knight.glb/meshes/0.bin")
+        );
+        assert_eq!(path("http://example.com:8999/content/my%20asset.png").as_deref(), Some("my asset.png"));
+        assert_eq!(path("http://example.com:8999/content/image.png?size=2"), None);
+        assert_eq!(path("http://example.com:8999/other/image.png"), None);
+        assert_eq!(path("http://example.com:9000/content/image.png"), None);
+        assert_eq!(path("https://example.com:8999/content/image.png"), None);
+        assert_eq!(path("http://example.com:8999/content/"), None);
+
+        assert!(is_content_path("assets/image.png"));
+        assert!(!is_content_path("../secret"));
+        assert!(!is_content_path("assets/../../secret"));
+        assert!(!is_content_path("/etc/passwd"));
+    }
+
+    #[tokio::test]
+    async fn writes_the_chunks_of_a_blob() {
+        let dir = cache_dir("writes");
+        let data = (0..CHUNK_SIZE * 2 + 100).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let hash = sha256_digest_bytes(&data);
+        let client = AssetStreamClient::new(dir.clone());
+        let rx = start(&client, &hash);
+
+        let chunks = chunks(&data, &hash);
+        client.write_chunk(chunks[0].clone()).await.unwrap();
+        // Sent again after a reconnection
+        client.write_chunk(chunks[0].clone()).await.unwrap();
+        // Later chunks can't come before the ones they follow
+        assert!(client.write_chunk(chunks[2].clone()).await.is_err());
+        client.write_chunk(chunks[1].clone()).await.unwrap();
+        client.write_chunk(chunks[2].clone()).await.unwrap();
+
+        let path = rx.await.unwrap().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        assert_eq!(client.cached(&hash), Some(path));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumes_a_blob_from_its_partial_file() {
+        let dir = cache_dir("resumes");
+        let data = (0..CHUNK_SIZE * 2 + 100).map(|i| (i % 13) as u8).collect::<Vec<_>>();
+        let hash = sha256_digest_bytes(&data);
+        let chunks = chunks(&data, &hash);
+
+        let client = AssetStreamClient::new(dir.clone());
+        let _rx = start(&client, &hash);
+        client.write_chunk(chunks[0].clone()).await.unwrap();
+        drop(client);
+
+        // The next session picks up from where the partial file stops
+        let client = AssetStreamClient::new(dir.clone());
+        let rx = start(&client, &hash);
+        assert_eq!(client.0.lock().downloads[&hash].received, CHUNK_SIZE as u64);
+        client.write_chunk(chunks[1].clone()).await.unwrap();
+        client.write_chunk(chunks[2].clone()).await.unwrap();
+        assert_eq!(std::fs::read(rx.await.unwrap().unwrap()).unwrap(), data);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_a_blob_which_does_not_match_its_hash() {
+        let dir = cache_dir("rejects");
+        let hash = sha256_digest_bytes(b"the real content");
+        let client = AssetStreamClient::new(dir.clone());
+        let _rx = start(&client, &hash);
+        assert!(client.write_chunk(chunks(b"other content", &hash)[0].clone()).await.is_err());
+        assert!(!client.partial_path(&hash).exists());
+        assert_eq!(client.cached(&hash), None);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn resolves_the_files_of_the_content() {
+        let dir = cache_dir("content");
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        std::fs::write(dir.join("assets/image.png"), b"image").unwrap();
+        let server = AssetStreamServer::new(None);
+        server.serve_content(AbsAssetUrl::parse("http://localhost:8999/content/").unwrap(), &dir);
+
+        let hash = server.resolve("assets/image.png").await.unwrap();
+        assert_eq!(hash, sha256_digest_bytes(b"image"));
+        assert_eq!(server.0.blobs.lock().get(&hash), Some(&dir.join("assets/image.png")));
+        assert!(server.resolve("assets/missing.png").await.is_err());
+        assert!(server.resolve("../image.png").await.is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let chunk = Chunk { hash: "a".repeat(64), offset: 3, len: 5, data: vec![1, 2] };
+        let decoded: ServerMessage = bincode::deserialize(&encode(&ServerMessage::Chunk(chunk))).unwrap();
+        assert!(matches!(decoded, ServerMessage::Chunk(Chunk { offset: 3, len: 5, ref data, .. }) if data == &[1, 2]));
+        let decoded: ClientMessage = bincode::deserialize(&encode(&ClientMessage::Resolve { path: "a.png".to_string() })).unwrap();
+        assert!(matches!(decoded, ClientMessage::Resolve { ref path } if path == "a.png"));
+    }
+}
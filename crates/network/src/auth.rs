@@ -0,0 +1,272 @@
+use std::{
+    collections::HashSet,
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use ambient_ecs::EntityData;
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKey},
+    signature::{PublicKey, Signature, SigningKey},
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::player::{user_display_name, user_roles};
+
+/// Verifies who the clients connecting to the server are.
+///
+/// Clients send the user id they claim to have, along with the ticket of their [AuthTicketProvider] if they have one.
+#[async_trait::async_trait]
+pub trait Authenticator: Debug + Send + Sync {
+    /// Returns who the client is known as on this server, or an error if it couldn't prove who it is, in which case the
+    /// connection is refused
+    async fn authenticate(&self, claimed_user_id: &str, ticket: Option<&[u8]>) -> anyhow::Result<Identity>;
+    /// Called when an authenticated user disconnects
+    fn end_session(&self, _user_id: &str) {}
+}
+
+/// Accepts any client under the user id it claims, which is the default
+#[derive(Debug)]
+pub struct TrustingAuthenticator;
+#[async_trait::async_trait]
+impl Authenticator for TrustingAuthenticator {
+    async fn authenticate(&self, claimed_user_id: &str, _ticket: Option<&[u8]>) -> anyhow::Result<Identity> {
+        Ok(Identity::new(claimed_user_id))
+    }
+}
+
+/// Who a client was authenticated as. The gameplay code sees it on the player entity, as [crate::player::user_id],
+/// [crate::player::user_display_name] and [crate::player::user_roles].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    pub user_id: String,
+    /// The name to show for the user, if it's not their user id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// What the user is, e.g. `admin` or `moderator`; the gameplay code decides what they may do
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+}
+impl Identity {
+    pub fn new(user_id: impl Into<String>) -> Self {
+        Self { user_id: user_id.into(), ..Default::default() }
+    }
+    /// The components of the player entity which show this identity to the gameplay code
+    pub fn player_data(&self) -> EntityData {
+        let data = EntityData::new().set(user_roles(), self.roles.clone());
+        match &self.display_name {
+            Some(display_name) => data.set(user_display_name(), display_name.clone()),
+            None => data,
+        }
+    }
+}
+
+/// What a signed [AuthToken] says about its bearer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenClaims {
+    #[serde(flatten)]
+    pub identity: Identity,
+    /// When the token stops being accepted, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// The servers the token is for, e.g. the id of a game; the servers which expect an audience refuse the tokens for
+    /// another one, so that a token can't be replayed on the servers of another game trusting the same issuer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audience: Option<String>,
+}
+impl TokenClaims {
+    /// Claims which expire `valid_for` from now
+    pub fn new(identity: Identity, valid_for: Option<Duration>) -> Self {
+        let expires_at =
+            valid_for.map(|valid_for| (SystemTime::now() + valid_for).duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs());
+        Self { identity, expires_at, audience: None }
+    }
+    pub fn with_audience(mut self, audience: impl Into<String>) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+}
+
+/// A token which proves who a client is, signed by an issuer the server trusts, such as the backend of the game.
+///
+/// It's sent as JSON, in which the claims are the JSON string they were signed as; an issuer in another language signs
+/// the UTF-8 bytes of that string with its ed25519 key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthToken {
+    /// The JSON of the [TokenClaims]
+    pub claims: String,
+    pub signature: Signature,
+}
+impl AuthToken {
+    pub fn issue(claims: &TokenClaims, key: &SigningKey) -> Self {
+        let claims = serde_json::to_string(claims).unwrap();
+        Self { signature: key.sign(claims.as_bytes()), claims }
+    }
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        serde_json::from_slice(data).context("Invalid auth token")
+    }
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+    /// The claims of the token, without checking who signed it; a client reads who it will join as with it
+    pub fn unverified_claims(&self) -> anyhow::Result<TokenClaims> {
+        serde_json::from_str(&self.claims).context("Invalid auth token claims")
+    }
+    /// Returns the claims of the token if it was signed by one of the `trusted_keys`, hasn't expired at `now`, and is
+    /// for the `audience` if one is expected
+    pub fn verify(&self, trusted_keys: &[PublicKey], audience: Option<&str>, now: SystemTime) -> anyhow::Result<TokenClaims> {
+        self.signature.verify(self.claims.as_bytes(), trusted_keys).context("Invalid auth token")?;
+        let claims = self.unverified_claims()?;
+        if let Some(expires_at) = claims.expires_at {
+            anyhow::ensure!(now <= SystemTime::UNIX_EPOCH + Duration::from_secs(expires_at), "The auth token has expired");
+        }
+        if let Some(audience) = audience {
+            anyhow::ensure!(claims.audience.as_deref() == Some(audience), "The auth token is not for this server");
+        }
+        Ok(claims)
+    }
+}
+/// A client with a token sends it as its ticket
+impl AuthTicketProvider for AuthToken {
+    fn ticket(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.to_json().into_bytes())
+    }
+}
+
+/// Only accepts the clients with an [AuthToken] signed by one of its trusted keys, under the identity of the token
+#[derive(Debug)]
+pub struct TokenAuthenticator {
+    pub trusted_keys: Vec<PublicKey>,
+    /// If set, only the tokens for this audience are accepted
+    pub audience: Option<String>,
+}
+#[async_trait::async_trait]
+impl Authenticator for TokenAuthenticator {
+    async fn authenticate(&self, _claimed_user_id: &str, ticket: Option<&[u8]>) -> anyhow::Result<Identity> {
+        let token = AuthToken::parse(ticket.context("No auth token")?)?;
+        Ok(token.verify(&self.trusted_keys, self.audience.as_deref(), SystemTime::now())?.identity)
+    }
+}
+
+#[derive(Debug)]
+pub struct ServerAuthenticatorKey;
+impl SyncAssetKey<Arc<dyn Authenticator>> for ServerAuthenticatorKey {
+    fn load(&self, _assets: AssetCache) -> Arc<dyn Authenticator> {
+        Arc::new(TrustingAuthenticator)
+    }
+}
+
+/// Creates the tickets a client proves who it is with, when connecting to a server
+pub trait AuthTicketProvider: Debug + Send + Sync {
+    fn ticket(&self) -> anyhow::Result<Vec<u8>>;
+}
+
+/// If set, the client sends a ticket from this provider when connecting
+#[derive(Debug)]
+pub struct ClientAuthTicketKey;
+impl SyncAssetKey<Arc<dyn AuthTicketProvider>> for ClientAuthTicketKey {}
+
+/// Whether a client may join the server, as decided by an [Authorizer]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Authorization {
+    Accept,
+    /// Refuses the client, which is shown the reason
+    Reject(String),
+}
+
+/// Decides whether the clients may join once they're authenticated, e.g. against a ban list or the number of players
+#[async_trait::async_trait]
+pub trait Authorizer: Debug + Send + Sync {
+    async fn authorize(&self, identity: &Identity) -> Authorization;
+}
+
+/// If set, the server asks this authorizer before letting each client in
+#[derive(Debug)]
+pub struct ServerAuthorizerKey;
+impl SyncAssetKey<Arc<dyn Authorizer>> for ServerAuthorizerKey {}
+
+/// Lets in the users by their user id: none of the `banned` ones, and if there are `allowed` ones, only them
+#[derive(Debug, Clone, Default)]
+pub struct UserListAuthorizer {
+    pub allowed: HashSet<String>,
+    pub banned: HashSet<String>,
+}
+#[async_trait::async_trait]
+impl Authorizer for UserListAuthorizer {
+    async fn authorize(&self, identity: &Identity) -> Authorization {
+        if self.banned.contains(&identity.user_id) {
+            Authorization::Reject("You are banned from this server".to_string())
+        } else if !self.allowed.is_empty() && !self.allowed.contains(&identity.user_id) {
+            Authorization::Reject("You are not on the list of players of this server".to_string())
+        } else {
+            Authorization::Accept
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signing_key(name: &str) -> SigningKey {
+        let path = std::env::temp_dir().join(format!("ambient_auth_test_{name}_{}.key", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let key = SigningKey::load_or_generate(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        key
+    }
+
+    #[test]
+    fn verify() {
+        let key = signing_key("verify");
+        let other = signing_key("verify_other");
+        let identity = Identity { user_id: "bob".to_string(), display_name: Some("Bob".to_string()), roles: vec!["admin".to_string()] };
+        let token = AuthToken::issue(&TokenClaims::new(identity.clone(), None), &key);
+        let token = AuthToken::parse(token.to_json().as_bytes()).unwrap();
+
+        assert_eq!(token.verify(&[other.public_key(), key.public_key()], None, SystemTime::now()).unwrap().identity, identity);
+        assert!(token.verify(&[other.public_key()], None, SystemTime::now()).is_err());
+
+        let mut forged = token.clone();
+        forged.claims = forged.claims.replace("bob", "eve");
+        assert!(forged.verify(&[key.public_key()], None, SystemTime::now()).is_err());
+    }
+
+    #[test]
+    fn expiry() {
+        let key = signing_key("expiry");
+        let claims = TokenClaims::new(Identity::new("bob"), Some(Duration::from_secs(60)));
+        let token = AuthToken::issue(&claims, &key);
+        let now = SystemTime::now();
+        assert!(token.verify(&[key.public_key()], None, now).is_ok());
+        assert!(token.verify(&[key.public_key()], None, now + Duration::from_secs(30)).is_ok());
+        assert!(token.verify(&[key.public_key()], None, now + Duration::from_secs(3600)).is_err());
+    }
+
+    #[test]
+    fn audience() {
+        let key = signing_key("audience");
+        let token = AuthToken::issue(&TokenClaims::new(Identity::new("bob"), None).with_audience("game"), &key);
+        assert!(token.verify(&[key.public_key()], Some("game"), SystemTime::now()).is_ok());
+        assert!(token.verify(&[key.public_key()], Some("other_game"), SystemTime::now()).is_err());
+        // A server which doesn't expect an audience accepts it
+        assert!(token.verify(&[key.public_key()], None, SystemTime::now()).is_ok());
+
+        let token = AuthToken::issue(&TokenClaims::new(Identity::new("bob"), None), &key);
+        assert!(token.verify(&[key.public_key()], Some("game"), SystemTime::now()).is_err());
+    }
+
+    #[tokio::test]
+    async fn user_list() {
+        let authorizer = UserListAuthorizer { banned: ["eve".to_string()].into(), ..Default::default() };
+        assert_eq!(authorizer.authorize(&Identity::new("bob")).await, Authorization::Accept);
+        assert_ne!(authorizer.authorize(&Identity::new("eve")).await, Authorization::Accept);
+
+        let authorizer = UserListAuthorizer { allowed: ["bob".to_string()].into(), banned: ["eve".to_string()].into() };
+        assert_eq!(authorizer.authorize(&Identity::new("bob")).await, Authorization::Accept);
+        assert_ne!(authorizer.authorize(&Identity::new("alice")).await, Authorization::Accept);
+        assert_ne!(authorizer.authorize(&Identity::new("eve")).await, Authorization::Accept);
+    }
+}
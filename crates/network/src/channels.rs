@@ -0,0 +1,325 @@
+//! Logical channels on a connection, each with its own reliability, ordering, priority and bandwidth budget, so that e.g.
+//! the chat, the replication of the state and the transfer of the assets don't hold each other up.
+//!
+//! A [Channel] sends the messages of its [ChannelConfig] on a [Connection]: the unreliable ones in datagrams, the reliable
+//! ones on a stream of their own, and the ordered ones on the one stream of the channel, so a lost packet only holds up
+//! the messages which have to come after it. The server receives the messages of the clients with the handlers
+//! registered with [register_channel], and the clients the ones of the server with the handlers registered with
+//! [register_client_channel].
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ambient_ecs::{components, Resource, World};
+use anyhow::Context;
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{
+    log_network_result,
+    server::SharedServerState,
+    transport::{Connection, RecvStream, SendStream},
+    AsyncMutex, BiStreamHandlers, DatagramHandlers, IncomingStream, NetworkError, OutgoingStream,
+};
+
+components!("network", {
+    /// The handlers of the channels of the server, on the client
+    @[Resource]
+    client_channel_handlers: ClientChannelHandlers,
+});
+
+/// Handles a message received on a channel by the server, from the player with the user id
+pub type ChannelHandler = Arc<dyn Fn(SharedServerState, &String, Bytes) + Sync + Send>;
+/// Handles a message received on a channel by the client
+pub type ClientChannelHandler = Arc<dyn Fn(&mut World, Bytes) + Sync + Send>;
+pub type ClientChannelHandlers = HashMap<u32, ClientChannelHandler>;
+
+/// What a channel guarantees about the delivery of its messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reliability {
+    /// The messages may be lost, or arrive out of order, and must fit in a datagram. Over a WebSocket, they're delivered
+    /// reliably anyway.
+    Unreliable,
+    /// The messages are all delivered, in any order: a lost packet only holds up the message it belongs to
+    Reliable,
+    /// The messages are all delivered, in the order they were sent
+    ReliableOrdered,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelConfig {
+    /// Tells the channels apart on the connection. It's the id of both the stream handler and the datagram handler of
+    /// the channel, so it must not be the one of another handler, such as [crate::RPC_STREAM_ID] or
+    /// [crate::messages::MESSAGE_STREAM_ID].
+    pub id: u32,
+    pub reliability: Reliability,
+    /// The streams of the channels with a higher priority are sent first, when the connection can't send everything at
+    /// once. It doesn't apply to the datagrams, nor over a WebSocket.
+    pub priority: i32,
+    /// How many bytes per second the channel may send, if it's limited: the unreliable messages over the budget are
+    /// dropped, and the reliable ones wait for it
+    pub budget: Option<u64>,
+}
+impl ChannelConfig {
+    pub fn new(id: u32, reliability: Reliability) -> Self {
+        Self { id, reliability, priority: 0, budget: None }
+    }
+}
+
+/// The sending end of a channel on a connection
+#[derive(Debug)]
+pub struct Channel {
+    config: ChannelConfig,
+    connection: Connection,
+    budget: Mutex<Budget>,
+    /// The stream of an ordered channel, opened with the first message
+    stream: AsyncMutex<Option<OutgoingStream>>,
+}
+impl Channel {
+    pub fn new(connection: Connection, config: ChannelConfig) -> Self {
+        Self { budget: Mutex::new(Budget::new(config.budget, Instant::now())), config, connection, stream: AsyncMutex::new(None) }
+    }
+    pub fn config(&self) -> &ChannelConfig {
+        &self.config
+    }
+
+    /// Sends `data` to the peer, as the channel is configured. An unreliable message over the budget is dropped.
+    pub async fn send(&self, data: Bytes) -> Result<(), NetworkError> {
+        match self.config.reliability {
+            Reliability::Unreliable => {
+                if !self.budget.lock().try_take(data.len() as u64, Instant::now()) {
+                    return Ok(());
+                }
+                let mut datagram = BytesMut::with_capacity(4 + data.len());
+                datagram.put_u32(self.config.id);
+                datagram.put(data);
+                self.connection.send_datagram(datagram.freeze())
+            }
+            Reliability::Reliable => {
+                self.wait_for_budget(data.len()).await;
+                let mut stream = OutgoingStream::new(self.open_stream().await?);
+                stream.stream.send(data).await?;
+                stream.stream.get_mut().finish().await
+            }
+            Reliability::ReliableOrdered => {
+                self.wait_for_budget(data.len()).await;
+                let mut stream = self.stream.lock().await;
+                if stream.is_none() {
+                    *stream = Some(OutgoingStream::new(self.open_stream().await?));
+                }
+                let res = stream.as_mut().unwrap().stream.send(data).await;
+                if res.is_err() {
+                    // The next message opens a new stream
+                    *stream = None;
+                }
+                Ok(res?)
+            }
+        }
+    }
+
+    async fn wait_for_budget(&self, len: usize) {
+        let wait = self.budget.lock().take(len as u64, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    async fn open_stream(&self) -> Result<SendStream, NetworkError> {
+        let (mut send, _recv) = self.connection.open_bi().await?;
+        send.set_priority(self.config.priority);
+        send.write_u32(self.config.id).await?;
+        Ok(send)
+    }
+}
+
+/// A token bucket, which holds up to a second of the budget
+#[derive(Debug)]
+pub struct Budget {
+    /// The bytes per second, if the budget is limited
+    rate: Option<u64>,
+    /// The bytes which may be sent now, negative when the channel has sent more than its budget
+    available: f64,
+    refilled: Instant,
+}
+impl Budget {
+    /// A budget of `rate` bytes per second, if it's limited
+    pub fn new(rate: Option<u64>, now: Instant) -> Self {
+        Self { rate, available: rate.unwrap_or_default() as f64, refilled: now }
+    }
+    fn refill(&mut self, now: Instant) {
+        if let Some(rate) = self.rate {
+            let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+            self.available = (self.available + elapsed * rate as f64).min(rate as f64);
+        }
+        self.refilled = now;
+    }
+    /// Takes `len` bytes from the budget if there are enough
+    pub fn try_take(&mut self, len: u64, now: Instant) -> bool {
+        if self.rate.is_none() {
+            return true;
+        }
+        self.refill(now);
+        if self.available < len as f64 {
+            return false;
+        }
+        self.available -= len as f64;
+        true
+    }
+    /// Takes `len` bytes from the budget, and returns how long to wait for before sending them so that the rate holds
+    fn take(&mut self, len: u64, now: Instant) -> Duration {
+        let rate = match self.rate {
+            Some(rate) => rate,
+            None => return Duration::ZERO,
+        };
+        self.refill(now);
+        self.available -= len as f64;
+        if self.available >= 0. {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.available / rate.max(1) as f64)
+        }
+    }
+}
+
+/// Registers the handler of the messages which the clients send on the channel `id` to the server
+pub fn register_channel(
+    bi_stream_handlers: &mut BiStreamHandlers,
+    datagram_handlers: &mut DatagramHandlers,
+    id: u32,
+    handler: ChannelHandler,
+) {
+    assert!(
+        !bi_stream_handlers.contains_key(&id) && !datagram_handlers.contains_key(&id),
+        "The id {id} of the channel is already the one of another handler"
+    );
+    bi_stream_handlers.insert(id, {
+        let handler = handler.clone();
+        Arc::new(move |state, _assets, user_id, _send, recv| {
+            let handler = handler.clone();
+            let user_id = user_id.to_string();
+            tokio::spawn(async move {
+                let res = read_frames(recv, |data| handler(state.clone(), &user_id, data)).await;
+                log_network_result!(res);
+            });
+        })
+    });
+    datagram_handlers.insert(id, Arc::new(move |state, _assets, user_id, data| handler(state, user_id, data)));
+}
+
+/// Registers the handler of the messages which the server sends on the channel `id` to the client `world`
+pub fn register_client_channel(world: &mut World, id: u32, handler: ClientChannelHandler) {
+    match world.resource_mut_opt(client_channel_handlers()) {
+        Some(handlers) => {
+            handlers.insert(id, handler);
+        }
+        None => world.add_resource(client_channel_handlers(), HashMap::from([(id, handler)])),
+    }
+}
+
+/// Hands a message received on the channel `id` by the client to its handler
+pub(crate) fn receive_on_client(world: &mut World, id: u32, data: Bytes) -> anyhow::Result<()> {
+    let handler = world
+        .resource_opt(client_channel_handlers())
+        .and_then(|handlers| handlers.get(&id))
+        .cloned()
+        .with_context(|| format!("No handler for the channel {id}"))?;
+    handler(world, data);
+    Ok(())
+}
+
+/// Reads the messages of a stream opened by the server, sending them with the id of their channel to `tx`
+pub(crate) async fn forward_stream(mut recv: RecvStream, tx: flume::Sender<(u32, Bytes)>) -> Result<(), NetworkError> {
+    let id = recv.read_u32().await?;
+    read_frames(recv, |data| {
+        tx.send((id, data)).ok();
+    })
+    .await
+}
+
+/// The id of the channel of `datagram`, and the message it carries
+pub(crate) fn payload_of_datagram(datagram: &Bytes) -> Option<(u32, Bytes)> {
+    if datagram.len() < 4 {
+        return None;
+    }
+    Some((u32::from_be_bytes(datagram[0..4].try_into().unwrap()), datagram.slice(4..)))
+}
+
+/// Reads the messages of a stream of a channel, once its id has been read
+async fn read_frames(recv: RecvStream, mut on_message: impl FnMut(Bytes)) -> Result<(), NetworkError> {
+    let mut stream = IncomingStream::new(recv).stream;
+    while let Some(frame) = stream.next().await {
+        on_message(frame?.freeze());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_budget() {
+        let now = Instant::now();
+        let mut budget = Budget::new(None, now);
+        assert!(budget.try_take(u64::MAX, now));
+        assert_eq!(budget.take(1 << 30, now), Duration::ZERO);
+    }
+
+    #[test]
+    fn budget_refills_up_to_a_second() {
+        let start = Instant::now();
+        let mut budget = Budget::new(Some(1000), start);
+        assert!(budget.try_take(600, start));
+        assert!(!budget.try_take(600, start));
+        assert!(budget.try_take(400, start));
+        // Half a second refills half of the rate
+        assert!(!budget.try_take(600, start + Duration::from_millis(500)));
+        assert!(budget.try_take(500, start + Duration::from_millis(500)));
+        // A long pause only refills a second of it
+        let later = start + Duration::from_secs(10);
+        assert!(!budget.try_take(1001, later));
+        assert!(budget.try_take(1000, later));
+    }
+
+    #[test]
+    fn reliable_messages_wait_for_the_budget() {
+        let start = Instant::now();
+        let mut budget = Budget::new(Some(1000), start);
+        assert_eq!(budget.take(1000, start), Duration::ZERO);
+        assert_eq!(budget.take(500, start), Duration::from_millis(500));
+        // The debt is paid back before the next message can go
+        assert_eq!(budget.take(500, start + Duration::from_millis(500)), Duration::from_millis(500));
+        assert!(!budget.try_take(1, start + Duration::from_millis(999)));
+    }
+
+    #[test]
+    fn datagram_payloads() {
+        let mut datagram = BytesMut::new();
+        datagram.put_u32(12);
+        datagram.put(&b"hello"[..]);
+        assert_eq!(payload_of_datagram(&datagram.freeze()), Some((12, Bytes::from_static(b"hello"))));
+        assert_eq!(payload_of_datagram(&Bytes::from_static(&[0, 0, 1])), None);
+    }
+
+    #[test]
+    fn client_handlers() {
+        ambient_core::init_all_components();
+        crate::init_all_components();
+        let mut world = World::new("client_handlers");
+        assert!(receive_on_client(&mut world, 1, Bytes::new()).is_err());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        for id in [1, 2] {
+            let received = received.clone();
+            register_client_channel(&mut world, id, Arc::new(move |_, data| received.lock().push((id, data))));
+        }
+        receive_on_client(&mut world, 2, Bytes::from_static(b"b")).unwrap();
+        receive_on_client(&mut world, 1, Bytes::from_static(b"a")).unwrap();
+        assert!(receive_on_client(&mut world, 3, Bytes::new()).is_err());
+        assert_eq!(*received.lock(), vec![(2, Bytes::from_static(b"b")), (1, Bytes::from_static(b"a"))]);
+    }
+}
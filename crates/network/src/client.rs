@@ -1,4 +1,6 @@
 use std::{
+    any::type_name,
+    collections::HashSet,
     fmt::{Debug, Display},
     net::SocketAddr,
     sync::Arc,
@@ -6,28 +8,40 @@ use std::{
 };
 
 use ambient_core::{asset_cache, gpu, mirror_window_components, runtime};
-use ambient_ecs::{components, query, EntityData, EntityId, Resource, SystemGroup, World, WorldDiff};
+use ambient_ecs::{components, query, EntityData, EntityId, Resource, SerializationProfile, SystemGroup, World, WorldChange, WorldDiff};
 use ambient_element::{Element, ElementComponent, ElementComponentExt, Hooks};
-use ambient_renderer::RenderTarget;
+use ambient_renderer::{render_scale, scaled_target_size, RenderTarget};
 use ambient_rpc::RpcRegistry;
-use ambient_std::{cb, fps_counter::FpsSample, log_result, to_byte_unit, CallbackFn, Cb};
+use ambient_std::{asset_cache::SyncAssetKeyExt, cb, fps_counter::FpsSample, log_result, CallbackFn, Cb};
+use ambient_sys::time::Instant;
 use ambient_ui::{Button, Centered, FlowColumn, FlowRow, Image, Text, Throbber};
-use anyhow::Context;
-use futures::{io::BufReader, AsyncBufReadExt, AsyncReadExt, Future, StreamExt};
+use anyhow::{bail, Context};
+use bytes::Bytes;
+use futures::{Future, StreamExt};
 use glam::UVec2;
+use itertools::Itertools;
 use parking_lot::Mutex;
-use quinn::{Connection, NewConnection};
 use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
 
 use crate::{
-    client_game_state::ClientGameState,
+    asset_stream::asset_stream_client,
+    auth::{AuthTicketProvider, ClientAuthTicketKey},
+    channels,
+    client_game_state::{game_screen_render_target, ClientGameState},
+    conditioner::{network_conditioner, ConditionedProxy, NetworkConditioner, NetworkConditionsPath},
     create_client_endpoint_random_port,
     events::event_registry,
-    is_remote_entity, log_network_result, player,
+    interpolation, is_remote_entity, log_network_result,
+    messages::{Message, MessageStream, MessageType},
+    player,
     protocol::{ClientInfo, ClientProtocol},
+    relay::{self, RelayAddressKey},
     rpc_request,
-    server::SharedServerState,
-    user_id, NetworkError,
+    server::{SharedServerState, DEFAULT_RECONNECT_GRACE},
+    stats::{network_stats, NetworkStats, Probe, ProbeReceiver, ProbeSender, StatsTracker, PROBE_INTERVAL, STATS_INTERVAL},
+    transport::{Connection, NewConnection},
+    user_id, websocket, NetworkError,
 };
 
 components!("network", {
@@ -57,6 +71,7 @@ pub struct GameClient {
     pub rpc_registry: Arc<RpcRegistry<GameRpcArgs>>,
     pub user_id: String,
     pub game_state: Arc<Mutex<ClientGameState>>,
+    messages: MessageStream,
 }
 
 impl GameClient {
@@ -66,7 +81,7 @@ impl GameClient {
         game_state: Arc<Mutex<ClientGameState>>,
         user_id: String,
     ) -> Self {
-        Self { connection, rpc_registry, user_id, game_state }
+        Self { connection, rpc_registry, user_id, game_state, messages: Default::default() }
     }
 
     const SIZE_LIMIT: usize = 100_000_000;
@@ -104,6 +119,11 @@ impl GameClient {
         })
     }
 
+    /// Sends a message of `message_type` to the server, where it becomes a [ambient_ecs::WorldEvent]
+    pub async fn send_message(&self, message_type: &MessageType, data: EntityData) -> Result<(), NetworkError> {
+        self.messages.send(&self.connection, &Message::new(message_type, data), message_type.reliable).await
+    }
+
     pub fn with_physics_world<R>(&self, f: impl Fn(&mut World) -> R) -> R {
         f(&mut self.game_state.lock().world)
     }
@@ -184,14 +204,25 @@ impl ElementComponent for GameClientView {
 
         let gpu = hooks.world.resource(gpu()).clone();
 
-        let render_target = hooks.use_memo_with(resolution, |_, &resolution| Arc::new(RenderTarget::new(gpu.clone(), resolution, None)));
+        // The game is rendered at its render scale and stretched over the view, while the UI on top of it is rendered at the
+        // full resolution of the window
+        let (scale, set_scale) = hooks.use_state(1.);
+        let render_target = hooks.use_memo_with((resolution, scale), |_, &(resolution, scale)| {
+            Arc::new(RenderTarget::new(gpu.clone(), scaled_target_size(resolution, scale), None))
+        });
 
         let (connection_status, set_connection_status) = hooks.use_state("Connecting".to_string());
 
         let assets = hooks.world.resource(asset_cache()).clone();
+        let auth_ticket_provider = ClientAuthTicketKey.try_get(&assets);
+        let relay_addr = RelayAddressKey.try_get(&assets);
+        let conditioner = NetworkConditionsPath.try_get(&assets).map(NetworkConditioner::from_file);
         let game_state = hooks.use_ref_with(|world| {
             let (systems, resources) = systems_and_resources();
             let mut state = ClientGameState::new(world, assets.clone(), user_id.clone(), render_target.clone(), systems, resources);
+            if let Some(conditioner) = &conditioner {
+                state.world.add_resource(network_conditioner(), conditioner.clone());
+            }
 
             (init_world.take().expect("Init called twice"))(&mut state.world, render_target.clone());
 
@@ -209,6 +240,14 @@ impl ElementComponent for GameClientView {
 
                 mirror_window_components(app_world, &mut game_state.world);
 
+                let game_scale = game_state.world.resource_opt(render_scale()).copied().unwrap_or(1.);
+                if game_scale != scale {
+                    set_scale(game_scale);
+                }
+                if !Arc::ptr_eq(game_state.world.resource(game_screen_render_target()), &render_target) {
+                    *game_state.world.resource_mut(game_screen_render_target()) = render_target.clone();
+                }
+
                 game_state.on_frame(&render_target);
             });
         }
@@ -233,12 +272,54 @@ impl ElementComponent for GameClientView {
                         }
                     };
 
-                    let mut on_init = {
+                    let mut on_channel_message = {
                         let game_state = game_state.clone();
-                        move |conn, info: ClientInfo| {
-                            let game_client = GameClient::new(conn, Arc::new(create_rpc_registry()), game_state.clone(), info.user_id);
+                        move |id, data| {
+                            let mut gs = game_state.lock();
+                            log_result!(channels::receive_on_client(&mut gs.world, id, data));
+                        }
+                    };
 
-                            game_state.lock().world.add_resource(self::game_client(), Some(game_client.clone()));
+                    let mut on_resume = {
+                        let game_state = game_state.clone();
+                        let create_rpc_registry = create_rpc_registry.clone();
+                        let on_in_entities = on_in_entities.clone();
+                        let set_game_client = set_game_client.clone();
+                        move |conn: Connection, info: ClientInfo, snapshot, server_time| {
+                            let game_client =
+                                GameClient::new(conn.clone(), Arc::new(create_rpc_registry()), game_state.clone(), info.user_id);
+
+                            let mut gs = game_state.lock();
+                            // The blobs being fetched resume on the new connection
+                            if let Some(asset_stream) = gs.world.resource_opt(asset_stream_client()) {
+                                asset_stream.connect(conn);
+                            }
+                            let diff = resync_diff(&gs.world, snapshot);
+                            if let Some(on_in_entities) = &on_in_entities {
+                                on_in_entities(&diff);
+                            }
+                            let moved = interpolation::before_server_diff(&mut gs.world, &diff);
+                            diff.apply(&mut gs.world, EntityData::new().set(is_remote_entity(), ()), false);
+                            interpolation::after_server_diff(&mut gs.world, moved, server_time);
+                            gs.world.add_resource(self::game_client(), Some(game_client.clone()));
+                            drop(gs);
+
+                            set_game_client(Some(game_client));
+                        }
+                    };
+
+                    let mut on_init = {
+                        let game_state = game_state.clone();
+                        move |conn: Connection, info: ClientInfo| {
+                            let game_client =
+                                GameClient::new(conn.clone(), Arc::new(create_rpc_registry()), game_state.clone(), info.user_id);
+
+                            let mut gs = game_state.lock();
+                            gs.world.add_resource(self::game_client(), Some(game_client.clone()));
+                            if let Some(asset_stream) = gs.world.resource_opt(asset_stream_client()) {
+                                asset_stream.connect(conn);
+                            }
+                            drop(gs);
 
                             // Update parent client
                             set_game_client(Some(game_client.clone()));
@@ -246,19 +327,27 @@ impl ElementComponent for GameClientView {
                         }
                     };
 
-                    let mut on_diff = |diff| {
+                    let mut on_diff = |diff, server_time| {
                         if let Some(on_in_entities) = &on_in_entities {
                             on_in_entities(&diff);
                         }
                         let mut gs = game_state.lock();
+                        let moved = interpolation::before_server_diff(&mut gs.world, &diff);
                         diff.apply(&mut gs.world, EntityData::new().set(is_remote_entity(), ()), false);
+                        interpolation::after_server_diff(&mut gs.world, moved, server_time);
                     };
 
                     let mut on_server_stats = |stats| {
                         server_stats_ctx(stats);
                     };
 
-                    let mut on_client_stats = |stats| {
+                    let mut on_client_stats = |stats: GameClientNetworkStats| {
+                        let mut gs = game_state.lock();
+                        match gs.world.resource_mut_opt(network_stats()) {
+                            Some(current) => *current = stats.0.clone(),
+                            None => gs.world.add_resource(network_stats(), stats.0.clone()),
+                        }
+                        drop(gs);
                         client_stats_ctx(stats);
                     };
 
@@ -266,11 +355,17 @@ impl ElementComponent for GameClientView {
                         set_connection_status,
                         server_addr,
                         user_id,
+                        auth_ticket_provider,
+                        relay_addr,
+                        conditioner,
+                        proxy: None,
                         on_init: &mut on_init,
+                        on_resume: &mut on_resume,
                         on_diff: &mut on_diff,
                         on_server_stats: &mut on_server_stats,
                         on_client_stats: &mut on_client_stats,
                         on_event: &mut on_event,
+                        on_channel_message: &mut on_channel_message,
                         on_disconnect,
                         init_destructor: None,
                     };
@@ -328,14 +423,25 @@ struct ClientInstance<'a> {
     set_connection_status: CallbackFn<String>,
     server_addr: SocketAddr,
     user_id: String,
+    auth_ticket_provider: Option<Arc<dyn AuthTicketProvider>>,
+    relay_addr: Option<SocketAddr>,
+    /// Conditions the connection to the server, through the [ConditionedProxy] of the current connection
+    conditioner: Option<NetworkConditioner>,
+    proxy: Option<ConditionedProxy>,
 
     /// Called when the client connected and received the world.
     on_init: &'a mut (dyn FnMut(Connection, ClientInfo) -> anyhow::Result<Box<dyn FnOnce() + Sync + Send>> + Send + Sync),
-    on_diff: &'a mut (dyn FnMut(WorldDiff) + Send + Sync),
+    /// Called when the client reconnected after losing its connection, with the snapshot of the world the server sent, and
+    /// the time of the server when it sent it
+    on_resume: &'a mut (dyn FnMut(Connection, ClientInfo, WorldDiff, Duration) + Send + Sync),
+    /// Called with the diffs the server sends, and the time of the server when it sent them
+    on_diff: &'a mut (dyn FnMut(WorldDiff, Duration) + Send + Sync),
 
     on_server_stats: &'a mut (dyn FnMut(GameClientServerStats) + Send + Sync),
     on_client_stats: &'a mut (dyn FnMut(GameClientNetworkStats) + Send + Sync),
     on_event: &'a mut (dyn FnMut(String, Box<[u8]>) + Send + Sync),
+    /// Called with the messages the server sends on its channels, and the id of their channel
+    on_channel_message: &'a mut (dyn FnMut(u32, Bytes) + Send + Sync),
     on_disconnect: Cb<dyn Fn() + Sync + Send + 'static>,
     init_destructor: Option<Box<dyn FnOnce() + Sync + Send>>,
 }
@@ -352,45 +458,113 @@ impl<'a> Drop for ClientInstance<'a> {
 impl<'a> ClientInstance<'a> {
     #[tracing::instrument(skip(self))]
     async fn run(mut self) -> anyhow::Result<()> {
+        let mut protocol = self.connect().await?;
+
+        // The first WorldDiff initializes the world, so wait for that until we say things are "ready"
+        (self.set_connection_status)("Receiving world".to_string());
+
+        let msg = protocol.next_diff().await?;
+        (self.on_diff)(msg, protocol.diff_decoder.server_time());
+        self.init_destructor =
+            Some((self.on_init)(protocol.connection(), protocol.client_info().clone()).context("Client initialization failed")?);
+
+        loop {
+            let err = match self.serve(&mut protocol).await {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+            if !connection_lost(&mut protocol, &err).await {
+                return Err(err);
+            }
+            protocol = self.reconnect(err).await?;
+
+            // The server sends its whole world again, which the world of the client is resynced with
+            let snapshot = protocol.next_diff().await?;
+            (self.on_resume)(protocol.connection(), protocol.client_info().clone(), snapshot, protocol.diff_decoder.server_time());
+        }
+    }
+
+    /// Connects to the server, and introduces the client to it
+    async fn connect(&mut self) -> anyhow::Result<ClientProtocol> {
         tracing::info!("Connecting to server at: {}", self.server_addr);
         (self.set_connection_status)(format!("Connecting to {}", self.server_addr));
-        let conn = open_connection(self.server_addr).await?;
+        let conn = match &self.conditioner {
+            Some(conditioner) => {
+                let proxy = conditioner.proxy(self.server_addr).await?;
+                let conn = open_connection(proxy.addr(), None).await;
+                self.proxy = Some(proxy);
+                conn?
+            }
+            None => open_connection(self.server_addr, self.relay_addr).await?,
+        };
 
         (self.set_connection_status)("Waiting for server to respond".to_string());
 
         // Set up the protocol.
-        let mut protocol = ClientProtocol::new(conn, self.user_id.clone()).await?;
-
-        let stats_interval = 5;
-        let mut stats_timer = tokio::time::interval(Duration::from_secs_f32(stats_interval as f32));
-        let mut prev_stats = protocol.connection().stats();
+        let auth_ticket = self
+            .auth_ticket_provider
+            .as_ref()
+            .map(|provider| provider.ticket())
+            .transpose()
+            .context("Failed to create authentication ticket")?;
+        ClientProtocol::new(conn, self.user_id.clone(), auth_ticket).await
+    }
 
-        // The first WorldDiff initializes the world, so wait for that until we say things are "ready"
-        (self.set_connection_status)("Receiving world".to_string());
+    /// Reconnects to the server after the connection was lost on `err`, for as long as the server keeps the session of the
+    /// client
+    async fn reconnect(&mut self, err: anyhow::Error) -> anyhow::Result<ClientProtocol> {
+        log::warn!("Lost the connection to the server, reconnecting: {err:#}");
+        let deadline = Instant::now() + RECONNECT_TIMEOUT;
+        loop {
+            match self.connect().await {
+                Ok(protocol) => {
+                    log::info!("Reconnected to the server");
+                    return Ok(protocol);
+                }
+                Err(reconnect_err) if Instant::now() + RECONNECT_INTERVAL < deadline => {
+                    log::warn!("Failed to reconnect, retrying: {reconnect_err:#}");
+                    (self.set_connection_status)("Reconnecting".to_string());
+                    tokio::time::sleep(RECONNECT_INTERVAL).await;
+                }
+                Err(reconnect_err) => return Err(err.context(format!("Failed to reconnect: {reconnect_err:#}"))),
+            }
+        }
+    }
 
-        let msg = protocol.diff_stream.next().await?;
-        (self.on_diff)(msg);
-        self.init_destructor =
-            Some((self.on_init)(protocol.connection(), protocol.client_info().clone()).context("Client initialization failed")?);
+    /// Runs the connection to the server, until it fails
+    async fn serve(&mut self, protocol: &mut ClientProtocol) -> anyhow::Result<()> {
+        let mut stats_timer = tokio::time::interval(STATS_INTERVAL);
+        let mut stats_tracker = StatsTracker::default();
+        let mut probe_timer = tokio::time::interval(PROBE_INTERVAL);
+        let mut probe_sender = ProbeSender::default();
+        let mut probes = ProbeReceiver::default();
+        // The messages of the streams of the channels, which are read by their own tasks
+        let (channel_tx, channel_rx) = flume::unbounded();
 
         // The server
         loop {
             tokio::select! {
                 msg = protocol.diff_stream.next() => {
                     profiling::scope!("game_in_entities");
-                    let msg: WorldDiff  = msg?;
-                    (self.on_diff)(msg);
+                    let msg = protocol.diff_decoder.decode(msg?).context("Failed to decompress world diff")?;
+                    (self.on_diff)(msg, protocol.diff_decoder.server_time());
                 }
                 _ = stats_timer.tick() => {
-                    let stats = protocol.connection().stats();
-
-                    (self.on_client_stats)(GameClientNetworkStats {
-                        latency_ms: protocol.connection().rtt().as_millis() as u64,
-                        bytes_sent: (stats.udp_tx.bytes - prev_stats.udp_tx.bytes) / stats_interval,
-                        bytes_received: (stats.udp_rx.bytes - prev_stats.udp_rx.bytes) / stats_interval,
-                    });
-
-                    prev_stats = stats;
+                    let connection = protocol.connection();
+                    let stats = stats_tracker.sample(connection.rtt(), connection.stats(), &probes, protocol.diff_decoder.received());
+                    (self.on_client_stats)(GameClientNetworkStats(stats));
+                }
+                _ = probe_timer.tick() => {
+                    protocol.connection().send_datagram(probe_sender.next_probe().to_datagram()).ok();
+                }
+                Some(Ok(datagram)) = protocol.conn.datagrams.next() => {
+                    if let Some(probe) = Probe::from_datagram(&datagram) {
+                        probes.receive(probe);
+                    } else if let Some(message) = Message::payload_of_datagram(&datagram) {
+                        (self.on_event)(type_name::<Message>().to_string(), message.into());
+                    } else if let Some((id, data)) = channels::payload_of_datagram(&datagram) {
+                        (self.on_channel_message)(id, data);
+                    }
                 }
                 Ok(stats) = protocol.stat_stream.next() => {
                     (self.on_server_stats)(GameClientServerStats(stats));
@@ -407,39 +581,186 @@ impl<'a> ClientInstance<'a> {
 
                     (self.on_event)(event_name, event_data.into_boxed_slice());
                 }
+                Some(Ok((_send, recv))) = protocol.conn.bi_streams.next() => {
+                    let channel_tx = channel_tx.clone();
+                    tokio::spawn(async move {
+                        log_network_result!(channels::forward_stream(recv, channel_tx).await);
+                    });
+                }
+                Ok((id, data)) = channel_rx.recv_async() => {
+                    (self.on_channel_message)(id, data);
+                }
             }
         }
     }
 }
 
-/// Set up and manage a connection to the server
-#[derive(Debug, Clone, Default)]
-pub struct GameClientNetworkStats {
-    pub latency_ms: u64,
-    pub bytes_sent: u64,
-    pub bytes_received: u64,
+/// Whether the connection of `protocol` was lost, e.g. it timed out, rather than closed by either side, once the client
+/// failed on `err`
+async fn connection_lost(protocol: &mut ClientProtocol, err: &anyhow::Error) -> bool {
+    if err.downcast_ref::<NetworkError>().map(|err| err.is_connection_lost()).unwrap_or(false) {
+        return true;
+    }
+    // The streams only tell that the connection is over, but the datagrams fail with the reason why
+    let reason = async {
+        loop {
+            match protocol.conn.datagrams.next().await {
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Some(err),
+                None => return None,
+            }
+        }
+    };
+    matches!(tokio::time::timeout(CONNECTION_LOST_TIMEOUT, reason).await, Ok(Some(err)) if err.is_connection_lost())
+}
+
+/// The changes which resync the `world` of a client which resumed its session with the `snapshot` the server sent, which
+/// spawns all of its entities: the remote entities which aren't in it anymore are despawned, the others are updated, and
+/// the new ones are spawned
+fn resync_diff(world: &World, snapshot: WorldDiff) -> WorldDiff {
+    let mut in_snapshot = HashSet::new();
+    let mut changes = Vec::new();
+    for change in snapshot.changes {
+        match change {
+            WorldChange::Spawn(Some(id), data) if world.exists(id) => {
+                in_snapshot.insert(id);
+                // The components the client added itself are kept
+                let removed = world
+                    .get_components(id)
+                    .unwrap()
+                    .into_iter()
+                    .filter(|&comp| SerializationProfile::REPLICATION.includes(comp) && data.get_entry(comp).is_none())
+                    .collect_vec();
+                changes.push(WorldChange::AddComponents(id, data));
+                if !removed.is_empty() {
+                    changes.push(WorldChange::RemoveComponents(id, removed));
+                }
+            }
+            change => {
+                if let WorldChange::Spawn(Some(id), _) = &change {
+                    in_snapshot.insert(*id);
+                }
+                changes.push(change);
+            }
+        }
+    }
+    let despawned =
+        query(()).incl(is_remote_entity()).iter(world, None).map(|(id, _)| id).filter(|id| !in_snapshot.contains(id)).collect_vec();
+    WorldDiff { changes }.despawn(despawned)
 }
 
+/// The statistics of the connection to the server, as seen by the client
+#[derive(Debug, Clone, Default)]
+pub struct GameClientNetworkStats(pub NetworkStats);
+
 impl Display for GameClientNetworkStats {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}ms, {}/s out, {}/s in", self.latency_ms, to_byte_unit(self.bytes_sent), to_byte_unit(self.bytes_received))
+        self.0.fmt(f)
     }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct GameClientServerStats(pub FpsSample);
 
-/// Connnect to the server endpoint.
+/// How long the client keeps trying to reconnect after losing its connection, which is how long the server keeps its session
+/// by default
+const RECONNECT_TIMEOUT: Duration = DEFAULT_RECONNECT_GRACE;
+/// How long the client waits between two attempts to reconnect
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the datagrams may take to tell why the connection is over
+const CONNECTION_LOST_TIMEOUT: Duration = Duration::from_millis(100);
+/// How long connecting over QUIC may take before falling back to a WebSocket
+const QUIC_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long connecting over a WebSocket may take before falling back to the relay
+const WEBSOCKET_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connnect to the server endpoint, over QUIC, or over a WebSocket if QUIC doesn't get through (e.g. if UDP is blocked),
+/// or through the relay at `relay_addr` if the server can't be reached directly (e.g. if it's behind a CGNAT).
 /// Does not handle a protocol.
 #[tracing::instrument]
-pub async fn open_connection(server_addr: SocketAddr) -> anyhow::Result<NewConnection> {
+pub async fn open_connection(server_addr: SocketAddr, relay_addr: Option<SocketAddr>) -> anyhow::Result<NewConnection> {
     tracing::info!("Connecting to world instance: {:?}", server_addr);
 
+    let quic_err = match open_quic_connection(server_addr).await {
+        Ok(conn) => return Ok(conn),
+        Err(err) => err,
+    };
+    tracing::warn!("Failed to connect over QUIC, falling back to WebSocket: {quic_err:#}");
+    let websocket_err = match tokio::time::timeout(WEBSOCKET_CONNECT_TIMEOUT, websocket::connect(server_addr)).await {
+        Ok(Ok(conn)) => {
+            tracing::info!("Got WebSocket connection");
+            return Ok(conn);
+        }
+        Ok(Err(err)) => anyhow::Error::from(err),
+        Err(err) => anyhow::Error::from(err).context("Timed out"),
+    };
+    let relay_addr = match relay_addr {
+        Some(relay_addr) => relay_addr,
+        None => bail!("Failed to connect over QUIC ({quic_err:#}) and over a WebSocket ({websocket_err:#})"),
+    };
+    tracing::warn!("Failed to connect over a WebSocket, falling back to the relay at {relay_addr}: {websocket_err:#}");
+    let conn = relay::connect(relay_addr, server_addr).await.with_context(|| {
+        format!("Failed to connect over QUIC ({quic_err:#}), over a WebSocket ({websocket_err:#}) and through the relay at {relay_addr}")
+    })?;
+
+    tracing::info!("Got relayed connection");
+    Ok(conn)
+}
+
+async fn open_quic_connection(server_addr: SocketAddr) -> anyhow::Result<NewConnection> {
     let endpoint = create_client_endpoint_random_port().context("Failed to create client endpoint")?;
 
     tracing::info!("Got endpoint");
-    let conn = endpoint.connect(server_addr, "localhost")?.await?;
+    let conn = tokio::time::timeout(QUIC_CONNECT_TIMEOUT, endpoint.connect(server_addr, "localhost")?).await.context("Timed out")??;
 
     tracing::info!("Got connection");
-    Ok(conn)
+    Ok(conn.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use ambient_ecs::{Debuggable, Networked, Serializable};
+
+    use super::*;
+
+    components!("test", {
+        @[Networked, Serializable, Debuggable]
+        health: f32,
+        @[Networked, Serializable, Debuggable]
+        shield: f32,
+        /// Added by the client to the entities of the server
+        @[Debuggable]
+        highlighted: (),
+    });
+
+    #[test]
+    fn resync_with_a_snapshot() {
+        ambient_core::init_all_components();
+        crate::init_all_components();
+        init_components();
+        let mut world = World::new("resync_with_a_snapshot");
+        let remote = |world: &mut World, data: EntityData| data.set(is_remote_entity(), ()).spawn(world);
+        let updated = remote(&mut world, EntityData::new().set(health(), 100.).set(shield(), 50.).set(highlighted(), ()));
+        let despawned = remote(&mut world, EntityData::new().set(health(), 10.));
+        let local = EntityData::new().set(health(), 1.).spawn(&mut world);
+        let spawned = EntityId::new();
+
+        // The shield of the updated entity was removed while the client was away
+        let snapshot = WorldDiff {
+            changes: vec![
+                WorldChange::Spawn(Some(updated), EntityData::new().set(health(), 80.)),
+                WorldChange::Spawn(Some(spawned), EntityData::new().set(health(), 20.)),
+            ],
+        };
+        let diff = resync_diff(&world, snapshot);
+        diff.apply(&mut world, EntityData::new().set(is_remote_entity(), ()), false);
+
+        assert_eq!(world.get(updated, health()).unwrap(), 80.);
+        assert!(!world.has_component(updated, shield()));
+        assert!(world.has_component(updated, highlighted()));
+        assert!(!world.exists(despawned));
+        assert_eq!(world.get(spawned, health()).unwrap(), 20.);
+        assert!(world.has_component(spawned, is_remote_entity()));
+        assert_eq!(world.get(local, health()).unwrap(), 1.);
+    }
 }
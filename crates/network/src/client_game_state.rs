@@ -8,19 +8,23 @@ use ambient_core::{
     transform::local_to_world,
     window_physical_size,
 };
-use ambient_ecs::{components, query, EntityData, FrameEvent, System, SystemGroup, World};
+use ambient_ecs::{components, query, world_events, EntityData, FrameEvent, System, SystemGroup, World};
 use ambient_gizmos::render::GizmoRenderer;
 use ambient_gpu::gpu::GpuKey;
-use ambient_renderer::{RenderTarget, Renderer, RendererConfig, RendererTarget};
+use ambient_renderer::{render_scale, RenderTarget, Renderer, RendererConfig, RendererSettings, RendererSettingsPath, RendererTarget};
 use ambient_std::{
     asset_cache::{AssetCache, SyncAssetKeyExt},
     color::Color,
     math::interpolate,
     shapes::Ray,
+    watched_config::WatchedConfig,
 };
 use glam::{vec2, Mat4, Vec2, Vec3, Vec3Swizzles};
 
-use crate::{player, user_id};
+use crate::{
+    interpolation::{self, interpolation_state, InterpolationState},
+    ownership, player, recording, user_id,
+};
 
 components!("rendering", {
     game_screen_render_target: Arc<RenderTarget>,
@@ -34,6 +38,7 @@ pub struct ClientGameState {
     temporary_systems: Vec<TempSystem>,
     gpu_world_sync_systems: SystemGroup<GpuWorldSyncEvent>,
     pub renderer: Renderer,
+    renderer_settings: Option<WatchedConfig<RendererSettings>>,
     assets: AssetCache,
     user_id: String,
 }
@@ -53,17 +58,30 @@ impl ClientGameState {
         client_systems: SystemGroup,
         client_resources: EntityData,
     ) -> Self {
+        let renderer_settings = RendererSettingsPath.try_get(&assets).map(WatchedConfig::new);
+        let settings = renderer_settings.as_ref().map(|settings| settings.current().clone()).unwrap_or_default();
+
         let mut game_world = World::new("client_game_world");
         let local_resources = world_instance_resources(AppResources::from_world(world))
             .set(crate::local_user_id(), player_id.clone())
             .set(game_screen_render_target(), render_target)
+            .set(render_scale(), settings.render_scale)
+            .set(interpolation_state(), InterpolationState::default())
+            .set_default(world_events())
             .append(client_resources);
         game_world.add_components(game_world.resource_entity(), local_resources).unwrap();
 
-        let systems = SystemGroup::new("game", vec![Box::new(client_systems), Box::new(world_instance_systems(true))]);
-        let mut renderer =
-            Renderer::new(world, assets.clone(), RendererConfig { scene: main_scene(), shadows: true, ..Default::default() });
-        renderer.post_transparent = Some(Box::new(GizmoRenderer::new(&assets)));
+        let systems = SystemGroup::new(
+            "game",
+            vec![
+                Box::new(client_systems),
+                Box::new(interpolation::client_systems()),
+                Box::new(ownership::client_systems()),
+                Box::new(recording::client_systems()),
+                Box::new(world_instance_systems(true)),
+            ],
+        );
+        let renderer = Self::create_renderer(world, &assets, &settings);
 
         Self {
             world: game_world,
@@ -71,15 +89,31 @@ impl ClientGameState {
             temporary_systems: Default::default(),
             gpu_world_sync_systems: gpu_world_sync_systems(),
             renderer,
+            renderer_settings,
             assets,
             user_id: player_id,
         }
     }
+    fn create_renderer(world: &mut World, assets: &AssetCache, settings: &RendererSettings) -> Renderer {
+        let config = RendererConfig { scene: main_scene(), shadows: true, hdr: true, occlusion_culling: true, ..Default::default() }
+            .with_settings(settings);
+        let mut renderer = Renderer::new(world, assets.clone(), config);
+        renderer.post_transparent = Some(Box::new(GizmoRenderer::new(assets)));
+        renderer
+    }
     #[profiling::function]
     pub fn on_frame(&mut self, target: &RenderTarget) {
+        if let Some(settings) = self.renderer_settings.as_mut().and_then(|settings| settings.poll()) {
+            let shader_debug_params = self.renderer.shader_debug_params;
+            self.renderer = Self::create_renderer(&mut self.world, &self.assets, settings);
+            self.renderer.shader_debug_params = shader_debug_params;
+            *self.world.resource_mut(render_scale()) = settings.render_scale;
+        }
         self.systems.run(&mut self.world, &FrameEvent);
         self.temporary_systems.retain_mut(|system| !(system.0)(&mut self.world));
         self.gpu_world_sync_systems.run(&mut self.world, &GpuWorldSyncEvent);
+        // The client world doesn't go through next_frame, so its event channels are updated here
+        self.world.update_event_channels();
         let gpu = GpuKey.get(&self.assets);
         let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("GameState.render") });
         let mut post_submit = Vec::new();
@@ -0,0 +1,388 @@
+//! A network condition simulator, to test the prediction and the interpolation without a bad network at hand.
+//!
+//! The client connects to the server through a local proxy, which adds latency and jitter to the traffic in both
+//! directions, drops packets, and caps the bandwidth, as its [NetworkConditions] say. The conditions can be changed while
+//! connected, with [NetworkConditioner::set], or by editing the file they were loaded from. The proxy forwards both QUIC
+//! (over UDP) and WebSockets (over TCP); as TCP doesn't lose packets, only the delays and the bandwidth cap apply to the
+//! WebSockets. The client doesn't fall back to the relay while its connection is conditioned.
+//!
+//! The client's [network_conditioner] changes the conditions from the code, e.g. from a debug menu.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use ambient_ecs::{components, Resource};
+use ambient_std::{
+    asset_cache::SyncAssetKey,
+    watched_config::{Config, WatchedConfig},
+};
+use anyhow::Context;
+use parking_lot::Mutex;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    task::JoinHandle,
+    time::Instant,
+};
+
+components!("network", {
+    /// Conditions the connection of the client to the server, if its [NetworkConditions] are simulated
+    @[Resource]
+    network_conditioner: NetworkConditioner,
+});
+
+/// The largest UDP packet the proxy forwards
+const MAX_PACKET_SIZE: usize = 65536;
+/// How long the packets may wait for the capped bandwidth before they're dropped, as a router's buffer would
+const MAX_QUEUE_DELAY: Duration = Duration::from_secs(1);
+/// The most UDP packets waiting to be delivered in each direction; the ones over it are dropped
+const MAX_QUEUED_PACKETS: usize = 1024;
+/// The most reads of a TCP connection waiting to be delivered in each direction; the proxy stops reading the connection
+/// while it's full
+const MAX_QUEUED_READS: usize = 64;
+
+/// The conditions of the simulated network, in each direction
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkConditions {
+    /// The delay added to each packet, in milliseconds; the round trip grows by twice as much
+    pub latency_ms: u32,
+    /// The most random delay added to each packet on top of the latency, in milliseconds. The packets over UDP may
+    /// arrive out of order.
+    pub jitter_ms: u32,
+    /// The probability of a packet over UDP to be dropped, between 0 and 1
+    pub loss: f32,
+    /// The most bytes per second, if the bandwidth is capped
+    pub bandwidth: Option<u64>,
+}
+impl Config for NetworkConditions {
+    fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!((0. ..=1.).contains(&self.loss), "loss must be between 0 and 1");
+        anyhow::ensure!(self.bandwidth != Some(0), "bandwidth must be positive");
+        Ok(())
+    }
+}
+
+/// The file to load the [NetworkConditions] of the client from, if its connection should be conditioned
+#[derive(Clone, Debug)]
+pub struct NetworkConditionsPath;
+impl SyncAssetKey<PathBuf> for NetworkConditionsPath {}
+
+/// The current [NetworkConditions], shared by the proxies
+#[derive(Debug, Clone)]
+pub struct NetworkConditioner(Arc<Mutex<ConditionerState>>);
+#[derive(Debug)]
+struct ConditionerState {
+    conditions: NetworkConditions,
+    file: Option<WatchedConfig<NetworkConditions>>,
+}
+impl NetworkConditioner {
+    pub fn new(conditions: NetworkConditions) -> Self {
+        Self(Arc::new(Mutex::new(ConditionerState { conditions, file: None })))
+    }
+    /// Loads the conditions from the TOML file at `path`, and follows its changes
+    pub fn from_file(path: impl Into<PathBuf>) -> Self {
+        let file = WatchedConfig::<NetworkConditions>::new(path);
+        Self(Arc::new(Mutex::new(ConditionerState { conditions: file.current().clone(), file: Some(file) })))
+    }
+    pub fn conditions(&self) -> NetworkConditions {
+        let mut state = self.0.lock();
+        if let Some(conditions) = state.file.as_mut().and_then(|file| file.poll()).cloned() {
+            state.conditions = conditions;
+        }
+        state.conditions.clone()
+    }
+    /// Changes the conditions of the connections which go through the proxies of this conditioner, until the file
+    /// they were loaded from changes, if any
+    pub fn set(&self, conditions: NetworkConditions) {
+        self.0.lock().conditions = conditions;
+    }
+
+    /// Starts a proxy which forwards the traffic to `server_addr` under the conditions of this conditioner
+    pub async fn proxy(&self, server_addr: SocketAddr) -> anyhow::Result<ConditionedProxy> {
+        let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.context("Failed to bind the UDP socket of the proxy")?;
+        let addr = udp.local_addr()?;
+        // The WebSockets come in on the same port
+        let tcp = TcpListener::bind(addr).await.context("Failed to bind the TCP socket of the proxy")?;
+        let tasks =
+            vec![tokio::spawn(forward_udp(udp, server_addr, self.clone())), tokio::spawn(forward_tcp(tcp, server_addr, self.clone()))];
+        log::info!("Conditioning the connection to {server_addr} through {addr}");
+        Ok(ConditionedProxy { addr, tasks })
+    }
+}
+
+/// A proxy started by [NetworkConditioner::proxy], which stops when it's dropped
+#[derive(Debug)]
+pub struct ConditionedProxy {
+    addr: SocketAddr,
+    tasks: Vec<JoinHandle<()>>,
+}
+impl ConditionedProxy {
+    /// The address to connect to instead of the server
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+impl Drop for ConditionedProxy {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// One direction of the traffic through a proxy
+struct Link {
+    conditioner: NetworkConditioner,
+    /// When the capped bandwidth is free again
+    free_at: Instant,
+    /// When the last packet is delivered, for the streams whose data has to stay in order
+    last_delivery: Instant,
+}
+impl Link {
+    fn new(conditioner: NetworkConditioner, now: Instant) -> Self {
+        Self { conditioner, free_at: now, last_delivery: now }
+    }
+
+    /// When a packet of `len` bytes sent at `now` is delivered, or `None` if it's lost
+    fn delivery(&mut self, len: usize, lossy: bool, ordered: bool, now: Instant) -> Option<Instant> {
+        let conditions = self.conditioner.conditions();
+        let mut rng = rand::thread_rng();
+        if lossy && rng.gen::<f32>() < conditions.loss {
+            return None;
+        }
+        let mut sent = now;
+        if let Some(bandwidth) = conditions.bandwidth {
+            sent = self.free_at.max(now);
+            if lossy && sent > now + MAX_QUEUE_DELAY {
+                return None;
+            }
+            self.free_at = sent + Duration::from_secs_f64(len as f64 / bandwidth as f64);
+            sent = self.free_at;
+        }
+        let jitter = if conditions.jitter_ms > 0 { rng.gen_range(0..=conditions.jitter_ms) } else { 0 };
+        let mut delivery = sent + Duration::from_millis((conditions.latency_ms + jitter) as u64);
+        if ordered {
+            delivery = delivery.max(self.last_delivery);
+        }
+        self.last_delivery = self.last_delivery.max(delivery);
+        Some(delivery)
+    }
+}
+
+/// Forwards the datagrams between the first client which sends one to `udp` and the server
+async fn forward_udp(udp: UdpSocket, server_addr: SocketAddr, conditioner: NetworkConditioner) {
+    let upstream = match connect_upstream_udp(server_addr).await {
+        Ok(upstream) => upstream,
+        Err(err) => {
+            log::error!("Failed to open the UDP socket to the server: {err:?}");
+            return;
+        }
+    };
+    let mut buf = vec![0; MAX_PACKET_SIZE];
+    let (len, client_addr) = match udp.recv_from(&mut buf).await {
+        Ok(res) => res,
+        Err(_) => return,
+    };
+    let (up_tx, up_rx) = flume::bounded(MAX_QUEUED_PACKETS);
+    let (down_tx, down_rx) = flume::bounded(MAX_QUEUED_PACKETS);
+    let mut up = Link::new(conditioner.clone(), Instant::now());
+    queue_packet(&up_tx, buf[..len].to_vec(), up.delivery(len, true, false, Instant::now()));
+
+    let upstream_loop = async {
+        while let Ok((len, addr)) = udp.recv_from(&mut buf).await {
+            if addr == client_addr {
+                queue_packet(&up_tx, buf[..len].to_vec(), up.delivery(len, true, false, Instant::now()));
+            }
+        }
+    };
+    let downstream_loop = async {
+        let mut down = Link::new(conditioner, Instant::now());
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        while let Ok(len) = upstream.recv(&mut buf).await {
+            queue_packet(&down_tx, buf[..len].to_vec(), down.delivery(len, true, false, Instant::now()));
+        }
+    };
+    tokio::join!(upstream_loop, downstream_loop, send_delivered(&upstream, None, up_rx), send_delivered(&udp, Some(client_addr), down_rx));
+}
+
+async fn connect_upstream_udp(server_addr: SocketAddr) -> std::io::Result<UdpSocket> {
+    let bind_addr: SocketAddr = if server_addr.is_ipv4() { "0.0.0.0:0".parse().unwrap() } else { "[::]:0".parse().unwrap() };
+    let socket = UdpSocket::bind(bind_addr).await?;
+    socket.connect(server_addr).await?;
+    Ok(socket)
+}
+
+/// Queues `packet` to be sent once it's delivered, if it isn't lost, and if the queue isn't full
+fn queue_packet(queue: &flume::Sender<(Instant, Vec<u8>)>, packet: Vec<u8>, delivery: Option<Instant>) {
+    if let Some(delivery) = delivery {
+        queue.try_send((delivery, packet)).ok();
+    }
+}
+
+/// Sends the packets queued by [queue_packet] on `socket` once they're delivered, which may be in another order than
+/// they were queued. Returns once the queue is closed and its packets are sent.
+async fn send_delivered(socket: &UdpSocket, target: Option<SocketAddr>, queue: flume::Receiver<(Instant, Vec<u8>)>) {
+    let mut waiting = BinaryHeap::new();
+    let mut closed = false;
+    while !closed || !waiting.is_empty() {
+        let next = waiting.peek().map(|Reverse((delivery, _)): &Reverse<(Instant, Vec<u8>)>| *delivery);
+        tokio::select! {
+            packet = queue.recv_async(), if !closed => match packet {
+                Ok(packet) if waiting.len() < MAX_QUEUED_PACKETS => waiting.push(Reverse(packet)),
+                Ok(_) => {}
+                Err(_) => closed = true,
+            },
+            _ = tokio::time::sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                let Reverse((_, packet)) = waiting.pop().unwrap();
+                // The packets may be lost anyway
+                match target {
+                    Some(target) => socket.send_to(&packet, target).await.ok(),
+                    None => socket.send(&packet).await.ok(),
+                };
+            }
+        }
+    }
+}
+
+/// Forwards the TCP connections to `listener` to the server
+async fn forward_tcp(listener: TcpListener, server_addr: SocketAddr, conditioner: NetworkConditioner) {
+    while let Ok((client, _)) = listener.accept().await {
+        let conditioner = conditioner.clone();
+        tokio::spawn(async move {
+            let server = match TcpStream::connect(server_addr).await {
+                Ok(server) => server,
+                Err(err) => {
+                    log::warn!("Failed to connect the proxy to the server: {err:?}");
+                    return;
+                }
+            };
+            let (client_read, client_write) = client.into_split();
+            let (server_read, server_write) = server.into_split();
+            tokio::join!(
+                forward_stream(client_read, server_write, Link::new(conditioner.clone(), Instant::now())),
+                forward_stream(server_read, client_write, Link::new(conditioner, Instant::now()))
+            );
+        });
+    }
+}
+
+/// Forwards the data of a TCP connection in one direction, in order
+async fn forward_stream(mut read: impl AsyncRead + Unpin, write: impl AsyncWrite + Send + Unpin + 'static, mut link: Link) {
+    let (tx, rx) = flume::bounded::<(Instant, Vec<u8>)>(MAX_QUEUED_READS);
+    let writer = tokio::spawn(async move {
+        let mut write = write;
+        while let Ok((delivery, data)) = rx.recv_async().await {
+            tokio::time::sleep_until(delivery).await;
+            if write.write_all(&data).await.is_err() {
+                break;
+            }
+        }
+        write.shutdown().await.ok();
+    });
+    let mut buf = vec![0; MAX_PACKET_SIZE];
+    while let Ok(len) = read.read(&mut buf).await {
+        if len == 0 {
+            break;
+        }
+        let delivery = link.delivery(len, false, true, Instant::now()).expect("The streams aren't lossy");
+        // Waits for the writer while the queue is full, which stops the reads and lets TCP slow the sender down
+        if tx.send_async((delivery, buf[..len].to_vec())).await.is_err() {
+            break;
+        }
+    }
+    drop(tx);
+    writer.await.ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(conditions: NetworkConditions, now: Instant) -> Link {
+        Link::new(NetworkConditioner::new(conditions), now)
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn packets_are_delayed_by_the_latency_and_the_jitter() {
+        let now = Instant::now();
+        let mut link = link(NetworkConditions { latency_ms: 50, ..Default::default() }, now);
+        assert_eq!(link.delivery(100, true, false, now), Some(now + ms(50)));
+
+        link.conditioner.set(NetworkConditions { latency_ms: 50, jitter_ms: 20, ..Default::default() });
+        let deliveries = (0..100).map(|i| link.delivery(100, true, false, now + ms(i)).unwrap() - (now + ms(i))).collect::<Vec<_>>();
+        assert!(deliveries.iter().all(|delay| (ms(50)..=ms(70)).contains(delay)));
+        assert!(deliveries.iter().any(|delay| *delay != deliveries[0]));
+    }
+
+    #[test]
+    fn ordered_packets_arrive_in_order() {
+        let now = Instant::now();
+        let mut link = link(NetworkConditions { latency_ms: 10, jitter_ms: 50, ..Default::default() }, now);
+        let deliveries = (0..100).map(|i| link.delivery(100, false, true, now + ms(i)).unwrap()).collect::<Vec<_>>();
+        assert!(deliveries.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn the_bandwidth_is_capped() {
+        let now = Instant::now();
+        let mut link = link(NetworkConditions { latency_ms: 10, bandwidth: Some(1000), ..Default::default() }, now);
+        assert_eq!(link.delivery(100, true, false, now), Some(now + ms(110)));
+        assert_eq!(link.delivery(100, true, false, now), Some(now + ms(210)));
+        // The bandwidth is free again once the packets are through
+        assert_eq!(link.delivery(100, true, false, now + ms(500)), Some(now + ms(610)));
+
+        // The lossy packets which would wait too long are dropped, but not the others
+        assert_eq!(link.delivery(1000, true, false, now + ms(500)), Some(now + ms(1610)));
+        assert_eq!(link.delivery(100, true, false, now + ms(500)), None);
+        assert_eq!(link.delivery(100, false, false, now + ms(500)), Some(now + ms(1710)));
+    }
+
+    #[test]
+    fn only_the_lossy_packets_are_lost() {
+        let now = Instant::now();
+        let mut link = link(NetworkConditions { loss: 1., ..Default::default() }, now);
+        assert_eq!(link.delivery(100, true, false, now), None);
+        assert_eq!(link.delivery(100, false, true, now), Some(now));
+    }
+
+    #[tokio::test]
+    async fn packets_are_sent_once_delivered() {
+        let sender = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let receiver = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let (tx, rx) = flume::bounded(MAX_QUEUED_PACKETS);
+        let now = Instant::now();
+        queue_packet(&tx, vec![2], Some(now + ms(100)));
+        queue_packet(&tx, vec![1], Some(now + ms(50)));
+        queue_packet(&tx, vec![0], None);
+        queue_packet(&tx, vec![3], Some(now + ms(150)));
+        drop(tx);
+
+        let target = receiver.local_addr().unwrap();
+        let received = async {
+            let mut received = Vec::new();
+            let mut buf = [0; 16];
+            while received.len() < 3 {
+                let len = receiver.recv(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..len]);
+            }
+            received
+        };
+        // The packets still waiting are sent after the queue closes
+        let (_, received) = tokio::join!(send_delivered(&sender, Some(target), rx), received);
+        assert_eq!(received, [1, 2, 3]);
+        assert!(Instant::now() >= now + ms(150));
+    }
+}
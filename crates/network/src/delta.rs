@@ -0,0 +1,343 @@
+//! Delta compression of the diffs sent to the clients.
+//!
+//! Most of the traffic of a busy scene is the changes to a few components, such as the transforms of the moving entities.
+//! The changes to the [Quantized] components are sent as the difference between their quantized value and the last one
+//! sent to the client for the same entity and component, which is a small integer when the value changes a little between
+//! two diffs. The diffs are sent on a reliable and ordered stream, so the last value sent is also the last one the client
+//! acknowledged, and the server doesn't need to keep more than that.
+//!
+//! The server and the client both keep a slot for each entity and component whose changes are sent this way. It's opened
+//! the first time the component changes, and closed when the entity is despawned or the component is added or removed;
+//! the first delta of a slot is the whole quantized value. The deltas are packed as the index of their slot, followed by
+//! each integer of the value zigzag encoded as a variable length integer, so that the small ones take a single byte.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use ambient_ecs::{components, ComponentDesc, EntityId, Quantized, WorldChange, WorldDiff};
+use ambient_std::varint::{read_varint, unzigzag, write_varint, zigzag};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::stats::ReplicationBytes;
+
+components!("network", {
+    /// The delta compression state of the diffs sent to a player
+    delta_encoder: DeltaEncoder,
+});
+
+/// A diff as it's sent to a client
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompressedDiff {
+    /// The time of the server when it sent the diff
+    time: Duration,
+    /// The changes which aren't sent as deltas
+    changes: Vec<WorldChange>,
+    /// The entity and component of the slots opened by this diff, in the order of their index
+    opened: Vec<(EntityId, ComponentDesc)>,
+    /// The packed deltas
+    deltas: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+struct Slot {
+    index: u64,
+    /// The quantized value last sent
+    values: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Slots {
+    next_index: u64,
+    entities: HashMap<EntityId, HashMap<ComponentDesc, Slot>>,
+    indices: HashMap<u64, (EntityId, ComponentDesc)>,
+}
+impl Slots {
+    /// Closes the slots of the components which `change` replaces the value of
+    fn close(&mut self, change: &WorldChange) {
+        let (id, components) = match change {
+            WorldChange::Spawn(Some(id), _) | WorldChange::Despawn(id) => (*id, None),
+            WorldChange::AddComponents(id, data) => (*id, Some(data.components())),
+            WorldChange::RemoveComponents(id, components) => (*id, Some(components.clone())),
+            WorldChange::Spawn(None, _) | WorldChange::Set(_, _) => return,
+        };
+        let slots = match self.entities.get_mut(&id) {
+            Some(slots) => slots,
+            None => return,
+        };
+        let closed = match components {
+            Some(components) => components.iter().filter_map(|component| slots.remove(component)).collect::<Vec<_>>(),
+            None => slots.drain().map(|(_, slot)| slot).collect(),
+        };
+        if slots.is_empty() {
+            self.entities.remove(&id);
+        }
+        for slot in closed {
+            self.indices.remove(&slot.index);
+        }
+    }
+    fn get_or_open(&mut self, id: EntityId, component: ComponentDesc, len: usize) -> (&mut Slot, bool) {
+        let slots = self.entities.entry(id).or_default();
+        let opened = !slots.contains_key(&component);
+        let slot = slots.entry(component).or_insert_with(|| {
+            let slot = Slot { index: self.next_index, values: vec![0; len] };
+            self.indices.insert(slot.index, (id, component));
+            self.next_index += 1;
+            slot
+        });
+        (slot, opened)
+    }
+}
+
+/// Compresses the diffs sent to a client. Each client needs its own, as the deltas are against what was sent to it.
+#[derive(Debug, Clone, Default)]
+pub struct DeltaEncoder {
+    slots: Slots,
+    sent: ReplicationBytes,
+}
+impl DeltaEncoder {
+    /// The bytes spent on each component by the diffs encoded so far
+    pub fn sent(&self) -> &ReplicationBytes {
+        &self.sent
+    }
+    /// Compresses `diff`, which the server sends at its `time`
+    pub fn encode(&mut self, diff: &WorldDiff, time: Duration) -> CompressedDiff {
+        // Only the last change to a quantized component is sent, unless it's replaced by a later change to the entity
+        let mut replaced_entities = HashSet::new();
+        let mut replaced_components = HashSet::new();
+        let mut quantized = Vec::new();
+        let mut changes = Vec::new();
+        for change in diff.changes.iter().rev() {
+            match change {
+                WorldChange::Set(id, entry) => {
+                    let replaced = replaced_entities.contains(id) || !replaced_components.insert((*id, entry.desc()));
+                    match entry.attribute::<Quantized>().map(|attribute| *attribute) {
+                        Some(attribute) if attribute.can_quantize(entry) => {
+                            if !replaced {
+                                quantized.push((*id, entry, attribute));
+                            }
+                        }
+                        _ => changes.push(change.clone()),
+                    }
+                }
+                WorldChange::Spawn(Some(id), _) | WorldChange::Despawn(id) => {
+                    replaced_entities.insert(*id);
+                    changes.push(change.clone());
+                }
+                WorldChange::AddComponents(id, data) => {
+                    replaced_components.extend(data.components().into_iter().map(|component| (*id, component)));
+                    changes.push(change.clone());
+                }
+                WorldChange::RemoveComponents(id, components) => {
+                    replaced_components.extend(components.iter().map(|component| (*id, *component)));
+                    changes.push(change.clone());
+                }
+                WorldChange::Spawn(None, _) => changes.push(change.clone()),
+            }
+        }
+        changes.reverse();
+        quantized.reverse();
+
+        for change in &changes {
+            self.slots.close(change);
+            self.sent.add_change(change);
+        }
+        let mut opened = Vec::new();
+        let mut deltas = Vec::new();
+        for (id, entry, attribute) in quantized {
+            let (slot, is_new) = self.slots.get_or_open(id, entry.desc(), attribute.float_count());
+            if is_new {
+                opened.push((id, entry.desc()));
+                self.sent.add(entry.desc(), bincode::serialized_size(&entry.desc()).unwrap_or_default());
+            }
+            let start = deltas.len();
+            write_varint(&mut deltas, slot.index);
+            for (last, value) in slot.values.iter_mut().zip(attribute.quantize(entry)) {
+                write_varint(&mut deltas, zigzag(value.wrapping_sub(*last)));
+                *last = value;
+            }
+            self.sent.add(entry.desc(), (deltas.len() - start) as u64);
+        }
+        CompressedDiff { time, changes, opened, deltas }
+    }
+}
+
+/// Decompresses the diffs received from the server
+#[derive(Debug, Clone, Default)]
+pub struct DeltaDecoder {
+    slots: Slots,
+    received: ReplicationBytes,
+    server_time: Duration,
+}
+impl DeltaDecoder {
+    /// The bytes spent on each component by the diffs decoded so far
+    pub fn received(&self) -> &ReplicationBytes {
+        &self.received
+    }
+    /// The time of the server when it sent the last diff decoded
+    pub fn server_time(&self) -> Duration {
+        self.server_time
+    }
+    /// The diff which was compressed to `diff`. The changes sent as deltas come after the other ones.
+    pub fn decode(&mut self, diff: CompressedDiff) -> anyhow::Result<WorldDiff> {
+        let CompressedDiff { time, mut changes, opened, deltas } = diff;
+        self.server_time = time;
+        for change in &changes {
+            self.slots.close(change);
+            self.received.add_change(change);
+        }
+        for (id, component) in opened {
+            let attribute = component.attribute::<Quantized>().with_context(|| format!("{} is not quantized", component.path()))?;
+            self.slots.get_or_open(id, component, attribute.float_count());
+            self.received.add(component, bincode::serialized_size(&component).unwrap_or_default());
+        }
+        let mut deltas = &deltas[..];
+        while !deltas.is_empty() {
+            let start = deltas.len();
+            let index = read_varint(&mut deltas)?;
+            let (id, component) = *self.slots.indices.get(&index).with_context(|| format!("Unknown delta slot {index}"))?;
+            let attribute = *component.attribute::<Quantized>().with_context(|| format!("{} is not quantized", component.path()))?;
+            let slot = self.slots.entities.get_mut(&id).and_then(|slots| slots.get_mut(&component)).unwrap();
+            for last in &mut slot.values {
+                *last = last.wrapping_add(unzigzag(read_varint(&mut deltas)?));
+            }
+            self.received.add(component, (start - deltas.len()) as u64);
+            changes.push(WorldChange::Set(id, attribute.dequantize(component, &slot.values)));
+        }
+        Ok(WorldDiff { changes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ambient_core::{
+        name,
+        transform::{rotation, translation},
+    };
+    use ambient_ecs::{ComponentEntry, EntityData};
+    use glam::{vec3, Quat, Vec3};
+
+    use super::*;
+
+    fn init() {
+        ambient_core::init_all_components();
+        crate::init_all_components();
+    }
+
+    fn move_to(id: EntityId, position: Vec3) -> WorldChange {
+        WorldChange::Set(id, ComponentEntry::new(translation(), position))
+    }
+
+    fn round_trip(encoder: &mut DeltaEncoder, decoder: &mut DeltaDecoder, changes: Vec<WorldChange>) -> (Vec<WorldChange>, usize) {
+        let compressed = encoder.encode(&WorldDiff { changes }, Duration::ZERO);
+        let len = compressed.deltas.len();
+        let compressed = bincode::deserialize(&bincode::serialize(&compressed).unwrap()).unwrap();
+        (decoder.decode(compressed).unwrap().changes, len)
+    }
+
+    fn translation_of(change: &WorldChange) -> Vec3 {
+        match change {
+            WorldChange::Set(_, entry) => *entry.downcast_ref::<Vec3>(),
+            change => panic!("Not a translation: {change:?}"),
+        }
+    }
+
+    #[test]
+    fn quantized_changes_round_trip() {
+        init();
+        let (mut encoder, mut decoder) = (DeltaEncoder::default(), DeltaDecoder::default());
+        let id = EntityId::new();
+        let spawn = WorldChange::Spawn(Some(id), EntityData::new().set(translation(), Vec3::ZERO));
+
+        // The spawn is sent as is, and the first delta holds the whole value
+        let (changes, first_len) = round_trip(&mut encoder, &mut decoder, vec![spawn.clone(), move_to(id, vec3(10., -20., 3.))]);
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(&changes[0], WorldChange::Spawn(Some(spawned), _) if *spawned == id));
+        assert!(translation_of(&changes[1]).abs_diff_eq(vec3(10., -20., 3.), 0.0005));
+
+        // The later ones only hold the small differences to the last value sent
+        let mut position = vec3(10., -20., 3.);
+        for _ in 0..10 {
+            position += vec3(0.01, -0.02, 0.);
+            let (changes, len) = round_trip(&mut encoder, &mut decoder, vec![move_to(id, position)]);
+            assert_eq!(changes.len(), 1);
+            assert!(translation_of(&changes[0]).abs_diff_eq(position, 0.0005));
+            assert!(len < first_len);
+            // A byte for the index of the slot, and one for each component
+            assert_eq!(len, 4);
+        }
+
+        // The rotations are normalized again, and the other components are sent as is
+        let rotated = Quat::from_rotation_y(1.);
+        let (changes, _) = round_trip(
+            &mut encoder,
+            &mut decoder,
+            vec![
+                WorldChange::Set(id, ComponentEntry::new(rotation(), rotated)),
+                WorldChange::Set(id, ComponentEntry::new(name(), "a".to_string())),
+            ],
+        );
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(&changes[0], WorldChange::Set(_, entry) if entry.downcast_ref::<String>() == "a"));
+        match &changes[1] {
+            WorldChange::Set(_, entry) => assert!(entry.downcast_ref::<Quat>().abs_diff_eq(rotated, 0.001)),
+            change => panic!("Not a rotation: {change:?}"),
+        }
+    }
+
+    #[test]
+    fn only_the_last_change_is_sent() {
+        init();
+        let (mut encoder, mut decoder) = (DeltaEncoder::default(), DeltaDecoder::default());
+        let id = EntityId::new();
+        let (changes, _) = round_trip(&mut encoder, &mut decoder, vec![move_to(id, Vec3::X), move_to(id, Vec3::Y)]);
+        assert_eq!(changes.len(), 1);
+        assert!(translation_of(&changes[0]).abs_diff_eq(Vec3::Y, 0.0005));
+
+        // The changes before the entity is despawned aren't sent at all
+        let (changes, _) = round_trip(&mut encoder, &mut decoder, vec![move_to(id, Vec3::Z), WorldChange::Despawn(id)]);
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(changes[0], WorldChange::Despawn(despawned) if despawned == id));
+    }
+
+    #[test]
+    fn structural_changes_reopen_the_slots() {
+        init();
+        let (mut encoder, mut decoder) = (DeltaEncoder::default(), DeltaDecoder::default());
+        let id = EntityId::new();
+        round_trip(&mut encoder, &mut decoder, vec![move_to(id, vec3(5., 5., 5.))]);
+
+        // Once the component is added again, the next delta holds the whole value rather than the difference
+        let add = WorldChange::AddComponents(id, EntityData::new().set(translation(), Vec3::ZERO));
+        let (changes, _) = round_trip(&mut encoder, &mut decoder, vec![add]);
+        assert_eq!(changes.len(), 1);
+        let (changes, _) = round_trip(&mut encoder, &mut decoder, vec![move_to(id, vec3(1., 2., 3.))]);
+        assert!(translation_of(&changes[0]).abs_diff_eq(vec3(1., 2., 3.), 0.0005));
+        assert_eq!(encoder.slots.indices.len(), 1);
+        assert_eq!(decoder.slots.indices.len(), 1);
+
+        let remove = WorldChange::RemoveComponents(id, vec![translation().desc()]);
+        round_trip(&mut encoder, &mut decoder, vec![remove]);
+        assert!(encoder.slots.indices.is_empty());
+        assert!(decoder.slots.indices.is_empty());
+        let (changes, _) = round_trip(&mut encoder, &mut decoder, vec![move_to(id, vec3(-1., 0., 0.))]);
+        assert!(translation_of(&changes[0]).abs_diff_eq(vec3(-1., 0., 0.), 0.0005));
+    }
+
+    #[test]
+    fn invalid_deltas_are_errors() {
+        init();
+        let mut decoder = DeltaDecoder::default();
+        let unknown_slot = CompressedDiff { time: Duration::ZERO, changes: Vec::new(), opened: Vec::new(), deltas: vec![7, 0, 0, 0] };
+        assert!(decoder.decode(unknown_slot).is_err());
+        let truncated = CompressedDiff {
+            time: Duration::ZERO,
+            changes: Vec::new(),
+            opened: vec![(EntityId::new(), translation().desc())],
+            deltas: vec![0, 2],
+        };
+        assert!(decoder.decode(truncated).is_err());
+    }
+}
@@ -0,0 +1,320 @@
+//! Discovery: how the clients find the servers to join, on the local network and on the internet.
+//!
+//! A server with a [ServerAnnouncement] answers the probes which the clients broadcast on the local network, and send to
+//! the [DISCOVERY_MULTICAST_ADDR] group, on the first free port of [DISCOVERY_PORTS]; the clients collect the answers
+//! with [discover_lan_servers]. As the answers are larger than the probes, only the probes from local addresses are
+//! answered, and only so many per second, so that the server can't be used to flood a host with answers to probes with
+//! a spoofed source. If there is a [ServerListKey], the server also announces itself to this list every
+//! [ANNOUNCE_INTERVAL], and the clients query it for the servers on the internet. The server lists are pluggable: a
+//! [ServerList] can be implemented for any backend, and [HttpServerList] talks to a simple HTTP API.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    ops::Range,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt},
+    download_asset::ReqwestClientKey,
+};
+use anyhow::bail;
+use futures::future;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use tokio::net::UdpSocket;
+
+use crate::server::SharedServerState;
+
+/// The UDP ports the servers answer the probes of the clients on the local network on, the first free one being used
+pub const DISCOVERY_PORTS: Range<u16> = 9200..9210;
+/// The multicast group the clients send their probes to, on top of broadcasting them
+pub const DISCOVERY_MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 65, 77);
+/// How often a server renews its entry on the server list, which may drop the entries which aren't renewed
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Starts the probes and the answers, so that the other datagrams on these ports are ignored
+const DISCOVERY_MAGIC: &[u8] = b"ambient-discovery";
+/// The most probes a server answers per second
+const MAX_ANSWERS_PER_SECOND: u32 = 32;
+/// The characters escaped in a segment of the path of a URL, which are all but the unreserved ones
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// What a server tells about itself to the clients looking for servers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    /// Where to join the server, e.g. `192.168.1.20:9000`
+    pub address: String,
+    pub name: String,
+    /// The id of the project the server runs
+    pub project_id: String,
+    pub players: u32,
+    pub max_players: Option<u32>,
+}
+
+/// How a server describes itself to the clients looking for servers
+#[derive(Debug, Clone)]
+pub struct ServerAnnouncement {
+    pub name: String,
+    pub project_id: String,
+    pub max_players: Option<u32>,
+    /// The address the players join the server at from the internet, as announced to the server list; on the local
+    /// network, the clients use the address they got the answer from
+    pub address: String,
+    /// Whether to answer the clients looking for servers on the local network
+    pub lan: bool,
+}
+impl ServerAnnouncement {
+    fn info(&self, players: usize) -> ServerInfo {
+        ServerInfo {
+            address: self.address.clone(),
+            name: self.name.clone(),
+            project_id: self.project_id.clone(),
+            players: players as u32,
+            max_players: self.max_players,
+        }
+    }
+}
+
+/// The answer of a server to a probe
+#[derive(Debug, Serialize, Deserialize)]
+struct LanAnswer {
+    /// Tells the answers of the same server apart, e.g. when it gets the probe from several interfaces
+    server_id: u64,
+    /// The port the players join the server on
+    port: u16,
+    info: ServerInfo,
+}
+
+/// A list of the servers on the internet, which the servers announce themselves to and the clients query
+#[async_trait::async_trait]
+pub trait ServerList: Sync + Send {
+    /// Adds the server to the list, or renews its entry
+    async fn announce(&self, info: &ServerInfo) -> anyhow::Result<()>;
+    /// Removes the server joined at `address` from the list
+    async fn withdraw(&self, address: &str) -> anyhow::Result<()>;
+    /// The servers on the list, only the ones running the project `project_id` if it's specified
+    async fn query(&self, project_id: Option<&str>) -> anyhow::Result<Vec<ServerInfo>>;
+}
+
+/// The server list the server announces itself to, and the client queries
+#[derive(Debug, Clone)]
+pub struct ServerListKey;
+impl SyncAssetKey<Arc<dyn ServerList>> for ServerListKey {}
+
+/// A [ServerList] behind an HTTP API: the servers `POST` their [ServerInfo] as JSON to `{url}/servers`, and withdraw with
+/// a `DELETE` of `{url}/servers/{address}`, and the clients `GET` the array of the servers from `{url}/servers`, with a
+/// `project_id` query parameter to only get the servers of a project
+pub struct HttpServerList {
+    assets: AssetCache,
+    url: String,
+}
+impl HttpServerList {
+    pub fn new(assets: AssetCache, url: &str) -> Self {
+        Self { assets, url: url.trim_end_matches('/').to_string() }
+    }
+}
+#[async_trait::async_trait]
+impl ServerList for HttpServerList {
+    async fn announce(&self, info: &ServerInfo) -> anyhow::Result<()> {
+        let client = ReqwestClientKey.get(&self.assets);
+        client.post(format!("{}/servers", self.url)).json(info).send().await?.error_for_status()?;
+        Ok(())
+    }
+    async fn withdraw(&self, address: &str) -> anyhow::Result<()> {
+        let client = ReqwestClientKey.get(&self.assets);
+        client.delete(server_url(&self.url, address)).send().await?.error_for_status()?;
+        Ok(())
+    }
+    async fn query(&self, project_id: Option<&str>) -> anyhow::Result<Vec<ServerInfo>> {
+        let client = ReqwestClientKey.get(&self.assets);
+        let mut request = client.get(format!("{}/servers", self.url));
+        if let Some(project_id) = project_id {
+            request = request.query(&[("project_id", project_id)]);
+        }
+        Ok(request.send().await?.error_for_status()?.json().await?)
+    }
+}
+
+/// The URL of the entry of the server joined at `address` on the server list at `url`; the address is escaped, as it may
+/// contain characters which mean something else in a URL (e.g. the brackets of an IPv6 address)
+fn server_url(url: &str, address: &str) -> String {
+    format!("{url}/servers/{}", utf8_percent_encode(address, PATH_SEGMENT))
+}
+
+/// Whether `ip` is on the local network (or the same host), which are the only ones whose probes are answered
+fn is_local_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_loopback() || ip.is_link_local(),
+        // Loopback, unique local (fc00::/7) and link local (fe80::/10)
+        IpAddr::V6(ip) => ip.is_loopback() || ip.segments()[0] & 0xfe00 == 0xfc00 || ip.segments()[0] & 0xffc0 == 0xfe80,
+    }
+}
+
+/// Answers the clients on the local network, and announces the server to `server_list`, until the future is dropped
+pub(crate) async fn announce(
+    announcement: ServerAnnouncement,
+    port: u16,
+    state: SharedServerState,
+    server_list: Option<Arc<dyn ServerList>>,
+) {
+    let lan = async {
+        if !announcement.lan {
+            return;
+        }
+        let socket = match bind_discovery_socket().await {
+            Ok(socket) => socket,
+            Err(err) => {
+                log::warn!("Failed to listen for the clients on the local network: {err:#}");
+                return;
+            }
+        };
+        let server_id = rand::random();
+        let mut buf = vec![0; u16::MAX as usize];
+        // The start of the current second, and the probes answered since
+        let mut answered = (Instant::now(), 0);
+        loop {
+            let (len, addr) = match socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(err) => {
+                    log::debug!("Failed to receive a discovery probe: {err}");
+                    continue;
+                }
+            };
+            if &buf[..len] != DISCOVERY_MAGIC || !is_local_address(addr.ip()) {
+                continue;
+            }
+            if answered.0.elapsed() >= Duration::from_secs(1) {
+                answered = (Instant::now(), 0);
+            }
+            if answered.1 >= MAX_ANSWERS_PER_SECOND {
+                continue;
+            }
+            answered.1 += 1;
+            let info = announcement.info(state.lock().player_count());
+            let mut answer = DISCOVERY_MAGIC.to_vec();
+            match bincode::serialize_into(&mut answer, &LanAnswer { server_id, port, info }) {
+                Ok(()) => {
+                    socket.send_to(&answer, addr).await.ok();
+                }
+                Err(err) => log::warn!("Failed to encode the discovery answer: {err:?}"),
+            }
+        }
+    };
+    let list = async {
+        let server_list = match &server_list {
+            Some(server_list) => server_list,
+            None => return,
+        };
+        let mut interval = tokio::time::interval(ANNOUNCE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let info = announcement.info(state.lock().player_count());
+            if let Err(err) = server_list.announce(&info).await {
+                log::warn!("Failed to announce the server to the server list: {err:#}");
+            }
+        }
+    };
+    future::join(lan, list).await;
+}
+
+/// Listens on all the interfaces, as that's where the broadcasts arrive; the probes from outside of the local network are
+/// ignored by [is_local_address]
+async fn bind_discovery_socket() -> anyhow::Result<UdpSocket> {
+    for port in DISCOVERY_PORTS {
+        if let Ok(socket) = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port)).await {
+            if let Err(err) = socket.join_multicast_v4(DISCOVERY_MULTICAST_ADDR, Ipv4Addr::UNSPECIFIED) {
+                log::debug!("Failed to join the discovery multicast group: {err}");
+            }
+            return Ok(socket);
+        }
+    }
+    bail!("All the discovery ports ({DISCOVERY_PORTS:?}) are taken")
+}
+
+/// Looks for the servers on the local network, collecting their answers for `timeout`
+pub async fn discover_lan_servers(timeout: Duration) -> anyhow::Result<Vec<ServerInfo>> {
+    let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)).await?;
+    socket.set_broadcast(true)?;
+    for port in DISCOVERY_PORTS {
+        for ip in [Ipv4Addr::BROADCAST, DISCOVERY_MULTICAST_ADDR, Ipv4Addr::LOCALHOST] {
+            if let Err(err) = socket.send_to(DISCOVERY_MAGIC, SocketAddr::new(IpAddr::V4(ip), port)).await {
+                log::debug!("Failed to send a discovery probe to {ip}:{port}: {err}");
+            }
+        }
+    }
+
+    let mut servers = Vec::new();
+    let mut server_ids = Vec::new();
+    let mut buf = vec![0; u16::MAX as usize];
+    let deadline = tokio::time::Instant::now() + timeout;
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (len, addr) = match received {
+            Ok(received) => received,
+            Err(err) => {
+                log::debug!("Failed to receive a discovery answer: {err}");
+                continue;
+            }
+        };
+        let answer = match buf[..len].strip_prefix(DISCOVERY_MAGIC).and_then(|data| bincode::deserialize::<LanAnswer>(data).ok()) {
+            Some(answer) => answer,
+            None => continue,
+        };
+        if server_ids.contains(&answer.server_id) {
+            continue;
+        }
+        server_ids.push(answer.server_id);
+        servers.push(ServerInfo { address: SocketAddr::new(addr.ip(), answer.port).to_string(), ..answer.info });
+    }
+    Ok(servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::server::ServerState;
+
+    #[test]
+    fn only_local_addresses_are_answered() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.20", "169.254.0.1", "::1", "fd00::1", "fe80::1"] {
+            assert!(is_local_address(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["8.8.8.8", "172.32.0.1", "2001:db8::1", "2606:4700::1111"] {
+            assert!(!is_local_address(ip.parse().unwrap()), "{ip}");
+        }
+        assert!(!is_local_address(IpAddr::V6(Ipv6Addr::new(0x2a00, 0, 0, 0, 0, 0, 0, 1))));
+    }
+
+    #[test]
+    fn server_addresses_are_escaped() {
+        assert_eq!(server_url("https://example.com", "192.168.1.20:9000"), "https://example.com/servers/192.168.1.20%3A9000");
+        assert_eq!(server_url("https://example.com", "[::1]:9000"), "https://example.com/servers/%5B%3A%3A1%5D%3A9000");
+        assert_eq!(server_url("https://example.com", "a/b?c#d"), "https://example.com/servers/a%2Fb%3Fc%23d");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn servers_are_found_on_the_local_network() {
+        let state = Arc::new(Mutex::new(ServerState::new_local()));
+        let announcement = ServerAnnouncement {
+            name: "servers_are_found_on_the_local_network".to_string(),
+            project_id: "discovery_test".to_string(),
+            max_players: Some(8),
+            address: "example.com:9000".to_string(),
+            lan: true,
+        };
+        let server = tokio::spawn(announce(announcement.clone(), 9123, state, None));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let servers = discover_lan_servers(Duration::from_millis(500)).await.unwrap();
+        server.abort();
+        let server = servers.iter().find(|server| server.name == announcement.name).unwrap();
+        // On the local network, the server is joined at the address it answered from
+        assert!(server.address.ends_with(":9123"), "{}", server.address);
+        assert_eq!((server.project_id.as_str(), server.players, server.max_players), ("discovery_test", 0, Some(8)));
+    }
+}
@@ -0,0 +1,354 @@
+//! Smooths the movement of the entities moved by the server, which would otherwise only move when a diff arrives (at
+//! the tick rate of the server).
+//!
+//! The client buffers the transforms the server sends, timed by when the server sent them, and shows the entities as they
+//! were [InterpolationSettings::delay] ago, interpolating between the two snapshots around that time. If the next snapshot is late, the entity keeps moving
+//! the way it was for up to [InterpolationSettings::max_extrapolation]. The entities which the client owns (see
+//! [crate::ownership]) are shown as it simulates them.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+use ambient_core::transform::{rotation, scale, translation};
+use ambient_ecs::{
+    components, Debuggable, Description, EntityId, FnSystem, Name, Networked, Resource, SystemGroup, World, WorldChange, WorldDiff,
+};
+use ambient_sys::time::Instant;
+use glam::{Quat, Vec3};
+
+use crate::ownership::is_locally_owned;
+
+components!("network", {
+    @[
+        Debuggable, Networked,
+        Name["No interpolation"],
+        Description["If attached, the clients show the transform of this entity as the server sends it, instead of interpolating between the last ones.\nUseful for the entities which are teleported, or predicted by the clients."]
+    ]
+    no_interpolation: (),
+    @[Resource]
+    interpolation_settings: InterpolationSettings,
+    @[Resource]
+    interpolation_state: InterpolationState,
+});
+
+/// How far back the snapshots are kept at most
+const MAX_SNAPSHOTS: usize = 32;
+/// How many of the last diffs the clock of the server is estimated from
+const CLOCK_SAMPLES: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterpolationSettings {
+    /// How far in the past the entities are shown. Should be at least one tick of the server, plus some margin for the
+    /// jitter of the network, so that there's usually a snapshot after the time which is shown.
+    pub delay: Duration,
+    /// How far the entities keep moving past their last snapshot, when the next one is late
+    pub max_extrapolation: Duration,
+}
+impl Default for InterpolationSettings {
+    fn default() -> Self {
+        Self { delay: Duration::from_millis(100), max_extrapolation: Duration::from_millis(100) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Pose {
+    translation: Option<Vec3>,
+    rotation: Option<Quat>,
+    scale: Option<Vec3>,
+}
+impl Pose {
+    fn from_world(world: &World, id: EntityId) -> Self {
+        Self {
+            translation: world.get(id, translation()).ok(),
+            rotation: world.get(id, rotation()).ok(),
+            scale: world.get(id, scale()).ok(),
+        }
+    }
+    fn write_to_world(&self, world: &mut World, id: EntityId) {
+        if let Some(value) = self.translation {
+            world.set(id, translation(), value).ok();
+        }
+        if let Some(value) = self.rotation {
+            world.set(id, rotation(), value).ok();
+        }
+        if let Some(value) = self.scale {
+            world.set(id, scale(), value).ok();
+        }
+    }
+    /// Interpolates from `self` (at `t = 0`) to `other` (at `t = 1`); past 1, it extrapolates
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        fn mix<T: Copy>(a: Option<T>, b: Option<T>, f: impl Fn(T, T) -> T) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(f(a, b)),
+                (_, b) => b,
+            }
+        }
+        Self {
+            translation: mix(self.translation, other.translation, |a, b| a.lerp(b, t)),
+            rotation: mix(self.rotation, other.rotation, |a, b| a.slerp(b, t)),
+            scale: mix(self.scale, other.scale, |a, b| a.lerp(b, t)),
+        }
+    }
+}
+
+/// The transforms of an entity sent by the server, from the oldest one, with the time at which they're shown
+#[derive(Debug, Clone, Default)]
+struct Snapshots(VecDeque<(Instant, Pose)>);
+impl Snapshots {
+    fn push(&mut self, time: Instant, pose: Pose) {
+        // The estimate of the clock of the server may move back a little
+        let time = self.0.back().map_or(time, |&(last, _)| time.max(last));
+        self.0.push_back((time, pose));
+        if self.0.len() > MAX_SNAPSHOTS {
+            self.0.pop_front();
+        }
+    }
+    fn last(&self) -> Pose {
+        self.0.back().unwrap().1
+    }
+    /// The pose at `time`, extrapolated for up to `max_extrapolation` past the last snapshot
+    fn sample(&mut self, time: Instant, max_extrapolation: Duration) -> Pose {
+        // Only the snapshot just before `time` is needed from the ones before it
+        while self.0.len() > 2 && self.0[1].0 <= time {
+            self.0.pop_front();
+        }
+        let (from_time, from) = self.0[0];
+        if self.0.len() == 1 || time <= from_time {
+            return from;
+        }
+        let (to_time, to) = self.0[1];
+        let span = to_time.duration_since(from_time).as_secs_f32();
+        if span <= 0. {
+            return to;
+        }
+        let time = time.min(to_time + max_extrapolation);
+        from.lerp(&to, time.duration_since(from_time).as_secs_f32() / span)
+    }
+    /// Whether the entity stopped at its last snapshot and `time` is past it, or the snapshots stopped coming for longer
+    /// than `max_extrapolation`, in which case the entity is assumed to have stopped at the last one
+    fn is_settled(&self, time: Instant, max_extrapolation: Duration) -> bool {
+        let len = self.0.len();
+        let (last_time, last) = self.0[len - 1];
+        (len >= 2 && last == self.0[len - 2].1 && last_time <= time) || time > last_time + max_extrapolation
+    }
+}
+
+/// Turns the times of the server into local times. The diffs take some time to arrive, which varies from one to the next,
+/// so the server sent each diff at least as long before it arrived as the diff which arrived the fastest after being sent.
+#[derive(Debug, Clone, Default)]
+struct ServerClock {
+    origin: Option<Instant>,
+    last_server_time: Duration,
+    /// The time after `origin` at which the time of the server was 0, as measured by each of the last diffs
+    offsets: VecDeque<f64>,
+}
+impl ServerClock {
+    /// The local time of `server_time`, measured by a diff sent at that time which arrived at `now`
+    fn local_time(&mut self, server_time: Duration, now: Instant) -> Instant {
+        let origin = *self.origin.get_or_insert(now);
+        // The server restarted, or the session is being replayed from the start
+        if server_time < self.last_server_time {
+            self.offsets.clear();
+        }
+        self.last_server_time = server_time;
+        self.offsets.push_back(now.duration_since(origin).as_secs_f64() - server_time.as_secs_f64());
+        if self.offsets.len() > CLOCK_SAMPLES {
+            self.offsets.pop_front();
+        }
+        let offset = self.offsets.iter().copied().fold(f64::INFINITY, f64::min);
+        origin + Duration::from_secs_f64((server_time.as_secs_f64() + offset).max(0.))
+    }
+}
+
+/// The snapshots of the entities which are moving
+#[derive(Debug, Clone, Default)]
+pub struct InterpolationState {
+    entities: HashMap<EntityId, Snapshots>,
+    last_diff: Option<Instant>,
+    clock: ServerClock,
+}
+
+/// The transforms of the entities moved by a diff, before it's applied
+#[derive(Debug)]
+pub struct MovedEntities(Vec<(EntityId, Pose)>);
+
+/// The entities whose transform is changed by `diff`, with their transform before it's applied.
+///
+/// The interpolated entities are put back where the server last sent them, so that the components which `diff` doesn't
+/// change keep the values of the server.
+pub fn before_server_diff(world: &mut World, diff: &WorldDiff) -> MovedEntities {
+    let latest = match world.resource_opt(interpolation_state()) {
+        Some(state) => state.entities.iter().map(|(id, snapshots)| (*id, snapshots.last())).collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
+    for (id, pose) in latest {
+        pose.write_to_world(world, id);
+    }
+    let components = [translation().index(), rotation().index(), scale().index()];
+    let mut moved = diff
+        .changes
+        .iter()
+        .filter_map(|change| match change {
+            WorldChange::Set(id, entry) if components.contains(&entry.index()) => Some(*id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    moved.sort();
+    moved.dedup();
+    MovedEntities(moved.into_iter().filter(|id| world.exists(*id)).map(|id| (id, Pose::from_world(world, id))).collect())
+}
+
+/// Records the transforms sent by the server at its `server_time` in a diff which has just been applied to `world`. `moved`
+/// are the entities returned by [before_server_diff] for it.
+pub fn after_server_diff(world: &mut World, moved: MovedEntities, server_time: Duration) {
+    after_server_diff_at(world, moved, server_time, Instant::now())
+}
+fn after_server_diff_at(world: &mut World, moved: MovedEntities, server_time: Duration, now: Instant) {
+    let settings = world.resource_opt(interpolation_settings()).copied().unwrap_or_default();
+    let poses = moved
+        .0
+        .into_iter()
+        .filter(|(id, _)| !world.has_component(*id, no_interpolation()) && !is_locally_owned(world, *id))
+        .map(|(id, previous)| (id, previous, Pose::from_world(world, id)))
+        .collect::<Vec<_>>();
+    let state = match world.resource_mut_opt(interpolation_state()) {
+        Some(state) => state,
+        None => return,
+    };
+    let shown_at = state.clock.local_time(server_time, now) + settings.delay;
+    let last_diff = state.last_diff.replace(shown_at);
+    let mut moved = HashSet::new();
+    for (id, previous, pose) in poses {
+        moved.insert(id);
+        let snapshots = state.entities.entry(id).or_insert_with(|| {
+            // The entity starts moving from where it was at the previous diff
+            let mut snapshots = Snapshots::default();
+            snapshots.push(last_diff.unwrap_or(shown_at), previous);
+            snapshots
+        });
+        snapshots.push(shown_at, pose);
+    }
+    // The entities which aren't in the diff were at the same place on the server at the time of this diff
+    for (id, snapshots) in &mut state.entities {
+        if !moved.contains(id) {
+            let last = snapshots.last();
+            snapshots.push(shown_at, last);
+        }
+    }
+}
+
+/// Shows the entities moved by the server where they were [InterpolationSettings::delay] ago
+pub fn client_systems() -> SystemGroup {
+    SystemGroup::new("network/interpolation", vec![Box::new(FnSystem::new(|world, _| interpolate(world, Instant::now())))])
+}
+
+/// Shows the entities moved by the server as they were [InterpolationSettings::delay] before `time`
+fn interpolate(world: &mut World, time: Instant) {
+    let settings = world.resource_opt(interpolation_settings()).copied().unwrap_or_default();
+    let mut state = match world.resource_mut_opt(interpolation_state()) {
+        Some(state) => std::mem::take(&mut state.entities),
+        None => return,
+    };
+    state.retain(|&id, snapshots| {
+        if !world.exists(id) || world.has_component(id, no_interpolation()) || is_locally_owned(world, id) {
+            return false;
+        }
+        if snapshots.is_settled(time, settings.max_extrapolation) {
+            snapshots.last().write_to_world(world, id);
+            return false;
+        }
+        snapshots.sample(time, settings.max_extrapolation).write_to_world(world, id);
+        true
+    });
+    world.resource_mut(interpolation_state()).entities = state;
+}
+
+#[cfg(test)]
+mod tests {
+    use ambient_ecs::EntityData;
+    use glam::vec3;
+
+    use super::*;
+
+    fn world() -> (World, EntityId) {
+        ambient_core::init_all_components();
+        crate::init_all_components();
+        let mut world = World::new("interpolation");
+        world.add_resource(interpolation_state(), InterpolationState::default());
+        let id = world.spawn(EntityData::new().set(translation(), Vec3::ZERO));
+        (world, id)
+    }
+
+    /// Applies a diff which the server sent at `server_time` (in ms), moving `id` to `x`, and which arrived at `arrival`
+    fn receive(world: &mut World, id: EntityId, x: f32, server_time: u64, arrival: Instant) {
+        let diff = WorldDiff::new().set(id, translation(), vec3(x, 0., 0.));
+        let moved = before_server_diff(world, &diff);
+        diff.apply(world, EntityData::new(), false);
+        after_server_diff_at(world, moved, Duration::from_millis(server_time), arrival);
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{a} != {b}");
+    }
+
+    #[test]
+    fn snapshots_are_timed_by_the_server() {
+        let (mut world, id) = world();
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        // Sent every 50ms, with 30ms of jitter on the second one
+        receive(&mut world, id, 0., 1000, start + ms(10));
+        receive(&mut world, id, 5., 1050, start + ms(90));
+        receive(&mut world, id, 10., 1100, start + ms(110));
+
+        let delay = InterpolationSettings::default().delay;
+        let times = world.resource(interpolation_state()).entities[&id].0.iter().map(|(time, _)| *time).collect::<Vec<_>>();
+        let expected = [0, 0, 50, 100].map(|time| start + ms(10) + delay + ms(time));
+        for (time, expected) in times.into_iter().zip(expected) {
+            assert!(time.max(expected).duration_since(time.min(expected)) < ms(1));
+        }
+
+        // Halfway between the second and the third snapshots, as sent by the server
+        interpolate(&mut world, start + ms(10) + delay + ms(75));
+        assert_near(world.get(id, translation()).unwrap().x, 7.5);
+    }
+
+    #[test]
+    fn the_clock_follows_the_fastest_diffs() {
+        let mut clock = ServerClock::default();
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        assert_eq!(clock.local_time(ms(500), start + ms(40)), start + ms(40));
+        // A diff which arrived faster than the first one moves the clock back
+        let time = clock.local_time(ms(600), start + ms(120));
+        assert!(time.duration_since(start + ms(120)) < ms(1));
+        let time = clock.local_time(ms(700), start + ms(260));
+        assert!(time.duration_since(start + ms(220)) < ms(1));
+        // The server restarted
+        let time = clock.local_time(ms(10), start + ms(300));
+        assert!(time.duration_since(start + ms(300)) < ms(1));
+    }
+
+    #[test]
+    fn entities_extrapolate_then_settle() {
+        let (mut world, id) = world();
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let settings = InterpolationSettings::default();
+        receive(&mut world, id, 0., 0, start);
+        receive(&mut world, id, 10., 100, start + ms(100));
+        let last = start + settings.delay + ms(100);
+
+        interpolate(&mut world, last + ms(50));
+        assert_near(world.get(id, translation()).unwrap().x, 15.);
+        // The extrapolation stops after the cap
+        interpolate(&mut world, last + settings.max_extrapolation);
+        assert_near(world.get(id, translation()).unwrap().x, 20.);
+        // And the entity is put back at its last snapshot when the next one is too late
+        interpolate(&mut world, last + settings.max_extrapolation + ms(1));
+        assert_eq!(world.get(id, translation()).unwrap(), vec3(10., 0., 0.));
+        assert!(world.resource(interpolation_state()).entities.is_empty());
+    }
+}
@@ -14,10 +14,7 @@ use ambient_std::{asset_cache::AssetCache, log_error, log_result};
 use bytes::Bytes;
 use client::GameRpcArgs;
 use futures::{Future, SinkExt, StreamExt};
-use quinn::{
-    ClientConfig, Connection, ConnectionClose, ConnectionError::ConnectionClosed, Endpoint, Incoming, NewConnection, RecvStream,
-    SendStream, ServerConfig, TransportConfig,
-};
+use quinn::{ClientConfig, ConnectionClose, ConnectionError::ConnectionClosed, Endpoint, Incoming, ServerConfig, TransportConfig};
 use rand::Rng;
 use rustls::{Certificate, PrivateKey, RootCertStore};
 use serde::{de::DeserializeOwned, Serialize};
@@ -25,18 +22,38 @@ use server::SharedServerState;
 use thiserror::Error;
 use tokio::io::AsyncWriteExt;
 use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
+use transport::{Connection, NewConnection, RecvStream, SendStream};
 
 pub type AsyncMutex<T> = tokio::sync::Mutex<T>;
+pub mod asset_stream;
+pub mod auth;
+pub mod channels;
 pub mod client;
 pub mod client_game_state;
+pub mod conditioner;
+pub mod delta;
+pub mod discovery;
 pub mod events;
 pub mod hooks;
+pub mod interpolation;
+pub mod messages;
+pub mod movement;
+pub mod ownership;
 pub mod protocol;
+pub mod recording;
+pub mod relay;
+pub mod relevance;
+pub mod replication;
+pub mod rooms;
 pub mod rpc;
 pub mod server;
+pub mod stats;
+pub mod status;
+pub mod transport;
+pub mod websocket;
 
 pub mod player {
-    use ambient_ecs::{components, Description, Name, Networked, Resource, Store};
+    use ambient_ecs::{components, Debuggable, Description, Name, Networked, Resource, Store};
 
     components!("player", {
         @[
@@ -51,6 +68,18 @@ pub mod player {
             Description["An identifier attached to all things owned by a user, and supplied by the user.\nThis can be attached to more than just the player; by convention, it is also attached to related entities, including their camera and body."]
         ]
         user_id: String,
+        @[
+            Networked, Debuggable,
+            Name["User display name"],
+            Description["The name to show for the user of this player, if the server authenticated them with one."]
+        ]
+        user_display_name: String,
+        @[
+            Networked, Debuggable,
+            Name["User roles"],
+            Description["What the server authenticated the user of this player as, e.g. `admin` or `moderator`; the gameplay code decides what they may do.\nEmpty unless the server authenticates the players with tokens."]
+        ]
+        user_roles: Vec<String>,
         @[
             Networked, Store, Resource,
             Name["Local user ID"],
@@ -92,9 +121,21 @@ components!("network", {
 
 pub fn init_all_components() {
     init_components();
+    channels::init_components();
     client::init_components();
+    conditioner::init_components();
+    delta::init_components();
     events::init_components();
+    interpolation::init_components();
+    messages::init_components();
+    movement::init_components();
+    ownership::init_components();
+    recording::init_components();
+    relevance::init_components();
+    replication::init_components();
+    rooms::init_components();
     server::init_components();
+    stats::init_components();
     client_game_state::init_components();
     player::init_components();
 }
@@ -171,14 +212,13 @@ pub async fn rpc_request<
     req: Req,
     size_limit: usize,
 ) -> Result<Resp, NetworkError> {
-    let stream = conn.open_bi();
-    let (mut send, recv) = stream.await.map_err(NetworkError::ConnectionError)?;
+    let (mut send, recv) = conn.open_bi().await?;
     send.write_u32(RPC_STREAM_ID).await?;
     let req = reg.serialize_req(func, req);
-    send.write_all(&req).await.map_err(NetworkError::from)?;
-    send.finish().await.map_err(NetworkError::from)?;
+    send.write_all(&req).await?;
+    send.finish().await?;
     drop(send);
-    let resp = recv.read_to_end(size_limit).await.map_err(NetworkError::from)?;
+    let resp = recv.read_to_end(size_limit).await?;
     let resp = reg.deserialize_resp(func, &resp)?;
     Ok(resp)
 }
@@ -223,6 +263,14 @@ pub enum NetworkError {
     WriteError(#[from] quinn::WriteError),
     #[error(transparent)]
     RpcError(#[from] RpcError),
+    #[error("Authentication failed: {0}")]
+    AuthenticationFailed(String),
+    #[error("Connection refused: {0}")]
+    ConnectionRefused(String),
+    #[error("Incompatible version: {0}")]
+    IncompatibleVersion(String),
+    #[error("Refused by the relay: {0}")]
+    RelayRefused(String),
 }
 
 impl NetworkError {
@@ -248,12 +296,31 @@ impl NetworkError {
     pub fn is_end_of_stream(&self) -> bool {
         matches!(self, Self::EndOfStream)
     }
+
+    /// Returns true if the connection was lost, e.g. it timed out or was reset, rather than closed by either side. The
+    /// client may reconnect, and resume its session.
+    pub fn is_connection_lost(&self) -> bool {
+        let connection_error = match self {
+            Self::ConnectionError(err) | Self::WriteError(quinn::WriteError::ConnectionLost(err)) => err,
+            Self::ReadToEndError(quinn::ReadToEndError::Read(quinn::ReadError::ConnectionLost(err))) => err,
+            // The streams of QUIC wrap their errors
+            Self::IOError(err) => match err.get_ref() {
+                Some(inner) => match (inner.downcast_ref::<quinn::WriteError>(), inner.downcast_ref::<quinn::ReadError>()) {
+                    (Some(quinn::WriteError::ConnectionLost(err)), _) | (_, Some(quinn::ReadError::ConnectionLost(err))) => err,
+                    _ => return false,
+                },
+                None => return matches!(err.kind(), ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe | ErrorKind::TimedOut),
+            },
+            _ => return false,
+        };
+        matches!(connection_error, quinn::ConnectionError::TimedOut | quinn::ConnectionError::Reset)
+    }
 }
 
 /// Abstracts the serialization for a fixed size stream.
 #[derive(Debug)]
 pub struct IncomingStream {
-    pub stream: FramedRead<quinn::RecvStream, LengthDelimitedCodec>,
+    pub stream: FramedRead<RecvStream, LengthDelimitedCodec>,
 }
 impl IncomingStream {
     /// Accept a new uni-directional peer stream. Waits for the server to open a
@@ -263,7 +330,7 @@ impl IncomingStream {
         Ok(Self::new(stream))
     }
 
-    pub fn new(stream: quinn::RecvStream) -> Self {
+    pub fn new(stream: RecvStream) -> Self {
         let mut codec = LengthDelimitedCodec::new();
         codec.set_max_frame_length(1_024 * 1_024 * 1_024);
         Self { stream: FramedRead::new(stream, codec) }
@@ -287,14 +354,14 @@ impl IncomingStream {
 
 #[derive(Debug)]
 pub struct OutgoingStream {
-    pub stream: FramedWrite<quinn::SendStream, LengthDelimitedCodec>,
+    pub stream: FramedWrite<SendStream, LengthDelimitedCodec>,
 }
 impl OutgoingStream {
     pub async fn open_uni(conn: &Connection) -> Result<Self, NetworkError> {
         Ok(OutgoingStream::new(conn.open_uni().await?))
     }
 
-    pub fn new(stream: quinn::SendStream) -> Self {
+    pub fn new(stream: SendStream) -> Self {
         let mut codec = LengthDelimitedCodec::new();
         codec.set_max_frame_length(1_024 * 1_024 * 1_024);
         Self { stream: FramedWrite::new(stream, codec) }
@@ -359,11 +426,7 @@ pub fn create_client_endpoint_random_port() -> Option<Endpoint> {
             let crypto = rustls::ClientConfig::builder().with_safe_defaults().with_root_certificates(roots).with_no_client_auth();
             let mut transport = TransportConfig::default();
             transport.keep_alive_interval(Some(Duration::from_secs_f32(1.)));
-            if std::env::var("DIMS_DISABLE_TIMEOUT").is_ok() {
-                transport.max_idle_timeout(None);
-            } else {
-                transport.max_idle_timeout(Some(Duration::from_secs_f32(60.).try_into().unwrap()));
-            }
+            transport.max_idle_timeout(idle_timeout().map(|timeout| timeout.try_into().unwrap()));
             let mut client_config = ClientConfig::new(Arc::new(crypto));
             client_config.transport = Arc::new(transport);
 
@@ -379,13 +442,19 @@ fn create_server(server_addr: SocketAddr) -> anyhow::Result<(Endpoint, Incoming)
     let cert_key = PrivateKey(CERT_KEY.to_vec());
     let mut server_conf = ServerConfig::with_single_cert(vec![cert], cert_key)?;
     let mut transport = TransportConfig::default();
+    transport.max_idle_timeout(idle_timeout().map(TryInto::try_into).transpose()?);
+    server_conf.transport = Arc::new(transport);
+    Ok(Endpoint::server(server_conf, server_addr)?)
+}
+
+/// How long a connection can go without hearing from the peer before it's closed. `DIMS_DISABLE_TIMEOUT` disables it,
+/// e.g. to be able to pause in a debugger.
+pub(crate) fn idle_timeout() -> Option<Duration> {
     if std::env::var("DIMS_DISABLE_TIMEOUT").is_ok() {
-        transport.max_idle_timeout(None);
+        None
     } else {
-        transport.max_idle_timeout(Some(Duration::from_secs_f32(60.).try_into()?));
+        Some(Duration::from_secs_f32(60.))
     }
-    server_conf.transport = Arc::new(transport);
-    Ok(Endpoint::server(server_conf, server_addr)?)
 }
 
 pub const CERT: &[u8] = include_bytes!("./cert.der");
@@ -424,3 +493,37 @@ macro_rules! unwrap_log_network_err {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lost_connections() {
+        use quinn::{ConnectionError, ReadError, ReadToEndError, WriteError};
+
+        let lost = [
+            NetworkError::ConnectionError(ConnectionError::TimedOut),
+            NetworkError::ConnectionError(ConnectionError::Reset),
+            NetworkError::WriteError(WriteError::ConnectionLost(ConnectionError::TimedOut)),
+            NetworkError::ReadToEndError(ReadToEndError::Read(ReadError::ConnectionLost(ConnectionError::Reset))),
+            NetworkError::IOError(std::io::Error::new(ErrorKind::Other, ReadError::ConnectionLost(ConnectionError::TimedOut))),
+            NetworkError::IOError(ErrorKind::BrokenPipe.into()),
+        ];
+        for err in lost {
+            assert!(err.is_connection_lost(), "{err:?}");
+        }
+
+        let closed = [
+            NetworkError::EndOfStream,
+            NetworkError::ConnectionClosed,
+            NetworkError::ConnectionError(ConnectionError::LocallyClosed),
+            NetworkError::WriteError(WriteError::ConnectionLost(ConnectionError::LocallyClosed)),
+            NetworkError::IOError(std::io::Error::new(ErrorKind::Other, WriteError::UnknownStream)),
+            NetworkError::IOError(ErrorKind::ConnectionReset.into()),
+        ];
+        for err in closed {
+            assert!(!err.is_connection_lost(), "{err:?}");
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Messages between the scripts of the server and the clients, for the one-shot commands which don't belong in a
+//! replicated component.
+//!
+//! Each message is declared with a [MessageType]: the server declares the ones of the project in its [message_types],
+//! and so do the clients which run the project. A message carries the version of its type, and the messages of another
+//! version than the declared one are dropped, so that an outdated client can't confuse the server. The reliable
+//! messages of each end go on a single stream, so they arrive in the order they were sent, and the unreliable ones in a
+//! datagram, which they must fit in; they may be lost or arrive out of order.
+//!
+//! On both ends, the messages received are [WorldEvent]s named after the message with a [MESSAGE_EVENT_PREFIX], which is
+//! how they reach the WASM modules of the server; the server adds the `user_id` of the sender to their data.
+
+use std::{collections::HashMap, sync::Arc};
+
+use ambient_ecs::{components, query, world_events, EntityData, EntityId, Resource, World, WorldEvent};
+use ambient_std::log_result;
+use anyhow::Context;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    events::send_event,
+    player::{player, user_id},
+    server::player_datagram_stream,
+    transport::Connection,
+    AsyncMutex, BiStreamHandlers, DatagramHandlers, IncomingStream, NetworkError, OutgoingStream,
+};
+
+components!("network", {
+    /// The messages declared on this end of the connection
+    @[Resource]
+    message_types: MessageTypes,
+});
+
+/// The prefix of the names of the events which the messages received become
+pub const MESSAGE_EVENT_PREFIX: &str = "message/";
+/// The id of the streams of the reliable messages
+pub const MESSAGE_STREAM_ID: u32 = 2;
+/// The handler id of the datagrams of the unreliable messages
+pub const MESSAGE_DATAGRAM_ID: u32 = 2;
+
+/// The size limit of a message sent reliably
+const MESSAGE_SIZE_LIMIT: usize = 10_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageType {
+    pub name: String,
+    /// Bumped when the components of the message change
+    pub version: u32,
+    /// Whether the message is delivered reliably and in order with the other reliable messages, or may be lost
+    pub reliable: bool,
+}
+impl MessageType {
+    pub fn new(name: impl Into<String>, version: u32, reliable: bool) -> Self {
+        Self { name: name.into(), version, reliable }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MessageTypes(HashMap<String, MessageType>);
+impl MessageTypes {
+    /// Declares `message_type`, replacing the type with the same name if any
+    pub fn declare(&mut self, message_type: MessageType) {
+        self.0.insert(message_type.name.clone(), message_type);
+    }
+    pub fn get(&self, name: &str) -> Option<&MessageType> {
+        self.0.get(name)
+    }
+    /// Checks that `message` is of a declared type, with the same version
+    fn check(&self, message: &Message) -> anyhow::Result<()> {
+        let message_type = self.get(&message.name).with_context(|| format!("The message {:?} isn't declared", message.name))?;
+        anyhow::ensure!(
+            message_type.version == message.version,
+            "The message {:?} is of version {}, but version {} is declared",
+            message.name,
+            message.version,
+            message_type.version
+        );
+        Ok(())
+    }
+}
+impl FromIterator<MessageType> for MessageTypes {
+    fn from_iter<T: IntoIterator<Item = MessageType>>(iter: T) -> Self {
+        let mut types = Self::default();
+        for message_type in iter {
+            types.declare(message_type);
+        }
+        types
+    }
+}
+
+/// A message as it's sent over the network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub name: String,
+    pub version: u32,
+    pub data: EntityData,
+}
+impl Message {
+    pub fn new(message_type: &MessageType, data: EntityData) -> Self {
+        Self { name: message_type.name.clone(), version: message_type.version, data }
+    }
+    pub fn event_name(&self) -> String {
+        format!("{MESSAGE_EVENT_PREFIX}{}", self.name)
+    }
+    fn to_datagram(&self) -> bincode::Result<Bytes> {
+        let mut data = MESSAGE_DATAGRAM_ID.to_be_bytes().to_vec();
+        bincode::serialize_into(&mut data, self)?;
+        Ok(data.into())
+    }
+    /// The encoded message in `datagram`, if it carries one
+    pub fn payload_of_datagram(datagram: &[u8]) -> Option<&[u8]> {
+        if datagram.len() < 4 || datagram[0..4] != MESSAGE_DATAGRAM_ID.to_be_bytes() {
+            return None;
+        }
+        Some(&datagram[4..])
+    }
+}
+
+/// Sends a message of the type declared as `name` in the [message_types] of the server `world`, to the player with the
+/// `user_id` of `data`, or to all the players if it has none
+pub fn send_to_players(world: &World, name: &str, mut data: EntityData) -> anyhow::Result<()> {
+    let message_type = world
+        .resource_opt(message_types())
+        .and_then(|types| types.get(name))
+        .with_context(|| format!("The message {name:?} isn't declared"))?;
+    let target = data.remove_self(user_id());
+    let message = Message::new(message_type, data);
+    let datagram = if message_type.reliable { None } else { Some(message.to_datagram()?) };
+
+    for (id, player_user_id) in query(user_id()).incl(player()).iter(world, None) {
+        if target.as_ref().map(|target| target != player_user_id).unwrap_or(false) {
+            continue;
+        }
+        match &datagram {
+            Some(datagram) => send_datagram(world, id, datagram.clone()),
+            None => send_event(world, id, message.clone()),
+        }
+    }
+    Ok(())
+}
+
+fn send_datagram(world: &World, player_id: EntityId, datagram: Bytes) {
+    if let Ok(tx) = world.get_ref(player_id, player_datagram_stream()) {
+        if tx.send(datagram).is_err() {
+            log::warn!("Attempt to send a message to a disconnected player");
+        }
+    }
+}
+
+/// The stream the client sends its reliable messages to the server on, opened with the first one
+#[derive(Debug, Clone, Default)]
+pub struct MessageStream(Arc<AsyncMutex<Option<OutgoingStream>>>);
+impl MessageStream {
+    /// Sends `message` to the server over `connection`, reliably or not as its type is declared
+    pub async fn send(&self, connection: &Connection, message: &Message, reliable: bool) -> Result<(), NetworkError> {
+        if !reliable {
+            return connection.send_datagram(message.to_datagram()?);
+        }
+        let mut stream = self.0.lock().await;
+        if stream.is_none() {
+            let (mut send, _recv) = connection.open_bi().await?;
+            send.write_u32(MESSAGE_STREAM_ID).await?;
+            *stream = Some(OutgoingStream::new(send));
+        }
+        stream.as_mut().unwrap().send(message).await
+    }
+}
+
+/// Receives the messages of the server on the client, as registered in its [crate::events::ServerEventRegistry]; the
+/// unreliable ones are handed to the registry too. The messages of the types the client doesn't declare are received
+/// anyway; only the versions of the declared ones are checked.
+pub fn receive_from_server(world: &mut World, message: Message) -> anyhow::Result<()> {
+    if let Some(types) = world.resource_opt(message_types()) {
+        if types.get(&message.name).is_some() {
+            types.check(&message)?;
+        }
+    }
+    add_event(world, message);
+    Ok(())
+}
+
+/// Registers the handlers of the messages the clients send to the server
+pub fn register_handlers(bi_stream_handlers: &mut BiStreamHandlers, datagram_handlers: &mut DatagramHandlers) {
+    bi_stream_handlers.insert(
+        MESSAGE_STREAM_ID,
+        Arc::new(|state, _assets, user_id, _send, recv| {
+            let user_id = user_id.to_string();
+            tokio::spawn(async move {
+                let mut recv = IncomingStream::new(recv);
+                recv.stream.decoder_mut().set_max_frame_length(MESSAGE_SIZE_LIMIT);
+                // The messages are handled one after the other, in the order the client sent them
+                loop {
+                    let message: Message = match recv.next().await {
+                        Ok(message) => message,
+                        Err(NetworkError::EndOfStream) => break,
+                        Err(err) => {
+                            log::warn!("Failed to receive a message from {user_id}: {err:?}");
+                            break;
+                        }
+                    };
+                    let mut state = state.lock();
+                    if let Some(world) = state.get_player_world_mut(&user_id) {
+                        log_result!(receive_from_player(world, &user_id, message));
+                    }
+                }
+            });
+        }),
+    );
+    datagram_handlers.insert(
+        MESSAGE_DATAGRAM_ID,
+        Arc::new(|state, _assets, user_id, data| {
+            let try_block = || {
+                let message: Message = bincode::deserialize(&data)?;
+                let mut state = state.lock();
+                if let Some(world) = state.get_player_world_mut(user_id) {
+                    receive_from_player(world, user_id, message)?;
+                }
+                Ok(()) as anyhow::Result<()>
+            };
+            log_result!(try_block());
+        }),
+    );
+}
+
+fn receive_from_player(world: &mut World, sender: &str, mut message: Message) -> anyhow::Result<()> {
+    let checked = match world.resource_opt(message_types()) {
+        Some(types) => types.check(&message),
+        None => Err(anyhow::anyhow!("No message is declared")),
+    };
+    checked.with_context(|| format!("Dropped a message from {sender}"))?;
+    message.data.set_self(user_id(), sender.to_string());
+    add_event(world, message);
+    Ok(())
+}
+
+fn add_event(world: &mut World, message: Message) {
+    world.resource_mut(world_events()).add_event(WorldEvent { name: message.event_name(), data: message.data });
+}
@@ -0,0 +1,326 @@
+//! Validation of the movements which the clients report for the entities they own (see [crate::ownership]), so that a
+//! modified client can't make its entities run faster than the game allows, teleport, or go through walls.
+//!
+//! Each state sent by an owner is checked against the envelope of its entity before it's applied: the [max_speed], the
+//! [max_acceleration], the [teleport_distance], and with [validate_collisions], the colliders between the last position
+//! and the new one (as checked by the [movement_collision_check] of the world). The envelopes are given a
+//! [MOVEMENT_TOLERANCE] for the jitter of the network, and the distance allowed by the speed builds up for at most
+//! [MAX_MOVEMENT_BURST], so that the states which arrive together after a hiccup aren't taken for a speed hack.
+//!
+//! A movement out of its envelope counts as one of the [movement_violations] of the player, and is handled as the
+//! [movement_violation_response] of the entity says: it's corrected (the state is dropped, and the owner is sent back
+//! the position of the server), only flagged (the state is applied anyway), or the player is kicked.
+
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use ambient_core::transform::translation;
+use ambient_ecs::{components, Debuggable, Description, EntityData, EntityId, Name, Networked, Resource, World};
+use ambient_sys::time::Instant;
+use glam::Vec3;
+use thiserror::Error;
+
+use crate::{get_player_by_user_id, ownership::OWNED_STATE_INTERVAL};
+
+components!("network", {
+    @[
+        Debuggable, Networked,
+        Name["Max speed"],
+        Description["If attached, the server rejects the movements of this entity reported by its owner which are faster than this, in meters per second."]
+    ]
+    max_speed: f32,
+    @[
+        Debuggable, Networked,
+        Name["Max acceleration"],
+        Description["If attached, the server rejects the movements of this entity reported by its owner which change its velocity faster than this, in meters per second squared."]
+    ]
+    max_acceleration: f32,
+    @[
+        Debuggable, Networked,
+        Name["Teleport distance"],
+        Description["If attached, the server rejects the movements of this entity reported by its owner which are longer than this at once, in meters, however long they took."]
+    ]
+    teleport_distance: f32,
+    @[
+        Debuggable, Networked,
+        Name["Validate collisions"],
+        Description["If attached, the server rejects the movements of this entity reported by its owner which go through a collider."]
+    ]
+    validate_collisions: (),
+    @[
+        Debuggable, Networked,
+        Name["Movement violation response"],
+        Description["What the server does when the owner of this entity reports a movement it rejects: 0 to correct it (the default), 1 to only flag it, and 2 to kick the player.\nIn all cases, the violation counts towards the `movement_violations` of the player."]
+    ]
+    movement_violation_response: u32,
+    @[
+        Debuggable,
+        Name["Movement violations"],
+        Description["How many of the movements reported by the client of this player were out of the envelope of their entity."]
+    ]
+    movement_violations: u32,
+
+    /// The movement of an entity since its owner last reported a valid one
+    movement_tracker: MovementTracker,
+    @[Resource]
+    movement_collision_check: MovementCollisionCheck,
+});
+
+/// How much faster than their envelope the movements may be, for the jitter of the network
+pub const MOVEMENT_TOLERANCE: f32 = 1.25;
+/// How long the distance allowed by the [max_speed] builds up for at most
+pub const MAX_MOVEMENT_BURST: Duration = Duration::from_millis(500);
+
+/// What the server does with the movements out of their envelope, as set by the [movement_violation_response]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ViolationResponse {
+    /// The state is dropped, and the owner is sent back the position of the server
+    #[default]
+    Correct,
+    /// The state is applied anyway
+    Flag,
+    /// The player is disconnected
+    Kick,
+}
+impl ViolationResponse {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => Self::Flag,
+            2 => Self::Kick,
+            _ => Self::Correct,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
+pub enum MovementViolation {
+    #[error("Moved {distance}m, but only {allowed}m were allowed")]
+    TooFast { distance: f32, allowed: f32 },
+    #[error("Accelerated at {acceleration}m/s², but the limit is {limit}m/s²")]
+    TooMuchAcceleration { acceleration: f32, limit: f32 },
+    #[error("Teleported {distance}m")]
+    Teleport { distance: f32 },
+    #[error("Went through a collider")]
+    Collision,
+    #[error("Moved to a position which isn't finite")]
+    NotFinite,
+}
+
+/// What to do with a state reported by the owner of an entity
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Accept,
+    Reject,
+    /// The state is rejected, and the player kicked for this reason
+    Kick(String),
+}
+
+/// Checks whether an entity moving from the first position to the second one goes through a collider
+#[derive(Clone)]
+pub struct MovementCollisionCheck(pub Arc<dyn Fn(&World, EntityId, Vec3, Vec3) -> bool + Sync + Send>);
+impl Debug for MovementCollisionCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MovementCollisionCheck").finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MovementTracker {
+    last_time: Instant,
+    /// The distance the entity may still move, as allowed by its [max_speed]
+    allowance: f32,
+    /// The velocity of the last movement, which isn't known before the first one
+    velocity: Option<Vec3>,
+}
+impl MovementTracker {
+    pub fn new(time: Instant) -> Self {
+        // The first movement may use the whole burst
+        Self { last_time: time, allowance: f32::INFINITY, velocity: None }
+    }
+}
+
+/// Checks that moving `id` from `from` to `to` at `time` stays within the envelope of the entity, and updates its
+/// `tracker` if it does
+pub fn check_movement(
+    world: &World,
+    id: EntityId,
+    tracker: &mut MovementTracker,
+    from: Vec3,
+    to: Vec3,
+    time: Instant,
+) -> Result<(), MovementViolation> {
+    if !to.is_finite() {
+        return Err(MovementViolation::NotFinite);
+    }
+    let dt = time.duration_since(tracker.last_time).as_secs_f32();
+    let delta = to - from;
+    let distance = delta.length();
+
+    if let Ok(&teleport_distance) = world.get_ref(id, teleport_distance()) {
+        if distance > teleport_distance {
+            return Err(MovementViolation::Teleport { distance });
+        }
+    }
+    let mut allowance = tracker.allowance;
+    if let Ok(&max_speed) = world.get_ref(id, max_speed()) {
+        let max_speed = max_speed * MOVEMENT_TOLERANCE;
+        allowance = (allowance + max_speed * dt).min(max_speed * MAX_MOVEMENT_BURST.as_secs_f32());
+        if distance > allowance {
+            return Err(MovementViolation::TooFast { distance, allowed: allowance });
+        }
+        allowance -= distance;
+    }
+    // The owner sends its states at most every interval, so each one covers at least that much time
+    let dt = dt.max(OWNED_STATE_INTERVAL.as_secs_f32());
+    let velocity = delta / dt;
+    if let (Ok(&max_acceleration), Some(last_velocity)) = (world.get_ref(id, max_acceleration()), tracker.velocity) {
+        let acceleration = (velocity - last_velocity).length() / dt;
+        let limit = max_acceleration * MOVEMENT_TOLERANCE;
+        if acceleration > limit {
+            return Err(MovementViolation::TooMuchAcceleration { acceleration, limit });
+        }
+    }
+    if world.has_component(id, validate_collisions()) {
+        if let Some(check) = world.resource_opt(movement_collision_check()) {
+            if (check.0)(world, id, from, to) {
+                return Err(MovementViolation::Collision);
+            }
+        }
+    }
+
+    *tracker = MovementTracker { last_time: time, allowance, velocity: Some(velocity) };
+    Ok(())
+}
+
+/// Validates the movement in `data`, a state of `id` reported by its owner `sender`, and counts the violation against the
+/// player if it's out of the envelope of the entity
+pub(crate) fn validate_owned_state(world: &mut World, sender: &str, id: EntityId, data: &EntityData) -> Verdict {
+    let (from, to) = match (world.get(id, translation()), data.get(translation())) {
+        (Ok(from), Some(to)) => (from, to),
+        _ => return Verdict::Accept,
+    };
+    let now = Instant::now();
+    let mut tracker = world.get_cloned(id, movement_tracker()).unwrap_or_else(|_| MovementTracker::new(now));
+    let result = check_movement(world, id, &mut tracker, from, to, now);
+    if world.has_component(id, movement_tracker()) {
+        world.set(id, movement_tracker(), tracker).unwrap();
+    } else {
+        world.add_component(id, movement_tracker(), tracker).unwrap();
+    }
+    let violation = match result {
+        Ok(()) => return Verdict::Accept,
+        Err(violation) => violation,
+    };
+
+    log::warn!("[{sender}] Invalid movement of {id}: {violation}");
+    if let Some(player_id) = get_player_by_user_id(world, sender) {
+        let violations = world.get(player_id, movement_violations()).unwrap_or_default() + 1;
+        world.add_component(player_id, movement_violations(), violations).unwrap();
+    }
+    match ViolationResponse::from_u32(world.get(id, movement_violation_response()).unwrap_or_default()) {
+        // The position can't be applied anyway
+        ViolationResponse::Correct | ViolationResponse::Flag if violation == MovementViolation::NotFinite => {
+            world.set(id, translation(), from).unwrap();
+            Verdict::Reject
+        }
+        ViolationResponse::Correct => {
+            // Setting the position of the server again sends it to the owner
+            world.set(id, translation(), from).unwrap();
+            Verdict::Reject
+        }
+        ViolationResponse::Flag => Verdict::Accept,
+        ViolationResponse::Kick => Verdict::Kick(violation.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+
+    const STEP: Duration = Duration::from_millis(100);
+
+    fn spawn(world: &mut World, envelope: EntityData) -> EntityId {
+        envelope.set(translation(), Vec3::ZERO).spawn(world)
+    }
+
+    #[test]
+    fn teleport() {
+        ambient_core::init_all_components();
+        crate::init_all_components();
+        let mut world = World::new("teleport");
+        let id = spawn(&mut world, EntityData::new().set(teleport_distance(), 5.));
+        let start = Instant::now();
+        let mut tracker = MovementTracker::new(start);
+
+        assert_eq!(check_movement(&world, id, &mut tracker, Vec3::ZERO, vec3(4., 0., 0.), start + STEP), Ok(()));
+        assert_eq!(
+            check_movement(&world, id, &mut tracker, vec3(4., 0., 0.), vec3(10., 0., 0.), start + STEP * 100),
+            Err(MovementViolation::Teleport { distance: 6. })
+        );
+    }
+
+    #[test]
+    fn too_fast() {
+        ambient_core::init_all_components();
+        crate::init_all_components();
+        let mut world = World::new("too_fast");
+        let id = spawn(&mut world, EntityData::new().set(max_speed(), 10.));
+        let start = Instant::now();
+        let mut tracker = MovementTracker::new(start);
+
+        // 10m/s with the tolerance is 1.25m per step, and the burst is 6.25m, which the first movement uses up
+        assert_eq!(check_movement(&world, id, &mut tracker, Vec3::ZERO, vec3(6., 0., 0.), start + STEP), Ok(()));
+        let mut position = vec3(6., 0., 0.);
+        for i in 2..10 {
+            let to = position + vec3(1.2, 0., 0.);
+            assert_eq!(check_movement(&world, id, &mut tracker, position, to, start + STEP * i), Ok(()));
+            position = to;
+        }
+        assert!(matches!(
+            check_movement(&world, id, &mut tracker, position, position + vec3(3., 0., 0.), start + STEP * 10),
+            Err(MovementViolation::TooFast { .. })
+        ));
+        // The rejected movement didn't use up the allowance
+        assert_eq!(check_movement(&world, id, &mut tracker, position, position + vec3(1., 0., 0.), start + STEP * 10), Ok(()));
+    }
+
+    #[test]
+    fn too_much_acceleration() {
+        ambient_core::init_all_components();
+        crate::init_all_components();
+        let mut world = World::new("too_much_acceleration");
+        let id = spawn(&mut world, EntityData::new().set(max_acceleration(), 10.));
+        let start = Instant::now();
+        let mut tracker = MovementTracker::new(start);
+
+        // 1m/s, then 1.5m/s, which is 5m/s²
+        assert_eq!(check_movement(&world, id, &mut tracker, Vec3::ZERO, vec3(0.1, 0., 0.), start + STEP), Ok(()));
+        assert_eq!(check_movement(&world, id, &mut tracker, vec3(0.1, 0., 0.), vec3(0.25, 0., 0.), start + STEP * 2), Ok(()));
+        // Then 10m/s, which is 85m/s²
+        assert!(matches!(
+            check_movement(&world, id, &mut tracker, vec3(0.25, 0., 0.), vec3(1.25, 0., 0.), start + STEP * 3),
+            Err(MovementViolation::TooMuchAcceleration { .. })
+        ));
+    }
+
+    #[test]
+    fn not_finite() {
+        ambient_core::init_all_components();
+        crate::init_all_components();
+        let mut world = World::new("not_finite");
+        // Without an envelope at all
+        let id = spawn(&mut world, EntityData::new());
+        let start = Instant::now();
+        let mut tracker = MovementTracker::new(start);
+
+        for to in [vec3(f32::NAN, 0., 0.), vec3(0., f32::INFINITY, 0.), vec3(0., 0., f32::NEG_INFINITY)] {
+            assert_eq!(check_movement(&world, id, &mut tracker, Vec3::ZERO, to, start + STEP), Err(MovementViolation::NotFinite));
+        }
+
+        world.add_component(id, movement_violation_response(), 1).unwrap();
+        let data = EntityData::new().set(translation(), vec3(f32::NAN, 0., 0.));
+        assert_eq!(validate_owned_state(&mut world, "player", id, &data), Verdict::Reject);
+        assert_eq!(world.get(id, translation()).unwrap(), Vec3::ZERO);
+    }
+}
@@ -0,0 +1,350 @@
+//! Ownership: which end of the connections has the authority to simulate an entity.
+//!
+//! The server simulates the entities, unless one has an [owner]: then the client of the player with that `user_id`
+//! simulates it, and sends the values of the [owned_components] to the server when they change, at most every
+//! [OWNED_STATE_INTERVAL]. The server validates the movements in these states (see [crate::movement]) and applies them,
+//! and replicates them to the other clients but not back to the owner, whose entity would otherwise be pulled back to
+//! where it was a round trip ago; the values which the server changed since are sent to the owner though, which is how it
+//! corrects or teleports the entity. The owner doesn't interpolate the entities it owns, and the prediction doesn't roll
+//! them back.
+//!
+//! The authority is transferred by setting or removing the [owner] of the entity on the server, with [set_owner]. When a
+//! player disconnects or changes room, the authority over their entities goes back to the server. The server keeps
+//! running its systems on the owned entities, so a dynamic physics body should be made kinematic while a client owns it.
+//!
+//! The [owned_components] are set on the server with [set_owned_components], and synced to the clients, so that both ends
+//! agree on what the owners send.
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use ambient_core::transform::{rotation, scale, translation};
+use ambient_ecs::{
+    components, query, ComponentDesc, Debuggable, Description, EntityData, EntityId, FnSystem, Name, Networked, Resource, SystemGroup,
+    World, WorldChange, WorldDiff,
+};
+use ambient_std::unwrap_log_err;
+use ambient_sys::time::Instant;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::game_client,
+    movement::{self, Verdict},
+    player::{local_user_id, user_id},
+    DatagramHandlers, ServerWorldExt,
+};
+
+components!("network", {
+    @[
+        Debuggable, Networked,
+        Name["Owner"],
+        Description["The `user_id` of the player whose client has the authority to simulate this entity, and sends its transform to the server.\nIf not attached, the server has the authority."]
+    ]
+    owner: String,
+    @[
+        Debuggable, Networked,
+        Name["Owned components"],
+        Description["The components which the owner of an entity sends to the server, as a synced resource; defaults to the transform."]
+    ]
+    owned_components: Vec<ComponentDesc>,
+    /// On the server, the owner and the sequence number of the last state applied to an entity
+    owned_state_seq: (String, u64),
+    /// The content versions of the owned components of an entity when its state was last sent by the client, or applied
+    /// by the server
+    owned_state_versions: Vec<(u32, u64)>,
+    @[Resource]
+    owned_state_sender: OwnedStateSender,
+});
+
+/// How often the owner of an entity sends its state at most
+pub const OWNED_STATE_INTERVAL: Duration = Duration::from_millis(33);
+/// The handler id of the datagrams of the owned states
+pub const OWNED_STATE_DATAGRAM_ID: u32 = 6;
+
+/// The state of an entity, as sent by its owner
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OwnedState {
+    pub entity: EntityId,
+    /// Increases with every state the client sends, so that the server drops the ones which arrive late
+    pub seq: u64,
+    pub data: EntityData,
+}
+impl OwnedState {
+    fn to_datagram(&self) -> bincode::Result<Bytes> {
+        let mut data = OWNED_STATE_DATAGRAM_ID.to_be_bytes().to_vec();
+        bincode::serialize_into(&mut data, self)?;
+        Ok(data.into())
+    }
+}
+
+/// Numbers the states sent by the client, and throttles them
+#[derive(Debug, Clone, Default)]
+pub struct OwnedStateSender {
+    next_seq: u64,
+    last_sent: Option<Instant>,
+}
+
+/// The [owned_components] of `world`, which are the same on the server and on its clients
+pub fn owned_components_of(world: &World) -> Vec<ComponentDesc> {
+    match world.synced_resource(owned_components()) {
+        Some(components) => components.clone(),
+        None => vec![translation().desc(), rotation().desc(), scale().desc()],
+    }
+}
+
+/// Sets the [owned_components] of the server `world`, which are synced to its clients
+pub fn set_owned_components(world: &mut World, components: Vec<ComponentDesc>) {
+    match world.synced_resource_entity() {
+        Some(id) => world.add_component(id, owned_components(), components).unwrap(),
+        None => log::warn!("The owned components can't be set without synced resources"),
+    }
+}
+
+/// The `user_id` of the player whose client has the authority over `id`, or `None` if the server has it
+pub fn owner_of(world: &World, id: EntityId) -> Option<String> {
+    world.get_cloned(id, owner()).ok()
+}
+
+/// Whether the client of the player `user_id` has the authority over `id`
+pub fn is_owned_by(world: &World, id: EntityId, user_id: &str) -> bool {
+    world.get_ref(id, owner()).map(|owner| owner == user_id).unwrap_or(false)
+}
+
+/// Whether the local client has the authority over `id`
+pub fn is_locally_owned(world: &World, id: EntityId) -> bool {
+    match world.resource_opt(local_user_id()) {
+        Some(local) => is_owned_by(world, id, local),
+        None => false,
+    }
+}
+
+/// Transfers the authority over `id` to the client of the player `user_id`, or back to the server if it's `None`
+pub fn set_owner(world: &mut World, id: EntityId, user_id: Option<&str>) {
+    world.remove_component(id, owned_state_seq()).ok();
+    match user_id {
+        Some(user_id) if !is_owned_by(world, id, user_id) => {
+            world.add_component(id, owner(), user_id.to_string()).ok();
+        }
+        Some(_) => {}
+        None => {
+            world.remove_component(id, owner()).ok();
+        }
+    }
+}
+
+/// Gives the authority over the entities owned by the player `user_id` back to the server
+pub fn release_owned_entities(world: &mut World, user_id: &str) {
+    let owned = query(owner()).iter(world, None).filter(|(_, owner)| *owner == user_id).map(|(id, _)| id).collect::<Vec<_>>();
+    for id in owned {
+        set_owner(world, id, None);
+    }
+}
+
+/// Removes from `diff` the values of the [owned_components] of the entities owned by the player `player_id` which their
+/// client sent, i.e. which the server hasn't changed since it applied them. Returns `None` if nothing is removed.
+pub(crate) fn player_diff(world: &World, player_id: EntityId, diff: &WorldDiff) -> Option<WorldDiff> {
+    let uid = world.get_ref(player_id, user_id()).ok()?;
+    let owned = query(owner()).iter(world, None).filter(|(_, owner)| *owner == uid).map(|(id, _)| id).collect::<HashSet<_>>();
+    if owned.is_empty() {
+        return None;
+    }
+    let components = owned_components_of(world);
+    let is_echo = |change: &WorldChange| match change {
+        WorldChange::Set(id, entry) if owned.contains(id) && components.contains(&entry.desc()) => {
+            match (world.get_component_content_version(*id, entry.desc().index()), world.get_ref(*id, owned_state_versions())) {
+                (Ok(version), Ok(applied)) => applied.contains(&(entry.desc().index(), version)),
+                _ => false,
+            }
+        }
+        _ => false,
+    };
+    if !diff.changes.iter().any(is_echo) {
+        return None;
+    }
+    Some(WorldDiff { changes: diff.changes.iter().filter(|change| !is_echo(change)).cloned().collect() })
+}
+
+/// Applies `state` sent by the player `sender` on the server, if they own its entity, it isn't older than the last one
+/// applied, and its movement is valid. Only the [owned_components] the entity already has are set.
+pub fn apply_owned_state(world: &mut World, sender: &str, state: OwnedState) -> Verdict {
+    if !is_owned_by(world, state.entity, sender) {
+        return Verdict::Reject;
+    }
+    let last = world.get_ref(state.entity, owned_state_seq()).ok();
+    if last.map(|(owner, seq)| owner == sender && state.seq <= *seq).unwrap_or(false) {
+        return Verdict::Reject;
+    }
+    let seq = (sender.to_string(), state.seq);
+    if world.has_component(state.entity, owned_state_seq()) {
+        world.set(state.entity, owned_state_seq(), seq).unwrap();
+    } else {
+        world.add_component(state.entity, owned_state_seq(), seq).unwrap();
+    }
+    let verdict = movement::validate_owned_state(world, sender, state.entity, &state.data);
+    if verdict != Verdict::Accept {
+        return verdict;
+    }
+
+    let components = owned_components_of(world);
+    for entry in state.data.iter() {
+        if components.contains(&entry.desc()) {
+            world.set_entry(state.entity, entry.clone()).ok();
+        }
+    }
+    let versions = components
+        .iter()
+        .filter_map(|desc| Some((desc.index(), world.get_component_content_version(state.entity, desc.index()).ok()?)))
+        .collect::<Vec<_>>();
+    if world.has_component(state.entity, owned_state_versions()) {
+        world.set(state.entity, owned_state_versions(), versions).unwrap();
+    } else {
+        world.add_component(state.entity, owned_state_versions(), versions).unwrap();
+    }
+    verdict
+}
+
+/// Registers the handler of the states the owners send to the server
+pub fn register_datagram_handler(handlers: &mut DatagramHandlers) {
+    handlers.insert(
+        OWNED_STATE_DATAGRAM_ID,
+        Arc::new(|state, _assets, user_id, data| {
+            let owned_state: OwnedState = unwrap_log_err!(bincode::deserialize(&data));
+            let mut state = state.lock();
+            let verdict = match state.get_player_world_mut(user_id) {
+                Some(world) => apply_owned_state(world, user_id, owned_state),
+                None => return,
+            };
+            if let Verdict::Kick(reason) = verdict {
+                state.kick_player(user_id, &reason);
+            }
+        }),
+    );
+}
+
+/// Sends the state of the entities owned by the local client to the server, when it has changed
+pub fn client_systems() -> SystemGroup {
+    SystemGroup::new(
+        "network/ownership",
+        vec![Box::new(FnSystem::new(|world, _| {
+            let connection = match world.resource_opt(game_client()) {
+                Some(Some(game_client)) => game_client.connection.clone(),
+                _ => return,
+            };
+            let local = match world.resource_opt(local_user_id()) {
+                Some(local) => local.clone(),
+                None => return,
+            };
+            let mut sender = world.resource_opt(owned_state_sender()).cloned().unwrap_or_default();
+            let now = Instant::now();
+            if sender.last_sent.map(|last_sent| now.duration_since(last_sent) < OWNED_STATE_INTERVAL).unwrap_or(false) {
+                return;
+            }
+
+            let components = owned_components_of(world);
+            let owned = query(owner()).iter(world, None).filter(|(_, owner)| **owner == local).map(|(id, _)| id).collect::<Vec<_>>();
+            for id in owned {
+                let versions = components
+                    .iter()
+                    .filter_map(|desc| Some((desc.index(), world.get_component_content_version(id, desc.index()).ok()?)))
+                    .collect::<Vec<_>>();
+                if world.get_ref(id, owned_state_versions()).map(|sent| *sent == versions).unwrap_or(false) {
+                    continue;
+                }
+                let mut data = EntityData::new();
+                for &desc in &components {
+                    if let Ok(entry) = world.get_entry(id, desc) {
+                        data.set_entry(entry);
+                    }
+                }
+                let state = OwnedState { entity: id, seq: sender.next_seq, data };
+                sender.next_seq += 1;
+                match state.to_datagram() {
+                    Ok(datagram) => {
+                        connection.send_datagram(datagram).ok();
+                    }
+                    Err(err) => log::warn!("Failed to encode the state of {id}: {err:?}"),
+                }
+                if world.has_component(id, owned_state_versions()) {
+                    world.set(id, owned_state_versions(), versions).unwrap();
+                } else {
+                    world.add_component(id, owned_state_versions(), versions).unwrap();
+                }
+            }
+            sender.last_sent = Some(now);
+            match world.resource_mut_opt(owned_state_sender()) {
+                Some(current) => *current = sender,
+                None => world.add_resource(owned_state_sender(), sender),
+            }
+        }))],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use ambient_ecs::{ArchetypeFilter, WorldStreamFilter};
+    use glam::{vec3, Vec3};
+
+    use super::*;
+    use crate::synced_resources;
+
+    fn owned_world(name: &'static str) -> (World, EntityId, EntityId) {
+        ambient_core::init_all_components();
+        crate::init_all_components();
+        let mut world = World::new(name);
+        let player = EntityData::new().set(user_id(), "alice".to_string()).spawn(&mut world);
+        let id = EntityData::new().set(translation(), Vec3::ZERO).set(owner(), "alice".to_string()).spawn(&mut world);
+        (world, player, id)
+    }
+
+    fn state(entity: EntityId, seq: u64, x: f32) -> OwnedState {
+        OwnedState { entity, seq, data: EntityData::new().set(translation(), vec3(x, 0., 0.)) }
+    }
+
+    #[test]
+    fn owned_components_are_synced_to_the_clients() {
+        let (mut server, _, _) = owned_world("owned_components_are_synced_to_the_clients");
+        EntityData::new().set(synced_resources(), ()).spawn(&mut server);
+        assert_eq!(owned_components_of(&server), vec![translation().desc(), rotation().desc(), scale().desc()]);
+        set_owned_components(&mut server, vec![translation().desc()]);
+
+        let filter = WorldStreamFilter::new(ArchetypeFilter::new(), Arc::new(|desc, _| desc.has_attribute::<Networked>()));
+        let diff: WorldDiff = bincode::deserialize(&bincode::serialize(&filter.initial_diff(&server)).unwrap()).unwrap();
+        let mut client = World::new("owned_components_are_synced_to_the_clients_client");
+        diff.apply(&mut client, EntityData::new(), false);
+        assert_eq!(owned_components_of(&client), vec![translation().desc()]);
+    }
+
+    #[test]
+    fn only_the_newer_states_of_the_owner_are_applied() {
+        let (mut world, _, id) = owned_world("only_the_newer_states_of_the_owner_are_applied");
+        assert_eq!(apply_owned_state(&mut world, "alice", state(id, 1, 1.)), Verdict::Accept);
+        assert_eq!(apply_owned_state(&mut world, "alice", state(id, 0, 2.)), Verdict::Reject);
+        assert_eq!(apply_owned_state(&mut world, "bob", state(id, 2, 3.)), Verdict::Reject);
+        assert_eq!(world.get(id, translation()).unwrap(), vec3(1., 0., 0.));
+
+        // A new owner starts their own sequence
+        set_owner(&mut world, id, Some("bob"));
+        assert_eq!(apply_owned_state(&mut world, "bob", state(id, 0, 4.)), Verdict::Accept);
+        assert_eq!(world.get(id, translation()).unwrap(), vec3(4., 0., 0.));
+        set_owner(&mut world, id, None);
+        assert_eq!(apply_owned_state(&mut world, "bob", state(id, 1, 5.)), Verdict::Reject);
+    }
+
+    #[test]
+    fn the_owner_only_receives_the_changes_of_the_server() {
+        let (mut world, alice, id) = owned_world("the_owner_only_receives_the_changes_of_the_server");
+        let bob = EntityData::new().set(user_id(), "bob".to_string()).spawn(&mut world);
+        assert_eq!(apply_owned_state(&mut world, "alice", state(id, 0, 1.)), Verdict::Accept);
+
+        let diff = WorldDiff::new().set(id, translation(), vec3(1., 0., 0.));
+        assert!(player_diff(&world, alice, &diff).unwrap().is_empty());
+        assert!(player_diff(&world, bob, &diff).is_none());
+
+        // E.g. a teleport by the server
+        world.set(id, translation(), vec3(10., 0., 0.)).unwrap();
+        assert!(player_diff(&world, alice, &diff).is_none());
+
+        release_owned_entities(&mut world, "alice");
+        assert_eq!(owner_of(&world, id), None);
+    }
+}
@@ -1,9 +1,19 @@
-use ambient_ecs::{ComponentRegistry, ExternalComponentDesc, WorldDiff};
+use std::time::Duration;
+
+use ambient_ecs::{ComponentDesc, ComponentRegistry, ExternalComponentDesc, SerializationProfile, WorldDiff};
+use ambient_std::sha256_digest;
 use anyhow::{Context, Result};
-use futures::{io::BufReader, StreamExt};
-use quinn::{NewConnection, RecvStream};
+use futures::StreamExt;
+use itertools::Itertools;
+use tokio::io::BufReader;
 
-use crate::{next_bincode_bi_stream, open_bincode_bi_stream, IncomingStream, NetworkError, OutgoingStream};
+use crate::{
+    auth::{Authenticator, Authorization, Authorizer, Identity},
+    delta::{CompressedDiff, DeltaDecoder},
+    next_bincode_bi_stream, open_bincode_bi_stream,
+    transport::{Connection, NewConnection, RecvStream},
+    IncomingStream, NetworkError, OutgoingStream,
+};
 
 #[derive(Debug)]
 pub struct ClientProtocol {
@@ -11,18 +21,31 @@ pub struct ClientProtocol {
     pub(crate) stat_stream: IncomingStream,
     client_info: ClientInfo,
     pub(crate) diff_stream: IncomingStream,
+    pub(crate) diff_decoder: DeltaDecoder,
 }
 
 impl ClientProtocol {
-    pub async fn new(mut conn: NewConnection, player_id: String) -> Result<Self> {
+    pub async fn new(mut conn: NewConnection, player_id: String, auth_ticket: Option<Vec<u8>>) -> Result<Self> {
         // Say who we are
         // The server will respond appropriately and return things such as
         // username (TODO)
         let (mut tx, mut rx) = open_bincode_bi_stream(&conn.connection).await?;
-        tx.send(&player_id).await?;
+        tx.send(&ClientHello { protocol_version: PROTOCOL_VERSION, schema_hash: schema_hash(), user_id: player_id, auth_ticket }).await?;
 
-        // The server will acknowledge and send the credentials back
-        let client_info: ClientInfo = rx.next().await?;
+        // The server will acknowledge and send the credentials back, unless it refuses us
+        let client_info = match rx.next().await? {
+            ServerHello::Accepted(client_info) => client_info,
+            ServerHello::Refused(reason) => return Err(NetworkError::ConnectionRefused(reason).into()),
+            ServerHello::Incompatible { protocol_version, schema_hash: server_schema_hash } => {
+                return Err(NetworkError::IncompatibleVersion(incompatibility(
+                    protocol_version,
+                    &server_schema_hash,
+                    PROTOCOL_VERSION,
+                    &schema_hash(),
+                ))
+                .into())
+            }
+        };
         ComponentRegistry::get_mut().add_external(client_info.external_components.clone());
 
         // Great, the server knows who we are.
@@ -35,11 +58,12 @@ impl ClientProtocol {
 
         log::info!("Setup client side protocol");
 
-        Ok(Self { conn, diff_stream, stat_stream, client_info })
+        Ok(Self { conn, diff_stream, stat_stream, client_info, diff_decoder: DeltaDecoder::default() })
     }
 
     pub async fn next_diff(&mut self) -> anyhow::Result<WorldDiff> {
-        self.diff_stream.next::<WorldDiff>().await.context("Failed to read world diff")
+        let diff = self.diff_stream.next::<CompressedDiff>().await.context("Failed to read world diff")?;
+        self.diff_decoder.decode(diff).context("Failed to decompress world diff")
     }
 
     pub async fn next_event(&mut self) -> anyhow::Result<BufReader<RecvStream>> {
@@ -53,13 +77,9 @@ impl ClientProtocol {
         &self.client_info
     }
 
-    pub(crate) fn connection(&self) -> quinn::Connection {
+    pub(crate) fn connection(&self) -> Connection {
         self.conn.connection.clone()
     }
-
-    pub fn uni_streams(&self) -> &quinn::IncomingUniStreams {
-        &self.conn.uni_streams
-    }
 }
 
 /// The server side protocol instantiation of the client communication
@@ -69,23 +89,62 @@ pub struct ServerProtocol {
     pub(crate) diff_stream: OutgoingStream,
     pub(crate) stat_stream: OutgoingStream,
     client_info: ClientInfo,
+    identity: Identity,
 }
 
 impl ServerProtocol {
-    pub async fn new(mut conn: NewConnection) -> Result<Self, NetworkError> {
+    pub async fn new(
+        mut conn: NewConnection,
+        authenticator: &dyn Authenticator,
+        authorizer: Option<&dyn Authorizer>,
+    ) -> Result<Self, NetworkError> {
         // The client now sends the player id
         let (mut tx, mut rx) = next_bincode_bi_stream(&mut conn).await?;
 
-        let user_id: String = rx.next().await?;
+        // A client of another version may not even say hello the same way
+        let hello = match rx.next::<ClientHello>().await {
+            Ok(hello) => hello,
+            Err(NetworkError::BadMsgFormat(err)) => {
+                log::warn!("Refusing a client of an unknown version: {err:?}");
+                refuse_incompatible(&conn, &mut tx, &mut rx).await;
+                return Err(NetworkError::IncompatibleVersion(format!("The client's handshake could not be read: {err}")));
+            }
+            Err(err) => return Err(err),
+        };
 
-        log::info!("Received handshake from {user_id:?}");
+        log::info!("Received handshake from {:?} over {}", hello.user_id, conn.connection.transport());
+
+        let schema_hash = schema_hash();
+        if hello.protocol_version != PROTOCOL_VERSION || hello.schema_hash != schema_hash {
+            let reason = incompatibility(PROTOCOL_VERSION, &schema_hash, hello.protocol_version, &hello.schema_hash);
+            log::warn!("Refusing {:?}: {reason}", hello.user_id);
+            refuse_incompatible(&conn, &mut tx, &mut rx).await;
+            return Err(NetworkError::IncompatibleVersion(reason));
+        }
+
+        let identity = match authenticator.authenticate(&hello.user_id, hello.auth_ticket.as_deref()).await {
+            Ok(identity) => identity,
+            Err(err) => {
+                log::warn!("Refusing {:?}: {:?}", hello.user_id, err);
+                let reason = format!("Authentication failed: {err:#}");
+                refuse(&conn, &mut tx, &mut rx, AUTHENTICATION_FAILED, &reason).await;
+                return Err(NetworkError::AuthenticationFailed(err.to_string()));
+            }
+        };
+        if let Some(authorizer) = authorizer {
+            if let Authorization::Reject(reason) = authorizer.authorize(&identity).await {
+                log::warn!("Refusing {:?}: {reason}", identity.user_id);
+                refuse(&conn, &mut tx, &mut rx, CONNECTION_REFUSED, &reason).await;
+                return Err(NetworkError::ConnectionRefused(reason));
+            }
+        }
 
         let external_components = ComponentRegistry::get().all_external().map(|x| x.0).collect();
 
         // Respond
-        let client_info = ClientInfo { user_id, external_components };
+        let client_info = ClientInfo { user_id: identity.user_id.clone(), external_components };
         log::info!("Responding with: {client_info:?}");
-        tx.send(&client_info).await?;
+        tx.send(&ServerHello::Accepted(client_info.clone())).await?;
 
         // Great, now open all required streams
         let mut diff_stream = OutgoingStream::open_uni(&conn.connection).await?;
@@ -94,18 +153,154 @@ impl ServerProtocol {
         let mut stat_stream = OutgoingStream::open_uni(&conn.connection).await?;
         stat_stream.send(&()).await?;
 
-        Ok(Self { conn, diff_stream, stat_stream, client_info })
+        Ok(Self { conn, diff_stream, stat_stream, client_info, identity })
     }
 
     pub fn client_info(&self) -> &ClientInfo {
         &self.client_info
     }
 
-    pub(crate) fn connection(&self) -> quinn::Connection {
+    /// Who the client was authenticated as
+    pub fn identity(&self) -> &Identity {
+        &self.identity
+    }
+
+    pub(crate) fn connection(&self) -> Connection {
         self.conn.connection.clone()
     }
 }
 
+/// The error code the connection is closed with when the client can't be authenticated
+pub const AUTHENTICATION_FAILED: u32 = 1;
+/// The error code the connection is closed with when the [Authorizer] rejects the client
+pub const CONNECTION_REFUSED: u32 = 2;
+/// The error code the connection is closed with when the server kicks the player
+pub const PLAYER_KICKED: u32 = 3;
+/// The error code the connection is closed with when the client and the server are of incompatible versions
+pub const INCOMPATIBLE_VERSION: u32 = 4;
+/// The error code the connection is closed with when the peer breaks the framing of the transport, e.g. by opening
+/// streams with ids which aren't its own
+pub const PROTOCOL_VIOLATION: u32 = 5;
+
+/// The version of the protocol between the clients and the server. The server refuses the clients of another version.
+///
+/// Bump it whenever the messages of the protocol change.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A hash of the replicated components, by their paths and their types, so that a client and a server which were built
+/// with different components don't read each other's diffs wrong. The components the server sends to its clients at the
+/// handshake (see [ClientInfo::external_components]) aren't included.
+///
+/// The types are the declared ones (see [declared_type]), which are the same on all the platforms; the Rust type names
+/// aren't, e.g. glam's vectors are in different modules depending on the SIMD support.
+pub fn schema_hash() -> String {
+    let registry = ComponentRegistry::get();
+    let external = registry.all_external().map(|(_, desc)| desc.index()).collect::<std::collections::HashSet<_>>();
+    schema_digest(
+        registry
+            .all()
+            .filter(|desc| !external.contains(&desc.index()) && SerializationProfile::REPLICATION.includes(*desc))
+            .map(|desc| (desc.path(), declared_type(&registry, desc))),
+    )
+}
+
+/// Hashes the components, by their paths and types, regardless of their order
+fn schema_digest(components: impl Iterator<Item = (String, String)>) -> String {
+    sha256_digest(&components.map(|(path, ty)| format!("{path}: {ty}")).sorted().join("\n"))
+}
+
+/// The type of the component as declared in an ambient.toml (e.g. `Vec3` or `VecString`) if it's a primitive one, or
+/// else its Rust type without the module paths
+fn declared_type(registry: &ComponentRegistry, desc: ComponentDesc) -> String {
+    match registry.get_primitive_component(desc.index()) {
+        Some(primitive) => format!("{:?}", primitive.ty),
+        None => strip_module_paths(desc.type_name()),
+    }
+}
+
+/// `alloc::vec::Vec<glam::f32::sse2::vec4::Vec4>` becomes `Vec<Vec4>`
+fn strip_module_paths(type_name: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = type_name;
+    while let Some(separator) = rest.find("::") {
+        let segment = &rest[..separator];
+        let start = segment.rfind(|c: char| !(c.is_alphanumeric() || c == '_')).map(|i| i + 1).unwrap_or(0);
+        stripped.push_str(&segment[..start]);
+        rest = &rest[separator + 2..];
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Describes how the builds of the server and of the client differ
+fn incompatibility(server_version: u32, server_schema_hash: &str, client_version: u32, client_schema_hash: &str) -> String {
+    if server_version != client_version {
+        format!(
+            "The client (protocol version {client_version}) and the server (protocol version {server_version}) were built from different versions of the engine; update them to the same version"
+        )
+    } else {
+        format!(
+            "The client (schema {client_schema_hash}) and the server (schema {server_schema_hash}) were built with different replicated components; rebuild them from the same sources"
+        )
+    }
+}
+
+/// How long a refused client has to read why before the connection is closed
+const REFUSAL_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Tells the client why it's refused, and closes the connection once it has hung up (closing it right away would drop the
+/// reason)
+async fn refuse(conn: &NewConnection, tx: &mut OutgoingStream, rx: &mut IncomingStream, error_code: u32, reason: &str) {
+    if tx.send(&ServerHello::Refused(reason.to_string())).await.is_ok() {
+        tokio::time::timeout(REFUSAL_GRACE_PERIOD, rx.next::<()>()).await.ok();
+    }
+    conn.connection.close(error_code, reason.as_bytes());
+}
+
+/// Tells the client which versions the server is of, for it to explain why they're incompatible, and closes the
+/// connection once it has hung up
+async fn refuse_incompatible(conn: &NewConnection, tx: &mut OutgoingStream, rx: &mut IncomingStream) {
+    let hello = ServerHello::Incompatible { protocol_version: PROTOCOL_VERSION, schema_hash: schema_hash() };
+    if tx.send(&hello).await.is_ok() {
+        tokio::time::timeout(REFUSAL_GRACE_PERIOD, rx.next::<()>()).await.ok();
+    }
+    conn.connection.close(INCOMPATIBLE_VERSION, b"Incompatible version");
+}
+
+/// The first message of a client, saying who it is
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct ClientHello {
+    /// First, so that it's read the same by all the versions
+    protocol_version: u32,
+    /// See [schema_hash]
+    schema_hash: String,
+    user_id: String,
+    /// See [crate::auth::AuthTicketProvider]
+    auth_ticket: Option<Vec<u8>>,
+}
+impl std::fmt::Debug for ClientHello {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientHello")
+            .field("protocol_version", &self.protocol_version)
+            .field("schema_hash", &self.schema_hash)
+            .field("user_id", &self.user_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The answer of the server to the [ClientHello]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+enum ServerHello {
+    Accepted(ClientInfo),
+    /// The client may not join, for this reason
+    Refused(String),
+    /// The client may not join, as its [PROTOCOL_VERSION] or [schema_hash] differ from the ones of the server
+    Incompatible {
+        protocol_version: u32,
+        schema_hash: String,
+    },
+}
+
 /// Contains things such as username (TODO) and user_id
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct ClientInfo {
@@ -118,3 +313,31 @@ impl std::fmt::Debug for ClientInfo {
         f.debug_struct("ClientInfo").field("user_id", &self.user_id).finish_non_exhaustive()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ambient_core::transform::{rotation, translation};
+
+    use super::*;
+
+    #[test]
+    fn schema_digest_is_pinned() {
+        let components = [("core::transform::translation", "Vec3"), ("core::transform::rotation", "Quat")];
+        assert_eq!(
+            schema_digest(components.into_iter().map(|(path, ty)| (path.to_string(), ty.to_string()))),
+            "a6e3594d3ef87a0467c39922a8e51d4067bda96b02f00601b4560cebb569feda"
+        );
+    }
+
+    #[test]
+    fn declared_types_are_platform_independent() {
+        ambient_core::init_all_components();
+        let registry = ComponentRegistry::get();
+        assert_eq!(declared_type(&registry, translation().desc()), "Vec3");
+        assert_eq!(declared_type(&registry, rotation().desc()), "Quat");
+
+        assert_eq!(strip_module_paths("alloc::vec::Vec<glam::f32::sse2::vec4::Vec4>"), "Vec<Vec4>");
+        assert_eq!(strip_module_paths("alloc::vec::Vec<glam::f32::scalar::vec4::Vec4>"), "Vec<Vec4>");
+        assert_eq!(strip_module_paths("(u32, core::option::Option<alloc::string::String>)"), "(u32, Option<String>)");
+    }
+}
@@ -0,0 +1,393 @@
+//! Recordings of the diffs a client received from the server, which can be replayed afterwards to inspect the session
+//! frame by frame.
+//!
+//! A recording is a sequence of frames, each of which is written as its length (as a little endian `u32`) followed by
+//! the bincode of a [RecordedFrame]. The frames are written as they arrive, so the recording of a session which
+//! crashed can still be opened, up to the last frame which was written.
+//!
+//! The client records the diffs it receives, and the server can record the whole world it replicates, before it's
+//! restricted to what's relevant to each player. A recording can be inspected frame by frame in a [SessionReplay], or
+//! played back into a client world in real time with a [SessionPlayer], which can seek and change its time scale. The
+//! [client_systems] play the [session_player] of the client world, if it has one.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use ambient_core::dtime;
+use ambient_ecs::{
+    components, query, ComponentDesc, ComponentEntry, EntityData, EntityId, FnSystem, Resource, SystemGroup, Validate, World, WorldChange,
+    WorldDiff,
+};
+use ambient_sys::time::Instant;
+use anyhow::Context;
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{interpolation, is_remote_entity};
+
+components!("network", {
+    /// The recording played back into the client world by the [client_systems]
+    @[Resource]
+    session_player: Arc<Mutex<SessionPlayer>>,
+    /// An entity spawned by a [SessionPlayer]
+    replayed_entity: (),
+});
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// The time since the start of the recording
+    pub time: Duration,
+    pub diff: WorldDiff,
+}
+
+/// Writes the diffs received by a client to a file
+pub struct SessionRecorder {
+    file: BufWriter<File>,
+    start: Instant,
+}
+impl std::fmt::Debug for SessionRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionRecorder").field("start", &self.start).finish_non_exhaustive()
+    }
+}
+impl SessionRecorder {
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("Failed to create the session recording {path:?}"))?;
+        Ok(Self { file: BufWriter::new(file), start: Instant::now() })
+    }
+    pub fn record(&mut self, diff: &WorldDiff) -> anyhow::Result<()> {
+        let frame = RecordedFrame { time: self.start.elapsed(), diff: diff.clone() };
+        let data = bincode::serialize(&frame)?;
+        self.file.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.file.write_all(&data)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// A session recorded by a [SessionRecorder]
+#[derive(Debug, Clone, Default)]
+pub struct SessionRecording {
+    pub frames: Vec<RecordedFrame>,
+}
+impl SessionRecording {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut file = BufReader::new(File::open(path).with_context(|| format!("Failed to open the session recording {path:?}"))?);
+        let mut frames = Vec::new();
+        loop {
+            let mut len = [0; 4];
+            match file.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            }
+            let mut data = vec![0; u32::from_le_bytes(len) as usize];
+            if file.read_exact(&mut data).is_err() {
+                log::warn!("The session recording {path:?} ends with a truncated frame, which is ignored");
+                break;
+            }
+            frames.push(bincode::deserialize(&data).with_context(|| format!("Invalid frame {} in {path:?}", frames.len()))?);
+        }
+        Ok(Self { frames })
+    }
+    /// The time of the last frame
+    pub fn duration(&self) -> Duration {
+        self.frames.last().map(|frame| frame.time).unwrap_or_default()
+    }
+    /// The last frame at or before `time`
+    pub fn frame_at(&self, time: Duration) -> Option<usize> {
+        self.frames.partition_point(|frame| frame.time <= time).checked_sub(1)
+    }
+    /// The first frame which spawns `id`
+    pub fn spawn_frame(&self, id: EntityId) -> Option<usize> {
+        self.frames.iter().position(|frame| {
+            frame.diff.changes.iter().any(|change| matches!(change, WorldChange::Spawn(Some(spawned), _) if *spawned == id))
+        })
+    }
+    /// The first frame which gives a component of `id` an invalid value (see [is_valid_entry]), and that component
+    pub fn first_invalid_frame(&self, id: EntityId) -> Option<(usize, ComponentDesc)> {
+        self.frames.iter().enumerate().find_map(|(index, frame)| {
+            frame.diff.changes.iter().find_map(|change| {
+                let invalid = match change {
+                    WorldChange::Spawn(Some(changed), data) | WorldChange::AddComponents(changed, data) if *changed == id => {
+                        data.iter().find(|entry| !is_valid_entry(entry))
+                    }
+                    WorldChange::Set(changed, entry) if *changed == id && !is_valid_entry(entry) => Some(entry),
+                    _ => None,
+                };
+                invalid.map(|entry| (index, entry.desc()))
+            })
+        })
+    }
+}
+
+/// Whether the value of `entry` is valid: the floating point values are invalid if they're not finite, and the values
+/// which the [Validate] function of their component rejects are invalid
+pub fn is_valid_entry(entry: &ComponentEntry) -> bool {
+    let finite = if let Some(value) = entry.try_downcast_ref::<f32>() {
+        value.is_finite()
+    } else if let Some(value) = entry.try_downcast_ref::<Vec2>() {
+        value.is_finite()
+    } else if let Some(value) = entry.try_downcast_ref::<Vec3>() {
+        value.is_finite()
+    } else if let Some(value) = entry.try_downcast_ref::<Vec4>() {
+        value.is_finite()
+    } else if let Some(value) = entry.try_downcast_ref::<Quat>() {
+        value.is_finite()
+    } else if let Some(value) = entry.try_downcast_ref::<Mat4>() {
+        value.is_finite()
+    } else {
+        true
+    };
+    // The validation may fix the value in place, which mustn't change the recording
+    finite && entry.attribute::<Validate>().map_or(true, |validate| validate.validate(&mut entry.clone()).is_ok())
+}
+
+/// The world of a [SessionRecording] at one of its frames
+#[derive(Debug)]
+pub struct SessionReplay {
+    recording: Arc<SessionRecording>,
+    world: World,
+    /// The number of frames applied to `world`
+    applied: usize,
+}
+impl SessionReplay {
+    pub fn new(recording: Arc<SessionRecording>) -> Self {
+        Self { recording, world: World::new("replay"), applied: 0 }
+    }
+    pub fn recording(&self) -> &Arc<SessionRecording> {
+        &self.recording
+    }
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+    /// Brings the world to its state right after `frame`. Going backwards replays the recording from the start.
+    pub fn seek(&mut self, frame: usize) {
+        let target = (frame + 1).min(self.recording.frames.len());
+        if target < self.applied {
+            self.world = World::new("replay");
+            self.applied = 0;
+        }
+        for frame in &self.recording.frames[self.applied..target] {
+            frame.diff.clone().apply(&mut self.world, EntityData::new(), false);
+        }
+        self.applied = target;
+    }
+}
+
+/// The position of the playback of a recording, which moves at `time_scale` times the real time unless it's paused
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Playhead {
+    pub time: Duration,
+    pub time_scale: f32,
+    pub paused: bool,
+}
+impl Default for Playhead {
+    fn default() -> Self {
+        Self { time: Duration::ZERO, time_scale: 1., paused: false }
+    }
+}
+impl Playhead {
+    /// Moves the playhead forward by `dt` of real time, stopping at `end`
+    pub fn advance(&mut self, dt: Duration, end: Duration) {
+        if !self.paused {
+            self.time = (self.time + dt.mul_f32(self.time_scale.max(0.))).min(end);
+        }
+    }
+}
+
+/// Plays a [SessionRecording] back into a client world, as if its diffs were arriving from the server; for instance to
+/// show a kill cam, or to watch a desync happen again.
+///
+/// The entities are spawned with [is_remote_entity], like the ones received from the server, and with [replayed_entity].
+/// Seeking backwards despawns the entities the player spawned, and plays the recording again from the start; the other
+/// entities of the world are left as they are.
+#[derive(Debug)]
+pub struct SessionPlayer {
+    recording: Arc<SessionRecording>,
+    pub playhead: Playhead,
+    /// The number of frames applied to the world
+    applied: usize,
+}
+impl SessionPlayer {
+    pub fn new(recording: Arc<SessionRecording>) -> Self {
+        Self { recording, playhead: Playhead::default(), applied: 0 }
+    }
+    pub fn recording(&self) -> &Arc<SessionRecording> {
+        &self.recording
+    }
+    /// Moves the playhead to `time`; the world catches up on the next [Self::update]
+    pub fn seek(&mut self, time: Duration) {
+        self.playhead.time = time.min(self.recording.duration());
+    }
+    /// Whether the whole recording has been played
+    pub fn is_finished(&self) -> bool {
+        self.applied == self.recording.frames.len()
+    }
+    /// Advances the playhead by `dt` of real time, and brings `world` to its state at the playhead
+    pub fn update(&mut self, world: &mut World, dt: Duration) {
+        self.playhead.advance(dt, self.recording.duration());
+        let target = self.recording.frame_at(self.playhead.time).map_or(0, |frame| frame + 1);
+        if target < self.applied {
+            for id in query(replayed_entity()).iter(world, None).map(|(id, _)| id).collect::<Vec<_>>() {
+                world.despawn(id);
+            }
+            self.applied = 0;
+        }
+        for frame in &self.recording.frames[self.applied..target] {
+            let moved = interpolation::before_server_diff(world, &frame.diff);
+            frame.diff.clone().apply(world, EntityData::new().set(is_remote_entity(), ()).set(replayed_entity(), ()), false);
+            interpolation::after_server_diff(world, moved, frame.time);
+        }
+        self.applied = target;
+    }
+}
+
+/// Plays the [session_player] of the client world, if it has one
+pub fn client_systems() -> SystemGroup {
+    SystemGroup::new(
+        "network/recording",
+        vec![Box::new(FnSystem::new(|world, _| {
+            let player = match world.resource_opt(session_player()) {
+                Some(player) => player.clone(),
+                None => return,
+            };
+            let dt = Duration::from_secs_f32(world.resource_opt(dtime()).copied().unwrap_or_default().max(0.));
+            player.lock().update(world, dt);
+        }))],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use ambient_core::transform::translation;
+    use glam::vec3;
+
+    use super::*;
+
+    components!("test", {
+        @[Validate[validate_mass]]
+        mass: f32,
+    });
+
+    fn validate_mass(mass: &mut f32) -> Result<(), String> {
+        if *mass < 0. {
+            return Err("Negative mass".to_string());
+        }
+        Ok(())
+    }
+
+    /// Spawns `id` at the origin, then moves it by one along X every 100ms, for a second
+    fn recording(id: EntityId) -> Arc<SessionRecording> {
+        let spawn = WorldDiff { changes: vec![WorldChange::Spawn(Some(id), EntityData::new().set(translation(), Vec3::ZERO))] };
+        let frames = std::iter::once(RecordedFrame { time: Duration::ZERO, diff: spawn })
+            .chain((1..=10).map(|i| RecordedFrame {
+                time: Duration::from_millis(100 * i),
+                diff: WorldDiff::new().set(id, translation(), vec3(i as f32, 0., 0.)),
+            }))
+            .collect();
+        Arc::new(SessionRecording { frames })
+    }
+
+    #[test]
+    fn frames_are_found_by_time() {
+        ambient_core::init_all_components();
+        let id = EntityId::new();
+        let recording = recording(id);
+        assert_eq!(recording.duration(), Duration::from_secs(1));
+        assert_eq!(recording.frame_at(Duration::from_millis(250)), Some(2));
+        assert_eq!(recording.frame_at(Duration::from_secs(2)), Some(10));
+        assert_eq!(recording.spawn_frame(id), Some(0));
+        assert_eq!(recording.spawn_frame(EntityId::new()), None);
+    }
+
+    #[test]
+    fn invalid_values_are_found() {
+        ambient_core::init_all_components();
+        init_components();
+        assert!(is_valid_entry(&ComponentEntry::new(translation(), Vec3::ONE)));
+        assert!(!is_valid_entry(&ComponentEntry::new(translation(), vec3(f32::NAN, 0., 0.))));
+        assert!(is_valid_entry(&ComponentEntry::new(mass(), 2.)));
+        assert!(!is_valid_entry(&ComponentEntry::new(mass(), -2.)));
+
+        let id = EntityId::new();
+        let recording = recording(id);
+        let mut invalid = (*recording).clone();
+        invalid.frames[4].diff = WorldDiff::new().set(id, mass(), -2.);
+        invalid.frames[7].diff = WorldDiff::new().set(id, translation(), Vec3::NAN);
+        assert_eq!(invalid.first_invalid_frame(id), Some((4, mass().desc())));
+        assert_eq!(recording.first_invalid_frame(id), None);
+    }
+
+    #[test]
+    fn player_follows_the_playhead() {
+        ambient_core::init_all_components();
+        crate::init_all_components();
+        let id = EntityId::new();
+        let mut world = World::new("player_follows_the_playhead");
+        let live = world.spawn(EntityData::new().set(is_remote_entity(), ()).set(translation(), vec3(9., 9., 9.)));
+        let mut player = SessionPlayer::new(recording(id));
+
+        player.playhead.time_scale = 2.;
+        player.update(&mut world, Duration::ZERO);
+        assert!(world.has_component(id, replayed_entity()));
+        assert_eq!(world.get(id, translation()).unwrap(), Vec3::ZERO);
+        player.update(&mut world, Duration::from_millis(160));
+        assert_eq!(player.playhead.time, Duration::from_millis(320));
+        assert_eq!(world.get(id, translation()).unwrap(), vec3(3., 0., 0.));
+
+        // Seeking back plays the recording again, and leaves the entities which weren't replayed alone
+        player.seek(Duration::from_millis(150));
+        player.update(&mut world, Duration::ZERO);
+        assert_eq!(world.get(id, translation()).unwrap(), vec3(1., 0., 0.));
+        assert_eq!(world.get(live, translation()).unwrap(), vec3(9., 9., 9.));
+
+        player.playhead.paused = true;
+        player.update(&mut world, Duration::from_secs(1));
+        assert_eq!(player.playhead.time, Duration::from_millis(150));
+
+        player.playhead.paused = false;
+        player.update(&mut world, Duration::from_secs(5));
+        assert!(player.is_finished());
+        assert_eq!(player.playhead.time, Duration::from_secs(1));
+        assert_eq!(world.get(id, translation()).unwrap(), vec3(10., 0., 0.));
+        assert!(world.exists(live));
+    }
+
+    #[test]
+    fn recordings_are_saved_and_loaded() {
+        ambient_core::init_all_components();
+        let path = std::env::temp_dir().join(format!("recordings_are_saved_and_loaded_{}", std::process::id()));
+        let id = EntityId::new();
+        let mut recorder = SessionRecorder::create(&path).unwrap();
+        for i in 0..3 {
+            recorder.record(&WorldDiff::new().set(id, translation(), vec3(i as f32, 0., 0.))).unwrap();
+        }
+        drop(recorder);
+        // As if the session crashed while writing a frame
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&[100, 0, 0, 0, 1, 2]).unwrap();
+
+        let recording = SessionRecording::load(&path);
+        std::fs::remove_file(&path).ok();
+        let recording = recording.unwrap();
+        assert_eq!(recording.frames.len(), 3);
+        assert!(recording.frames.windows(2).all(|frames| frames[0].time <= frames[1].time));
+        match &recording.frames[2].diff.changes[..] {
+            [WorldChange::Set(changed, entry)] => {
+                assert_eq!(*changed, id);
+                assert_eq!(entry.try_downcast_ref::<Vec3>(), Some(&vec3(2., 0., 0.)));
+            }
+            changes => panic!("Unexpected changes: {changes:?}"),
+        }
+    }
+}
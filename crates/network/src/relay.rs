@@ -0,0 +1,389 @@
+//! Relaying: the connections between the clients and a server which can't reach each other directly, e.g. because the
+//! server is hosted behind a carrier-grade NAT, go through a relay which both of them can reach.
+//!
+//! A relay is run with [Relay::run]. A server which uses it keeps a control connection to the relay, and is registered
+//! under the address the relay sees it at, with the port of the server; or with the next free port, if another server
+//! behind the same NAT already has it. The players join it at this address as usual, and when their client can't connect
+//! to it directly, over QUIC nor over a WebSocket, it asks the relay of its [RelayAddressKey] for the server registered
+//! under this address. The relay tells the server, which opens a connection to the relay for this client with the secret
+//! it got when it registered, and the relay pipes the two together.
+//!
+//! The connections through a relay are plain WebSocket connections (see [crate::websocket]) from end to end, which aren't
+//! encrypted: the relay, and anyone on the way to it, sees the messages going by.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use ambient_std::asset_cache::SyncAssetKey;
+use futures::{
+    channel::{mpsc, oneshot},
+    future, Sink, SinkExt, Stream, StreamExt, TryStreamExt,
+};
+use parking_lot::Mutex;
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{
+    tungstenite::{self, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{
+    transport::NewConnection,
+    websocket::{binary_sink, binary_stream, websocket_error, Side, WsConnection},
+    NetworkError,
+};
+
+/// The port a relay listens on by default
+pub const RELAY_PORT: u16 = 9100;
+/// How long a client waits for the server to take its connection
+const RELAY_ACCEPT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the relay pings the control connections of the servers, to keep them open through the NATs
+const RELAY_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The relay the client falls back to when it can't connect to the server directly
+#[derive(Debug, Clone)]
+pub struct RelayAddressKey;
+impl SyncAssetKey<SocketAddr> for RelayAddressKey {}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum RelayRequest {
+    /// Registers the server which sends it, which the players join on `port`
+    Register { port: u16 },
+    /// Asks for a connection to the server registered under `session`
+    Join { session: String },
+    /// Takes the connection of the client `id` for the server registered under `session`, which it proves with the
+    /// `secret` it was registered with
+    Accept { session: String, secret: String, id: u64 },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum RelayResponse {
+    /// The server is registered under `session`, and takes the connections of its clients with `secret`
+    Registered {
+        session: String,
+        secret: String,
+    },
+    /// A client asks to join the server, which should open a connection to the relay to take it
+    Incoming {
+        id: u64,
+        client_addr: SocketAddr,
+    },
+    /// The connection is piped to the peer from now on
+    Connected,
+    Refused(String),
+}
+
+type RelaySocket = WebSocketStream<TcpStream>;
+
+/// A server registered with the relay
+struct Session {
+    secret: String,
+    control: mpsc::UnboundedSender<RelayResponse>,
+    /// The clients waiting for the server to take their connection, by id
+    pending: HashMap<u64, oneshot::Sender<RelaySocket>>,
+}
+
+type Sessions = Arc<Mutex<HashMap<String, Session>>>;
+
+/// Pipes the connections of the clients to the servers registered with it
+pub struct Relay {
+    listener: TcpListener,
+    pub port: u16,
+}
+impl Relay {
+    pub async fn new_with_port(port: u16) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port)).await?;
+        let port = listener.local_addr()?.port();
+        log::info!("Relay listening on port {port}");
+        Ok(Self { listener, port })
+    }
+    pub async fn run(self) -> anyhow::Result<()> {
+        let sessions = Sessions::default();
+        loop {
+            let (stream, addr) = self.listener.accept().await?;
+            let sessions = sessions.clone();
+            tokio::spawn(async move {
+                if let Err(err) = handle_connection(stream, addr, sessions).await {
+                    log::warn!("Relay connection from {addr} failed: {err}");
+                }
+            });
+        }
+    }
+}
+
+async fn handle_connection(stream: TcpStream, addr: SocketAddr, sessions: Sessions) -> Result<(), NetworkError> {
+    let mut socket = tokio_tungstenite::accept_async(stream).await.map_err(websocket_error)?;
+    match recv(&mut socket).await? {
+        RelayRequest::Register { port } => {
+            let (control, responses) = mpsc::unbounded();
+            let secret = new_secret();
+            let session = {
+                let mut sessions = sessions.lock();
+                let session = free_session(addr.ip(), port, |session| sessions.contains_key(session));
+                if let Some(session) = &session {
+                    sessions.insert(session.clone(), Session { secret: secret.clone(), control, pending: HashMap::new() });
+                }
+                session
+            };
+            let session = match session {
+                Some(session) => session,
+                None => {
+                    return send(&mut socket, &RelayResponse::Refused(format!("Too many servers are registered at {}", addr.ip()))).await
+                }
+            };
+            log::info!("Registered the server at {session}");
+            let result = run_control(&mut socket, &session, secret, responses).await;
+            sessions.lock().remove(&session);
+            log::info!("Unregistered the server at {session}");
+            result
+        }
+        RelayRequest::Join { session } => {
+            let (server_tx, server_rx) = oneshot::channel();
+            let mut id = rand::random();
+            let requested = match sessions.lock().get_mut(&session) {
+                Some(registered) => {
+                    while registered.pending.contains_key(&id) {
+                        id = rand::random();
+                    }
+                    registered.pending.insert(id, server_tx);
+                    registered.control.unbounded_send(RelayResponse::Incoming { id, client_addr: addr }).is_ok()
+                }
+                None => false,
+            };
+            if !requested {
+                return send(&mut socket, &RelayResponse::Refused(format!("No server is registered at {session}"))).await;
+            }
+            let server = tokio::time::timeout(RELAY_ACCEPT_TIMEOUT, server_rx).await;
+            if let Some(registered) = sessions.lock().get_mut(&session) {
+                registered.pending.remove(&id);
+            }
+            let server = match server {
+                Ok(Ok(server)) => server,
+                _ => return send(&mut socket, &RelayResponse::Refused("The server didn't take the connection".to_string())).await,
+            };
+            send(&mut socket, &RelayResponse::Connected).await?;
+            log::info!("Piping {addr} to the server at {session}");
+            pipe(socket, server).await;
+            Ok(())
+        }
+        RelayRequest::Accept { session, secret, id } => {
+            // Only the registered server may take the connections of its clients
+            let client = sessions
+                .lock()
+                .get_mut(&session)
+                .filter(|registered| registered.secret == secret)
+                .and_then(|registered| registered.pending.remove(&id));
+            match client {
+                Some(client) => {
+                    send(&mut socket, &RelayResponse::Connected).await?;
+                    client.send(socket).ok();
+                    Ok(())
+                }
+                None => send(&mut socket, &RelayResponse::Refused(format!("No client {id} is waiting for the server at {session}"))).await,
+            }
+        }
+    }
+}
+
+/// The most servers registered at the same address, e.g. when they're all behind the same carrier-grade NAT
+const MAX_SESSIONS_PER_IP: u16 = 256;
+
+/// The address a server at `ip` which the players join on `port` is registered under: the first one from `port` on which
+/// isn't `taken` by another server behind the same NAT
+fn free_session(ip: IpAddr, port: u16, taken: impl Fn(&str) -> bool) -> Option<String> {
+    (0..MAX_SESSIONS_PER_IP)
+        .filter_map(|offset| port.checked_add(offset))
+        .map(|port| SocketAddr::new(ip, port).to_string())
+        .find(|session| !taken(session))
+}
+
+/// The secret with which a server takes the connections of its clients, which can't be guessed by anyone else
+fn new_secret() -> String {
+    let bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Forwards the requests of the clients to the server registered as `session`, until it disconnects
+async fn run_control(
+    socket: &mut RelaySocket,
+    session: &str,
+    secret: String,
+    mut responses: mpsc::UnboundedReceiver<RelayResponse>,
+) -> Result<(), NetworkError> {
+    send(socket, &RelayResponse::Registered { session: session.to_string(), secret }).await?;
+    let mut keep_alive = tokio::time::interval(RELAY_KEEP_ALIVE_INTERVAL);
+    loop {
+        tokio::select! {
+            Some(response) = responses.next() => send(socket, &response).await?,
+            _ = keep_alive.tick() => socket.send(Message::Ping(Vec::new())).await.map_err(websocket_error)?,
+            message = socket.next() => match message {
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(websocket_error(err)),
+            },
+        }
+    }
+}
+
+/// Forwards the frames between `a` and `b`, until either of them disconnects
+async fn pipe(a: RelaySocket, b: RelaySocket) {
+    let (a_sink, a_stream) = a.split();
+    let (b_sink, b_stream) = b.split();
+    let a_to_b = a_stream.try_filter(|message| future::ready(message.is_binary())).forward(b_sink);
+    let b_to_a = b_stream.try_filter(|message| future::ready(message.is_binary())).forward(a_sink);
+    future::select(a_to_b, b_to_a).await;
+}
+
+/// Connects to the server which is registered under `server_addr` with the relay at `relay_addr`
+pub async fn connect(relay_addr: SocketAddr, server_addr: SocketAddr) -> Result<NewConnection, NetworkError> {
+    let mut socket = connect_to_relay(relay_addr, &RelayRequest::Join { session: server_addr.to_string() }).await?;
+    match recv(&mut socket).await? {
+        RelayResponse::Connected => {}
+        RelayResponse::Refused(reason) => return Err(NetworkError::RelayRefused(reason)),
+        response => return Err(NetworkError::RelayRefused(format!("Unexpected response {response:?}"))),
+    }
+    let (sink, stream) = socket.split();
+    Ok(WsConnection::spawn(binary_sink(sink), binary_stream(stream), Side::Client, server_addr))
+}
+
+/// The registration of a server with a relay, through which the clients which can't reach the server directly join it
+pub struct RelayListener {
+    relay_addr: SocketAddr,
+    session: String,
+    secret: String,
+    control: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+impl RelayListener {
+    /// Registers the server which the players join on `port` with the relay at `relay_addr`
+    pub async fn register(relay_addr: SocketAddr, port: u16) -> Result<Self, NetworkError> {
+        let mut control = connect_to_relay(relay_addr, &RelayRequest::Register { port }).await?;
+        match recv(&mut control).await? {
+            RelayResponse::Registered { session, secret } => Ok(Self { relay_addr, session, secret, control }),
+            RelayResponse::Refused(reason) => Err(NetworkError::RelayRefused(reason)),
+            response => Err(NetworkError::RelayRefused(format!("Unexpected response {response:?}"))),
+        }
+    }
+    /// The address the players join the server at, which is the one the relay sees it at; with another port if the one of
+    /// the server is taken by another server behind the same NAT
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+    /// Waits for a client to join the server through the relay
+    pub async fn accept(&mut self) -> Result<RelayedClient, NetworkError> {
+        loop {
+            if let RelayResponse::Incoming { id, client_addr } = recv(&mut self.control).await? {
+                return Ok(RelayedClient {
+                    relay_addr: self.relay_addr,
+                    session: self.session.clone(),
+                    secret: self.secret.clone(),
+                    id,
+                    client_addr,
+                });
+            }
+        }
+    }
+}
+
+/// A client waiting for the server to take its connection through the relay
+#[derive(Debug, Clone)]
+pub struct RelayedClient {
+    relay_addr: SocketAddr,
+    session: String,
+    secret: String,
+    id: u64,
+    pub client_addr: SocketAddr,
+}
+impl RelayedClient {
+    /// Opens the connection to the client through the relay
+    pub async fn connect(self) -> Result<NewConnection, NetworkError> {
+        let mut socket =
+            connect_to_relay(self.relay_addr, &RelayRequest::Accept { session: self.session, secret: self.secret, id: self.id }).await?;
+        match recv(&mut socket).await? {
+            RelayResponse::Connected => {}
+            RelayResponse::Refused(reason) => return Err(NetworkError::RelayRefused(reason)),
+            response => return Err(NetworkError::RelayRefused(format!("Unexpected response {response:?}"))),
+        }
+        let (sink, stream) = socket.split();
+        Ok(WsConnection::spawn(binary_sink(sink), binary_stream(stream), Side::Server, self.client_addr))
+    }
+}
+
+async fn connect_to_relay(
+    relay_addr: SocketAddr,
+    request: &RelayRequest,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, NetworkError> {
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{relay_addr}")).await.map_err(websocket_error)?;
+    send(&mut socket, request).await?;
+    Ok(socket)
+}
+
+async fn send<Si: Sink<Message, Error = tungstenite::Error> + Unpin, T: Serialize>(sink: &mut Si, message: &T) -> Result<(), NetworkError> {
+    sink.send(Message::Binary(bincode::serialize(message)?)).await.map_err(websocket_error)
+}
+
+/// Receives the next message, skipping the pings and pongs
+async fn recv<St: Stream<Item = Result<Message, tungstenite::Error>> + Unpin, T: DeserializeOwned>(
+    stream: &mut St,
+) -> Result<T, NetworkError> {
+    loop {
+        match stream.next().await {
+            Some(Ok(Message::Binary(data))) => return Ok(bincode::deserialize(&data)?),
+            Some(Ok(Message::Close(_))) | None => return Err(NetworkError::ConnectionClosed),
+            Some(Ok(_)) => {}
+            Some(Err(err)) => return Err(websocket_error(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_session_skips_the_taken_ports() {
+        let ip = IpAddr::V4(Ipv4Addr::new(100, 64, 0, 1));
+        assert_eq!(free_session(ip, 9000, |_| false).as_deref(), Some("100.64.0.1:9000"));
+        let taken = ["100.64.0.1:9000", "100.64.0.1:9001"];
+        assert_eq!(free_session(ip, 9000, |session| taken.contains(&session)).as_deref(), Some("100.64.0.1:9002"));
+        assert_eq!(free_session(ip, 9000, |_| true), None);
+        assert_eq!(free_session(ip, u16::MAX, |session| session.ends_with(":65535")), None);
+    }
+
+    #[test]
+    fn secrets_are_random() {
+        let secret = new_secret();
+        assert_eq!(secret.len(), 32);
+        assert_ne!(secret, new_secret());
+    }
+
+    #[tokio::test]
+    async fn relays_a_client_to_its_server() {
+        let relay = Relay::new_with_port(0).await.unwrap();
+        let relay_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), relay.port);
+        tokio::spawn(relay.run());
+
+        let mut server = RelayListener::register(relay_addr, 9000).await.unwrap();
+        assert_eq!(server.session(), "127.0.0.1:9000");
+        // Another server behind the same address gets the next port, rather than being refused
+        let other = RelayListener::register(relay_addr, 9000).await.unwrap();
+        assert_eq!(other.session(), "127.0.0.1:9001");
+
+        assert!(matches!(connect(relay_addr, "127.0.0.1:9002".parse().unwrap()).await, Err(NetworkError::RelayRefused(_))));
+
+        let client = tokio::spawn(connect(relay_addr, "127.0.0.1:9000".parse().unwrap()));
+        let relayed = server.accept().await.unwrap();
+
+        // The connection can't be taken without the secret of the server, even from the same address
+        let request = RelayRequest::Accept { session: relayed.session.clone(), secret: other.secret.clone(), id: relayed.id };
+        let mut socket = connect_to_relay(relay_addr, &request).await.unwrap();
+        assert!(matches!(recv(&mut socket).await.unwrap(), RelayResponse::Refused(_)));
+
+        relayed.connect().await.unwrap();
+        client.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,331 @@
+//! Interest management: the server only replicates to a player the entities which are relevant to them, so that the
+//! traffic of a client doesn't grow with the size of the world.
+//!
+//! If the player entity has a [relevance_radius], the entities with a `translation` are only replicated to the player
+//! while they're within that distance of the player's [avatar], or in the same [relevance_zone] as it. The entities
+//! without a position (such as the players and the resources) are always replicated.
+//!
+//! The entities which stop being relevant are despawned on the client, and spawned again with their whole state when
+//! they become relevant again. To avoid doing that over and over for an entity at the edge of the radius, an entity
+//! only stops being relevant once it's [LEAVE_RADIUS_FACTOR] times as far.
+
+use std::collections::{HashMap, HashSet};
+
+use ambient_core::transform::translation;
+use ambient_ecs::{
+    components, query, Debuggable, Description, EntityId, Name, Networked, World, WorldChange, WorldDiff, WorldStreamFilter,
+};
+use glam::{ivec3, IVec3, Vec3};
+
+components!("network", {
+    @[
+        Debuggable, Networked,
+        Name["Relevance radius"],
+        Description["If attached to a player entity, the server only replicates to the player the entities within this distance of their avatar, or in the same zone as it.\nThe entities without a `translation` are always replicated."]
+    ]
+    relevance_radius: f32,
+    @[
+        Debuggable, Networked,
+        Name["Avatar"],
+        Description["The entity whose position and zone decide which entities are relevant to this player.\nIf not attached, the player entity itself is used."]
+    ]
+    avatar: EntityId,
+    @[
+        Debuggable, Networked,
+        Name["Relevance zone"],
+        Description["The entities in the same zone as the avatar of a player are replicated to them, however far they are."]
+    ]
+    relevance_zone: String,
+    @[
+        Debuggable, Networked,
+        Name["Always relevant"],
+        Description["If attached, this entity is replicated to all players, however far it is from them."]
+    ]
+    always_relevant: (),
+
+    /// The entities which are currently replicated to a player with a [relevance_radius]
+    replicated_entities: HashSet<EntityId>,
+});
+
+/// How much farther than the [relevance_radius] an entity has to go to stop being relevant
+pub const LEAVE_RADIUS_FACTOR: f32 = 1.1;
+
+/// The replicated entities, bucketed by what can make them relevant, so that the entities relevant to a player are found
+/// without going through all of them. It's built once per diff, and shared by all the players.
+#[derive(Debug, Default)]
+pub(crate) struct RelevanceGrid {
+    /// The size of the cells, which is the largest distance an entity can be relevant from, so that a player only looks
+    /// at the few cells around their avatar
+    cell_size: f32,
+    cells: HashMap<IVec3, Vec<(EntityId, Vec3)>>,
+    zones: HashMap<String, Vec<EntityId>>,
+    /// The entities which are relevant to all players, because they're [always_relevant] or have no position
+    everywhere: Vec<EntityId>,
+}
+impl RelevanceGrid {
+    pub fn new(world: &World, filter: &WorldStreamFilter) -> Self {
+        let radii = query((relevance_radius(),)).iter(world, None).map(|(_, (&radius,))| radius).collect::<Vec<_>>();
+        // Without any player having a radius, all entities are relevant to all of them, which doesn't need a grid
+        if radii.is_empty() {
+            return Self::default();
+        }
+        let max_radius = radii.into_iter().fold(1., f32::max);
+        let mut grid = Self { cell_size: max_radius * LEAVE_RADIUS_FACTOR, ..Default::default() };
+        for id in filter.all_entities(world) {
+            if world.has_component(id, always_relevant()) {
+                grid.everywhere.push(id);
+                continue;
+            }
+            let zone = world.get_ref(id, relevance_zone()).ok();
+            if let Some(zone) = zone {
+                grid.zones.entry(zone.clone()).or_default().push(id);
+            }
+            match world.get(id, translation()) {
+                Ok(position) => grid.cells.entry(grid.cell(position)).or_default().push((id, position)),
+                // Nothing can be said about the distance to the entities without a position, so they're relevant to all
+                // players, unless they belong to a zone
+                Err(_) if zone.is_none() => grid.everywhere.push(id),
+                Err(_) => {}
+            }
+        }
+        grid
+    }
+    fn cell(&self, position: Vec3) -> IVec3 {
+        (position / self.cell_size).floor().as_ivec3()
+    }
+    /// The entities relevant to an avatar at `position` in `zone`. The `replicated` entities stay relevant a bit farther
+    /// than `radius`.
+    fn relevant(&self, position: Option<Vec3>, zone: Option<&String>, radius: f32, replicated: &HashSet<EntityId>) -> HashSet<EntityId> {
+        let mut relevant = self.everywhere.iter().copied().collect::<HashSet<_>>();
+        if let Some(entities) = zone.and_then(|zone| self.zones.get(zone)) {
+            relevant.extend(entities);
+        }
+        let origin = match position {
+            Some(origin) => origin,
+            // Nothing can be said about the distance to the entities before the avatar has a position
+            None => {
+                relevant.extend(self.cells.values().flatten().map(|&(id, _)| id));
+                return relevant;
+            }
+        };
+        let leave_radius = radius * LEAVE_RADIUS_FACTOR;
+        let (min, max) = (self.cell(origin - leave_radius), self.cell(origin + leave_radius));
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let entities = match self.cells.get(&ivec3(x, y, z)) {
+                        Some(entities) => entities,
+                        None => continue,
+                    };
+                    relevant.extend(entities.iter().filter_map(|&(id, position)| {
+                        let radius = if replicated.contains(&id) { leave_radius } else { radius };
+                        (origin.distance_squared(position) <= radius * radius).then_some(id)
+                    }));
+                }
+            }
+        }
+        relevant
+    }
+}
+
+/// Restricts `diff` to the entities which are relevant to the player `player_id`, spawning and despawning the ones which
+/// became relevant or stopped being since the last diff. Returns `None` if all of `diff` is relevant to the player.
+pub(crate) fn player_diff(
+    world: &mut World,
+    filter: &WorldStreamFilter,
+    grid: &RelevanceGrid,
+    player_id: EntityId,
+    diff: &WorldDiff,
+) -> Option<WorldDiff> {
+    let radius = world.get(player_id, relevance_radius()).ok();
+    let replicated = world.get_ref(player_id, replicated_entities()).ok().cloned();
+    if radius.is_none() && replicated.is_none() {
+        return None;
+    }
+    // Before the player has a radius, everything was replicated to them, except what's spawned by this diff
+    let replicated = replicated.unwrap_or_else(|| {
+        let spawned = diff.changes.iter().filter_map(|change| match change {
+            WorldChange::Spawn(id, _) => *id,
+            _ => None,
+        });
+        let spawned = spawned.collect::<HashSet<_>>();
+        filter.all_entities(world).filter(|id| !spawned.contains(id)).collect()
+    });
+
+    let relevant = match radius {
+        Some(radius) => {
+            let avatar = world.get(player_id, avatar()).unwrap_or(player_id);
+            let zone = world.get_ref(avatar, relevance_zone()).ok();
+            grid.relevant(world.get(avatar, translation()).ok(), zone, radius, &replicated)
+        }
+        None => filter.all_entities(world).collect(),
+    };
+
+    // The changes to the entities which were and are still relevant go through; the other ones are covered by spawning or
+    // despawning the entity
+    let mut changes = diff
+        .changes
+        .iter()
+        .filter(|change| {
+            let id = match change {
+                WorldChange::Spawn(None, _) => return false,
+                WorldChange::Despawn(id) => return replicated.contains(id),
+                WorldChange::Spawn(Some(id), _)
+                | WorldChange::AddComponents(id, _)
+                | WorldChange::RemoveComponents(id, _)
+                | WorldChange::Set(id, _) => *id,
+            };
+            replicated.contains(&id) && relevant.contains(&id)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    for &id in replicated.difference(&relevant) {
+        if world.exists(id) {
+            changes.push(WorldChange::Despawn(id));
+        }
+    }
+    for &id in relevant.difference(&replicated) {
+        changes.push(WorldChange::Spawn(Some(id), filter.read_entity_components(world, id).into()));
+    }
+
+    if radius.is_none() {
+        world.remove_component(player_id, replicated_entities()).unwrap();
+    } else if world.has_component(player_id, replicated_entities()) {
+        world.set(player_id, replicated_entities(), relevant).unwrap();
+    } else {
+        world.add_component(player_id, replicated_entities(), relevant).unwrap();
+    }
+    Some(WorldDiff { changes })
+}
+
+#[cfg(test)]
+mod tests {
+    use ambient_core::name;
+    use ambient_ecs::EntityData;
+    use glam::vec3;
+
+    use super::*;
+
+    fn init() {
+        ambient_core::init_all_components();
+        crate::init_all_components();
+    }
+
+    fn spawn_at(world: &mut World, position: Vec3) -> EntityId {
+        EntityData::new().set(translation(), position).spawn(world)
+    }
+
+    /// Runs a tick of the replication to `player`, and returns the entities it spawned and despawned
+    fn tick(world: &mut World, player: EntityId, diff: &WorldDiff) -> (Vec<EntityId>, Vec<EntityId>) {
+        let filter = WorldStreamFilter::default();
+        let grid = RelevanceGrid::new(world, &filter);
+        let diff = player_diff(world, &filter, &grid, player, diff).unwrap();
+        let (mut spawned, mut despawned) = (Vec::new(), Vec::new());
+        for change in diff.changes {
+            match change {
+                WorldChange::Spawn(Some(id), _) => spawned.push(id),
+                WorldChange::Despawn(id) => despawned.push(id),
+                _ => {}
+            }
+        }
+        spawned.sort();
+        despawned.sort();
+        (spawned, despawned)
+    }
+
+    #[test]
+    fn only_the_entities_within_the_radius_are_relevant() {
+        init();
+        let mut world = World::new("relevance");
+        let player = EntityData::new().set(relevance_radius(), 10.).set(translation(), Vec3::ZERO).spawn(&mut world);
+        let near = spawn_at(&mut world, vec3(5., 0., 0.));
+        let diagonal = spawn_at(&mut world, vec3(-6., -6., 0.));
+        let far = spawn_at(&mut world, vec3(50., 0., 0.));
+        let unpositioned = EntityData::new().set(name(), "resource".to_string()).spawn(&mut world);
+        let everywhere = EntityData::new().set(translation(), vec3(1000., 0., 0.)).set(always_relevant(), ()).spawn(&mut world);
+
+        let (spawned, despawned) = tick(&mut world, player, &WorldDiff::new());
+        assert!(spawned.is_empty());
+        assert_eq!(despawned, vec![far]);
+        let replicated = world.get_ref(player, replicated_entities()).unwrap();
+        assert_eq!(*replicated, HashSet::from([world.resource_entity(), player, near, diagonal, unpositioned, everywhere]));
+    }
+
+    #[test]
+    fn entities_leave_past_the_hysteresis() {
+        init();
+        let mut world = World::new("relevance");
+        let player = EntityData::new().set(relevance_radius(), 10.).set(translation(), Vec3::ZERO).spawn(&mut world);
+        let entity = spawn_at(&mut world, vec3(20., 0., 0.));
+        tick(&mut world, player, &WorldDiff::new());
+
+        // It becomes relevant within the radius
+        world.set(entity, translation(), vec3(9., 0., 0.)).unwrap();
+        assert_eq!(tick(&mut world, player, &WorldDiff::new()), (vec![entity], vec![]));
+        // It stays relevant just past the radius, and changes to it go through
+        world.set(entity, translation(), vec3(10.5, 0., 0.)).unwrap();
+        let diff = WorldDiff::new().set(entity, translation(), vec3(10.5, 0., 0.));
+        let filter = WorldStreamFilter::default();
+        let grid = RelevanceGrid::new(&world, &filter);
+        let player_diff = player_diff(&mut world, &filter, &grid, player, &diff).unwrap();
+        assert!(matches!(&player_diff.changes[..], [WorldChange::Set(id, _)] if *id == entity));
+        // And stops being once past the hysteresis
+        world.set(entity, translation(), vec3(11.5, 0., 0.)).unwrap();
+        assert_eq!(tick(&mut world, player, &WorldDiff::new()), (vec![], vec![entity]));
+    }
+
+    #[test]
+    fn zones_and_avatars() {
+        init();
+        let mut world = World::new("relevance");
+        let avatar_id =
+            EntityData::new().set(translation(), vec3(100., 0., 0.)).set(relevance_zone(), "house".to_string()).spawn(&mut world);
+        let player = EntityData::new().set(relevance_radius(), 10.).set(avatar(), avatar_id).spawn(&mut world);
+        let near_avatar = spawn_at(&mut world, vec3(105., 0., 0.));
+        let near_player = spawn_at(&mut world, vec3(5., 0., 0.));
+        let in_zone =
+            EntityData::new().set(translation(), vec3(-500., 0., 0.)).set(relevance_zone(), "house".to_string()).spawn(&mut world);
+        let other_zone = EntityData::new().set(relevance_zone(), "garden".to_string()).spawn(&mut world);
+
+        let (_, despawned) = tick(&mut world, player, &WorldDiff::new());
+        let mut expected = vec![near_player, other_zone];
+        expected.sort();
+        assert_eq!(despawned, expected);
+        let replicated = world.get_ref(player, replicated_entities()).unwrap();
+        assert_eq!(*replicated, HashSet::from([world.resource_entity(), player, avatar_id, near_avatar, in_zone]));
+    }
+
+    #[test]
+    fn spawned_entities_are_only_sent_when_relevant() {
+        init();
+        let mut world = World::new("relevance");
+        let player = EntityData::new().set(relevance_radius(), 10.).set(translation(), Vec3::ZERO).spawn(&mut world);
+        tick(&mut world, player, &WorldDiff::new());
+
+        let near = spawn_at(&mut world, vec3(0., 3., 0.));
+        let far = spawn_at(&mut world, vec3(0., 300., 0.));
+        let diff = WorldDiff {
+            changes: vec![
+                WorldChange::Spawn(Some(near), EntityData::new().set(translation(), vec3(0., 3., 0.))),
+                WorldChange::Spawn(Some(far), EntityData::new().set(translation(), vec3(0., 300., 0.))),
+            ],
+        };
+        assert_eq!(tick(&mut world, player, &diff), (vec![near], vec![]));
+    }
+
+    #[test]
+    fn removing_the_radius_replicates_everything_again() {
+        init();
+        let mut world = World::new("relevance");
+        let player = EntityData::new().set(relevance_radius(), 10.).set(translation(), Vec3::ZERO).spawn(&mut world);
+        let far = spawn_at(&mut world, vec3(0., 0., 300.));
+        tick(&mut world, player, &WorldDiff::new());
+
+        world.remove_component(player, relevance_radius()).unwrap();
+        assert_eq!(tick(&mut world, player, &WorldDiff::new()), (vec![far], vec![]));
+        assert!(!world.has_component(player, replicated_entities()));
+        let filter = WorldStreamFilter::default();
+        let grid = RelevanceGrid::new(&world, &filter);
+        assert!(player_diff(&mut world, &filter, &grid, player, &WorldDiff::new()).is_none());
+    }
+}
@@ -0,0 +1,276 @@
+//! Replication rates and priorities: the server replicates the changes to a component at most at its [ReplicationRate],
+//! and within the bandwidth budget of each player, sending the changes of the highest [ReplicationPriority] first.
+//!
+//! The changes which can't be sent yet are merged: only the latest value of a component of an entity waits, and it's
+//! sent as soon as the rate and the budget let it through, the values which waited the longest first among those of the
+//! same priority. Spawning and despawning entities, and adding and removing components, are always sent right away, as
+//! the other changes depend on them; they carry the latest values, which replace the ones waiting.
+//!
+//! The budget of a player is their [replication_budget]. Without one, the changes are only held back by their rate.
+
+use std::{cmp::Reverse, collections::HashMap, time::Duration};
+
+use ambient_ecs::{
+    components, query, ComponentEntry, Debuggable, Description, EntityId, Name, Networked, ReplicationPriority, ReplicationRate, World,
+    WorldChange, WorldDiff,
+};
+use ambient_sys::time::Instant;
+use itertools::Itertools;
+
+components!("network", {
+    @[
+        Debuggable, Networked,
+        Name["Replication budget"],
+        Description["If attached to a player entity, the server replicates at most this many bytes per second to the player.\nThe changes over the budget wait for the next diffs, the ones of the components with the highest priority going first."]
+    ]
+    replication_budget: u64,
+
+    /// The changes waiting to be replicated to a player
+    replication_schedule: ReplicationSchedule,
+});
+
+#[derive(Debug, Clone)]
+pub struct ReplicationSchedule {
+    /// The latest value of each component of each entity which waits to be sent
+    pending: HashMap<(EntityId, u32), Pending>,
+    /// When the components with a rate may be sent again
+    next: HashMap<(EntityId, u32), Instant>,
+    /// The bytes which may be sent now, negative once more than the budget has been sent
+    available: f64,
+    refilled: Instant,
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    entry: ComponentEntry,
+    priority: i32,
+    /// When the first of the changes merged into this one was made
+    since: Instant,
+}
+
+impl ReplicationSchedule {
+    fn new(now: Instant) -> Self {
+        // The budget starts full, once it's known
+        Self { pending: HashMap::new(), next: HashMap::new(), available: f64::INFINITY, refilled: now }
+    }
+
+    fn forget_entity(&mut self, id: EntityId) {
+        self.pending.retain(|key, _| key.0 != id);
+        self.next.retain(|key, _| key.0 != id);
+    }
+
+    /// Queues the changes of `diff`, and returns the ones to send now
+    fn schedule(&mut self, diff: &WorldDiff, budget: Option<u64>, now: Instant) -> WorldDiff {
+        if let Some(budget) = budget {
+            // The budget holds up to a second of it
+            let elapsed = now.duration_since(self.refilled).as_secs_f64();
+            self.available = (self.available + elapsed * budget as f64).min(budget as f64);
+        }
+        self.refilled = now;
+        let spend = |change: &WorldChange, available: &mut f64| {
+            if budget.is_some() {
+                *available -= bincode::serialized_size(change).unwrap_or_default() as f64;
+            }
+        };
+
+        let mut changes = Vec::new();
+        for change in &diff.changes {
+            match change {
+                WorldChange::Set(id, entry) => {
+                    let key = (*id, entry.desc().index());
+                    if budget.is_none() && !entry.desc().has_attribute::<ReplicationRate>() {
+                        self.pending.remove(&key);
+                        spend(change, &mut self.available);
+                        changes.push(change.clone());
+                        continue;
+                    }
+                    let priority = entry.attribute::<ReplicationPriority>().map(|priority| priority.0).unwrap_or_default();
+                    let since = self.pending.get(&key).map(|pending| pending.since).unwrap_or(now);
+                    self.pending.insert(key, Pending { entry: entry.clone(), priority, since });
+                    continue;
+                }
+                WorldChange::Spawn(Some(id), _) | WorldChange::Despawn(id) => self.forget_entity(*id),
+                WorldChange::AddComponents(id, data) => {
+                    for entry in data.iter() {
+                        self.pending.remove(&(*id, entry.desc().index()));
+                    }
+                }
+                WorldChange::RemoveComponents(id, descs) => {
+                    for desc in descs {
+                        self.pending.remove(&(*id, desc.index()));
+                    }
+                }
+                WorldChange::Spawn(None, _) => {}
+            }
+            spend(change, &mut self.available);
+            changes.push(change.clone());
+        }
+
+        self.next.retain(|_, next| *next > now);
+        let due = self
+            .pending
+            .iter()
+            .filter(|(key, _)| !self.next.contains_key(key))
+            .map(|(&key, pending)| (key, pending.priority, pending.since))
+            .sorted_by_key(|&(_, priority, since)| (Reverse(priority), since))
+            .collect_vec();
+        for (key, _, _) in due {
+            if budget.is_some() && self.available <= 0. {
+                break;
+            }
+            let Pending { entry, .. } = self.pending.remove(&key).unwrap();
+            if let Some(rate) = entry.attribute::<ReplicationRate>() {
+                self.next.insert(key, now + Duration::from_secs_f32(1. / rate.0));
+            }
+            let change = WorldChange::Set(key.0, entry);
+            spend(&change, &mut self.available);
+            changes.push(change);
+        }
+        WorldDiff { changes }
+    }
+}
+
+/// Holds back the changes of `diff` which the rates of their components or the budget of the player `player_id` don't
+/// let through yet, and adds the ones which waited and may be sent now. Returns `None` if all of `diff` is sent as is.
+pub(crate) fn player_diff(world: &mut World, player_id: EntityId, diff: &WorldDiff, now: Instant) -> Option<WorldDiff> {
+    let budget = world.get(player_id, replication_budget()).ok();
+    if !world.has_component(player_id, replication_schedule()) {
+        let scheduled = diff.changes.iter().any(|change| match change {
+            WorldChange::Set(_, entry) => budget.is_some() || entry.desc().has_attribute::<ReplicationRate>(),
+            _ => false,
+        });
+        if !scheduled {
+            return None;
+        }
+        world.add_component(player_id, replication_schedule(), ReplicationSchedule::new(now)).unwrap();
+    }
+    let schedule = world.get_mut(player_id, replication_schedule()).unwrap();
+    let diff = schedule.schedule(diff, budget, now);
+    if budget.is_none() && schedule.pending.is_empty() && schedule.next.is_empty() {
+        world.remove_component(player_id, replication_schedule()).unwrap();
+    }
+    Some(diff)
+}
+
+/// Whether changes are waiting to be replicated to any of the players, so that the next diffs have to be sent even if
+/// nothing changed
+pub(crate) fn has_pending(world: &World) -> bool {
+    query((replication_schedule(),)).iter(world, None).any(|(_, (schedule,))| !schedule.pending.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use ambient_core::{name, transform::translation};
+    use ambient_ecs::EntityData;
+    use glam::{vec3, Vec3};
+
+    use super::*;
+
+    fn init() {
+        ambient_core::init_all_components();
+        crate::init_all_components();
+    }
+
+    fn move_to(id: EntityId, x: f32) -> WorldChange {
+        WorldChange::Set(id, ComponentEntry::new(translation(), vec3(x, 0., 0.)))
+    }
+
+    fn rename(id: EntityId, value: &str) -> WorldChange {
+        WorldChange::Set(id, ComponentEntry::new(name(), value.to_string()))
+    }
+
+    fn diff(changes: Vec<WorldChange>) -> WorldDiff {
+        WorldDiff { changes }
+    }
+
+    /// The translations and names set by `diff`, in order
+    fn sent(diff: &WorldDiff) -> Vec<String> {
+        diff.changes
+            .iter()
+            .map(|change| match change {
+                WorldChange::Set(_, entry) => match entry.try_downcast_ref::<Vec3>() {
+                    Some(translation) => translation.x.to_string(),
+                    None => entry.try_downcast_ref::<String>().unwrap().clone(),
+                },
+                change => format!("{change:?}"),
+            })
+            .collect()
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn transforms_are_replicated_at_their_rate() {
+        init();
+        let t0 = Instant::now();
+        let mut schedule = ReplicationSchedule::new(t0);
+        let id = EntityId::new();
+
+        assert_eq!(sent(&schedule.schedule(&diff(vec![move_to(id, 1.)]), None, t0)), ["1"]);
+        // The components without a rate aren't held back
+        assert_eq!(sent(&schedule.schedule(&diff(vec![move_to(id, 2.), rename(id, "a")]), None, t0 + ms(10))), ["a"]);
+        assert!(sent(&schedule.schedule(&diff(vec![move_to(id, 3.)]), None, t0 + ms(20))).is_empty());
+        // Only the latest value is sent, once the rate lets it through
+        assert_eq!(sent(&schedule.schedule(&WorldDiff::new(), None, t0 + ms(34))), ["3"]);
+        assert!(sent(&schedule.schedule(&WorldDiff::new(), None, t0 + ms(100))).is_empty());
+        assert!(schedule.pending.is_empty());
+
+        // Each entity has its own rate
+        let other = EntityId::new();
+        let both = sent(&schedule.schedule(&diff(vec![move_to(id, 4.), move_to(other, 5.)]), None, t0 + ms(110)));
+        assert_eq!(both.into_iter().sorted().collect_vec(), ["4", "5"]);
+    }
+
+    #[test]
+    fn the_budget_goes_to_the_highest_priorities_first() {
+        init();
+        let t0 = Instant::now();
+        let mut schedule = ReplicationSchedule::new(t0);
+        let ids = (0..4).map(|_| EntityId::new()).collect_vec();
+        // Enough for two translations a second
+        let budget = Some(2 * bincode::serialized_size(&move_to(ids[0], 0.)).unwrap());
+
+        let moves = ids.iter().enumerate().map(|(i, &id)| move_to(id, i as f32)).collect_vec();
+        assert_eq!(sent(&schedule.schedule(&diff(moves), budget, t0)).len(), 2);
+        // The budget is spent, but the names wait behind the translations of the higher priority
+        let mut later = Vec::new();
+        for (i, &id) in ids.iter().enumerate() {
+            later.extend(sent(&schedule.schedule(&diff(vec![rename(id, &format!("name {i}"))]), budget, t0 + ms(1 + i as u64))));
+        }
+        assert_eq!(later.len(), 1);
+        assert!(later[0].parse::<f32>().is_ok());
+
+        // The budget refills over a second, and the changes which waited the longest go first among the same priority
+        let mut rest = Vec::new();
+        for second in 1..10 {
+            rest.extend(sent(&schedule.schedule(&WorldDiff::new(), budget, t0 + ms(1000 * second))));
+        }
+        assert!(rest[0].parse::<f32>().is_ok());
+        assert_eq!(rest[1..], ["name 0", "name 1", "name 2", "name 3"]);
+        assert!(schedule.pending.is_empty());
+    }
+
+    #[test]
+    fn structural_changes_replace_the_waiting_values() {
+        init();
+        let t0 = Instant::now();
+        let mut schedule = ReplicationSchedule::new(t0);
+        let id = EntityId::new();
+        schedule.schedule(&diff(vec![move_to(id, 1.)]), None, t0);
+        schedule.schedule(&diff(vec![move_to(id, 2.)]), None, t0 + ms(10));
+        assert_eq!(schedule.pending.len(), 1);
+
+        // The added component carries the latest value, so the waiting one is dropped
+        let add = WorldChange::AddComponents(id, EntityData::new().set(translation(), vec3(3., 0., 0.)));
+        assert_eq!(schedule.schedule(&diff(vec![add]), None, t0 + ms(20)).changes.len(), 1);
+        assert!(schedule.pending.is_empty());
+
+        schedule.schedule(&diff(vec![move_to(id, 4.)]), None, t0 + ms(25));
+        assert_eq!(schedule.pending.len(), 1);
+        assert_eq!(schedule.schedule(&diff(vec![WorldChange::Despawn(id)]), None, t0 + ms(30)).changes.len(), 1);
+        assert!(schedule.pending.is_empty() && schedule.next.is_empty());
+        assert!(sent(&schedule.schedule(&WorldDiff::new(), None, t0 + ms(100))).is_empty());
+    }
+}
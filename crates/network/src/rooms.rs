@@ -0,0 +1,42 @@
+//! Rooms: the isolated worlds which a server runs side by side, such as a lobby and the matches started from it, each
+//! with its own entities, physics scene and script instances.
+//!
+//! A room is a [crate::server::WorldInstance], and the main one is [crate::server::MAIN_INSTANCE_ID]. The scripts move a player to
+//! another room by setting the [room] of the player entity; after the tick, the player is despawned from their room and
+//! spawned in the new one, and their client receives the difference between the two worlds. A room which doesn't exist
+//! yet is created from the main room: its physics and its scripts start over, and only the entities which neither the
+//! players nor the scripts spawned are copied. The scripts of the new room can tell it apart by its [room_id]. A room
+//! other than the main one is removed when its last player leaves.
+
+use ambient_ecs::{components, query, Debuggable, Description, Name, Networked, Resource, World};
+
+use crate::player::{player, user_id};
+
+components!("network", {
+    @[
+        Debuggable, Networked,
+        Name["Room"],
+        Description["The room this player is in, which is an isolated world with its own simulation and scripts.\nSet it to move the player to another room; the room is created from the main room if it doesn't exist."]
+    ]
+    room: String,
+    @[
+        Debuggable, Resource,
+        Name["Room ID"],
+        Description["The ID of the room this world is. The main room is `main`."]
+    ]
+    room_id: String,
+});
+
+/// The players of `world` whose [room] was set to another room, with the room they go to
+pub fn pending_moves(world: &World) -> Vec<(String, String)> {
+    let current = match world.resource_opt(room_id()) {
+        Some(current) => current,
+        None => return Vec::new(),
+    };
+    query((user_id(), room()))
+        .incl(player())
+        .iter(world, None)
+        .filter(|(_, (_, room))| *room != current)
+        .map(|(_, (user_id, room))| (user_id.clone(), room.clone()))
+        .collect()
+}
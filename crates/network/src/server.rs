@@ -8,38 +8,55 @@ use std::{
 
 use ambient_core::{asset_cache, no_sync};
 use ambient_ecs::{
-    components, dont_store, query, ArchetypeFilter, ComponentDesc, EntityData, EntityId, FrameEvent, System, SystemGroup, World,
-    WorldStream, WorldStreamCompEvent, WorldStreamFilter,
+    components, dont_store, query, ArchetypeFilter, ComponentDesc, EntityData, EntityId, FrameEvent, FramedEvents, System, SystemGroup,
+    World, WorldDiff, WorldStream, WorldStreamCompEvent, WorldStreamFilter,
 };
 use ambient_std::{
-    asset_cache::AssetCache,
+    asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt},
     fps_counter::{FpsCounter, FpsSample},
     friendly_id, log_result,
+    watched_config::{Config, WatchedConfig},
 };
 use ambient_sys::time::Instant;
-use anyhow::bail;
+use anyhow::{bail, Context};
 use bytes::Bytes;
 use flume::Sender;
-use futures::StreamExt;
+use futures::{future, StreamExt};
+use itertools::Itertools;
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
-use quinn::{Endpoint, Incoming, NewConnection, RecvStream, SendStream};
+use quinn::{ConnectionError, Endpoint, Incoming};
+use serde::{Deserialize, Serialize};
 use tokio::{
-    io::AsyncReadExt,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
     time::{interval, MissedTickBehavior},
 };
 use tracing::{debug_span, Instrument};
 
 use crate::{
-    bi_stream_handlers, create_server, datagram_handlers, get_player_by_user_id, player,
-    protocol::{ClientInfo, ServerProtocol},
-    NetworkError,
+    auth::{Authenticator, Authorizer, Identity, ServerAuthenticatorKey, ServerAuthorizerKey},
+    bi_stream_handlers, create_server, datagram_handlers,
+    delta::{delta_encoder, DeltaEncoder},
+    discovery::{self, ServerAnnouncement, ServerListKey},
+    get_player_by_user_id, ownership, player,
+    protocol::{ServerProtocol, PLAYER_KICKED},
+    recording::SessionRecorder,
+    relay::{RelayListener, RelayedClient},
+    relevance,
+    replication::{self, replication_schedule},
+    rooms::{self, room, room_id},
+    stats::{network_stats, Probe, ProbeReceiver, StatsTracker, STATS_INTERVAL},
+    transport::{Connection, NewConnection, RecvStream, SendStream},
+    websocket, NetworkError, ServerWorldExt,
 };
 
 components!("network", {
     player_entity_stream: Sender<Vec<u8>>,
     player_event_stream: Sender<Vec<u8>>,
     player_stats_stream: Sender<FpsSample>,
+    /// The datagrams to send to the player, e.g. the unreliable messages
+    player_datagram_stream: Sender<Bytes>,
 });
 
 #[derive(Debug, Clone, Copy)]
@@ -51,10 +68,50 @@ pub struct ForkedEvent;
 #[derive(Debug, Clone, Copy)]
 pub struct ShutdownEvent;
 
+/// The simulation settings of a [GameServer], which can be tuned live from a file with [WatchedConfig]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerSettings {
+    /// How many times per second the world is simulated
+    pub tick_rate: f32,
+    /// How many ticks the changes to the world are batched over before they are sent to the players
+    pub replication_interval: u32,
+}
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self { tick_rate: 60., replication_interval: 1 }
+    }
+}
+impl Config for ServerSettings {
+    fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!((1. ..=240.).contains(&self.tick_rate), "tick_rate must be between 1 and 240");
+        // The changes are only kept for so many frames
+        let max_interval = FramedEvents::<()>::HISTORY_SIZE as u32 / 2;
+        anyhow::ensure!(
+            (1..=max_interval).contains(&self.replication_interval),
+            "replication_interval must be between 1 and {max_interval}"
+        );
+        Ok(())
+    }
+}
+
+/// The file to load the [ServerSettings] from, if any
+#[derive(Clone, Debug)]
+pub struct ServerSettingsPath;
+impl SyncAssetKey<std::path::PathBuf> for ServerSettingsPath {}
+
+fn create_sim_interval(settings: &ServerSettings) -> tokio::time::Interval {
+    let mut sim_interval = interval(Duration::from_secs_f32(1. / settings.tick_rate));
+    sim_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    sim_interval
+}
+
 pub struct WorldInstance {
     pub world: World,
     pub world_stream: WorldStream,
     pub systems: SystemGroup,
+    /// Records the diffs of the whole world, before they're restricted to what's relevant to each player
+    pub recorder: Option<SessionRecorder>,
 }
 
 pub fn create_player_entity_data(
@@ -62,6 +119,7 @@ pub fn create_player_entity_data(
     entities_tx: Sender<Vec<u8>>,
     events_tx: Sender<Vec<u8>>,
     stats_tx: Sender<FpsSample>,
+    datagrams_tx: Sender<Bytes>,
 ) -> EntityData {
     EntityData::new()
         .set(crate::player::player(), ())
@@ -69,6 +127,8 @@ pub fn create_player_entity_data(
         .set(player_entity_stream(), entities_tx)
         .set(player_stats_stream(), stats_tx)
         .set(player_event_stream(), events_tx)
+        .set(player_datagram_stream(), datagrams_tx)
+        .set(delta_encoder(), DeltaEncoder::default())
         .set_default(dont_store())
 }
 
@@ -80,16 +140,48 @@ impl WorldInstance {
     pub fn despawn_player(&mut self, user_id: &str) -> Option<EntityData> {
         self.world.despawn(get_player_by_user_id(&self.world, user_id)?)
     }
+    /// Starts recording the world to `recorder`, from a snapshot of its current state
+    pub fn start_recording(&mut self, mut recorder: SessionRecorder) -> anyhow::Result<()> {
+        // The snapshot covers the changes which weren't sent yet
+        self.broadcast_diffs();
+        recorder.record(&self.world_stream.filter().initial_diff(&self.world))?;
+        self.recorder = Some(recorder);
+        Ok(())
+    }
     pub fn broadcast_diffs(&mut self) {
         let diff = self.world_stream.next_diff(&self.world);
-        if diff.is_empty() {
+        if diff.is_empty() && !replication::has_pending(&self.world) {
             return;
         }
-        let msg = bincode::serialize(&diff).unwrap();
+        if let Some(recorder) = self.recorder.as_mut().filter(|_| !diff.is_empty()) {
+            if let Err(err) = recorder.record(&diff) {
+                log::warn!("Failed to record the diff: {err:?}");
+            }
+        }
 
         profiling::scope!("Send MsgEntities");
-        for (_, (entity_stream,)) in query((player_entity_stream(),)).iter(&self.world, None) {
-            let msg = msg.clone();
+        let players = query((player_entity_stream(),)).iter(&self.world, None).map(|(id, (stream,))| (id, stream.clone())).collect_vec();
+        let grid = relevance::RelevanceGrid::new(&self.world, self.world_stream.filter());
+        let time = self.time();
+        for (id, entity_stream) in players {
+            let relevant_diff = relevance::player_diff(&mut self.world, self.world_stream.filter(), &grid, id, &diff);
+            let relevant_diff = relevant_diff.as_ref().unwrap_or(&diff);
+            let owned_diff = ownership::player_diff(&self.world, id, relevant_diff);
+            let owned_diff = owned_diff.as_ref().unwrap_or(relevant_diff);
+            let scheduled_diff = replication::player_diff(&mut self.world, id, owned_diff, Instant::now());
+            let player_diff = scheduled_diff.as_ref().unwrap_or(owned_diff);
+            if player_diff.is_empty() {
+                continue;
+            }
+            let msg = match self.world.get_mut(id, delta_encoder()) {
+                Ok(encoder) => bincode::serialize(&encoder.encode(player_diff, time)).unwrap(),
+                Err(_) => {
+                    let mut encoder = DeltaEncoder::default();
+                    let msg = bincode::serialize(&encoder.encode(player_diff, time)).unwrap();
+                    self.world.add_component(id, delta_encoder(), encoder).unwrap();
+                    msg
+                }
+            };
             if let Err(_err) = entity_stream.send(msg) {
                 log::warn!("Failed to broadcast diff to player");
             }
@@ -98,6 +190,10 @@ impl WorldInstance {
     pub fn player_count(&self) -> usize {
         query((player(),)).iter(&self.world, None).count()
     }
+    /// The time of the last step
+    pub fn time(&self) -> Duration {
+        self.world.resource_opt(ambient_core::time()).copied().unwrap_or_default()
+    }
     pub fn step(&mut self, time: Duration) {
         self.world.set(self.world.resource_entity(), ambient_core::time(), time).unwrap();
         self.systems.run(&mut self.world, &FrameEvent);
@@ -106,20 +202,41 @@ impl WorldInstance {
 }
 
 pub const MAIN_INSTANCE_ID: &str = "main";
+/// How long the session of a player whose connection was lost is kept for them to resume, by default
+pub const DEFAULT_RECONNECT_GRACE: Duration = Duration::from_secs(30);
 
 pub struct Player {
     pub instance: String,
     pub abort_handle: Arc<OnceCell<tokio::task::JoinHandle<()>>>,
     pub connection_id: String,
+    /// Who the player was authenticated as
+    pub identity: Identity,
+    /// The connection of the player's client; `None` for a local player
+    pub connection: Option<Connection>,
+    /// When the connection of the player was lost, while they may still reconnect and resume their session
+    pub disconnected_at: Option<Instant>,
 }
 
 impl Player {
-    pub fn new(instance: String, abort_handle: Arc<OnceCell<tokio::task::JoinHandle<()>>>, connection_id: String) -> Self {
-        Self { instance, abort_handle, connection_id }
+    pub fn new(
+        instance: String,
+        abort_handle: Arc<OnceCell<tokio::task::JoinHandle<()>>>,
+        connection_id: String,
+        identity: Identity,
+        connection: Option<Connection>,
+    ) -> Self {
+        Self { instance, abort_handle, connection_id, identity, connection, disconnected_at: None }
     }
 
     pub fn new_local(instance: String) -> Self {
-        Self { instance, abort_handle: Arc::new(OnceCell::new()), connection_id: friendly_id() }
+        Self {
+            instance,
+            abort_handle: Arc::new(OnceCell::new()),
+            connection_id: friendly_id(),
+            identity: Identity::default(),
+            connection: None,
+            disconnected_at: None,
+        }
     }
 }
 
@@ -130,6 +247,11 @@ pub struct ServerState {
     pub create_server_systems: Arc<dyn Fn(&mut World) -> SystemGroup + Sync + Send>,
     pub create_on_forking_systems: Arc<dyn Fn() -> SystemGroup<ForkingEvent> + Sync + Send>,
     pub create_shutdown_systems: Arc<dyn Fn() -> SystemGroup<ShutdownEvent> + Sync + Send>,
+    /// The tick rate of the simulation, over the last sample
+    pub tick_sample: Option<FpsSample>,
+    /// How long the session of a player whose connection was lost is kept: their entity stays in the world, and they
+    /// resume it if they reconnect in time. Zero removes the players as soon as they disconnect.
+    pub reconnect_grace: Duration,
 }
 impl ServerState {
     pub fn new_local() -> Self {
@@ -141,6 +263,7 @@ impl ServerState {
                     world: World::new("main_server"),
                     world_stream: WorldStream::new(world_stream_filter),
                     systems: SystemGroup::new("", vec![]),
+                    recorder: None,
                 },
             )]
             .into(),
@@ -148,6 +271,8 @@ impl ServerState {
             create_server_systems: Arc::new(|_| SystemGroup::new("", vec![])),
             create_on_forking_systems: Arc::new(|| SystemGroup::new("", vec![])),
             create_shutdown_systems: Arc::new(|| SystemGroup::new("", vec![])),
+            tick_sample: None,
+            reconnect_grace: DEFAULT_RECONNECT_GRACE,
         }
     }
     pub fn new(
@@ -156,7 +281,15 @@ impl ServerState {
         create_on_forking_systems: Arc<dyn Fn() -> SystemGroup<ForkingEvent> + Sync + Send>,
         create_shutdown_systems: Arc<dyn Fn() -> SystemGroup<ShutdownEvent> + Sync + Send>,
     ) -> Self {
-        Self { instances, players: Default::default(), create_server_systems, create_on_forking_systems, create_shutdown_systems }
+        Self {
+            instances,
+            players: Default::default(),
+            create_server_systems,
+            create_on_forking_systems,
+            create_shutdown_systems,
+            tick_sample: None,
+            reconnect_grace: DEFAULT_RECONNECT_GRACE,
+        }
     }
 
     pub fn step(&mut self) {
@@ -185,6 +318,131 @@ impl ServerState {
     pub fn get_player_world(&self, user_id: &str) -> Option<&World> {
         self.get_player_world_instance(user_id).map(|i| &i.world)
     }
+    /// Creates the instance `id` as a copy of the current state of the instance `from`, without its players, which then runs
+    /// on its own with new server systems; the [ForkingEvent] systems are run on the copy first. Does nothing if the instance
+    /// `id` already exists.
+    pub fn fork_instance(&mut self, from: &str, id: &str, resources: EntityData, synced_res: EntityData) -> anyhow::Result<()> {
+        if self.instances.contains_key(id) {
+            return Ok(());
+        }
+        let new_instance = {
+            let instance = self.instances.get(from).with_context(|| format!("No instance {from} to fork"))?;
+            let mut world = instance.world.clone();
+
+            for (entity, _) in query(crate::player::user_id()).collect_cloned(&world, None) {
+                world.despawn(entity);
+            }
+            world
+                .add_components(world.resource_entity(), resources.append(ambient_core::async_ecs::async_ecs_resources()))
+                .context("Failed to add the resources of the forked instance")?;
+            world.add_resource(room_id(), id.to_string());
+            if let Some(synced_resources) = world.synced_resource_entity() {
+                world.add_components(synced_resources, synced_res).context("Failed to add the synced resources of the forked instance")?;
+            }
+
+            let mut on_forking = (self.create_on_forking_systems)();
+            on_forking.run(&mut world, &ForkingEvent);
+
+            world.reset_events();
+
+            WorldInstance {
+                systems: (self.create_server_systems)(&mut world),
+                world,
+                world_stream: instance.world_stream.clone(),
+                recorder: None,
+            }
+        };
+        self.instances.insert(id.to_string(), new_instance);
+        Ok(())
+    }
+    /// Moves the player `user_id` to the instance `new_instance_id`. Their client receives the difference between the two
+    /// worlds, and their old instance is removed if they were its last player (unless it's the main one).
+    pub fn move_player(&mut self, user_id: &str, new_instance_id: &str) {
+        let old_instance_id = self.players.get(user_id).unwrap().instance.clone();
+        if old_instance_id == new_instance_id {
+            return;
+        }
+
+        let instances = &mut self.instances;
+
+        // Borrow the new world mutably to broadcast its diffs.
+        instances.get_mut(new_instance_id).unwrap().broadcast_diffs();
+
+        // Borrow both worlds immutably to extract the old world's player count and the diff between the two, and
+        // to broadcast the latest diffs for the new instance.
+        let (old_player_count, diff) = {
+            let (old_instance, new_instance) = instances.get(&old_instance_id).zip(instances.get(new_instance_id)).unwrap();
+            (
+                old_instance.player_count(),
+                WorldDiff::from_a_to_b(old_instance.world_stream.filter().clone(), &old_instance.world, &new_instance.world),
+            )
+        };
+
+        // Borrow the old world mutably to remove the player and their streams.
+        let (entities_tx, events_tx, stats_tx, datagrams_tx, mut encoder) = {
+            let old_instance = instances.get_mut(&old_instance_id).unwrap();
+            ownership::release_owned_entities(&mut old_instance.world, user_id);
+            let mut ed = old_instance.despawn_player(user_id).unwrap();
+            (
+                ed.remove_self(player_entity_stream()).unwrap(),
+                ed.remove_self(player_event_stream()).unwrap(),
+                ed.remove_self(player_stats_stream()).unwrap(),
+                ed.remove_self(player_datagram_stream()).unwrap(),
+                ed.remove_self(delta_encoder()).unwrap_or_default(),
+            )
+        };
+
+        // The client keeps decoding the diffs against what it was sent, so the player keeps their encoder
+        let msg = bincode::serialize(&encoder.encode(&diff, instances[new_instance_id].time())).unwrap();
+
+        // Borrow the new world mutably to spawn the player in with their old streams.
+        let player = self.players.get_mut(user_id).unwrap();
+        instances.get_mut(new_instance_id).unwrap().spawn_player(
+            create_player_entity_data(user_id, entities_tx.clone(), events_tx, stats_tx, datagrams_tx)
+                .set(delta_encoder(), encoder)
+                .set(room(), new_instance_id.to_string())
+                .append(player.identity.player_data()),
+        );
+        player.instance = new_instance_id.to_string();
+
+        entities_tx.send(msg).ok();
+
+        // Remove old instance
+        if old_player_count == 1 && old_instance_id != MAIN_INSTANCE_ID {
+            self.remove_instance(&old_instance_id);
+        }
+    }
+    /// Moves the players whose [room] was set to another room, creating the rooms which don't exist yet from the main one
+    pub fn move_players_between_rooms(&mut self) {
+        let moves = self.instances.values().flat_map(|instance| rooms::pending_moves(&instance.world)).collect_vec();
+        for (user_id, target) in moves {
+            log::info!("Moving player {user_id} to room {target}");
+            if let Err(err) = self.fork_instance(MAIN_INSTANCE_ID, &target, EntityData::new(), EntityData::new()) {
+                log::error!("Failed to create room {target}: {err:?}");
+                continue;
+            }
+            self.move_player(&user_id, &target);
+        }
+    }
+    /// Disconnects the player `user_id`, telling their client why
+    pub fn kick_player(&self, user_id: &str, reason: &str) {
+        if let Some(connection) = self.players.get(user_id).and_then(|player| player.connection.as_ref()) {
+            log::info!("[{user_id}] Kicked: {reason}");
+            connection.close(PLAYER_KICKED, reason.as_bytes());
+        }
+    }
+    /// Removes the player `user_id` and their entity, along with their room if they were its last player (unless it's the
+    /// main one)
+    pub fn remove_player(&mut self, user_id: &str) -> Option<Player> {
+        let player = self.players.remove(user_id)?;
+        let instance = self.instances.get_mut(&player.instance).unwrap();
+        ownership::release_owned_entities(&mut instance.world, user_id);
+        instance.despawn_player(user_id);
+        if instance.player_count() == 0 && player.instance != MAIN_INSTANCE_ID {
+            self.remove_instance(&player.instance);
+        }
+        Some(player)
+    }
     pub fn remove_instance(&mut self, instance_id: &str) {
         log::info!("Removing server instance id={}", instance_id);
         let mut sys = (self.create_shutdown_systems)();
@@ -194,21 +452,43 @@ impl ServerState {
     }
 }
 
+/// Accepts the clients over QUIC, over WebSockets on the TCP port with the same number for the clients which can't use
+/// QUIC, and through its relay for the clients which can't reach it directly
 pub struct GameServer {
     _endpoint: Endpoint,
     incoming: Incoming,
+    websocket_listener: TcpListener,
     pub port: u16,
     /// Shuts down the server if there are no players
     pub use_inactivity_shutdown: bool,
+    /// Records the main instance of the server
+    pub recorder: Option<SessionRecorder>,
+    /// The relay the server is registered with, if any
+    pub relay: Option<RelayListener>,
+    /// How the server describes itself on the local network and on the [ServerListKey], if it's discoverable
+    pub announcement: Option<ServerAnnouncement>,
+    /// Gets the state of the server once it runs, e.g. for a status view
+    pub state_listener: Option<flume::Sender<SharedServerState>>,
 }
 impl GameServer {
     pub async fn new_with_port(port: u16) -> anyhow::Result<Self> {
         let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port);
 
         let (endpoint, incoming) = create_server(server_addr)?;
+        let websocket_listener = TcpListener::bind(server_addr).await?;
 
         log::info!("GameServer listening on port {}", port);
-        Ok(Self { _endpoint: endpoint, incoming, port, use_inactivity_shutdown: true })
+        Ok(Self {
+            _endpoint: endpoint,
+            incoming,
+            websocket_listener,
+            port,
+            use_inactivity_shutdown: true,
+            recorder: None,
+            relay: None,
+            announcement: None,
+            state_listener: None,
+        })
     }
     pub async fn new_with_port_in_range(port_range: Range<u16>) -> anyhow::Result<Self> {
         for port in port_range {
@@ -232,7 +512,8 @@ impl GameServer {
         create_shutdown_systems: Arc<dyn Fn() -> SystemGroup<ShutdownEvent> + Sync + Send>,
         is_sync_component: Arc<dyn Fn(ComponentDesc, WorldStreamCompEvent) -> bool + Sync + Send>,
     ) -> SharedServerState {
-        let Self { mut incoming, .. } = self;
+        let Self { mut incoming, websocket_listener, recorder, mut relay, announcement, state_listener, .. } = self;
+        world.add_resource(room_id(), MAIN_INSTANCE_ID.to_string());
         let assets = world.resource(asset_cache()).clone();
         let world_stream_filter = WorldStreamFilter::new(ArchetypeFilter::new().excl(no_sync()), is_sync_component);
         let state = Arc::new(Mutex::new(ServerState::new(
@@ -242,6 +523,7 @@ impl GameServer {
                     systems: create_server_systems(&mut world),
                     world,
                     world_stream: WorldStream::new(world_stream_filter.clone()),
+                    recorder: None,
                 },
             )]
             .into_iter()
@@ -250,10 +532,26 @@ impl GameServer {
             create_on_forking_systems,
             create_shutdown_systems,
         )));
+        if let Some(recorder) = recorder {
+            if let Err(err) = state.lock().instances.get_mut(MAIN_INSTANCE_ID).unwrap().start_recording(recorder) {
+                log::error!("Failed to start recording the server: {err:?}");
+            }
+        }
+
+        if let Some(state_listener) = state_listener {
+            state_listener.send(state.clone()).ok();
+        }
+
+        let server_list = ServerListKey.try_get(&assets);
+        let discovery = announcement
+            .clone()
+            .map(|announcement| tokio::spawn(discovery::announce(announcement, self.port, state.clone(), server_list.clone())));
 
         let mut fps_counter = FpsCounter::new();
-        let mut sim_interval = interval(Duration::from_secs_f32(1. / 60.));
-        sim_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut watched_settings = ServerSettingsPath.try_get(&assets).map(WatchedConfig::<ServerSettings>::new);
+        let mut settings = watched_settings.as_ref().map(|settings| settings.current().clone()).unwrap_or_default();
+        let mut sim_interval = create_sim_interval(&settings);
+        let mut tick = 0u64;
 
         let mut inactivity_interval = interval(Duration::from_secs_f32(5.));
         let mut last_active = ambient_sys::time::Instant::now();
@@ -274,7 +572,33 @@ impl GameServer {
 
 
                     tracing::info!("Accepted connection");
-                    run_connection(conn, state.clone(), world_stream_filter.clone(), assets.clone());
+                    run_connection(conn.into(), state.clone(), world_stream_filter.clone(), assets.clone());
+                }
+                Ok((stream, addr)) = websocket_listener.accept() => {
+                    tracing::info!("Received WebSocket connection from {addr}");
+                    let (state, world_stream_filter, assets) = (state.clone(), world_stream_filter.clone(), assets.clone());
+                    tokio::spawn(async move {
+                        match websocket::accept(stream).await {
+                            Ok(conn) => {
+                                tracing::info!("Accepted WebSocket connection");
+                                run_connection(conn, state, world_stream_filter, assets);
+                            }
+                            Err(e) => tracing::error!("Failed to accept incoming WebSocket connection. {e}"),
+                        }
+                    });
+                }
+                Some(client) = next_relayed_client(&mut relay) => {
+                    tracing::info!("Received relayed connection from {}", client.client_addr);
+                    let (state, world_stream_filter, assets) = (state.clone(), world_stream_filter.clone(), assets.clone());
+                    tokio::spawn(async move {
+                        match client.connect().await {
+                            Ok(conn) => {
+                                tracing::info!("Accepted relayed connection");
+                                run_connection(conn, state, world_stream_filter, assets);
+                            }
+                            Err(e) => tracing::error!("Failed to accept incoming relayed connection. {e}"),
+                        }
+                    });
                 }
                 _ = sim_interval.tick() => {
                     fps_counter.frame_start();
@@ -283,15 +607,26 @@ impl GameServer {
                         profiling::finish_frame!();
                         profiling::scope!("sim_tick");
                         state.step();
-                        state.broadcast_diffs();
+                        state.move_players_between_rooms();
+                        tick += 1;
+                        if tick % settings.replication_interval as u64 == 0 {
+                            state.broadcast_diffs();
+                        }
                         if let Some(sample) = fps_counter.frame_end() {
                             for instance in state.instances.values() {
                                 for (_, (stream,)) in query((player_stats_stream(),)).iter(&instance.world, None) {
                                     stream.send(sample.clone()).ok();
                                 }
                             }
+                            state.tick_sample = Some(sample);
                         }
                     });
+                    if let Some(new_settings) = watched_settings.as_mut().and_then(|settings| settings.poll()) {
+                        if new_settings.tick_rate != settings.tick_rate {
+                            sim_interval = create_sim_interval(new_settings);
+                        }
+                        settings = new_settings.clone();
+                    }
                 }
                 _ = inactivity_interval.tick(), if self.use_inactivity_shutdown => {
                     if state.lock().player_count() == 0 {
@@ -310,6 +645,14 @@ impl GameServer {
             }
         }
         log::info!("[{}] GameServer shutting down", self.port);
+        if let Some(discovery) = discovery {
+            discovery.abort();
+        }
+        if let (Some(announcement), Some(server_list)) = (&announcement, &server_list) {
+            if let Err(err) = server_list.withdraw(&announcement.address).await {
+                log::warn!("Failed to withdraw the server from the server list: {err:#}");
+            }
+        }
         {
             let mut state = state.lock();
             let create_shutdown_systems = state.create_shutdown_systems.clone();
@@ -323,10 +666,28 @@ impl GameServer {
     }
 }
 
+/// Waits for the next client joining through the relay; the relay is dropped if the server loses its connection to it
+async fn next_relayed_client(relay: &mut Option<RelayListener>) -> Option<RelayedClient> {
+    let listener = match relay {
+        Some(listener) => listener,
+        None => return future::pending().await,
+    };
+    match listener.accept().await {
+        Ok(client) => Some(client),
+        Err(err) => {
+            log::error!("Lost the connection to the relay: {err}");
+            *relay = None;
+            None
+        }
+    }
+}
+
 /// Setup the protocol and enter the update loop for a new connected client
 #[tracing::instrument(skip_all)]
 fn run_connection(connection: NewConnection, state: SharedServerState, world_stream_filter: WorldStreamFilter, assets: AssetCache) {
     let connection_id = friendly_id();
+    let authenticator = ServerAuthenticatorKey.get(&assets);
+    let authorizer = ServerAuthorizerKey.try_get(&assets);
     let handle = Arc::new(OnceCell::new());
     handle
         .set({
@@ -335,72 +696,112 @@ fn run_connection(connection: NewConnection, state: SharedServerState, world_str
                 let (diffs_tx, diffs_rx) = flume::unbounded();
                 let (stats_tx, stats_rx) = flume::unbounded();
                 let (events_tx, events_rx) = flume::unbounded();
+                let (datagrams_tx, datagrams_rx) = flume::unbounded();
 
-                let on_init = |client: ClientInfo| {
+                let on_init = |identity: Identity, connection: Connection| {
                     log::info!("Locking world");
                     let mut state = state.lock();
                     // If there's an old player
-                    let reconnecting = if let Some(player) = state.players.get_mut(&client.user_id) {
+                    let (reconnecting, instance_id) = if let Some(player) = state.players.get_mut(&identity.user_id) {
                         if let Some(handle) = player.abort_handle.get() {
                             handle.abort();
                         }
                         player.abort_handle = handle.clone();
                         player.connection_id = connection_id.clone();
+                        player.identity = identity.clone();
+                        player.connection = Some(connection);
+                        player.disconnected_at = None;
                         log::info!("Player reconnecting");
-                        true
+                        (true, player.instance.clone())
                     } else {
                         state.players.insert(
-                            client.user_id.clone(),
+                            identity.user_id.clone(),
                             Player {
                                 instance: MAIN_INSTANCE_ID.to_string(),
                                 abort_handle: handle.clone(),
                                 connection_id: connection_id.clone(),
+                                identity: identity.clone(),
+                                connection: Some(connection),
+                                disconnected_at: None,
                             },
                         );
-                        false
+                        (false, MAIN_INSTANCE_ID.to_string())
                     };
 
-                    let instance = state.instances.get_mut(MAIN_INSTANCE_ID).unwrap();
+                    // A player reconnects to the room they were in
+                    let instance = state.instances.get_mut(&instance_id).unwrap();
 
                     // Bring world stream up to the current time
                     log::info!("Broadcasting diffs");
                     instance.broadcast_diffs();
                     log::info!("Creating init diff");
 
+                    // The initial diff only spawns entities, so it doesn't open any delta slot, and the player starts with an
+                    // empty encoder (even when reconnecting, as the client resyncs its world with it)
                     let diff = world_stream_filter.initial_diff(&instance.world);
-                    let diff = bincode::serialize(&diff).unwrap();
+                    let diff = bincode::serialize(&DeltaEncoder::default().encode(&diff, instance.time())).unwrap();
 
                     log_result!(diffs_tx.send(diff));
                     log::info!("Init diff sent");
 
                     if !reconnecting {
-                        instance.spawn_player(create_player_entity_data(
-                            &client.user_id,
-                            diffs_tx.clone(),
-                            events_tx.clone(),
-                            stats_tx.clone(),
-                        ));
+                        instance.spawn_player(
+                            create_player_entity_data(
+                                &identity.user_id,
+                                diffs_tx.clone(),
+                                events_tx.clone(),
+                                stats_tx.clone(),
+                                datagrams_tx.clone(),
+                            )
+                            .set(room(), instance_id)
+                            .append(identity.player_data()),
+                        );
                         log::info!("Player spawned");
                     } else {
-                        let entity = get_player_by_user_id(&instance.world, &client.user_id).unwrap();
+                        let entity = get_player_by_user_id(&instance.world, &identity.user_id).unwrap();
+                        instance.world.add_components(entity, identity.player_data()).unwrap();
                         instance.world.set(entity, player_entity_stream(), diffs_tx.clone()).unwrap();
                         instance.world.set(entity, player_stats_stream(), stats_tx.clone()).unwrap();
                         instance.world.set(entity, player_event_stream(), events_tx.clone()).unwrap();
+                        instance.world.set(entity, player_datagram_stream(), datagrams_tx.clone()).unwrap();
+                        instance.world.remove_component(entity, delta_encoder()).unwrap();
+                        // The initial diff has the latest values of the changes which were waiting
+                        instance.world.remove_component(entity, replication_schedule()).ok();
                         log::info!("Player reconnected");
                     }
                 };
 
-                let on_disconnect = |user_id: &Option<String>| {
+                let on_disconnect = |user_id: &Option<String>, connection_lost: bool| {
                     if let Some(user_id) = user_id {
                         log::info!("[{}] Disconnecting", user_id);
+                        let shared_state = state.clone();
                         let mut state = state.lock();
                         if state.players.get(user_id).map(|p| p.connection_id != connection_id).unwrap_or(false) {
                             log::info!("[{}] Disconnected (reconnection)", user_id);
                             return;
                         }
-                        if let Some(player) = state.players.remove(user_id) {
-                            state.instances.get_mut(&player.instance).unwrap().despawn_player(user_id);
+                        let grace = state.reconnect_grace;
+                        if connection_lost && !grace.is_zero() {
+                            if let Some(player) = state.players.get_mut(user_id) {
+                                // The player keeps their entity until the grace period is over, unless they reconnect before
+                                player.disconnected_at = Some(Instant::now());
+                                let (user_id, connection_id, authenticator) =
+                                    (user_id.clone(), connection_id.clone(), authenticator.clone());
+                                tokio::spawn(async move {
+                                    tokio::time::sleep(grace).await;
+                                    let mut state = shared_state.lock();
+                                    if state.players.get(&user_id).map(|p| p.connection_id == connection_id).unwrap_or(false) {
+                                        state.remove_player(&user_id);
+                                        authenticator.end_session(&user_id);
+                                        log::info!("[{}] Disconnected (didn't reconnect in time)", user_id);
+                                    }
+                                });
+                                log::info!("[{}] Connection lost, keeping the session for {:?}", user_id, grace);
+                                return;
+                            }
                         }
+                        state.remove_player(user_id);
+                        authenticator.end_session(user_id);
 
                         log::info!("[{}] Disconnected", user_id);
                     }
@@ -452,15 +853,39 @@ fn run_connection(connection: NewConnection, state: SharedServerState, world_str
                     }
                 };
 
+                let on_network_stats = |user_id: &String, tracker: &mut StatsTracker, connection: &Connection, probes: &ProbeReceiver| {
+                    let mut state = state.lock();
+                    let world = match state.get_player_world_mut(user_id) {
+                        Some(world) => world,
+                        None => return,
+                    };
+                    let player_id = match get_player_by_user_id(world, user_id) {
+                        Some(player_id) => player_id,
+                        None => return,
+                    };
+                    let replication = world.get_ref(player_id, delta_encoder()).map(|encoder| encoder.sent().clone()).unwrap_or_default();
+                    let stats = tracker.sample(connection.rtt(), connection.stats(), probes, &replication);
+                    if world.has_component(player_id, network_stats()) {
+                        world.set(player_id, network_stats(), stats).unwrap();
+                    } else {
+                        world.add_component(player_id, network_stats(), stats).unwrap();
+                    }
+                };
+
                 let client = ClientInstance {
                     diffs_rx,
                     stats_rx,
                     events_rx,
+                    datagrams_rx,
                     on_init: &on_init,
                     on_rpc: &on_rpc,
                     on_datagram: &on_datagram,
+                    on_network_stats: &on_network_stats,
                     on_disconnect: &on_disconnect,
+                    authenticator: authenticator.clone(),
+                    authorizer: authorizer.clone(),
                     user_id: None,
+                    connection_lost: false,
                 };
 
                 match client.run(connection).await {
@@ -468,12 +893,18 @@ fn run_connection(connection: NewConnection, state: SharedServerState, world_str
                     Err(err) if err.is_closed() => {
                         log::info!("Connection closed by client");
                     }
+                    // The player was kicked
+                    Err(NetworkError::ConnectionError(ConnectionError::LocallyClosed)) => {}
                     Err(err) if err.is_end_of_stream() => {
                         log::warn!("Stream was closed prematurely");
                     }
                     Err(NetworkError::IOError(err)) if err.kind() == std::io::ErrorKind::NotConnected => {
                         log::warn!("Not connected: {err:?}");
                     }
+                    // Already logged by the protocol
+                    Err(
+                        NetworkError::AuthenticationFailed(_) | NetworkError::ConnectionRefused(_) | NetworkError::IncompatibleVersion(_),
+                    ) => {}
                     Err(err) => {
                         log::error!("Server error: {err:?}");
                     }
@@ -488,40 +919,57 @@ struct ClientInstance<'a> {
     diffs_rx: flume::Receiver<Vec<u8>>,
     stats_rx: flume::Receiver<FpsSample>,
     events_rx: flume::Receiver<Vec<u8>>,
+    datagrams_rx: flume::Receiver<Bytes>,
 
-    on_init: &'a (dyn Fn(ClientInfo) + Send + Sync),
+    on_init: &'a (dyn Fn(Identity, Connection) + Send + Sync),
     on_datagram: &'a (dyn Fn(&String, Bytes) + Send + Sync),
+    on_network_stats: &'a (dyn Fn(&String, &mut StatsTracker, &Connection, &ProbeReceiver) + Send + Sync),
     on_rpc: &'a (dyn Fn(&String, u32, SendStream, RecvStream) + Send + Sync),
-    on_disconnect: &'a (dyn Fn(&Option<String>) + Send + Sync),
+    on_disconnect: &'a (dyn Fn(&Option<String>, bool) + Send + Sync),
+    authenticator: Arc<dyn Authenticator>,
+    authorizer: Option<Arc<dyn Authorizer>>,
     user_id: Option<String>,
+    /// Whether the connection was lost, rather than closed, in which case the player may resume their session
+    connection_lost: bool,
 }
 
 impl<'a> Drop for ClientInstance<'a> {
     fn drop(&mut self) {
         log::info!("Closed server side connection for");
         tokio::task::block_in_place(|| {
-            (self.on_disconnect)(&self.user_id);
+            (self.on_disconnect)(&self.user_id, self.connection_lost);
         })
     }
 }
 
 impl<'a> ClientInstance<'a> {
-    #[tracing::instrument(skip_all)]
     pub async fn run(mut self, conn: NewConnection) -> Result<(), NetworkError> {
+        let res = self.serve(conn).await;
+        self.connection_lost = matches!(&res, Err(err) if err.is_connection_lost());
+        res
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn serve(&mut self, conn: NewConnection) -> Result<(), NetworkError> {
         tracing::info!("Connecting to client");
-        let mut proto = ServerProtocol::new(conn).await?;
+        let mut proto = ServerProtocol::new(conn, &*self.authenticator, self.authorizer.as_deref()).await?;
 
         log::debug!("Client loop starting");
         let mut entities_rx = self.diffs_rx.stream();
         let mut stats_rx = self.stats_rx.stream();
         let mut events_rx = self.events_rx.stream();
+        let mut datagrams_rx = self.datagrams_rx.stream();
 
         tokio::task::block_in_place(|| {
-            (self.on_init)(proto.client_info().clone());
+            (self.on_init)(proto.identity().clone(), proto.connection());
         });
         let user_id = proto.client_info().user_id.clone();
         self.user_id = Some(user_id.clone());
 
+        let mut stats_timer = interval(STATS_INTERVAL);
+        let mut stats_tracker = StatsTracker::default();
+        let mut probes = ProbeReceiver::default();
+
         loop {
             tokio::select! {
                 Some(msg) = entities_rx.next() => {
@@ -537,11 +985,25 @@ impl<'a> ClientInstance<'a> {
                     let span =tracing::debug_span!("server_event");
                     let mut stream = proto.connection().open_uni().instrument(span).await?;
 
-                    stream.write(&msg).await?;
+                    stream.write_all(&msg).await?;
+                }
+                Some(datagram) = datagrams_rx.next() => {
+                    let _span = tracing::debug_span!("server_datagram").entered();
+                    proto.connection().send_datagram(datagram).ok();
                 }
                 Some(Ok(datagram)) = proto.conn.datagrams.next() => {
                     let _span =tracing::debug_span!("datagram").entered();
-                    tokio::task::block_in_place(|| (self.on_datagram)(&user_id, datagram))
+                    match Probe::from_datagram(&datagram) {
+                        Some(probe) => {
+                            probes.receive(probe);
+                            // Echoed back for the client to measure its side
+                            proto.connection().send_datagram(datagram).ok();
+                        }
+                        None => tokio::task::block_in_place(|| (self.on_datagram)(&user_id, datagram)),
+                    }
+                }
+                _ = stats_timer.tick() => {
+                    tokio::task::block_in_place(|| (self.on_network_stats)(&user_id, &mut stats_tracker, &proto.connection(), &probes));
                 }
                 Some(Ok((tx, mut rx))) = proto.conn.bi_streams.next() => {
                     let span = tracing::debug_span!("rpc");
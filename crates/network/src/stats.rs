@@ -0,0 +1,352 @@
+//! Statistics of the connection of each client, to tell why a game lags or uses more bandwidth than it should.
+//!
+//! Both ends of a connection sample its [NetworkStats] every [STATS_INTERVAL]. The round trip time and the bytes which
+//! went through come from the transport. The packet loss and the jitter are measured with probes: every
+//! [PROBE_INTERVAL], the client sends a small datagram with a sequence number and the time it was sent, which the server
+//! echoes back. The probes which never arrive are lost, and the variation of their transit time is the jitter
+//! (computed like the interarrival jitter of RTP). The server sees the probes on their way up, and the client sees them
+//! after a round trip. Over a WebSocket the datagrams are delivered reliably, so nothing is ever lost.
+//!
+//! The replication cost of each component is how many bytes the diffs spend on its values: the server counts the diffs it
+//! sends to each player, and the client the diffs it receives.
+
+use std::{collections::HashMap, fmt::Display, time::Duration};
+
+use ambient_ecs::{components, ComponentDesc, Debuggable, Description, Name, WorldChange};
+use ambient_std::to_byte_unit;
+use ambient_sys::time::Instant;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::transport::ConnectionStats;
+
+components!("network", {
+    @[
+        Debuggable,
+        Name["Network stats"],
+        Description["The statistics of the connection of this player, updated every second.\nOn the server, it's attached to each player entity; on the client, it's a resource with the client's view of its own connection."]
+    ]
+    network_stats: NetworkStats,
+});
+
+/// How often the statistics of a connection are sampled
+pub const STATS_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the client sends a probe
+pub const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+/// The handler id of the probe datagrams, which are handled by the connection itself rather than the
+/// [crate::datagram_handlers]
+pub const PROBE_DATAGRAM_ID: u32 = u32::MAX;
+
+/// The statistics of a connection over the last [STATS_INTERVAL]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NetworkStats {
+    pub rtt: Duration,
+    /// How much the transit time of the probes varies
+    pub jitter: Duration,
+    /// The share of the probes which were lost, between 0 and 1
+    pub packet_loss: f32,
+    pub bytes_sent_per_second: u64,
+    pub bytes_received_per_second: u64,
+    /// How many bytes per second the diffs spent on each component, from the most expensive one
+    pub replication_costs: Vec<ReplicationCost>,
+}
+impl Display for NetworkStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}ms rtt, {}ms jitter, {:.1}% loss, {}/s out, {}/s in",
+            self.rtt.as_millis(),
+            self.jitter.as_millis(),
+            self.packet_loss * 100.,
+            to_byte_unit(self.bytes_sent_per_second),
+            to_byte_unit(self.bytes_received_per_second)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplicationCost {
+    /// The path of the component
+    pub component: String,
+    pub bytes_per_second: u64,
+}
+
+/// The number of bytes the diffs spent on each component since the connection was opened
+#[derive(Debug, Clone, Default)]
+pub struct ReplicationBytes(HashMap<ComponentDesc, u64>);
+impl ReplicationBytes {
+    pub fn get(&self, component: ComponentDesc) -> u64 {
+        self.0.get(&component).copied().unwrap_or_default()
+    }
+    pub(crate) fn add(&mut self, component: ComponentDesc, bytes: u64) {
+        *self.0.entry(component).or_default() += bytes;
+    }
+    /// Counts the values and the component ids of `change` against their components; the ids of the entities aren't
+    /// counted
+    pub(crate) fn add_change(&mut self, change: &WorldChange) {
+        match change {
+            WorldChange::Spawn(_, data) | WorldChange::AddComponents(_, data) => {
+                for entry in data.iter() {
+                    self.add(entry.desc(), bincode::serialized_size(entry).unwrap_or_default());
+                }
+            }
+            WorldChange::Set(_, entry) => self.add(entry.desc(), bincode::serialized_size(entry).unwrap_or_default()),
+            WorldChange::RemoveComponents(_, components) => {
+                for &component in components {
+                    self.add(component, bincode::serialized_size(&component).unwrap_or_default());
+                }
+            }
+            WorldChange::Despawn(_) => {}
+        }
+    }
+}
+
+/// A probe datagram, as sent by the client and echoed back by the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probe {
+    pub sequence: u64,
+    /// When the probe was sent, in microseconds since the client started sending them
+    pub sent_micros: u64,
+}
+impl Probe {
+    pub fn to_datagram(self) -> Bytes {
+        let mut data = Vec::with_capacity(20);
+        data.extend_from_slice(&PROBE_DATAGRAM_ID.to_be_bytes());
+        data.extend_from_slice(&self.sequence.to_be_bytes());
+        data.extend_from_slice(&self.sent_micros.to_be_bytes());
+        data.into()
+    }
+    /// The probe in `datagram`, if it is one
+    pub fn from_datagram(datagram: &[u8]) -> Option<Self> {
+        if datagram.len() != 20 || datagram[0..4] != PROBE_DATAGRAM_ID.to_be_bytes() {
+            return None;
+        }
+        Some(Self {
+            sequence: u64::from_be_bytes(datagram[4..12].try_into().unwrap()),
+            sent_micros: u64::from_be_bytes(datagram[12..20].try_into().unwrap()),
+        })
+    }
+}
+
+/// Numbers the probes sent by the client
+#[derive(Debug, Clone)]
+pub struct ProbeSender {
+    start: Instant,
+    next_sequence: u64,
+}
+impl Default for ProbeSender {
+    fn default() -> Self {
+        Self { start: Instant::now(), next_sequence: 0 }
+    }
+}
+impl ProbeSender {
+    pub fn next_probe(&mut self) -> Probe {
+        let probe = Probe { sequence: self.next_sequence, sent_micros: self.start.elapsed().as_micros() as u64 };
+        self.next_sequence += 1;
+        probe
+    }
+}
+
+/// Counts the probes received and lost, and their jitter
+#[derive(Debug, Clone)]
+pub struct ProbeReceiver {
+    start: Instant,
+    next_sequence: u64,
+    received: u64,
+    lost: u64,
+    last_transit: Option<i64>,
+    /// In microseconds
+    jitter: f64,
+}
+impl Default for ProbeReceiver {
+    fn default() -> Self {
+        Self { start: Instant::now(), next_sequence: 0, received: 0, lost: 0, last_transit: None, jitter: 0. }
+    }
+}
+impl ProbeReceiver {
+    pub fn receive(&mut self, probe: Probe) {
+        // The probes skipped over are lost, until they arrive late
+        if probe.sequence < self.next_sequence {
+            self.lost = self.lost.saturating_sub(1);
+        } else {
+            self.lost += probe.sequence - self.next_sequence;
+            self.next_sequence = probe.sequence + 1;
+        }
+        self.received += 1;
+
+        // The clocks of the two ends are offset, but that cancels out in the difference of the transit times
+        let transit = self.start.elapsed().as_micros() as i64 - probe.sent_micros as i64;
+        if let Some(last_transit) = self.last_transit {
+            self.jitter += ((transit - last_transit).abs() as f64 - self.jitter) / 16.;
+        }
+        self.last_transit = Some(transit);
+    }
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+    pub fn jitter(&self) -> Duration {
+        Duration::from_micros(self.jitter as u64)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Totals {
+    time: Instant,
+    connection: ConnectionStats,
+    received_probes: u64,
+    lost_probes: u64,
+    replication: ReplicationBytes,
+}
+
+/// Turns the totals of a connection into its [NetworkStats] since the last sample
+#[derive(Debug, Clone, Default)]
+pub struct StatsTracker {
+    last: Option<Totals>,
+}
+impl StatsTracker {
+    pub fn sample(
+        &mut self,
+        rtt: Duration,
+        connection: ConnectionStats,
+        probes: &ProbeReceiver,
+        replication: &ReplicationBytes,
+    ) -> NetworkStats {
+        let totals = Totals {
+            time: Instant::now(),
+            connection,
+            received_probes: probes.received(),
+            lost_probes: probes.lost(),
+            replication: replication.clone(),
+        };
+        let mut stats = NetworkStats { rtt, jitter: probes.jitter(), ..Default::default() };
+        if let Some(last) = self.last.replace(totals.clone()) {
+            stats.set_rates(&last, &totals);
+        }
+        stats
+    }
+}
+
+impl NetworkStats {
+    /// Sets the rates between two totals of the same connection. The totals of the replication go back to zero when
+    /// the player changes instance, in which case the components count from zero again.
+    fn set_rates(&mut self, last: &Totals, totals: &Totals) {
+        let seconds = totals.time.duration_since(last.time).as_secs_f64().max(0.001);
+        let per_second = |bytes: u64| (bytes as f64 / seconds).round() as u64;
+
+        self.bytes_sent_per_second = per_second(totals.connection.bytes_sent.saturating_sub(last.connection.bytes_sent));
+        self.bytes_received_per_second = per_second(totals.connection.bytes_received.saturating_sub(last.connection.bytes_received));
+
+        let received = totals.received_probes.saturating_sub(last.received_probes);
+        let lost = totals.lost_probes.saturating_sub(last.lost_probes);
+        if received + lost > 0 {
+            self.packet_loss = lost as f32 / (received + lost) as f32;
+        }
+
+        let mut costs = totals
+            .replication
+            .0
+            .iter()
+            .filter_map(|(&component, &bytes)| {
+                let last_bytes = last.replication.get(component);
+                let bytes = if bytes >= last_bytes { bytes - last_bytes } else { bytes };
+                (bytes > 0).then(|| ReplicationCost { component: component.path(), bytes_per_second: per_second(bytes) })
+            })
+            .collect::<Vec<_>>();
+        costs.sort_by(|a, b| b.bytes_per_second.cmp(&a.bytes_per_second).then_with(|| a.component.cmp(&b.component)));
+        self.replication_costs = costs;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    components!("test", {
+        position: f32,
+        health: f32,
+    });
+
+    #[test]
+    fn probe_datagrams() {
+        let probe = Probe { sequence: 7, sent_micros: 123_456 };
+        assert_eq!(Probe::from_datagram(&probe.to_datagram()), Some(probe));
+        // Other datagrams aren't probes
+        let mut other = probe.to_datagram().to_vec();
+        other[0..4].copy_from_slice(&5u32.to_be_bytes());
+        assert_eq!(Probe::from_datagram(&other), None);
+        assert_eq!(Probe::from_datagram(&probe.to_datagram()[..19]), None);
+    }
+
+    #[test]
+    fn probes_are_lost_until_they_arrive_late() {
+        let mut receiver = ProbeReceiver::default();
+        for sequence in [0, 1, 4] {
+            receiver.receive(Probe { sequence, sent_micros: 0 });
+        }
+        assert_eq!((receiver.received(), receiver.lost()), (3, 2));
+        receiver.receive(Probe { sequence: 2, sent_micros: 0 });
+        assert_eq!((receiver.received(), receiver.lost()), (4, 1));
+        receiver.receive(Probe { sequence: 5, sent_micros: 0 });
+        assert_eq!((receiver.received(), receiver.lost()), (5, 1));
+    }
+
+    #[test]
+    fn jitter_follows_the_variation_of_the_transit_times() {
+        let mut receiver = ProbeReceiver::default();
+        receiver.receive(Probe { sequence: 0, sent_micros: 0 });
+        assert_eq!(receiver.jitter(), Duration::ZERO);
+        // The transit times alternate by 10ms, which the jitter converges to by a sixteenth each time
+        receiver.receive(Probe { sequence: 1, sent_micros: 10_000 });
+        receiver.receive(Probe { sequence: 2, sent_micros: 0 });
+        let expected = 625. + (10_000. - 625.) / 16.;
+        assert!((receiver.jitter().as_micros() as f64 - expected).abs() < 100., "{:?}", receiver.jitter());
+    }
+
+    #[test]
+    fn rates_between_two_samples() {
+        init_components();
+        let start = Instant::now();
+        let mut replication = ReplicationBytes::default();
+        replication.add(position().desc(), 100);
+        replication.add(health().desc(), 500);
+        let last = Totals {
+            time: start,
+            connection: ConnectionStats { bytes_sent: 1000, bytes_received: 200 },
+            received_probes: 10,
+            lost_probes: 0,
+            replication: replication.clone(),
+        };
+        replication.add(position().desc(), 400);
+        replication.add(health().desc(), 100);
+        let totals = Totals {
+            time: start + Duration::from_secs(2),
+            connection: ConnectionStats { bytes_sent: 5000, bytes_received: 400 },
+            received_probes: 19,
+            lost_probes: 1,
+            replication,
+        };
+
+        let mut stats = NetworkStats::default();
+        stats.set_rates(&last, &totals);
+        assert_eq!((stats.bytes_sent_per_second, stats.bytes_received_per_second), (2000, 100));
+        assert_eq!(stats.packet_loss, 0.1);
+        assert_eq!(
+            stats.replication_costs,
+            vec![
+                ReplicationCost { component: position().path(), bytes_per_second: 200 },
+                ReplicationCost { component: health().path(), bytes_per_second: 50 },
+            ]
+        );
+
+        // The replication totals start over when the player changes instance
+        let mut moved = totals.clone();
+        moved.time = totals.time + Duration::from_secs(1);
+        moved.replication = ReplicationBytes::default();
+        moved.replication.add(health().desc(), 30);
+        stats.set_rates(&totals, &moved);
+        assert_eq!(stats.replication_costs, vec![ReplicationCost { component: health().path(), bytes_per_second: 30 }]);
+    }
+}
@@ -0,0 +1,195 @@
+//! The status of a running server, for its operators: its tick rate, its rooms, its players and their connections, and
+//! the admin commands they run on it. The status view of the headless servers shows it.
+
+use std::str::FromStr;
+
+use ambient_ecs::query;
+use ambient_std::fps_counter::FpsSample;
+use anyhow::Context;
+
+use crate::{
+    get_player_by_user_id,
+    player::player,
+    rooms::room,
+    server::ServerState,
+    stats::{network_stats, NetworkStats},
+    transport::Transport,
+};
+
+/// The commands an operator can run, as they're typed
+pub const ADMIN_COMMANDS_HELP: &str = "kick <user_id> [reason], move <user_id> <room>";
+
+#[derive(Debug, Clone)]
+pub struct ServerStatus {
+    /// The tick rate of the simulation, over the last sample
+    pub tick: Option<FpsSample>,
+    pub rooms: Vec<RoomStatus>,
+    pub players: Vec<PlayerStatus>,
+    /// The resident memory of the process in bytes, where it's known
+    pub memory: Option<u64>,
+}
+impl ServerStatus {
+    pub fn bytes_sent_per_second(&self) -> u64 {
+        self.players.iter().filter_map(|player| player.stats.as_ref()).map(|stats| stats.bytes_sent_per_second).sum()
+    }
+    pub fn bytes_received_per_second(&self) -> u64 {
+        self.players.iter().filter_map(|player| player.stats.as_ref()).map(|stats| stats.bytes_received_per_second).sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RoomStatus {
+    pub id: String,
+    pub entities: usize,
+    pub players: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayerStatus {
+    pub user_id: String,
+    pub display_name: Option<String>,
+    pub room: String,
+    /// How the player is connected; `None` for a local player
+    pub transport: Option<Transport>,
+    /// Whether the player lost their connection, and may still resume their session
+    pub connection_lost: bool,
+    /// The statistics of the connection of the player, once they're sampled
+    pub stats: Option<NetworkStats>,
+}
+
+/// The status of the server `state`
+pub fn server_status(state: &ServerState) -> ServerStatus {
+    let mut rooms = state
+        .instances
+        .iter()
+        .map(|(id, instance)| RoomStatus {
+            id: id.clone(),
+            entities: instance.world.len(),
+            players: query(()).incl(player()).iter(&instance.world, None).count(),
+        })
+        .collect::<Vec<_>>();
+    rooms.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut players = state
+        .players
+        .iter()
+        .map(|(user_id, player)| {
+            let stats = state
+                .get_player_world(user_id)
+                .and_then(|world| world.get_cloned(get_player_by_user_id(world, user_id)?, network_stats()).ok());
+            PlayerStatus {
+                user_id: user_id.clone(),
+                display_name: player.identity.display_name.clone(),
+                room: player.instance.clone(),
+                transport: player.connection.as_ref().map(|connection| connection.transport()),
+                connection_lost: player.disconnected_at.is_some(),
+                stats,
+            }
+        })
+        .collect::<Vec<_>>();
+    players.sort_by(|a, b| a.user_id.cmp(&b.user_id));
+
+    ServerStatus { tick: state.tick_sample.clone(), rooms, players, memory: process_memory() }
+}
+
+/// The resident memory of this process, read from `/proc` on Linux
+fn process_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.trim_start_matches("VmRSS:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// A command run by an operator on the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminCommand {
+    /// Disconnects the player, telling them why
+    Kick { user_id: String, reason: String },
+    /// Moves the player to another room, creating it if it doesn't exist
+    Move { user_id: String, room: String },
+}
+impl FromStr for AdminCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let command = words.next().context("No command")?;
+        match command {
+            "kick" => {
+                let user_id = words.next().context("Usage: kick <user_id> [reason]")?.to_string();
+                let reason = words.collect::<Vec<_>>().join(" ");
+                Ok(Self::Kick { user_id, reason: if reason.is_empty() { "Kicked by an admin".to_string() } else { reason } })
+            }
+            "move" => match (words.next(), words.next(), words.next()) {
+                (Some(user_id), Some(room), None) => Ok(Self::Move { user_id: user_id.to_string(), room: room.to_string() }),
+                _ => anyhow::bail!("Usage: move <user_id> <room>"),
+            },
+            _ => anyhow::bail!("Unknown command {command:?}; the commands are: {ADMIN_COMMANDS_HELP}"),
+        }
+    }
+}
+
+/// Runs `command` on the server `state`, returning what it did
+pub fn run_admin_command(state: &mut ServerState, command: AdminCommand) -> anyhow::Result<String> {
+    match command {
+        AdminCommand::Kick { user_id, reason } => {
+            anyhow::ensure!(state.players.contains_key(&user_id), "No player {user_id:?}");
+            state.kick_player(&user_id, &reason);
+            Ok(format!("Kicked {user_id}"))
+        }
+        AdminCommand::Move { user_id, room: target } => {
+            let world = state.get_player_world_mut(&user_id).with_context(|| format!("No player {user_id:?}"))?;
+            let player_id = get_player_by_user_id(world, &user_id).with_context(|| format!("The player {user_id:?} has no entity yet"))?;
+            world.add_component(player_id, room(), target.clone())?;
+            Ok(format!("Moving {user_id} to {target}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_admin_commands() {
+        assert_eq!(
+            "kick bob  spamming the chat".parse::<AdminCommand>().unwrap(),
+            AdminCommand::Kick { user_id: "bob".to_string(), reason: "spamming the chat".to_string() }
+        );
+        assert_eq!(
+            "kick bob".parse::<AdminCommand>().unwrap(),
+            AdminCommand::Kick { user_id: "bob".to_string(), reason: "Kicked by an admin".to_string() }
+        );
+        assert_eq!(
+            " move bob arena ".parse::<AdminCommand>().unwrap(),
+            AdminCommand::Move { user_id: "bob".to_string(), room: "arena".to_string() }
+        );
+        for invalid in ["", "kick", "move bob", "move bob arena lobby", "ban bob"] {
+            assert!(invalid.parse::<AdminCommand>().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn bandwidth_of_the_sampled_players() {
+        let player = |user_id: &str, stats: Option<(u64, u64)>| PlayerStatus {
+            user_id: user_id.to_string(),
+            display_name: None,
+            room: "main".to_string(),
+            transport: None,
+            connection_lost: false,
+            stats: stats.map(|(sent, received)| NetworkStats {
+                bytes_sent_per_second: sent,
+                bytes_received_per_second: received,
+                ..Default::default()
+            }),
+        };
+        let status = ServerStatus {
+            tick: None,
+            rooms: Vec::new(),
+            players: vec![player("a", Some((1000, 10))), player("b", None), player("c", Some((500, 20)))],
+            memory: None,
+        };
+        assert_eq!(status.bytes_sent_per_second(), 1500);
+        assert_eq!(status.bytes_received_per_second(), 30);
+    }
+}
@@ -0,0 +1,222 @@
+//! The connections between the clients and the server, which go over QUIC, or over a WebSocket for the clients which
+//! can't use QUIC (such as the browsers) or which go through a relay (see [crate::relay]).
+//!
+//! Both transports provide the same streams and datagrams, so the protocol doesn't depend on which one a client uses.
+
+use std::{
+    fmt::Display,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use quinn::VarInt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+use crate::{
+    websocket::{WsConnection, WsRecvStream, WsSendStream},
+    NetworkError,
+};
+
+/// How a client is connected to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Quic,
+    WebSocket,
+}
+impl Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transport::Quic => write!(f, "QUIC"),
+            Transport::WebSocket => write!(f, "WebSocket"),
+        }
+    }
+}
+
+/// The amount of data which went through a connection
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+/// A handle to a connection, which opens the streams to the peer
+#[derive(Debug, Clone)]
+pub enum Connection {
+    Quic(quinn::Connection),
+    WebSocket(WsConnection),
+}
+impl Connection {
+    pub fn transport(&self) -> Transport {
+        match self {
+            Connection::Quic(_) => Transport::Quic,
+            Connection::WebSocket(_) => Transport::WebSocket,
+        }
+    }
+    pub fn remote_address(&self) -> SocketAddr {
+        match self {
+            Connection::Quic(conn) => conn.remote_address(),
+            Connection::WebSocket(conn) => conn.remote_address(),
+        }
+    }
+    /// Tells this connection apart from the other ones which are open, e.g. to know when a peer has reconnected
+    pub fn stable_id(&self) -> usize {
+        match self {
+            Connection::Quic(conn) => conn.stable_id(),
+            Connection::WebSocket(conn) => conn.stable_id(),
+        }
+    }
+    pub async fn open_uni(&self) -> Result<SendStream, NetworkError> {
+        match self {
+            Connection::Quic(conn) => Ok(SendStream::Quic(conn.open_uni().await?)),
+            Connection::WebSocket(conn) => Ok(SendStream::WebSocket(conn.open_uni()?)),
+        }
+    }
+    pub async fn open_bi(&self) -> Result<(SendStream, RecvStream), NetworkError> {
+        match self {
+            Connection::Quic(conn) => {
+                let (send, recv) = conn.open_bi().await?;
+                Ok((SendStream::Quic(send), RecvStream::Quic(recv)))
+            }
+            Connection::WebSocket(conn) => {
+                let (send, recv) = conn.open_bi()?;
+                Ok((SendStream::WebSocket(send), RecvStream::WebSocket(recv)))
+            }
+        }
+    }
+    /// Sends an unreliable message to the peer. Over a WebSocket, the datagrams are delivered reliably.
+    pub fn send_datagram(&self, data: Bytes) -> Result<(), NetworkError> {
+        match self {
+            Connection::Quic(conn) => {
+                conn.send_datagram(data).map_err(|err| NetworkError::IOError(io::Error::new(io::ErrorKind::Other, err)))
+            }
+            Connection::WebSocket(conn) => conn.send_datagram(data),
+        }
+    }
+    /// Closes the connection right away, telling the peer why with an application defined `error_code` and `reason`
+    pub fn close(&self, error_code: u32, reason: &[u8]) {
+        match self {
+            Connection::Quic(conn) => conn.close(VarInt::from_u32(error_code), reason),
+            Connection::WebSocket(conn) => conn.close(error_code, reason),
+        }
+    }
+    pub fn rtt(&self) -> Duration {
+        match self {
+            Connection::Quic(conn) => conn.rtt(),
+            Connection::WebSocket(conn) => conn.rtt(),
+        }
+    }
+    pub fn stats(&self) -> ConnectionStats {
+        match self {
+            Connection::Quic(conn) => {
+                let stats = conn.stats();
+                ConnectionStats { bytes_sent: stats.udp_tx.bytes, bytes_received: stats.udp_rx.bytes }
+            }
+            Connection::WebSocket(conn) => conn.stats(),
+        }
+    }
+}
+
+/// A connection which has just been established, with the streams and datagrams coming from the peer
+pub struct NewConnection {
+    pub connection: Connection,
+    /// The unidirectional streams opened by the peer, in the order they were opened
+    pub uni_streams: BoxStream<'static, Result<RecvStream, NetworkError>>,
+    /// The bidirectional streams opened by the peer, in the order they were opened
+    pub bi_streams: BoxStream<'static, Result<(SendStream, RecvStream), NetworkError>>,
+    pub datagrams: BoxStream<'static, Result<Bytes, NetworkError>>,
+}
+impl std::fmt::Debug for NewConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NewConnection").field("connection", &self.connection).finish_non_exhaustive()
+    }
+}
+impl From<quinn::NewConnection> for NewConnection {
+    fn from(conn: quinn::NewConnection) -> Self {
+        Self {
+            connection: Connection::Quic(conn.connection),
+            uni_streams: conn.uni_streams.map_ok(RecvStream::Quic).err_into().boxed(),
+            bi_streams: conn.bi_streams.map_ok(|(send, recv)| (SendStream::Quic(send), RecvStream::Quic(recv))).err_into().boxed(),
+            datagrams: conn.datagrams.err_into().boxed(),
+        }
+    }
+}
+
+/// The sending half of a stream
+#[derive(Debug)]
+pub enum SendStream {
+    Quic(quinn::SendStream),
+    WebSocket(WsSendStream),
+}
+impl SendStream {
+    /// Tells the peer that everything has been sent, and waits for it to have received it
+    pub async fn finish(&mut self) -> Result<(), NetworkError> {
+        match self {
+            SendStream::Quic(stream) => Ok(stream.finish().await?),
+            SendStream::WebSocket(stream) => stream.finish(),
+        }
+    }
+    /// Sets the priority of the stream against the other streams of the connection, the higher first. The streams of a
+    /// WebSocket are all sent in the order they're written to.
+    pub fn set_priority(&self, priority: i32) {
+        if let SendStream::Quic(stream) = self {
+            // Only fails if the stream was already closed, which the next write reports
+            stream.set_priority(priority).ok();
+        }
+    }
+}
+impl AsyncWrite for SendStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            SendStream::Quic(stream) => Pin::new(stream).poll_write(cx, buf),
+            SendStream::WebSocket(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            SendStream::Quic(stream) => Pin::new(stream).poll_flush(cx),
+            SendStream::WebSocket(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            SendStream::Quic(stream) => Pin::new(stream).poll_shutdown(cx),
+            SendStream::WebSocket(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// The receiving half of a stream
+#[derive(Debug)]
+pub enum RecvStream {
+    Quic(quinn::RecvStream),
+    WebSocket(WsRecvStream),
+}
+impl RecvStream {
+    /// Reads the rest of the stream, failing if it's longer than `size_limit`
+    pub async fn read_to_end(self, size_limit: usize) -> Result<Vec<u8>, NetworkError> {
+        match self {
+            RecvStream::Quic(stream) => Ok(stream.read_to_end(size_limit).await?),
+            RecvStream::WebSocket(stream) => {
+                let mut data = Vec::new();
+                stream.take(size_limit as u64 + 1).read_to_end(&mut data).await?;
+                if data.len() > size_limit {
+                    return Err(NetworkError::IOError(io::Error::new(io::ErrorKind::InvalidData, "Stream is too long")));
+                }
+                Ok(data)
+            }
+        }
+    }
+}
+impl AsyncRead for RecvStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            RecvStream::Quic(stream) => Pin::new(stream).poll_read(cx, buf),
+            RecvStream::WebSocket(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
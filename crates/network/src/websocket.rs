@@ -0,0 +1,729 @@
+//! A connection over a WebSocket, for the clients which can't use QUIC (such as the browsers).
+//!
+//! The streams and datagrams of the connection are sent as frames over the single WebSocket, so the streams are
+//! ordered with respect to each other, and the datagrams are delivered reliably.
+//!
+//! Unlike QUIC, a `ws://` connection is not encrypted. Servers which are reached over the internet should be put
+//! behind a proxy which terminates TLS, and be connected to with a `wss://` url through [connect_url].
+//!
+//! The frames waiting to be sent are bounded: writing to a stream waits once [MAX_QUEUED_BYTES] are queued, and the
+//! datagrams sent past it are dropped. The data received on a stream is bounded too, so a stream which isn't read
+//! eventually stops the whole connection from being read, which in turn stops the peer from writing.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use ambient_sys::time::{sleep, Instant};
+use bytes::Bytes;
+use futures::{
+    channel::mpsc,
+    future::{self, Either},
+    ready, Sink, SinkExt, Stream, StreamExt,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{
+    idle_timeout,
+    protocol::PROTOCOL_VIOLATION,
+    transport::{Connection, ConnectionStats, NewConnection, RecvStream, SendStream},
+    NetworkError,
+};
+
+/// How often the connection is pinged, to keep it alive and to measure the round trip time
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(1);
+/// The most data sent in a single frame, which keeps the frames well under the message size limit of the WebSocket
+const MAX_FRAME_DATA: usize = 1024 * 1024;
+/// How much data of the streams and datagrams may be waiting to be sent
+pub const MAX_QUEUED_BYTES: usize = 4 * MAX_FRAME_DATA;
+/// How many frames of data received on a stream may be waiting to be read
+const MAX_QUEUED_FRAMES: usize = 16;
+/// How many streams may be open for receiving at once
+pub const MAX_STREAMS: usize = 1024;
+/// How many streams and datagrams received may be waiting to be accepted
+const MAX_INCOMING: usize = 256;
+
+/// Which end of the connection this is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Client,
+    Server,
+}
+impl Side {
+    /// The id of the first stream opened by this side; the client opens the even ids, and the server the odd ones
+    fn first_stream_id(self) -> u64 {
+        match self {
+            Side::Client => 0,
+            Side::Server => 1,
+        }
+    }
+    fn peer(self) -> Self {
+        match self {
+            Side::Client => Side::Server,
+            Side::Server => Side::Client,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Frame {
+    OpenUni(u64),
+    OpenBi(u64),
+    Data(u64, Vec<u8>),
+    Finish(u64),
+    Datagram(Vec<u8>),
+    /// Echoed back as a [Frame::Pong], with the time it was sent at in microseconds since the connection started
+    Ping(u64),
+    Pong(u64),
+    Close(u32, String),
+}
+impl Frame {
+    /// How much of the send queue the frame takes up
+    fn queued_len(&self) -> usize {
+        match self {
+            Frame::Data(_, data) | Frame::Datagram(data) => data.len(),
+            _ => 0,
+        }
+    }
+}
+
+/// The receiving end of a stream, as seen by the connection
+struct StreamSender {
+    data: mpsc::Sender<Bytes>,
+    /// Set when the peer finished the stream, to tell it apart from a stream cut off by the connection closing
+    finished: Arc<AtomicBool>,
+}
+
+/// The streams being received, by stream id
+type Streams = Arc<Mutex<HashMap<u64, StreamSender>>>;
+
+#[derive(Debug, Default)]
+struct ConnectionState {
+    closed: AtomicBool,
+    rtt_micros: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    /// How much data of the streams and datagrams is waiting to be sent
+    queued_bytes: AtomicUsize,
+    /// The streams waiting for the send queue to drain
+    blocked_writers: Mutex<Vec<Waker>>,
+}
+impl ConnectionState {
+    fn is_queue_full(&self) -> bool {
+        self.queued_bytes.load(Ordering::Acquire) >= MAX_QUEUED_BYTES
+    }
+    /// Ready once there's room in the send queue, or once the connection is closed
+    fn poll_queue(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.is_queue_full() || self.closed.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        self.blocked_writers.lock().push(cx.waker().clone());
+        // The queue may have drained while registering
+        if !self.is_queue_full() || self.closed.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+    fn dequeue(&self, len: usize) {
+        if len > 0 && self.queued_bytes.fetch_sub(len, Ordering::AcqRel) - len < MAX_QUEUED_BYTES {
+            self.wake_writers();
+        }
+    }
+    fn wake_writers(&self) {
+        for waker in std::mem::take(&mut *self.blocked_writers.lock()) {
+            waker.wake();
+        }
+    }
+}
+
+struct Shared {
+    remote_address: SocketAddr,
+    frames: mpsc::UnboundedSender<Frame>,
+    streams: Streams,
+    state: Arc<ConnectionState>,
+    next_stream_id: AtomicU64,
+}
+impl Shared {
+    fn send(&self, frame: Frame) -> Result<(), NetworkError> {
+        if self.state.closed.load(Ordering::Acquire) {
+            return Err(NetworkError::ConnectionClosed);
+        }
+        let len = frame.queued_len();
+        self.state.queued_bytes.fetch_add(len, Ordering::AcqRel);
+        self.frames.unbounded_send(frame).map_err(|_| {
+            self.state.dequeue(len);
+            NetworkError::ConnectionClosed
+        })
+    }
+}
+
+/// A handle to a connection over a WebSocket. The connection is closed when all the handles and streams are dropped.
+#[derive(Clone)]
+pub struct WsConnection(Arc<Shared>);
+impl std::fmt::Debug for WsConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WsConnection").field("remote_address", &self.0.remote_address).finish_non_exhaustive()
+    }
+}
+impl WsConnection {
+    /// Runs a connection over a WebSocket which sends and receives binary messages through `sink` and `stream`
+    pub fn spawn<Si, St, E>(sink: Si, stream: St, side: Side, remote_address: SocketAddr) -> NewConnection
+    where
+        Si: Sink<Vec<u8>, Error = E> + Send + Unpin + 'static,
+        St: Stream<Item = Result<Vec<u8>, E>> + Send + Unpin + 'static,
+        E: Display + Send + 'static,
+    {
+        let (frames_tx, frames_rx) = mpsc::unbounded();
+        let (uni_tx, uni_rx) = mpsc::channel(MAX_INCOMING);
+        let (bi_tx, bi_rx) = mpsc::channel(MAX_INCOMING);
+        let (datagrams_tx, datagrams_rx) = mpsc::channel(MAX_INCOMING);
+        let shared = Arc::new(Shared {
+            remote_address,
+            frames: frames_tx,
+            streams: Default::default(),
+            state: Default::default(),
+            next_stream_id: AtomicU64::new(side.first_stream_id()),
+        });
+        let start = Instant::now();
+        ambient_sys::task::spawn(write(sink, frames_rx, shared.state.clone(), start));
+        ambient_sys::task::spawn(read(
+            stream,
+            Arc::downgrade(&shared),
+            Incoming { uni: uni_tx, bi: bi_tx, datagrams: datagrams_tx, next_stream_id: side.peer().first_stream_id() },
+            start,
+        ));
+        NewConnection {
+            connection: Connection::WebSocket(Self(shared)),
+            uni_streams: uni_rx.boxed(),
+            bi_streams: bi_rx.boxed(),
+            datagrams: datagrams_rx.boxed(),
+        }
+    }
+    pub fn remote_address(&self) -> SocketAddr {
+        self.0.remote_address
+    }
+    /// Tells this connection apart from the other ones which are open
+    pub fn stable_id(&self) -> usize {
+        Arc::as_ptr(&self.0) as usize
+    }
+    pub fn open_uni(&self) -> Result<WsSendStream, NetworkError> {
+        let id = self.next_stream_id();
+        self.0.send(Frame::OpenUni(id))?;
+        Ok(WsSendStream::new(self.0.clone(), id))
+    }
+    pub fn open_bi(&self) -> Result<(WsSendStream, WsRecvStream), NetworkError> {
+        let id = self.next_stream_id();
+        let recv = WsRecvStream::new(&self.0.streams, id);
+        self.0.send(Frame::OpenBi(id))?;
+        Ok((WsSendStream::new(self.0.clone(), id), recv))
+    }
+    /// Sends a datagram, which is dropped if the send queue is full
+    pub fn send_datagram(&self, data: Bytes) -> Result<(), NetworkError> {
+        if self.0.state.is_queue_full() {
+            return Ok(());
+        }
+        self.0.send(Frame::Datagram(data.to_vec()))
+    }
+    pub fn close(&self, error_code: u32, reason: &[u8]) {
+        self.0.send(Frame::Close(error_code, String::from_utf8_lossy(reason).into_owned())).ok();
+        close_streams(&self.0.state, &self.0.streams);
+    }
+    pub fn rtt(&self) -> Duration {
+        Duration::from_micros(self.0.state.rtt_micros.load(Ordering::Relaxed))
+    }
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            bytes_sent: self.0.state.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.0.state.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+    fn next_stream_id(&self) -> u64 {
+        self.0.next_stream_id.fetch_add(2, Ordering::Relaxed)
+    }
+}
+
+/// Marks the connection as closed, and cuts off the streams which are still being received or waiting to send
+fn close_streams(state: &ConnectionState, streams: &Streams) {
+    state.closed.store(true, Ordering::Release);
+    streams.lock().clear();
+    state.wake_writers();
+}
+
+/// Where the streams and datagrams opened by the peer go
+struct Incoming {
+    uni: mpsc::Sender<Result<RecvStream, NetworkError>>,
+    bi: mpsc::Sender<Result<(SendStream, RecvStream), NetworkError>>,
+    datagrams: mpsc::Sender<Result<Bytes, NetworkError>>,
+    /// The lowest id the next stream opened by the peer may have
+    next_stream_id: u64,
+}
+impl Incoming {
+    /// Checks that a stream opened by the peer has one of the peer's ids, which it hasn't used yet, and that the peer
+    /// doesn't have too many streams open
+    fn accept_stream_id(&mut self, id: u64, open_streams: usize) -> Result<(), String> {
+        if id % 2 != self.next_stream_id % 2 {
+            return Err(format!("Stream {id} has an id of the other side"));
+        }
+        if id < self.next_stream_id {
+            return Err(format!("Stream {id} was already opened"));
+        }
+        if open_streams >= MAX_STREAMS {
+            return Err(format!("Too many streams open (at most {MAX_STREAMS})"));
+        }
+        self.next_stream_id = id + 2;
+        Ok(())
+    }
+}
+
+/// Sends the frames, and pings the peer, until the connection is closed or all its handles are dropped
+async fn write<Si: Sink<Vec<u8>, Error = E> + Unpin, E: Display>(
+    mut sink: Si,
+    mut frames: mpsc::UnboundedReceiver<Frame>,
+    state: Arc<ConnectionState>,
+    start: Instant,
+) {
+    let mut keep_alive = Box::pin(sleep(KEEP_ALIVE_INTERVAL));
+    loop {
+        let frame = match future::select(frames.next(), &mut keep_alive).await {
+            Either::Left((Some(frame), _)) => frame,
+            Either::Left((None, _)) => break,
+            Either::Right(_) => Frame::Ping(start.elapsed().as_micros() as u64),
+        };
+        if let Frame::Ping(_) = frame {
+            keep_alive = Box::pin(sleep(KEEP_ALIVE_INTERVAL));
+        }
+        let is_close = matches!(frame, Frame::Close(..));
+        let queued_len = frame.queued_len();
+        let message = bincode::serialize(&frame).unwrap();
+        state.bytes_sent.fetch_add(message.len() as u64, Ordering::Relaxed);
+        let sent = sink.send(message).await;
+        state.dequeue(queued_len);
+        if let Err(err) = sent {
+            log::info!("WebSocket write error: {err}");
+            break;
+        }
+        if is_close {
+            break;
+        }
+    }
+    sink.close().await.ok();
+    state.closed.store(true, Ordering::Release);
+    state.wake_writers();
+}
+
+/// Receives the frames until the connection is closed
+async fn read<St: Stream<Item = Result<Vec<u8>, E>> + Unpin, E: Display>(
+    mut stream: St,
+    shared: Weak<Shared>,
+    mut incoming: Incoming,
+    start: Instant,
+) {
+    let (state, streams) = match shared.upgrade() {
+        Some(shared) => (shared.state.clone(), shared.streams.clone()),
+        None => return,
+    };
+    loop {
+        let message = match idle_timeout() {
+            Some(timeout) => match future::select(stream.next(), Box::pin(sleep(timeout))).await {
+                Either::Left((message, _)) => message,
+                Either::Right(_) => {
+                    log::info!("WebSocket connection timed out");
+                    break;
+                }
+            },
+            None => stream.next().await,
+        };
+        let message = match message {
+            Some(Ok(message)) => message,
+            Some(Err(err)) => {
+                log::info!("WebSocket read error: {err}");
+                break;
+            }
+            None => break,
+        };
+        state.bytes_received.fetch_add(message.len() as u64, Ordering::Relaxed);
+        let frame: Frame = match bincode::deserialize(&message) {
+            Ok(frame) => frame,
+            Err(err) => {
+                log::warn!("Invalid WebSocket frame: {err}");
+                break;
+            }
+        };
+        if let Frame::OpenUni(id) | Frame::OpenBi(id) = frame {
+            let open_streams = streams.lock().len();
+            if let Err(err) = incoming.accept_stream_id(id, open_streams) {
+                log::warn!("Closing the WebSocket connection: {err}");
+                if let Some(shared) = shared.upgrade() {
+                    shared.send(Frame::Close(PROTOCOL_VIOLATION, err)).ok();
+                }
+                break;
+            }
+        }
+        match frame {
+            Frame::OpenUni(id) => {
+                let recv = RecvStream::WebSocket(WsRecvStream::new(&streams, id));
+                incoming.uni.send(Ok(recv)).await.ok();
+            }
+            Frame::OpenBi(id) => {
+                // All the handles are gone, so nothing could answer the stream anyway
+                let shared = match shared.upgrade() {
+                    Some(shared) => shared,
+                    None => break,
+                };
+                let recv = WsRecvStream::new(&streams, id);
+                let send = WsSendStream::new(shared, id);
+                incoming.bi.send(Ok((SendStream::WebSocket(send), RecvStream::WebSocket(recv)))).await.ok();
+            }
+            Frame::Data(id, data) => {
+                // The receiving half may have been dropped
+                let sender = streams.lock().get(&id).map(|stream| stream.data.clone());
+                if let Some(mut sender) = sender {
+                    // Waits for the stream to be read, which holds up the rest of the connection
+                    if sender.send(data.into()).await.is_err() {
+                        streams.lock().remove(&id);
+                    }
+                }
+            }
+            Frame::Finish(id) => {
+                if let Some(stream) = streams.lock().remove(&id) {
+                    stream.finished.store(true, Ordering::Release);
+                }
+            }
+            Frame::Datagram(data) => {
+                // Like over QUIC, the datagrams which can't be kept up with are dropped
+                incoming.datagrams.try_send(Ok(data.into())).ok();
+            }
+            Frame::Ping(time) => {
+                if let Some(shared) = shared.upgrade() {
+                    shared.send(Frame::Pong(time)).ok();
+                }
+            }
+            Frame::Pong(time) => {
+                let rtt = (start.elapsed().as_micros() as u64).saturating_sub(time);
+                state.rtt_micros.store(rtt, Ordering::Relaxed);
+            }
+            Frame::Close(error_code, reason) => {
+                log::info!("WebSocket connection closed by peer ({error_code}): {reason}");
+                break;
+            }
+        }
+    }
+    close_streams(&state, &streams);
+}
+
+/// The sending half of a stream over a WebSocket. The stream is finished when it's dropped.
+pub struct WsSendStream {
+    connection: Arc<Shared>,
+    id: u64,
+    finished: bool,
+}
+impl std::fmt::Debug for WsSendStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WsSendStream").field("id", &self.id).field("finished", &self.finished).finish()
+    }
+}
+impl WsSendStream {
+    fn new(connection: Arc<Shared>, id: u64) -> Self {
+        Self { connection, id, finished: false }
+    }
+    /// Tells the peer that everything has been sent. Unlike with QUIC, this doesn't wait for the peer to have received it.
+    pub fn finish(&mut self) -> Result<(), NetworkError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        self.connection.send(Frame::Finish(self.id))
+    }
+}
+impl AsyncWrite for WsSendStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if self.finished {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        ready!(self.connection.state.poll_queue(cx));
+        let len = buf.len().min(MAX_FRAME_DATA);
+        if len > 0 {
+            if let Err(err) = self.connection.send(Frame::Data(self.id, buf[..len].to_vec())) {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::ConnectionReset, err)));
+            }
+        }
+        Poll::Ready(Ok(len))
+    }
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.get_mut().finish().map_err(|err| io::Error::new(io::ErrorKind::ConnectionReset, err)))
+    }
+}
+impl Drop for WsSendStream {
+    fn drop(&mut self) {
+        self.finish().ok();
+    }
+}
+
+/// The receiving half of a stream over a WebSocket
+#[derive(Debug)]
+pub struct WsRecvStream {
+    data: mpsc::Receiver<Bytes>,
+    finished: Arc<AtomicBool>,
+    /// What's left of the data received last
+    buffer: Bytes,
+}
+impl WsRecvStream {
+    fn new(streams: &Streams, id: u64) -> Self {
+        let (tx, rx) = mpsc::channel(MAX_QUEUED_FRAMES);
+        let finished = Arc::new(AtomicBool::new(false));
+        streams.lock().insert(id, StreamSender { data: tx, finished: finished.clone() });
+        Self { data: rx, finished, buffer: Bytes::new() }
+    }
+}
+impl AsyncRead for WsRecvStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.buffer.is_empty() {
+            match ready!(this.data.poll_next_unpin(cx)) {
+                Some(data) => this.buffer = data,
+                // The stream is finished
+                None if this.finished.load(Ordering::Acquire) => return Poll::Ready(Ok(())),
+                None => return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into())),
+            }
+        }
+        let len = buf.remaining().min(this.buffer.len());
+        buf.put_slice(&this.buffer.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Connects to the server at `server_addr` with a plaintext WebSocket
+pub async fn connect(server_addr: SocketAddr) -> Result<NewConnection, NetworkError> {
+    connect_url(&format!("ws://{server_addr}"), server_addr).await
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::*;
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::{io, net::SocketAddr};
+
+    use futures::{future, Sink, SinkExt, Stream, StreamExt, TryStreamExt};
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::{self, Message};
+
+    use super::{Side, WsConnection};
+    use crate::{transport::NewConnection, NetworkError};
+
+    /// Accepts a client which connected to the TCP listener of the server with a WebSocket
+    pub async fn accept(stream: TcpStream) -> Result<NewConnection, NetworkError> {
+        let remote_address = stream.peer_addr()?;
+        let socket = tokio_tungstenite::accept_async(stream).await.map_err(websocket_error)?;
+        let (sink, stream) = socket.split();
+        Ok(WsConnection::spawn(binary_sink(sink), binary_stream(stream), Side::Server, remote_address))
+    }
+
+    /// Connects to the server at `url` with a WebSocket, which may be a `wss://` url of a proxy terminating TLS in front
+    /// of the server at `remote_address`
+    pub async fn connect_url(url: &str, remote_address: SocketAddr) -> Result<NewConnection, NetworkError> {
+        let (socket, _) = tokio_tungstenite::connect_async(url).await.map_err(websocket_error)?;
+        let (sink, stream) = socket.split();
+        Ok(WsConnection::spawn(binary_sink(sink), binary_stream(stream), Side::Client, remote_address))
+    }
+
+    pub(crate) fn binary_sink<Si: Sink<Message, Error = tungstenite::Error> + Send + Unpin>(
+        sink: Si,
+    ) -> impl Sink<Vec<u8>, Error = tungstenite::Error> + Send + Unpin {
+        sink.with(|data| future::ready(Ok(Message::Binary(data))))
+    }
+
+    pub(crate) fn binary_stream<St: Stream<Item = Result<Message, tungstenite::Error>> + Send + Unpin>(
+        stream: St,
+    ) -> impl Stream<Item = Result<Vec<u8>, tungstenite::Error>> + Send + Unpin {
+        stream.try_filter_map(|message| {
+            future::ready(Ok(match message {
+                Message::Binary(data) => Some(data),
+                _ => None,
+            }))
+        })
+    }
+
+    pub(crate) fn websocket_error(err: tungstenite::Error) -> NetworkError {
+        NetworkError::IOError(io::Error::new(io::ErrorKind::Other, err))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub use web::*;
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use std::net::SocketAddr;
+
+    use futures::{channel::mpsc, future, SinkExt, StreamExt};
+    use gloo_net::websocket::{futures::WebSocket, Message};
+
+    use super::{Side, WsConnection, MAX_INCOMING};
+    use crate::{transport::NewConnection, NetworkError};
+
+    /// Connects to the server at `url` with the WebSocket of the browser, which may be a `wss://` url of a proxy
+    /// terminating TLS in front of the server at `remote_address`
+    pub async fn connect_url(url: &str, remote_address: SocketAddr) -> Result<NewConnection, NetworkError> {
+        let (outgoing_tx, outgoing_rx) = mpsc::channel::<Vec<u8>>(MAX_INCOMING);
+        let (incoming_tx, incoming_rx) = mpsc::channel::<Result<Vec<u8>, String>>(MAX_INCOMING);
+        let url = url.to_string();
+        // The socket of the browser can't leave its thread, so it's pumped from and into channels which can
+        ambient_sys::task::spawn_local(move || async move {
+            let socket = match WebSocket::open(&url) {
+                Ok(socket) => socket,
+                Err(err) => {
+                    let mut incoming_tx = incoming_tx;
+                    incoming_tx.send(Err(err.to_string())).await.ok();
+                    return;
+                }
+            };
+            let (sink, stream) = socket.split();
+            let send = outgoing_rx.map(|data| Ok(Message::Bytes(data))).forward(sink);
+            let receive = stream
+                .filter_map(|message| {
+                    future::ready(match message {
+                        Ok(Message::Bytes(data)) => Some(Ok(Ok(data))),
+                        Ok(Message::Text(_)) => None,
+                        Err(err) => Some(Ok(Err(err.to_string()))),
+                    })
+                })
+                .forward(incoming_tx);
+            future::select(Box::pin(send), Box::pin(receive)).await;
+        });
+        Ok(WsConnection::spawn(outgoing_tx.sink_map_err(|err| err.to_string()), incoming_rx, Side::Client, remote_address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    /// Two ends of a connection over in-memory channels
+    fn pair() -> (NewConnection, NewConnection) {
+        let (client_tx, server_rx) = mpsc::unbounded::<Vec<u8>>();
+        let (server_tx, client_rx) = mpsc::unbounded::<Vec<u8>>();
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let sink = |tx: mpsc::UnboundedSender<Vec<u8>>| tx.sink_map_err(|err| err.to_string());
+        let stream = |rx: mpsc::UnboundedReceiver<Vec<u8>>| rx.map(Ok::<_, String>);
+        let client = WsConnection::spawn(sink(client_tx), stream(client_rx), Side::Client, addr);
+        let server = WsConnection::spawn(sink(server_tx), stream(server_rx), Side::Server, addr);
+        (client, server)
+    }
+
+    fn ws(connection: &Connection) -> &WsConnection {
+        match connection {
+            Connection::WebSocket(connection) => connection,
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_and_datagrams() {
+        let (mut client, mut server) = pair();
+
+        let mut send = ws(&client.connection).open_uni().unwrap();
+        send.write_all(b"hello").await.unwrap();
+        send.finish().unwrap();
+        let mut recv = server.uni_streams.next().await.unwrap().unwrap();
+        let mut data = Vec::new();
+        AsyncReadExt::read_to_end(&mut recv, &mut data).await.unwrap();
+        assert_eq!(data, b"hello");
+
+        let (mut send, mut recv) = ws(&server.connection).open_bi().unwrap();
+        send.write_all(b"ping").await.unwrap();
+        let (mut client_send, mut client_recv) = client.bi_streams.next().await.unwrap().unwrap();
+        let mut data = [0; 4];
+        client_recv.read_exact(&mut data).await.unwrap();
+        assert_eq!(&data, b"ping");
+        client_send.write_all(b"pong").await.unwrap();
+        recv.read_exact(&mut data).await.unwrap();
+        assert_eq!(&data, b"pong");
+
+        client.connection.send_datagram(Bytes::from_static(b"datagram")).unwrap();
+        assert_eq!(server.datagrams.next().await.unwrap().unwrap(), Bytes::from_static(b"datagram"));
+    }
+
+    #[tokio::test]
+    async fn streams_are_reset_when_the_connection_closes() {
+        let (client, mut server) = pair();
+        let mut send = ws(&client.connection).open_uni().unwrap();
+        send.write_all(b"partial").await.unwrap();
+        let mut recv = server.uni_streams.next().await.unwrap().unwrap();
+        client.connection.close(0, b"bye");
+        let mut data = Vec::new();
+        let err = AsyncReadExt::read_to_end(&mut recv, &mut data).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(data, b"partial");
+    }
+
+    #[tokio::test]
+    async fn writes_wait_for_the_send_queue() {
+        // Nothing reads the other end, so nothing is ever sent
+        let (sink, _stream) = mpsc::channel::<Vec<u8>>(0);
+        let (_tx, stream) = mpsc::unbounded::<Result<Vec<u8>, String>>();
+        let conn = WsConnection::spawn(sink.sink_map_err(|err| err.to_string()), stream, Side::Client, "127.0.0.1:9000".parse().unwrap());
+        let mut send = ws(&conn.connection).open_uni().unwrap();
+        let data = vec![0; MAX_FRAME_DATA];
+        let filled = tokio::time::timeout(Duration::from_millis(200), async {
+            loop {
+                send.write(&data).await.unwrap();
+            }
+        })
+        .await;
+        assert!(filled.is_err());
+        let queued = ws(&conn.connection).0.state.queued_bytes.load(Ordering::Acquire);
+        assert!(queued <= MAX_QUEUED_BYTES + MAX_FRAME_DATA, "{queued}");
+    }
+
+    #[test]
+    fn stream_ids_of_the_peer_are_checked() {
+        let (uni, _) = mpsc::channel(1);
+        let (bi, _) = mpsc::channel(1);
+        let (datagrams, _) = mpsc::channel(1);
+        let mut incoming = Incoming { uni, bi, datagrams, next_stream_id: Side::Client.first_stream_id() };
+        assert!(incoming.accept_stream_id(0, 0).is_ok());
+        assert!(incoming.accept_stream_id(1, 0).is_err());
+        assert!(incoming.accept_stream_id(0, 0).is_err());
+        assert!(incoming.accept_stream_id(4, 0).is_ok());
+        assert!(incoming.accept_stream_id(2, 0).is_err());
+        assert!(incoming.accept_stream_id(6, MAX_STREAMS).is_err());
+        assert!(incoming.accept_stream_id(6, MAX_STREAMS - 1).is_ok());
+    }
+
+    #[tokio::test]
+    async fn closes_on_a_stream_of_the_wrong_side() {
+        let (client_tx, server_rx) = mpsc::unbounded::<Vec<u8>>();
+        let (server_tx, mut client_rx) = mpsc::unbounded::<Vec<u8>>();
+        let server = WsConnection::spawn(
+            server_tx.sink_map_err(|err| err.to_string()),
+            server_rx.map(Ok::<_, String>),
+            Side::Server,
+            "127.0.0.1:9000".parse().unwrap(),
+        );
+        client_tx.unbounded_send(bincode::serialize(&Frame::OpenUni(1)).unwrap()).unwrap();
+        loop {
+            let frame: Frame = bincode::deserialize(&client_rx.next().await.unwrap()).unwrap();
+            if let Frame::Close(code, _) = frame {
+                assert_eq!(code, PROTOCOL_VIOLATION);
+                break;
+            }
+        }
+        assert!(ws(&server.connection).open_uni().is_err());
+    }
+}
@@ -1,9 +1,9 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, sync::Arc};
 
 use ambient_core::{asset_cache, transform::translation};
 use ambient_ecs::{query, ArchetypeFilter, EntityId, World};
 use ambient_meshes::cuboid::CuboidMesh;
-use ambient_network::client::GameRpcArgs;
+use ambient_network::{client::GameRpcArgs, movement::MovementCollisionCheck};
 use ambient_std::{asset_cache::SyncAssetKeyExt, mesh::Mesh, shapes::Ray};
 use glam::Vec3;
 use itertools::Itertools;
@@ -81,6 +81,19 @@ pub fn raycast_collider_type_px(world: &World, collider_type: ColliderScene, ray
     Vec::new()
 }
 
+/// A [MovementCollisionCheck] against the colliders of the physics scene, other than the ones of the entity itself
+pub fn movement_collision_check() -> MovementCollisionCheck {
+    MovementCollisionCheck(Arc::new(|world, id, from, to| {
+        let distance = (to - from).length();
+        if distance <= f32::EPSILON {
+            return false;
+        }
+        raycast_collider_type(world, ColliderScene::Physics, Ray::new(from, (to - from) / distance))
+            .into_iter()
+            .any(|(hit, dist)| hit != id && dist < distance)
+    }))
+}
+
 pub fn intersect_frustum(world: &World, frustum_corners: &[Vec3; 8]) -> Vec<EntityId> {
     let mut hit_call = PxOverlapCallback::new(1000);
     let filter_data = PxQueryFilterData::new();
@@ -127,6 +127,7 @@ pub fn create_server_resources(assets: &AssetCache, server_resources: &mut Entit
     server_resources.set_self(crate::trigger_areas_scene(), trigger_areas);
     server_resources.set_self(self::main_controller_manager(), main_controller_manager);
     server_resources.set_self(self::wood_physics_material(), PxMaterial::new(physics.physics, 0.5, 0.5, 0.6));
+    server_resources.set_self(ambient_network::movement::movement_collision_check(), intersection::movement_collision_check());
 }
 
 #[derive(Debug, Clone)]
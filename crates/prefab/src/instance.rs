@@ -0,0 +1,162 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use ambient_core::{asset_cache, runtime, scene::Scene};
+use ambient_ecs::{query, FnSystem, SystemGroup, World};
+use ambient_std::{
+    asset_cache::{AssetCache, AsyncAssetKey, AsyncAssetKeyExt, SyncAssetKeyExt},
+    asset_url::{AbsAssetUrl, AssetUrl, ServerBaseUrlKey, ServerContentDirKey},
+    download_asset::{AssetError, BytesFromUrl},
+};
+use ambient_sys::time::Instant;
+use anyhow::Context;
+use async_trait::async_trait;
+use parking_lot::Mutex;
+
+use crate::{prefab_instance, spawned};
+
+/// How often the prefab files are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A prefab which has been loaded, and the file it's watched from if it's on this machine
+struct LoadedPrefab {
+    scene: Arc<Scene>,
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+}
+
+/// The prefabs by url; they're `None` while they're loading
+type Prefabs = Arc<Mutex<HashMap<AbsAssetUrl, Option<LoadedPrefab>>>>;
+
+/// Instantiates the [prefab_instance]s, and updates them when their prefab files change
+pub fn instance_systems() -> SystemGroup {
+    let prefabs = Prefabs::default();
+    let watched = prefabs.clone();
+    let mut last_check = Instant::now();
+    SystemGroup::new(
+        "prefab/instances",
+        vec![
+            query(prefab_instance()).excl(spawned()).to_system(move |q, world, qs, _| {
+                for (id, url) in q.collect_cloned(world, qs) {
+                    let url = match resolve_url(world, &url) {
+                        Ok(url) => url,
+                        Err(err) => {
+                            log::warn!("Invalid prefab url {url:?}: {err:?}");
+                            world.add_component(id, spawned(), ()).unwrap();
+                            continue;
+                        }
+                    };
+                    let loaded = prefabs.lock().get(&url).map(|prefab| prefab.as_ref().map(|prefab| prefab.scene.clone()));
+                    let scene = match loaded {
+                        Some(Some(scene)) => scene,
+                        Some(None) => continue,
+                        None => {
+                            load(world, prefabs.clone(), url);
+                            continue;
+                        }
+                    };
+                    scene.instantiate(world, id);
+                    world.add_component(id, spawned(), ()).unwrap();
+                }
+            }),
+            Box::new(FnSystem::new(move |world, _| {
+                if last_check.elapsed() < WATCH_INTERVAL {
+                    return;
+                }
+                last_check = Instant::now();
+                reload_changed(world, &watched);
+            })),
+        ],
+    )
+}
+
+pub(crate) fn resolve_url(world: &World, url: &str) -> anyhow::Result<AbsAssetUrl> {
+    let base_url = ServerBaseUrlKey.get(world.resource(asset_cache()));
+    Ok(AssetUrl::parse(url)?.resolve(&base_url)?)
+}
+
+/// The file `url` is loaded from, if it's on this machine
+fn local_path(assets: &AssetCache, url: &AbsAssetUrl) -> Option<PathBuf> {
+    if let Ok(Some(path)) = url.to_file_path() {
+        return Some(path);
+    }
+    let dir = ServerContentDirKey.get(assets)?;
+    let relative = url.0.as_str().strip_prefix(ServerBaseUrlKey.get(assets).0.as_str())?;
+    Some(dir.join(relative))
+}
+
+fn modified(path: &Option<PathBuf>) -> Option<SystemTime> {
+    path.as_ref().and_then(|path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+}
+
+fn load(world: &World, prefabs: Prefabs, url: AbsAssetUrl) {
+    prefabs.lock().insert(url.clone(), None);
+    let assets = world.resource(asset_cache()).clone();
+    world.resource(runtime()).spawn(async move {
+        let path = local_path(&assets, &url);
+        let modified = modified(&path);
+        match SceneFromUrl(url.clone()).get(&assets).await {
+            Ok(scene) => {
+                prefabs.lock().insert(url, Some(LoadedPrefab { scene, path, modified }));
+            }
+            // It stays as loading, so that it's not loaded again every frame
+            Err(err) => log::warn!("Failed to load prefab {url}: {err:?}"),
+        }
+    });
+}
+
+/// Updates the instances of the prefabs whose files have changed
+fn reload_changed(world: &mut World, prefabs: &Prefabs) {
+    let mut changed = Vec::new();
+    for (url, prefab) in prefabs.lock().iter_mut() {
+        let prefab = match prefab {
+            Some(prefab) if prefab.path.is_some() => prefab,
+            _ => continue,
+        };
+        let modified = modified(&prefab.path);
+        if modified == prefab.modified {
+            continue;
+        }
+        prefab.modified = modified;
+        let path = prefab.path.as_ref().unwrap();
+        let scene = std::fs::read_to_string(path).context("Failed to read the file").and_then(|json| Ok(Scene::from_json(&json)?));
+        match scene {
+            Ok(scene) => {
+                log::info!("Reloading prefab {url}");
+                let old = std::mem::replace(&mut prefab.scene, Arc::new(scene));
+                changed.push((url.clone(), old, prefab.scene.clone()));
+            }
+            Err(err) => log::warn!("Failed to reload prefab {url}: {err:?}"),
+        }
+    }
+    if changed.is_empty() {
+        return;
+    }
+
+    let instances = query(prefab_instance()).incl(spawned()).iter(world, None).map(|(id, url)| (id, url.clone())).collect::<Vec<_>>();
+    for (id, url) in instances {
+        let url = match resolve_url(world, &url) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+        if let Some((_, old, new)) = changed.iter().find(|(changed_url, _, _)| *changed_url == url) {
+            new.update_instance(world, id, old);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SceneFromUrl(pub AbsAssetUrl);
+#[async_trait]
+impl AsyncAssetKey<Result<Arc<Scene>, AssetError>> for SceneFromUrl {
+    async fn load(self, assets: AssetCache) -> Result<Arc<Scene>, AssetError> {
+        let data = BytesFromUrl::new(self.0.clone(), true).get(&assets).await?;
+        let scene = tokio::task::block_in_place(|| serde_json::from_slice::<Scene>(&data))
+            .with_context(|| format!("Failed to deserialize scene from url {}", self.0))?;
+        Ok(Arc::new(scene))
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt::Debug,
+    sync::Arc,
+    time::Duration,
+};
+
+use ambient_core::{
+    asset_cache,
+    async_ecs::async_run,
+    hierarchy::{children, despawn_recursive, parent, remove_child, set_parent},
+    runtime,
+    scene::SceneSpawner,
+};
+use ambient_ecs::{dont_store, query, EntityData, EntityId, FnSystem, SystemGroup, World};
+use ambient_model::{model_from_url, Model, ModelFromUrl};
+use ambient_std::{asset_cache::AsyncAssetKeyExt, asset_url::AbsAssetUrl};
+use ambient_sys::time::Instant;
+use parking_lot::Mutex;
+
+use crate::{
+    level_assets, level_error, level_from_url, level_loaded, level_progress, resolve_url, streaming::streaming_volumes_system, SceneFromUrl,
+};
+
+/// A level which is loaded (or being loaded) into the world under its own root entity, which holds the
+/// [level_from_url], [level_progress] and [level_loaded] components.
+///
+/// Several levels can be loaded at once: load the next level before unloading the current one for transitions, and
+/// load sublevels under a level to stream parts of it in and out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LevelHandle(pub EntityId);
+impl LevelHandle {
+    /// Starts loading the level scene at `url` (which may be relative to the server) into `world`
+    pub fn load(world: &mut World, url: impl Into<String>) -> Self {
+        Self(world.spawn(EntityData::new().set(level_from_url(), url.into())))
+    }
+    /// Starts loading the level scene at `url` under this level, so that it's unloaded with it
+    pub fn load_sublevel(&self, world: &mut World, url: impl Into<String>) -> Self {
+        let sublevel = Self::load(world, url);
+        set_parent(world, sublevel.0, Some(self.0)).ok();
+        sublevel
+    }
+    /// The root entity of the level; the roots of the scene are its children
+    pub fn root(&self) -> EntityId {
+        self.0
+    }
+    /// How much of the level has been loaded, from 0 to 1
+    pub fn progress(&self, world: &World) -> f32 {
+        world.get(self.0, level_progress()).unwrap_or_default()
+    }
+    pub fn is_loaded(&self, world: &World) -> bool {
+        world.has_component(self.0, level_loaded())
+    }
+    /// The reason the level failed to load, if it did
+    pub fn error(&self, world: &World) -> Option<String> {
+        world.get_cloned(self.0, level_error()).ok()
+    }
+    /// Despawns all the entities of the level (and its sublevels), and releases its assets. If the level is still
+    /// loading, it's not spawned when it's done.
+    pub fn unload(self, world: &mut World) {
+        if let Ok(parent) = world.get(self.0, parent()) {
+            remove_child(world, parent, self.0).ok();
+        }
+        despawn_recursive(world, self.0);
+    }
+}
+
+/// The assets used by a level. They're kept loaded for as long as the level is, so that its entities don't have to
+/// wait for them, and released when it's unloaded.
+#[derive(Clone, Default)]
+pub struct LevelAssets {
+    pub models: Vec<Arc<Model>>,
+}
+impl Debug for LevelAssets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LevelAssets").field("models", &self.models.len()).finish()
+    }
+}
+
+/// How long the levels may take to spawn per frame; larger levels are spawned over several frames
+const LEVEL_SPAWN_BUDGET: Duration = Duration::from_millis(2);
+
+/// A level which has been loaded, and is being spawned
+struct LevelSpawn {
+    id: EntityId,
+    spawner: SceneSpawner,
+    assets: LevelAssets,
+    /// The number of steps of the progress of the level; spawning it is the last one
+    steps: usize,
+}
+
+/// The levels waiting to be spawned, in the order they finished loading
+type PendingSpawns = Arc<Mutex<VecDeque<LevelSpawn>>>;
+
+/// Loads the levels of the entities with a [level_from_url], and streams the sublevels of the [crate::streaming_volume]s
+pub fn level_systems() -> SystemGroup {
+    let pending = PendingSpawns::default();
+    let spawning = pending.clone();
+    SystemGroup::new(
+        "prefab/levels",
+        vec![
+            query(level_from_url()).excl(level_progress()).to_system(move |q, world, qs, _| {
+                for (id, url) in q.collect_cloned(world, qs) {
+                    world.add_component(id, level_progress(), 0.).unwrap();
+                    // The entities of a level aren't stored, so the root of a level from a saved world may still list them as children
+                    if let Ok(ids) = world.get_cloned(id, children()) {
+                        let ids = ids.into_iter().filter(|&child| world.exists(child)).collect();
+                        world.set(id, children(), ids).unwrap();
+                    }
+                    match resolve_url(world, &url) {
+                        Ok(url) => load(world, id, url, pending.clone()),
+                        Err(err) => fail(world, id, format!("Invalid level url {url:?}: {err:?}")),
+                    }
+                }
+            }),
+            Box::new(FnSystem::new(move |world, _| spawn_pending(world, &spawning))),
+            streaming_volumes_system(),
+        ],
+    )
+}
+
+fn load(world: &World, id: EntityId, url: AbsAssetUrl, pending: PendingSpawns) {
+    let assets = world.resource(asset_cache()).clone();
+    let async_run = world.resource(async_run()).clone();
+    world.resource(runtime()).spawn(async move {
+        let scene = match SceneFromUrl(url.clone()).get(&assets).await {
+            Ok(scene) => scene,
+            Err(err) => {
+                async_run.run(move |world| fail(world, id, format!("Failed to load level {url}: {err:?}")));
+                return;
+            }
+        };
+
+        // The scene is the first step, then each model it uses, and then spawning it
+        let model_urls =
+            scene.entities.iter().filter_map(|entity| entity.components.get_ref(model_from_url()).cloned()).collect::<HashSet<_>>();
+        let steps = 2 + model_urls.len();
+        let report = |done: usize| {
+            let progress = done as f32 / steps as f32;
+            async_run.run(move |world| {
+                world.set(id, level_progress(), progress).ok();
+            });
+        };
+        report(1);
+
+        let mut level = LevelAssets::default();
+        for (i, model_url) in model_urls.into_iter().enumerate() {
+            let model = match ModelFromUrl::for_model_from_url(&model_url) {
+                Ok(key) => key.get(&assets).await.map_err(|err| format!("{err:?}")),
+                Err(err) => Err(format!("{err:?}")),
+            };
+            // The entity shows the model failed to load itself, so this doesn't fail the level
+            match model {
+                Ok(model) => level.models.push(model),
+                Err(err) => log::warn!("Failed to preload model {model_url} of level {url}: {err}"),
+            }
+            report(2 + i);
+        }
+
+        let spawn = LevelSpawn { id, spawner: SceneSpawner::new(scene), assets: level, steps };
+        async_run.run(move |_| pending.lock().push_back(spawn));
+    });
+}
+
+/// Spawns the loaded levels within the [LEVEL_SPAWN_BUDGET]
+fn spawn_pending(world: &mut World, pending: &PendingSpawns) {
+    let start = Instant::now();
+    let mut pending = pending.lock();
+    while let Some(spawn) = pending.front_mut() {
+        // The level was unloaded while it was loading
+        if !world.exists(spawn.id) {
+            for id in spawn.spawner.spawned().collect::<Vec<_>>() {
+                world.despawn(id);
+            }
+            pending.pop_front();
+            continue;
+        }
+        let spawned = spawn.spawner.spawned().count();
+        let done = spawn.spawner.spawn(world, LEVEL_SPAWN_BUDGET.saturating_sub(start.elapsed()));
+        // The level is loaded again from its url when the world is, so its entities are left out of the saved world
+        for id in spawn.spawner.spawned().skip(spawned).collect::<Vec<_>>() {
+            world.add_component(id, dont_store(), ()).unwrap();
+        }
+        if !done {
+            let progress = (spawn.steps as f32 - 1. + spawn.spawner.progress()) / spawn.steps as f32;
+            world.set(spawn.id, level_progress(), progress).unwrap();
+            break;
+        }
+
+        let LevelSpawn { id, spawner, assets, .. } = pending.pop_front().unwrap();
+        for root in spawner.roots() {
+            set_parent(world, root, Some(id)).unwrap();
+        }
+        world.add_component(id, level_assets(), assets).unwrap();
+        world.set(id, level_progress(), 1.).unwrap();
+        world.add_component(id, level_loaded(), ()).unwrap();
+        if start.elapsed() >= LEVEL_SPAWN_BUDGET {
+            break;
+        }
+    }
+}
+
+fn fail(world: &mut World, id: EntityId, error: String) {
+    log::warn!("{error}");
+    if world.exists(id) {
+        world.add_component(id, level_error(), error).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ambient_core::{name, scene::Scene};
+
+    use super::*;
+
+    fn init() {
+        ambient_ecs::init_components();
+        ambient_core::init_all_components();
+        ambient_model::init_components();
+        crate::init_components();
+    }
+
+    /// A scene of a root with `count` children
+    fn scene(count: usize) -> Arc<Scene> {
+        let mut world = World::new("scene");
+        let root = world.spawn(EntityData::new().set(name(), "root".to_string()));
+        for i in 0..count {
+            let child = world.spawn(EntityData::new().set(name(), format!("child {i}")));
+            set_parent(&mut world, child, Some(root)).unwrap();
+        }
+        Arc::new(Scene::from_world(&world, [root], None).unwrap())
+    }
+
+    /// The pending spawns once `level` has been loaded, and its scene is `scene`
+    fn pending(world: &mut World, level: LevelHandle, scene: Arc<Scene>) -> PendingSpawns {
+        world.add_component(level.root(), level_progress(), 0.5).unwrap();
+        let spawn = LevelSpawn { id: level.root(), spawner: SceneSpawner::new(scene), assets: LevelAssets::default(), steps: 2 };
+        Arc::new(Mutex::new(VecDeque::from([spawn])))
+    }
+
+    #[test]
+    fn loaded_levels_are_spawned_under_their_root() {
+        init();
+        let mut world = World::new("loaded_levels_are_spawned_under_their_root");
+        let level = LevelHandle::load(&mut world, "level.json");
+        let pending = pending(&mut world, level, scene(3));
+        spawn_pending(&mut world, &pending);
+
+        assert!(pending.lock().is_empty());
+        assert!(level.is_loaded(&world));
+        assert_eq!(level.progress(&world), 1.);
+        let roots = world.get_cloned(level.root(), children()).unwrap();
+        assert_eq!(roots.len(), 1);
+        let spawned = world.get_cloned(roots[0], children()).unwrap();
+        assert_eq!(spawned.len(), 3);
+        // The level is stored as its url only
+        assert!(!world.has_component(level.root(), dont_store()));
+        assert!(roots.iter().chain(&spawned).all(|&id| world.has_component(id, dont_store())));
+    }
+
+    #[test]
+    fn levels_unloaded_while_loading_are_not_spawned() {
+        init();
+        let mut world = World::new("levels_unloaded_while_loading_are_not_spawned");
+        let level = LevelHandle::load(&mut world, "level.json");
+        let sublevel = level.load_sublevel(&mut world, "sublevel.json");
+        let pending = pending(&mut world, sublevel, scene(3));
+
+        level.unload(&mut world);
+        let entities = world.len();
+        spawn_pending(&mut world, &pending);
+        assert!(pending.lock().is_empty());
+        assert!(!world.exists(sublevel.root()));
+        assert_eq!(world.len(), entities);
+    }
+}
@@ -15,6 +15,14 @@ use ambient_std::{
 };
 use anyhow::Context;
 use async_trait::async_trait;
+use glam::Vec3;
+
+mod instance;
+mod level;
+mod streaming;
+
+pub use instance::*;
+pub use level::*;
 
 components!("prefab", {
     @[
@@ -23,6 +31,78 @@ components!("prefab", {
         Description["Load and attach a prefab from a URL or relative path.\nWhen loaded, the components from this prefab will add to or replace the existing components for the entity."]
     ]
     prefab_from_url: String,
+    @[
+        Debuggable, Networked, Store,
+        Name["Prefab instance"],
+        Description["Instantiates the prefab scene at this URL or relative path under this entity.\nWhen the scene file changes, the instance is updated, except for the components which were changed on it."]
+    ]
+    prefab_instance: String,
+    @[
+        Debuggable, Networked, Store,
+        Name["Level from URL"],
+        Description["Loads the level scene at this URL or relative path under this entity, along with the models it uses.\nDespawn this entity recursively to unload the level; levels loaded under it are unloaded with it.\nThe entities of the level aren't stored; it's loaded again from this URL instead."]
+    ]
+    level_from_url: String,
+    @[
+        Debuggable, Networked,
+        Name["Level progress"],
+        Description["How much of the level of this entity has been loaded, from 0 to 1."]
+    ]
+    level_progress: f32,
+    @[
+        Debuggable, Networked,
+        Name["Level loaded"],
+        Description["If attached, the level of this entity has been loaded and spawned."]
+    ]
+    level_loaded: (),
+    @[
+        Debuggable, Networked,
+        Name["Level error"],
+        Description["If attached, the level of this entity failed to load, for this reason."]
+    ]
+    level_error: String,
+    @[
+        Debuggable,
+        Name["Level assets"],
+        Description["The assets used by the level of this entity, which are kept loaded until the level is unloaded."]
+    ]
+    level_assets: LevelAssets,
+    @[
+        Debuggable, Networked, Store,
+        Name["Streaming volume"],
+        Description["Loads the sublevel scene at this URL or relative path under this entity while a streaming source is in (or near) this volume, and unloads it when they leave.\nThe volume is an axis-aligned box around the position of this entity."]
+    ]
+    streaming_volume: String,
+    @[
+        Debuggable, Networked, Store,
+        Name["Streaming volume extents"],
+        Description["The half size of the box of this streaming volume. Defaults to a point."]
+    ]
+    streaming_volume_extents: Vec3,
+    @[
+        Debuggable, Networked, Store,
+        Name["Streaming volume preload distance"],
+        Description["How far from this streaming volume a streaming source has to be for its sublevel to start loading, so that it's loaded by the time they get in."]
+    ]
+    streaming_volume_preload_distance: f32,
+    @[
+        Debuggable, Networked, Store,
+        Name["Streaming volume hysteresis"],
+        Description["How much further than the preload distance all the streaming sources have to be for the sublevel of this streaming volume to be unloaded.\nThis keeps the sublevel from being loaded and unloaded repeatedly when a source moves along the edge of the volume."]
+    ]
+    streaming_volume_hysteresis: f32,
+    @[
+        Debuggable,
+        Name["Streaming volume level"],
+        Description["The root of the sublevel loaded by this streaming volume, if it's loaded."]
+    ]
+    streaming_volume_level: EntityId,
+    @[
+        Debuggable, Networked, Store,
+        Name["Streaming source"],
+        Description["If attached, the streaming volumes load their sublevels around the position of this entity, such as the body or the camera of a player."]
+    ]
+    streaming_source: (),
     @[
         Debuggable,
         Name["Spawned"],
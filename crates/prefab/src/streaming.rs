@@ -0,0 +1,131 @@
+use ambient_core::{
+    hierarchy::set_parent,
+    transform::{local_to_world, translation},
+};
+use ambient_ecs::{dont_store, query, DynSystem, EntityId, World};
+use glam::Vec3;
+
+use crate::{
+    streaming_source, streaming_volume, streaming_volume_extents, streaming_volume_hysteresis, streaming_volume_level,
+    streaming_volume_preload_distance, LevelHandle,
+};
+
+/// Loads the sublevels of the [streaming_volume]s which a [streaming_source] gets close to, and unloads them once they're
+/// all far enough away again
+pub(crate) fn streaming_volumes_system() -> DynSystem {
+    query(streaming_volume()).to_system(|q, world, qs, _| {
+        let sources = query(()).incl(streaming_source()).iter(world, None).filter_map(|(id, _)| position(world, id)).collect::<Vec<_>>();
+        for (id, url) in q.collect_cloned(world, qs) {
+            let center = match position(world, id) {
+                Some(center) => center,
+                None => continue,
+            };
+            let extents = world.get(id, streaming_volume_extents()).unwrap_or_default();
+            let preload_distance = world.get(id, streaming_volume_preload_distance()).unwrap_or_default();
+            let hysteresis = world.get(id, streaming_volume_hysteresis()).unwrap_or_default();
+            let distance = sources.iter().map(|&source| distance_to_box(source, center, extents)).fold(f32::INFINITY, f32::min);
+
+            match world.get(id, streaming_volume_level()) {
+                Err(_) if distance <= preload_distance => {
+                    let level = LevelHandle::load(world, url);
+                    // The volume loads the sublevel again when the world is loaded
+                    world.add_component(level.root(), dont_store(), ()).unwrap();
+                    set_parent(world, level.root(), Some(id)).unwrap();
+                    world.add_component(id, streaming_volume_level(), level.root()).unwrap();
+                }
+                Ok(level) if distance > preload_distance + hysteresis => {
+                    LevelHandle(level).unload(world);
+                    world.remove_component(id, streaming_volume_level()).unwrap();
+                }
+                _ => {}
+            }
+        }
+    })
+}
+
+/// The position of `id` in the world
+fn position(world: &World, id: EntityId) -> Option<Vec3> {
+    world.get(id, local_to_world()).map(|transform| transform.w_axis.truncate()).or_else(|_| world.get(id, translation())).ok()
+}
+
+/// The distance from `point` to the axis-aligned box at `center` with the half size `extents`; 0 inside of it
+fn distance_to_box(point: Vec3, center: Vec3, extents: Vec3) -> f32 {
+    ((point - center).abs() - extents).max(Vec3::ZERO).length()
+}
+
+#[cfg(test)]
+mod tests {
+    use ambient_core::hierarchy::parent;
+    use ambient_ecs::{EntityData, FrameEvent};
+    use glam::vec3;
+
+    use super::*;
+    use crate::level_from_url;
+
+    fn init() {
+        ambient_ecs::init_components();
+        ambient_core::init_all_components();
+        crate::init_components();
+    }
+
+    #[test]
+    fn sublevels_stream_in_and_out() {
+        init();
+        let mut world = World::new("sublevels_stream_in_and_out");
+        let volume = world.spawn(
+            EntityData::new()
+                .set(streaming_volume(), "sublevel.json".to_string())
+                .set(translation(), vec3(100., 0., 0.))
+                .set(streaming_volume_extents(), Vec3::splat(10.))
+                .set(streaming_volume_preload_distance(), 5.)
+                .set(streaming_volume_hysteresis(), 5.),
+        );
+        let source = world.spawn(EntityData::new().set_default(streaming_source()).set(translation(), Vec3::ZERO));
+        let mut system = streaming_volumes_system();
+        let mut move_source = |world: &mut World, x: f32| {
+            world.set(source, translation(), vec3(x, 0., 0.)).unwrap();
+            system.run(world, &FrameEvent);
+            world.get(volume, streaming_volume_level()).ok()
+        };
+
+        assert_eq!(move_source(&mut world, 0.), None);
+        // The sublevel starts loading within the preload distance
+        let level = move_source(&mut world, 86.).unwrap();
+        assert_eq!(world.get_cloned(level, level_from_url()).unwrap(), "sublevel.json");
+        assert_eq!(world.get(level, parent()).unwrap(), volume);
+        assert!(world.has_component(level, dont_store()));
+        // It stays loaded inside of the volume, and within the hysteresis
+        assert_eq!(move_source(&mut world, 100.), Some(level));
+        assert_eq!(move_source(&mut world, 81.), Some(level));
+        // And is unloaded past it
+        assert_eq!(move_source(&mut world, 79.), None);
+        assert!(!world.exists(level));
+        // Then loaded again when the source comes back
+        assert!(move_source(&mut world, 86.).is_some());
+    }
+
+    #[test]
+    fn sublevels_stay_loaded_while_any_source_is_near() {
+        init();
+        let mut world = World::new("sublevels_stay_loaded_while_any_source_is_near");
+        let volume = world.spawn(EntityData::new().set(streaming_volume(), "sublevel.json".to_string()).set(translation(), Vec3::ZERO));
+        let near = world.spawn(EntityData::new().set_default(streaming_source()).set(translation(), Vec3::ZERO));
+        world.spawn(EntityData::new().set_default(streaming_source()).set(translation(), Vec3::splat(100.)));
+        let mut system = streaming_volumes_system();
+
+        system.run(&mut world, &FrameEvent);
+        assert!(world.has_component(volume, streaming_volume_level()));
+        world.despawn(near);
+        system.run(&mut world, &FrameEvent);
+        assert!(!world.has_component(volume, streaming_volume_level()));
+    }
+
+    #[test]
+    fn distance_to_boxes() {
+        let extents = vec3(1., 2., 3.);
+        assert_eq!(distance_to_box(Vec3::ONE, Vec3::ZERO, extents), 0.);
+        assert_eq!(distance_to_box(vec3(5., 0., 0.), Vec3::ZERO, extents), 4.);
+        assert_eq!(distance_to_box(vec3(4., 6., 3.), Vec3::ZERO, extents), 5.);
+        assert_eq!(distance_to_box(vec3(10., 0., 0.), vec3(10., 0., 0.), Vec3::ZERO), 0.);
+    }
+}
@@ -22,6 +22,11 @@ pub struct Manifest {
     pub components: HashMap<IdentifierPathBuf, NamespaceOrComponent>,
     #[serde(default)]
     pub concepts: HashMap<Identifier, Concept>,
+    #[serde(default)]
+    pub budget: Budget,
+    pub discord: Option<Discord>,
+    #[serde(default)]
+    pub messages: HashMap<Identifier, Message>,
 }
 impl Manifest {
     pub fn parse(manifest: &str) -> Result<Self, toml::de::Error> {
@@ -68,6 +73,55 @@ pub struct Project {
     pub organization: Option<Identifier>,
 }
 
+/// Performance limits for the project; exceeding one is reported at runtime, but doesn't stop anything
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Budget {
+    pub max_entities: Option<usize>,
+    /// How long a single system may take to run, in milliseconds
+    pub max_system_time_ms: Option<f32>,
+    /// How long a frame may take on the client, in milliseconds
+    pub max_frame_time_ms: Option<f32>,
+}
+
+/// Shows what the players are doing on their Discord profile, and lets their friends ask to join them
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Discord {
+    /// The id of the application registered on Discord for the project
+    pub app_id: u64,
+    /// The first line of the activity; defaults to the name of the project
+    pub details: Option<String>,
+    /// The second line of the activity, e.g. the current map; scripts can change it
+    pub state: Option<String>,
+    /// The key of an art asset uploaded to the Discord application
+    pub large_image: Option<String>,
+    /// How many players fit in a party; the party size isn't shown if not set
+    pub max_party_size: Option<u32>,
+    /// Whether Discord lets the friends of the players ask to join them
+    #[serde(default = "default_joinable")]
+    pub joinable: bool,
+}
+fn default_joinable() -> bool {
+    true
+}
+
+/// A message which the scripts of the server and the clients send each other
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Message {
+    pub description: String,
+    /// Bumped when the components of the message change; the messages with another version are dropped
+    #[serde(default = "default_message_version")]
+    pub version: u32,
+    /// Whether the message is delivered reliably and in order, or may be lost like a datagram
+    #[serde(default = "default_reliable")]
+    pub reliable: bool,
+}
+fn default_message_version() -> u32 {
+    1
+}
+fn default_reliable() -> bool {
+    true
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum NamespaceOrComponent {
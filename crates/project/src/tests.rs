@@ -2,7 +2,10 @@ use std::collections::HashMap;
 
 use ambient_ecs::primitive_component_definitions;
 
-use crate::{Component, ComponentType, Concept, Identifier, IdentifierPathBuf, Manifest, Namespace, Project, Version, VersionError};
+use crate::{
+    Budget, Component, ComponentType, Concept, Discord, Identifier, IdentifierPathBuf, Manifest, Message, Namespace, Project, Version,
+    VersionError,
+};
 
 #[test]
 fn can_parse_tictactoe_toml() {
@@ -52,6 +55,9 @@ fn can_parse_tictactoe_toml() {
                     components: HashMap::from_iter([(IdentifierPathBuf::new("cell").unwrap(), toml::Value::Integer(0))])
                 }
             )]),
+            budget: Budget::default(),
+            discord: None,
+            messages: HashMap::new(),
         })
     )
 }
@@ -97,10 +103,78 @@ fn can_parse_manifest_with_namespaces() {
                 )
             ]),
             concepts: HashMap::new(),
+            budget: Budget::default(),
+            discord: None,
+            messages: HashMap::new(),
         })
     )
 }
 
+#[test]
+fn can_parse_budget() {
+    const TOML: &str = r#"
+    [project]
+    id = "stress_test"
+    version = "0.0.1"
+
+    [budget]
+    max_entities = 10000
+    max_system_time_ms = 2.5
+    "#;
+
+    assert_eq!(
+        Manifest::parse(TOML).unwrap().budget,
+        Budget { max_entities: Some(10000), max_system_time_ms: Some(2.5), max_frame_time_ms: None }
+    );
+}
+
+#[test]
+fn can_parse_discord() {
+    const TOML: &str = r#"
+    [project]
+    id = "arena"
+    version = "0.0.1"
+
+    [discord]
+    app_id = 1061234567890123456
+    state = "In the lobby"
+    max_party_size = 8
+    "#;
+
+    assert_eq!(
+        Manifest::parse(TOML).unwrap().discord,
+        Some(Discord {
+            app_id: 1061234567890123456,
+            details: None,
+            state: Some("In the lobby".to_string()),
+            large_image: None,
+            max_party_size: Some(8),
+            joinable: true
+        })
+    );
+}
+
+#[test]
+fn can_parse_messages() {
+    const TOML: &str = r#"
+    [project]
+    id = "arena"
+    version = "0.0.1"
+
+    [messages]
+    jump = { description = "The player jumps" }
+    aim = { description = "Where the player aims", version = 2, reliable = false }
+    "#;
+
+    assert_eq!(
+        Manifest::parse(TOML).unwrap().messages,
+        HashMap::from_iter([
+            (Identifier::new("jump").unwrap(), Message { description: "The player jumps".to_string(), version: 1, reliable: true }),
+            (Identifier::new("aim").unwrap(), Message { description: "Where the player aims".to_string(), version: 2, reliable: false }),
+        ])
+    );
+}
+
 #[test]
 fn can_validate_identifiers() {
     use Identifier as I;
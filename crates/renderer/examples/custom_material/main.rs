@@ -1,74 +1,18 @@
-use std::sync::Arc;
-
 use ambient_app::{App, AppBuilder};
 use ambient_core::{asset_cache, camera::active_camera, hierarchy::set_component_recursive, main_scene, mesh, transform::*};
 use ambient_ecs::EntityData;
-use ambient_gpu::{
-    gpu::GpuKey,
-    shader_module::{BindGroupDesc, ShaderModule},
-};
 use ambient_meshes::QuadMeshKey;
 use ambient_model_import::model_crate::ModelCrate;
 use ambient_renderer::{
     gpu_primitives, material,
-    materials::flat_material::{get_flat_shader, FlatMaterial},
-    primitives, renderer_shader, Material, MaterialShader, SharedMaterial, StandardShaderKey, MATERIAL_BIND_GROUP,
-};
-use ambient_std::{
-    asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt},
-    asset_url::AbsAssetUrl,
-    cb, friendly_id,
-    math::SphericalCoords,
+    materials::{
+        custom_material::{CustomMaterial, CustomMaterialConfig, CustomMaterialParam},
+        flat_material::{get_flat_shader, FlatMaterial},
+    },
+    primitives, renderer_shader, SharedMaterial,
 };
+use ambient_std::{asset_cache::SyncAssetKeyExt, asset_url::AbsAssetUrl, cb, math::SphericalCoords};
 use glam::*;
-use wgpu::BindGroup;
-
-#[derive(Clone, Debug)]
-pub struct CustomMaterialShaderKey;
-impl SyncAssetKey<Arc<MaterialShader>> for CustomMaterialShaderKey {
-    fn load(&self, _assets: AssetCache) -> Arc<MaterialShader> {
-        Arc::new(MaterialShader {
-            id: "custom_material_shader".to_string(),
-            shader: ShaderModule::new(
-                "CustomMaterial",
-                include_str!("material.wgsl"),
-                vec![BindGroupDesc { entries: vec![], label: MATERIAL_BIND_GROUP.into() }.into()],
-            ),
-        })
-    }
-}
-
-pub struct CustomMaterial {
-    id: String,
-    bind_group: wgpu::BindGroup,
-}
-impl CustomMaterial {
-    pub fn new(assets: AssetCache) -> Self {
-        let gpu = GpuKey.get(&assets);
-        let layout = CustomMaterialShaderKey.get(&assets).shader.first_layout(&assets);
-        Self {
-            id: friendly_id(),
-            bind_group: gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &layout,
-                entries: &[],
-                label: Some("CustomMaterial.bind_group"),
-            }),
-        }
-    }
-}
-impl std::fmt::Debug for CustomMaterial {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("CustomMaterial").field("id", &self.id).finish()
-    }
-}
-impl Material for CustomMaterial {
-    fn bind(&self) -> &BindGroup {
-        &self.bind_group
-    }
-    fn id(&self) -> &str {
-        &self.id
-    }
-}
 
 async fn init(app: &mut App) {
     let world = &mut app.world;
@@ -76,16 +20,26 @@ async fn init(app: &mut App) {
 
     let model = ModelCrate::local_import(&assets, &AbsAssetUrl::parse("assets/Soldier.glb").unwrap(), true, false).await.unwrap();
     let entity = model.spawn(world, &Default::default());
-    set_component_recursive(
-        world,
-        entity,
-        renderer_shader(),
-        cb(|assets, config| {
-            StandardShaderKey { material_shader: CustomMaterialShaderKey.get(assets), lit: true, shadow_cascades: config.shadow_cascades }
-                .get(assets)
-        }),
-    );
-    set_component_recursive(world, entity, material(), SharedMaterial::new(CustomMaterial::new(assets.clone())));
+    let custom = CustomMaterial::new(
+        assets.clone(),
+        CustomMaterialConfig {
+            name: "Custom".to_string(),
+            fragment: include_str!("material.wgsl").to_string(),
+            vertex: Some(include_str!("vertex.wgsl").to_string()),
+            lit: true,
+            params: vec![
+                ("tint".to_string(), CustomMaterialParam::Vec3(vec3(1., 0.5, 0.5))),
+                ("sway".to_string(), CustomMaterialParam::F32(0.1)),
+            ],
+            textures: vec![],
+            transparent: None,
+            double_sided: None,
+            sampling: None,
+        },
+    )
+    .unwrap();
+    set_component_recursive(world, entity, renderer_shader(), custom.renderer_shader_producer());
+    set_component_recursive(world, entity, material(), SharedMaterial::new(custom));
     // world.add_component(entity, rotation(), glam::Quat::from_rotation_x(std::f32::consts::PI / 2.)).unwrap();
     // world.set(entity, animation_controller(), AnimationController::looping("Walk")).unwrap();
 
@@ -1,9 +1,8 @@
-
 fn get_material(in: MaterialInput) -> MaterialOutput {
     var out: MaterialOutput;
-    out.opacity = in.world_position.x;
-    out.alpha_cutoff = -0.1;
-    out.base_color = in.normal;
+    out.opacity = 1.;
+    out.alpha_cutoff = 0.;
+    out.base_color = material_params.tint * (in.normal * 0.5 + 0.5);
     out.emissive_factor = vec3<f32>(0., 0., 0.);
     out.shading = 1.;
     out.normal = in.normal;
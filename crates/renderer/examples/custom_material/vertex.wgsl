@@ -0,0 +1,5 @@
+fn get_material_vertex(out: VertexOutput) -> VertexOutput {
+    var res = out;
+    res.world_position.x += sin(global_params.time * 2.) * material_params.sway * out.local_position.z;
+    return res;
+}
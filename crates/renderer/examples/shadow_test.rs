@@ -64,9 +64,15 @@ async fn init(app: &mut App) {
         .set_default(cast_shadows())
         .spawn_static(world);
 
-    for i in 0..5 {
-        let conf = RendererConfig::default();
-        let shadow_cam = demo_cam.create_snapping_shadow_camera(sun_direction, i, 5, conf.shadow_map_resolution);
+    let conf = RendererConfig::default();
+    for i in 0..conf.shadow_cascades {
+        let shadow_cam = demo_cam.create_snapping_shadow_camera(
+            sun_direction,
+            i,
+            conf.shadow_cascades,
+            &conf.shadow_cascade_splits,
+            conf.shadow_map_resolution,
+        );
         Cube.el()
             .remove(translation())
             .remove(scale())
@@ -77,7 +83,11 @@ async fn init(app: &mut App) {
             .set_default(cast_shadows())
             .spawn_static(world);
 
-        for point in &demo_cam.to_shadows_far_bound().world_space_frustum_points_for_shadow_cascade(i, 5) {
+        for point in &demo_cam.to_shadows_far_bound().world_space_frustum_points_for_shadow_cascade(
+            i,
+            conf.shadow_cascades,
+            &conf.shadow_cascade_splits,
+        ) {
             Cube.el().set(translation(), *point).set(color(), vec4(0., 0., 0., 1.)).set_default(cast_shadows()).spawn_static(world);
         }
     }
@@ -34,9 +34,12 @@ async fn init(app: &mut App) {
             base_color: texture,
             normalmap: DefaultNormalMapViewKey.get(&assets),
             metallic_roughness: PixelTextureViewKey::white().get(&assets),
+            emissive: None,
             transparent: None,
             double_sided: None,
             depth_write_enabled: None,
+            sampling: None,
+            detail: None,
         },
     ));
 
@@ -0,0 +1,60 @@
+use ambient_app::{get_time_since_app_start, App, AppBuilder};
+use ambient_core::{camera::active_camera, main_scene, transform::*};
+use ambient_ecs::{query, FnSystem};
+use ambient_element::ElementComponentExt;
+use ambient_primitives::{Cube, UVSphere};
+use ambient_renderer::{
+    color,
+    trails::{trail_axis, trail_color, trail_end_width, trail_lifetime, trail_width},
+};
+use ambient_std::math::SphericalCoords;
+use glam::*;
+
+async fn init(app: &mut App) {
+    let world = &mut app.world;
+
+    Cube.el()
+        .set(scale(), vec3(20., 20., 1.))
+        .set(translation(), vec3(0., 0., -1.))
+        .set(color(), vec4(0.5, 0.5, 0.5, 1.))
+        .spawn_static(world);
+
+    // A camera-facing trail, narrowing as it fades
+    UVSphere::default()
+        .el()
+        .set(scale(), Vec3::splat(0.2))
+        .set(trail_width(), 0.3)
+        .set(trail_end_width(), 0.)
+        .set(trail_lifetime(), 1.)
+        .set(trail_color(), vec4(1., 0.5, 0., 1.))
+        .spawn_static(world);
+    // A trail along the local Z axis of the entity, like a sword slash
+    Cube.el()
+        .set(scale(), vec3(0.1, 0.1, 1.))
+        .set(trail_width(), 1.)
+        .set(trail_lifetime(), 0.5)
+        .set(trail_axis(), Vec3::Z)
+        .set(trail_color(), vec4(0.3, 0.6, 1., 0.8))
+        .spawn_static(world);
+
+    ambient_cameras::spherical::new(vec3(0., 0., 0.), SphericalCoords::new(std::f32::consts::PI / 4., std::f32::consts::PI / 4., 10.))
+        .set(active_camera(), 0.)
+        .set(main_scene(), ())
+        .spawn(world);
+
+    app.systems.add(Box::new(FnSystem::new(|world, _| {
+        let time = get_time_since_app_start(world).as_secs_f32();
+        let ids = query(trail_lifetime()).iter(world, None).map(|(id, _)| id).collect::<Vec<_>>();
+        for (i, id) in ids.into_iter().enumerate() {
+            let angle = time * (1. + i as f32);
+            let radius = 2. + 2. * i as f32;
+            world.set(id, translation(), vec3(angle.cos() * radius, angle.sin() * radius, 0.5)).unwrap();
+            world.set(id, rotation(), Quat::from_rotation_z(angle)).unwrap();
+        }
+    })));
+}
+
+fn main() {
+    env_logger::init();
+    AppBuilder::simple().block_on(init);
+}
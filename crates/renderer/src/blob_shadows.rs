@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use ambient_core::transform::local_to_world;
+use ambient_ecs::{components, query, Component, Debuggable, Description, Name, Networked, Store, World};
+use ambient_gpu::{
+    gpu::{Gpu, GpuKey},
+    shader_module::{BindGroupDesc, GraphicsPipeline, GraphicsPipelineInfo, Shader, ShaderModule},
+    typed_buffer::TypedBuffer,
+};
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKeyExt},
+    include_file,
+};
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+use wgpu::{BindGroupLayoutEntry, BufferUsages, ShaderStages};
+
+use crate::{cast_shadows, get_overlay_module, no_cast_shadows, RendererConfig, RendererTarget, GLOBALS_BIND_GROUP};
+
+components!("rendering", {
+    @[
+        Debuggable, Networked, Store,
+        Name["Blob shadow"],
+        Description["If attached, a soft shadow is projected on the surfaces below this entity, as if cast by a sphere of this radius around it.\nThis is a cheap fallback which is only drawn when the entity isn't in a shadow map, i.e. when shadows are disabled or the entity doesn't `cast_shadows`."]
+    ]
+    blob_shadow: f32,
+    @[
+        Debuggable, Networked, Store,
+        Name["Blob shadow length"],
+        Description["Stretches the blob shadow of this entity into a capsule of this length along its local Z axis, which suits characters better. Defaults to 0."]
+    ]
+    blob_shadow_length: f32,
+    @[
+        Debuggable, Networked, Store,
+        Name["Blob shadow opacity"],
+        Description["How dark the blob shadow of this entity is, from 0 to 1. Defaults to 0.6."]
+    ]
+    blob_shadow_opacity: f32,
+});
+
+const DEFAULT_OPACITY: f32 = 0.6;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+struct BlobShadow {
+    /// The start of the capsule casting the shadow, and its radius in w
+    start: Vec4,
+    /// The end of the capsule casting the shadow, and the opacity of the shadow in w
+    end: Vec4,
+}
+
+/// Renders the blob shadows of all the entities of a scene in a single draw call. Each shadow is drawn as a box around
+/// the space it can fall in, which darkens the opaque surfaces inside of it.
+pub struct BlobShadowRenderer {
+    gpu: Arc<Gpu>,
+    scene: Component<()>,
+    pipeline: GraphicsPipeline,
+    buffer: TypedBuffer<BlobShadow>,
+    shadows: Vec<BlobShadow>,
+}
+impl BlobShadowRenderer {
+    pub fn new(assets: &AssetCache, config: &RendererConfig) -> Self {
+        let gpu = GpuKey.get(assets);
+        let layout = BindGroupDesc {
+            entries: vec![
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+            label: "BLOB_SHADOWS_BIND_GROUP".into(),
+        };
+        let shader = Shader::from_modules(
+            assets,
+            "BlobShadows",
+            [
+                &get_overlay_module(assets, config.shadow_cascades),
+                &ShaderModule::new("BlobShadows", include_file!("blob_shadows.wgsl"), vec![layout.into()]),
+            ],
+        );
+        let pipeline = shader.to_pipeline(
+            &gpu,
+            GraphicsPipelineInfo {
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.color_format(&gpu),
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                ..Default::default()
+            },
+        );
+        let buffer = TypedBuffer::new(
+            gpu.clone(),
+            "BlobShadowRenderer.buffer",
+            128,
+            0,
+            BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+        );
+        Self { gpu, scene: config.scene, pipeline, buffer, shadows: Vec::new() }
+    }
+
+    /// `shadow_maps` is whether the renderer has shadow maps; if so, the entities which `cast_shadows` are skipped
+    #[profiling::function]
+    pub fn render(
+        &mut self,
+        world: &World,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &RendererTarget,
+        globals_bind_group: &wgpu::BindGroup,
+        shadow_maps: bool,
+    ) {
+        self.shadows.clear();
+        for (id, (&radius, local_to_world)) in query((blob_shadow(), local_to_world())).incl(self.scene).iter(world, None) {
+            if shadow_maps && world.has_component(id, cast_shadows()) && !world.has_component(id, no_cast_shadows()) {
+                continue;
+            }
+            let start = local_to_world.w_axis.truncate();
+            let length = world.get(id, blob_shadow_length()).unwrap_or(0.);
+            let end = start + local_to_world.transform_vector3(Vec3::Z).normalize_or_zero() * length;
+            let opacity = world.get(id, blob_shadow_opacity()).unwrap_or(DEFAULT_OPACITY);
+            self.shadows.push(BlobShadow { start: start.extend(radius), end: end.extend(opacity) });
+        }
+        if self.shadows.is_empty() {
+            return;
+        }
+
+        self.buffer.fill(&self.shadows, |_| {});
+        let bind_group = self.gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("BlobShadowRenderer.bind_group"),
+            layout: self.pipeline.shader().get_bind_group_layout_by_name("BLOB_SHADOWS_BIND_GROUP").unwrap(),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: self.buffer.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(target.depth()) },
+            ],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Blob shadows"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target.color(),
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: true },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(self.pipeline.pipeline());
+        self.pipeline.bind(&mut render_pass, GLOBALS_BIND_GROUP, globals_bind_group);
+        self.pipeline.bind(&mut render_pass, "BLOB_SHADOWS_BIND_GROUP", &bind_group);
+        render_pass.draw(0..36, 0..self.shadows.len() as u32);
+    }
+}
+
+impl std::fmt::Debug for BlobShadowRenderer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlobShadowRenderer").field("shadows", &self.shadows.len()).finish()
+    }
+}
@@ -0,0 +1,102 @@
+struct BlobShadow {
+  start: vec4<f32>,
+  end: vec4<f32>,
+};
+
+struct BlobShadows {
+  shadows: array<BlobShadow>,
+};
+
+@group(#BLOB_SHADOWS_BIND_GROUP)
+@binding(0)
+var<storage> blob_shadows: BlobShadows;
+
+@group(#BLOB_SHADOWS_BIND_GROUP)
+@binding(1)
+var depth_buffer: texture_depth_2d;
+
+// How far below the caster the shadow reaches, in radii
+let MAX_GAP: f32 = 4.;
+// How much the shadow widens per radius of gap between the caster and the surface
+let SPREAD: f32 = 0.5;
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) world_position: vec3<f32>,
+  @location(1) face_normal: vec3<f32>,
+  @location(2) inst: u32,
+};
+
+@vertex
+fn vs_main(@builtin(instance_index) instance_index: u32, @builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+  let shadow = blob_shadows.shadows[instance_index];
+  let radius = shadow.start.w;
+  let margin = radius * (1. + MAX_GAP * SPREAD);
+  let box_min = min(shadow.start.xyz, shadow.end.xyz) - vec3<f32>(margin, margin, radius * (1. + MAX_GAP));
+  let box_max = max(shadow.start.xyz, shadow.end.xyz) + vec3<f32>(margin, margin, radius);
+
+  // Six faces of two triangles each; the corner of the face is in uv
+  let face = vertex_index / 6u;
+  let axis = face / 2u;
+  let side = f32(face % 2u);
+  var uv: vec2<f32>;
+  switch (vertex_index % 6u) {
+    case 0u: { uv = vec2<f32>(0., 0.); }
+    case 1u, 4u: { uv = vec2<f32>(1., 0.); }
+    case 2u, 3u: { uv = vec2<f32>(0., 1.); }
+    default: { uv = vec2<f32>(1., 1.); }
+  }
+  var corner: vec3<f32>;
+  var normal = vec3<f32>(0., 0., 0.);
+  if (axis == 0u) {
+    corner = vec3<f32>(side, uv.x, uv.y);
+    normal.x = side * 2. - 1.;
+  } else if (axis == 1u) {
+    corner = vec3<f32>(uv.x, side, uv.y);
+    normal.y = side * 2. - 1.;
+  } else {
+    corner = vec3<f32>(uv.x, uv.y, side);
+    normal.z = side * 2. - 1.;
+  }
+  let world_position = mix(box_min, box_max, corner);
+  return VertexOutput(global_params.projection_view * vec4<f32>(world_position, 1.), world_position, normal, instance_index);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  // Only the faces pointing away from the camera are shaded, so that every pixel is shaded once, even from inside the box
+  if (dot(in.face_normal, in.world_position - global_params.camera_position.xyz) < 0.) {
+    discard;
+  }
+  let shadow = blob_shadows.shadows[in.inst];
+  let start = shadow.start.xyz;
+  let end = shadow.end.xyz;
+  let radius = shadow.start.w;
+
+  let depth = textureLoad(depth_buffer, vec2<i32>(in.position.xy), 0);
+  // Nothing was drawn there (this is reverse-z)
+  if (depth <= 0.) {
+    discard;
+  }
+  let screen_size = vec2<f32>(textureDimensions(depth_buffer));
+  let screen_ndc = screen_pixel_to_ndc(in.position.xy, screen_size);
+  let surface = project_point(global_params.inv_projection_view, vec3<f32>(screen_ndc.xy, depth));
+
+  // The point of the capsule closest to the surface when seen from above; the lowest end if the capsule is upright
+  let dir = end.xy - start.xy;
+  let dir_len2 = dot(dir, dir);
+  var t = select(0., 1., end.z < start.z);
+  if (dir_len2 > 0.0001) {
+    t = clamp(dot(surface.xy - start.xy, dir) / dir_len2, 0., 1.);
+  }
+  let center = mix(start, end, t);
+
+  let gap = (center.z - surface.z) / radius - 1.;
+  if (gap < -1. || gap > MAX_GAP) {
+    discard;
+  }
+  let spread = 1. + max(gap, 0.) * SPREAD;
+  let distance = length(surface.xy - center.xy) / radius;
+  let strength = (1. - smoothstep(0.5 * spread, spread, distance)) * (1. - max(gap, 0.) / MAX_GAP);
+  return vec4<f32>(0., 0., 0., shadow.end.w * strength);
+}
@@ -17,7 +17,10 @@ use glam::{uvec2, UVec2, UVec3};
 use parking_lot::Mutex;
 use wgpu::{BindGroupLayout, BindGroupLayoutEntry, BindingType, BufferBindingType, ShaderStages};
 
-use super::{get_defs_module, get_resources_module, DrawIndexedIndirect, PrimitiveIndex, RESOURCES_BIND_GROUP};
+use super::{
+    get_defs_module, get_resources_module, impostors::FIRST_IMPOSTOR_CAMERA, DrawIndexedIndirect, PrimitiveIndex, FIRST_PASS_CAMERA,
+    RESOURCES_BIND_GROUP,
+};
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
@@ -174,6 +177,8 @@ impl RendererCollect {
                         layout_desc.into(),
                         ShaderModuleIdentifier::constant("COLLECT_WORKGROUP_SIZE", COLLECT_WORKGROUP_SIZE),
                         ShaderModuleIdentifier::constant("COLLECT_CHUNK_SIZE", COLLECT_CHUNK_SIZE),
+                        ShaderModuleIdentifier::constant("FIRST_IMPOSTOR_CAMERA", FIRST_IMPOSTOR_CAMERA),
+                        ShaderModuleIdentifier::constant("FIRST_PASS_CAMERA", FIRST_PASS_CAMERA),
                     ],
                 ),
             ],
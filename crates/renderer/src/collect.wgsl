@@ -56,7 +56,9 @@ fn is_visible(entity_loc: vec2<u32>, primitive_lod: u32) -> bool {
         var cameras = get_entity_renderer_cameras_visible(visibility_from);
         return bool(cameras[params.camera]);
     } else {
-        return true;
+        // The captures of the impostors only have the entities being captured in them, and the forward pass draws
+        // these in its first pass
+        return params.camera < #FIRST_IMPOSTOR_CAMERAu || params.camera == #FIRST_PASS_CAMERAu;
     }
 }
 
@@ -1,6 +1,7 @@
 use std::{collections::HashMap, f32::INFINITY};
 
 use ambient_core::{
+    asset_cache,
     bounding::world_bounding_sphere,
     camera::{shadow_cameras_from_world, Camera},
     gpu_components,
@@ -10,6 +11,7 @@ use ambient_ecs::{ArchetypeFilter, World};
 use ambient_gpu::{
     gpu::GpuKey,
     shader_module::{BindGroupDesc, ShaderModule, ShaderModuleIdentifier},
+    texture::TextureView,
     typed_buffer::TypedBuffer,
 };
 use ambient_std::{
@@ -17,16 +19,30 @@ use ambient_std::{
     include_file,
     shapes::Plane,
 };
-use glam::{Mat4, UVec3, Vec2, Vec3, Vec3Swizzles, Vec4};
+use glam::{Mat4, UVec2, UVec3, Vec2, Vec3, Vec3Swizzles, Vec4};
 use wgpu::{BindGroupLayoutEntry, BindingType, BufferBindingType, ShaderStages};
 
-use crate::{get_sun_light_direction, RendererConfig};
+use crate::{
+    get_sun_light_direction,
+    impostors::{FIRST_IMPOSTOR_CAMERA, MAX_IMPOSTOR_CAPTURES},
+    skinning::SkinsBufferKey,
+    RendererConfig,
+};
 
 gpu_components! {
     world_bounding_sphere() => renderer_cameras_visible: GpuComponentFormat::U32Array20,
 }
 
 const CULLING_BIND_GROUP: &str = "LODDING_BIND_GROUP";
+const OCCLUSION_BIND_GROUP: &str = "OCCLUSION_BIND_GROUP";
+
+/// The forward pass first draws the entities which were visible to the main camera last frame, and then the ones which
+/// the occlusion culling finds aren't hidden behind those after all (two-pass occlusion culling); each pass has a camera
+/// after the ones of the impostor captures
+pub(crate) const FIRST_PASS_CAMERA: u32 = FIRST_IMPOSTOR_CAMERA + MAX_IMPOSTOR_CAPTURES;
+pub(crate) const SECOND_PASS_CAMERA: u32 = FIRST_PASS_CAMERA + 1;
+/// Not a camera, but whether the entity was in view and not occluded at the end of the last frame
+const VISIBLE_LAST_FRAME: u32 = SECOND_PASS_CAMERA + 1;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
@@ -65,13 +81,27 @@ struct CullingParams {
     pub main_camera: CullCamera,
     pub shadow_cameras: [CullCamera; MAX_SHADOW_CASCADES as usize],
     pub lod_cutoff_scaling: f32,
-    pub _padding: UVec3,
+    pub occlusion_culling: u32,
+    pub _padding: UVec2,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Default, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct OcclusionParams {
+    pub projection_view: Mat4,
+}
+
+/// Tests what the main camera sees against a [crate::depth_pyramid::DepthPyramid] of the first forward pass
+struct OcclusionCulling {
+    updater: GpuWorldUpdater,
+    params: TypedBuffer<OcclusionParams>,
 }
 
 pub struct Culling {
     config: RendererConfig,
     updater: GpuWorldUpdater,
     params: TypedBuffer<CullingParams>,
+    occlusion: Option<OcclusionCulling>,
 }
 
 impl Culling {
@@ -82,15 +112,30 @@ impl Culling {
             vec![
                 ShaderModuleIdentifier::bind_group(BindGroupDesc {
                     label: CULLING_BIND_GROUP.into(),
-                    entries: vec![BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::COMPUTE,
-                        ty: BindingType::Buffer { ty: BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
-                        count: None,
-                    }],
+                    entries: vec![
+                        BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer { ty: BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
                 }),
                 ShaderModuleIdentifier::constant("SHADOW_CASCADES", config.shadow_cascades),
                 ShaderModuleIdentifier::constant("MAX_SHADOW_CASCADES", MAX_SHADOW_CASCADES),
+                ShaderModuleIdentifier::constant("FIRST_IMPOSTOR_CAMERA", FIRST_IMPOSTOR_CAMERA),
+                ShaderModuleIdentifier::constant("FIRST_PASS_CAMERA", FIRST_PASS_CAMERA),
+                ShaderModuleIdentifier::constant("VISIBLE_LAST_FRAME", VISIBLE_LAST_FRAME),
             ],
         );
 
@@ -109,6 +154,7 @@ impl Culling {
                 1,
                 wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::UNIFORM,
             ),
+            occlusion: if config.occlusion_culling { Some(OcclusionCulling::new(assets, &config)) } else { None },
             config,
         }
     }
@@ -127,12 +173,17 @@ impl Culling {
             return;
         };
 
-        let mut params =
-            CullingParams { lod_cutoff_scaling: self.config.lod_cutoff_scaling, main_camera: main_camera.into(), ..Default::default() };
+        let mut params = CullingParams {
+            lod_cutoff_scaling: self.config.lod_cutoff_scaling,
+            occlusion_culling: self.config.occlusion_culling as u32,
+            main_camera: main_camera.into(),
+            ..Default::default()
+        };
         if self.config.shadow_cascades > 0 {
             let shadow_cameras = shadow_cameras_from_world(
                 world,
                 self.config.shadow_cascades,
+                &self.config.shadow_cascade_splits,
                 self.config.shadow_map_resolution,
                 get_sun_light_direction(world, self.config.scene),
                 self.config.scene,
@@ -145,13 +196,104 @@ impl Culling {
 
         self.params.fill(&[params], |_| {});
 
+        let skins_h = SkinsBufferKey.get(world.resource(asset_cache()));
+        let skins = skins_h.lock();
         let bind_group = self.updater.gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: self.updater.pipeline.shader().get_bind_group_layout_by_name(CULLING_BIND_GROUP).unwrap(),
-            entries: &[wgpu::BindGroupEntry { binding: 0, resource: self.params.buffer().as_entire_binding() }],
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: self.params.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: skins.joint_spheres.buffer().as_entire_binding() },
+            ],
         });
+        drop(skins);
         let mut binding_context = binding_context.clone();
         binding_context.insert(CULLING_BIND_GROUP.to_string(), &bind_group);
         self.updater.run_with_encoder(encoder, world, binding_context);
     }
+
+    /// Decides what the second forward pass draws, from the `depth_pyramid` of the first one
+    #[profiling::function]
+    pub fn run_occlusion<'a>(
+        &mut self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        world: &World,
+        depth_pyramid: &TextureView,
+        binding_context: &HashMap<String, &'a wgpu::BindGroup>,
+    ) {
+        let occlusion = if let Some(occlusion) = &mut self.occlusion {
+            occlusion
+        } else {
+            return;
+        };
+        let main_camera = if let Some(camera) = Camera::get_active(world, self.config.scene) {
+            camera
+        } else {
+            return;
+        };
+        occlusion.params.fill(&[OcclusionParams { projection_view: main_camera.projection_view() }], |_| {});
+
+        let bind_group = occlusion.updater.gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: occlusion.updater.pipeline.shader().get_bind_group_layout_by_name(OCCLUSION_BIND_GROUP).unwrap(),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: occlusion.params.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(depth_pyramid) },
+            ],
+        });
+        let mut binding_context = binding_context.clone();
+        binding_context.insert(OCCLUSION_BIND_GROUP.to_string(), &bind_group);
+        occlusion.updater.run_with_encoder(encoder, world, binding_context);
+    }
+}
+
+impl OcclusionCulling {
+    fn new(assets: &AssetCache, config: &RendererConfig) -> Self {
+        let module = ShaderModule::new(
+            "OcclusionCulling",
+            include_file!("occlusion_culling.wgsl"),
+            vec![
+                ShaderModuleIdentifier::bind_group(BindGroupDesc {
+                    label: OCCLUSION_BIND_GROUP.into(),
+                    entries: vec![
+                        BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer { ty: BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                    ],
+                }),
+                ShaderModuleIdentifier::constant("FIRST_PASS_CAMERA", FIRST_PASS_CAMERA),
+                ShaderModuleIdentifier::constant("SECOND_PASS_CAMERA", SECOND_PASS_CAMERA),
+                ShaderModuleIdentifier::constant("VISIBLE_LAST_FRAME", VISIBLE_LAST_FRAME),
+            ],
+        );
+        Self {
+            updater: GpuWorldUpdater::new(
+                assets.clone(),
+                "OcclusionCulling".to_string(),
+                ArchetypeFilter::new().incl(world_bounding_sphere()).incl(config.scene),
+                vec![module],
+                "update(entity_loc);",
+            ),
+            params: TypedBuffer::new(
+                GpuKey.get(assets),
+                "OcclusionCulling.params",
+                1,
+                1,
+                wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::UNIFORM,
+            ),
+        }
+    }
 }
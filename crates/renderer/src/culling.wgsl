@@ -23,12 +23,21 @@ struct Params {
     main_camera: Camera,
     shadow_cameras: array<Camera, #MAX_SHADOW_CASCADES>,
     lod_cutoff_scaling: f32,
+    occlusion_culling: u32,
 };
 
 @group(#LODDING_BIND_GROUP)
 @binding(0)
 var<uniform> params: Params;
 
+struct JointSpheres {
+    data: array<vec4<f32>>,
+};
+
+@group(#LODDING_BIND_GROUP)
+@binding(1)
+var<storage> joint_spheres: JointSpheres;
+
 struct CameraCullResult {
     fully_contained: bool,
     inside: bool,
@@ -60,6 +69,46 @@ fn cull_camera(camera: Camera, bounding_sphere: vec4<f32>) -> CameraCullResult {
     return res;
 }
 
+// Whether the spheres of the joints of a skinned mesh are all on the outer side of the same plane of the frustum. Its
+// vertices are blended between their joints, so they're within the convex hull of the spheres, which is then out of view
+fn joints_outside_camera(camera: Camera, entity_loc: vec2<u32>) -> bool {
+    let offset = get_entity_skin(entity_loc);
+    let count = get_entity_joint_sphere_count(entity_loc);
+    var any_joint = false;
+    var top = true;
+    var bottom = true;
+    var right = true;
+    var left = true;
+    var near = true;
+    var far = true;
+    for (var i = 0u; i < count; i = i + 1u) {
+        let sphere = joint_spheres.data[offset + i];
+        let radius = sphere.w;
+        if (radius < 0.) {
+            continue;
+        }
+        any_joint = true;
+        let center = (camera.view * vec4<f32>(sphere.xyz, 1.)).xyz;
+        top = top && plane_distance(camera.frustum_top, center) > radius;
+        bottom = bottom && plane_distance(camera.frustum_top, vec3<f32>(center.x, -center.y, center.z)) > radius;
+        right = right && plane_distance(camera.frustum_right, center) > radius;
+        left = left && plane_distance(camera.frustum_right, vec3<f32>(-center.x, center.y, center.z)) > radius;
+        near = near && center.z + radius <= camera.frustum_near;
+        far = far && center.z - radius >= camera.frustum_far;
+    }
+    return any_joint && (top || bottom || right || left || near || far);
+}
+
+// Culls the bounding sphere of the entity, and then the spheres of its joints if it's a skinned mesh, whose bounding
+// sphere is loose once it's posed
+fn cull_entity(camera: Camera, entity_loc: vec2<u32>, bounding_sphere: vec4<f32>) -> CameraCullResult {
+    var res = cull_camera(camera, bounding_sphere);
+    if (res.inside && !res.fully_contained && has_entity_skin(entity_loc) && has_entity_joint_sphere_count(entity_loc)) {
+        res.inside = !joints_outside_camera(camera, entity_loc);
+    }
+    return res;
+}
+
 fn get_lod(entity_loc: vec2<u32>) -> u32 {
 
     let bounding_sphere = get_entity_world_bounding_sphere(entity_loc);
@@ -82,9 +131,11 @@ fn update(entity_loc: vec2<u32>) {
     if (has_entity_gpu_lod(entity_loc)) {
         set_entity_gpu_lod(entity_loc, get_lod(entity_loc));
     }
+    var previous = get_entity_renderer_cameras_visible(entity_loc);
+    let visible_last_frame = bool(previous[#VISIBLE_LAST_FRAMEu]);
     var cameras: array<u32, 20>;
     let bounding_sphere = get_entity_world_bounding_sphere(entity_loc);
-    cameras[0] = u32(cull_camera(params.main_camera, bounding_sphere).inside);
+    cameras[0] = u32(cull_entity(params.main_camera, entity_loc, bounding_sphere).inside);
     for (var i=0; i < #SHADOW_CASCADES; i = i + 1) {
         cameras[i + 1] = u32(false);
     }
@@ -95,7 +146,7 @@ fn update(entity_loc: vec2<u32>) {
         if (pixel_size < 0.01) {
             break;
         }
-        let res = cull_camera(params.shadow_cameras[i], bounding_sphere);
+        let res = cull_entity(params.shadow_cameras[i], entity_loc, bounding_sphere);
         if (res.inside) {
             cameras[i + 1] = u32(true);
         }
@@ -103,5 +154,19 @@ fn update(entity_loc: vec2<u32>) {
             break;
         }
     }
+    if (has_entity_impostor_state(entity_loc)) {
+        // Drawn as a billboard instead by the main camera, and by the camera of its capture when it's being captured
+        let impostor_state = get_entity_impostor_state(entity_loc);
+        if (impostor_state != 0u) {
+            cameras[0] = u32(false);
+        }
+        if (impostor_state > 1u) {
+            cameras[#FIRST_IMPOSTOR_CAMERAu + impostor_state - 2u] = u32(true);
+        }
+    }
+    // The first pass of the forward renderer draws what was visible last frame, and the occlusion culling then decides
+    // what the second one draws; without occlusion culling, the first pass draws everything in view
+    cameras[#FIRST_PASS_CAMERAu] = u32(bool(cameras[0]) && (params.occlusion_culling == 0u || visible_last_frame));
+    cameras[#VISIBLE_LAST_FRAMEu] = u32(visible_last_frame);
     set_entity_renderer_cameras_visible(entity_loc, cameras);
 }
@@ -0,0 +1,152 @@
+use std::{num::NonZeroU32, sync::Arc};
+
+use ambient_gpu::{
+    gpu::{Gpu, GpuKey},
+    shader_module::{BindGroupDesc, GraphicsPipeline, GraphicsPipelineInfo, Shader, ShaderModule},
+    texture::{Texture, TextureView},
+};
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKeyExt},
+    include_file,
+};
+use glam::{uvec2, UVec2};
+use wgpu::{BindGroupLayoutEntry, BindingType, PrimitiveTopology, ShaderStages};
+
+const DEPTH_PYRAMID_BIND_GROUP: &str = "DEPTH_PYRAMID_BIND_GROUP";
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+/// The mips of the depth of a frame, where each texel holds the farthest depth of the texels it covers in the previous
+/// one (a hierarchical-Z buffer); anything nearer than that is in front of everything in the area, so an object whose
+/// nearest depth is farther away than it is occluded
+pub(crate) struct DepthPyramid {
+    gpu: Arc<Gpu>,
+    /// The size of the depth the pyramid was built from
+    depth_size: UVec2,
+    /// All the mips, for the culling to sample; there are none before the pyramid is first built
+    pub view: Option<TextureView>,
+    mips: Vec<TextureView>,
+    reduce_depth: GraphicsPipeline,
+    reduce: GraphicsPipeline,
+    layout: Arc<wgpu::BindGroupLayout>,
+    /// Stands in for the source mip of the first one, which is reduced from the depth instead
+    dummy_source: TextureView,
+}
+impl DepthPyramid {
+    pub fn new(assets: &AssetCache) -> Self {
+        let gpu = GpuKey.get(assets);
+
+        fn texture_entry(binding: u32, sample_type: wgpu::TextureSampleType) -> BindGroupLayoutEntry {
+            BindGroupLayoutEntry {
+                binding,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture { sample_type, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false },
+                count: None,
+            }
+        }
+        let layout = BindGroupDesc {
+            entries: vec![
+                texture_entry(0, wgpu::TextureSampleType::Depth),
+                texture_entry(1, wgpu::TextureSampleType::Float { filterable: false }),
+            ],
+            label: DEPTH_PYRAMID_BIND_GROUP.into(),
+        };
+        let shader = Shader::from_modules(
+            assets,
+            "DepthPyramid",
+            [&ShaderModule::new("DepthPyramid", include_file!("depth_pyramid.wgsl"), vec![layout.into()])],
+        );
+        let pipeline = |fs_main: &str| {
+            shader.to_pipeline(
+                &gpu,
+                GraphicsPipelineInfo {
+                    fs_main,
+                    targets: &[Some(FORMAT.into())],
+                    topology: PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let dummy_source = Self::create_texture(gpu.clone(), UVec2::ONE, 1).create_view(&Default::default());
+        let layout = shader.get_bind_group_layout_by_name(DEPTH_PYRAMID_BIND_GROUP).unwrap().clone();
+        Self {
+            depth_size: UVec2::ZERO,
+            view: None,
+            mips: Vec::new(),
+            reduce_depth: pipeline("fs_reduce_depth"),
+            reduce: pipeline("fs_reduce"),
+            layout,
+            dummy_source,
+            gpu,
+        }
+    }
+
+    fn create_texture(gpu: Arc<Gpu>, size: UVec2, mip_count: u32) -> Arc<Texture> {
+        Arc::new(Texture::new(
+            gpu,
+            &wgpu::TextureDescriptor {
+                label: Some("DepthPyramid"),
+                size: wgpu::Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 },
+                mip_level_count: mip_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+        ))
+    }
+
+    fn resize(&mut self, depth_size: UVec2) {
+        if self.view.is_some() && self.depth_size == depth_size {
+            return;
+        }
+        // The first mip is half the size of the depth, and the last one is a single texel
+        let size = (depth_size / 2).max(uvec2(1, 1));
+        let mip_count = u32::BITS - size.max_element().leading_zeros();
+        let texture = Self::create_texture(self.gpu.clone(), size, mip_count);
+        self.mips = (0..mip_count)
+            .map(|mip| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("DepthPyramid.mip"),
+                    base_mip_level: mip,
+                    mip_level_count: NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        self.view = Some(texture.create_view(&Default::default()));
+        self.depth_size = depth_size;
+    }
+
+    /// Builds the pyramid from the `depth` of the frame so far
+    pub fn build(&mut self, encoder: &mut wgpu::CommandEncoder, depth: &wgpu::TextureView, depth_size: wgpu::Extent3d) {
+        profiling::scope!("DepthPyramid");
+        self.resize(uvec2(depth_size.width, depth_size.height));
+        for (mip, target) in self.mips.iter().enumerate() {
+            let (pipeline, source) = match mip {
+                0 => (&self.reduce_depth, &self.dummy_source),
+                _ => (&self.reduce, &self.mips[mip - 1]),
+            };
+            let bind_group = self.gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(depth) },
+                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(source) },
+                ],
+                label: Some("DepthPyramid.bind_group"),
+            });
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("DepthPyramid"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: true },
+                })],
+                depth_stencil_attachment: None,
+            });
+            rpass.set_pipeline(pipeline.pipeline());
+            pipeline.bind(&mut rpass, DEPTH_PYRAMID_BIND_GROUP, &bind_group);
+            rpass.draw(0..4, 0..1);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+
+@group(#DEPTH_PYRAMID_BIND_GROUP)
+@binding(0)
+var depth: texture_depth_2d;
+
+@group(#DEPTH_PYRAMID_BIND_GROUP)
+@binding(1)
+var source: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let x = f32(i32(vertex_index) / 2);
+    let y = f32(i32(vertex_index) & 1);
+    out.position = vec4<f32>(x * 4.0 - 1.0, 1.0 - y * 4.0, 0.0, 1.0);
+    return out;
+}
+
+// The texels of a level which a texel of the next one covers; each level is half the size of the previous one, rounded
+// down, so a texel covers up to 3 of them in each direction when the size is odd
+struct Footprint {
+    start: vec2<i32>,
+    end: vec2<i32>,
+};
+
+fn footprint(source_size: vec2<i32>, position: vec4<f32>) -> Footprint {
+    let size = max(source_size / 2, vec2<i32>(1));
+    let texel = vec2<i32>(position.xy);
+    var res: Footprint;
+    res.start = texel * source_size / size;
+    res.end = min(((texel + 1) * source_size + size - 1) / size, source_size);
+    return res;
+}
+
+// Keeps the farthest depth of the footprint, which is the lowest one as the depth is reversed
+@fragment
+fn fs_reduce_depth(in: VertexOutput) -> @location(0) f32 {
+    let footprint = footprint(textureDimensions(depth), in.position);
+    var farthest = 1.;
+    for (var y = footprint.start.y; y < footprint.end.y; y = y + 1) {
+        for (var x = footprint.start.x; x < footprint.end.x; x = x + 1) {
+            farthest = min(farthest, textureLoad(depth, vec2<i32>(x, y), 0));
+        }
+    }
+    return farthest;
+}
+
+@fragment
+fn fs_reduce(in: VertexOutput) -> @location(0) f32 {
+    let footprint = footprint(textureDimensions(source), in.position);
+    var farthest = 1.;
+    for (var y = footprint.start.y; y < footprint.end.y; y = y + 1) {
+        for (var x = footprint.start.x; x < footprint.end.x; x = x + 1) {
+            farthest = min(farthest, textureLoad(source, vec2<i32>(x, y), 0).r);
+        }
+    }
+    return farthest;
+}
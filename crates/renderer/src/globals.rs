@@ -1,7 +1,7 @@
 use std::{sync::Arc, time::Instant};
 
 use ambient_core::{
-    camera::{far, fog, get_active_camera, projection_view},
+    camera::{far, fog, get_active_camera, projection_view, Camera},
     transform::{get_world_position, get_world_rotation, local_to_world},
 };
 use ambient_ecs::{Component, ECSError, World};
@@ -11,7 +11,7 @@ use ambient_gpu::{
     std_assets::DefaultSamplerKey,
     texture::{Texture, TextureView},
 };
-use ambient_std::asset_cache::{AssetCache, SyncAssetKeyExt};
+use ambient_std::asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt};
 use glam::{vec3, Mat4, UVec2, Vec3, Vec4};
 use wgpu::BindGroup;
 
@@ -46,6 +46,11 @@ pub(crate) struct GlobalParams {
     pub fog_height_falloff: f32,
     pub fog_density: f32,
     pub debug_params: ShaderDebugParams,
+    /// How many texels around the sampled one are compared with, in each direction, when filtering the shadows
+    pub shadow_pcf_radius: i32,
+    /// Added to the mip level the textures of the materials are sampled from
+    pub texture_mip_bias: f32,
+    padding: [f32; 2],
 }
 
 impl Default for GlobalParams {
@@ -66,6 +71,9 @@ impl Default for GlobalParams {
             fog_height_falloff: 0.5,
             fog_density: 0.5,
             debug_params: Default::default(),
+            shadow_pcf_radius: 0,
+            texture_mip_bias: 0.,
+            padding: Default::default(),
         }
     }
 }
@@ -145,16 +153,32 @@ pub fn globals_layout() -> BindGroupDesc {
                 },
                 count: None,
             },
+            wgpu::BindGroupLayoutEntry {
+                binding: 8,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
         ],
         label: "GLOBALS_BIND_GROUP".into(),
     }
 }
 
+/// When the renderers started; the `time` of the shader globals is relative to it
+#[derive(Debug)]
+pub struct RendererStartTimeKey;
+impl SyncAssetKey<Instant> for RendererStartTimeKey {
+    fn load(&self, _assets: AssetCache) -> Instant {
+        Instant::now()
+    }
+}
+
 pub(crate) struct ForwardGlobals {
     gpu: Arc<Gpu>,
     buffer: wgpu::Buffer,
     shadow_cameras_buffer: wgpu::Buffer,
     shadow_sampler: wgpu::Sampler,
+    material_sampler: Arc<wgpu::Sampler>,
     dummy_shadow_texture: TextureView,
     pub(crate) params: GlobalParams,
     scene: Component<()>,
@@ -163,7 +187,14 @@ pub(crate) struct ForwardGlobals {
 }
 
 impl ForwardGlobals {
-    pub fn new(gpu: Arc<Gpu>, layout: Arc<wgpu::BindGroupLayout>, shadow_cascades: u32, scene: Component<()>) -> Self {
+    pub fn new(
+        gpu: Arc<Gpu>,
+        layout: Arc<wgpu::BindGroupLayout>,
+        shadow_cascades: u32,
+        material_sampler: Arc<wgpu::Sampler>,
+        scene: Component<()>,
+        start_time: Instant,
+    ) -> Self {
         let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("ForwardGlobals.buffer"),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
@@ -195,11 +226,12 @@ impl ForwardGlobals {
             buffer,
             shadow_cameras_buffer,
             shadow_sampler,
+            material_sampler,
             dummy_shadow_texture: create_dummy_shadow_texture(gpu.clone()).create_view(&Default::default()),
             params,
             gpu,
             scene,
-            start_time: Instant::now(),
+            start_time,
             layout,
         }
     }
@@ -222,6 +254,7 @@ impl ForwardGlobals {
                 wgpu::BindGroupEntry { binding: 5, resource: wgpu::BindingResource::TextureView(&solids_frame.color_buffer_view) },
                 wgpu::BindGroupEntry { binding: 6, resource: wgpu::BindingResource::TextureView(&solids_frame.depth_buffer_view) },
                 wgpu::BindGroupEntry { binding: 7, resource: wgpu::BindingResource::TextureView(&solids_frame.normals_quat_buffer_view) },
+                wgpu::BindGroupEntry { binding: 8, resource: wgpu::BindingResource::Sampler(&self.material_sampler) },
             ],
             label: Some("ForwardGlobals.bind_group"),
         })
@@ -255,6 +288,16 @@ impl ForwardGlobals {
         self.gpu.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.params]));
         self.gpu.queue.write_buffer(&self.shadow_cameras_buffer, 0, bytemuck::cast_slice(shadow_cameras));
     }
+    /// Like [Self::update], but from the point of view of `camera` rather than the active camera of the scene
+    pub fn update_with_camera(&mut self, world: &World, shadow_cameras: &[ShadowCameraData], camera: &Camera) {
+        self.update(world, shadow_cameras);
+        let p = &mut self.params;
+        p.projection_view = camera.projection_view();
+        p.inv_projection_view = p.projection_view.inverse();
+        p.camera_position = camera.position().extend(1.);
+        p.camera_forward = camera.forward();
+        self.gpu.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.params]));
+    }
 }
 
 fn create_dummy_shadow_texture(gpu: Arc<Gpu>) -> Arc<Texture> {
@@ -276,6 +319,7 @@ pub struct ShadowAndUIGlobals {
     gpu: Arc<Gpu>,
     pub bind_group: wgpu::BindGroup,
     buffer: wgpu::Buffer,
+    start_time: Instant,
 }
 impl ShadowAndUIGlobals {
     pub fn new(assets: AssetCache, layout: Arc<wgpu::BindGroupLayout>) -> Self {
@@ -324,17 +368,20 @@ impl ShadowAndUIGlobals {
                         binding: 7,
                         resource: wgpu::BindingResource::TextureView(&dummy_prev_frame.normals_quat_buffer_view),
                     },
+                    wgpu::BindGroupEntry { binding: 8, resource: wgpu::BindingResource::Sampler(&DefaultSamplerKey.get(&assets)) },
                 ],
                 label: Some("ShadowGlobals.bind_group"),
             }),
             buffer,
             gpu,
+            start_time: RendererStartTimeKey.get(&assets),
         }
     }
     pub fn update(&self, world: &World, scene: Component<()>, projection_view: Mat4) {
         let mut params = GlobalParams {
             projection_view,
             camera_position: projection_view.inverse().project_point3(-Vec3::Z).extend(1.),
+            time: Instant::now().duration_since(self.start_time).as_secs_f32(),
             ..Default::default()
         };
         if let Some(id) = get_active_camera(world, scene) {
@@ -22,6 +22,10 @@ struct ForwardGlobalParams {
     debug_metallic_roughness: f32,
     debug_normals: f32,
     debug_shading: f32,
+    debug_padding: f32,
+
+    shadow_pcf_radius: i32,
+    texture_mip_bias: f32,
 };
 
 struct ShadowCamera {
@@ -61,13 +65,34 @@ var solids_screen_depth: texture_depth_2d;
 @binding(7)
 var solids_screen_normal_quat: texture_2d<f32>;
 
+// Samples the textures of materials, per the texture quality settings
+@group(#GLOBALS_BIND_GROUP)
+@binding(8)
+var default_material_sampler: sampler;
+
 fn inside(v: vec3<f32>) -> bool {
     return v.x > -1. && v.x < 1. && v.y > -1. && v.y < 1. && v.z > 0. && v.z < 1.;
 }
 
 fn fetch_shadow_cascade(cascade: i32, homogeneous_coords: vec3<f32>) -> f32 {
     let light_local = homogeneous_coords.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5, 0.5);
-    return textureSampleCompareLevel(shadow_texture, shadow_sampler, light_local, cascade, homogeneous_coords.z + 0.0001);
+    let depth = homogeneous_coords.z + 0.0001;
+    let radius = global_params.shadow_pcf_radius;
+    if (radius <= 0) {
+        return textureSampleCompareLevel(shadow_texture, shadow_sampler, light_local, cascade, depth);
+    }
+
+    // Percentage-closer filtering; each sample is bilinearly filtered by the comparison sampler as well
+    let texel_size = 1. / vec2<f32>(textureDimensions(shadow_texture));
+    var lit = 0.;
+    for (var y: i32 = -radius; y <= radius; y = y + 1) {
+        for (var x: i32 = -radius; x <= radius; x = x + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel_size;
+            lit = lit + textureSampleCompareLevel(shadow_texture, shadow_sampler, light_local + offset, cascade, depth);
+        }
+    }
+    let width = f32(radius * 2 + 1);
+    return lit / (width * width);
 }
 
 fn get_shadow_cascade(world_position: vec4<f32>) -> i32 {
@@ -141,6 +166,8 @@ struct MaterialInput {
     instance_index: u32,
     entity_loc: vec2<u32>,
     local_position: vec3<f32>,
+    // The vertex color; white if the mesh has none
+    color: vec4<f32>,
 };
 
 struct MaterialOutput {
@@ -221,7 +248,7 @@ fn geometry_smith(normal: vec3<f32>, v: vec3<f32>, l: vec3<f32>, roughness: f32)
         * geometry_schlick_ggx(ndotl, k);
 }
 
-fn shading(material: MaterialOutput, world_position: vec4<f32>) -> vec4<f32> {
+fn shading(material: MaterialOutput, world_position: vec4<f32>, receive_shadows: bool) -> vec4<f32> {
     if (global_params.debug_shading > 0.0) {
       return vec4(material.base_color.rgb, material.opacity);
     }
@@ -290,7 +317,10 @@ fn shading(material: MaterialOutput, world_position: vec4<f32>) -> vec4<f32> {
 
     let radiance = global_params.sun_diffuse.rgb;
 
-    let in_shadow = fetch_shadow(ndotl, world_position);
+    var in_shadow = 1.;
+    if (receive_shadows) {
+        in_shadow = fetch_shadow(ndotl, world_position);
+    }
 
     let direct = (lambert + specular) * radiance * ndotl * in_shadow;
 
@@ -0,0 +1,673 @@
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU32,
+    sync::Arc,
+    time::Duration,
+};
+
+use ambient_core::{
+    bounding::{visibility_from, world_bounding_sphere},
+    camera::{Camera, Projection},
+    gpu_components,
+    gpu_ecs::{gpu_world, GpuComponentFormat, ENTITIES_BIND_GROUP},
+    hierarchy::children,
+    time,
+};
+use ambient_ecs::{
+    components, query, ArchetypeFilter, Component, Debuggable, Description, EntityId, Name, Networked, Store, SystemGroup, World,
+};
+use ambient_gpu::{
+    gpu::{Gpu, GpuKey},
+    mesh_buffer::MeshBuffer,
+    shader_module::{BindGroupDesc, GraphicsPipeline, GraphicsPipelineInfo, Shader, ShaderModule},
+    texture::{Texture, TextureView},
+    typed_buffer::TypedBuffer,
+};
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKeyExt},
+    include_file,
+    shapes::Sphere,
+};
+use bytemuck::{Pod, Zeroable};
+use glam::{uvec4, Mat4, UVec4, Vec3, Vec4};
+use itertools::Itertools;
+use wgpu::{BindGroupLayoutEntry, BindingType, BufferUsages, ShaderStages};
+
+use crate::{
+    get_overlay_module, primitives, sampler::MaterialSamplerKey, ForwardGlobals, RenderTarget, RendererCollectState, RendererConfig,
+    RendererResources, RendererStartTimeKey, RendererTarget, ShadowsRenderer, TreeRenderer, GLOBALS_BIND_GROUP, MAX_SHADOW_CASCADES,
+    RESOURCES_BIND_GROUP,
+};
+
+components!("rendering", {
+    @[
+        Debuggable, Networked, Store,
+        Name["Impostor distance"],
+        Description["If attached, this entity is drawn as a billboard instead of its meshes when it's farther than this from the camera.\nThe billboard is a picture of the entity which is re-captured every `impostor_refresh_interval`, which makes hundreds of animated characters affordable in the distance. The meshes still cast the shadows."]
+    ]
+    impostor_distance: f32,
+    @[
+        Debuggable, Networked, Store,
+        Name["Impostor refresh interval"],
+        Description["How often, in seconds, the billboard of an entity with `impostor_distance` is re-captured, which is how choppy its animations get in the distance. Defaults to 0.25."]
+    ]
+    impostor_refresh_interval: f32,
+});
+gpu_components! {
+    // 0 while the entity is drawn with its meshes, 1 while it's drawn as an impostor, and 2 + i while it's also being
+    // captured by the i:th capture of the frame
+    impostor_distance() => impostor_state: GpuComponentFormat::U32,
+}
+
+/// Only the entity with the [impostor_distance] gets an `impostor_state`, but the meshes of a model are on its descendants, so
+/// they're all made visible from it; which also makes them captured together
+pub fn systems() -> SystemGroup {
+    SystemGroup::new(
+        "impostors",
+        vec![query((impostor_distance(),)).to_system(|q, world, qs, _| {
+            let mut visible_from = Vec::new();
+            for (id, _) in q.iter(world, qs) {
+                let mut descendants = world.get_ref(id, children()).cloned().unwrap_or_default();
+                while let Some(descendant) = descendants.pop() {
+                    if world.has_component(descendant, primitives()) && world.get(descendant, visibility_from()).ok() != Some(id) {
+                        visible_from.push((descendant, id));
+                    }
+                    if let Ok(children) = world.get_ref(descendant, children()) {
+                        descendants.extend(children.iter().copied());
+                    }
+                }
+            }
+            for (descendant, id) in visible_from {
+                world.add_component(descendant, visibility_from(), id).unwrap();
+            }
+        })],
+    )
+}
+
+const IMPOSTORS_BIND_GROUP: &str = "IMPOSTORS_BIND_GROUP";
+
+const DEFAULT_REFRESH_INTERVAL: f32 = 0.25;
+/// The width and height of the captures
+const CAPTURE_RESOLUTION: u32 = 128;
+/// The most entities drawn as impostors at once; the ones beyond keep their meshes
+const MAX_IMPOSTORS: u32 = 256;
+/// The most impostors captured in a frame; the most overdue ones go first
+pub(crate) const MAX_IMPOSTOR_CAPTURES: u32 = 8;
+/// The culling has a camera for each capture of the frame, after the main camera and the shadow cascades
+pub(crate) const FIRST_IMPOSTOR_CAMERA: u32 = MAX_SHADOW_CASCADES + 1;
+/// Entities go back to their meshes a little closer than their `impostor_distance`, so that they don't flicker between
+/// the two on the boundary
+const SWITCHOVER_HYSTERESIS: f32 = 0.95;
+/// How much wider than the bounding sphere of the entity a capture is, for the limbs which reach out of it when animated
+const CAPTURE_MARGIN: f32 = 1.1;
+
+#[derive(Debug, Clone, Copy)]
+struct Capture {
+    time: Duration,
+    /// Half of the width of the capture, at the center of the entity
+    half_size: f32,
+}
+
+#[derive(Debug, Default)]
+struct ImpostorSlot {
+    /// None until the entity is first captured
+    capture: Option<Capture>,
+}
+
+/// The slots of the entities drawn as impostors, which are the layers of their captures in the atlas
+#[derive(Debug, Default)]
+struct ImpostorSlots {
+    slots: Vec<Option<ImpostorSlot>>,
+    layers: HashMap<EntityId, u32>,
+}
+impl ImpostorSlots {
+    /// Frees the slots of the entities which aren't in `impostors` anymore, and gives the new ones a slot in order, for
+    /// as long as there are any left
+    fn assign(&mut self, impostors: &[EntityId]) {
+        let kept: HashSet<EntityId> = impostors.iter().copied().collect();
+        let slots = &mut self.slots;
+        self.layers.retain(|id, layer| {
+            let keep = kept.contains(id);
+            if !keep {
+                slots[*layer as usize] = None;
+            }
+            keep
+        });
+        for &id in impostors {
+            if !self.layers.contains_key(&id) && self.allocate(id).is_none() {
+                break;
+            }
+        }
+    }
+    fn allocate(&mut self, id: EntityId) -> Option<u32> {
+        let layer = match self.slots.iter().position(|slot| slot.is_none()) {
+            Some(layer) => layer,
+            None if self.slots.len() < MAX_IMPOSTORS as usize => {
+                self.slots.push(None);
+                self.slots.len() - 1
+            }
+            None => return None,
+        };
+        self.slots[layer] = Some(ImpostorSlot::default());
+        self.layers.insert(id, layer as u32);
+        Some(layer as u32)
+    }
+    fn contains(&self, id: EntityId) -> bool {
+        self.layers.contains_key(&id)
+    }
+    fn layer(&self, id: EntityId) -> Option<u32> {
+        self.layers.get(&id).copied()
+    }
+    fn slot(&self, layer: u32) -> &ImpostorSlot {
+        self.slots[layer as usize].as_ref().unwrap()
+    }
+    fn slot_mut(&mut self, layer: u32) -> &mut ImpostorSlot {
+        self.slots[layer as usize].as_mut().unwrap()
+    }
+    /// How many layers the atlas needs
+    fn len(&self) -> u32 {
+        self.slots.len() as u32
+    }
+}
+
+/// How far the camera at `eye` is from the entity, if it's far enough for it to be drawn as an impostor.
+/// `was_impostor` if it was one last frame, in which case it stays one a little closer
+fn impostor_camera_distance(eye: Vec3, sphere: &Sphere, impostor_distance: f32, was_impostor: bool) -> Option<f32> {
+    let distance = if was_impostor { impostor_distance * SWITCHOVER_HYSTERESIS } else { impostor_distance };
+    let camera_distance = eye.distance(sphere.center);
+    // The capture needs the camera to be well outside of the entity
+    (camera_distance > distance.max(sphere.radius * CAPTURE_MARGIN * 2.)).then_some(camera_distance)
+}
+
+/// Whether an impostor last captured at `captured` (None if it never was) has to be re-captured at `now`
+fn is_capture_due(now: Duration, captured: Option<Duration>, refresh_interval: f32) -> bool {
+    captured.map(|captured| now.saturating_sub(captured).as_secs_f32() >= refresh_interval).unwrap_or(true)
+}
+
+/// The due captures which fit in this frame; the ones which were never captured first, then the most overdue ones
+fn captures_of_frame<T>(due: impl IntoIterator<Item = (T, Option<Duration>)>) -> Vec<T> {
+    due.into_iter().sorted_by_key(|(_, captured)| *captured).take(MAX_IMPOSTOR_CAPTURES as usize).map(|(x, _)| x).collect()
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+struct Billboard {
+    /// The center of the billboard, and half of its width in w
+    center: Vec4,
+    right: Vec4,
+    up: Vec4,
+    layer: UVec4,
+}
+
+/// The captures of the impostors, in the layer of their slot
+struct ImpostorAtlas {
+    color: Arc<Texture>,
+    normals: Arc<Texture>,
+    color_view: TextureView,
+    normals_view: TextureView,
+    layers: u32,
+}
+impl ImpostorAtlas {
+    fn new(gpu: Arc<Gpu>, color_format: wgpu::TextureFormat, layers: u32) -> Self {
+        let texture = |label, format| {
+            Arc::new(Texture::new(
+                gpu.clone(),
+                &wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d { width: CAPTURE_RESOLUTION, height: CAPTURE_RESOLUTION, depth_or_array_layers: layers },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC
+                        | wgpu::TextureUsages::COPY_DST,
+                },
+            ))
+        };
+        let color = texture("ImpostorAtlas.color", color_format);
+        let normals = texture("ImpostorAtlas.normals", wgpu::TextureFormat::Rgba8Snorm);
+        let array_view = |texture: &Arc<Texture>| {
+            texture.create_view(&wgpu::TextureViewDescriptor { dimension: Some(wgpu::TextureViewDimension::D2Array), ..Default::default() })
+        };
+        Self { color_view: array_view(&color), normals_view: array_view(&normals), color, normals, layers }
+    }
+    fn layer_view(texture: &Arc<Texture>, layer: u32) -> TextureView {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("ImpostorAtlas.layer"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: NonZeroU32::new(1),
+            ..Default::default()
+        })
+    }
+    fn copy_from(&self, encoder: &mut wgpu::CommandEncoder, other: &ImpostorAtlas) {
+        for (from, to) in [(&other.color, &self.color), (&other.normals, &self.normals)] {
+            encoder.copy_texture_to_texture(from.handle.as_image_copy(), to.handle.as_image_copy(), from.size);
+        }
+    }
+}
+
+/// The globals and the collected primitives of one of the captures of a frame
+struct CaptureState {
+    globals: ForwardGlobals,
+    collect_state: RendererCollectState,
+}
+
+/// Draws the far away entities with an [impostor_distance] as billboards, which are pictures of them re-captured every
+/// [impostor_refresh_interval]. The culling hides their meshes from the main camera, and shows them to the camera of
+/// their capture when they're re-captured.
+pub(crate) struct ImpostorRenderer {
+    gpu: Arc<Gpu>,
+    assets: AssetCache,
+    scene: Component<()>,
+    color_format: wgpu::TextureFormat,
+    atlas: Option<ImpostorAtlas>,
+    depth: TextureView,
+    slots: ImpostorSlots,
+    capture_states: Vec<CaptureState>,
+    /// The layers captured this frame, and the cameras they're captured with
+    captures: Vec<(u32, Camera)>,
+    billboards: Vec<Billboard>,
+    buffer: TypedBuffer<Billboard>,
+    pipeline: GraphicsPipeline,
+    sampler: wgpu::Sampler,
+}
+impl ImpostorRenderer {
+    pub fn new(assets: &AssetCache, config: &RendererConfig, renderer_resources: &RendererResources) -> Self {
+        let gpu = GpuKey.get(assets);
+        let color_format = config.color_format(&gpu);
+
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = BindGroupDesc {
+            entries: vec![
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(1),
+                texture_entry(2),
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: IMPOSTORS_BIND_GROUP.into(),
+        };
+        let shader = Shader::from_modules(
+            assets,
+            "Impostors",
+            [
+                &get_overlay_module(assets, config.shadow_cascades),
+                &ShaderModule::new("Impostors", include_file!("impostors.wgsl"), vec![layout.into()]),
+            ],
+        );
+        let pipeline = shader.to_pipeline(
+            &gpu,
+            GraphicsPipelineInfo {
+                targets: &[Some(color_format.into()), Some(wgpu::TextureFormat::Rgba8Snorm.into())],
+                depth: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: true,
+                    // This is Greater because we're using reverse-z NDC
+                    depth_compare: wgpu::CompareFunction::Greater,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                ..Default::default()
+            },
+        );
+
+        let capture_states = (0..MAX_IMPOSTOR_CAPTURES)
+            .map(|_| {
+                let mut globals = ForwardGlobals::new(
+                    gpu.clone(),
+                    renderer_resources.globals_layout.clone(),
+                    config.shadow_cascades,
+                    MaterialSamplerKey::from(&config.texture_sampling).get(assets),
+                    config.scene,
+                    RendererStartTimeKey.get(assets),
+                );
+                globals.params.shadow_pcf_radius = (config.shadow_pcf_kernel / 2) as i32;
+                globals.params.texture_mip_bias = config.texture_sampling.mip_bias;
+                CaptureState { globals, collect_state: RendererCollectState::new(assets) }
+            })
+            .collect_vec();
+
+        let depth = Arc::new(Texture::new(
+            gpu.clone(),
+            &wgpu::TextureDescriptor {
+                label: Some("ImpostorRenderer.depth"),
+                size: wgpu::Extent3d { width: CAPTURE_RESOLUTION, height: CAPTURE_RESOLUTION, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Depth32Float,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            },
+        ))
+        .create_view(&Default::default());
+
+        Self {
+            assets: assets.clone(),
+            scene: config.scene,
+            color_format,
+            atlas: None,
+            depth,
+            slots: ImpostorSlots::default(),
+            capture_states,
+            captures: Vec::new(),
+            billboards: Vec::new(),
+            buffer: TypedBuffer::new(
+                gpu.clone(),
+                "ImpostorRenderer.buffer",
+                64,
+                0,
+                BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            ),
+            pipeline,
+            sampler: gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("ImpostorRenderer.sampler"),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            }),
+            gpu,
+        }
+    }
+
+    /// Decides which entities are drawn as impostors this frame, and which of them are re-captured, and passes it on to
+    /// the culling through their `impostor_state`; so it has to run before it
+    #[profiling::function]
+    pub fn update(&mut self, world: &World, encoder: &mut wgpu::CommandEncoder, camera: &Camera) {
+        let now = world.resource_opt(time()).copied().unwrap_or_default();
+        let eye = camera.position();
+
+        // The closest ones get a slot first, when there aren't enough for all of them
+        let impostors = query((impostor_distance(), world_bounding_sphere()))
+            .incl(self.scene)
+            .iter(world, None)
+            .filter_map(|(id, (&distance, sphere))| {
+                impostor_camera_distance(eye, sphere, distance, self.slots.contains(id))
+                    .map(|camera_distance| (id, *sphere, camera_distance))
+            })
+            .sorted_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(id, sphere, _)| (id, sphere))
+            .collect_vec();
+
+        self.slots.assign(&impostors.iter().map(|&(id, _)| id).collect_vec());
+        self.reserve(encoder, self.slots.len());
+
+        // The most overdue captures go first, and the ones behind the camera can wait until they come into view
+        let forward = camera.forward();
+        let due = captures_of_frame(impostors.iter().filter_map(|&(id, sphere)| {
+            let layer = self.slots.layer(id)?;
+            let captured = self.slots.slot(layer).capture.map(|capture| capture.time);
+            let interval = world.get(id, impostor_refresh_interval()).unwrap_or(DEFAULT_REFRESH_INTERVAL);
+            let in_front = (sphere.center - eye).dot(forward) > -sphere.radius;
+            (is_capture_due(now, captured, interval) && in_front).then_some(((id, layer, sphere), captured))
+        }));
+
+        let mut states = HashMap::new();
+        self.captures.clear();
+        for (i, (id, layer, sphere)) in due.into_iter().enumerate() {
+            let (capture_camera, half_size) = capture_camera(eye, &sphere);
+            self.slots.slot_mut(layer).capture = Some(Capture { time: now, half_size });
+            self.captures.push((layer, capture_camera));
+            states.insert(id, 2 + i as u32);
+        }
+
+        self.billboards.clear();
+        for &(id, sphere) in &impostors {
+            let layer = match self.slots.layer(id) {
+                Some(layer) => layer,
+                None => continue,
+            };
+            if let Some(capture) = self.slots.slot(layer).capture {
+                let (right, up) = billboard_axes(sphere.center - eye);
+                self.billboards.push(Billboard {
+                    center: sphere.center.extend(capture.half_size),
+                    right: right.extend(0.),
+                    up: up.extend(0.),
+                    layer: uvec4(layer, 0, 0, 0),
+                });
+                states.entry(id).or_insert(1);
+            }
+        }
+
+        // Written straight to the GPU world rather than through a component, as the culling of this frame needs it
+        let gpu_world = world.resource(gpu_world()).lock();
+        for arch in ArchetypeFilter::new().incl(impostor_distance()).iter_archetypes(world) {
+            if let Some((buffer, offset, _)) = gpu_world.get_buffer(GpuComponentFormat::U32, gpu_components::impostor_state(), arch.id) {
+                let arch_states = (0..arch.entity_count())
+                    .map(|index| states.get(&arch.get_entity_id_from_index(index)).copied().unwrap_or(0))
+                    .collect_vec();
+                if !arch_states.is_empty() {
+                    self.gpu.queue.write_buffer(buffer, offset, bytemuck::cast_slice(&arch_states));
+                }
+            }
+        }
+    }
+
+    /// Grows the atlas to have at least `layers`, keeping the captures in it
+    fn reserve(&mut self, encoder: &mut wgpu::CommandEncoder, layers: u32) {
+        let current = self.atlas.as_ref().map(|atlas| atlas.layers).unwrap_or_default();
+        if layers <= current {
+            return;
+        }
+        let atlas = ImpostorAtlas::new(self.gpu.clone(), self.color_format, layers.next_power_of_two().clamp(16, MAX_IMPOSTORS));
+        if let Some(previous) = &self.atlas {
+            atlas.copy_from(encoder, previous);
+        }
+        self.atlas = Some(atlas);
+    }
+
+    /// Renders the captures of this frame, with the `forward` renderer from the point of view of their cameras
+    #[allow(clippy::too_many_arguments)]
+    #[profiling::function]
+    pub fn capture(
+        &mut self,
+        world: &World,
+        encoder: &mut wgpu::CommandEncoder,
+        post_submit: &mut Vec<Box<dyn FnOnce() + Send + Send>>,
+        forward: &TreeRenderer,
+        shadows: Option<&ShadowsRenderer>,
+        solids_frame: &RenderTarget,
+        resources_bind_group: &wgpu::BindGroup,
+        entities_bind_group: &wgpu::BindGroup,
+        mesh_buffer: &MeshBuffer,
+    ) {
+        let atlas = match &self.atlas {
+            Some(atlas) => atlas,
+            None => return,
+        };
+        let shadow_cameras = shadows.map(|x| x.get_cameras()).unwrap_or_default();
+        for (i, (layer, camera)) in self.captures.iter().enumerate() {
+            let state = &mut self.capture_states[i];
+            state.globals.update_with_camera(world, &shadow_cameras, camera);
+            state.collect_state.set_camera(FIRST_IMPOSTOR_CAMERA + i as u32);
+            forward.run_collect(encoder, post_submit, resources_bind_group, entities_bind_group, &mut state.collect_state);
+
+            let globals_bind_group = state.globals.create_bind_group(self.assets.clone(), shadows.map(|x| &x.shadow_view), solids_frame);
+            let color = ImpostorAtlas::layer_view(&atlas.color, *layer);
+            let normals = ImpostorAtlas::layer_view(&atlas.normals, *layer);
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Impostor capture"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: &color,
+                        resolve_target: None,
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), store: true },
+                    }),
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: &normals,
+                        resolve_target: None,
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: true },
+                    }),
+                ],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth,
+                    depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(0.0), store: true }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_index_buffer(mesh_buffer.index_buffer.buffer().slice(..), wgpu::IndexFormat::Uint32);
+            forward.render(
+                &mut render_pass,
+                &self.capture_states[i].collect_state,
+                &[
+                    (GLOBALS_BIND_GROUP, &globals_bind_group),
+                    (RESOURCES_BIND_GROUP, resources_bind_group),
+                    (ENTITIES_BIND_GROUP, entities_bind_group),
+                ],
+            );
+        }
+    }
+
+    /// Draws the billboards of the impostors into the `target`
+    #[profiling::function]
+    pub fn render(&mut self, encoder: &mut wgpu::CommandEncoder, target: &RendererTarget, globals_bind_group: &wgpu::BindGroup) {
+        let atlas = match &self.atlas {
+            Some(atlas) if !self.billboards.is_empty() => atlas,
+            _ => return,
+        };
+        self.buffer.fill(&self.billboards, |_| {});
+        let bind_group = self.gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("ImpostorRenderer.bind_group"),
+            layout: self.pipeline.shader().get_bind_group_layout_by_name(IMPOSTORS_BIND_GROUP).unwrap(),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: self.buffer.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&atlas.color_view) },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&atlas.normals_view) },
+                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+            ],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Impostors"),
+            color_attachments: &[
+                Some(wgpu::RenderPassColorAttachment {
+                    view: target.color(),
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: true },
+                }),
+                Some(wgpu::RenderPassColorAttachment {
+                    view: target.normals(),
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: true },
+                }),
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: target.depth(),
+                depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Load, store: true }),
+                stencil_ops: None,
+            }),
+        });
+        render_pass.set_pipeline(self.pipeline.pipeline());
+        self.pipeline.bind(&mut render_pass, GLOBALS_BIND_GROUP, globals_bind_group);
+        self.pipeline.bind(&mut render_pass, IMPOSTORS_BIND_GROUP, &bind_group);
+        render_pass.draw(0..6, 0..self.billboards.len() as u32);
+    }
+
+    pub fn n_impostors(&self) -> usize {
+        self.billboards.len()
+    }
+}
+
+impl std::fmt::Debug for ImpostorRenderer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImpostorRenderer").field("impostors", &self.slots.layers.len()).finish()
+    }
+}
+
+/// The right and up axes of a billboard seen along `direction`; the same as the ones of a view looking along it
+fn billboard_axes(direction: Vec3) -> (Vec3, Vec3) {
+    let forward = direction.normalize();
+    let up = if forward.z.abs() < 0.99 { Vec3::Z } else { Vec3::Y };
+    let right = up.cross(forward).normalize();
+    (right, forward.cross(right))
+}
+
+/// A camera at `eye` which just fits the `sphere`, and half of the width of what it sees at the center of the sphere
+fn capture_camera(eye: Vec3, sphere: &Sphere) -> (Camera, f32) {
+    let radius = sphere.radius * CAPTURE_MARGIN;
+    let distance = eye.distance(sphere.center);
+    let half_fov = (radius / distance).min(0.99).asin();
+    let (_, up) = billboard_axes(sphere.center - eye);
+    let camera = Camera {
+        view: Mat4::look_at_lh(eye, sphere.center, up),
+        projection: Projection::Perspective { fovy: half_fov * 2., aspect_ratio: 1., near: distance - radius, far: distance + radius },
+        shadows_far: distance + radius,
+    };
+    (camera, distance * half_fov.tan())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots() {
+        let mut slots = ImpostorSlots::default();
+        let (a, b, c) = (EntityId::new(), EntityId::new(), EntityId::new());
+        slots.assign(&[a, b]);
+        assert_eq!((slots.layer(a), slots.layer(b)), (Some(0), Some(1)));
+
+        // The layer of the one which left is reused, and the others keep theirs
+        slots.assign(&[b, c]);
+        assert_eq!((slots.layer(a), slots.layer(b), slots.layer(c)), (None, Some(1), Some(0)));
+        assert_eq!(slots.len(), 2);
+
+        // The ones beyond the limit keep their meshes
+        let many = (0..MAX_IMPOSTORS + 4).map(|_| EntityId::new()).collect_vec();
+        slots.assign(&many);
+        assert_eq!(slots.len(), MAX_IMPOSTORS);
+        assert!(slots.contains(many[MAX_IMPOSTORS as usize - 1]));
+        assert!(!slots.contains(many[MAX_IMPOSTORS as usize]));
+        assert!(!slots.contains(b));
+    }
+
+    #[test]
+    fn hysteresis() {
+        let sphere = Sphere { center: Vec3::ZERO, radius: 1. };
+        assert_eq!(impostor_camera_distance(Vec3::X * 98., &sphere, 100., false), None);
+        assert_eq!(impostor_camera_distance(Vec3::X * 98., &sphere, 100., true), Some(98.));
+        assert_eq!(impostor_camera_distance(Vec3::X * 94., &sphere, 100., true), None);
+        assert_eq!(impostor_camera_distance(Vec3::X * 101., &sphere, 100., false), Some(101.));
+        // Too close to be captured, whatever the impostor distance
+        assert_eq!(impostor_camera_distance(Vec3::X * 2., &sphere, 0., false), None);
+    }
+
+    #[test]
+    fn due_captures() {
+        let ms = Duration::from_millis;
+        assert!(is_capture_due(ms(1000), None, 0.25));
+        assert!(!is_capture_due(ms(1000), Some(ms(900)), 0.25));
+        assert!(is_capture_due(ms(1000), Some(ms(750)), 0.25));
+
+        let due = (0..10).map(|i| (i, Some(ms(10_000 - i * 1000)))).chain([(10, None)]);
+        assert_eq!(captures_of_frame(due), vec![10, 9, 8, 7, 6, 5, 4, 3]);
+    }
+}
@@ -0,0 +1,73 @@
+struct Billboard {
+  // The center of the billboard, and half of its width in w
+  center: vec4<f32>,
+  right: vec4<f32>,
+  up: vec4<f32>,
+  layer: vec4<u32>,
+};
+
+struct Billboards {
+  billboards: array<Billboard>,
+};
+
+@group(#IMPOSTORS_BIND_GROUP)
+@binding(0)
+var<storage> billboards: Billboards;
+
+@group(#IMPOSTORS_BIND_GROUP)
+@binding(1)
+var impostor_colors: texture_2d_array<f32>;
+
+@group(#IMPOSTORS_BIND_GROUP)
+@binding(2)
+var impostor_normals: texture_2d_array<f32>;
+
+@group(#IMPOSTORS_BIND_GROUP)
+@binding(3)
+var impostor_sampler: sampler;
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) texcoord: vec2<f32>,
+  @location(1) @interpolate(flat) layer: u32,
+};
+
+// Two triangles; x goes right and y up across the billboard
+fn billboard_corner(vertex_index: u32) -> vec2<f32> {
+  switch (vertex_index) {
+    case 0u: { return vec2<f32>(-1., -1.); }
+    case 1u, 4u: { return vec2<f32>(1., -1.); }
+    case 2u, 3u: { return vec2<f32>(-1., 1.); }
+    default: { return vec2<f32>(1., 1.); }
+  }
+}
+
+@vertex
+fn vs_main(@builtin(instance_index) instance_index: u32, @builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+  let billboard = billboards.billboards[instance_index];
+  let corner = billboard_corner(vertex_index);
+  let position = billboard.center.xyz + (billboard.right.xyz * corner.x + billboard.up.xyz * corner.y) * billboard.center.w;
+
+  var out: VertexOutput;
+  out.position = global_params.projection_view * vec4<f32>(position, 1.);
+  out.texcoord = vec2<f32>(corner.x, -corner.y) * 0.5 + 0.5;
+  out.layer = billboard.layer.x;
+  return out;
+}
+
+struct FsOut {
+  @location(0) color: vec4<f32>,
+  @location(1) normal: vec4<f32>,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FsOut {
+  let color = textureSample(impostor_colors, impostor_sampler, in.texcoord, i32(in.layer));
+  if (color.a < 0.5) {
+    discard;
+  }
+  let size = textureDimensions(impostor_normals);
+  let texel = clamp(vec2<i32>(in.texcoord * vec2<f32>(size)), vec2<i32>(0), size - 1);
+  // The captures are cleared to transparent black, so the filtered colors are premultiplied along the silhouette
+  return FsOut(vec4<f32>(color.rgb / color.a, 1.), textureLoad(impostor_normals, texel, i32(in.layer), 0));
+}
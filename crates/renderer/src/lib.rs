@@ -20,18 +20,23 @@ use downcast_rs::{impl_downcast, DowncastSync};
 use glam::{uvec4, UVec2, UVec4, Vec3, Vec4};
 use serde::{Deserialize, Serialize};
 
+pub mod blob_shadows;
 mod collect;
 mod culling;
+mod depth_pyramid;
 mod globals;
+pub mod impostors;
 pub mod lod;
 pub mod materials;
 mod outlines;
 mod overlay_renderer;
+mod post;
 mod renderer;
 mod shaders;
 mod shadow_renderer;
 pub mod skinning;
 mod target;
+pub mod trails;
 mod transparent_renderer;
 mod tree_renderer;
 use ambient_ecs::{query, Component};
@@ -41,6 +46,7 @@ pub use globals::*;
 pub use materials::*;
 use ordered_float::OrderedFloat;
 pub use outlines::*;
+pub use post::*;
 pub use renderer::*;
 pub use shaders::*;
 pub use shadow_renderer::*;
@@ -58,6 +64,12 @@ components!("rendering", {
     material: SharedMaterial,
     @[Resource]
     renderer_stats: String,
+    @[
+        Resource, Debuggable,
+        Name["Render scale"],
+        Description["The resolution the 3D scene is rendered at, relative to the window. The UI is always rendered at the native resolution of the window, so text stays crisp when this is lowered.\nDefaults to 1."]
+    ]
+    render_scale: f32,
     @[
         MakeDefault, Debuggable, Networked, Store,
         Name["Overlay"],
@@ -82,6 +94,18 @@ components!("rendering", {
         Description["If attached, this entity will cast shadows."]
     ]
     cast_shadows: (),
+    @[
+        MakeDefault, Debuggable, Networked, Store,
+        Name["No cast shadows"],
+        Description["If attached, this entity will not cast shadows, even if it has `cast_shadows` (e.g. from its model)."]
+    ]
+    no_cast_shadows: (),
+    @[
+        MakeDefault, Debuggable, Networked, Store,
+        Name["No receive shadows"],
+        Description["If attached, this entity will not be shadowed by the sun's shadow maps."]
+    ]
+    no_receive_shadows: (),
     @[
         Debuggable, Networked, Store,
         Name["Sun"],
@@ -128,6 +152,8 @@ components!("rendering", {
 gpu_components! {
     color() => color: GpuComponentFormat::Vec4,
     primitives() => primitives: GpuComponentFormat::UVec4Array20,
+    // Only checked for with `has_entity_no_receive_shadows`, so nothing is ever written to it
+    no_receive_shadows() => no_receive_shadows: GpuComponentFormat::U32,
 }
 pub fn init_all_componets() {
     init_components();
@@ -135,10 +161,15 @@ pub fn init_all_componets() {
     outlines::init_components();
     outlines::init_gpu_components();
     culling::init_gpu_components();
+    impostors::init_components();
+    impostors::init_gpu_components();
     lod::init_components();
     lod::init_gpu_components();
     skinning::init_components();
     skinning::init_gpu_components();
+    trails::init_components();
+    blob_shadows::init_components();
+    custom_material::init_components();
 }
 
 pub fn systems() -> SystemGroup {
@@ -165,6 +196,7 @@ pub fn systems() -> SystemGroup {
                 }
             }),
             Box::new(outlines::systems()),
+            Box::new(impostors::systems()),
         ],
     )
 }
@@ -0,0 +1,657 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashSet},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use ambient_core::{asset_cache, async_ecs::async_run, mesh, runtime};
+use ambient_ecs::{components, query, Debuggable, Description, EntityData, Name, Networked, Store, SystemGroup};
+use ambient_gpu::{
+    gpu::{Gpu, GpuKey},
+    shader_module::{BindGroupDesc, Shader, ShaderModule},
+    std_assets::DefaultSamplerKey,
+    texture::TextureView,
+    texture_loaders::TextureFromUrl,
+};
+use ambient_std::{
+    asset_cache::{AssetCache, AsyncAssetKey, AsyncAssetKeyExt, SyncAssetKey, SyncAssetKeyExt},
+    asset_url::{AbsAssetUrl, AssetUrl},
+    cb,
+    download_asset::{AssetError, BytesFromUrl, JsonFromUrl},
+    friendly_id, include_file,
+};
+use anyhow::Context;
+use async_trait::async_trait;
+use glam::{Vec2, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+use wgpu::{util::DeviceExt, BindGroup};
+
+use super::{
+    super::{
+        get_forward_module, material, primitives, renderer_shader, Material, MaterialShader, RenderPrimitive, RendererShader,
+        RendererShaderProducer, SharedMaterial, MATERIAL_BIND_GROUP,
+    },
+    sampler::{MaterialSamplerKey, TextureSampling},
+};
+
+components!("rendering", {
+    @[
+        Debuggable, Networked, Store,
+        Name["Custom material"],
+        Description["The URL of a custom material (the `.custom_material.json` built by the `CustomMaterials` pipeline) to render the meshes of this entity with, on the clients.\nA custom material has WGSL of its own, along with typed parameters and textures."]
+    ]
+    custom_material: String,
+});
+
+/// The value of a parameter of a [CustomMaterial], which is a member of the `material_params` uniform of its WGSL
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CustomMaterialParam {
+    F32(f32),
+    U32(u32),
+    I32(i32),
+    Vec2(Vec2),
+    Vec3(Vec3),
+    Vec4(Vec4),
+}
+impl CustomMaterialParam {
+    fn wgsl_type(&self) -> &'static str {
+        match self {
+            CustomMaterialParam::F32(_) => "f32",
+            CustomMaterialParam::U32(_) => "u32",
+            CustomMaterialParam::I32(_) => "i32",
+            CustomMaterialParam::Vec2(_) => "vec2<f32>",
+            CustomMaterialParam::Vec3(_) => "vec3<f32>",
+            CustomMaterialParam::Vec4(_) => "vec4<f32>",
+        }
+    }
+    /// The alignment and size of the type in a uniform buffer
+    fn layout(&self) -> (usize, usize) {
+        match self {
+            CustomMaterialParam::F32(_) | CustomMaterialParam::U32(_) | CustomMaterialParam::I32(_) => (4, 4),
+            CustomMaterialParam::Vec2(_) => (8, 8),
+            CustomMaterialParam::Vec3(_) => (16, 12),
+            CustomMaterialParam::Vec4(_) => (16, 16),
+        }
+    }
+    fn to_bytes(self) -> Vec<u8> {
+        match self {
+            CustomMaterialParam::F32(x) => bytemuck::bytes_of(&x).to_vec(),
+            CustomMaterialParam::U32(x) => bytemuck::bytes_of(&x).to_vec(),
+            CustomMaterialParam::I32(x) => bytemuck::bytes_of(&x).to_vec(),
+            CustomMaterialParam::Vec2(x) => bytemuck::bytes_of(&x).to_vec(),
+            CustomMaterialParam::Vec3(x) => bytemuck::bytes_of(&x).to_vec(),
+            CustomMaterialParam::Vec4(x) => bytemuck::bytes_of(&x).to_vec(),
+        }
+    }
+}
+
+/// Lays the parameters out one after the other by the alignment rules of WGSL; returns where each of them is, and the
+/// size of the buffer
+fn layout_params(params: &[(String, CustomMaterialParam)]) -> (Vec<usize>, usize) {
+    let align_to = |offset: usize, align: usize| (offset + align - 1) / align * align;
+    let mut end = 0;
+    let offsets = params
+        .iter()
+        .map(|(_, param)| {
+            let (align, size) = param.layout();
+            let offset = align_to(end, align);
+            end = offset + size;
+            offset
+        })
+        .collect();
+    // Uniform structs are aligned to 16 bytes, and have at least one member
+    (offsets, align_to(end.max(1), 16))
+}
+
+/// How many textures a [CustomMaterial] can have; it's the least every GPU can sample in a shader stage
+pub const MAX_CUSTOM_MATERIAL_TEXTURES: usize = 16;
+
+fn validate_identifier(name: &str) -> anyhow::Result<()> {
+    let mut chars = name.chars();
+    anyhow::ensure!(
+        chars.next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false)
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !name.starts_with("__"),
+        "{name:?} is not a valid WGSL identifier"
+    );
+    Ok(())
+}
+
+/// The names declared at the top level of WGSL source, e.g. `global_params` for `var<uniform> global_params: ...`
+fn declared_names(source: &str) -> HashSet<String> {
+    source
+        .lines()
+        .filter_map(|line| {
+            let rest = ["var", "let", "fn", "struct"].iter().find_map(|keyword| line.strip_prefix(keyword))?;
+            // The address space of `var<storage>` and the like
+            let rest = match rest.strip_prefix('<') {
+                Some(rest) => &rest[rest.find('>')? + 1..],
+                None => rest,
+            };
+            if !rest.starts_with(' ') {
+                return None;
+            }
+            let name = rest.trim_start().chars().take_while(|&c| c.is_ascii_alphanumeric() || c == '_').collect::<String>();
+            if name.is_empty() {
+                None
+            } else {
+                Some(name)
+            }
+        })
+        .collect()
+}
+
+/// Checks that the parameters and the textures have valid and distinct names, and that the textures don't shadow what
+/// the engine declares (e.g. `global_params` or `primitives`), as they're declared next to it
+fn validate_names<'a>(
+    assets: &AssetCache,
+    params: &'a [(String, CustomMaterialParam)],
+    textures: impl ExactSizeIterator<Item = &'a str>,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        textures.len() <= MAX_CUSTOM_MATERIAL_TEXTURES,
+        "A custom material can have at most {MAX_CUSTOM_MATERIAL_TEXTURES} textures, but this one has {}",
+        textures.len()
+    );
+    let mut reserved = declared_names(&Shader::preprocess(&custom_shader_modules(assets, &Default::default(), None, 1)));
+    reserved.extend(["material_params", "material_sampler", "get_material", "get_material_vertex"].map(String::from));
+
+    let mut names = HashSet::new();
+    for (name, _) in params {
+        validate_identifier(name)?;
+        anyhow::ensure!(names.insert(name.as_str()), "{name:?} is declared more than once");
+    }
+    for name in textures {
+        validate_identifier(name)?;
+        anyhow::ensure!(!reserved.contains(name), "{name:?} is already declared by the engine");
+        anyhow::ensure!(names.insert(name), "{name:?} is declared more than once");
+    }
+    Ok(())
+}
+
+/// Declares the parameters, the sampler and the textures of a material, followed by its own WGSL
+fn material_source<'a>(fragment: &str, params: &[(String, CustomMaterialParam)], textures: impl Iterator<Item = &'a str>) -> String {
+    let mut source = "struct CustomMaterialParams {\n".to_string();
+    for (name, param) in params {
+        source += &format!("    {name}: {},\n", param.wgsl_type());
+    }
+    if params.is_empty() {
+        source += "    unused: u32,\n";
+    }
+    source += "};\n\n@group(#MATERIAL_BIND_GROUP)\n@binding(0)\nvar<uniform> material_params: CustomMaterialParams;\n\n";
+    source += "@group(#MATERIAL_BIND_GROUP)\n@binding(1)\nvar material_sampler: sampler;\n\n";
+    for (i, name) in textures.enumerate() {
+        source += &format!("@group(#MATERIAL_BIND_GROUP)\n@binding({})\nvar {name}: texture_2d<f32>;\n\n", i + 2);
+    }
+    source + fragment
+}
+
+/// The layout of the [MATERIAL_BIND_GROUP] of a material with `texture_count` textures
+fn material_layout(texture_count: u32) -> BindGroupDesc {
+    let visibility = wgpu::ShaderStages::VERTEX_FRAGMENT;
+    let mut entries = vec![
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility,
+            ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        },
+    ];
+    entries.extend((0..texture_count).map(|i| wgpu::BindGroupLayoutEntry {
+        binding: i + 2,
+        visibility,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }));
+    BindGroupDesc { entries, label: MATERIAL_BIND_GROUP.into() }
+}
+
+/// The modules a [CustomMaterial] is rendered with, in order
+fn custom_shader_modules(
+    assets: &AssetCache,
+    material_shader: &ShaderModule,
+    vertex: Option<&ShaderModule>,
+    shadow_cascades: u32,
+) -> Vec<ShaderModule> {
+    let mut modules = vec![
+        get_forward_module(assets, shadow_cascades),
+        material_shader.clone(),
+        ShaderModule::new("StandardMaterial", include_file!("../shaders/standard.wgsl"), vec![]),
+    ];
+    if let Some(vertex) = vertex {
+        modules.push(vertex.clone());
+        modules.push(ShaderModule::new("CustomMaterialVertex", include_file!("custom_material.wgsl"), vec![]));
+    }
+    modules
+}
+
+/// Checks the WGSL of a material as it will be compiled, with the parameters and textures it declares, so that the
+/// mistakes in it fail when it's built rather than when it's rendered
+pub fn validate_custom_material(
+    assets: &AssetCache,
+    fragment: &str,
+    vertex: Option<&str>,
+    params: &[(String, CustomMaterialParam)],
+    textures: &[&str],
+) -> anyhow::Result<()> {
+    validate_names(assets, params, textures.iter().copied())?;
+    let material_shader = ShaderModule::new(
+        "CustomMaterial",
+        material_source(fragment, params, textures.iter().copied()),
+        vec![material_layout(textures.len() as u32).into()],
+    );
+    let vertex = vertex.map(|vertex| ShaderModule::new("CustomMaterialVertexStage", vertex.to_string(), vec![]));
+    let source = Shader::preprocess(&custom_shader_modules(assets, &material_shader, vertex.as_ref(), 1));
+
+    let module = naga::front::wgsl::parse_str(&source).map_err(|err| anyhow::anyhow!("{}", err.emit_to_string(&source)))?;
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(&module)
+        .map_err(|err| anyhow::anyhow!("{}", err.emit_to_string(&source)))?;
+    Ok(())
+}
+
+/// The [MaterialShader] of a [CustomMaterial], which is shared by the materials with the same WGSL, parameter types and
+/// texture names
+pub struct CustomMaterialShaderKey {
+    /// Derived from the source, so that it's the same for the same source
+    pub id: String,
+    pub source: String,
+    pub texture_count: u32,
+}
+impl std::fmt::Debug for CustomMaterialShaderKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomMaterialShaderKey").field("id", &self.id).finish()
+    }
+}
+impl SyncAssetKey<Arc<MaterialShader>> for CustomMaterialShaderKey {
+    fn load(&self, _assets: AssetCache) -> Arc<MaterialShader> {
+        Arc::new(MaterialShader {
+            id: self.id.clone(),
+            shader: ShaderModule::new(self.id.clone(), self.source.clone(), vec![material_layout(self.texture_count).into()]),
+        })
+    }
+}
+
+/// The [RendererShader] of a [CustomMaterial]: the standard one, with the vertices moved by the material if it has a
+/// vertex stage
+pub struct CustomShaderKey {
+    pub material_shader: Arc<MaterialShader>,
+    /// Defines `get_material_vertex`; its source is part of the id of the material shader
+    pub vertex: Option<ShaderModule>,
+    pub lit: bool,
+    pub shadow_cascades: u32,
+}
+impl std::fmt::Debug for CustomShaderKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomShaderKey")
+            .field("material_shader", &self.material_shader.id)
+            .field("lit", &self.lit)
+            .field("shadow_cascades", &self.shadow_cascades)
+            .finish()
+    }
+}
+impl SyncAssetKey<Arc<RendererShader>> for CustomShaderKey {
+    fn load(&self, assets: AssetCache) -> Arc<RendererShader> {
+        let id = format!("custom_shader_{}_{}", self.material_shader.id, self.lit);
+        let modules = custom_shader_modules(&assets, &self.material_shader.shader, self.vertex.as_ref(), self.shadow_cascades);
+        let shader = Shader::from_modules(&assets, id.clone(), &modules);
+
+        Arc::new(RendererShader {
+            shader,
+            id,
+            vs_main: if self.vertex.is_some() { "vs_custom_main".to_string() } else { "vs_main".to_string() },
+            fs_shadow_main: "fs_shadow_main".to_string(),
+            fs_forward_main: if self.lit { "fs_forward_lit_main".to_string() } else { "fs_forward_unlit_main".to_string() },
+            fs_outline_main: "fs_outlines_main".to_string(),
+            transparent: false,
+            double_sided: false,
+            depth_write_enabled: true,
+            transparency_group: 0,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CustomMaterialConfig {
+    pub name: String,
+    /// WGSL which defines `fn get_material(in: MaterialInput) -> MaterialOutput`, as the built-in materials do. It can
+    /// read the parameters from `material_params`, and sample the textures by their names with `material_sampler`
+    pub fragment: String,
+    /// WGSL which defines `fn get_material_vertex(out: VertexOutput) -> VertexOutput`, to move the vertices (e.g. for
+    /// wind or waves) by changing their `world_position`, and optionally their normal and tangents
+    pub vertex: Option<String>,
+    /// Unlit materials output their base color as it is
+    pub lit: bool,
+    /// The members of `material_params`, in order
+    pub params: Vec<(String, CustomMaterialParam)>,
+    /// The textures, which are declared as `texture_2d<f32>` under their names
+    pub textures: Vec<(String, Arc<TextureView>)>,
+    pub transparent: Option<bool>,
+    pub double_sided: Option<bool>,
+    /// How `material_sampler` samples; if not set, it's the default sampler. Its `mip_bias` is up to the WGSL, e.g. as a
+    /// parameter passed to `textureSampleBias`
+    pub sampling: Option<TextureSampling>,
+}
+
+/// A material with WGSL of its own, for the looks the [PbrMaterial](super::pbr_material::PbrMaterial) can't do. Its
+/// parameters can be changed after it's created with [CustomMaterial::set_param]
+pub struct CustomMaterial {
+    gpu: Arc<Gpu>,
+    id: String,
+    pub config: CustomMaterialConfig,
+    shader: Arc<MaterialShader>,
+    vertex: Option<ShaderModule>,
+    /// Where each of the parameters is in the buffer
+    offsets: Vec<usize>,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+impl CustomMaterial {
+    pub fn new(assets: AssetCache, config: CustomMaterialConfig) -> anyhow::Result<Self> {
+        let gpu = GpuKey.get(&assets);
+        validate_names(&assets, &config.params, config.textures.iter().map(|(name, _)| name.as_str()))?;
+        if let Some(sampling) = &config.sampling {
+            sampling.validate()?;
+        }
+
+        let source = material_source(&config.fragment, &config.params, config.textures.iter().map(|(name, _)| name.as_str()));
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        config.vertex.hash(&mut hasher);
+        let id = format!("custom_material_shader_{:x}", hasher.finish());
+        let shader = CustomMaterialShaderKey { id: id.clone(), source, texture_count: config.textures.len() as u32 }.get(&assets);
+        let vertex = config.vertex.as_ref().map(|vertex| ShaderModule::new(format!("{id}_vertex"), vertex.clone(), vec![]));
+
+        let (offsets, size) = layout_params(&config.params);
+        let mut contents = vec![0; size];
+        for ((_, param), &offset) in config.params.iter().zip(&offsets) {
+            let bytes = param.to_bytes();
+            contents[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+        let buffer = gpu.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("CustomMaterial.buffer"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            contents: &contents,
+        });
+        let sampler = match &config.sampling {
+            Some(sampling) => MaterialSamplerKey::from(sampling).get(&assets),
+            None => DefaultSamplerKey.get(&assets),
+        };
+        let mut entries = vec![
+            wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::Buffer(buffer.as_entire_buffer_binding()) },
+            wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&sampler) },
+        ];
+        for (i, (_, texture)) in config.textures.iter().enumerate() {
+            entries.push(wgpu::BindGroupEntry { binding: i as u32 + 2, resource: wgpu::BindingResource::TextureView(&texture.handle) });
+        }
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &shader.shader.first_layout(&assets),
+            entries: &entries,
+            label: Some("CustomMaterial.bind_group"),
+        });
+
+        Ok(Self { id: friendly_id(), gpu, config, shader, vertex, offsets, buffer, bind_group })
+    }
+    /// Changes the value of a parameter on the GPU; it has to keep its type. [CustomMaterial::config] keeps the initial
+    /// values
+    pub fn set_param(&self, name: &str, value: CustomMaterialParam) -> anyhow::Result<()> {
+        let index = self
+            .config
+            .params
+            .iter()
+            .position(|(param, _)| param == name)
+            .with_context(|| format!("The material {} has no parameter {name:?}", self.config.name))?;
+        let (_, initial) = &self.config.params[index];
+        anyhow::ensure!(
+            std::mem::discriminant(initial) == std::mem::discriminant(&value),
+            "The parameter {name:?} is a {}, not a {}",
+            initial.wgsl_type(),
+            value.wgsl_type()
+        );
+        self.gpu.queue.write_buffer(&self.buffer, self.offsets[index] as u64, &value.to_bytes());
+        Ok(())
+    }
+    /// The [renderer_shader] to render with this material
+    pub fn renderer_shader_producer(&self) -> RendererShaderProducer {
+        let material_shader = self.shader.clone();
+        let vertex = self.vertex.clone();
+        let lit = self.config.lit;
+        cb(move |assets, config| {
+            CustomShaderKey {
+                material_shader: material_shader.clone(),
+                vertex: vertex.clone(),
+                lit,
+                shadow_cascades: config.shadow_cascades,
+            }
+            .get(assets)
+        })
+    }
+    pub fn gpu_size(&self) -> u64 {
+        self.config.textures.iter().map(|(_, texture)| texture.texture.size_in_bytes).sum()
+    }
+}
+impl std::fmt::Debug for CustomMaterial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomMaterial").field("id", &self.id).field("name", &self.config.name).finish()
+    }
+}
+impl Material for CustomMaterial {
+    fn bind(&self) -> &BindGroup {
+        &self.bind_group
+    }
+    fn id(&self) -> &str {
+        &self.id
+    }
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+    fn transparent(&self) -> Option<bool> {
+        self.config.transparent
+    }
+    fn double_sided(&self) -> Option<bool> {
+        self.config.double_sided
+    }
+}
+
+/// A texture of a [CustomMaterialFromUrl]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CustomMaterialTexture {
+    pub url: AssetUrl,
+    /// If the texture holds data rather than colors, e.g. a normal map or a mask; colors are sRGB encoded
+    #[serde(default)]
+    pub linear: bool,
+}
+
+/// A [CustomMaterial] as a `.custom_material.json` file, whose WGSL and textures are paths relative to it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CustomMaterialFromUrl {
+    pub name: Option<String>,
+    pub fragment: AssetUrl,
+    pub vertex: Option<AssetUrl>,
+    /// Defaults to true
+    pub lit: Option<bool>,
+    #[serde(default)]
+    pub params: BTreeMap<String, CustomMaterialParam>,
+    #[serde(default)]
+    pub textures: BTreeMap<String, CustomMaterialTexture>,
+    pub transparent: Option<bool>,
+    pub double_sided: Option<bool>,
+    pub sampling: Option<TextureSampling>,
+}
+impl CustomMaterialFromUrl {
+    pub fn resolve(&self, base_url: &AbsAssetUrl) -> anyhow::Result<Self> {
+        let mut textures = BTreeMap::new();
+        for (name, texture) in &self.textures {
+            textures.insert(name.clone(), CustomMaterialTexture { url: texture.url.resolve(base_url)?.into(), ..texture.clone() });
+        }
+        Ok(Self {
+            fragment: self.fragment.resolve(base_url)?.into(),
+            vertex: if let Some(x) = &self.vertex { Some(x.resolve(base_url)?.into()) } else { None },
+            textures,
+            ..self.clone()
+        })
+    }
+    pub fn relative_path_from(&self, base_url: &AbsAssetUrl) -> Self {
+        Self {
+            fragment: base_url.relative_path(self.fragment.path()).into(),
+            vertex: self.vertex.as_ref().map(|x| base_url.relative_path(x.path()).into()),
+            textures: self
+                .textures
+                .iter()
+                .map(|(name, texture)| {
+                    (name.clone(), CustomMaterialTexture { url: base_url.relative_path(texture.url.path()).into(), ..texture.clone() })
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+async fn load_wgsl(assets: &AssetCache, url: &AssetUrl) -> Result<String, AssetError> {
+    let bytes = BytesFromUrl::new(url.clone().unwrap_abs(), true).get(assets).await?;
+    Ok(String::from_utf8(bytes.to_vec()).with_context(|| format!("{url} is not UTF-8"))?)
+}
+
+#[async_trait]
+impl AsyncAssetKey<Result<Arc<CustomMaterial>, AssetError>> for CustomMaterialFromUrl {
+    async fn load(self, assets: AssetCache) -> Result<Arc<CustomMaterial>, AssetError> {
+        let fragment = load_wgsl(&assets, &self.fragment).await?;
+        let vertex = match &self.vertex {
+            Some(vertex) => Some(load_wgsl(&assets, vertex).await?),
+            None => None,
+        };
+        let mut textures = Vec::new();
+        for (name, texture) in &self.textures {
+            let format = if texture.linear { wgpu::TextureFormat::Rgba8Unorm } else { wgpu::TextureFormat::Rgba8UnormSrgb };
+            let view = TextureFromUrl { url: texture.url.clone().unwrap_abs(), format, color_space: None }
+                .get(&assets)
+                .await?
+                .create_view(&Default::default());
+            textures.push((name.clone(), Arc::new(view)));
+        }
+        let config = CustomMaterialConfig {
+            name: self.name.clone().unwrap_or_else(|| self.fragment.to_string()),
+            fragment,
+            vertex,
+            lit: self.lit.unwrap_or(true),
+            params: self.params.into_iter().collect(),
+            textures,
+            transparent: self.transparent,
+            double_sided: self.double_sided,
+            sampling: self.sampling,
+        };
+        Ok(Arc::new(CustomMaterial::new(assets, config)?))
+    }
+}
+
+async fn load_custom_material(assets: &AssetCache, url: AbsAssetUrl) -> anyhow::Result<Arc<CustomMaterial>> {
+    let desc = JsonFromUrl::<CustomMaterialFromUrl>::new(url.clone(), true).get(assets).await?;
+    Ok(desc.resolve(&url)?.get(assets).await?)
+}
+
+/// Loads the custom materials, and renders the entities with them
+pub fn client_systems() -> SystemGroup {
+    SystemGroup::new(
+        "custom_material_client",
+        vec![query(custom_material().changed()).to_system(|q, world, qs, _| {
+            for (id, url) in q.collect_cloned(world, qs) {
+                let abs_url = match AbsAssetUrl::parse(&url) {
+                    Ok(url) => url,
+                    Err(err) => {
+                        log::error!("Invalid custom material url {url:?}: {err:?}");
+                        continue;
+                    }
+                };
+                let assets = world.resource(asset_cache()).clone();
+                let async_run = world.resource(async_run()).clone();
+                world.resource(runtime()).spawn(async move {
+                    match load_custom_material(&assets, abs_url).await {
+                        Ok(mat) => async_run.run(move |world| {
+                            if !world.get_ref(id, custom_material()).map(|current| current == &url).unwrap_or(false) {
+                                return;
+                            }
+                            let shader = mat.renderer_shader_producer();
+                            let mat = SharedMaterial::from(mat);
+                            // The entities of models have their meshes in their primitives rather than in `mesh`
+                            if !world.has_component(id, mesh()) {
+                                if let Ok(current) = world.get_ref(id, primitives()) {
+                                    let new_primitives = current
+                                        .iter()
+                                        .map(|primitive| RenderPrimitive {
+                                            shader: shader.clone(),
+                                            material: mat.clone(),
+                                            ..primitive.clone()
+                                        })
+                                        .collect();
+                                    world.set(id, primitives(), new_primitives).ok();
+                                }
+                            }
+                            world.add_components(id, EntityData::new().set(renderer_shader(), shader).set(material(), mat)).ok();
+                        }),
+                        Err(err) => log::error!("Failed to load the custom material {url}: {err:?}"),
+                    }
+                });
+            }
+        })],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec2, vec3};
+
+    use super::*;
+
+    fn params(values: &[CustomMaterialParam]) -> Vec<(String, CustomMaterialParam)> {
+        values.iter().enumerate().map(|(i, &value)| (format!("param_{i}"), value)).collect()
+    }
+
+    #[test]
+    fn layout_params_follows_wgsl_alignment() {
+        // A scalar after a vec3 goes in its padding
+        assert_eq!(layout_params(&params(&[CustomMaterialParam::Vec3(Vec3::ONE), CustomMaterialParam::F32(1.)])), (vec![0, 12], 16));
+        // A vec3 after a scalar is aligned to 16 bytes
+        assert_eq!(layout_params(&params(&[CustomMaterialParam::F32(1.), CustomMaterialParam::Vec3(vec3(1., 2., 3.))])), (vec![0, 16], 32));
+        assert_eq!(
+            layout_params(&params(&[
+                CustomMaterialParam::U32(1),
+                CustomMaterialParam::Vec2(vec2(1., 2.)),
+                CustomMaterialParam::I32(-1),
+                CustomMaterialParam::Vec4(Vec4::ONE)
+            ])),
+            (vec![0, 8, 16, 32], 48)
+        );
+        // The size is rounded up to 16 bytes, and is never empty
+        assert_eq!(layout_params(&params(&[CustomMaterialParam::F32(1.)])), (vec![0], 16));
+        assert_eq!(layout_params(&[]), (vec![], 16));
+    }
+
+    #[test]
+    fn validate_identifier_accepts_wgsl_identifiers() {
+        for name in ["color", "_tint", "base_color2", "A"] {
+            assert!(validate_identifier(name).is_ok(), "{name}");
+        }
+        for name in ["", "2d", "__reserved", "with space", "dash-ed", "ünicode", "semi;colon"] {
+            assert!(validate_identifier(name).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn declared_names_finds_the_top_level_declarations() {
+        let source = "struct Params {\n    color: vec4<f32>,\n};\n@group(0)\n@binding(0)\nvar<uniform> global_params: Params;\nvar default_sampler: sampler;\nlet PI: f32 = 3.14;\nfn get_material(in: MaterialInput) -> MaterialOutput {\n    var out: MaterialOutput;\n    return out;\n}\n";
+        assert_eq!(
+            declared_names(source),
+            ["Params", "global_params", "default_sampler", "PI", "get_material"].into_iter().map(String::from).collect()
+        );
+    }
+}
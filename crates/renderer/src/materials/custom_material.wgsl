@@ -0,0 +1,8 @@
+
+// Lets the material move the vertices in world space, after which they're projected again
+@vertex
+fn vs_custom_main(@builtin(instance_index) instance_index: u32, @builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out = get_material_vertex(get_vertex_output(instance_index, vertex_index));
+    out.position = global_params.projection_view * out.world_position;
+    return out;
+}
@@ -1,2 +1,4 @@
+pub mod custom_material;
 pub mod flat_material;
 pub mod pbr_material;
+pub mod sampler;
@@ -14,17 +14,32 @@ use ambient_std::{
     friendly_id, include_file,
 };
 use async_trait::async_trait;
-use glam::Vec4;
+use glam::{uvec2, UVec2, Vec2, Vec3, Vec4, Vec4Swizzles};
 use serde::{Deserialize, Serialize};
 use wgpu::{util::DeviceExt, BindGroup};
 
-use super::super::{Material, MaterialShader, RendererShader, MATERIAL_BIND_GROUP};
-use crate::{RendererConfig, StandardShaderKey};
+use super::{
+    super::{Material, MaterialShader, RendererShader, MATERIAL_BIND_GROUP},
+    sampler::{MaterialSamplerKey, TextureSampling},
+};
+use crate::{RendererConfig, RendererStartTimeKey, StandardShaderKey};
 
 #[derive(Debug)]
 pub struct PbrMaterialShaderKey;
 impl SyncAssetKey<Arc<MaterialShader>> for PbrMaterialShaderKey {
     fn load(&self, _assets: AssetCache) -> Arc<MaterialShader> {
+        fn texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+            wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }
+        }
         Arc::new(MaterialShader {
             id: "pbr_material_shader".to_string(),
             shader: ShaderModule::new(
@@ -48,36 +63,15 @@ impl SyncAssetKey<Arc<MaterialShader>> for PbrMaterialShaderKey {
                             ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                             count: None,
                         },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 2,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                multisampled: false,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 3,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                multisampled: false,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 4,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                multisampled: false,
-                            },
-                            count: None,
-                        },
+                        // Base color, normal map, metallic roughness, then the detail base color, normal map and mask, then
+                        // the emissive map
+                        texture_entry(2),
+                        texture_entry(3),
+                        texture_entry(4),
+                        texture_entry(5),
+                        texture_entry(6),
+                        texture_entry(7),
+                        texture_entry(8),
                     ],
                     label: MATERIAL_BIND_GROUP.into(),
                 }
@@ -103,7 +97,27 @@ pub struct PbrMaterialParams {
     pub alpha_cutoff: f32,
     pub metallic: f32,
     pub roughness: f32,
-    pub _padding: u32,
+    /// The number of frames of the flipbook; 0 if the base color isn't animated
+    pub flipbook_frame_count: u32,
+    /// The number of columns and rows of frames in the base color texture
+    pub flipbook_grid: UVec2,
+    pub flipbook_fps: f32,
+    /// When the flipbook starts playing, relative to the renderer's start time
+    pub flipbook_start_time: f32,
+    pub flipbook_looping: u32,
+    /// 1 if the material samples its textures with a sampler of its own, in which case this is its mip bias; set from
+    /// [PbrMaterialConfig::sampling]
+    pub own_sampler: u32,
+    pub mip_bias: f32,
+    /// Where the detail maps are blended in: 0 nowhere, 1 per the vertex colors, 2 per the detail mask; set from
+    /// [PbrMaterialConfig::detail]
+    pub detail_blend: u32,
+    pub detail_tiling: Vec2,
+    /// How much of the detail base color and normal map are blended in where the blend weight is 1
+    pub detail_base_color_strength: f32,
+    pub detail_normal_strength: f32,
+    /// Picks the channel of the vertex colors or mask that's the blend weight
+    pub detail_channel: Vec4,
 }
 impl Default for PbrMaterialParams {
     fn default() -> Self {
@@ -113,7 +127,18 @@ impl Default for PbrMaterialParams {
             alpha_cutoff: 0.5,
             metallic: 1.,
             roughness: 1.,
-            _padding: Default::default(),
+            flipbook_frame_count: 0,
+            flipbook_grid: UVec2::ONE,
+            flipbook_fps: 0.,
+            flipbook_start_time: 0.,
+            flipbook_looping: 0,
+            own_sampler: 0,
+            mip_bias: 0.,
+            detail_blend: 0,
+            detail_tiling: Vec2::ONE,
+            detail_base_color_strength: 0.,
+            detail_normal_strength: 0.,
+            detail_channel: Vec4::X,
         }
     }
 }
@@ -127,9 +152,23 @@ pub struct PbrMaterialConfig {
     /// r: Metallic
     /// g: Roughness
     pub metallic_roughness: Arc<TextureView>,
+    /// Multiplies the emissive factor, with linear colors; white if not set
+    pub emissive: Option<Arc<TextureView>>,
     pub transparent: Option<bool>,
     pub double_sided: Option<bool>,
     pub depth_write_enabled: Option<bool>,
+    /// How the textures are sampled; if not set, they follow the texture quality settings of the renderer
+    pub sampling: Option<TextureSampling>,
+    /// The detail maps; they're only blended in if [PbrMaterialParams::detail_blend] is set
+    pub detail: Option<PbrDetailConfig>,
+}
+/// The textures of the [DetailMaps] of a material; how they're blended in is in its [PbrMaterialParams]
+#[derive(Clone, Debug)]
+pub struct PbrDetailConfig {
+    pub base_color: Arc<TextureView>,
+    pub normalmap: Arc<TextureView>,
+    /// The blend weights, if they aren't the vertex colors
+    pub mask: Arc<TextureView>,
 }
 pub struct PbrMaterial {
     gpu: Arc<Gpu>,
@@ -139,16 +178,30 @@ pub struct PbrMaterial {
     bind_group: wgpu::BindGroup,
 }
 impl PbrMaterial {
-    pub fn new(assets: AssetCache, config: PbrMaterialConfig) -> Self {
+    pub fn new(assets: AssetCache, mut config: PbrMaterialConfig) -> Self {
         let gpu = GpuKey.get(&assets);
+        // Materials without a sampler of their own sample with the one of the renderer, but still need one bound
+        let sampler = match &config.sampling {
+            Some(sampling) => {
+                config.params.own_sampler = 1;
+                config.params.mip_bias = sampling.mip_bias;
+                MaterialSamplerKey::from(sampling).get(&assets)
+            }
+            None => DefaultSamplerKey.get(&assets),
+        };
         let layout = PbrMaterialShaderKey.get(&assets).shader.first_layout(&assets);
+        let detail = config.detail.clone().unwrap_or_else(|| PbrDetailConfig {
+            base_color: PixelTextureViewKey::white().get(&assets),
+            normalmap: DefaultNormalMapViewKey.get(&assets),
+            mask: PixelTextureViewKey::white().get(&assets),
+        });
+        let emissive = config.emissive.clone().unwrap_or_else(|| PixelTextureViewKey::white().get(&assets));
 
         let buffer = gpu.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("PbrMaterial.buffer"),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             contents: bytemuck::cast_slice(&[config.params]),
         });
-        let sampler = DefaultSamplerKey.get(&assets);
         Self {
             id: friendly_id(),
             bind_group: gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -159,6 +212,10 @@ impl PbrMaterial {
                     wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&config.base_color.handle) },
                     wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&config.normalmap.handle) },
                     wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(&config.metallic_roughness.handle) },
+                    wgpu::BindGroupEntry { binding: 5, resource: wgpu::BindingResource::TextureView(&detail.base_color.handle) },
+                    wgpu::BindGroupEntry { binding: 6, resource: wgpu::BindingResource::TextureView(&detail.normalmap.handle) },
+                    wgpu::BindGroupEntry { binding: 7, resource: wgpu::BindingResource::TextureView(&detail.mask.handle) },
+                    wgpu::BindGroupEntry { binding: 8, resource: wgpu::BindingResource::TextureView(&emissive.handle) },
                 ],
                 label: Some("PbrMaterial.bind_group"),
             }),
@@ -181,9 +238,12 @@ impl PbrMaterial {
                 base_color: texture,
                 normalmap: DefaultNormalMapViewKey.get(assets),
                 metallic_roughness: PixelTextureViewKey::white().get(assets),
+                emissive: None,
                 transparent: None,
                 double_sided: None,
                 depth_write_enabled: None,
+                sampling: None,
+                detail: None,
             },
         )
     }
@@ -194,6 +254,10 @@ impl PbrMaterial {
         self.config.base_color.texture.size_in_bytes
             + self.config.normalmap.texture.size_in_bytes
             + self.config.metallic_roughness.texture.size_in_bytes
+            + self.config.emissive.as_ref().map_or(0, |emissive| emissive.texture.size_in_bytes)
+            + self.config.detail.as_ref().map_or(0, |detail| {
+                detail.base_color.texture.size_in_bytes + detail.normalmap.texture.size_in_bytes + detail.mask.texture.size_in_bytes
+            })
     }
 }
 impl std::fmt::Debug for PbrMaterial {
@@ -237,6 +301,138 @@ impl Default for AlphaMode {
     }
 }
 
+/// Plays the base color (and opacity) map of a material as an animation. The map is a sheet of `columns` x `rows`
+/// frames, which are played left to right, then top to bottom.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Flipbook {
+    pub columns: u32,
+    pub rows: u32,
+    pub frame_count: u32,
+    pub fps: f32,
+    /// If false, the animation stops on its last frame
+    pub looping: bool,
+}
+
+/// A clear, shiny layer over the material, such as the varnish of car paint or lacquered wood
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Clearcoat {
+    /// How much of the layer there is, from 0 to 1
+    pub factor: f32,
+    pub roughness: f32,
+}
+
+/// The soft highlight of cloth and velvet at grazing angles
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Sheen {
+    pub color: Vec3,
+    pub roughness: f32,
+}
+
+/// How much of the sheen color the material reflects on average over the view angles, which is how the [Sheen] is
+/// approximated
+const SHEEN_ALBEDO: f32 = 0.25;
+
+/// The luminance, in nits (cd/m²), which an emissive color of 1 stands for: the renderer has no exposure, so its colors
+/// are relative to the white of the display, which is taken to be the reference white of ITU-R BT.2408
+pub const REFERENCE_WHITE_NITS: f32 = 203.;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChannel {
+    #[default]
+    R,
+    G,
+    B,
+    A,
+}
+impl ColorChannel {
+    /// The vector which picks this channel out of a color with a dot product
+    pub fn mask(self) -> Vec4 {
+        match self {
+            ColorChannel::R => Vec4::X,
+            ColorChannel::G => Vec4::Y,
+            ColorChannel::B => Vec4::Z,
+            ColorChannel::A => Vec4::W,
+        }
+    }
+}
+
+/// A second base color and normal map, tiled more densely than the material's own and blended over them where a channel
+/// of the vertex colors or of a mask says so, which keeps large surfaces from looking flat and repetitive
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DetailMaps {
+    pub base_color: Option<AssetUrl>,
+    pub normalmap: Option<AssetUrl>,
+    /// The blend weights, which are laid out over the material's own texture coordinates. If not set, the vertex colors
+    /// are the blend weights
+    pub mask: Option<AssetUrl>,
+    /// The channel of the mask or vertex colors which is the blend weight
+    pub channel: ColorChannel,
+    /// How many times the detail maps repeat for each time the material's own maps do
+    pub tiling: Vec2,
+    /// How much of the detail maps is blended in where the blend weight is 1, from 0 to 1
+    pub strength: f32,
+}
+impl Default for DetailMaps {
+    fn default() -> Self {
+        Self { base_color: None, normalmap: None, mask: None, channel: ColorChannel::R, tiling: Vec2::ONE, strength: 1. }
+    }
+}
+impl DetailMaps {
+    pub fn resolve(&self, base_url: &AbsAssetUrl) -> anyhow::Result<Self> {
+        Ok(Self {
+            base_color: if let Some(x) = &self.base_color { Some(x.resolve(base_url)?.into()) } else { None },
+            normalmap: if let Some(x) = &self.normalmap { Some(x.resolve(base_url)?.into()) } else { None },
+            mask: if let Some(x) = &self.mask { Some(x.resolve(base_url)?.into()) } else { None },
+            ..self.clone()
+        })
+    }
+    pub fn relative_path_from(&self, base_url: &AbsAssetUrl) -> Self {
+        Self {
+            base_color: self.base_color.as_ref().map(|x| base_url.relative_path(x.path()).into()),
+            normalmap: self.normalmap.as_ref().map(|x| base_url.relative_path(x.path()).into()),
+            mask: self.mask.as_ref().map(|x| base_url.relative_path(x.path()).into()),
+            ..self.clone()
+        }
+    }
+    fn write_params(&self, params: &mut PbrMaterialParams) {
+        let strength = self.strength.clamp(0., 1.);
+        params.detail_blend = if self.mask.is_some() { 2 } else { 1 };
+        params.detail_tiling = self.tiling;
+        params.detail_base_color_strength = if self.base_color.is_some() { strength } else { 0. };
+        params.detail_normal_strength = if self.normalmap.is_some() { strength } else { 0. };
+        params.detail_channel = self.channel.mask();
+    }
+    async fn load(&self, assets: &AssetCache) -> Result<PbrDetailConfig, AssetError> {
+        async fn load_texture(
+            assets: &AssetCache,
+            url: &Option<AssetUrl>,
+            format: wgpu::TextureFormat,
+        ) -> Result<Option<Arc<TextureView>>, AssetError> {
+            Ok(match url {
+                Some(url) => Some(Arc::new(
+                    TextureFromUrl { url: url.clone().unwrap_abs(), format, color_space: None }
+                        .get(assets)
+                        .await?
+                        .create_view(&Default::default()),
+                )),
+                None => None,
+            })
+        }
+        Ok(PbrDetailConfig {
+            base_color: load_texture(assets, &self.base_color, wgpu::TextureFormat::Rgba8UnormSrgb)
+                .await?
+                .unwrap_or_else(|| PixelTextureViewKey::white().get(assets)),
+            normalmap: load_texture(assets, &self.normalmap, wgpu::TextureFormat::Rgba8Unorm)
+                .await?
+                .unwrap_or_else(|| DefaultNormalMapViewKey.get(assets)),
+            mask: load_texture(assets, &self.mask, wgpu::TextureFormat::Rgba8Unorm)
+                .await?
+                .unwrap_or_else(|| PixelTextureViewKey::white().get(assets)),
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct PbrMaterialFromUrl {
     pub name: Option<String>,
@@ -246,9 +442,20 @@ pub struct PbrMaterialFromUrl {
     pub opacity: Option<AssetUrl>,
     pub normalmap: Option<AssetUrl>,
     pub metallic_roughness: Option<AssetUrl>,
+    /// Multiplies the emissive factor. Its colors are linear, as they're intensities rather than reflectances, unless
+    /// `emissive_srgb` is set
+    #[serde(default)]
+    pub emissive: Option<AssetUrl>,
+    /// Whether the emissive map is sRGB encoded, e.g. as glTF's are, in which case it's decoded when it's sampled
+    #[serde(default)]
+    pub emissive_srgb: bool,
 
     pub base_color_factor: Option<Vec4>,
     pub emissive_factor: Option<Vec4>,
+    /// The luminance of the emissive color in nits (cd/m²), which the emissive factor is then only the color of. Values
+    /// above [REFERENCE_WHITE_NITS] are brighter than the display's white, and glow with the bloom of the camera
+    #[serde(default)]
+    pub emissive_strength: Option<f32>,
     pub transparent: Option<bool>,
     pub alpha_cutoff: Option<f32>,
     pub double_sided: Option<bool>,
@@ -256,6 +463,26 @@ pub struct PbrMaterialFromUrl {
     pub metallic: f32,
     #[serde(default)]
     pub roughness: f32,
+    #[serde(default)]
+    pub flipbook: Option<Flipbook>,
+    /// How much light goes through the material, from 0 to 1, as with thin glass. The renderer has no refraction, so
+    /// the material is made as transparent instead.
+    #[serde(default)]
+    pub transmission: Option<f32>,
+    /// The renderer has a single specular highlight, so the clearcoat is approximated by making the material shinier, by
+    /// as much as there's clearcoat
+    #[serde(default)]
+    pub clearcoat: Option<Clearcoat>,
+    /// The renderer has no sheen, so it's approximated by adding the sheen color to the base color, as much as it's
+    /// reflected on average
+    #[serde(default)]
+    pub sheen: Option<Sheen>,
+    /// How the textures are sampled; if not set, they follow the texture quality settings of the renderer
+    #[serde(default)]
+    pub sampling: Option<TextureSampling>,
+    /// Detail maps blended over the material's own maps by the vertex colors or a mask
+    #[serde(default)]
+    pub detail: Option<DetailMaps>,
 }
 impl PbrMaterialFromUrl {
     pub fn resolve(&self, base_url: &AbsAssetUrl) -> anyhow::Result<Self> {
@@ -267,14 +494,23 @@ impl PbrMaterialFromUrl {
             opacity: if let Some(x) = &self.opacity { Some(x.resolve(base_url)?.into()) } else { None },
             normalmap: if let Some(x) = &self.normalmap { Some(x.resolve(base_url)?.into()) } else { None },
             metallic_roughness: if let Some(x) = &self.metallic_roughness { Some(x.resolve(base_url)?.into()) } else { None },
+            emissive: if let Some(x) = &self.emissive { Some(x.resolve(base_url)?.into()) } else { None },
+            emissive_srgb: self.emissive_srgb,
 
             base_color_factor: self.base_color_factor,
             emissive_factor: self.emissive_factor,
+            emissive_strength: self.emissive_strength,
             transparent: self.transparent,
             alpha_cutoff: self.alpha_cutoff,
             double_sided: self.double_sided,
             metallic: self.metallic,
             roughness: self.roughness,
+            flipbook: self.flipbook,
+            transmission: self.transmission,
+            clearcoat: self.clearcoat,
+            sheen: self.sheen,
+            sampling: self.sampling,
+            detail: if let Some(x) = &self.detail { Some(x.resolve(base_url)?) } else { None },
         })
     }
     pub fn relative_path_from(&self, base_url: &AbsAssetUrl) -> Self {
@@ -286,14 +522,23 @@ impl PbrMaterialFromUrl {
             opacity: self.opacity.as_ref().map(|x| base_url.relative_path(x.path()).into()),
             normalmap: self.normalmap.as_ref().map(|x| base_url.relative_path(x.path()).into()),
             metallic_roughness: self.metallic_roughness.as_ref().map(|x| base_url.relative_path(x.path()).into()),
+            emissive: self.emissive.as_ref().map(|x| base_url.relative_path(x.path()).into()),
+            emissive_srgb: self.emissive_srgb,
 
             base_color_factor: self.base_color_factor,
             emissive_factor: self.emissive_factor,
+            emissive_strength: self.emissive_strength,
             transparent: self.transparent,
             alpha_cutoff: self.alpha_cutoff,
             double_sided: self.double_sided,
             metallic: self.metallic,
             roughness: self.roughness,
+            flipbook: self.flipbook,
+            transmission: self.transmission,
+            clearcoat: self.clearcoat,
+            sheen: self.sheen,
+            sampling: self.sampling,
+            detail: self.detail.as_ref().map(|x| x.relative_path_from(base_url)),
         }
     }
 }
@@ -312,7 +557,11 @@ impl AsyncAssetKey<Result<Arc<PbrMaterial>, AssetError>> for PbrMaterialFromUrl
                 .await?,
             )
         } else if let Some(albedo) = &self.base_color {
-            Some(TextureFromUrl { url: albedo.clone().unwrap_abs(), format: wgpu::TextureFormat::Rgba8UnormSrgb }.get(&assets).await?)
+            Some(
+                TextureFromUrl { url: albedo.clone().unwrap_abs(), format: wgpu::TextureFormat::Rgba8UnormSrgb, color_space: None }
+                    .get(&assets)
+                    .await?,
+            )
         } else {
             None
         };
@@ -322,7 +571,7 @@ impl AsyncAssetKey<Result<Arc<PbrMaterial>, AssetError>> for PbrMaterialFromUrl
         };
         let normalmap = if let Some(normalmap) = &self.normalmap {
             Arc::new(
-                TextureFromUrl { url: normalmap.clone().unwrap_abs(), format: wgpu::TextureFormat::Rgba8Unorm }
+                TextureFromUrl { url: normalmap.clone().unwrap_abs(), format: wgpu::TextureFormat::Rgba8Unorm, color_space: None }
                     .get(&assets)
                     .await?
                     .create_view(&Default::default()),
@@ -333,7 +582,7 @@ impl AsyncAssetKey<Result<Arc<PbrMaterial>, AssetError>> for PbrMaterialFromUrl
 
         let metallic_roughness = if let Some(metallic_roughness) = self.metallic_roughness {
             Arc::new(
-                TextureFromUrl { url: metallic_roughness.clone().unwrap_abs(), format: wgpu::TextureFormat::Rgba8Unorm }
+                TextureFromUrl { url: metallic_roughness.clone().unwrap_abs(), format: wgpu::TextureFormat::Rgba8Unorm, color_space: None }
                     .get(&assets)
                     .await?
                     .create_view(&Default::default()),
@@ -341,14 +590,58 @@ impl AsyncAssetKey<Result<Arc<PbrMaterial>, AssetError>> for PbrMaterialFromUrl
         } else {
             PixelTextureViewKey::white().get(&assets)
         };
+        let emissive = if let Some(emissive) = &self.emissive {
+            let format = if self.emissive_srgb { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm };
+            Some(Arc::new(
+                TextureFromUrl { url: emissive.clone().unwrap_abs(), format, color_space: None }
+                    .get(&assets)
+                    .await?
+                    .create_view(&Default::default()),
+            ))
+        } else {
+            None
+        };
 
-        let params = PbrMaterialParams {
+        // An emissive map or strength makes the material glow white unless the emissive factor says otherwise
+        let emissive_factor =
+            self.emissive_factor.unwrap_or(if emissive.is_some() || self.emissive_strength.is_some() { Vec4::ONE } else { Vec4::ZERO });
+        let mut params = PbrMaterialParams {
             base_color_factor: self.base_color_factor.unwrap_or(Vec4::ONE),
-            emissive_factor: self.emissive_factor.unwrap_or(Vec4::ZERO),
+            emissive_factor: match self.emissive_strength {
+                Some(nits) => (emissive_factor.xyz() * nits.max(0.) / REFERENCE_WHITE_NITS).extend(emissive_factor.w),
+                None => emissive_factor,
+            },
             alpha_cutoff: self.alpha_cutoff.unwrap_or(0.01),
             metallic: self.metallic,
             roughness: self.roughness,
-            _padding: Default::default(),
+            ..Default::default()
+        };
+        let mut transparent = self.transparent;
+        if let Some(transmission) = self.transmission.filter(|&transmission| transmission > 0.) {
+            params.base_color_factor.w *= 1. - transmission.min(1.);
+            transparent = Some(true);
+        }
+        if let Some(clearcoat) = &self.clearcoat {
+            let coated = params.roughness + (clearcoat.roughness - params.roughness) * clearcoat.factor.clamp(0., 1.);
+            params.roughness = params.roughness.min(coated);
+        }
+        if let Some(sheen) = &self.sheen {
+            let base_color = params.base_color_factor.xyz() + sheen.color * SHEEN_ALBEDO;
+            params.base_color_factor = base_color.min(Vec3::ONE).extend(params.base_color_factor.w);
+        }
+        if let Some(flipbook) = &self.flipbook {
+            params.flipbook_frame_count = flipbook.frame_count.min(flipbook.columns * flipbook.rows);
+            params.flipbook_grid = uvec2(flipbook.columns, flipbook.rows).max(UVec2::ONE);
+            params.flipbook_fps = flipbook.fps;
+            params.flipbook_start_time = RendererStartTimeKey.get(&assets).elapsed().as_secs_f32();
+            params.flipbook_looping = flipbook.looping as u32;
+        }
+        let detail = match &self.detail {
+            Some(detail) => {
+                detail.write_params(&mut params);
+                Some(detail.load(&assets).await?)
+            }
+            None => None,
         };
 
         let name = self.name.or(self.base_color.map(|x| x.to_string())).unwrap_or_default();
@@ -361,9 +654,12 @@ impl AsyncAssetKey<Result<Arc<PbrMaterial>, AssetError>> for PbrMaterialFromUrl
                 base_color: color_view.clone(),
                 normalmap,
                 metallic_roughness,
-                transparent: self.transparent,
+                emissive,
+                transparent,
                 double_sided: self.double_sided,
                 depth_write_enabled: None,
+                sampling: self.sampling,
+                detail,
             },
         )))
     }
@@ -4,6 +4,18 @@ struct PbrMaterialParams {
     alpha_cutoff: f32,
     metallic: f32,
     roughness: f32,
+    flipbook_frame_count: u32,
+    flipbook_grid: vec2<u32>,
+    flipbook_fps: f32,
+    flipbook_start_time: f32,
+    flipbook_looping: u32,
+    own_sampler: u32,
+    mip_bias: f32,
+    detail_blend: u32,
+    detail_tiling: vec2<f32>,
+    detail_base_color_strength: f32,
+    detail_normal_strength: f32,
+    detail_channel: vec4<f32>,
 };
 
 @group(#MATERIAL_BIND_GROUP)
@@ -26,20 +38,91 @@ var normal_texture: texture_2d<f32>;
 @binding(4)
 var metallic_roughness: texture_2d<f32>;
 
+@group(#MATERIAL_BIND_GROUP)
+@binding(5)
+var detail_base_color: texture_2d<f32>;
+
+@group(#MATERIAL_BIND_GROUP)
+@binding(6)
+var detail_normal: texture_2d<f32>;
+
+@group(#MATERIAL_BIND_GROUP)
+@binding(7)
+var detail_mask: texture_2d<f32>;
+
+@group(#MATERIAL_BIND_GROUP)
+@binding(8)
+var emissive_texture: texture_2d<f32>;
+
+// Maps the texcoord to the current frame of the flipbook in the base color texture
+fn flipbook_texcoord(texcoord: vec2<f32>) -> vec2<f32> {
+    let frame_count = pbr_params.flipbook_frame_count;
+    if (frame_count == 0u) {
+        return texcoord;
+    }
+    var frame = u32(max(global_params.time - pbr_params.flipbook_start_time, 0.) * pbr_params.flipbook_fps);
+    if (pbr_params.flipbook_looping != 0u) {
+        frame = frame % frame_count;
+    } else {
+        frame = min(frame, frame_count - 1u);
+    }
+    let grid = pbr_params.flipbook_grid;
+    let cell = vec2<f32>(f32(frame % grid.x), f32(frame / grid.x));
+    return (cell + fract(texcoord)) / vec2<f32>(grid);
+}
+
+// Samples with the sampler of the material if it has one, or else with the one of the texture quality settings. The
+// mip bias is applied by scaling the gradients, as the flipbook needs explicit gradients anyway
+fn sample_pbr_texture(tex: texture_2d<f32>, texcoord: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32> {
+    if (pbr_params.own_sampler != 0u) {
+        let scale = exp2(pbr_params.mip_bias);
+        return textureSampleGrad(tex, base_color_sampler, texcoord, ddx * scale, ddy * scale);
+    }
+    let scale = exp2(global_params.texture_mip_bias);
+    return textureSampleGrad(tex, default_material_sampler, texcoord, ddx * scale, ddy * scale);
+}
+
+// How much the detail maps are blended in, from the vertex colors or the detail mask
+fn detail_weight(in: MaterialInput, ddx: vec2<f32>, ddy: vec2<f32>) -> f32 {
+    if (pbr_params.detail_blend == 1u) {
+        return dot(in.color, pbr_params.detail_channel);
+    }
+    if (pbr_params.detail_blend == 2u) {
+        return dot(sample_pbr_texture(detail_mask, in.texcoord, ddx, ddy), pbr_params.detail_channel);
+    }
+    return 0.;
+}
+
 fn get_material(in: MaterialInput) -> MaterialOutput {
     var out: MaterialOutput;
-    let base_color_texture_sample = textureSample(base_color_texture, base_color_sampler, in.texcoord);
-    let mr = textureSample(metallic_roughness, base_color_sampler, in.texcoord);
-    let color = base_color_texture_sample * pbr_params.base_color_factor * get_entity_color_or(in.entity_loc, vec4<f32>(1., 1., 1., 1.));
+    let ddx = dpdx(in.texcoord);
+    let ddy = dpdy(in.texcoord);
+    // The gradients are those of the original texcoord, so that there's no seam where the flipbook texcoord wraps
+    let grid = vec2<f32>(pbr_params.flipbook_grid);
+    let base_color_texture_sample = sample_pbr_texture(base_color_texture, flipbook_texcoord(in.texcoord), ddx / grid, ddy / grid);
+    let mr = sample_pbr_texture(metallic_roughness, in.texcoord, ddx, ddy);
+
+    let detail = detail_weight(in, ddx, ddy);
+    let tiling = pbr_params.detail_tiling;
+    let detail_texcoord = in.texcoord * tiling;
+    var base_color_sample = base_color_texture_sample;
+    var normal = sample_pbr_texture(normal_texture, in.texcoord, ddx, ddy).xyz * 2. - 1.;
+    if (detail > 0.) {
+        let detail_color = sample_pbr_texture(detail_base_color, detail_texcoord, ddx * tiling, ddy * tiling);
+        base_color_sample = vec4<f32>(mix(base_color_sample.rgb, detail_color.rgb, detail * pbr_params.detail_base_color_strength), base_color_sample.a);
+        let detail_normal_sample = sample_pbr_texture(detail_normal, detail_texcoord, ddx * tiling, ddy * tiling).xyz * 2. - 1.;
+        normal = mix(normal, detail_normal_sample, detail * pbr_params.detail_normal_strength);
+    }
+
+    let color = base_color_sample * pbr_params.base_color_factor * get_entity_color_or(in.entity_loc, vec4<f32>(1., 1., 1., 1.));
     out.opacity = color.a;
     out.metallic = mr.r * pbr_params.metallic;
     out.roughness = mr.g * pbr_params.roughness;
 
     out.alpha_cutoff = pbr_params.alpha_cutoff;
     out.base_color = color.rgb;
-    out.emissive_factor = pbr_params.emissive_factor.rgb;
+    out.emissive_factor = pbr_params.emissive_factor.rgb * sample_pbr_texture(emissive_texture, in.texcoord, ddx, ddy).rgb;
     out.shading = 1.;
-    let normal = textureSample(normal_texture, base_color_sampler, in.texcoord).xyz * 2. - 1.;
     out.normal = in.normal_matrix * normal;
     return out;
 }
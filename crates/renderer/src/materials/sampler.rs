@@ -0,0 +1,99 @@
+use std::{num::NonZeroU8, sync::Arc};
+
+use ambient_gpu::gpu::GpuKey;
+use ambient_std::asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt};
+use serde::{Deserialize, Serialize};
+
+/// How the textures of materials are filtered between their texels and mip levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TextureFiltering {
+    /// The closest texel of the closest mip level, for a pixelated look
+    Nearest,
+    /// Blends the closest texels of the closest mip level
+    Bilinear,
+    /// Blends the closest texels of the two closest mip levels
+    #[default]
+    Trilinear,
+}
+
+/// How the textures of materials are sampled
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureSampling {
+    pub filtering: TextureFiltering,
+    /// The maximum level of anisotropic filtering, from 1 (off) to 16, which keeps the textures seen at grazing angles
+    /// (e.g. distant ground) sharp; only applies to trilinear filtering
+    pub anisotropy: u8,
+    /// Added to the mip level the textures are sampled from; negative is sharper, positive blurrier
+    pub mip_bias: f32,
+}
+impl Default for TextureSampling {
+    fn default() -> Self {
+        TextureQuality::default().sampling()
+    }
+}
+impl TextureSampling {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(self.anisotropy.is_power_of_two() && self.anisotropy <= 16, "The anisotropy must be 1, 2, 4, 8 or 16");
+        anyhow::ensure!((-4. ..=4.).contains(&self.mip_bias), "The mip bias must be between -4 and 4");
+        Ok(())
+    }
+}
+
+/// Presets of the [TextureSampling] of the materials, from the cheapest to the sharpest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TextureQuality {
+    Low,
+    Medium,
+    #[default]
+    High,
+    Ultra,
+}
+impl TextureQuality {
+    pub fn sampling(self) -> TextureSampling {
+        let (filtering, anisotropy, mip_bias) = match self {
+            TextureQuality::Low => (TextureFiltering::Bilinear, 1, 0.5),
+            TextureQuality::Medium => (TextureFiltering::Trilinear, 4, 0.),
+            TextureQuality::High => (TextureFiltering::Trilinear, 16, 0.),
+            TextureQuality::Ultra => (TextureFiltering::Trilinear, 16, -0.5),
+        };
+        TextureSampling { filtering, anisotropy, mip_bias }
+    }
+}
+
+/// A repeating sampler for the textures of materials. The mip bias of the [TextureSampling] isn't part of the sampler;
+/// the shaders apply it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaterialSamplerKey {
+    pub filtering: TextureFiltering,
+    pub anisotropy: u8,
+}
+impl From<&TextureSampling> for MaterialSamplerKey {
+    fn from(sampling: &TextureSampling) -> Self {
+        Self { filtering: sampling.filtering, anisotropy: sampling.anisotropy }
+    }
+}
+impl SyncAssetKey<Arc<wgpu::Sampler>> for MaterialSamplerKey {
+    fn load(&self, assets: AssetCache) -> Arc<wgpu::Sampler> {
+        let gpu = GpuKey.get(&assets);
+        let (filter, mipmap_filter) = match self.filtering {
+            TextureFiltering::Nearest => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest),
+            TextureFiltering::Bilinear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest),
+            TextureFiltering::Trilinear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear),
+        };
+        // Anisotropic filtering requires every filter to be linear
+        let anisotropy_clamp =
+            if self.filtering == TextureFiltering::Trilinear { NonZeroU8::new(self.anisotropy).filter(|x| x.get() > 1) } else { None };
+        Arc::new(gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("MaterialSampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter,
+            anisotropy_clamp,
+            ..Default::default()
+        }))
+    }
+}
@@ -0,0 +1,59 @@
+
+struct OcclusionParams {
+    projection_view: mat4x4<f32>,
+};
+
+@group(#OCCLUSION_BIND_GROUP)
+@binding(0)
+var<uniform> occlusion: OcclusionParams;
+
+@group(#OCCLUSION_BIND_GROUP)
+@binding(1)
+var depth_pyramid: texture_2d<f32>;
+
+// Whether the bounding sphere is behind the farthest depth of the area of the pyramid it covers on screen
+fn is_occluded(bounding_sphere: vec4<f32>) -> bool {
+    var uv_min = vec2<f32>(1.);
+    var uv_max = vec2<f32>(0.);
+    var nearest = 0.;
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        let corner = vec3<f32>(f32(i & 1u), f32((i >> 1u) & 1u), f32((i >> 2u) & 1u)) * 2. - 1.;
+        let clip = occlusion.projection_view * vec4<f32>(bounding_sphere.xyz + corner * bounding_sphere.w, 1.);
+        if (clip.w <= 0.) {
+            // Reaches behind the camera
+            return false;
+        }
+        let ndc = clip.xyz / clip.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        uv_min = min(uv_min, uv);
+        uv_max = max(uv_max, uv);
+        nearest = max(nearest, ndc.z);
+    }
+    uv_min = clamp(uv_min, vec2<f32>(0.), vec2<f32>(1.));
+    uv_max = clamp(uv_max, vec2<f32>(0.), vec2<f32>(1.));
+
+    // The mip where the area is at most a texel wide, so that it covers no more than 2x2 texels
+    let size = (uv_max - uv_min) * vec2<f32>(textureDimensions(depth_pyramid, 0));
+    let mip = i32(clamp(ceil(log2(max(max(size.x, size.y), 1.))), 0., f32(textureNumLevels(depth_pyramid) - 1)));
+    let mip_size = textureDimensions(depth_pyramid, mip);
+    let start = min(vec2<i32>(uv_min * vec2<f32>(mip_size)), mip_size - 1);
+    let end = min(vec2<i32>(uv_max * vec2<f32>(mip_size)), mip_size - 1);
+
+    var farthest = 1.;
+    for (var y = start.y; y <= end.y; y = y + 1) {
+        for (var x = start.x; x <= end.x; x = x + 1) {
+            farthest = min(farthest, textureLoad(depth_pyramid, vec2<i32>(x, y), mip).r);
+        }
+    }
+    // The depth is reversed, so nearer is higher
+    return nearest < farthest;
+}
+
+fn update(entity_loc: vec2<u32>) {
+    var cameras = get_entity_renderer_cameras_visible(entity_loc);
+    let visible = bool(cameras[0]) && !is_occluded(get_entity_world_bounding_sphere(entity_loc));
+    // The first pass has drawn the ones which were visible last frame already
+    cameras[#SECOND_PASS_CAMERAu] = u32(visible && !bool(cameras[#FIRST_PASS_CAMERAu]));
+    cameras[#VISIBLE_LAST_FRAMEu] = u32(visible);
+    set_entity_renderer_cameras_visible(entity_loc, cameras);
+}
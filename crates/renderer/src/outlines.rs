@@ -83,7 +83,7 @@ impl Outlines {
         let pipeline = shader.to_pipeline(
             &gpu,
             GraphicsPipelineInfo {
-                targets: &[Some(gpu.swapchain_format().into())],
+                targets: &[Some(renderer_config.color_format(&gpu).into())],
                 topology: PrimitiveTopology::TriangleStrip,
                 ..Default::default()
             },
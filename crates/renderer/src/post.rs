@@ -0,0 +1,416 @@
+use std::{num::NonZeroU32, sync::Arc, time::Instant};
+
+use ambient_core::camera::{auto_exposure, bloom_intensity, bloom_threshold, exposure, get_active_camera, tonemapper};
+use ambient_ecs::{Component, World};
+use ambient_gpu::{
+    gpu::{Gpu, GpuKey},
+    shader_module::{BindGroupDesc, ComputePipeline, GraphicsPipeline, GraphicsPipelineInfo, Shader, ShaderModule},
+    texture::{Texture, TextureView},
+};
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKeyExt},
+    include_file,
+};
+use bytemuck::{Pod, Zeroable};
+use glam::{uvec2, UVec4};
+use wgpu::{BindGroupLayoutEntry, BindingType, PrimitiveTopology, ShaderStages};
+
+use crate::{RenderTarget, RendererTarget};
+
+/// The format of the frame of a renderer with [crate::RendererConfig::hdr], which holds the brightness of the scene
+/// before it's exposed and tone mapped
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The most mips of the bloom, each half the size of the previous one; the more there are, the wider the glow
+const BLOOM_MIPS: u32 = 6;
+/// How fast auto exposure adapts; it catches up with 1 - e^-speed of the difference each second
+const AUTO_EXPOSURE_SPEED: f32 = 1.5;
+
+const POST_BIND_GROUP: &str = "POST_BIND_GROUP";
+const POST_SOURCE_BIND_GROUP: &str = "POST_SOURCE_BIND_GROUP";
+
+/// The post-processing settings of the active camera of a scene, from its components
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostSettings {
+    /// The exposure compensation in stops
+    pub exposure: f32,
+    pub auto_exposure: bool,
+    pub bloom_intensity: f32,
+    pub bloom_threshold: f32,
+    /// 0 to clip at white, 1 for ACES and 2 for Reinhard
+    pub tonemapper: u32,
+}
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self { exposure: 0., auto_exposure: false, bloom_intensity: 0., bloom_threshold: 1., tonemapper: 0 }
+    }
+}
+impl PostSettings {
+    pub fn from_world(world: &World, scene: Component<()>) -> Self {
+        let default = Self::default();
+        match get_active_camera(world, scene) {
+            Some(id) => Self {
+                exposure: world.get(id, exposure()).unwrap_or(default.exposure),
+                auto_exposure: world.has_component(id, auto_exposure()),
+                bloom_intensity: world.get(id, bloom_intensity()).unwrap_or(default.bloom_intensity).max(0.),
+                bloom_threshold: world.get(id, bloom_threshold()).unwrap_or(default.bloom_threshold).max(0.),
+                tonemapper: world.get(id, tonemapper()).unwrap_or(default.tonemapper),
+            },
+            None => default,
+        }
+    }
+}
+
+/// The parameters of the passes, laid out as the `PostParams` of post.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct PostParams {
+    exposure: f32,
+    auto_exposure: u32,
+    /// The fraction of the difference to the luminance of this frame the adapted luminance catches up with
+    exposure_adaptation: f32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    bloom_mips: u32,
+    tonemapper: u32,
+    _padding: u32,
+}
+
+/// The bright-pass and downsampled mips of the bloom, which are blurred back up into the first one
+struct Bloom {
+    mips: Vec<TextureView>,
+}
+impl Bloom {
+    fn new(gpu: Arc<Gpu>, frame_size: wgpu::Extent3d) -> Self {
+        let size = uvec2(frame_size.width, frame_size.height) / 2;
+        let size = size.max(uvec2(1, 1));
+        // Stops before the smallest mip gets narrower than a texel
+        let mip_count = (u32::BITS - size.min_element().leading_zeros()).min(BLOOM_MIPS);
+        let texture = Arc::new(Texture::new(
+            gpu,
+            &wgpu::TextureDescriptor {
+                label: Some("PostProcessing.bloom"),
+                size: wgpu::Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 },
+                mip_level_count: mip_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HDR_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+        ));
+        let mips = (0..mip_count)
+            .map(|mip| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("PostProcessing.bloom_mip"),
+                    base_mip_level: mip,
+                    mip_level_count: NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        Self { mips }
+    }
+}
+
+/// Renders the scene in HDR and maps it to the target: auto exposure, then a bright pass which is downsampled and
+/// blurred back up into the bloom, and finally the exposure, bloom and tone mapping of the camera in a single pass
+pub(crate) struct PostProcessing {
+    gpu: Arc<Gpu>,
+    /// The frame the scene is rendered to
+    pub frame: RenderTarget,
+    bloom: Option<Bloom>,
+    bloom_enabled: bool,
+    luminance: ComputePipeline,
+    bright: GraphicsPipeline,
+    downsample: GraphicsPipeline,
+    upsample: GraphicsPipeline,
+    /// Maps the frame to the target, for the format of the target it was last built for
+    composite: Option<(wgpu::TextureFormat, GraphicsPipeline)>,
+    shader: Arc<Shader>,
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    source_layout: Arc<wgpu::BindGroupLayout>,
+    dummy_bloom: TextureView,
+    last_frame: Option<Instant>,
+    /// Whether the adapted luminance is up to date, i.e. auto exposure was on last frame
+    exposure_adapted: bool,
+}
+impl PostProcessing {
+    pub fn new(assets: &AssetCache, bloom: bool) -> Self {
+        let gpu = GpuKey.get(assets);
+
+        fn texture_entry(binding: u32, sample_type: wgpu::TextureSampleType) -> BindGroupLayoutEntry {
+            BindGroupLayoutEntry {
+                binding,
+                visibility: ShaderStages::FRAGMENT | ShaderStages::COMPUTE,
+                ty: BindingType::Texture { sample_type, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false },
+                count: None,
+            }
+        }
+        let float = wgpu::TextureSampleType::Float { filterable: true };
+        let layout = BindGroupDesc {
+            entries: vec![
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT | ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT | ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: POST_BIND_GROUP.into(),
+        };
+        let source_layout = BindGroupDesc {
+            entries: vec![
+                texture_entry(0, float),
+                texture_entry(1, float),
+                texture_entry(2, wgpu::TextureSampleType::Depth),
+                texture_entry(3, float),
+            ],
+            label: POST_SOURCE_BIND_GROUP.into(),
+        };
+
+        let shader = Shader::from_modules(
+            assets,
+            "PostProcessing",
+            [&ShaderModule::new("PostProcessing", include_file!("post.wgsl"), vec![layout.into(), source_layout.into()])],
+        );
+        let pipeline = |fs_main: &str, target: wgpu::ColorTargetState| {
+            shader.to_pipeline(
+                &gpu,
+                GraphicsPipelineInfo {
+                    fs_main,
+                    targets: &[Some(target)],
+                    topology: PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+            )
+        };
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        let params_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("PostProcessing.params"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            size: std::mem::size_of::<PostParams>() as u64,
+            mapped_at_creation: false,
+        });
+        let exposure_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("PostProcessing.exposure"),
+            usage: wgpu::BufferUsages::STORAGE,
+            size: std::mem::size_of::<f32>() as u64,
+            mapped_at_creation: false,
+        });
+        let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("PostProcessing.sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: shader.get_bind_group_layout_by_name(POST_BIND_GROUP).unwrap(),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: params_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: exposure_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::Sampler(&sampler) },
+            ],
+            label: Some("PostProcessing.bind_group"),
+        });
+
+        Self {
+            frame: Self::create_frame(gpu.clone(), wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 }),
+            bloom: None,
+            bloom_enabled: bloom,
+            luminance: shader.to_compute_pipeline(&gpu, "cs_luminance"),
+            bright: pipeline("fs_bright", HDR_FORMAT.into()),
+            downsample: pipeline("fs_downsample", HDR_FORMAT.into()),
+            upsample: pipeline(
+                "fs_upsample",
+                wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(wgpu::BlendState { color: additive, alpha: additive }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                },
+            ),
+            composite: None,
+            params_buffer,
+            bind_group,
+            source_layout: shader.get_bind_group_layout_by_name(POST_SOURCE_BIND_GROUP).unwrap().clone(),
+            shader,
+            dummy_bloom: Arc::new(Texture::new_single_color_texture(gpu.clone(), UVec4::ZERO)).create_view(&Default::default()),
+            last_frame: None,
+            exposure_adapted: false,
+            gpu,
+        }
+    }
+
+    fn create_composite(&self, format: wgpu::TextureFormat) -> GraphicsPipeline {
+        self.shader.to_pipeline(
+            &self.gpu,
+            GraphicsPipelineInfo {
+                fs_main: "fs_composite",
+                targets: &[Some(format.into()), Some(wgpu::TextureFormat::Rgba8Snorm.into())],
+                depth: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                topology: PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn create_frame(gpu: Arc<Gpu>, size: wgpu::Extent3d) -> RenderTarget {
+        RenderTarget::with_color_format(gpu, uvec2(size.width, size.height), HDR_FORMAT, None)
+    }
+
+    /// Matches the size of the frame to the one of the target
+    pub fn resize(&mut self, size: wgpu::Extent3d) {
+        if self.frame.color_buffer.size != size {
+            self.frame = Self::create_frame(self.gpu.clone(), size);
+            self.bloom = None;
+        }
+    }
+
+    /// Maps the frame to the `target`, and passes its depth and normals on there for what's rendered after
+    pub fn render(&mut self, encoder: &mut wgpu::CommandEncoder, settings: &PostSettings, target: &RendererTarget) {
+        profiling::scope!("PostProcessing");
+        let now = Instant::now();
+        let dt = self.last_frame.map(|last_frame| now.duration_since(last_frame).as_secs_f32()).unwrap_or_default();
+        self.last_frame = Some(now);
+        // Snaps to the luminance of the frame when auto exposure is turned on, rather than adapting from a stale one
+        let exposure_adaptation = if self.exposure_adapted { 1. - (-dt * AUTO_EXPOSURE_SPEED).exp() } else { 1. };
+        self.exposure_adapted = settings.auto_exposure;
+        // The target may be the swapchain, or a texture of another format
+        let format = target.format();
+        if self.composite.as_ref().map_or(true, |(composite_format, _)| *composite_format != format) {
+            self.composite = Some((format, self.create_composite(format)));
+        }
+
+        let bloom_enabled = self.bloom_enabled && settings.bloom_intensity > 0.;
+        if bloom_enabled && self.bloom.is_none() {
+            self.bloom = Some(Bloom::new(self.gpu.clone(), self.frame.color_buffer.size));
+        }
+        let bloom = self.bloom.as_ref().filter(|_| bloom_enabled);
+        let params = PostParams {
+            exposure: settings.exposure,
+            auto_exposure: settings.auto_exposure as u32,
+            exposure_adaptation,
+            bloom_threshold: settings.bloom_threshold,
+            bloom_intensity: settings.bloom_intensity,
+            bloom_mips: bloom.map(|bloom| bloom.mips.len() as u32).unwrap_or_default(),
+            tonemapper: settings.tonemapper,
+            _padding: 0,
+        };
+        self.gpu.queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let frame = &self.frame.color_buffer_view;
+        if settings.auto_exposure {
+            let source_bind_group = self.create_source_bind_group(frame, &self.dummy_bloom);
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("PostProcessing.luminance") });
+            cpass.set_pipeline(self.luminance.pipeline());
+            self.luminance.bind(&mut cpass, POST_BIND_GROUP, &self.bind_group);
+            self.luminance.bind(&mut cpass, POST_SOURCE_BIND_GROUP, &source_bind_group);
+            cpass.dispatch_workgroups(1, 1, 1);
+        }
+        if let Some(bloom) = bloom {
+            self.run_pass(encoder, &self.bright, frame, &bloom.mips[0], wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+            for mip in 1..bloom.mips.len() {
+                self.run_pass(encoder, &self.downsample, &bloom.mips[mip - 1], &bloom.mips[mip], wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+            }
+            for mip in (1..bloom.mips.len()).rev() {
+                self.run_pass(encoder, &self.upsample, &bloom.mips[mip], &bloom.mips[mip - 1], wgpu::LoadOp::Load);
+            }
+        }
+        {
+            let source_bind_group = self.create_source_bind_group(frame, bloom.map(|bloom| &bloom.mips[0]).unwrap_or(&self.dummy_bloom));
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("PostProcessing.composite"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: target.color(),
+                        resolve_target: None,
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: true },
+                    }),
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: target.normals(),
+                        resolve_target: None,
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: true },
+                    }),
+                ],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: target.depth(),
+                    depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(0.0), store: true }),
+                    stencil_ops: None,
+                }),
+            });
+            let (_, composite) = self.composite.as_ref().unwrap();
+            rpass.set_pipeline(composite.pipeline());
+            composite.bind(&mut rpass, POST_BIND_GROUP, &self.bind_group);
+            composite.bind(&mut rpass, POST_SOURCE_BIND_GROUP, &source_bind_group);
+            rpass.draw(0..4, 0..1);
+        }
+    }
+
+    fn create_source_bind_group(&self, source: &wgpu::TextureView, bloom: &wgpu::TextureView) -> wgpu::BindGroup {
+        self.gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.source_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(source) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(bloom) },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&self.frame.depth_buffer_view) },
+                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&self.frame.normals_quat_buffer_view) },
+            ],
+            label: Some("PostProcessing.source_bind_group"),
+        })
+    }
+
+    fn run_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &GraphicsPipeline,
+        source: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) {
+        let source_bind_group = self.create_source_bind_group(source, &self.dummy_bloom);
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("PostProcessing.bloom"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            })],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(pipeline.pipeline());
+        pipeline.bind(&mut rpass, POST_BIND_GROUP, &self.bind_group);
+        pipeline.bind(&mut rpass, POST_SOURCE_BIND_GROUP, &source_bind_group);
+        rpass.draw(0..4, 0..1);
+    }
+}
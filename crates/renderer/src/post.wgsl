@@ -0,0 +1,200 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texcoord: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let x = i32(vertex_index) / 2;
+    let y = i32(vertex_index) & 1;
+    let tc = vec2<f32>(
+        f32(x) * 2.0,
+        f32(y) * 2.0
+    );
+    out.position = vec4<f32>(
+        tc.x * 2.0 - 1.0,
+        1.0 - tc.y * 2.0,
+        0.0, 1.0
+    );
+    out.texcoord = tc;
+    return out;
+}
+
+struct PostParams {
+    exposure: f32,
+    auto_exposure: u32,
+    exposure_adaptation: f32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    bloom_mips: u32,
+    tonemapper: u32,
+    _padding: u32,
+};
+
+struct ExposureState {
+    average_log2_luminance: f32,
+};
+
+@group(#POST_BIND_GROUP)
+@binding(0)
+var<uniform> params: PostParams;
+
+@group(#POST_BIND_GROUP)
+@binding(1)
+var<storage, read_write> exposure_state: ExposureState;
+
+@group(#POST_BIND_GROUP)
+@binding(2)
+var post_sampler: sampler;
+
+@group(#POST_SOURCE_BIND_GROUP)
+@binding(0)
+var source: texture_2d<f32>;
+
+@group(#POST_SOURCE_BIND_GROUP)
+@binding(1)
+var bloom: texture_2d<f32>;
+
+@group(#POST_SOURCE_BIND_GROUP)
+@binding(2)
+var frame_depth: texture_depth_2d;
+
+@group(#POST_SOURCE_BIND_GROUP)
+@binding(3)
+var frame_normals: texture_2d<f32>;
+
+let MIDDLE_GREY: f32 = 0.18;
+// Keeps a few very bright pixels from flickering as large blobs of bloom
+let MAX_BLOOM: f32 = 64.;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// What the colors of the frame are multiplied with; auto exposure brings the average luminance to middle grey
+fn exposure() -> f32 {
+    var stops = params.exposure;
+    if (params.auto_exposure != 0u) {
+        stops += log2(MIDDLE_GREY) - exposure_state.average_log2_luminance;
+    }
+    return exp2(stops);
+}
+
+var<workgroup> log2_luminances: array<f32, 256>;
+
+// Averages the log luminance of a grid of 64x64 points over the frame, and moves the adapted luminance towards it
+@compute
+@workgroup_size(16, 16)
+fn cs_luminance(@builtin(local_invocation_id) id: vec3<u32>, @builtin(local_invocation_index) index: u32) {
+    let size = vec2<f32>(textureDimensions(source));
+    var sum = 0.;
+    for (var y = 0u; y < 4u; y += 1u) {
+        for (var x = 0u; x < 4u; x += 1u) {
+            let texcoord = (vec2<f32>(id.xy * 4u + vec2<u32>(x, y)) + 0.5) / 64.;
+            sum += log2(max(luminance(textureLoad(source, vec2<i32>(texcoord * size), 0).rgb), 0.0001));
+        }
+    }
+    log2_luminances[index] = sum / 16.;
+    workgroupBarrier();
+    for (var stride = 128u; stride > 0u; stride /= 2u) {
+        if (index < stride) {
+            log2_luminances[index] += log2_luminances[index + stride];
+        }
+        workgroupBarrier();
+    }
+    if (index == 0u) {
+        let average = clamp(log2_luminances[0] / 256., -16., 16.);
+        exposure_state.average_log2_luminance = mix(exposure_state.average_log2_luminance, average, params.exposure_adaptation);
+    }
+}
+
+fn sample_source(texcoord: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(source, post_sampler, texcoord, 0.);
+}
+
+// Halves the resolution of the source with four bilinear taps, which average 4x4 texels
+fn downsample(texcoord: vec2<f32>) -> vec3<f32> {
+    let texel = 1. / vec2<f32>(textureDimensions(source));
+    return (
+        sample_source(texcoord + texel * vec2<f32>(-1., -1.)).rgb +
+        sample_source(texcoord + texel * vec2<f32>(1., -1.)).rgb +
+        sample_source(texcoord + texel * vec2<f32>(-1., 1.)).rgb +
+        sample_source(texcoord + texel * vec2<f32>(1., 1.)).rgb
+    ) * 0.25;
+}
+
+// Keeps what's brighter than the bloom threshold after exposure, with a soft knee so that pixels start glowing gradually
+@fragment
+fn fs_bright(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = downsample(in.texcoord) * exposure();
+    let brightness = max(color.r, max(color.g, color.b));
+    let threshold = params.bloom_threshold;
+    let knee = threshold * 0.5;
+    let soft = clamp(brightness - threshold + knee, 0., 2. * knee);
+    let contribution = max(soft * soft / (4. * knee + 0.0001), brightness - threshold) / max(brightness, 0.0001);
+    return vec4<f32>(min(color * contribution, vec3<f32>(MAX_BLOOM)), 1.);
+}
+
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.texcoord), 1.);
+}
+
+// Blurs the smaller mip with a 3x3 tent filter, to be added onto the larger one
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1. / vec2<f32>(textureDimensions(source));
+    var color = sample_source(in.texcoord).rgb * 4.;
+    color += (
+        sample_source(in.texcoord + texel * vec2<f32>(-1., 0.)).rgb +
+        sample_source(in.texcoord + texel * vec2<f32>(1., 0.)).rgb +
+        sample_source(in.texcoord + texel * vec2<f32>(0., -1.)).rgb +
+        sample_source(in.texcoord + texel * vec2<f32>(0., 1.)).rgb
+    ) * 2.;
+    color += (
+        sample_source(in.texcoord + texel * vec2<f32>(-1., -1.)).rgb +
+        sample_source(in.texcoord + texel * vec2<f32>(1., -1.)).rgb +
+        sample_source(in.texcoord + texel * vec2<f32>(-1., 1.)).rgb +
+        sample_source(in.texcoord + texel * vec2<f32>(1., 1.)).rgb
+    );
+    return vec4<f32>(color / 16., 1.);
+}
+
+// The fit of the ACES filmic curve by Krzysztof Narkowicz:
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+fn tonemap_aces(color: vec3<f32>) -> vec3<f32> {
+    return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), vec3<f32>(0.), vec3<f32>(1.));
+}
+
+fn tonemap(color: vec3<f32>) -> vec3<f32> {
+    if (params.tonemapper == 1u) {
+        return tonemap_aces(color);
+    } else if (params.tonemapper == 2u) {
+        return color / (1. + luminance(color));
+    }
+    return clamp(color, vec3<f32>(0.), vec3<f32>(1.));
+}
+
+struct CompositeOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+};
+
+// Exposes the frame, adds the bloom and maps the result to the range of the display. The depth and normals of the
+// frame are passed on, for what's rendered on top of it
+@fragment
+fn fs_composite(in: VertexOutput) -> CompositeOutput {
+    let p = vec2<i32>(in.position.xy);
+    let frame = textureLoad(source, p, 0);
+    var color = frame.rgb * exposure();
+    if (params.bloom_mips > 0u) {
+        color += textureSampleLevel(bloom, post_sampler, in.texcoord, 0.).rgb * params.bloom_intensity / f32(params.bloom_mips);
+    }
+    var out: CompositeOutput;
+    out.color = vec4<f32>(tonemap(color), frame.a);
+    out.normal = textureLoad(frame_normals, p, 0);
+    out.depth = textureLoad(frame_depth, p, 0);
+    return out;
+}
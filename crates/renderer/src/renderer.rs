@@ -16,18 +16,31 @@ use ambient_gpu::{
 use ambient_std::{
     asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt},
     color::Color,
+    colorspace::RgbColorSpace,
+    watched_config::Config,
 };
 use glam::uvec2;
+use serde::{Deserialize, Serialize};
 use wgpu::{BindGroupLayout, TextureView};
 
 use super::{
     get_common_module, get_globals_module, get_resources_module,
     overlay_renderer::{OverlayConfig, OverlayRenderer},
+    post::{PostProcessing, PostSettings, HDR_FORMAT},
     shadow_renderer::ShadowsRenderer,
-    Culling, FSMain, ForwardGlobals, Outlines, OutlinesConfig, RenderTarget, RendererCollect, RendererCollectState, TransparentRenderer,
-    TransparentRendererConfig, TreeRenderer, TreeRendererConfig,
+    Culling, FSMain, ForwardGlobals, Outlines, OutlinesConfig, RenderTarget, RendererCollect, RendererCollectState, RendererStartTimeKey,
+    TransparentRenderer, TransparentRendererConfig, TreeRenderer, TreeRendererConfig, FIRST_PASS_CAMERA, MAX_SHADOW_CASCADES,
+    SECOND_PASS_CAMERA,
+};
+use crate::{
+    blob_shadows::BlobShadowRenderer,
+    depth_pyramid::DepthPyramid,
+    impostors::ImpostorRenderer,
+    sampler::{MaterialSamplerKey, TextureFiltering, TextureQuality, TextureSampling},
+    skinning::SkinsBufferKey,
+    trails::TrailRenderer,
+    ShaderDebugParams,
 };
-use crate::{skinning::SkinsBufferKey, ShaderDebugParams};
 pub const GLOBALS_BIND_GROUP: &str = "GLOBALS_BIND_GROUP";
 pub const MATERIAL_BIND_GROUP: &str = "MATERIAL_BIND_GROUP";
 pub const RESOURCES_BIND_GROUP: &str = "RESOURCES_BIND_GROUP";
@@ -76,18 +89,187 @@ pub struct RendererConfig {
     pub shadows: bool,
     pub shadow_map_resolution: u32,
     pub shadow_cascades: u32,
+    pub shadow_cascade_splits: ShadowCascadeSplits,
+    pub shadow_pcf_kernel: u32,
+    pub shadow_depth_bias: i32,
+    pub shadow_slope_bias: f32,
     pub lod_cutoff_scaling: f32,
+    /// How the textures of the materials which don't have sampling settings of their own are sampled
+    pub texture_sampling: TextureSampling,
+    /// Renders the scene in high dynamic range, and maps it to the target with the exposure, bloom and tone mapping of
+    /// the active camera. The frame replaces what's in the target rather than being drawn over it
+    pub hdr: bool,
+    /// Whether the cameras can have bloom, when rendering in HDR
+    pub bloom: bool,
+    /// Skips drawing the entities which are hidden behind others, by testing them against a depth pyramid of the ones
+    /// which were visible last frame
+    pub occlusion_culling: bool,
 }
 
 impl Default for RendererConfig {
     fn default() -> Self {
-        Self { scene: ui_scene(), shadows: true, shadow_map_resolution: 1024, shadow_cascades: 5, lod_cutoff_scaling: 1. }
+        Self {
+            scene: ui_scene(),
+            shadows: true,
+            shadow_map_resolution: 1024,
+            shadow_cascades: 4,
+            shadow_cascade_splits: Default::default(),
+            shadow_pcf_kernel: 3,
+            shadow_depth_bias: 2,
+            shadow_slope_bias: 1.5,
+            lod_cutoff_scaling: 1.,
+            texture_sampling: Default::default(),
+            hdr: false,
+            bloom: true,
+            occlusion_culling: false,
+        }
+    }
+}
+
+impl RendererConfig {
+    pub fn with_settings(self, settings: &RendererSettings) -> Self {
+        Self {
+            shadows: self.shadows && settings.shadows,
+            shadow_map_resolution: settings.shadow_map_resolution,
+            shadow_cascades: settings.shadow_cascades,
+            shadow_cascade_splits: settings.shadow_cascade_splits.clone(),
+            shadow_pcf_kernel: settings.shadow_pcf_kernel,
+            shadow_depth_bias: settings.shadow_depth_bias,
+            shadow_slope_bias: settings.shadow_slope_bias,
+            lod_cutoff_scaling: settings.lod_cutoff_scaling,
+            texture_sampling: settings.texture_sampling(),
+            hdr: self.hdr && settings.hdr,
+            bloom: settings.bloom,
+            occlusion_culling: self.occlusion_culling && settings.occlusion_culling,
+            ..self
+        }
+    }
+    /// The format of the color target the scene is rendered to
+    pub fn color_format(&self, gpu: &Gpu) -> wgpu::TextureFormat {
+        if self.hdr {
+            HDR_FORMAT
+        } else {
+            gpu.swapchain_format()
+        }
     }
 }
 
+/// The quality settings of the [Renderer], which can be tuned live from a file with
+/// [ambient_std::watched_config::WatchedConfig]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RendererSettings {
+    pub shadows: bool,
+    /// The width and height of each cascade of the sun's shadow map
+    pub shadow_map_resolution: u32,
+    pub shadow_cascades: u32,
+    /// Where the shadow distance of the camera is split between the cascades
+    pub shadow_cascade_splits: ShadowCascadeSplits,
+    /// The width of the square of texels which are sampled to soften the shadows (percentage-closer filtering); 1 takes a
+    /// single sample
+    pub shadow_pcf_kernel: u32,
+    /// The constant depth bias of the shadow casters, which keeps surfaces from shadowing themselves (shadow acne)
+    pub shadow_depth_bias: i32,
+    /// The depth bias of the shadow casters which scales with their slope relative to the sun
+    pub shadow_slope_bias: f32,
+    pub lod_cutoff_scaling: f32,
+    /// The preset the sampling of the textures of the materials starts from
+    pub texture_quality: TextureQuality,
+    /// Overrides the filtering of the [Self::texture_quality] preset
+    pub texture_filtering: Option<TextureFiltering>,
+    /// Overrides the anisotropy of the [Self::texture_quality] preset
+    pub texture_anisotropy: Option<u8>,
+    /// Overrides the mip bias of the [Self::texture_quality] preset
+    pub texture_mip_bias: Option<f32>,
+    /// Renders the 3D scene in high dynamic range, which the exposure, bloom and tone mapping of its camera need; turning it
+    /// off saves memory and bandwidth on weak GPUs
+    pub hdr: bool,
+    /// Whether the cameras can have bloom; it takes a few blurring passes each frame
+    pub bloom: bool,
+    /// Skips drawing what's hidden behind other objects, at the cost of a second forward pass; it pays off in dense scenes
+    pub occlusion_culling: bool,
+    /// The initial [crate::render_scale] of the 3D scene
+    pub render_scale: f32,
+    /// The color space of the display, which the sRGB colors of the frames are converted to; e.g. `DisplayP3` for a wide
+    /// gamut display in its native mode, which would show them oversaturated otherwise
+    pub output_color_space: RgbColorSpace,
+}
+impl Default for RendererSettings {
+    fn default() -> Self {
+        let config = RendererConfig::default();
+        Self {
+            shadows: config.shadows,
+            shadow_map_resolution: config.shadow_map_resolution,
+            shadow_cascades: config.shadow_cascades,
+            shadow_cascade_splits: config.shadow_cascade_splits,
+            shadow_pcf_kernel: config.shadow_pcf_kernel,
+            shadow_depth_bias: config.shadow_depth_bias,
+            shadow_slope_bias: config.shadow_slope_bias,
+            lod_cutoff_scaling: config.lod_cutoff_scaling,
+            texture_quality: Default::default(),
+            texture_filtering: None,
+            texture_anisotropy: None,
+            texture_mip_bias: None,
+            hdr: true,
+            bloom: config.bloom,
+            occlusion_culling: true,
+            render_scale: 1.,
+            output_color_space: RgbColorSpace::Srgb,
+        }
+    }
+}
+impl RendererSettings {
+    /// The [TextureSampling] of the [Self::texture_quality] preset, with the overrides applied
+    pub fn texture_sampling(&self) -> TextureSampling {
+        let preset = self.texture_quality.sampling();
+        TextureSampling {
+            filtering: self.texture_filtering.unwrap_or(preset.filtering),
+            anisotropy: self.texture_anisotropy.unwrap_or(preset.anisotropy),
+            mip_bias: self.texture_mip_bias.unwrap_or(preset.mip_bias),
+        }
+    }
+}
+impl Config for RendererSettings {
+    fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.shadow_map_resolution.is_power_of_two() && (128..=8192).contains(&self.shadow_map_resolution),
+            "shadow_map_resolution must be a power of two between 128 and 8192"
+        );
+        anyhow::ensure!(
+            (1..=MAX_SHADOW_CASCADES).contains(&self.shadow_cascades),
+            "shadow_cascades must be between 1 and {MAX_SHADOW_CASCADES}"
+        );
+        if let ShadowCascadeSplits::Distances(distances) = &self.shadow_cascade_splits {
+            anyhow::ensure!(
+                distances.len() + 1 == self.shadow_cascades as usize,
+                "shadow_cascade_splits must have a distance for each cascade but the last"
+            );
+            anyhow::ensure!(
+                distances.iter().all(|d| *d > 0.) && distances.windows(2).all(|w| w[0] < w[1]),
+                "shadow_cascade_splits distances must be positive and increasing"
+            );
+        }
+        anyhow::ensure!(self.shadow_pcf_kernel % 2 == 1 && self.shadow_pcf_kernel <= 7, "shadow_pcf_kernel must be 1, 3, 5 or 7");
+        anyhow::ensure!(
+            self.shadow_depth_bias >= 0 && self.shadow_slope_bias >= 0.,
+            "shadow_depth_bias and shadow_slope_bias can't be negative"
+        );
+        anyhow::ensure!(self.lod_cutoff_scaling > 0., "lod_cutoff_scaling must be positive");
+        self.texture_sampling().validate()?;
+        anyhow::ensure!((0.25..=2.).contains(&self.render_scale), "render_scale must be between 0.25 and 2");
+        Ok(())
+    }
+}
+
+/// The file to load the [RendererSettings] of the game from, if any
+#[derive(Clone, Debug)]
+pub struct RendererSettingsPath;
+impl SyncAssetKey<std::path::PathBuf> for RendererSettingsPath {}
+
+#[derive(Clone, Copy)]
 pub enum RendererTarget<'a> {
     Target(&'a RenderTarget),
-    Direct { color: &'a TextureView, depth: &'a TextureView, normals: &'a TextureView, size: wgpu::Extent3d },
+    Direct { color: &'a TextureView, depth: &'a TextureView, normals: &'a TextureView, size: wgpu::Extent3d, format: wgpu::TextureFormat },
 }
 impl<'a> RendererTarget<'a> {
     pub fn color(&self) -> &'a TextureView {
@@ -114,6 +296,13 @@ impl<'a> RendererTarget<'a> {
             RendererTarget::Direct { size, .. } => *size,
         }
     }
+    /// The format of the color target
+    pub fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            RendererTarget::Target(target) => target.color_buffer.format,
+            RendererTarget::Direct { format, .. } => *format,
+        }
+    }
 }
 
 pub trait SubRenderer: std::fmt::Debug + Send + Sync {
@@ -136,11 +325,18 @@ pub struct Renderer {
     pub shadows: Option<ShadowsRenderer>,
     forward_globals: ForwardGlobals,
     forward_collect_state: RendererCollectState,
+    /// What the second forward pass draws, with occlusion culling
+    second_pass_collect_state: RendererCollectState,
+    depth_pyramid: Option<DepthPyramid>,
     forward: TreeRenderer,
     overlays: OverlayRenderer,
+    blob_shadows: BlobShadowRenderer,
     transparent: TransparentRenderer,
+    trails: TrailRenderer,
+    impostors: ImpostorRenderer,
     solids_frame: RenderTarget,
     outlines: Outlines,
+    post: Option<PostProcessing>,
     pub post_forward: Option<Box<dyn SubRenderer>>,
     pub post_transparent: Option<Box<dyn SubRenderer>>,
 }
@@ -158,8 +354,17 @@ impl Renderer {
 
         Self {
             culling: Culling::new(&assets, config.clone()),
-            forward_globals: ForwardGlobals::new(gpu.clone(), renderer_resources.globals_layout.clone(), shadow_cascades, config.scene),
+            forward_globals: ForwardGlobals::new(
+                gpu.clone(),
+                renderer_resources.globals_layout.clone(),
+                shadow_cascades,
+                MaterialSamplerKey::from(&config.texture_sampling).get(&assets),
+                config.scene,
+                RendererStartTimeKey.get(&assets),
+            ),
             forward_collect_state: RendererCollectState::new(&assets),
+            second_pass_collect_state: RendererCollectState::new(&assets),
+            depth_pyramid: if config.occlusion_culling { Some(DepthPyramid::new(&assets)) } else { None },
             shadows,
             overlays: OverlayRenderer::new(
                 assets.clone(),
@@ -167,15 +372,16 @@ impl Renderer {
                 OverlayConfig {
                     fs_main: FSMain::Forward,
                     gpu: gpu.clone(),
-                    targets: vec![Some(gpu.swapchain_format().into())],
+                    targets: vec![Some(config.color_format(&gpu).into())],
                     resources: renderer_resources.clone(),
                 },
             ),
+            blob_shadows: BlobShadowRenderer::new(&assets, &config),
             forward: TreeRenderer::new(TreeRendererConfig {
                 gpu: gpu.clone(),
                 assets: assets.clone(),
                 renderer_config: config.clone(),
-                targets: vec![Some(gpu.swapchain_format().into()), Some(wgpu::TextureFormat::Rgba8Snorm.into())],
+                targets: vec![Some(config.color_format(&gpu).into()), Some(wgpu::TextureFormat::Rgba8Snorm.into())],
                 filter: ArchetypeFilter::new().incl(config.scene),
                 renderer_resources: renderer_resources.clone(),
                 fs_main: FSMain::Forward,
@@ -189,7 +395,7 @@ impl Renderer {
                 assets: assets.clone(),
                 renderer_config: config.clone(),
                 targets: vec![Some(wgpu::ColorTargetState {
-                    format: gpu.swapchain_format(),
+                    format: config.color_format(&gpu),
                     blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -198,9 +404,12 @@ impl Renderer {
                 fs_main: FSMain::Forward,
                 render_opaque: false,
             }),
-            solids_frame: RenderTarget::new(
+            trails: TrailRenderer::new(&assets, &config),
+            impostors: ImpostorRenderer::new(&assets, &config, &renderer_resources),
+            solids_frame: RenderTarget::with_color_format(
                 gpu.clone(),
                 uvec2(1, 1),
+                config.color_format(&gpu),
                 Some(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST),
             ),
             outlines: Outlines::new(
@@ -208,6 +417,7 @@ impl Renderer {
                 OutlinesConfig { scene: config.scene, renderer_resources: renderer_resources.clone() },
                 config.clone(),
             ),
+            post: if config.hdr { Some(PostProcessing::new(&assets, config.bloom)) } else { None },
             resources_layout: renderer_resources.resources_layout,
             config,
             shader_debug_params: Default::default(),
@@ -227,11 +437,22 @@ impl Renderer {
     ) {
         profiling::scope!("Renderer.render");
 
+        // With post-processing, the scene is rendered to its HDR frame, which is mapped to the target at the end
+        let output = target;
+        if let Some(post) = &mut self.post {
+            post.resize(output.size());
+        }
+        let (target, clear) = match &self.post {
+            Some(post) => (RendererTarget::Target(&post.frame), Some(clear.unwrap_or(Color::rgba(0., 0., 0., 0.)))),
+            None => (output, clear),
+        };
+
         if let RendererTarget::Target(target) = &target {
             if self.solids_frame.color_buffer.size != target.color_buffer.size {
-                self.solids_frame = RenderTarget::new(
+                self.solids_frame = RenderTarget::with_color_format(
                     self.gpu.clone(),
                     uvec2(target.color_buffer.size.width, target.color_buffer.size.height),
+                    self.config.color_format(&self.gpu),
                     Some(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST),
                 );
             }
@@ -250,9 +471,10 @@ impl Renderer {
         let main_camera = Camera::get_active(world, self.config.scene).unwrap_or_default();
         {
             profiling::scope!("Update");
+            self.impostors.update(world, encoder, &main_camera);
             self.culling.run(encoder, world, &Default::default());
 
-            self.forward_collect_state.set_camera(0);
+            self.forward_collect_state.set_camera(FIRST_PASS_CAMERA);
             self.forward.update(world);
             self.overlays.update(world);
             self.forward.run_collect(encoder, post_submit, &resources_bind_group, &entities_bind_group, &mut self.forward_collect_state);
@@ -264,6 +486,8 @@ impl Renderer {
         }
 
         self.forward_globals.params.debug_params = self.shader_debug_params;
+        self.forward_globals.params.shadow_pcf_radius = (self.config.shadow_pcf_kernel / 2) as i32;
+        self.forward_globals.params.texture_mip_bias = self.config.texture_sampling.mip_bias;
         self.forward_globals.update(world, &self.shadows.as_ref().map(|x| x.get_cameras()).unwrap_or_default());
         let forward_globals_bind_group = self.forward_globals.create_bind_group(
             world.resource(asset_cache()).clone(),
@@ -318,6 +542,50 @@ impl Renderer {
             }
         }
 
+        if let Some(depth_pyramid) = &mut self.depth_pyramid {
+            profiling::scope!("Forward second pass");
+            depth_pyramid.build(encoder, target.depth(), target.size());
+            if let Some(depth_pyramid) = &depth_pyramid.view {
+                self.culling.run_occlusion(encoder, world, depth_pyramid, &Default::default());
+            }
+            self.second_pass_collect_state.set_camera(SECOND_PASS_CAMERA);
+            self.forward.run_collect(
+                encoder,
+                post_submit,
+                &resources_bind_group,
+                &entities_bind_group,
+                &mut self.second_pass_collect_state,
+            );
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Forward second pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: target.color(),
+                        resolve_target: None,
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: true },
+                    }),
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: target.normals(),
+                        resolve_target: None,
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: true },
+                    }),
+                ],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: target.depth(),
+                    depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Load, store: true }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_index_buffer(mesh_buffer.index_buffer.buffer().slice(..), wgpu::IndexFormat::Uint32);
+
+            self.forward.render(&mut render_pass, &self.second_pass_collect_state, &binds);
+            {
+                profiling::scope!("Drop render pass");
+                drop(render_pass);
+            }
+        }
+
         if let Some(post_forward) = &mut self.post_forward {
             post_forward.render(
                 world,
@@ -328,6 +596,21 @@ impl Renderer {
             );
         }
 
+        self.impostors.capture(
+            world,
+            encoder,
+            post_submit,
+            &self.forward,
+            self.shadows.as_ref(),
+            &self.solids_frame,
+            &resources_bind_group,
+            &entities_bind_group,
+            &mesh_buffer,
+        );
+        self.impostors.render(encoder, &target, &forward_globals_bind_group);
+
+        self.blob_shadows.render(world, encoder, &target, &forward_globals_bind_group, self.shadows.is_some());
+
         {
             let binds = [(GLOBALS_BIND_GROUP, &forward_globals_bind_group)];
 
@@ -377,6 +660,8 @@ impl Renderer {
             }
         }
 
+        self.trails.render(world, encoder, &target, &forward_globals_bind_group, main_camera.position());
+
         if let Some(post_transparent) = &mut self.post_transparent {
             post_transparent.render(
                 world,
@@ -388,6 +673,10 @@ impl Renderer {
         }
 
         self.outlines.render(world, encoder, post_submit, &target, &binds, &mesh_buffer);
+
+        if let Some(post) = &mut self.post {
+            post.render(encoder, &PostSettings::from_world(world, self.config.scene), &output);
+        }
     }
 
     pub fn dump_to_tmp_file(&self) {
@@ -409,11 +698,12 @@ impl Renderer {
     }
     pub fn stats(&self) -> String {
         format!(
-            "{} forward: {}/{} transparent: {}",
+            "{} forward: {}/{} transparent: {} impostors: {}",
             self.shadows.as_ref().map(|x| x.stats()).unwrap_or_default(),
             self.forward.n_entities(),
             self.forward.n_nodes(),
-            self.transparent.n_entities()
+            self.transparent.n_entities(),
+            self.impostors.n_impostors()
         )
     }
     pub fn dump(&self, f: &mut dyn std::io::Write) {
@@ -5,7 +5,7 @@ var<storage> mesh_metadatas: MeshMetadatas;
 
 @group(#RESOURCES_BIND_GROUP)
 @binding(#MESH_POSITION_BINDING)
-var<storage> mesh_position: Vec3Buffer;
+var<storage> mesh_position: UVec4Buffer; // xyz are the f32 bits of the position, w is the RGBA8 vertex color
 @group(#RESOURCES_BIND_GROUP)
 @binding(#MESH_NORMAL_BINDING)
 var<storage> mesh_normal: Vec3Buffer;
@@ -23,7 +23,7 @@ var<storage> mesh_joint: UVec4Buffer;
 var<storage> mesh_weight: Vec4Buffer;
 
 fn get_raw_mesh_position(vertex_index: u32) -> vec3<f32> {
-    return mesh_position.data[vertex_index];
+    return bitcast<vec3<f32>>(mesh_position.data[vertex_index].xyz);
 }
 
 fn get_raw_mesh_uv(vertex_index: u32) -> vec2<f32> {
@@ -31,7 +31,7 @@ fn get_raw_mesh_uv(vertex_index: u32) -> vec2<f32> {
 }
 
 fn get_mesh_position(mesh_id: u32, vertex_index: u32) -> vec3<f32> {
-    return mesh_position.data[mesh_metadatas.data[mesh_id].position_offset + vertex_index];
+    return bitcast<vec3<f32>>(mesh_position.data[mesh_metadatas.data[mesh_id].position_offset + vertex_index].xyz);
 }
 fn get_mesh_normal(mesh_id: u32, vertex_index: u32) -> vec3<f32> {
     return mesh_normal.data[mesh_metadatas.data[mesh_id].normal_offset + vertex_index];
@@ -48,6 +48,10 @@ fn get_mesh_joint(mesh_id: u32, vertex_index: u32) -> vec4<u32> {
 fn get_mesh_weight(mesh_id: u32, vertex_index: u32) -> vec4<f32> {
     return mesh_weight.data[mesh_metadatas.data[mesh_id].weight_offset + vertex_index];
 }
+// White for meshes without vertex colors
+fn get_mesh_color(mesh_id: u32, vertex_index: u32) -> vec4<f32> {
+    return unpack4x8unorm(mesh_position.data[mesh_metadatas.data[mesh_id].position_offset + vertex_index].w);
+}
 
 @group(#RESOURCES_BIND_GROUP)
 @binding(#SKINS_BINDING)
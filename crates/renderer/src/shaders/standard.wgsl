@@ -8,10 +8,10 @@ struct VertexOutput {
     @location(4) world_bitangent: vec3<f32>,
     @location(5) world_normal: vec3<f32>,
     @location(6) local_position: vec3<f32>,
+    @location(7) color: vec4<f32>,
 };
 
-@vertex
-fn vs_main(@builtin(instance_index) instance_index: u32, @builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+fn get_vertex_output(instance_index: u32, vertex_index: u32) -> VertexOutput {
     var out: VertexOutput;
 
     let primitive = primitives.data[instance_index];
@@ -21,6 +21,7 @@ fn vs_main(@builtin(instance_index) instance_index: u32, @builtin(vertex_index)
 
     out.instance_index = instance_index;
     out.texcoord = get_mesh_texcoord0(mesh_index, vertex_index);
+    out.color = get_mesh_color(mesh_index, vertex_index);
 
     let world = model_to_world(entity_loc, mesh_index, vertex_index);
 
@@ -36,6 +37,11 @@ fn vs_main(@builtin(instance_index) instance_index: u32, @builtin(vertex_index)
     return out;
 }
 
+@vertex
+fn vs_main(@builtin(instance_index) instance_index: u32, @builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    return get_vertex_output(instance_index, vertex_index);
+}
+
 fn get_material_in(in: VertexOutput, is_front: bool) -> MaterialInput {
     var material_in: MaterialInput;
     material_in.position = in.position;
@@ -50,6 +56,7 @@ fn get_material_in(in: VertexOutput, is_front: bool) -> MaterialInput {
     material_in.instance_index = in.instance_index;
     material_in.entity_loc = primitives.data[in.instance_index].xy;
     material_in.local_position = in.local_position;
+    material_in.color = in.color;
     return material_in;
 }
 
@@ -83,7 +90,7 @@ fn fs_forward_lit_main(in: VertexOutput, @builtin(front_facing) is_front: bool)
     material.normal = normalize(material.normal);
 
     return MainFsOut(
-        shading(material, in.world_position),
+        shading(material, in.world_position, !has_entity_no_receive_shadows(material_in.entity_loc)),
         quat_from_mat3(material_in.normal_matrix)
     );
 }
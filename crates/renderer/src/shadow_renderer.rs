@@ -15,8 +15,8 @@ use smallvec::SmallVec;
 use wgpu::DepthBiasState;
 
 use super::{
-    cast_shadows, get_active_sun, FSMain, RendererCollectState, RendererResources, ShadowAndUIGlobals, TreeRenderer, TreeRendererConfig,
-    GLOBALS_BIND_GROUP, MAX_SHADOW_CASCADES, RESOURCES_BIND_GROUP,
+    cast_shadows, get_active_sun, no_cast_shadows, FSMain, RendererCollectState, RendererResources, ShadowAndUIGlobals, TreeRenderer,
+    TreeRendererConfig, GLOBALS_BIND_GROUP, MAX_SHADOW_CASCADES, RESOURCES_BIND_GROUP,
 };
 use crate::{default_sun_direction, RendererConfig};
 
@@ -59,13 +59,14 @@ impl ShadowsRenderer {
                 assets: assets.clone(),
                 renderer_config: config.clone(),
                 targets: vec![],
-                filter: ArchetypeFilter::new().incl(main_scene()).incl(cast_shadows()),
+                filter: ArchetypeFilter::new().incl(main_scene()).incl(cast_shadows()).excl(no_cast_shadows()),
                 renderer_resources: renderer_resources.clone(),
                 fs_main: FSMain::Shadow,
                 opaque_only: false,
                 depth_stencil: true,
                 cull_mode: Some(wgpu::Face::Front),
-                depth_bias: DepthBiasState { constant: -2, slope_scale: -1.5, clamp: 0.0 },
+                // The shadow maps are reverse-z, so the casters are pushed away from the sun by a negative bias
+                depth_bias: DepthBiasState { constant: -config.shadow_depth_bias, slope_scale: -config.shadow_slope_bias, clamp: 0.0 },
             }),
             cascades: (0..config.shadow_cascades)
                 .map(|i| ShadowCascade {
@@ -121,6 +122,7 @@ impl ShadowsRenderer {
                 sun_direction,
                 i as u32,
                 self.config.shadow_cascades,
+                &self.config.shadow_cascade_splits,
                 self.config.shadow_map_resolution,
             );
             cascade.globals.update(world, main_scene(), new_camera.projection_view());
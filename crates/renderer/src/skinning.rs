@@ -4,17 +4,22 @@ use std::sync::{
 };
 
 use ambient_core::{
-    asset_cache, gpu_components,
+    asset_cache,
+    bounding::local_bounding_aabb,
+    gpu_components,
     gpu_ecs::{GpuComponentFormat, GpuWorldSyncEvent, MappedComponentToGpuSystem},
-    transform::{inv_local_to_world, local_to_world},
+    transform::{inv_local_to_world, local_to_world, mesh_to_local},
 };
 use ambient_ecs::{components, query, Commands, Description, EntityId, Name, Networked, Store, SystemGroup};
 use ambient_gpu::{
     gpu::{Gpu, GpuKey},
     typed_buffer::TypedBuffer,
 };
-use ambient_std::asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt};
-use glam::Mat4;
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt},
+    shapes::AABB,
+};
+use glam::{Mat4, Vec4};
 use itertools::Itertools;
 use parking_lot::Mutex;
 
@@ -33,6 +38,12 @@ components!("rendering", {
         Description["Contains the matrices for each joint of this skinned mesh.\nThis should be used in combination with `joints`."]
     ]
     joint_matrices: Vec<glam::Mat4>,
+    @[
+        Networked, Store,
+        Name["Joint bounding boxes"],
+        Description["The bounding box of the vertices each joint of this skinned mesh moves, in the bind pose.\nThe `local_bounding_aabb` of the mesh is recomputed from them and the `joint_matrices` every frame, so that it contains the mesh whatever the pose, and the culling tests each of them posed, rather than the bounding sphere of the whole mesh."]
+    ]
+    joint_bounding_aabbs: Vec<Option<AABB>>,
 
     skin: Skin,
 
@@ -41,6 +52,7 @@ components!("rendering", {
 });
 gpu_components! {
     skin() => skin: GpuComponentFormat::U32,
+    joint_bounding_aabbs() => joint_sphere_count: GpuComponentFormat::U32,
 }
 
 #[derive(Debug, Clone)]
@@ -67,27 +79,41 @@ impl SyncAssetKey<Arc<Mutex<SkinsBuffer>>> for SkinsBufferKey {
 // works; keep an index buffer and a data buffer, and re-use indices
 pub struct SkinsBuffer {
     pub buffer: TypedBuffer<Mat4>,
+    /// The bounding sphere in world space of the vertices each joint moves, as the center and radius, at the same offsets as
+    /// the joint matrices; the joints which don't move any vertex have a negative radius
+    pub joint_spheres: TypedBuffer<Vec4>,
 }
 impl SkinsBuffer {
     fn new(gpu: Arc<Gpu>) -> Self {
         Self {
             buffer: TypedBuffer::new(
-                gpu,
+                gpu.clone(),
                 "SkinsBuffer.buffer",
                 1,
                 1,
                 wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
             ),
+            joint_spheres: TypedBuffer::new(
+                gpu,
+                "SkinsBuffer.joint_spheres",
+                1,
+                1,
+                wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            ),
         }
     }
     pub fn create(&mut self, size: u32) -> Skin {
         let skin = Skin(Arc::new(AtomicU32::new(self.buffer.len() as u32)));
         self.buffer.resize(self.buffer.len() + size as u64, true);
+        self.joint_spheres.resize(self.buffer.len(), true);
         skin
     }
     pub fn update(&self, skin: &Skin, joint_matrices: &[Mat4]) {
         self.buffer.write(skin.get_offset() as u64, joint_matrices);
     }
+    pub fn update_joint_spheres(&self, skin: &Skin, joint_spheres: &[Vec4]) {
+        self.joint_spheres.write(skin.get_offset() as u64, joint_spheres);
+    }
 }
 
 pub fn skinning_systems() -> SystemGroup {
@@ -108,6 +134,17 @@ pub fn skinning_systems() -> SystemGroup {
                     })
                     .collect_vec();
                 skins.update(skin, &joint_matrices);
+                // The bind pose bounds don't contain the mesh once it's animated, so they follow the pose
+                if let Ok(joint_aabbs) = world.get_ref(id, joint_bounding_aabbs()) {
+                    // The vertices are skinned in the space of the mesh, as on the gpu
+                    let mesh_to_local = world.get(id, mesh_to_local()).unwrap_or_default();
+                    match skinned_aabb(joint_aabbs, &joint_matrices, mesh_to_local) {
+                        Some(aabb) if world.has_component(id, local_bounding_aabb()) => commands.set(id, local_bounding_aabb(), aabb),
+                        _ => {}
+                    }
+                    let mesh_to_world = world.get(id, local_to_world()).unwrap_or_default() * mesh_to_local;
+                    skins.update_joint_spheres(skin, &joint_spheres(joint_aabbs, &joint_matrices, mesh_to_world));
+                }
                 commands.set(id, self::joint_matrices(), joint_matrices);
             }
             commands.apply(world).unwrap();
@@ -115,14 +152,129 @@ pub fn skinning_systems() -> SystemGroup {
     )
 }
 
+/// The bounding box of a skinned mesh posed by `joint_matrices` and then transformed by `mesh_to_local`, from the bounding
+/// boxes of the vertices each joint moves
+pub fn skinned_aabb(joint_aabbs: &[Option<AABB>], joint_matrices: &[Mat4], mesh_to_local: Mat4) -> Option<AABB> {
+    let aabbs = joint_aabbs
+        .iter()
+        .zip(joint_matrices)
+        .filter_map(|(aabb, &joint_matrix)| aabb.map(|aabb| aabb.transform(&(mesh_to_local * joint_matrix)).to_aabb()))
+        .collect_vec();
+    AABB::unions(&aabbs)
+}
+
+/// The bounding spheres of the vertices each joint of a skinned mesh posed by `joint_matrices` moves, transformed by
+/// `mesh_to_world`, with one per joint matrix. A skinned vertex is blended between its joints, so it's within the convex
+/// hull of these spheres: the mesh is out of view if they're all on the outer side of the same plane of the frustum.
+pub fn joint_spheres(joint_aabbs: &[Option<AABB>], joint_matrices: &[Mat4], mesh_to_world: Mat4) -> Vec<Vec4> {
+    joint_matrices
+        .iter()
+        .enumerate()
+        .map(|(joint, &joint_matrix)| match joint_aabbs.get(joint).copied().flatten() {
+            Some(aabb) => {
+                let sphere = aabb.transform(&(mesh_to_world * joint_matrix)).to_sphere();
+                sphere.center.extend(sphere.radius)
+            }
+            None => Vec4::new(0., 0., 0., -1.),
+        })
+        .collect()
+}
+
 pub fn gpu_world_systems() -> SystemGroup<GpuWorldSyncEvent> {
     SystemGroup::new(
         "skinning/gpu_world",
-        vec![Box::new(MappedComponentToGpuSystem::new(
-            GpuComponentFormat::U32,
-            skin(),
-            gpu_components::skin(),
-            Box::new(|_, _, skin| skin.get_offset()),
-        ))],
+        vec![
+            Box::new(MappedComponentToGpuSystem::new(
+                GpuComponentFormat::U32,
+                skin(),
+                gpu_components::skin(),
+                Box::new(|_, _, skin| skin.get_offset()),
+            )),
+            Box::new(MappedComponentToGpuSystem::new(
+                GpuComponentFormat::U32,
+                joint_bounding_aabbs(),
+                gpu_components::joint_sphere_count(),
+                Box::new(|world, id, joint_aabbs| {
+                    joint_aabbs.len().min(world.get_ref(id, joints()).map_or(0, |joints| joints.len())) as u32
+                }),
+            )),
+        ],
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use ambient_std::mesh::Mesh;
+    use glam::{vec3, vec4, Quat, UVec4, Vec3};
+
+    use super::*;
+
+    /// A bar along x, moved by joint 0 on the left and joint 1 on the right, blended in the middle
+    fn bar() -> Mesh {
+        let xs = (0..=20).map(|i| i as f32 / 10. - 1.).collect_vec();
+        let weights = |x: f32| {
+            let right = (x + 0.5).clamp(0., 1.);
+            vec4(1. - right, right, 0., 0.)
+        };
+        Mesh {
+            positions: Some(xs.iter().flat_map(|&x| [vec3(x, -0.1, 0.), vec3(x, 0.1, 0.)]).collect()),
+            joint_indices: Some(xs.iter().flat_map(|_| [UVec4::new(0, 1, 0, 0); 2]).collect()),
+            joint_weights: Some(xs.iter().flat_map(|&x| [weights(x); 2]).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn poses() -> Vec<Vec<Mat4>> {
+        (0..8)
+            .map(|i| {
+                let angle = i as f32 * 0.4;
+                vec![
+                    Mat4::from_rotation_z(-angle / 2.),
+                    Mat4::from_translation(vec3(1., 0., 0.)) * Mat4::from_rotation_y(angle) * Mat4::from_translation(vec3(-1., 0., 0.)),
+                    Mat4::from_scale(Vec3::splat(10.)),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn skinned_aabb_contains_the_posed_mesh() {
+        let mesh = bar();
+        let joint_aabbs = mesh.joint_aabbs();
+        assert_eq!(joint_aabbs.len(), 2);
+        let mesh_to_local = Mat4::from_scale_rotation_translation(Vec3::splat(2.), Quat::from_rotation_x(1.), vec3(0., 3., 0.));
+        for joint_matrices in poses() {
+            let aabb = skinned_aabb(&joint_aabbs, &joint_matrices, mesh_to_local).unwrap();
+            let mut posed = mesh.clone();
+            posed.apply_skin(&joint_matrices);
+            for position in posed.positions.unwrap() {
+                let position = mesh_to_local.transform_point3(position);
+                assert!(position.cmpge(aabb.min - 1e-4).all() && position.cmple(aabb.max + 1e-4).all(), "{position} {aabb:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn posed_mesh_is_within_the_hull_of_the_joint_spheres() {
+        let mesh = bar();
+        let joint_aabbs = mesh.joint_aabbs();
+        let mesh_to_world = Mat4::from_translation(vec3(5., 0., -2.));
+        let directions = [Vec3::X, Vec3::Y, Vec3::Z, vec3(1., 1., 0.).normalize(), vec3(-1., 0.5, 1.).normalize()];
+        for joint_matrices in poses() {
+            let spheres = joint_spheres(&joint_aabbs, &joint_matrices, mesh_to_world);
+            assert_eq!(spheres.len(), 3);
+            // The third joint doesn't move any vertex
+            assert!(spheres[2].w < 0.);
+
+            let mut posed = mesh.clone();
+            posed.apply_skin(&joint_matrices);
+            // Whichever plane all the spheres are outside of, all the vertices are too
+            for direction in directions.into_iter().flat_map(|direction| [direction, -direction]) {
+                let spheres_extent = spheres[..2].iter().map(|sphere| sphere.truncate().dot(direction) + sphere.w).fold(f32::MIN, f32::max);
+                for position in posed.positions.as_ref().unwrap() {
+                    assert!(mesh_to_world.transform_point3(*position).dot(direction) <= spheres_extent + 1e-4);
+                }
+            }
+        }
+    }
+}
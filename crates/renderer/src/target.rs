@@ -17,6 +17,11 @@ pub struct RenderTarget {
 }
 impl RenderTarget {
     pub fn new(gpu: Arc<Gpu>, size: UVec2, usage: Option<wgpu::TextureUsages>) -> Self {
+        let format = gpu.swapchain_format();
+        Self::with_color_format(gpu, size, format, usage)
+    }
+    /// A target whose color buffer has `color_format` rather than the format of the swapchain, e.g. to render in HDR
+    pub fn with_color_format(gpu: Arc<Gpu>, size: UVec2, color_format: wgpu::TextureFormat, usage: Option<wgpu::TextureUsages>) -> Self {
         let usage =
             usage.unwrap_or(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC);
         let sc_desc = gpu.sc_desc(size);
@@ -40,7 +45,7 @@ impl RenderTarget {
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: sc_desc.format,
+                format: color_format,
                 usage,
             },
         ));
@@ -66,3 +71,8 @@ impl RenderTarget {
         }
     }
 }
+
+/// The size of a target rendered at `scale` times `size`, which is never empty
+pub fn scaled_target_size(size: UVec2, scale: f32) -> UVec2 {
+    (size.as_vec2() * scale).round().as_uvec2().max(UVec2::ONE)
+}
@@ -0,0 +1,278 @@
+use std::{sync::Arc, time::Duration};
+
+use ambient_core::{time, transform::local_to_world};
+use ambient_ecs::{components, query, Component, Debuggable, Description, Name, Networked, Store, SystemGroup, World};
+use ambient_gpu::{
+    gpu::{Gpu, GpuKey},
+    shader_module::{BindGroupDesc, GraphicsPipeline, GraphicsPipelineInfo, Shader, ShaderModule},
+    typed_buffer::TypedBuffer,
+};
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKeyExt},
+    include_file,
+};
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+use itertools::Itertools;
+use wgpu::{BindGroupLayoutEntry, BufferUsages, ShaderStages};
+
+use crate::{get_overlay_module, RendererConfig, RendererTarget, GLOBALS_BIND_GROUP};
+
+components!("rendering", {
+    @[
+        Debuggable, Networked, Store,
+        Name["Trail width"],
+        Description["If attached along with `trail_lifetime`, this entity leaves a trail of this width behind it as it moves."]
+    ]
+    trail_width: f32,
+    @[
+        Debuggable, Networked, Store,
+        Name["Trail end width"],
+        Description["The width the trail of this entity narrows (or widens) to by the end of its lifetime. Defaults to `trail_width`."]
+    ]
+    trail_end_width: f32,
+    @[
+        Debuggable, Networked, Store,
+        Name["Trail lifetime"],
+        Description["How long, in seconds, each part of the trail of this entity stays visible."]
+    ]
+    trail_lifetime: f32,
+    @[
+        Debuggable, Networked, Store,
+        Name["Trail color"],
+        Description["The color of the trail of this entity where it's emitted. Defaults to white."]
+    ]
+    trail_color: Vec4,
+    @[
+        Debuggable, Networked, Store,
+        Name["Trail end color"],
+        Description["The color the trail of this entity fades to by the end of its lifetime. Defaults to `trail_color` with no opacity."]
+    ]
+    trail_end_color: Vec4,
+    @[
+        Debuggable, Networked, Store,
+        Name["Trail axis"],
+        Description["If attached, the trail of this entity spreads along this axis (in the local space of the entity) instead of facing the camera.\nThis is useful for sword slashes or tire marks."]
+    ]
+    trail_axis: Vec3,
+    trail_points: Vec<TrailPoint>,
+});
+
+/// A new point is only added to a trail once the entity moved this far from the previous one
+const MIN_POINT_DISTANCE: f32 = 0.02;
+const MAX_TRAIL_POINTS: usize = 512;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrailPoint {
+    pub position: Vec3,
+    /// The direction the trail spreads along at this point, in world space, or None if it faces the camera
+    pub axis: Option<Vec3>,
+    pub time: Duration,
+}
+
+pub fn trail_systems() -> SystemGroup {
+    SystemGroup::new(
+        "trails",
+        vec![
+            query((trail_width(), trail_lifetime())).excl(trail_points()).to_system(|q, world, qs, _| {
+                for id in q.collect_ids(world, qs) {
+                    world.add_component(id, trail_points(), Vec::new()).unwrap();
+                }
+            }),
+            query(trail_points()).to_system(|q, world, qs, _| {
+                let removed = q
+                    .iter(world, qs)
+                    .map(|(id, _)| id)
+                    .filter(|&id| !world.has_component(id, trail_width()) || !world.has_component(id, trail_lifetime()))
+                    .collect_vec();
+                for id in removed {
+                    world.remove_component(id, trail_points()).unwrap();
+                }
+            }),
+            query((trail_lifetime(), local_to_world())).incl(trail_points()).to_system(|q, world, qs, _| {
+                let now = *world.resource(time());
+                let emitted = q
+                    .iter(world, qs)
+                    .map(|(id, (&lifetime, local_to_world))| {
+                        let axis = world.get(id, trail_axis()).ok().map(|axis| local_to_world.transform_vector3(axis).normalize_or_zero());
+                        (id, lifetime, TrailPoint { position: local_to_world.w_axis.truncate(), axis, time: now })
+                    })
+                    .collect_vec();
+                for (id, lifetime, point) in emitted {
+                    update_trail(world.get_mut(id, trail_points()).unwrap(), point, lifetime);
+                }
+            }),
+        ],
+    )
+}
+
+/// Drops the expired points of a trail and adds the current position of the entity to it. The last point follows the
+/// entity until it's far enough from the one before it, at which point it stays behind and a new one is added.
+fn update_trail(points: &mut Vec<TrailPoint>, point: TrailPoint, lifetime: f32) {
+    points.retain(|p| point.time.saturating_sub(p.time).as_secs_f32() < lifetime);
+    let len = points.len();
+    if len >= 2 && points[len - 2].position.distance(point.position) < MIN_POINT_DISTANCE {
+        points[len - 1] = point;
+    } else {
+        points.push(point);
+    }
+    if points.len() > MAX_TRAIL_POINTS {
+        points.drain(..points.len() - MAX_TRAIL_POINTS);
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+struct TrailVertex {
+    position: Vec4,
+    /// Half of the width of the trail, along the direction it spreads in
+    side: Vec4,
+    color: Vec4,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+struct TrailSegment {
+    start: TrailVertex,
+    end: TrailVertex,
+}
+
+/// Renders the trails of all the entities of a scene in a single draw call, with one instance per segment
+pub struct TrailRenderer {
+    gpu: Arc<Gpu>,
+    scene: Component<()>,
+    pipeline: GraphicsPipeline,
+    buffer: TypedBuffer<TrailSegment>,
+    segments: Vec<TrailSegment>,
+}
+impl TrailRenderer {
+    pub fn new(assets: &AssetCache, config: &RendererConfig) -> Self {
+        let gpu = GpuKey.get(assets);
+        let layout = BindGroupDesc {
+            entries: vec![BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: "TRAILS_BIND_GROUP".into(),
+        };
+        let shader = Shader::from_modules(
+            assets,
+            "Trails",
+            [
+                &get_overlay_module(assets, config.shadow_cascades),
+                &ShaderModule::new("Trails", include_file!("trails.wgsl"), vec![layout.into()]),
+            ],
+        );
+        let pipeline = shader.to_pipeline(
+            &gpu,
+            GraphicsPipelineInfo {
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.color_format(&gpu),
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                depth: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: false,
+                    // This is Greater because we're using reverse-z NDC
+                    depth_compare: wgpu::CompareFunction::Greater,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                ..Default::default()
+            },
+        );
+        let buffer = TypedBuffer::new(
+            gpu.clone(),
+            "TrailRenderer.buffer",
+            128,
+            0,
+            BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+        );
+        Self { gpu, scene: config.scene, pipeline, buffer, segments: Vec::new() }
+    }
+
+    #[profiling::function]
+    pub fn render(
+        &mut self,
+        world: &World,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &RendererTarget,
+        globals_bind_group: &wgpu::BindGroup,
+        camera_position: Vec3,
+    ) {
+        self.segments.clear();
+        let now = match world.resource_opt(time()) {
+            Some(&now) => now,
+            None => return,
+        };
+        for (id, (points, &width, &lifetime)) in query((trail_points(), trail_width(), trail_lifetime())).incl(self.scene).iter(world, None)
+        {
+            let color = world.get(id, trail_color()).unwrap_or(Vec4::ONE);
+            let end_color = world.get(id, trail_end_color()).unwrap_or(color * Vec4::new(1., 1., 1., 0.));
+            let end_width = world.get(id, trail_end_width()).unwrap_or(width);
+            let vertices = points
+                .iter()
+                .enumerate()
+                .map(|(i, point)| {
+                    let age = (now.saturating_sub(point.time).as_secs_f32() / lifetime).clamp(0., 1.);
+                    let half_width = (width + (end_width - width) * age) / 2.;
+                    let direction = match point.axis {
+                        Some(axis) => axis,
+                        None => {
+                            let previous = points[i.saturating_sub(1)].position;
+                            let next = points[(i + 1).min(points.len() - 1)].position;
+                            (next - previous).cross(camera_position - point.position).normalize_or_zero()
+                        }
+                    };
+                    TrailVertex {
+                        position: point.position.extend(1.),
+                        side: (direction * half_width).extend(0.),
+                        color: color.lerp(end_color, age),
+                    }
+                })
+                .collect_vec();
+            self.segments.extend(vertices.iter().tuple_windows().map(|(&start, &end)| TrailSegment { start, end }));
+        }
+        if self.segments.is_empty() {
+            return;
+        }
+
+        self.buffer.fill(&self.segments, |_| {});
+        let bind_group = self.gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("TrailRenderer.bind_group"),
+            layout: self.pipeline.shader().get_bind_group_layout_by_name("TRAILS_BIND_GROUP").unwrap(),
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: self.buffer.buffer().as_entire_binding() }],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Trails"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target.color(),
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: true },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: target.depth(),
+                depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Load, store: true }),
+                stencil_ops: None,
+            }),
+        });
+        render_pass.set_pipeline(self.pipeline.pipeline());
+        self.pipeline.bind(&mut render_pass, GLOBALS_BIND_GROUP, globals_bind_group);
+        self.pipeline.bind(&mut render_pass, "TRAILS_BIND_GROUP", &bind_group);
+        render_pass.draw(0..6, 0..self.segments.len() as u32);
+    }
+}
+
+impl std::fmt::Debug for TrailRenderer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrailRenderer").field("segments", &self.segments.len()).finish()
+    }
+}
@@ -0,0 +1,55 @@
+struct TrailVertex {
+  position: vec4<f32>,
+  side: vec4<f32>,
+  color: vec4<f32>,
+};
+
+struct TrailSegment {
+  start: TrailVertex,
+  end: TrailVertex,
+};
+
+struct TrailSegments {
+  segments: array<TrailSegment>,
+};
+
+@group(#TRAILS_BIND_GROUP)
+@binding(0)
+var<storage> trail_segments: TrailSegments;
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) color: vec4<f32>,
+  @location(1) side: f32,
+};
+
+// x goes along the segment, y across the trail
+fn segment_corner(vertex_index: u32) -> vec2<f32> {
+  switch (vertex_index) {
+    case 0u: { return vec2<f32>(0., -1.); }
+    case 1u: { return vec2<f32>(1., -1.); }
+    case 2u, 3u: { return vec2<f32>(0., 1.); }
+    case 4u: { return vec2<f32>(1., -1.); }
+    default: { return vec2<f32>(1., 1.); }
+  }
+}
+
+@vertex
+fn vs_main(@builtin(instance_index) instance_index: u32, @builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+  let segment = trail_segments.segments[instance_index];
+  let corner = segment_corner(vertex_index);
+
+  let start = segment.start.position.xyz + segment.start.side.xyz * corner.y;
+  let end = segment.end.position.xyz + segment.end.side.xyz * corner.y;
+  let position = mix(start, end, corner.x);
+  let color = mix(segment.start.color, segment.end.color, corner.x);
+
+  return VertexOutput(global_params.projection_view * vec4<f32>(position, 1.), color, corner.y);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  // Soften the edges of the trail
+  let edge = 1. - smoothstep(0.8, 1., abs(in.side));
+  return vec4<f32>(in.color.rgb, in.color.a * edge);
+}
@@ -40,28 +40,82 @@ impl Rust {
     }
 
     pub fn build(&self, working_directory: &Path, package_name: &str) -> anyhow::Result<Vec<u8>> {
-        Ok(std::fs::read(
-            parse_command_result_for_filenames(self.0.run(
-                "cargo",
-                [
-                    "build",
-                    "--release",
-                    "--message-format",
-                    "json",
-                    "--target",
-                    "wasm32-wasi",
-                    "--package",
-                    package_name,
-                ],
-                Some(working_directory),
-            ))?
+        Ok(std::fs::read(self.build_artifact(
+            working_directory,
+            package_name,
+            None,
+            false,
+        )?)?)
+    }
+
+    /// Builds `package_name` and returns the path to the resulting `.wasm` artifact.
+    ///
+    /// If `target_directory` is specified, it is used instead of the crate's `target` directory.
+    /// If `debug_info` is true, the artifact includes DWARF debug info, even though it's a release build.
+    pub fn build_artifact(
+        &self,
+        working_directory: &Path,
+        package_name: &str,
+        target_directory: Option<&Path>,
+        debug_info: bool,
+    ) -> anyhow::Result<PathBuf> {
+        let mut args: Vec<&OsStr> = [
+            "build",
+            "--release",
+            "--message-format",
+            "json",
+            "--target",
+            "wasm32-wasi",
+            "--package",
+            package_name,
+        ]
+        .into_iter()
+        .map(OsStr::new)
+        .collect();
+        if let Some(target_directory) = target_directory {
+            args.extend([OsStr::new("--target-dir"), target_directory.as_os_str()]);
+        }
+        if debug_info {
+            args.extend([
+                OsStr::new("--config"),
+                OsStr::new("profile.release.debug=true"),
+            ]);
+        }
+
+        parse_command_result_for_filenames(self.0.run("cargo", args, Some(working_directory)))?
             .into_iter()
             .find(|p| p.extension().unwrap_or_default() == "wasm")
-            .context("no wasm artifact")?,
-        )?)
+            .context("no wasm artifact")
     }
 }
 
+/// Returns true if `wasm-opt` (from [Binaryen](https://github.com/WebAssembly/binaryen)) is installed.
+pub fn wasm_opt_installed() -> bool {
+    Installation
+        .run("wasm-opt", ["--version"], None)
+        .map(|(success, _, _)| success)
+        .unwrap_or(false)
+}
+
+/// Optimizes the WASM module at `input` with `wasm-opt`, and writes the result to `output`.
+pub fn optimize_wasm(
+    input: &Path,
+    output: &Path,
+    args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+) -> anyhow::Result<()> {
+    let mut all_args = vec![
+        input.as_os_str().to_owned(),
+        OsStr::new("-o").to_owned(),
+        output.as_os_str().to_owned(),
+    ];
+    all_args.extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+    handle_command_failure(
+        "optimize wasm",
+        Installation.run("wasm-opt", all_args, None),
+    )?;
+    Ok(())
+}
+
 #[derive(Clone)]
 struct Installation;
 impl Installation {
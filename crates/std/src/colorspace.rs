@@ -82,6 +82,205 @@ impl HslRepresentation {
     }
 }
 
+pub struct OklabRepresentation;
+impl OklabRepresentation {
+    /// converts a color in linear sRGB space to Oklab space
+    #[inline]
+    pub fn linear_srgb_to_oklab([red, green, blue]: [f32; 3]) -> [f32; 3] {
+        // https://bottosson.github.io/posts/oklab/
+        let l = (0.41222147 * red + 0.53633254 * green + 0.051445993 * blue).cbrt();
+        let m = (0.2119035 * red + 0.6806995 * green + 0.10739696 * blue).cbrt();
+        let s = (0.08830246 * red + 0.28171885 * green + 0.6299787 * blue).cbrt();
+        [
+            0.21045426 * l + 0.7936178 * m - 0.004072047 * s,
+            1.9779985 * l - 2.4285922 * m + 0.4505937 * s,
+            0.025904037 * l + 0.78277177 * m - 0.80867577 * s,
+        ]
+    }
+
+    /// converts a color in Oklab space to linear sRGB space
+    #[inline]
+    pub fn oklab_to_linear_srgb([lightness, a, b]: [f32; 3]) -> [f32; 3] {
+        let l = (lightness + 0.39633778 * a + 0.21580376 * b).powi(3);
+        let m = (lightness - 0.105561346 * a - 0.06385417 * b).powi(3);
+        let s = (lightness - 0.08948418 * a - 1.2914855 * b).powi(3);
+        [
+            4.0767417 * l - 3.3077116 * m + 0.23096994 * s,
+            -1.268438 * l + 2.6097574 * m - 0.34131938 * s,
+            -0.0041960864 * l - 0.7034186 * m + 1.7076147 * s,
+        ]
+    }
+
+    /// converts a color in linear sRGB space to OKLCH (lightness, chroma, hue in degrees)
+    #[inline]
+    pub fn linear_srgb_to_oklch(rgb: [f32; 3]) -> (f32, f32, f32) {
+        let [lightness, a, b] = Self::linear_srgb_to_oklab(rgb);
+        let chroma = (a * a + b * b).sqrt();
+        let hue = if chroma < 1e-6 { 0.0 } else { b.atan2(a).to_degrees().rem_euclid(360.0) };
+        (lightness, chroma, hue)
+    }
+
+    /// converts a color in OKLCH space to linear sRGB space
+    ///
+    /// The result may be outside of [0.0, 1.0] if the color is outside of the sRGB gamut
+    #[inline]
+    pub fn oklch_to_linear_srgb(lightness: f32, chroma: f32, hue: f32) -> [f32; 3] {
+        let (sin, cos) = hue.to_radians().sin_cos();
+        Self::oklab_to_linear_srgb([lightness, chroma * cos, chroma * sin])
+    }
+
+    /// converts a color in OKLCH space to linear sRGB space, reducing the chroma until it fits in the sRGB gamut
+    pub fn oklch_to_linear_srgb_clamped(lightness: f32, chroma: f32, hue: f32) -> [f32; 3] {
+        let lightness = lightness.clamp(0.0, 1.0);
+        let in_gamut = |rgb: [f32; 3]| rgb.iter().all(|c| (-1e-4..=1.0 + 1e-4).contains(c));
+        let rgb = Self::oklch_to_linear_srgb(lightness, chroma, hue);
+        if in_gamut(rgb) {
+            return rgb.map(|c| c.clamp(0.0, 1.0));
+        }
+        let (mut low, mut high) = (0.0, chroma);
+        for _ in 0..16 {
+            let mid = (low + high) / 2.0;
+            if in_gamut(Self::oklch_to_linear_srgb(lightness, mid, hue)) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        Self::oklch_to_linear_srgb(lightness, low, hue).map(|c| c.clamp(0.0, 1.0))
+    }
+}
+
+/// An RGB color space, identified by its primaries and whether its values are encoded with its transfer function.
+///
+/// Colors are converted through linear sRGB without clamping, so a color outside of the sRGB gamut comes out with
+/// components below 0 or above 1 rather than being clamped to the sRGB primaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "uncategorized", derive(serde::Serialize, serde::Deserialize))]
+pub enum RgbColorSpace {
+    #[default]
+    Srgb,
+    LinearSrgb,
+    /// The color space of most recent displays; it has the sRGB white point and transfer function, and a wider gamut
+    DisplayP3,
+    LinearDisplayP3,
+    /// The color space of HDR and UHD video, with the BT.2020 transfer function (not PQ or HLG)
+    Rec2020,
+    LinearRec2020,
+}
+impl RgbColorSpace {
+    pub fn is_linear(self) -> bool {
+        matches!(self, RgbColorSpace::LinearSrgb | RgbColorSpace::LinearDisplayP3 | RgbColorSpace::LinearRec2020)
+    }
+    /// The color space with the same primaries, without the transfer function
+    pub fn linear(self) -> Self {
+        match self {
+            RgbColorSpace::Srgb | RgbColorSpace::LinearSrgb => RgbColorSpace::LinearSrgb,
+            RgbColorSpace::DisplayP3 | RgbColorSpace::LinearDisplayP3 => RgbColorSpace::LinearDisplayP3,
+            RgbColorSpace::Rec2020 | RgbColorSpace::LinearRec2020 => RgbColorSpace::LinearRec2020,
+        }
+    }
+    /// The transfer function the values in this color space are encoded with
+    pub fn transfer_function(self) -> TransferFunction {
+        match self {
+            RgbColorSpace::Srgb | RgbColorSpace::DisplayP3 => TransferFunction::Srgb,
+            RgbColorSpace::Rec2020 => TransferFunction::Rec2020,
+            _ => TransferFunction::Linear,
+        }
+    }
+    /// The matrix converting a color in linear sRGB space to the linear version of this color space, by rows
+    pub fn from_linear_srgb_matrix(self) -> [[f32; 3]; 3] {
+        match self.linear() {
+            RgbColorSpace::LinearDisplayP3 => LINEAR_SRGB_TO_LINEAR_DISPLAY_P3,
+            RgbColorSpace::LinearRec2020 => LINEAR_SRGB_TO_LINEAR_REC2020,
+            _ => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+    /// converts a color in this color space to linear sRGB space
+    pub fn to_linear_srgb(self, rgb: [f32; 3]) -> [f32; 3] {
+        let linear = match self {
+            RgbColorSpace::Srgb | RgbColorSpace::DisplayP3 => rgb.map(|c| mirrored(c, f32::nonlinear_to_linear_srgb)),
+            RgbColorSpace::Rec2020 => rgb.map(|c| mirrored(c, rec2020_to_linear)),
+            _ => rgb,
+        };
+        match self.linear() {
+            RgbColorSpace::LinearDisplayP3 => mul_matrix(&LINEAR_DISPLAY_P3_TO_LINEAR_SRGB, linear),
+            RgbColorSpace::LinearRec2020 => mul_matrix(&LINEAR_REC2020_TO_LINEAR_SRGB, linear),
+            _ => linear,
+        }
+    }
+    /// converts a color in linear sRGB space to this color space
+    pub fn from_linear_srgb(self, rgb: [f32; 3]) -> [f32; 3] {
+        let linear = match self.linear() {
+            RgbColorSpace::LinearDisplayP3 => mul_matrix(&LINEAR_SRGB_TO_LINEAR_DISPLAY_P3, rgb),
+            RgbColorSpace::LinearRec2020 => mul_matrix(&LINEAR_SRGB_TO_LINEAR_REC2020, rgb),
+            _ => rgb,
+        };
+        match self {
+            RgbColorSpace::Srgb | RgbColorSpace::DisplayP3 => linear.map(|c| mirrored(c, f32::linear_to_nonlinear_srgb)),
+            RgbColorSpace::Rec2020 => linear.map(|c| mirrored(c, linear_to_rec2020)),
+            _ => linear,
+        }
+    }
+    /// converts a color in this color space to `to`
+    pub fn convert(self, to: RgbColorSpace, rgb: [f32; 3]) -> [f32; 3] {
+        if self == to {
+            return rgb;
+        }
+        to.from_linear_srgb(self.to_linear_srgb(rgb))
+    }
+    /// Whether a color in linear sRGB space can be shown in this color space without clamping
+    pub fn contains_linear_srgb(self, rgb: [f32; 3]) -> bool {
+        self.linear().from_linear_srgb(rgb).iter().all(|c| (-1e-4..=1.0 + 1e-4).contains(c))
+    }
+}
+
+/// How the values of a color space are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransferFunction {
+    Linear,
+    Srgb,
+    /// The BT.2020 transfer function, which is also BT.709's
+    Rec2020,
+}
+
+// Derived from the primaries and the D65 white point, through CIE XYZ
+const LINEAR_SRGB_TO_LINEAR_DISPLAY_P3: [[f32; 3]; 3] =
+    [[0.8224621, 0.177538, 0.0], [0.0331941, 0.9668058, 0.0], [0.0170827, 0.0723974, 0.9105199]];
+const LINEAR_DISPLAY_P3_TO_LINEAR_SRGB: [[f32; 3]; 3] =
+    [[1.2249401, -0.2249404, 0.0], [-0.0420569, 1.0420571, 0.0], [-0.0196376, -0.0786361, 1.0982735]];
+// https://www.itu.int/pub/R-REP-BT.2087
+const LINEAR_SRGB_TO_LINEAR_REC2020: [[f32; 3]; 3] =
+    [[0.627404, 0.329282, 0.0433136], [0.069097, 0.91954, 0.0113612], [0.0163916, 0.0880132, 0.895595]];
+const LINEAR_REC2020_TO_LINEAR_SRGB: [[f32; 3]; 3] =
+    [[1.660491, -0.5876411, -0.0728499], [-0.1245505, 1.1328999, -0.0083494], [-0.0181508, -0.1005789, 1.1187297]];
+
+fn mul_matrix(matrix: &[[f32; 3]; 3], [red, green, blue]: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * red + row[1] * green + row[2] * blue)
+}
+
+/// Applies a transfer function to negative values too, by mirroring it around 0, like extended sRGB does
+fn mirrored(value: f32, transfer: impl Fn(f32) -> f32) -> f32 {
+    transfer(value.abs()).copysign(value)
+}
+
+// https://www.itu.int/rec/R-REC-BT.2020
+const REC2020_ALPHA: f32 = 1.0992968;
+const REC2020_BETA: f32 = 0.018053968;
+fn linear_to_rec2020(value: f32) -> f32 {
+    if value < REC2020_BETA {
+        4.5 * value
+    } else {
+        REC2020_ALPHA * value.powf(0.45) - (REC2020_ALPHA - 1.0)
+    }
+}
+fn rec2020_to_linear(value: f32) -> f32 {
+    if value < 4.5 * REC2020_BETA {
+        value / 4.5
+    } else {
+        ((value + (REC2020_ALPHA - 1.0)) / REC2020_ALPHA).powf(1.0 / 0.45)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -183,4 +382,59 @@ mod test {
         assert_eq!((saturation * 100.0).round() as u32, 83);
         assert_eq!((lightness * 100.0).round() as u32, 51);
     }
+
+    #[test]
+    fn oklab_roundtrip() {
+        // reference values from https://bottosson.github.io/posts/oklab/
+        let [l, a, b] = OklabRepresentation::linear_srgb_to_oklab([1.0, 1.0, 1.0]);
+        assert!((l - 1.0).abs() < 1e-3 && a.abs() < 1e-3 && b.abs() < 1e-3);
+
+        for rgb in [[0.2, 0.4, 0.6], [1.0, 0.0, 0.0], [0.0, 0.5, 0.1], [0.9, 0.9, 0.05]] {
+            let (l, c, h) = OklabRepresentation::linear_srgb_to_oklch(rgb);
+            let roundtrip = OklabRepresentation::oklch_to_linear_srgb(l, c, h);
+            for i in 0..3 {
+                assert!((rgb[i] - roundtrip[i]).abs() < 1e-4, "{rgb:?} != {roundtrip:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn oklch_gamut_clamping() {
+        let rgb = OklabRepresentation::oklch_to_linear_srgb_clamped(0.7, 0.5, 150.0);
+        assert!(rgb.iter().all(|c| (0.0..=1.0).contains(c)));
+        let (l, _, h) = OklabRepresentation::linear_srgb_to_oklch(rgb);
+        assert!((l - 0.7).abs() < 1e-2);
+        assert!((h - 150.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn wide_gamut_conversions() {
+        let spaces = [
+            RgbColorSpace::Srgb,
+            RgbColorSpace::LinearSrgb,
+            RgbColorSpace::DisplayP3,
+            RgbColorSpace::LinearDisplayP3,
+            RgbColorSpace::Rec2020,
+            RgbColorSpace::LinearRec2020,
+        ];
+        let assert_close = |a: [f32; 3], b: [f32; 3]| assert!(a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-3), "{a:?} != {b:?}");
+        for space in spaces {
+            // The white point is the same everywhere
+            assert_close(space.from_linear_srgb([1.0, 1.0, 1.0]), [1.0, 1.0, 1.0]);
+            for to in spaces {
+                let rgb = [0.9, 0.3, 0.05];
+                assert_close(to.convert(space, space.convert(to, rgb)), rgb);
+            }
+        }
+
+        // Pure P3 red is outside of sRGB, and isn't clamped on the way through linear sRGB
+        let p3_red = RgbColorSpace::DisplayP3.to_linear_srgb([1.0, 0.0, 0.0]);
+        assert!(p3_red[0] > 1.0 && p3_red[1] < 0.0);
+        assert!(!RgbColorSpace::LinearSrgb.contains_linear_srgb(p3_red));
+        assert!(RgbColorSpace::DisplayP3.contains_linear_srgb(p3_red));
+        let p3_orange = RgbColorSpace::DisplayP3.to_linear_srgb([1.0, 0.5, 0.1]);
+        assert!(!RgbColorSpace::Srgb.contains_linear_srgb(p3_orange));
+        assert!(RgbColorSpace::Rec2020.contains_linear_srgb(p3_orange));
+        assert_close(RgbColorSpace::Srgb.convert(RgbColorSpace::DisplayP3, [1.0, 0.0, 0.0]), [0.9175, 0.2003, 0.1387]);
+    }
 }
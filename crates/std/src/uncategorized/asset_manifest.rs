@@ -0,0 +1,86 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt},
+    asset_url::{AbsAssetUrl, AssetType, AssetUrl, ServerBaseUrlKey},
+};
+
+/// Maps stable, human-readable asset ids (e.g. `characters/knight`) to the built assets.
+///
+/// This is written by the build next to its output, and lets game code look up assets by id
+/// instead of hardcoding the urls produced by the pipelines.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AssetManifest {
+    pub assets: BTreeMap<String, AssetManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetManifestEntry {
+    /// The url of the built asset, relative to the build directory
+    pub url: AssetUrl,
+    #[serde(rename = "type")]
+    pub type_: AssetType,
+    /// The sha256 of the built asset's content
+    pub hash: String,
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl AssetManifest {
+    /// The file name of the manifest, relative to the build directory
+    pub const FILE_NAME: &'static str = "asset_manifest.json";
+
+    pub fn get(&self, id: &str) -> Option<&AssetManifestEntry> {
+        self.assets.get(id)
+    }
+    /// Resolves the url of the asset with the given id against `base_url`, which is the url of the build directory
+    pub fn url(&self, id: &str, base_url: &AbsAssetUrl) -> Option<AbsAssetUrl> {
+        self.get(id)?.url.resolve(&base_url.as_directory()).ok()
+    }
+    pub fn with_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = (&'a str, &'a AssetManifestEntry)> + 'a {
+        self.assets.iter().filter(move |(_, entry)| entry.tags.iter().any(|t| t == tag)).map(|(id, entry)| (id.as_str(), entry))
+    }
+    pub async fn from_url(assets: &AssetCache, url: &AbsAssetUrl) -> anyhow::Result<Self> {
+        url.download_json(assets).await
+    }
+}
+
+/// The manifest of the currently running project; empty until one is inserted
+#[derive(Debug, Clone)]
+pub struct AssetManifestKey;
+impl SyncAssetKey<Arc<AssetManifest>> for AssetManifestKey {
+    fn load(&self, _assets: AssetCache) -> Arc<AssetManifest> {
+        Arc::new(AssetManifest::default())
+    }
+}
+
+/// Looks up an asset of the running project by its id, resolved against the server's content url
+pub fn asset_manifest_url(assets: &AssetCache, id: &str) -> Option<AbsAssetUrl> {
+    AssetManifestKey.get(assets).url(id, &ServerBaseUrlKey.get(assets))
+}
+
+#[test]
+fn test_asset_manifest_url() {
+    let mut manifest = AssetManifest::default();
+    manifest.assets.insert(
+        "characters/knight".to_string(),
+        AssetManifestEntry {
+            url: AssetUrl::parse("assets/characters/knight.glb/prefabs/main.json").unwrap(),
+            type_: AssetType::Prefab,
+            hash: String::new(),
+            name: "knight.glb".to_string(),
+            tags: vec!["characters".to_string()],
+        },
+    );
+    let base_url = AbsAssetUrl::parse("http://localhost:8999/content").unwrap();
+    assert_eq!(
+        manifest.url("characters/knight", &base_url).unwrap().to_string(),
+        "http://localhost:8999/content/assets/characters/knight.glb/prefabs/main.json"
+    );
+    assert!(manifest.url("characters/archer", &base_url).is_none());
+    assert_eq!(manifest.with_tag("characters").count(), 1);
+    assert_eq!(manifest.with_tag("ui").count(), 0);
+}
@@ -1,6 +1,7 @@
 use std::{
     marker::PhantomData,
     path::{Path, PathBuf},
+    str::FromStr,
     sync::Arc,
 };
 
@@ -13,11 +14,12 @@ use serde::{
     de::{DeserializeOwned, Visitor},
     Deserialize, Deserializer, Serialize, Serializer,
 };
-use url::Url;
+use url::{form_urlencoded, Url};
 
 use crate::{
     asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt},
-    download_asset::{download, AssetsCacheDir},
+    download_asset::AssetsCacheDir,
+    download_cache::{self, CancellationToken, DownloadProgress},
     Cb,
 };
 
@@ -29,6 +31,15 @@ impl SyncAssetKey<AbsAssetUrl> for ServerBaseUrlKey {
     }
 }
 
+/// The directory on this machine which the content at [ServerBaseUrlKey] is served from, if any
+#[derive(Debug, Clone)]
+pub struct ServerContentDirKey;
+impl SyncAssetKey<Option<PathBuf>> for ServerContentDirKey {
+    fn load(&self, _assets: AssetCache) -> Option<PathBuf> {
+        None
+    }
+}
+
 /// This is a thin wrapper around Url, which is guaranteed to always
 /// be an absolute url (including when pointing to a local file).
 ///
@@ -87,8 +98,11 @@ impl AbsAssetUrl {
             Self(Url::from_directory_path(path).unwrap())
         }
     }
+    /// The fragment is not part of the cache path, as it isn't part of the downloaded resource
     pub fn relative_cache_path(&self) -> String {
-        self.0.to_string().replace("://", "/").replace(':', "_")
+        let mut url = self.0.clone();
+        url.set_fragment(None);
+        url.to_string().replace("://", "/").replace([':', '?'], "_")
     }
     pub fn absolute_cache_path(&self, assets: &AssetCache) -> PathBuf {
         AssetsCacheDir.get(assets).join(self.relative_cache_path())
@@ -107,6 +121,55 @@ impl AbsAssetUrl {
         url.set_path(&format!("{}.{}", url.path(), extension));
         Self(url)
     }
+    /// Replaces the extension of the last path segment, or adds one if it has none: test.png -> test.hello
+    pub fn with_extension(&self, extension: &str) -> Self {
+        let path = self.0.path();
+        let file_start = path.rfind('/').map(|i| i + 1).unwrap_or(0);
+        let stem = match path[file_start..].rfind('.') {
+            Some(i) if i > 0 => &path[..file_start + i],
+            _ => path,
+        };
+        let mut url = self.0.clone();
+        url.set_path(&format!("{stem}.{extension}"));
+        Self(url)
+    }
+    /// Returns the value of the query parameter `key` (i.e. `model.glb?lod=2`), if it's present and can be parsed as a `T`
+    pub fn query_param<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.0.query_pairs().find(|(k, _)| k == key).and_then(|(_, value)| value.parse().ok())
+    }
+    /// Returns a copy of this url with the query parameter `key` set to `value`, replacing any previous value
+    pub fn with_query_param(&self, key: &str, value: impl ToString) -> Self {
+        let pairs = self.0.query_pairs().filter(|(k, _)| k != key).map(|(k, v)| (k.into_owned(), v.into_owned())).collect::<Vec<_>>();
+        let mut url = self.0.clone();
+        url.query_pairs_mut().clear().extend_pairs(pairs).append_pair(key, &value.to_string());
+        Self(url)
+    }
+    pub fn fragment(&self) -> Option<&str> {
+        self.0.fragment()
+    }
+    /// Returns the value of `key` in a fragment of `key=value` pairs (i.e. `model.glb#scene=1`), if it's present and can be
+    /// parsed as a `T`
+    pub fn fragment_param<T: FromStr>(&self, key: &str) -> Option<T> {
+        form_urlencoded::parse(self.0.fragment()?.as_bytes()).find(|(k, _)| k == key).and_then(|(_, value)| value.parse().ok())
+    }
+    /// Returns a copy of this url with the fragment parameter `key` set to `value`, replacing any previous value
+    pub fn with_fragment_param(&self, key: &str, value: impl ToString) -> Self {
+        let mut fragment = form_urlencoded::Serializer::new(String::new());
+        if let Some(previous) = self.0.fragment() {
+            fragment.extend_pairs(form_urlencoded::parse(previous.as_bytes()).filter(|(k, _)| k != key));
+        }
+        fragment.append_pair(key, &value.to_string());
+        let mut url = self.0.clone();
+        url.set_fragment(Some(&fragment.finish()));
+        Self(url)
+    }
+    /// Returns a copy of this url without its query and fragment
+    pub fn without_query_and_fragment(&self) -> Self {
+        let mut url = self.0.clone();
+        url.set_query(None);
+        url.set_fragment(None);
+        Self(url)
+    }
 
     #[cfg(target_arch = "wasm32")]
     pub fn to_file_path(&self) -> anyhow::Result<Option<PathBuf>> {
@@ -174,14 +237,24 @@ impl AbsAssetUrl {
         if let Some(path) = self.to_file_path()? {
             Ok(ambient_sys::fs::read(path).await.context(format!("Failed to read file at: {:}", self.0))?)
         } else {
-            Ok(download(assets, self.0.clone(), |resp| async { Ok(resp.bytes().await?) }).await?.to_vec())
+            download_cache::download_bytes(assets, self).await
         }
     }
+    /// Like [Self::download_bytes], but reports the progress to `on_progress` as the body is received, and fails with a
+    /// [download_cache::DownloadCancelled] error as soon as `cancel` is cancelled
+    pub async fn download_bytes_with_progress(
+        &self,
+        assets: &AssetCache,
+        on_progress: impl Fn(DownloadProgress) + Send + Sync + 'static,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Vec<u8>> {
+        download_cache::download_bytes_with_progress(assets, self, Arc::new(on_progress), cancel).await
+    }
     pub async fn download_string(&self, assets: &AssetCache) -> anyhow::Result<String> {
         if let Some(path) = self.to_file_path()? {
             Ok(ambient_sys::fs::read_to_string(path).await.context(format!("Failed to read file at: {:}", self.0))?)
         } else {
-            Ok(download(assets, self.0.clone(), |resp| async { Ok(resp.text().await?) }).await?)
+            Ok(String::from_utf8(download_cache::download_bytes(assets, self).await?)?)
         }
     }
     pub async fn download_json<T: 'static + Send + DeserializeOwned>(&self, assets: &AssetCache) -> anyhow::Result<T> {
@@ -189,7 +262,7 @@ impl AbsAssetUrl {
             let content: Vec<u8> = ambient_sys::fs::read(path).await.context(format!("Failed to read file at: {:}", self.0))?;
             Ok(serde_json::from_slice(&content)?)
         } else {
-            Ok(download(assets, self.0.clone(), |resp| async { Ok(resp.json::<T>().await?) }).await?)
+            Ok(serde_json::from_slice(&download_cache::download_bytes(assets, self).await?)?)
         }
     }
     pub async fn download_toml<T: DeserializeOwned>(&self, assets: &AssetCache) -> anyhow::Result<T> {
@@ -222,6 +295,30 @@ fn test_abs_asset_url() {
     assert_eq!(AbsAssetUrl::parse("http://t.c/a/b/c.png").unwrap().last_dir_name(), Some("b"));
 }
 
+#[test]
+fn test_abs_asset_url_query_and_fragment() {
+    let url = AbsAssetUrl::parse("http://t.c/a/model.glb?lod=2&token=a%20b#scene=1").unwrap();
+    assert_eq!(url.extension(), Some("glb".to_string()));
+    assert_eq!(url.query_param::<u32>("lod"), Some(2));
+    assert_eq!(url.query_param::<String>("token"), Some("a b".to_string()));
+    assert_eq!(url.query_param::<u32>("token"), None);
+    assert_eq!(url.fragment_param::<u32>("scene"), Some(1));
+    assert_eq!(url.path().as_str(), "/a/model.glb");
+
+    assert_eq!(url.with_query_param("lod", 3).to_string(), "http://t.c/a/model.glb?token=a+b&lod=3#scene=1");
+    assert_eq!(url.with_fragment_param("node", "root").to_string(), "http://t.c/a/model.glb?lod=2&token=a%20b#scene=1&node=root");
+    assert_eq!(url.with_extension("fbx").to_string(), "http://t.c/a/model.fbx?lod=2&token=a%20b#scene=1");
+    assert_eq!(url.add_extension("meta").to_string(), "http://t.c/a/model.glb.meta?lod=2&token=a%20b#scene=1");
+    assert_eq!(url.as_directory().to_string(), "http://t.c/a/model.glb/?lod=2&token=a%20b#scene=1");
+    assert_eq!(url.without_query_and_fragment().to_string(), "http://t.c/a/model.glb");
+    assert_eq!(url.relative_cache_path(), "http/t.c/a/model.glb_lod=2&token=a%20b");
+    assert_eq!(AbsAssetUrl::parse("http://t.c/a.b/model").unwrap().with_extension("glb").to_string(), "http://t.c/a.b/model.glb");
+
+    let relative = AssetUrl::parse("model.glb#scene=1").unwrap();
+    assert_eq!(relative.extension(), Some("glb".to_string()));
+    assert_eq!(relative.resolve(&AbsAssetUrl::parse("http://t.c/a/").unwrap()).unwrap().fragment_param::<u32>("scene"), Some(1));
+}
+
 /// This is either an absolute url (which can also be an absolute file:// url),
 /// or a relative path which needs to be resolved
 ///
@@ -243,7 +340,7 @@ impl AssetUrl {
     pub fn extension(&self) -> Option<String> {
         match self {
             AssetUrl::Absolute(url) => url.extension(),
-            AssetUrl::Relative(path) => path.extension().map(|x| x.to_string().to_lowercase()),
+            AssetUrl::Relative(_) => RelativePath::new(self.path()).extension().map(|x| x.to_string().to_lowercase()),
         }
     }
     pub fn resolve(&self, base_url: &AbsAssetUrl) -> Result<AbsAssetUrl, url::ParseError> {
@@ -252,10 +349,11 @@ impl AssetUrl {
             AssetUrl::Relative(path) => Ok(AbsAssetUrl(base_url.0.join(path.as_str())?)),
         }
     }
+    /// The path, without the query and fragment
     pub fn path(&self) -> &str {
         match self {
             AssetUrl::Absolute(url) => url.0.path(),
-            AssetUrl::Relative(path) => path.as_str(),
+            AssetUrl::Relative(path) => path.as_str().split(['?', '#']).next().unwrap(),
         }
     }
     pub fn join(&self, path: impl AsRef<str>) -> Result<Self, url::ParseError> {
@@ -473,6 +571,10 @@ pub enum AssetType {
     /// Represents a vorbis backed file
     VorbisTrack,
     SoundGraph,
+    /// Represents a video from the video pipeline, see `ambient_video::VideoDesc`
+    Video,
+    /// Represents a piece of music from the music pipeline, see `ambient_world_audio::music::MusicDesc`
+    Music,
 }
 
 impl AssetType {
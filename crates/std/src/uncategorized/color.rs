@@ -8,7 +8,16 @@ use glam::{vec3, vec4, Vec3, Vec4};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::colorspace::{HslRepresentation, SrgbColorSpace};
+use crate::colorspace::{HslRepresentation, OklabRepresentation, RgbColorSpace, SrgbColorSpace};
+
+/// The color space in which palettes are derived from a base color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteSpace {
+    /// Cheap, but colors with the same HSL lightness can look very different in brightness
+    Hsl,
+    /// Perceptually uniform, so derived colors keep the perceived lightness and colorfulness of the base color
+    Oklch,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Color {
@@ -45,6 +54,20 @@ pub enum Color {
         /// Alpha component. [0.0, 1.0]
         alpha: f32,
     },
+    /// RGBA color in another RGB colorspace, such as Display P3 or Rec. 2020. The colors outside of the sRGB gamut keep
+    /// their components below 0 or above 1 when converted to the other variants.
+    RgbaIn {
+        /// The colorspace of the components
+        space: RgbColorSpace,
+        /// Red component. [0.0, 1.0]
+        red: f32,
+        /// Green component. [0.0, 1.0]
+        green: f32,
+        /// Blue component. [0.0, 1.0]
+        blue: f32,
+        /// Alpha component. [0.0, 1.0]
+        alpha: f32,
+    },
 }
 
 impl Color {
@@ -119,6 +142,16 @@ impl Color {
         Err(HexColorError::Length)
     }
 
+    /// Converts a `Color` to a hex string (`RRGGBBAA`) from sRGB colorspace.
+    ///
+    /// Components are clamped to [0.0, 1.0]; the result can be parsed back with [`Color::hex`].
+    pub fn to_hex(&self) -> String {
+        let [r, g, b, a] = self.as_rgba_f32();
+        let to_u8 = |v: f32| (v.clamp(0., 1.) * 255.0).round() as u8;
+        hex::encode_upper([to_u8(r), to_u8(g), to_u8(b), to_u8(a)])
+    }
+
+    /// A hardcoded debug color for the first few values; see [Color::debug] for a coherent set of any size
     pub fn u8_debug(v: u8) -> Self {
         if v == 0 {
             Self::rgb(1., 1., 1.)
@@ -208,14 +241,14 @@ impl Color {
     /// Get alpha.
     pub fn a(&self) -> f32 {
         match self {
-            Color::Rgba { alpha, .. } | Color::RgbaLinear { alpha, .. } | Color::Hsla { alpha, .. } => *alpha,
+            Color::Rgba { alpha, .. } | Color::RgbaLinear { alpha, .. } | Color::Hsla { alpha, .. } | Color::RgbaIn { alpha, .. } => *alpha,
         }
     }
 
     /// Set alpha.
     pub fn set_a(&mut self, a: f32) -> &mut Self {
         match self {
-            Color::Rgba { alpha, .. } | Color::RgbaLinear { alpha, .. } | Color::Hsla { alpha, .. } => {
+            Color::Rgba { alpha, .. } | Color::RgbaLinear { alpha, .. } | Color::Hsla { alpha, .. } | Color::RgbaIn { alpha, .. } => {
                 *alpha = a;
             }
         }
@@ -236,6 +269,10 @@ impl Color {
                 let [red, green, blue] = HslRepresentation::hsl_to_nonlinear_srgb(*hue, *saturation, *lightness);
                 Color::Rgba { red, green, blue, alpha: *alpha }
             }
+            Color::RgbaIn { space, red, green, blue, alpha } => {
+                let [red, green, blue] = space.convert(RgbColorSpace::Srgb, [*red, *green, *blue]);
+                Color::Rgba { red, green, blue, alpha: *alpha }
+            }
         }
     }
 
@@ -258,6 +295,10 @@ impl Color {
                     alpha: *alpha,
                 }
             }
+            Color::RgbaIn { space, red, green, blue, alpha } => {
+                let [red, green, blue] = space.to_linear_srgb([*red, *green, *blue]);
+                Color::RgbaLinear { red, green, blue, alpha: *alpha }
+            }
         }
     }
 
@@ -277,6 +318,7 @@ impl Color {
                 Color::Hsla { hue, saturation, lightness, alpha: *alpha }
             }
             Color::Hsla { .. } => *self,
+            Color::RgbaIn { .. } => self.as_rgba().as_hsla(),
         }
     }
 
@@ -291,6 +333,10 @@ impl Color {
                 let [red, green, blue] = HslRepresentation::hsl_to_nonlinear_srgb(hue, saturation, lightness);
                 [red, green, blue, alpha]
             }
+            Color::RgbaIn { space, red, green, blue, alpha } => {
+                let [red, green, blue] = space.convert(RgbColorSpace::Srgb, [red, green, blue]);
+                [red, green, blue, alpha]
+            }
         }
     }
 
@@ -306,7 +352,37 @@ impl Color {
                 let [red, green, blue] = HslRepresentation::hsl_to_nonlinear_srgb(hue, saturation, lightness);
                 [red.nonlinear_to_linear_srgb(), green.nonlinear_to_linear_srgb(), blue.nonlinear_to_linear_srgb(), alpha]
             }
+            Color::RgbaIn { space, red, green, blue, alpha } => {
+                let [red, green, blue] = space.to_linear_srgb([red, green, blue]);
+                [red, green, blue, alpha]
+            }
+        }
+    }
+
+    /// Converts a `Color` to a `[f32; 4]` from linear RGB colorspace, with the color components multiplied by alpha
+    #[inline]
+    pub fn as_premultiplied_linear_rgba_f32(self: Color) -> [f32; 4] {
+        let [red, green, blue, alpha] = self.as_linear_rgba_f32();
+        [red * alpha, green * alpha, blue * alpha, alpha]
+    }
+
+    /// Premultiplies the color components by alpha.
+    ///
+    /// Premultiplication happens in linear RGB colorspace, so the result is always a `Color::RgbaLinear`.
+    pub fn premultiply(self) -> Self {
+        let [red, green, blue, alpha] = self.as_premultiplied_linear_rgba_f32();
+        Color::RgbaLinear { red, green, blue, alpha }
+    }
+
+    /// Reverses [Color::premultiply], dividing the linear RGB color components by alpha.
+    ///
+    /// Fully transparent colors have no recoverable color and become transparent black.
+    pub fn unpremultiply(self) -> Self {
+        let [red, green, blue, alpha] = self.as_linear_rgba_f32();
+        if alpha == 0. {
+            return Color::rgba_linear(0., 0., 0., 0.);
         }
+        Color::RgbaLinear { red: red / alpha, green: green / alpha, blue: blue / alpha, alpha }
     }
 
     /// Converts a `Color` to a `[f32; 4]` from HSL colorspace
@@ -325,6 +401,7 @@ impl Color {
                 [hue, saturation, lightness, alpha]
             }
             Color::Hsla { hue, saturation, lightness, alpha } => [hue, saturation, lightness, alpha],
+            Color::RgbaIn { .. } => self.as_rgba().as_hsla_f32(),
         }
     }
 
@@ -347,6 +424,7 @@ impl Color {
                 let [red, green, blue] = HslRepresentation::hsl_to_nonlinear_srgb(hue, saturation, lightness);
                 u32::from_le_bytes([(red * 255.0) as u8, (green * 255.0) as u8, (blue * 255.0) as u8, (alpha * 255.0) as u8])
             }
+            Color::RgbaIn { .. } => self.as_rgba().as_rgba_u32(),
         }
     }
 
@@ -374,6 +452,7 @@ impl Color {
                     (alpha * 255.0) as u8,
                 ])
             }
+            Color::RgbaIn { .. } => self.as_rgba_linear().as_linear_rgba_u32(),
         }
     }
 
@@ -398,6 +477,220 @@ impl Color {
     pub fn darken(self, amount: f32) -> Self {
         self.lighten(-amount)
     }
+
+    /// Applies `f` to each of the linear RGB components, leaving alpha unchanged.
+    ///
+    /// The result is always a `Color::RgbaLinear`, so HDR values above 1 are kept.
+    pub fn map_rgb(self, f: impl Fn(f32) -> f32) -> Self {
+        let [red, green, blue, alpha] = self.as_linear_rgba_f32();
+        Color::RgbaLinear { red: f(red), green: f(green), blue: f(blue), alpha }
+    }
+
+    /// Clamps each of the linear RGB components between `min` and `max` (e.g. `0.` and the peak brightness of an HDR
+    /// value), and alpha between 0 and 1.
+    ///
+    /// The result is always a `Color::RgbaLinear`.
+    pub fn clamp(self, min: f32, max: f32) -> Self {
+        let mut color = self.map_rgb(|component| component.clamp(min, max));
+        color.set_a(self.a().clamp(0., 1.));
+        color
+    }
+
+    /// Clamps each of the components between 0 and 1, like `saturate` in a shader; not to be confused with
+    /// [Color::saturate], which changes the HSL saturation.
+    ///
+    /// The result is always a `Color::RgbaLinear`.
+    pub fn saturate_components(self) -> Self {
+        self.clamp(0., 1.)
+    }
+
+    /// Reorders the linear RGBA components: the component `i` of the result is the component `order[i]` of this color,
+    /// e.g. `[2, 1, 0, 3]` swaps red and blue.
+    ///
+    /// The result is always a `Color::RgbaLinear`; an index above 3 is an error.
+    pub fn swizzle(self, order: [usize; 4]) -> Result<Self, SwizzleError> {
+        if let Some(&index) = order.iter().find(|&&i| i > 3) {
+            return Err(SwizzleError(index));
+        }
+        let components = self.as_linear_rgba_f32();
+        let [red, green, blue, alpha] = order.map(|i| components[i]);
+        Ok(Color::RgbaLinear { red, green, blue, alpha })
+    }
+
+    /// The relative luminance of this color (Rec. 709 coefficients, on linear RGB); above 1 for HDR values
+    pub fn luminance(self) -> f32 {
+        let [red, green, blue, _] = self.as_linear_rgba_f32();
+        0.2126 * red + 0.7152 * green + 0.0722 * blue
+    }
+
+    /// The gray with the same luminance as this color, and the same alpha
+    ///
+    /// The result is always a `Color::RgbaLinear`.
+    pub fn grayscale(self) -> Self {
+        let luminance = self.luminance();
+        Color::RgbaLinear { red: luminance, green: luminance, blue: luminance, alpha: self.a() }
+    }
+
+    /// Converts a `Color` to OKLCH (lightness, chroma, hue in degrees) and alpha
+    pub fn as_oklcha_f32(self) -> [f32; 4] {
+        let [red, green, blue, alpha] = self.as_linear_rgba_f32();
+        let (lightness, chroma, hue) = OklabRepresentation::linear_srgb_to_oklch([red, green, blue]);
+        [lightness, chroma, hue, alpha]
+    }
+
+    /// New `Color` from OKLCH; the chroma is reduced if needed to fit in the sRGB gamut
+    pub fn oklcha(lightness: f32, chroma: f32, hue: f32, alpha: f32) -> Color {
+        let [red, green, blue] = OklabRepresentation::oklch_to_linear_srgb_clamped(lightness, chroma, hue);
+        Color::RgbaLinear { red, green, blue, alpha }
+    }
+
+    /// New `Color` from RGBA in any RGB colorspace, tagged with it. Colors outside of the sRGB gamut are kept as they
+    /// are, with components below 0 or above 1 when converted to sRGB.
+    pub const fn rgba_in(space: RgbColorSpace, r: f32, g: f32, b: f32, a: f32) -> Color {
+        Color::RgbaIn { space, red: r, green: g, blue: b, alpha: a }
+    }
+
+    /// The colorspace the components of this color are in
+    pub fn color_space(&self) -> RgbColorSpace {
+        match self {
+            Color::Rgba { .. } | Color::Hsla { .. } => RgbColorSpace::Srgb,
+            Color::RgbaLinear { .. } => RgbColorSpace::LinearSrgb,
+            Color::RgbaIn { space, .. } => *space,
+        }
+    }
+
+    /// New `Color` from Display P3 colorspace.
+    pub const fn display_p3(r: f32, g: f32, b: f32, a: f32) -> Color {
+        Color::rgba_in(RgbColorSpace::DisplayP3, r, g, b, a)
+    }
+
+    /// Converts a `Color` to a `[f32; 4]` in any RGB colorspace; the alpha is unchanged
+    pub fn as_rgba_f32_in(self, space: RgbColorSpace) -> [f32; 4] {
+        if let Color::RgbaIn { space: from, red, green, blue, alpha } = self {
+            let [red, green, blue] = from.convert(space, [red, green, blue]);
+            return [red, green, blue, alpha];
+        }
+        let [red, green, blue, alpha] = self.as_linear_rgba_f32();
+        let [red, green, blue] = space.from_linear_srgb([red, green, blue]);
+        [red, green, blue, alpha]
+    }
+
+    /// Whether this color can be shown in `space` without being clamped
+    pub fn is_in_gamut(self, space: RgbColorSpace) -> bool {
+        let [red, green, blue, _] = self.as_linear_rgba_f32();
+        space.contains_linear_srgb([red, green, blue])
+    }
+
+    /// Whether the linear RGBA components of this color and `other` are all within `epsilon` of each other, whichever
+    /// variants they are
+    pub fn abs_diff_eq(&self, other: &Color, epsilon: f32) -> bool {
+        self.as_linear_rgba_f32().iter().zip(other.as_linear_rgba_f32()).all(|(a, b)| (a - b).abs() <= epsilon)
+    }
+
+    /// Returns this color with its lightness and hue changed by `map`, in the given color space
+    fn map_lightness_hue(self, space: PaletteSpace, map: impl Fn(f32, f32) -> (f32, f32)) -> Color {
+        match space {
+            PaletteSpace::Hsl => {
+                let [hue, saturation, lightness, alpha] = self.as_hsla_f32();
+                let (lightness, hue) = map(lightness, hue);
+                Color::Hsla { hue: hue.rem_euclid(360.), saturation, lightness: lightness.clamp(0., 1.), alpha }
+            }
+            PaletteSpace::Oklch => {
+                let [lightness, chroma, hue, alpha] = self.as_oklcha_f32();
+                let (lightness, hue) = map(lightness, hue);
+                Color::oklcha(lightness, chroma, hue.rem_euclid(360.), alpha)
+            }
+        }
+    }
+
+    /// Rotates the hue of this color by `degrees`
+    pub fn rotate_hue(self, degrees: f32, space: PaletteSpace) -> Color {
+        self.map_lightness_hue(space, |lightness, hue| (lightness, hue + degrees))
+    }
+
+    /// The color on the opposite side of the color wheel
+    pub fn complementary(self, space: PaletteSpace) -> Color {
+        self.rotate_hue(180., space)
+    }
+
+    /// This color surrounded by its neighbours `degrees` away on the color wheel (30 is a common choice)
+    pub fn analogous(self, degrees: f32, space: PaletteSpace) -> [Color; 3] {
+        [self.rotate_hue(-degrees, space), self, self.rotate_hue(degrees, space)]
+    }
+
+    /// This color and the two colors evenly spaced around the color wheel from it
+    pub fn triadic(self, space: PaletteSpace) -> [Color; 3] {
+        [self, self.rotate_hue(120., space), self.rotate_hue(240., space)]
+    }
+
+    /// `count` colors going from this color towards black (not included)
+    pub fn shades(self, count: usize, space: PaletteSpace) -> Vec<Color> {
+        (0..count).map(|i| self.map_lightness_hue(space, |lightness, hue| (lightness * (1. - i as f32 / count as f32), hue))).collect()
+    }
+
+    /// `count` colors going from this color towards white (not included)
+    pub fn tints(self, count: usize, space: PaletteSpace) -> Vec<Color> {
+        (0..count)
+            .map(|i| self.map_lightness_hue(space, |lightness, hue| (lightness + (1. - lightness) * i as f32 / count as f32, hue)))
+            .collect()
+    }
+
+    /// A distinct color for each index, for debug visualizations.
+    ///
+    /// The colors share the same perceived lightness and are spread around the color wheel
+    /// with the golden angle, so that consecutive indices are easy to tell apart.
+    pub fn debug(index: usize) -> Color {
+        const GOLDEN_ANGLE: f32 = 137.50777;
+        Color::oklcha(0.75, 0.15, (index as f32 * GOLDEN_ANGLE).rem_euclid(360.), 1.)
+    }
+}
+
+/// A [Color] which implements [Eq], [Ord] and [Hash], so that it can be used as a key, such as in a palette.
+///
+/// Colors are compared by their sRGBA components quantized to [OrderedColor::STEPS] steps, so the same color in
+/// different variants (or with rounding errors) is the same key. Components outside of [0.0, 1.0] are kept.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct OrderedColor(pub Color);
+impl OrderedColor {
+    pub const STEPS: f32 = 4096.;
+
+    /// The quantized sRGBA components which the color is compared by
+    pub fn key(&self) -> [i32; 4] {
+        let [red, green, blue, alpha] = self.0.as_linear_rgba_f32();
+        let nonlinear = |c: f32| c.abs().linear_to_nonlinear_srgb().copysign(c);
+        [nonlinear(red), nonlinear(green), nonlinear(blue), alpha].map(|c| (c * Self::STEPS).round() as i32)
+    }
+}
+impl PartialEq for OrderedColor {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+impl Eq for OrderedColor {}
+impl PartialOrd for OrderedColor {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for OrderedColor {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+impl std::hash::Hash for OrderedColor {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+impl From<Color> for OrderedColor {
+    fn from(color: Color) -> Self {
+        OrderedColor(color)
+    }
+}
+impl From<OrderedColor> for Color {
+    fn from(color: OrderedColor) -> Self {
+        color.0
+    }
 }
 
 impl Default for Color {
@@ -430,6 +723,13 @@ impl AddAssign<Color> for Color {
                 *lightness += rhs[2];
                 *alpha += rhs[3];
             }
+            Color::RgbaIn { space, red, green, blue, alpha } => {
+                let rhs = rhs.as_rgba_f32_in(*space);
+                *red += rhs[0];
+                *green += rhs[1];
+                *blue += rhs[2];
+                *alpha += rhs[3];
+            }
         }
     }
 }
@@ -451,6 +751,10 @@ impl Add<Color> for Color {
                 let rhs = rhs.as_linear_rgba_f32();
                 Color::Hsla { hue: hue + rhs[0], saturation: saturation + rhs[1], lightness: lightness + rhs[2], alpha: alpha + rhs[3] }
             }
+            Color::RgbaIn { space, red, green, blue, alpha } => {
+                let rhs = rhs.as_rgba_f32_in(space);
+                Color::RgbaIn { space, red: red + rhs[0], green: green + rhs[1], blue: blue + rhs[2], alpha: alpha + rhs[3] }
+            }
         }
     }
 }
@@ -530,6 +834,9 @@ impl Mul<f32> for Color {
             Color::Hsla { hue, saturation, lightness, alpha } => {
                 Color::Hsla { hue: hue * rhs, saturation: saturation * rhs, lightness: lightness * rhs, alpha }
             }
+            Color::RgbaIn { space, red, green, blue, alpha } => {
+                Color::RgbaIn { space, red: red * rhs, green: green * rhs, blue: blue * rhs, alpha }
+            }
         }
     }
 }
@@ -537,7 +844,7 @@ impl Mul<f32> for Color {
 impl MulAssign<f32> for Color {
     fn mul_assign(&mut self, rhs: f32) {
         match self {
-            Color::Rgba { red, green, blue, .. } | Color::RgbaLinear { red, green, blue, .. } => {
+            Color::Rgba { red, green, blue, .. } | Color::RgbaLinear { red, green, blue, .. } | Color::RgbaIn { red, green, blue, .. } => {
                 *red *= rhs;
                 *green *= rhs;
                 *blue *= rhs;
@@ -565,6 +872,9 @@ impl Mul<Vec4> for Color {
             Color::Hsla { hue, saturation, lightness, alpha } => {
                 Color::Hsla { hue: hue * rhs.x, saturation: saturation * rhs.y, lightness: lightness * rhs.z, alpha: alpha * rhs.w }
             }
+            Color::RgbaIn { space, red, green, blue, alpha } => {
+                Color::RgbaIn { space, red: red * rhs.x, green: green * rhs.y, blue: blue * rhs.z, alpha: alpha * rhs.w }
+            }
         }
     }
 }
@@ -572,7 +882,9 @@ impl Mul<Vec4> for Color {
 impl MulAssign<Vec4> for Color {
     fn mul_assign(&mut self, rhs: Vec4) {
         match self {
-            Color::Rgba { red, green, blue, alpha } | Color::RgbaLinear { red, green, blue, alpha } => {
+            Color::Rgba { red, green, blue, alpha }
+            | Color::RgbaLinear { red, green, blue, alpha }
+            | Color::RgbaIn { red, green, blue, alpha, .. } => {
                 *red *= rhs.x;
                 *green *= rhs.y;
                 *blue *= rhs.z;
@@ -600,6 +912,9 @@ impl Mul<Vec3> for Color {
             Color::Hsla { hue, saturation, lightness, alpha } => {
                 Color::Hsla { hue: hue * rhs.x, saturation: saturation * rhs.y, lightness: lightness * rhs.z, alpha }
             }
+            Color::RgbaIn { space, red, green, blue, alpha } => {
+                Color::RgbaIn { space, red: red * rhs.x, green: green * rhs.y, blue: blue * rhs.z, alpha }
+            }
         }
     }
 }
@@ -607,7 +922,7 @@ impl Mul<Vec3> for Color {
 impl MulAssign<Vec3> for Color {
     fn mul_assign(&mut self, rhs: Vec3) {
         match self {
-            Color::Rgba { red, green, blue, .. } | Color::RgbaLinear { red, green, blue, .. } => {
+            Color::Rgba { red, green, blue, .. } | Color::RgbaLinear { red, green, blue, .. } | Color::RgbaIn { red, green, blue, .. } => {
                 *red *= rhs.x;
                 *green *= rhs.y;
                 *blue *= rhs.z;
@@ -635,6 +950,9 @@ impl Mul<[f32; 4]> for Color {
             Color::Hsla { hue, saturation, lightness, alpha } => {
                 Color::Hsla { hue: hue * rhs[0], saturation: saturation * rhs[1], lightness: lightness * rhs[2], alpha: alpha * rhs[3] }
             }
+            Color::RgbaIn { space, red, green, blue, alpha } => {
+                Color::RgbaIn { space, red: red * rhs[0], green: green * rhs[1], blue: blue * rhs[2], alpha: alpha * rhs[3] }
+            }
         }
     }
 }
@@ -642,7 +960,9 @@ impl Mul<[f32; 4]> for Color {
 impl MulAssign<[f32; 4]> for Color {
     fn mul_assign(&mut self, rhs: [f32; 4]) {
         match self {
-            Color::Rgba { red, green, blue, alpha } | Color::RgbaLinear { red, green, blue, alpha } => {
+            Color::Rgba { red, green, blue, alpha }
+            | Color::RgbaLinear { red, green, blue, alpha }
+            | Color::RgbaIn { red, green, blue, alpha, .. } => {
                 *red *= rhs[0];
                 *green *= rhs[1];
                 *blue *= rhs[2];
@@ -670,6 +990,9 @@ impl Mul<[f32; 3]> for Color {
             Color::Hsla { hue, saturation, lightness, alpha } => {
                 Color::Hsla { hue: hue * rhs[0], saturation: saturation * rhs[1], lightness: lightness * rhs[2], alpha }
             }
+            Color::RgbaIn { space, red, green, blue, alpha } => {
+                Color::RgbaIn { space, red: red * rhs[0], green: green * rhs[1], blue: blue * rhs[2], alpha }
+            }
         }
     }
 }
@@ -677,7 +1000,7 @@ impl Mul<[f32; 3]> for Color {
 impl MulAssign<[f32; 3]> for Color {
     fn mul_assign(&mut self, rhs: [f32; 3]) {
         match self {
-            Color::Rgba { red, green, blue, .. } | Color::RgbaLinear { red, green, blue, .. } => {
+            Color::Rgba { red, green, blue, .. } | Color::RgbaLinear { red, green, blue, .. } | Color::RgbaIn { red, green, blue, .. } => {
                 *red *= rhs[0];
                 *green *= rhs[1];
                 *blue *= rhs[2];
@@ -691,6 +1014,69 @@ impl MulAssign<[f32; 3]> for Color {
     }
 }
 
+/// Returns up to `count` dominant colors of `image`, most common first.
+///
+/// This uses median cut quantization on a subsample of the opaque pixels, in sRGB colorspace.
+/// Fewer colors are returned if the image doesn't have enough distinct colors.
+pub fn extract_palette(image: &image::DynamicImage, count: usize) -> Vec<Color> {
+    const MAX_SAMPLES: usize = 1 << 16;
+
+    let image = image.to_rgba8();
+    let step = (image.pixels().len() / MAX_SAMPLES).max(1);
+    let pixels = image.pixels().step_by(step).filter(|p| p[3] >= 128).map(|p| [p[0], p[1], p[2]]).collect::<Vec<_>>();
+    if pixels.is_empty() || count == 0 {
+        return Vec::new();
+    }
+
+    // Returns the channel with the largest range of values in the box, and that range
+    fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+        (0..3)
+            .map(|c| {
+                let (min, max) = pixels.iter().fold((u8::MAX, u8::MIN), |(min, max), p| (min.min(p[c]), max.max(p[c])));
+                (c, max - min)
+            })
+            .max_by_key(|(_, range)| *range)
+            .unwrap()
+    }
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < count {
+        let (index, channel, range) = boxes
+            .iter()
+            .enumerate()
+            .map(|(i, pixels)| {
+                let (channel, range) = widest_channel(pixels);
+                (i, channel, range)
+            })
+            .max_by_key(|(_, _, range)| *range)
+            .unwrap();
+        if range == 0 {
+            break;
+        }
+        let mut pixels = boxes.swap_remove(index);
+        pixels.sort_unstable_by_key(|p| p[channel]);
+        // Split at the median, but without separating pixels with the same value
+        let median = pixels[pixels.len() / 2][channel];
+        let split = match pixels.partition_point(|p| p[channel] < median) {
+            0 => pixels.partition_point(|p| p[channel] <= median),
+            split => split,
+        };
+        let upper = pixels.split_off(split);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    boxes.sort_by_key(|pixels| std::cmp::Reverse(pixels.len()));
+    boxes
+        .iter()
+        .map(|pixels| {
+            let sum = pixels.iter().fold([0u64; 3], |sum, p| [sum[0] + p[0] as u64, sum[1] + p[1] as u64, sum[2] + p[2] as u64]);
+            let n = pixels.len() as u64;
+            Color::rgb_u8((sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8)
+        })
+        .collect()
+}
+
 #[derive(Debug, Error)]
 pub enum HexColorError {
     #[error("Unexpected length of hex string")]
@@ -699,6 +1085,37 @@ pub enum HexColorError {
     Hex(#[from] hex::FromHexError),
 }
 
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Invalid component index {0} in a swizzle; the components are 0 to 3")]
+pub struct SwizzleError(pub usize);
+
+/// Compact serde representation of a `Color` as a `"#rrggbbaa"` string.
+///
+/// Use with `#[serde(with = "ambient_std::color::serde_hex")]` on fields that end up in hand-edited files.
+/// Deserialization also accepts the verbose enum form, so existing files keep loading.
+pub mod serde_hex {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::Color;
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("#{}", color.to_hex().to_lowercase()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum HexOrColor {
+            Hex(String),
+            Color(Color),
+        }
+        match HexOrColor::deserialize(deserializer)? {
+            HexOrColor::Hex(hex) => Color::hex(hex.strip_prefix('#').unwrap_or(&hex)).map_err(D::Error::custom),
+            HexOrColor::Color(color) => Ok(color),
+        }
+    }
+}
+
 fn decode_rgb(data: &[u8]) -> Result<Color, HexColorError> {
     let mut buf = [0; 3];
     match hex::decode_to_slice(data, &mut buf) {
@@ -751,6 +1168,136 @@ mod tests {
         assert!(Color::hex("1234567890").is_err());
     }
 
+    #[test]
+    fn to_hex_color() {
+        assert_eq!(Color::rgba(1.0, 0.0, 1.0, 1.0).to_hex(), "FF00FFFF");
+        assert_eq!(Color::rgba(0.0, 0.0, 0.0, 0.0).to_hex(), "00000000");
+        assert_eq!(Color::rgba(2.0, -1.0, 0.5, 1.0).to_hex(), "FF0080FF");
+        assert_eq!(Color::hex(Color::rgba_u8(18, 52, 86, 120).to_hex()).unwrap(), Color::rgba_u8(18, 52, 86, 120));
+        assert_eq!(Color::rgb_linear(1.0, 1.0, 1.0).to_hex(), "FFFFFFFF");
+    }
+
+    #[test]
+    fn serde_hex_color() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Material {
+            #[serde(with = "serde_hex")]
+            color: Color,
+        }
+
+        let material = Material { color: Color::rgba_u8(255, 128, 0, 255) };
+        let json = serde_json::to_string(&material).unwrap();
+        assert_eq!(json, r##"{"color":"#ff8000ff"}"##);
+        assert_eq!(serde_json::from_str::<Material>(&json).unwrap(), material);
+
+        assert_eq!(serde_json::from_str::<Material>(r#"{"color":"FF8000"}"#).unwrap(), material);
+        let verbose = serde_json::to_string(&material.color).unwrap();
+        assert_eq!(serde_json::from_str::<Material>(&format!(r#"{{"color":{verbose}}}"#)).unwrap(), material);
+        assert!(serde_json::from_str::<Material>(r##"{"color":"#12"}"##).is_err());
+    }
+
+    #[test]
+    fn premultiplied_alpha() {
+        let color = Color::rgba_linear(0.8, 0.4, 0.2, 0.5);
+        assert_eq!(color.as_premultiplied_linear_rgba_f32(), [0.4, 0.2, 0.1, 0.5]);
+        assert_eq!(color.premultiply(), Color::rgba_linear(0.4, 0.2, 0.1, 0.5));
+        assert_eq!(color.premultiply().unpremultiply(), color);
+        assert_eq!(Color::rgba(1.0, 1.0, 1.0, 0.0).premultiply().unpremultiply(), Color::rgba_linear(0.0, 0.0, 0.0, 0.0));
+
+        let srgb = Color::rgba(0.5, 0.5, 0.5, 0.25).premultiply().as_linear_rgba_f32();
+        assert!((srgb[0] - 0.5f32.nonlinear_to_linear_srgb() * 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn wide_gamut() {
+        let p3_red = Color::display_p3(1.0, 0.0, 0.0, 0.5);
+        assert!(!p3_red.is_in_gamut(RgbColorSpace::Srgb));
+        assert!(p3_red.is_in_gamut(RgbColorSpace::DisplayP3));
+        let [r, g, b, a] = p3_red.as_rgba_f32_in(RgbColorSpace::DisplayP3);
+        assert!((r - 1.0).abs() < 1e-4 && g.abs() < 1e-4 && b.abs() < 1e-4 && a == 0.5);
+        assert!(Color::rgb(0.2, 0.6, 0.4).is_in_gamut(RgbColorSpace::Srgb));
+    }
+
+    #[test]
+    fn colors_are_tagged_with_their_color_space() {
+        let p3 = Color::display_p3(0.75, 0.5, 0.25, 1.0);
+        assert_eq!(p3.color_space(), RgbColorSpace::DisplayP3);
+        assert_eq!(Color::rgb(0.75, 0.5, 0.25).color_space(), RgbColorSpace::Srgb);
+        assert_eq!(Color::rgb_linear(0.75, 0.5, 0.25).color_space(), RgbColorSpace::LinearSrgb);
+        // The components stay as they were given, in their color space
+        assert_eq!(p3.as_rgba_f32_in(RgbColorSpace::DisplayP3), [0.75, 0.5, 0.25, 1.0]);
+        assert_eq!(p3 * 0.5, Color::display_p3(0.375, 0.25, 0.125, 1.0));
+        assert_eq!(p3 + Color::display_p3(0.125, 0.25, 0.5, 0.), Color::display_p3(0.875, 0.75, 0.75, 1.0));
+
+        // Converted to sRGB when used as another variant
+        let srgb = p3.as_rgba_f32();
+        assert_eq!(srgb, p3.as_rgba().as_rgba_f32());
+        assert!(p3.abs_diff_eq(&Color::rgba(srgb[0], srgb[1], srgb[2], srgb[3]), 1e-5));
+        assert!(p3.abs_diff_eq(&p3.as_hsla(), 1e-4));
+        let [r, g, b] = RgbColorSpace::DisplayP3.convert(RgbColorSpace::Srgb, [0.75, 0.5, 0.25]);
+        assert!((srgb[0] - r).abs() < 1e-6 && (srgb[1] - g).abs() < 1e-6 && (srgb[2] - b).abs() < 1e-6);
+        assert!(srgb[0] > 0.75, "The P3 red is more saturated than the sRGB one: {srgb:?}");
+
+        let json = serde_json::to_string(&p3).unwrap();
+        assert_eq!(serde_json::from_str::<Color>(&json).unwrap(), p3);
+    }
+
+    #[test]
+    fn approximate_equality() {
+        let color = Color::rgba(0.2, 0.4, 0.6, 0.8);
+        assert_ne!(color, color.as_rgba_linear());
+        assert!(color.abs_diff_eq(&color.as_rgba_linear(), 1e-6));
+        assert!(color.abs_diff_eq(&color.as_hsla(), 1e-5));
+        assert!(!color.abs_diff_eq(&Color::rgba(0.2, 0.4, 0.61, 0.8), 1e-3));
+
+        let mut palette = std::collections::HashMap::new();
+        palette.insert(OrderedColor(color), "blue");
+        assert_eq!(palette.get(&OrderedColor(color.as_hsla())), Some(&"blue"));
+        assert_eq!(palette.get(&OrderedColor(Color::rgba(0.2, 0.4, 0.6, 0.8 + 1e-6))), Some(&"blue"));
+        assert_eq!(palette.get(&OrderedColor(Color::rgba(0.2, 0.4, 0.61, 0.8))), None);
+        assert!(OrderedColor(Color::BLACK) < OrderedColor(Color::WHITE));
+    }
+
+    #[test]
+    fn palettes() {
+        let base = Color::hsl(30., 0.8, 0.5);
+        let hue = |color: Color| color.as_hsla_f32()[0];
+        assert!((hue(base.complementary(PaletteSpace::Hsl)) - 210.).abs() < 1e-3);
+        let [a, b, c] = base.triadic(PaletteSpace::Hsl);
+        assert_eq!(a, base);
+        assert!((hue(b) - 150.).abs() < 1e-3 && (hue(c) - 270.).abs() < 1e-3);
+        assert!((hue(base.analogous(30., PaletteSpace::Hsl)[0]) - 0.).abs() < 1e-3);
+
+        let oklch = base.as_oklcha_f32();
+        let complementary = base.complementary(PaletteSpace::Oklch).as_oklcha_f32();
+        assert!((oklch[0] - complementary[0]).abs() < 1e-3);
+        assert!(((oklch[2] + 180.).rem_euclid(360.) - complementary[2]).abs() < 1.);
+
+        let shades = base.shades(4, PaletteSpace::Oklch);
+        assert_eq!(shades.len(), 4);
+        assert!(shades.windows(2).all(|w| w[0].as_oklcha_f32()[0] > w[1].as_oklcha_f32()[0]));
+        let tints = base.tints(4, PaletteSpace::Hsl);
+        assert!(tints.windows(2).all(|w| w[0].as_hsla_f32()[2] < w[1].as_hsla_f32()[2]));
+
+        assert_ne!(Color::debug(0), Color::debug(1));
+        assert!((Color::debug(3).as_oklcha_f32()[0] - 0.75).abs() < 1e-3);
+    }
+
+    #[test]
+    fn dominant_colors() {
+        let image = image::RgbaImage::from_fn(8, 8, |x, y| match (x < 4, y < 4) {
+            (true, _) => image::Rgba([255, 0, 0, 255]),
+            (false, true) => image::Rgba([0, 0, 255, 255]),
+            (false, false) => image::Rgba([0, 255, 0, if x == 7 { 0 } else { 255 }]),
+        });
+        let image = image::DynamicImage::ImageRgba8(image);
+
+        assert_eq!(extract_palette(&image, 3), vec![Color::rgb_u8(255, 0, 0), Color::rgb_u8(0, 0, 255), Color::rgb_u8(0, 255, 0)]);
+        assert_eq!(extract_palette(&image, 10).len(), 3);
+        assert_eq!(extract_palette(&image, 1).len(), 1);
+        assert!(extract_palette(&image, 0).is_empty());
+    }
+
     #[test]
     fn conversions_vec4() {
         let starting_vec4 = Vec4::new(0.4, 0.5, 0.6, 1.0);
@@ -827,4 +1374,27 @@ mod tests {
 
         assert_eq!(starting_color * transformation, mutated_color,);
     }
+
+    #[test]
+    fn component_wise_helpers() {
+        let hdr = Color::rgba_linear(4.0, -0.5, 0.5, 1.5);
+        assert_eq!(hdr.clamp(0., 2.), Color::rgba_linear(2.0, 0.0, 0.5, 1.0));
+        assert_eq!(hdr.saturate_components(), Color::rgba_linear(1.0, 0.0, 0.5, 1.0));
+        assert_eq!(hdr.map_rgb(|component| component * 2.), Color::rgba_linear(8.0, -1.0, 1.0, 1.5));
+        assert_eq!(hdr.swizzle([2, 1, 0, 3]), Ok(Color::rgba_linear(0.5, -0.5, 4.0, 1.5)));
+        assert_eq!(hdr.swizzle([0, 1, 4, 3]), Err(SwizzleError(4)));
+        assert!(Color::hsla(120., 1., 0.5, 0.5).saturate_components().abs_diff_eq(&Color::rgba(0., 1., 0., 0.5), 1e-5));
+    }
+
+    #[test]
+    fn luminance_and_grayscale() {
+        assert!((Color::WHITE.luminance() - 1.).abs() < 1e-5);
+        assert_eq!(Color::BLACK.luminance(), 0.);
+        assert!((Color::rgb_linear(0., 1., 0.).luminance() - 0.7152).abs() < 1e-5);
+        assert!((Color::rgb_linear(2., 2., 2.).luminance() - 2.).abs() < 1e-5);
+
+        let gray = Color::rgba(1., 0., 0., 0.5).grayscale();
+        assert!(gray.abs_diff_eq(&Color::rgba_linear(0.2126, 0.2126, 0.2126, 0.5), 1e-5));
+        assert!((gray.luminance() - Color::rgb(1., 0., 0.).luminance()).abs() < 1e-5);
+    }
 }
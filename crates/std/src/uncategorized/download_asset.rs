@@ -1,4 +1,4 @@
-use std::{borrow::BorrowMut, marker::PhantomData, path::PathBuf, sync::Arc, time::Duration};
+use std::{marker::PhantomData, path::PathBuf, sync::Arc, time::Duration};
 
 use ambient_sys::task::wasm_nonsend;
 use anyhow::{anyhow, Context};
@@ -12,7 +12,9 @@ use yaml_rust::YamlLoader;
 use crate::{
     asset_cache::{AssetCache, AssetKeepalive, AsyncAssetKey, AsyncAssetKeyExt, SyncAssetKey, SyncAssetKeyExt},
     asset_url::AbsAssetUrl,
+    download_cache,
     mesh::Mesh,
+    url_scheme::url_scheme_handler,
 };
 
 pub type AssetResult<T> = Result<T, AssetError>;
@@ -74,6 +76,17 @@ pub(crate) async fn download<T: 'static + Send, F: Future<Output = anyhow::Resul
     assets: &AssetCache,
     url: impl reqwest::IntoUrl,
     map: impl 'static + Send + Fn(reqwest::Response) -> F,
+) -> anyhow::Result<T> {
+    download_with(assets, url, |request| request, map).await
+}
+
+/// Like [download], but `request` can customize the request (e.g. to add headers). A `304 Not Modified` response
+/// is passed on to `map`, as it's the expected answer to a conditional request.
+pub(crate) async fn download_with<T: 'static + Send, F: Future<Output = anyhow::Result<T>>>(
+    assets: &AssetCache,
+    url: impl reqwest::IntoUrl,
+    request: impl 'static + Send + Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+    map: impl 'static + Send + Fn(reqwest::Response) -> F,
 ) -> anyhow::Result<T> {
     let url_str = url.as_str().to_string();
     let url = url.into_url()?;
@@ -90,8 +103,8 @@ pub(crate) async fn download<T: 'static + Send, F: Future<Output = anyhow::Resul
             log::info!("download [pending ] {}", url_short);
             let _permit = semaphore.acquire().await.unwrap();
             log::info!("download [download] {}", url_short);
-            let resp = client.get(url.clone()).send().await.with_context(|| format!("Failed to download {url_str}"))?;
-            if !resp.status().is_success() {
+            let resp = request(client.get(url.clone())).send().await.with_context(|| format!("Failed to download {url_str}"))?;
+            if !resp.status().is_success() && resp.status() != reqwest::StatusCode::NOT_MODIFIED {
                 log::warn!("Request for {} failed: {:?}", url_str, resp.status());
                 return Err(anyhow!("Downloading {url_str} failed, bad status code: {:?}", resp.status()));
             }
@@ -141,7 +154,7 @@ impl AsyncAssetKey<AssetResult<Arc<Vec<u8>>>> for BytesFromUrl {
             return Ok(Arc::new(ambient_sys::fs::read(path).await.context(format!("Failed to read file at: {:}", self.url.0))?));
         }
 
-        let body = download(&assets, self.url.0.clone(), |resp| async { Ok(resp.bytes().await?) }).await?.to_vec();
+        let body = download_cache::download_bytes(&assets, &self.url).await?;
         assert!(!body.is_empty());
         Ok(Arc::new(body))
     }
@@ -185,30 +198,29 @@ impl AsyncAssetKey<AssetResult<Arc<PathBuf>>> for BytesFromUrlCachedPath {
         if let Some(path) = self.url.to_file_path()? {
             return Ok(Arc::new(path));
         }
+        if let Some(path) = download_cache::content_source_path(&assets, &self.url).await {
+            return Ok(Arc::new(path.with_context(|| format!("Failed to load {}", self.url))?));
+        }
         let path = self.url.absolute_cache_path(&assets);
-        if !path.exists() {
-            use tokio::io::AsyncWriteExt;
-            let mut dir = path.clone();
-            dir.pop();
-            std::fs::create_dir_all(&dir).context(format!("Failed to create asset dir: {dir:?}"))?;
-            let tmp_path = path.with_extension(".downloading");
-            download(&assets, self.url.0.clone(), {
-                let tmp_path = tmp_path.clone();
-                move |mut resp| {
-                    let tmp_path = tmp_path.clone();
-                    async move {
-                        let mut file = tokio::fs::File::create(&tmp_path).await.context(format!("Failed to create file: {tmp_path:?}"))?;
-                        while let Some(mut item) = resp.chunk().await.context("Failed to download chunk")? {
-                            file.write_all_buf(item.borrow_mut()).await.context("Failed to write to tmp file")?;
-                        }
-                        file.flush().await.context("Failed to flush tmp file")?;
-                        Ok(())
-                    }
-                }
-            })
-            .await?;
-            std::fs::rename(&tmp_path, &path).context(format!("Failed to rename tmp file, from: {tmp_path:?}, to: {path:?}"))?;
-            log::info!("Cached asset at {:?}", path);
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".validators.json");
+        let validators_path = path.with_file_name(file_name);
+        if let Some(handler) = url_scheme_handler(&assets, &self.url) {
+            if !path.exists() {
+                let mut dir = path.clone();
+                dir.pop();
+                std::fs::create_dir_all(&dir).context(format!("Failed to create asset dir: {dir:?}"))?;
+                let tmp_path = path.with_extension(".downloading");
+                let content = handler.download_bytes(&assets, &self.url).await.with_context(|| format!("Failed to load {}", self.url))?;
+                ambient_sys::fs::write(&tmp_path, content).await.context(format!("Failed to write file: {tmp_path:?}"))?;
+                std::fs::rename(&tmp_path, &path).context(format!("Failed to rename tmp file, from: {tmp_path:?}, to: {path:?}"))?;
+                log::info!("Cached asset at {:?}", path);
+            }
+        } else if !path.exists() || validators_path.exists() {
+            // Copies which were cached without validators can't be revalidated, so they are kept as they are
+            if download_cache::revalidate(&assets, &self.url, &path, &validators_path, None).await?.is_some() {
+                log::info!("Cached asset at {:?}", path);
+            }
         }
 
         return Ok(Arc::new(path));
@@ -317,4 +329,24 @@ impl<T: DeserializeOwned + std::fmt::Debug + Sync + Send + 'static> AsyncAssetKe
     }
 }
 
-pub type MeshFromUrl = BincodeFromUrl<Mesh>;
+/// Loads a [Mesh] serialized with bincode, or compressed with [Mesh::to_compressed_bytes]
+#[derive(Debug, Clone)]
+pub struct MeshFromUrl {
+    pub url: AbsAssetUrl,
+    pub cache_on_disk: bool,
+}
+impl MeshFromUrl {
+    pub fn new(url: AbsAssetUrl, cache_on_disk: bool) -> Self {
+        Self { url, cache_on_disk }
+    }
+    pub fn parse_url(url: impl AsRef<str>, cache_on_disk: bool) -> anyhow::Result<Self> {
+        Ok(Self { url: AbsAssetUrl::parse(url)?, cache_on_disk })
+    }
+}
+#[async_trait]
+impl AsyncAssetKey<AssetResult<Arc<Mesh>>> for MeshFromUrl {
+    async fn load(self, assets: AssetCache) -> AssetResult<Arc<Mesh>> {
+        let data = BytesFromUrl { url: self.url.clone(), cache_on_disk: self.cache_on_disk }.get(&assets).await?;
+        Ok(Arc::new(Mesh::from_bytes(&data).context("Failed to deserialize")?))
+    }
+}
@@ -0,0 +1,389 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use futures::future::Either;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::Notify;
+
+use crate::{
+    asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt},
+    asset_url::AbsAssetUrl,
+    download_asset::{download, download_with},
+    url_scheme::url_scheme_handler,
+};
+
+/// When set, remote downloads made with [AbsAssetUrl::download_bytes] (and the other `download_*` methods)
+/// are persisted in this directory. On the next run, they are revalidated with the server using their
+/// `ETag` and `Last-Modified` headers instead of being downloaded again, and the cached copy is used
+/// if the server can't be reached.
+///
+/// This is a layer below the in-memory [AssetCache]; it's disabled unless the directory is inserted.
+#[derive(Clone, Debug)]
+pub struct DownloadCacheDir;
+impl SyncAssetKey<PathBuf> for DownloadCacheDir {}
+
+/// Loads the content of some of the remote urls from elsewhere than their host, such as the game connection. The
+/// downloads go through it first once it's inserted as the [ContentSourceKey], and the urls it doesn't know are
+/// downloaded as usual.
+#[async_trait]
+pub trait ContentSource: Send + Sync {
+    /// The path of the content of `url` on this machine once it's fetched, or None if it isn't one of its urls
+    async fn fetch(&self, url: &AbsAssetUrl) -> Option<anyhow::Result<PathBuf>>;
+}
+
+#[derive(Clone, Debug)]
+pub struct ContentSourceKey;
+impl SyncAssetKey<Arc<dyn ContentSource>> for ContentSourceKey {}
+
+/// The path of the content of `url` which the [ContentSourceKey] fetched, if it's one of its urls
+#[cfg(not(target_os = "unknown"))]
+pub(crate) async fn content_source_path(assets: &AssetCache, url: &AbsAssetUrl) -> Option<anyhow::Result<PathBuf>> {
+    ContentSourceKey.try_get(assets)?.fetch(url).await
+}
+
+/// The validators of a cached download, stored next to its content
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct CacheEntry {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+enum Fetched {
+    NotModified,
+    Modified(Vec<u8>, CacheEntry),
+}
+
+/// How much of a download has been received so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadProgress {
+    pub downloaded: u64,
+    /// The size announced by the server, if any
+    pub total: Option<u64>,
+}
+
+pub type DownloadProgressCb = Arc<dyn Fn(DownloadProgress) + Send + Sync>;
+
+/// Cancels the downloads it's passed to once [CancellationToken::cancel] is called on it, or on any of its clones
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<CancellationState>);
+
+#[derive(Debug, Default)]
+struct CancellationState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+    /// Completes once the token is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            // Created before the check, so that a cancellation in between isn't missed
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// The error returned by a download which was cancelled with a [CancellationToken]
+#[derive(Debug, Error)]
+#[error("The download of {0} was cancelled")]
+pub struct DownloadCancelled(pub AbsAssetUrl);
+
+/// Downloads the content of `url`, through the [DownloadCacheDir] if there is one. Urls with a custom scheme are
+/// loaded by their [crate::url_scheme::UrlSchemeHandler] instead, and aren't cached; and the urls of the [ContentSourceKey]
+/// are loaded from it.
+pub(crate) async fn download_bytes(assets: &AssetCache, url: &AbsAssetUrl) -> anyhow::Result<Vec<u8>> {
+    download_bytes_reporting(assets, url, None).await
+}
+
+/// Like [download_bytes], but also loads files, reports the progress to `on_progress` as the body is received, and stops
+/// as soon as `cancel` is cancelled. Files and urls with a custom scheme are loaded in one go, so their progress is only
+/// reported once they are loaded.
+pub(crate) async fn download_bytes_with_progress(
+    assets: &AssetCache,
+    url: &AbsAssetUrl,
+    on_progress: DownloadProgressCb,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<u8>> {
+    if cancel.is_cancelled() {
+        return Err(DownloadCancelled(url.clone()).into());
+    }
+    let download = async {
+        match url.to_file_path()? {
+            Some(path) => {
+                let content = ambient_sys::fs::read(path).await.with_context(|| format!("Failed to read file at: {url}"))?;
+                report_loaded(Some(&on_progress), &content);
+                Ok(content)
+            }
+            None => download_bytes_reporting(assets, url, Some(on_progress.clone())).await,
+        }
+    };
+    match futures::future::select(Box::pin(cancel.cancelled()), Box::pin(download)).await {
+        Either::Left(_) => Err(DownloadCancelled(url.clone()).into()),
+        Either::Right((res, _)) => res,
+    }
+}
+
+async fn download_bytes_reporting(
+    assets: &AssetCache,
+    url: &AbsAssetUrl,
+    on_progress: Option<DownloadProgressCb>,
+) -> anyhow::Result<Vec<u8>> {
+    if let Some(handler) = url_scheme_handler(assets, url) {
+        let content = handler.download_bytes(assets, url).await.with_context(|| format!("Failed to load {url}"))?;
+        report_loaded(on_progress.as_ref(), &content);
+        return Ok(content);
+    }
+    #[cfg(not(target_os = "unknown"))]
+    if let Some(path) = content_source_path(assets, url).await {
+        let path = path.with_context(|| format!("Failed to load {url}"))?;
+        let content = ambient_sys::fs::read(&path).await.with_context(|| format!("Failed to read file: {path:?}"))?;
+        report_loaded(on_progress.as_ref(), &content);
+        return Ok(content);
+    }
+    #[cfg(not(target_os = "unknown"))]
+    if let Some(dir) = DownloadCacheDir.try_get(assets) {
+        return download_bytes_through(assets, url, dir, on_progress).await;
+    }
+    download(assets, url.0.clone(), move |resp| read_body(resp, on_progress.clone())).await
+}
+
+/// Reads the body of `resp`, reporting the progress to `on_progress` after each chunk
+async fn read_body(mut resp: reqwest::Response, on_progress: Option<DownloadProgressCb>) -> anyhow::Result<Vec<u8>> {
+    let on_progress = match on_progress {
+        Some(on_progress) => on_progress,
+        None => return Ok(resp.bytes().await?.to_vec()),
+    };
+    let total = resp.content_length();
+    on_progress(DownloadProgress { downloaded: 0, total });
+    #[cfg(not(target_os = "unknown"))]
+    {
+        let mut body = Vec::with_capacity(total.unwrap_or_default() as usize);
+        while let Some(chunk) = resp.chunk().await? {
+            body.extend_from_slice(&chunk);
+            on_progress(DownloadProgress { downloaded: body.len() as u64, total });
+        }
+        Ok(body)
+    }
+    // The body can't be streamed on the web
+    #[cfg(target_os = "unknown")]
+    {
+        let body = resp.bytes().await?.to_vec();
+        on_progress(DownloadProgress { downloaded: body.len() as u64, total });
+        Ok(body)
+    }
+}
+
+/// Reports content which was loaded in one go as a completed download
+fn report_loaded(on_progress: Option<&DownloadProgressCb>, content: &[u8]) {
+    if let Some(on_progress) = on_progress {
+        on_progress(DownloadProgress { downloaded: content.len() as u64, total: Some(content.len() as u64) });
+    }
+}
+
+#[cfg(not(target_os = "unknown"))]
+async fn download_bytes_through(
+    assets: &AssetCache,
+    url: &AbsAssetUrl,
+    dir: PathBuf,
+    on_progress: Option<DownloadProgressCb>,
+) -> anyhow::Result<Vec<u8>> {
+    let content_path = dir.join(crate::sha256_digest(url.0.as_ref()));
+    let entry_path = content_path.with_extension("json");
+    match revalidate(assets, url, &content_path, &entry_path, on_progress.clone()).await? {
+        Some(content) => Ok(content),
+        None => {
+            let content =
+                ambient_sys::fs::read(&content_path).await.with_context(|| format!("Failed to read cached download {content_path:?}"))?;
+            report_loaded(on_progress.as_ref(), &content);
+            Ok(content)
+        }
+    }
+}
+
+/// Makes sure `content_path` holds the current content of `url`, with its validators stored at `entry_path`.
+///
+/// If there's a copy already, the request is made conditional on it having changed, so an unchanged url only costs a
+/// `304 Not Modified` response. Returns the new content if it was downloaded, or `None` if the existing copy is to be
+/// used; that's also the case when the server can't be reached.
+#[cfg(not(target_os = "unknown"))]
+pub(crate) async fn revalidate(
+    assets: &AssetCache,
+    url: &AbsAssetUrl,
+    content_path: &std::path::Path,
+    entry_path: &std::path::Path,
+    on_progress: Option<DownloadProgressCb>,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    use reqwest::{header, StatusCode};
+
+    let url_str = url.0.to_string();
+    let cached = match (content_path.exists(), ambient_sys::fs::read(entry_path).await) {
+        (true, Ok(data)) => serde_json::from_slice::<CacheEntry>(&data).ok().filter(|entry| entry.url == url_str),
+        _ => None,
+    };
+
+    let validators = cached.clone().unwrap_or_default();
+    let fetched = download_with(
+        assets,
+        url.0.clone(),
+        move |mut request| {
+            if let Some(etag) = &validators.etag {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+            }
+            request
+        },
+        move |resp| {
+            let url = url_str.clone();
+            let on_progress = on_progress.clone();
+            async move {
+                if resp.status() == StatusCode::NOT_MODIFIED {
+                    return Ok(Fetched::NotModified);
+                }
+                let get_header = |name: header::HeaderName| {
+                    resp.headers().get(name).and_then(|value| value.to_str().ok()).map(|value| value.to_string())
+                };
+                let entry = CacheEntry { url, etag: get_header(header::ETAG), last_modified: get_header(header::LAST_MODIFIED) };
+                Ok(Fetched::Modified(read_body(resp, on_progress).await?, entry))
+            }
+        },
+    )
+    .await;
+
+    match fetched {
+        Ok(Fetched::NotModified) => {
+            log::info!("Using cached download of {url}");
+            Ok(None)
+        }
+        Ok(Fetched::Modified(content, entry)) => {
+            if let Some(dir) = content_path.parent() {
+                std::fs::create_dir_all(dir).with_context(|| format!("Failed to create download cache dir: {dir:?}"))?;
+            }
+            // The validators are removed while the content is replaced, so that an interrupted write is never considered valid
+            if entry_path.exists() {
+                std::fs::remove_file(entry_path).with_context(|| format!("Failed to remove {entry_path:?}"))?;
+            }
+            let tmp_path = content_path.with_extension("downloading");
+            ambient_sys::fs::write(&tmp_path, &content).await.with_context(|| format!("Failed to write {tmp_path:?}"))?;
+            std::fs::rename(&tmp_path, content_path).with_context(|| format!("Failed to rename {tmp_path:?} to {content_path:?}"))?;
+            // Without validators the content can't be revalidated, so it will be downloaded again next time
+            if entry.etag.is_some() || entry.last_modified.is_some() {
+                ambient_sys::fs::write(entry_path, serde_json::to_vec(&entry)?)
+                    .await
+                    .with_context(|| format!("Failed to write {entry_path:?}"))?;
+            }
+            Ok(Some(content))
+        }
+        Err(err) if cached.is_some() => {
+            log::warn!("Failed to revalidate {url}, using the cached download instead: {err:?}");
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use super::{CancellationToken, DownloadCancelled, DownloadProgress};
+    use crate::{asset_cache::AssetCache, asset_url::AbsAssetUrl};
+
+    #[tokio::test]
+    async fn progress_and_cancellation() {
+        let assets = AssetCache::new(tokio::runtime::Handle::current());
+        let path = std::env::temp_dir().join(format!("ambient_download_progress_{}", std::process::id()));
+        std::fs::write(&path, b"content").unwrap();
+        let url = AbsAssetUrl::from_file_path(&path);
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let on_progress = {
+            let reported = reported.clone();
+            move |progress| reported.lock().push(progress)
+        };
+        let content = url.download_bytes_with_progress(&assets, on_progress, &CancellationToken::new()).await.unwrap();
+        assert_eq!(content, b"content");
+        assert_eq!(*reported.lock(), vec![DownloadProgress { downloaded: 7, total: Some(7) }]);
+
+        let cancel = CancellationToken::new();
+        let waiting = tokio::spawn({
+            let cancel = cancel.clone();
+            async move { cancel.cancelled().await }
+        });
+        cancel.cancel();
+        waiting.await.unwrap();
+        let err = url.download_bytes_with_progress(&assets, |_| {}, &cancel).await.unwrap_err();
+        assert!(err.downcast_ref::<DownloadCancelled>().is_some());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn conditional_requests() {
+        use std::io::{Read, Write};
+
+        // Serves `content` with an ETag, and answers 304 to the requests which already have it
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let request = String::from_utf8(request).unwrap().to_lowercase();
+                let response = if request.contains("if-none-match: \"v1\"") {
+                    "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string()
+                } else {
+                    "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 7\r\nConnection: close\r\n\r\ncontent".to_string()
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+
+        let assets = AssetCache::new(tokio::runtime::Handle::current());
+        let url = AbsAssetUrl::parse(format!("http://127.0.0.1:{port}/asset.bin")).unwrap();
+        let dir = std::env::temp_dir().join(format!("ambient_conditional_requests_{}", std::process::id()));
+        let (content_path, entry_path) = (dir.join("asset.bin"), dir.join("asset.bin.json"));
+        let downloaded = super::revalidate(&assets, &url, &content_path, &entry_path, None).await.unwrap();
+        assert_eq!(downloaded.as_deref(), Some(&b"content"[..]));
+        assert_eq!(super::revalidate(&assets, &url, &content_path, &entry_path, None).await.unwrap(), None);
+        assert_eq!(std::fs::read(&content_path).unwrap(), b"content");
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,237 @@
+//! The attributes of the points, which are stored in the order of a traversal of the mesh, usually as integers which are
+//! predicted from the values decoded before them
+
+use anyhow::{bail, ensure, Context};
+
+use super::{
+    buffer::{unzigzag, Buffer},
+    prediction::{MeshData, Octahedron, Prediction},
+    rans::decode_symbols,
+};
+
+pub(super) const POSITION: u8 = 0;
+
+/// How an attribute is declared in the data
+#[derive(Debug, Clone)]
+pub(super) struct Declaration {
+    pub kind: u8,
+    pub data_type: DataType,
+    pub components: usize,
+    pub unique_id: u32,
+}
+impl Declaration {
+    pub fn decode(buffer: &mut Buffer) -> anyhow::Result<Self> {
+        let kind = buffer.u8()?;
+        let data_type = DataType::decode(buffer.u8()?)?;
+        let components = buffer.u8()? as usize;
+        ensure!(components > 0, "Invalid number of attribute components");
+        let _normalized = buffer.u8()?;
+        let unique_id = buffer.varint_u32()?;
+        Ok(Self { kind, data_type, components, unique_id })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum DataType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+    Bool,
+}
+impl DataType {
+    fn decode(data_type: u8) -> anyhow::Result<Self> {
+        Ok(match data_type {
+            1 => Self::I8,
+            2 => Self::U8,
+            3 => Self::I16,
+            4 => Self::U16,
+            5 => Self::I32,
+            6 => Self::U32,
+            7 => Self::I64,
+            8 => Self::U64,
+            9 => Self::F32,
+            10 => Self::F64,
+            11 => Self::Bool,
+            _ => bail!("Invalid attribute data type {data_type}"),
+        })
+    }
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 | Self::Bool => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::I64 | Self::U64 | Self::F64 => 8,
+        }
+    }
+    fn read(self, bytes: &[u8]) -> f64 {
+        match self {
+            Self::I8 => bytes[0] as i8 as f64,
+            Self::U8 | Self::Bool => bytes[0] as f64,
+            Self::I16 => i16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::U16 => u16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::I64 => i64::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::U64 => u64::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::F32 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Self::F64 => f64::from_le_bytes(bytes.try_into().unwrap()),
+        }
+    }
+    /// The integer decoded as a value of this type
+    fn convert(self, value: i32) -> f64 {
+        match self {
+            Self::I8 => value as i8 as f64,
+            Self::U8 => value as u8 as f64,
+            Self::I16 => value as i16 as f64,
+            Self::U16 => value as u16 as f64,
+            Self::U32 | Self::U64 => value as u32 as f64,
+            Self::Bool => (value != 0) as u8 as f64,
+            _ => value as f64,
+        }
+    }
+}
+
+/// How the values of an attribute are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Encoding {
+    /// As is
+    Generic,
+    /// As predicted integers
+    Integer,
+    /// As predicted integers, which are the quantized floats
+    Quantization,
+    /// As predicted octahedral coordinates of the normals
+    Normals,
+}
+impl Encoding {
+    pub fn decode(buffer: &mut Buffer) -> anyhow::Result<Self> {
+        Ok(match buffer.u8()? {
+            0 => Self::Generic,
+            1 => Self::Integer,
+            2 => Self::Quantization,
+            3 => Self::Normals,
+            encoding => bail!("Unknown attribute encoding {encoding}"),
+        })
+    }
+}
+
+/// An attribute which is decoded
+pub(super) struct Attribute {
+    pub declaration: Declaration,
+    pub encoding: Encoding,
+    /// The value of each point
+    pub point_values: Vec<u32>,
+    /// The integers the values were stored as, which the other attributes are predicted with
+    pub portable: Vec<i32>,
+    /// The decoded values, with a value for each component
+    pub values: Vec<f64>,
+}
+impl Attribute {
+    fn portable_components(&self) -> usize {
+        match self.encoding {
+            Encoding::Normals => 2,
+            _ => self.declaration.components,
+        }
+    }
+
+    /// The quantized position of a value, if this is the position attribute
+    pub fn position(&self, point: u32) -> Option<[i64; 3]> {
+        if self.declaration.kind != POSITION || self.portable_components() != 3 {
+            return None;
+        }
+        let value = *self.point_values.get(point as usize)? as usize;
+        let position = self.portable.get(value * 3..value * 3 + 3)?;
+        Some([position[0] as i64, position[1] as i64, position[2] as i64])
+    }
+
+    /// Decodes the values of the attribute, which are the values of the points `value_points` (but for the
+    /// transforms, which are decoded with [Attribute::decode_transform])
+    pub fn decode_values<'a>(
+        &mut self,
+        buffer: &mut Buffer<'a>,
+        value_points: &[u32],
+        mesh: Option<MeshData>,
+        corner_count: usize,
+        positions: &dyn Fn(u32) -> Option<[i64; 3]>,
+    ) -> anyhow::Result<()> {
+        let count = value_points.len();
+        if self.encoding == Encoding::Generic {
+            let size = self.declaration.data_type.size();
+            let bytes = buffer.bytes(count * self.declaration.components * size)?;
+            self.values = bytes.chunks_exact(size).map(|bytes| self.declaration.data_type.read(bytes)).collect();
+            return Ok(());
+        }
+
+        let components = self.portable_components();
+        let mut prediction = Prediction::new(buffer, mesh.is_some(), self.encoding == Encoding::Normals)?;
+        let value_count = count * components;
+        let mut values = if buffer.u8()? > 0 {
+            decode_symbols(buffer, value_count, components)?.into_iter().map(|value| value as i32).collect::<Vec<_>>()
+        } else {
+            let size = buffer.u8()? as usize;
+            ensure!((1..=4).contains(&size), "Invalid integer size {size}");
+            let bytes = buffer.bytes(value_count * size)?;
+            bytes.chunks_exact(size).map(|bytes| bytes.iter().rev().fold(0u32, |value, &byte| value << 8 | byte as u32) as i32).collect()
+        };
+        if !prediction.as_ref().map_or(false, |prediction| prediction.corrections_positive()) {
+            values.iter_mut().for_each(|value| *value = unzigzag(*value as u32));
+        }
+        if let Some(prediction) = &mut prediction {
+            prediction.decode_data(buffer, corner_count)?;
+            let positions = |entry: usize| positions(*value_points.get(entry)?);
+            prediction.compute_original_values(&mut values, components, mesh, &positions)?;
+        }
+        self.portable = values;
+        Ok(())
+    }
+
+    /// Decodes the parameters of the transform of the values, and transforms them back
+    pub fn decode_transform(&mut self, buffer: &mut Buffer) -> anyhow::Result<()> {
+        match self.encoding {
+            Encoding::Generic => {}
+            Encoding::Integer => {
+                let data_type = self.declaration.data_type;
+                self.values = self.portable.iter().map(|&value| data_type.convert(value)).collect();
+            }
+            Encoding::Quantization => {
+                let components = self.declaration.components;
+                let min = (0..components).map(|_| buffer.f32()).collect::<anyhow::Result<Vec<_>>>()?;
+                let range = buffer.f32()?;
+                let bits = buffer.u8()? as u32;
+                ensure!((1..=30).contains(&bits), "Invalid quantization bits {bits}");
+                let scale = range / ((1u32 << bits) - 1) as f32;
+                self.values =
+                    self.portable.iter().enumerate().map(|(i, &value)| (value as f32 * scale + min[i % components]) as f64).collect();
+            }
+            Encoding::Normals => {
+                let octahedron = Octahedron::new(buffer.u8()? as u32)?;
+                ensure!(self.declaration.components == 3, "Invalid number of normal components");
+                self.values = self
+                    .portable
+                    .chunks_exact(2)
+                    .flat_map(|coords| octahedron.coords_to_vector(coords[0], coords[1]))
+                    .map(|value| value as f64)
+                    .collect();
+            }
+        }
+        Ok(())
+    }
+
+    /// The values of the points, with a value for each component
+    pub fn point_values(&self) -> anyhow::Result<Vec<f64>> {
+        let components = self.declaration.components;
+        let mut values = Vec::with_capacity(self.point_values.len() * components);
+        for &value in &self.point_values {
+            let value = value as usize;
+            values.extend_from_slice(self.values.get(value * components..(value + 1) * components).context("Missing attribute value")?);
+        }
+        Ok(values)
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::{ensure, Context};
+
+use crate::varint::read_varint;
+
+/// Reads the values of a Draco bitstream, which are little endian
+pub(super) struct Buffer<'a> {
+    data: &'a [u8],
+}
+impl<'a> Buffer<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// The number of bytes left, which bounds the counts of the data before they're allocated
+    pub fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        ensure!(self.data.len() >= len, "The Draco data is truncated");
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+    pub fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+    pub fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+    pub fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    pub fn f32(&mut self) -> anyhow::Result<f32> {
+        Ok(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    /// An unsigned LEB128 value
+    pub fn varint(&mut self) -> anyhow::Result<u64> {
+        read_varint(&mut self.data).context("Invalid varint in the Draco data")
+    }
+    pub fn varint_u32(&mut self) -> anyhow::Result<u32> {
+        self.varint()?.try_into().context("Invalid varint in the Draco data")
+    }
+
+    /// The bits of the rest of the data; the bytes read are skipped with [Buffer::skip_bits]
+    pub fn bits(&self) -> BitReader<'a> {
+        BitReader { data: self.data, position: 0 }
+    }
+    pub fn skip_bits(&mut self, bits: &BitReader) -> anyhow::Result<()> {
+        self.bytes((bits.position + 7) / 8)?;
+        Ok(())
+    }
+    /// The bits of a bit sequence, whose size is stored first
+    pub fn bit_sequence(&mut self) -> anyhow::Result<BitReader<'a>> {
+        let len = self.varint()? as usize;
+        Ok(BitReader { data: self.bytes(len)?, position: 0 })
+    }
+}
+
+/// Reads bits, least significant first
+pub(super) struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+impl BitReader<'_> {
+    pub fn read(&mut self, count: u32) -> anyhow::Result<u32> {
+        ensure!(count <= 32, "Invalid number of bits {count}");
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.position / 8).context("The Draco bit sequence is truncated")?;
+            value |= (((byte >> (self.position % 8)) & 1) as u32) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// The inverse of the zigzag encoding of the signed values of Draco, which keeps the sign in the lowest bit
+pub(super) fn unzigzag(value: u32) -> i32 {
+    if value & 1 == 1 {
+        -((value >> 1) as i32) - 1
+    } else {
+        (value >> 1) as i32
+    }
+}
@@ -0,0 +1,189 @@
+//! The connectivity of the meshes, as the corners of their triangles. The corners `3 * face..3 * face + 3` are the
+//! corners of a face, in counter clockwise order.
+
+use anyhow::Context;
+
+const INVALID: u32 = u32::MAX;
+
+pub(super) fn next(corner: u32) -> u32 {
+    if corner % 3 == 2 {
+        corner - 2
+    } else {
+        corner + 1
+    }
+}
+pub(super) fn previous(corner: u32) -> u32 {
+    if corner % 3 == 0 {
+        corner + 2
+    } else {
+        corner - 1
+    }
+}
+
+pub(super) trait Corners {
+    fn vertex(&self, corner: u32) -> u32;
+    /// The corner facing the same edge as `corner`, in the neighbouring face
+    fn opposite(&self, corner: u32) -> Option<u32>;
+    /// The first corner of `vertex` in counter clockwise order, which is on the boundary if the vertex is
+    fn left_most_corner(&self, vertex: u32) -> Option<u32>;
+    fn vertex_count(&self) -> usize;
+    fn face_count(&self) -> usize;
+
+    /// The corner of the same vertex in the face on the left of `corner`
+    fn swing_left(&self, corner: u32) -> Option<u32> {
+        self.opposite(next(corner)).map(next)
+    }
+    /// The corner of the same vertex in the face on the right of `corner`
+    fn swing_right(&self, corner: u32) -> Option<u32> {
+        self.opposite(previous(corner)).map(previous)
+    }
+    fn left_corner(&self, corner: u32) -> Option<u32> {
+        self.opposite(previous(corner))
+    }
+    fn right_corner(&self, corner: u32) -> Option<u32> {
+        self.opposite(next(corner))
+    }
+    fn is_on_boundary(&self, vertex: u32) -> bool {
+        self.left_most_corner(vertex).and_then(|corner| self.swing_left(corner)).is_none()
+    }
+    /// The corners of the vertex of `corner`, starting from it and swinging left, then right from it once the boundary
+    /// is reached
+    fn vertex_corners(&self, corner: u32) -> Vec<u32> {
+        let mut corners = vec![corner];
+        let mut current = self.swing_left(corner);
+        while let Some(left) = current {
+            if left == corner {
+                return corners;
+            }
+            corners.push(left);
+            current = self.swing_left(left);
+        }
+        current = self.swing_right(corner);
+        while let Some(right) = current {
+            // Only reached on broken meshes, where a vertex is on the boundary only to the left
+            if right == corner || corners.len() > 3 * self.face_count() {
+                break;
+            }
+            corners.push(right);
+            current = self.swing_right(right);
+        }
+        corners
+    }
+}
+
+/// The connectivity decoded by edgebreaker, where each vertex has a single position
+#[derive(Debug, Clone)]
+pub(super) struct CornerTable {
+    corner_vertices: Vec<u32>,
+    opposites: Vec<u32>,
+    vertex_corners: Vec<u32>,
+}
+impl CornerTable {
+    /// Fails if the corners can't be indexed with a `u32`
+    pub fn new(face_count: usize) -> anyhow::Result<Self> {
+        let corner_count =
+            face_count.checked_mul(3).filter(|&count| count <= u32::MAX as usize).context("Too many faces for a corner table")?;
+        Ok(Self { corner_vertices: vec![INVALID; corner_count], opposites: vec![INVALID; corner_count], vertex_corners: Vec::new() })
+    }
+    pub fn corner_count(&self) -> usize {
+        self.corner_vertices.len()
+    }
+
+    pub fn add_vertex(&mut self) -> u32 {
+        self.vertex_corners.push(INVALID);
+        self.vertex_corners.len() as u32 - 1
+    }
+    pub fn map_corner(&mut self, corner: u32, vertex: u32) {
+        self.corner_vertices[corner as usize] = vertex;
+    }
+    pub fn set_left_most_corner(&mut self, vertex: u32, corner: Option<u32>) {
+        self.vertex_corners[vertex as usize] = corner.unwrap_or(INVALID);
+    }
+    pub fn set_opposites(&mut self, a: u32, b: u32) {
+        self.opposites[a as usize] = b;
+        self.opposites[b as usize] = a;
+    }
+}
+impl Corners for CornerTable {
+    fn vertex(&self, corner: u32) -> u32 {
+        self.corner_vertices[corner as usize]
+    }
+    fn opposite(&self, corner: u32) -> Option<u32> {
+        Some(self.opposites[corner as usize]).filter(|&opposite| opposite != INVALID)
+    }
+    fn left_most_corner(&self, vertex: u32) -> Option<u32> {
+        Some(self.vertex_corners[vertex as usize]).filter(|&corner| corner != INVALID)
+    }
+    fn vertex_count(&self) -> usize {
+        self.vertex_corners.len()
+    }
+    fn face_count(&self) -> usize {
+        self.corner_vertices.len() / 3
+    }
+}
+
+/// The connectivity of an attribute whose values are split along some edges, the seams, which can't be crossed. The
+/// vertices are split where they are on seams, so that each has a single value of the attribute.
+#[derive(Debug, Clone)]
+pub(super) struct SeamCornerTable<'a> {
+    table: &'a CornerTable,
+    /// If the edge facing each corner is a seam
+    seams: &'a [bool],
+    corner_vertices: Vec<u32>,
+    vertex_corners: Vec<u32>,
+}
+impl<'a> SeamCornerTable<'a> {
+    pub fn new(table: &'a CornerTable, seams: &'a [bool], vertex_on_seam: &[bool]) -> Self {
+        let mut seam_table = Self { table, seams, corner_vertices: vec![INVALID; seams.len()], vertex_corners: Vec::new() };
+        for vertex in 0..table.vertex_count() as u32 {
+            let first = match table.left_most_corner(vertex) {
+                Some(corner) => corner,
+                None => continue,
+            };
+            // The first corner is on a seam, if there's one, when swinging left
+            let mut first_corner = first;
+            if vertex_on_seam[vertex as usize] {
+                while let Some(left) = seam_table.swing_left(first_corner) {
+                    if left == first {
+                        break;
+                    }
+                    first_corner = left;
+                }
+            }
+            let mut seam_vertex = seam_table.vertex_corners.len() as u32;
+            seam_table.vertex_corners.push(first_corner);
+            seam_table.corner_vertices[first_corner as usize] = seam_vertex;
+            let mut corner = table.swing_right(first_corner);
+            while let Some(current) = corner.filter(|&corner| corner != first_corner) {
+                if seams[next(current) as usize] {
+                    seam_vertex = seam_table.vertex_corners.len() as u32;
+                    seam_table.vertex_corners.push(current);
+                }
+                seam_table.corner_vertices[current as usize] = seam_vertex;
+                corner = table.swing_right(current);
+            }
+        }
+        seam_table
+    }
+}
+impl Corners for SeamCornerTable<'_> {
+    fn vertex(&self, corner: u32) -> u32 {
+        self.corner_vertices[corner as usize]
+    }
+    fn opposite(&self, corner: u32) -> Option<u32> {
+        if self.seams[corner as usize] {
+            None
+        } else {
+            self.table.opposite(corner)
+        }
+    }
+    fn left_most_corner(&self, vertex: u32) -> Option<u32> {
+        self.vertex_corners.get(vertex as usize).copied()
+    }
+    fn vertex_count(&self) -> usize {
+        self.vertex_corners.len()
+    }
+    fn face_count(&self) -> usize {
+        self.table.face_count()
+    }
+}
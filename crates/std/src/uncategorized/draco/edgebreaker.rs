@@ -0,0 +1,409 @@
+//! The edgebreaker connectivity of Draco, which encodes the triangles as the steps of a traversal of the mesh: each face
+//! is one of five symbols, depending on which of its neighbours were already visited. The decoder replays the steps
+//! backwards, which reconnects the faces.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, ensure, Context};
+
+use super::{
+    buffer::{BitReader, Buffer},
+    corner_table::{next, previous, CornerTable, Corners},
+    rans::{decode_symbols, BitDecoder},
+};
+
+const STANDARD_TRAVERSAL: u8 = 0;
+const VALENCE_TRAVERSAL: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Symbol {
+    /// A face between two edges of the boundary
+    C,
+    /// A face which joins two parts of the boundary
+    S,
+    /// A face with a new vertex, whose left edge is on the boundary
+    L,
+    /// A face with a new vertex, whose right edge is on the boundary
+    R,
+    /// A face with three new vertices, which starts a part of the mesh
+    E,
+}
+
+/// The connectivity decoded by edgebreaker
+pub(super) struct Edgebreaker {
+    pub table: CornerTable,
+    /// The seams of the attributes with their own connectivity
+    pub seams: Vec<Seams>,
+    /// If each vertex is on a boundary
+    pub is_hole: Vec<bool>,
+    /// The number of vertices once the ones merged together are removed, if the mesh has no attribute connectivity
+    pub vertex_count: usize,
+}
+
+/// Where the values of an attribute are split
+#[derive(Debug, Clone)]
+pub(super) struct Seams {
+    /// If the edge facing each corner is a seam
+    pub edges: Vec<bool>,
+    /// If each vertex of the mesh is on a seam
+    pub vertices: Vec<bool>,
+}
+
+pub(super) fn decode_connectivity(buffer: &mut Buffer) -> anyhow::Result<Edgebreaker> {
+    let traversal_kind = buffer.u8()?;
+    let vertex_count = buffer.varint_u32()? as usize;
+    let face_count = buffer.varint_u32()? as usize;
+    let attribute_data_count = buffer.u8()? as usize;
+    let symbol_count = buffer.varint_u32()? as usize;
+    ensure!(symbol_count <= face_count && face_count <= symbol_count + symbol_count / 3, "Invalid number of faces");
+    let split_symbol_count = buffer.varint_u32()? as usize;
+    ensure!(split_symbol_count <= symbol_count, "Invalid number of split symbols");
+    // Each face takes at least a bit of the data left, so that the counts of malformed data aren't allocated
+    ensure!(face_count / 8 <= buffer.remaining(), "Invalid number of faces");
+    let table = CornerTable::new(face_count)?;
+    let corner_count = table.corner_count();
+    ensure!(vertex_count + split_symbol_count <= corner_count, "Invalid number of vertices");
+
+    let mut splits = decode_topology_splits(buffer, face_count)?;
+    let mut traversal = TraversalDecoder::new(buffer, traversal_kind, attribute_data_count, vertex_count + split_symbol_count, face_count)?;
+    let mut decoder = Decoder {
+        table,
+        is_hole: vec![true; vertex_count + split_symbol_count],
+        active_corners: Vec::new(),
+        split_corners: HashMap::new(),
+        isolated_vertices: Vec::new(),
+        remove_isolated_vertices: attribute_data_count == 0,
+    };
+    let vertex_count = decoder.decode(&mut traversal, &mut splits, symbol_count)?;
+
+    // The seams are decoded for the edges between faces; the boundaries are always seams
+    let mut seam_edges = vec![vec![false; corner_count]; attribute_data_count];
+    let table = &decoder.table;
+    for corner in 0..corner_count as u32 {
+        match table.opposite(corner) {
+            None => seam_edges.iter_mut().for_each(|edges| edges[corner as usize] = true),
+            Some(opposite) if opposite / 3 < corner / 3 => {}
+            Some(_) => {
+                for (edges, seams) in seam_edges.iter_mut().zip(&mut traversal.seams) {
+                    edges[corner as usize] = seams.read();
+                }
+            }
+        }
+    }
+    let seams = seam_edges
+        .into_iter()
+        .map(|mut edges| {
+            let mut vertices = vec![false; table.vertex_count()];
+            for corner in 0..corner_count as u32 {
+                if edges[corner as usize] {
+                    vertices[table.vertex(next(corner)) as usize] = true;
+                    vertices[table.vertex(previous(corner)) as usize] = true;
+                    if let Some(opposite) = table.opposite(corner) {
+                        edges[opposite as usize] = true;
+                        vertices[table.vertex(next(opposite)) as usize] = true;
+                        vertices[table.vertex(previous(opposite)) as usize] = true;
+                    }
+                }
+            }
+            Seams { edges, vertices }
+        })
+        .collect();
+    Ok(Edgebreaker { table: decoder.table, seams, is_hole: decoder.is_hole, vertex_count })
+}
+
+/// A face of the traversal which connects to a face decoded later, which ends with an S symbol
+struct TopologySplit {
+    /// The symbols are numbered in the order of the encoder, which is the reverse of the decoder
+    source_symbol: usize,
+    split_symbol: usize,
+    /// If the split face is on the right edge of the source face, rather than on its left one
+    right_edge: bool,
+}
+
+fn decode_topology_splits(buffer: &mut Buffer, face_count: usize) -> anyhow::Result<Vec<TopologySplit>> {
+    let count = buffer.varint_u32()? as usize;
+    // Each split takes at least two bytes
+    ensure!(count <= face_count && count <= buffer.remaining() / 2, "Invalid number of topology splits");
+    let mut splits = Vec::with_capacity(count);
+    let mut last_source_symbol = 0;
+    for _ in 0..count {
+        let source_symbol = last_source_symbol + buffer.varint_u32()? as usize;
+        let split_symbol = source_symbol.checked_sub(buffer.varint_u32()? as usize).context("Invalid topology split")?;
+        splits.push(TopologySplit { source_symbol, split_symbol, right_edge: false });
+        last_source_symbol = source_symbol;
+    }
+    let mut bits = buffer.bits();
+    for split in &mut splits {
+        split.right_edge = bits.read(1)? == 1;
+    }
+    buffer.skip_bits(&bits)?;
+    Ok(splits)
+}
+
+struct TraversalDecoder<'a> {
+    symbols: SymbolDecoder<'a>,
+    /// If the faces starting each part of the mesh are inside of it, rather than on its boundary
+    start_faces: BitDecoder<'a>,
+    seams: Vec<BitDecoder<'a>>,
+}
+enum SymbolDecoder<'a> {
+    Standard(BitReader<'a>),
+    /// The symbols are compressed by the valence of the next vertex of the active corner
+    Valence {
+        contexts: Vec<Vec<u32>>,
+        valences: Vec<u32>,
+        context: Option<usize>,
+        last_symbol: Symbol,
+    },
+}
+impl<'a> TraversalDecoder<'a> {
+    fn new(buffer: &mut Buffer<'a>, kind: u8, attribute_data_count: usize, vertex_count: usize, face_count: usize) -> anyhow::Result<Self> {
+        let standard_symbols = match kind {
+            STANDARD_TRAVERSAL => Some(buffer.bit_sequence()?),
+            VALENCE_TRAVERSAL => None,
+            _ => bail!("Unsupported edgebreaker traversal {kind}"),
+        };
+        let start_faces = BitDecoder::new(buffer)?;
+        let seams = (0..attribute_data_count).map(|_| BitDecoder::new(buffer)).collect::<anyhow::Result<_>>()?;
+        let symbols = match standard_symbols {
+            Some(symbols) => SymbolDecoder::Standard(symbols),
+            None => {
+                let split_symbol_count = buffer.varint_u32()? as usize;
+                ensure!(split_symbol_count < vertex_count, "Invalid number of split symbols");
+                ensure!(buffer.u8()? == 0, "Unsupported edgebreaker valence mode");
+                let contexts = (MIN_VALENCE..=MAX_VALENCE)
+                    .map(|_| {
+                        let count = buffer.varint_u32()? as usize;
+                        ensure!(count <= face_count, "Invalid number of edgebreaker symbols");
+                        decode_symbols(buffer, count, 1)
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+                SymbolDecoder::Valence { contexts, valences: vec![0; vertex_count], context: None, last_symbol: Symbol::E }
+            }
+        };
+        Ok(Self { symbols, start_faces, seams })
+    }
+
+    fn symbol(&mut self) -> anyhow::Result<Symbol> {
+        match &mut self.symbols {
+            SymbolDecoder::Standard(bits) => Ok(match bits.read(1)? {
+                0 => Symbol::C,
+                _ => [Symbol::S, Symbol::L, Symbol::R, Symbol::E][bits.read(2)? as usize],
+            }),
+            SymbolDecoder::Valence { contexts, context, last_symbol, .. } => {
+                // Without a context, the symbol starts a part of the mesh
+                if let Some(context) = *context {
+                    let symbol = contexts[context].pop().context("Invalid edgebreaker symbols")?;
+                    *last_symbol = *[Symbol::C, Symbol::S, Symbol::L, Symbol::R, Symbol::E]
+                        .get(symbol as usize)
+                        .context("Invalid edgebreaker symbol")?;
+                } else {
+                    *last_symbol = Symbol::E;
+                }
+                Ok(*last_symbol)
+            }
+        }
+    }
+    fn new_active_corner(&mut self, table: &CornerTable, corner: u32) {
+        if let SymbolDecoder::Valence { valences, context, last_symbol, .. } = &mut self.symbols {
+            let vertices = [table.vertex(corner), table.vertex(next(corner)), table.vertex(previous(corner))];
+            let added = match last_symbol {
+                Symbol::C | Symbol::S => [0, 1, 1],
+                Symbol::R => [1, 1, 2],
+                Symbol::L => [1, 2, 1],
+                Symbol::E => [2, 2, 2],
+            };
+            for (vertex, added) in vertices.into_iter().zip(added) {
+                valences[vertex as usize] += added;
+            }
+            let valence = valences[vertices[1] as usize].clamp(MIN_VALENCE, MAX_VALENCE);
+            *context = Some((valence - MIN_VALENCE) as usize);
+        }
+    }
+    fn merge_vertices(&mut self, target: u32, source: u32) {
+        if let SymbolDecoder::Valence { valences, .. } = &mut self.symbols {
+            valences[target as usize] += valences[source as usize];
+        }
+    }
+}
+
+const MIN_VALENCE: u32 = 2;
+const MAX_VALENCE: u32 = 7;
+
+struct Decoder {
+    table: CornerTable,
+    is_hole: Vec<bool>,
+    /// The corners facing the edges of the boundary which the next faces are attached to
+    active_corners: Vec<u32>,
+    /// The corners the split symbols are attached to, by symbol
+    split_corners: HashMap<usize, u32>,
+    isolated_vertices: Vec<u32>,
+    remove_isolated_vertices: bool,
+}
+impl Decoder {
+    fn decode(&mut self, traversal: &mut TraversalDecoder, splits: &mut Vec<TopologySplit>, symbol_count: usize) -> anyhow::Result<usize> {
+        let max_vertex_count = self.is_hole.len();
+        let face_count = self.table.face_count();
+        for symbol_index in 0..symbol_count {
+            let corner = 3 * symbol_index as u32;
+            let symbol = traversal.symbol()?;
+            match symbol {
+                Symbol::C => self.decode_c(corner)?,
+                Symbol::L | Symbol::R => self.decode_l_r(corner, symbol == Symbol::R)?,
+                Symbol::S => self.decode_s(traversal, corner, symbol_index)?,
+                Symbol::E => {
+                    for i in 0..3 {
+                        let vertex = self.table.add_vertex();
+                        self.table.map_corner(corner + i, vertex);
+                        self.table.set_left_most_corner(vertex, Some(corner + i));
+                    }
+                    self.active_corners.push(corner);
+                }
+            }
+            ensure!(self.table.vertex_count() <= max_vertex_count, "Invalid edgebreaker connectivity");
+            let active_corner = *self.active_corners.last().unwrap();
+            traversal.new_active_corner(&self.table, active_corner);
+
+            // The faces which aren't attached to the previous ones may start topology splits
+            if matches!(symbol, Symbol::L | Symbol::R | Symbol::E) {
+                let encoder_symbol = symbol_count - symbol_index - 1;
+                while let Some(split) = splits.last() {
+                    ensure!(split.source_symbol <= encoder_symbol, "Invalid topology split");
+                    if split.source_symbol != encoder_symbol {
+                        break;
+                    }
+                    let split_corner = if split.right_edge { next(active_corner) } else { previous(active_corner) };
+                    self.split_corners.insert(symbol_count - split.split_symbol - 1, split_corner);
+                    splits.pop();
+                }
+            }
+        }
+
+        // The start faces close the parts of the mesh which are inside of them
+        let mut face = symbol_count;
+        while let Some(corner) = self.active_corners.pop() {
+            if !traversal.start_faces.read() {
+                continue;
+            }
+            ensure!(face < face_count, "Invalid number of faces");
+            let vertex_n = self.table.vertex(next(corner));
+            let corner_b = next(self.table.left_most_corner(vertex_n).context("Invalid edgebreaker connectivity")?);
+            let vertex_x = self.table.vertex(next(corner_b));
+            let corner_c = next(self.table.left_most_corner(vertex_x).context("Invalid edgebreaker connectivity")?);
+            ensure!(corner != corner_b && corner != corner_c && corner_b != corner_c, "Invalid edgebreaker connectivity");
+            ensure!(
+                [corner, corner_b, corner_c].iter().all(|&corner| self.table.opposite(corner).is_none()),
+                "Invalid edgebreaker connectivity"
+            );
+            let vertex_p = self.table.vertex(next(corner_c));
+            let new_corner = 3 * face as u32;
+            face += 1;
+            for (i, (opposite, vertex)) in [(corner, vertex_x), (corner_b, vertex_p), (corner_c, vertex_n)].into_iter().enumerate() {
+                self.table.set_opposites(new_corner + i as u32, opposite);
+                self.table.map_corner(new_corner + i as u32, vertex);
+                self.is_hole[vertex as usize] = false;
+            }
+        }
+        ensure!(face == face_count, "Invalid number of faces");
+
+        // The vertices merged by the split symbols are replaced with the last ones
+        let mut vertex_count = self.table.vertex_count();
+        for &isolated in &self.isolated_vertices {
+            let mut last = vertex_count as u32 - 1;
+            while self.table.left_most_corner(last).is_none() {
+                vertex_count -= 1;
+                last = vertex_count as u32 - 1;
+            }
+            if last < isolated {
+                continue;
+            }
+            let left_most_corner = self.table.left_most_corner(last).unwrap();
+            for corner in self.table.vertex_corners(left_most_corner) {
+                self.table.map_corner(corner, isolated);
+            }
+            self.table.set_left_most_corner(isolated, Some(left_most_corner));
+            self.table.set_left_most_corner(last, None);
+            self.is_hole[isolated as usize] = self.is_hole[last as usize];
+            self.is_hole[last as usize] = false;
+            vertex_count -= 1;
+        }
+        Ok(vertex_count)
+    }
+
+    fn active_corner(&self) -> anyhow::Result<u32> {
+        self.active_corners.last().copied().context("Invalid edgebreaker connectivity")
+    }
+
+    fn decode_c(&mut self, corner: u32) -> anyhow::Result<()> {
+        let corner_a = self.active_corner()?;
+        let vertex_x = self.table.vertex(next(corner_a));
+        let corner_b = next(self.table.left_most_corner(vertex_x).context("Invalid edgebreaker connectivity")?);
+        ensure!(corner_a != corner_b, "Invalid edgebreaker connectivity");
+        ensure!(self.table.opposite(corner_a).is_none() && self.table.opposite(corner_b).is_none(), "Invalid edgebreaker connectivity");
+        self.table.set_opposites(corner_a, corner + 1);
+        self.table.set_opposites(corner_b, corner + 2);
+        let vertex_a_previous = self.table.vertex(previous(corner_a));
+        let vertex_b_next = self.table.vertex(next(corner_b));
+        ensure!(vertex_x != vertex_a_previous && vertex_x != vertex_b_next, "Invalid edgebreaker connectivity");
+        self.table.map_corner(corner, vertex_x);
+        self.table.map_corner(corner + 1, vertex_b_next);
+        self.table.map_corner(corner + 2, vertex_a_previous);
+        self.table.set_left_most_corner(vertex_a_previous, Some(corner + 2));
+        self.is_hole[vertex_x as usize] = false;
+        *self.active_corners.last_mut().unwrap() = corner;
+        Ok(())
+    }
+
+    fn decode_l_r(&mut self, corner: u32, right: bool) -> anyhow::Result<()> {
+        let corner_a = self.active_corner()?;
+        ensure!(self.table.opposite(corner_a).is_none(), "Invalid edgebreaker connectivity");
+        let (opposite, corner_l, corner_r) = if right { (corner + 2, corner + 1, corner) } else { (corner + 1, corner, corner + 2) };
+        self.table.set_opposites(opposite, corner_a);
+        let vertex = self.table.add_vertex();
+        ensure!(self.table.vertex_count() <= self.is_hole.len(), "Invalid edgebreaker connectivity");
+        self.table.map_corner(opposite, vertex);
+        self.table.set_left_most_corner(vertex, Some(opposite));
+        let vertex_r = self.table.vertex(previous(corner_a));
+        self.table.map_corner(corner_r, vertex_r);
+        self.table.set_left_most_corner(vertex_r, Some(corner_r));
+        self.table.map_corner(corner_l, self.table.vertex(next(corner_a)));
+        *self.active_corners.last_mut().unwrap() = corner;
+        Ok(())
+    }
+
+    fn decode_s(&mut self, traversal: &mut TraversalDecoder, corner: u32, symbol_index: usize) -> anyhow::Result<()> {
+        let corner_b = self.active_corners.pop().context("Invalid edgebreaker connectivity")?;
+        if let Some(&split_corner) = self.split_corners.get(&symbol_index) {
+            self.active_corners.push(split_corner);
+        }
+        let corner_a = self.active_corner()?;
+        ensure!(corner_a != corner_b, "Invalid edgebreaker connectivity");
+        ensure!(self.table.opposite(corner_a).is_none() && self.table.opposite(corner_b).is_none(), "Invalid edgebreaker connectivity");
+        self.table.set_opposites(corner_a, corner + 2);
+        self.table.set_opposites(corner_b, corner + 1);
+        let vertex_p = self.table.vertex(previous(corner_a));
+        self.table.map_corner(corner, vertex_p);
+        self.table.map_corner(corner + 1, self.table.vertex(next(corner_a)));
+        let vertex_b_previous = self.table.vertex(previous(corner_b));
+        self.table.map_corner(corner + 2, vertex_b_previous);
+        self.table.set_left_most_corner(vertex_b_previous, Some(corner + 2));
+
+        // The vertex n is merged into the vertex p
+        let first_corner_n = next(corner_b);
+        let vertex_n = self.table.vertex(first_corner_n);
+        traversal.merge_vertices(vertex_p, vertex_n);
+        self.table.set_left_most_corner(vertex_p, self.table.left_most_corner(vertex_n));
+        let mut corner_n = Some(first_corner_n);
+        while let Some(current) = corner_n {
+            self.table.map_corner(current, vertex_p);
+            corner_n = self.table.swing_left(current);
+            ensure!(corner_n != Some(first_corner_n), "Invalid edgebreaker connectivity");
+        }
+        self.table.set_left_most_corner(vertex_n, None);
+        if self.remove_isolated_vertices {
+            self.isolated_vertices.push(vertex_n);
+        }
+        *self.active_corners.last_mut().unwrap() = corner;
+        Ok(())
+    }
+}
@@ -0,0 +1,464 @@
+//! A decoder of the meshes compressed with [Draco](https://github.com/google/draco), which the glTF files using
+//! `KHR_draco_mesh_compression` store their primitives with.
+//!
+//! Only version 2.2 of the bitstream is read, which is what the encoders have written since 2018. Both the sequential
+//! and the edgebreaker connectivities are read, with all of the prediction schemes the encoder uses.
+
+use anyhow::{bail, ensure, Context};
+
+use self::{
+    attributes::{Attribute, Declaration, Encoding},
+    buffer::Buffer,
+    corner_table::{Corners, SeamCornerTable},
+    prediction::MeshData,
+    rans::decode_symbols,
+    traversal::{assign_points, traverse, Traversal},
+};
+
+mod attributes;
+mod buffer;
+mod corner_table;
+mod edgebreaker;
+mod prediction;
+mod rans;
+mod traversal;
+
+const MAGIC: &[u8] = b"DRACO";
+const VERSION: (u8, u8) = (2, 2);
+const TRIANGULAR_MESH: u8 = 1;
+const SEQUENTIAL: u8 = 0;
+const EDGEBREAKER: u8 = 1;
+const METADATA_FLAG: u16 = 0x8000;
+
+/// A decoded mesh. Each point has a value of each of the attributes.
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    /// The points of the triangles
+    pub indices: Vec<u32>,
+    pub point_count: usize,
+    pub attributes: Vec<MeshAttribute>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MeshAttribute {
+    /// The id the attribute is referred to by, such as in the glTF extension
+    pub unique_id: u32,
+    pub components: usize,
+    /// The values of the points, with `components` values each. They're exact for the integers of up to 32 bits
+    /// and the floats, which is all that the glTF accessors can hold.
+    pub values: Vec<f64>,
+}
+
+/// Decodes a mesh compressed with Draco
+pub fn decode_mesh(data: &[u8]) -> anyhow::Result<Mesh> {
+    let mut buffer = Buffer::new(data);
+    ensure!(buffer.bytes(MAGIC.len()).ok() == Some(MAGIC), "Not Draco data");
+    let version = (buffer.u8()?, buffer.u8()?);
+    ensure!(version == VERSION, "Unsupported Draco version {}.{}", version.0, version.1);
+    ensure!(buffer.u8()? == TRIANGULAR_MESH, "The Draco data isn't a mesh");
+    let method = buffer.u8()?;
+    let flags = buffer.u16()?;
+    if flags & METADATA_FLAG != 0 {
+        skip_metadata(&mut buffer)?;
+    }
+
+    let mut mesh = match method {
+        SEQUENTIAL => decode_sequential(&mut buffer)?,
+        EDGEBREAKER => decode_edgebreaker(&mut buffer)?,
+        _ => bail!("Unknown Draco encoding {method}"),
+    };
+    mesh.attributes.sort_by_key(|attribute| attribute.unique_id);
+    Ok(mesh)
+}
+
+fn decode_sequential(buffer: &mut Buffer) -> anyhow::Result<Mesh> {
+    let face_count = buffer.varint_u32()? as usize;
+    let point_count = buffer.varint_u32()? as usize;
+    // Like the reference decoder, there's at least a byte left for each index and each point is on a face, so that
+    // the counts of malformed data aren't allocated
+    let corner_count = face_count.checked_mul(3).filter(|&count| count <= buffer.remaining()).context("Invalid number of faces")?;
+    ensure!(corner_count <= u32::MAX as usize, "Invalid number of faces");
+    ensure!(point_count <= corner_count, "Invalid number of points");
+    let indices = if buffer.u8()? == 0 {
+        // The indices are compressed as the differences to the previous one
+        let mut last = 0i64;
+        decode_symbols(buffer, corner_count, 1)?
+            .into_iter()
+            .map(|symbol| {
+                let difference = (symbol >> 1) as i64;
+                last += if symbol & 1 == 1 { -difference } else { difference };
+                ensure!((0..=i32::MAX as i64).contains(&last), "Invalid index");
+                Ok(last as u32)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+    } else {
+        (0..corner_count)
+            .map(|_| {
+                Ok(if point_count < 1 << 8 {
+                    buffer.u8()? as u32
+                } else if point_count < 1 << 16 {
+                    buffer.u16()? as u32
+                } else if point_count < 1 << 21 {
+                    buffer.varint_u32()?
+                } else {
+                    buffer.u32()?
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+    };
+    ensure!(indices.iter().all(|&index| (index as usize) < point_count), "Invalid index");
+
+    // The values of the attributes are in the order of the points
+    let decoder_count = buffer.u8()? as usize;
+    let declarations = (0..decoder_count).map(|_| decode_declarations(buffer)).collect::<anyhow::Result<Vec<_>>>()?;
+    let value_points = (0..point_count as u32).collect::<Vec<_>>();
+    let mut attributes = Vec::new();
+    for declarations in declarations {
+        let start = attributes.len();
+        for (declaration, encoding) in declarations {
+            let mut attribute =
+                Attribute { declaration, encoding, point_values: value_points.clone(), portable: Vec::new(), values: Vec::new() };
+            let positions = |point: u32| attributes.iter().find_map(|attribute: &Attribute| attribute.position(point));
+            attribute.decode_values(buffer, &value_points, None, 0, &positions)?;
+            attributes.push(attribute);
+        }
+        for attribute in &mut attributes[start..] {
+            attribute.decode_transform(buffer)?;
+        }
+    }
+    Ok(Mesh { indices, point_count, attributes: mesh_attributes(&attributes)? })
+}
+
+/// The attributes of an attribute decoder, and how they're stored
+fn decode_declarations(buffer: &mut Buffer) -> anyhow::Result<Vec<(Declaration, Encoding)>> {
+    let count = buffer.varint_u32()? as usize;
+    ensure!(count > 0, "Invalid number of attributes");
+    let declarations = (0..count).map(|_| Declaration::decode(buffer)).collect::<anyhow::Result<Vec<_>>>()?;
+    declarations.into_iter().map(|declaration| Ok((declaration, Encoding::decode(buffer)?))).collect()
+}
+
+/// Which connectivity the values of the attributes of an attribute decoder follow
+struct AttributeDecoder {
+    /// The attribute data with the seams of the attributes, if they have their own
+    attribute_data: Option<usize>,
+    /// If the values are on the corners of the seam connectivity of the attribute data, rather than on the vertices
+    corners: bool,
+    traversal: Traversal,
+}
+
+fn decode_edgebreaker(buffer: &mut Buffer) -> anyhow::Result<Mesh> {
+    let connectivity = edgebreaker::decode_connectivity(buffer)?;
+    let table = &connectivity.table;
+    let seam_tables = connectivity.seams.iter().map(|seams| SeamCornerTable::new(table, &seams.edges, &seams.vertices)).collect::<Vec<_>>();
+    let (corner_points, point_count) = if seam_tables.is_empty() {
+        ((0..3 * table.face_count() as u32).map(|corner| table.vertex(corner)).collect(), connectivity.vertex_count)
+    } else {
+        let attribute_tables = seam_tables.iter().map(|table| table as &dyn Corners).collect::<Vec<_>>();
+        assign_points(table, &connectivity.is_hole, &attribute_tables)
+    };
+
+    let decoder_count = buffer.u8()? as usize;
+    let decoders = (0..decoder_count)
+        .map(|_| {
+            let attribute_data = buffer.u8()? as i8;
+            let attribute_data = usize::try_from(attribute_data).ok();
+            ensure!(attribute_data.map_or(true, |data| data < seam_tables.len()), "Invalid attribute data {attribute_data:?}");
+            let corners = match buffer.u8()? {
+                0 => false,
+                1 => true,
+                kind => bail!("Unknown attribute decoder {kind}"),
+            };
+            let traversal = match buffer.u8()? {
+                0 => Traversal::DepthFirst,
+                1 if !corners => Traversal::PredictionDegree,
+                traversal => bail!("Unsupported attribute traversal {traversal}"),
+            };
+            ensure!(!corners || attribute_data.is_some(), "The corner attributes have no attribute data");
+            Ok(AttributeDecoder { attribute_data, corners, traversal })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let declarations = (0..decoder_count).map(|_| decode_declarations(buffer)).collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut attributes = Vec::new();
+    for (decoder, declarations) in decoders.iter().zip(declarations) {
+        let seam_table = decoder.attribute_data.filter(|_| decoder.corners).map(|data| &seam_tables[data]);
+        let attribute_table: &dyn Corners = match seam_table {
+            Some(seam_table) => seam_table,
+            None => table,
+        };
+        let encoding = traverse(attribute_table, &corner_points, decoder.traversal);
+        let mut point_values = vec![0; point_count];
+        for corner in 0..3 * table.face_count() as u32 {
+            let value = encoding.vertex_values[attribute_table.vertex(corner) as usize];
+            ensure!(value != u32::MAX, "Invalid attribute connectivity");
+            point_values[corner_points[corner as usize] as usize] = value;
+        }
+
+        let start = attributes.len();
+        for (declaration, attribute_encoding) in declarations {
+            let mut attribute = Attribute {
+                declaration,
+                encoding: attribute_encoding,
+                point_values: point_values.clone(),
+                portable: Vec::new(),
+                values: Vec::new(),
+            };
+            let positions = |point: u32| attributes.iter().find_map(|attribute: &Attribute| attribute.position(point));
+            let mesh = MeshData { table: attribute_table, encoding: &encoding };
+            attribute.decode_values(buffer, &encoding.value_points, Some(mesh), 3 * table.face_count(), &positions)?;
+            attributes.push(attribute);
+        }
+        for attribute in &mut attributes[start..] {
+            attribute.decode_transform(buffer)?;
+        }
+    }
+    Ok(Mesh { indices: corner_points, point_count, attributes: mesh_attributes(&attributes)? })
+}
+
+fn mesh_attributes(attributes: &[Attribute]) -> anyhow::Result<Vec<MeshAttribute>> {
+    attributes
+        .iter()
+        .map(|attribute| {
+            Ok(MeshAttribute {
+                unique_id: attribute.declaration.unique_id,
+                components: attribute.declaration.components,
+                values: attribute.point_values()?,
+            })
+        })
+        .collect()
+}
+
+fn skip_metadata(buffer: &mut Buffer) -> anyhow::Result<()> {
+    let attribute_count = buffer.varint_u32()?;
+    for _ in 0..attribute_count {
+        buffer.varint_u32()?;
+        skip_metadata_entries(buffer, 0)?;
+    }
+    skip_metadata_entries(buffer, 0)
+}
+fn skip_metadata_entries(buffer: &mut Buffer, depth: usize) -> anyhow::Result<()> {
+    ensure!(depth < 32, "The Draco metadata is too deep");
+    for _ in 0..buffer.varint_u32()? {
+        let name_len = buffer.u8()? as usize;
+        buffer.bytes(name_len)?;
+        let value_len = buffer.varint_u32()? as usize;
+        buffer.bytes(value_len)?;
+    }
+    for _ in 0..buffer.varint_u32()? {
+        let name_len = buffer.u8()? as usize;
+        buffer.bytes(name_len)?;
+        skip_metadata_entries(buffer, depth + 1).context("Invalid Draco metadata")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{attributes::POSITION, *};
+
+    /// Writes the values the way the Draco encoder does
+    #[derive(Default)]
+    struct Writer(Vec<u8>);
+    impl Writer {
+        fn u8(&mut self, value: u8) -> &mut Self {
+            self.0.push(value);
+            self
+        }
+        fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+            self.0.extend_from_slice(bytes);
+            self
+        }
+        fn varint(&mut self, mut value: u64) -> &mut Self {
+            while value >= 0x80 {
+                self.0.push(value as u8 | 0x80);
+                value >>= 7;
+            }
+            self.u8(value as u8)
+        }
+        fn header(&mut self, method: u8, flags: u16) -> &mut Self {
+            self.bytes(MAGIC).u8(VERSION.0).u8(VERSION.1).u8(TRIANGULAR_MESH).u8(method).bytes(&flags.to_le_bytes())
+        }
+        fn declaration(&mut self, kind: u8, data_type: u8, components: u8, unique_id: u64) -> &mut Self {
+            self.u8(kind).u8(data_type).u8(components).u8(0).varint(unique_id)
+        }
+        /// Compresses the symbols with the raw scheme
+        fn symbols(&mut self, symbols: &[u32]) -> &mut Self {
+            let symbol_bits = (32 - symbols.iter().max().unwrap().leading_zeros()).max(1);
+            let precision_bits = (3 * symbol_bits / 2).clamp(12, 20);
+            let precision = 1 << precision_bits;
+            let mut probabilities = vec![0u32; *symbols.iter().max().unwrap() as usize + 1];
+            for &symbol in symbols {
+                probabilities[symbol as usize] += 1;
+            }
+            for probability in &mut probabilities {
+                *probability = *probability * precision / symbols.len() as u32;
+            }
+            let missing = precision - probabilities.iter().sum::<u32>();
+            *probabilities.iter_mut().max().unwrap() += missing;
+
+            self.u8(RAW_SCHEME).u8(symbol_bits as u8).varint(probabilities.len() as u64);
+            for &probability in &probabilities {
+                match probability {
+                    0 => self.u8(3),
+                    1..=0x3f => self.u8((probability << 2) as u8),
+                    0x40..=0x3fff => self.u8((probability << 2 | 1) as u8).u8((probability >> 6) as u8),
+                    _ => self.u8((probability << 2 | 2) as u8).u8((probability >> 6) as u8).u8((probability >> 14) as u8),
+                };
+            }
+            let cumulative = probabilities.iter().scan(0, |cumulative, &probability| {
+                *cumulative += probability;
+                Some(*cumulative - probability)
+            });
+            let cumulative = cumulative.collect::<Vec<_>>();
+            let l_base = 4 * precision;
+            let mut state = l_base;
+            let mut data = Vec::new();
+            for &symbol in symbols.iter().rev() {
+                let probability = probabilities[symbol as usize];
+                while state >= l_base / precision * 256 * probability {
+                    data.push(state as u8);
+                    state /= 256;
+                }
+                state = state / probability * precision + state % probability + cumulative[symbol as usize];
+            }
+            write_ans_state(&mut data, state - l_base);
+            self.varint(data.len() as u64).bytes(&data)
+        }
+        /// Compresses the bits with the bit coder, with even probabilities
+        fn bits(&mut self, bits: &[bool]) -> &mut Self {
+            let mut state = L_BASE;
+            let mut data = Vec::new();
+            for &bit in bits.iter().rev() {
+                if state >= L_BASE / 256 * 256 * 128 {
+                    data.push(state as u8);
+                    state /= 256;
+                }
+                state = state / 128 * 256 + state % 128 + if bit { 0 } else { 128 };
+            }
+            write_ans_state(&mut data, state - L_BASE);
+            self.u8(128).varint(data.len() as u64).bytes(&data)
+        }
+    }
+    const RAW_SCHEME: u8 = 1;
+    const L_BASE: u32 = 4096;
+
+    fn write_ans_state(data: &mut Vec<u8>, state: u32) {
+        if state < 1 << 6 {
+            data.push(state as u8);
+        } else if state < 1 << 14 {
+            data.extend_from_slice(&(1 << 14 | state as u16).to_le_bytes());
+        } else {
+            data.extend_from_slice(&(2 << 22 | state).to_le_bytes()[..3]);
+        }
+    }
+
+    fn zigzag(value: i32) -> u32 {
+        if value >= 0 {
+            (value as u32) << 1
+        } else {
+            ((-(value + 1)) as u32) << 1 | 1
+        }
+    }
+
+    /// A quad of two triangles with compressed indices, quantized positions predicted by their differences and
+    /// texture coordinates stored as is
+    fn sequential_quad() -> Vec<u8> {
+        let mut data = Writer::default();
+        data.header(SEQUENTIAL, METADATA_FLAG);
+        // The metadata of the position, and of the file with a nested entry
+        data.varint(1).varint(0).varint(1).u8(4).bytes(b"name").varint(3).bytes(b"pos").varint(0);
+        data.varint(0).varint(1).u8(3).bytes(b"sub").varint(1).u8(1).bytes(b"a").varint(1).u8(7).varint(0);
+
+        let indices = [0i32, 1, 2, 2, 1, 3];
+        let index_differences = indices.iter().scan(0, |last, &index| {
+            // The sign is in the lowest bit, without the offset of the zigzag encoding
+            let difference = ((index - *last).unsigned_abs() << 1) | (index < *last) as u32;
+            *last = index;
+            Some(difference)
+        });
+        data.varint(2).varint(4).u8(0).symbols(&index_differences.collect::<Vec<_>>());
+
+        data.u8(2);
+        data.varint(1).declaration(POSITION, 9, 3, 0).u8(2);
+        data.varint(1).declaration(TEX_COORD_KIND, 2, 2, 5).u8(0);
+
+        // The positions are quantized to 4 bits in a range of 3 from -1
+        let quantized = [0, 0, 0, 15, 0, 0, 0, 15, 0, 15, 15, 5];
+        let corrections = (0..quantized.len()).map(|i| zigzag(quantized[i] - if i < 3 { 0 } else { quantized[i - 3] })).collect::<Vec<_>>();
+        data.u8(0).u8(1).u8(1).symbols(&corrections).bytes(&0i32.to_le_bytes()).bytes(&15i32.to_le_bytes());
+        data.bytes(&(-1f32).to_le_bytes()).bytes(&(-1f32).to_le_bytes()).bytes(&0f32.to_le_bytes());
+        data.bytes(&3f32.to_le_bytes()).u8(4);
+
+        data.bytes(&[0, 0, 255, 0, 0, 255, 255, 255]);
+        data.0
+    }
+    const TEX_COORD_KIND: u8 = 3;
+
+    #[test]
+    fn sequential() {
+        let mesh = decode_mesh(&sequential_quad()).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 1, 3]);
+        assert_eq!(mesh.point_count, 4);
+        assert_eq!(mesh.attributes.len(), 2);
+        assert_eq!((mesh.attributes[0].unique_id, mesh.attributes[0].components), (0, 3));
+        assert_eq!(mesh.attributes[0].values, vec![-1., -1., 0., 2., -1., 0., -1., 2., 0., 2., 2., 1.]);
+        assert_eq!((mesh.attributes[1].unique_id, mesh.attributes[1].components), (5, 2));
+        assert_eq!(mesh.attributes[1].values, vec![0., 0., 255., 0., 0., 255., 255., 255.]);
+    }
+
+    #[test]
+    fn edgebreaker() {
+        let mut data = Writer::default();
+        data.header(EDGEBREAKER, 0);
+        data.u8(0).varint(4).varint(2).u8(0).varint(2).varint(0);
+        // No topology splits
+        data.varint(0);
+        // An E face, then an R face attached to it, and no start face closing it
+        data.varint(1).u8(0b101111).bits(&[false]);
+
+        data.u8(1).u8(u8::MAX).u8(0).u8(0);
+        data.varint(2).declaration(POSITION, 5, 3, 0).declaration(TEX_COORD_KIND, 2, 1, 1).u8(1).u8(0);
+
+        // The vertices are visited in the order 1, 2, 0, 3, and the last one is predicted by the parallelogram
+        let corrections = [10, 0, 0, -10, 10, 0, 0, -10, 0, 0, 0, 5];
+        data.u8(1).u8(1).u8(0).u8(1).bytes(&corrections.map(|correction| zigzag(correction) as u8));
+        data.bytes(&0i32.to_le_bytes()).bytes(&10i32.to_le_bytes());
+        data.bytes(&[1, 2, 0, 3]);
+
+        let mesh = decode_mesh(&data.0).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 1, 3]);
+        assert_eq!(mesh.point_count, 4);
+        assert_eq!(mesh.attributes[0].values, vec![0., 0., 0., 10., 0., 0., 0., 10., 0., 10., 10., 5.]);
+        assert_eq!(mesh.attributes[1].values, vec![0., 1., 2., 3.]);
+    }
+
+    #[test]
+    fn truncated() {
+        let data = sequential_quad();
+        for len in 0..data.len() {
+            assert!(decode_mesh(&data[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn oversized_counts() {
+        // The counts aren't allocated before they're checked against the size of the data
+        let mut data = Writer::default();
+        data.header(SEQUENTIAL, 0).varint(u32::MAX as u64 / 3).varint(u32::MAX as u64).u8(0).symbols(&[0]);
+        assert!(decode_mesh(&data.0).is_err());
+
+        let mut data = Writer::default();
+        data.header(SEQUENTIAL, 0).varint(1 << 28).varint(1 << 28).u8(1).bytes(&[0; 64]);
+        assert!(decode_mesh(&data.0).is_err());
+
+        let mut data = Writer::default();
+        data.header(EDGEBREAKER, 0).u8(2).varint(u32::MAX as u64).varint(u32::MAX as u64).u8(0);
+        data.varint(u32::MAX as u64).varint(0).varint(0).bytes(&[0; 64]);
+        assert!(decode_mesh(&data.0).is_err());
+
+        // The topology splits are checked against the data too
+        let mut data = Writer::default();
+        data.header(EDGEBREAKER, 0).u8(0).varint(4).varint(256).u8(0).varint(256).varint(0).varint(256).bytes(&[0; 64]);
+        assert!(decode_mesh(&data.0).is_err());
+    }
+}
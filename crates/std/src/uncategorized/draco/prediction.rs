@@ -0,0 +1,575 @@
+//! The prediction schemes of Draco, which store the values of the attributes as their difference to a value predicted
+//! from the ones decoded before them
+
+use anyhow::{bail, ensure, Context};
+
+use super::{
+    buffer::Buffer,
+    corner_table::{next, previous, Corners},
+    rans::BitDecoder,
+    traversal::Encoding,
+};
+
+/// The connectivity a prediction follows, and the order of the values in it
+pub(super) struct MeshData<'a> {
+    pub table: &'a dyn Corners,
+    pub encoding: &'a Encoding,
+}
+
+pub(super) struct Prediction<'a> {
+    scheme: Scheme<'a>,
+    transform: Transform,
+}
+
+enum Scheme<'a> {
+    /// Each value is predicted to be the previous one
+    Difference,
+    /// A vertex is predicted to complete the parallelogram of a face next to it
+    Parallelogram,
+    /// The average of the parallelograms of all of the faces next to the vertex
+    MultiParallelogram,
+    /// The average of the parallelograms which don't cross a crease, by number of parallelograms
+    ConstrainedMultiParallelogram { creases: [std::vec::IntoIter<bool>; MAX_PARALLELOGRAMS] },
+    /// The texture coordinates are predicted from the shape of the triangle in the positions, which is mirrored or not
+    TexCoordsPortable { orientations: Vec<bool> },
+    /// The normals are predicted from the positions of the faces around them, and may be flipped
+    GeometricNormal { flips: Option<BitDecoder<'a>> },
+}
+
+const MAX_PARALLELOGRAMS: usize = 4;
+
+impl<'a> Prediction<'a> {
+    /// The prediction of the values of an attribute, which reads the methods from `buffer`. Without a mesh, the mesh
+    /// predictions fall back to differences.
+    pub fn new(buffer: &mut Buffer<'a>, has_mesh: bool, normals: bool) -> anyhow::Result<Option<Self>> {
+        let method = buffer.u8()? as i8;
+        if method == -2 {
+            return Ok(None);
+        }
+        let transform = buffer.u8()? as i8;
+        let scheme = match method {
+            0 => Scheme::Difference,
+            _ if !has_mesh => Scheme::Difference,
+            1 => Scheme::Parallelogram,
+            2 => Scheme::MultiParallelogram,
+            4 => Scheme::ConstrainedMultiParallelogram { creases: Default::default() },
+            5 => Scheme::TexCoordsPortable { orientations: Vec::new() },
+            6 => Scheme::GeometricNormal { flips: None },
+            _ => bail!("Unsupported prediction method {method}"),
+        };
+        let transform = match (transform, normals) {
+            (1, false) => Transform::Wrap { min: 0, max: 0 },
+            (2, true) => Transform::Octahedron { octahedron: Octahedron::default(), canonicalized: false },
+            (3, true) => Transform::Octahedron { octahedron: Octahedron::default(), canonicalized: true },
+            _ => bail!("Unsupported prediction transform {transform}"),
+        };
+        Ok(Some(Self { scheme, transform }))
+    }
+
+    /// If the corrections are stored without their sign
+    pub fn corrections_positive(&self) -> bool {
+        matches!(self.transform, Transform::Octahedron { .. })
+    }
+
+    pub fn decode_data(&mut self, buffer: &mut Buffer<'a>, corner_count: usize) -> anyhow::Result<()> {
+        match &mut self.scheme {
+            Scheme::ConstrainedMultiParallelogram { creases } => {
+                for creases in creases {
+                    let count = buffer.varint_u32()? as usize;
+                    ensure!(count <= corner_count, "Invalid number of crease edges");
+                    if count > 0 {
+                        let mut bits = BitDecoder::new(buffer)?;
+                        *creases = (0..count).map(|_| bits.read()).collect::<Vec<_>>().into_iter();
+                    }
+                }
+            }
+            Scheme::TexCoordsPortable { orientations } => {
+                let count = buffer.u32()? as i32;
+                ensure!(count >= 0, "Invalid number of texture coordinate orientations");
+                let mut bits = BitDecoder::new(buffer)?;
+                let mut orientation = true;
+                for _ in 0..count {
+                    if !bits.read() {
+                        orientation = !orientation;
+                    }
+                    orientations.push(orientation);
+                }
+            }
+            _ => {}
+        }
+        self.transform.decode_data(buffer)?;
+        if let Scheme::GeometricNormal { flips } = &mut self.scheme {
+            *flips = Some(BitDecoder::new(buffer)?);
+        }
+        Ok(())
+    }
+
+    /// Replaces the corrections in `values` with the values they were computed from. `positions` are the quantized
+    /// positions of the values, which the texture coordinates and normals are predicted with.
+    pub fn compute_original_values(
+        &mut self,
+        values: &mut [i32],
+        components: usize,
+        mesh: Option<MeshData>,
+        positions: &dyn Fn(usize) -> Option<[i64; 3]>,
+    ) -> anyhow::Result<()> {
+        let count = values.len() / components;
+        if count == 0 {
+            return Ok(());
+        }
+        let mesh = match (&self.scheme, mesh) {
+            (Scheme::Difference, _) => None,
+            (_, Some(mesh)) => Some(mesh),
+            (_, None) => bail!("The prediction requires a mesh"),
+        };
+        let transform = &self.transform;
+        ensure!(!matches!(transform, Transform::Octahedron { .. }) || components == 2, "Invalid normal prediction");
+        let mut prediction = vec![0; components];
+        match (&mut self.scheme, mesh) {
+            (Scheme::Difference, _) | (_, None) => {
+                transform.compute_original_value(&prediction, values, components);
+                for entry in 1..count {
+                    let (decoded, rest) = values.split_at_mut(entry * components);
+                    transform.compute_original_value(&decoded[(entry - 1) * components..], rest, components);
+                }
+            }
+            (Scheme::Parallelogram, Some(mesh)) => {
+                transform.compute_original_value(&prediction, values, components);
+                for entry in 1..count.min(mesh.encoding.value_corners.len()) {
+                    let corner = mesh.encoding.value_corners[entry];
+                    let (decoded, rest) = values.split_at_mut(entry * components);
+                    let prediction = match parallelogram(&mesh, entry, corner, decoded, components, &mut prediction) {
+                        Some(prediction) => prediction,
+                        None => &decoded[(entry - 1) * components..],
+                    };
+                    transform.compute_original_value(prediction, rest, components);
+                }
+            }
+            (Scheme::MultiParallelogram, Some(mesh)) => {
+                transform.compute_original_value(&prediction, values, components);
+                let mut sum = vec![0i32; components];
+                for entry in 1..count.min(mesh.encoding.value_corners.len()) {
+                    let start = mesh.encoding.value_corners[entry];
+                    let (decoded, rest) = values.split_at_mut(entry * components);
+                    sum.fill(0);
+                    let mut parallelograms = 0;
+                    let mut corner = Some(start);
+                    while let Some(current) = corner {
+                        if let Some(prediction) = parallelogram(&mesh, entry, current, decoded, components, &mut prediction) {
+                            sum.iter_mut().zip(prediction).for_each(|(sum, value)| *sum = sum.wrapping_add(*value));
+                            parallelograms += 1;
+                        }
+                        corner = mesh.table.swing_right(current).filter(|&corner| corner != start);
+                    }
+                    if parallelograms == 0 {
+                        transform.compute_original_value(&decoded[(entry - 1) * components..], rest, components);
+                    } else {
+                        sum.iter_mut().for_each(|sum| *sum /= parallelograms);
+                        transform.compute_original_value(&sum, rest, components);
+                    }
+                }
+            }
+            (Scheme::ConstrainedMultiParallelogram { creases }, Some(mesh)) => {
+                transform.compute_original_value(&prediction, values, components);
+                let mut predictions = vec![vec![0; components]; MAX_PARALLELOGRAMS];
+                let mut sum = vec![0i32; components];
+                for entry in 1..count.min(mesh.encoding.value_corners.len()) {
+                    let start = mesh.encoding.value_corners[entry];
+                    let (decoded, rest) = values.split_at_mut(entry * components);
+
+                    // The parallelograms are found swinging left, then right from the start once a boundary is reached
+                    let mut parallelograms = 0;
+                    let mut corner = Some(start);
+                    let mut first_pass = true;
+                    while let Some(current) = corner {
+                        if let Some(prediction) = parallelogram(&mesh, entry, current, decoded, components, &mut prediction) {
+                            predictions[parallelograms].copy_from_slice(prediction);
+                            parallelograms += 1;
+                            if parallelograms == MAX_PARALLELOGRAMS {
+                                break;
+                            }
+                        }
+                        corner = if first_pass { mesh.table.swing_left(current) } else { mesh.table.swing_right(current) };
+                        if corner == Some(start) {
+                            break;
+                        }
+                        if corner.is_none() && first_pass {
+                            first_pass = false;
+                            corner = mesh.table.swing_right(start);
+                        }
+                    }
+
+                    sum.fill(0);
+                    let mut used = 0;
+                    for prediction in &predictions[..parallelograms] {
+                        let crease = creases[parallelograms - 1].next().context("Invalid crease edges")?;
+                        if !crease {
+                            sum.iter_mut().zip(prediction).for_each(|(sum, value)| *sum = sum.wrapping_add(*value));
+                            used += 1;
+                        }
+                    }
+                    if used == 0 {
+                        transform.compute_original_value(&decoded[(entry - 1) * components..], rest, components);
+                    } else {
+                        sum.iter_mut().for_each(|sum| *sum /= used);
+                        transform.compute_original_value(&sum, rest, components);
+                    }
+                }
+            }
+            (Scheme::TexCoordsPortable { orientations }, Some(mesh)) => {
+                ensure!(components == 2, "Invalid texture coordinates prediction");
+                for entry in 0..count.min(mesh.encoding.value_corners.len()) {
+                    let corner = mesh.encoding.value_corners[entry];
+                    let (decoded, rest) = values.split_at_mut(entry * components);
+                    let prediction = predict_tex_coord(&mesh, entry, corner, decoded, orientations, positions)?;
+                    transform.compute_original_value(&prediction, rest, components);
+                }
+            }
+            (Scheme::GeometricNormal { flips }, Some(mesh)) => {
+                let flips = flips.as_mut().context("The normal flips weren't decoded")?;
+                let octahedron = match transform {
+                    Transform::Octahedron { octahedron, .. } => *octahedron,
+                    Transform::Wrap { .. } => bail!("Invalid normal prediction"),
+                };
+                for entry in 0..count.min(mesh.encoding.value_corners.len()) {
+                    let corner = mesh.encoding.value_corners[entry];
+                    let mut normal = predict_normal(&mesh, corner, positions)?;
+                    octahedron.canonicalize_vector(&mut normal);
+                    if flips.read() {
+                        normal = normal.map(|value| -value);
+                    }
+                    let prediction = octahedron.vector_to_coords(normal);
+                    transform.compute_original_value(&prediction, &mut values[entry * 2..], 2);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The parallelogram prediction of the value of `entry` at `corner`, if the values of the opposite face were decoded
+fn parallelogram<'a>(
+    mesh: &MeshData,
+    entry: usize,
+    corner: u32,
+    decoded: &[i32],
+    components: usize,
+    prediction: &'a mut [i32],
+) -> Option<&'a [i32]> {
+    let opposite = mesh.table.opposite(corner)?;
+    let value = |corner: u32| mesh.encoding.vertex_values[mesh.table.vertex(corner) as usize] as usize;
+    let (opposite_value, next_value, previous_value) = (value(opposite), value(next(opposite)), value(previous(opposite)));
+    if opposite_value >= entry || next_value >= entry || previous_value >= entry {
+        return None;
+    }
+    for (c, prediction) in prediction.iter_mut().enumerate() {
+        let component = |value: usize| decoded[value * components + c] as i64;
+        *prediction = (component(next_value) + component(previous_value) - component(opposite_value)) as i32;
+    }
+    Some(prediction)
+}
+
+fn predict_tex_coord(
+    mesh: &MeshData,
+    entry: usize,
+    corner: u32,
+    decoded: &[i32],
+    orientations: &mut Vec<bool>,
+    positions: &dyn Fn(usize) -> Option<[i64; 3]>,
+) -> anyhow::Result<[i32; 2]> {
+    let value = |corner: u32| mesh.encoding.vertex_values[mesh.table.vertex(corner) as usize] as usize;
+    let (next_entry, previous_entry) = (value(next(corner)), value(previous(corner)));
+    let tex_coord = |entry: usize| [decoded[entry * 2] as i64, decoded[entry * 2 + 1] as i64];
+    let position = |entry: usize| positions(entry).context("Missing position for the texture coordinates prediction");
+
+    if previous_entry < entry && next_entry < entry {
+        let (next_uv, previous_uv) = (tex_coord(next_entry), tex_coord(previous_entry));
+        if next_uv == previous_uv {
+            return Ok([previous_uv[0] as i32, previous_uv[1] as i32]);
+        }
+        let (tip, next_position, previous_position) = (position(entry)?, position(next_entry)?, position(previous_entry)?);
+        let pn = sub3(previous_position, next_position);
+        let pn_norm2 = dot3(pn, pn);
+        if pn_norm2 != 0 {
+            // The tip is projected on the opposite edge, and the distance to it is rotated into the texture coordinates
+            let cn = sub3(tip, next_position);
+            let cn_dot_pn = dot3(pn, cn);
+            let pn_uv = [previous_uv[0] - next_uv[0], previous_uv[1] - next_uv[1]];
+            let n_uv_max = next_uv[0].abs().max(next_uv[1].abs());
+            ensure!(n_uv_max <= i64::MAX / pn_norm2, "Invalid texture coordinates prediction");
+            let pn_uv_max = pn_uv[0].abs().max(pn_uv[1].abs());
+            ensure!(pn_uv_max == 0 || cn_dot_pn <= i64::MAX / pn_uv_max, "Invalid texture coordinates prediction");
+            let x_uv = [0, 1].map(|i| next_uv[i].wrapping_mul(pn_norm2).wrapping_add(cn_dot_pn.wrapping_mul(pn_uv[i])));
+            let pn_max = pn[0].abs().max(pn[1].abs()).max(pn[2].abs());
+            ensure!(cn_dot_pn <= i64::MAX / pn_max, "Invalid texture coordinates prediction");
+            let x_position = [0, 1, 2].map(|i| next_position[i].wrapping_add(cn_dot_pn.wrapping_mul(pn[i]) / pn_norm2));
+            let cx = sub3(tip, x_position);
+            let cx_norm2 = dot3(cx, cx) as u64;
+            let norm = int_sqrt(cx_norm2.wrapping_mul(pn_norm2 as u64)) as i64;
+            let cx_uv = [pn_uv[1].wrapping_mul(norm), (-pn_uv[0]).wrapping_mul(norm)];
+            let orientation = orientations.pop().context("Missing texture coordinate orientation")?;
+            let predicted = [0, 1].map(|i| {
+                let uv = if orientation { x_uv[i].wrapping_add(cx_uv[i]) } else { x_uv[i].wrapping_sub(cx_uv[i]) };
+                (uv / pn_norm2) as i32
+            });
+            return Ok(predicted);
+        }
+    }
+
+    // Without the two other corners, the prediction falls back to the next one or to the previous value, as the encoder
+    // does (which never uses the previous corner on its own)
+    let source = if next_entry < entry {
+        next_entry
+    } else if entry > 0 {
+        entry - 1
+    } else {
+        return Ok([0, 0]);
+    };
+    let uv = tex_coord(source);
+    Ok([uv[0] as i32, uv[1] as i32])
+}
+
+fn predict_normal(mesh: &MeshData, corner: u32, positions: &dyn Fn(usize) -> Option<[i64; 3]>) -> anyhow::Result<[i32; 3]> {
+    let position = |corner: u32| {
+        positions(mesh.encoding.vertex_values[mesh.table.vertex(corner) as usize] as usize)
+            .context("Missing position for the normal prediction")
+    };
+    let center = position(corner)?;
+    let mut normal = [0i64; 3];
+    for corner in mesh.table.vertex_corners(corner) {
+        let to_next = sub3(position(next(corner))?, center);
+        let to_previous = sub3(position(previous(corner))?, center);
+        let cross = [
+            to_next[1].wrapping_mul(to_previous[2]).wrapping_sub(to_next[2].wrapping_mul(to_previous[1])),
+            to_next[2].wrapping_mul(to_previous[0]).wrapping_sub(to_next[0].wrapping_mul(to_previous[2])),
+            to_next[0].wrapping_mul(to_previous[1]).wrapping_sub(to_next[1].wrapping_mul(to_previous[0])),
+        ];
+        normal = [0, 1, 2].map(|i| normal[i].wrapping_add(cross[i]));
+    }
+    const UPPER_BOUND: i64 = 1 << 29;
+    let abs_sum = normal.iter().fold(0i64, |sum, value| sum.wrapping_add(value.wrapping_abs()));
+    if abs_sum > UPPER_BOUND {
+        let quotient = abs_sum / UPPER_BOUND;
+        normal = normal.map(|value| value / quotient);
+    }
+    Ok(normal.map(|value| value as i32))
+}
+
+fn sub3(a: [i64; 3], b: [i64; 3]) -> [i64; 3] {
+    [a[0].wrapping_sub(b[0]), a[1].wrapping_sub(b[1]), a[2].wrapping_sub(b[2])]
+}
+fn dot3(a: [i64; 3], b: [i64; 3]) -> i64 {
+    a[0].wrapping_mul(b[0]).wrapping_add(a[1].wrapping_mul(b[1])).wrapping_add(a[2].wrapping_mul(b[2]))
+}
+
+/// The floor of the square root of `number`, found with Newton's method
+fn int_sqrt(number: u64) -> u64 {
+    if number == 0 {
+        return 0;
+    }
+    let mut remaining = number;
+    let mut root = 1u64;
+    while remaining >= 2 {
+        root *= 2;
+        remaining /= 4;
+    }
+    loop {
+        root = (root + number / root) / 2;
+        if root.checked_mul(root).map_or(true, |square| square <= number) {
+            return root;
+        }
+    }
+}
+
+/// How the predicted values are combined with the corrections
+enum Transform {
+    /// The values are wrapped around the range they were in
+    Wrap { min: i32, max: i32 },
+    /// The octahedral coordinates of the normals, whose corrections are wrapped around the octahedron
+    Octahedron { octahedron: Octahedron, canonicalized: bool },
+}
+impl Transform {
+    fn decode_data(&mut self, buffer: &mut Buffer) -> anyhow::Result<()> {
+        match self {
+            Transform::Wrap { min, max } => {
+                *min = buffer.u32()? as i32;
+                *max = buffer.u32()? as i32;
+                ensure!(*min <= *max && (*max as i64 - *min as i64) < i32::MAX as i64, "Invalid prediction range");
+            }
+            Transform::Octahedron { octahedron, .. } => {
+                let max_quantized_value = buffer.u32()? as i32;
+                ensure!(max_quantized_value % 2 == 1, "Invalid normal quantization");
+                *octahedron = Octahedron::new(32 - max_quantized_value.leading_zeros())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Computes the first `components` values from the corrections there and `prediction`
+    fn compute_original_value(&self, prediction: &[i32], values: &mut [i32], components: usize) {
+        let values = &mut values[..components];
+        match self {
+            Transform::Wrap { min, max } => {
+                let range = max - min + 1;
+                for (value, &prediction) in values.iter_mut().zip(prediction) {
+                    let mut original = prediction.clamp(*min, *max).wrapping_add(*value);
+                    if original > *max {
+                        original -= range;
+                    } else if original < *min {
+                        original += range;
+                    }
+                    *value = original;
+                }
+            }
+            Transform::Octahedron { octahedron, canonicalized } => {
+                let original = octahedron.original_value([prediction[0], prediction[1]], [values[0], values[1]], *canonicalized);
+                values.copy_from_slice(&original);
+            }
+        }
+    }
+}
+
+/// The unit vectors stored as coordinates on an octahedron, which is unfolded into a square
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Octahedron {
+    max_quantized_value: i32,
+    max_value: i32,
+    center_value: i32,
+}
+impl Octahedron {
+    pub fn new(quantization_bits: u32) -> anyhow::Result<Self> {
+        ensure!((2..=30).contains(&quantization_bits), "Invalid normal quantization {quantization_bits}");
+        let max_quantized_value = (1 << quantization_bits) - 1;
+        let max_value = max_quantized_value - 1;
+        Ok(Self { max_quantized_value, max_value, center_value: max_value / 2 })
+    }
+
+    fn original_value(&self, prediction: [i32; 2], correction: [i32; 2], canonicalized: bool) -> [i32; 2] {
+        let center = self.center_value;
+        let mut prediction = [prediction[0] - center, prediction[1] - center];
+        let in_diamond = prediction[0].abs() + prediction[1].abs() <= center;
+        if !in_diamond {
+            prediction = self.invert_diamond(prediction);
+        }
+        let mut original = if canonicalized {
+            let in_bottom_left = (prediction[0] == 0 && prediction[1] == 0) || (prediction[0] < 0 && prediction[1] <= 0);
+            let rotations = match (prediction[0].signum(), prediction[1].signum()) {
+                (0, 0) => 0,
+                (0, 1) => 3,
+                (0, _) => 1,
+                (1, y) if y >= 0 => 2,
+                (1, _) => 1,
+                (_, y) if y <= 0 => 0,
+                _ => 3,
+            };
+            if !in_bottom_left {
+                prediction = rotate(prediction, rotations);
+            }
+            let original = [self.mod_max(prediction[0] + correction[0]), self.mod_max(prediction[1] + correction[1])];
+            if in_bottom_left {
+                original
+            } else {
+                rotate(original, (4 - rotations) % 4)
+            }
+        } else {
+            [self.mod_max(prediction[0] + correction[0]), self.mod_max(prediction[1] + correction[1])]
+        };
+        if !in_diamond {
+            original = self.invert_diamond(original);
+        }
+        [original[0] + center, original[1] + center]
+    }
+
+    fn invert_diamond(&self, [s, t]: [i32; 2]) -> [i32; 2] {
+        let (sign_s, sign_t) = if s >= 0 && t >= 0 {
+            (1, 1)
+        } else if s <= 0 && t <= 0 {
+            (-1, -1)
+        } else {
+            (if s > 0 { 1 } else { -1 }, if t > 0 { 1 } else { -1 })
+        };
+        let (corner_s, corner_t) = (sign_s * self.center_value, sign_t * self.center_value);
+        let (s, t) = (2 * s - corner_s, 2 * t - corner_t);
+        let (s, t) = if sign_s * sign_t >= 0 { (-t, -s) } else { (t, s) };
+        [(s + corner_s) / 2, (t + corner_t) / 2]
+    }
+
+    fn mod_max(&self, value: i32) -> i32 {
+        if value > self.center_value {
+            value - self.max_quantized_value
+        } else if value < -self.center_value {
+            value + self.max_quantized_value
+        } else {
+            value
+        }
+    }
+
+    /// Scales `vector` so that the sum of its absolute components is the center value
+    fn canonicalize_vector(&self, vector: &mut [i32; 3]) {
+        let abs_sum = vector.iter().map(|value| value.unsigned_abs() as i64).sum::<i64>();
+        if abs_sum == 0 {
+            vector[0] = self.center_value;
+        } else {
+            vector[0] = (vector[0] as i64 * self.center_value as i64 / abs_sum) as i32;
+            vector[1] = (vector[1] as i64 * self.center_value as i64 / abs_sum) as i32;
+            let z = self.center_value - vector[0].abs() - vector[1].abs();
+            vector[2] = if vector[2] >= 0 { z } else { -z };
+        }
+    }
+
+    fn vector_to_coords(&self, vector: [i32; 3]) -> [i32; 2] {
+        let (s, t) = if vector[0] >= 0 {
+            (vector[1] + self.center_value, vector[2] + self.center_value)
+        } else {
+            (
+                if vector[1] < 0 { vector[2].abs() } else { self.max_value - vector[2].abs() },
+                if vector[2] < 0 { vector[1].abs() } else { self.max_value - vector[1].abs() },
+            )
+        };
+        self.canonicalize_coords(s, t)
+    }
+
+    fn canonicalize_coords(&self, s: i32, t: i32) -> [i32; 2] {
+        let (max, center) = (self.max_value, self.center_value);
+        if (s == 0 && t == 0) || (s == 0 && t == max) || (s == max && t == 0) {
+            [max, max]
+        } else if s == 0 && t > center {
+            [s, center - (t - center)]
+        } else if s == max && t < center {
+            [s, center + (center - t)]
+        } else if t == max && s < center {
+            [center + (center - s), t]
+        } else if t == 0 && s > center {
+            [center - (s - center), t]
+        } else {
+            [s, t]
+        }
+    }
+
+    /// The unit vector of the quantized coordinates `s` and `t`
+    pub fn coords_to_vector(&self, s: i32, t: i32) -> [f32; 3] {
+        let scale = 2. / self.max_value as f32;
+        let mut y = s as f32 * scale - 1.;
+        let mut z = t as f32 * scale - 1.;
+        let x = 1. - y.abs() - z.abs();
+        let x_offset = (-x).max(0.);
+        y += if y < 0. { x_offset } else { -x_offset };
+        z += if z < 0. { x_offset } else { -x_offset };
+        let norm2 = x * x + y * y + z * z;
+        if norm2 < 1e-6 {
+            [0.; 3]
+        } else {
+            let scale = 1. / norm2.sqrt();
+            [x * scale, y * scale, z * scale]
+        }
+    }
+}
+
+fn rotate([s, t]: [i32; 2], rotations: i32) -> [i32; 2] {
+    match rotations {
+        1 => [t, -s],
+        2 => [-s, -t],
+        3 => [-t, s],
+        _ => [s, t],
+    }
+}
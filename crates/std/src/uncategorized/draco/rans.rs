@@ -0,0 +1,171 @@
+//! The rANS entropy coders of Draco, which most of its values are compressed with
+
+use anyhow::{bail, ensure, Context};
+
+use super::buffer::Buffer;
+
+const L_BASE: u32 = 4096;
+const IO_BASE: u32 = 256;
+
+/// The state of an rANS decoder, which reads the data from its end
+struct Ans<'a> {
+    data: &'a [u8],
+    state: u32,
+}
+impl<'a> Ans<'a> {
+    fn new(data: &'a [u8], l_base: u32) -> anyhow::Result<Self> {
+        let last = *data.last().context("The rANS data is empty")?;
+        let len = match last >> 6 {
+            0 => 1,
+            1 => 2,
+            2 => 3,
+            _ if l_base == L_BASE => bail!("Invalid rANS data"),
+            _ => 4,
+        };
+        ensure!(data.len() >= len, "The rANS data is truncated");
+        let (data, head) = data.split_at(data.len() - len);
+        let state = head.iter().rev().fold(0, |state, &byte| state << 8 | byte as u32) & ((1 << (len * 8 - 2)) - 1);
+        let state = state + l_base;
+        ensure!(state < l_base * IO_BASE, "Invalid rANS data");
+        Ok(Self { data, state })
+    }
+    fn renormalize(&mut self, l_base: u32) {
+        while self.state < l_base {
+            match self.data.split_last() {
+                Some((&byte, data)) => {
+                    self.state = self.state * IO_BASE + byte as u32;
+                    self.data = data;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// Decodes bits, with the probability of a zero stored first
+pub(super) struct BitDecoder<'a> {
+    ans: Ans<'a>,
+    probability_zero: u32,
+}
+impl<'a> BitDecoder<'a> {
+    pub fn new(buffer: &mut Buffer<'a>) -> anyhow::Result<Self> {
+        let probability_zero = buffer.u8()? as u32;
+        let len = buffer.varint()? as usize;
+        Ok(Self { ans: Ans::new(buffer.bytes(len)?, L_BASE)?, probability_zero })
+    }
+    pub fn read(&mut self) -> bool {
+        let probability_one = 256 - self.probability_zero;
+        // The state is renormalized a single byte at a time for the bits
+        if self.ans.state < L_BASE {
+            if let Some((&byte, data)) = self.ans.data.split_last() {
+                self.ans.state = self.ans.state * IO_BASE + byte as u32;
+                self.ans.data = data;
+            }
+        }
+        let (quotient, remainder) = (self.ans.state / 256, self.ans.state % 256);
+        let scaled = quotient * probability_one;
+        let one = remainder < probability_one;
+        self.ans.state = if one { scaled + remainder } else { self.ans.state - scaled - probability_one };
+        one
+    }
+}
+
+/// Decodes symbols with the probabilities stored first
+struct SymbolDecoder<'a> {
+    ans: Ans<'a>,
+    precision_bits: u32,
+    /// The probability and cumulative probability of each symbol
+    probabilities: Vec<(u32, u32)>,
+    /// The symbol of each slot of the precision
+    lookup: Vec<u32>,
+}
+impl<'a> SymbolDecoder<'a> {
+    /// The precision is picked from the bit length of the symbols, which is the maximum one for the tags
+    fn new(buffer: &mut Buffer<'a>, symbol_bits: u32) -> anyhow::Result<Option<Self>> {
+        let precision_bits = (3 * symbol_bits / 2).clamp(12, 20);
+        let precision = 1 << precision_bits;
+        let symbol_count = buffer.varint_u32()? as usize;
+        let mut probabilities = Vec::with_capacity(symbol_count.min(1 << 20));
+        while probabilities.len() < symbol_count {
+            let data = buffer.u8()?;
+            if data & 3 == 3 {
+                // A run of zero probabilities
+                let count = (data >> 2) as usize + 1;
+                ensure!(probabilities.len() + count <= symbol_count, "Invalid rANS probabilities");
+                probabilities.extend(std::iter::repeat(0).take(count));
+            } else {
+                let mut probability = (data >> 2) as u32;
+                for i in 0..(data & 3) as u32 {
+                    probability |= (buffer.u8()? as u32) << (8 * (i + 1) - 2);
+                }
+                probabilities.push(probability);
+            }
+        }
+        if symbol_count == 0 {
+            return Ok(None);
+        }
+
+        let mut lookup = Vec::with_capacity(precision as usize);
+        let mut cumulative = 0;
+        let probabilities = probabilities
+            .into_iter()
+            .enumerate()
+            .map(|(symbol, probability)| {
+                let entry = (probability, cumulative);
+                cumulative += probability;
+                ensure!(cumulative <= precision, "Invalid rANS probabilities");
+                lookup.resize(cumulative as usize, symbol as u32);
+                Ok(entry)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        ensure!(cumulative == precision, "Invalid rANS probabilities");
+
+        let len = buffer.varint()? as usize;
+        let ans = Ans::new(buffer.bytes(len)?, 4 * precision)?;
+        Ok(Some(Self { ans, precision_bits, probabilities, lookup }))
+    }
+    fn read(&mut self) -> u32 {
+        let precision = 1 << self.precision_bits;
+        self.ans.renormalize(4 * precision);
+        let (quotient, remainder) = (self.ans.state / precision, self.ans.state % precision);
+        let symbol = self.lookup[remainder as usize];
+        let (probability, cumulative) = self.probabilities[symbol as usize];
+        self.ans.state = quotient * probability + remainder - cumulative;
+        symbol
+    }
+}
+
+const TAGGED_SCHEME: u8 = 0;
+const RAW_SCHEME: u8 = 1;
+
+/// Decodes `count` symbols, which are the values of `components` components each
+pub(super) fn decode_symbols(buffer: &mut Buffer, count: usize, components: usize) -> anyhow::Result<Vec<u32>> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    // The symbols can take less than a bit each, so `count` is bounded by the callers from the number of faces, and
+    // only what's preallocated is bounded by the data
+    let mut symbols = Vec::with_capacity(count.min(buffer.remaining()));
+    match buffer.u8()? {
+        TAGGED_SCHEME => {
+            // The bit length of the values of each entry is compressed, and the values are stored as is after them
+            let mut tags = SymbolDecoder::new(buffer, 5)?.context("The symbols have no tags")?;
+            let mut bits = buffer.bits();
+            while symbols.len() < count {
+                let bit_length = tags.read();
+                for _ in 0..components.min(count - symbols.len()) {
+                    symbols.push(bits.read(bit_length)?);
+                }
+            }
+            buffer.skip_bits(&bits)?;
+        }
+        RAW_SCHEME => {
+            let symbol_bits = buffer.u8()? as u32;
+            ensure!((1..=18).contains(&symbol_bits), "Invalid symbol bit length {symbol_bits}");
+            let mut decoder = SymbolDecoder::new(buffer, symbol_bits)?.context("The symbols have no probabilities")?;
+            symbols.extend((0..count).map(|_| decoder.read()));
+        }
+        scheme => bail!("Unknown symbol coding {scheme}"),
+    }
+    Ok(symbols)
+}
@@ -0,0 +1,225 @@
+//! The order the values of the attributes are stored in, which follows a traversal of the mesh
+
+use super::corner_table::{next, previous, CornerTable, Corners};
+
+/// Where the values of an attribute are in the traversal
+#[derive(Debug, Clone, Default)]
+pub(super) struct Encoding {
+    /// The value of each vertex of the connectivity the attribute was traversed with
+    pub vertex_values: Vec<u32>,
+    /// The corner each value was first visited at
+    pub value_corners: Vec<u32>,
+    /// The point of each value
+    pub value_points: Vec<u32>,
+}
+impl Encoding {
+    fn new(vertex_count: usize) -> Self {
+        Self { vertex_values: vec![u32::MAX; vertex_count], value_corners: Vec::new(), value_points: Vec::new() }
+    }
+    fn visit(&mut self, corner_points: &[u32], vertex: u32, corner: u32) {
+        self.vertex_values[vertex as usize] = self.value_corners.len() as u32;
+        self.value_corners.push(corner);
+        self.value_points.push(corner_points[corner as usize]);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Traversal {
+    DepthFirst,
+    /// The vertices which can be predicted from the most faces come first
+    PredictionDegree,
+}
+
+struct Visited {
+    faces: Vec<bool>,
+    vertices: Vec<bool>,
+}
+impl Visited {
+    fn face(&self, corner: Option<u32>) -> bool {
+        corner.map_or(true, |corner| self.faces[corner as usize / 3])
+    }
+}
+
+/// Visits the vertices of `table`, from each of its faces in order
+pub(super) fn traverse(table: &(impl Corners + ?Sized), corner_points: &[u32], traversal: Traversal) -> Encoding {
+    let mut encoding = Encoding::new(table.vertex_count());
+    let mut visited = Visited { faces: vec![false; table.face_count()], vertices: vec![false; table.vertex_count()] };
+    let mut prediction_degrees = vec![0; table.vertex_count()];
+    for face in 0..table.face_count() as u32 {
+        let corner = 3 * face;
+        match traversal {
+            Traversal::DepthFirst => depth_first(table, corner_points, corner, &mut visited, &mut encoding),
+            Traversal::PredictionDegree => {
+                prediction_degree(table, corner_points, corner, &mut visited, &mut prediction_degrees, &mut encoding)
+            }
+        }
+    }
+    encoding
+}
+
+fn visit_vertex(table: &(impl Corners + ?Sized), corner_points: &[u32], corner: u32, visited: &mut Visited, encoding: &mut Encoding) {
+    let vertex = table.vertex(corner);
+    if !visited.vertices[vertex as usize] {
+        visited.vertices[vertex as usize] = true;
+        encoding.visit(corner_points, vertex, corner);
+    }
+}
+
+fn depth_first(table: &(impl Corners + ?Sized), corner_points: &[u32], corner: u32, visited: &mut Visited, encoding: &mut Encoding) {
+    if visited.faces[corner as usize / 3] {
+        return;
+    }
+    visit_vertex(table, corner_points, next(corner), visited, encoding);
+    visit_vertex(table, corner_points, previous(corner), visited, encoding);
+
+    let mut stack = vec![Some(corner)];
+    while let Some(&top) = stack.last() {
+        let mut corner = match top {
+            Some(corner) if !visited.faces[corner as usize / 3] => corner,
+            _ => {
+                stack.pop();
+                continue;
+            }
+        };
+        loop {
+            visited.faces[corner as usize / 3] = true;
+            let vertex = table.vertex(corner);
+            if !visited.vertices[vertex as usize] {
+                let on_boundary = table.is_on_boundary(vertex);
+                visited.vertices[vertex as usize] = true;
+                encoding.visit(corner_points, vertex, corner);
+                if !on_boundary {
+                    corner = table.right_corner(corner).unwrap();
+                    continue;
+                }
+            }
+            let right = table.right_corner(corner);
+            let left = table.left_corner(corner);
+            match (visited.face(right), visited.face(left)) {
+                (true, true) => {
+                    stack.pop();
+                    break;
+                }
+                (true, false) => corner = left.unwrap(),
+                (false, true) => corner = right.unwrap(),
+                (false, false) => {
+                    // The right face is traversed first, then the left one
+                    *stack.last_mut().unwrap() = left;
+                    stack.push(right);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+const MAX_PRIORITY: usize = 3;
+
+fn prediction_degree(
+    table: &(impl Corners + ?Sized),
+    corner_points: &[u32],
+    corner: u32,
+    visited: &mut Visited,
+    prediction_degrees: &mut [u32],
+    encoding: &mut Encoding,
+) {
+    let mut stacks: [Vec<u32>; MAX_PRIORITY] = Default::default();
+    let mut best_priority = 0;
+    stacks[0].push(corner);
+    visit_vertex(table, corner_points, next(corner), visited, encoding);
+    visit_vertex(table, corner_points, previous(corner), visited, encoding);
+    visit_vertex(table, corner_points, corner, visited, encoding);
+
+    // Visiting an unvisited vertex is best done once it can be predicted from more than one face
+    let mut priority = |corner: u32, visited: &Visited| {
+        let vertex = table.vertex(corner) as usize;
+        if visited.vertices[vertex] {
+            0
+        } else {
+            prediction_degrees[vertex] += 1;
+            if prediction_degrees[vertex] > 1 {
+                1
+            } else {
+                2
+            }
+        }
+    };
+    while let Some((best, mut corner)) = (best_priority..MAX_PRIORITY).find_map(|priority| Some((priority, stacks[priority].pop()?))) {
+        best_priority = best;
+        if visited.faces[corner as usize / 3] {
+            continue;
+        }
+        loop {
+            visited.faces[corner as usize / 3] = true;
+            visit_vertex(table, corner_points, corner, visited, encoding);
+            let right = table.right_corner(corner);
+            let left = table.left_corner(corner);
+            let right_visited = visited.face(right);
+            if !visited.face(left) {
+                let left = left.unwrap();
+                let priority = priority(left, visited);
+                if right_visited && priority <= best_priority {
+                    corner = left;
+                    continue;
+                }
+                stacks[priority].push(left);
+                best_priority = best_priority.min(priority);
+            }
+            if !right_visited {
+                let right = right.unwrap();
+                let priority = priority(right, visited);
+                if priority <= best_priority {
+                    corner = right;
+                    continue;
+                }
+                stacks[priority].push(right);
+                best_priority = best_priority.min(priority);
+            }
+            break;
+        }
+    }
+}
+
+/// The points of the corners, which are the vertices of the mesh split where any of the attributes is, and the number
+/// of points
+pub(super) fn assign_points(table: &CornerTable, is_hole: &[bool], attribute_tables: &[&dyn Corners]) -> (Vec<u32>, usize) {
+    let mut corner_points = vec![0; 3 * table.face_count()];
+    let mut point_count = 0;
+    for vertex in 0..table.vertex_count() as u32 {
+        let left_most_corner = match table.left_most_corner(vertex) {
+            Some(corner) => corner,
+            None => continue,
+        };
+        // The points are split along the seams, so the first one starts at one of them if the vertex isn't on a boundary
+        let mut first_corner = left_most_corner;
+        if !is_hole[vertex as usize] {
+            'attributes: for attribute_table in attribute_tables {
+                let attribute_vertex = attribute_table.vertex(left_most_corner);
+                let mut corner = table.swing_right(left_most_corner);
+                while let Some(current) = corner.filter(|&corner| corner != left_most_corner) {
+                    if attribute_table.vertex(current) != attribute_vertex {
+                        first_corner = current;
+                        break 'attributes;
+                    }
+                    corner = table.swing_right(current);
+                }
+            }
+        }
+        corner_points[first_corner as usize] = point_count;
+        point_count += 1;
+        let mut previous_corner = first_corner;
+        let mut corner = table.swing_right(first_corner);
+        while let Some(current) = corner.filter(|&corner| corner != first_corner) {
+            let on_seam = attribute_tables.iter().any(|table| table.vertex(current) != table.vertex(previous_corner));
+            corner_points[current as usize] = if on_seam {
+                point_count += 1;
+                point_count - 1
+            } else {
+                corner_points[previous_corner as usize]
+            };
+            previous_corner = current;
+            corner = table.swing_right(current);
+        }
+    }
+    (corner_points, point_count as usize)
+}
@@ -1,4 +1,41 @@
 pub fn sha256_digest(value: &str) -> String {
-    let digest = ring::digest::digest(&ring::digest::SHA256, value.as_bytes());
+    sha256_digest_bytes(value.as_bytes())
+}
+
+pub fn sha256_digest_bytes(value: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, value);
     data_encoding::HEXLOWER.encode(digest.as_ref())
 }
+
+/// The same digest as [sha256_digest_bytes], of content which comes in parts
+#[derive(Clone)]
+pub struct Sha256Digest(ring::digest::Context);
+impl Sha256Digest {
+    pub fn new() -> Self {
+        Self(ring::digest::Context::new(&ring::digest::SHA256))
+    }
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+    pub fn finish(self) -> String {
+        data_encoding::HEXLOWER.encode(self.0.finish().as_ref())
+    }
+}
+impl Default for Sha256Digest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl std::fmt::Debug for Sha256Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sha256Digest").finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn test_sha256_digest() {
+    let mut digest = Sha256Digest::new();
+    digest.update(b"hello ");
+    digest.update(b"world");
+    assert_eq!(digest.finish(), sha256_digest("hello world"));
+}
@@ -0,0 +1,497 @@
+//! Fixed-point math, for the simulations which must give bit-identical results on every platform, such as the ones of
+//! lockstep games: unlike `f32`, whose results may differ with the compiler, the CPU and the math library, these are
+//! only integer operations.
+//!
+//! A [Fixed] is a signed 32.32 fixed-point number. The arithmetic wraps on overflow, in debug builds too, so that all the
+//! builds agree. The conversions from and to the glam types are for the rendering, which doesn't need to be
+//! deterministic; the simulation should only convert floats to fixed-point numbers from its inputs, such as constants.
+
+use std::{
+    fmt::{Debug, Display},
+    ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign},
+};
+
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+const FRAC_BITS: u32 = 32;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Fixed(i64);
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(1 << FRAC_BITS);
+    pub const HALF: Fixed = Fixed(1 << (FRAC_BITS - 1));
+    pub const MIN: Fixed = Fixed(i64::MIN);
+    pub const MAX: Fixed = Fixed(i64::MAX);
+    /// The smallest positive number
+    pub const EPSILON: Fixed = Fixed(1);
+    pub const PI: Fixed = Fixed(13_493_037_705);
+    pub const FRAC_PI_2: Fixed = Fixed(6_746_518_852);
+    pub const TAU: Fixed = Fixed(26_986_075_409);
+
+    /// The number with the underlying bits, which are the number times 2^32
+    pub const fn from_bits(bits: i64) -> Self {
+        Self(bits)
+    }
+    pub const fn to_bits(self) -> i64 {
+        self.0
+    }
+    pub const fn from_int(value: i32) -> Self {
+        Self((value as i64) << FRAC_BITS)
+    }
+    /// `numerator / denominator`, rounded towards zero; e.g. `Fixed::from_ratio(3, 10)` for 0.3, exactly as on every
+    /// platform
+    pub const fn from_ratio(numerator: i64, denominator: i64) -> Self {
+        Self((((numerator as i128) << FRAC_BITS) / denominator as i128) as i64)
+    }
+    /// The nearest number to `value`, which is saturated to the range of [Fixed]
+    pub fn from_f32(value: f32) -> Self {
+        Self::from_f64(value as f64)
+    }
+    /// The nearest number to `value`, which is saturated to the range of [Fixed]
+    pub fn from_f64(value: f64) -> Self {
+        // Multiplying by a power of two is exact, and the cast saturates
+        Self((value * (1u64 << FRAC_BITS) as f64).round() as i64)
+    }
+    pub fn to_f32(self) -> f32 {
+        self.to_f64() as f32
+    }
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / (1u64 << FRAC_BITS) as f64
+    }
+    /// The integer part, rounded towards negative infinity
+    pub const fn to_int(self) -> i32 {
+        (self.0 >> FRAC_BITS) as i32
+    }
+
+    pub const fn abs(self) -> Self {
+        Self(self.0.wrapping_abs())
+    }
+    pub const fn signum(self) -> Self {
+        Self::from_int(self.0.signum() as i32)
+    }
+    pub const fn floor(self) -> Self {
+        Self(self.0 & !(Self::ONE.0 - 1))
+    }
+    pub const fn ceil(self) -> Self {
+        Self(self.0.wrapping_add(Self::ONE.0 - 1)).floor()
+    }
+    /// Rounds half-way cases away from zero, like [f32::round]
+    pub const fn round(self) -> Self {
+        if self.0 < 0 {
+            Self(Self(self.0.wrapping_neg().wrapping_add(Self::HALF.0)).floor().0.wrapping_neg())
+        } else {
+            Self(self.0.wrapping_add(Self::HALF.0)).floor()
+        }
+    }
+    /// The part after the point, always positive: `self - self.floor()`
+    pub const fn fract(self) -> Self {
+        Self(self.0 & (Self::ONE.0 - 1))
+    }
+    pub fn min(self, other: Self) -> Self {
+        Ord::min(self, other)
+    }
+    pub fn max(self, other: Self) -> Self {
+        Ord::max(self, other)
+    }
+    pub fn clamp(self, min: Self, max: Self) -> Self {
+        Ord::clamp(self, min, max)
+    }
+    pub fn lerp(self, other: Self, t: Self) -> Self {
+        self + (other - self) * t
+    }
+
+    /// The square root, rounded down; zero for the negative numbers
+    pub fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Self::ZERO;
+        }
+        Self(isqrt((self.0 as u128) << FRAC_BITS) as i64)
+    }
+    /// The sine of an angle in radians, within about 1e-8 of the exact result
+    pub fn sin(self) -> Self {
+        // Reduces the angle to [-PI, PI), and then to [-PI/2, PI/2] with sin(x) = sin(PI - x)
+        let mut x = Self((self + Self::PI).0.rem_euclid(Self::TAU.0)) - Self::PI;
+        if x > Self::FRAC_PI_2 {
+            x = Self::PI - x;
+        } else if x < -Self::FRAC_PI_2 {
+            x = -Self::PI - x;
+        }
+        // The Taylor series up to x^13, by Horner's method
+        let x2 = x * x;
+        let mut sum = Self::ONE;
+        for n in (1..=6).rev() {
+            sum = Self::ONE - x2 * sum / Self::from_int((2 * n) * (2 * n + 1));
+        }
+        x * sum
+    }
+    /// The cosine of an angle in radians, within about 1e-8 of the exact result
+    pub fn cos(self) -> Self {
+        (self + Self::FRAC_PI_2).sin()
+    }
+    /// The angle in radians between the positive x axis and the point (`x`, `self`), in [-PI, PI], within about 2e-5 of
+    /// the exact result
+    pub fn atan2(self, x: Self) -> Self {
+        let y = self;
+        if x == Self::ZERO && y == Self::ZERO {
+            return Self::ZERO;
+        }
+        // atan(z) for z in [0, 1], with a minimax polynomial
+        let atan = |z: Self| {
+            let z2 = z * z;
+            let coefficients = [
+                Self::from_ratio(208_351, 10_000_000),
+                Self::from_ratio(-851_330, 10_000_000),
+                Self::from_ratio(1_801_410, 10_000_000),
+                Self::from_ratio(-3_302_995, 10_000_000),
+                Self::from_ratio(9_998_660, 10_000_000),
+            ];
+            coefficients.into_iter().fold(Self::ZERO, |sum, coefficient| sum * z2 + coefficient) * z
+        };
+        let (ax, ay) = (x.abs(), y.abs());
+        let angle = if ay <= ax { atan(ay / ax) } else { Self::FRAC_PI_2 - atan(ax / ay) };
+        let angle = if x < Self::ZERO { Self::PI - angle } else { angle };
+        if y < Self::ZERO {
+            -angle
+        } else {
+            angle
+        }
+    }
+}
+impl Debug for Fixed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Fixed({})", self.to_f64())
+    }
+}
+impl Display for Fixed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.to_f64(), f)
+    }
+}
+impl From<i32> for Fixed {
+    fn from(value: i32) -> Self {
+        Self::from_int(value)
+    }
+}
+impl Add for Fixed {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0.wrapping_add(rhs.0))
+    }
+}
+impl Sub for Fixed {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0.wrapping_sub(rhs.0))
+    }
+}
+impl Mul for Fixed {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self(((self.0 as i128 * rhs.0 as i128) >> FRAC_BITS) as i64)
+    }
+}
+impl Div for Fixed {
+    type Output = Self;
+    /// # Panics
+    /// Panics if `rhs` is zero
+    fn div(self, rhs: Self) -> Self {
+        Self((((self.0 as i128) << FRAC_BITS) / rhs.0 as i128) as i64)
+    }
+}
+impl Neg for Fixed {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self(self.0.wrapping_neg())
+    }
+}
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+impl MulAssign for Fixed {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+impl DivAssign for Fixed {
+    fn div_assign(&mut self, rhs: Self) {
+        *self = *self / rhs;
+    }
+}
+
+/// The integer square root of `value`, rounded down
+fn isqrt(value: u128) -> u128 {
+    let mut result = 0;
+    let mut bit = 1u128 << ((127 - value.leading_zeros()) & !1);
+    let mut value = value;
+    while bit != 0 {
+        if value >= result + bit {
+            value -= result + bit;
+            result = (result >> 1) + bit;
+        } else {
+            result >>= 1;
+        }
+        bit >>= 2;
+    }
+    result
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FixedVec2 {
+    pub x: Fixed,
+    pub y: Fixed,
+}
+impl FixedVec2 {
+    pub const ZERO: FixedVec2 = FixedVec2::new(Fixed::ZERO, Fixed::ZERO);
+    pub const ONE: FixedVec2 = FixedVec2::new(Fixed::ONE, Fixed::ONE);
+    pub const X: FixedVec2 = FixedVec2::new(Fixed::ONE, Fixed::ZERO);
+    pub const Y: FixedVec2 = FixedVec2::new(Fixed::ZERO, Fixed::ONE);
+
+    pub const fn new(x: Fixed, y: Fixed) -> Self {
+        Self { x, y }
+    }
+    pub const fn splat(value: Fixed) -> Self {
+        Self::new(value, value)
+    }
+    pub fn from_vec2(value: Vec2) -> Self {
+        Self::new(Fixed::from_f32(value.x), Fixed::from_f32(value.y))
+    }
+    pub fn to_vec2(self) -> Vec2 {
+        Vec2::new(self.x.to_f32(), self.y.to_f32())
+    }
+    pub fn extend(self, z: Fixed) -> FixedVec3 {
+        FixedVec3::new(self.x, self.y, z)
+    }
+    pub fn dot(self, other: Self) -> Fixed {
+        self.x * other.x + self.y * other.y
+    }
+    /// The z component of the cross product of the two vectors extended with a zero z
+    pub fn perp_dot(self, other: Self) -> Fixed {
+        self.x * other.y - self.y * other.x
+    }
+    pub fn length_squared(self) -> Fixed {
+        self.dot(self)
+    }
+    pub fn length(self) -> Fixed {
+        self.length_squared().sqrt()
+    }
+    pub fn distance(self, other: Self) -> Fixed {
+        (self - other).length()
+    }
+    /// The vector with the same direction and a length of one, or zero if this vector is zero
+    pub fn normalize_or_zero(self) -> Self {
+        let length = self.length();
+        if length == Fixed::ZERO {
+            Self::ZERO
+        } else {
+            self / length
+        }
+    }
+    pub fn lerp(self, other: Self, t: Fixed) -> Self {
+        self + (other - self) * t
+    }
+    pub fn min(self, other: Self) -> Self {
+        Self::new(self.x.min(other.x), self.y.min(other.y))
+    }
+    pub fn max(self, other: Self) -> Self {
+        Self::new(self.x.max(other.x), self.y.max(other.y))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FixedVec3 {
+    pub x: Fixed,
+    pub y: Fixed,
+    pub z: Fixed,
+}
+impl FixedVec3 {
+    pub const ZERO: FixedVec3 = FixedVec3::new(Fixed::ZERO, Fixed::ZERO, Fixed::ZERO);
+    pub const ONE: FixedVec3 = FixedVec3::new(Fixed::ONE, Fixed::ONE, Fixed::ONE);
+    pub const X: FixedVec3 = FixedVec3::new(Fixed::ONE, Fixed::ZERO, Fixed::ZERO);
+    pub const Y: FixedVec3 = FixedVec3::new(Fixed::ZERO, Fixed::ONE, Fixed::ZERO);
+    pub const Z: FixedVec3 = FixedVec3::new(Fixed::ZERO, Fixed::ZERO, Fixed::ONE);
+
+    pub const fn new(x: Fixed, y: Fixed, z: Fixed) -> Self {
+        Self { x, y, z }
+    }
+    pub const fn splat(value: Fixed) -> Self {
+        Self::new(value, value, value)
+    }
+    pub fn from_vec3(value: Vec3) -> Self {
+        Self::new(Fixed::from_f32(value.x), Fixed::from_f32(value.y), Fixed::from_f32(value.z))
+    }
+    pub fn to_vec3(self) -> Vec3 {
+        Vec3::new(self.x.to_f32(), self.y.to_f32(), self.z.to_f32())
+    }
+    pub fn truncate(self) -> FixedVec2 {
+        FixedVec2::new(self.x, self.y)
+    }
+    pub fn dot(self, other: Self) -> Fixed {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+    pub fn cross(self, other: Self) -> Self {
+        Self::new(self.y * other.z - self.z * other.y, self.z * other.x - self.x * other.z, self.x * other.y - self.y * other.x)
+    }
+    pub fn length_squared(self) -> Fixed {
+        self.dot(self)
+    }
+    pub fn length(self) -> Fixed {
+        self.length_squared().sqrt()
+    }
+    pub fn distance(self, other: Self) -> Fixed {
+        (self - other).length()
+    }
+    /// The vector with the same direction and a length of one, or zero if this vector is zero
+    pub fn normalize_or_zero(self) -> Self {
+        let length = self.length();
+        if length == Fixed::ZERO {
+            Self::ZERO
+        } else {
+            self / length
+        }
+    }
+    pub fn lerp(self, other: Self, t: Fixed) -> Self {
+        self + (other - self) * t
+    }
+    pub fn min(self, other: Self) -> Self {
+        Self::new(self.x.min(other.x), self.y.min(other.y), self.z.min(other.z))
+    }
+    pub fn max(self, other: Self) -> Self {
+        Self::new(self.x.max(other.x), self.y.max(other.y), self.z.max(other.z))
+    }
+}
+
+macro_rules! impl_vector_ops {
+    ($vec:ident, $glam:ident, $from_glam:ident, $to_glam:ident, $($field:ident),+) => {
+        impl Add for $vec {
+            type Output = Self;
+            fn add(self, rhs: Self) -> Self {
+                Self { $($field: self.$field + rhs.$field),+ }
+            }
+        }
+        impl Sub for $vec {
+            type Output = Self;
+            fn sub(self, rhs: Self) -> Self {
+                Self { $($field: self.$field - rhs.$field),+ }
+            }
+        }
+        impl Mul<Fixed> for $vec {
+            type Output = Self;
+            fn mul(self, rhs: Fixed) -> Self {
+                Self { $($field: self.$field * rhs),+ }
+            }
+        }
+        impl Div<Fixed> for $vec {
+            type Output = Self;
+            fn div(self, rhs: Fixed) -> Self {
+                Self { $($field: self.$field / rhs),+ }
+            }
+        }
+        impl Neg for $vec {
+            type Output = Self;
+            fn neg(self) -> Self {
+                Self { $($field: -self.$field),+ }
+            }
+        }
+        impl AddAssign for $vec {
+            fn add_assign(&mut self, rhs: Self) {
+                *self = *self + rhs;
+            }
+        }
+        impl SubAssign for $vec {
+            fn sub_assign(&mut self, rhs: Self) {
+                *self = *self - rhs;
+            }
+        }
+        impl MulAssign<Fixed> for $vec {
+            fn mul_assign(&mut self, rhs: Fixed) {
+                *self = *self * rhs;
+            }
+        }
+        impl DivAssign<Fixed> for $vec {
+            fn div_assign(&mut self, rhs: Fixed) {
+                *self = *self / rhs;
+            }
+        }
+        impl From<$glam> for $vec {
+            fn from(value: $glam) -> Self {
+                Self::$from_glam(value)
+            }
+        }
+        impl From<$vec> for $glam {
+            fn from(value: $vec) -> Self {
+                value.$to_glam()
+            }
+        }
+    };
+}
+impl_vector_ops!(FixedVec2, Vec2, from_vec2, to_vec2, x, y);
+impl_vector_ops!(FixedVec3, Vec3, from_vec3, to_vec3, x, y, z);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(value: Fixed, expected: f64, epsilon: f64) {
+        assert!((value.to_f64() - expected).abs() <= epsilon, "{value} isn't {expected}");
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = Fixed::from_ratio(3, 2);
+        let b = Fixed::from_int(-4);
+        assert_eq!(a + b, Fixed::from_ratio(-5, 2));
+        assert_eq!(a * b, Fixed::from_int(-6));
+        assert_eq!(b / a, Fixed::from_ratio(-8, 3));
+        assert_eq!(Fixed::from_f32(0.75), Fixed::from_ratio(3, 4));
+        assert_eq!(Fixed::from_f64(1e30), Fixed::MAX);
+        assert_eq!(Fixed::MAX + Fixed::EPSILON, Fixed::MIN);
+    }
+
+    #[test]
+    fn rounding() {
+        let values = [-2.5, -1.75, -1.5, -1.25, -1., -0.5, 0., 0.25, 0.5, 1.5, 2.75];
+        for value in values {
+            let fixed = Fixed::from_f64(value);
+            assert_eq!(fixed.floor().to_f64(), value.floor(), "floor {value}");
+            assert_eq!(fixed.ceil().to_f64(), value.ceil(), "ceil {value}");
+            assert_eq!(fixed.round().to_f64(), value.round(), "round {value}");
+            assert_eq!(fixed.fract().to_f64(), value - value.floor(), "fract {value}");
+            assert_eq!(fixed.to_int(), value.floor() as i32, "to_int {value}");
+        }
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(Fixed::from_int(9).sqrt(), Fixed::from_int(3));
+        assert_near(Fixed::from_int(2).sqrt(), std::f64::consts::SQRT_2, 1e-9);
+        assert_eq!(Fixed::from_int(-1).sqrt(), Fixed::ZERO);
+        for i in -20..=20 {
+            let angle = i as f64 * 0.4;
+            assert_near(Fixed::from_f64(angle).sin(), angle.sin(), 1e-8);
+            assert_near(Fixed::from_f64(angle).cos(), angle.cos(), 1e-8);
+            let (y, x) = (angle.sin() * 3., (angle * 1.7).cos() * 2.);
+            assert_near(Fixed::from_f64(y).atan2(Fixed::from_f64(x)), y.atan2(x), 2e-5);
+        }
+    }
+
+    #[test]
+    fn vectors() {
+        let a = FixedVec3::from_vec3(Vec3::new(3., 0., 4.));
+        assert_eq!(a.length(), Fixed::from_int(5));
+        assert_eq!(a.normalize_or_zero().to_vec3(), Vec3::new(0.6, 0., 0.8));
+        assert_eq!(FixedVec3::X.cross(FixedVec3::Y), FixedVec3::Z);
+        assert_eq!(FixedVec3::ZERO.normalize_or_zero(), FixedVec3::ZERO);
+        let b = FixedVec2::new(Fixed::ONE, Fixed::from_int(2));
+        assert_eq!(Vec2::from(b * Fixed::HALF - FixedVec2::ONE), Vec2::new(-0.5, 0.));
+        assert_eq!(b.perp_dot(FixedVec2::X), Fixed::from_int(-2));
+    }
+}
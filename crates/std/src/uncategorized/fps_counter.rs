@@ -9,6 +9,7 @@ pub struct FpsCounter {
     n_frames: u32,
     slowest_frame: Duration,
     active_time: Duration,
+    frame_times: FrameTimeHistogram,
 }
 impl FpsCounter {
     pub fn new() -> Self {
@@ -18,6 +19,7 @@ impl FpsCounter {
             n_frames: 0,
             slowest_frame: Duration::ZERO,
             active_time: Duration::ZERO,
+            frame_times: FrameTimeHistogram::new(),
         }
     }
     pub fn frame_start(&mut self) {
@@ -29,6 +31,7 @@ impl FpsCounter {
         let frame_duration = self.current_frame_start.elapsed();
         self.slowest_frame = self.slowest_frame.max(frame_duration);
         self.active_time += frame_duration;
+        self.frame_times.record(frame_duration);
         if self.n_frames > 100 || duration.as_secs_f32() > 1. {
             let res =
                 Some(FpsSample { n_frames: self.n_frames, duration, slowest_frame: self.slowest_frame, active_time: self.active_time });
@@ -45,6 +48,10 @@ impl FpsCounter {
         self.frame_start();
         sample
     }
+    /// The distribution of all frame times since the counter was created
+    pub fn frame_times(&self) -> &FrameTimeHistogram {
+        &self.frame_times
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -79,3 +86,177 @@ impl FpsSample {
         format!("{:.1}%/{:.1} ms max", self.activity_perc(1000. / 60.), self.slowest_frame.as_secs_f64() * 1000.)
     }
 }
+
+/// The distribution of frame times over a session.
+///
+/// Averages hide stutters, so this keeps every frame in buckets of [FrameTimeHistogram::BUCKET_WIDTH]; frames slower
+/// than [FrameTimeHistogram::MAX_BUCKETED] all go in the last bucket, but their total time is kept exactly.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameTimeHistogram {
+    buckets: Vec<u32>,
+    n_frames: u64,
+    total_time: Duration,
+    slow_frames_time: Duration,
+    slowest_frame: Duration,
+}
+impl FrameTimeHistogram {
+    pub const BUCKET_WIDTH: Duration = Duration::from_micros(100);
+    pub const MAX_BUCKETED: Duration = Duration::from_millis(100);
+    const N_BUCKETS: usize = (Self::MAX_BUCKETED.as_micros() / Self::BUCKET_WIDTH.as_micros()) as usize + 1;
+
+    pub fn new() -> Self {
+        Self {
+            buckets: vec![0; Self::N_BUCKETS],
+            n_frames: 0,
+            total_time: Duration::ZERO,
+            slow_frames_time: Duration::ZERO,
+            slowest_frame: Duration::ZERO,
+        }
+    }
+    pub fn record(&mut self, frame_time: Duration) {
+        let bucket = (frame_time.as_micros() / Self::BUCKET_WIDTH.as_micros()) as usize;
+        if bucket >= Self::N_BUCKETS - 1 {
+            self.slow_frames_time += frame_time;
+        }
+        self.buckets[bucket.min(Self::N_BUCKETS - 1)] += 1;
+        self.n_frames += 1;
+        self.total_time += frame_time;
+        self.slowest_frame = self.slowest_frame.max(frame_time);
+    }
+    pub fn n_frames(&self) -> u64 {
+        self.n_frames
+    }
+    pub fn slowest_frame(&self) -> Duration {
+        self.slowest_frame
+    }
+    pub fn average_frame_time(&self) -> Duration {
+        if self.n_frames == 0 {
+            return Duration::ZERO;
+        }
+        self.total_time.div_f64(self.n_frames as f64)
+    }
+    /// The frame time which `percentile` (0-100) of the frames are at or below, rounded up to the bucket width
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.n_frames == 0 {
+            return Duration::ZERO;
+        }
+        let target = ((self.n_frames as f64) * percentile / 100.).ceil().max(1.) as u64;
+        let mut count = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            count += *n as u64;
+            if count >= target {
+                return if i == Self::N_BUCKETS - 1 { self.slowest_frame } else { Self::BUCKET_WIDTH * (i as u32 + 1) };
+            }
+        }
+        self.slowest_frame
+    }
+    /// The average frame rate over the slowest `percent` of the frames; `low_fps(1.)` is the "1% low"
+    pub fn low_fps(&self, percent: f64) -> f32 {
+        if self.n_frames == 0 {
+            return 0.;
+        }
+        let mut remaining = ((self.n_frames as f64) * percent / 100.).ceil().max(1.) as u64;
+        let n_frames = remaining;
+        let mut total = Duration::ZERO;
+        for (i, n) in self.buckets.iter().enumerate().rev() {
+            let n = (*n as u64).min(remaining);
+            if n == 0 {
+                continue;
+            }
+            if i == Self::N_BUCKETS - 1 {
+                // The slow frames are averaged, since only their total is known
+                total += self.slow_frames_time.mul_f64(n as f64 / self.buckets[i] as f64);
+            } else {
+                // The middle of the bucket
+                total += Self::BUCKET_WIDTH.mul_f64(n as f64 * (i as f64 + 0.5));
+            }
+            remaining -= n;
+            if remaining == 0 {
+                break;
+            }
+        }
+        n_frames as f32 / total.as_secs_f32()
+    }
+    pub fn stats(&self) -> FrameTimeStats {
+        let ms = |duration: Duration| duration.as_secs_f32() * 1000.;
+        let average = self.average_frame_time();
+        FrameTimeStats {
+            n_frames: self.n_frames,
+            average_fps: if average.is_zero() { 0. } else { 1. / average.as_secs_f32() },
+            average_ms: ms(average),
+            p50_ms: ms(self.percentile(50.)),
+            p95_ms: ms(self.percentile(95.)),
+            p99_ms: ms(self.percentile(99.)),
+            p99_9_ms: ms(self.percentile(99.9)),
+            max_ms: ms(self.slowest_frame),
+            low_1_percent_fps: self.low_fps(1.),
+            low_0_1_percent_fps: self.low_fps(0.1),
+        }
+    }
+    /// The non-empty buckets as `frame_time_ms,frames` rows, where `frame_time_ms` is the start of the bucket
+    pub fn to_csv(&self) -> String {
+        let mut csv = "frame_time_ms,frames\n".to_string();
+        for (i, n) in self.buckets.iter().enumerate().filter(|(_, n)| **n > 0) {
+            csv += &format!("{:.1},{n}\n", (Self::BUCKET_WIDTH * i as u32).as_secs_f64() * 1000.);
+        }
+        csv
+    }
+}
+impl Default for FrameTimeHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A summary of a [FrameTimeHistogram]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FrameTimeStats {
+    pub n_frames: u64,
+    pub average_fps: f32,
+    pub average_ms: f32,
+    pub p50_ms: f32,
+    pub p95_ms: f32,
+    pub p99_ms: f32,
+    pub p99_9_ms: f32,
+    pub max_ms: f32,
+    pub low_1_percent_fps: f32,
+    pub low_0_1_percent_fps: f32,
+}
+impl FrameTimeStats {
+    pub fn dump(&self) -> String {
+        format!(
+            "{} frames, {:.1} fps avg, {:.1} fps 1% low, {:.1} fps 0.1% low, p50/p99/max {:.1}/{:.1}/{:.1} ms",
+            self.n_frames, self.average_fps, self.low_1_percent_fps, self.low_0_1_percent_fps, self.p50_ms, self.p99_ms, self.max_ms
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::FrameTimeHistogram;
+
+    #[test]
+    fn frame_time_lows() {
+        let mut histogram = FrameTimeHistogram::new();
+        for _ in 0..990 {
+            histogram.record(Duration::from_micros(10_050));
+        }
+        for _ in 0..9 {
+            histogram.record(Duration::from_micros(50_050));
+        }
+        histogram.record(Duration::from_millis(200));
+
+        let stats = histogram.stats();
+        assert_eq!(stats.n_frames, 1000);
+        assert_eq!(histogram.percentile(50.), Duration::from_micros(10_100));
+        assert_eq!(histogram.percentile(99.), Duration::from_micros(10_100));
+        assert_eq!(histogram.percentile(99.9), Duration::from_micros(50_100));
+        assert_eq!(histogram.percentile(100.), Duration::from_millis(200));
+        // The slowest 10 frames: 9 at ~50 ms and one at 200 ms
+        assert!((stats.low_1_percent_fps - 10. / 0.6505).abs() < 0.01, "{}", stats.low_1_percent_fps);
+        assert!((stats.low_0_1_percent_fps - 5.).abs() < 0.01, "{}", stats.low_0_1_percent_fps);
+        assert_eq!(histogram.to_csv(), "frame_time_ms,frames\n10.0,990\n50.0,9\n100.0,1\n");
+    }
+}
@@ -0,0 +1,310 @@
+//! A global allocator which keeps track of the live allocations of each subsystem, to find out where memory goes and
+//! what keeps growing.
+//!
+//! Allocations are attributed to the innermost [AllocScope] of the thread which made them:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: InstrumentedAllocator = InstrumentedAllocator::new(std::alloc::System);
+//!
+//! let _scope = AllocScope::enter("physics");
+//! ```
+//!
+//! In leak check mode ([InstrumentedAllocator::leak_check]), every allocation made in a scope with that tag is recorded
+//! (with a backtrace if `RUST_BACKTRACE` is set) until it's freed, so the ones which are still outstanding at shutdown can
+//! be dumped with [InstrumentedAllocator::dump_leaks].
+
+use std::{
+    alloc::{GlobalAlloc, Layout},
+    backtrace::Backtrace,
+    cell::Cell,
+    collections::{BTreeMap, HashMap},
+    marker::PhantomData,
+    ptr::null_mut,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use parking_lot::Mutex;
+
+use crate::to_byte_unit;
+
+/// How many different tags there can be; allocations in scopes with more tags than that are untagged
+pub const MAX_ALLOC_TAGS: usize = 64;
+const UNTAGGED: u32 = 0;
+/// Set in the header of the allocations which are recorded for the leak check
+const LEAK_CHECKED: u64 = 1 << 32;
+
+/// The names of the tags, except for [UNTAGGED]; the id of a tag is its index + 1
+static TAG_NAMES: Mutex<Vec<&'static str>> = parking_lot::const_mutex(Vec::new());
+
+thread_local! {
+    static CURRENT_TAG: Cell<u32> = const { Cell::new(UNTAGGED) };
+    /// Set while the allocator allocates for its own bookkeeping, which must not be recorded itself
+    static IN_ALLOCATOR: Cell<bool> = const { Cell::new(false) };
+}
+
+fn tag_id(name: &'static str) -> u32 {
+    let mut names = TAG_NAMES.lock();
+    match names.iter().position(|n| *n == name) {
+        Some(index) => index as u32 + 1,
+        None if names.len() + 1 < MAX_ALLOC_TAGS => {
+            names.push(name);
+            names.len() as u32
+        }
+        None => UNTAGGED,
+    }
+}
+fn tag_name(id: u32) -> &'static str {
+    if id == UNTAGGED {
+        "untagged"
+    } else {
+        TAG_NAMES.lock()[id as usize - 1]
+    }
+}
+
+/// Attributes the allocations made on this thread to `tag` until it's dropped.
+///
+/// Scopes nest, and only track the current thread, so they shouldn't be held across an `.await`.
+pub struct AllocScope {
+    previous: u32,
+    _not_send: PhantomData<*const ()>,
+}
+impl AllocScope {
+    pub fn enter(tag: &'static str) -> Self {
+        let id = tag_id(tag);
+        Self { previous: CURRENT_TAG.with(|current| current.replace(id)), _not_send: PhantomData }
+    }
+}
+impl Drop for AllocScope {
+    fn drop(&mut self) {
+        CURRENT_TAG.with(|current| current.set(self.previous));
+    }
+}
+
+struct TagCounters {
+    live_bytes: AtomicUsize,
+    live_allocations: AtomicUsize,
+    total_allocations: AtomicUsize,
+    leak_check: AtomicBool,
+}
+impl TagCounters {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: TagCounters = TagCounters {
+        live_bytes: AtomicUsize::new(0),
+        live_allocations: AtomicUsize::new(0),
+        total_allocations: AtomicUsize::new(0),
+        leak_check: AtomicBool::new(false),
+    };
+}
+
+struct OutstandingAllocation {
+    tag: u32,
+    size: usize,
+    backtrace: Backtrace,
+}
+
+/// The live allocations of a tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllocStats {
+    pub tag: &'static str,
+    pub live_bytes: usize,
+    pub live_allocations: usize,
+    /// All the allocations made with the tag so far, including the freed ones
+    pub total_allocations: usize,
+}
+
+/// Wraps another allocator (usually [std::alloc::System]), and counts the allocations per [AllocScope] tag.
+///
+/// Each allocation gets a small header to remember its tag, and reallocations always move, so this is meant for
+/// debugging rather than to be enabled all the time.
+pub struct InstrumentedAllocator<A> {
+    inner: A,
+    counters: [TagCounters; MAX_ALLOC_TAGS],
+    outstanding: Mutex<BTreeMap<usize, OutstandingAllocation>>,
+}
+impl<A> InstrumentedAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner, counters: [TagCounters::INIT; MAX_ALLOC_TAGS], outstanding: parking_lot::const_mutex(BTreeMap::new()) }
+    }
+    /// The live allocations of each tag which has been used so far
+    pub fn stats(&self) -> Vec<AllocStats> {
+        let n_tags = TAG_NAMES.lock().len() + 1;
+        (0..n_tags as u32)
+            .map(|id| {
+                let counters = &self.counters[id as usize];
+                AllocStats {
+                    tag: tag_name(id),
+                    live_bytes: counters.live_bytes.load(Ordering::Relaxed),
+                    live_allocations: counters.live_allocations.load(Ordering::Relaxed),
+                    total_allocations: counters.total_allocations.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+    /// Starts recording the allocations made with `tag`, so that the ones which are never freed can be dumped with
+    /// [InstrumentedAllocator::dump_leaks]. Only the allocations made after this call are checked.
+    pub fn leak_check(&self, tag: &'static str) {
+        let id = tag_id(tag);
+        if id != UNTAGGED {
+            self.counters[id as usize].leak_check.store(true, Ordering::Relaxed);
+        }
+    }
+    /// Logs the leak checked allocations which haven't been freed yet, and returns how many there are
+    pub fn dump_leaks(&self) -> usize {
+        // The tag names can't be looked up while the outstanding allocations are locked, since registering a tag allocates
+        let outstanding = {
+            let _guard = BookkeepingGuard::try_enter();
+            let outstanding = self.outstanding.lock();
+            outstanding
+                .iter()
+                .map(|(ptr, allocation)| (*ptr, allocation.tag, allocation.size, allocation.backtrace.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let mut per_tag = HashMap::<u32, (usize, usize)>::new();
+        for (ptr, tag, size, backtrace) in &outstanding {
+            let (count, bytes) = per_tag.entry(*tag).or_default();
+            *count += 1;
+            *bytes += size;
+            log::warn!("Outstanding allocation in {}: {} bytes at {:#x}\n{}", tag_name(*tag), size, ptr, backtrace);
+        }
+        for (tag, (count, bytes)) in per_tag {
+            log::warn!("{} outstanding allocations in {}, {} in total", count, tag_name(tag), to_byte_unit(bytes as u64));
+        }
+        outstanding.len()
+    }
+
+    fn record_alloc(&self, ptr: *mut u8, size: usize) -> u64 {
+        let tag = CURRENT_TAG.try_with(|current| current.get()).unwrap_or(UNTAGGED);
+        let counters = &self.counters[tag as usize];
+        counters.live_bytes.fetch_add(size, Ordering::Relaxed);
+        counters.live_allocations.fetch_add(1, Ordering::Relaxed);
+        counters.total_allocations.fetch_add(1, Ordering::Relaxed);
+        if counters.leak_check.load(Ordering::Relaxed) {
+            if let Some(_guard) = BookkeepingGuard::try_enter() {
+                let allocation = OutstandingAllocation { tag, size, backtrace: Backtrace::capture() };
+                self.outstanding.lock().insert(ptr as usize, allocation);
+                return tag as u64 | LEAK_CHECKED;
+            }
+        }
+        tag as u64
+    }
+    fn record_dealloc(&self, ptr: *mut u8, size: usize, header: u64) {
+        let counters = &self.counters[(header as u32) as usize];
+        counters.live_bytes.fetch_sub(size, Ordering::Relaxed);
+        counters.live_allocations.fetch_sub(1, Ordering::Relaxed);
+        if header & LEAK_CHECKED != 0 {
+            if let Some(_guard) = BookkeepingGuard::try_enter() {
+                self.outstanding.lock().remove(&(ptr as usize));
+            }
+        }
+    }
+}
+
+/// Marks the current thread as doing bookkeeping, so the allocations it makes meanwhile aren't leak checked
+struct BookkeepingGuard;
+impl BookkeepingGuard {
+    fn try_enter() -> Option<Self> {
+        match IN_ALLOCATOR.try_with(|in_allocator| in_allocator.replace(true)) {
+            Ok(false) => Some(Self),
+            _ => None,
+        }
+    }
+}
+impl Drop for BookkeepingGuard {
+    fn drop(&mut self) {
+        IN_ALLOCATOR.with(|in_allocator| in_allocator.set(false));
+    }
+}
+
+/// The layout of an allocation with room for its header, and the offset of the allocation in it. The header is the
+/// `u64` right before the allocation.
+fn with_header(layout: Layout) -> Option<(Layout, usize)> {
+    let offset = layout.align().max(16);
+    Some((Layout::from_size_align(layout.size().checked_add(offset)?, offset).ok()?, offset))
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for InstrumentedAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (outer, offset) = match with_header(layout) {
+            Some(outer) => outer,
+            None => return null_mut(),
+        };
+        let base = self.inner.alloc(outer);
+        if base.is_null() {
+            return base;
+        }
+        let ptr = base.add(offset);
+        let header = self.record_alloc(ptr, layout.size());
+        (ptr.sub(8) as *mut u64).write(header);
+        ptr
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // This succeeded when the memory was allocated
+        let (outer, offset) = with_header(layout).unwrap();
+        let header = (ptr.sub(8) as *const u64).read();
+        self.record_dealloc(ptr, layout.size(), header);
+        self.inner.dealloc(ptr.sub(offset), outer);
+    }
+}
+
+/// Logs the tags whose live memory grew since the last time it was called
+#[derive(Debug, Default)]
+pub struct AllocGrowthLogger {
+    previous: HashMap<&'static str, usize>,
+}
+impl AllocGrowthLogger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn log(&mut self, stats: &[AllocStats]) {
+        for stats in stats {
+            let previous = self.previous.insert(stats.tag, stats.live_bytes).unwrap_or(0);
+            if stats.live_bytes > previous {
+                log::info!(
+                    "{} grew by {} to {} in {} allocations",
+                    stats.tag,
+                    to_byte_unit((stats.live_bytes - previous) as u64),
+                    to_byte_unit(stats.live_bytes as u64),
+                    stats.live_allocations
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+
+    use super::{AllocScope, InstrumentedAllocator};
+
+    #[test]
+    fn tags_and_leaks() {
+        let allocator = InstrumentedAllocator::new(System);
+        allocator.leak_check("test_leaky");
+        let layout = Layout::from_size_align(100, 64).unwrap();
+        unsafe {
+            let (kept, freed) = {
+                let _scope = AllocScope::enter("test_leaky");
+                (allocator.alloc(layout), allocator.alloc(layout))
+            };
+            assert_eq!(kept as usize % 64, 0);
+            let other = {
+                let _scope = AllocScope::enter("test_other");
+                allocator.alloc(layout)
+            };
+            allocator.dealloc(freed, layout);
+
+            let stats = allocator.stats();
+            let leaky = stats.iter().find(|stats| stats.tag == "test_leaky").unwrap();
+            assert_eq!((leaky.live_bytes, leaky.live_allocations, leaky.total_allocations), (100, 1, 2));
+            let other_stats = stats.iter().find(|stats| stats.tag == "test_other").unwrap();
+            assert_eq!((other_stats.live_bytes, other_stats.live_allocations), (100, 1));
+            assert_eq!(allocator.dump_leaks(), 1);
+
+            allocator.dealloc(kept, layout);
+            allocator.dealloc(other, layout);
+            assert_eq!(allocator.dump_leaks(), 0);
+        }
+    }
+}
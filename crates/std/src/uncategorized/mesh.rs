@@ -2,7 +2,11 @@ use glam::*;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
-use crate::shapes::AABB;
+use crate::{meshopt, shapes::AABB};
+
+/// The start of the meshes serialized by [Mesh::to_compressed_bytes]. The meshes serialized with bincode start with the
+/// length of their name, which is never this much.
+const COMPRESSED_MAGIC: &[u8; 8] = b"AMESHOPT";
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Mesh {
@@ -95,6 +99,29 @@ impl Mesh {
             }
         }
     }
+    /// The bounding box of the vertices each joint has a weight on, indexed by joint, or `None` for the joints which don't
+    /// move any vertex. A skinned vertex is a weighted average of its position transformed by each of its joints, so
+    /// whatever the pose, it stays within the union of these boxes transformed by the joint matrices.
+    pub fn joint_aabbs(&self) -> Vec<Option<AABB>> {
+        let mut aabbs: Vec<Option<AABB>> = Vec::new();
+        if let (Some(positions), Some(weights), Some(indices)) = (&self.positions, &self.joint_weights, &self.joint_indices) {
+            for ((&position, weight), index) in positions.iter().zip(weights.iter()).zip(indices.iter()) {
+                for (joint, weight) in index.to_array().into_iter().zip(weight.to_array()) {
+                    if weight <= 0. {
+                        continue;
+                    }
+                    let joint = joint as usize;
+                    if aabbs.len() <= joint {
+                        aabbs.resize(joint + 1, None);
+                    }
+                    let aabb = aabbs[joint].get_or_insert(AABB { min: position, max: position });
+                    aabb.min = aabb.min.min(position);
+                    aabb.max = aabb.max.max(position);
+                }
+            }
+        }
+        aabbs
+    }
 
     #[profiling::function]
     pub fn append(&mut self, mut mesh: Mesh) {
@@ -215,6 +242,49 @@ impl Mesh {
         }
         self.tangents = Some(tangents);
     }
+    /// Serializes the mesh with its vertex attributes and indices compressed with [meshopt], which usually makes it a
+    /// few times smaller. It's read back with [Mesh::from_bytes].
+    pub fn to_compressed_bytes(&self) -> Vec<u8> {
+        fn compress<T: bytemuck::Pod>(values: &Option<Vec<T>>) -> Option<CompressedAttribute> {
+            values.as_ref().map(|values| CompressedAttribute::compress(values))
+        }
+        let mesh = CompressedMesh {
+            name: self.name.clone(),
+            positions: compress(&self.positions),
+            colors: compress(&self.colors),
+            normals: compress(&self.normals),
+            tangents: compress(&self.tangents),
+            texcoords: self.texcoords.iter().map(|texcoords| CompressedAttribute::compress(texcoords)).collect(),
+            joint_indices: compress(&self.joint_indices),
+            joint_weights: compress(&self.joint_weights),
+            indices: self.indices.as_ref().map(|indices| CompressedIndices::compress(indices)),
+        };
+        let mut data = COMPRESSED_MAGIC.to_vec();
+        bincode::serialize_into(&mut data, &mesh).unwrap();
+        data
+    }
+    /// Deserializes a mesh serialized with bincode, or with [Mesh::to_compressed_bytes]
+    pub fn from_bytes(data: &[u8]) -> anyhow::Result<Self> {
+        let data = match data.strip_prefix(COMPRESSED_MAGIC) {
+            Some(data) => data,
+            None => return Ok(bincode::deserialize(data)?),
+        };
+        fn decompress<T: bytemuck::Pod>(values: Option<CompressedAttribute>) -> anyhow::Result<Option<Vec<T>>> {
+            values.map(|values| values.decompress()).transpose()
+        }
+        let mesh: CompressedMesh = bincode::deserialize(data)?;
+        Ok(Self {
+            name: mesh.name,
+            positions: decompress(mesh.positions)?,
+            colors: decompress(mesh.colors)?,
+            normals: decompress(mesh.normals)?,
+            tangents: decompress(mesh.tangents)?,
+            texcoords: mesh.texcoords.into_iter().map(|texcoords| texcoords.decompress()).collect::<anyhow::Result<_>>()?,
+            joint_indices: decompress(mesh.joint_indices)?,
+            joint_weights: decompress(mesh.joint_weights)?,
+            indices: mesh.indices.map(|indices| indices.decompress()).transpose()?,
+        })
+    }
     pub fn size_in_bytes(&self) -> usize {
         self.positions.as_ref().map(|x| std::mem::size_of_val(&**x)).unwrap_or(0)
             + self.colors.as_ref().map(|x| std::mem::size_of_val(&**x)).unwrap_or(0)
@@ -226,3 +296,55 @@ impl Mesh {
             + self.texcoords.iter().map(|x| std::mem::size_of_val(&**x)).sum::<usize>()
     }
 }
+
+/// A [Mesh] serialized by [Mesh::to_compressed_bytes]
+#[derive(Serialize, Deserialize)]
+struct CompressedMesh {
+    name: String,
+    positions: Option<CompressedAttribute>,
+    colors: Option<CompressedAttribute>,
+    normals: Option<CompressedAttribute>,
+    tangents: Option<CompressedAttribute>,
+    texcoords: Vec<CompressedAttribute>,
+    joint_indices: Option<CompressedAttribute>,
+    joint_weights: Option<CompressedAttribute>,
+    indices: Option<CompressedIndices>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompressedAttribute {
+    count: u32,
+    data: Vec<u8>,
+}
+impl CompressedAttribute {
+    fn compress<T: bytemuck::Pod>(values: &[T]) -> Self {
+        Self { count: values.len() as u32, data: meshopt::encode_vertex_buffer(bytemuck::cast_slice(values), std::mem::size_of::<T>()) }
+    }
+    fn decompress<T: bytemuck::Pod>(self) -> anyhow::Result<Vec<T>> {
+        let data = meshopt::decode_vertex_buffer(&self.data, self.count as usize, std::mem::size_of::<T>())?;
+        Ok(bytemuck::pod_collect_to_vec(&data))
+    }
+}
+
+/// The indices are compressed as triangles when they can be, which is much smaller
+#[derive(Serialize, Deserialize)]
+enum CompressedIndices {
+    Triangles { count: u32, data: Vec<u8> },
+    Sequence { count: u32, data: Vec<u8> },
+}
+impl CompressedIndices {
+    fn compress(indices: &[u32]) -> Self {
+        let count = indices.len() as u32;
+        if indices.len() % 3 == 0 {
+            Self::Triangles { count, data: meshopt::encode_index_buffer(indices) }
+        } else {
+            Self::Sequence { count, data: meshopt::encode_index_sequence(indices) }
+        }
+    }
+    fn decompress(self) -> anyhow::Result<Vec<u32>> {
+        match self {
+            Self::Triangles { count, data } => meshopt::decode_index_buffer(&data, count as usize),
+            Self::Sequence { count, data } => meshopt::decode_index_sequence(&data, count as usize),
+        }
+    }
+}
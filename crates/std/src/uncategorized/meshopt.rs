@@ -0,0 +1,613 @@
+//! A port of the vertex and index buffer codecs of [meshoptimizer](https://github.com/zeux/meshoptimizer), which the
+//! buffers of the glTF files using `EXT_meshopt_compression` are compressed with.
+//!
+//! The decoders read version 0 of the vertex codec, and versions 0 and 1 of the index codecs; these are the versions the
+//! glTF extension allows. The encoders write version 0 of the vertex codec and version 1 of the index codecs.
+
+use anyhow::{ensure, Context};
+
+use crate::varint::{read_varint, write_varint};
+
+const VERTEX_HEADER: u8 = 0xa0;
+const INDEX_HEADER: u8 = 0xe0;
+const SEQUENCE_HEADER: u8 = 0xd0;
+
+const VERTEX_BLOCK_SIZE_BYTES: usize = 8192;
+const VERTEX_BLOCK_MAX_SIZE: usize = 256;
+const BYTE_GROUP_SIZE: usize = 16;
+const TAIL_MAX_SIZE: usize = 32;
+
+/// The codeaux values which can be encoded in the code of a triangle, picked by meshoptimizer from their frequency
+const CODE_AUX_TABLE: [u8; 16] = [0x00, 0x76, 0x87, 0x56, 0x67, 0x78, 0xa9, 0x86, 0x65, 0x89, 0x68, 0x98, 0x01, 0x69, 0, 0];
+
+/// How the values of a buffer view were transformed before being compressed, which [decode_filter] reverses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Unit vectors stored as octahedral coordinates, in 8 or 16 bits signed components
+    Octahedral,
+    /// Unit quaternions stored as their three smallest components, in 16 bits signed components
+    Quaternion,
+    /// Floats stored as a 24 bits mantissa and an 8 bits exponent
+    Exponential,
+}
+
+/// Decodes `vertex_count` vertices of `vertex_size` bytes compressed with [encode_vertex_buffer]
+pub fn decode_vertex_buffer(encoded: &[u8], vertex_count: usize, vertex_size: usize) -> anyhow::Result<Vec<u8>> {
+    ensure!(vertex_size > 0 && vertex_size <= 256 && vertex_size % 4 == 0, "Invalid vertex size {vertex_size}");
+    ensure!(encoded.len() > vertex_size, "The vertex buffer is truncated");
+    ensure!(encoded[0] == VERTEX_HEADER, "Unsupported vertex buffer encoding {:#x}", encoded[0]);
+
+    let mut last_vertex = [0; 256];
+    last_vertex[..vertex_size].copy_from_slice(&encoded[encoded.len() - vertex_size..]);
+    let mut vertices = vec![0; vertex_count * vertex_size];
+    let mut data = &encoded[1..];
+    for block in vertices.chunks_mut(vertex_block_size(vertex_size) * vertex_size) {
+        data = decode_vertex_block(data, block, vertex_size, &mut last_vertex)?;
+    }
+    ensure!(data.len() == vertex_size.max(TAIL_MAX_SIZE), "Invalid vertex buffer");
+    Ok(vertices)
+}
+
+/// Compresses `vertices`, which are `vertex_size` bytes each. The vertices compress best when the similar ones are next
+/// to each other, and when the bytes which rarely change (such as the high bytes of the floats) are the same for all of
+/// them.
+pub fn encode_vertex_buffer(vertices: &[u8], vertex_size: usize) -> Vec<u8> {
+    assert!(vertex_size > 0 && vertex_size <= 256 && vertex_size % 4 == 0, "Invalid vertex size {vertex_size}");
+    assert_eq!(vertices.len() % vertex_size, 0, "The vertices aren't {vertex_size} bytes each");
+
+    let mut first_vertex = [0; 256];
+    let first_vertex_size = vertex_size.min(vertices.len());
+    first_vertex[..first_vertex_size].copy_from_slice(&vertices[..first_vertex_size]);
+    let mut last_vertex = first_vertex;
+    let mut encoded = vec![VERTEX_HEADER];
+    for block in vertices.chunks(vertex_block_size(vertex_size) * vertex_size) {
+        encode_vertex_block(&mut encoded, block, vertex_size, &mut last_vertex);
+    }
+    // The first vertex is at the end, which also pads the data so that the decoder can read a group without checking
+    // the bounds
+    encoded.resize(encoded.len() + TAIL_MAX_SIZE.saturating_sub(vertex_size), 0);
+    encoded.extend_from_slice(&first_vertex[..vertex_size]);
+    encoded
+}
+
+fn vertex_block_size(vertex_size: usize) -> usize {
+    ((VERTEX_BLOCK_SIZE_BYTES / vertex_size) & !(BYTE_GROUP_SIZE - 1)).min(VERTEX_BLOCK_MAX_SIZE)
+}
+
+fn aligned_group_size(count: usize) -> usize {
+    (count + BYTE_GROUP_SIZE - 1) & !(BYTE_GROUP_SIZE - 1)
+}
+
+fn zigzag8(value: u8) -> u8 {
+    (((value as i8) >> 7) as u8) ^ (value << 1)
+}
+fn unzigzag8(value: u8) -> u8 {
+    (value & 1).wrapping_neg() ^ (value >> 1)
+}
+
+/// Each byte of the vertices of a block is stored separately, as the deltas to the same byte of the previous vertex
+fn decode_vertex_block<'a>(
+    mut data: &'a [u8],
+    block: &mut [u8],
+    vertex_size: usize,
+    last_vertex: &mut [u8; 256],
+) -> anyhow::Result<&'a [u8]> {
+    let vertex_count = block.len() / vertex_size;
+    let mut buffer = [0; VERTEX_BLOCK_MAX_SIZE];
+    for k in 0..vertex_size {
+        data = decode_bytes(data, &mut buffer[..aligned_group_size(vertex_count)])?;
+        let mut previous = last_vertex[k];
+        for (i, delta) in buffer[..vertex_count].iter().enumerate() {
+            previous = unzigzag8(*delta).wrapping_add(previous);
+            block[i * vertex_size + k] = previous;
+        }
+    }
+    last_vertex[..vertex_size].copy_from_slice(&block[(vertex_count - 1) * vertex_size..]);
+    Ok(data)
+}
+fn encode_vertex_block(encoded: &mut Vec<u8>, block: &[u8], vertex_size: usize, last_vertex: &mut [u8; 256]) {
+    let vertex_count = block.len() / vertex_size;
+    let mut buffer = [0; VERTEX_BLOCK_MAX_SIZE];
+    for k in 0..vertex_size {
+        let mut previous = last_vertex[k];
+        for (i, delta) in buffer[..vertex_count].iter_mut().enumerate() {
+            let value = block[i * vertex_size + k];
+            *delta = zigzag8(value.wrapping_sub(previous));
+            previous = value;
+        }
+        encode_bytes(encoded, &buffer[..aligned_group_size(vertex_count)]);
+    }
+    last_vertex[..vertex_size].copy_from_slice(&block[(vertex_count - 1) * vertex_size..]);
+}
+
+/// The bytes are stored by groups of 16, each of which is packed in 0, 2, 4 or 8 bits per byte; the bytes which don't
+/// fit in 2 or 4 bits are stored after the group. The number of bits of the groups is stored first, in 2 bits per group.
+fn decode_bytes<'a>(data: &'a [u8], buffer: &mut [u8]) -> anyhow::Result<&'a [u8]> {
+    let header_size = (buffer.len() / BYTE_GROUP_SIZE + 3) / 4;
+    ensure!(data.len() >= header_size, "The vertex buffer is truncated");
+    let (header, mut data) = data.split_at(header_size);
+    for (i, group) in buffer.chunks_mut(BYTE_GROUP_SIZE).enumerate() {
+        // A group takes at most 24 bytes, and the tail is always there
+        ensure!(data.len() >= TAIL_MAX_SIZE, "The vertex buffer is truncated");
+        let bits_log2 = (header[i / 4] >> ((i % 4) * 2)) & 3;
+        data = match bits_log2 {
+            0 => {
+                group.fill(0);
+                data
+            }
+            3 => {
+                group.copy_from_slice(&data[..BYTE_GROUP_SIZE]);
+                &data[BYTE_GROUP_SIZE..]
+            }
+            _ => {
+                let bits = 1 << bits_log2;
+                let sentinel = (1u8 << bits) - 1;
+                let (packed, mut rest) = data.split_at(BYTE_GROUP_SIZE * bits / 8);
+                for (j, value) in group.iter_mut().enumerate() {
+                    let packed_value = (packed[j * bits / 8] >> (8 - bits - (j * bits) % 8)) & sentinel;
+                    *value = if packed_value == sentinel {
+                        let (&value, remaining) = rest.split_first().unwrap();
+                        rest = remaining;
+                        value
+                    } else {
+                        packed_value
+                    };
+                }
+                rest
+            }
+        };
+    }
+    Ok(data)
+}
+fn encode_bytes(encoded: &mut Vec<u8>, buffer: &[u8]) {
+    let header_start = encoded.len();
+    encoded.resize(header_start + (buffer.len() / BYTE_GROUP_SIZE + 3) / 4, 0);
+    for (i, group) in buffer.chunks(BYTE_GROUP_SIZE).enumerate() {
+        let bits =
+            [1, 2, 4]
+                .into_iter()
+                .fold(8, |best, bits| if encoded_group_size(group, bits) < encoded_group_size(group, best) { bits } else { best });
+        encoded[header_start + i / 4] |= (bits.trailing_zeros() as u8) << ((i % 4) * 2);
+        match bits {
+            1 => {}
+            8 => encoded.extend_from_slice(group),
+            _ => {
+                let sentinel = (1u8 << bits) - 1;
+                for values in group.chunks(8 / bits) {
+                    encoded.push(values.iter().fold(0, |packed, &value| (packed << bits) | value.min(sentinel)));
+                }
+                encoded.extend(group.iter().filter(|&&value| value >= sentinel));
+            }
+        }
+    }
+}
+fn encoded_group_size(group: &[u8], bits: usize) -> usize {
+    match bits {
+        // A group of zeros takes no space, which is what a single bit per byte stands for
+        1 if group.iter().all(|&value| value == 0) => 0,
+        1 => usize::MAX,
+        8 => BYTE_GROUP_SIZE,
+        _ => {
+            let sentinel = (1u8 << bits) - 1;
+            BYTE_GROUP_SIZE * bits / 8 + group.iter().filter(|&&value| value >= sentinel).count()
+        }
+    }
+}
+
+/// Decodes the `index_count` indices of a triangle list compressed with [encode_index_buffer]
+pub fn decode_index_buffer(encoded: &[u8], index_count: usize) -> anyhow::Result<Vec<u32>> {
+    ensure!(index_count % 3 == 0, "The index count of a triangle list must be a multiple of 3, not {index_count}");
+    ensure!(encoded.len() >= 1 + index_count / 3 + 16, "The index buffer is truncated");
+    ensure!(encoded[0] & 0xf0 == INDEX_HEADER, "Unsupported index buffer encoding {:#x}", encoded[0]);
+    let version = encoded[0] & 0x0f;
+    ensure!(version <= 1, "Unsupported index buffer version {version}");
+    // From version 1, the vertex codes 13 and 14 stand for the last free index minus and plus one
+    let fec_max = if version >= 1 { 13 } else { 15 };
+
+    let codes = &encoded[1..1 + index_count / 3];
+    // The codeaux table at the end also pads the data, as a triangle takes at most 16 bytes of it
+    let data_end = encoded.len() - 16;
+    let code_aux_table = &encoded[data_end..];
+    let mut data = ByteReader { data: encoded, position: 1 + codes.len() };
+    let mut fifos = Fifos::default();
+    let mut next = 0u32;
+    let mut last = 0u32;
+    let mut indices = Vec::with_capacity(index_count);
+    for &code in codes {
+        ensure!(data.position <= data_end, "The index buffer is truncated");
+        if code < 0xf0 {
+            // One of the last edges, and a vertex which is recent, the next one or a free index
+            let [a, b] = fifos.edge(code >> 4);
+            let fec = code & 15;
+            let c = if fec == 0 {
+                next += 1;
+                next - 1
+            } else if fec < fec_max {
+                fifos.vertex(fec)
+            } else {
+                last = if fec == 15 { data.index(last)? } else { last.wrapping_add(fec as u32).wrapping_sub(fec as u32 ^ 3) };
+                last
+            };
+            fifos.push_vertex_if(c, fec == 0 || fec >= fec_max);
+            indices.extend([a, b, c]);
+            fifos.push_edge(c, b);
+            fifos.push_edge(a, c);
+        } else {
+            // Three vertices which are either recent, the next ones or free indices
+            let (fea, code_aux) = match code {
+                0xfe => (0, data.byte()?),
+                0xff => (15, data.byte()?),
+                _ => (0, code_aux_table[(code & 15) as usize]),
+            };
+            let (feb, fec) = (code_aux >> 4, code_aux & 15);
+            let mut vertices = [0; 3];
+            for (vertex, fe) in vertices.iter_mut().zip([fea, feb, fec]) {
+                *vertex = match fe {
+                    0 => {
+                        next += 1;
+                        next - 1
+                    }
+                    15 => {
+                        last = data.index(last)?;
+                        last
+                    }
+                    _ => fifos.vertex(fe - 1),
+                };
+            }
+            let [a, b, c] = vertices;
+            fifos.push_vertex_if(a, true);
+            fifos.push_vertex_if(b, feb == 0 || feb == 15);
+            fifos.push_vertex_if(c, fec == 0 || fec == 15);
+            indices.extend([a, b, c]);
+            fifos.push_edge(b, a);
+            fifos.push_edge(c, b);
+            fifos.push_edge(a, c);
+        }
+    }
+    ensure!(data.position == data_end, "Invalid index buffer");
+    Ok(indices)
+}
+
+/// Compresses the indices of a triangle list. They compress best when the triangles are ordered for the vertex cache,
+/// and the vertices are in the order in which the triangles first use them. The decoded triangles may start from another
+/// of their vertices, but keep their winding.
+pub fn encode_index_buffer(indices: &[u32]) -> Vec<u8> {
+    assert_eq!(indices.len() % 3, 0, "The index count of a triangle list must be a multiple of 3");
+    const FEC_MAX: u8 = 13;
+
+    let mut codes = Vec::with_capacity(indices.len() / 3);
+    let mut data = Vec::new();
+    let mut fifos = Fifos::default();
+    let mut next = 0u32;
+    let mut last = 0u32;
+    for triangle in indices.chunks_exact(3) {
+        match fifos.find_edge(triangle) {
+            Some((fe, rotation)) if fe < 15 => {
+                let [a, b, c] = rotate(triangle, rotation);
+                let fec = match fifos.find_vertex(c) {
+                    Some(fc) if (1..FEC_MAX).contains(&fc) => fc,
+                    _ if c == next => {
+                        next += 1;
+                        0
+                    }
+                    _ if c.wrapping_add(1) == last => 13,
+                    _ if c == last.wrapping_add(1) => 14,
+                    _ => 15,
+                };
+                codes.push((fe << 4) | fec);
+                if fec == 15 {
+                    write_index(&mut data, c, last);
+                }
+                if fec >= FEC_MAX {
+                    last = c;
+                }
+                fifos.push_vertex_if(c, fec == 0 || fec >= FEC_MAX);
+                fifos.push_edge(c, b);
+                fifos.push_edge(a, c);
+            }
+            _ => {
+                // The next vertex goes first, so that it doesn't need to be encoded
+                let rotation = if triangle[1] == next {
+                    1
+                } else if triangle[2] == next {
+                    2
+                } else {
+                    0
+                };
+                let [a, b, c] = rotate(triangle, rotation);
+                let (fb, fc) = (fifos.find_vertex(b), fifos.find_vertex(c));
+                let mut free_or_next = |vertex: u32| {
+                    if vertex == next {
+                        next += 1;
+                        0
+                    } else {
+                        15
+                    }
+                };
+                let fea = free_or_next(a);
+                let mut recent_free_or_next = |vertex: u32, age: Option<u8>| match age {
+                    Some(age) if age < 14 => age + 1,
+                    _ => free_or_next(vertex),
+                };
+                let feb = recent_free_or_next(b, fb);
+                let fec = recent_free_or_next(c, fc);
+                let code_aux = (feb << 4) | fec;
+                match CODE_AUX_TABLE.iter().position(|&value| value == code_aux) {
+                    Some(index) if fea == 0 && index < 14 => codes.push(0xf0 | index as u8),
+                    _ => {
+                        codes.push(if fea == 0 { 0xfe } else { 0xff });
+                        data.push(code_aux);
+                    }
+                }
+                for (vertex, fe) in [(a, fea), (b, feb), (c, fec)] {
+                    if fe == 15 {
+                        write_index(&mut data, vertex, last);
+                        last = vertex;
+                    }
+                }
+                fifos.push_vertex_if(a, true);
+                fifos.push_vertex_if(b, feb == 0 || feb == 15);
+                fifos.push_vertex_if(c, fec == 0 || fec == 15);
+                fifos.push_edge(b, a);
+                fifos.push_edge(c, b);
+                fifos.push_edge(a, c);
+            }
+        }
+    }
+    let mut encoded = vec![INDEX_HEADER | 1];
+    encoded.extend(codes);
+    encoded.extend(data);
+    encoded.extend(CODE_AUX_TABLE);
+    encoded
+}
+
+/// Decodes the `index_count` indices compressed with [encode_index_sequence]
+pub fn decode_index_sequence(encoded: &[u8], index_count: usize) -> anyhow::Result<Vec<u32>> {
+    ensure!(encoded.len() >= 1 + index_count + 4, "The index sequence is truncated");
+    ensure!(encoded[0] & 0xf0 == SEQUENCE_HEADER, "Unsupported index sequence encoding {:#x}", encoded[0]);
+    let version = encoded[0] & 0x0f;
+    ensure!(version <= 1, "Unsupported index sequence version {version}");
+
+    // The data is followed by 4 bytes of padding, as an index takes at most 5 bytes
+    let data_end = encoded.len() - 4;
+    let mut data = ByteReader { data: encoded, position: 1 };
+    let mut last = [0u32; 2];
+    let mut indices = Vec::with_capacity(index_count);
+    for _ in 0..index_count {
+        ensure!(data.position < data_end, "The index sequence is truncated");
+        let value = data.varint()?;
+        // The low bit picks which of the last two indices the delta is to
+        let baseline = (value & 1) as usize;
+        last[baseline] = last[baseline].wrapping_add(unzigzag32(value >> 1));
+        indices.push(last[baseline]);
+    }
+    ensure!(data.position == data_end, "Invalid index sequence");
+    Ok(indices)
+}
+
+/// Compresses a sequence of indices, which don't need to be triangles. They compress best when each of them is close to
+/// one of the last two. As in meshoptimizer, the deltas between them must fit in 30 bits.
+pub fn encode_index_sequence(indices: &[u32]) -> Vec<u8> {
+    let mut encoded = vec![SEQUENCE_HEADER | 1];
+    let mut last = [0u32; 2];
+    let mut baseline = 0;
+    for &index in indices {
+        // Switch to the other baseline when the delta doesn't fit in a byte anymore
+        let delta = index.wrapping_sub(last[baseline]) as i32;
+        if delta.unsigned_abs() >= 30 {
+            baseline ^= 1;
+        }
+        let delta = index.wrapping_sub(last[baseline]);
+        write_varint(&mut encoded, ((zigzag32(delta) << 1) | baseline as u32) as u64);
+        last[baseline] = index;
+    }
+    encoded.extend([0; 4]);
+    encoded
+}
+
+/// Reverses the `filter` of the decoded `data`, whose values are `stride` bytes each
+pub fn decode_filter(data: &mut [u8], stride: usize, filter: Filter) -> anyhow::Result<()> {
+    ensure!(stride > 0 && data.len() % stride == 0, "The data isn't made of values of {stride} bytes");
+    match filter {
+        Filter::Octahedral if stride == 4 => {
+            for value in data.chunks_exact_mut(4) {
+                let components = octahedral(value[0] as i8 as f32, value[1] as i8 as f32, value[2] as i8 as f32, i8::MAX as f32);
+                for (byte, component) in value.iter_mut().zip(components) {
+                    *byte = component as i8 as u8;
+                }
+            }
+        }
+        Filter::Octahedral if stride == 8 => {
+            for value in data.chunks_exact_mut(8) {
+                let [x, y, z, _] = read_i16x4(value);
+                let components = octahedral(x as f32, y as f32, z as f32, i16::MAX as f32);
+                for (bytes, component) in value.chunks_exact_mut(2).zip(components) {
+                    bytes.copy_from_slice(&(component as i16).to_le_bytes());
+                }
+            }
+        }
+        Filter::Quaternion if stride == 8 => {
+            for value in data.chunks_exact_mut(8) {
+                let components = read_i16x4(value);
+                // The scale of the components is in the high bits of the fourth one, and the index of the largest one, which is
+                // left out, in its two low bits
+                let scale = std::f32::consts::FRAC_1_SQRT_2 / (components[3] | 3) as f32;
+                let [x, y, z] = [components[0], components[1], components[2]].map(|component| component as f32 * scale);
+                let w = (1. - x * x - y * y - z * z).max(0.).sqrt();
+                let largest = (components[3] & 3) as usize;
+                for (offset, component) in [1, 2, 3, 0].into_iter().zip([x, y, z, w]) {
+                    let index = (largest + offset) & 3;
+                    value[index * 2..index * 2 + 2].copy_from_slice(&((component * i16::MAX as f32).round() as i16).to_le_bytes());
+                }
+            }
+        }
+        Filter::Exponential if stride % 4 == 0 => {
+            for value in data.chunks_exact_mut(4) {
+                let bits = u32::from_le_bytes(value.try_into().unwrap());
+                let mantissa = ((bits << 8) as i32) >> 8;
+                let exponent = (bits as i32) >> 24;
+                let scale = f32::from_bits(((exponent + 127) as u32) << 23);
+                value.copy_from_slice(&(scale * mantissa as f32).to_le_bytes());
+            }
+        }
+        _ => anyhow::bail!("The {filter:?} filter doesn't apply to values of {stride} bytes"),
+    }
+    Ok(())
+}
+
+/// Decodes a unit vector from its octahedral coordinates, and scales it to `max`
+fn octahedral(x: f32, y: f32, z: f32, max: f32) -> [f32; 3] {
+    let z = z - x.abs() - y.abs();
+    // The coordinates are folded over for the lower half of the octahedron
+    let t = z.min(0.);
+    let x = x + if x >= 0. { t } else { -t };
+    let y = y + if y >= 0. { t } else { -t };
+    let scale = max / (x * x + y * y + z * z).sqrt();
+    [x, y, z].map(|component| (component * scale).round())
+}
+
+fn read_i16x4(value: &[u8]) -> [i16; 4] {
+    [0, 1, 2, 3].map(|i| i16::from_le_bytes([value[i * 2], value[i * 2 + 1]]))
+}
+
+/// The last edges and vertices of the triangles, which the next ones refer to by how far back they are
+#[derive(Debug, Clone)]
+struct Fifos {
+    edges: [[u32; 2]; 16],
+    edge_offset: usize,
+    vertices: [u32; 16],
+    vertex_offset: usize,
+}
+impl Default for Fifos {
+    fn default() -> Self {
+        Self { edges: [[u32::MAX; 2]; 16], edge_offset: 0, vertices: [u32::MAX; 16], vertex_offset: 0 }
+    }
+}
+impl Fifos {
+    fn edge(&self, age: u8) -> [u32; 2] {
+        self.edges[self.edge_offset.wrapping_sub(1 + age as usize) & 15]
+    }
+    fn vertex(&self, age: u8) -> u32 {
+        self.vertices[self.vertex_offset.wrapping_sub(1 + age as usize) & 15]
+    }
+    /// The age of the most recent edge of `triangle`, and the rotation of the triangle which starts with that edge
+    fn find_edge(&self, triangle: &[u32]) -> Option<(u8, usize)> {
+        (0..16).find_map(|age| {
+            let edge = self.edge(age);
+            (0..3).find(|&rotation| edge == [triangle[rotation], triangle[(rotation + 1) % 3]]).map(|rotation| (age, rotation))
+        })
+    }
+    fn find_vertex(&self, vertex: u32) -> Option<u8> {
+        (0..16).find(|&age| self.vertex(age) == vertex)
+    }
+    fn push_edge(&mut self, a: u32, b: u32) {
+        self.edges[self.edge_offset] = [a, b];
+        self.edge_offset = (self.edge_offset + 1) & 15;
+    }
+    fn push_vertex_if(&mut self, vertex: u32, condition: bool) {
+        self.vertices[self.vertex_offset] = vertex;
+        self.vertex_offset = (self.vertex_offset + condition as usize) & 15;
+    }
+}
+
+fn rotate(triangle: &[u32], rotation: usize) -> [u32; 3] {
+    [triangle[rotation], triangle[(rotation + 1) % 3], triangle[(rotation + 2) % 3]]
+}
+
+fn zigzag32(value: u32) -> u32 {
+    (value << 1) ^ ((value as i32) >> 31) as u32
+}
+fn unzigzag32(value: u32) -> u32 {
+    (value >> 1) ^ (value & 1).wrapping_neg()
+}
+
+/// Writes the difference between `index` and `last`
+fn write_index(data: &mut Vec<u8>, index: u32, last: u32) {
+    write_varint(data, zigzag32(index.wrapping_sub(last)) as u64);
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+impl ByteReader<'_> {
+    fn byte(&mut self) -> anyhow::Result<u8> {
+        let byte = *self.data.get(self.position).context("Unexpected end of the compressed data")?;
+        self.position += 1;
+        Ok(byte)
+    }
+    /// Reads a 32 bit value written by [write_varint]
+    fn varint(&mut self) -> anyhow::Result<u32> {
+        let mut rest = &self.data[self.position..];
+        let value = read_varint(&mut rest).context("Invalid compressed data")?;
+        self.position = self.data.len() - rest.len();
+        value.try_into().context("Invalid compressed data")
+    }
+    /// Reads an index written by [write_index]
+    fn index(&mut self, last: u32) -> anyhow::Result<u32> {
+        Ok(last.wrapping_add(unzigzag32(self.varint()?)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The vertices and triangles of a `size` by `size` grid
+    fn grid(size: u32) -> (Vec<u8>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        for y in 0..=size {
+            for x in 0..=size {
+                for component in [x as f32 * 0.1, y as f32 * 0.1, (x as f32 * 0.3).sin(), 1.] {
+                    vertices.extend(component.to_le_bytes());
+                }
+            }
+        }
+        let mut indices = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let a = y * (size + 1) + x;
+                indices.extend([a, a + size + 1, a + 1, a + 1, a + size + 1, a + size + 2]);
+            }
+        }
+        (vertices, indices)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (vertices, indices) = grid(40);
+        let encoded = encode_vertex_buffer(&vertices, 16);
+        assert!(encoded.len() < vertices.len() / 2);
+        assert_eq!(decode_vertex_buffer(&encoded, 41 * 41, 16).unwrap(), vertices);
+
+        let encoded = encode_index_buffer(&indices);
+        assert!(encoded.len() < indices.len());
+        assert_eq!(decode_index_buffer(&encoded, indices.len()).unwrap(), indices);
+
+        let encoded = encode_index_sequence(&indices);
+        assert_eq!(decode_index_sequence(&encoded, indices.len()).unwrap(), indices);
+    }
+
+    #[test]
+    fn truncated() {
+        let (vertices, indices) = grid(4);
+        let encoded = encode_vertex_buffer(&vertices, 16);
+        assert!((0..encoded.len()).all(|len| decode_vertex_buffer(&encoded[..len], 25, 16).is_err()));
+        let encoded = encode_index_sequence(&indices);
+        assert!((0..encoded.len()).all(|len| decode_index_sequence(&encoded[..len], indices.len()).is_err()));
+    }
+
+    #[test]
+    fn filters() {
+        let mut data = [(3u32 & 0xffffff) | (2 << 24), (-5i32 as u32 & 0xffffff) | ((-1i32 as u32) << 24)]
+            .into_iter()
+            .flat_map(u32::to_le_bytes)
+            .collect::<Vec<_>>();
+        decode_filter(&mut data, 8, Filter::Exponential).unwrap();
+        assert_eq!(data.chunks(4).map(|value| f32::from_le_bytes(value.try_into().unwrap())).collect::<Vec<_>>(), [12., -2.5]);
+
+        let mut data = [54u8, 0, 127, 0, 127, 127, 127, 0];
+        decode_filter(&mut data, 4, Filter::Octahedral).unwrap();
+        assert_eq!(data.map(|value| value as i8), [76, 0, 102, 0, 0, 0, -127, 0]);
+    }
+}
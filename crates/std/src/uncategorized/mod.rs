@@ -1,19 +1,30 @@
 pub use ambient_asset_cache as asset_cache;
+pub mod asset_manifest;
 pub mod asset_url;
 pub mod barc;
 pub mod color;
 pub mod disk_cache;
 pub mod download_asset;
+pub mod download_cache;
+pub mod draco;
 pub mod encode;
+pub mod fixed;
 pub mod fps_counter;
 pub mod id;
+pub mod instrumented_alloc;
 pub mod math;
 pub mod mesh;
+pub mod meshopt;
 pub mod ordered_glam;
 pub mod shapes;
+pub mod signature;
 pub mod sparse_vec;
 pub mod time;
+pub mod url_scheme;
+pub mod uv_unwrap;
+pub mod varint;
+pub mod watched_config;
 
-pub use encode::sha256_digest;
+pub use encode::{sha256_digest, sha256_digest_bytes, Sha256Digest};
 pub use id::friendly_id;
 pub use time::{from_now, pretty_duration, FromDuration, IntoDuration};
@@ -0,0 +1,143 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::Context;
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use serde::{Deserialize, Serialize};
+
+/// The extension of the detached signature written next to a signed file (`main.wasm` -> `main.wasm.sig`)
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+/// An ed25519 key, used to sign build outputs so that their provenance can be verified before they are loaded
+pub struct SigningKey(Ed25519KeyPair);
+impl SigningKey {
+    pub fn from_pkcs8(pkcs8: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self(Ed25519KeyPair::from_pkcs8(pkcs8).map_err(|err| anyhow::anyhow!("Invalid ed25519 key: {err}"))?))
+    }
+    /// Loads the PKCS#8 encoded key at `path`, or generates a new one and saves it there if there is none
+    pub fn load_or_generate(path: &Path) -> anyhow::Result<Self> {
+        if path.exists() {
+            return Self::from_pkcs8(&std::fs::read(path)?).with_context(|| format!("Failed to load signing key {path:?}"));
+        }
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).map_err(|err| anyhow::anyhow!("Failed to generate key: {err}"))?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, pkcs8.as_ref()).with_context(|| format!("Failed to save signing key to {path:?}"))?;
+        let key = Self::from_pkcs8(pkcs8.as_ref())?;
+        log::info!("Generated a new signing key at {path:?}, with the public key {}", key.public_key());
+        Ok(key)
+    }
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.0.public_key().as_ref().to_vec())
+    }
+    pub fn sign(&self, content: &[u8]) -> Signature {
+        Signature { public_key: self.public_key(), signature: data_encoding::HEXLOWER.encode(self.0.sign(content).as_ref()) }
+    }
+}
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SigningKey").field(&self.public_key()).finish()
+    }
+}
+
+/// An ed25519 public key, which is displayed and parsed as hex
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PublicKey(Vec<u8>);
+impl Display for PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&data_encoding::HEXLOWER.encode(&self.0))
+    }
+}
+impl FromStr for PublicKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = data_encoding::HEXLOWER_PERMISSIVE.decode(s.as_bytes()).with_context(|| format!("Invalid public key: {s}"))?;
+        anyhow::ensure!(bytes.len() == 32, "Invalid public key: {s} is not 32 bytes long");
+        Ok(Self(bytes))
+    }
+}
+impl TryFrom<String> for PublicKey {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+impl From<PublicKey> for String {
+    fn from(key: PublicKey) -> Self {
+        key.to_string()
+    }
+}
+
+/// A detached signature, along with the key which made it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signature {
+    pub public_key: PublicKey,
+    /// Hex encoded
+    pub signature: String,
+}
+impl Signature {
+    /// Verifies that this is a valid signature of `content`, made by one of the `trusted_keys`
+    pub fn verify(&self, content: &[u8], trusted_keys: &[PublicKey]) -> anyhow::Result<()> {
+        anyhow::ensure!(trusted_keys.contains(&self.public_key), "Signed with an untrusted key: {}", self.public_key);
+        let signature = data_encoding::HEXLOWER_PERMISSIVE.decode(self.signature.as_bytes()).context("Invalid signature encoding")?;
+        UnparsedPublicKey::new(&ED25519, &self.public_key.0)
+            .verify(content, &signature)
+            .map_err(|_| anyhow::anyhow!("Invalid signature from {}", self.public_key))
+    }
+}
+
+/// Returns the path of the detached signature of the file at `path`
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(SIGNATURE_EXTENSION);
+    path.into()
+}
+
+/// Verifies `content`, read from `path`, against the detached signature next to it
+pub fn verify_file(path: &Path, content: &[u8], trusted_keys: &[PublicKey]) -> anyhow::Result<()> {
+    let signature_path = signature_path(path);
+    let signature = std::fs::read(&signature_path).with_context(|| format!("{path:?} is not signed; no {signature_path:?}"))?;
+    let signature: Signature = serde_json::from_slice(&signature).with_context(|| format!("Invalid signature file {signature_path:?}"))?;
+    signature.verify(content, trusted_keys).with_context(|| format!("Failed to verify {path:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::{rand::SystemRandom, signature::Ed25519KeyPair};
+
+    use super::{signature_path, PublicKey, Signature, SigningKey};
+
+    #[test]
+    fn sign_and_verify() {
+        let generate = || SigningKey::from_pkcs8(Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap().as_ref()).unwrap();
+        let key = generate();
+        let other_key = generate();
+        let content = b"\0asm module";
+
+        let signature = key.sign(content);
+        signature.verify(content, &[other_key.public_key(), key.public_key()]).unwrap();
+        assert!(signature.verify(content, &[other_key.public_key()]).is_err());
+        assert!(signature.verify(b"\0asm tampered", &[key.public_key()]).is_err());
+        // A signature which claims to be from a trusted key doesn't verify if it wasn't made by it
+        let forged = Signature { public_key: key.public_key(), ..other_key.sign(content) };
+        assert!(forged.verify(content, &[key.public_key()]).is_err());
+
+        let json = serde_json::to_string(&signature).unwrap();
+        assert_eq!(serde_json::from_str::<Signature>(&json).unwrap(), signature);
+        assert_eq!(key.public_key().to_string().parse::<PublicKey>().unwrap(), key.public_key());
+        assert!("abcd".parse::<PublicKey>().is_err());
+
+        assert_eq!(signature_path("build/main.wasm".as_ref()), std::path::PathBuf::from("build/main.wasm.sig"));
+    }
+}
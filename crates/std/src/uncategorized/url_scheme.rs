@@ -0,0 +1,82 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::{
+    asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt},
+    asset_url::AbsAssetUrl,
+};
+
+/// Loads the content of urls with a custom scheme, such as `pak://` or `ipfs://`, from a game specific storage backend.
+///
+/// Once registered with [register_url_scheme], [AbsAssetUrl::download_bytes] (and the other `download_*` methods) use it
+/// for all the urls with that scheme.
+#[async_trait]
+pub trait UrlSchemeHandler: Send + Sync {
+    async fn download_bytes(&self, assets: &AssetCache, url: &AbsAssetUrl) -> anyhow::Result<Vec<u8>>;
+}
+
+/// The registered [UrlSchemeHandler]s, by scheme
+#[derive(Clone, Default)]
+pub struct UrlSchemeHandlers(HashMap<String, Arc<dyn UrlSchemeHandler>>);
+impl std::fmt::Debug for UrlSchemeHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct UrlSchemeHandlersKey;
+impl SyncAssetKey<UrlSchemeHandlers> for UrlSchemeHandlersKey {
+    fn load(&self, _assets: AssetCache) -> UrlSchemeHandlers {
+        UrlSchemeHandlers::default()
+    }
+}
+
+/// Makes `handler` load all the urls with the given `scheme` (without the `://`), replacing any previous handler for it.
+///
+/// Handlers take precedence over the built-in `http` and `https` downloads; `file` urls are always read from disk.
+pub fn register_url_scheme(assets: &AssetCache, scheme: impl Into<String>, handler: impl UrlSchemeHandler + 'static) {
+    let mut handlers = UrlSchemeHandlersKey.get(assets);
+    handlers.0.insert(scheme.into().to_lowercase(), Arc::new(handler));
+    UrlSchemeHandlersKey.insert(assets, handlers);
+}
+
+/// Returns the handler registered for the scheme of `url`, if any
+pub fn url_scheme_handler(assets: &AssetCache, url: &AbsAssetUrl) -> Option<Arc<dyn UrlSchemeHandler>> {
+    UrlSchemeHandlersKey.try_get(assets)?.0.get(url.0.scheme()).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves the path of the urls as their content, or fails for the ones under `missing/`
+    struct PathHandler(&'static str);
+    #[async_trait]
+    impl UrlSchemeHandler for PathHandler {
+        async fn download_bytes(&self, _assets: &AssetCache, url: &AbsAssetUrl) -> anyhow::Result<Vec<u8>> {
+            let path = url.0.path();
+            anyhow::ensure!(!path.starts_with("/missing/"), "Not in the archive: {path}");
+            Ok(format!("{}{path}", self.0).into_bytes())
+        }
+    }
+
+    #[tokio::test]
+    async fn urls_are_loaded_by_the_handler_of_their_scheme() {
+        let assets = AssetCache::new(tokio::runtime::Handle::current());
+        let url = AbsAssetUrl::parse("pak://archive/models/crate.glb").unwrap();
+        assert!(url_scheme_handler(&assets, &url).is_none());
+
+        register_url_scheme(&assets, "PAK", PathHandler("first:"));
+        assert_eq!(url.download_bytes(&assets).await.unwrap(), b"first:/models/crate.glb");
+        assert_eq!(url.download_string(&assets).await.unwrap(), "first:/models/crate.glb");
+        assert!(url_scheme_handler(&assets, &AbsAssetUrl::parse("ipfs://archive/crate.glb").unwrap()).is_none());
+
+        register_url_scheme(&assets, "pak", PathHandler("second:"));
+        assert_eq!(url.download_bytes(&assets).await.unwrap(), b"second:/models/crate.glb");
+
+        let err = AbsAssetUrl::parse("pak://archive/missing/crate.glb").unwrap().download_bytes(&assets).await.unwrap_err();
+        assert_eq!(format!("{err:#}"), "Failed to load pak://archive/missing/crate.glb: Not in the archive: /missing/crate.glb");
+    }
+}
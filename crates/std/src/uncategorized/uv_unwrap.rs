@@ -0,0 +1,428 @@
+//! Generates the second UVs of the meshes, which the lightmaps are baked with: each triangle gets a place of its own in the
+//! unit square, so that no two triangles share a texel, and the charts are padded so that the texels don't bleed into each
+//! other when they're filtered.
+//!
+//! As in xatlas, the triangles are grouped into charts, which are flattened and then packed:
+//! - A chart grows from the largest triangle left over the edges to its neighbours, the positions being welded, for as
+//!   long as their normals are within [LightmapUvSettings::max_chart_angle] of the first one's, and their projection onto
+//!   its plane doesn't overlap the triangles of the chart.
+//! - The chart is flattened by that projection, which keeps the proportions of its triangles up to the angle, and rotated
+//!   to the smallest rectangle around it.
+//! - The rectangles are packed on shelves, the tallest first, into a square which is then scaled to the unit square, the
+//!   charts being [LightmapUvSettings::padding] texels apart at the [LightmapUvSettings::resolution] of the lightmap.
+//!
+//! The vertices on the seams between the charts are split. The charts of several meshes can be packed together, for the
+//! meshes which share a lightmap.
+
+use std::collections::{HashMap, VecDeque};
+
+use glam::{vec2, Vec2, Vec3};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use super::mesh::Mesh;
+
+/// How the second UVs are generated
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LightmapUvSettings {
+    /// The width and height of the lightmap, in texels
+    pub resolution: u32,
+    /// The texels between the charts
+    pub padding: u32,
+    /// The largest angle between the normals of the triangles of a chart, in degrees, below 90
+    pub max_chart_angle: f32,
+}
+impl Default for LightmapUvSettings {
+    fn default() -> Self {
+        Self { resolution: 512, padding: 2, max_chart_angle: 60. }
+    }
+}
+
+/// How many times the square is grown before giving up on the padding, when the charts can't be that far apart at the
+/// resolution
+const MAX_PACKING_ATTEMPTS: usize = 100;
+
+/// Generates the second UVs of `meshes`, packing their charts into the same unit square; the UVs are the first ones too
+/// for the meshes which have none. The meshes without positions or indices are left as they are.
+#[profiling::function]
+pub fn generate_lightmap_uvs(meshes: &mut [&mut Mesh], settings: &LightmapUvSettings) {
+    let charts = meshes.iter().map(|mesh| create_charts(mesh, settings.max_chart_angle)).collect_vec();
+    let sizes = charts.iter().flatten().map(|chart| chart.size).collect_vec();
+    let (offsets, side) = pack(&sizes, settings);
+    let mut offsets = offsets.into_iter();
+    for (mesh, charts) in meshes.iter_mut().zip(charts) {
+        let offsets = offsets.by_ref().take(charts.len()).collect_vec();
+        if !charts.is_empty() {
+            split_charts(mesh, &charts, &offsets, side);
+        }
+    }
+}
+
+impl Mesh {
+    /// Generates the second UVs of the mesh, which the lightmaps are baked with; see [generate_lightmap_uvs]
+    pub fn generate_lightmap_uvs(&mut self, settings: &LightmapUvSettings) {
+        generate_lightmap_uvs(&mut [self], settings);
+    }
+}
+
+/// A group of triangles of a mesh which are flattened together
+#[derive(Debug)]
+struct Chart {
+    triangles: Vec<usize>,
+    /// The position of the corners of each triangle in the chart's rectangle
+    uvs: Vec<[Vec2; 3]>,
+    size: Vec2,
+}
+
+fn create_charts(mesh: &Mesh, max_chart_angle: f32) -> Vec<Chart> {
+    let (positions, indices) = match (&mesh.positions, &mesh.indices) {
+        (Some(positions), Some(indices)) => (positions, indices),
+        _ => return Vec::new(),
+    };
+    let triangle_count = indices.len() / 3;
+    let corners = |triangle: usize| [0, 1, 2].map(|corner| positions[indices[triangle * 3 + corner] as usize]);
+
+    // The vertices which are split in the mesh, e.g. for their normals, are neighbours all the same
+    let mut welded = HashMap::new();
+    let welded_indices = positions
+        .iter()
+        .map(|position| {
+            let next = welded.len() as u32;
+            *welded.entry(position.to_array().map(f32::to_bits)).or_insert(next)
+        })
+        .collect_vec();
+    let edge = |triangle: usize, corner: usize| {
+        let a = welded_indices[indices[triangle * 3 + corner] as usize];
+        let b = welded_indices[indices[triangle * 3 + (corner + 1) % 3] as usize];
+        (a.min(b), a.max(b))
+    };
+    let mut edge_triangles = HashMap::<(u32, u32), Vec<usize>>::new();
+    for triangle in 0..triangle_count {
+        for corner in 0..3 {
+            edge_triangles.entry(edge(triangle, corner)).or_default().push(triangle);
+        }
+    }
+
+    let (normals, areas): (Vec<Vec3>, Vec<f32>) = (0..triangle_count)
+        .map(|triangle| {
+            let [a, b, c] = corners(triangle);
+            let cross = (b - a).cross(c - a);
+            (cross.normalize_or_zero(), cross.length() / 2.)
+        })
+        .unzip();
+    let total_edge_length: f32 =
+        (0..triangle_count).map(|triangle| corners(triangle)).map(|[a, b, c]| a.distance(b) + b.distance(c) + c.distance(a)).sum();
+    let cell_size = (total_edge_length / (triangle_count * 3).max(1) as f32).max(f32::EPSILON);
+    let min_cos = max_chart_angle.clamp(0., 89.).to_radians().cos();
+
+    let mut charted = vec![false; triangle_count];
+    let mut charts = Vec::new();
+    for seed in (0..triangle_count).sorted_by(|&a, &b| areas[b].total_cmp(&areas[a])) {
+        if charted[seed] {
+            continue;
+        }
+        let normal = if normals[seed] == Vec3::ZERO { Vec3::Z } else { normals[seed] };
+        let (u, v) = normal.any_orthonormal_pair();
+        let project = |triangle: usize| corners(triangle).map(|position| vec2(position.dot(u), position.dot(v)));
+
+        let mut flattened = FlattenedTriangles::new(cell_size);
+        let mut triangles = vec![seed];
+        let mut uvs = vec![project(seed)];
+        charted[seed] = true;
+        flattened.insert(uvs[0]);
+        let mut queue = VecDeque::from([seed]);
+        while let Some(triangle) = queue.pop_front() {
+            for corner in 0..3 {
+                for &neighbour in &edge_triangles[&edge(triangle, corner)] {
+                    if charted[neighbour] {
+                        continue;
+                    }
+                    let projected = project(neighbour);
+                    // The degenerate triangles cover no texels, and go with any of their neighbours
+                    let degenerate = areas[neighbour] <= f32::EPSILON;
+                    if !degenerate && (normals[neighbour].dot(normal) < min_cos || flattened.overlaps(projected)) {
+                        continue;
+                    }
+                    charted[neighbour] = true;
+                    if !degenerate {
+                        flattened.insert(projected);
+                    }
+                    triangles.push(neighbour);
+                    uvs.push(projected);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        let (uvs, size) = fit_rectangle(uvs);
+        charts.push(Chart { triangles, uvs, size });
+    }
+    charts
+}
+
+/// The flattened triangles of a chart, in a grid to find the ones which a new triangle could overlap
+struct FlattenedTriangles {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    triangles: Vec<[Vec2; 3]>,
+}
+impl FlattenedTriangles {
+    fn new(cell_size: f32) -> Self {
+        Self { cell_size, cells: HashMap::new(), triangles: Vec::new() }
+    }
+    fn cells(&self, triangle: [Vec2; 3]) -> impl Iterator<Item = (i32, i32)> {
+        let min = triangle[0].min(triangle[1]).min(triangle[2]) / self.cell_size;
+        let max = triangle[0].max(triangle[1]).max(triangle[2]) / self.cell_size;
+        (min.x.floor() as i32..=max.x.floor() as i32).cartesian_product(min.y.floor() as i32..=max.y.floor() as i32)
+    }
+    fn insert(&mut self, triangle: [Vec2; 3]) {
+        let index = self.triangles.len();
+        self.triangles.push(triangle);
+        for cell in self.cells(triangle).collect_vec() {
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+    fn overlaps(&self, triangle: [Vec2; 3]) -> bool {
+        // The triangles which share an edge or a corner only touch
+        let epsilon = self.cell_size * 1e-4;
+        self.cells(triangle)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .any(|&other| triangles_overlap(triangle, self.triangles[other], epsilon))
+    }
+}
+
+/// Whether the insides of the triangles `a` and `b` overlap by more than `epsilon`, by the separating axis theorem
+fn triangles_overlap(a: [Vec2; 3], b: [Vec2; 3], epsilon: f32) -> bool {
+    let axes = [a, b].into_iter().flat_map(|triangle| (0..3).map(move |i| (triangle[(i + 1) % 3] - triangle[i]).perp()));
+    for axis in axes {
+        let axis = axis.normalize_or_zero();
+        if axis == Vec2::ZERO {
+            continue;
+        }
+        let (a_min, a_max) = a.iter().map(|point| point.dot(axis)).minmax().into_option().unwrap();
+        let (b_min, b_max) = b.iter().map(|point| point.dot(axis)).minmax().into_option().unwrap();
+        if a_max <= b_min + epsilon || b_max <= a_min + epsilon {
+            return false;
+        }
+    }
+    true
+}
+
+/// Rotates the flattened triangles of a chart to the smallest rectangle around them, wider than tall, and moves them into
+/// it. Returns the triangles and the size of the rectangle.
+fn fit_rectangle(mut uvs: Vec<[Vec2; 3]>) -> (Vec<[Vec2; 3]>, Vec2) {
+    let hull = convex_hull(uvs.iter().flatten().copied().collect());
+    // The smallest rectangle has a side along an edge of the hull
+    let rotation = (0..hull.len())
+        .map(|i| (hull[(i + 1) % hull.len()] - hull[i]).normalize_or_zero())
+        .filter(|direction| *direction != Vec2::ZERO)
+        .map(|direction| vec2(direction.x, -direction.y))
+        .min_by(|a, b| bounds_area(&hull, *a).total_cmp(&bounds_area(&hull, *b)))
+        .unwrap_or(Vec2::X);
+    let (min, max) = bounds(uvs.iter().flatten().map(|point| rotation.rotate(*point)));
+    let mut size = max - min;
+    let turn = size.y > size.x;
+    for point in uvs.iter_mut().flatten() {
+        *point = rotation.rotate(*point) - min;
+        if turn {
+            *point = vec2(size.y - point.y, point.x);
+        }
+    }
+    if turn {
+        size = vec2(size.y, size.x);
+    }
+    (uvs, size)
+}
+
+fn bounds(points: impl Iterator<Item = Vec2>) -> (Vec2, Vec2) {
+    points.fold((Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)), |(min, max), point| (min.min(point), max.max(point)))
+}
+
+fn bounds_area(points: &[Vec2], rotation: Vec2) -> f32 {
+    let (min, max) = bounds(points.iter().map(|point| rotation.rotate(*point)));
+    (max - min).x * (max - min).y
+}
+
+/// The convex hull of `points`, by Andrew's monotone chain
+fn convex_hull(mut points: Vec<Vec2>) -> Vec<Vec2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let mut hull: Vec<Vec2> = Vec::new();
+    for pass in 0..2 {
+        let start = hull.len();
+        let iter: Box<dyn Iterator<Item = &Vec2>> = if pass == 0 { Box::new(points.iter()) } else { Box::new(points.iter().rev()) };
+        for &point in iter {
+            while hull.len() >= start + 2 && (hull[hull.len() - 1] - hull[hull.len() - 2]).perp_dot(point - hull[hull.len() - 2]) <= 0. {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // The last point is the first of the other half
+        hull.pop();
+    }
+    hull
+}
+
+/// Packs the rectangles of `sizes` on shelves into a square, with the padding of `settings` around them. Returns where
+/// each rectangle goes, and the side of the square.
+fn pack(sizes: &[Vec2], settings: &LightmapUvSettings) -> (Vec<Vec2>, f32) {
+    let order = (0..sizes.len()).sorted_by(|&a, &b| sizes[b].y.total_cmp(&sizes[a].y)).collect_vec();
+    let area: f32 = sizes.iter().map(|size| size.x * size.y).sum();
+    let widest = sizes.iter().map(|size| size.x).fold(0., f32::max);
+    let mut side = (area.sqrt() * 1.1).max(widest).max(f32::EPSILON);
+    let resolution = settings.resolution.max(1) as f32;
+    for attempt in 0.. {
+        let padding = settings.padding as f32 * side / resolution;
+        let mut offsets = vec![Vec2::ZERO; sizes.len()];
+        let mut cursor = Vec2::splat(padding);
+        let mut shelf_height = 0.;
+        let mut fits = true;
+        for &index in &order {
+            let size = sizes[index];
+            if cursor.x + size.x + padding > side && cursor.x > padding {
+                cursor = vec2(padding, cursor.y + shelf_height + padding);
+                shelf_height = 0.;
+            }
+            fits &= cursor.x + size.x + padding <= side;
+            offsets[index] = cursor;
+            cursor.x += size.x + padding;
+            shelf_height = f32::max(shelf_height, size.y);
+        }
+        let height = cursor.y + shelf_height + padding;
+        if fits && height <= side {
+            return (offsets, side);
+        }
+        if attempt == MAX_PACKING_ATTEMPTS {
+            log::warn!("The charts can't be {} texels apart at a resolution of {resolution}", settings.padding);
+            return (offsets, side.max(height));
+        }
+        side *= 1.05;
+    }
+    unreachable!()
+}
+
+/// Splits the vertices of `mesh` on the seams between its `charts`, and sets its second UVs to where the charts are in
+/// the square of `side` once they're moved to their `offsets`
+fn split_charts(mesh: &mut Mesh, charts: &[Chart], offsets: &[Vec2], side: f32) {
+    let indices = mesh.indices.as_ref().unwrap();
+    let mut new_indices = vec![0; indices.len()];
+    // The vertex each new vertex is copied from
+    let mut sources = Vec::new();
+    let mut lightmap_uvs = Vec::new();
+    for (chart_index, (chart, offset)) in charts.iter().zip(offsets).enumerate() {
+        let mut vertices = HashMap::new();
+        for (&triangle, uvs) in chart.triangles.iter().zip(&chart.uvs) {
+            for corner in 0..3 {
+                let source = indices[triangle * 3 + corner];
+                new_indices[triangle * 3 + corner] = *vertices.entry((chart_index, source)).or_insert_with(|| {
+                    sources.push(source);
+                    lightmap_uvs.push((*offset + uvs[corner]) / side);
+                    sources.len() as u32 - 1
+                });
+            }
+        }
+    }
+
+    fn remap<T: Copy>(values: &mut Option<Vec<T>>, sources: &[u32]) {
+        if let Some(values) = values {
+            *values = sources.iter().map(|&source| values[source as usize]).collect();
+        }
+    }
+    remap(&mut mesh.positions, &sources);
+    remap(&mut mesh.colors, &sources);
+    remap(&mut mesh.normals, &sources);
+    remap(&mut mesh.tangents, &sources);
+    remap(&mut mesh.joint_indices, &sources);
+    remap(&mut mesh.joint_weights, &sources);
+    for texcoords in &mut mesh.texcoords {
+        *texcoords = sources.iter().map(|&source| texcoords[source as usize]).collect();
+    }
+    mesh.indices = Some(new_indices);
+    if mesh.texcoords.is_empty() {
+        mesh.texcoords.push(lightmap_uvs.clone());
+    }
+    mesh.texcoords.truncate(1);
+    mesh.texcoords.push(lightmap_uvs);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube() -> Mesh {
+        // Each face has vertices of its own, as for their normals
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        for axis in 0..3 {
+            for sign in [-1., 1.] {
+                let normal = Vec3::AXES[axis] * sign;
+                let (u, v) = (Vec3::AXES[(axis + 1) % 3], Vec3::AXES[(axis + 2) % 3]);
+                let start = positions.len() as u32;
+                positions.extend([normal - u - v, normal + u - v, normal + u + v, normal - u + v]);
+                if sign > 0. {
+                    indices.extend([0, 1, 2, 0, 2, 3].map(|i| start + i));
+                } else {
+                    indices.extend([0, 2, 1, 0, 3, 2].map(|i| start + i));
+                }
+            }
+        }
+        Mesh { normals: Some(positions.clone()), positions: Some(positions), indices: Some(indices), ..Default::default() }
+    }
+
+    fn assert_no_overlaps(uvs: &[Vec2], indices: &[u32]) {
+        let triangles = indices.chunks(3).map(|triangle| [0, 1, 2].map(|i| uvs[triangle[i] as usize])).collect_vec();
+        for (i, a) in triangles.iter().enumerate() {
+            for b in &triangles[i + 1..] {
+                assert!(!triangles_overlap(*a, *b, 1e-6), "{a:?} overlaps {b:?}");
+            }
+        }
+        assert!(uvs.iter().all(|uv| uv.cmpge(Vec2::ZERO).all() && uv.cmple(Vec2::ONE).all()));
+    }
+
+    #[test]
+    fn cube_faces_become_charts() {
+        let mut mesh = cube();
+        mesh.generate_lightmap_uvs(&LightmapUvSettings::default());
+        assert_eq!(mesh.texcoords.len(), 2);
+        // The faces are at right angles, so each is a chart, whose 4 vertices aren't shared
+        assert_eq!(mesh.positions.as_ref().unwrap().len(), 24);
+        assert_eq!(mesh.texcoords[1].len(), 24);
+        assert_no_overlaps(&mesh.texcoords[1], mesh.indices.as_ref().unwrap());
+
+        // With a wide enough angle, the charts cross the edges of the cube but still don't overlap
+        let mut mesh = cube();
+        mesh.generate_lightmap_uvs(&LightmapUvSettings { max_chart_angle: 89., ..Default::default() });
+        assert_no_overlaps(&mesh.texcoords[1], mesh.indices.as_ref().unwrap());
+    }
+
+    #[test]
+    fn charts_are_padded() {
+        let settings = LightmapUvSettings { resolution: 64, padding: 3, max_chart_angle: 30. };
+        let mut a = cube();
+        let mut b = cube();
+        b.texcoords = vec![vec![Vec2::ONE; 24]];
+        generate_lightmap_uvs(&mut [&mut a, &mut b], &settings);
+        // The UVs of the meshes which had none are the lightmap's
+        assert_eq!(a.texcoords[0], a.texcoords[1]);
+        assert!(b.texcoords[0].iter().all(|uv| *uv == Vec2::ONE));
+
+        // Packed together, the 12 faces are 3 texels apart
+        let faces = [&a, &b]
+            .iter()
+            .flat_map(|mesh| {
+                let uvs = &mesh.texcoords[1];
+                mesh.indices.as_ref().unwrap().chunks(6).map(|face| bounds(face.iter().map(|&i| uvs[i as usize] * 64.))).collect_vec()
+            })
+            .collect_vec();
+        for (i, (a_min, a_max)) in faces.iter().enumerate() {
+            for (b_min, b_max) in &faces[i + 1..] {
+                let gap = (*b_min - *a_max).max(*a_min - *b_max);
+                assert!(gap.x >= 3. - 1e-3 || gap.y >= 3. - 1e-3, "{a_min}..{a_max} and {b_min}..{b_max} are too close");
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Unsigned LEB128 integers, which take a single byte for the values below 128, and the zigzag encoding of the signed
+//! integers which keeps the small negative values small.
+
+use anyhow::Context;
+
+/// Writes `value` 7 bits at a time, with the high bit of each byte set if more bytes follow
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads a value written by [write_varint] from the start of `input`, which is advanced past it
+pub fn read_varint(input: &mut &[u8]) -> anyhow::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first().context("Truncated varint")?;
+        *input = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    anyhow::bail!("Invalid varint")
+}
+
+/// Maps 0, -1, 1, -2.. to 0, 1, 2, 3..
+pub fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+pub fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        for value in [0, 1, -1, 63, -64, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(value)), value);
+        }
+        assert_eq!([0, -1, 1, -2].map(zigzag), [0, 1, 2, 3]);
+
+        let mut bytes = Vec::new();
+        for value in [0, 127, 128, u64::MAX] {
+            write_varint(&mut bytes, value);
+        }
+        assert_eq!(bytes.len(), 1 + 1 + 2 + 10);
+        let mut input = &bytes[..];
+        for value in [0, 127, 128, u64::MAX] {
+            assert_eq!(read_varint(&mut input).unwrap(), value);
+        }
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn invalid_varints() {
+        assert!(read_varint(&mut &[][..]).is_err());
+        assert!(read_varint(&mut &[0x80, 0x80][..]).is_err());
+        assert!(read_varint(&mut &[0xff; 11][..]).is_err());
+    }
+}
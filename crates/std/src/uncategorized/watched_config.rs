@@ -0,0 +1,122 @@
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use ambient_sys::time::Instant;
+use serde::de::DeserializeOwned;
+
+/// Settings which can be loaded from a TOML file; see [WatchedConfig]
+pub trait Config: DeserializeOwned + Default + Clone + Debug + Send + Sync + 'static {
+    /// Checks that the values make sense; an invalid file is rejected as a whole
+    fn validate(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Settings loaded from a TOML file, which are reloaded when the file changes, so that they can be tuned while running.
+///
+/// The file is read at most every [WatchedConfig::POLL_INTERVAL]. If a new version of it can't be parsed or isn't valid,
+/// an error is logged and the last valid settings are kept, until the file is fixed.
+#[derive(Debug)]
+pub struct WatchedConfig<T: Config> {
+    path: PathBuf,
+    current: T,
+    content: Option<String>,
+    last_poll: Option<Instant>,
+}
+impl<T: Config> WatchedConfig<T> {
+    pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Loads the settings from `path`; the defaults are used until it exists and is valid
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let mut config = Self { path: path.into(), current: T::default(), content: None, last_poll: Some(Instant::now()) };
+        config.reload();
+        config
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn current(&self) -> &T {
+        &self.current
+    }
+    /// Returns the new settings if the file changed since the last poll, and they are valid
+    pub fn poll(&mut self) -> Option<&T> {
+        if self.last_poll.map(|last_poll| last_poll.elapsed() < Self::POLL_INTERVAL).unwrap_or(false) {
+            return None;
+        }
+        self.last_poll = Some(Instant::now());
+        if self.reload() {
+            Some(&self.current)
+        } else {
+            None
+        }
+    }
+    fn reload(&mut self) -> bool {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(_) => return false,
+        };
+        if self.content.as_ref() == Some(&content) {
+            return false;
+        }
+        let config = toml::from_str::<T>(&content).map_err(anyhow::Error::from).and_then(|config| config.validate().map(|_| config));
+        self.content = Some(content);
+        match config {
+            Ok(config) => {
+                log::info!("Loaded settings from {:?}: {:?}", self.path, config);
+                self.current = config;
+                true
+            }
+            Err(err) => {
+                log::error!("Invalid settings in {:?}, keeping the previous ones: {:?}", self.path, err);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::{Config, WatchedConfig};
+
+    #[derive(Debug, Clone, PartialEq, Deserialize)]
+    #[serde(default)]
+    struct TestConfig {
+        rate: u32,
+        name: String,
+    }
+    impl Default for TestConfig {
+        fn default() -> Self {
+            Self { rate: 60, name: "default".to_string() }
+        }
+    }
+    impl Config for TestConfig {
+        fn validate(&self) -> anyhow::Result<()> {
+            anyhow::ensure!(self.rate > 0, "rate must be positive");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reload_and_rollback() {
+        let path = std::env::temp_dir().join(format!("ambient_watched_config_{}.toml", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let mut config = WatchedConfig::<TestConfig>::new(&path);
+        assert_eq!(config.current(), &TestConfig::default());
+
+        let mut write = |content: &str| {
+            std::fs::write(&path, content).unwrap();
+            config.last_poll = None;
+            config.poll().cloned()
+        };
+        assert_eq!(write("rate = 30"), Some(TestConfig { rate: 30, name: "default".to_string() }));
+        assert_eq!(write("rate = 0"), None);
+        assert_eq!(write("rate = "), None);
+        assert_eq!(config.current().rate, 30);
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt::Debug,
+    sync::Arc,
+    time::Duration,
+};
+
+use ambient_network::auth::{AuthTicketProvider, Authenticator, Identity};
+use anyhow::Context;
+use parking_lot::Mutex;
+use steamworks::{AuthSessionValidateError, CallbackHandle, ClientManager, SteamId, ValidateAuthTicketResponse};
+
+use crate::SteamClient;
+
+/// How long Steam has to validate a ticket before the client is refused
+const VALIDATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Creates Steam session tickets for the connections to servers
+pub struct SteamTicketProvider {
+    steam: SteamClient,
+}
+impl SteamTicketProvider {
+    pub(crate) fn new(steam: SteamClient) -> Self {
+        Self { steam }
+    }
+}
+impl AuthTicketProvider for SteamTicketProvider {
+    fn ticket(&self) -> anyhow::Result<Vec<u8>> {
+        let user = self.steam.client.user();
+        // There's only one connection at a time, so the tickets of the previous ones aren't needed anymore
+        let mut tickets = self.steam.local_tickets.lock();
+        for (ticket, _) in tickets.drain(..) {
+            user.cancel_authentication_ticket(ticket);
+        }
+        let (ticket, data) = user.authentication_session_ticket();
+        tickets.push((ticket, data.clone()));
+        Ok(data)
+    }
+}
+impl Drop for SteamTicketProvider {
+    fn drop(&mut self) {
+        let user = self.steam.client.user();
+        for (ticket, _) in self.steam.local_tickets.lock().drain(..) {
+            user.cancel_authentication_ticket(ticket);
+        }
+    }
+}
+impl Debug for SteamTicketProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SteamTicketProvider").field("steam", &self.steam).finish_non_exhaustive()
+    }
+}
+
+type PendingValidations = HashMap<u64, flume::Sender<Result<(), AuthSessionValidateError>>>;
+
+/// Only accepts the clients with a valid Steam session ticket for the Steam id they connect as.
+///
+/// This uses the Steam client of the host, so it's meant for servers hosted by a player. Steam can't validate the tickets
+/// of the host itself, so the host's Steam id is only accepted from the client in the same process, which presents a
+/// ticket this process handed out.
+pub struct SteamAuthenticator {
+    steam: SteamClient,
+    pending: Arc<Mutex<PendingValidations>>,
+    _callback: CallbackHandle<ClientManager>,
+}
+impl SteamAuthenticator {
+    pub(crate) fn new(steam: SteamClient) -> Self {
+        let pending = Arc::new(Mutex::new(PendingValidations::new()));
+        let callback = {
+            let pending = pending.clone();
+            steam.client.register_callback(move |response: ValidateAuthTicketResponse| {
+                match pending.lock().remove(&response.steam_id.raw()) {
+                    Some(tx) => {
+                        tx.send(response.response).ok();
+                    }
+                    None => log::info!("Steam session of {:?} changed: {:?}", response.steam_id, response.response),
+                }
+            })
+        };
+        Self { steam, pending, _callback: callback }
+    }
+}
+#[async_trait::async_trait]
+impl Authenticator for SteamAuthenticator {
+    async fn authenticate(&self, claimed_user_id: &str, ticket: Option<&[u8]>) -> anyhow::Result<Identity> {
+        let steam_id = SteamId::from_raw(claimed_user_id.parse().context("The user id is not a Steam id")?);
+        let ticket = ticket.context("No Steam session ticket")?;
+        if steam_id == self.steam.steam_id() {
+            if self.steam.local_tickets.lock().iter().any(|(_, data)| data == ticket) {
+                return Ok(Identity::new(claimed_user_id));
+            }
+            anyhow::bail!("Only the host can connect with the host's Steam id");
+        }
+
+        let (tx, rx) = flume::bounded(1);
+        match self.pending.lock().entry(steam_id.raw()) {
+            // The validation responses only say whose session it was, so there can't be two at once for the same user
+            Entry::Occupied(_) => anyhow::bail!("The Steam session of {steam_id:?} is already being validated"),
+            Entry::Vacant(slot) => {
+                slot.insert(tx);
+            }
+        }
+        if let Err(err) = self.steam.client.user().begin_authentication_session(steam_id, ticket) {
+            self.pending.lock().remove(&steam_id.raw());
+            anyhow::bail!("Invalid Steam session ticket: {err:?}");
+        }
+        match tokio::time::timeout(VALIDATION_TIMEOUT, rx.recv_async()).await {
+            Ok(Ok(Ok(()))) => Ok(Identity::new(steam_id.raw().to_string())),
+            Ok(Ok(Err(err))) => {
+                self.steam.client.user().end_authentication_session(steam_id);
+                anyhow::bail!("Steam refused the session ticket: {err:?}")
+            }
+            _ => {
+                self.pending.lock().remove(&steam_id.raw());
+                self.steam.client.user().end_authentication_session(steam_id);
+                anyhow::bail!("Steam didn't validate the session ticket in time")
+            }
+        }
+    }
+    fn end_session(&self, user_id: &str) {
+        if let Ok(raw) = user_id.parse() {
+            let steam_id = SteamId::from_raw(raw);
+            if steam_id != self.steam.steam_id() {
+                self.steam.client.user().end_authentication_session(steam_id);
+            }
+        }
+    }
+}
+impl Debug for SteamAuthenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SteamAuthenticator").field("steam", &self.steam).finish_non_exhaustive()
+    }
+}
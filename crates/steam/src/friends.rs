@@ -0,0 +1,84 @@
+use steamworks::{CallbackHandle, ClientManager, FriendFlags, FriendState, GameRichPresenceJoinRequested, SteamId};
+
+use crate::SteamClient;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SteamFriend {
+    pub steam_id: SteamId,
+    pub name: String,
+    pub online: bool,
+    /// Whether they are playing this game right now
+    pub in_game: bool,
+}
+
+impl SteamClient {
+    pub fn friends(&self) -> Vec<SteamFriend> {
+        let app_id = self.client.utils().app_id();
+        self.client
+            .friends()
+            .get_friends(FriendFlags::IMMEDIATE)
+            .into_iter()
+            .map(|friend| SteamFriend {
+                steam_id: friend.id(),
+                name: friend.name(),
+                online: !matches!(friend.state(), FriendState::Offline),
+                in_game: friend.game_played().map(|game| game.game.app_id() == app_id).unwrap_or(false),
+            })
+            .collect()
+    }
+    /// Lets friends join the server at `host` (an address with a port) from their friends list, or stops letting them
+    pub fn set_joinable(&self, host: Option<&str>) {
+        let connect = host.map(connect_string);
+        if !self.client.friends().set_rich_presence("connect", connect.as_deref()) {
+            log::warn!("Failed to make {host:?} joinable on Steam");
+        }
+    }
+    /// Sends an invite to join the server at `host` to a friend
+    pub fn invite(&self, friend: SteamId, host: &str) -> bool {
+        self.client.friends().get_friend(friend).invite_user_to_game(&connect_string(host))
+    }
+    /// The servers the player asked to join from Steam, while the game was running
+    pub fn join_requests(&self) -> &flume::Receiver<String> {
+        &self.join_requests
+    }
+}
+
+pub(crate) fn register_join_requests(
+    client: &steamworks::Client<ClientManager>,
+    join_tx: flume::Sender<String>,
+) -> CallbackHandle<ClientManager> {
+    client.register_callback(move |request: GameRichPresenceJoinRequested| match parse_connect_string(&request.connect) {
+        Some(host) => {
+            log::info!("Joining {host} from Steam");
+            join_tx.send(host).ok();
+        }
+        None => log::warn!("Invalid Steam join request: {:?}", request.connect),
+    })
+}
+
+/// The connect strings are also passed on the command line when Steam launches the game to join a friend, so they use
+/// the same convention as the Source engine
+fn connect_string(host: &str) -> String {
+    format!("+connect {host}")
+}
+fn parse_connect_string(connect: &str) -> Option<String> {
+    let host = connect.trim().strip_prefix("+connect")?.trim();
+    if host.is_empty() {
+        None
+    } else {
+        Some(host.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{connect_string, parse_connect_string};
+
+    #[test]
+    fn connect_strings() {
+        assert_eq!(parse_connect_string(&connect_string("192.168.0.2:9000")).as_deref(), Some("192.168.0.2:9000"));
+        assert_eq!(parse_connect_string(" +connect  example.com:9000 ").as_deref(), Some("example.com:9000"));
+        assert_eq!(parse_connect_string("+connect"), None);
+        assert_eq!(parse_connect_string("example.com:9000"), None);
+    }
+}
@@ -0,0 +1,131 @@
+//! Optional Steamworks integration: signs players in with their Steam account, lets them join and invite their friends,
+//! and shows what they are doing in their friends list.
+//!
+//! The Steam client needs to be running, and the game needs a Steam app id.
+
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use ambient_ecs::{components, query, Debuggable, Description, Name, Networked, Resource, Store, SystemGroup};
+use ambient_network::player::{local_user_id, user_id};
+use ambient_std::asset_cache::SyncAssetKey;
+use parking_lot::Mutex;
+use steamworks::{AuthTicket, CallbackHandle, ClientManager, SteamId};
+
+mod auth;
+mod friends;
+
+pub use auth::{SteamAuthenticator, SteamTicketProvider};
+pub use friends::SteamFriend;
+
+components!("steam", {
+    @[Resource]
+    steam_client: SteamClient,
+    @[
+        Debuggable, Networked, Store,
+        Name["Steam rich presence"],
+        Description["Shown as the status of the player in their Steam friends list, when attached to their player entity."]
+    ]
+    steam_rich_presence: String,
+});
+
+/// How often the Steam callbacks are run
+const CALLBACK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A connection to the local Steam client
+#[derive(Clone)]
+pub struct SteamClient {
+    client: steamworks::Client<ClientManager>,
+    join_requests: flume::Receiver<String>,
+    /// The session tickets this process has handed out, with their data
+    local_tickets: Arc<Mutex<Vec<(AuthTicket, Vec<u8>)>>>,
+    _callbacks: Arc<Vec<CallbackHandle<ClientManager>>>,
+}
+impl SteamClient {
+    /// Connects to the Steam client, as the game `app_id`. The callbacks are run on a thread of their own, until the
+    /// process exits.
+    pub fn init(app_id: u32) -> anyhow::Result<Self> {
+        let (client_tx, client_rx) = flume::bounded(1);
+        std::thread::Builder::new().name("steam_callbacks".to_string()).spawn(move || {
+            let (client, single) = match steamworks::Client::init_app(app_id) {
+                Ok(client) => client,
+                Err(err) => {
+                    client_tx.send(Err(anyhow::anyhow!("Failed to initialize Steam: {err}"))).ok();
+                    return;
+                }
+            };
+            client_tx.send(Ok(client)).ok();
+            loop {
+                single.run_callbacks();
+                std::thread::sleep(CALLBACK_INTERVAL);
+            }
+        })?;
+        let client = client_rx.recv()??;
+
+        let (join_tx, join_requests) = flume::unbounded();
+        let callbacks = vec![friends::register_join_requests(&client, join_tx)];
+        log::info!("Signed in to Steam as {}", client.friends().name());
+        Ok(Self { client, join_requests, local_tickets: Default::default(), _callbacks: Arc::new(callbacks) })
+    }
+    pub fn steam_id(&self) -> SteamId {
+        self.client.user().steam_id()
+    }
+    /// The user id to connect to servers with; servers using a [SteamAuthenticator] only accept this one
+    pub fn user_id(&self) -> String {
+        self.steam_id().raw().to_string()
+    }
+    pub fn ticket_provider(&self) -> SteamTicketProvider {
+        SteamTicketProvider::new(self.clone())
+    }
+    pub fn authenticator(&self) -> SteamAuthenticator {
+        SteamAuthenticator::new(self.clone())
+    }
+    /// Shows `status` in the friends list, or clears it
+    pub fn set_rich_presence(&self, status: Option<&str>) {
+        if !self.client.friends().set_rich_presence("status", status) {
+            log::warn!("Failed to set the Steam rich presence to {status:?}");
+        }
+    }
+}
+impl Debug for SteamClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SteamClient").field("steam_id", &self.steam_id()).finish_non_exhaustive()
+    }
+}
+
+/// Set when the game was started with Steam
+#[derive(Debug)]
+pub struct SteamClientKey;
+impl SyncAssetKey<SteamClient> for SteamClientKey {}
+
+/// Updates the Steam rich presence of the local player from its [steam_rich_presence]; needs the [steam_client] resource
+pub fn client_systems() -> SystemGroup {
+    SystemGroup::new(
+        "steam",
+        vec![
+            query((user_id(), steam_rich_presence().changed())).to_system(|q, world, qs, _| {
+                let steam = match world.resource_opt(steam_client()) {
+                    Some(steam) => steam,
+                    None => return,
+                };
+                let local_user_id = world.resource(local_user_id());
+                for (_, (user_id, status)) in q.iter(world, qs) {
+                    if user_id == local_user_id {
+                        steam.set_rich_presence(Some(status));
+                    }
+                }
+            }),
+            query((user_id(), steam_rich_presence().removed())).to_system(|q, world, qs, _| {
+                let steam = match world.resource_opt(steam_client()) {
+                    Some(steam) => steam,
+                    None => return,
+                };
+                let local_user_id = world.resource(local_user_id());
+                for (_, (user_id, _)) in q.iter(world, qs) {
+                    if user_id == local_user_id {
+                        steam.set_rich_presence(None);
+                    }
+                }
+            }),
+        ],
+    )
+}
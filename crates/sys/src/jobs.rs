@@ -0,0 +1,308 @@
+//! A work-stealing job system, which spreads the CPU heavy work of the engine and of the native game systems over the
+//! threads of the machine.
+//!
+//! The jobs run on a pool of worker threads. Each worker has a queue of its own: it runs its newest jobs first, and steals
+//! the oldest ones of the others once it has none left. The jobs spawned in a [scope] may borrow from the stack, as the
+//! scope waits for all of them, and helps running them meanwhile; [Scope::spawn_after] chains them, so that a job only
+//! starts once the ones it depends on are done. The parallel loops, such as [par_for_each_mut], are built on top of it,
+//! and [run] lets async code wait for a job without blocking its executor.
+//!
+//! There's a worker per core but one, or as many as the `AMBIENT_JOB_WORKERS` environment variable says. Without threads
+//! (e.g. on the web), there are no workers, and the jobs run on the thread which waits for them.
+
+use std::{
+    any::Any,
+    cell::Cell,
+    collections::VecDeque,
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use once_cell::sync::OnceCell;
+use parking_lot::{Condvar, Mutex};
+
+/// The number of items each job of the parallel loops takes at least, as smaller jobs cost more to schedule than to run
+const MIN_CHUNK_LEN: usize = 32;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+thread_local! {
+    /// The index of the worker the current thread is, if it's one
+    static WORKER: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+struct Pool {
+    /// The queue of each worker
+    queues: Vec<Mutex<VecDeque<Task>>>,
+    /// The jobs queued by the threads which aren't workers
+    injector: Mutex<VecDeque<Task>>,
+    /// How many jobs are queued, for the workers to know when to sleep
+    queued: AtomicUsize,
+    sleep: Mutex<()>,
+    wake: Condvar,
+}
+impl Pool {
+    fn push(&self, task: Task) {
+        match WORKER.with(Cell::get) {
+            Some(index) => self.queues[index].lock().push_back(task),
+            None => self.injector.lock().push_back(task),
+        }
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        // Taking the lock makes sure that a worker which found no job is either still looking, or already waiting
+        drop(self.sleep.lock());
+        self.wake.notify_one();
+    }
+
+    /// Takes the next job for the worker `index`, or for a thread which isn't a worker
+    fn find(&self, index: Option<usize>) -> Option<Task> {
+        // Its own jobs first, the newest first as their data is the most likely to still be in the cache
+        let task =
+            index.and_then(|index| self.queues[index].lock().pop_back()).or_else(|| self.injector.lock().pop_front()).or_else(|| {
+                // Then the oldest job of another worker, starting from the next one so that they don't all steal from the same
+                let start = index.map(|index| index + 1).unwrap_or_default();
+                (0..self.queues.len())
+                    .map(|offset| (start + offset) % self.queues.len())
+                    .filter(|&other| Some(other) != index)
+                    .find_map(|other| self.queues[other].lock().pop_front())
+            })?;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        Some(task)
+    }
+
+    fn work(&self, index: usize) {
+        WORKER.with(|worker| worker.set(Some(index)));
+        loop {
+            match self.find(Some(index)) {
+                Some(task) => task(),
+                None => {
+                    let mut guard = self.sleep.lock();
+                    if self.queued.load(Ordering::SeqCst) == 0 {
+                        self.wake.wait(&mut guard);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn pool() -> &'static Pool {
+    static POOL: OnceCell<Pool> = OnceCell::new();
+    POOL.get_or_init(|| {
+        // The thread waiting for the jobs runs them too
+        let workers = std::env::var("AMBIENT_JOB_WORKERS")
+            .ok()
+            .and_then(|workers| workers.parse().ok())
+            .unwrap_or_else(|| std::thread::available_parallelism().map(|count| count.get()).unwrap_or(1).saturating_sub(1));
+        for index in 0..workers {
+            std::thread::Builder::new()
+                .name(format!("ambient-job-{index}"))
+                .spawn(move || pool().work(index))
+                .expect("Failed to spawn a job worker");
+        }
+        Pool {
+            queues: (0..workers).map(|_| Mutex::new(VecDeque::new())).collect(),
+            injector: Mutex::new(VecDeque::new()),
+            queued: AtomicUsize::new(0),
+            sleep: Mutex::new(()),
+            wake: Condvar::new(),
+        }
+    })
+}
+
+/// The number of threads the jobs run on: the workers, and the thread which waits for them
+pub fn thread_count() -> usize {
+    pool().queues.len() + 1
+}
+
+/// Runs `f`, which may spawn jobs borrowing from the stack in the scope it's given, and waits for all of them to be done.
+/// The thread runs the queued jobs while it waits.
+///
+/// Panics if `f` or one of the jobs panicked, once they're all done.
+pub fn scope<'scope, R>(f: impl FnOnce(&Scope<'scope>) -> R) -> R {
+    let scope = Scope { pending: AtomicUsize::new(0), panic: Mutex::new(None), _marker: PhantomData };
+    let res = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+    scope.wait();
+    if let Some(panic) = scope.panic.lock().take() {
+        panic::resume_unwind(panic);
+    }
+    res.unwrap_or_else(|panic| panic::resume_unwind(panic))
+}
+
+/// The jobs spawned by a call to [scope]
+pub struct Scope<'scope> {
+    /// The jobs spawned in the scope which aren't done yet
+    pending: AtomicUsize,
+    /// The first panic of the jobs, which is resumed once they're all done
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+    _marker: PhantomData<fn(&'scope ()) -> &'scope ()>,
+}
+impl<'scope> Scope<'scope> {
+    /// Spawns the job `f`, which may borrow anything which outlives the scope
+    pub fn spawn(&self, f: impl FnOnce(&Scope<'scope>) + Send + 'scope) -> Job {
+        self.spawn_after(&[], f)
+    }
+
+    /// Spawns the job `f`, which starts once all the jobs of `dependencies` are done
+    pub fn spawn_after(&self, dependencies: &[Job], f: impl FnOnce(&Scope<'scope>) + Send + 'scope) -> Job {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        let job = Job(Arc::new(Mutex::new(Some(Vec::new()))));
+        let scope = ScopePtr(self);
+        let task: Box<dyn FnOnce() + Send + 'scope> = Box::new({
+            let job = job.clone();
+            move || {
+                let scope = scope.get();
+                if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| f(scope))) {
+                    scope.panic.lock().get_or_insert(panic);
+                }
+                job.complete();
+                // The scope may be gone as soon as this is done
+                scope.pending.fetch_sub(1, Ordering::SeqCst);
+            }
+        });
+        // SAFETY: the scope waits for all of its jobs to be done before it returns, so what they borrow outlives them
+        let task: Task = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Task>(task) };
+
+        if dependencies.is_empty() {
+            pool().push(task);
+            return job;
+        }
+        // The last of the dependencies to be done queues the job
+        let remaining = Arc::new((AtomicUsize::new(dependencies.len()), Mutex::new(Some(task))));
+        for dependency in dependencies {
+            let remaining = remaining.clone();
+            dependency.then(Box::new(move || {
+                if remaining.0.fetch_sub(1, Ordering::SeqCst) == 1 {
+                    if let Some(task) = remaining.1.lock().take() {
+                        pool().push(task);
+                    }
+                }
+            }));
+        }
+        job
+    }
+
+    fn wait(&self) {
+        let pool = pool();
+        let index = WORKER.with(Cell::get);
+        while self.pending.load(Ordering::SeqCst) != 0 {
+            match pool.find(index) {
+                Some(task) => task(),
+                // The jobs which are left are running on the other threads
+                None => std::thread::yield_now(),
+            }
+        }
+    }
+}
+
+/// A pointer to a scope, for its jobs to reach it
+struct ScopePtr<'scope>(*const Scope<'scope>);
+// SAFETY: the scope is Sync, and outlives its jobs
+unsafe impl Send for ScopePtr<'_> {}
+impl<'scope> ScopePtr<'scope> {
+    fn get(&self) -> &Scope<'scope> {
+        // SAFETY: the scope outlives its jobs
+        unsafe { &*self.0 }
+    }
+}
+
+/// A job spawned in a [Scope], which other jobs can be spawned after with [Scope::spawn_after]
+#[derive(Clone)]
+pub struct Job(Arc<Mutex<Option<Vec<Task>>>>);
+impl Job {
+    pub fn is_done(&self) -> bool {
+        self.0.lock().is_none()
+    }
+
+    /// Runs `task` once the job is done
+    fn then(&self, task: Task) {
+        let mut dependents = self.0.lock();
+        if let Some(dependents) = dependents.as_mut() {
+            dependents.push(task);
+            return;
+        }
+        drop(dependents);
+        task();
+    }
+
+    fn complete(&self) {
+        let dependents = self.0.lock().take();
+        for task in dependents.into_iter().flatten() {
+            task();
+        }
+    }
+}
+impl std::fmt::Debug for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Job").field("done", &self.is_done()).finish()
+    }
+}
+
+/// How many items each job of a parallel loop over `len` items takes
+fn chunk_len(len: usize) -> usize {
+    // A few jobs per thread, so that the threads which are done first take the rest
+    (len / (thread_count() * 4)).max(MIN_CHUNK_LEN)
+}
+
+/// Calls `f` with the consecutive chunks of `items` in parallel, and returns what it returned for each, in order
+pub fn par_chunks<T: Sync, U: Send>(items: &[T], f: impl Fn(&[T]) -> U + Sync) -> Vec<U> {
+    let chunk_len = chunk_len(items.len());
+    if items.len() <= chunk_len {
+        return vec![f(items)];
+    }
+    let mut results = items.chunks(chunk_len).map(|_| None).collect::<Vec<_>>();
+    scope(|scope| {
+        let f = &f;
+        for (chunk, result) in items.chunks(chunk_len).zip(results.iter_mut()) {
+            scope.spawn(move |_| *result = Some(f(chunk)));
+        }
+    });
+    results.into_iter().map(|result| result.unwrap()).collect()
+}
+
+/// Calls `f` with each of `items` in parallel
+pub fn par_for_each<T: Sync>(items: &[T], f: impl Fn(&T) + Sync) {
+    par_chunks(items, |chunk| chunk.iter().for_each(&f));
+}
+
+/// Calls `f` with each of `items` in parallel, which it may modify
+pub fn par_for_each_mut<T: Send>(items: &mut [T], f: impl Fn(&mut T) + Sync) {
+    let chunk_len = chunk_len(items.len());
+    if items.len() <= chunk_len {
+        items.iter_mut().for_each(f);
+        return;
+    }
+    scope(|scope| {
+        let f = &f;
+        for chunk in items.chunks_mut(chunk_len) {
+            scope.spawn(move |_| chunk.iter_mut().for_each(f));
+        }
+    });
+}
+
+/// Maps each of `items` with `f` in parallel, keeping their order
+pub fn par_map<T: Sync, U: Send>(items: &[T], f: impl Fn(&T) -> U + Sync) -> Vec<U> {
+    par_chunks(items, |chunk| chunk.iter().map(&f).collect::<Vec<_>>()).into_iter().flatten().collect()
+}
+
+/// Runs `f` as a job, for async code to wait for a heavy computation without blocking its executor. Without workers, `f`
+/// runs right away.
+///
+/// Panics if `f` panicked.
+pub async fn run<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let pool = pool();
+    if pool.queues.is_empty() {
+        return f();
+    }
+    let (tx, rx) = flume::bounded(1);
+    pool.push(Box::new(move || {
+        tx.send(panic::catch_unwind(AssertUnwindSafe(f))).ok();
+    }));
+    match rx.recv_async().await.expect("The jobs are never dropped") {
+        Ok(value) => value,
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
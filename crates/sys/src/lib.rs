@@ -1,4 +1,5 @@
 pub mod control;
+pub mod jobs;
 pub mod task;
 pub mod time;
 pub mod timer;
@@ -0,0 +1,68 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use ambient_sys::jobs::{self, par_for_each_mut, par_map, scope};
+use parking_lot::Mutex;
+
+#[test]
+fn parallel_loops() {
+    let mut values = (0..10_000u64).collect::<Vec<_>>();
+    par_for_each_mut(&mut values, |value| *value *= 2);
+    assert!(values.iter().enumerate().all(|(index, &value)| value == index as u64 * 2));
+
+    let squares = par_map(&values, |value| value * value);
+    assert_eq!(squares.len(), values.len());
+    assert!(squares.iter().zip(&values).all(|(square, value)| *square == value * value));
+
+    let sum = AtomicUsize::new(0);
+    jobs::par_for_each(&values, |&value| {
+        sum.fetch_add(value as usize, Ordering::Relaxed);
+    });
+    assert_eq!(sum.into_inner() as u64, values.iter().sum::<u64>());
+}
+
+#[test]
+fn dependency_chains() {
+    let order = Mutex::new(Vec::new());
+    scope(|scope| {
+        let first = scope.spawn(|_| order.lock().push(1));
+        let second = scope.spawn_after(std::slice::from_ref(&first), |_| order.lock().push(2));
+        let third = scope.spawn_after(&[first, second.clone()], |scope| {
+            order.lock().push(3);
+            // Jobs may spawn more jobs in the scope
+            scope.spawn(|_| order.lock().push(4));
+        });
+        scope.spawn_after(&[second, third], |_| order.lock().push(5));
+    });
+    let order = order.into_inner();
+    assert_eq!(&order[..3], &[1, 2, 3]);
+    assert_eq!(order.len(), 5);
+    assert!(order[3..].contains(&4) && order[3..].contains(&5));
+}
+
+#[test]
+fn panics_are_resumed() {
+    let done = Arc::new(AtomicUsize::new(0));
+    let res = std::panic::catch_unwind(|| {
+        scope(|scope| {
+            scope.spawn(|_| panic!("Job failed"));
+            for _ in 0..10 {
+                let done = done.clone();
+                scope.spawn(move |_| {
+                    done.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        })
+    });
+    assert!(res.is_err());
+    // The scope still waits for the other jobs
+    assert_eq!(done.load(Ordering::SeqCst), 10);
+}
+
+#[test]
+fn async_jobs() {
+    let value = futures::executor::block_on(jobs::run(|| (0..1000u64).sum::<u64>()));
+    assert_eq!(value, 499_500);
+}
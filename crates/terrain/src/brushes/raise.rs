@@ -139,6 +139,7 @@ impl RaiseBrush {
                 ))
                 .unwrap(),
                 format: wgpu::TextureFormat::Rgba8Unorm,
+                color_space: None,
             }
             .get(&assets)
             .await
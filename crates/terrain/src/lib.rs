@@ -667,6 +667,7 @@ impl ElementComponent for Terrain {
                 ))
                 .unwrap(),
                 format: wgpu::TextureFormat::Rgba8Unorm,
+                color_space: None,
             },
         )
         .and_then(|x| x.ok())
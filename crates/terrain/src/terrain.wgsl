@@ -173,9 +173,11 @@ fn triplanar_sample(p: vec3<f32>, normal: vec3<f32>, terrain_sample: TerrainTrip
     let z_tc = maybe_rot_45deg(p.xy / settings.top_scale, bool(settings.rot_side_45_deg));
     let x_tc = maybe_rot_45deg(vec2<f32>(p.y, -p.z) / settings.side_scale, bool(settings.rot_side_45_deg));
     let y_tc = maybe_rot_45deg(-p.xz / settings.side_scale, bool(settings.rot_side_45_deg));
-    let z_color = textureSample(surface_color_2k, texture_sampler, z_tc, terrain_sample.top_texture).rgb * settings.top_color;
-    let x_color = textureSample(surface_color_2k, texture_sampler, x_tc, terrain_sample.side_texture).rgb * settings.side_color;
-    let y_color = textureSample(surface_color_2k, texture_sampler, y_tc, terrain_sample.side_texture).rgb * settings.side_color;
+    // The ground textures follow the texture quality settings, as they're the ones seen at the most grazing angles
+    let bias = global_params.texture_mip_bias;
+    let z_color = textureSampleBias(surface_color_2k, default_material_sampler, z_tc, terrain_sample.top_texture, bias).rgb * settings.top_color;
+    let x_color = textureSampleBias(surface_color_2k, default_material_sampler, x_tc, terrain_sample.side_texture, bias).rgb * settings.side_color;
+    let y_color = textureSampleBias(surface_color_2k, default_material_sampler, y_tc, terrain_sample.side_texture, bias).rgb * settings.side_color;
 
     // let z_normal = textureSample(surface_normals_2k, texture_sampler, z_tc, top_layer).rgb;
     // let x_normal = textureSample(surface_normals_2k, texture_sampler, x_tc, side_layer).rgb;
@@ -275,7 +277,7 @@ fn fs_forward_main(in: VertexOutput) -> MainFsOut {
     let x = mat3_from_quat(quat_from_mat3(normal_mat)) * vec3<f32>(0., 0., 1.);
 
     return MainFsOut(
-        shading(material, in.world_position),
+        shading(material, in.world_position, !has_entity_no_receive_shadows(primitives.data[in.instance_index].xy)),
         quat_from_mat3(normal_mat)
     );
 }
@@ -6,7 +6,7 @@ use std::{
     },
 };
 
-use ambient_core::{runtime, window::WindowCtl, window_ctl};
+use ambient_core::runtime;
 use ambient_ecs::World;
 use ambient_element::{element_component, Element, ElementComponent, ElementComponentExt, Hooks};
 use ambient_input::{on_app_focus_change, on_app_keyboard_input, on_app_mouse_input, KeyboardEvent};
@@ -221,14 +221,9 @@ pub fn Button(
 
     let content = style
         .create_container(is_pressed, is_working, disabled, toggled, hover, hotkey, hotkey_modifier, tooltip, content)
-        .with_clickarea()
-        .on_mouse_enter(
-            closure!(clone set_hover, |world, _| { set_hover(true); world.resource(window_ctl()).send(WindowCtl::SetCursorIcon(CursorIcon::Hand)).ok(); }),
-        )
-        .on_mouse_leave(move |world, _| {
-            set_hover(false);
-            world.resource(window_ctl()).send(WindowCtl::SetCursorIcon(CursorIcon::Default)).ok();
-        })
+        .with_cursor(CursorIcon::Hand)
+        .on_mouse_enter(closure!(clone set_hover, |_, _| set_hover(true)))
+        .on_mouse_leave(move |_, _| set_hover(false))
         .listener(
             on_app_mouse_input(),
             Arc::new(closure!(clone set_is_pressed, clone on_invoked, clone set_is_working, |world, _, event| {
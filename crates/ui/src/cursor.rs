@@ -0,0 +1,205 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ambient_core::{
+    asset_cache,
+    async_ecs::async_run,
+    mouse_position, runtime,
+    transform::translation,
+    window::{CursorImage, WindowCtl},
+    window_ctl, window_custom_cursors, window_scale_factor,
+};
+use ambient_ecs::{components, Debuggable, Description, FnSystem, Name, Resource, SystemGroup, World};
+use ambient_element::{Element, ElementComponentExt, ShareableElementTree};
+use ambient_gpu::texture_loaders::Rgba8ImageFromUrl;
+use ambient_input::picking::picker_intersecting;
+use ambient_std::{asset_cache::AsyncAssetKeyExt, asset_url::AbsAssetUrl};
+use glam::{vec3, UVec2, Vec2};
+use image::{imageops::FilterType, RgbaImage};
+use serde::{Deserialize, Serialize};
+use winit::window::CursorIcon;
+
+use crate::{height, width, ImageFromUrl};
+
+components!("ui", {
+    @[
+        Debuggable,
+        Name["Cursor hint"],
+        Description["The cursor shown while the mouse is over this UI element, e.g. `Hand` for buttons, `Text` for text inputs or `EwResize` for splitters."]
+    ]
+    cursor_hint: CursorIcon,
+    @[
+        Resource, Debuggable,
+        Name["Cursor theme"],
+        Description["Custom cursors which are shown instead of the system cursors for the cursor hints."]
+    ]
+    cursor_theme: CursorTheme,
+    @[
+        Resource, Debuggable,
+        Name["Custom cursor"],
+        Description["If set, this cursor is shown instead of the system cursors, whatever the mouse is over."]
+    ]
+    custom_cursor: Option<CustomCursor>,
+});
+
+/// A cursor drawn from an image asset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomCursor {
+    pub url: String,
+    /// The size the image is drawn at, in logical pixels
+    pub size: Vec2,
+    /// The point of the image which is at the position of the mouse, in logical pixels from its top left corner
+    pub hotspot: Vec2,
+}
+
+/// The custom cursors to show for each [cursor_hint]; the system cursor is shown for the hints which aren't in the theme
+#[derive(Debug, Clone, Default)]
+pub struct CursorTheme(pub HashMap<CursorIcon, CustomCursor>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Cursor {
+    System(CursorIcon),
+    Custom(CustomCursor),
+}
+
+fn current_cursor(world: &World) -> Cursor {
+    if let Some(Some(cursor)) = world.resource_opt(custom_cursor()) {
+        return Cursor::Custom(cursor.clone());
+    }
+    let hint = world
+        .resource_opt(picker_intersecting())
+        .and_then(|intersecting| *intersecting)
+        .and_then(|intersecting| world.get(intersecting.entity, cursor_hint()).ok())
+        .unwrap_or(CursorIcon::Default);
+    match world.resource_opt(cursor_theme()).and_then(|theme| theme.0.get(&hint)) {
+        Some(cursor) => Cursor::Custom(cursor.clone()),
+        None => Cursor::System(hint),
+    }
+}
+
+/// How a [Cursor] is shown
+#[derive(Debug, Clone, PartialEq)]
+enum Shown {
+    System(CursorIcon),
+    /// As the hardware cursor, at the scale factor of the window
+    Hardware(CustomCursor, f64),
+    /// Drawn on top of the UI, with the system cursor hidden
+    Software(CustomCursor),
+}
+
+/// The pixels of `image` at the size of `cursor`, at `scale_factor`
+fn cursor_image(image: &RgbaImage, cursor: &CustomCursor, scale_factor: f64) -> CursorImage {
+    let size = (cursor.size.as_dvec2() * scale_factor).round().as_uvec2().max(UVec2::ONE);
+    let rgba = image::imageops::resize(image, size.x, size.y, FilterType::Triangle).into_raw();
+    CursorImage { rgba, size, hotspot: (cursor.hotspot.as_dvec2() * scale_factor).round().as_uvec2() }
+}
+
+/// Loads the image of `cursor`, and shows it as the hardware cursor unless the cursor changed meanwhile
+fn show_hardware_cursor(world: &World, cursor: CustomCursor, scale_factor: f64) {
+    let assets = world.resource(asset_cache()).clone();
+    let async_run = world.resource(async_run()).clone();
+    world.resource(runtime()).spawn(async move {
+        let url = match AbsAssetUrl::parse(&cursor.url) {
+            Ok(url) => url,
+            Err(err) => {
+                log::warn!("Invalid cursor url {}: {err:?}", cursor.url);
+                return;
+            }
+        };
+        let image = match (Rgba8ImageFromUrl { url }).get(&assets).await {
+            Ok(image) => image,
+            Err(err) => {
+                log::warn!("Failed to load the cursor {}: {err:?}", cursor.url);
+                return;
+            }
+        };
+        let image = Arc::new(cursor_image(&image, &cursor, scale_factor));
+        async_run.run(move |world| {
+            if current_cursor(world) == Cursor::Custom(cursor) {
+                if let Some(ctl) = world.resource_opt(window_ctl()) {
+                    ctl.send(WindowCtl::SetCustomCursor(Some(image))).ok();
+                }
+            }
+        });
+    });
+}
+
+/// Shows the cursor hinted by the UI element the mouse is over, or the custom cursor.
+///
+/// The custom cursors are shown as the hardware cursor if the window can (see `window_custom_cursors`), so that they
+/// follow the mouse without lag. Otherwise they're drawn on top of the UI at the position of the mouse, with the system
+/// cursor hidden.
+pub fn systems() -> SystemGroup {
+    let mut shown: Option<Shown> = None;
+    let mut overlay: Option<ShareableElementTree> = None;
+    SystemGroup::new(
+        "ui/cursor",
+        vec![Box::new(FnSystem::new(move |world, _| {
+            let scale_factor = *world.resource(window_scale_factor());
+            let cursor = match current_cursor(world) {
+                Cursor::System(icon) => Shown::System(icon),
+                Cursor::Custom(custom) if world.resource_opt(window_custom_cursors()).copied().unwrap_or_default() => {
+                    Shown::Hardware(custom, scale_factor)
+                }
+                Cursor::Custom(custom) => Shown::Software(custom),
+            };
+            if shown.as_ref() != Some(&cursor) {
+                let ctl = match world.resource_opt(window_ctl()) {
+                    Some(ctl) => ctl.clone(),
+                    None => return,
+                };
+                if matches!(shown, Some(Shown::Hardware(..))) && !matches!(cursor, Shown::Hardware(..)) {
+                    ctl.send(WindowCtl::SetCustomCursor(None)).ok();
+                }
+                let software = matches!(cursor, Shown::Software(_));
+                if matches!(shown, Some(Shown::Software(_))) != software {
+                    ctl.send(WindowCtl::ShowCursor(!software)).ok();
+                }
+                let overlay_el = match &cursor {
+                    Shown::System(icon) => {
+                        ctl.send(WindowCtl::SetCursorIcon(*icon)).ok();
+                        Element::new()
+                    }
+                    Shown::Hardware(custom, scale_factor) => {
+                        show_hardware_cursor(world, custom.clone(), *scale_factor);
+                        Element::new()
+                    }
+                    Shown::Software(custom) => {
+                        ImageFromUrl { url: custom.url.clone() }.el().set(width(), custom.size.x).set(height(), custom.size.y)
+                    }
+                };
+                match (&overlay, &cursor) {
+                    (Some(overlay), _) => overlay.0.lock().migrate_root(world, overlay_el),
+                    (None, Shown::Software(_)) => overlay = Some(ShareableElementTree::new(world, overlay_el)),
+                    (None, _) => {}
+                }
+                shown = Some(cursor);
+            }
+
+            if let (Some(overlay), Some(Shown::Software(custom))) = (&overlay, &shown) {
+                let mut tree = overlay.0.lock();
+                tree.update(world);
+                if let Some(root) = tree.root_entity() {
+                    let position = *world.resource(mouse_position()) / scale_factor as f32 - custom.hotspot;
+                    // In front of the rest of the UI
+                    world.set(root, translation(), vec3(position.x, position.y, -0.99)).ok();
+                }
+            }
+        }))],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_images_are_scaled_to_physical_pixels() {
+        let image = RgbaImage::from_pixel(64, 64, image::Rgba([255, 0, 0, 255]));
+        let cursor = CustomCursor { url: String::new(), size: Vec2::new(16., 24.), hotspot: Vec2::new(2., 3.5) };
+        let scaled = cursor_image(&image, &cursor, 1.5);
+        assert_eq!(scaled.size, UVec2::new(24, 36));
+        assert_eq!(scaled.hotspot, UVec2::new(3, 5));
+        assert_eq!(scaled.rgba.len(), 24 * 36 * 4);
+        assert_eq!(scaled.rgba[..4], [255, 0, 0, 255]);
+    }
+}
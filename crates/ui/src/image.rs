@@ -43,9 +43,12 @@ impl ElementComponent for Image {
                         base_color: texture,
                         normalmap: DefaultNormalMapViewKey.get(&assets),
                         metallic_roughness: PixelTextureViewKey::white().get(&assets),
+                        emissive: None,
                         transparent: None,
                         double_sided: None,
                         depth_write_enabled: None,
+                        sampling: None,
+                        detail: None,
                     },
                 ))
             })
@@ -109,7 +112,7 @@ impl ElementComponent for ImageFromUrl {
             .use_async(|w| {
                 let assets = w.resource(asset_cache()).clone();
                 async move {
-                    TextureFromUrl { url: AbsAssetUrl::parse(url)?, format: wgpu::TextureFormat::Rgba8UnormSrgb }
+                    TextureFromUrl { url: AbsAssetUrl::parse(url)?, format: wgpu::TextureFormat::Rgba8UnormSrgb, color_space: None }
                         .get(&assets)
                         .await
                         .map(|x| Arc::new(x.create_view(&Default::default())))
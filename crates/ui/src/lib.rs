@@ -28,12 +28,13 @@ use itertools::Itertools;
 use parking_lot::Mutex;
 use winit::{
     event::{ElementState, ModifiersState, MouseButton, MouseScrollDelta, WindowEvent},
-    window::CursorGrabMode,
+    window::{CursorGrabMode, CursorIcon},
 };
 
 mod asset_url;
 mod button;
 mod collections;
+mod cursor;
 mod dropdown;
 mod editor;
 pub mod graph;
@@ -52,10 +53,12 @@ mod text;
 mod text_input;
 mod text_material;
 mod throbber;
+mod ui_state;
 
 pub use asset_url::*;
 pub use button::*;
 pub use collections::*;
+pub use cursor::*;
 pub use dropdown::*;
 pub use editor::*;
 pub use hooks::*;
@@ -72,10 +75,12 @@ pub use tabs::*;
 pub use text::*;
 pub use text_input::*;
 pub use throbber::*;
+pub use ui_state::*;
 
 pub use self::image::*;
 
 pub fn init_all_componets() {
+    cursor::init_components();
     layout::init_components();
     layout::init_gpu_components();
     rect::init_components();
@@ -86,7 +91,14 @@ pub fn init_all_componets() {
 pub fn systems() -> SystemGroup {
     SystemGroup::new(
         "ui",
-        vec![Box::new(rect::systems()), Box::new(text::systems()), Box::new(layout::layout_systems()), Box::new(screens::systems())],
+        vec![
+            Box::new(rect::systems()),
+            Box::new(text::systems()),
+            Box::new(layout::layout_systems()),
+            Box::new(screens::systems()),
+            Box::new(cursor::systems()),
+            ui_state::save_system(),
+        ],
     )
 }
 
@@ -236,7 +248,7 @@ impl ElementComponent for Centered {
 pub struct ScrollArea(pub Element);
 impl ElementComponent for ScrollArea {
     fn render(self: Box<Self>, hooks: &mut Hooks) -> Element {
-        let (scroll, set_scroll) = hooks.use_state(0.);
+        let (scroll, set_scroll) = use_ui_state(hooks, "scroll", 0.);
         UIBase
             .el()
             .init_default(children())
@@ -378,6 +390,8 @@ pub trait UIExt {
     fn on_mouse_down<F: Fn(&mut World, EntityId, MouseButton) + Sync + Send + 'static>(self, handle: F) -> Self;
     fn on_mouse_up<F: Fn(&mut World, EntityId, MouseButton) + Sync + Send + 'static>(self, handle: F) -> Self;
     fn with_clickarea(self) -> Self;
+    /// Shows the `cursor` while the mouse is over this element; see [cursor_hint]
+    fn with_cursor(self, cursor: CursorIcon) -> Self;
     fn with_background(self, color: Color) -> Self;
     fn on_size_change(self, current: Vec2, on_change: Arc<dyn Fn(Vec2) + Sync + Send + 'static>) -> Self;
 }
@@ -414,6 +428,9 @@ impl UIExt for Element {
     fn with_clickarea(self) -> Self {
         self.init(mouse_pickable(), AABB::ZERO)
     }
+    fn with_cursor(self, cursor: CursorIcon) -> Self {
+        self.with_clickarea().set(cursor_hint(), cursor)
+    }
     fn with_background(self, background: Color) -> Self {
         with_rect(self).set(background_color(), background)
     }
@@ -0,0 +1,218 @@
+//! UI state which is kept across sessions, such as scroll positions, collapsed sections and open panels.
+//!
+//! The state of an element is keyed by the ids of the [UiStateScope]s it's in, so that it's found again when the same
+//! UI is shown in a later session. The elements which aren't in a scope keep their state for the session only.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use ambient_core::asset_cache;
+use ambient_ecs::{ComponentValue, DynSystem, FnSystem};
+use ambient_element::{element_component, Element, ElementComponentExt, Hooks, Setter};
+use ambient_std::{
+    asset_cache::{AssetCache, SyncAssetKey, SyncAssetKeyExt},
+    cb,
+};
+use ambient_sys::time::Instant;
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// How often the [UiState] is saved to its file, if it changed
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The file the [UiState] is loaded from and saved to, if any
+#[derive(Clone, Debug)]
+pub struct UiStatePath;
+impl SyncAssetKey<PathBuf> for UiStatePath {}
+
+#[derive(Clone, Debug)]
+pub struct UiStateKey;
+impl SyncAssetKey<Arc<UiState>> for UiStateKey {
+    fn load(&self, assets: AssetCache) -> Arc<UiState> {
+        Arc::new(match UiStatePath.try_get(&assets) {
+            Some(path) => UiState::load(path),
+            None => UiState::default(),
+        })
+    }
+}
+
+/// The persisted state of the UI elements, by key
+#[derive(Debug, Default)]
+pub struct UiState {
+    path: Option<PathBuf>,
+    values: Mutex<HashMap<String, serde_json::Value>>,
+    dirty: AtomicBool,
+}
+impl UiState {
+    /// Loads the state saved to `path`, which [UiState::save] saves it back to. Starts empty if the file doesn't exist.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let values = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
+                log::warn!("Failed to parse the UI state at {path:?}: {err}");
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Self { path: Some(path), values: Mutex::new(values), dirty: AtomicBool::new(false) }
+    }
+    /// The value saved under `key`, if there's one and it has the type `T`
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.values.lock().get(key)?.clone();
+        serde_json::from_value(value).ok()
+    }
+    pub fn set<T: Serialize>(&self, key: &str, value: &T) {
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                self.values.lock().insert(key.to_string(), value);
+                self.dirty.store(true, Ordering::Relaxed);
+            }
+            Err(err) => log::warn!("Failed to serialize the UI state {key:?}: {err}"),
+        }
+    }
+    pub fn remove(&self, key: &str) {
+        if self.values.lock().remove(key).is_some() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+    /// Writes the state to its file, if it has one and the state changed since it was last saved
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let content = serde_json::to_string_pretty(&*self.values.lock())?;
+        let res = path.parent().map_or(Ok(()), std::fs::create_dir_all).and_then(|_| std::fs::write(path, content));
+        if res.is_err() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        Ok(res?)
+    }
+}
+
+/// Saves the [UiState] every [SAVE_INTERVAL]
+pub(crate) fn save_system() -> DynSystem {
+    let mut last_save = Instant::now();
+    Box::new(FnSystem::new(move |world, _| {
+        if last_save.elapsed() < SAVE_INTERVAL {
+            return;
+        }
+        last_save = Instant::now();
+        if let Err(err) = UiStateKey.get(world.resource(asset_cache())).save() {
+            log::warn!("Failed to save the UI state: {err:?}");
+        }
+    }))
+}
+
+/// The ids of the [UiStateScope]s an element is in, joined with `/`
+#[derive(Debug, Clone)]
+struct UiStateScopePath(String);
+
+/// Persists the state of the elements in `content` across sessions, under `id`.
+///
+/// Scopes can be nested, in which case the id is relative to the parent scope. The elements using [use_ui_state] with
+/// the same id (such as two [crate::ScrollArea]s) should be given scopes of their own, so that they don't share it.
+#[element_component]
+pub fn UiStateScope(hooks: &mut Hooks, id: String, content: Element) -> Element {
+    let path = match hooks.consume_context::<UiStateScopePath>() {
+        Some((parent, _)) => format!("{}/{id}", parent.0),
+        None => id,
+    };
+    UiStateScopeProvider { path: path.clone(), content }.el().key(path)
+}
+impl UiStateScope {
+    pub fn el(id: impl Into<String>, content: Element) -> Element {
+        Self { id: id.into(), content }.el()
+    }
+}
+
+#[element_component]
+fn UiStateScopeProvider(hooks: &mut Hooks, path: String, content: Element) -> Element {
+    hooks.provide_context(|| UiStateScopePath(path));
+    content
+}
+
+/// Like [Hooks::use_state], except that if the element is in a [UiStateScope], the state is saved under `id` in it,
+/// and restored from there in the later sessions
+pub fn use_ui_state<T>(hooks: &mut Hooks, id: &str, default: T) -> (T, Setter<T>)
+where
+    T: Clone + Debug + ComponentValue + Serialize + DeserializeOwned,
+{
+    let key = hooks.consume_context::<UiStateScopePath>().map(|(scope, _)| format!("{}/{id}", scope.0));
+    let state = UiStateKey.get(hooks.world.resource(asset_cache()));
+    let (value, set_value) = hooks.use_state_with({
+        let key = key.clone();
+        let state = state.clone();
+        move |_| key.and_then(|key| state.get(&key)).unwrap_or(default)
+    });
+    let key = match key {
+        Some(key) => key,
+        None => return (value, set_value),
+    };
+    let set_value: Setter<T> = cb(move |value: T| {
+        state.set(&key, &value);
+        set_value(value);
+    });
+    (value, set_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ui_state_is_saved_and_loaded() {
+        let path = std::env::temp_dir().join(format!("ambient_ui_state_{}/ui_state.json", std::process::id()));
+        let state = UiState::load(&path);
+        assert_eq!(state.get::<f32>("editor/scroll"), None);
+
+        state.set("editor/scroll", &12.5f32);
+        state.set("editor/open", &vec!["entities".to_string()]);
+        state.set("editor/removed", &true);
+        state.remove("editor/removed");
+        state.save().unwrap();
+
+        let loaded = UiState::load(&path);
+        assert_eq!(loaded.get::<f32>("editor/scroll"), Some(12.5));
+        assert_eq!(loaded.get::<Vec<String>>("editor/open"), Some(vec!["entities".to_string()]));
+        assert_eq!(loaded.get::<bool>("editor/removed"), None);
+        // A value of another type is ignored rather than misread
+        assert_eq!(loaded.get::<String>("editor/scroll"), None);
+
+        // Nothing is written when nothing changed
+        std::fs::remove_file(&path).unwrap();
+        loaded.save().unwrap();
+        assert!(!path.exists());
+        loaded.set("editor/scroll", &0f32);
+        loaded.save().unwrap();
+        assert!(path.exists());
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn ui_state_starts_empty_from_an_invalid_file() {
+        let path = std::env::temp_dir().join(format!("ambient_ui_state_invalid_{}.json", std::process::id()));
+        std::fs::write(&path, "not json").unwrap();
+        let state = UiState::load(&path);
+        assert_eq!(state.get::<f32>("scroll"), None);
+        std::fs::remove_file(&path).unwrap();
+
+        // Without a file it's kept for the session only
+        let state = UiState::default();
+        state.set("scroll", &1f32);
+        state.save().unwrap();
+        assert_eq!(state.get::<f32>("scroll"), Some(1.));
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use ambient_std::{
+    asset_cache::{AssetCache, AsyncAssetKey, AsyncAssetKeyExt},
+    asset_url::AbsAssetUrl,
+    download_asset::{AssetError, AssetResult, BytesFromUrl},
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// The description of a video, written by the `Video` pipeline. The frames and the audio are kept in separate files, so
+/// that the server only needs to download this to know how long the video is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoDesc {
+    pub width: u32,
+    pub height: u32,
+    pub fps: f32,
+    pub frame_count: u32,
+    /// The url of the [VideoFrames]
+    pub frames: String,
+    /// The url of the audio track, as Ogg Vorbis
+    pub audio: Option<String>,
+}
+impl VideoDesc {
+    /// In seconds
+    pub fn duration(&self) -> f32 {
+        self.frame_count as f32 / self.fps
+    }
+    /// The frame shown at `position` (in seconds)
+    pub fn frame_at(&self, position: f32) -> usize {
+        ((position.max(0.) * self.fps) as usize).min(self.frame_count.saturating_sub(1) as usize)
+    }
+}
+
+/// The frames of a video, each encoded as a JPEG image
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VideoFrames {
+    pub frames: Vec<Vec<u8>>,
+}
+impl VideoFrames {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+    pub fn decode(&self, index: usize) -> anyhow::Result<image::RgbaImage> {
+        let frame = self.frames.get(index).ok_or_else(|| anyhow::anyhow!("No frame {index}"))?;
+        Ok(image::load_from_memory_with_format(frame, image::ImageFormat::Jpeg)?.to_rgba8())
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct VideoFramesFromUrl(pub AbsAssetUrl);
+#[async_trait]
+impl AsyncAssetKey<AssetResult<Arc<VideoFrames>>> for VideoFramesFromUrl {
+    async fn load(self, assets: AssetCache) -> AssetResult<Arc<VideoFrames>> {
+        let bytes = BytesFromUrl::new(self.0.clone(), true).get(&assets).await?;
+        VideoFrames::from_bytes(&bytes)
+            .map(Arc::new)
+            .map_err(|err| AssetError::from(err.context(format!("Invalid video frames {}", self.0))))
+    }
+}
@@ -0,0 +1,231 @@
+//! Plays the videos built by the `Video` pipeline on entities.
+//!
+//! The server owns the playhead of each video ([video_position]) and fires [VIDEO_END_EVENT] when a video ends; the
+//! clients follow it, show the frames on the mesh of the entity (or in the UI, through [video_texture]), and play the
+//! audio track on the audio mixer.
+
+use std::sync::Arc;
+
+use ambient_core::{asset_cache, async_ecs::async_run, dtime, mesh, runtime};
+use ambient_ecs::{
+    components, ensure_has_component, query, world_events, Debuggable, Description, EntityData, EntityId, Name, Networked, Store,
+    SystemGroup, World, WorldEvent,
+};
+use ambient_gpu::{
+    gpu::GpuKey,
+    std_assets::{DefaultNormalMapViewKey, PixelTextureViewKey},
+    texture::TextureView,
+};
+use ambient_renderer::{
+    material,
+    materials::pbr_material::{get_pbr_shader_unlit, PbrMaterial, PbrMaterialConfig, PbrMaterialParams},
+    renderer_shader, SharedMaterial,
+};
+use ambient_std::{
+    asset_cache::{AssetCache, AsyncAssetKeyExt, SyncAssetKeyExt},
+    asset_url::AbsAssetUrl,
+    cb,
+    download_asset::JsonFromUrl,
+};
+use ambient_world_audio::audio_mixer;
+use parking_lot::Mutex;
+
+mod asset;
+mod player;
+
+pub use asset::{VideoDesc, VideoFrames, VideoFramesFromUrl};
+pub use player::VideoPlayer;
+
+/// Fired on the server when a video reaches its end, including each time a looping video starts over. The event data
+/// contains the `id` of the entity playing it.
+pub const VIDEO_END_EVENT: &str = "core/video_end";
+
+components!("video", {
+    @[
+        Debuggable, Networked, Store,
+        Name["Video"],
+        Description["The URL of a video (the `.video.json` built by the `Video` pipeline) to play on this entity.\nIf the entity has a mesh, the video is shown on it."]
+    ]
+    video: String,
+    @[
+        Debuggable, Networked, Store,
+        Name["Video paused"],
+        Description["If attached, the video of this entity is paused."]
+    ]
+    video_paused: (),
+    @[
+        Debuggable, Networked, Store,
+        Name["Video looping"],
+        Description["If attached, the video of this entity starts over when it ends."]
+    ]
+    video_looping: (),
+    @[
+        Debuggable, Networked, Store,
+        Name["Video position"],
+        Description["The playhead of the video of this entity, in seconds.\nThe server advances it while the video is playing; set it to seek."]
+    ]
+    video_position: f32,
+    @[
+        Debuggable, Networked, Store,
+        Name["Video duration"],
+        Description["The duration of the video of this entity, in seconds.\nThis is set by the server once the video is loaded."]
+    ]
+    video_duration: f32,
+    @[
+        Debuggable, Networked, Store,
+        Name["Video volume"],
+        Description["The volume of the audio of the video of this entity. Defaults to 1."]
+    ]
+    video_volume: f32,
+
+    video_player: Arc<Mutex<VideoPlayer>>,
+    /// The current frame of the video of this entity, on the clients
+    video_texture: Arc<TextureView>,
+});
+
+fn parse_url(url: &str) -> Option<AbsAssetUrl> {
+    match AbsAssetUrl::parse(url) {
+        Ok(url) => Some(url),
+        Err(err) => {
+            log::error!("Invalid video url {url:?}: {err:?}");
+            None
+        }
+    }
+}
+
+/// Runs the playheads of the videos and fires [VIDEO_END_EVENT]
+pub fn server_systems() -> SystemGroup {
+    SystemGroup::new(
+        "video_server",
+        vec![
+            ensure_has_component(video(), video_position(), 0.),
+            query(video().changed()).to_system(|q, world, qs, _| {
+                for (id, url) in q.collect_cloned(world, qs) {
+                    let abs_url = match parse_url(&url) {
+                        Some(url) => url,
+                        None => continue,
+                    };
+                    let assets = world.resource(asset_cache()).clone();
+                    let async_run = world.resource(async_run()).clone();
+                    world.resource(runtime()).spawn(async move {
+                        match JsonFromUrl::<VideoDesc>::new(abs_url, true).get(&assets).await {
+                            Ok(desc) => async_run.run(move |world| {
+                                if world.get_ref(id, video()).map(|current| current == &url).unwrap_or(false) {
+                                    world.add_component(id, video_duration(), desc.duration()).ok();
+                                }
+                            }),
+                            Err(err) => log::error!("Failed to load the video {url}: {err:?}"),
+                        }
+                    });
+                }
+            }),
+            query((video_position(), video_duration())).excl(video_paused()).to_system(|q, world, qs, _| {
+                let dtime = *world.resource(dtime());
+                for (id, (position, duration)) in q.collect_cloned(world, qs) {
+                    if position >= duration {
+                        continue;
+                    }
+                    let mut position = position + dtime;
+                    if position >= duration {
+                        position = if world.has_component(id, video_looping()) { (position - duration).min(duration) } else { duration };
+                        world.resource_mut(world_events()).add_event(WorldEvent {
+                            name: VIDEO_END_EVENT.to_string(),
+                            data: EntityData::new().set(ambient_ecs::id(), id),
+                        });
+                    }
+                    world.set(id, video_position(), position).ok();
+                }
+            }),
+        ],
+    )
+}
+
+/// Loads the videos, shows their frames and plays their audio
+pub fn client_systems() -> SystemGroup {
+    SystemGroup::new(
+        "video_client",
+        vec![
+            query(video().changed()).to_system(|q, world, qs, _| {
+                for (id, url) in q.collect_cloned(world, qs) {
+                    let abs_url = match parse_url(&url) {
+                        Some(url) => url,
+                        None => continue,
+                    };
+                    let assets = world.resource(asset_cache()).clone();
+                    let async_run = world.resource(async_run()).clone();
+                    world.resource(runtime()).spawn(async move {
+                        match load_player(&assets, abs_url).await {
+                            Ok(player) => async_run.run(move |world| {
+                                if world.get_ref(id, video()).map(|current| current == &url).unwrap_or(false) {
+                                    add_player(world, &assets, id, player);
+                                }
+                            }),
+                            Err(err) => log::error!("Failed to load the video {url}: {err:?}"),
+                        }
+                    });
+                }
+            }),
+            query((video_player(), video_position())).to_system(|q, world, qs, _| {
+                let runtime = world.resource(runtime());
+                let mixer = world.resource_opt(audio_mixer());
+                let dtime = *world.resource(dtime());
+                for (id, (player, &position)) in q.iter(world, qs) {
+                    let paused = world.has_component(id, video_paused());
+                    let volume = world.get(id, video_volume()).unwrap_or(1.);
+                    player.lock().update(runtime, mixer, position, paused, volume, dtime);
+                }
+            }),
+            query(video().removed()).to_system(|q, world, qs, _| {
+                for (id, _) in q.collect_cloned(world, qs) {
+                    world.remove_component(id, video_player()).ok();
+                    world.remove_component(id, video_texture()).ok();
+                }
+            }),
+        ],
+    )
+}
+
+async fn load_player(assets: &AssetCache, url: AbsAssetUrl) -> anyhow::Result<VideoPlayer> {
+    let desc = JsonFromUrl::<VideoDesc>::new(url, true).get(assets).await?;
+    let frames = VideoFramesFromUrl(AbsAssetUrl::parse(&desc.frames)?).get(assets).await?;
+    let audio_track = match &desc.audio {
+        Some(audio) => Some(
+            ambient_audio::VorbisFromUrl { url: AbsAssetUrl::parse(audio)? }
+                .get(assets)
+                .await
+                .map_err(|err| anyhow::anyhow!("Failed to load the audio track: {err:?}"))?,
+        ),
+        None => None,
+    };
+    Ok(VideoPlayer::new(GpuKey.get(assets), desc, frames, audio_track))
+}
+
+fn add_player(world: &mut World, assets: &AssetCache, id: EntityId, player: VideoPlayer) {
+    let texture = player.texture();
+    let mut data = EntityData::new().set(video_player(), Arc::new(Mutex::new(player))).set(video_texture(), texture.clone());
+    if world.has_component(id, mesh()) {
+        data.set_self(material(), video_material(assets, texture));
+        data.set_self(renderer_shader(), cb(get_pbr_shader_unlit));
+    }
+    world.add_components(id, data).ok();
+}
+
+fn video_material(assets: &AssetCache, texture: Arc<TextureView>) -> SharedMaterial {
+    SharedMaterial::new(PbrMaterial::new(
+        assets.clone(),
+        PbrMaterialConfig {
+            source: "Video".to_string(),
+            name: "Video".to_string(),
+            params: PbrMaterialParams::default(),
+            base_color: texture,
+            normalmap: DefaultNormalMapViewKey.get(assets),
+            metallic_roughness: PixelTextureViewKey::white().get(assets),
+            emissive: None,
+            transparent: None,
+            double_sided: None,
+            depth_write_enabled: None,
+            sampling: None,
+            detail: None,
+        },
+    ))
+}
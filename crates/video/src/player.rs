@@ -0,0 +1,184 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+};
+
+use ambient_audio::{vorbis::VorbisTrack, AudioMixer, Frame, SampleRate, Source};
+use ambient_gpu::{
+    gpu::Gpu,
+    texture::{Texture, TextureView},
+};
+use ambient_sys::task::RuntimeHandle;
+
+use crate::asset::{VideoDesc, VideoFrames};
+
+/// How far the local playhead can drift from the server's before it jumps to it
+const RESYNC_THRESHOLD: f32 = 0.25;
+
+/// Plays a video on the client: follows the playhead set by the server, decodes the frames in the background and
+/// writes them to [VideoPlayer::texture], and plays the audio track on the mixer.
+pub struct VideoPlayer {
+    desc: Arc<VideoDesc>,
+    frames: Arc<VideoFrames>,
+    audio_track: Option<Arc<VorbisTrack>>,
+    texture: Arc<Texture>,
+    texture_view: Arc<TextureView>,
+    position: f32,
+    paused: bool,
+    shown_frame: Option<usize>,
+    decoding: bool,
+    decoded_tx: flume::Sender<(usize, Option<image::RgbaImage>)>,
+    decoded_rx: flume::Receiver<(usize, Option<image::RgbaImage>)>,
+    audio: Option<Arc<AudioControl>>,
+}
+impl VideoPlayer {
+    pub fn new(gpu: Arc<Gpu>, desc: Arc<VideoDesc>, frames: Arc<VideoFrames>, audio_track: Option<Arc<VorbisTrack>>) -> Self {
+        let texture = Arc::new(Texture::new(
+            gpu,
+            &wgpu::TextureDescriptor {
+                label: Some("Video"),
+                size: wgpu::Extent3d { width: desc.width, height: desc.height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+        ));
+        let texture_view = Arc::new(texture.create_view(&Default::default()));
+        let (decoded_tx, decoded_rx) = flume::unbounded();
+        Self {
+            desc,
+            frames,
+            audio_track,
+            texture,
+            texture_view,
+            position: 0.,
+            paused: true,
+            shown_frame: None,
+            decoding: false,
+            decoded_tx,
+            decoded_rx,
+            audio: None,
+        }
+    }
+    /// The current frame of the video
+    pub fn texture(&self) -> Arc<TextureView> {
+        self.texture_view.clone()
+    }
+    pub fn desc(&self) -> &Arc<VideoDesc> {
+        &self.desc
+    }
+    /// The local playhead, in seconds
+    pub fn position(&self) -> f32 {
+        self.position
+    }
+
+    /// Advances the playhead by `dtime`, following `server_position` if it drifted too far from it, and starts decoding
+    /// the frame to show
+    pub fn update(
+        &mut self,
+        runtime: &RuntimeHandle,
+        mixer: Option<&AudioMixer>,
+        server_position: f32,
+        paused: bool,
+        volume: f32,
+        dtime: f32,
+    ) {
+        let duration = self.desc.duration();
+        if !paused {
+            self.position = (self.position + dtime).min(duration);
+        }
+        let resync = (self.position - server_position).abs() > RESYNC_THRESHOLD || paused != self.paused;
+        if resync {
+            self.position = server_position.clamp(0., duration);
+            self.paused = paused;
+            self.stop_audio();
+        }
+        match (&self.audio, mixer, &self.audio_track) {
+            (Some(audio), _, _) => audio.volume.store(volume.to_bits(), Ordering::Relaxed),
+            (None, Some(mixer), Some(track)) if !paused && self.position < duration => {
+                self.audio = Some(play_audio(mixer, track, self.position, volume));
+            }
+            _ => {}
+        }
+
+        for (index, image) in self.decoded_rx.try_iter() {
+            // Frames which failed to decode are skipped, the previous one stays on screen
+            if let Some(image) = image {
+                self.texture.write(image.as_raw());
+            }
+            self.shown_frame = Some(index);
+            self.decoding = false;
+        }
+        let frame = self.desc.frame_at(self.position);
+        if self.shown_frame != Some(frame) && !self.decoding {
+            self.decoding = true;
+            let frames = self.frames.clone();
+            let decoded_tx = self.decoded_tx.clone();
+            let (width, height) = (self.desc.width, self.desc.height);
+            runtime.spawn_blocking(move || {
+                let image = match frames.decode(frame) {
+                    Ok(image) if image.dimensions() == (width, height) => Some(image),
+                    Ok(image) => {
+                        log::warn!("Frame {frame} of the video is {:?} instead of {:?}", image.dimensions(), (width, height));
+                        None
+                    }
+                    Err(err) => {
+                        log::warn!("Failed to decode frame {frame} of the video: {err:?}");
+                        None
+                    }
+                };
+                decoded_tx.send((frame, image)).ok();
+            });
+        }
+    }
+    fn stop_audio(&mut self) {
+        if let Some(audio) = self.audio.take() {
+            audio.stopped.store(true, Ordering::Relaxed);
+        }
+    }
+}
+impl Drop for VideoPlayer {
+    fn drop(&mut self) {
+        self.stop_audio();
+    }
+}
+impl std::fmt::Debug for VideoPlayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VideoPlayer").field("desc", &self.desc).field("position", &self.position).field("paused", &self.paused).finish()
+    }
+}
+
+fn play_audio(mixer: &AudioMixer, track: &VorbisTrack, position: f32, volume: f32) -> Arc<AudioControl> {
+    let control = Arc::new(AudioControl { stopped: AtomicBool::new(false), volume: AtomicU32::new(volume.to_bits()) });
+    let source = track.decode().skip(std::time::Duration::from_secs_f32(position));
+    mixer.play(Controlled { source, control: control.clone() });
+    control
+}
+
+/// The mixer can't stop a sound or change its volume, so the source does it instead
+struct AudioControl {
+    stopped: AtomicBool,
+    volume: AtomicU32,
+}
+struct Controlled<S> {
+    source: S,
+    control: Arc<AudioControl>,
+}
+impl<S: Source> Source for Controlled<S> {
+    fn next_sample(&mut self) -> Option<Frame> {
+        if self.control.stopped.load(Ordering::Relaxed) {
+            None
+        } else {
+            let volume = f32::from_bits(self.control.volume.load(Ordering::Relaxed));
+            self.source.next_sample().map(|frame| frame * volume)
+        }
+    }
+    fn sample_rate(&self) -> SampleRate {
+        self.source.sample_rate()
+    }
+    fn sample_count(&self) -> Option<u64> {
+        self.source.sample_count()
+    }
+}
@@ -20,6 +20,7 @@ use crate::{
 use ambient_core::asset_cache;
 use ambient_std::{
     asset_cache::SyncAssetKeyExt,
+    asset_manifest::asset_manifest_url,
     asset_url::{AssetUrl, ServerBaseUrlKey},
 };
 use physxx::{PxControllerCollisionFlag, PxControllerFilters};
@@ -402,7 +403,12 @@ impl host::Host for Bindings {
     }
 
     fn asset_url(&mut self, path: &str) -> Option<String> {
-        let base_url = ServerBaseUrlKey.get(self.world().resource(asset_cache()));
+        let assets = self.world().resource(asset_cache());
+        // Ids from the asset manifest (e.g. `characters/knight`) take precedence over paths
+        if let Some(url) = asset_manifest_url(assets, path) {
+            return Some(url.to_string());
+        }
+        let base_url = ServerBaseUrlKey.get(assets);
         AssetUrl::parse(path)
             .ok()?
             .resolve(&base_url)
@@ -7,7 +7,7 @@ use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
 
 use super::{
     conversion::{FromBindgen, IntoBindgen},
-    implementation::entity::get_component_type,
+    implementation::entity::{get_component_type, validated},
     interface::host,
 };
 
@@ -121,7 +121,7 @@ macro_rules! define_component_types {
                 ComponentListTypeParam as CLTP, ComponentOptionTypeParam as COTP,
                 ComponentTypeParam as CTP,
             };
-            with_component_registry(|cr| {
+            let mut data: EntityData = with_component_registry(|cr| {
                 components
                     .into_iter()
                     .flat_map(|(index, value)| {
@@ -138,7 +138,11 @@ macro_rules! define_component_types {
                         }
                     })
                     .collect()
-            })
+            });
+            for err in data.validate() {
+                log::warn!("{err}");
+            }
+            data
         }
 
         pub(crate) fn add_component(
@@ -151,17 +155,23 @@ macro_rules! define_component_types {
                 $(
                 host::ComponentTypeParam::[<Type $value >](value) => {
                     if let Some(component) = get_component_type::<$type>(index) {
-                        world.add_component(entity_id, component, value.from_bindgen())?;
+                        if let Some(value) = validated(component, value.from_bindgen()) {
+                            world.add_component(entity_id, component, value)?;
+                        }
                     }
                 }
                 host::ComponentTypeParam::TypeList(host::ComponentListTypeParam::[<Type $value >](value)) => {
                     if let Some(component) = get_component_type::<Vec<$type>>(index) {
-                        world.add_component(entity_id, component, value.from_bindgen())?;
+                        if let Some(value) = validated(component, value.from_bindgen()) {
+                            world.add_component(entity_id, component, value)?;
+                        }
                     }
                 }
                 host::ComponentTypeParam::TypeOption(host::ComponentOptionTypeParam::[<Type $value >](value)) => {
                     if let Some(component) = get_component_type::<Option<$type>>(index) {
-                        world.add_component(entity_id, component, value.from_bindgen())?;
+                        if let Some(value) = validated(component, value.from_bindgen()) {
+                            world.add_component(entity_id, component, value)?;
+                        }
                     }
                 }
                 ) *
@@ -180,17 +190,23 @@ macro_rules! define_component_types {
                 $(
                 host::ComponentTypeParam::[<Type $value >](value) => {
                     if let Some(component) = get_component_type::<$type>(index) {
-                        world.set(entity_id, component, value.from_bindgen())?;
+                        if let Some(value) = validated(component, value.from_bindgen()) {
+                            world.set(entity_id, component, value)?;
+                        }
                     }
                 }
                 host::ComponentTypeParam::TypeList(host::ComponentListTypeParam::[<Type $value >](value)) => {
                     if let Some(component) = get_component_type::<Vec<$type>>(index) {
-                        world.set(entity_id, component, value.from_bindgen())?;
+                        if let Some(value) = validated(component, value.from_bindgen()) {
+                            world.set(entity_id, component, value)?;
+                        }
                     }
                 }
                 host::ComponentTypeParam::TypeOption(host::ComponentOptionTypeParam::[<Type $value >](value)) => {
                     if let Some(component) = get_component_type::<Option<$type>>(index) {
-                        world.set(entity_id, component, value.from_bindgen())?;
+                        if let Some(value) = validated(component, value.from_bindgen()) {
+                            world.set(entity_id, component, value)?;
+                        }
                     }
                 }
                 ) *
@@ -2,8 +2,8 @@ use crate::shared::host_guest_state::QueryStateMap;
 use ambient_animation::{animation_controller, AnimationController};
 use ambient_core::transform::translation;
 use ambient_ecs::{
-    query as ecs_query, with_component_registry, Component, ComponentValue, EntityData, EntityId,
-    QueryEvent, QueryState, World,
+    query as ecs_query, validate_component, with_component_registry, Component, ComponentEntry,
+    ComponentValue, EntityData, EntityId, QueryEvent, QueryState, World,
 };
 use anyhow::Context;
 use glam::Vec3;
@@ -31,6 +31,18 @@ pub fn get_component_type<T: ComponentValue>(component_index: u32) -> Option<Com
     Some(Component::new(desc))
 }
 
+/// Runs the [ambient_ecs::Validate] function of `component` on a `value` set by a script. The
+/// values which are rejected are logged, and shouldn't be set.
+pub fn validated<T: ComponentValue>(component: Component<T>, value: T) -> Option<T> {
+    match validate_component(ComponentEntry::new(component, value)) {
+        Ok(entry) => Some(entry.into_inner()),
+        Err(err) => {
+            log::warn!("{err}");
+            None
+        }
+    }
+}
+
 pub fn get_component_index(id: &str) -> Option<u32> {
     with_component_registry(|r| Some(r.get_by_path(id)?.index()))
 }
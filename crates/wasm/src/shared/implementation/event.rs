@@ -1,6 +1,7 @@
 use crate::shared::host_guest_state::BaseHostGuestState;
 use ambient_ecs::EntityData;
 use ambient_ecs::{world_events, WorldEvent};
+use ambient_network::messages::{self, MESSAGE_EVENT_PREFIX};
 
 pub fn subscribe(shared_state: &mut BaseHostGuestState, name: &str) {
     shared_state.subscribed_events.insert(name.to_string());
@@ -10,6 +11,13 @@ pub fn send(shared_state: &mut BaseHostGuestState, name: &str, data: EntityData)
     if name.starts_with("core/") {
         return;
     }
+    // The messages are sent to the clients rather than the other modules
+    if let Some(message) = name.strip_prefix(MESSAGE_EVENT_PREFIX) {
+        if let Err(err) = messages::send_to_players(shared_state.world(), message, data) {
+            log::warn!("Failed to send message: {err:#}");
+        }
+        return;
+    }
     shared_state
         .world_mut()
         .resource_mut(world_events())
@@ -6,8 +6,9 @@ pub(crate) mod implementation;
 pub mod interface;
 
 mod module;
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
+use ambient_core::budget::budget_monitor;
 use ambient_ecs::{
     components, dont_despawn_on_unload, query, Component, EntityData, EntityId, Networked,
     Resource, Store, World,
@@ -318,9 +319,17 @@ pub fn run<
         return None;
     }
 
+    let start = Instant::now();
     let result = run_and_catch_panics(|| state.run(world, context));
+    let time = start.elapsed();
     world.set(id, state_component, state).ok();
 
+    // Attributes the time to the module, so that the slow ones can be found on a shared server
+    let source = format!("wasm/{}", get_module_name(world, id));
+    if let Some(monitor) = world.resource_mut_opt(budget_monitor()) {
+        monitor.record_time(&source, time);
+    }
+
     let err = result.err().map(|err| (id, err));
 
     err
@@ -46,6 +46,7 @@ pub fn systems() -> SystemGroup {
                             ))
                             .unwrap(),
                             format: wgpu::TextureFormat::Rgba8Unorm,
+                            color_space: None,
                         }
                         .get(&assets)
                         .await
@@ -1,10 +1,18 @@
 mod error;
 mod events;
 mod graph;
+pub mod music;
 mod sounds;
 pub mod systems;
+pub mod voice;
 pub use ambient_audio as core;
 pub use error::*;
 pub use events::*;
 pub use graph::*;
 pub use sounds::*;
+
+pub fn init_all_components() {
+    init_components();
+    music::init_components();
+    voice::init_components();
+}
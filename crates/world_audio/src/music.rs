@@ -0,0 +1,461 @@
+//! Interactive music: a piece of music is made of stems (e.g. drums, bass and strings) which play in sync and loop
+//! together, and which fade in and out with the [music_intensity] of the entity playing it. The stems only start and
+//! stop fading on the beat or on the bar, so that the music swells and calms down without hard cuts.
+//!
+//! A piece is authored as a `.music.json` file, which the `Music` pipeline builds into a [MusicDesc] pointing at the
+//! built stems. Set [music] on an entity to its url to play it on the clients, and drive [music_intensity] from a
+//! script; the clients follow it.
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+};
+
+use ambient_audio::{vorbis::VorbisTrack, AudioMixer, Frame, SampleConversion, SampleRate, Source, VoicePriority, VorbisFromUrl};
+use ambient_core::{asset_cache, async_ecs::async_run, runtime};
+use ambient_ecs::{components, query, Debuggable, Description, Name, Networked, Store, SystemGroup};
+use ambient_std::{
+    asset_cache::{AssetCache, AsyncAssetKeyExt},
+    asset_url::AbsAssetUrl,
+    download_asset::JsonFromUrl,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::audio_mixer;
+
+/// The music keeps its voice of the mixer, however many other sounds play
+const MUSIC_PRIORITY: i32 = i32::MAX;
+
+components!("audio", {
+    @[
+        Debuggable, Networked, Store,
+        Name["Music"],
+        Description["The URL of a piece of music (the `.music.json` built by the `Music` pipeline) to play on the clients.\nIts stems fade in and out with the `music_intensity` of this entity."]
+    ]
+    music: String,
+    @[
+        Debuggable, Networked, Store,
+        Name["Music intensity"],
+        Description["How intense the music of this entity is, from 0 to 1. Defaults to 0.\nThe stems whose intensity range includes it fade in on the next beat or bar (as the music says), and the others fade out."]
+    ]
+    music_intensity: f32,
+    @[
+        Debuggable, Networked, Store,
+        Name["Music volume"],
+        Description["The volume of the music of this entity. Defaults to 1."]
+    ]
+    music_volume: f32,
+
+    /// Plays the music of this entity, on the clients
+    music_player: Arc<MusicPlayer>,
+});
+
+/// A piece of music, as authored in a `.music.json` file. The `Music` pipeline writes it back with the urls of the built
+/// stems.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MusicDesc {
+    /// The tempo, in beats per minute
+    pub bpm: f32,
+    /// Defaults to 4
+    #[serde(default = "default_beats_per_bar")]
+    pub beats_per_bar: u32,
+    /// The length of the piece, in bars, after which all the stems start over together.
+    /// Defaults to the length of the longest stem.
+    #[serde(default)]
+    pub bars: Option<u32>,
+    /// When the stems start fading in or out after the intensity changes
+    #[serde(default)]
+    pub transition: MusicTransition,
+    /// How long the stems fade in and out for, in beats. Defaults to 1
+    #[serde(default = "default_fade_beats")]
+    pub fade_beats: f32,
+    pub stems: Vec<MusicStem>,
+}
+impl MusicDesc {
+    pub fn samples_per_beat(&self, sample_rate: SampleRate) -> f32 {
+        sample_rate as f32 * 60. / self.bpm.max(1.)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MusicTransition {
+    /// As soon as the intensity changes
+    Immediate,
+    /// On the next beat
+    Beat,
+    /// On the next bar
+    #[default]
+    Bar,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MusicStem {
+    /// The audio of the stem; in the authored file, its path relative to the `.music.json` file (in any format the
+    /// `Audio` pipeline supports), and once built, the url of an Ogg Vorbis file
+    pub audio: String,
+    /// The stem plays while the intensity is between `min_intensity` and `max_intensity`. Defaults to 0
+    #[serde(default)]
+    pub min_intensity: f32,
+    /// Defaults to 1
+    #[serde(default = "default_one")]
+    pub max_intensity: f32,
+    /// Defaults to 1
+    #[serde(default = "default_one")]
+    pub volume: f32,
+}
+impl MusicStem {
+    fn plays_at(&self, intensity: f32) -> bool {
+        (self.min_intensity..=self.max_intensity).contains(&intensity)
+    }
+}
+
+fn default_beats_per_bar() -> u32 {
+    4
+}
+fn default_fade_beats() -> f32 {
+    1.
+}
+fn default_one() -> f32 {
+    1.
+}
+
+/// What the game thread changes on a playing piece of music
+#[derive(Debug)]
+struct MusicControl {
+    intensity: AtomicU32,
+    volume: AtomicU32,
+    stopped: AtomicBool,
+}
+
+/// Plays a piece of music on the mixer, until dropped
+#[derive(Debug)]
+pub struct MusicPlayer {
+    control: Arc<MusicControl>,
+}
+impl MusicPlayer {
+    /// `tracks` are the stems of `desc`, in order
+    pub fn play(mixer: &AudioMixer, desc: &MusicDesc, tracks: Vec<Arc<VorbisTrack>>, intensity: f32, volume: f32) -> Self {
+        let control = Arc::new(MusicControl {
+            intensity: AtomicU32::new(intensity.to_bits()),
+            volume: AtomicU32::new(volume.to_bits()),
+            stopped: AtomicBool::new(false),
+        });
+        let sample_rate = tracks.first().map(|track| track.decode().sample_rate()).unwrap_or(44100);
+        let stems = tracks.into_iter().map(|track| Box::new(move || decode(&track, sample_rate)) as DecodeStem<_>).collect();
+        mixer.play_with_priority(MusicSource::new(desc, stems, sample_rate, control.clone()), || VoicePriority {
+            priority: MUSIC_PRIORITY,
+            audibility: 1.,
+        });
+        Self { control }
+    }
+    pub fn set_intensity(&self, intensity: f32) {
+        self.control.intensity.store(intensity.to_bits(), Ordering::Relaxed);
+    }
+    pub fn set_volume(&self, volume: f32) {
+        self.control.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+}
+impl Drop for MusicPlayer {
+    fn drop(&mut self) {
+        self.control.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Decodes a stem from its start, each time the piece starts over
+type DecodeStem<S> = Box<dyn Fn() -> S + Send>;
+
+struct StemSource<S> {
+    desc: MusicStem,
+    decode: DecodeStem<S>,
+    source: S,
+    /// Whether the stem fades in or out
+    playing: bool,
+    /// The position at which the stem switches to `playing` the other way, once the intensity says it should
+    switch_at: Option<u64>,
+    gain: f32,
+}
+
+/// Mixes the stems in sync, and starts them all over at the end of the piece. Ends once stopped and faded out
+struct MusicSource<S> {
+    stems: Vec<StemSource<S>>,
+    control: Arc<MusicControl>,
+    sample_rate: SampleRate,
+    /// The samples between the transitions
+    transition_len: u64,
+    /// The samples the piece loops after
+    loop_len: u64,
+    /// How much the gain of a stem changes by per sample, as it fades in or out
+    fade_step: f32,
+    /// In samples, since the piece last started over
+    position: u64,
+}
+impl<S: Source> MusicSource<S> {
+    /// `stems` decode the stems of `desc`, in order, at `sample_rate`
+    fn new(desc: &MusicDesc, stems: Vec<DecodeStem<S>>, sample_rate: SampleRate, control: Arc<MusicControl>) -> Self {
+        let intensity = f32::from_bits(control.intensity.load(Ordering::Relaxed));
+        let stems: Vec<_> = desc
+            .stems
+            .iter()
+            .zip(stems)
+            .map(|(stem, decode)| {
+                let playing = stem.plays_at(intensity);
+                StemSource { source: decode(), desc: stem.clone(), decode, playing, switch_at: None, gain: if playing { 1. } else { 0. } }
+            })
+            .collect();
+
+        let samples_per_beat = desc.samples_per_beat(sample_rate);
+        let transition_len = match desc.transition {
+            MusicTransition::Immediate => 1.,
+            MusicTransition::Beat => samples_per_beat,
+            MusicTransition::Bar => samples_per_beat * desc.beats_per_bar as f32,
+        };
+        let loop_len = match desc.bars {
+            Some(bars) => (samples_per_beat * (bars * desc.beats_per_bar) as f32) as u64,
+            None => stems.iter().filter_map(|stem| stem.source.sample_count()).max().unwrap_or(0),
+        };
+        Self {
+            stems,
+            control,
+            sample_rate,
+            transition_len: (transition_len as u64).max(1),
+            loop_len: loop_len.max(1),
+            fade_step: 1. / (desc.fade_beats * samples_per_beat).max(1.),
+            position: 0,
+        }
+    }
+
+    /// The position of the next transition, which is `loop_len` at the end of the piece
+    fn next_transition(&self) -> u64 {
+        let next = (self.position + self.transition_len - 1) / self.transition_len * self.transition_len;
+        next.min(self.loop_len)
+    }
+}
+
+fn decode(track: &VorbisTrack, sample_rate: SampleRate) -> Box<dyn Source> {
+    let source = track.decode();
+    if source.sample_rate() == sample_rate {
+        Box::new(source)
+    } else {
+        Box::new(SampleConversion::new(source, sample_rate))
+    }
+}
+
+impl<S: Source> Source for MusicSource<S> {
+    fn next_sample(&mut self) -> Option<Frame> {
+        let stopped = self.control.stopped.load(Ordering::Relaxed);
+        let intensity = f32::from_bits(self.control.intensity.load(Ordering::Relaxed));
+        let next_transition = self.next_transition();
+        for stem in &mut self.stems {
+            if stopped {
+                stem.playing = false;
+                stem.switch_at = None;
+            } else if stem.desc.plays_at(intensity) == stem.playing {
+                // The intensity went back before the switch
+                stem.switch_at = None;
+            } else {
+                let switch_at = *stem.switch_at.get_or_insert(next_transition);
+                if self.position >= switch_at {
+                    stem.playing = !stem.playing;
+                    stem.switch_at = None;
+                }
+            }
+        }
+        if stopped && self.stems.iter().all(|stem| stem.gain <= 0.) {
+            return None;
+        }
+
+        if self.position >= self.loop_len {
+            self.position = 0;
+            for stem in &mut self.stems {
+                stem.source = (stem.decode)();
+            }
+        }
+        self.position += 1;
+
+        let mut res = Frame::ZERO;
+        for stem in &mut self.stems {
+            stem.gain = if stem.playing { (stem.gain + self.fade_step).min(1.) } else { (stem.gain - self.fade_step).max(0.) };
+            // Stems shorter than the piece are silent until it starts over
+            if let Some(sample) = stem.source.next_sample() {
+                res += sample * stem.gain * stem.desc.volume;
+            }
+        }
+        Some(res * f32::from_bits(self.control.volume.load(Ordering::Relaxed)))
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn sample_count(&self) -> Option<u64> {
+        None
+    }
+}
+
+async fn load_music(assets: &AssetCache, url: AbsAssetUrl) -> anyhow::Result<(Arc<MusicDesc>, Vec<Arc<VorbisTrack>>)> {
+    let desc = JsonFromUrl::<MusicDesc>::new(url, true).get(assets).await?;
+    let mut tracks = Vec::new();
+    for stem in &desc.stems {
+        let track = VorbisFromUrl { url: AbsAssetUrl::parse(&stem.audio)? }
+            .get(assets)
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to load the stem {}: {err:?}", stem.audio))?;
+        tracks.push(track);
+    }
+    Ok((desc, tracks))
+}
+
+/// Loads the music and plays it, following its intensity and volume
+pub fn client_systems() -> SystemGroup {
+    SystemGroup::new(
+        "music_client",
+        vec![
+            query(music().changed()).to_system(|q, world, qs, _| {
+                for (id, url) in q.collect_cloned(world, qs) {
+                    world.remove_component(id, music_player()).ok();
+                    let abs_url = match AbsAssetUrl::parse(&url) {
+                        Ok(url) => url,
+                        Err(err) => {
+                            log::error!("Invalid music url {url:?}: {err:?}");
+                            continue;
+                        }
+                    };
+                    let assets = world.resource(asset_cache()).clone();
+                    let async_run = world.resource(async_run()).clone();
+                    world.resource(runtime()).spawn(async move {
+                        match load_music(&assets, abs_url).await {
+                            Ok((desc, tracks)) => async_run.run(move |world| {
+                                if !world.get_ref(id, music()).map(|current| current == &url).unwrap_or(false) {
+                                    return;
+                                }
+                                if let Some(mixer) = world.resource_opt(audio_mixer()) {
+                                    let intensity = world.get(id, music_intensity()).unwrap_or(0.);
+                                    let volume = world.get(id, music_volume()).unwrap_or(1.);
+                                    let player = MusicPlayer::play(mixer, &desc, tracks, intensity, volume);
+                                    world.add_component(id, music_player(), Arc::new(player)).ok();
+                                }
+                            }),
+                            Err(err) => log::error!("Failed to load the music {url}: {err:?}"),
+                        }
+                    });
+                }
+            }),
+            query(music_player()).to_system(|q, world, qs, _| {
+                for (id, player) in q.iter(world, qs) {
+                    player.set_intensity(world.get(id, music_intensity()).unwrap_or(0.));
+                    player.set_volume(world.get(id, music_volume()).unwrap_or(1.));
+                }
+            }),
+            query(music().removed()).to_system(|q, world, qs, _| {
+                for (id, _) in q.collect_cloned(world, qs) {
+                    world.remove_component(id, music_player()).ok();
+                }
+            }),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use ambient_audio::BufferedSource;
+
+    use super::*;
+
+    /// 100 samples per beat
+    const SAMPLE_RATE: SampleRate = 100;
+
+    type Stem = BufferedSource<Vec<f32>, f32>;
+
+    /// Plays `desc` with synthetic stems of `stems` samples
+    fn play(desc: &str, stems: Vec<Vec<f32>>, intensity: f32) -> (MusicSource<Stem>, Arc<MusicControl>) {
+        let desc: MusicDesc = serde_json::from_str(desc).unwrap();
+        let control = Arc::new(MusicControl {
+            intensity: AtomicU32::new(intensity.to_bits()),
+            volume: AtomicU32::new(1f32.to_bits()),
+            stopped: AtomicBool::new(false),
+        });
+        let stems = stems
+            .into_iter()
+            .map(|samples| Box::new(move || BufferedSource::new(samples.clone(), 1, SAMPLE_RATE)) as DecodeStem<Stem>)
+            .collect();
+        (MusicSource::new(&desc, stems, SAMPLE_RATE, control.clone()), control)
+    }
+
+    fn set_intensity(control: &MusicControl, intensity: f32) {
+        control.intensity.store(intensity.to_bits(), Ordering::Relaxed);
+    }
+
+    fn next_samples(source: &mut impl Source, count: usize) -> Vec<f32> {
+        (0..count).map(|_| source.next_sample().unwrap().x).collect()
+    }
+
+    #[test]
+    fn stems_fade_in_on_the_next_bar() {
+        let (mut source, control) = play(
+            r#"{"bpm": 60, "bars": 2, "stems": [{"audio": "a"}, {"audio": "b", "min_intensity": 0.5}]}"#,
+            vec![vec![1.; 800], vec![1.; 800]],
+            0.,
+        );
+        assert_eq!(next_samples(&mut source, 100), vec![1.; 100]);
+        set_intensity(&control, 0.7);
+        let samples = next_samples(&mut source, 500);
+        // The bar starts at 400, and the stem fades in over a beat
+        assert!(samples[..300].iter().all(|&sample| sample == 1.));
+        assert!((samples[350] - 1.5).abs() < 0.02, "{}", samples[350]);
+        assert_eq!(samples[450], 2.);
+
+        set_intensity(&control, 0.2);
+        let samples = next_samples(&mut source, 300);
+        // It fades back out as the piece starts over, at 800
+        assert_eq!(samples[199], 2.);
+        assert!((samples[250] - 1.5).abs() < 0.02, "{}", samples[250]);
+    }
+
+    #[test]
+    fn stems_fade_in_on_the_next_beat() {
+        let (mut source, control) = play(
+            r#"{"bpm": 60, "transition": "Beat", "fade_beats": 0.5, "stems": [{"audio": "a", "min_intensity": 0.5}]}"#,
+            vec![vec![1.; 1000]],
+            0.,
+        );
+        next_samples(&mut source, 150);
+        set_intensity(&control, 1.);
+        let samples = next_samples(&mut source, 150);
+        assert!(samples[..50].iter().all(|&sample| sample == 0.));
+        assert!((samples[75] - 0.5).abs() < 0.03, "{}", samples[75]);
+        assert_eq!(samples[100], 1.);
+    }
+
+    #[test]
+    fn switches_are_cancelled_when_the_intensity_goes_back() {
+        let (mut source, control) = play(r#"{"bpm": 60, "stems": [{"audio": "a", "max_intensity": 0.5}]}"#, vec![vec![1.; 1000]], 0.);
+        next_samples(&mut source, 100);
+        set_intensity(&control, 1.);
+        next_samples(&mut source, 200);
+        set_intensity(&control, 0.);
+        assert!(next_samples(&mut source, 500).iter().all(|&sample| sample == 1.));
+    }
+
+    #[test]
+    fn stems_start_over_together() {
+        // The piece is as long as its longest stem
+        let ramp = |len: usize| (0..len).map(|i| i as f32).collect::<Vec<_>>();
+        let (mut source, _) = play(r#"{"bpm": 60, "stems": [{"audio": "a"}, {"audio": "b"}]}"#, vec![ramp(300), vec![1000.; 200]], 0.);
+        let samples = next_samples(&mut source, 700);
+        assert_eq!(samples[199], 1199.);
+        // The shorter stem is silent until the piece starts over
+        assert_eq!(samples[250], 250.);
+        assert_eq!(samples[300], 1000.);
+        assert_eq!(samples[650], 1050.);
+    }
+
+    #[test]
+    fn music_ends_once_stopped_and_faded_out() {
+        let (mut source, control) = play(r#"{"bpm": 60, "stems": [{"audio": "a"}]}"#, vec![vec![1.; 1000]], 0.);
+        next_samples(&mut source, 10);
+        control.stopped.store(true, Ordering::Relaxed);
+        let samples = std::iter::from_fn(|| source.next_sample()).map(|frame| frame.x).collect::<Vec<_>>();
+        assert!((100..=101).contains(&samples.len()), "{}", samples.len());
+        assert!(samples.windows(2).all(|pair| pair[1] < pair[0]));
+    }
+}
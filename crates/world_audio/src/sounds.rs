@@ -69,7 +69,8 @@ fn get_audio_listener(world: &World) -> anyhow::Result<&Arc<Mutex<AudioListener>
     Ok(listener)
 }
 
-/// Makes a sound source emit from the entity
+/// Makes a sound source emit from the entity. The sound competes for the voices of the mixer with the priority of the
+/// emitter, and how loud the listener hears it
 pub fn play_sound_on_entity<S: 'static + Source>(world: &World, id: EntityId, source: S) -> anyhow::Result<Sound> {
     let hrtf_lib = world.resource(hrtf_lib());
     let mixer = world.resource(audio_mixer());
@@ -77,5 +78,12 @@ pub fn play_sound_on_entity<S: 'static + Source>(world: &World, id: EntityId, so
 
     let listener = get_audio_listener(world)?;
 
-    Ok(mixer.play(source.spatial(hrtf_lib, listener.clone(), emitter.clone())))
+    let priority = {
+        let (listener, emitter) = (listener.clone(), emitter.clone());
+        move || {
+            let listener = listener.lock();
+            emitter.lock().voice_priority(&listener)
+        }
+    };
+    Ok(mixer.play_with_priority(source.spatial(hrtf_lib, listener.clone(), emitter.clone()), priority))
 }
@@ -47,5 +47,8 @@ pub fn spatial_audio_systems() -> SystemGroup {
 }
 
 pub fn client_systems() -> SystemGroup {
-    SystemGroup::new("Spatial audio", vec![Box::new(spatial_audio_systems())])
+    SystemGroup::new(
+        "Spatial audio",
+        vec![Box::new(spatial_audio_systems()), Box::new(crate::voice::client_systems()), Box::new(crate::music::client_systems())],
+    )
 }
@@ -0,0 +1,667 @@
+//! Voice chat: the clients capture the voice of their players from the microphone, and the server relays it to the
+//! other players, whose clients play it from the entity of the player speaking, through the spatial audio.
+//!
+//! The voice is encoded with Opus, in frames of 20ms which are sent on the unreliable [VOICE_CHANNEL_ID] channel, so
+//! that a lost frame is concealed rather than holding up the ones after it. The server tags each frame with the player
+//! entity of the speaker, which multiplexes the voices of all the players on the one channel, and drops the voice of
+//! the players with [voice_muted], as well as the voice a player sends beyond a few times the bitrate. The clients
+//! decode the voice of each speaker into a jitter buffer of its own, which starts playing once it holds a few frames,
+//! from the speaker's entity: the voice is heard from where the entity is, as its `local_to_world` says.
+//!
+//! A client only captures the microphone while its [VoiceChat] transmits.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ambient_audio::{AudioCapture, AudioEmitter, Frame, SampleRate, Source};
+use ambient_core::runtime;
+use ambient_ecs::{components, query, Debuggable, Description, EntityId, FnSystem, Name, Networked, Resource, SystemGroup, World};
+use ambient_network::{
+    channels::{register_channel, register_client_channel, Budget, Channel, ChannelConfig, Reliability},
+    client::game_client,
+    get_player_by_user_id,
+    server::SharedServerState,
+    BiStreamHandlers, DatagramHandlers,
+};
+use audiopus::{
+    coder::{Decoder, Encoder},
+    packet::Packet,
+    Application, Bitrate, Channels, MutSignals, SampleRate as OpusSampleRate,
+};
+use bytes::Bytes;
+use itertools::Itertools;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{audio_emitter, audio_listener, audio_mixer, play_sound_on_entity};
+
+components!("audio", {
+    @[
+        Debuggable, Networked,
+        Name["Voice muted"],
+        Description["If attached to a player entity, the server doesn't relay the voice of this player to the other players."]
+    ]
+    voice_muted: (),
+
+    /// Captures the voice of the local player, on the client
+    @[Resource]
+    voice_chat: VoiceChat,
+    /// The voice of this player, played by the clients of the other players
+    voice_stream: Arc<Mutex<VoiceStream>>,
+});
+
+/// The id of the channel the voice is sent on, in both directions
+pub const VOICE_CHANNEL_ID: u32 = 8;
+
+/// The rate of the voice, as encoded and as played
+const SAMPLE_RATE: u32 = 48000;
+/// The samples of a frame, 20ms of them
+const FRAME_LEN: usize = 960;
+/// The samples of the longest frame Opus decodes, 120ms of them
+const MAX_FRAME_LEN: usize = 5760;
+/// The most bytes of an Opus packet
+const MAX_PACKET_SIZE: usize = 1275;
+const BITRATE: i32 = 24000;
+/// The frames encoded but not sent yet, beyond which the oldest are dropped, a second of them
+const MAX_PENDING_PACKETS: usize = 50;
+/// The frames lost in a row which are concealed; when more are lost, the voice picks up from the next frame
+const MAX_CONCEALED_FRAMES: u32 = 5;
+/// How far behind the frame expected next a frame may be to be one which arrived late, rather than the first of the
+/// speaker starting over
+const MAX_REORDERED_FRAMES: u32 = 50;
+/// The samples buffered before the voice starts playing, and again after the buffer runs dry, to ride out the jitter
+const PREBUFFER: usize = 3 * FRAME_LEN;
+/// The most samples buffered, beyond which the oldest are dropped so that the voice catches up
+const MAX_BUFFERED: usize = 10 * FRAME_LEN;
+/// How long a speaker may be silent before their voice stops being played, until they speak again
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The bytes per second of voice the server relays for each player, four times the bitrate, so that a client can't
+/// have the server flood the other players
+const MAX_VOICE_RATE: u64 = 4 * BITRATE as u64 / 8;
+
+fn channel_config() -> ChannelConfig {
+    ChannelConfig::new(VOICE_CHANNEL_ID, Reliability::Unreliable)
+}
+
+/// A frame of the voice of the local player, sent to the server
+#[derive(Debug, Serialize, Deserialize)]
+struct VoicePacket {
+    seq: u32,
+    data: Vec<u8>,
+}
+
+/// A frame of the voice of the player `speaker`, relayed by the server
+#[derive(Debug, Serialize, Deserialize)]
+struct VoiceFrame {
+    speaker: EntityId,
+    seq: u32,
+    data: Vec<u8>,
+}
+
+/// The channel to a player the voices are relayed on
+struct Peer {
+    /// The connection of the player the channel is on, which changes when they reconnect
+    connection_id: String,
+    channel: Arc<Channel>,
+}
+
+/// The players the server relays the voice of, and to
+#[derive(Default)]
+struct Relay {
+    /// The channels to the listeners, by user id
+    peers: HashMap<String, Peer>,
+    /// The voice each speaker may still send, by user id
+    budgets: HashMap<String, Budget>,
+}
+impl Relay {
+    /// Whether a packet of `len` bytes from `user_id` fits in their budget
+    fn admit(&mut self, user_id: &str, len: usize, now: Instant) -> bool {
+        let budget = self.budgets.entry(user_id.to_string()).or_insert_with(|| Budget::new(Some(MAX_VOICE_RATE), now));
+        budget.try_take(len as u64, now)
+    }
+}
+
+/// Registers the handler which relays the voice of each player to the other players of their world
+pub fn register_handlers(bi_stream_handlers: &mut BiStreamHandlers, datagram_handlers: &mut DatagramHandlers) {
+    let relay_state = Arc::new(Mutex::new(Relay::default()));
+    register_channel(
+        bi_stream_handlers,
+        datagram_handlers,
+        VOICE_CHANNEL_ID,
+        Arc::new(move |state, user_id, data| relay(&relay_state, state, user_id, data)),
+    );
+}
+
+fn relay(relay: &Mutex<Relay>, state: SharedServerState, user_id: &str, data: Bytes) {
+    let mut relay = relay.lock();
+    if !relay.admit(user_id, data.len(), Instant::now()) {
+        return;
+    }
+    let packet = match bincode::deserialize::<VoicePacket>(&data) {
+        Ok(packet) if packet.data.len() <= MAX_PACKET_SIZE => packet,
+        Ok(_) => return,
+        Err(err) => {
+            log::warn!("Invalid voice packet from {user_id}: {err}");
+            return;
+        }
+    };
+    let state = state.lock();
+    let (instance, world) = match (state.players.get(user_id), state.get_player_world(user_id)) {
+        (Some(player), Some(world)) => (&player.instance, world),
+        _ => return,
+    };
+    let speaker = match get_player_by_user_id(world, user_id) {
+        Some(speaker) => speaker,
+        None => return,
+    };
+    if world.has_component(speaker, voice_muted()) {
+        return;
+    }
+    let frame = Bytes::from(bincode::serialize(&VoiceFrame { speaker, seq: packet.seq, data: packet.data }).unwrap());
+
+    // Forget the players who left
+    relay.peers.retain(|user_id, _| state.players.contains_key(user_id));
+    relay.budgets.retain(|user_id, _| state.players.contains_key(user_id));
+    let mut channels = Vec::new();
+    for (listener_id, listener) in &state.players {
+        if listener_id == user_id || listener.instance != *instance || listener.disconnected_at.is_some() {
+            continue;
+        }
+        let connection = match &listener.connection {
+            Some(connection) => connection,
+            None => continue,
+        };
+        if relay.peers.get(listener_id).map(|peer| peer.connection_id != listener.connection_id).unwrap_or(true) {
+            let channel = Arc::new(Channel::new(connection.clone(), channel_config()));
+            relay.peers.insert(listener_id.clone(), Peer { connection_id: listener.connection_id.clone(), channel });
+        }
+        channels.push(relay.peers[listener_id].channel.clone());
+    }
+    tokio::spawn(async move {
+        for channel in channels {
+            // The frames may be lost anyway
+            channel.send(frame.clone()).await.ok();
+        }
+    });
+}
+
+/// Captures the voice of the local player and sends it to the server, while it transmits
+#[derive(Debug, Clone, Default)]
+pub struct VoiceChat(Arc<Mutex<VoiceChatState>>);
+#[derive(Debug, Default)]
+struct VoiceChatState {
+    transmitting: bool,
+    /// Whether the microphone is captured, which it stops being shortly after the voice chat stops transmitting
+    capturing: bool,
+    /// The frames encoded since the last frame of the game, which are sent then
+    packets: VecDeque<Bytes>,
+    /// The sequence number of the next frame, which goes on from one capture to the next
+    seq: u32,
+}
+
+impl VoiceChat {
+    pub fn transmitting(&self) -> bool {
+        self.0.lock().transmitting
+    }
+    /// Starts or stops sending the voice of the local player, e.g. while a push-to-talk key is held. The microphone is
+    /// only captured while transmitting; if it can't be, the voice chat stops transmitting.
+    pub fn set_transmitting(&self, transmitting: bool) {
+        let mut state = self.0.lock();
+        state.transmitting = transmitting;
+        if transmitting && !state.capturing {
+            state.capturing = true;
+            let weak = Arc::downgrade(&self.0);
+            if let Err(err) = thread::Builder::new().name("voice_capture".to_string()).spawn(move || capture(weak)) {
+                log::error!("Failed to start capturing the voice: {err}");
+                state.transmitting = false;
+                state.capturing = false;
+            }
+        }
+    }
+
+    fn take_packets(&self) -> Vec<Bytes> {
+        self.0.lock().packets.drain(..).collect()
+    }
+}
+
+/// Captures the microphone and encodes the voice, for as long as the voice chat transmits. The capture stays on this
+/// thread, as it can't be moved across threads.
+fn capture(state: Weak<Mutex<VoiceChatState>>) {
+    let stop = |err: anyhow::Error| {
+        log::warn!("Failed to capture the voice: {err:#}");
+        if let Some(state) = state.upgrade() {
+            let mut state = state.lock();
+            state.transmitting = false;
+            state.capturing = false;
+        }
+    };
+    let seq = match state.upgrade() {
+        Some(state) => state.lock().seq,
+        None => return,
+    };
+    let (tx, rx) = flume::unbounded::<Vec<f32>>();
+    let capture = match AudioCapture::new(move |samples| {
+        tx.send(samples.to_vec()).ok();
+    }) {
+        Ok(capture) => capture,
+        Err(err) => return stop(err.into()),
+    };
+    let mut encoder = match VoiceEncoder::new(capture.sample_rate(), seq) {
+        Ok(encoder) => encoder,
+        Err(err) => return stop(err),
+    };
+    loop {
+        let samples = match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(samples) => samples,
+            Err(flume::RecvTimeoutError::Timeout) => Vec::new(),
+            Err(flume::RecvTimeoutError::Disconnected) => return stop(anyhow::anyhow!("The microphone stopped")),
+        };
+        let state = match state.upgrade() {
+            Some(state) => state,
+            None => return,
+        };
+        let mut state = state.lock();
+        if !state.transmitting {
+            state.capturing = false;
+            return;
+        }
+        for packet in encoder.encode(&samples) {
+            if state.packets.len() >= MAX_PENDING_PACKETS {
+                state.packets.pop_front();
+            }
+            state.packets.push_back(packet);
+        }
+        state.seq = encoder.seq;
+    }
+}
+
+/// Encodes the voice into packets of a frame each
+struct VoiceEncoder {
+    encoder: Encoder,
+    resampler: Resampler,
+    /// The samples which don't make up a whole frame yet
+    samples: Vec<f32>,
+    seq: u32,
+}
+impl VoiceEncoder {
+    /// Encodes the samples captured at `sample_rate`, numbering the frames from `seq`
+    fn new(sample_rate: SampleRate, seq: u32) -> anyhow::Result<Self> {
+        let mut encoder = Encoder::new(OpusSampleRate::Hz48000, Channels::Mono, Application::Voip)?;
+        encoder.set_bitrate(Bitrate::BitsPerSecond(BITRATE))?;
+        // A frame carries a rougher copy of the one before, which replaces it if it's lost
+        encoder.set_inband_fec(true)?;
+        encoder.set_packet_loss_perc(10)?;
+        Ok(Self { encoder, resampler: Resampler::new(sample_rate, SAMPLE_RATE as _), samples: Vec::new(), seq })
+    }
+
+    /// Encodes the frames which `samples` complete
+    fn encode(&mut self, samples: &[f32]) -> Vec<Bytes> {
+        self.resampler.resample(samples, &mut self.samples);
+        let mut packets = Vec::new();
+        let mut output = [0; MAX_PACKET_SIZE];
+        while self.samples.len() >= FRAME_LEN {
+            let frame = self.samples.drain(..FRAME_LEN).collect_vec();
+            match self.encoder.encode_float(&frame, &mut output) {
+                Ok(len) => {
+                    let packet = VoicePacket { seq: self.seq, data: output[..len].to_vec() };
+                    packets.push(Bytes::from(bincode::serialize(&packet).unwrap()));
+                }
+                Err(err) => log::warn!("Failed to encode the voice: {err}"),
+            }
+            self.seq = self.seq.wrapping_add(1);
+        }
+        packets
+    }
+}
+
+/// Resamples a stream of samples linearly
+struct Resampler {
+    /// How far apart the output samples are, in input samples
+    step: f64,
+    /// Where the next output sample is, relative to the first input sample of the next call; -1 is `last`
+    pos: f64,
+    last: f32,
+}
+impl Resampler {
+    fn new(from: SampleRate, to: SampleRate) -> Self {
+        Self { step: from as f64 / to as f64, pos: 0., last: 0. }
+    }
+
+    fn resample(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        if input.is_empty() {
+            return;
+        }
+        let last = self.last;
+        let sample = |i: isize| if i < 0 { last } else { input[i as usize] };
+        while self.pos < (input.len() - 1) as f64 {
+            let i = self.pos.floor();
+            let (a, b) = (sample(i as isize), sample(i as isize + 1));
+            output.push(a + (b - a) * (self.pos - i) as f32);
+            self.pos += self.step;
+        }
+        self.pos -= input.len() as f64;
+        self.last = input[input.len() - 1];
+    }
+}
+
+/// Decodes the voice of a speaker into the buffer it's played from
+pub struct VoiceStream {
+    decoder: Decoder,
+    /// The frame expected next
+    next_seq: Option<u32>,
+    buffer: Arc<Mutex<VoiceBuffer>>,
+    last_received: Instant,
+}
+impl VoiceStream {
+    fn new(buffer: Arc<Mutex<VoiceBuffer>>) -> anyhow::Result<Self> {
+        let decoder = Decoder::new(OpusSampleRate::Hz48000, Channels::Mono)?;
+        Ok(Self { decoder, next_seq: None, buffer, last_received: Instant::now() })
+    }
+
+    /// Decodes the frame `seq` into the buffer, after concealing the frames lost since the one before. The frames which
+    /// arrive after the ones which follow them are dropped, unless they're so far behind that the speaker started over.
+    fn receive(&mut self, seq: u32, data: &[u8]) {
+        let lost = match self.next_seq {
+            Some(next_seq) => seq.wrapping_sub(next_seq),
+            None => 0,
+        };
+        if lost > u32::MAX / 2 && lost.wrapping_neg() <= MAX_REORDERED_FRAMES {
+            return;
+        }
+        self.last_received = Instant::now();
+        self.next_seq = Some(seq.wrapping_add(1));
+        if (1..=MAX_CONCEALED_FRAMES).contains(&lost) {
+            for _ in 1..lost {
+                self.decode(None, FRAME_LEN, false);
+            }
+            // The last frame lost is recovered from the copy this one carries
+            self.decode(Some(data), FRAME_LEN, true);
+        }
+        self.decode(Some(data), MAX_FRAME_LEN, false);
+    }
+
+    /// Decodes `len` samples at most from `data`, or conceals a lost frame of `len` samples if there's no data
+    fn decode(&mut self, data: Option<&[u8]>, len: usize, fec: bool) {
+        let mut output = [0.; MAX_FRAME_LEN];
+        let res = data
+            .map(Packet::try_from)
+            .transpose()
+            .and_then(|packet| self.decoder.decode_float(packet, MutSignals::try_from(&mut output[..len])?, fec));
+        match res {
+            Ok(decoded) => {
+                let mut buffer = self.buffer.lock();
+                buffer.samples.extend(&output[..decoded]);
+                let excess = buffer.samples.len().saturating_sub(MAX_BUFFERED);
+                buffer.samples.drain(..excess);
+            }
+            Err(err) => log::warn!("Failed to decode the voice: {err}"),
+        }
+    }
+}
+
+/// The decoded samples of a voice, waiting to be played
+#[derive(Debug, Default)]
+struct VoiceBuffer {
+    samples: VecDeque<f32>,
+    playing: bool,
+}
+impl VoiceBuffer {
+    /// The next sample to play; silence while the buffer fills up, at first and again after it ran dry
+    fn next_sample(&mut self) -> f32 {
+        if !self.playing {
+            if self.samples.len() < PREBUFFER {
+                return 0.;
+            }
+            self.playing = true;
+        }
+        match self.samples.pop_front() {
+            Some(sample) => sample,
+            None => {
+                self.playing = false;
+                0.
+            }
+        }
+    }
+}
+
+/// Plays a voice, until its stream is dropped
+struct VoiceSource(Weak<Mutex<VoiceBuffer>>);
+impl Source for VoiceSource {
+    fn next_sample(&mut self) -> Option<Frame> {
+        let buffer = self.0.upgrade()?;
+        let sample = buffer.lock().next_sample();
+        Some(Frame::splat(sample))
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        SAMPLE_RATE as _
+    }
+
+    fn sample_count(&self) -> Option<u64> {
+        None
+    }
+
+    fn sample_buffered(&mut self, output: &mut [Frame]) -> usize {
+        let buffer = match self.0.upgrade() {
+            Some(buffer) => buffer,
+            None => return 0,
+        };
+        let mut buffer = buffer.lock();
+        for frame in output.iter_mut() {
+            *frame += Frame::splat(buffer.next_sample());
+        }
+        output.len()
+    }
+}
+
+/// Registers the handler of the voices the server relays to the client `world`, and `voice_chat`, which sends the voice
+/// of the local player with the [client_systems]
+pub fn register_client_handler(world: &mut World, voice_chat: VoiceChat) {
+    world.add_resource(self::voice_chat(), voice_chat);
+    register_client_channel(world, VOICE_CHANNEL_ID, Arc::new(receive));
+}
+
+fn receive(world: &mut World, data: Bytes) {
+    let frame = match bincode::deserialize::<VoiceFrame>(&data) {
+        Ok(frame) => frame,
+        Err(err) => {
+            log::warn!("Invalid voice frame: {err}");
+            return;
+        }
+    };
+    // Nobody listens, or the speaker isn't known here
+    if world.resource_opt(audio_mixer()).is_none()
+        || query(audio_listener()).iter(world, None).next().is_none()
+        || !world.exists(frame.speaker)
+    {
+        return;
+    }
+    let stream = match world.get_ref(frame.speaker, voice_stream()) {
+        Ok(stream) => stream.clone(),
+        Err(_) => match play_voice(world, frame.speaker) {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::warn!("Failed to play the voice of {}: {err:#}", frame.speaker);
+                return;
+            }
+        },
+    };
+    stream.lock().receive(frame.seq, &frame.data);
+}
+
+/// Plays the voice of `speaker` from its entity
+fn play_voice(world: &mut World, speaker: EntityId) -> anyhow::Result<Arc<Mutex<VoiceStream>>> {
+    if !world.has_component(speaker, audio_emitter()) {
+        world.add_component(speaker, audio_emitter(), Arc::new(Mutex::new(AudioEmitter::default())))?;
+    }
+    let buffer = Arc::new(Mutex::new(VoiceBuffer::default()));
+    let stream = Arc::new(Mutex::new(VoiceStream::new(buffer.clone())?));
+    play_sound_on_entity(world, speaker, VoiceSource(Arc::downgrade(&buffer)))?;
+    world.add_component(speaker, voice_stream(), stream.clone())?;
+    Ok(stream)
+}
+
+pub fn client_systems() -> SystemGroup {
+    // The channel to the server, and the id of the connection it's on
+    let mut channel: Option<(usize, Arc<Channel>)> = None;
+    SystemGroup::new(
+        "voice",
+        vec![
+            // Sends the voice captured since the last frame
+            Box::new(FnSystem::new(move |world, _| {
+                let packets = match world.resource_opt(voice_chat()) {
+                    Some(voice_chat) => voice_chat.take_packets(),
+                    None => return,
+                };
+                let game_client = match world.resource_opt(game_client()) {
+                    Some(Some(game_client)) if !packets.is_empty() => game_client,
+                    _ => return,
+                };
+                let connection = &game_client.connection;
+                if channel.as_ref().map(|(id, _)| *id != connection.stable_id()).unwrap_or(true) {
+                    channel = Some((connection.stable_id(), Arc::new(Channel::new(connection.clone(), channel_config()))));
+                }
+                let channel = channel.as_ref().unwrap().1.clone();
+                world.resource(runtime()).spawn(async move {
+                    for packet in packets {
+                        channel.send(packet).await.ok();
+                    }
+                });
+            })),
+            // Stops playing the voices of the speakers who went silent
+            query(voice_stream()).to_system(|q, world, qs, _| {
+                let idle = q
+                    .iter(world, qs)
+                    .filter(|(_, stream)| stream.lock().last_received.elapsed() > IDLE_TIMEOUT)
+                    .map(|(id, _)| id)
+                    .collect_vec();
+                for id in idle {
+                    world.remove_component(id, voice_stream()).ok();
+                }
+            }),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The data of the packets of `frames` frames of a tone
+    fn encode_frames(frames: usize) -> Vec<Vec<u8>> {
+        let mut encoder = VoiceEncoder::new(SAMPLE_RATE as _, 0).unwrap();
+        // The resampler holds the last sample back
+        let samples = (0..frames * FRAME_LEN + 1).map(|i| (i as f32 * 0.05).sin() * 0.5).collect_vec();
+        let packets = encoder.encode(&samples);
+        assert_eq!(packets.len(), frames);
+        packets.iter().map(|packet| bincode::deserialize::<VoicePacket>(packet).unwrap().data).collect()
+    }
+
+    #[test]
+    fn resampler_keeps_linear_signals_across_calls() {
+        let mut resampler = Resampler::new(2, 3);
+        let input = (0..300).map(|i| i as f32).collect_vec();
+        let mut output = Vec::new();
+        for chunk in input.chunks(7) {
+            resampler.resample(chunk, &mut output);
+        }
+        assert!((output.len() as i64 - 449).abs() <= 1, "{}", output.len());
+        for (i, sample) in output.iter().enumerate() {
+            assert!((sample - i as f32 * 2. / 3.).abs() < 1e-3, "{i}: {sample}");
+        }
+    }
+
+    #[test]
+    fn resampler_converts_the_rate() {
+        let mut resampler = Resampler::new(44100, 48000);
+        let mut output = Vec::new();
+        for _ in 0..100 {
+            resampler.resample(&[1.; 441], &mut output);
+        }
+        assert!((output.len() as i64 - 48000).abs() <= 2, "{}", output.len());
+        assert!(output[1..].iter().all(|sample| (sample - 1.).abs() < 1e-6));
+    }
+
+    #[test]
+    fn voice_stream_conceals_lost_frames_and_drops_late_ones() {
+        let packets = encode_frames(3);
+        let buffer = Arc::new(Mutex::new(VoiceBuffer::default()));
+        let mut stream = VoiceStream::new(buffer.clone()).unwrap();
+        let buffered = || buffer.lock().samples.len();
+
+        stream.receive(10, &packets[0]);
+        assert_eq!(buffered(), FRAME_LEN);
+        // 11 is concealed, and 12 recovered from 13
+        stream.receive(13, &packets[1]);
+        assert_eq!(buffered(), 4 * FRAME_LEN);
+        // Late
+        stream.receive(12, &packets[2]);
+        assert_eq!(buffered(), 4 * FRAME_LEN);
+        // Too many lost to conceal; the voice picks up from there
+        stream.receive(14 + MAX_CONCEALED_FRAMES + 1, &packets[2]);
+        assert_eq!(buffered(), 5 * FRAME_LEN);
+        stream.receive(1000, &packets[0]);
+        assert_eq!(buffered(), 6 * FRAME_LEN);
+        // So far behind that the speaker started over
+        stream.receive(0, &packets[1]);
+        assert_eq!(buffered(), 7 * FRAME_LEN);
+        assert_eq!(stream.next_seq, Some(1));
+
+        // The buffer drops the oldest samples to catch up
+        for seq in 1..20 {
+            stream.receive(seq, &packets[seq as usize % 3]);
+        }
+        assert_eq!(buffered(), MAX_BUFFERED);
+    }
+
+    #[test]
+    fn voice_stream_numbering_wraps_around() {
+        let packets = encode_frames(2);
+        let buffer = Arc::new(Mutex::new(VoiceBuffer::default()));
+        let mut stream = VoiceStream::new(buffer.clone()).unwrap();
+        stream.receive(u32::MAX, &packets[0]);
+        stream.receive(0, &packets[1]);
+        assert_eq!(buffer.lock().samples.len(), 2 * FRAME_LEN);
+        stream.receive(u32::MAX, &packets[0]);
+        assert_eq!(buffer.lock().samples.len(), 2 * FRAME_LEN);
+    }
+
+    #[test]
+    fn voice_buffer_prebuffers() {
+        let buffer = Arc::new(Mutex::new(VoiceBuffer::default()));
+        let mut source = VoiceSource(Arc::downgrade(&buffer));
+        buffer.lock().samples.extend(std::iter::repeat(1.).take(PREBUFFER - 1));
+        let mut output = [Frame::ZERO; 4];
+        source.sample_buffered(&mut output);
+        assert_eq!(output, [Frame::ZERO; 4]);
+
+        buffer.lock().samples.extend([1., 1.]);
+        let mut output = vec![Frame::ZERO; PREBUFFER + 2];
+        source.sample_buffered(&mut output);
+        assert!(output[..PREBUFFER + 1].iter().all(|frame| *frame == Frame::splat(1.)));
+        // Ran dry, so it fills up again before playing
+        assert_eq!(output[PREBUFFER + 1], Frame::ZERO);
+        buffer.lock().samples.push_back(1.);
+        assert_eq!(source.next_sample(), Some(Frame::ZERO));
+
+        drop(buffer);
+        assert_eq!(source.next_sample(), None);
+    }
+
+    #[test]
+    fn relay_limits_the_voice_of_each_speaker() {
+        let mut relay = Relay::default();
+        let now = Instant::now();
+        let packet_len = MAX_VOICE_RATE as usize / 100;
+        assert_eq!((0..200).filter(|_| relay.admit("a", packet_len, now)).count(), 100);
+        // The other speakers have budgets of their own
+        assert!(relay.admit("b", packet_len, now));
+        // The budget refills over time
+        assert!(!relay.admit("a", packet_len, now));
+        assert!(relay.admit("a", packet_len, now + Duration::from_millis(20)));
+    }
+}
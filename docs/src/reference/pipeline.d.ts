@@ -1,6 +1,7 @@
 // This file is generated by the `generate-docs` tool. Please do not manually edit it.
 // pipeline.json
 export type u32 = number;
+export type u8 = number;
 export type f32 = number;
 export type Vec2 = [number, number];
 export type Vec3 = [number, number, number];
@@ -115,10 +116,18 @@ export type Pipeline = {
         normalmap?: AssetUrl,
         /// The metallic roughness map of this material.
         metallic_roughness?: AssetUrl,
+        /// The emissive map of this material, which the emissive factor is multiplied by.
+        /// It must be linear (i.e. not sRGB encoded), as it holds intensities; the build fails otherwise.
+        emissive?: AssetUrl,
         /// The color that this material should be multiplied by. Defaults to white for PBR.
         base_color_factor?: Vec4,
-        /// The emissive factor of this material (i.e. the color that it emits). Defaults to black for PBR.
+        /// The emissive factor of this material (i.e. the color that it emits). Defaults to black for PBR, or to white if
+        /// there's an emissive map or strength.
         emissive_factor?: Vec4,
+        /// The luminance of the emissive color in nits (cd/m²), 203 nits being the white of the display.
+        /// Brighter emissives glow with the bloom of the camera.
+        /// Defaults to the emissive factor being the color as displayed.
+        emissive_strength?: f32,
         /// Whether or not this material is transparent. Defaults to false for PBR.
         transparent?: boolean,
         /// The opacity level (between 0 and 1) at which this material will not be rendered.
@@ -131,6 +140,46 @@ export type Pipeline = {
         metallic?: f32,
         /// The roughness coefficient of this material. Defaults to 1 for PBR.
         roughness?: f32,
+        /// Plays the base color map as an animation. The base color map can be an animated GIF or PNG, or a sheet of frames.
+        flipbook?: {
+          /// The number of columns of frames in the base color map.
+          /// Required if the base color map is a sheet of frames; computed if it's an animated GIF or PNG.
+          columns?: u32,
+          /// The number of rows of frames in the base color map.
+          /// Required if the base color map is a sheet of frames; computed if it's an animated GIF or PNG.
+          rows?: u32,
+          /// The number of frames to play. Defaults to all of them.
+          frame_count?: u32,
+          /// The number of frames played per second.
+          /// Defaults to the frame rate of the animated GIF or PNG, or to 10 for sheets of frames.
+          fps?: f32,
+          /// Whether or not the animation starts over when it ends. Defaults to true.
+          looping?: boolean,
+        },
+        /// How the maps of this material are sampled. Defaults to the texture quality settings of the renderer.
+        sampling?: {
+          /// How the maps are filtered between their texels and mip levels. Defaults to "Trilinear".
+          filtering?: "Nearest" | "Bilinear" | "Trilinear",
+          /// The maximum level of anisotropic filtering, from 1 (off) to 16. Only applies to trilinear filtering. Defaults to 16.
+          anisotropy?: u8,
+          /// Added to the mip level the maps are sampled from; negative is sharper, positive blurrier. Defaults to 0.
+          mip_bias?: f32,
+        },
+        /// A second base color and normal map, tiled over this material and blended in by the vertex colors or a mask.
+        detail?: {
+          /// The detail base color map.
+          base_color?: AssetUrl,
+          /// The detail normal map.
+          normalmap?: AssetUrl,
+          /// Where the detail maps are blended in, laid out like the material's own maps. Defaults to the vertex colors.
+          mask?: AssetUrl,
+          /// The channel of the mask or vertex colors which is the blend weight. Defaults to "R".
+          channel?: "R" | "G" | "B" | "A",
+          /// How many times the detail maps repeat for each time the material's own maps do. Defaults to [1, 1].
+          tiling?: Vec2,
+          /// How much of the detail maps is blended in where the blend weight is 1, from 0 to 1. Defaults to 1.
+          strength?: f32,
+        },
         /// The non-PBR specular map of this material. If specified, it will be translated to a PBR equivalent.
         specular?: AssetUrl,
         /// The non-PBR specular exponent of this material. If specified alongside `specular`, it will be translated to a PBR equivalent.
@@ -188,6 +237,25 @@ export type Pipeline = {
       /// Re-center this mesh such that the root is located at the origin.
       type: "Center",
     })[],
+    /// Compress the meshes with meshopt, which makes them a few times smaller to download. Off by default.
+    compress_meshes?: boolean,
+    /// Generate the second UVs of the static meshes which don't have them, which the lightmaps are baked with. Off by default.
+    lightmap_uvs?: {
+      /// The width and height of the lightmap, in texels. 512 by default.
+      resolution?: number,
+      /// The texels between the charts, so that they don't bleed into each other. 2 by default.
+      padding?: number,
+      /// The largest angle between the normals of the triangles of a chart, in degrees. 60 by default.
+      max_chart_angle?: number,
+      /// Which meshes share a lightmap.
+      packing?: {
+        /// Each mesh has a lightmap of its own. The default.
+        type: "PerMesh",
+      } | {
+        /// The static meshes of a model share a lightmap, as when they're merged into one.
+        type: "PerModel",
+      },
+    },
   } | {
     /// The materials asset pipeline.
     /// Will import specific materials without needing to be part of a model.
@@ -209,10 +277,18 @@ export type Pipeline = {
       normalmap?: AssetUrl,
       /// The metallic roughness map of this material.
       metallic_roughness?: AssetUrl,
+      /// The emissive map of this material, which the emissive factor is multiplied by.
+      /// It must be linear (i.e. not sRGB encoded), as it holds intensities; the build fails otherwise.
+      emissive?: AssetUrl,
       /// The color that this material should be multiplied by. Defaults to white for PBR.
       base_color_factor?: Vec4,
-      /// The emissive factor of this material (i.e. the color that it emits). Defaults to black for PBR.
+      /// The emissive factor of this material (i.e. the color that it emits). Defaults to black for PBR, or to white if
+      /// there's an emissive map or strength.
       emissive_factor?: Vec4,
+      /// The luminance of the emissive color in nits (cd/m²), 203 nits being the white of the display.
+      /// Brighter emissives glow with the bloom of the camera.
+      /// Defaults to the emissive factor being the color as displayed.
+      emissive_strength?: f32,
       /// Whether or not this material is transparent. Defaults to false for PBR.
       transparent?: boolean,
       /// The opacity level (between 0 and 1) at which this material will not be rendered.
@@ -225,6 +301,46 @@ export type Pipeline = {
       metallic?: f32,
       /// The roughness coefficient of this material. Defaults to 1 for PBR.
       roughness?: f32,
+      /// Plays the base color map as an animation. The base color map can be an animated GIF or PNG, or a sheet of frames.
+      flipbook?: {
+        /// The number of columns of frames in the base color map.
+        /// Required if the base color map is a sheet of frames; computed if it's an animated GIF or PNG.
+        columns?: u32,
+        /// The number of rows of frames in the base color map.
+        /// Required if the base color map is a sheet of frames; computed if it's an animated GIF or PNG.
+        rows?: u32,
+        /// The number of frames to play. Defaults to all of them.
+        frame_count?: u32,
+        /// The number of frames played per second.
+        /// Defaults to the frame rate of the animated GIF or PNG, or to 10 for sheets of frames.
+        fps?: f32,
+        /// Whether or not the animation starts over when it ends. Defaults to true.
+        looping?: boolean,
+      },
+      /// How the maps of this material are sampled. Defaults to the texture quality settings of the renderer.
+      sampling?: {
+        /// How the maps are filtered between their texels and mip levels. Defaults to "Trilinear".
+        filtering?: "Nearest" | "Bilinear" | "Trilinear",
+        /// The maximum level of anisotropic filtering, from 1 (off) to 16. Only applies to trilinear filtering. Defaults to 16.
+        anisotropy?: u8,
+        /// Added to the mip level the maps are sampled from; negative is sharper, positive blurrier. Defaults to 0.
+        mip_bias?: f32,
+      },
+      /// A second base color and normal map, tiled over this material and blended in by the vertex colors or a mask.
+      detail?: {
+        /// The detail base color map.
+        base_color?: AssetUrl,
+        /// The detail normal map.
+        normalmap?: AssetUrl,
+        /// Where the detail maps are blended in, laid out like the material's own maps. Defaults to the vertex colors.
+        mask?: AssetUrl,
+        /// The channel of the mask or vertex colors which is the blend weight. Defaults to "R".
+        channel?: "R" | "G" | "B" | "A",
+        /// How many times the detail maps repeat for each time the material's own maps do. Defaults to [1, 1].
+        tiling?: Vec2,
+        /// How much of the detail maps is blended in where the blend weight is 1, from 0 to 1. Defaults to 1.
+        strength?: f32,
+      },
       /// The non-PBR specular map of this material. If specified, it will be translated to a PBR equivalent.
       specular?: AssetUrl,
       /// The non-PBR specular exponent of this material. If specified alongside `specular`, it will be translated to a PBR equivalent.
@@ -239,6 +355,29 @@ export type Pipeline = {
     /// The audio asset pipeline.
     /// Will import supported audio file formats and produce Ogg Vorbis files to be used by the runtime.
     type: "Audio",
+  } | {
+    /// The script bundles asset pipeline.
+    /// Will copy `.script_bundle` files, and compile Rust crates (directories with a `Cargo.toml`) to WASM.
+    type: "ScriptBundles",
+    /// Whether or not compiled scripts should be optimized with `wasm-opt`. This is skipped with a warning if `wasm-opt` is not installed.
+    /// Defaults to true.
+    optimize?: boolean,
+    /// Whether or not the custom sections (debug info, names, producers, etc.) of compiled scripts should be removed from the bundle.
+    /// Defaults to true.
+    strip_custom_sections?: boolean,
+    /// Compile scripts with debug info, and write it to a separate `.debug.wasm` file next to the build artifacts in the `target` directory.
+    /// This file is not part of the build output, but can be used to symbolicate the bundle locally.
+    split_debug_info?: boolean,
+  } | {
+    /// The video asset pipeline.
+    /// Will import supported video file formats and split them into frames and an Ogg Vorbis audio track, to be played by the runtime.
+    type: "Video",
+    /// The number of frames per second of the output videos.
+    /// Defaults to 30.
+    fps?: f32,
+    /// Videos taller than this are scaled down to this height, keeping their aspect ratio.
+    /// Defaults to 720.
+    max_height?: u32,
   },
   /// Filter the sources used to feed this pipeline.
   /// This is a list of glob patterns for accepted files.
@@ -1,4 +1,5 @@
 use crate::{
+    components,
     global::{EntityId, Vec3},
     internal::{
         component::{
@@ -189,3 +190,25 @@ pub fn mutate_component_with_default<
 pub fn resources() -> EntityId {
     host::entity_resources().from_bindgen()
 }
+
+/// Gives the client of the player `user_id` the authority to simulate `entity`: the client then
+/// sends the transform of `entity` to the server, which stops sending it back to that client. If
+/// `user_id` is `None`, the server takes the authority back.
+///
+/// The authority also goes back to the server when the player leaves or changes room.
+pub fn set_owner(entity: EntityId, user_id: Option<&str>) {
+    match user_id {
+        Some(user_id) => add_component(
+            entity,
+            components::core::network::owner(),
+            user_id.to_string(),
+        ),
+        None => remove_component(entity, components::core::network::owner()),
+    }
+}
+
+/// The `user_id` of the player whose client has the authority to simulate `entity`, or `None`
+/// if the server has it.
+pub fn owner(entity: EntityId) -> Option<String> {
+    get_component(entity, components::core::network::owner())
+}
@@ -10,6 +10,12 @@ pub const COLLIDER_LOAD: &str = "core/collider_load";
 pub const MODULE_LOAD: &str = "core/module_load";
 /// Fired when the module is unloaded.
 pub const MODULE_UNLOAD: &str = "core/module_unload";
+/// Fired when a video reaches its end, including each time a looping video starts over.
+/// Components will contain the `id` of the entity playing it.
+pub const VIDEO_END: &str = "core/video_end";
+/// Fired when a button of the tuning panel of the debugger is pressed.
+/// Components will contain the `id` of the button's entity.
+pub const TUNING_BUTTON: &str = "core/tuning_button";
 
 /// Sends a (non-core) event to all other modules. This can be used for inter-module communication.
 pub fn send(name: impl AsRef<str>, data: Entity) {
@@ -146,6 +146,8 @@ pub async fn until_this(event: &str, condition: impl Fn(&Entity) -> bool + 'stat
 }
 
 /// This method resolves a relative path to an asset in a Ambient module, to an absolute url
+///
+/// An asset id from the build's asset manifest (e.g. `characters/knight` for `assets/characters/knight.glb`) can be used instead of a path.
 pub fn asset_url(path: impl AsRef<str>) -> Option<String> {
     host::asset_url(path.as_ref())
 }
@@ -15,10 +15,14 @@ pub mod entity;
 pub mod event;
 /// Global functions and types for your convenience.
 pub mod global;
+/// Messages between the server and the clients, as declared in the project.
+pub mod message;
 /// Physics-related functionality, including applying forces, changing physical properties, and more.
 pub mod physics;
 /// Player-related functionality.
 pub mod player;
+/// Tuning of gameplay constants live from the debugger, through sliders, toggles and buttons.
+pub mod tuning;
 
 /// Helpful imports that almost all Ambient projects will use.
 pub mod prelude;
@@ -0,0 +1,30 @@
+use crate::{
+    components, event,
+    global::{on as on_event, EventResult},
+    internal::component::Entity,
+};
+
+/// The prefix of the events which carry the messages, as the runtime routes them.
+const EVENT_PREFIX: &str = "message/";
+
+/// Sends the message declared as `name` in the `[messages]` of the project to the client of the
+/// player with `user_id`, or to the clients of all the players if it's `None`.
+///
+/// The message is delivered reliably or not as it's declared, and its `data` becomes an event named
+/// `message/<name>` on the client.
+pub fn send(name: &str, user_id: Option<&str>, data: Entity) {
+    let data = match user_id {
+        Some(user_id) => data.with(components::core::player::user_id(), user_id.to_string()),
+        None => data,
+    };
+    event::send(format!("{EVENT_PREFIX}{name}"), data);
+}
+
+/// `on` calls `callback` every time a client sends the message declared as `name` in the `[messages]`
+/// of the project.
+///
+/// The data of the message has the `user_id` of the player who sent it. The messages of another
+/// version than the declared one are dropped before they get here.
+pub fn on(name: &str, callback: impl Fn(&Entity) -> EventResult + 'static) {
+    on_event(&format!("{EVENT_PREFIX}{name}"), callback)
+}
@@ -1,8 +1,10 @@
 use std::collections::HashSet;
 
 use crate::{
+    components, entity,
     global::{EntityId, Vec2},
     internal::{
+        component::Entity,
         conversion::{FromBindgen, IntoBindgen},
         host,
     },
@@ -492,3 +494,117 @@ pub fn get_raw_input_delta(player_id: EntityId) -> Option<(RawInputDelta, RawInp
     let (p, c) = get_prev_and_current_raw_input(player_id)?;
     Some((c.delta(&p), c))
 }
+
+/// A rumble of the gamepad of a player. Play it with [rumble].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rumble {
+    /// The strength of the low frequency (strong) motor, from 0 to 1.
+    pub low_frequency: f32,
+    /// The strength of the high frequency (weak) motor, from 0 to 1.
+    pub high_frequency: f32,
+    /// How long the gamepad rumbles for, in seconds.
+    pub duration: f32,
+    /// How long the motors take to ramp up to their strength, in seconds.
+    pub attack: f32,
+    /// How long the motors take to fade out at the end of the rumble, in seconds.
+    pub fade: f32,
+}
+
+/// Rumbles the gamepad of `player_id`, replacing the rumble it's playing if any.
+///
+/// The rumble is played on the gamepad with the `gamepad_index` of the player, on the machine of the
+/// player or of its `owner`, so that each player of a local multiplayer game can have their own gamepad.
+pub fn rumble(player_id: EntityId, rumble: Rumble) {
+    use components::core::player::*;
+    entity::add_components(
+        player_id,
+        Entity::new()
+            .with(gamepad_rumble_low_frequency(), rumble.low_frequency)
+            .with(gamepad_rumble_high_frequency(), rumble.high_frequency)
+            .with(gamepad_rumble_attack(), rumble.attack)
+            .with(gamepad_rumble_fade(), rumble.fade)
+            .with(gamepad_rumble_duration(), rumble.duration),
+    );
+}
+
+/// Stops the rumble of the gamepad of `player_id`.
+pub fn stop_rumble(player_id: EntityId) {
+    entity::add_component(
+        player_id,
+        components::core::player::gamepad_rumble_duration(),
+        0.,
+    );
+}
+
+/// How the window of a player is shown. Set it with [set_window_mode].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WindowMode {
+    /// A regular window.
+    #[default]
+    Windowed,
+    /// A window covering the whole monitor, at its current resolution.
+    BorderlessFullscreen,
+    /// Takes over the monitor, which can change its resolution (see [set_window_resolution]).
+    ExclusiveFullscreen,
+}
+
+/// Sets how the window of `player_id` is shown.
+pub fn set_window_mode(player_id: EntityId, mode: WindowMode) {
+    let mode = match mode {
+        WindowMode::Windowed => 0,
+        WindowMode::BorderlessFullscreen => 1,
+        WindowMode::ExclusiveFullscreen => 2,
+    };
+    entity::add_component(player_id, components::core::player::window_mode(), mode);
+}
+
+/// Moves the window of `player_id` to the `monitor`th monitor connected to their machine, from 0.
+pub fn set_window_monitor(player_id: EntityId, monitor: u32) {
+    entity::add_component(
+        player_id,
+        components::core::player::window_monitor(),
+        monitor,
+    );
+}
+
+/// Sets the size of the window of `player_id`, in physical pixels.
+///
+/// In exclusive fullscreen, this is the resolution of the monitor, which has to be one it supports.
+pub fn set_window_resolution(player_id: EntityId, resolution: Vec2) {
+    entity::add_component(
+        player_id,
+        components::core::player::window_resolution(),
+        resolution,
+    );
+}
+
+/// Sets the size the window of `player_id` can't be resized below, in physical pixels.
+pub fn set_window_min_size(player_id: EntityId, size: Vec2) {
+    entity::add_component(player_id, components::core::player::window_min_size(), size);
+}
+
+/// Sets the title of the window of `player_id`.
+pub fn set_window_title(player_id: EntityId, title: impl Into<String>) {
+    entity::add_component(
+        player_id,
+        components::core::player::window_title(),
+        title.into(),
+    );
+}
+
+/// Moves `player_id` to the `room`, which is an isolated world with its own entities, physics and
+/// scripts. The player is moved at the end of the frame, as a new player entity in the room.
+///
+/// A room which doesn't exist yet is created from the main room (`"main"`), with its scripts
+/// started over; use [room] to tell the rooms apart. The rooms other than the main one are removed
+/// when their last player leaves.
+pub fn move_to_room(player_id: EntityId, room: impl Into<String>) {
+    entity::add_component(player_id, components::core::network::room(), room.into());
+}
+
+/// The room this module runs in, which is `"main"` unless the module runs in a room created by
+/// [move_to_room].
+pub fn room() -> String {
+    entity::get_component(entity::resources(), components::core::network::room_id())
+        .unwrap_or_else(|| "main".to_string())
+}
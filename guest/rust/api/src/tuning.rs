@@ -0,0 +1,73 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::{
+    components, entity, event,
+    global::{on, EntityId, EventOk},
+    internal::component::Entity,
+};
+
+thread_local! {
+    static CONTROLS: RefCell<HashMap<String, EntityId>> = RefCell::new(HashMap::new());
+}
+
+/// The entity of the control with the `label`, which is spawned with `components` the first time it's used.
+///
+/// Returns whether it was spawned.
+fn control(label: &str, components: impl FnOnce() -> Entity) -> (EntityId, bool) {
+    CONTROLS.with(|controls| {
+        let mut controls = controls.borrow_mut();
+        if let Some(id) = controls.get(label) {
+            return (*id, false);
+        }
+        let id = entity::spawn(
+            &components().with(components::core::tuning::tuning_label(), label.to_string()),
+        );
+        controls.insert(label.to_string(), id);
+        (id, true)
+    })
+}
+
+/// A slider in the tuning panel of the debugger, which returns its current value.
+///
+/// The slider is added the first time this is called with the `label`, with the `default` value; after that,
+/// it returns the value it has been set to in the debugger. Prefix the `label` with a group and a `/` (e.g.
+/// `"Player/Jump height"`) to show it in that group.
+pub fn slider(label: &str, default: f32, min: f32, max: f32) -> f32 {
+    let (id, _) = control(label, || {
+        Entity::new()
+            .with(components::core::tuning::tuning_value(), default)
+            .with(components::core::tuning::tuning_min(), min)
+            .with(components::core::tuning::tuning_max(), max)
+    });
+    entity::get_component(id, components::core::tuning::tuning_value()).unwrap_or(default)
+}
+
+/// A toggle in the tuning panel of the debugger, which returns its current value.
+///
+/// The toggle is added the first time this is called with the `label`, with the `default` value; after that,
+/// it returns the value it has been set to in the debugger. Prefix the `label` with a group and a `/` to show
+/// it in that group.
+pub fn toggle(label: &str, default: bool) -> bool {
+    let (id, _) = control(label, || {
+        Entity::new().with(components::core::tuning::tuning_toggle(), default)
+    });
+    entity::get_component(id, components::core::tuning::tuning_toggle()).unwrap_or(default)
+}
+
+/// A button in the tuning panel of the debugger, which calls `callback` when it's pressed.
+///
+/// The button is added the first time this is called with the `label`; the later calls don't do anything. Prefix
+/// the `label` with a group and a `/` to show it in that group.
+pub fn button(label: &str, callback: impl Fn() + 'static) {
+    let (id, spawned) = control(label, || {
+        Entity::new().with_default(components::core::tuning::tuning_button())
+    });
+    if spawned {
+        on(event::TUNING_BUTTON, move |data| {
+            if data.get(components::core::ecs::id()) == Some(id) {
+                callback();
+            }
+            EventOk
+        });
+    }
+}